@@ -0,0 +1,59 @@
+//! This application enables or disables resolving each call site in a panic backtrace
+//! to its source code location.
+//!
+//! Examples:
+//! * `backtrace_lines`: print whether source code locations are enabled.
+//! * `backtrace_lines on`: print the source code location of each call site in panic backtraces.
+//! * `backtrace_lines off`: stop printing source code locations.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use app_io::println;
+use getopts::Options;
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    match matches.free.first().map(String::as_str) {
+        Some("on") => panic_wrapper::set_source_locations_in_backtraces(true),
+        Some("off") => panic_wrapper::set_source_locations_in_backtraces(false),
+        Some(other) => {
+            println!("Error: invalid argument {:?}, expected \"on\" or \"off\"", other);
+            return -1;
+        }
+        None => { }
+    }
+
+    if panic_wrapper::source_locations_in_backtraces() {
+        println!("Source code locations in panic backtraces are enabled");
+    } else {
+        println!("Source code locations in panic backtraces are disabled");
+    }
+    0
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: backtrace_lines [OPTION] [on | off]
+Enables or disables printing the source code location of each call site in panic backtraces.
+Resolving locations loads each crate's debug sections from within the panic handler, so only enable this while debugging.";
@@ -0,0 +1,63 @@
+//! This application enables or disables writing an ELF core file
+//! for each application task that is killed due to an exception.
+//!
+//! Examples:
+//! * `coredump`: print whether core files are enabled.
+//! * `coredump on`: write core files into `/cores` for crashed application tasks.
+//! * `coredump off`: stop writing core files.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use app_io::println;
+use getopts::Options;
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    let result = match matches.free.first().map(String::as_str) {
+        Some("on") => core_dump::set_enabled(true),
+        Some("off") => core_dump::set_enabled(false),
+        Some(other) => {
+            println!("Error: invalid argument {:?}, expected \"on\" or \"off\"", other);
+            return -1;
+        }
+        None => Ok(()),
+    };
+    if let Err(e) = result {
+        println!("Error: {}", e);
+        return -1;
+    }
+
+    if core_dump::is_enabled() {
+        println!("Core files are enabled, and will be written into {}", core_dump::CORE_DIRECTORY);
+    } else {
+        println!("Core files are disabled");
+    }
+    0
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: coredump [OPTION] [on | off]
+Enables or disables writing an ELF core file for each application task that crashes due to an exception.
+Core files can be loaded in GDB on the host together with the crate object files.";
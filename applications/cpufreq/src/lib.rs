@@ -0,0 +1,106 @@
+//! This application views and controls CPU frequencies,
+//! e.g., to pin them for reproducible benchmarks.
+//!
+//! Examples:
+//! * `cpufreq`: print the frequency ratios of the platform and the current CPU.
+//! * `cpufreq --pin 20`: pin all CPUs to ratio 20 (2000 MHz), disabling turbo boost.
+//! * `cpufreq --unpin`: re-enable turbo boost and let all CPUs run at their max frequency.
+//! * `cpufreq --modulation 8`: throttle the current CPU's clock to run 8/16ths of the time.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use app_io::println;
+use cpu_freq::{ClockModulation, BUS_CLOCK_MHZ};
+use getopts::Options;
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("p", "pin", "pin all CPUs to the given ratio, disabling turbo boost", "RATIO");
+    opts.optflag("u", "unpin", "undo a previous `--pin`, re-enabling turbo boost");
+    opts.optopt("m", "modulation", "set the current CPU's clock modulation duty cycle in sixteenths (0 to disable)", "DUTY");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    if !cpu_freq::is_supported() {
+        println!("Error: this CPU doesn't support frequency control.");
+        return -1;
+    }
+
+    let result = if let Some(ratio) = matches.opt_str("p") {
+        match ratio.parse::<u8>() {
+            Ok(ratio) => cpu_freq::pin_frequency(ratio),
+            Err(_) => Err("invalid ratio"),
+        }
+    } else if matches.opt_present("u") {
+        cpu_freq::unpin_frequency()
+    } else if let Some(duty) = matches.opt_str("m") {
+        match duty.parse::<u8>() {
+            Ok(0) => cpu_freq::set_clock_modulation(ClockModulation::Disabled),
+            Ok(duty) => cpu_freq::set_clock_modulation(ClockModulation::DutyCycle(duty)),
+            Err(_) => Err("invalid duty cycle"),
+        }
+    } else {
+        Ok(())
+    };
+    if let Err(e) = result {
+        println!("Error: {}", e);
+        return -1;
+    }
+
+    match print_info() {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Error: {}", e);
+            -1
+        }
+    }
+}
+
+fn print_info() -> Result<(), &'static str> {
+    let info = cpu_freq::platform_info()?;
+    println!("Base ratio:      {} ({} MHz)", info.base_ratio, info.base_mhz());
+    println!("Min ratio:       {} ({} MHz)", info.min_ratio, info.min_ratio as u64 * BUS_CLOCK_MHZ);
+    if cpu_freq::turbo_available()? {
+        println!("Turbo boost:     {}", if cpu_freq::turbo_enabled()? { "enabled" } else { "disabled" });
+        println!("Turbo ratios:    {:?}{}",
+            cpu_freq::turbo_ratio_limits()?,
+            if info.turbo_ratio_programmable { "" } else { " (read-only)" },
+        );
+    } else {
+        println!("Turbo boost:     unavailable");
+    }
+
+    let current_ratio = cpu_freq::current_ratio()?;
+    println!("Current CPU:     {}", cpu::current_cpu());
+    println!("  current ratio: {} ({} MHz)", current_ratio, current_ratio as u64 * BUS_CLOCK_MHZ);
+    println!("  target ratio:  {}", cpu_freq::target_ratio()?);
+    match cpu_freq::clock_modulation()? {
+        ClockModulation::Disabled => println!("  modulation:    disabled"),
+        ClockModulation::DutyCycle(duty) => println!("  modulation:    {}/16", duty),
+    }
+    Ok(())
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: cpufreq [OPTION]
+Views and controls CPU frequency ratios, which are multiples of the 100 MHz bus clock.
+Pinning the frequency of all CPUs makes benchmark results more reproducible.";
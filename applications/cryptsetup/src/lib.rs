@@ -0,0 +1,177 @@
+//! This application formats and opens storage devices encrypted via the `crypt_device` layer.
+//!
+//! Examples:
+//! * `cryptsetup`: list the storage devices and the opened encrypted devices.
+//! * `cryptsetup format 0`: format storage device 0 for encryption, destroying its contents.
+//! * `cryptsetup open 0 secure`: open storage device 0 as the encrypted device named "secure".
+//! * `cryptsetup close secure`: close the encrypted device named "secure".
+//! * `cryptsetup passwd 0`: change the passphrase of storage device 0, which must be formatted for encryption.
+//!
+//! The passphrase is read from stdin, without echoing it if the terminal supports that,
+//! and is wiped from memory once it's no longer needed.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use app_io::{print, println};
+use crypt_device::CryptDevice;
+use crypto::Zeroizing;
+use getopts::Options;
+use storage_manager::StorageDeviceRef;
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    let free: Vec<&str> = matches.free.iter().map(String::as_str).collect();
+    let result = match free.as_slice() {
+        [] => {
+            list();
+            Ok(())
+        }
+        ["format", device] => format(device),
+        ["open", device, name] => open(device, name),
+        ["close", name] => close(name),
+        ["passwd", device] => change_passphrase(device),
+        _ => {
+            print_usage(opts);
+            return -1;
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Error: {}", e);
+            -1
+        }
+    }
+}
+
+fn list() {
+    println!("Storage devices:");
+    for (i, device) in storage_manager::storage_devices().enumerate() {
+        let dev = device.lock();
+        println!("  {}: {} blocks of {} bytes", i, dev.size_in_blocks(), dev.block_size());
+    }
+    println!("Opened encrypted devices:");
+    for name in crypt_device::mapping_names() {
+        println!("  {}", name);
+    }
+}
+
+fn format(device: &str) -> Result<(), &'static str> {
+    let device = find_device(device)?;
+    println!("WARNING: this will irrevocably destroy all data on the device.");
+    let passphrase = read_new_passphrase()?;
+    CryptDevice::format(device, passphrase.as_bytes())?;
+    println!("Formatted device for encryption.");
+    Ok(())
+}
+
+fn open(device: &str, name: &str) -> Result<(), &'static str> {
+    let device = find_device(device)?;
+    let passphrase = read_passphrase("Enter passphrase: ")?;
+    let crypt_device = CryptDevice::open(device, passphrase.as_bytes())?;
+    crypt_device::add_mapping(name, crypt_device)?;
+    println!("Opened encrypted device {:?}.", name);
+    Ok(())
+}
+
+fn change_passphrase(device: &str) -> Result<(), &'static str> {
+    let device = find_device(device)?;
+    let old_passphrase = read_passphrase("Enter current passphrase: ")?;
+    let new_passphrase = read_new_passphrase()?;
+    CryptDevice::change_passphrase(&device, old_passphrase.as_bytes(), new_passphrase.as_bytes())?;
+    println!("Changed passphrase.");
+    Ok(())
+}
+
+fn close(name: &str) -> Result<(), &'static str> {
+    crypt_device::remove_mapping(name).ok_or("no opened encrypted device has that name")?;
+    println!("Closed encrypted device {:?}.", name);
+    Ok(())
+}
+
+/// Returns the storage device with the given index, as shown by `list()`.
+fn find_device(index: &str) -> Result<StorageDeviceRef, &'static str> {
+    let index: usize = index.parse().map_err(|_| "invalid storage device index")?;
+    storage_manager::storage_devices().nth(index).ok_or("no storage device has that index")
+}
+
+/// Reads a new passphrase, which must be entered twice.
+fn read_new_passphrase() -> Result<Zeroizing<String>, &'static str> {
+    let passphrase = read_passphrase("Enter new passphrase: ")?;
+    let confirmation = read_passphrase("Confirm passphrase: ")?;
+    if passphrase != confirmation {
+        return Err("passphrases do not match");
+    }
+    Ok(passphrase)
+}
+
+/// Prints the given prompt and reads one line from stdin, with echo disabled if possible.
+fn read_passphrase(prompt: &str) -> Result<Zeroizing<String>, &'static str> {
+    print!("{}", prompt);
+    let discipline = app_io::line_discipline().ok();
+    let previous_echo = discipline.as_ref().map(|d| d.echo());
+    if let Some(ref d) = discipline {
+        d.set_echo(false);
+    }
+
+    let result = read_line();
+
+    if let (Some(d), Some(echo)) = (discipline, previous_echo) {
+        d.set_echo(echo);
+    }
+    println!();
+    let line = result?;
+    if line.is_empty() {
+        return Err("the passphrase must not be empty");
+    }
+    Ok(line)
+}
+
+fn read_line() -> Result<Zeroizing<String>, &'static str> {
+    let stdin = app_io::stdin()?;
+    // Preallocated such that a typical passphrase isn't copied when the buffer grows.
+    let mut line = Zeroizing::new(Vec::with_capacity(256));
+    let mut buf = [0u8];
+    loop {
+        let cnt = stdin.read(&mut buf).or(Err("failed to read from stdin"))?;
+        if cnt == 0 || buf[0] == b'\n' {
+            break;
+        }
+        line.push(buf[0]);
+    }
+    String::from_utf8(core::mem::take(&mut *line))
+        .map(Zeroizing::new)
+        .map_err(|_| "the passphrase must be valid UTF-8")
+}
+
+const USAGE: &str = "Usage: cryptsetup [OPTION]
+       cryptsetup format DEVICE
+       cryptsetup open DEVICE NAME
+       cryptsetup close NAME
+       cryptsetup passwd DEVICE
+Formats, opens, and closes storage devices with transparent AES-XTS encryption,
+and changes their passphrases.
+DEVICE is the index of a storage device, as listed when run without arguments.";
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
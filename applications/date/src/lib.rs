@@ -5,15 +5,134 @@
 
 extern crate alloc;
 #[macro_use] extern crate app_io;
+extern crate getopts;
 extern crate rtc;
 
 use alloc::vec::Vec;
 use alloc::string::String;
+use core::time::Duration;
+use getopts::Options;
+use rtc::DateTime;
 
 
-pub fn main(_args: Vec<String>) -> isize {
-    let now = rtc::read_rtc();
-    println!("{}", now);
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optflag("u", "utc", "print the date and time in UTC instead of local time");
+    opts.optopt("z", "utc-offset", "set the local timezone's offset from UTC, e.g., \"-05:00\"", "OFFSET");
+    opts.optflag("", "rtc-local", "treat the RTC hardware as holding local time");
+    opts.optflag("", "rtc-utc", "treat the RTC hardware as holding UTC (the default)");
+    opts.optopt("s", "set", "set the local date and time, e.g., \"2024-01-31 13:45:00\"", "DATETIME");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(_f) => {
+            println!("{}", _f);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+    if matches.opt_present("rtc-local") && matches.opt_present("rtc-utc") {
+        println!("--rtc-local and --rtc-utc cannot be used together");
+        return -1;
+    }
+
+    // Changing the timezone or the RTC's mode changes how the RTC's time is interpreted,
+    // so the wall clock must be re-synchronized with the RTC afterwards.
+    let mut resync = false;
+    if let Some(offset) = matches.opt_str("z") {
+        let Some(seconds_east) = parse_utc_offset(&offset) else {
+            println!("invalid UTC offset {:?}, expected \"+HH:MM\" or \"-HH:MM\"", offset);
+            return -1;
+        };
+        if let Err(e) = rtc::set_utc_offset(seconds_east) {
+            println!("failed to set UTC offset: {}", e);
+            return -1;
+        }
+        resync |= rtc::rtc_holds_local_time();
+    }
+    if matches.opt_present("rtc-local") || matches.opt_present("rtc-utc") {
+        rtc::set_rtc_holds_local_time(matches.opt_present("rtc-local"));
+        resync = true;
+    }
+    if resync {
+        if let Err(e) = rtc::init_wall_clock() {
+            println!("failed to re-synchronize the wall clock with the RTC: {}", e);
+            return -1;
+        }
+    }
+
+    if let Some(datetime) = matches.opt_str("s") {
+        let Some(local) = parse_datetime(&datetime) else {
+            println!("invalid date and time {:?}, expected \"YYYY-MM-DD HH:MM:SS\"", datetime);
+            return -1;
+        };
+        let unix_seconds = local.local_to_utc().to_unix_timestamp();
+        if unix_seconds < 0 {
+            println!("cannot set a time before the Unix epoch");
+            return -1;
+        }
+        if let Err(e) = rtc::set_wall_time(Duration::from_secs(unix_seconds as u64)) {
+            println!("failed to set the date and time: {}", e);
+            return -1;
+        }
+    }
+
+    if matches.opt_present("u") {
+        println!("{} UTC", rtc::now_utc());
+    } else {
+        let now = rtc::now_local();
+        let offset_minutes = rtc::utc_offset() / 60;
+        let sign = if offset_minutes < 0 { '-' } else { '+' };
+        println!("{} UTC{}{:02}:{:02}", now, sign, offset_minutes.abs() / 60, offset_minutes.abs() % 60);
+    }
 
     0
 }
+
+/// Parses a UTC offset of the form `+HH:MM` or `-HH:MM` into seconds east of UTC.
+fn parse_utc_offset(offset: &str) -> Option<i32> {
+    let (sign, rest) = match offset.as_bytes().first()? {
+        b'+' => (1, &offset[1..]),
+        b'-' => (-1, &offset[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':')?;
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if minutes >= 60 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Parses a date and time of the form `YYYY-MM-DD HH:MM:SS`.
+fn parse_datetime(datetime: &str) -> Option<DateTime> {
+    let (date, time) = datetime.trim().split_once(' ')?;
+    let mut date = date.split('-');
+    let mut time = time.split(':');
+    let parsed = DateTime {
+        year:   date.next()?.parse().ok()?,
+        month:  date.next()?.parse().ok()?,
+        day:    date.next()?.parse().ok()?,
+        hour:   time.next()?.parse().ok()?,
+        minute: time.next()?.parse().ok()?,
+        second: time.next()?.parse().ok()?,
+    };
+    if date.next().is_some() || time.next().is_some() || !parsed.is_valid() {
+        return None;
+    }
+    Some(parsed)
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: date [OPTION]...
+Prints the current date and time, optionally after changing the timezone or setting the time.";
@@ -0,0 +1,105 @@
+//! This application prints statistics about the usage of the kernel heap,
+//! including per-size-class occupancy and, optionally, allocation and free rates,
+//! as well as the memory held by other subsystems, e.g., window framebuffers.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{format, string::String, vec::Vec};
+use app_io::println;
+use core::time::Duration;
+use getopts::Options;
+use heap::HeapStats;
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optflag("c", "classes", "print the occupancy of each allocation size class");
+    opts.optflag("s", "subsystems", "print the memory held by other subsystems, e.g., window framebuffers");
+    opts.optopt("r", "rate", "measure allocation and free rates over the given interval", "MILLISECONDS");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    let interval_ms = match matches.opt_str("r").map(|s| s.parse::<u64>()) {
+        Some(Ok(ms)) if ms > 0 => Some(ms),
+        Some(_) => {
+            println!("Error: the rate interval must be a positive number of milliseconds");
+            return -1;
+        }
+        None => None,
+    };
+
+    let stats = heap::heap_stats();
+    print_summary(&stats);
+    if matches.opt_present("c") {
+        print_size_classes(&stats);
+    }
+    if matches.opt_present("s") {
+        print_subsystems();
+    }
+
+    if let Some(ms) = interval_ms {
+        if sleep::sleep(Duration::from_millis(ms)).is_err() {
+            println!("Error: failed to sleep for the rate interval");
+            return -1;
+        }
+        let after = heap::heap_stats();
+        let allocs = after.total_allocations - stats.total_allocations;
+        let frees = after.total_deallocations - stats.total_deallocations;
+        println!(
+            "\nOver {} ms: {} allocations ({}/s), {} frees ({}/s)",
+            ms, allocs, allocs as u64 * 1000 / ms, frees, frees as u64 * 1000 / ms,
+        );
+    }
+
+    0
+}
+
+fn print_summary(stats: &HeapStats) {
+    println!("Heap total:      {:>12} bytes", stats.total_bytes);
+    println!("Heap used:       {:>12} bytes", stats.used_bytes);
+    println!("Heap free:       {:>12} bytes", stats.free_bytes());
+    println!("Peak used:       {:>12} bytes", stats.peak_used_bytes);
+    println!("Live objects:    {:>12}", stats.live_allocations());
+    println!("Allocations:     {:>12}", stats.total_allocations);
+    println!("Frees:           {:>12}", stats.total_deallocations);
+    println!("Failed allocs:   {:>12}", stats.failed_allocations);
+}
+
+fn print_size_classes(stats: &HeapStats) {
+    println!("\n{:>10}  {:>12}  {:>14}  {:>14}", "SIZE", "LIVE OBJECTS", "LIVE BYTES", "TOTAL ALLOCS");
+    for class in stats.size_classes.iter() {
+        if class.max_size == usize::MAX {
+            println!("{:>10}  {:>12}  {:>14}  {:>14}", "large", class.live_objects, class.live_bytes, class.total_allocations);
+        } else {
+            println!("{:>10}  {:>12}  {:>14}  {:>14}", class.max_size, class.live_objects, class.live_bytes, class.total_allocations);
+        }
+    }
+}
+
+fn print_subsystems() {
+    println!();
+    for (name, bytes) in memory_pressure::memory_usage() {
+        println!("{:<17}{:>12} bytes", format!("{}:", name), bytes);
+    }
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: heapinfo [OPTION]...
+Prints usage statistics about the kernel heap.";
@@ -42,7 +42,6 @@ use noline::{builder::EditorBuilder, sync::embedded::IO as Io};
 use path::PathBuf;
 use stdio::Stdio;
 use sync_block::Mutex;
-use task::{ExitValue, KillReason};
 use tty::{Event, LineDiscipline};
 
 pub use crate::error::{Error, Result};
@@ -313,39 +312,25 @@ impl Shell {
             println!("multiple matching files found, running: {app_path}");
         }
 
-        let task = spawn::new_application_task_builder(&app_path, None)
-            .map_err(Error::SpawnFailed)?
-            .argument(args.into_iter().map(ToOwned::to_owned).collect::<Vec<_>>())
-            .block()
-            .spawn()
-            .unwrap();
-        let task_ref = task.clone();
-
-        let id = task.id;
-        // TODO: Double arc :(
-        app_io::insert_child_streams(id, streams);
-        task.unblock().map_err(Error::UnblockFailed)?;
+        let app = app_spawn::spawn_application(
+            &app_path,
+            args.into_iter().map(ToOwned::to_owned).collect::<Vec<_>>(),
+            Some(streams),
+            None,
+        )
+        .map_err(Error::SpawnFailed)?;
+        let task_ref = app.task().clone();
 
         // Spawn watchdog task.
         spawn::new_task_builder(
             move |_| {
-                let task_ref = task.clone();
-
-                let exit_value = match task.join().unwrap() {
-                    ExitValue::Completed(status) => {
-                        match status.downcast_ref::<isize>() {
-                            Some(num) => *num,
-                            // FIXME: Document/decide on a number for when app doesn't
-                            // return isize.
-                            None => 210,
-                        }
+                let task_ref = app.task().clone();
+                let exit_value = match app.join() {
+                    Ok(exit) => exit.status_code(),
+                    Err(e) => {
+                        error!("failed to join application task: {e}");
+                        app_spawn::PANICKED_STATUS
                     }
-                    ExitValue::Killed(reason) => match reason {
-                        // FIXME: Document/decide on a number. This is used by bash.
-                        KillReason::Requested => 130,
-                        KillReason::Panic(_) => 1,
-                        KillReason::Exception(num) => num.into(),
-                    },
                 };
 
                 let mut jobs = self.jobs.lock();
@@ -0,0 +1,158 @@
+//! This application queries the database of hardware-touching code in the current namespace:
+//! the MMIO regions that each crate has mapped, and the sites in loaded crates
+//! that perform volatile or port I/O accesses.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::{String, ToString}, vec::Vec};
+use app_io::println;
+use getopts::{Matches, Options};
+use mmio_audit::{AccessKind, AuditDatabase};
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("c", "crate", "only show the mappings and access sites of the given crate", "CRATE");
+    opts.optopt("k", "kind", "only show access sites of the given kind: read, write, volatile, or port", "KIND");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    match run(&matches) {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Error: {}", e);
+            -1
+        }
+    }
+}
+
+fn run(matches: &Matches) -> Result<(), String> {
+    let command = matches.free.first().map(String::as_str).unwrap_or("summary");
+    let args = &matches.free[matches.free.len().min(1)..];
+    match command {
+        "summary" => summary(),
+        "mappings" => mappings(matches.opt_str("c").as_deref()),
+        "sites" => sites(matches.opt_str("c").as_deref(), matches.opt_str("k").as_deref()),
+        "zones" => zones(args),
+        "logging" => logging(args),
+        other => Err(alloc::format!("unknown command {:?}", other)),
+    }
+}
+
+fn collect() -> Result<AuditDatabase, String> {
+    let namespace = task::with_current_task(|t| t.get_namespace().clone())
+        .map_err(|_| "couldn't get current task".to_string())?;
+    Ok(AuditDatabase::collect(&namespace))
+}
+
+fn summary() -> Result<(), String> {
+    let database = collect()?;
+    let summary = database.summary();
+    if summary.is_empty() {
+        println!("No MMIO mappings or volatile access sites found.");
+        return Ok(());
+    }
+    println!("{:<32} {:>6} {:>12} {:>8} {:>10}", "CRATE", "MMIO", "MMIO BYTES", "SITES", "ACCESSORS");
+    for (crate_name, s) in &summary {
+        println!("{:<32} {:>6} {:>12} {:>8} {:>10}", crate_name, s.mmio_mappings, s.mmio_bytes, s.access_sites, s.accessor_sections);
+    }
+    let dropped = memory::dropped_mmio_mappings();
+    if dropped > 0 {
+        println!("\n{} MMIO mappings weren't recorded because the log was full.", dropped);
+    }
+    Ok(())
+}
+
+fn mappings(crate_name: Option<&str>) -> Result<(), String> {
+    let database = collect()?;
+    println!("{:<18} {:<18} {:>10} {:<8} LOCATION", "PHYSICAL", "VIRTUAL", "SIZE", "STATE");
+    for m in database.mmio_mappings.iter().filter(|m| crate_name.map_or(true, |c| m.crate_name() == c)) {
+        println!("{:<#18X} {:<#18X} {:>10} {:<8} {}",
+            m.phys_start.value(), m.virt_start.value(), m.size_in_bytes,
+            if m.mapped { "mapped" } else { "unmapped" },
+            m.location,
+        );
+    }
+    Ok(())
+}
+
+fn sites(crate_name: Option<&str>, kind: Option<&str>) -> Result<(), String> {
+    let kind = kind.map(|k| match k {
+        "read" => Ok(AccessKind::VolatileRead),
+        "write" => Ok(AccessKind::VolatileWrite),
+        "volatile" => Ok(AccessKind::Volatile),
+        "port" => Ok(AccessKind::PortIo),
+        other => Err(alloc::format!("unknown access kind {:?}", other)),
+    }).transpose()?;
+    let database = collect()?;
+    let mut count = 0;
+    for site in database.access_sites.iter()
+        .filter(|s| crate_name.map_or(true, |c| s.crate_name.as_str() == c))
+        .filter(|s| kind.map_or(true, |k| s.kind == k))
+    {
+        println!("{}", site);
+        count += 1;
+    }
+    for accessor in database.accessor_sections.iter()
+        .filter(|a| crate_name.map_or(true, |c| a.crate_name.as_str() == c))
+        .filter(|a| kind.map_or(true, |k| a.kind == k))
+    {
+        println!("{}: {} (accessor, {})", accessor.crate_name, accessor.section, accessor.kind);
+        count += 1;
+    }
+    if count == 0 {
+        println!("No volatile access sites found.");
+    }
+    Ok(())
+}
+
+fn zones(args: &[String]) -> Result<(), String> {
+    match args {
+        [] => match mmio_audit::zones() {
+            Some(zones) => println!("Zones: {}", zones.join(", ")),
+            None => println!("No zones are set; all crates are audited."),
+        },
+        [clear] if clear == "clear" => mmio_audit::set_zones(None),
+        prefixes => mmio_audit::set_zones(Some(prefixes.to_vec())),
+    }
+    Ok(())
+}
+
+fn logging(args: &[String]) -> Result<(), String> {
+    match args.first().map(String::as_str) {
+        None => println!("MMIO logging is {}.", if memory::mmio_logging() { "on" } else { "off" }),
+        Some("on") => memory::set_mmio_logging(true),
+        Some("off") => memory::set_mmio_logging(false),
+        Some(other) => return Err(alloc::format!("expected \"on\" or \"off\", not {:?}", other)),
+    }
+    Ok(())
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: hwaudit [OPTIONS] [COMMAND]
+Shows where hardware-touching code lives in the current namespace.
+
+Commands:
+    summary               the number of MMIO mappings and volatile access sites in each crate (default)
+    mappings              every recorded MMIO mapping and the source location that created it
+    sites                 every volatile access site, as CRATE: SECTION+OFFSET -> ACCESSOR (KIND)
+    zones [PREFIX...]     restrict the audit to crates starting with the given prefixes
+    zones clear           audit all crates
+    logging [on|off]      show or set whether new MMIO mappings are recorded";
@@ -0,0 +1,67 @@
+//! This application isolates CPUs such that only tasks pinned to them run there,
+//! without timer ticks or device interrupts disturbing them.
+//!
+//! Examples:
+//! * `isolcpus`: list the isolated CPUs.
+//! * `isolcpus 2 3`: isolate CPUs 2 and 3.
+//! * `isolcpus --undo 3`: end the isolation of CPU 3.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use app_io::println;
+use getopts::Options;
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optflag("u", "undo", "end the isolation of the given CPUs");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    for arg in &matches.free {
+        let Some(cpu_id) = arg.parse::<u32>().ok().and_then(|id| cpu::cpus().find(|c| c.value() == id)) else {
+            println!("Error: {:?} is not a valid CPU ID", arg);
+            return -1;
+        };
+        let result = if matches.opt_present("u") {
+            cpu_isolation::unisolate(cpu_id)
+        } else {
+            cpu_isolation::isolate(cpu_id)
+        };
+        if let Err(e) = result {
+            println!("Error: failed to change the isolation of CPU {}: {}", cpu_id, e);
+            return -1;
+        }
+    }
+
+    let isolated = cpu_isolation::isolated_cpus();
+    if isolated.is_empty() {
+        println!("No CPUs are isolated.");
+    } else {
+        println!("Isolated CPUs: {:?}", isolated);
+    }
+    0
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: isolcpus [OPTION] [CPU]...
+Isolates the given CPUs, such that only tasks pinned to them will run there.
+An isolated CPU receives no timer ticks while it runs a single task, and no device interrupts.";
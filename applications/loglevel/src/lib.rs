@@ -0,0 +1,91 @@
+//! This application allows viewing and changing the log level at runtime,
+//! either the default level used by all crates or a level for specific crates/modules.
+//!
+//! Examples:
+//! * `loglevel`: print the current default level and all per-target levels.
+//! * `loglevel warn`: set the default log level to `warn`.
+//! * `loglevel mod_mgmt=trace net=warn`: set per-target log levels.
+//! * `loglevel mod_mgmt=default`: remove the per-target level for `mod_mgmt`.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{format, string::String, vec::Vec};
+use app_io::println;
+use getopts::Options;
+use log::LevelFilter;
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optflag("c", "clear", "remove all per-target log levels before applying any new ones");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    if matches.opt_present("c") {
+        logger::clear_target_log_levels();
+    }
+
+    for arg in matches.free.iter() {
+        if let Err(e) = apply(arg) {
+            println!("Error: {}", e);
+            return -1;
+        }
+    }
+
+    print_levels();
+    0
+}
+
+/// Applies a single `LEVEL` or `TARGET=LEVEL` argument.
+fn apply(arg: &str) -> Result<(), String> {
+    match arg.split_once('=') {
+        Some((target, level_str)) => {
+            if target.is_empty() {
+                return Err(format!("missing target name in {arg:?}"));
+            }
+            let level = if level_str.eq_ignore_ascii_case("default") {
+                None
+            } else {
+                Some(parse_level(level_str)?)
+            };
+            logger::set_target_log_level(target, level);
+        }
+        None => logger::set_default_log_level(parse_level(arg)?),
+    }
+    Ok(())
+}
+
+fn parse_level(s: &str) -> Result<LevelFilter, String> {
+    s.parse::<LevelFilter>().map_err(|_| format!(
+        "invalid log level {s:?}, expected one of: off, error, warn, info, debug, trace"
+    ))
+}
+
+fn print_levels() {
+    println!("default: {}", logger::default_log_level());
+    for (target, level) in logger::target_log_levels() {
+        println!("{}: {}", target, level);
+    }
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: loglevel [OPTION] [LEVEL] [TARGET=LEVEL]...
+Views or sets the default log level and per-crate/module log levels.
+Use TARGET=default to remove a per-target log level.";
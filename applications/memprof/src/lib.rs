@@ -0,0 +1,148 @@
+//! This application profiles memory loads that suffer from high latency (e.g., cache misses)
+//! using PEBS load latency sampling, and attributes them to the crate functions that performed them.
+//!
+//! By default, it samples whatever runs on the current CPU for the given duration.
+//! Alternatively, it can run a built-in workload of symbol lookups in the current namespace,
+//! which exercises the trie-based crate and symbol maps and is useful for tuning them.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::{String, ToString}, vec::Vec};
+use app_io::println;
+use core::time::Duration;
+use getopts::{Matches, Options};
+use pmu_x86::pebs::{self, LoadLatencyConfig, LoadLatencySample};
+use task::ExitValue;
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("d", "duration", "sample the current CPU for the given duration (default: 1000)", "MILLISECONDS");
+    opts.optopt("s", "symbols", "instead, sample the given number of passes of symbol lookups in this namespace", "PASSES");
+    opts.optopt("l", "latency", "only sample loads that take at least this many cycles (default: 32)", "CYCLES");
+    opts.optopt("p", "period", "record one of every PERIOD qualifying loads (default: 1000)", "PERIOD");
+    opts.optopt("n", "top", "print only the top N functions by total latency (default: 20)", "N");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    match run(&matches) {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Error: {}", e);
+            -1
+        }
+    }
+}
+
+/// What to do while sampling.
+#[derive(Clone, Copy)]
+enum Workload {
+    /// Sleep for the given duration, sampling any other tasks on this CPU.
+    Sleep(Duration),
+    /// Look up every symbol and crate in the namespace the given number of times.
+    SymbolLookups(usize),
+}
+
+fn run(matches: &Matches) -> Result<(), String> {
+    let mut config = LoadLatencyConfig::default();
+    if let Some(latency) = parse_opt(matches, "l")? {
+        config.latency_threshold = latency;
+    }
+    if let Some(period) = parse_opt(matches, "p")? {
+        config.sample_period = period;
+    }
+    let top = parse_opt::<usize>(matches, "n")?.unwrap_or(20);
+    let workload = match parse_opt(matches, "s")? {
+        Some(passes) => Workload::SymbolLookups(passes),
+        None => Workload::Sleep(Duration::from_millis(parse_opt(matches, "d")?.unwrap_or(1000))),
+    };
+
+    pmu_x86::init()?;
+
+    // Sampling only occurs on a single CPU, so the profiling task must stay on that CPU.
+    let cpu = cpu::current_cpu();
+    let profiler = spawn::new_task_builder(profile, (config, workload))
+        .name(String::from("memprof"))
+        .pin_on_cpu(cpu)
+        .spawn()?;
+    let samples = match profiler.join()? {
+        ExitValue::Completed(result) => match result.downcast_ref::<Result<Vec<LoadLatencySample>, &'static str>>() {
+            Some(Ok(samples)) => samples.clone(),
+            Some(Err(e)) => return Err(e.to_string()),
+            None => return Err("memprof task returned an unexpected value".to_string()),
+        },
+        ExitValue::Killed(reason) => return Err(alloc::format!("memprof task was killed: {:?}", reason)),
+    };
+
+    if samples.is_empty() {
+        println!("No loads exceeded the latency threshold of {} cycles on CPU {}.", config.latency_threshold, cpu);
+        return Ok(());
+    }
+    let hotspots = pebs::hotspots(&samples)?;
+    let misses = samples.iter().filter(|s| s.data_source.is_cache_miss()).count();
+    println!("{} samples on CPU {} ({} missed all caches), across {} functions:", samples.len(), cpu, misses, hotspots.len());
+    for hotspot in hotspots.iter().take(top) {
+        println!("{}", hotspot);
+    }
+    Ok(())
+}
+
+/// The entry point of the profiling task, which samples loads while running the given workload.
+fn profile((config, workload): (LoadLatencyConfig, Workload)) -> Result<Vec<LoadLatencySample>, &'static str> {
+    pmu_x86::init()?;
+    pebs::start_load_latency_sampling(config)?;
+    let result = match workload {
+        Workload::Sleep(duration) => sleep::sleep(duration).map_err(|_| "memprof: failed to sleep"),
+        Workload::SymbolLookups(passes) => symbol_lookups(passes),
+    };
+    let samples = pebs::stop_load_latency_sampling()?;
+    result.map(|_| samples)
+}
+
+/// Looks up every symbol and crate in the current namespace `passes` times.
+fn symbol_lookups(passes: usize) -> Result<(), &'static str> {
+    let namespace = task::with_current_task(|t| t.get_namespace().clone())
+        .map_err(|_| "memprof: couldn't get current task")?;
+    let view = namespace.view();
+    let symbols: Vec<String> = view.symbols().map(|(name, _)| name.to_string()).collect();
+    let crates: Vec<String> = view.crates().map(|(name, _)| name.to_string()).collect();
+    drop(view);
+
+    let mut found = 0;
+    for _ in 0..passes {
+        found += symbols.iter().filter(|s| namespace.get_symbol(s).upgrade().is_some()).count();
+        found += crates.iter().filter(|c| namespace.get_crate(c).is_some()).count();
+    }
+    if found == 0 {
+        return Err("memprof: no symbols or crates were found in this namespace");
+    }
+    Ok(())
+}
+
+fn parse_opt<T: core::str::FromStr>(matches: &Matches, opt: &str) -> Result<Option<T>, String> {
+    matches.opt_str(opt)
+        .map(|s| s.parse::<T>().map_err(|_| alloc::format!("invalid value for option -{}: {:?}", opt, s)))
+        .transpose()
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: memprof [OPTIONS]
+Profiles memory loads with high latency on the current CPU using PEBS,
+and prints the functions that performed them, sorted by total latency.";
@@ -0,0 +1,329 @@
+//! A terminal multiplexer that runs several shells in split panes of one terminal.
+//!
+//! Each pane is backed by its own tty and `hull` shell (see the `tty_mux` crate),
+//! so a serial console or a single terminal window can run several interactive
+//! programs at once. Sessions can be detached, leaving their shells running,
+//! and reattached later from any terminal.
+//!
+//! Key bindings are entered after the `Ctrl+B` prefix:
+//! * `%`: split the focused pane into left and right panes.
+//! * `"`: split the focused pane into top and bottom panes.
+//! * `o` or `n`: focus the next pane; `p`: focus the previous pane.
+//! * `x`: close the focused pane.
+//! * `d`: detach from the session.
+//! * `Ctrl+B`: send a literal `Ctrl+B` to the focused pane.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use app_io::{println, ImmutableWrite};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use getopts::{Matches, Options};
+use tty_mux::{Rect, Session, Split};
+
+/// The key that must precede every mux key binding.
+const PREFIX: u8 = 0x02; // Ctrl+B
+
+/// How often the renderer redraws panes whose contents have changed.
+const RENDER_INTERVAL: Duration = Duration::from_millis(30);
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optflag("l", "list", "list all sessions");
+    opts.optopt("s", "session", "create a new session with the given name", "NAME");
+    opts.optopt("a", "attach", "reattach to a detached session", "NAME");
+    opts.optopt("k", "kill", "kill a session and all of its shells", "NAME");
+    opts.optopt("", "size", "the size of the terminal (default: 80x24)", "COLUMNSxROWS");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    match rmain(matches) {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Error: {}", e);
+            -1
+        }
+    }
+}
+
+fn rmain(matches: Matches) -> Result<(), String> {
+    if matches.opt_present("l") {
+        for (name, attached) in tty_mux::sessions() {
+            println!("{}{}", name, if attached { " (attached)" } else { "" });
+        }
+        return Ok(());
+    }
+    if let Some(name) = matches.opt_str("k") {
+        return tty_mux::kill_session(&name).map_err(String::from);
+    }
+
+    let (columns, rows) = match matches.opt_str("size") {
+        Some(size) => parse_size(&size)?,
+        None => (80, 24),
+    };
+    // The bottom row is reserved for the status line.
+    let pane_rows = rows - 1;
+
+    let session = if let Some(name) = matches.opt_str("a") {
+        let session = tty_mux::attach_session(&name)?;
+        session.resize(columns, pane_rows);
+        session
+    } else {
+        let name = match matches.opt_str("s") {
+            Some(name) => name,
+            None => next_session_name(),
+        };
+        tty_mux::create_session(&name, columns, pane_rows)?
+    };
+
+    let result = run(&session, columns, rows);
+    session.detach();
+    result.map_err(String::from)
+}
+
+fn run(session: &Arc<Session>, columns: usize, rows: usize) -> Result<(), &'static str> {
+    let discipline = app_io::line_discipline()?;
+    let stdin = app_io::stdin()?;
+    let stdout = app_io::stdout()?;
+    discipline.set_raw();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let renderer = spawn::new_task_builder(
+        render_loop,
+        (session.clone(), stdout.clone(), stop.clone(), columns, rows),
+    )
+    .name(format!("mux_{}_renderer", session.name()))
+    .spawn();
+
+    let result = match renderer {
+        Ok(renderer) => {
+            let result = input_loop(session, &*stdin, &stop);
+            stop.store(true, Ordering::Release);
+            let _ = renderer.join();
+            result
+        }
+        Err(e) => Err(e),
+    };
+
+    // Clear the terminal and move the cursor to its top-left corner.
+    let _ = stdout.write_all(b"\x1b[2J\x1b[H");
+    discipline.set_sane();
+    result
+}
+
+/// Forwards input to the focused pane and handles key bindings until the
+/// session is detached or all of its panes have exited.
+fn input_loop(
+    session: &Session,
+    stdin: &dyn app_io::ImmutableRead,
+    stop: &AtomicBool,
+) -> Result<(), &'static str> {
+    let mut buf = [0u8; 64];
+    let mut prefixed = false;
+
+    while !stop.load(Ordering::Acquire) {
+        let len = stdin.read(&mut buf).map_err(|_| "failed to read stdin")?;
+        if len == 0 {
+            break;
+        }
+
+        let mut start = 0;
+        for (i, &byte) in buf[..len].iter().enumerate() {
+            if !prefixed {
+                if byte == PREFIX {
+                    forward(session, &buf[start..i]);
+                    start = i + 1;
+                    prefixed = true;
+                }
+                continue;
+            }
+
+            prefixed = false;
+            start = i + 1;
+            match byte {
+                b'%' => split(session, Split::Vertical),
+                b'"' => split(session, Split::Horizontal),
+                b'o' | b'n' => session.focus_next(),
+                b'p' => session.focus_previous(),
+                b'x' => {
+                    if let Some(pane) = session.focused_pane() {
+                        if session.close_pane(pane.id()) {
+                            return Ok(());
+                        }
+                    }
+                }
+                b'd' => return Ok(()),
+                PREFIX => forward(session, &[PREFIX]),
+                _ => {}
+            }
+        }
+        if !prefixed {
+            forward(session, &buf[start..len]);
+        }
+    }
+    Ok(())
+}
+
+fn forward(session: &Session, bytes: &[u8]) {
+    if bytes.is_empty() {
+        return;
+    }
+    if let Some(pane) = session.focused_pane() {
+        if let Err(e) = pane.write(bytes) {
+            log::error!("mux: {}", e);
+        }
+    }
+}
+
+fn split(session: &Session, split: Split) {
+    if let Err(e) = session.split(split) {
+        log::error!("mux: couldn't split pane: {}", e);
+    }
+}
+
+/// Periodically redraws the panes of the session whose contents have changed.
+fn render_loop(
+    (session, stdout, stop, columns, rows): (Arc<Session>, Arc<dyn ImmutableWrite>, Arc<AtomicBool>, usize, usize),
+) {
+    let mut previous_layout: Vec<(usize, Rect)> = Vec::new();
+    let mut previous_focused = None;
+    let mut output = Vec::new();
+
+    while !stop.load(Ordering::Acquire) {
+        if session.reap_exited_panes() {
+            let _ = stdout.write_all(b"\x1b[2J\x1b[H[mux: all panes exited, press any key]");
+            stop.store(true, Ordering::Release);
+            break;
+        }
+
+        let panes = session.panes();
+        let layout: Vec<(usize, Rect)> = panes.iter().map(|(p, rect)| (p.id(), *rect)).collect();
+        let focused = session.focused_pane().map(|p| p.id());
+        let full_redraw = layout != previous_layout || focused != previous_focused;
+
+        output.clear();
+        if full_redraw {
+            output.extend_from_slice(b"\x1b[2J");
+            for (_, rect) in &layout {
+                draw_borders(&mut output, rect, columns, rows - 1);
+            }
+            draw_status_line(&mut output, &session, focused, rows);
+        }
+
+        let mut cursor = None;
+        for (pane, rect) in &panes {
+            let mut screen = pane.screen();
+            if screen.take_dirty() || full_redraw {
+                for row in 0..screen.rows().min(rect.rows) {
+                    move_to(&mut output, rect.column, rect.row + row);
+                    let line = screen.row(row);
+                    output.extend_from_slice(&line[..line.len().min(rect.columns)]);
+                }
+            }
+            if Some(pane.id()) == focused {
+                let (column, row) = screen.cursor();
+                cursor = Some((rect.column + column, rect.row + row));
+            }
+        }
+
+        if !output.is_empty() {
+            if let Some((column, row)) = cursor {
+                move_to(&mut output, column, row);
+            }
+            let _ = stdout.write_all(&output);
+        }
+        previous_layout = layout;
+        previous_focused = focused;
+
+        if sleep::sleep(RENDER_INTERVAL).is_err() {
+            break;
+        }
+    }
+}
+
+/// Draws a border along the right and bottom edges of `rect`, if another pane
+/// lies beyond them.
+fn draw_borders(output: &mut Vec<u8>, rect: &Rect, columns: usize, rows: usize) {
+    let right = rect.column + rect.columns;
+    if right < columns {
+        for row in rect.row..rect.row + rect.rows {
+            move_to(output, right, row);
+            output.push(b'|');
+        }
+    }
+    let bottom = rect.row + rect.rows;
+    if bottom < rows {
+        move_to(output, rect.column, bottom);
+        output.extend(core::iter::repeat(b'-').take(rect.columns));
+    }
+}
+
+fn draw_status_line(output: &mut Vec<u8>, session: &Session, focused: Option<usize>, rows: usize) {
+    move_to(output, 0, rows - 1);
+    output.extend_from_slice(b"\x1b[7m[");
+    output.extend_from_slice(session.name().as_bytes());
+    output.push(b']');
+    for (pane, _) in session.panes() {
+        let marker = if Some(pane.id()) == focused { "*" } else { "" };
+        output.extend_from_slice(format!(" {}{}", pane.id(), marker).as_bytes());
+    }
+    output.extend_from_slice(b"\x1b[0m");
+}
+
+fn move_to(output: &mut Vec<u8>, column: usize, row: usize) {
+    output.extend_from_slice(format!("\x1b[{};{}H", row + 1, column + 1).as_bytes());
+}
+
+fn next_session_name() -> String {
+    let existing = tty_mux::sessions();
+    (0..)
+        .map(|i: usize| i.to_string())
+        .find(|name| !existing.iter().any(|(n, _)| n == name))
+        .unwrap()
+}
+
+fn parse_size(size: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("invalid terminal size {size:?}, expected COLUMNSxROWS");
+    let (columns, rows) = size.split_once('x').ok_or_else(invalid)?;
+    let columns: usize = columns.parse().map_err(|_| invalid())?;
+    let rows: usize = rows.parse().map_err(|_| invalid())?;
+    if columns < 2 || rows < 2 {
+        return Err(invalid());
+    }
+    Ok((columns, rows))
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: mux [OPTION]
+Runs several shells in split panes of one terminal.
+With no options, creates a new session with an automatically-chosen name.
+Key bindings follow the Ctrl+B prefix:
+  %  split left/right     \"  split top/bottom
+  o  next pane            p  previous pane
+  x  close pane           d  detach";
@@ -0,0 +1,106 @@
+//! This application connects to and disconnects from network block devices via the `nbd_client` crate.
+//!
+//! Examples:
+//! * `nbd`: list the connected network block devices.
+//! * `nbd connect 10.0.2.2 disk`: connect to the default export of the NBD server
+//!   at 10.0.2.2 (the QEMU host) and name the device "disk".
+//! * `nbd connect -p 10810 -e scratch 10.0.2.2 disk`: connect to the export named "scratch"
+//!   on port 10810.
+//! * `nbd disconnect disk`: disconnect the device named "disk".
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use app_io::println;
+use core::str::FromStr;
+use getopts::{Matches, Options};
+use nbd_client::NbdDevice;
+use net::{IpAddress, IpEndpoint};
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("p", "port", "the TCP port of the NBD server (default: 10809)", "PORT");
+    opts.optopt("e", "export", "the name of the export to connect to (default: the server's default export)", "NAME");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    let free: Vec<&str> = matches.free.iter().map(String::as_str).collect();
+    let result = match free.as_slice() {
+        [] => {
+            list();
+            Ok(())
+        }
+        ["connect", server, name] => connect(&matches, server, name),
+        ["disconnect", name] => disconnect(name),
+        _ => {
+            print_usage(opts);
+            return -1;
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Error: {}", e);
+            -1
+        }
+    }
+}
+
+fn list() {
+    for name in nbd_client::device_names() {
+        if let Some(device) = nbd_client::device(&name) {
+            let dev = device.lock();
+            println!("{}: {} blocks of {} bytes", name, dev.size_in_blocks(), dev.block_size());
+        }
+    }
+}
+
+fn connect(matches: &Matches, server: &str, name: &str) -> Result<(), &'static str> {
+    let address = IpAddress::from_str(server).map_err(|_| "invalid server IP address")?;
+    let port = matches
+        .opt_get_default("p", nbd_client::DEFAULT_PORT)
+        .map_err(|_| "invalid port")?;
+    let export_name = matches.opt_str("e").unwrap_or_default();
+
+    let interface = net::get_default_interface().ok_or("no network interfaces available")?;
+    let device = NbdDevice::connect(interface, IpEndpoint::new(address, port), &export_name)?;
+    let read_only = device.is_read_only();
+    nbd_client::add_device(name, device)?;
+    println!(
+        "Connected network block device {:?}{}.",
+        name,
+        if read_only { " (read-only)" } else { "" },
+    );
+    Ok(())
+}
+
+fn disconnect(name: &str) -> Result<(), &'static str> {
+    nbd_client::remove_device(name).ok_or("no network block device has that name")?;
+    println!("Disconnected network block device {:?}.", name);
+    Ok(())
+}
+
+const USAGE: &str = "Usage: nbd [OPTION]
+       nbd connect [-p PORT] [-e EXPORT] SERVER NAME
+       nbd disconnect NAME
+Connects to and disconnects from exports on network block device (NBD) servers.
+Connected devices can be used like any other storage device.";
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
@@ -1,5 +1,10 @@
-//! This application allows querying about and interacting with namespaces in Theseus, 
+//! This application allows querying about and interacting with namespaces in Theseus,
 //! specifically `CrateNamespace`s.
+//!
+//! Namespaces can be referred to by name if they are registered (see [`mod_mgmt::register_namespace()`]),
+//! which includes the initial kernel namespace and all namespaces created by `ns create`,
+//! or if they are in the chain of recursive namespaces of the current task's namespace.
+//! The current task's namespace itself is referred to as `.`.
 
 #![no_std]
 extern crate alloc;
@@ -23,8 +28,8 @@ use alloc::{
     vec::Vec,
 };
 use getopts::{Options, Matches};
-use mod_mgmt::CrateNamespace;
-use fs_node::FileRef;
+use mod_mgmt::{CrateNamespace, NamespaceDir};
+use fs_node::{DirRef, FileRef};
 use path::PathBuf;
 
 
@@ -34,13 +39,14 @@ pub fn main(args: Vec<String>) -> isize {
     opts.optflag("r", "recursive", "include recursive namespaces");
     opts.optflag("f", "files", "lists crate object files available in this namespace rather than currently-loaded crates");
     opts.optopt("", "load", "load a crate into the current namespace. Ignores all other arguments.", "CRATE_OBJ_FILE_PATH");
+    opts.optopt("", "lazy-sections", "set whether crates subsequently loaded into the current namespace defer creating metadata for their local sections. Ignores all other arguments.", "on|off");
 
     let matches = match opts.parse(args) {
         Ok(m) => m,
         Err(_f) => {
             println!("{}", _f);
             print_usage(opts);
-            return -1; 
+            return -1;
         }
     };
 
@@ -67,12 +73,54 @@ fn rmain(matches: Matches) -> Result<(), String> {
     let recursive = matches.opt_present("r");
     let mut output = String::new();
 
-    if let Some(crate_obj_file_path) = matches.opt_str("load") {
+    if let Some(setting) = matches.opt_str("lazy-sections") {
+        let enable = parse_on_off("--lazy-sections", &setting)?;
+        namespace.set_lazy_section_metadata(enable);
+        writeln!(output, "Lazy section metadata is now {} for namespace {}", setting, namespace.name()).unwrap();
+    } else if let Some(crate_obj_file_path) = matches.opt_str("load") {
         let path = PathBuf::from(crate_obj_file_path);
         let file = path.get_file(&curr_wd).ok_or_else(||
             format!("Couldn't resolve path to crate object file at {path:?}")
         )?;
         load_crate(&mut output, file, &namespace)?;
+    } else if let Some((command, args)) = matches.free.split_first() {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match (command.as_str(), args.as_slice()) {
+            ("list", []) => list_namespaces(&mut output, &namespace)
+                .map_err(|_e| String::from("String formatting error"))?,
+            ("show", [ns_name]) => {
+                let target = find_namespace(&namespace, ns_name)?;
+                show_namespace(&mut output, &target, recursive)
+                    .map_err(|_e| String::from("String formatting error"))?;
+            }
+            ("create", [ns_name, dir_path, rest @ ..]) if rest.len() <= 1 => {
+                let recursive_namespace = rest.first()
+                    .map(|r_ns_name| find_namespace(&namespace, r_ns_name))
+                    .transpose()?;
+                create_namespace(&mut output, ns_name, dir_path, &curr_wd, recursive_namespace)?;
+            }
+            ("remove", [ns_name]) => {
+                mod_mgmt::unregister_namespace(ns_name)
+                    .ok_or_else(|| format!("No namespace named {ns_name:?} was created with `ns create`"))?;
+                writeln!(output, "Removed namespace {ns_name}; it will be dropped once no tasks are running in it").unwrap();
+            }
+            ("load", [ns_name, crate_file]) => {
+                let target = find_namespace(&namespace, ns_name)?;
+                let file = find_crate_object_file(&target, crate_file, &curr_wd)?;
+                load_crate(&mut output, file, &target)?;
+            }
+            ("unload", [ns_name, crate_name]) => {
+                let target = find_namespace(&namespace, ns_name)?;
+                unload_crate(&mut output, &target, crate_name)?;
+            }
+            ("fuzzy", [ns_name, setting]) => {
+                let target = find_namespace(&namespace, ns_name)?;
+                let enable = parse_on_off("fuzzy", setting)?;
+                target.set_fuzzy_symbol_matching(enable);
+                writeln!(output, "Fuzzy symbol matching is now {} for namespace {}", setting, target.name()).unwrap();
+            }
+            _ => return Err(format!("Invalid command or arguments: {:?}. Run `ns --help` for usage.", matches.free)),
+        }
     } else if matches.opt_present("f") {
         print_files(&mut output, 0, namespace.deref(), recursive)
             .map_err(|_e| String::from("String formatting error"))?;
@@ -86,6 +134,169 @@ fn rmain(matches: Matches) -> Result<(), String> {
 }
 
 
+fn parse_on_off(setting_name: &str, setting: &str) -> Result<bool, String> {
+    match setting {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("Invalid {setting_name} setting {setting:?}, expected \"on\" or \"off\"")),
+    }
+}
+
+
+/// Finds the namespace with the given name, which is either `.` for the current namespace,
+/// the name of a registered namespace, or the name of a namespace in the current namespace's recursive chain.
+fn find_namespace(current: &Arc<CrateNamespace>, ns_name: &str) -> Result<Arc<CrateNamespace>, String> {
+    if ns_name == "." {
+        return Ok(Arc::clone(current));
+    }
+    if let Some(ns) = mod_mgmt::get_registered_namespace(ns_name) {
+        return Ok(ns);
+    }
+    let mut ns = Some(current);
+    while let Some(n) = ns {
+        if n.name() == ns_name {
+            return Ok(Arc::clone(n));
+        }
+        ns = n.recursive_namespace();
+    }
+    Err(format!("Couldn't find namespace {ns_name:?}. Run `ns list` to see all known namespaces."))
+}
+
+
+/// Returns the chain of recursive namespaces starting at (and including) the given `namespace`.
+fn recursive_chain(namespace: &Arc<CrateNamespace>) -> Vec<Arc<CrateNamespace>> {
+    let mut chain = Vec::new();
+    let mut ns = Some(namespace);
+    while let Some(n) = ns {
+        chain.push(Arc::clone(n));
+        ns = n.recursive_namespace();
+    }
+    chain
+}
+
+
+fn list_namespaces(output: &mut String, current: &Arc<CrateNamespace>) -> core::fmt::Result {
+    // Collect all registered namespaces and all namespaces that tasks are running in,
+    // along with their recursive namespaces, without duplicates.
+    let registered = mod_mgmt::registered_namespaces();
+    let mut namespaces: Vec<(Arc<CrateNamespace>, usize)> = Vec::new();
+    let mut add = |ns: &Arc<CrateNamespace>, is_task_ns: bool| {
+        for n in recursive_chain(ns) {
+            let num_tasks = usize::from(is_task_ns && Arc::ptr_eq(&n, ns));
+            match namespaces.iter_mut().find(|(existing, _)| Arc::ptr_eq(existing, &n)) {
+                Some((_, tasks)) => *tasks += num_tasks,
+                None => namespaces.push((n, num_tasks)),
+            }
+        }
+    };
+    for ns in &registered {
+        add(ns, false);
+    }
+    for (_id, weak_task) in task::all_tasks() {
+        if let Some(t) = weak_task.upgrade() {
+            add(t.get_namespace(), true);
+        }
+    }
+
+    writeln!(output, "{:<24} {:>8} {:>6} {:<24} FLAGS", "NAMESPACE", "CRATES", "TASKS", "RECURSIVE")?;
+    for (ns, num_tasks) in namespaces {
+        let mut flags = String::new();
+        if Arc::ptr_eq(&ns, current) { flags.push_str("current "); }
+        if registered.iter().any(|r| Arc::ptr_eq(r, &ns)) { flags.push_str("registered "); }
+        if ns.fuzzy_symbol_matching() { flags.push_str("fuzzy "); }
+        if ns.lazy_section_metadata() { flags.push_str("lazy-sections "); }
+        if ns.strict_symbol_conflicts() { flags.push_str("strict "); }
+        writeln!(output, "{:<24} {:>8} {:>6} {:<24} {}",
+            ns.name(),
+            ns.crate_names(false).len(),
+            num_tasks,
+            ns.recursive_namespace().map_or("-", |r_ns| r_ns.name()),
+            flags.trim_end(),
+        )?;
+    }
+    Ok(())
+}
+
+
+fn show_namespace(output: &mut String, namespace: &Arc<CrateNamespace>, recursive: bool) -> core::fmt::Result {
+    write!(output, "Namespace {}", namespace.name())?;
+    for r_ns in recursive_chain(namespace).iter().skip(1) {
+        write!(output, " -> {}", r_ns.name())?;
+    }
+    writeln!(output)?;
+    writeln!(output, "  directory: {}", namespace.dir().lock().get_absolute_path())?;
+    writeln!(output, "  fuzzy symbol matching: {}", on_off(namespace.fuzzy_symbol_matching()))?;
+    writeln!(output, "  lazy section metadata: {}", on_off(namespace.lazy_section_metadata()))?;
+    writeln!(output, "  strict symbol conflicts: {}", on_off(namespace.strict_symbol_conflicts()))?;
+    let usage = namespace.memory_usage();
+    for (kind, bytes, num_crates) in [
+        ("exclusive", usage.exclusive, usage.exclusive_crates),
+        ("shared", usage.shared, usage.shared_crates),
+    ] {
+        writeln!(output, "  {} memory: {} bytes in {} crates (text: {}, rodata: {}, data: {}, tls: {})",
+            kind, bytes.total(), num_crates, bytes.text, bytes.rodata, bytes.data, bytes.tls,
+        )?;
+    }
+    print_crates(output, 0, namespace.deref(), recursive)
+}
+
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled { "on" } else { "off" }
+}
+
+
+fn create_namespace(
+    output: &mut String,
+    ns_name: &str,
+    dir_path: &str,
+    curr_wd: &DirRef,
+    recursive_namespace: Option<Arc<CrateNamespace>>,
+) -> Result<(), String> {
+    let path = PathBuf::from(dir_path.to_string());
+    let dir = path.get_dir(curr_wd).ok_or_else(||
+        format!("Couldn't resolve path to namespace directory at {path:?}")
+    )?;
+    let recursive_namespace = match recursive_namespace {
+        Some(r_ns) => r_ns,
+        None => mod_mgmt::get_initial_kernel_namespace()
+            .cloned()
+            .ok_or_else(|| String::from("initial kernel namespace not yet initialized"))?,
+    };
+    let new_namespace = Arc::new(CrateNamespace::new(
+        ns_name.to_string(),
+        NamespaceDir::new(dir),
+        Some(Arc::clone(&recursive_namespace)),
+    ));
+    mod_mgmt::register_namespace(new_namespace).map_err(String::from)?;
+    writeln!(output, "Created namespace {} over {}, atop namespace {}",
+        ns_name, path, recursive_namespace.name(),
+    ).unwrap();
+    Ok(())
+}
+
+
+/// Finds the crate object file given by `crate_file`, which is either a path to the file
+/// or the prefix of a file name in one of the given `namespace`'s own search directories.
+fn find_crate_object_file(namespace: &Arc<CrateNamespace>, crate_file: &str, curr_wd: &DirRef) -> Result<FileRef, String> {
+    let path = PathBuf::from(crate_file.to_string());
+    if let Some(file) = path.get_file(curr_wd) {
+        return Ok(file);
+    }
+    match CrateNamespace::get_crate_object_file_starting_with(namespace, crate_file) {
+        Some((file, ns)) if Arc::ptr_eq(ns, namespace) => Ok(file),
+        Some((_file, ns)) => Err(format!(
+            "Crate object file {crate_file:?} was found in recursive namespace {}, not in namespace {}",
+            ns.name(), namespace.name(),
+        )),
+        None => Err(format!(
+            "Couldn't find a path to or a single crate object file starting with {crate_file:?} in namespace {}",
+            namespace.name(),
+        )),
+    }
+}
+
+
 fn load_crate(output: &mut String, crate_file_ref: FileRef, namespace: &Arc<CrateNamespace>) -> Result<(), String> {
     let kernel_mmi_ref = memory::get_kernel_mmi_ref().ok_or_else(|| "Cannot get kernel_mmi_ref".to_string())?;
     let (_new_crate_ref, _new_syms) = namespace.load_crate(
@@ -93,18 +304,42 @@ fn load_crate(output: &mut String, crate_file_ref: FileRef, namespace: &Arc<Crat
         None,
         kernel_mmi_ref,
         false
-    ).map_err(String::from)?;
+    ).map_err(|e| e.to_string())?;
     writeln!(output, "Loaded crate with {} new symbols from {}", _new_syms, crate_file_ref.lock().get_absolute_path()).unwrap();
     Ok(())
 }
 
 
+/// Unloads the crate with the given name, or the single crate whose name starts with it,
+/// from the given namespace. Crates in recursive namespaces are not considered.
+fn unload_crate(output: &mut String, namespace: &Arc<CrateNamespace>, crate_name: &str) -> Result<(), String> {
+    let matches: Vec<String> = CrateNamespace::get_crates_starting_with(namespace, crate_name)
+        .into_iter()
+        .filter(|(_, _, ns)| Arc::ptr_eq(ns, namespace))
+        .map(|(name, _, _)| name.to_string())
+        .collect();
+    let full_name = match matches.as_slice() {
+        _ if matches.iter().any(|name| name == crate_name) => crate_name.to_string(),
+        [name] => name.clone(),
+        [] => return Err(format!("No crate matching {crate_name:?} is loaded in namespace {}", namespace.name())),
+        _ => return Err(format!("Multiple crates in namespace {} match {crate_name:?}: {matches:?}", namespace.name())),
+    };
+    namespace.unload_crate(&full_name).map_err(String::from)?;
+    writeln!(output, "Unloaded crate {} from namespace {}", full_name, namespace.name()).unwrap();
+    Ok(())
+}
+
+
 fn print_files(output: &mut String, indent: usize, namespace: &CrateNamespace, recursive: bool) -> core::fmt::Result {
     writeln!(output, "\n{:indent$}{} CrateNamespace has crate object files:", "", namespace.name(), indent = indent)?;
-    let mut files = namespace.dir().lock().list();
-    files.sort();
-    for f in files {
-        writeln!(output, "{:indent$}{}", "", f, indent = (indent + 4))?;
+    for dir in namespace.search_dirs() {
+        let dir_locked = dir.lock();
+        writeln!(output, "{:indent$}in {}:", "", dir_locked.get_absolute_path(), indent = (indent + 2))?;
+        let mut files = dir_locked.list();
+        files.sort();
+        for f in files {
+            writeln!(output, "{:indent$}{}", "", f, indent = (indent + 4))?;
+        }
     }
 
     if recursive {
@@ -145,5 +380,19 @@ fn print_usage(opts: Options) {
 }
 
 
-const USAGE: &str = "\nUsage: ns [OPTION]
-Lists the crates that are loaded in the currently-active crate namespace.";
+const USAGE: &str = "\nUsage: ns [OPTION]... [COMMAND [ARGS]...]
+Inspects and manipulates crate namespaces.
+With no command, lists the crates that are loaded in the currently-active crate namespace.
+
+Commands:
+  list                              list all registered namespaces and namespaces that tasks are running in
+  show NAMESPACE                    show a namespace's settings, recursive namespaces, memory usage, and loaded crates
+  create NAME DIR [RECURSIVE_NS]    create and register a namespace over the crate object files in DIR,
+                                    atop RECURSIVE_NS (default: the initial kernel namespace)
+  remove NAME                       unregister a namespace that was created with `create`
+  load NAMESPACE CRATE_FILE         load a crate object file (a path or a file name prefix) into a namespace
+  unload NAMESPACE CRATE            unload a crate (a name or unique name prefix) from a namespace
+  fuzzy NAMESPACE on|off            set whether a namespace ignores symbol hashes when resolving dependencies
+
+NAMESPACE is either `.` for the current namespace, the name of a registered namespace,
+or the name of a namespace recursively underneath the current namespace.";
@@ -4,17 +4,24 @@ extern crate alloc;
 
 extern crate task;
 extern crate getopts;
-extern crate scheduler;
+extern crate heap;
+extern crate memory;
 
 use getopts::Options;
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use core::fmt::Write;
+use core::time::Duration;
+use task::RunState;
 
 pub fn main(args: Vec<String>) -> isize {
     let mut opts = Options::new();
     opts.optflag("h", "help", "print this help menu");
     opts.optflag("b", "brief", "print only task id and name");
+    opts.optflag("c", "crates", "print the memory used by each crate instead of tasks");
+    opts.optflag("j", "json", "print tasks as a JSON array instead of a table");
+    opts.optflag("", "no-color", "don't colorize the table");
 
     let matches = match opts.parse(args) {
         Ok(m) => m,
@@ -28,56 +35,227 @@ pub fn main(args: Vec<String>) -> isize {
         return print_usage(opts)
     }
 
-    // Print headers
-    if matches.opt_present("b") {
-        println!("{0:<5}  {1}", "ID", "NAME");
+    if matches.opt_present("c") {
+        return print_crate_memory();
     }
-    else {
-        #[cfg(any(epoch_scheduler, priority_scheduler))] {
-            println!("{0:<5}  {1:<10}  {2:<4}  {3:<4}  {4:<5}  {5:<10}  {6}", "ID", "RUNSTATE", "CPU", "PIN", "TYPE", "PRIORITY", "NAME");
-        }
-        #[cfg(not(any(epoch_scheduler, priority_scheduler)))] {
-            println!("{0:<5}  {1:<10}  {2:<4}  {3:<4}  {4:<5}  {5}", "ID", "RUNSTATE", "CPU", "PIN", "TYPE", "NAME");
-        }
+
+    let tasks = task::all_task_info();
+    if matches.opt_present("j") {
+        print!("{}", tasks_to_json(&tasks));
+        return 0;
     }
 
-    // Print all tasks
-    let mut num_tasks = 0;
     let mut task_string = String::new();
-    for (id, wtask) in task::all_tasks() {
-        let Some(task) = wtask.upgrade() else { continue };
-        num_tasks += 1;
-        if matches.opt_present("b") {
-            writeln!(task_string, "{0:<5}  {1}", id, task.name).expect("Failed to write to task_string.");
+    if matches.opt_present("b") {
+        writeln!(task_string, "{0:<5}  {1}", "ID", "NAME").expect("Failed to write to task_string.");
+        for info in &tasks {
+            writeln!(task_string, "{0:<5}  {1}", info.id, info.name).expect("Failed to write to task_string.");
         }
-        else {
-            // All printed fields below must be strings to ensure the width formatting specifier below works properly.
-            let runstate = format!("{:?}", task.runstate());
-            let cpu = task.running_on_cpu().map(|cpu| format!("{cpu}")).unwrap_or_else(|| String::from("-"));
-            let pinned = task.pinned_cpu().map(|pin| format!("{pin}")).unwrap_or_else(|| String::from("-"));
-            let task_type = if task.is_an_idle_task {"I"}
-                else if task.is_application() {"A"}
-                else {" "} ;
-
-            #[cfg(any(epoch_scheduler, priority_scheduler))] {
-                let priority = scheduler::priority(&task).map(|priority| format!("{}", priority)).unwrap_or_else(|| String::from("-"));
-                task_string.push_str(
-                    &format!("{0:<5}  {1:<10}  {2:<4}  {3:<4}  {4:<5}  {5:<10}  {6}\n", 
-                    id, runstate, cpu, pinned, task_type, priority, task.name)
-                );
-            }
-            #[cfg(not(any(epoch_scheduler, priority_scheduler)))] {
-                writeln!(task_string, "{0:<5}  {1:<10}  {2:<4}  {3:<4}  {4:<5}  {5}", 
-                    id, runstate, cpu, pinned, task_type, task.name).expect("Failed to write to task_string.");
+    }
+    else {
+        let color = !matches.opt_present("no-color");
+        // All printed fields below must be strings to ensure the width formatting specifier below works properly.
+        let rows: Vec<[String; 13]> = tasks.iter().map(|info| [
+            info.id.to_string(),
+            format!("{:?}", info.runstate),
+            info.running_on_cpu.map(|cpu| format!("{cpu}")).unwrap_or_else(|| String::from("-")),
+            info.pinned_cpu.map(|pin| format!("{pin}")).unwrap_or_else(|| String::from("-")),
+            String::from(task_type(info)),
+            info.priority.map(|priority| priority.to_string()).unwrap_or_else(|| String::from("-")),
+            heap::task_heap_usage(info.id).map(|usage| format_bytes(usage.live_bytes)).unwrap_or_else(|| String::from("-")),
+            info.stack_usage.map(|usage| {
+                let mut s = format_bytes(usage.max_used_bytes);
+                if usage.is_near_overflow() { s.push('!'); }
+                s
+            }).unwrap_or_else(|| String::from("-")),
+            format_permille(info.runtime_stats.cpu_permille()),
+            format_duration(info.runtime_stats.runtime),
+            info.namespace.clone(),
+            info.app_crate.clone().unwrap_or_else(|| String::from("-")),
+            info.name.clone(),
+        ]).collect();
+
+        let headers = ["ID", "RUNSTATE", "CPU", "PIN", "TYPE", "PRIORITY", "HEAP", "STACK", "CPU%", "TIME", "NAMESPACE", "CRATE", "NAME"];
+        let mut widths = headers.map(str::len);
+        for row in &rows {
+            for (width, field) in widths.iter_mut().zip(row) {
+                *width = (*width).max(field.len());
             }
         }
+
+        write_row(&mut task_string, &headers.map(String::from), &widths, |_| None);
+        for (info, row) in tasks.iter().zip(&rows) {
+            write_row(&mut task_string, row, &widths, |column| match column {
+                1 if color => runstate_color(info.runstate),
+                7 if color && info.stack_usage.is_some_and(|usage| usage.is_near_overflow()) => Some(RED),
+                _ => None,
+            });
+        }
     }
     print!("{}", task_string);
-    println!("Total number of tasks: {}", num_tasks);
+    println!("Total number of tasks: {}", tasks.len());
+    if !matches.opt_present("b") {
+        let (untracked_bytes, _) = heap::untracked_heap_usage();
+        let exited_bytes: usize = heap::all_task_heap_usage().iter()
+            .filter(|usage| usage.exited)
+            .map(|usage| usage.live_bytes)
+            .sum();
+        println!("Heap not attributed to a running task: {} (exited tasks: {})",
+            format_bytes(untracked_bytes + exited_bytes), format_bytes(exited_bytes));
+    }
     
     0
 }
 
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const GRAY: &str = "\x1b[90m";
+const RESET: &str = "\x1b[0m";
+
+fn task_type(info: &task::TaskInfo) -> &'static str {
+    if info.is_idle_task { "I" }
+    else if info.app_crate.is_some() { "A" }
+    else { "-" }
+}
+
+fn runstate_color(runstate: RunState) -> Option<&'static str> {
+    match runstate {
+        RunState::Runnable => Some(GREEN),
+        RunState::Blocked => Some(YELLOW),
+        RunState::Initing | RunState::Exited | RunState::Reaped => Some(GRAY),
+    }
+}
+
+/// Writes one row of aligned columns, coloring each column for which `color` returns an ANSI color code.
+///
+/// The last column is not padded.
+fn write_row<F>(output: &mut String, fields: &[String], widths: &[usize], color: F)
+    where F: Fn(usize) -> Option<&'static str>
+{
+    for (column, (field, width)) in fields.iter().zip(widths).enumerate() {
+        if column > 0 {
+            output.push_str("  ");
+        }
+        let padded = if column + 1 == fields.len() {
+            field.clone()
+        } else {
+            format!("{field:<width$}")
+        };
+        match color(column) {
+            Some(code) => write!(output, "{code}{padded}{RESET}"),
+            None => write!(output, "{padded}"),
+        }.expect("Failed to write to output.");
+    }
+    output.push('\n');
+}
+
+/// Formats the given tasks as a JSON array of objects, one per task.
+fn tasks_to_json(tasks: &[task::TaskInfo]) -> String {
+    fn opt<T: core::fmt::Display>(value: Option<T>) -> String {
+        value.map(|v| v.to_string()).unwrap_or_else(|| String::from("null"))
+    }
+
+    let mut json = String::from("[\n");
+    for (i, info) in tasks.iter().enumerate() {
+        let heap_bytes = heap::task_heap_usage(info.id).map(|usage| usage.live_bytes);
+        write!(json,
+            "  {{\"id\": {}, \"name\": {}, \"namespace\": {}, \"crate\": {}, \"type\": {}, \"runstate\": {}, \
+            \"cpu\": {}, \"pinned_cpu\": {}, \"priority\": {}, \"heap_bytes\": {}, \"stack_used_bytes\": {}, \"stack_size_bytes\": {}, \
+            \"runtime_ns\": {}, \"context_switches\": {}, \"cpu_permille\": {}}}",
+            info.id,
+            json_string(&info.name),
+            json_string(&info.namespace),
+            info.app_crate.as_deref().map(json_string).unwrap_or_else(|| String::from("null")),
+            json_string(task_type(info)),
+            json_string(&format!("{:?}", info.runstate)),
+            opt(info.running_on_cpu.map(|cpu| cpu.value())),
+            opt(info.pinned_cpu.map(|cpu| cpu.value())),
+            opt(info.priority),
+            opt(heap_bytes),
+            opt(info.stack_usage.map(|usage| usage.max_used_bytes)),
+            opt(info.stack_usage.map(|usage| usage.size_in_bytes)),
+            info.runtime_stats.runtime.as_nanos(),
+            info.runtime_stats.context_switches,
+            info.runtime_stats.cpu_permille(),
+        ).expect("Failed to write to json.");
+        json.push_str(if i + 1 < tasks.len() { ",\n" } else { "\n" });
+    }
+    json.push_str("]\n");
+    json
+}
+
+/// Quotes and escapes the given string as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => { let _ = write!(quoted, "\\u{:04x}", c as u32); }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Prints the size of each crate's loaded sections and of the memory mappings it created.
+fn print_crate_memory() -> isize {
+    let Ok(namespace) = task::with_current_task(|t| t.get_namespace().clone()) else {
+        println!("failed to get current task");
+        return -1;
+    };
+
+    // Maps each crate name to its (section bytes, mapped bytes).
+    let mut crates: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    namespace.for_each_crate(true, |_, crate_ref| {
+        let krate = crate_ref.lock_as_ref();
+        crates.entry(krate.crate_name_without_hash().to_string()).or_default().0 += krate.resident_size_in_bytes();
+        true
+    });
+    for (crate_name, mapped_bytes) in memory::mapped_bytes_by_crate() {
+        crates.entry(crate_name.to_string()).or_default().1 += mapped_bytes;
+    }
+
+    println!("{0:<10}  {1:<10}  {2}", "SECTIONS", "MAPPINGS", "CRATE");
+    let mut output = String::new();
+    for (crate_name, (section_bytes, mapped_bytes)) in crates.iter() {
+        writeln!(output, "{0:<10}  {1:<10}  {2}", format_bytes(*section_bytes), format_bytes(*mapped_bytes), crate_name)
+            .expect("Failed to write to output.");
+    }
+    print!("{}", output);
+    let total_sections: usize = crates.values().map(|(s, _)| s).sum();
+    let total_mappings: usize = crates.values().map(|(_, m)| m).sum();
+    println!("Total: {} in sections, {} in mappings ({} unattributed)",
+        format_bytes(total_sections), format_bytes(total_mappings), format_bytes(memory::unattributed_mapped_bytes()));
+    0
+}
+
+/// Formats a number in tenths of a percent as a percentage with one decimal place.
+fn format_permille(permille: u64) -> String {
+    format!("{}.{}", permille / 10, permille % 10)
+}
+
+/// Formats a duration as seconds with millisecond precision.
+fn format_duration(duration: Duration) -> String {
+    format!("{}.{:03}s", duration.as_secs(), duration.subsec_millis())
+}
+
+/// Formats a number of bytes in the largest unit that keeps it at least 1.
+fn format_bytes(bytes: usize) -> String {
+    const KIB: usize = 1024;
+    const MIB: usize = 1024 * KIB;
+    if bytes >= MIB {
+        format!("{}.{} MiB", bytes / MIB, (bytes % MIB) * 10 / MIB)
+    } else if bytes >= KIB {
+        format!("{}.{} KiB", bytes / KIB, (bytes % KIB) * 10 / KIB)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
 fn print_usage(opts: Options) -> isize {
     println!("{}", opts.usage(BRIEF));
     0
@@ -88,6 +266,18 @@ const BRIEF: &str = "Usage: ps [options]\n
     CPU:       the cpu core the task is currently running on.
     PIN:       the core the task is pinned on, if any.
     RUNSTATE:  runnability status of this task, e.g., whether it can be scheduled in.
+    PRIORITY:  the task's priority, if it is scheduled by a priority scheduler.
+    HEAP:      the heap memory allocated by this task that hasn't yet been freed.
+               Only tracked if Theseus is built with THESEUS_CONFIG=\"task_heap_usage\".
+    STACK:     the maximum stack depth this task has reached, followed by '!' if it is close to overflowing.
+    CPU%:      the share of its lifetime that the task has spent running.
+    TIME:      the total CPU time the task has used.
+    NAMESPACE: the crate namespace the task runs in.
+    CRATE:     the application crate the task was spawned from, if any.
     ID:        the unique identifier for this task.
-    NAME:      the name of the task.";
+    NAME:      the name of the task.
+
+    With -c, for each crate:
+    SECTIONS:  the size of the crate's loaded sections.
+    MAPPINGS:  the size of the memory mappings created by the crate's code.";
     
\ No newline at end of file
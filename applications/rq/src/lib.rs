@@ -35,7 +35,8 @@ pub fn main(args: Vec<String>) -> isize {
             "Secondary CPU"
         };
 
-        println!("\n{} (CPU: {})", core_type, cpu);
+        let isolated = if task::scheduler::is_isolated(cpu) { ", isolated" } else { "" };
+        println!("\n{} (CPU: {}{})", core_type, cpu, isolated);
 
         let mut runqueue_contents = String::new();
         for task in task_list.iter() {
@@ -0,0 +1,109 @@
+//! This application controls the recording of timer-based profiling scopes
+//! (see the `profile_scope` crate) and prints the resulting call tree,
+//! or the folded stacks that host flamegraph tools accept as input.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use app_io::{print, println};
+use getopts::{Matches, Options};
+use profile_scope::CallTreeNode;
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("n", "capacity", "with `start`, the number of scopes recorded per CPU (default: 4096)", "SCOPES");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    match run(&matches) {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Error: {}", e);
+            -1
+        }
+    }
+}
+
+fn run(matches: &Matches) -> Result<(), String> {
+    match matches.free.first().map(String::as_str).unwrap_or("status") {
+        "start" => {
+            let capacity = matches.opt_str("n")
+                .map(|n| n.parse::<usize>().map_err(|_| alloc::format!("invalid capacity {:?}", n)))
+                .transpose()?
+                .unwrap_or(profile_scope::DEFAULT_CAPACITY);
+            profile_scope::start(capacity)?;
+            println!("Started recording profiling scopes.");
+        }
+        "stop" => {
+            profile_scope::stop();
+            println!("Stopped recording profiling scopes.");
+        }
+        "status" => {
+            println!("Profiling scopes are {}being recorded; {} have been recorded, {} were overwritten.",
+                if profile_scope::is_active() { "" } else { "not " },
+                profile_scope::records().len(),
+                profile_scope::overwritten_records(),
+            );
+        }
+        "tree" => {
+            let tree = profile_scope::call_tree();
+            if tree.roots.is_empty() {
+                println!("No profiling scopes have been recorded.");
+                return Ok(());
+            }
+            println!("{:>8} {:>14} {:>14}  SCOPE", "COUNT", "TOTAL (us)", "SELF (us)");
+            for root in &tree.roots {
+                print_node(root, 0);
+            }
+        }
+        "folded" => {
+            print!("{}", profile_scope::call_tree().folded_stacks());
+        }
+        other => return Err(alloc::format!("unknown command {:?}", other)),
+    }
+    Ok(())
+}
+
+fn print_node(node: &CallTreeNode, depth: usize) {
+    println!("{:>8} {:>14} {:>14}  {:indent$}{}",
+        node.count,
+        node.total_time.as_micros(),
+        node.self_time().as_micros(),
+        "",
+        node.name,
+        indent = depth * 2,
+    );
+    for child in &node.children {
+        print_node(child, depth + 1);
+    }
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: scopeprof [OPTIONS] [COMMAND]
+Controls the recording of profiling scopes instrumented with `profile_scope!()`.
+
+Commands:
+    start       discard previously-recorded scopes and start recording
+    stop        stop recording, keeping the recorded scopes
+    status      show whether scopes are being recorded (default)
+    tree        print the call tree of the recorded scopes
+    folded      print the recorded scopes as folded stacks, which can be rendered
+                on the host by `flamegraph.pl` or `inferno-flamegraph`";
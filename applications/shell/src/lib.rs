@@ -21,6 +21,7 @@ extern crate app_io;
 extern crate fs_node;
 extern crate environment;
 extern crate libterm;
+extern crate clipboard;
 
 #[macro_use] extern crate alloc;
 #[macro_use] extern crate log;
@@ -290,6 +291,25 @@ impl Shell {
         Ok(())
     }
 
+    /// Insert pasted text to the input buffer if a task is running, otherwise to the command line.
+    /// Line breaks and tabs become spaces and other control characters are skipped,
+    /// so that pasting can never execute a command.
+    fn paste_text(&mut self, text: &str) -> Result<(), &'static str> {
+        for c in text.chars() {
+            let c = match c {
+                '\n' | '\r' | '\t' => ' ',
+                c if c.is_control() => continue,
+                c => c,
+            };
+            if self.fg_job_num.is_some() {
+                self.insert_char_to_input_buff(c, true)?;
+            } else {
+                self.insert_char_to_cmdline(c, true)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Move the cursor to the very beginning of the input command line.
     fn move_cursor_leftmost(&mut self) -> Result<(), &'static str> {
         self.update_cursor_pos(self.cmdline.len())?;
@@ -1286,6 +1306,26 @@ impl Shell {
                         self.key_event_producer.write_one(input_event.key_event);
                     }
 
+                    // Copies the current command line (or the running task's pending input) to the clipboard.
+                    Event::CopyEvent(ref cap) => {
+                        let text = if self.fg_job_num.is_some() { &self.input_buffer } else { &self.cmdline };
+                        if let Err(e) = clipboard::set_text(cap, text) {
+                            error!("Failed to copy to the clipboard: {}", e);
+                        }
+                    }
+
+                    // Pastes text from the clipboard as if it had been typed, except that it is never executed.
+                    Event::PasteEvent(ref cap) => {
+                        match clipboard::get_text(cap) {
+                            Ok(Some(text)) => {
+                                self.paste_text(&text)?;
+                                need_refresh = true;
+                            }
+                            Ok(None) => { }
+                            Err(e) => error!("Failed to paste from the clipboard: {}", e),
+                        }
+                    }
+
                     _unhandled => { 
                         // trace!("Shell is ignoring unhandled event: {:?}", _unhandled);
                     }
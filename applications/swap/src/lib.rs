@@ -17,6 +17,7 @@ extern crate hpet;
 extern crate task;
 extern crate path;
 extern crate fs_node;
+extern crate crate_watchdog;
 
 use alloc::{
     string::{String, ToString},
@@ -24,7 +25,7 @@ use alloc::{
     sync::Arc,
 };
 use getopts::{Options, Matches};
-use mod_mgmt::{NamespaceDir, IntoCrateObjectFile};
+use mod_mgmt::{CrateNamespace, NamespaceDir, IntoCrateObjectFile};
 use crate_swap::SwapRequest;
 use hpet::get_hpet;
 use path::Path;
@@ -41,6 +42,9 @@ pub fn main(args: Vec<String>) -> isize {
     opts.optflag("v", "verbose", "enable verbose logging of crate swapping actions");
     opts.optflag("c", "cache", "enable caching of the old crate(s) removed by the swapping action");
     opts.optopt("d", "directory-crates", "the absolute path of the base directory where new crates will be loaded from", "PATH");
+    opts.optflag("g", "generation", "load each given NEW crate as a new generation of the loaded crate with the same name, re-linking its dependents automatically");
+    opts.optflag("w", "watchdog", "swap a single crate, then run the new crate's health check and roll back the swap if it fails");
+    opts.optopt("p", "patch", "replace only the function with the given fully-qualified symbol name, using the given object file", "SYMBOL");
     opts.optmulti("t", "state-transfer", "the fully-qualified symbol names of state transfer functions, to be run in the order given", "SYMBOL");
 
     let matches = match opts.parse(args) {
@@ -86,6 +90,34 @@ fn rmain(matches: Matches) -> Result<(), String> {
     let cache_old_crates = matches.opt_present("c");
     let state_transfer_functions = matches.opt_strs("t");
 
+    if let Some(symbol) = matches.opt_str("p") {
+        return do_patch(
+            &symbol,
+            &matches.free,
+            &curr_dir,
+            override_namespace_crate_dir,
+            verbose,
+        );
+    }
+
+    if matches.opt_present("w") {
+        return do_swap_checked(
+            &matches.free,
+            &curr_dir,
+            override_namespace_crate_dir,
+            verbose,
+        );
+    }
+
+    if matches.opt_present("g") {
+        return do_load_generations(
+            &matches.free,
+            &curr_dir,
+            override_namespace_crate_dir,
+            verbose,
+        );
+    }
+
     let free_args = matches.free.join(" ");
     println!("arguments: {}", free_args);
 
@@ -214,6 +246,114 @@ fn do_swap(
 }
 
 
+/// Loads each of the given crates as a new generation of the currently-loaded crate with the same name.
+fn do_load_generations(
+    new_crates: &[String],
+    curr_dir: &DirRef,
+    override_namespace_crate_dir: Option<NamespaceDir>,
+    verbose_log: bool,
+) -> Result<(), String> {
+    if new_crates.is_empty() {
+        return Err("no new crates specified.".to_string());
+    }
+    let kernel_mmi_ref = memory::get_kernel_mmi_ref().ok_or_else(|| "couldn't get kernel_mmi_ref".to_string())?;
+    let namespace = task::with_current_task(|t| t.get_namespace().clone())
+        .map_err(|_| "Couldn't get current task")?;
+
+    for new_crate_str in new_crates {
+        let new_crate_file = if let Some(f) = override_namespace_crate_dir.as_ref().and_then(|ns_dir| ns_dir.get_file_starting_with(new_crate_str)) {
+            f
+        } else if let Some(FileOrDir::File(f)) = Path::new(new_crate_str).get(curr_dir) {
+            f
+        } else {
+            namespace.dir().get_file_starting_with(new_crate_str)
+                .ok_or_else(|| format!("couldn't find a single crate object file matching {new_crate_str:?}"))?
+        };
+
+        let generation = crate_swap::load_new_generation(&namespace, &new_crate_file, kernel_mmi_ref, verbose_log)
+            .map_err(|e| e.to_string())?;
+        println!("Replaced {} with {} (generation {}), re-linked {} dependents.",
+            generation.old_crate_name, generation.crate_name, generation.generation, generation.relinked_dependents,
+        );
+        let crate_name_without_hash = namespace.get_crate(&generation.crate_name)
+            .map(|new_crate| String::from(new_crate.lock_as_ref().crate_name_without_hash()))
+            .ok_or_else(|| format!("couldn't find newly-loaded crate {:?}", generation.crate_name))?;
+        let still_in_use = crate_swap::retired_generations(&crate_name_without_hash);
+        for (old_generation, old_crate_name) in still_in_use {
+            println!("    retired generation {} ({}) is still in use", old_generation, old_crate_name);
+        }
+    }
+    Ok(())
+}
+
+
+/// Swaps a single crate and rolls back the swap if the new crate's health check fails.
+fn do_swap_checked(
+    free_args: &[String],
+    curr_dir: &DirRef,
+    override_namespace_crate_dir: Option<NamespaceDir>,
+    verbose_log: bool,
+) -> Result<(), String> {
+    let [old_crate_str, new_crate_str] = free_args else {
+        return Err("expected an old crate name and a new crate object file.".to_string());
+    };
+    let kernel_mmi_ref = memory::get_kernel_mmi_ref().ok_or_else(|| "couldn't get kernel_mmi_ref".to_string())?;
+    let namespace = task::with_current_task(|t| t.get_namespace().clone())
+        .map_err(|_| "Couldn't get current task")?;
+
+    let (old_crate_name, ..) = CrateNamespace::get_crate_starting_with(&namespace, old_crate_str)
+        .ok_or_else(|| format!("couldn't find a single loaded crate matching {old_crate_str:?}"))?;
+    let new_crate_file = if let Some(f) = override_namespace_crate_dir.as_ref().and_then(|ns_dir| ns_dir.get_file_starting_with(new_crate_str)) {
+        f
+    } else if let Some(FileOrDir::File(f)) = Path::new(new_crate_str).get(curr_dir) {
+        f
+    } else {
+        namespace.dir().get_file_starting_with(new_crate_str)
+            .ok_or_else(|| format!("couldn't find a single crate object file matching {new_crate_str:?}"))?
+    };
+
+    let new_crate_ref = crate_watchdog::swap_crate_checked(
+        &namespace,
+        &old_crate_name,
+        &new_crate_file,
+        kernel_mmi_ref,
+        crate_watchdog::DEFAULT_TIMEOUT,
+        verbose_log,
+    ).map_err(|e| e.to_string())?;
+    println!("Swapped {} for {}, which passed its health check.", old_crate_name, new_crate_ref.lock_as_ref().crate_name);
+    Ok(())
+}
+
+
+/// Replaces the function with the given fully-qualified `symbol` with its version in the given object file.
+fn do_patch(
+    symbol: &str,
+    free_args: &[String],
+    curr_dir: &DirRef,
+    override_namespace_crate_dir: Option<NamespaceDir>,
+    verbose_log: bool,
+) -> Result<(), String> {
+    let [object_file_str] = free_args else {
+        return Err("expected exactly one object file containing the replacement function.".to_string());
+    };
+    let kernel_mmi_ref = memory::get_kernel_mmi_ref().ok_or_else(|| "couldn't get kernel_mmi_ref".to_string())?;
+    let namespace = task::with_current_task(|t| t.get_namespace().clone())
+        .map_err(|_| "Couldn't get current task")?;
+
+    let object_file = if let Some(f) = override_namespace_crate_dir.as_ref().and_then(|ns_dir| ns_dir.get_file_starting_with(object_file_str)) {
+        f
+    } else if let Some(FileOrDir::File(f)) = Path::new(object_file_str).get(curr_dir) {
+        f
+    } else {
+        return Err(format!("couldn't find object file {object_file_str:?}"));
+    };
+
+    let new_sec = namespace.patch_function(symbol, &object_file, kernel_mmi_ref, verbose_log)
+        .map_err(|e| e.to_string())?;
+    println!("Patched {} with {} at {:#X}.", symbol, new_sec.name, new_sec.virt_addr);
+    Ok(())
+}
+
 
 fn print_usage(opts: Options) {
     println!("{}", opts.usage(USAGE));
@@ -226,4 +366,18 @@ The OLD and NEW values are crate names, such as \"my_crate-<hash>\".
 Both the old crate name and the new crate name can be prefixes, e.g., \"my_cra\" will find \"my_crate-<hash>\", 
 but *only* if there is a single matching crate or object file.
 A third element of each tuple is the optional 'reexport_new_symbols_as_old' boolean, which if true, 
-will reexport new symbols under their old names, if those symbols match (excluding hashes).";
+will reexport new symbols under their old names, if those symbols match (excluding hashes).
+
+Usage: swap --generation NEW1 [NEW2]...
+Loads each NEW crate as a new generation of the loaded crate with the same name (excluding hashes),
+migrates the old crate's .data and .bss sections into it, re-links all crates that depended on the old crate
+to use the new one, and retires the old crate.
+
+Usage: swap --watchdog OLD NEW
+Swaps the single crate OLD for NEW, and then runs the health check exported by NEW, if any.
+If the health check fails, panics, or times out, the swap is rolled back.
+
+Usage: swap --patch SYMBOL OBJECT_FILE
+Replaces only the function with the given fully-qualified SYMBOL, which must be in a crate loaded
+into the current namespace, with the version of that function in OBJECT_FILE.
+The rest of the old crate remains loaded, and calls to the old function are redirected to the new one.";
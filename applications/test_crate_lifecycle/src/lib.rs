@@ -0,0 +1,358 @@
+//! A stress test that continuously exercises the crate management subsystem.
+//!
+//! In each iteration, one worker task per CPU (each pinned to that CPU) creates and registers
+//! its own namespace atop the current namespace, and then:
+//! 1. loads all test crates into it at once, with strict symbol conflict checking
+//!    and lazy section metadata enabled,
+//! 2. uses each test crate by invoking its `main` function,
+//! 3. swaps each test crate with a fresh copy of itself and uses it again,
+//! 4. checks that unloading each test crate wouldn't break other crates, unloads it,
+//!    and finally unregisters and drops the namespace.
+//!
+//! The opt-in crate management features are enabled for the duration of the test:
+//! crates are prefetched via `crate_readahead`, and the test crates are loaded
+//! on multiple CPUs via `parallel_crate_loader` if there is more than one of them.
+//!
+//! After every step, the namespace's symbol map is checked for consistency with its loaded crates,
+//! and after the swap and unload steps, the replaced or unloaded crates must have been freed.
+//! Once all iterations are done, the number of free frames and the heap usage are compared
+//! against those after the first (warm-up) iteration, which must not have grown by more than the tolerance.
+//!
+//! The test crates must be application crates whose `main` function returns quickly
+//! and doesn't use stdio, as it is invoked directly by the worker tasks; the default is `hello`.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use app_io::println;
+use crate_swap::SwapRequest;
+use getopts::{Matches, Options};
+use memory::PAGE_SIZE;
+use mod_mgmt::{dependency_graph, CrateNamespace, IntoCrateObjectFile, SectionType, StrongCrateRef, SECTION_HASH_DELIMITER};
+use task::{ExitValue, JoinableTaskRef};
+
+const DEFAULT_ITERATIONS: usize = 10;
+const DEFAULT_TEST_CRATE: &str = "hello";
+/// The default number of frames by which free memory may shrink across all iterations.
+const DEFAULT_TOLERANCE_FRAMES: usize = 32;
+
+/// The signature of an application crate's `main` function.
+type MainFunc = fn(Vec<String>) -> isize;
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optflag("v", "verbose", "print the result of every iteration");
+    opts.optopt("n", "iterations", "the number of iterations to run (default 10)", "N");
+    opts.optmulti("c", "crate", "the name of an application crate to use as a test crate (default \"hello\")", "CRATE");
+    opts.optopt("t", "tolerance", "the number of frames by which free memory may shrink (default 32)", "FRAMES");
+
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    match rmain(matches) {
+        Ok(()) => {
+            println!("test_crate_lifecycle: PASSED");
+            0
+        }
+        Err(e) => {
+            println!("test_crate_lifecycle: FAILED: {}", e);
+            -1
+        }
+    }
+}
+
+fn rmain(matches: Matches) -> Result<(), String> {
+    let iterations = parse_opt(&matches, "n", DEFAULT_ITERATIONS)?;
+    let tolerance_frames = parse_opt(&matches, "t", DEFAULT_TOLERANCE_FRAMES)?;
+    let verbose = matches.opt_present("v");
+    let mut test_crates = matches.opt_strs("c");
+    if test_crates.is_empty() {
+        test_crates.push(String::from(DEFAULT_TEST_CRATE));
+    }
+    if iterations < 2 {
+        return Err("at least 2 iterations are needed to check for memory growth".into());
+    }
+
+    let parent_namespace = task::with_current_task(|t| t.get_namespace().clone())
+        .map_err(|_| "couldn't get current task")?;
+    let test_crates = Arc::new(test_crates);
+    println!("Running {} iterations on {} CPUs with test crates {:?}", iterations, cpu::cpu_count(), test_crates);
+    if test_crates.len() < 2 {
+        println!("Note: crates are only loaded in parallel if more than one test crate is given.");
+    }
+
+    // Readahead is disabled by default, whereas parallel loading is enabled at boot,
+    // so only the former is disabled again afterwards.
+    crate_readahead::enable();
+    parallel_crate_loader::enable();
+    let result = run_iterations(iterations, tolerance_frames, verbose, &parent_namespace, &test_crates);
+    crate_readahead::disable();
+    result
+}
+
+/// Runs all iterations and then checks that memory usage hasn't grown since the first one.
+fn run_iterations(
+    iterations: usize,
+    tolerance_frames: usize,
+    verbose: bool,
+    parent_namespace: &Arc<CrateNamespace>,
+    test_crates: &Arc<Vec<String>>,
+) -> Result<(), String> {
+    let mut baseline = None;
+    for iteration in 0..iterations {
+        run_iteration(iteration, parent_namespace, test_crates)?;
+        let usage = MemoryUsage::current();
+        if verbose {
+            println!("Iteration {}: {} free frames, {} heap bytes used", iteration, usage.free_frames, usage.heap_used_bytes);
+        }
+        // The first iteration is a warm-up, e.g., for lazily-initialized caches in the kernel.
+        if iteration == 0 {
+            baseline = Some(usage);
+        }
+    }
+
+    let baseline = baseline.ok_or("no iterations were run")?;
+    let end = MemoryUsage::current();
+    let lost_frames = baseline.free_frames.saturating_sub(end.free_frames);
+    let heap_growth = end.heap_used_bytes.saturating_sub(baseline.heap_used_bytes);
+    println!("Memory after warm-up: {} free frames, {} heap bytes used", baseline.free_frames, baseline.heap_used_bytes);
+    println!("Memory at end:        {} free frames, {} heap bytes used", end.free_frames, end.heap_used_bytes);
+    if lost_frames > tolerance_frames {
+        return Err(format!("free memory shrank by {lost_frames} frames over {} iterations", iterations - 1));
+    }
+    if heap_growth > tolerance_frames * PAGE_SIZE {
+        return Err(format!("heap usage grew by {heap_growth} bytes over {} iterations", iterations - 1));
+    }
+    Ok(())
+}
+
+/// Runs one iteration on all CPUs concurrently, returning the first error of any worker.
+fn run_iteration(iteration: usize, parent_namespace: &Arc<CrateNamespace>, test_crates: &Arc<Vec<String>>) -> Result<(), String> {
+    let mut workers: Vec<(cpu::CpuId, JoinableTaskRef)> = Vec::new();
+    for cpu in cpu::cpus() {
+        let args = (format!("lifecycle_test_{iteration}_{cpu}"), Arc::clone(parent_namespace), Arc::clone(test_crates));
+        let worker = spawn::new_task_builder(worker_task, args)
+            .name(format!("test_crate_lifecycle_{cpu}"))
+            .pin_on_cpu(cpu)
+            .spawn()?;
+        workers.push((cpu, worker));
+    }
+
+    let mut first_error = None;
+    for (cpu, worker) in workers {
+        let result = match worker.join()? {
+            ExitValue::Completed(value) => match value.downcast::<Result<(), String>>() {
+                Ok(result) => *result,
+                Err(_) => Err("worker task returned an unexpected value".to_string()),
+            },
+            ExitValue::Killed(reason) => Err(format!("worker task was killed: {reason}")),
+        };
+        if let Err(e) = result {
+            first_error.get_or_insert(format!("iteration {iteration}, CPU {cpu}: {e}"));
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+/// The entry point of a worker task, which runs one iteration in its own namespace.
+fn worker_task((namespace_name, parent_namespace, test_crates): (String, Arc<CrateNamespace>, Arc<Vec<String>>)) -> Result<(), String> {
+    let namespace = Arc::new(CrateNamespace::new(
+        namespace_name,
+        parent_namespace.dir().clone(),
+        Some(parent_namespace),
+    ));
+    namespace.set_strict_symbol_conflicts(true);
+    namespace.set_lazy_section_metadata(true);
+
+    // Only registered namespaces have their crates loaded in parallel and prefetched.
+    mod_mgmt::register_namespace(Arc::clone(&namespace))?;
+    let result = exercise_namespace(&namespace, &test_crates);
+    mod_mgmt::unregister_namespace(namespace.name());
+    result
+}
+
+/// Loads, uses, swaps, and unloads the test crates in the given empty namespace.
+fn exercise_namespace(namespace: &Arc<CrateNamespace>, test_crates: &[String]) -> Result<(), String> {
+    let kernel_mmi_ref = memory::get_kernel_mmi_ref().ok_or("couldn't get kernel MMI")?;
+
+    // Load
+    let mut files = Vec::with_capacity(test_crates.len());
+    for test_crate in test_crates {
+        let (file, _) = namespace.method_get_crate_object_file_starting_with(&format!("{test_crate}-"))
+            .ok_or_else(|| format!("couldn't find a single crate object file for {test_crate:?}"))?;
+        files.push(file);
+    }
+    namespace.load_crates(files.iter(), None, kernel_mmi_ref, false)
+        .map_err(|e| format!("failed to load {test_crates:?}: {e}"))?;
+    let crate_names: Vec<String> = namespace.crate_names(false).iter().map(|name| name.to_string()).collect();
+    if crate_names.len() != test_crates.len() {
+        return Err(format!("loading {test_crates:?} loaded crates {crate_names:?}"));
+    }
+    check_symbol_map(namespace)?;
+
+    // Use
+    for crate_name in &crate_names {
+        run_main(namespace, crate_name)?;
+    }
+
+    // Swap each crate with a fresh copy of itself.
+    for crate_name in &crate_names {
+        let old_crate = get_crate(namespace, crate_name)?;
+        let old_file = old_crate.lock_as_ref().object_file.clone();
+        let weak_old_crate = StrongCrateRef::downgrade(&old_crate);
+        drop(old_crate);
+
+        let request = SwapRequest::new(
+            Some(crate_name),
+            Arc::clone(namespace),
+            IntoCrateObjectFile::File(old_file),
+            None,
+            false,
+        ).map_err(|e| format!("invalid swap request for {crate_name:?}: {e:?}"))?;
+        crate_swap::swap_crates(namespace, vec![request], None, Vec::new(), kernel_mmi_ref, false, false)
+            .map_err(|e| format!("failed to swap {crate_name:?}: {e}"))?;
+        if weak_old_crate.upgrade().is_some() {
+            return Err(format!("crate {crate_name:?} was not freed after being swapped out"));
+        }
+        check_symbol_map(namespace)?;
+        run_main(namespace, crate_name)?;
+    }
+
+    // Unload
+    for crate_name in &crate_names {
+        let crate_ref = get_crate(namespace, crate_name)?;
+        let impact = dependency_graph::would_unload_break(&crate_ref);
+        if impact.would_break() {
+            return Err(format!("unloading {crate_name:?} would break crates {:?}", impact.dependents));
+        }
+        let weak_crate = StrongCrateRef::downgrade(&crate_ref);
+        drop(crate_ref);
+        namespace.unload_crate(crate_name).map_err(|e| format!("failed to unload {crate_name:?}: {e}"))?;
+        if weak_crate.upgrade().is_some() {
+            return Err(format!("crate {crate_name:?} was not freed after being unloaded"));
+        }
+        check_symbol_map(namespace)?;
+    }
+    if !namespace.crate_names(false).is_empty() {
+        return Err("namespace still contains crates after unloading all test crates".into());
+    }
+    if namespace.symbol_map().lock().iter().next().is_some() {
+        return Err("namespace still contains symbols after unloading all test crates".into());
+    }
+    Ok(())
+}
+
+/// Returns the crate with the given name, which must be loaded in the given namespace itself.
+fn get_crate(namespace: &CrateNamespace, crate_name: &str) -> Result<StrongCrateRef, String> {
+    namespace.crate_tree().lock().get(crate_name.as_bytes())
+        .map(StrongCrateRef::clone_shallow)
+        .ok_or_else(|| format!("crate {crate_name:?} is not loaded in namespace {:?}", namespace.name()))
+}
+
+/// Finds and invokes the `main` function of the given application crate.
+fn run_main(namespace: &CrateNamespace, crate_name: &str) -> Result<(), String> {
+    let main_sec = {
+        let krate = get_crate(namespace, crate_name)?;
+        let krate = krate.lock_as_ref();
+        let expected_name = format!("{}main{}", krate.crate_name_as_prefix(), SECTION_HASH_DELIMITER);
+        krate.find_section(|sec| sec.typ == SectionType::Text && sec.name_without_hash() == expected_name)
+            .cloned()
+            .ok_or_else(|| format!("crate {crate_name:?} has no main function"))?
+    };
+    // SAFETY: test crates must be application crates, whose `main` function has this signature.
+    let main_func = unsafe { main_sec.as_func::<MainFunc>() }?;
+    let ret = main_func(vec![String::from("test_crate_lifecycle")]);
+    if ret != 0 {
+        return Err(format!("main function of {crate_name:?} returned {ret}"));
+    }
+    Ok(())
+}
+
+/// Checks that every symbol in the namespace's symbol map belongs to a crate loaded in that namespace,
+/// that every global section of those crates is in the symbol map, and that no crates in it define the same symbol.
+fn check_symbol_map(namespace: &CrateNamespace) -> Result<(), String> {
+    let crates: Vec<StrongCrateRef> = namespace.crate_tree().lock().iter()
+        .map(|(_, crate_ref)| crate_ref.clone_shallow())
+        .collect();
+    let symbols: Vec<_> = namespace.symbol_map().lock().iter()
+        .map(|(name, weak_sec)| (name.clone(), weak_sec.clone()))
+        .collect();
+
+    for (name, weak_sec) in &symbols {
+        let sec = weak_sec.upgrade().ok_or_else(|| format!("symbol {name:?} refers to a freed section"))?;
+        let parent = sec.parent_crate.upgrade().ok_or_else(|| format!("symbol {name:?} belongs to a freed crate"))?;
+        if !crates.iter().any(|c| c.inner_ptr_eq(&parent)) {
+            return Err(format!("symbol {name:?} belongs to a crate that isn't in namespace {:?}", namespace.name()));
+        }
+    }
+
+    for crate_ref in &crates {
+        let krate = crate_ref.lock_as_ref();
+        for sec in krate.global_sections_iter() {
+            let mapped = symbols.iter()
+                .find(|(name, _)| *name == sec.name)
+                .and_then(|(_, weak_sec)| weak_sec.upgrade());
+            if !mapped.map_or(false, |mapped| Arc::ptr_eq(&mapped, sec)) {
+                return Err(format!("global symbol {:?} of crate {:?} is missing from the symbol map", sec.name, krate.crate_name));
+            }
+        }
+    }
+
+    for conflict in namespace.symbol_conflicts() {
+        let in_this_namespace = conflict.definitions.iter().filter(|d| d.namespace == namespace.name()).count();
+        if in_this_namespace > 1 {
+            return Err(format!("symbol {:?} is defined by multiple crates: {:?}", conflict.symbol, conflict.definitions));
+        }
+    }
+    Ok(())
+}
+
+/// A snapshot of system-wide memory usage.
+struct MemoryUsage {
+    free_frames: usize,
+    heap_used_bytes: usize,
+}
+
+impl MemoryUsage {
+    fn current() -> MemoryUsage {
+        MemoryUsage {
+            free_frames: memory::num_free_general_frames(),
+            heap_used_bytes: heap::heap_stats().used_bytes,
+        }
+    }
+}
+
+fn parse_opt(matches: &Matches, name: &str, default: usize) -> Result<usize, String> {
+    match matches.opt_str(name) {
+        Some(s) => s.parse().map_err(|_| format!("invalid value for -{name}: {s:?}")),
+        None => Ok(default),
+    }
+}
+
+const USAGE: &str = "Usage: test_crate_lifecycle [OPTION]...
+Repeatedly loads, uses, swaps, and unloads test crates in separate namespaces on every CPU,
+checking the consistency of each namespace's symbol map and that no memory is leaked.";
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
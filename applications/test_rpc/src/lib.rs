@@ -0,0 +1,113 @@
+//! Tests the `namespace_rpc` crate by serving a simple interface from one task
+//! and calling it from another.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{format, string::String, vec::Vec};
+use app_io::println;
+use log::error;
+use namespace_rpc::{Dispatch, InterfaceId, InterfaceVersion, RpcError, ServiceEndpoint};
+
+namespace_rpc::rpc_interface! {
+    /// A trivial interface used to test RPC.
+    pub interface Calculator("test_rpc.calculator", 1, 2) {
+        client: CalculatorClient,
+        server: CalculatorServer,
+        /// Returns the sum of `a` and `b`.
+        fn add(a: u64, b: u64) -> u64;
+        /// Returns a greeting for the given name.
+        fn greet(name: String) -> String;
+        /// Returns the number of calls handled so far, including this one.
+        fn calls() -> usize;
+    }
+}
+
+struct MyCalculator {
+    calls: usize,
+}
+
+impl Calculator for MyCalculator {
+    fn add(&mut self, a: u64, b: u64) -> u64 {
+        self.calls += 1;
+        a + b
+    }
+
+    fn greet(&mut self, name: String) -> String {
+        self.calls += 1;
+        format!("Hello, {}!", name)
+    }
+
+    fn calls(&mut self) -> usize {
+        self.calls += 1;
+        self.calls
+    }
+}
+
+/// The number of calls that the test sends to the service.
+const CALLS: usize = 4;
+
+pub fn main(_args: Vec<String>) -> isize {
+    match rmain() {
+        Ok(()) => {
+            println!("test_rpc passed.");
+            0
+        }
+        Err(e) => {
+            error!("test_rpc failed: {}", e);
+            println!("test_rpc failed: {}", e);
+            -1
+        }
+    }
+}
+
+fn rmain() -> Result<(), &'static str> {
+    let endpoint = namespace_rpc::register_service(CalculatorClient::INTERFACE, 4)
+        .map_err(|_| "failed to register service")?;
+    if namespace_rpc::register_service(CalculatorClient::INTERFACE, 4).err() != Some(RpcError::AlreadyRegistered) {
+        return Err("registering the same interface twice should fail");
+    }
+    let server = spawn::new_task_builder(serve, endpoint)
+        .name(String::from("test_rpc_server"))
+        .spawn()?;
+
+    let client = CalculatorClient::connect().map_err(|_| "failed to connect to service")?;
+    if client.add(40, 2) != Ok(42) {
+        return Err("add() returned the wrong result");
+    }
+    if client.greet(String::from("Theseus")).as_deref() != Ok("Hello, Theseus!") {
+        return Err("greet() returned the wrong result");
+    }
+    if namespace_rpc::connect(InterfaceId { version: InterfaceVersion::new(1, 3), ..CalculatorClient::INTERFACE }).is_ok() {
+        return Err("connecting with a newer minor version should fail");
+    }
+    if namespace_rpc::connect(InterfaceId { version: InterfaceVersion::new(2, 0), ..CalculatorClient::INTERFACE }).is_ok() {
+        return Err("connecting with a different major version should fail");
+    }
+    let connection = namespace_rpc::connect(InterfaceId { version: InterfaceVersion::new(1, 0), ..CalculatorClient::INTERFACE })
+        .map_err(|_| "connecting with an older minor version should succeed")?;
+    if connection.call_raw("subtract", Vec::new()) != Err(RpcError::UnknownMethod) {
+        return Err("calling an unknown method should fail");
+    }
+    if client.calls() != Ok(3) {
+        return Err("calls() returned the wrong result");
+    }
+
+    server.join()?;
+    if namespace_rpc::connect(CalculatorClient::INTERFACE).err() != Some(RpcError::NoSuchService) {
+        return Err("service should be unregistered once its endpoint is dropped");
+    }
+    Ok(())
+}
+
+/// Handles exactly `CALLS` calls, then drops the endpoint, which unregisters the service.
+fn serve(endpoint: ServiceEndpoint) -> Result<(), &'static str> {
+    let mut server = CalculatorServer(MyCalculator { calls: 0 });
+    for _ in 0 .. CALLS {
+        let call = endpoint.receive().map_err(|_| "failed to receive call")?;
+        let result = server.dispatch(call.method(), call.args());
+        call.reply(result);
+    }
+    Ok(())
+}
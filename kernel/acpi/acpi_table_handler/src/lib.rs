@@ -39,6 +39,8 @@ pub fn acpi_table_handler(
         hpet::HPET_SIGNATURE => hpet::handle(acpi_tables, signature, length, phys_addr),
         madt::MADT_SIGNATURE => madt::handle(acpi_tables, signature, length, phys_addr),
         dmar::DMAR_SIGNATURE => dmar::handle(acpi_tables, signature, length, phys_addr),
+        srat::SRAT_SIGNATURE => srat::handle(acpi_tables, signature, length, phys_addr),
+        slit::SLIT_SIGNATURE => slit::handle(acpi_tables, signature, length, phys_addr),
         _ => {
             warn!("Skipping unsupported ACPI table {:?}", core::str::from_utf8(&signature).unwrap_or("Unknown Signature"));
             Ok(())
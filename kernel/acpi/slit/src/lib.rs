@@ -0,0 +1,84 @@
+//! Support for the SLIT ACPI table (System Locality Distance Information Table),
+//! which describes the relative memory access latency between NUMA nodes.
+//!
+//! The layout of this table is defined in Section 5.2.17 of the ACPI specification.
+
+#![no_std]
+
+use core::mem::size_of;
+use memory::PhysicalAddress;
+use sdt::Sdt;
+use acpi_table::{AcpiSignature, AcpiTables};
+use zerocopy::FromBytes;
+
+pub const SLIT_SIGNATURE: &[u8; 4] = b"SLIT";
+
+/// The distance from a NUMA node to itself, which all other distances are relative to.
+pub const LOCAL_DISTANCE: u8 = 10;
+/// The distance value that indicates that one node is unreachable from another.
+pub const UNREACHABLE_DISTANCE: u8 = 0xFF;
+
+/// The handler for parsing the SLIT table and adding it to the ACPI tables list.
+pub fn handle(
+    acpi_tables: &mut AcpiTables,
+    signature: AcpiSignature,
+    length: usize,
+    phys_addr: PhysicalAddress
+) -> Result<(), &'static str> {
+    // The dynamic part of the SLIT is a matrix of one-byte distances.
+    let slice_start_paddr = phys_addr + size_of::<SlitAcpiTable>();
+    let slice_length = length.checked_sub(size_of::<SlitAcpiTable>())
+        .ok_or("SLIT length was smaller than its fixed-size part")?;
+    acpi_tables.add_table_location(signature, phys_addr, Some((slice_start_paddr, slice_length)))
+}
+
+
+/// The fixed-size components of the SLIT ACPI table.
+/// Its layout and total size must exactly match that of the ACPI specification.
+#[derive(Clone, Copy, Debug, FromBytes)]
+#[repr(C, packed)]
+struct SlitAcpiTable {
+    header: Sdt,
+    number_of_localities: u64,
+    // Following this is an `N x N` matrix of distances, where `N` is the number of localities.
+}
+const _: () = assert!(core::mem::size_of::<SlitAcpiTable>() == 44);
+const _: () = assert!(core::mem::align_of::<SlitAcpiTable>() == 1);
+
+
+/// A wrapper around the SLIT ACPI table, which describes the distances between NUMA nodes.
+pub struct Slit<'t> {
+    /// The number of localities (NUMA nodes).
+    num_localities: usize,
+    /// The `num_localities x num_localities` matrix of distances, in row-major order.
+    distances: &'t [u8],
+}
+
+impl<'t> Slit<'t> {
+    /// Finds the SLIT in the given `AcpiTables` and returns a reference to it.
+    pub fn get(acpi_tables: &'t AcpiTables) -> Option<Slit<'t>> {
+        let table: &SlitAcpiTable = acpi_tables.table(SLIT_SIGNATURE).ok()?;
+        let num_localities = table.number_of_localities as usize;
+        let distances: &[u8] = acpi_tables.table_slice(SLIT_SIGNATURE).ok()?;
+        if distances.len() < num_localities.checked_mul(num_localities)? {
+            return None;
+        }
+        Some(Slit { num_localities, distances })
+    }
+
+    /// Returns the number of localities (NUMA nodes) described by this table.
+    pub fn num_localities(&self) -> usize {
+        self.num_localities
+    }
+
+    /// Returns the relative distance from locality `from` to locality `to`,
+    /// where [`LOCAL_DISTANCE`] is the distance from a locality to itself.
+    ///
+    /// Returns `None` if either locality doesn't exist.
+    pub fn distance(&self, from: usize, to: usize) -> Option<u8> {
+        if from >= self.num_localities || to >= self.num_localities {
+            return None;
+        }
+        self.distances.get(from * self.num_localities + to).copied()
+    }
+}
@@ -0,0 +1,264 @@
+//! Support for the SRAT ACPI table (System Resource Affinity Table),
+//! which describes the NUMA proximity domain of each CPU and physical memory range.
+//!
+//! The layout of this table is defined in Section 5.2.16 of the ACPI specification.
+
+#![no_std]
+
+use core::mem::size_of;
+use memory::{MappedPages, PhysicalAddress};
+use sdt::Sdt;
+use acpi_table::{AcpiSignature, AcpiTables};
+use zerocopy::FromBytes;
+
+pub const SRAT_SIGNATURE: &[u8; 4] = b"SRAT";
+
+/// The handler for parsing the SRAT table and adding it to the ACPI tables list.
+pub fn handle(
+    acpi_tables: &mut AcpiTables,
+    signature: AcpiSignature,
+    _length: usize,
+    phys_addr: PhysicalAddress
+) -> Result<(), &'static str> {
+    // The SRAT has a variable number of entries, and each entry is of variable size.
+    // So we can't determine the slice_length (just use 0 instead), but we can determine where it starts.
+    let slice_start_paddr = phys_addr + size_of::<SratAcpiTable>();
+    acpi_tables.add_table_location(signature, phys_addr, Some((slice_start_paddr, 0)))
+}
+
+
+/// The fixed-size components of the SRAT ACPI table.
+/// Its layout and total size must exactly match that of the ACPI specification.
+#[derive(Clone, Copy, Debug, FromBytes)]
+#[repr(C, packed)]
+struct SratAcpiTable {
+    header: Sdt,
+    _reserved1: u32,
+    _reserved2: u64,
+    // Following this is a variable number of variable-sized table entries,
+    // so we cannot include them here.
+}
+const _: () = assert!(core::mem::size_of::<SratAcpiTable>() == 48);
+const _: () = assert!(core::mem::align_of::<SratAcpiTable>() == 1);
+
+
+/// A wrapper around the SRAT ACPI table, which describes NUMA affinity.
+///
+/// You most likely only care about the `iter()` method.
+pub struct Srat<'t> {
+    /// The underlying MappedPages that cover this SRAT.
+    mapped_pages: &'t MappedPages,
+    /// The offset into the above `mapped_pages` at which the dynamic part
+    /// of the SRAT table begins.
+    dynamic_entries_starting_offset: usize,
+    /// The total size in bytes of all dynamic entries.
+    /// This is *not* the number of entries.
+    dynamic_entries_total_size: usize,
+}
+
+impl<'t> Srat<'t> {
+    /// Finds the SRAT in the given `AcpiTables` and returns a reference to it.
+    pub fn get(acpi_tables: &'t AcpiTables) -> Option<Srat<'t>> {
+        let table: &SratAcpiTable = acpi_tables.table(SRAT_SIGNATURE).ok()?;
+        let total_length = table.header.length as usize;
+        let dynamic_part_length = total_length.checked_sub(size_of::<SratAcpiTable>())?;
+        let loc = acpi_tables.table_location(SRAT_SIGNATURE)?;
+        Some(Srat {
+            mapped_pages: acpi_tables.mapping(),
+            dynamic_entries_starting_offset: loc.slice_offset_and_length?.0,
+            dynamic_entries_total_size: dynamic_part_length,
+        })
+    }
+
+    /// Returns an [`Iterator`] over the SRAT's entries,
+    /// which are variable in both number and size.
+    pub fn iter(&self) -> SratIter<'t> {
+        SratIter {
+            mapped_pages: self.mapped_pages,
+            offset: self.dynamic_entries_starting_offset,
+            end_of_entries: self.dynamic_entries_starting_offset + self.dynamic_entries_total_size,
+        }
+    }
+}
+
+
+/// An [`Iterator`] over the dynamic entries of the SRAT.
+#[derive(Clone)]
+pub struct SratIter<'t> {
+    /// The underlying MappedPages that contain all ACPI tables.
+    mapped_pages: &'t MappedPages,
+    /// The offset of the next entry, which should point to an `EntryRecord`
+    /// at the start of each iteration.
+    offset: usize,
+    /// The end bound of all SRAT entries.
+    end_of_entries: usize,
+}
+
+impl<'t> Iterator for SratIter<'t> {
+    type Item = SratEntry<'t>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if (self.offset + ENTRY_RECORD_SIZE) > self.end_of_entries {
+            return None;
+        }
+        let (entry_type, entry_size) = {
+            let entry_record: &EntryRecord = self.mapped_pages.as_type(self.offset).ok()?;
+            (entry_record.typ, entry_record.size as usize)
+        };
+        if entry_size < ENTRY_RECORD_SIZE || (self.offset + entry_size) > self.end_of_entries {
+            return None;
+        }
+        let entry = match entry_type {
+            ENTRY_TYPE_LOCAL_APIC_AFFINITY if entry_size == size_of::<SratLocalApicAffinity>() => {
+                self.mapped_pages.as_type(self.offset).ok().map(SratEntry::LocalApicAffinity)
+            }
+            ENTRY_TYPE_MEMORY_AFFINITY if entry_size == size_of::<SratMemoryAffinity>() => {
+                self.mapped_pages.as_type(self.offset).ok().map(SratEntry::MemoryAffinity)
+            }
+            ENTRY_TYPE_LOCAL_X2APIC_AFFINITY if entry_size == size_of::<SratLocalX2ApicAffinity>() => {
+                self.mapped_pages.as_type(self.offset).ok().map(SratEntry::LocalX2ApicAffinity)
+            }
+            _ => None,
+        };
+        // move the offset to the beginning of the next entry record
+        self.offset += entry_size;
+        entry.or(Some(SratEntry::UnknownOrCorrupt(entry_type)))
+    }
+}
+
+
+/// An SRAT entry record, which precedes each actual SRAT entry
+/// and describes its type and size.
+#[derive(Clone, Copy, Debug, FromBytes)]
+#[repr(packed)]
+struct EntryRecord {
+    /// The type identifier of an SRAT entry.
+    typ: u8,
+    /// The size in bytes of an SRAT entry.
+    size: u8,
+}
+const ENTRY_RECORD_SIZE: usize = size_of::<EntryRecord>();
+
+// The following list specifies SRAT entry type IDs.
+const ENTRY_TYPE_LOCAL_APIC_AFFINITY:   u8 = 0;
+const ENTRY_TYPE_MEMORY_AFFINITY:       u8 = 1;
+const ENTRY_TYPE_LOCAL_X2APIC_AFFINITY: u8 = 2;
+
+/// Bit 0 of an SRAT entry's flags: the entry is enabled and should be used.
+const FLAG_ENABLED: u32 = 1 << 0;
+
+
+/// The set of possible SRAT entries.
+#[derive(Copy, Clone, Debug)]
+pub enum SratEntry<'t> {
+    /// The proximity domain of a CPU with a Local APIC.
+    LocalApicAffinity(&'t SratLocalApicAffinity),
+    /// The proximity domain of a range of physical memory.
+    MemoryAffinity(&'t SratMemoryAffinity),
+    /// The proximity domain of a CPU with a Local x2APIC.
+    LocalX2ApicAffinity(&'t SratLocalX2ApicAffinity),
+    /// The SRAT table had an entry of an unknown type or mismatched length.
+    /// The entry type ID is included.
+    UnknownOrCorrupt(u8),
+}
+
+/// SRAT Processor Local APIC/SAPIC Affinity
+#[derive(Copy, Clone, Debug, FromBytes)]
+#[repr(packed)]
+pub struct SratLocalApicAffinity {
+    _header: EntryRecord,
+    proximity_domain_low: u8,
+    /// Local APIC ID
+    pub apic_id: u8,
+    flags: u32,
+    _local_sapic_eid: u8,
+    proximity_domain_high: [u8; 3],
+    _clock_domain: u32,
+}
+const _: () = assert!(core::mem::size_of::<SratLocalApicAffinity>() == 16);
+const _: () = assert!(core::mem::align_of::<SratLocalApicAffinity>() == 1);
+
+impl SratLocalApicAffinity {
+    /// Returns the proximity domain (NUMA node) of this CPU.
+    pub fn proximity_domain(&self) -> u32 {
+        let [b1, b2, b3] = self.proximity_domain_high;
+        u32::from_le_bytes([self.proximity_domain_low, b1, b2, b3])
+    }
+
+    /// Returns whether this entry is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.flags & FLAG_ENABLED == FLAG_ENABLED
+    }
+}
+
+/// SRAT Memory Affinity
+#[derive(Copy, Clone, Debug, FromBytes)]
+#[repr(packed)]
+pub struct SratMemoryAffinity {
+    _header: EntryRecord,
+    /// Proximity domain (NUMA node) of this memory range
+    pub proximity_domain: u32,
+    _reserved1: u16,
+    base_address_low: u32,
+    base_address_high: u32,
+    length_low: u32,
+    length_high: u32,
+    _reserved2: u32,
+    flags: u32,
+    _reserved3: u64,
+}
+const _: () = assert!(core::mem::size_of::<SratMemoryAffinity>() == 40);
+const _: () = assert!(core::mem::align_of::<SratMemoryAffinity>() == 1);
+
+impl SratMemoryAffinity {
+    /// Returns the physical address at which this memory range begins.
+    pub fn base_address(&self) -> u64 {
+        ((self.base_address_high as u64) << 32) | self.base_address_low as u64
+    }
+
+    /// Returns the length in bytes of this memory range.
+    pub fn length(&self) -> u64 {
+        ((self.length_high as u64) << 32) | self.length_low as u64
+    }
+
+    /// Returns whether this entry is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.flags & FLAG_ENABLED == FLAG_ENABLED
+    }
+
+    /// Returns whether this memory range may be hot-plugged.
+    pub fn is_hot_pluggable(&self) -> bool {
+        const HOT_PLUGGABLE: u32 = 1 << 1;
+        self.flags & HOT_PLUGGABLE == HOT_PLUGGABLE
+    }
+
+    /// Returns whether this memory range is non-volatile.
+    pub fn is_non_volatile(&self) -> bool {
+        const NON_VOLATILE: u32 = 1 << 2;
+        self.flags & NON_VOLATILE == NON_VOLATILE
+    }
+}
+
+/// SRAT Processor Local x2APIC Affinity
+#[derive(Copy, Clone, Debug, FromBytes)]
+#[repr(packed)]
+pub struct SratLocalX2ApicAffinity {
+    _header: EntryRecord,
+    _reserved1: u16,
+    /// Proximity domain (NUMA node) of this CPU
+    pub proximity_domain: u32,
+    /// Local x2APIC ID
+    pub x2apic_id: u32,
+    flags: u32,
+    _clock_domain: u32,
+    _reserved2: u32,
+}
+const _: () = assert!(core::mem::size_of::<SratLocalX2ApicAffinity>() == 24);
+const _: () = assert!(core::mem::align_of::<SratLocalX2ApicAffinity>() == 1);
+
+impl SratLocalX2ApicAffinity {
+    /// Returns whether this entry is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.flags & FLAG_ENABLED == FLAG_ENABLED
+    }
+}
@@ -6,7 +6,7 @@ extern crate alloc;
 use alloc::vec::Vec;
 use log::{debug, warn, info};
 use spin::Mutex;
-use memory::{PageTable, PhysicalAddress};
+use memory::{FrameRange, PageTable, PhysicalAddress};
 use rsdp::Rsdp;
 use acpi_table::AcpiTables;
 use acpi_table_handler::acpi_table_handler;
@@ -124,5 +124,49 @@ pub fn init(rsdp_address: Option<PhysicalAddress>, page_table: &mut PageTable) -
         }
     }
 
+    // If we have an SRAT table, use it (and the SLIT table, if any) to obtain the NUMA topology.
+    {
+        let acpi_tables = ACPI_TABLES.lock();
+        if let Some(srat_table) = srat::Srat::get(&acpi_tables) {
+            let mut topology = numa::NumaTopology::new();
+            for entry in srat_table.iter() {
+                match entry {
+                    srat::SratEntry::MemoryAffinity(mem) if mem.is_enabled() && mem.length() > 0 => {
+                        let domain = mem.proximity_domain;
+                        let base = PhysicalAddress::new(mem.base_address() as usize)
+                            .ok_or("SRAT memory affinity base address was invalid")?;
+                        debug!("SRAT: memory {:#X} - {:#X} is on NUMA node {}",
+                            base, mem.base_address() + mem.length(), domain,
+                        );
+                        let frames = FrameRange::from_phys_addr(base, mem.length() as usize);
+                        if let Err(e) = memory::add_node_memory_range(domain, frames) {
+                            warn!("SRAT: couldn't add memory range to NUMA node {}: {}", domain, e);
+                        }
+                        topology.add_node(domain);
+                    }
+                    srat::SratEntry::LocalApicAffinity(lapic) if lapic.is_enabled() => {
+                        topology.add_cpu(lapic.apic_id as u32, lapic.proximity_domain());
+                    }
+                    srat::SratEntry::LocalX2ApicAffinity(x2apic) if x2apic.is_enabled() => {
+                        topology.add_cpu(x2apic.x2apic_id, x2apic.proximity_domain);
+                    }
+                    _ => { }
+                }
+            }
+
+            if let Some(slit_table) = slit::Slit::get(&acpi_tables) {
+                let n = slit_table.num_localities();
+                let distances = (0..n)
+                    .flat_map(|from| (0..n).map(move |to| (from, to)))
+                    .map(|(from, to)| slit_table.distance(from, to).unwrap_or(slit::UNREACHABLE_DISTANCE))
+                    .collect();
+                topology.set_distances(n, distances)?;
+            } else {
+                debug!("This machine has no SLIT table, assuming uniform distances between NUMA nodes.");
+            }
+            numa::init(topology)?;
+        }
+    }
+
     Ok(())
 }
@@ -0,0 +1,868 @@
+//! A driver for SATA drives attached to an AHCI controller.
+//!
+//! The primary struct of interest is [`AhciDrive`], which supports two ways of doing I/O:
+//! * Asynchronous requests via [`AhciDrive::submit_read()`], [`AhciDrive::submit_write()`],
+//!   and [`AhciDrive::submit_flush()`], each of which returns an [`io_completion::Completion`]
+//!   that can be waited on or `.await`ed.
+//!   Drives that support native command queueing (NCQ) can have as many requests in flight
+//!   as they have queue slots; requests beyond that are queued in the driver.
+//! * The synchronous [`BlockReader`] and [`BlockWriter`] interfaces of a [`StorageDevice`],
+//!   which submit requests through a bounce buffer and wait for each one to complete.
+//!
+//! Requests are completed by the controller's MSI interrupt handler.
+//! Errors are recovered from by a dedicated task, which restarts the failed port and fails
+//! the requests that were in flight on it. That task also fails the requests on a port that
+//! has made no progress for [`REQUEST_TIMEOUT`], e.g., because an interrupt was lost.
+//! A port that can't be restarted is considered dead, and all requests submitted to it fail.
+//! Only one AHCI controller is currently supported.
+
+#![no_std]
+#![feature(abi_x86_interrupt)]
+
+extern crate alloc;
+
+mod regs;
+
+use alloc::{boxed::Box, collections::VecDeque, string::String, sync::Arc, vec::Vec};
+use core::{sync::atomic::{fence, Ordering}, time::Duration};
+use ata::AtaIdentifyData;
+use interrupts::{interrupt_handler, EoiBehaviour};
+use io::{BlockIo, BlockReader, BlockWriter, IoError, KnownLength};
+use io_completion::{Completer, Completion};
+use log::{error, info, warn};
+use memory::{BorrowedMappedPages, MappedPages, Mutable, PhysicalAddress, DMA_FLAGS, MMIO_FLAGS, create_contiguous_mapping, map_frame_range};
+use pci::PciDevice;
+use spin::{Mutex, Once};
+use storage_device::{StorageController, StorageDevice, StorageDeviceRef};
+use sync_irq::IrqSafeMutex;
+use time::Instant;
+use regs::*;
+
+/// The PCI class, subclass, and programming interface of an AHCI controller.
+pub const AHCI_CLASS: (u8, u8, u8) = (0x01, 0x06, 0x01);
+/// The index of the AHCI Base Address Register (ABAR), through which the controller's registers are mapped.
+const ABAR_INDEX: usize = 5;
+
+/// The size of a sector, which is the unit of all offsets and sizes in ATA commands.
+pub const SECTOR_SIZE: usize = 512;
+/// The maximum number of bytes transferred by a single request,
+/// which is the most that a single physical region descriptor can describe.
+pub const MAX_TRANSFER_SIZE: usize = 4 * 1024 * 1024;
+/// The size of the buffer through which the synchronous [`BlockReader`] and [`BlockWriter`] interfaces transfer data.
+const BOUNCE_BUFFER_SIZE: usize = 128 * 1024;
+
+// The layout of each port's command memory: a command list with one 32-byte header per command slot,
+// then the 256-byte area into which the controller copies FISes received from the device,
+// then one command table per command slot, each of which must be 128-byte aligned.
+const COMMAND_HEADER_SIZE: usize = 32;
+const RECEIVED_FIS_OFFSET: usize = 0x400;
+const COMMAND_TABLES_OFFSET: usize = 0x500;
+/// Each command table holds a 64-byte command FIS, a 16-byte ATAPI command, 48 reserved bytes,
+/// and a single physical region descriptor (PRD), padded out to a multiple of 128 bytes.
+const COMMAND_TABLE_SIZE: usize = 0x100;
+const PRD_OFFSET: usize = 0x80;
+const MAX_COMMAND_SLOTS: usize = 32;
+const PORT_MEMORY_SIZE: usize = COMMAND_TABLES_OFFSET + MAX_COMMAND_SLOTS * COMMAND_TABLE_SIZE;
+
+/// The type of a host-to-device register FIS, which carries an ATA command.
+const FIS_TYPE_REG_H2D: u8 = 0x27;
+/// The length of a host-to-device register FIS, in bytes.
+const FIS_LENGTH: usize = 20;
+/// The bit of a command header that indicates the command writes data to the device.
+const COMMAND_HEADER_WRITE: u32 = 1 << 6;
+
+// The ATA commands used by this driver, all of which use 48-bit LBAs.
+const ATA_CMD_READ_DMA_EXT: u8 = 0x25;
+const ATA_CMD_WRITE_DMA_EXT: u8 = 0x35;
+const ATA_CMD_READ_FPDMA_QUEUED: u8 = 0x60;
+const ATA_CMD_WRITE_FPDMA_QUEUED: u8 = 0x61;
+const ATA_CMD_FLUSH_CACHE_EXT: u8 = 0xEA;
+const ATA_CMD_IDENTIFY_DEVICE: u8 = 0xEC;
+/// The bit of the device register that selects LBA addressing.
+const ATA_DEVICE_LBA: u8 = 1 << 6;
+
+/// How many times to poll a register before giving up, which is only done during initialization.
+const MAX_POLLS: usize = 10_000_000;
+/// How long the recovery task waits for a register to reach the expected value before giving up.
+const RECOVERY_POLL_TIMEOUT: Duration = Duration::from_millis(500);
+/// How often the recovery task checks for ports that have failed or timed out.
+const RECOVERY_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a port can have requests in flight without completing any of them
+/// before those requests are failed and the port is restarted.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The single AHCI controller, which is shared by its drives and its interrupt handler.
+static AHCI_HBA: Once<IrqSafeMutex<AhciHba>> = Once::new();
+
+
+/// Initializes the given AHCI controller and the SATA drives attached to it,
+/// and returns a storage controller through which those drives can be accessed.
+pub fn init(pci_device: &PciDevice) -> Result<AhciController, &'static str> {
+    if AHCI_HBA.is_completed() {
+        return Err("ahci: only one AHCI controller is currently supported");
+    }
+    if pci_device.msi_capability().is_none() {
+        return Err("ahci: controller doesn't support MSI, which this driver requires");
+    }
+    pci_device.pci_set_command_bus_master_bit();
+
+    let mem_base = pci_device.determine_mem_base(ABAR_INDEX)?;
+    let mut regs: BorrowedMappedPages<HbaRegisters, Mutable> = map_frame_range(mem_base, core::mem::size_of::<HbaRegisters>(), MMIO_FLAGS)?
+        .into_borrowed_mut(0)
+        .map_err(|(_mp, err)| err)?;
+
+    let ghc = regs.ghc.read();
+    regs.ghc.write(ghc | GHC_AE);
+    let cap = regs.cap.read();
+    let num_slots = (((cap >> CAP_NCS_SHIFT) & CAP_NCS_MASK) + 1) as usize;
+    let ncq_capable = cap & CAP_SNCQ != 0;
+    let addr64 = cap & CAP_S64A != 0;
+    let vs = regs.vs.read();
+    info!("ahci: AHCI {}.{} controller at {} with {} command slots per port, NCQ {}",
+        vs >> 16, (vs >> 8) & 0xFF, pci_device.location, num_slots,
+        if ncq_capable { "supported" } else { "unsupported" },
+    );
+
+    let mut hba = AhciHba { regs, ports: core::array::from_fn(|_| None) };
+    let mut drives = Vec::new();
+    let implemented = hba.regs.pi.read();
+    for port_num in (0 .. MAX_COMMAND_SLOTS).filter(|p| implemented & (1 << p) != 0) {
+        let port_regs = &mut hba.regs.ports[port_num];
+        match AhciPort::init(port_regs, num_slots, addr64) {
+            Ok(Some(mut port)) => match port.identify(port_regs) {
+                Ok(identify_data) => {
+                    if !identify_data.supports_lba48() {
+                        warn!("ahci: skipping drive on port {}, which doesn't support 48-bit LBA", port_num);
+                        continue;
+                    }
+                    if ncq_capable && identify_data.supports_ncq() {
+                        port.ncq = true;
+                        port.num_slots = num_slots.min(identify_data.ncq_queue_depth());
+                    }
+                    let num_sectors = match identify_data.max_48_bit_lba {
+                        0 => identify_data.user_addressable_sectors as u64,
+                        n => n,
+                    };
+                    info!("ahci: port {}: drive {:?} with {} sectors, {}",
+                        port_num, identify_data.model_number, num_sectors,
+                        if port.ncq { "using NCQ" } else { "not using NCQ" },
+                    );
+                    hba.ports[port_num] = Some(port);
+                    drives.push((port_num, identify_data, num_sectors));
+                }
+                Err(e) => warn!("ahci: couldn't identify drive on port {}: {}", port_num, e),
+            },
+            Ok(None) => { }
+            Err(e) => warn!("ahci: couldn't initialize port {}: {}", port_num, e),
+        }
+    }
+
+    let interrupt_num = pci_device.pci_enable_msi_vectors(cpu::current_cpu(), &[ahci_interrupt_handler])?;
+    for (port, port_regs) in hba.ports.iter().zip(hba.regs.ports.iter_mut()) {
+        if port.is_some() {
+            let is = port_regs.is.read();
+            port_regs.is.write(is);
+            port_regs.ie.write(PORT_IS_DHRS | PORT_IS_SDBS | PORT_IS_ERRORS);
+        }
+    }
+    let is = hba.regs.is.read();
+    hba.regs.is.write(is);
+
+    // The interrupt handler uses the static instance, so only enable interrupts once it exists.
+    let hba = AHCI_HBA.call_once(|| IrqSafeMutex::new(hba));
+    {
+        let mut hba = hba.lock();
+        let ghc = hba.regs.ghc.read();
+        hba.regs.ghc.write(ghc | GHC_IE);
+    }
+    info!("ahci: using MSI interrupt {:#X}", interrupt_num);
+
+    spawn::new_task_builder(recovery_task, hba)
+        .name(String::from("ahci_recovery"))
+        .spawn()?;
+
+    let drives = drives.into_iter()
+        .map(|(port, identify_data, num_sectors)| Arc::new(Mutex::new(AhciDrive {
+            hba,
+            port,
+            identify_data,
+            num_sectors,
+            bounce_buffer: None,
+        })))
+        .collect();
+    Ok(AhciController { drives })
+}
+
+
+/// A storage controller for an AHCI controller and the SATA drives attached to its ports.
+pub struct AhciController {
+    drives: Vec<Arc<Mutex<AhciDrive>>>,
+}
+
+impl AhciController {
+    /// Returns the SATA drives attached to this controller.
+    pub fn drives(&self) -> &[Arc<Mutex<AhciDrive>>] {
+        &self.drives
+    }
+}
+
+impl StorageController for AhciController {
+    fn devices<'c>(&'c self) -> Box<(dyn Iterator<Item = StorageDeviceRef> + 'c)> {
+        Box::new(self.drives.iter().map(|drive| Arc::clone(drive) as StorageDeviceRef))
+    }
+}
+
+
+/// A physically-contiguous buffer that a drive reads data into or writes data from.
+///
+/// Its length is always a nonzero multiple of [`SECTOR_SIZE`] no greater than its capacity,
+/// which is at most [`MAX_TRANSFER_SIZE`].
+pub struct IoBuffer {
+    mp: MappedPages,
+    paddr: PhysicalAddress,
+    len: usize,
+    capacity: usize,
+}
+
+impl IoBuffer {
+    /// Allocates a new buffer of `len` bytes.
+    pub fn new(len: usize) -> Result<IoBuffer, &'static str> {
+        if !valid_transfer_size(len) {
+            return Err("ahci: buffer length must be a nonzero multiple of the sector size, up to MAX_TRANSFER_SIZE");
+        }
+        let (mp, paddr) = create_contiguous_mapping(len, DMA_FLAGS)?;
+        Ok(IoBuffer { mp, paddr, len, capacity: len })
+    }
+
+    /// Returns the number of bytes that a request using this buffer transfers.
+    pub fn length(&self) -> usize {
+        self.len
+    }
+
+    /// Changes the number of bytes that a request using this buffer transfers,
+    /// which cannot exceed the length this buffer was created with.
+    pub fn set_length(&mut self, len: usize) -> Result<(), &'static str> {
+        if !valid_transfer_size(len) || len > self.capacity {
+            return Err("ahci: invalid length for IoBuffer");
+        }
+        self.len = len;
+        Ok(())
+    }
+
+    /// Returns the first [`IoBuffer::length()`] bytes of this buffer.
+    pub fn as_slice(&self) -> &[u8] {
+        self.mp.as_slice(0, self.len).expect("BUG: IoBuffer is smaller than its length")
+    }
+
+    /// Returns the first [`IoBuffer::length()`] bytes of this buffer, mutably.
+    pub fn as_slice_mut(&mut self) -> &mut [u8] {
+        self.mp.as_slice_mut(0, self.len).expect("BUG: IoBuffer is smaller than its length")
+    }
+}
+
+fn valid_transfer_size(len: usize) -> bool {
+    len != 0 && len % SECTOR_SIZE == 0 && len <= MAX_TRANSFER_SIZE
+}
+
+
+/// A request that was submitted to a port but hasn't yet completed.
+enum Request {
+    /// Reads into or writes from the buffer, which is handed back to the submitter upon completion.
+    Transfer {
+        write: bool,
+        lba: u64,
+        buffer: IoBuffer,
+        completer: Completer<IoBuffer>,
+    },
+    Flush(Completer<()>),
+}
+
+impl Request {
+    fn complete(self, result: Result<(), IoError>) {
+        match self {
+            Request::Transfer { buffer, completer, .. } => completer.complete(result.map(|_| buffer)),
+            Request::Flush(completer) => completer.complete(result),
+        }
+    }
+
+    /// Fails this request without freeing its buffer,
+    /// which must be done if the controller may still access that buffer.
+    fn fail_and_leak(self, error: IoError) {
+        match self {
+            Request::Transfer { buffer, completer, .. } => {
+                core::mem::forget(buffer);
+                completer.complete(Err(error));
+            }
+            Request::Flush(completer) => completer.complete(Err(error)),
+        }
+    }
+}
+
+
+/// The AHCI controller's registers and the state of each port that has a drive attached.
+struct AhciHba {
+    regs: BorrowedMappedPages<HbaRegisters, Mutable>,
+    ports: [Option<AhciPort>; MAX_COMMAND_SLOTS],
+}
+
+impl AhciHba {
+    /// Submits the given request to the given port, which issues it to the drive as soon as possible.
+    fn submit(&mut self, port_num: usize, request: Request) -> Result<(), IoError> {
+        let port = self.ports.get_mut(port_num)
+            .and_then(Option::as_mut)
+            .ok_or(IoError::Other("ahci: no drive is attached to that port"))?;
+        if port.dead {
+            return Err(IoError::Other("ahci: port is dead, as it couldn't be restarted after an error"));
+        }
+        port.backlog.push_back(request);
+        port.issue_backlog(&mut self.regs.ports[port_num]);
+        Ok(())
+    }
+
+    /// Handles an interrupt from the controller by completing every request that has finished.
+    fn handle_interrupt(&mut self) {
+        let is = self.regs.is.read();
+        for port_num in (0 .. MAX_COMMAND_SLOTS).filter(|p| is & (1 << p) != 0) {
+            let port_regs = &mut self.regs.ports[port_num];
+            match self.ports[port_num].as_mut() {
+                Some(port) => port.handle_interrupt(port_regs),
+                None => {
+                    let port_is = port_regs.is.read();
+                    port_regs.is.write(port_is);
+                }
+            }
+        }
+        // This must be cleared after the ports' interrupt status, otherwise it is immediately set again.
+        self.regs.is.write(is);
+    }
+
+    /// Returns `true` if the given port must be restarted,
+    /// either because it reported an error or because its requests timed out.
+    fn needs_recovery(&mut self, port_num: usize) -> bool {
+        let Some(port) = self.ports[port_num].as_mut() else { return false };
+        if port.dead {
+            return false;
+        }
+        if !port.failed && port.issued != 0 && port.last_progress.elapsed() > REQUEST_TIMEOUT {
+            error!("ahci: port {} made no progress for {:?}, failing its requests", port_num, REQUEST_TIMEOUT);
+            port.failed = true;
+        }
+        port.failed
+    }
+
+    /// Fails every request that was in flight on the given port, which must have been stopped,
+    /// and resumes issuing requests to it if it was restarted.
+    ///
+    /// If the port couldn't be stopped, the buffers of the failed requests are leaked,
+    /// and if it couldn't be restarted, the port is marked as dead and its backlog is failed too.
+    fn finish_recovery(&mut self, port_num: usize, stopped: bool, restarted: bool) {
+        let Some(port) = self.ports[port_num].as_mut() else { return };
+        for request in port.slots.iter_mut().filter_map(Option::take) {
+            let error = IoError::Other("ahci: request failed and its port was restarted");
+            if stopped {
+                request.complete(Err(error));
+            } else {
+                request.fail_and_leak(error);
+            }
+        }
+        port.issued = 0;
+        port.exclusive = false;
+        port.failed = false;
+        if restarted {
+            port.issue_backlog(&mut self.regs.ports[port_num]);
+        } else {
+            port.dead = true;
+            for request in port.backlog.drain(..) {
+                request.complete(Err(IoError::Other("ahci: port is dead, as it couldn't be restarted after an error")));
+            }
+        }
+    }
+}
+
+
+/// A port of the AHCI controller to which a SATA drive is attached.
+struct AhciPort {
+    /// The command list, received FIS area, and command tables of this port.
+    memory: MappedPages,
+    memory_paddr: PhysicalAddress,
+    /// Whether the controller can access memory above 4 GiB.
+    addr64: bool,
+    /// Whether data transfers are issued as NCQ commands.
+    ncq: bool,
+    /// The number of command slots used by this port.
+    num_slots: usize,
+    /// The request in each command slot.
+    slots: Vec<Option<Request>>,
+    /// A bitmask of the command slots whose commands have been issued but not yet completed.
+    issued: u32,
+    /// Whether the command in flight is a non-queued command, which must be the only command in flight.
+    exclusive: bool,
+    /// Requests that haven't yet been issued, in the order they were submitted.
+    backlog: VecDeque<Request>,
+    /// When a request was last issued to an idle port or last completed.
+    last_progress: Instant,
+    /// Whether the port reported an error or timed out, after which no requests are issued
+    /// until the recovery task has restarted the port.
+    failed: bool,
+    /// Whether the port couldn't be restarted after an error, after which all requests fail.
+    dead: bool,
+}
+
+impl AhciPort {
+    /// Initializes the given port and starts it if a SATA drive is attached to it.
+    ///
+    /// Returns `None` if there's no SATA drive attached to the port.
+    fn init(regs: &mut PortRegisters, num_slots: usize, addr64: bool) -> Result<Option<AhciPort>, &'static str> {
+        if regs.ssts.read() & SSTS_DET_MASK != SSTS_DET_PRESENT || regs.sig.read() != SIG_ATA {
+            return Ok(None);
+        }
+        stop_port(regs)?;
+
+        let (mut memory, memory_paddr) = create_contiguous_mapping(PORT_MEMORY_SIZE, DMA_FLAGS)?;
+        memory.as_slice_mut::<u8>(0, PORT_MEMORY_SIZE)?.fill(0);
+        let mut port = AhciPort {
+            memory,
+            memory_paddr,
+            addr64,
+            ncq: false,
+            num_slots,
+            slots: (0 .. num_slots).map(|_| None).collect(),
+            issued: 0,
+            exclusive: false,
+            backlog: VecDeque::new(),
+            last_progress: Instant::ZERO,
+            failed: false,
+            dead: false,
+        };
+        let command_list = port.dma_address(memory_paddr, PORT_MEMORY_SIZE)?;
+        let received_fis = command_list + RECEIVED_FIS_OFFSET as u64;
+        regs.clb.write(command_list as u32);
+        regs.clbu.write((command_list >> 32) as u32);
+        regs.fb.write(received_fis as u32);
+        regs.fbu.write((received_fis >> 32) as u32);
+
+        start_port(regs)?;
+        Ok(Some(port))
+    }
+
+    /// Issues an identify command to the drive and waits for it by polling,
+    /// which must only be done before the port's interrupts are enabled.
+    fn identify(&mut self, regs: &mut PortRegisters) -> Result<AtaIdentifyData, &'static str> {
+        let buffer = IoBuffer::new(SECTOR_SIZE)?;
+        let fis = register_fis(ATA_CMD_IDENTIFY_DEVICE, 0, 0, None);
+        self.write_command(0, &fis, Some((&buffer, false)))?;
+        fence(Ordering::SeqCst);
+        regs.ci.write(1);
+
+        if !poll_until(|| regs.ci.read() & 1 == 0 || regs.is.read() & PORT_IS_ERRORS != 0) {
+            return Err("ahci: identify command timed out");
+        }
+        if regs.is.read() & PORT_IS_ERRORS != 0 {
+            if let Err(e) = stop_port(regs).and_then(|_| start_port(regs)) {
+                error!("ahci: couldn't restart port after a failed identify command: {}", e);
+            }
+            return Err("ahci: identify command failed");
+        }
+        let mut data = [0; SECTOR_SIZE];
+        data.copy_from_slice(buffer.as_slice());
+        Ok(AtaIdentifyData::new(data))
+    }
+
+    /// Issues as many requests from the backlog as possible, in order.
+    ///
+    /// A non-queued command can only be issued once all other commands have completed,
+    /// and nothing else can be issued until it has completed.
+    fn issue_backlog(&mut self, regs: &mut PortRegisters) {
+        if self.failed || self.dead {
+            return;
+        }
+        while let Some(request) = self.backlog.front() {
+            let queued = self.ncq && matches!(request, Request::Transfer { .. });
+            let can_issue = if queued { !self.exclusive } else { self.issued == 0 };
+            if !can_issue {
+                break;
+            }
+            let Some(slot) = (0 .. self.num_slots).find(|&s| self.issued & (1 << s) == 0) else { break };
+            let request = self.backlog.pop_front().unwrap();
+            if let Err(e) = self.issue(regs, slot, &request, queued) {
+                request.complete(Err(IoError::Other(e)));
+                continue;
+            }
+            self.slots[slot] = Some(request);
+            if self.issued == 0 {
+                self.last_progress = Instant::now();
+            }
+            self.issued |= 1 << slot;
+            self.exclusive = !queued;
+        }
+    }
+
+    /// Builds the command for the given request in the given slot and issues it to the drive.
+    fn issue(&mut self, regs: &mut PortRegisters, slot: usize, request: &Request, queued: bool) -> Result<(), &'static str> {
+        match request {
+            Request::Transfer { write, lba, buffer, .. } => {
+                let command = match (*write, queued) {
+                    (false, false) => ATA_CMD_READ_DMA_EXT,
+                    (true, false) => ATA_CMD_WRITE_DMA_EXT,
+                    (false, true) => ATA_CMD_READ_FPDMA_QUEUED,
+                    (true, true) => ATA_CMD_WRITE_FPDMA_QUEUED,
+                };
+                let sector_count = (buffer.length() / SECTOR_SIZE) as u16;
+                let tag = if queued { Some(slot as u8) } else { None };
+                self.write_command(slot, &register_fis(command, *lba, sector_count, tag), Some((buffer, *write)))?;
+            }
+            Request::Flush(_) => {
+                self.write_command(slot, &register_fis(ATA_CMD_FLUSH_CACHE_EXT, 0, 0, None), None)?;
+            }
+        }
+        fence(Ordering::SeqCst);
+        if queued {
+            regs.sact.write(1 << slot);
+        }
+        regs.ci.write(1 << slot);
+        Ok(())
+    }
+
+    /// Writes the command header and command table of the given slot.
+    ///
+    /// If the command transfers data, `data` holds its buffer and whether the data is written to the drive.
+    fn write_command(&mut self, slot: usize, fis: &[u8; FIS_LENGTH], data: Option<(&IoBuffer, bool)>) -> Result<(), &'static str> {
+        let table_offset = COMMAND_TABLES_OFFSET + slot * COMMAND_TABLE_SIZE;
+        let table_addr = self.dma_address(self.memory_paddr + table_offset, COMMAND_TABLE_SIZE)?;
+        let prd = match data {
+            Some((buffer, _)) => Some(self.dma_address(buffer.paddr, buffer.length())?),
+            None => None,
+        };
+
+        let table = self.memory.as_slice_mut::<u8>(table_offset, COMMAND_TABLE_SIZE)?;
+        table.fill(0);
+        table[.. FIS_LENGTH].copy_from_slice(fis);
+        if let (Some(addr), Some((buffer, _))) = (prd, data) {
+            let prd_entry = &mut table[PRD_OFFSET .. PRD_OFFSET + 16];
+            prd_entry[0..8].copy_from_slice(&addr.to_le_bytes());
+            // The byte count is stored as one less than the actual number of bytes.
+            prd_entry[12..16].copy_from_slice(&(buffer.length() as u32 - 1).to_le_bytes());
+        }
+
+        let mut flags = (FIS_LENGTH / 4) as u32;
+        if let Some((_, true)) = data {
+            flags |= COMMAND_HEADER_WRITE;
+        }
+        let num_prds = prd.is_some() as u32;
+        let header = self.memory.as_slice_mut::<u32>(slot * COMMAND_HEADER_SIZE, COMMAND_HEADER_SIZE / 4)?;
+        header.fill(0);
+        header[0] = flags | (num_prds << 16);
+        header[2] = table_addr as u32;
+        header[3] = (table_addr >> 32) as u32;
+        Ok(())
+    }
+
+    /// Returns the address that the controller uses to access `len` bytes at `paddr`,
+    /// or an error if the controller can't access that memory.
+    fn dma_address(&self, paddr: PhysicalAddress, len: usize) -> Result<u64, &'static str> {
+        let addr = paddr.value() as u64;
+        if !self.addr64 && addr + len as u64 > 1 << 32 {
+            return Err("ahci: controller doesn't support 64-bit addresses, but the buffer is above 4 GiB");
+        }
+        Ok(addr)
+    }
+
+    /// Completes every request that has finished, then issues more requests from the backlog.
+    ///
+    /// If the port reported an error, it is instead marked as failed and left to the recovery task.
+    fn handle_interrupt(&mut self, regs: &mut PortRegisters) {
+        let is = regs.is.read();
+        regs.is.write(is);
+        if is & PORT_IS_ERRORS != 0 {
+            error!("ahci: port error, interrupt status {:#X}, task file {:#X}, SATA error {:#X}",
+                is, regs.tfd.read(), regs.serr.read(),
+            );
+            self.failed = true;
+        } else if !self.failed {
+            // A command has completed once its slot is cleared from both the command issue and SATA active registers.
+            let outstanding = regs.ci.read() | regs.sact.read();
+            let completed = self.issued & !outstanding;
+            for slot in (0 .. self.num_slots).filter(|s| completed & (1 << s) != 0) {
+                if let Some(request) = self.slots[slot].take() {
+                    request.complete(Ok(()));
+                }
+            }
+            if completed != 0 {
+                self.last_progress = Instant::now();
+            }
+            self.issued &= !completed;
+            if self.issued == 0 {
+                self.exclusive = false;
+            }
+        }
+        self.issue_backlog(regs);
+    }
+}
+
+/// Access to a port's registers, through which the port can be stopped and started.
+trait PortAccess {
+    /// Invokes `f` on the port's registers.
+    fn with_regs<R>(&mut self, f: impl FnOnce(&mut PortRegisters) -> R) -> R;
+    /// Polls the given condition on the port's registers until it's true, returning `false` if it never was.
+    fn poll_until(&mut self, condition: impl FnMut(&mut PortRegisters) -> bool) -> bool;
+}
+
+/// Direct access to a port's registers, which busy-waits and is thus only used during initialization.
+impl PortAccess for PortRegisters {
+    fn with_regs<R>(&mut self, f: impl FnOnce(&mut PortRegisters) -> R) -> R {
+        f(self)
+    }
+
+    fn poll_until(&mut self, mut condition: impl FnMut(&mut PortRegisters) -> bool) -> bool {
+        poll_until(|| condition(self))
+    }
+}
+
+/// Access to a port's registers from the recovery task, which only holds the controller's lock
+/// while accessing them and sleeps in between polls, such that interrupts aren't disabled for long.
+struct LockedPort {
+    hba: &'static IrqSafeMutex<AhciHba>,
+    port_num: usize,
+}
+
+impl PortAccess for LockedPort {
+    fn with_regs<R>(&mut self, f: impl FnOnce(&mut PortRegisters) -> R) -> R {
+        f(&mut self.hba.lock().regs.ports[self.port_num])
+    }
+
+    fn poll_until(&mut self, mut condition: impl FnMut(&mut PortRegisters) -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < RECOVERY_POLL_TIMEOUT {
+            if self.with_regs(&mut condition) {
+                return true;
+            }
+            if sleep::sleep(Duration::from_millis(1)).is_err() {
+                break;
+            }
+        }
+        false
+    }
+}
+
+/// Stops the given port from processing its command list and receiving FISes.
+fn stop_port(port: &mut impl PortAccess) -> Result<(), &'static str> {
+    port.with_regs(|regs| {
+        let cmd = regs.cmd.read();
+        regs.cmd.write(cmd & !PORT_CMD_ST);
+    });
+    if !port.poll_until(|regs| regs.cmd.read() & PORT_CMD_CR == 0) {
+        return Err("ahci: port's command list didn't stop running");
+    }
+    port.with_regs(|regs| {
+        let cmd = regs.cmd.read();
+        regs.cmd.write(cmd & !PORT_CMD_FRE);
+    });
+    if !port.poll_until(|regs| regs.cmd.read() & PORT_CMD_FR == 0) {
+        return Err("ahci: port's FIS receive didn't stop running");
+    }
+    Ok(())
+}
+
+/// Clears any errors and starts the given port processing its command list.
+fn start_port(port: &mut impl PortAccess) -> Result<(), &'static str> {
+    port.with_regs(|regs| {
+        let cmd = regs.cmd.read();
+        regs.cmd.write(cmd | PORT_CMD_FRE);
+        regs.serr.write(u32::MAX);
+        let is = regs.is.read();
+        regs.is.write(is);
+    });
+    if !port.poll_until(|regs| regs.tfd.read() & (TFD_STATUS_BSY | TFD_STATUS_DRQ) == 0) {
+        return Err("ahci: drive stayed busy");
+    }
+    port.with_regs(|regs| {
+        let cmd = regs.cmd.read();
+        regs.cmd.write(cmd | PORT_CMD_ST);
+    });
+    Ok(())
+}
+
+/// Polls the given condition until it's true, returning `false` if it never was.
+fn poll_until(mut condition: impl FnMut() -> bool) -> bool {
+    for _ in 0 .. MAX_POLLS {
+        if condition() {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    false
+}
+
+/// The entry point of the task that restarts ports that have failed or timed out.
+fn recovery_task(hba: &'static IrqSafeMutex<AhciHba>) -> Result<(), &'static str> {
+    loop {
+        sleep::sleep(RECOVERY_INTERVAL).map_err(|_| "ahci: failed to sleep")?;
+        for port_num in 0 .. MAX_COMMAND_SLOTS {
+            if !hba.lock().needs_recovery(port_num) {
+                continue;
+            }
+            let mut port = LockedPort { hba, port_num };
+            let stopped = stop_port(&mut port);
+            let restarted = stopped.and_then(|_| start_port(&mut port));
+            if let Err(e) = restarted {
+                error!("ahci: couldn't restart port {} after an error, marking it as dead: {}", port_num, e);
+            }
+            hba.lock().finish_recovery(port_num, stopped.is_ok(), restarted.is_ok());
+        }
+    }
+}
+
+/// Builds a host-to-device register FIS that issues the given ATA command.
+///
+/// NCQ commands must be given a `tag`, which is their command slot,
+/// and carry their sector count in the features field instead of the count field.
+fn register_fis(command: u8, lba: u64, sector_count: u16, tag: Option<u8>) -> [u8; FIS_LENGTH] {
+    let lba = lba.to_le_bytes();
+    let count = sector_count.to_le_bytes();
+    let mut fis = [0; FIS_LENGTH];
+    fis[0] = FIS_TYPE_REG_H2D;
+    fis[1] = 1 << 7; // this FIS carries a command
+    fis[2] = command;
+    fis[4..7].copy_from_slice(&lba[0..3]);
+    fis[7] = ATA_DEVICE_LBA;
+    fis[8..11].copy_from_slice(&lba[3..6]);
+    match tag {
+        Some(tag) => {
+            fis[3] = count[0];
+            fis[11] = count[1];
+            fis[12] = tag << 3;
+        }
+        None => {
+            fis[12] = count[0];
+            fis[13] = count[1];
+        }
+    }
+    fis
+}
+
+interrupt_handler!(ahci_interrupt_handler, _, _stack_frame, {
+    if let Some(hba) = AHCI_HBA.get() {
+        hba.lock().handle_interrupt();
+    } else {
+        error!("BUG: ahci_interrupt_handler(): AHCI controller hasn't yet been initialized!");
+    }
+    EoiBehaviour::HandlerDidNotSendEoi
+});
+
+
+/// A SATA drive attached to a port of an AHCI controller.
+pub struct AhciDrive {
+    hba: &'static IrqSafeMutex<AhciHba>,
+    port: usize,
+    /// Data that represents the characteristics of the drive.
+    identify_data: AtaIdentifyData,
+    /// The size of the drive in sectors.
+    num_sectors: u64,
+    /// The buffer used by the synchronous I/O interfaces, which is allocated upon first use.
+    bounce_buffer: Option<IoBuffer>,
+}
+
+impl AhciDrive {
+    /// Returns the data that the drive reported in response to an identify command.
+    pub fn identify_data(&self) -> &AtaIdentifyData {
+        &self.identify_data
+    }
+
+    /// Submits a request to read from this drive, starting at the given `block_offset`,
+    /// into the given `buffer`. The length of the `buffer` determines the number of bytes read.
+    ///
+    /// The returned completion yields the `buffer` once it has been filled.
+    pub fn submit_read(&self, buffer: IoBuffer, block_offset: usize) -> Result<Completion<IoBuffer>, IoError> {
+        self.submit_transfer(false, buffer, block_offset)
+    }
+
+    /// Submits a request to write the contents of the given `buffer` to this drive,
+    /// starting at the given `block_offset`.
+    ///
+    /// The returned completion yields the `buffer` once its contents have been written.
+    /// Use [`AhciDrive::submit_flush()`] to ensure those contents have reached persistent storage.
+    pub fn submit_write(&self, buffer: IoBuffer, block_offset: usize) -> Result<Completion<IoBuffer>, IoError> {
+        self.submit_transfer(true, buffer, block_offset)
+    }
+
+    /// Submits a request to flush the drive's write cache,
+    /// which completes after all previously-submitted writes have completed.
+    pub fn submit_flush(&self) -> Result<Completion<()>, IoError> {
+        let (completer, completion) = io_completion::new();
+        self.hba.lock().submit(self.port, Request::Flush(completer))?;
+        Ok(completion)
+    }
+
+    fn submit_transfer(&self, write: bool, buffer: IoBuffer, block_offset: usize) -> Result<Completion<IoBuffer>, IoError> {
+        self.check_bounds(buffer.length(), block_offset)?;
+        let (completer, completion) = io_completion::new();
+        let request = Request::Transfer { write, lba: block_offset as u64, buffer, completer };
+        self.hba.lock().submit(self.port, request)?;
+        Ok(completion)
+    }
+
+    /// Checks that `len` bytes starting at `block_offset` are within the drive.
+    fn check_bounds(&self, len: usize, block_offset: usize) -> Result<(), IoError> {
+        if len % SECTOR_SIZE != 0 {
+            return Err(IoError::InvalidInput);
+        }
+        match (block_offset as u64).checked_add((len / SECTOR_SIZE) as u64) {
+            Some(end) if end <= self.num_sectors => Ok(()),
+            _ => Err(IoError::InvalidInput),
+        }
+    }
+
+    /// Takes the bounce buffer and sets its length to `len`, allocating it if necessary.
+    fn take_bounce_buffer(&mut self, len: usize) -> Result<IoBuffer, IoError> {
+        let mut buffer = match self.bounce_buffer.take() {
+            Some(buffer) => buffer,
+            None => IoBuffer::new(BOUNCE_BUFFER_SIZE)?,
+        };
+        buffer.set_length(len)?;
+        Ok(buffer)
+    }
+}
+
+impl StorageDevice for AhciDrive {
+    fn size_in_blocks(&self) -> usize {
+        self.num_sectors as usize
+    }
+}
+impl BlockIo for AhciDrive {
+    fn block_size(&self) -> usize { SECTOR_SIZE }
+}
+impl KnownLength for AhciDrive {
+    fn len(&self) -> usize { self.block_size() * self.size_in_blocks() }
+}
+impl BlockReader for AhciDrive {
+    fn read_blocks(&mut self, buffer: &mut [u8], block_offset: usize) -> Result<usize, IoError> {
+        self.check_bounds(buffer.len(), block_offset)?;
+        let mut sector = block_offset;
+        for chunk in buffer.chunks_mut(BOUNCE_BUFFER_SIZE) {
+            let bounce_buffer = self.take_bounce_buffer(chunk.len())?;
+            let bounce_buffer = self.submit_read(bounce_buffer, sector)?.wait()?;
+            chunk.copy_from_slice(bounce_buffer.as_slice());
+            self.bounce_buffer = Some(bounce_buffer);
+            sector += chunk.len() / SECTOR_SIZE;
+        }
+        Ok(buffer.len() / SECTOR_SIZE)
+    }
+}
+impl BlockWriter for AhciDrive {
+    fn write_blocks(&mut self, buffer: &[u8], block_offset: usize) -> Result<usize, IoError> {
+        self.check_bounds(buffer.len(), block_offset)?;
+        let mut sector = block_offset;
+        for chunk in buffer.chunks(BOUNCE_BUFFER_SIZE) {
+            let mut bounce_buffer = self.take_bounce_buffer(chunk.len())?;
+            bounce_buffer.as_slice_mut().copy_from_slice(chunk);
+            let bounce_buffer = self.submit_write(bounce_buffer, sector)?.wait()?;
+            self.bounce_buffer = Some(bounce_buffer);
+            sector += chunk.len() / SECTOR_SIZE;
+        }
+        Ok(buffer.len() / SECTOR_SIZE)
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.submit_flush()?.wait()
+    }
+}
@@ -0,0 +1,121 @@
+//! The memory-mapped registers of an AHCI controller (HBA) and its ports,
+//! as defined in the Serial ATA AHCI 1.3.1 specification, section 3.
+//!
+//! The HBA's registers are mapped from its AHCI Base Address Register (ABAR), i.e., BAR5.
+//! The generic host control registers are followed by the registers of each of the up to 32 ports.
+
+use volatile::{Volatile, ReadOnly};
+use zerocopy::FromBytes;
+
+/// The layout in memory of the generic host control registers of an AHCI controller,
+/// followed by the registers of all of its ports.
+#[derive(FromBytes)]
+#[repr(C)]
+pub struct HbaRegisters {
+    /// Host capabilities
+    pub cap:                        ReadOnly<u32>,          // 0x0
+    /// Global host control
+    pub ghc:                        Volatile<u32>,          // 0x4
+    /// Interrupt status, with one bit per port
+    pub is:                         Volatile<u32>,          // 0x8
+    /// Ports implemented, with one bit per port
+    pub pi:                         ReadOnly<u32>,          // 0xC
+    /// AHCI version
+    pub vs:                         ReadOnly<u32>,          // 0x10
+    _padding0:                      [u8; 236],              // 0x14 - 0xFF
+
+    pub ports:                      [PortRegisters; 32],    // 0x100 - 0x10FF
+}
+
+const _: () = assert!(core::mem::size_of::<HbaRegisters>() == 0x1100);
+
+/// The layout in memory of the registers of a single AHCI port.
+#[derive(FromBytes)]
+#[repr(C)]
+pub struct PortRegisters {
+    /// Command list base address, lower 32 bits
+    pub clb:                        Volatile<u32>,          // 0x0
+    /// Command list base address, upper 32 bits
+    pub clbu:                       Volatile<u32>,          // 0x4
+    /// Received FIS base address, lower 32 bits
+    pub fb:                         Volatile<u32>,          // 0x8
+    /// Received FIS base address, upper 32 bits
+    pub fbu:                        Volatile<u32>,          // 0xC
+    /// Interrupt status
+    pub is:                         Volatile<u32>,          // 0x10
+    /// Interrupt enable
+    pub ie:                         Volatile<u32>,          // 0x14
+    /// Command and status
+    pub cmd:                        Volatile<u32>,          // 0x18
+    _padding0:                      u32,                    // 0x1C
+    /// Task file data, which mirrors the ATA status and error registers
+    pub tfd:                        ReadOnly<u32>,          // 0x20
+    /// Signature of the attached device
+    pub sig:                        ReadOnly<u32>,          // 0x24
+    /// SATA status
+    pub ssts:                       ReadOnly<u32>,          // 0x28
+    /// SATA control
+    pub sctl:                       Volatile<u32>,          // 0x2C
+    /// SATA error
+    pub serr:                       Volatile<u32>,          // 0x30
+    /// SATA active, with one bit per command slot that holds an outstanding NCQ command
+    pub sact:                       Volatile<u32>,          // 0x34
+    /// Command issue, with one bit per command slot that holds an outstanding command
+    pub ci:                         Volatile<u32>,          // 0x38
+    /// SATA notification
+    pub sntf:                       Volatile<u32>,          // 0x3C
+    /// FIS-based switching control
+    pub fbs:                        Volatile<u32>,          // 0x40
+    _padding1:                      [u8; 60],               // 0x44 - 0x7F
+}
+
+const _: () = assert!(core::mem::size_of::<PortRegisters>() == 0x80);
+
+/// Supports 64-bit addressing.
+pub const CAP_S64A: u32 = 1 << 31;
+/// Supports native command queueing.
+pub const CAP_SNCQ: u32 = 1 << 30;
+/// The bit offset of the number of command slots per port, minus one.
+pub const CAP_NCS_SHIFT: u32 = 8;
+pub const CAP_NCS_MASK: u32 = 0x1F;
+
+/// AHCI enable; when cleared, the controller operates in legacy IDE mode.
+pub const GHC_AE: u32 = 1 << 31;
+/// Interrupt enable.
+pub const GHC_IE: u32 = 1 << 1;
+
+/// Start processing the command list.
+pub const PORT_CMD_ST: u32 = 1 << 0;
+/// FIS receive enable.
+pub const PORT_CMD_FRE: u32 = 1 << 4;
+/// FIS receive running.
+pub const PORT_CMD_FR: u32 = 1 << 14;
+/// Command list running.
+pub const PORT_CMD_CR: u32 = 1 << 15;
+
+/// A D2H register FIS was received, which completes a non-queued command.
+pub const PORT_IS_DHRS: u32 = 1 << 0;
+/// A set device bits FIS was received, which completes one or more NCQ commands.
+pub const PORT_IS_SDBS: u32 = 1 << 3;
+/// Interface fatal error.
+pub const PORT_IS_IFS: u32 = 1 << 27;
+/// Host bus data error.
+pub const PORT_IS_HBDS: u32 = 1 << 28;
+/// Host bus fatal error.
+pub const PORT_IS_HBFS: u32 = 1 << 29;
+/// Task file error, i.e., the device reported an error for a command.
+pub const PORT_IS_TFES: u32 = 1 << 30;
+/// All of the interrupt status bits that indicate a failed command.
+pub const PORT_IS_ERRORS: u32 = PORT_IS_IFS | PORT_IS_HBDS | PORT_IS_HBFS | PORT_IS_TFES;
+
+/// The ATA status bits in the task file data register that indicate the drive is busy.
+pub const TFD_STATUS_DRQ: u32 = 1 << 3;
+pub const TFD_STATUS_BSY: u32 = 1 << 7;
+
+/// The device detection bits of the SATA status register.
+pub const SSTS_DET_MASK: u32 = 0xF;
+/// A device is present and communication with it is established.
+pub const SSTS_DET_PRESENT: u32 = 3;
+
+/// The signature of a SATA drive, as opposed to an ATAPI device or port multiplier.
+pub const SIG_ATA: u32 = 0x0000_0101;
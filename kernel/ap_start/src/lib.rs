@@ -75,17 +75,32 @@ pub fn kstart_ap(
 
     #[cfg(target_arch = "x86_64")] {
         // initialize interrupts (including TSS/GDT) for this AP
-        let (double_fault_stack, privilege_stack) = {
+        let (double_fault_stack, page_fault_stack, privilege_stack) = {
             let mut kernel_mmi = kernel_mmi_ref.lock();
             (
                 stack::alloc_stack(KERNEL_STACK_SIZE_IN_PAGES, &mut kernel_mmi.page_table)
                     .expect("kstart_ap(): could not allocate double fault stack"),
+                stack::alloc_stack(KERNEL_STACK_SIZE_IN_PAGES, &mut kernel_mmi.page_table)
+                    .expect("kstart_ap(): could not allocate page fault stack"),
                 stack::alloc_stack(1, &mut kernel_mmi.page_table)
                     .expect("kstart_ap(): could not allocate privilege stack"),
             )
         };
-        let _idt = interrupts::init_ap(cpu_id, double_fault_stack.top_unusable(), privilege_stack.top_unusable())
-            .expect("kstart_ap(): failed to initialize interrupts!");
+        let _idt = interrupts::init_ap(
+            cpu_id,
+            double_fault_stack.top_unusable(),
+            page_fault_stack.top_unusable(),
+            privilege_stack.top_unusable(),
+        ).expect("kstart_ap(): failed to initialize interrupts!");
+        // The exception stacks are used by this CPU for as long as it runs,
+        // so they must never be unmapped.
+        core::mem::forget(double_fault_stack);
+        core::mem::forget(page_fault_stack);
+
+        // Machine-check exceptions must be enabled on each CPU individually.
+        if let Err(e) = machine_check::init() {
+            log::warn!("kstart_ap(): machine-check exceptions are disabled on CPU {}: {}", cpu_id, e);
+        }
 
         // Initialize this CPU's Local APIC such that we can use everything that depends on APIC IDs.
         // This must be done before initializing task spawning, because that relies on the ability to
@@ -113,6 +128,7 @@ pub fn kstart_ap(
     // per-CPU storage, tasking, and create the idle task for this CPU.
     cls_allocator::reload_current_cpu();
     let bootstrap_task = spawn::init(kernel_mmi_ref.clone(), cpu_id, this_ap_stack).unwrap();
+    watchdog::register_cpu(cpu_id);
 
     // The PAT must be initialized explicitly on every CPU,
     // but it is not a fatal error if it doesn't exist.
@@ -8,6 +8,7 @@ use zerocopy::FromBytes;
 use spin::Once;
 use raw_cpuid::CpuId as X86CpuIdInstr;
 use msr::*;
+use msr::registers::ApicBase;
 use sync_irq::IrqSafeRwLock;
 use memory::{PageTable, PhysicalAddress, PteFlags, MappedPages, allocate_pages, allocate_frames_at, AllocatedFrames, BorrowedMappedPages, Mutable};
 use kernel_config::time::CONFIG_TIMESLICE_PERIOD_MICROSECONDS;
@@ -17,11 +18,6 @@ use pit_clock_basic::pit_wait;
 use bit_field::BitField;
 use log::{error, info, debug, trace};
 
-// Values for the `IA32_APIC_BASE` MSR.
-const IA32_APIC_IS_BSP:                u64 = 1 << 8;
-const IA32_APIC_XAPIC_ENABLE:          u64 = 1 << 11;
-const IA32_APIC_X2APIC_ENABLE:         u64 = 1 << 10;
-
 // Value for the APIC spurious interrupt vector register.
 const APIC_SW_ENABLE:                  u32 = 1 << 8;
 /// The IRQ number reserved for spurious APIC interrupts (as recommended by OS dev wiki).
@@ -95,10 +91,10 @@ pub fn bootstrap_cpu() -> Option<ApicId> {
 /// Returns true if the currently executing CPU is the bootstrap CPU, 
 /// i.e., the first procesor to run after system power-on.
 pub fn is_bootstrap_cpu() -> bool {
-    rdmsr(IA32_APIC_BASE) & IA32_APIC_IS_BSP == IA32_APIC_IS_BSP
+    ApicBase::read().map_or(false, |apic_base| apic_base.is_bsp())
 }
 
-/// Returns true if the machine has support for x2apic
+/// Returns true if the machine has support for x2apic, i.e., `CPUID.01H:ECX[21]` is set.
 pub fn has_x2apic() -> bool {
     static IS_X2APIC: Once<bool> = Once::new(); // cache the result
     let res: &bool = IS_X2APIC.call_once(||
@@ -228,11 +224,21 @@ impl LapicIpiDestination {
 /// and enables the Local APIC hardware in the correct mode.
 pub fn init() {
     let is_x2apic = has_x2apic();
-    debug!("is x2apic? {}. IA32_APIC_BASE (phys addr): {:X?}", is_x2apic, rdmsr(IA32_APIC_BASE));
-
-    if !is_x2apic {
-        // Ensure the local apic is enabled in xapic mode, otherwise we'll get a General Protection fault
-        unsafe { wrmsr(IA32_APIC_BASE, rdmsr(IA32_APIC_BASE) | IA32_APIC_XAPIC_ENABLE); }
+    let apic_base = match ApicBase::read() {
+        Ok(apic_base) => apic_base,
+        Err(e) => {
+            error!("apic::init(): {}", e);
+            return;
+        }
+    };
+    debug!("is x2apic? {}. IA32_APIC_BASE: {:X?}", is_x2apic, apic_base);
+
+    // Ensure the local apic is enabled, otherwise we'll get a General Protection fault.
+    // If supported, x2apic mode is enabled at the same time, as a transition
+    // from x2apic mode back to xapic mode is not allowed without first disabling the APIC.
+    let enabled_apic_base = apic_base.with_xapic_enabled(true).with_x2apic_enabled(is_x2apic);
+    if let Err(e) = unsafe { enabled_apic_base.write() } {
+        error!("apic::init(): couldn't enable the local APIC: {}", e);
     }
 }
 
@@ -244,7 +250,7 @@ fn map_apic(page_table: &mut PageTable) -> Result<MappedPages, &'static str> {
     let frame = if let Some(apic_frame) = APIC_FRAME.get() {
         apic_frame
     } else {
-        let phys_addr = PhysicalAddress::new(rdmsr(IA32_APIC_BASE) as usize)
+        let phys_addr = PhysicalAddress::new(ApicBase::read()?.base_address() as usize)
             .ok_or("APIC physical address was invalid")?;
         let apic_frame = allocate_frames_at(phys_addr, 1)?;
         APIC_FRAME.call_once(|| apic_frame)
@@ -406,6 +412,8 @@ pub enum LapicInitError {
     },
     /// An error occurred while mapping the Local APIC's MMIO registers into memory.
     MemoryMappingError(&'static str),
+    /// An error occurred while accessing the `IA32_APIC_BASE` MSR.
+    ApicBaseError(&'static str),
     /// The Local APIC already existed (BUG), given by the included `ApicId`.
     AlreadyExisted(ApicId),
 }
@@ -488,7 +496,8 @@ impl LocalApic {
         };
 
         // Check whether the caller's expectations about BSP vs AP were met.
-        let is_bootstrap_cpu = rdmsr(IA32_APIC_BASE) & IA32_APIC_IS_BSP == IA32_APIC_IS_BSP;
+        let apic_base = ApicBase::read().map_err(LapicInitError::ApicBaseError)?;
+        let is_bootstrap_cpu = apic_base.is_bsp();
         if should_be_bsp && !is_bootstrap_cpu {
             return Err(LapicInitError::NotBSP);
         }
@@ -499,10 +508,10 @@ impl LocalApic {
         // Next, before we can check other conditions, we have to enable the APIC hardware
         // (which, if xapic, also requires mapping the Local APIC's MMIO registers).
         let inner: LapicType;
-        let enable_bitmask: u64;
+        let enabled_apic_base: ApicBase;
         if has_x2apic() {
             inner = LapicType::X2Apic;
-            enable_bitmask = IA32_APIC_XAPIC_ENABLE | IA32_APIC_X2APIC_ENABLE;
+            enabled_apic_base = apic_base.with_xapic_enabled(true).with_x2apic_enabled(true);
         } else {
             let apic_regs = map_apic(page_table)
                 .map_err(LapicInitError::MemoryMappingError)
@@ -512,11 +521,11 @@ impl LocalApic {
                 )?;
 
             inner = LapicType::XApic(apic_regs);
-            enable_bitmask = IA32_APIC_XAPIC_ENABLE;
+            enabled_apic_base = apic_base.with_xapic_enabled(true);
         };
 
         // Enable the xapic/x2apic hardware.
-        unsafe { wrmsr(IA32_APIC_BASE, rdmsr(IA32_APIC_BASE) | enable_bitmask); }
+        unsafe { enabled_apic_base.write() }.map_err(LapicInitError::ApicBaseError)?;
 
 		let mut lapic = LocalApic {
             inner,
@@ -566,6 +575,10 @@ impl LocalApic {
     /// There is only one BSP per system.
     pub fn is_bootstrap_cpu(&self) -> bool { self.is_bootstrap_cpu }
 
+    /// Returns `true` if this Local APIC is operating in x2apic mode,
+    /// in which its registers are accessed via MSRs rather than MMIO.
+    pub fn is_x2apic(&self) -> bool { matches!(self.inner, LapicType::X2Apic) }
+
     /// Set this Local APIC to a known "clean state" and enable its spurious interrupt vector.
     fn clean_enable(&mut self) {
         let is_bootstrap_cpu = self.is_bootstrap_cpu;
@@ -790,6 +803,8 @@ impl LocalApic {
     /// Writes `value` to this lapic's Interrupt Control Register.
     pub fn set_icr(&mut self, value: u64) {
         match &mut self.inner {
+            // In x2apic mode, the whole ICR is written at once with a single `wrmsr`,
+            // and there is no delivery status bit to wait on.
             LapicType::X2Apic => unsafe { wrmsr(IA32_X2APIC_ICR, value) },
             LapicType::XApic(regs) => {
                 const ICR_DELIVERY_STATUS: u32 = 1 << 12;
@@ -806,6 +821,12 @@ impl LocalApic {
     /// Send an IPI to the cores specified by the given destination
     pub fn send_ipi(&mut self, irq: u8, destination: LapicIpiDestination) {
         const NORMAL_IPI_ICR: u64 = 0x4000;
+
+        // x2apic offers a dedicated register for sending a fixed IPI to the current CPU.
+        if let (LapicType::X2Apic, LapicIpiDestination::Me) = (&self.inner, &destination) {
+            unsafe { wrmsr(IA32_X2APIC_SELF_IPI, irq as u64) };
+            return;
+        }
         
         let dest = destination.as_icr_value();
         let icr = NORMAL_IPI_ICR | (irq as u64) | dest;
@@ -837,6 +858,27 @@ impl LocalApic {
         }
     }
 
+    /// Returns the current value of this lapic's Task Priority Register.
+    ///
+    /// Interrupts with a priority class (the upper 4 bits of the vector)
+    /// less than or equal to the upper 4 bits of this value will not be delivered.
+    pub fn task_priority(&self) -> u8 {
+        match &self.inner {
+            LapicType::X2Apic => rdmsr(IA32_X2APIC_TPR) as u8,
+            LapicType::XApic(regs) => regs.task_priority.read() as u8,
+        }
+    }
+
+    /// Sets this lapic's Task Priority Register to the given `priority`.
+    ///
+    /// See [`LocalApic::task_priority()`] for how this value is interpreted.
+    pub fn set_task_priority(&mut self, priority: u8) {
+        match &mut self.inner {
+            LapicType::X2Apic => unsafe { wrmsr(IA32_X2APIC_TPR, priority as u64) },
+            LapicType::XApic(regs) => regs.task_priority.write(priority as u32),
+        }
+    }
+
     /// Set the NonMaskableInterrupt redirect for this LocalApic.
     ///
     /// Argument `lint` can be either 0 or 1, since each local APIC has two LVT LINTs
@@ -0,0 +1,139 @@
+//! A typed API for spawning applications and collecting their results.
+//!
+//! [`spawn_application()`] standardizes how an application is started:
+//! it loads the application crate, verifies its `main` entry point
+//! (including its signature, if the crate has debug symbols),
+//! passes the given arguments, and connects the given stdio streams and environment
+//! before the application starts running.
+//! The returned [`AppTask`] can be joined to obtain an [`AppExit`] status.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::fmt;
+use app_io::IoStreams;
+use environment::Environment;
+use path::Path;
+use spin::Mutex;
+use task::{ExitValue, JoinableTaskRef, KillReason};
+
+/// The exit status code used when an application's `main` function returned a non-`isize` value.
+pub const INVALID_RETURN_TYPE_STATUS: isize = 210;
+/// The exit status code used when an application was killed by request, e.g., `Ctrl + C`.
+pub const KILLED_STATUS: isize = 130;
+/// The exit status code used when an application panicked.
+pub const PANICKED_STATUS: isize = 1;
+
+/// Spawns a new task that runs the application crate in the given object file.
+///
+/// # Arguments
+/// * `crate_object_file`: the path to the application's crate object file,
+///   relative to the current task's namespace directory.
+/// * `args`: the arguments passed to the application's `main` function.
+/// * `stdio`: the streams for the application's standard input, output, and error.
+///   If `None`, the application inherits its parent's streams (see [`app_io`]).
+/// * `env`: the environment for the application. If `None`, the current task's environment is used.
+///
+/// The application crate is loaded into the current task's namespace.
+pub fn spawn_application(
+    crate_object_file: &Path,
+    args: Vec<String>,
+    stdio: Option<IoStreams>,
+    env: Option<Arc<Mutex<Environment>>>,
+) -> Result<AppTask, &'static str> {
+    let task = spawn::new_application_task_builder(crate_object_file, None)?
+        .argument(args)
+        .block()
+        .spawn()?;
+
+    if let Some(streams) = stdio {
+        app_io::insert_child_streams(task.id, streams);
+    }
+    if let Some(env) = env {
+        task.set_env(env);
+    }
+    if task.unblock().is_err() {
+        // The task never ran, so it must be killed and its streams removed, or they would be leaked.
+        // Killing it fails if it has already exited, in which case joining it still reaps it.
+        let _ = task.kill(KillReason::Requested);
+        app_io::remove_child_streams(task.id);
+        let _ = task.join();
+        return Err("spawn_application(): couldn't unblock the new application task");
+    }
+    Ok(AppTask { task })
+}
+
+/// A handle to a running application task that was spawned by [`spawn_application()`].
+pub struct AppTask {
+    task: JoinableTaskRef,
+}
+
+impl AppTask {
+    /// Returns the ID of the application's task.
+    pub fn id(&self) -> usize {
+        self.task.id
+    }
+
+    /// Returns the underlying joinable task reference.
+    pub fn task(&self) -> &JoinableTaskRef {
+        &self.task
+    }
+
+    /// Returns `true` if the application task has exited.
+    pub fn has_exited(&self) -> bool {
+        self.task.has_exited()
+    }
+
+    /// Blocks until the application task exits, and then returns its exit status.
+    pub fn join(self) -> Result<AppExit, &'static str> {
+        let exit = match self.task.join()? {
+            ExitValue::Completed(value) => match value.downcast_ref::<isize>() {
+                Some(status) => AppExit::Returned(*status),
+                None => AppExit::InvalidReturnType,
+            },
+            ExitValue::Killed(reason) => AppExit::Killed(reason),
+        };
+        Ok(exit)
+    }
+}
+
+/// The result of running an application to completion.
+#[derive(Debug)]
+pub enum AppExit {
+    /// The application's `main` function returned the enclosed value.
+    Returned(isize),
+    /// The application's `main` function returned a value of an unexpected type.
+    InvalidReturnType,
+    /// The application was killed for the enclosed reason, e.g., it panicked.
+    Killed(KillReason),
+}
+
+impl AppExit {
+    /// Returns a shell-style integer status code for this exit, where `0` indicates success.
+    pub fn status_code(&self) -> isize {
+        match self {
+            Self::Returned(status) => *status,
+            Self::InvalidReturnType => INVALID_RETURN_TYPE_STATUS,
+            Self::Killed(KillReason::Requested) => KILLED_STATUS,
+            Self::Killed(KillReason::Panic(_)) => PANICKED_STATUS,
+            Self::Killed(KillReason::Exception(num)) => (*num).into(),
+        }
+    }
+
+    /// Returns `true` if the application returned `0` from its `main` function.
+    pub fn is_success(&self) -> bool {
+        matches!(self, Self::Returned(0))
+    }
+}
+
+impl fmt::Display for AppExit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Returned(status) => write!(f, "exited with status {status}"),
+            Self::InvalidReturnType => write!(f, "returned an unexpected (non-isize) value"),
+            Self::Killed(reason) => write!(f, "was killed: {reason}"),
+        }
+    }
+}
@@ -1041,7 +1041,7 @@ pub struct AtaIdentifyData {
 impl AtaIdentifyData {
 	/// Converts the given byte array, which should be the result of an ATA identify command,
 	/// into a struct that contains the identified details of an ATA drive.
-	fn new(arr: [u8; SECTOR_SIZE_IN_BYTES])-> AtaIdentifyData {
+	pub fn new(arr: [u8; SECTOR_SIZE_IN_BYTES])-> AtaIdentifyData {
 		let mut identify_data: AtaIdentifyData = unsafe { core::mem::transmute(arr) };
 		Self::flip_bytes(&mut identify_data.serial_number.0);
 		Self::flip_bytes(&mut identify_data.firmware_version.0);
@@ -1049,6 +1049,23 @@ impl AtaIdentifyData {
 		identify_data
 	}
 
+	/// Returns `true` if the drive supports 48-bit LBA addressing.
+	pub fn supports_lba48(&self) -> bool {
+		let command_set_support = self.command_set_support;
+		command_set_support[1] & (1 << 10) != 0
+	}
+
+	/// Returns `true` if the drive supports native command queueing (NCQ),
+	/// which is only usable through an AHCI controller.
+	pub fn supports_ncq(&self) -> bool {
+		self.serial_ata_capabilities & (1 << 8) != 0
+	}
+
+	/// Returns the maximum number of commands that the drive can queue via NCQ.
+	pub fn ncq_queue_depth(&self) -> usize {
+		(self.queue_depth & 0x1F) as usize + 1
+	}
+
 	/// Flips pairs of bytes to rectify quasi-endianness issues in the ATA identify response.
 	fn flip_bytes(bytes: &mut [u8]) {
 		for pair in bytes.chunks_mut(2) {
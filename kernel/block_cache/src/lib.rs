@@ -1,179 +1,508 @@
 //! A caching layer for block based storage devices.
-//! 
+//!
 //! For many storage devices, calls to the backing medium are quite expensive. This layer intends to reduce those calls,
-//! improving efficiency in exchange for additional memory usage. Note that this crate is intended to be used as a part of
-//! `block_io`, but should work on its own.
-//! 
+//! improving efficiency in exchange for additional memory usage.
+//!
+//! A [`BlockCache`] wraps a storage device and implements the [`StorageDevice`] trait itself,
+//! so a filesystem can be mounted on top of the cache rather than directly on the device,
+//! as `disk_fs::Disk` does for `fat32` and `ext2`.
+//! Repeated reads of the same blocks, such as a filesystem's directories and metadata,
+//! are then served from memory.
+//!
+//! # Eviction
+//! A cache holds up to a fixed number of blocks. Once it is full, the least-recently used block
+//! is evicted to make room for each new block, and is written back to the device first if it was modified.
+//!
+//! # Write-back
+//! Writes only modify the cached blocks, which are written back to the device when they are evicted,
+//! when the cache is flushed via [`BlockWriter::flush()`], and periodically by the task spawned
+//! via [`spawn_flush_task()`]. Each run of contiguous modified blocks is written back with a single
+//! device write, so a burst of small writes to the same region of the device is coalesced.
+//!
+//! # Invalidation
+//! A cache assumes that it is the only means of accessing the underlying device.
+//! If any other crate writes to the device directly, it must then invalidate the affected blocks
+//! in every cache of that device via [`invalidate_device()`], such that they are re-read from the device.
+//!
+//! # Memory pressure
+//! Caches created via [`BlockCache::new_ref()`] give back their clean blocks when memory runs out,
+//! via a `memory_pressure` shrinker that evicts them in least-recently used order.
+//! Modified blocks are never evicted by the shrinker, as writing them back may block.
+//!
+//! # Profiling
+//! Device reads and write-backs are instrumented as `profile_scope` scopes,
+//! so their share of filesystem time shows up in a `scopeprof` flamegraph.
+//!
 //! # Limitations
-//! Currently, the `BlockCache` struct is hardcoded to use a `StorageDevice` reference,
-//! when in reality it should just use anything that implements traits like `BlockReader + BlockWriter`. 
-//! 
-//! The read and write functions currently only support reading/writing individual blocks from disk even
-//! when the caller might prefer reading a larger number of contiguous blocks. This is inneficient and an
-//! optimized implementation should read multiple blocks at once if possible.
-//! 
-//! Cached blocks are stored as vectors of bytes on the heap, 
-//! we should do something else such as separate mapped regions. 
-//! Cached blocks cannot yet be dropped to relieve memory pressure. 
-//! 
-//! Note that this cache only holds a reference to the underlying block device.
-//! As such if any other system crates perform writes to the underlying device,
-//! in that case the cache will give incorrect and potentially inconsistent results.
-//! In the long run, the only way around this would be to only expose a `BlockCache`
-//! instead of exposing a `StorageDevice`. I suppose the least disruptive way to implement this
-//! might be with a layer of indirection to an implementor of a BlockReader like trait,
-//! so that the underlying block reader can be switched out when a cache is enabled.
+//! Cached blocks are stored as vectors of bytes on the heap,
+//! we should do something else such as separate mapped regions.
 
 #![no_std]
 
-#[macro_use] extern crate alloc;
-extern crate hashbrown;
-extern crate storage_device;
+extern crate alloc;
 
-use alloc::vec::Vec;
-use hashbrown::{
-    HashMap,
-    hash_map::Entry,
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
 };
+use core::ops::Range;
+use io::{BlockIo, BlockReader, BlockWriter, IoError, KnownLength};
+use log::{error, warn};
+use profile_scope::profile_scope;
+use kernel_config::memory::PAGE_SIZE;
+use spin::{Mutex, Once};
 use storage_device::{StorageDevice, StorageDeviceRef};
-use alloc::borrow::{Cow, ToOwned};
+use task::JoinableTaskRef;
+use time::Duration;
+
+/// How often the task spawned by [`spawn_flush_task()`] should write back modified blocks,
+/// unless there is a reason to use a different interval.
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A shareable reference to a [`BlockCache`],
+/// which can also be used as a [`StorageDeviceRef`].
+pub type BlockCacheRef = Arc<Mutex<BlockCache>>;
+
+/// All caches created via [`BlockCache::new_ref()`], such that they can be invalidated.
+static CACHES: Mutex<Vec<Weak<Mutex<BlockCache>>>> = Mutex::new(Vec::new());
+
+/// Whether the shrinker that evicts blocks from all caches has been registered.
+static SHRINKER: Once<()> = Once::new();
 
 /// A cache to store read and written blocks from a storage device.
 pub struct BlockCache {
-    /// The cache of blocks (sectors) read from the storage device,
-    /// a map from block number to data byte array.
-    cache: InternalCache,
+    /// The cache of blocks (sectors) read from or written to the storage device,
+    /// a map from block number to the cached block.
+    cache: BTreeMap<usize, CachedBlock>,
+    /// The numbers of the cached blocks, keyed by when they were last used,
+    /// such that the first entry is the least-recently used block.
+    lru: BTreeMap<u64, usize>,
+    /// A counter that is incremented every time a cached block is used.
+    clock: u64,
+    /// The maximum number of blocks in the cache.
+    capacity: usize,
     /// The underlying storage device from where the blocks are read/written.
     storage_device: StorageDeviceRef,
+    block_size: usize,
+    size_in_blocks: usize,
+    stats: CacheStats,
+}
+
+/// Statistics about the usage of a [`BlockCache`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CacheStats {
+    /// The number of blocks read from the cache without accessing the storage device.
+    pub hits: u64,
+    /// The number of blocks that had to be read from the storage device.
+    pub misses: u64,
+    /// The number of modified blocks that have been written back to the storage device.
+    pub write_backs: u64,
+    /// The number of blocks that were evicted to make room for other blocks.
+    pub evictions: u64,
 }
 
 impl BlockCache {
-    /// Creates a new `BlockCache` device 
-    pub fn new(storage_device: StorageDeviceRef) -> BlockCache {
+    /// Creates a new `BlockCache` that holds up to `capacity` blocks of the given `storage_device`.
+    pub fn new(storage_device: StorageDeviceRef, capacity: usize) -> BlockCache {
+        let (block_size, size_in_blocks) = {
+            let locked_device = storage_device.lock();
+            (locked_device.block_size(), locked_device.size_in_blocks())
+        };
         BlockCache {
-            cache: HashMap::new(),
+            cache: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            capacity: capacity.max(1),
             storage_device,
+            block_size,
+            size_in_blocks,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Creates a new shareable `BlockCache` that holds up to `capacity` blocks of the given `storage_device`.
+    ///
+    /// Unlike a cache created via [`BlockCache::new()`], this cache is invalidated by [`invalidate_device()`]
+    /// and shrunk when memory runs out.
+    pub fn new_ref(storage_device: StorageDeviceRef, capacity: usize) -> BlockCacheRef {
+        SHRINKER.call_once(|| {
+            if let Err(e) = memory_pressure::register_shrinker("block_cache", alloc::boxed::Box::new(shrink_all_caches)) {
+                error!("block_cache: failed to register shrinker: {}", e);
+            }
+        });
+        let cache = Arc::new(Mutex::new(BlockCache::new(storage_device, capacity)));
+        let mut caches = CACHES.lock();
+        caches.retain(|cache| cache.strong_count() > 0);
+        caches.push(Arc::downgrade(&cache));
+        cache
+    }
+
+    /// Returns the underlying storage device of this cache.
+    pub fn storage_device(&self) -> &StorageDeviceRef {
+        &self.storage_device
+    }
+
+    /// Returns the maximum number of blocks in this cache.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of blocks currently in this cache.
+    pub fn cached_blocks(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Returns the number of modified blocks in this cache that have yet to be written back.
+    pub fn dirty_blocks(&self) -> usize {
+        self.cache.values().filter(|cached_block| matches!(cached_block.state, CacheState::Modified)).count()
+    }
+
+    /// Returns statistics about the usage of this cache.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Returns the contents of the given block, reading it from the storage device
+    /// only if it isn't already in the cache.
+    pub fn read_block(&mut self, block_num: usize) -> Result<&[u8], &'static str> {
+        if block_num >= self.size_in_blocks {
+            return Err("block_cache: block is beyond the end of the storage device");
+        }
+        if self.is_valid(block_num) {
+            self.stats.hits += 1;
+            self.touch(block_num);
+        } else {
+            profile_scope!("block_cache::read_from_device");
+            let mut block = vec![0; self.block_size];
+            self.storage_device.lock().read_blocks(&mut block, block_num)?;
+            self.stats.misses += 1;
+            self.insert(block_num, block, CacheState::Shared)?;
         }
+        Ok(&self.cache[&block_num].block)
     }
 
-    /// Flushes the given block to the backing storage device. 
-    /// If the `block_to_flush` is None, all blocks in the entire cache
-    /// will be written back to the storage device.
-    pub fn flush(&mut self, block_num: Option<usize>) -> Result<(), &'static str> {
-        let mut locked_device = self.storage_device.lock();
-        if let Some(bn) = block_num {
-            // Flush just one block
-            if let Some(cached_block) = self.cache.get_mut(&bn) {
-                Self::flush_block(&mut *locked_device, bn, cached_block)?;
+    /// Overwrites the contents of the given block in the cache with `buffer`,
+    /// which must be exactly one block long.
+    ///
+    /// The block isn't written to the storage device until it is written back.
+    pub fn write_block(&mut self, block_num: usize, buffer: &[u8]) -> Result<(), &'static str> {
+        if buffer.len() != self.block_size {
+            return Err("block_cache: buffer length must be the block size");
+        }
+        if block_num >= self.size_in_blocks {
+            return Err("block_cache: block is beyond the end of the storage device");
+        }
+        self.insert(block_num, buffer.to_vec(), CacheState::Modified)
+    }
+
+    /// Writes back the given block to the storage device if it was modified.
+    /// If the block isn't in the cache, this does nothing.
+    pub fn flush_block(&mut self, block_num: usize) -> Result<(), &'static str> {
+        match self.cache.get(&block_num) {
+            Some(cached_block) if matches!(cached_block.state, CacheState::Modified) => self.write_back(block_num, 1),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes back all modified blocks in this cache to the storage device,
+    /// coalescing each run of contiguous modified blocks into a single write.
+    ///
+    /// Unlike [`BlockWriter::flush()`], this doesn't flush the storage device itself.
+    /// Returns the number of blocks that were written back.
+    pub fn write_back_all(&mut self) -> Result<usize, &'static str> {
+        let dirty: Vec<usize> = self.cache.iter()
+            .filter(|(_, cached_block)| matches!(cached_block.state, CacheState::Modified))
+            .map(|(&block_num, _)| block_num)
+            .collect();
+
+        let mut start = 0;
+        while start < dirty.len() {
+            let mut end = start + 1;
+            while end < dirty.len() && dirty[end] == dirty[end - 1] + 1 {
+                end += 1;
             }
-            // If the block wasn't in the cache, do nothing.
+            self.write_back(dirty[start], end - start)?;
+            start = end;
         }
-        else {
-            // Flush all blocks
-            for (bn, cached_block) in self.cache.iter_mut() {
-                Self::flush_block(&mut *locked_device, *bn, cached_block)?;
+        Ok(dirty.len())
+    }
+
+    /// Marks the given range of blocks as invalid, such that they are re-read
+    /// from the storage device the next time they are read.
+    ///
+    /// This must be called whenever those blocks are modified on the storage device
+    /// by any means other than this cache.
+    /// Any modifications to those blocks that haven't yet been written back are discarded.
+    pub fn invalidate(&mut self, blocks: Range<usize>) {
+        for (block_num, cached_block) in self.cache.range_mut(blocks) {
+            if matches!(cached_block.state, CacheState::Modified) {
+                warn!("block_cache: discarding modifications to invalidated block {}", block_num);
             }
+            cached_block.state = CacheState::Invalid;
         }
-        Ok(())
     }
 
-    /// An internal function that first checks the cache for a specific block
-    /// in order to avoid reading from the storage device.
-    /// If that block exists in the cache, it is copied into the buffer. 
-    /// If not, it is read from the storage device into the cache, and then copied into the buffer.
-    pub fn read_block(cache: &mut BlockCache, block: usize) -> Result<&[u8], &'static str> {
-        let mut locked_device = cache.storage_device.lock();
-        match cache.cache.entry(block) {
-            Entry::Occupied(occ) => {
-                // An existing entry in the cache can be used directly (without going to the backing store)
-                // if it's in the `Modified` or `Shared` state.
-                // But if it's in the `Invalid` state, we have to re-read the block from the storage device.
-                let cached_block = occ.into_mut();
-                match cached_block.state {
-                    CacheState::Modified | CacheState::Shared => Ok(&cached_block.block),
-                    CacheState::Invalid => {
-                        locked_device.read_blocks(&mut cached_block.block, block)?;
-                        cached_block.state = CacheState::Shared;
-                        Ok(&cached_block.block)
-                    }
-                }
+    /// Marks every block in the cache as invalid, as per [`BlockCache::invalidate()`].
+    pub fn invalidate_all(&mut self) {
+        self.invalidate(0 .. usize::MAX);
+    }
+
+    /// Evicts clean blocks, least-recently used first, until at least `max_bytes` bytes have been freed
+    /// or no clean blocks remain, and returns the number of bytes freed.
+    ///
+    /// Modified blocks are left in the cache.
+    pub fn shrink(&mut self, max_bytes: usize) -> usize {
+        let clean_blocks: Vec<(u64, usize)> = self.lru.iter()
+            .filter(|(_, block_num)| !matches!(self.cache[block_num].state, CacheState::Modified))
+            .map(|(&last_used, &block_num)| (last_used, block_num))
+            .collect();
+        let mut freed = 0;
+        for (last_used, block_num) in clean_blocks {
+            if freed >= max_bytes {
+                break;
             }
-            Entry::Vacant(vacant) => {
-                // A vacant entry will be read from the backing storage device,
-                // so it will always start out in the `Shared` state.
-                let mut v = vec![0; locked_device.block_size()];
-                locked_device.read_blocks(&mut v, block)?;
-                let cb = CachedBlock {
-                    block: v,
-                    state: CacheState::Shared,
-                };
-                let cached_block = vacant.insert(cb);
-                Ok(&cached_block.block)
+            self.lru.remove(&last_used);
+            if let Some(cached_block) = self.cache.remove(&block_num) {
+                freed += cached_block.block.capacity();
             }
+            self.stats.evictions += 1;
         }
+        freed
     }
 
-    pub fn write_block(&mut self, block_num: usize, buffer_to_write: Cow<[u8]>)
-    //pub fn write_block(&mut self, block_num: usize, buffer_to_write: Cow<[u8]>)
-        -> Result<(), &'static str> 
-        {
-            let mut locked_device = self.storage_device.lock();
+    /// Returns `true` if the given block is in the cache and can be read without going to the storage device.
+    fn is_valid(&self, block_num: usize) -> bool {
+        self.cache.get(&block_num).is_some_and(|cached_block| !matches!(cached_block.state, CacheState::Invalid))
+    }
 
-            let owned_buffer: Vec<u8> = match buffer_to_write {
-                Cow::Borrowed(slice_ref) => slice_ref.to_owned(),
-                Cow::Owned(vec_owned) => vec_owned, 
-            };
+    /// Marks the given block as the most-recently used block.
+    fn touch(&mut self, block_num: usize) {
+        self.clock += 1;
+        if let Some(cached_block) = self.cache.get_mut(&block_num) {
+            self.lru.remove(&cached_block.last_used);
+            cached_block.last_used = self.clock;
+            self.lru.insert(self.clock, block_num);
+        }
+    }
 
-            let mut new_cached_block = CachedBlock {
-                block: owned_buffer,
-                state: CacheState::Modified,
-            };
-            // Currently using a write-through policy right now, so flush the block immediately
-            BlockCache::flush_block(&mut *locked_device, block_num, &mut new_cached_block)?;
-            self.cache.insert(block_num, new_cached_block);
+    /// Inserts the given block contents into the cache with the given `state`,
+    /// evicting the least-recently used blocks if the cache is full.
+    fn insert(&mut self, block_num: usize, block: Vec<u8>, state: CacheState) -> Result<(), &'static str> {
+        if let Some(cached_block) = self.cache.get_mut(&block_num) {
+            cached_block.block = block;
+            cached_block.state = state;
+        } else {
+            while self.cache.len() >= self.capacity {
+                self.evict_one()?;
+            }
+            self.cache.insert(block_num, CachedBlock { block, state, last_used: 0 });
+        }
+        self.touch(block_num);
+        Ok(())
+    }
 
-            Ok(())
+    /// Evicts the least-recently used block from the cache, writing it back first if it was modified.
+    fn evict_one(&mut self) -> Result<(), &'static str> {
+        let Some((_, &block_num)) = self.lru.first_key_value() else {
+            return Ok(());
+        };
+        self.flush_block(block_num)?;
+        if let Some(cached_block) = self.cache.remove(&block_num) {
+            self.lru.remove(&cached_block.last_used);
+        }
+        self.stats.evictions += 1;
+        Ok(())
     }
 
-    /// An internal function that writes out the given `cached_block`
-    /// to the given locked `StorageDevice` if the cached block is in the `Modified` state.
-    fn flush_block(locked_device: &mut dyn StorageDevice, block_num: usize, cached_block: &mut CachedBlock) -> Result<(), &'static str> {
-        // we only need to actually write blocks in the `Modified` state.
-        match cached_block.state {
-            CacheState::Shared | CacheState::Invalid => { },
-            CacheState::Modified => {
-                locked_device.write_blocks(&cached_block.block, block_num)?;
+    /// Writes back the `count` contiguous blocks starting at `first_block`, all of which must be in the cache,
+    /// to the storage device with a single write.
+    fn write_back(&mut self, first_block: usize, count: usize) -> Result<(), &'static str> {
+        profile_scope!();
+        let mut buffer = Vec::with_capacity(count * self.block_size);
+        for block_num in first_block .. first_block + count {
+            buffer.extend_from_slice(&self.cache[&block_num].block);
+        }
+        self.storage_device.lock().write_blocks(&buffer, first_block)?;
+        for block_num in first_block .. first_block + count {
+            if let Some(cached_block) = self.cache.get_mut(&block_num) {
                 cached_block.state = CacheState::Shared;
             }
         }
+        self.stats.write_backs += count as u64;
         Ok(())
     }
+
+    /// Returns an error if `buffer` doesn't hold a whole number of blocks,
+    /// or if it extends beyond the end of the storage device when starting at `block_offset`.
+    fn check_range(&self, buffer: &[u8], block_offset: usize) -> Result<usize, IoError> {
+        if buffer.len() % self.block_size != 0 {
+            return Err(IoError::InvalidInput);
+        }
+        let count = buffer.len() / self.block_size;
+        if block_offset.checked_add(count).map_or(true, |end| end > self.size_in_blocks) {
+            return Err(IoError::InvalidInput);
+        }
+        Ok(count)
+    }
+}
+
+impl Drop for BlockCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.write_back_all() {
+            error!("block_cache: failed to write back modified blocks upon drop: {}", e);
+        }
+    }
 }
 
+impl BlockIo for BlockCache {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+}
+
+impl KnownLength for BlockCache {
+    fn len(&self) -> usize {
+        self.block_size * self.size_in_blocks
+    }
+}
+
+impl BlockReader for BlockCache {
+    fn read_blocks(&mut self, buffer: &mut [u8], block_offset: usize) -> Result<usize, IoError> {
+        let count = self.check_range(buffer, block_offset)?;
+        let block_size = self.block_size;
+        let mut i = 0;
+        while i < count {
+            let block_num = block_offset + i;
+            if self.is_valid(block_num) {
+                self.stats.hits += 1;
+                self.touch(block_num);
+                buffer[i * block_size .. (i + 1) * block_size].copy_from_slice(&self.cache[&block_num].block);
+                i += 1;
+                continue;
+            }
+            // Read the whole run of consecutive blocks that aren't in the cache with a single device read.
+            let run = (i .. count).take_while(|&j| !self.is_valid(block_offset + j)).count();
+            let chunk = &mut buffer[i * block_size .. (i + run) * block_size];
+            {
+                profile_scope!("block_cache::read_from_device");
+                self.storage_device.lock().read_blocks(chunk, block_num)?;
+            }
+            self.stats.misses += run as u64;
+            for (j, block) in chunk.chunks_exact(block_size).enumerate() {
+                self.insert(block_num + j, block.to_vec(), CacheState::Shared)?;
+            }
+            i += run;
+        }
+        Ok(count)
+    }
+}
+
+impl BlockWriter for BlockCache {
+    fn write_blocks(&mut self, buffer: &[u8], block_offset: usize) -> Result<usize, IoError> {
+        let count = self.check_range(buffer, block_offset)?;
+        for (i, block) in buffer.chunks_exact(self.block_size).enumerate() {
+            self.insert(block_offset + i, block.to_vec(), CacheState::Modified)?;
+        }
+        Ok(count)
+    }
+
+    /// Writes back all modified blocks and then flushes the underlying storage device.
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.write_back_all()?;
+        self.storage_device.lock().flush()
+    }
+}
+
+impl StorageDevice for BlockCache {
+    fn size_in_blocks(&self) -> usize {
+        self.size_in_blocks
+    }
+}
+
+/// Invalidates the given range of blocks in every cache of the given `storage_device`,
+/// as per [`BlockCache::invalidate()`].
+///
+/// This must be called by anything that writes to a storage device without going through its caches.
+/// Neither the lock on the `storage_device` nor on any of its caches may be held.
+pub fn invalidate_device(storage_device: &StorageDeviceRef, blocks: Range<usize>) {
+    let caches: Vec<BlockCacheRef> = CACHES.lock().iter().filter_map(Weak::upgrade).collect();
+    for cache in caches {
+        let mut cache = cache.lock();
+        if Arc::ptr_eq(&cache.storage_device, storage_device) {
+            cache.invalidate(blocks.clone());
+        }
+    }
+}
+
+/// The `memory_pressure` shrinker, which evicts clean blocks from every cache created via [`BlockCache::new_ref()`].
+///
+/// Cached blocks are stored on the heap, so the freed memory is only returned to the frame allocator
+/// once the heap releases it, e.g., via the heap's own shrinker. Thus, the returned number of frames is an estimate.
+fn shrink_all_caches(num_frames: usize) -> usize {
+    // Don't wait for any locks, as the shrinker may be invoked while they're held.
+    let caches: Vec<BlockCacheRef> = match CACHES.try_lock() {
+        Some(caches) => caches.iter().filter_map(Weak::upgrade).collect(),
+        None => return 0,
+    };
+    let max_bytes = num_frames.saturating_mul(PAGE_SIZE);
+    let mut freed = 0;
+    for cache in caches {
+        if freed >= max_bytes {
+            break;
+        }
+        if let Some(mut cache) = cache.try_lock() {
+            freed += cache.shrink(max_bytes - freed);
+        }
+    }
+    freed / PAGE_SIZE
+}
+
+/// Spawns a task that writes back the modified blocks of the given `cache` every `interval`,
+/// such that modifications reach the storage device even if the cache is never flushed explicitly.
+///
+/// The task exits once the cache has been dropped.
+pub fn spawn_flush_task(cache: &BlockCacheRef, interval: Duration) -> Result<JoinableTaskRef, &'static str> {
+    spawn::new_task_builder(flush_task, (Arc::downgrade(cache), interval))
+        .name("block_cache_flusher".into())
+        .spawn()
+}
+
+/// The entry point for a task spawned by [`spawn_flush_task()`].
+fn flush_task((cache, interval): (Weak<Mutex<BlockCache>>, Duration)) -> Result<(), &'static str> {
+    loop {
+        sleep::sleep(interval).map_err(|_| "block_cache: failed to sleep")?;
+        let Some(cache) = cache.upgrade() else {
+            return Ok(());
+        };
+        let result = cache.lock().write_back_all();
+        if let Err(e) = result {
+            error!("block_cache: failed to write back modified blocks: {}", e);
+        }
+    }
+}
 
 
 /// A block from a storage device stored in a cache.
 /// This currently includes the actual owned cached content as a vector of bytes on the heap,
-/// in addition to the `CacheState` of the cached item.
-/// 
-/// TODO: allow non-dirty blocks to be freed (reclaimed) upon memory pressure. 
+/// in addition to the `CacheState` of the cached item and when it was last used.
 #[derive(Debug)]
-struct CachedBlock { // Not sure if this should be public, but it seems necessary to fix type leak. TODO make non-public.
+struct CachedBlock {
     block: Vec<u8>,
     state: CacheState,
+    /// The value of the cache's clock when this block was last used, which is its key in the LRU list.
+    last_used: u64,
 }
 
-type InternalCache = HashMap<usize, CachedBlock>;
-
 
 /// The states of an item in the cache, following the MSI cache coherence protocol.
 #[derive(Debug)]
-#[allow(dead_code)]
 enum CacheState {
     /// Dirty: the cached item has been modified more recently than the backing store,
     /// so it must be flushed at a future time to guarantee data correctness and consistency.
     /// A `Modified` cached item **cannot** be safely dropped from the cache.
-    /// A `Modified` cached item can be safely read from or overwritten without going to the backing store.  
+    /// A `Modified` cached item can be safely read from or overwritten without going to the backing store.
     Modified,
     /// Clean: the cached item and the backing store are in sync; they have the same value.
     /// A `Shared` cached item can be safely dropped from the cache.
@@ -182,7 +511,7 @@ enum CacheState {
     /// as the backing storage has a more recent copy than the cache.
     /// Therefore, if a read of an `Invalid` cached item is requested,
     /// it must be re-read from the backing storage.
-    /// An `Invalid` item can still be overwritten in the cache without going to the backing store. 
+    /// An `Invalid` item can still be overwritten in the cache without going to the backing store.
     /// An `Invalid` item can be safely dropped from the cache.
-    Invalid,  
+    Invalid,
 }
@@ -29,6 +29,11 @@ pub trait MemoryRegion {
 
     /// Returns whether the region can be used by the frame allocator.
     fn is_usable(&self) -> bool;
+
+    /// Returns whether the firmware reported that the region contains faulty memory.
+    ///
+    /// Defective regions are never usable, and they must not be accessed at all.
+    fn is_defective(&self) -> bool;
 }
 
 pub trait ElfSection {
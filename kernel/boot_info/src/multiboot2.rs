@@ -16,6 +16,10 @@ impl<'a> crate::MemoryRegion for &'a multiboot2::MemoryArea {
     fn is_usable(&self) -> bool {
         matches!(self.typ(), multiboot2::MemoryAreaType::Available)
     }
+
+    fn is_defective(&self) -> bool {
+        matches!(self.typ(), multiboot2::MemoryAreaType::Defective)
+    }
 }
 
 type MemoryRegionIterator<'a> = impl Iterator<Item = &'a multiboot2::MemoryArea>;
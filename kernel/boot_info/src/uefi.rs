@@ -14,6 +14,11 @@ pub const STACK_SIZE: usize = (KERNEL_STACK_SIZE_IN_PAGES + 2) * PAGE_SIZE;
 const MODULES_MEMORY_KIND: uefi_bootloader_api::MemoryRegionKind =
     uefi_bootloader_api::MemoryRegionKind::UnknownUefi(0x80000000);
 
+/// The memory region kind of UEFI's `EfiUnusableMemory` type,
+/// i.e., memory in which the firmware detected errors.
+const UNUSABLE_MEMORY_KIND: uefi_bootloader_api::MemoryRegionKind =
+    uefi_bootloader_api::MemoryRegionKind::UnknownUefi(8);
+
 impl crate::MemoryRegion for uefi_bootloader_api::MemoryRegion {
     fn start(&self) -> PhysicalAddress {
         PhysicalAddress::new_canonical(self.start)
@@ -26,6 +31,10 @@ impl crate::MemoryRegion for uefi_bootloader_api::MemoryRegion {
     fn is_usable(&self) -> bool {
         matches!(self.kind, uefi_bootloader_api::MemoryRegionKind::Usable)
     }
+
+    fn is_defective(&self) -> bool {
+        self.kind == UNUSABLE_MEMORY_KIND
+    }
 }
 
 pub struct MemoryRegions<'a> {
@@ -84,6 +84,9 @@ pub fn init(
         logger::set_log_mirror_function(mirror_log_callbacks::mirror_to_early_vga);
     }
 
+    // Retain recent log messages such that they can be streamed to remote clients later on.
+    logger::enable_log_buffer(log_stream::LOG_BUFFER_SIZE);
+
     // calculate TSC period and initialize it
     // not strictly necessary, but more accurate if we do it early on before interrupts, multicore, and multitasking
     #[cfg(target_arch = "x86_64")]
@@ -105,16 +108,27 @@ pub fn init(
     // arch-gate: the IDT & special stacks are x86_64 specific
     #[cfg(target_arch = "x86_64")]
     let idt = {
-        let (double_fault_stack, privilege_stack) = {
+        let (double_fault_stack, page_fault_stack, privilege_stack) = {
             let mut kernel_mmi = kernel_mmi_ref.lock();
             (
                 stack::alloc_stack(KERNEL_STACK_SIZE_IN_PAGES, &mut kernel_mmi.page_table)
                     .ok_or("could not allocate double fault stack")?,
+                stack::alloc_stack(KERNEL_STACK_SIZE_IN_PAGES, &mut kernel_mmi.page_table)
+                    .ok_or("could not allocate page fault stack")?,
                 stack::alloc_stack(1, &mut kernel_mmi.page_table)
                     .ok_or("could not allocate privilege stack")?,
             )
         };
-        interrupts::init(double_fault_stack.top_unusable(), privilege_stack.top_unusable())?
+        let idt = interrupts::init(
+            double_fault_stack.top_unusable(),
+            page_fault_stack.top_unusable(),
+            privilege_stack.top_unusable(),
+        )?;
+        // The exception stacks are used by this CPU for as long as it runs,
+        // so they must never be unmapped.
+        core::mem::forget(double_fault_stack);
+        core::mem::forget(page_fault_stack);
+        idt
     };
 
     #[cfg(target_arch = "aarch64")] {
@@ -133,6 +147,7 @@ pub fn init(
     // which is bootstrapped from this current execution context.
     scheduler::init()?;
     let bootstrap_task = spawn::init(kernel_mmi_ref.clone(), bsp_id, bsp_initial_stack)?;
+    watchdog::register_cpu(bsp_id);
     info!("Created initial bootstrap task: {:?}", bootstrap_task);
 
     // after we've initialized the task subsystem, we can use better exception handlers
@@ -162,6 +177,24 @@ pub fn init(
     // Now that other CPUs are fully booted, init TLB shootdowns,
     // which rely on Local APICs to broadcast an IPI to all running CPUs.
     tlb_shootdown::init();
+
+    // Every CPU is now running a task, so the current task can be safely obtained
+    // from any context in which the heap is used, allowing allocations to be attributed to tasks.
+    heap::set_task_id_source(task::get_my_current_task_id);
+
+    // Symbolize the backtraces in heap error reports now that crates can be looked up by address.
+    #[cfg(kasan)]
+    heap::kasan::set_symbolizer(|return_address| {
+        let section = mod_mgmt::get_initial_kernel_namespace()
+            .and_then(|ns| ns.get_section_containing_address(return_address, false));
+        match section {
+            Some((sec, offset)) => error!("    {:>#018X} in {} + {:#X}", return_address, sec.name, offset),
+            None => error!("    {:>#018X} in ??", return_address),
+        }
+    });
+
+    // Allow caches and drivers (e.g., the virtio balloon) to free up memory before a frame allocation fails.
+    memory_pressure::init()?;
     
     // Initialize the per-core heaps.
     // arch-gate: no multicore support on aarch64 at the moment
@@ -180,20 +213,36 @@ pub fn init(
     }
 
     // arch-gate: no windowing/input support on aarch64 at the moment
+    // If the window manager can't be started, fall back to a text console on the framebuffer.
     #[cfg(target_arch = "x86_64")]
-    match window_manager::init() {
+    match window_manager::init().or_else(|error| {
+        error!("Failed to init window manager: {error}");
+        fb_console::init()
+    }) {
         Ok((key_producer, mouse_producer)) => {
             device_manager::init(key_producer, mouse_producer)?;
         },
         Err(error) => {
-            error!("Failed to init window manager (expected if using nographic): {error}");
+            error!("Failed to init framebuffer console (expected if using nographic): {error}");
         }
     }
 
     #[cfg(target_arch = "aarch64")]
     device_manager::init()?;
 
+    // Initialize the RTC-backed wall clock and RTC alarms.
+    // A missing or invalid RTC is not a fatal error.
+    #[cfg(target_arch = "x86_64")] {
+        if let Err(e) = rtc::init_wall_clock() {
+            error!("Failed to initialize the RTC wall clock: {e}");
+        }
+        if let Err(e) = rtc::init_alarms() {
+            error!("Failed to initialize RTC alarms: {e}");
+        }
+    }
+
     task_fs::init()?;
+    sys_fs::init()?;
 
     // create a SIMD personality
     #[cfg(simd_personality)] {
@@ -213,6 +262,26 @@ pub fn init(
 
     // 2. Spawn various system tasks/daemons,
     console::start_connection_detection()?;
+    symbol_loader::start()?;
+    hung_task_detector::start()?;
+    #[cfg(page_merging)]
+    page_merger::start()?;
+    #[cfg(target_arch = "x86_64")] {
+        heap_shrinker::start()?;
+        if let Err(e) = machine_check::start_recovery_task() {
+            error!("Failed to start the machine-check recovery task: {e}");
+        }
+        if let Err(e) = machine_check::start_polling(machine_check::DEFAULT_POLL_INTERVAL) {
+            error!("Failed to start polling for corrected machine-check errors: {e}");
+        }
+    }
+    config_reload::start()?;
+    if net::get_default_interface().is_some() {
+        log_stream::start(log_stream::DEFAULT_PORT)?;
+    }
+
+    // Crates that are loaded together can now be loaded on all CPUs.
+    parallel_crate_loader::enable();
 
     // 3. Start the first application(s).
     first_application::start()?;
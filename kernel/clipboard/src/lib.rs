@@ -0,0 +1,156 @@
+//! A system-wide clipboard that windowed applications use to exchange data, e.g., text.
+//!
+//! The clipboard holds at most one item: a sequence of bytes tagged with its MIME type.
+//!
+//! # Capabilities
+//! Reading or writing the clipboard requires a [`ClipboardCapability`] with the corresponding [`Rights`].
+//! Capabilities can only be created via [`grant()`] by kernel tasks, i.e., not by applications.
+//! The window manager grants one to the active window whenever the user presses the copy or paste
+//! keyboard shortcut, and sends it along with the `CopyEvent` or `PasteEvent`.
+//! Thus, an application can only access the clipboard in response to the user asking it to.
+//!
+//! Granting a new capability revokes all previously-granted capabilities with the same rights,
+//! so an application cannot hold on to a capability in order to keep snooping on the clipboard.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+/// The MIME type of UTF-8 text.
+pub const MIME_TEXT_PLAIN: &str = "text/plain;charset=utf-8";
+
+/// The maximum number of bytes the clipboard can hold.
+pub const MAX_CONTENTS_SIZE: usize = 1 << 20;
+
+/// What a [`ClipboardCapability`] permits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rights {
+    /// Permits reading the clipboard's contents, i.e., pasting.
+    Read,
+    /// Permits replacing the clipboard's contents, i.e., copying.
+    Write,
+}
+
+/// An unforgeable permission to read or write the clipboard; see the [crate-level docs](crate).
+///
+/// A capability is valid until another capability with the same [`Rights`] is granted.
+#[derive(Clone, Debug)]
+pub struct ClipboardCapability {
+    rights: Rights,
+    generation: u64,
+}
+
+impl ClipboardCapability {
+    /// Returns what this capability permits.
+    pub fn rights(&self) -> Rights {
+        self.rights
+    }
+
+    /// Returns `true` if this capability hasn't been revoked by a newer one.
+    pub fn is_valid(&self) -> bool {
+        generation(self.rights).load(Ordering::Acquire) == self.generation
+    }
+
+    fn check(&self, rights: Rights) -> Result<(), &'static str> {
+        if self.rights != rights {
+            return Err("clipboard capability doesn't grant the required rights");
+        }
+        if !self.is_valid() {
+            return Err("clipboard capability has been revoked");
+        }
+        Ok(())
+    }
+}
+
+/// The contents of the clipboard.
+#[derive(Clone, Debug)]
+pub struct ClipboardContents {
+    /// The MIME type of `data`, e.g., [`MIME_TEXT_PLAIN`].
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl ClipboardContents {
+    /// Returns the contents as a string if they are text.
+    pub fn as_text(&self) -> Option<&str> {
+        if self.mime_type.starts_with("text/") {
+            core::str::from_utf8(&self.data).ok()
+        } else {
+            None
+        }
+    }
+}
+
+static CONTENTS: Mutex<Option<ClipboardContents>> = Mutex::new(None);
+
+/// The generation of the most recently-granted read capability.
+static READ_GENERATION: AtomicU64 = AtomicU64::new(0);
+/// The generation of the most recently-granted write capability.
+static WRITE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn generation(rights: Rights) -> &'static AtomicU64 {
+    match rights {
+        Rights::Read => &READ_GENERATION,
+        Rights::Write => &WRITE_GENERATION,
+    }
+}
+
+/// Grants a new capability with the given `rights`, revoking all previous capabilities with the same rights.
+///
+/// Returns an error if the current task is an application task.
+pub fn grant(rights: Rights) -> Result<ClipboardCapability, &'static str> {
+    let is_kernel_task = task::with_current_task(|t| !t.is_application()).unwrap_or(false);
+    if !is_kernel_task {
+        return Err("only kernel tasks may grant clipboard capabilities");
+    }
+    let generation = generation(rights).fetch_add(1, Ordering::AcqRel) + 1;
+    Ok(ClipboardCapability { rights, generation })
+}
+
+/// Replaces the clipboard's contents with the given `data` of the given `mime_type`.
+///
+/// Requires a valid capability with [`Rights::Write`].
+pub fn set_contents(cap: &ClipboardCapability, mime_type: &str, data: Vec<u8>) -> Result<(), &'static str> {
+    cap.check(Rights::Write)?;
+    if data.len() > MAX_CONTENTS_SIZE {
+        return Err("data is too large for the clipboard");
+    }
+    *CONTENTS.lock() = Some(ClipboardContents { mime_type: String::from(mime_type), data });
+    Ok(())
+}
+
+/// Replaces the clipboard's contents with the given `text`.
+///
+/// Requires a valid capability with [`Rights::Write`].
+pub fn set_text(cap: &ClipboardCapability, text: &str) -> Result<(), &'static str> {
+    set_contents(cap, MIME_TEXT_PLAIN, Vec::from(text.as_bytes()))
+}
+
+/// Returns a copy of the clipboard's contents, or `None` if it is empty.
+///
+/// Requires a valid capability with [`Rights::Read`].
+pub fn get_contents(cap: &ClipboardCapability) -> Result<Option<ClipboardContents>, &'static str> {
+    cap.check(Rights::Read)?;
+    Ok(CONTENTS.lock().clone())
+}
+
+/// Returns the clipboard's contents if they are text, or `None` if the clipboard is empty or holds something else.
+///
+/// Requires a valid capability with [`Rights::Read`].
+pub fn get_text(cap: &ClipboardCapability) -> Result<Option<String>, &'static str> {
+    cap.check(Rights::Read)?;
+    Ok(CONTENTS.lock().as_ref().and_then(|c| c.as_text().map(String::from)))
+}
+
+/// Empties the clipboard.
+///
+/// Requires a valid capability with [`Rights::Write`].
+pub fn clear(cap: &ClipboardCapability) -> Result<(), &'static str> {
+    cap.check(Rights::Write)?;
+    *CONTENTS.lock() = None;
+    Ok(())
+}
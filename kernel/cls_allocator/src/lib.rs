@@ -2,7 +2,7 @@
 
 extern crate alloc;
 
-use alloc::{sync::Arc, vec::Vec};
+use alloc::vec::Vec;
 
 use cpu::CpuId;
 use crate_metadata::{LoadedSection, StrongSectionRef};
@@ -34,7 +34,7 @@ pub fn add_static_section(
 pub fn add_dynamic_section(
     section: LoadedSection,
     alignment: usize,
-) -> Result<(usize, Arc<LoadedSection>), LocalStorageInitializerError> {
+) -> Result<(usize, StrongSectionRef), LocalStorageInitializerError> {
     CLS_INITIALIZER
         .lock()
         .add_new_dynamic_section(section, alignment)
@@ -58,6 +58,13 @@ pub struct FramebufferUpdates<'a, P: Pixel> {
     /// should be composited. 
     /// This coordinate is expressed relative to the top-left corner of the destination framebuffer. 
     pub coordinate_in_dest_framebuffer: Coord,
+    /// How opaque the source framebuffer is as a whole, from `0` (invisible) to `255` (fully opaque).
+    /// This is applied on top of the transparency of each of its pixels.
+    pub opacity: u8,
+    /// How many destination pixels each source pixel covers along each axis, e.g., `2` to draw
+    /// a framebuffer rendered at a low logical resolution onto a high-resolution screen.
+    /// A scale of `1` composites the source framebuffer unscaled.
+    pub scale: usize,
 }
 
 /// A `CompositableRegion` is an abstract region (i.e., a bounding box) 
@@ -82,12 +89,28 @@ pub trait CompositableRegion {
     /// The `dest_coord` is the coordinate in the destination buffer (relative to its top-left corner)
     /// where the `src_fb` will be composited (starting at the `src_fb`'s top-left corner).
     /// `src_fb_row_range` is the index range of rows in the source framebuffer to blend.
+    /// `opacity` is the opacity of the whole `src_fb`; see [`FramebufferUpdates::opacity`].
     fn blend_buffers<P: Pixel>(
         &self, 
         src_fb: &Framebuffer<P>, 
         dest_fb: &mut Framebuffer<P>, 
         dest_coord: Coord,
-        src_fb_row_range: Range<usize>       
+        src_fb_row_range: Range<usize>,
+        opacity: u8,
+    ) -> Result<(), &'static str>;
+
+    /// Blends the source framebuffer `src_fb`, scaled up by the integer factor `scale`,
+    /// into the pixels of the destination framebuffer `dest_fb` within this region.
+    /// The `dest_coord` is the coordinate in the destination buffer (relative to its top-left corner)
+    /// where the top-left corner of the scaled `src_fb` will be composited.
+    /// `opacity` is the opacity of the whole `src_fb`; see [`FramebufferUpdates::opacity`].
+    fn blend_buffers_scaled<P: Pixel>(
+        &self,
+        src_fb: &Framebuffer<P>,
+        dest_fb: &mut Framebuffer<P>,
+        dest_coord: Coord,
+        scale: usize,
+        opacity: u8,
     ) -> Result<(), &'static str>;
 }
 
@@ -108,13 +131,31 @@ impl CompositableRegion for Coord {
         dest_fb: &mut Framebuffer<P>, 
         dest_coord: Coord,        
         _src_fb_row_range: Range<usize>,
+        opacity: u8,
     ) -> Result<(), &'static str>{
         let relative_coord = *self - dest_coord;
-        if let Some(pixel) = src_fb.get_pixel(relative_coord) {
-            dest_fb.draw_pixel(*self, pixel);
+        if opacity == u8::MAX {
+            if let Some(pixel) = src_fb.get_pixel(relative_coord) {
+                dest_fb.draw_pixel(*self, pixel);
+            }
+        } else {
+            dest_fb.composite_rect_with_opacity(src_fb, relative_coord, *self, 1, 1, opacity);
         }
         Ok(())
     }
+
+    fn blend_buffers_scaled<P: Pixel>(
+        &self,
+        src_fb: &Framebuffer<P>,
+        dest_fb: &mut Framebuffer<P>,
+        dest_coord: Coord,
+        scale: usize,
+        opacity: u8,
+    ) -> Result<(), &'static str> {
+        let pixel = Rectangle { top_left: *self, bottom_right: *self + (1, 1) };
+        dest_fb.composite_scaled(src_fb, dest_coord, scale, pixel, opacity);
+        Ok(())
+    }
 }
 
 impl CompositableRegion for Rectangle {
@@ -134,6 +175,7 @@ impl CompositableRegion for Rectangle {
         dest_fb: &mut Framebuffer<P>,
         dest_coord: Coord,
         src_fb_row_range: Range<usize>,
+        opacity: u8,
     ) -> Result<(), &'static str> {
         let (dest_width, dest_height) = dest_fb.get_size();
         let (src_width, src_height) = src_fb.get_size();
@@ -177,23 +219,22 @@ impl CompositableRegion for Rectangle {
         let width = core::cmp::min(coordinate_end.x as usize, src_width) - src_x_start;
         let height = core::cmp::min(coordinate_end.y as usize, src_height) - src_y_start;
 
-        // copy every line of the block to the dest framebuffer.
-        let src_buffer = &src_fb.buffer();
-        for i in 0..height {
-            let src_start = Coord::new(src_x_start as isize, (src_y_start + i) as isize);
-            let src_start_index = match src_fb.index_of(src_start) {
-                Some(index) => index,
-                None => {continue;}
-            };
-            let src_end_index = src_start_index + width;
-            let dest_start = src_start + dest_coord;
-            let dest_start_index =  match dest_fb.index_of(dest_start) {
-                Some(index) => index,
-                None => {continue;}
-            };
-            dest_fb.composite_buffer(&src_buffer[src_start_index..src_end_index], dest_start_index);
-        }
+        // composite the block into the dest framebuffer one row at a time.
+        let src_start = Coord::new(src_x_start as isize, src_y_start as isize);
+        dest_fb.composite_rect_with_opacity(src_fb, src_start, src_start + dest_coord, width, height, opacity);
 
         Ok(())
     }
+
+    fn blend_buffers_scaled<P: Pixel>(
+        &self,
+        src_fb: &Framebuffer<P>,
+        dest_fb: &mut Framebuffer<P>,
+        dest_coord: Coord,
+        scale: usize,
+        opacity: u8,
+    ) -> Result<(), &'static str> {
+        dest_fb.composite_scaled(src_fb, dest_coord, scale, *self, opacity);
+        Ok(())
+    }
 }
@@ -0,0 +1,227 @@
+//! The built-in configuration consumers.
+
+use crate::{entries, register, ApplyFn};
+use alloc::{format, vec::Vec};
+use color::Color;
+use core::str::FromStr;
+use keycodes_ascii::Keycode;
+use log::{warn, LevelFilter};
+use spin::Mutex;
+
+/// Registers all built-in configuration consumers.
+///
+/// A consumer whose existing configuration file can't be applied is still registered,
+/// such that a corrected file will be applied.
+pub(crate) fn register_all() {
+    let consumers: &[(&str, ApplyFn)] = &[
+        ("log_levels", apply_log_levels),
+        ("theme", apply_theme),
+        #[cfg(target_arch = "x86_64")]
+        ("keyboard", apply_keyboard),
+        ("keymap", apply_keymap),
+        ("mouse", apply_mouse),
+        ("network", apply_network),
+    ];
+    for (file_name, apply) in consumers {
+        if let Err(e) = register(file_name, *apply) {
+            warn!("Failed to apply configuration file {:?}: {}", file_name, e);
+        }
+    }
+}
+
+fn parse<T: FromStr>(value: &str, error: &'static str) -> Result<T, &'static str> {
+    value.parse().map_err(|_| error)
+}
+
+/// Applies the `log_levels` configuration file.
+///
+/// The `default` key sets the default log level, and every other key is a log target.
+/// Per-target overrides that aren't listed are removed.
+fn apply_log_levels(contents: &str) -> Result<(), &'static str> {
+    // Parse everything first, such that an invalid file doesn't partially apply.
+    let mut default = None;
+    let mut targets = Vec::new();
+    for entry in entries(contents) {
+        let (key, value) = entry?;
+        let level: LevelFilter = parse(value, "invalid log level, expected one of off, error, warn, info, debug, trace")?;
+        if key == "default" {
+            default = Some(level);
+        } else {
+            targets.push((key, level));
+        }
+    }
+
+    if let Some(level) = default {
+        logger::set_default_log_level(level);
+    }
+    logger::clear_target_log_levels();
+    for (target, level) in targets {
+        logger::set_target_log_level(target, Some(level));
+    }
+    Ok(())
+}
+
+/// Applies the `theme` configuration file.
+///
+/// The `foreground` and `background` keys set the colors of terminals as hexadecimal RGB values,
+/// e.g., `#90EE90`. Colors that aren't listed revert to their defaults.
+fn apply_theme(contents: &str) -> Result<(), &'static str> {
+    let mut foreground = libterm::FONT_FOREGROUND_COLOR;
+    let mut background = libterm::FONT_BACKGROUND_COLOR;
+    for entry in entries(contents) {
+        let (key, value) = entry?;
+        let hex = value.strip_prefix('#').or_else(|| value.strip_prefix("0x"))
+            .ok_or("invalid theme color, expected a hexadecimal RGB value, e.g., #90EE90")?;
+        let rgb = u32::from_str_radix(hex, 16).ok().filter(|rgb| *rgb <= 0xFF_FFFF)
+            .ok_or("invalid theme color, expected a hexadecimal RGB value, e.g., #90EE90")?;
+        match key {
+            "foreground" => foreground = Color::new(rgb),
+            "background" => background = Color::new(rgb),
+            _ => return Err("unknown theme setting"),
+        }
+    }
+    libterm::set_colors(foreground, background);
+    Ok(())
+}
+
+/// Applies the `keyboard` configuration file.
+///
+/// Settings that aren't listed keep their current values.
+#[cfg(target_arch = "x86_64")]
+fn apply_keyboard(contents: &str) -> Result<(), &'static str> {
+    use keyboard::TypematicDelay;
+
+    let mut typematic = keyboard::default_typematic();
+    let leds = keyboard::led_state();
+    let (mut caps_lock, mut num_lock, mut scroll_lock) = (leds.caps_lock(), leds.number_lock(), leds.scroll_lock());
+    let (mut typematic_changed, mut locks_changed) = (false, false);
+    for entry in entries(contents) {
+        let (key, value) = entry?;
+        match key {
+            "repeat_rate" => {
+                typematic.repeat_rate = parse(value, "invalid keyboard repeat_rate, expected 0 to 31")?;
+                typematic_changed = true;
+            }
+            "delay_ms" => {
+                typematic.delay = match value {
+                    "250" => TypematicDelay::_250ms,
+                    "500" => TypematicDelay::_500ms,
+                    "750" => TypematicDelay::_750ms,
+                    "1000" => TypematicDelay::_1000ms,
+                    _ => return Err("invalid keyboard delay_ms, expected 250, 500, 750, or 1000"),
+                };
+                typematic_changed = true;
+            }
+            "caps_lock" | "num_lock" | "scroll_lock" => {
+                let enabled = parse(value, "invalid keyboard lock state, expected true or false")?;
+                match key {
+                    "caps_lock" => caps_lock = enabled,
+                    "num_lock" => num_lock = enabled,
+                    _ => scroll_lock = enabled,
+                }
+                locks_changed = true;
+            }
+            _ => return Err("unknown keyboard setting"),
+        }
+    }
+
+    if typematic_changed {
+        keyboard::set_default_typematic(typematic)?;
+    }
+    if locks_changed {
+        keyboard::set_lock_state(caps_lock, num_lock, scroll_lock)?;
+    }
+    Ok(())
+}
+
+/// The ID of the key remapping filter added by the `keymap` configuration file, if any.
+static KEYMAP_FILTER: Mutex<Option<input_router::FilterId>> = Mutex::new(None);
+
+/// Applies the `keymap` configuration file.
+///
+/// Each key is the name of a key that is remapped to the key named by its value,
+/// e.g., `CapsLock = Control`. Keys that aren't listed are not remapped.
+fn apply_keymap(contents: &str) -> Result<(), &'static str> {
+    let mut mappings = Vec::new();
+    for entry in entries(contents) {
+        let (from, to) = entry?;
+        mappings.push((parse_keycode(from)?, parse_keycode(to)?));
+    }
+
+    let mut filter = KEYMAP_FILTER.lock();
+    if let Some(id) = filter.take() {
+        input_router::remove_filter(id);
+    }
+    if !mappings.is_empty() {
+        *filter = Some(input_router::add_filter(
+            input_router::InputDeviceKind::Keyboard,
+            alloc::sync::Arc::new(input_router::KeyRemap { mappings }),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the keycode with the given name, e.g., `CapsLock`, ignoring case.
+fn parse_keycode(name: &str) -> Result<Keycode, &'static str> {
+    (0..=u8::MAX)
+        .filter_map(|code| Keycode::try_from(code).ok())
+        .find(|keycode| format!("{keycode:?}").eq_ignore_ascii_case(name))
+        .ok_or("unknown key name in keymap")
+}
+
+/// The ID of the mouse acceleration filter added by the `mouse` configuration file, if any.
+static MOUSE_ACCELERATION_FILTER: Mutex<Option<input_router::FilterId>> = Mutex::new(None);
+
+/// Applies the `mouse` configuration file.
+///
+/// Mouse acceleration is enabled if the `acceleration_factor` key is listed and greater than 1,
+/// in which case `acceleration_threshold` defaults to 0.
+fn apply_mouse(contents: &str) -> Result<(), &'static str> {
+    let mut factor = 1;
+    let mut threshold = 0;
+    for entry in entries(contents) {
+        let (key, value) = entry?;
+        match key {
+            "acceleration_factor" => factor = parse(value, "invalid mouse acceleration_factor, expected a positive integer")?,
+            "acceleration_threshold" => threshold = parse(value, "invalid mouse acceleration_threshold, expected a non-negative integer")?,
+            _ => return Err("unknown mouse setting"),
+        }
+    }
+    if factor < 1 || threshold < 0 {
+        return Err("mouse acceleration_factor must be positive and acceleration_threshold non-negative");
+    }
+
+    let mut filter = MOUSE_ACCELERATION_FILTER.lock();
+    if let Some(id) = filter.take() {
+        input_router::remove_filter(id);
+    }
+    if factor > 1 {
+        *filter = Some(input_router::add_filter(
+            input_router::InputDeviceKind::Mouse,
+            alloc::sync::Arc::new(input_router::MouseAcceleration { threshold, factor }),
+        ));
+    }
+    Ok(())
+}
+
+/// Applies the `network` configuration file to the default network interface.
+///
+/// Both the `address` and `gateway` keys are required.
+fn apply_network(contents: &str) -> Result<(), &'static str> {
+    let mut address = None;
+    let mut gateway = None;
+    for entry in entries(contents) {
+        let (key, value) = entry?;
+        match key {
+            "address" => address = Some(parse::<net::IpCidr>(value, "invalid network address, expected CIDR notation, e.g., 10.0.2.15/24")?),
+            "gateway" => gateway = Some(parse::<net::IpAddress>(value, "invalid network gateway address")?),
+            _ => return Err("unknown network setting"),
+        }
+    }
+    let (Some(address), Some(gateway)) = (address, gateway) else {
+        return Err("network configuration requires both an address and a gateway");
+    };
+    net::get_default_interface()
+        .ok_or("no network interface exists")?
+        .set_ip_config(address, gateway)
+}
@@ -0,0 +1,159 @@
+//! Applies changes to configuration files to the subsystems that consume them, without a restart.
+//!
+//! Each configuration consumer is [registered](register()) along with the name of its
+//! configuration file within the [`CONFIG_DIRECTORY`].
+//! The task spawned by [`start()`] watches that directory via the `file_watch` crate,
+//! and whenever a consumer's file is created or modified, it reads the new contents
+//! and passes them to that consumer, which applies them immediately.
+//!
+//! Configuration files consist of `key = value` lines, which can be parsed with [`entries()`].
+//! Blank lines and lines starting with `#` are ignored.
+//!
+//! The following consumers are built in:
+//! * `log_levels`: the default log level and per-target log levels, e.g., `default = info`
+//!   and `mod_mgmt = debug`. Targets not listed revert to the default log level.
+//! * `theme`: the `foreground` and `background` colors of terminals, e.g., `background = #000000`.
+//! * `keyboard`: the keyboard's typematic settings (`repeat_rate`, `delay_ms`)
+//!   and lock state (`caps_lock`, `num_lock`, `scroll_lock`).
+//! * `keymap`: keys to be remapped to other keys, e.g., `CapsLock = Control`,
+//!   applied by the `input_router` to all keyboards.
+//! * `mouse`: mouse acceleration (`acceleration_factor`, `acceleration_threshold`),
+//!   applied by the `input_router` to all mice.
+//! * `network`: the `address` (in CIDR notation) and `gateway` of the default network interface.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use file_watch::{FileEventKind, Watcher};
+use fs_node::FileOrDir;
+use io::{ByteReader, KnownLength};
+use log::{error, info, warn};
+use path::Path;
+use spin::Mutex;
+use task::JoinableTaskRef;
+
+mod consumers;
+
+/// The absolute path of the directory that contains all configuration files.
+pub const CONFIG_DIRECTORY: &str = "/config";
+
+/// A function that applies the given contents of a configuration file.
+pub type ApplyFn = fn(&str) -> Result<(), &'static str>;
+
+/// The registered consumers, keyed by the name of their configuration file.
+static CONSUMERS: Mutex<BTreeMap<String, ApplyFn>> = Mutex::new(BTreeMap::new());
+
+/// Registers a consumer of the configuration file with the given name in the [`CONFIG_DIRECTORY`].
+///
+/// If that file already exists, its contents are applied immediately.
+/// Afterwards, `apply` is invoked with the new contents whenever the file is created or modified.
+/// Any existing consumer of the same file is replaced.
+pub fn register(file_name: &str, apply: ApplyFn) -> Result<(), &'static str> {
+    if file_name.is_empty() || file_name.contains('/') {
+        return Err("configuration file names must be non-empty and can't contain '/'");
+    }
+    CONSUMERS.lock().insert(file_name.to_string(), apply);
+    if let Some(contents) = read_config_file(file_name)? {
+        apply(&contents)?;
+    }
+    Ok(())
+}
+
+/// Returns an iterator over the `(key, value)` entries in the given configuration file contents.
+///
+/// Keys and values are trimmed of surrounding whitespace.
+/// Blank lines and comment lines (starting with `#`) are skipped;
+/// an error is returned for each other line that doesn't contain an `=`.
+pub fn entries(contents: &str) -> impl Iterator<Item = Result<(&str, &str), &'static str>> {
+    contents.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .ok_or("configuration lines must have the form `key = value`")
+        )
+}
+
+/// Creates the [`CONFIG_DIRECTORY`], registers the built-in consumers,
+/// and spawns the task that applies changes to configuration files.
+pub fn start() -> Result<JoinableTaskRef, &'static str> {
+    if Path::new(CONFIG_DIRECTORY).get(root::get_root()).is_none() {
+        vfs_node::VFSDirectory::create(CONFIG_DIRECTORY.trim_start_matches('/').to_string(), root::get_root())?;
+    }
+    consumers::register_all();
+    spawn::new_task_builder(config_reloader, ())
+        .name("config_reloader".into())
+        .spawn()
+}
+
+/// The entry point for the task that applies changes to configuration files.
+fn config_reloader(_: ()) -> Result<(), &'static str> {
+    let (waker, blocker) = waker::new_waker();
+    let watcher = Watcher::new(waker);
+    watcher.watch(CONFIG_DIRECTORY);
+    info!("config_reloader task started, watching {}", CONFIG_DIRECTORY);
+
+    loop {
+        // Coalesce multiple events for the same file, e.g., from a series of small writes.
+        let mut changed: Vec<String> = Vec::new();
+        for event in watcher.take_events() {
+            let Some(file_name) = event.path.strip_prefix(CONFIG_DIRECTORY).and_then(|p| p.strip_prefix('/')) else {
+                continue;
+            };
+            match event.kind {
+                FileEventKind::Created | FileEventKind::Modified => {
+                    if !changed.iter().any(|c| c == file_name) {
+                        changed.push(file_name.to_string());
+                    }
+                }
+                FileEventKind::Removed => {
+                    if CONSUMERS.lock().contains_key(file_name) {
+                        info!("Configuration file {:?} was removed; keeping its current settings", event.path);
+                    }
+                }
+            }
+        }
+        for file_name in changed {
+            reload(&file_name);
+        }
+        blocker.block();
+    }
+}
+
+/// Applies the current contents of the given configuration file to its consumer, if any.
+fn reload(file_name: &str) {
+    let Some(apply) = CONSUMERS.lock().get(file_name).copied() else { return };
+    match read_config_file(file_name) {
+        Ok(Some(contents)) => match apply(&contents) {
+            Ok(()) => info!("Applied configuration file {:?}", file_name),
+            Err(e) => error!("Failed to apply configuration file {:?}: {}", file_name, e),
+        },
+        Ok(None) => { }
+        Err(e) => warn!("Failed to read configuration file {:?}: {}", file_name, e),
+    }
+}
+
+/// Reads the given file in the [`CONFIG_DIRECTORY`], returning `None` if it doesn't exist.
+fn read_config_file(file_name: &str) -> Result<Option<String>, &'static str> {
+    let mut path = String::from(CONFIG_DIRECTORY);
+    path.push('/');
+    path.push_str(file_name);
+    let file = match Path::new(&path).get(root::get_root()) {
+        Some(FileOrDir::File(f)) => f,
+        Some(FileOrDir::Dir(_)) => return Err("configuration file path is a directory"),
+        None => return Ok(None),
+    };
+    let mut file = file.lock();
+    let mut buf = alloc::vec![0u8; file.len()];
+    if !buf.is_empty() {
+        let bytes_read = file.read_at(&mut buf, 0).map_err(|_| "failed to read configuration file")?;
+        buf.truncate(bytes_read);
+    }
+    String::from_utf8(buf).map(Some).map_err(|_| "configuration file is not valid UTF-8")
+}
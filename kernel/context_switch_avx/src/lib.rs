@@ -73,6 +73,13 @@ impl ContextAVX {
     pub fn set_first_register(&mut self, value: usize) {
         self.regular.set_first_register(value);
     }
+
+    /// Returns the saved value of the frame pointer register.
+    ///
+    /// On x86_64, this is the `rbp` register.
+    pub fn frame_pointer(&self) -> usize {
+        self.regular.frame_pointer()
+    }
 }
 
 
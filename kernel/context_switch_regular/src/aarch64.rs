@@ -54,6 +54,16 @@ impl ContextRegular {
     pub fn set_first_register(&mut self, value: usize) {
         self.x28 = value;
     }
+
+    /// Returns the saved value of the frame pointer register.
+    ///
+    /// When frame pointers are enabled, this can be used to walk the call stack
+    /// of a task that is not currently running.
+    ///
+    /// On aarch64, this is the `x29` register.
+    pub fn frame_pointer(&self) -> usize {
+        self.x29_frame_register
+    }
 }
 
 /// Reads the value of the first register from the actual CPU register hardware.
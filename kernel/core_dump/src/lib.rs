@@ -0,0 +1,438 @@
+//! Writes ELF core files for application tasks that crashed due to an exception.
+//!
+//! Once enabled via [`set_enabled()`], the exception handlers record a core file
+//! for each application task that they kill, which is then written into the [`CORE_DIRECTORY`].
+//! A core file contains the following:
+//! * An `NT_PRSTATUS` note with the registers saved in the exception stack frame,
+//!   i.e., the instruction pointer, stack pointer, flags, and segment selectors,
+//!   plus the task's TLS base address as its `fs_base`.
+//!   Other general-purpose registers are not available to the exception handlers and are zeroed.
+//! * An `NT_PRPSINFO` note with the task's name.
+//! * A `THESEUS` note that maps the sections of the crates in the task's namespace
+//!   to their virtual addresses and, for dumped sections, to their offsets in the core file.
+//! * Loadable segments with the contents of the task's stack, its TLS area,
+//!   and the data/bss pages of every crate in the task's namespace.
+//!
+//! Executable and read-only sections are not dumped, as they can be loaded from the crate object files.
+//! The section map note is text with one line per section:
+//! `<crate name> <section index> <virtual address> <size> <core file offset or '-'>`,
+//! which a host-side script can use to `add-symbol-file` each crate object file into GDB
+//! at the addresses where Theseus loaded its sections.
+//!
+//! Exception handlers run with interrupts disabled, so they can neither allocate memory nor write files.
+//! Thus, a core file is recorded into a buffer that is allocated when core files are enabled,
+//! and the `core_dump` task then writes it into a file.
+//! Only one core file can be pending at a time; crashes that occur in the meantime aren't recorded.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::ToString,
+    vec,
+};
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    task::Waker,
+};
+use log::{error, info};
+use fs_node::FileOrDir;
+use io::ByteWriter;
+use memfs::MemFile;
+use memory::VirtualAddress;
+use path::Path;
+use spin::Once;
+use sync_irq::IrqSafeMutex;
+use task::TaskRef;
+
+/// The absolute path of the directory into which core files are written.
+pub const CORE_DIRECTORY: &str = "/cores";
+/// The size of the buffer into which a core file is recorded, which limits the size of core files.
+const CORE_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+/// The size of the buffer into which a core file's notes are recorded.
+const NOTES_BUFFER_SIZE: usize = 256 * 1024;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// The buffers into which the next core file is recorded, or `None` if none are available.
+static CORE_BUFFERS: IrqSafeMutex<Option<CoreBuffers>> = IrqSafeMutex::new(None);
+static WRITER_SPAWNED: AtomicBool = AtomicBool::new(false);
+/// The waker of the task that writes recorded core files into the [`CORE_DIRECTORY`].
+static WRITER_WAKER: Once<Waker> = Once::new();
+
+/// Enables or disables writing a core file for each crashed application task.
+///
+/// The first time that core files are enabled, this spawns the task that writes them.
+pub fn set_enabled(enabled: bool) -> Result<(), &'static str> {
+    if enabled {
+        let mut buffers = CORE_BUFFERS.lock();
+        if buffers.is_none() {
+            *buffers = Some(CoreBuffers::new());
+        }
+        drop(buffers);
+        if !WRITER_SPAWNED.swap(true, Ordering::AcqRel) {
+            if let Err(e) = spawn::new_task_builder(core_file_writer, ()).name("core_dump".into()).spawn() {
+                WRITER_SPAWNED.store(false, Ordering::Release);
+                return Err(e);
+            }
+        }
+    }
+    ENABLED.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Returns whether core files are written for crashed application tasks.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// The registers of a crashed task that were saved in its exception stack frame.
+#[derive(Debug, Clone, Copy)]
+pub struct ExceptionRegisters {
+    pub instruction_pointer: u64,
+    pub stack_pointer: u64,
+    pub flags: u64,
+    pub code_segment: u64,
+    pub stack_segment: u64,
+}
+
+// ELF constants, see the ELF specification and the Linux `elf.h` header.
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+/// The type of the note that maps crate sections to addresses and core file offsets.
+const NT_THESEUS_SECTIONS: u32 = 1;
+/// The size of the x86_64 Linux `elf_prstatus` struct.
+const PRSTATUS_SIZE: usize = 336;
+/// The offset of the registers (`pr_reg`) within the `elf_prstatus` struct.
+const PRSTATUS_REGS_OFFSET: usize = 112;
+/// The size of the x86_64 Linux `elf_prpsinfo` struct.
+const PRPSINFO_SIZE: usize = 136;
+
+/// The preallocated buffers into which a core file is recorded.
+struct CoreBuffers {
+    core: FixedBuffer,
+    notes: FixedBuffer,
+    /// The crashed task whose core file has been recorded but not yet written, if any.
+    pending: Option<TaskRef>,
+}
+
+impl CoreBuffers {
+    fn new() -> CoreBuffers {
+        CoreBuffers {
+            core: FixedBuffer::new(CORE_BUFFER_SIZE),
+            notes: FixedBuffer::new(NOTES_BUFFER_SIZE),
+            pending: None,
+        }
+    }
+}
+
+/// A buffer of a fixed size that fails to grow rather than allocating.
+struct FixedBuffer {
+    buf: Box<[u8]>,
+    len: usize,
+}
+
+impl FixedBuffer {
+    fn new(size: usize) -> FixedBuffer {
+        FixedBuffer { buf: vec![0u8; size].into_boxed_slice(), len: 0 }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Writes the given bytes at the given offset, which must be within the buffer's current length.
+    fn write_at(&mut self, offset: usize, bytes: &[u8]) {
+        self.buf[offset .. offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<(), &'static str> {
+        let end = self.reserve(bytes.len())?;
+        self.buf[end - bytes.len() .. end].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Appends `count` zero bytes, returning the new length.
+    fn push_zeros(&mut self, count: usize) -> Result<usize, &'static str> {
+        let end = self.reserve(count)?;
+        self.buf[end - count .. end].fill(0);
+        Ok(end)
+    }
+
+    fn reserve(&mut self, count: usize) -> Result<usize, &'static str> {
+        let end = self.len.checked_add(count)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or("core file is too large for the preallocated core buffer")?;
+        self.len = end;
+        Ok(end)
+    }
+}
+
+impl Write for FixedBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+/// Records a core file for the given `task`, which crashed due to the given exception.
+///
+/// This doesn't allocate memory, so it can be used by exception handlers.
+/// The core file is written afterwards into the [`CORE_DIRECTORY`] by the `core_dump` task,
+/// named after the task's name and ID, e.g., `/cores/hello-42.core`.
+pub fn record_core_file(
+    task: &TaskRef,
+    exception_number: u8,
+    registers: &ExceptionRegisters,
+) -> Result<(), &'static str> {
+    let mut guard = CORE_BUFFERS.lock();
+    let buffers = guard.as_mut().ok_or("no core buffer is available")?;
+    if buffers.pending.is_some() {
+        return Err("another core file is still being written");
+    }
+    let CoreBuffers { core, notes, .. } = &mut *buffers;
+    core.len = 0;
+    notes.len = 0;
+
+    let (stack_bottom, stack_top) = task.with_kstack(|stack| (stack.bottom(), stack.top_unusable()));
+    let tls = task.tls_area().as_bytes();
+    let namespace = task.get_namespace();
+    let mut crates_with_data = 0;
+    namespace.for_each_crate(false, |_name, crate_ref| {
+        crates_with_data += crate_ref.lock_as_ref().data_pages.is_some() as usize;
+        true
+    });
+    let segment_count = 1 + !tls.is_empty() as usize + crates_with_data;
+
+    // The core file consists of the ELF header, the program headers,
+    // the contents of each segment, and finally the notes.
+    core.push_zeros(ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE * (1 + segment_count))?;
+    write_elf_header(core, 1 + segment_count);
+    let mut segment_index = 0;
+    let mut push_segment = |core: &mut FixedBuffer, start: VirtualAddress, size: usize| -> Result<usize, &'static str> {
+        segment_index += 1;
+        if segment_index > segment_count {
+            return Err("the crashed task's namespace changed while recording its core file");
+        }
+        let offset = core.len;
+        // SAFETY: each segment is a range of memory that is currently mapped and owned by the crashed task:
+        // its stack and TLS area, which live as long as the task, or the data pages of a crate in its namespace,
+        // which are kept alive by the crate reference held by that namespace.
+        let contents = unsafe { core::slice::from_raw_parts(start.value() as *const u8, size) };
+        core.push(contents)?;
+        write_program_header(core, segment_index, PT_LOAD, PF_R | PF_W, offset, start.value(), size, 1);
+        Ok(offset)
+    };
+    push_segment(core, stack_bottom, stack_top.value() - stack_bottom.value())?;
+    if !tls.is_empty() {
+        push_segment(core, VirtualAddress::new_canonical(tls.as_ptr() as usize), tls.len())?;
+    }
+
+    push_note(notes, "CORE", NT_PRSTATUS, &prstatus(task, exception_number, registers))?;
+    push_note(notes, "CORE", NT_PRPSINFO, &prpsinfo(task))?;
+    // The size of the section map isn't known until every crate has been dumped.
+    let section_map_size_offset = notes.len + 4;
+    push_note(notes, "THESEUS", NT_THESEUS_SECTIONS, &[])?;
+    let section_map_start = notes.len;
+
+    let mut result = Ok(());
+    namespace.for_each_crate(false, |_name, crate_ref| {
+        let krate = crate_ref.lock_as_ref();
+        let data = match krate.data_pages {
+            Some((_, ref range)) => {
+                let size = range.end.value() - range.start.value();
+                push_segment(core, range.start, size).map(|offset| Some((range.start, size, offset)))
+            }
+            None => Ok(None),
+        };
+        result = data.and_then(|data| {
+            for (shndx, sec) in krate.sections.iter() {
+                // The "addresses" of TLS and CLS sections are offsets into their local storage areas.
+                if sec.typ.is_tls() || sec.typ == crate_metadata::SectionType::Cls {
+                    continue;
+                }
+                let file_offset = data.filter(|(start, size, _)| sec.virt_addr >= *start && sec.virt_addr.value() < start.value() + size)
+                    .map(|(start, _, offset)| offset + (sec.virt_addr.value() - start.value()));
+                let line = match file_offset {
+                    Some(file_offset) => writeln!(notes, "{} {} {:#x} {:#x} {:#x}", krate.crate_name, shndx, sec.virt_addr.value(), sec.size, file_offset),
+                    None => writeln!(notes, "{} {} {:#x} {:#x} -", krate.crate_name, shndx, sec.virt_addr.value(), sec.size),
+                };
+                line.map_err(|_| "core file is too large for the preallocated core buffer")?;
+            }
+            Ok(())
+        });
+        result.is_ok()
+    });
+    result?;
+    if segment_index != segment_count {
+        return Err("the crashed task's namespace changed while recording its core file");
+    }
+    let section_map_size = notes.len - section_map_start;
+    notes.write_at(section_map_size_offset, &(section_map_size as u32).to_le_bytes());
+    notes.push_zeros(align4(section_map_size) - section_map_size)?;
+
+    let notes_offset = core.len;
+    core.push(notes.as_slice())?;
+    write_program_header(core, 0, PT_NOTE, 0, notes_offset, 0, notes.len, 0);
+
+    buffers.pending = Some(task.clone());
+    if let Some(waker) = WRITER_WAKER.get() {
+        waker.wake_by_ref();
+    }
+    Ok(())
+}
+
+/// The entry point for the task that writes recorded core files into the [`CORE_DIRECTORY`].
+fn core_file_writer(_: ()) -> Result<(), &'static str> {
+    let (waker, blocker) = waker::new_waker();
+    WRITER_WAKER.call_once(|| waker);
+    loop {
+        // The core buffer is moved out of the lock while it's written into a file,
+        // and the crashed task remains pending until then, such that no other crash is recorded into it.
+        let pending = CORE_BUFFERS.lock().as_mut().and_then(|buffers| {
+            let task = buffers.pending.clone()?;
+            Some((task, core::mem::replace(&mut buffers.core, FixedBuffer::new(0))))
+        });
+        if let Some((task, core)) = pending {
+            match write_core_file(&task, core.as_slice()) {
+                Ok(path) => info!("Wrote core file {}", path),
+                Err(e) => error!("Failed to write core file for task {:?}: {}", task.name, e),
+            }
+            if let Some(buffers) = CORE_BUFFERS.lock().as_mut() {
+                buffers.core = core;
+                buffers.pending = None;
+            }
+        }
+        blocker.block();
+    }
+}
+
+/// Writes the given recorded core file of the given task into the [`CORE_DIRECTORY`], returning its path.
+fn write_core_file(task: &TaskRef, core: &[u8]) -> Result<alloc::string::String, &'static str> {
+    let core_dir = match Path::new(CORE_DIRECTORY).get(root::get_root()) {
+        Some(FileOrDir::Dir(dir)) => dir,
+        Some(FileOrDir::File(_)) => return Err("the core file directory path is a file"),
+        None => vfs_node::VFSDirectory::create(CORE_DIRECTORY.trim_start_matches('/').to_string(), root::get_root())?,
+    };
+    let file_name = format!("{}-{}.core", task.name, task.id);
+    let file = MemFile::create(file_name.clone(), &core_dir)?;
+    file.lock().write_at(core, 0).map_err(|_| "failed to write core file")?;
+    Ok(format!("{}/{}", CORE_DIRECTORY, file_name))
+}
+
+/// Returns the POSIX signal number that corresponds to the given x86_64 exception.
+fn signal_number(exception_number: u8) -> u32 {
+    match exception_number {
+        0x0 | 0x10 | 0x13 => 8,  // SIGFPE
+        0x1 | 0x3 => 5,          // SIGTRAP
+        0x6 => 4,                // SIGILL
+        0x11 => 7,               // SIGBUS
+        _ => 11,                 // SIGSEGV
+    }
+}
+
+/// Returns the contents of an x86_64 `elf_prstatus` struct for the given crashed task.
+fn prstatus(task: &TaskRef, exception_number: u8, registers: &ExceptionRegisters) -> [u8; PRSTATUS_SIZE] {
+    let mut prstatus = [0u8; PRSTATUS_SIZE];
+    let signal = signal_number(exception_number);
+    // `pr_info.si_signo`, `pr_cursig`, and `pr_pid`
+    prstatus[0..4].copy_from_slice(&signal.to_le_bytes());
+    prstatus[12..14].copy_from_slice(&(signal as u16).to_le_bytes());
+    prstatus[32..36].copy_from_slice(&(task.id as u32).to_le_bytes());
+
+    // The indices of registers within `pr_reg`, which is a Linux `user_regs_struct`.
+    let regs = [
+        (16, registers.instruction_pointer),
+        (17, registers.code_segment),
+        (18, registers.flags),
+        (19, registers.stack_pointer),
+        (20, registers.stack_segment),
+        (21, task.tls_area().base_pointer()), // fs_base
+    ];
+    for (index, value) in regs {
+        let reg_offset = PRSTATUS_REGS_OFFSET + index * 8;
+        prstatus[reg_offset .. reg_offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+    prstatus
+}
+
+/// Returns the contents of an x86_64 `elf_prpsinfo` struct for the given crashed task.
+fn prpsinfo(task: &TaskRef) -> [u8; PRPSINFO_SIZE] {
+    let mut prpsinfo = [0u8; PRPSINFO_SIZE];
+    // `pr_sname` is 'R' for running, and `pr_pid` follows `pr_flag`, `pr_uid`, and `pr_gid`.
+    prpsinfo[1] = b'R';
+    prpsinfo[24..28].copy_from_slice(&(task.id as u32).to_le_bytes());
+    // `pr_fname` holds up to 15 characters, and `pr_psargs` up to 79.
+    let name = task.name.as_bytes();
+    let fname_len = name.len().min(15);
+    prpsinfo[40 .. 40 + fname_len].copy_from_slice(&name[..fname_len]);
+    let psargs_len = name.len().min(79);
+    prpsinfo[56 .. 56 + psargs_len].copy_from_slice(&name[..psargs_len]);
+    prpsinfo
+}
+
+/// Appends an ELF note with the given owner `name`, `note_type`, and `desc`ription,
+/// padding the name and description to 4-byte alignment.
+fn push_note(notes: &mut FixedBuffer, name: &str, note_type: u32, desc: &[u8]) -> Result<(), &'static str> {
+    let name_size = name.len() + 1; // including the null terminator
+    notes.push(&(name_size as u32).to_le_bytes())?;
+    notes.push(&(desc.len() as u32).to_le_bytes())?;
+    notes.push(&note_type.to_le_bytes())?;
+    notes.push(name.as_bytes())?;
+    notes.push_zeros(align4(name_size) - name.len())?;
+    notes.push(desc)?;
+    notes.push_zeros(align4(desc.len()) - desc.len())?;
+    Ok(())
+}
+
+fn align4(size: usize) -> usize {
+    (size + 3) & !3
+}
+
+/// Writes the ELF header into the space reserved for it at the start of the core file.
+fn write_elf_header(core: &mut FixedBuffer, program_header_count: usize) {
+    let mut header = [0u8; ELF_HEADER_SIZE];
+    // `e_ident`: magic, 64-bit, little endian, version 1, System V ABI.
+    header[0..8].copy_from_slice(&[0x7F, b'E', b'L', b'F', 2, 1, 1, 0]);
+    header[16..18].copy_from_slice(&ET_CORE.to_le_bytes());
+    header[18..20].copy_from_slice(&EM_X86_64.to_le_bytes());
+    header[20..24].copy_from_slice(&1u32.to_le_bytes());                             // e_version
+    header[32..40].copy_from_slice(&(ELF_HEADER_SIZE as u64).to_le_bytes());         // e_phoff
+    header[52..54].copy_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());         // e_ehsize
+    header[54..56].copy_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());     // e_phentsize
+    header[56..58].copy_from_slice(&(program_header_count as u16).to_le_bytes());    // e_phnum
+    core.write_at(0, &header);
+}
+
+/// Writes the program header with the given `index` into the space reserved for it after the ELF header.
+#[allow(clippy::too_many_arguments)]
+fn write_program_header(
+    core: &mut FixedBuffer,
+    index: usize,
+    typ: u32,
+    flags: u32,
+    offset: usize,
+    vaddr: usize,
+    size: usize,
+    align: u64,
+) {
+    let mut header = [0u8; PROGRAM_HEADER_SIZE];
+    header[0..4].copy_from_slice(&typ.to_le_bytes());
+    header[4..8].copy_from_slice(&flags.to_le_bytes());
+    header[8..16].copy_from_slice(&(offset as u64).to_le_bytes());
+    header[16..24].copy_from_slice(&(vaddr as u64).to_le_bytes());  // p_vaddr
+    header[32..40].copy_from_slice(&(size as u64).to_le_bytes());   // p_filesz
+    header[40..48].copy_from_slice(&(size as u64).to_le_bytes());   // p_memsz
+    header[48..56].copy_from_slice(&align.to_le_bytes());
+    core.write_at(ELF_HEADER_SIZE + index * PROGRAM_HEADER_SIZE, &header);
+}
@@ -10,7 +10,6 @@
 
 #![no_std]
 
-#[cfg(target_arch = "aarch64")]
 extern crate alloc;
 
 #[cfg_attr(target_arch = "x86_64", path = "x86_64.rs")]
@@ -19,6 +18,7 @@ mod arch;
 
 pub use arch::*;
 
+use alloc::vec::Vec;
 use derive_more::*;
 
 /// A unique identifier for a CPU core.
@@ -46,3 +46,90 @@ impl CpuId {
             .unwrap_or_else(|_| panic!("couldn't convert CpuId {self} into a u8"))
     }
 }
+
+/// A set of CPUs, e.g., the CPUs that a task is allowed to run on.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CpuSet {
+    /// The CPUs in this set, sorted and without duplicates.
+    cpus: Vec<CpuId>,
+}
+
+impl CpuSet {
+    /// Returns an empty set.
+    pub const fn new() -> CpuSet {
+        CpuSet { cpus: Vec::new() }
+    }
+
+    /// Returns a set that contains only the given CPU.
+    pub fn single(cpu: CpuId) -> CpuSet {
+        CpuSet { cpus: alloc::vec![cpu] }
+    }
+
+    /// Adds the given CPU to this set.
+    ///
+    /// Returns `true` if it wasn't already in this set.
+    pub fn insert(&mut self, cpu: CpuId) -> bool {
+        match self.cpus.binary_search(&cpu) {
+            Ok(_) => false,
+            Err(i) => {
+                self.cpus.insert(i, cpu);
+                true
+            }
+        }
+    }
+
+    /// Removes the given CPU from this set.
+    ///
+    /// Returns `true` if it was in this set.
+    pub fn remove(&mut self, cpu: CpuId) -> bool {
+        match self.cpus.binary_search(&cpu) {
+            Ok(i) => {
+                self.cpus.remove(i);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Returns `true` if the given CPU is in this set.
+    pub fn contains(&self, cpu: CpuId) -> bool {
+        self.cpus.binary_search(&cpu).is_ok()
+    }
+
+    /// Returns the number of CPUs in this set.
+    pub fn len(&self) -> usize {
+        self.cpus.len()
+    }
+
+    /// Returns `true` if this set contains no CPUs.
+    pub fn is_empty(&self) -> bool {
+        self.cpus.is_empty()
+    }
+
+    /// Returns an iterator over the CPUs in this set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = CpuId> + '_ {
+        self.cpus.iter().copied()
+    }
+}
+
+impl FromIterator<CpuId> for CpuSet {
+    fn from_iter<I: IntoIterator<Item = CpuId>>(iter: I) -> CpuSet {
+        let mut cpus: Vec<CpuId> = iter.into_iter().collect();
+        cpus.sort_unstable();
+        cpus.dedup();
+        CpuSet { cpus }
+    }
+}
+
+impl core::fmt::Display for CpuSet {
+    /// Formats this set as a comma-separated list of CPUs, e.g., `0,2,3`.
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        for (i, cpu) in self.cpus.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{cpu}")?;
+        }
+        Ok(())
+    }
+}
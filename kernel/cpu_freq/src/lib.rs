@@ -0,0 +1,280 @@
+//! Control over the frequency of Intel x86_64 CPUs, for reproducible benchmarking.
+//!
+//! The frequency of a CPU is its bus clock multiplied by a *ratio*,
+//! which is chosen by the hardware within the limits set by the following MSRs:
+//! * `MSR_PLATFORM_INFO`: the base (max non-turbo) and minimum ratios; see [`platform_info()`].
+//! * `IA32_PERF_CTL`: the requested ratio; see [`set_target_ratio()`].
+//! * `IA32_MISC_ENABLE`: whether turbo boost is allowed at all; see [`set_turbo_enabled()`].
+//! * `MSR_TURBO_RATIO_LIMIT`: the max turbo ratio for each number of active cores;
+//!   see [`set_turbo_ratio_limits()`].
+//! * `IA32_CLOCK_MODULATION`: an on-demand duty cycle that stops the clock for a fraction
+//!   of the time, independent of the ratio; see [`set_clock_modulation()`].
+//!
+//! All of the above are per-CPU (or per-package) settings that affect only the current CPU,
+//! with the exception of [`pin_frequency()`] and [`unpin_frequency()`],
+//! which apply their settings to every CPU.
+
+#![no_std]
+
+use log::{info, warn};
+use msr::{
+    IA32_CLOCK_MODULATION, IA32_MISC_ENABLE, IA32_PERF_CTL, IA32_PERF_STATUS,
+    MSR_PLATFORM_INFO, MSR_TURBO_RATIO_LIMIT,
+};
+use raw_cpuid::CpuId as X86CpuIdInstr;
+use spin::Once;
+use x86_64::registers::model_specific::Msr;
+
+/// The bus clock frequency, in MHz, that ratios are multiplied by
+/// on all Intel CPUs since the Nehalem microarchitecture.
+pub const BUS_CLOCK_MHZ: u64 = 100;
+
+/// `IA32_MISC_ENABLE` bit 16: Enhanced Intel SpeedStep Technology is enabled,
+/// which is required for writes to `IA32_PERF_CTL` to take effect.
+const MISC_ENABLE_EIST: u64 = 1 << 16;
+/// `IA32_MISC_ENABLE` bit 38: turbo mode is disabled.
+const MISC_ENABLE_TURBO_DISABLE: u64 = 1 << 38;
+
+/// `MSR_PLATFORM_INFO` bit 28: `MSR_TURBO_RATIO_LIMIT` is writable.
+const PLATFORM_INFO_PROGRAMMABLE_TURBO: u64 = 1 << 28;
+
+/// `IA32_CLOCK_MODULATION` bit 4: on-demand clock modulation is enabled.
+const CLOCK_MODULATION_ENABLE: u64 = 1 << 4;
+/// `IA32_CLOCK_MODULATION` bits 3:0: the duty cycle, in sixteenths.
+const CLOCK_MODULATION_DUTY_MASK: u64 = 0xF;
+
+/// Returns `true` if this CPU supports the frequency control MSRs used by this crate,
+/// i.e., it is an Intel CPU with Enhanced Intel SpeedStep Technology.
+pub fn is_supported() -> bool {
+    static IS_SUPPORTED: Once<bool> = Once::new();
+    *IS_SUPPORTED.call_once(|| {
+        let cpuid = X86CpuIdInstr::new();
+        let is_intel = cpuid.get_vendor_info().map_or(false, |v| v.as_str() == "GenuineIntel");
+        let has_eist = cpuid.get_feature_info().map_or(false, |f| f.has_eist());
+        is_intel && has_eist
+    })
+}
+
+fn check_supported() -> Result<(), &'static str> {
+    if is_supported() {
+        Ok(())
+    } else {
+        Err("this CPU doesn't support Intel frequency control MSRs")
+    }
+}
+
+fn rdmsr(msr: u32) -> Result<u64, &'static str> {
+    check_supported()?;
+    // SAFETY: all MSRs used in this crate exist on Intel CPUs that support EIST.
+    Ok(unsafe { Msr::new(msr).read() })
+}
+
+fn wrmsr(msr: u32, value: u64) -> Result<(), &'static str> {
+    check_supported()?;
+    // SAFETY: all MSRs used in this crate exist on Intel CPUs that support EIST,
+    //         and changing a CPU's frequency doesn't affect memory safety.
+    unsafe { Msr::new(msr).write(value) };
+    Ok(())
+}
+
+/// The fixed frequency ratios of this platform, read from `MSR_PLATFORM_INFO`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlatformInfo {
+    /// The maximum non-turbo ratio, i.e., the ratio of the CPU's base frequency.
+    pub base_ratio: u8,
+    /// The minimum ratio that the CPU can run at (its max efficiency ratio).
+    pub min_ratio: u8,
+    /// Whether the turbo ratio limits can be changed via [`set_turbo_ratio_limits()`].
+    pub turbo_ratio_programmable: bool,
+}
+
+impl PlatformInfo {
+    /// Returns the base frequency of the CPU in MHz.
+    pub fn base_mhz(&self) -> u64 {
+        self.base_ratio as u64 * BUS_CLOCK_MHZ
+    }
+}
+
+/// Returns the fixed frequency ratios of this platform.
+pub fn platform_info() -> Result<PlatformInfo, &'static str> {
+    let value = rdmsr(MSR_PLATFORM_INFO)?;
+    Ok(PlatformInfo {
+        base_ratio: (value >> 8) as u8,
+        min_ratio: (value >> 40) as u8,
+        turbo_ratio_programmable: value & PLATFORM_INFO_PROGRAMMABLE_TURBO != 0,
+    })
+}
+
+/// Returns the ratio that the current CPU is currently running at.
+pub fn current_ratio() -> Result<u8, &'static str> {
+    rdmsr(IA32_PERF_STATUS).map(|value| (value >> 8) as u8)
+}
+
+/// Returns the ratio that has been requested for the current CPU.
+pub fn target_ratio() -> Result<u8, &'static str> {
+    rdmsr(IA32_PERF_CTL).map(|value| (value >> 8) as u8)
+}
+
+/// Requests that the current CPU run at the given `ratio`.
+///
+/// Ratios above the base ratio are only granted if turbo boost is enabled,
+/// and may be reduced by the hardware to stay within its power and thermal limits.
+pub fn set_target_ratio(ratio: u8) -> Result<(), &'static str> {
+    let info = platform_info()?;
+    let max_ratio = if turbo_enabled()? {
+        turbo_ratio_limits()?[0]
+    } else {
+        info.base_ratio
+    };
+    if ratio < info.min_ratio || ratio > max_ratio {
+        return Err("the requested ratio is outside of the range supported by this CPU");
+    }
+    if rdmsr(IA32_MISC_ENABLE)? & MISC_ENABLE_EIST == 0 {
+        return Err("Enhanced Intel SpeedStep Technology was disabled by the firmware");
+    }
+    let value = rdmsr(IA32_PERF_CTL)?;
+    wrmsr(IA32_PERF_CTL, (value & !0xFF00) | ((ratio as u64) << 8))
+}
+
+/// Returns `true` if turbo boost is available on this CPU, regardless of whether it's enabled.
+pub fn turbo_available() -> Result<bool, &'static str> {
+    // Disabling turbo via `IA32_MISC_ENABLE` also clears its CPUID feature bit.
+    let has_turbo = X86CpuIdInstr::new()
+        .get_thermal_power_info()
+        .map_or(false, |info| info.has_turbo_boost());
+    Ok(has_turbo || rdmsr(IA32_MISC_ENABLE)? & MISC_ENABLE_TURBO_DISABLE != 0)
+}
+
+/// Returns `true` if turbo boost is enabled on the current CPU.
+pub fn turbo_enabled() -> Result<bool, &'static str> {
+    Ok(turbo_available()? && rdmsr(IA32_MISC_ENABLE)? & MISC_ENABLE_TURBO_DISABLE == 0)
+}
+
+/// Enables or disables turbo boost on the current CPU.
+pub fn set_turbo_enabled(enable: bool) -> Result<(), &'static str> {
+    if !turbo_available()? {
+        return if enable { Err("this CPU doesn't support turbo boost") } else { Ok(()) };
+    }
+    let value = rdmsr(IA32_MISC_ENABLE)?;
+    let value = if enable {
+        value & !MISC_ENABLE_TURBO_DISABLE
+    } else {
+        value | MISC_ENABLE_TURBO_DISABLE
+    };
+    wrmsr(IA32_MISC_ENABLE, value)
+}
+
+/// Returns the maximum turbo ratios, where the ratio at index `i`
+/// applies when `i + 1` cores are active.
+pub fn turbo_ratio_limits() -> Result<[u8; 8], &'static str> {
+    rdmsr(MSR_TURBO_RATIO_LIMIT).map(u64::to_le_bytes)
+}
+
+/// Sets the maximum turbo ratios, where the ratio at index `i`
+/// applies when `i + 1` cores are active.
+///
+/// This is only possible if [`PlatformInfo::turbo_ratio_programmable`] is `true`.
+pub fn set_turbo_ratio_limits(limits: [u8; 8]) -> Result<(), &'static str> {
+    if !platform_info()?.turbo_ratio_programmable {
+        return Err("this CPU's turbo ratio limits are read-only");
+    }
+    wrmsr(MSR_TURBO_RATIO_LIMIT, u64::from_le_bytes(limits))
+}
+
+/// The state of on-demand clock modulation, i.e., software-controlled clock throttling.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockModulation {
+    /// The clock runs all of the time.
+    Disabled,
+    /// The clock runs for the given number of sixteenths of the time, from `1` to `15`.
+    ///
+    /// Unless the CPU supports extended clock modulation, this must be an even number.
+    DutyCycle(u8),
+}
+
+/// Returns the state of on-demand clock modulation on the current CPU.
+pub fn clock_modulation() -> Result<ClockModulation, &'static str> {
+    let value = rdmsr(IA32_CLOCK_MODULATION)?;
+    Ok(if value & CLOCK_MODULATION_ENABLE == 0 {
+        ClockModulation::Disabled
+    } else {
+        ClockModulation::DutyCycle((value & CLOCK_MODULATION_DUTY_MASK) as u8)
+    })
+}
+
+/// Sets the state of on-demand clock modulation on the current CPU.
+pub fn set_clock_modulation(modulation: ClockModulation) -> Result<(), &'static str> {
+    let value = rdmsr(IA32_CLOCK_MODULATION)? & !(CLOCK_MODULATION_ENABLE | CLOCK_MODULATION_DUTY_MASK);
+    let value = match modulation {
+        ClockModulation::Disabled => value,
+        ClockModulation::DutyCycle(duty) => {
+            let has_extended = X86CpuIdInstr::new()
+                .get_thermal_power_info()
+                .map_or(false, |info| info.has_ecmd());
+            if duty == 0 || duty > 15 {
+                return Err("the clock modulation duty cycle must be between 1 and 15 sixteenths");
+            }
+            if !has_extended && duty % 2 != 0 {
+                return Err("this CPU only supports clock modulation duty cycles in eighths");
+            }
+            value | CLOCK_MODULATION_ENABLE | duty as u64
+        }
+    };
+    wrmsr(IA32_CLOCK_MODULATION, value)
+}
+
+/// Pins every CPU to run at the given `ratio`, such that benchmark results are reproducible.
+///
+/// This disables turbo boost and clock modulation, and requests the given ratio on every CPU.
+/// The `ratio` must be no higher than the base ratio; see [`platform_info()`].
+pub fn pin_frequency(ratio: u8) -> Result<(), &'static str> {
+    let info = platform_info()?;
+    if ratio > info.base_ratio {
+        return Err("cannot pin the frequency above the base frequency, as turbo boost is disabled");
+    }
+    on_each_cpu(pin_this_cpu, ratio)?;
+    info!("Pinned all CPUs to ratio {} ({} MHz)", ratio, ratio as u64 * BUS_CLOCK_MHZ);
+    Ok(())
+}
+
+fn pin_this_cpu(ratio: u8) -> Result<(), &'static str> {
+    set_turbo_enabled(false)?;
+    set_clock_modulation(ClockModulation::Disabled)?;
+    set_target_ratio(ratio)
+}
+
+/// Undoes [`pin_frequency()`] by re-enabling turbo boost (if available)
+/// and requesting the highest possible ratio on every CPU.
+pub fn unpin_frequency() -> Result<(), &'static str> {
+    check_supported()?;
+    on_each_cpu(unpin_this_cpu, ())
+}
+
+fn unpin_this_cpu(_: ()) -> Result<(), &'static str> {
+    let max_ratio = if turbo_available()? {
+        set_turbo_enabled(true)?;
+        turbo_ratio_limits()?[0]
+    } else {
+        platform_info()?.base_ratio
+    };
+    set_target_ratio(max_ratio)
+}
+
+/// Invokes `func` with `arg` on every CPU, as the frequency control MSRs
+/// only affect the CPU that accesses them.
+///
+/// Returns the first error returned by `func` on any CPU, if any.
+fn on_each_cpu<A>(func: fn(A) -> Result<(), &'static str>, arg: A) -> Result<(), &'static str>
+where
+    A: Clone + Send + 'static,
+{
+    let mut result = Ok(());
+    for (cpu, cpu_result) in spawn::on_each_cpu("cpu_freq", func, arg)? {
+        let cpu_result = cpu_result.unwrap_or(Err("on_each_cpu(): task was killed"));
+        if let Err(e) = cpu_result {
+            warn!("cpu_freq: failed on CPU {}: {}", cpu, e);
+            result = result.and(Err(e));
+        }
+    }
+    result
+}
@@ -0,0 +1,129 @@
+//! Isolates CPUs such that latency-critical tasks pinned to them run undisturbed.
+//!
+//! An isolated CPU:
+//! * only runs tasks that are explicitly pinned to it, e.g., network polling or realtime tasks;
+//!   all other tasks, including background kernel tasks, are scheduled on other CPUs.
+//! * receives no timer ticks while it has only a single task to run (x86_64 only).
+//! * receives no device interrupts routed through the I/O APIC(s), which are steered to the
+//!   bootstrap CPU instead (x86_64 only). Interrupts that devices deliver via MSI
+//!   to a specific CPU are not affected, so drivers must avoid choosing isolated CPUs.
+//!   The steered interrupts are routed back to the CPU once its isolation ends.
+//!
+//! See [`scheduler::set_isolated()`] for the scheduling details.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use cpu::CpuId;
+use log::info;
+#[cfg(target_arch = "x86_64")]
+use spin::Mutex;
+
+/// An I/O APIC interrupt that was steered away from an isolated CPU.
+#[cfg(target_arch = "x86_64")]
+struct SteeredIrq {
+    /// The isolated CPU to which the interrupt was originally routed.
+    cpu_id: CpuId,
+    ioapic_id: u8,
+    irq: u8,
+}
+
+/// The interrupts that were steered away from isolated CPUs,
+/// which are routed back to them by [`unisolate()`].
+#[cfg(target_arch = "x86_64")]
+static STEERED_IRQS: Mutex<Vec<SteeredIrq>> = Mutex::new(Vec::new());
+
+/// Isolates the given CPU.
+///
+/// Returns an error if the given CPU is the bootstrap CPU, which handles system-wide interrupts,
+/// or if it can't be isolated by the scheduler.
+pub fn isolate(cpu_id: CpuId) -> Result<(), &'static str> {
+    if Some(cpu_id) == cpu::bootstrap_cpu() {
+        return Err("the bootstrap CPU cannot be isolated");
+    }
+    scheduler::set_isolated(cpu_id, true)?;
+    let steered = steer_interrupts_away(cpu_id)?;
+    info!("Isolated CPU {}, steered {} interrupt(s) to the bootstrap CPU", cpu_id, steered);
+    Ok(())
+}
+
+/// Ends the isolation of the given CPU, allowing it to run general-purpose tasks again.
+///
+/// Interrupts that were steered away from this CPU upon isolating it are routed back to it,
+/// unless they have since been routed elsewhere than the bootstrap CPU.
+pub fn unisolate(cpu_id: CpuId) -> Result<(), &'static str> {
+    scheduler::set_isolated(cpu_id, false)?;
+    let restored = restore_interrupts(cpu_id)?;
+    info!("Unisolated CPU {}, steered {} interrupt(s) back to it", cpu_id, restored);
+    Ok(())
+}
+
+/// Returns the list of isolated CPUs.
+pub fn isolated_cpus() -> Vec<CpuId> {
+    scheduler::isolated_cpus()
+}
+
+/// Redirects all I/O APIC interrupts that are currently routed to the given CPU to the bootstrap CPU.
+///
+/// Returns the number of interrupts that were redirected.
+#[cfg(target_arch = "x86_64")]
+fn steer_interrupts_away(cpu_id: CpuId) -> Result<usize, &'static str> {
+    let bsp = cpu::bootstrap_cpu().ok_or("couldn't determine the bootstrap CPU")?;
+    let isolated_apic_id = apic::ApicId::from(cpu_id).value();
+    let mut steered_irqs = STEERED_IRQS.lock();
+    let mut steered = 0;
+    for (ioapic_id, ioapic) in ioapic::get_ioapics() {
+        let mut ioapic = ioapic.lock();
+        for irq in 0 .. ioapic.num_irqs() {
+            if ioapic.irq_destination(irq) == isolated_apic_id {
+                ioapic.set_irq_destination(irq, bsp.into())?;
+                steered_irqs.push(SteeredIrq { cpu_id, ioapic_id: *ioapic_id, irq });
+                steered += 1;
+            }
+        }
+    }
+    Ok(steered)
+}
+
+/// Routes the I/O APIC interrupts that were steered away from the given CPU back to it.
+///
+/// Returns the number of interrupts that were routed back.
+#[cfg(target_arch = "x86_64")]
+fn restore_interrupts(cpu_id: CpuId) -> Result<usize, &'static str> {
+    let bsp_apic_id = apic::ApicId::from(
+        cpu::bootstrap_cpu().ok_or("couldn't determine the bootstrap CPU")?
+    ).value();
+    let mut steered_irqs = STEERED_IRQS.lock();
+    let mut restored = 0;
+    let mut result = Ok(());
+    steered_irqs.retain(|steered| {
+        if steered.cpu_id != cpu_id || result.is_err() {
+            return true;
+        }
+        if let Some(ioapic) = ioapic::get_ioapic(steered.ioapic_id) {
+            let mut ioapic = ioapic.lock();
+            // Leave alone any interrupt that has been routed elsewhere in the meantime.
+            if ioapic.irq_destination(steered.irq) == bsp_apic_id {
+                result = ioapic.set_irq_destination(steered.irq, cpu_id.into());
+                if result.is_err() {
+                    return true;
+                }
+                restored += 1;
+            }
+        }
+        false
+    });
+    result.map(|_| restored)
+}
+
+#[cfg(target_arch = "aarch64")]
+fn steer_interrupts_away(_cpu_id: CpuId) -> Result<usize, &'static str> {
+    Ok(0)
+}
+
+#[cfg(target_arch = "aarch64")]
+fn restore_interrupts(_cpu_id: CpuId) -> Result<usize, &'static str> {
+    Ok(0)
+}
@@ -42,14 +42,15 @@
 
 #![deny(unsafe_op_in_unsafe_fn)]
 #![no_std]
+#![feature(allocator_api)]
 
 extern crate alloc;
 
-use core::{fmt, mem::size_of, ops::Range};
+use core::{fmt, mem::size_of, ops::Range, time::Duration};
 use log::{error, debug, trace};
 use spin::{Mutex, RwLock, Once};
 use alloc::{
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     format,
     string::{
         String,
@@ -63,6 +64,7 @@ use cow_arc::{CowArc, CowWeak};
 use fs_node::{FileRef, WeakFileRef};
 use hashbrown::HashMap;
 use goblin::elf::reloc::*;
+use object_cache::ObjectCache;
 
 pub use str_ref::StrRef;
 pub use crate_metadata_serde::{
@@ -84,9 +86,19 @@ pub type StrongCrateRef  = CowArc<LoadedCrate>;
 /// A Weak reference to a [`LoadedCrate`].
 pub type WeakCrateRef = CowWeak<LoadedCrate>;
 /// A Strong reference ([`Arc`]) to a [`LoadedSection`].
-pub type StrongSectionRef  = Arc<LoadedSection>;
+pub type StrongSectionRef  = Arc<LoadedSection, &'static ObjectCache>;
 /// A Weak reference ([`Weak`]) to a [`LoadedSection`].
-pub type WeakSectionRef = Weak<LoadedSection>;
+pub type WeakSectionRef = Weak<LoadedSection, &'static ObjectCache>;
+
+/// Many sections are created and dropped whenever crates are loaded and unloaded,
+/// so they're allocated from a dedicated object cache rather than the general heap.
+static SECTION_CACHE: ObjectCache = ObjectCache::for_arc::<LoadedSection>("LoadedSection");
+
+/// Returns a [`WeakSectionRef`] that doesn't refer to any section,
+/// i.e., one that will always fail to be upgraded.
+pub fn empty_weak_section_ref() -> WeakSectionRef {
+    Weak::new_in(&SECTION_CACHE)
+}
 
 /// `.text` sections are read-only and executable.
 pub const TEXT_SECTION_FLAGS: PteFlags = PteFlags::from_bits_truncate(
@@ -220,6 +232,11 @@ pub struct LoadedCrate {
     /// but we keep each section's shndx (section header index from its crate's ELF file)
     /// as the key because it helps us quickly handle relocations and crate swapping.
     pub sections: HashMap<Shndx, StrongSectionRef>,
+    /// The local `.text` and `.rodata` sections in this crate whose full `LoadedSection` metadata
+    /// hasn't been created, which only occurs if this crate was loaded in lazy section metadata mode.
+    /// These sections are loaded into memory like all others, but they are not in the `sections` map above.
+    /// Their metadata is created on demand, e.g., when a backtrace needs the name of a section.
+    pub lazy_sections: BTreeMap<Shndx, LazySection>,
     /// A tuple of:    
     /// 1. The `MappedPages` that contain sections that are readable and executable, but not writable,
     ///     i.e., the `.text` sections for this crate,
@@ -269,6 +286,54 @@ pub struct LoadedCrate {
     /// When a crate is first loaded, this will be empty by default, 
     /// because this crate will only have populated its `global_sections` set during loading. 
     pub reexported_symbols: BTreeSet<StrRef>,
+    /// Statistics about how long this crate took to load and how much memory it occupies.
+    pub load_stats: CrateLoadStats,
+}
+
+/// Statistics gathered while loading a single crate,
+/// used to measure the performance of crate loading and linking.
+///
+/// These are all zero for crates that were not loaded from an object file at runtime,
+/// e.g., the `nano_core`. The times are also zero for crates loaded before a clock source was registered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CrateLoadStats {
+    /// The time spent parsing the object file and copying its sections into memory.
+    pub parse_time: Duration,
+    /// The time spent resolving symbols and writing relocations.
+    pub relocation_time: Duration,
+    /// The number of bytes mapped for this crate's executable sections.
+    pub text_bytes: usize,
+    /// The number of bytes mapped for this crate's read-only sections.
+    pub rodata_bytes: usize,
+    /// The number of bytes mapped for this crate's read-write sections.
+    pub data_bytes: usize,
+    /// The number of relocation entries that were applied to this crate.
+    pub num_relocations: usize,
+    /// The number of global symbols this crate added to its namespace's symbol map.
+    pub symbols_added: usize,
+}
+
+impl CrateLoadStats {
+    /// Adds each of the `other` statistics to this one.
+    pub fn accumulate(&mut self, other: &CrateLoadStats) {
+        self.parse_time      += other.parse_time;
+        self.relocation_time += other.relocation_time;
+        self.text_bytes      += other.text_bytes;
+        self.rodata_bytes    += other.rodata_bytes;
+        self.data_bytes      += other.data_bytes;
+        self.num_relocations += other.num_relocations;
+        self.symbols_added   += other.symbols_added;
+    }
+
+    /// Returns the total time spent loading the crate.
+    pub fn total_time(&self) -> Duration {
+        self.parse_time + self.relocation_time
+    }
+
+    /// Returns the total number of bytes mapped for the crate.
+    pub fn total_bytes(&self) -> usize {
+        self.text_bytes + self.rodata_bytes + self.data_bytes
+    }
 }
 
 impl fmt::Debug for LoadedCrate {
@@ -333,6 +398,26 @@ impl LoadedCrate {
             .unwrap_or(&self.crate_name)
     }
 
+    /// Returns the total size in bytes of this crate's sections that occupy memory.
+    ///
+    /// This excludes `.tbss` sections, which are placeholders that don't occupy any memory,
+    /// as well as any padding in the pages that hold this crate's sections.
+    pub fn resident_size_in_bytes(&self) -> usize {
+        self.sections.values()
+            .filter(|sec| sec.typ != SectionType::TlsBss)
+            .map(|sec| sec.size)
+            .chain(self.lazy_sections.values().map(|lazy| lazy.size))
+            .sum()
+    }
+
+    /// Returns the lazy section in this crate (see [`LoadedCrate::lazy_sections`])
+    /// that contains the given virtual address, along with its shndx.
+    pub fn lazy_section_containing(&self, virt_addr: VirtualAddress) -> Option<(Shndx, &LazySection)> {
+        self.lazy_sections.iter()
+            .find(|(_, lazy)| lazy.contains(virt_addr))
+            .map(|(shndx, lazy)| (*shndx, lazy))
+    }
+
     /// Returns this crate name as a symbol prefix, including a trailing "`::`".
     /// If there is no hash, then it returns the entire name with a trailing "`::`".
     /// # Example
@@ -405,7 +490,8 @@ impl LoadedCrate {
             let old_start_address = old_mp_range.1.start.value();
             let size = old_mp_range.1.end.value() - old_start_address;
             let offset = old_start_address - old_mp_locked.start_address().value();
-            let new_mp = old_mp_range.0.lock().deep_copy(page_table, Some(flags.writable(true)))?;
+            let mut new_mp = old_mp_range.0.lock().deep_copy(page_table, Some(flags.writable(true)))?;
+            new_mp.disown();
             let new_start_address = new_mp.start_address() + offset;
             Ok((Arc::new(Mutex::new(new_mp)), new_start_address .. (new_start_address + size)))
         };
@@ -437,6 +523,9 @@ impl LoadedCrate {
             object_file:             self.object_file.clone(),
             debug_symbols_file:      self.debug_symbols_file.clone(),
             sections:                HashMap::new(),
+            // Lazy section metadata mode is never used alongside `internal_deps`,
+            // so there are no lazy sections to copy.
+            lazy_sections:           BTreeMap::new(),
             text_pages:              new_text_pages_range,
             rodata_pages:            new_rodata_pages_range,
             data_pages:              new_data_pages_range,
@@ -444,6 +533,7 @@ impl LoadedCrate {
             tls_sections:            self.tls_sections.clone(),
             data_sections:           self.data_sections.clone(),
             reexported_symbols:      self.reexported_symbols.clone(),
+            load_stats:              self.load_stats,
         });
         let new_crate_weak_ref = CowArc::downgrade(&new_crate);
 
@@ -480,7 +570,7 @@ impl LoadedCrate {
             };
             let new_sec_virt_addr = new_sec_virt_addr.ok_or("BUG: couldn't get virt_addr for new section")?;
 
-            let new_sec = Arc::new(LoadedSection::with_dependencies(
+            let new_sec = LoadedSection::with_dependencies(
                 old_sec.typ,                            // section type is the same
                 old_sec.name.clone(),                   // name is the same
                 new_sec_mapped_pages_ref,               // mapped_pages is different, points to the new duplicated one
@@ -492,7 +582,7 @@ impl LoadedCrate {
                 old_sec_inner.sections_i_depend_on.clone(),   // dependencies are the same, but relocations need to be re-written
                 Vec::new(),                             // no sections can possibly depend on this one, since we just created it
                 old_sec_inner.internal_dependencies.clone()   // internal dependencies are the same, but relocations need to be re-written
-            ));
+            ).into_ref();
 
             new_sections.insert(*shndx, new_sec);
         }
@@ -631,6 +721,30 @@ pub fn section_name_str_ref(section_type: &SectionType) -> StrRef {
 }
 
 
+/// The location of a loaded section whose full [`LoadedSection`] metadata hasn't been created.
+///
+/// This is much smaller than a `LoadedSection`, as it has no name, dependency lists,
+/// or references to its parent crate or `MappedPages`.
+/// The full metadata can be recreated from the parent crate's object file when needed.
+#[derive(Clone, Copy, Debug)]
+pub struct LazySection {
+    /// The type of this section, which is always `.text` or `.rodata`.
+    pub typ: SectionType,
+    /// The offset into the parent crate's `text_pages` or `rodata_pages` where this section starts.
+    pub mapped_pages_offset: usize,
+    /// The starting `VirtualAddress` of this section.
+    pub virt_addr: VirtualAddress,
+    /// The size in bytes of this section.
+    pub size: usize,
+}
+impl LazySection {
+    /// Returns true if this section contains the given virtual address.
+    pub fn contains(&self, virt_addr: VirtualAddress) -> bool {
+        self.virt_addr <= virt_addr && virt_addr.value() < self.virt_addr.value() + self.size
+    }
+}
+
+
 /// The parts of a `LoadedSection` that may be mutable, i.e., 
 /// only the parts that could change after a section is initially loaded and linked.
 #[derive(Default)]
@@ -756,6 +870,11 @@ impl LoadedSection {
         }
     }
 
+    /// Moves this section into a new [`StrongSectionRef`].
+    pub fn into_ref(self) -> StrongSectionRef {
+        Arc::new_in(self, &SECTION_CACHE)
+    }
+
     /// Returns the substring of this section's name that excludes the trailing hash. 
     /// 
     /// See the identical associated function [`section_name_without_hash()`](#fn.section_name_without_hash.html) for more. 
@@ -998,7 +1117,29 @@ impl RelocationEntry {
     /// does NOT depend on the target section's address itself in any way 
     /// (i.e., it only depends on the source section)
     pub fn is_absolute(&self) -> bool {
-        matches!(self.typ, R_X86_64_32 | R_X86_64_64)
+        #[cfg(target_arch = "x86_64")] {
+            matches!(self.typ, R_X86_64_32 | R_X86_64_64)
+        }
+        #[cfg(target_arch = "aarch64")] {
+            matches!(self.typ,
+                R_AARCH64_ABS64
+                | R_AARCH64_ABS32
+                | R_AARCH64_ABS16
+                | R_AARCH64_MOVW_UABS_G0
+                | R_AARCH64_MOVW_UABS_G0_NC
+                | R_AARCH64_MOVW_UABS_G1
+                | R_AARCH64_MOVW_UABS_G1_NC
+                | R_AARCH64_MOVW_UABS_G2
+                | R_AARCH64_MOVW_UABS_G2_NC
+                | R_AARCH64_MOVW_UABS_G3
+                | R_AARCH64_ADD_ABS_LO12_NC
+                | R_AARCH64_LDST8_ABS_LO12_NC
+                | R_AARCH64_LDST16_ABS_LO12_NC
+                | R_AARCH64_LDST32_ABS_LO12_NC
+                | R_AARCH64_LDST64_ABS_LO12_NC
+                | R_AARCH64_LDST128_ABS_LO12_NC
+            )
+        }
     }
 }
 
@@ -1264,7 +1405,8 @@ fn write_relocation_arch(
             overflow_check = overflow_range.map(|range| (source_val, range));
         }
 
-        R_AARCH64_ADR_PREL_PG_HI21 => {
+        R_AARCH64_ADR_PREL_PG_HI21
+        | R_AARCH64_ADR_PREL_PG_HI21_NC => {
             // This is a "page" relocation, in which values used for relocation calculations
             // are "page-aligned", i.e., the least-significant 12 bits are cleared.
             // It is always 12 bits, regardless of the hardware's actual page size.
@@ -1294,17 +1436,53 @@ fn write_relocation_arch(
                 target_ref.try_into()
                     .map_err(|_| "BUG: R_AARCH64_ADR_PREL_PG_HI21 relocation target val was not a u32")?
             );
-            // Set the instruction's two immediate value ranges to the proper ranges of the shifted source value.
-            let new_source_val =
-                  (existing_target_val & !(IMMEDIATE_FIELD_MASK_LO << IMMEDIATE_FIELD_SHIFT_LO))
-                | (existing_target_val & !(IMMEDIATE_FIELD_MASK_HI << IMMEDIATE_FIELD_SHIFT_HI))
+            // Set the instruction's two immediate value ranges to the proper ranges of the shifted source value,
+            // clearing both ranges of the existing immediate value first.
+            let new_source_val = (existing_target_val
+                    & !(IMMEDIATE_FIELD_MASK_LO << IMMEDIATE_FIELD_SHIFT_LO)
+                    & !(IMMEDIATE_FIELD_MASK_HI << IMMEDIATE_FIELD_SHIFT_HI))
                 | ((shifted_source_val & IMMEDIATE_FIELD_MASK_LO) << IMMEDIATE_FIELD_SHIFT_LO)
-                | ((shifted_source_val & IMMEDIATE_FIELD_MASK_HI) << IMMEDIATE_FIELD_SHIFT_HI);
+                | ((shifted_source_val >> 2 & IMMEDIATE_FIELD_MASK_HI) << IMMEDIATE_FIELD_SHIFT_HI);
             if verbose_log { trace!("                    existing_instr: {:#X}, new_instr: {:#X}", existing_target_val, new_source_val); }
             target_ref.copy_from_slice(&new_source_val.to_ne_bytes());
 
             const RANGE_32_BIT_ADR_SIGNED: Range<isize> = -TWO.pow(32) .. TWO.pow(32);
-            overflow_check = Some((source_val_usize, RANGE_32_BIT_ADR_SIGNED));
+            overflow_check = match relocation_entry.typ {
+                R_AARCH64_ADR_PREL_PG_HI21 => Some((source_val_usize, RANGE_32_BIT_ADR_SIGNED)),
+                _pg_hi21_nc                => None,
+            };
+        }
+
+        // This relocation type is for the ADR instruction, which forms a PC-relative address
+        // within +/- 1 MiB of the current instruction, without page alignment.
+        R_AARCH64_ADR_PREL_LO21 => {
+            // The immediate field has the same split layout as that of ADRP (see above),
+            // but it holds the unshifted byte offset.
+            // See: <https://developer.arm.com/documentation/ddi0596/2021-12/Base-Instructions/ADR--Form-PC-relative-address->
+            const IMMEDIATE_FIELD_SHIFT_HI: u8 = 5;
+            const IMMEDIATE_FIELD_MASK_HI: u32 = 0x7FFFF;
+            const IMMEDIATE_FIELD_SHIFT_LO: u8 = 29;
+            const IMMEDIATE_FIELD_MASK_LO: u32 = 0x3;
+
+            let target_range = target_sec_offset .. (target_sec_offset + size_of::<u32>());
+            let target_ref = &mut target_sec_slice[target_range];
+            let source_val_usize = source_sec_vaddr.value().wrapping_add(relocation_entry.addend).wrapping_sub(target_ref.as_ptr() as usize);
+            let source_val = source_val_usize as u32;
+            if verbose_log { trace!("                    target_ptr: {:p}, source_val: {:#X} (from source_sec_vaddr {:#X})", target_ref.as_ptr(), source_val_usize, source_sec_vaddr); }
+            let existing_target_val = u32::from_ne_bytes(
+                target_ref.try_into()
+                    .map_err(|_| "BUG: R_AARCH64_ADR_PREL_LO21 relocation target val was not a u32")?
+            );
+            let new_source_val = (existing_target_val
+                    & !(IMMEDIATE_FIELD_MASK_LO << IMMEDIATE_FIELD_SHIFT_LO)
+                    & !(IMMEDIATE_FIELD_MASK_HI << IMMEDIATE_FIELD_SHIFT_HI))
+                | ((source_val & IMMEDIATE_FIELD_MASK_LO) << IMMEDIATE_FIELD_SHIFT_LO)
+                | ((source_val >> 2 & IMMEDIATE_FIELD_MASK_HI) << IMMEDIATE_FIELD_SHIFT_HI);
+            if verbose_log { trace!("                    existing_instr: {:#X}, new_instr: {:#X}", existing_target_val, new_source_val); }
+            target_ref.copy_from_slice(&new_source_val.to_ne_bytes());
+
+            const RANGE_21_BIT_SIGNED: Range<isize> = -TWO.pow(20) .. TWO.pow(20);
+            overflow_check = Some((source_val_usize, RANGE_21_BIT_SIGNED));
         }
 
         // These relocation types all use the same logic, but have different bit masks
@@ -1380,6 +1558,43 @@ fn write_relocation_arch(
             overflow_check = Some((source_val, RANGE_27_BIT_SIGNED));
         }
 
+        // These relocation types are for conditional branch instructions and PC-relative literal loads,
+        // which all encode a signed word offset (the byte offset divided by 4) starting at bit 5.
+        R_AARCH64_CONDBR19
+        | R_AARCH64_LD_PREL_LO19
+        | R_AARCH64_TSTBR14 => {
+            // See: <https://developer.arm.com/documentation/ddi0596/2021-12/Base-Instructions/B-cond--Branch-conditionally->,
+            // <https://developer.arm.com/documentation/ddi0596/2021-12/Base-Instructions/LDR--literal---Load-Register--literal-->,
+            // and <https://developer.arm.com/documentation/ddi0596/2021-12/Base-Instructions/TBZ--Test-bit-and-Branch-if-Zero->
+            const IMMEDIATE_FIELD_SHIFT: u8 = 5;
+            const SOURCE_VALUE_SHIFT: u8    = 2;
+            const RANGE_21_BIT_SIGNED: Range<isize> = -TWO.pow(20) .. TWO.pow(20);
+            const RANGE_16_BIT_BRANCH_SIGNED: Range<isize> = -TWO.pow(15) .. TWO.pow(15);
+            let (immediate_field_mask, overflow_range): (u32, _) = match relocation_entry.typ {
+                // The immediate field occupies 14 bits [18:5] in test-and-branch instructions.
+                R_AARCH64_TSTBR14 => (0x3FFF, RANGE_16_BIT_BRANCH_SIGNED),
+                // The immediate field occupies 19 bits [23:5] in conditional branch and literal load instructions.
+                _condbr_or_ld     => (0x7FFFF, RANGE_21_BIT_SIGNED),
+            };
+
+            let target_range = target_sec_offset .. (target_sec_offset + size_of::<u32>());
+            let target_ref = &mut target_sec_slice[target_range];
+            let source_val = source_sec_vaddr.value().wrapping_add(relocation_entry.addend).wrapping_sub(target_ref.as_ptr() as usize);
+            let shifted_source_val = source_val >> SOURCE_VALUE_SHIFT;
+            if verbose_log { trace!("                    target_ptr: {:p}, source_val: {:#X}, shifted_source_val: {:#X} (from source_sec_vaddr {:#X})", target_ref.as_ptr(), source_val, shifted_source_val, source_sec_vaddr); }
+            let existing_target_val = u32::from_ne_bytes(
+                target_ref.try_into()
+                    .map_err(|_| "BUG: R_AARCH64_CONDBR19/LD_PREL_LO19/TSTBR14 relocation target val was not a u32")?
+            );
+            // Set the instruction's immediate value to the shifted source value.
+            let immediate_field_value = shifted_source_val as u32 & immediate_field_mask;
+            let new_source_val = (existing_target_val & !(immediate_field_mask << IMMEDIATE_FIELD_SHIFT))
+                | (immediate_field_value << IMMEDIATE_FIELD_SHIFT);
+            if verbose_log { trace!("                    existing_instr: {:#X}, new_instr: {:#X}, imm val: {:#X}", existing_target_val, new_source_val, immediate_field_value); }
+            target_ref.copy_from_slice(&new_source_val.to_ne_bytes());
+            overflow_check = Some((source_val, overflow_range));
+        }
+
         // These relocation types are for thread-local storage, only the "local-exec" tls model.
         R_AARCH64_TLSLE_ADD_TPREL_HI12
         | R_AARCH64_TLSLE_ADD_TPREL_LO12
@@ -6,9 +6,11 @@
 //! The types in this module remove those refcount types so that they can be
 //! reconstructed individually at runtime after deserialization by the `crate_metadata` crate.
 //!
-//! This is currently only used to parse and serialize the `nano_core` binary at compile time.
+//! This is used to parse and serialize the `nano_core` binary at compile time.
 //! The `nano_core`'s [`SerializedCrate`] is then included as a boot module
 //! so it can be deserialized into a LoadedCrate at runtime by `mod_mgmt`.
+//! It is also used by `mod_mgmt` to save the already-linked crates of a namespace
+//! into a [`SerializedNamespace`] image, which can be restored without re-linking.
 //! 
 //! Some other types have been moved from `crate_metadata` into this crate because
 //! they are required for (de)serialization, e.g., [`SectionType`].
@@ -33,6 +35,7 @@ extern crate alloc;
 use alloc::{
     collections::{BTreeMap, BTreeSet},
     string::String,
+    vec::Vec,
 };
 use hashbrown::HashMap;
 use serde::{Deserialize, Serialize};
@@ -84,6 +87,95 @@ pub struct SerializedSection {
     pub size: usize,
 }
 
+/// A (de)serializable image of the already-loaded and linked crates in a namespace.
+///
+/// Unlike a [`SerializedCrate`] of the `nano_core`, this includes the actual contents
+/// of each crate's memory and the dependencies between its sections and other crates' sections,
+/// such that the crates can be restored at the exact same virtual addresses without re-linking them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerializedNamespace {
+    /// The name of the namespace that these crates were loaded into.
+    pub namespace_name: String,
+    /// The crates in the namespace, not including crates in its recursive namespace.
+    pub crates: Vec<SerializedLoadedCrate>,
+}
+
+/// A (de)serializable representation of a single loaded and linked crate within a [`SerializedNamespace`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerializedLoadedCrate {
+    /// The crate's metadata and sections.
+    pub crate_info: SerializedCrate,
+    /// The name of the object file that the crate was loaded from.
+    pub object_file_name: String,
+    /// A hash of the contents of the object file that the crate was loaded from,
+    /// used to ensure that the same object file still exists upon restore.
+    pub object_file_hash: u64,
+    /// The contents of the crate's executable pages, if any.
+    pub text_pages: Option<SerializedPages>,
+    /// The contents of the crate's read-only pages, if any.
+    pub rodata_pages: Option<SerializedPages>,
+    /// The contents of the crate's read-write pages, if any.
+    pub data_pages: Option<SerializedPages>,
+    /// The symbols that this crate re-exports (e.g., after a crate swap)
+    /// and the starting virtual address of the section in this crate that each one refers to.
+    pub reexported_symbols: Vec<(String, usize)>,
+    /// The dependencies of this crate's sections on sections in other crates.
+    pub dependencies: Vec<SerializedDependency>,
+    /// The dependencies between sections within this crate, as `(target shndx, source shndx, relocation)`.
+    pub internal_dependencies: Vec<(Shndx, Shndx, SerializedRelocation)>,
+    /// The sections of this crate whose full metadata was never created, keyed by their shndx.
+    pub lazy_sections: Vec<(Shndx, SerializedLazySection)>,
+}
+
+/// A (de)serializable representation of a section without full metadata, i.e., a `LazySection`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerializedLazySection {
+    /// The type of the section.
+    pub ty: SectionType,
+    /// The starting virtual address of the range covered by this section.
+    pub virtual_address: usize,
+    /// The offset into this section's containing `MappedPages` where this section starts.
+    pub offset: usize,
+    /// The size of the section.
+    pub size: usize,
+}
+
+/// The contents of a contiguous range of a crate's memory.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerializedPages {
+    /// The starting virtual address of the memory, which must be page-aligned.
+    pub start_address: usize,
+    /// The contents of the memory; its length is the size of the memory in bytes.
+    pub contents: Vec<u8>,
+}
+
+/// A (de)serializable representation of one section's dependency on a section in another crate.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerializedDependency {
+    /// The shndx of the section in this crate that depends on the source section,
+    /// i.e., the section in which the relocation was written.
+    pub target_shndx: Shndx,
+    /// The name of the crate that contains the source section.
+    pub source_crate: String,
+    /// The name of the source section.
+    pub source_section: String,
+    /// The starting virtual address of the source section,
+    /// which must be the same upon restore for the relocation to still be valid.
+    pub source_address: usize,
+    /// The relocation that connects the target section to the source section.
+    pub relocation: SerializedRelocation,
+}
+
+/// A (de)serializable representation of a relocation entry.
+///
+/// See `RelocationEntry` for more detail on the fields of this struct.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SerializedRelocation {
+    pub typ: u32,
+    pub addend: usize,
+    pub offset: usize,
+}
+
 /// A Section Header iNDeX (SHNDX), as specified by the ELF format. 
 /// Even though this is typically encoded as a `u16`,
 /// its decoded form can exceed the max size of `u16`.
@@ -0,0 +1,56 @@
+//! Prefetches the object files of crates that a crate being loaded is likely to need,
+//! hiding the latency of reading and parsing them behind that crate's relocation work.
+//!
+//! `mod_mgmt` records which crates each crate load pulls in to resolve missing symbols
+//! and predicts them for subsequent loads of the same crate, but it can't spawn tasks itself.
+//! This crate registers [`mod_mgmt::ReadaheadHooks`] that prefetch the predicted crates
+//! in a separate task, such that they are prefetched concurrently with the load that requested them.
+//! As a prefetch task may run on any CPU, the CLS data image of every CPU is regenerated
+//! whenever a prefetched crate is used.
+//!
+//! Readahead is disabled until [`enable()`] is invoked, in which case `mod_mgmt` doesn't
+//! record the history of crate loads either.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use log::{debug, error, warn};
+use mod_mgmt::{ReadaheadHooks, ReadaheadRequest};
+
+/// Enables readahead of likely-needed crates for all subsequent crate loads.
+pub fn enable() {
+    mod_mgmt::set_readahead_hooks(Some(ReadaheadHooks {
+        prefetch: readahead_hook,
+        current_task_id: task::get_my_current_task_id,
+        reload_cls: parallel_crate_loader::reload_cls_on_all_cpus,
+    }));
+}
+
+/// Disables readahead, discarding any crates that were prefetched but not yet used.
+pub fn disable() {
+    mod_mgmt::set_readahead_hooks(None);
+}
+
+fn readahead_hook(request: ReadaheadRequest) {
+    let name = format!("readahead_{}", request.crate_name);
+    if let Err(e) = spawn::new_task_builder(prefetch_task, request).name(name).spawn() {
+        error!("crate_readahead: failed to spawn prefetch task: {}", e);
+    }
+}
+
+/// The entry point of a prefetch task, which prefetches the requested crates in order.
+fn prefetch_task(request: ReadaheadRequest) {
+    let Some(kernel_mmi_ref) = memory::get_kernel_mmi_ref() else { return };
+    for (namespace_name, file) in request.files {
+        // Only registered namespaces (including the initial kernel namespace) can be found by name.
+        let Some(namespace) = mod_mgmt::get_registered_namespace(&namespace_name) else {
+            debug!("crate_readahead: skipping prefetch into unregistered namespace {:?}", namespace_name);
+            continue;
+        };
+        if let Err(e) = namespace.prefetch_crate(&file, kernel_mmi_ref, false) {
+            warn!("crate_readahead: failed to prefetch a crate needed by {:?}: {}", request.crate_name, e);
+        }
+    }
+}
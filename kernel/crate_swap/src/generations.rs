@@ -0,0 +1,162 @@
+//! Generational crate versioning, in which loading a new version of a crate
+//! automatically re-links every crate that depended on its previous version.
+//!
+//! This is an opt-in alternative to assembling a [`SwapRequest`](crate::SwapRequest) by hand:
+//! calling [`load_new_generation()`] with the object file for a new version of crate `A`
+//! finds the currently-loaded version of `A` in the given namespace,
+//! replaces it via [`CrateNamespace::swap_crate()`], which re-links all sections that depend on it,
+//! and then retires the old version.
+//!
+//! A retired crate is removed from its namespace, but is not necessarily dropped right away,
+//! as it may still be referenced elsewhere, e.g., by a running application task.
+//! It is tracked here until its last reference is dropped; see [`retired_generations()`].
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use spin::Mutex;
+use memory::MmiRef;
+use fs_node::FileRef;
+use path::PathBuf;
+use mod_mgmt::{
+    CrateNamespace,
+    LoadError,
+    StrRef,
+    StrongCrateRef,
+    WeakCrateRef,
+    CRATE_HASH_DELIMITER,
+    crate_name_from_path,
+};
+
+/// The generations of every crate that has been loaded via [`load_new_generation()`],
+/// keyed by crate name without its hash.
+static GENERATIONS: Mutex<BTreeMap<String, CrateGenerations>> = Mutex::new(BTreeMap::new());
+
+/// The generation history of a single crate.
+struct CrateGenerations {
+    /// The generation number of the currently-loaded version of the crate.
+    current: u64,
+    /// Previous versions of the crate that may still be in use,
+    /// along with their generation numbers and full crate names.
+    retired: Vec<(u64, StrRef, WeakCrateRef)>,
+}
+
+/// Information about a new generation of a crate loaded by [`load_new_generation()`].
+#[derive(Debug, Clone)]
+pub struct Generation {
+    /// The full name of the newly-loaded crate, including its hash.
+    pub crate_name: StrRef,
+    /// The generation number of the newly-loaded crate.
+    /// The first version of a crate that was loaded normally is generation `0`.
+    pub generation: u64,
+    /// The full name of the crate that was replaced, i.e., the previous generation.
+    pub old_crate_name: StrRef,
+    /// The number of relocations in dependent sections that were rewritten
+    /// to point to the new generation.
+    pub relinked_dependents: usize,
+}
+
+/// Loads a new version of an already-loaded crate from the given object file,
+/// re-links all crates that depend on the currently-loaded version such that they use the new version,
+/// and retires the old version.
+///
+/// The old version is the single crate in `namespace` (or its recursive namespaces)
+/// whose name, excluding its hash, matches that of the new crate.
+/// The replacement itself is performed by [`CrateNamespace::swap_crate()`] on the namespace
+/// that contains the old crate, so the new crate is added to that namespace,
+/// and the contents of the old crate's `.data` and `.bss` sections are migrated into the new crate.
+///
+/// The old crate is removed from its namespace along with its symbols,
+/// and is dropped once nothing else references it.
+///
+/// # Errors
+/// See [`CrateNamespace::swap_crate()`]. If it fails, the old crate remains in use,
+/// although any state that was already migrated into the new crate is discarded along with it.
+///
+/// # Warning: Correctness not guaranteed
+/// Like `swap_crates()`, this doesn't check that the new crate is ABI-compatible with the old one.
+pub fn load_new_generation(
+    namespace: &Arc<CrateNamespace>,
+    new_crate_object_file: &FileRef,
+    kernel_mmi_ref: &MmiRef,
+    verbose_log: bool,
+) -> Result<Generation, LoadError> {
+    let new_crate_name_without_hash = {
+        let path = PathBuf::from(new_crate_object_file.lock().get_name());
+        let new_crate_name = crate_name_from_path(&path)
+            .ok_or("load_new_generation(): couldn't get crate name from object file")?;
+        String::from(new_crate_name.split(CRATE_HASH_DELIMITER).next().unwrap_or(new_crate_name))
+    };
+
+    let (old_crate_name, old_crate_ref, old_namespace) = CrateNamespace::get_crate_starting_with(
+        namespace,
+        &format!("{new_crate_name_without_hash}{CRATE_HASH_DELIMITER}"),
+    ).ok_or(LoadError::CrateNotFound { name: new_crate_name_without_hash.clone() })?;
+
+    // Count the live dependents before they're re-linked, as `swap_crate()` clears them from the old crate.
+    let relinked_dependents = old_crate_ref.lock_as_ref().global_sections_iter()
+        .map(|old_sec| old_sec.inner.read().sections_dependent_on_me.iter()
+            .filter(|weak_dep| weak_dep.section.strong_count() > 0)
+            .count()
+        )
+        .sum();
+
+    let new_crate_ref = old_namespace.swap_crate(&old_crate_name, new_crate_object_file, kernel_mmi_ref, verbose_log)?;
+    let new_crate_name = new_crate_ref.lock_as_ref().crate_name.clone();
+
+    let generation = {
+        let mut generations = GENERATIONS.lock();
+        let entry = generations.entry(new_crate_name_without_hash).or_insert(CrateGenerations { current: 0, retired: Vec::new() });
+        entry.retired.push((entry.current, old_crate_name.clone(), StrongCrateRef::downgrade(&old_crate_ref)));
+        entry.current += 1;
+        entry.current
+    };
+    drop(old_crate_ref);
+    prune_retired();
+
+    info!("load_new_generation(): replaced {:?} with {:?} (generation {}) in namespace {}, re-linked {} dependents",
+        old_crate_name, new_crate_name, generation, old_namespace.name(), relinked_dependents,
+    );
+    Ok(Generation {
+        crate_name: new_crate_name,
+        generation,
+        old_crate_name,
+        relinked_dependents,
+    })
+}
+
+/// Returns the generation number of the currently-loaded version of the given crate,
+/// whose name should not include a hash.
+///
+/// Returns `0` if no new generations of the crate have been loaded via [`load_new_generation()`].
+pub fn current_generation(crate_name_without_hash: &str) -> u64 {
+    GENERATIONS.lock().get(crate_name_without_hash).map_or(0, |g| g.current)
+}
+
+/// Returns the generation numbers and full names of the retired versions of the given crate
+/// that are still in use, i.e., haven't yet been dropped.
+///
+/// The name of the given crate should not include a hash.
+pub fn retired_generations(crate_name_without_hash: &str) -> Vec<(u64, StrRef)> {
+    prune_retired();
+    GENERATIONS.lock().get(crate_name_without_hash)
+        .map(|g| g.retired.iter().map(|(generation, name, _)| (*generation, name.clone())).collect())
+        .unwrap_or_default()
+}
+
+/// Forgets about retired crates that have been dropped.
+fn prune_retired() {
+    for generations in GENERATIONS.lock().values_mut() {
+        generations.retired.retain(|(generation, name, weak_crate)| {
+            let in_use = weak_crate.upgrade().is_some();
+            if !in_use {
+                info!("Retired crate {:?} (generation {}) has been dropped", name, generation);
+            }
+            in_use
+        });
+    }
+}
+
@@ -21,7 +21,7 @@ extern crate hpet;
 
 use core::{
     fmt,
-    ops::Deref,
+    ops::{Deref, DerefMut},
 };
 use alloc::{
     borrow::{Cow, ToOwned},
@@ -38,15 +38,19 @@ use mod_mgmt::{
     CrateNamespace,
     NamespaceDir,
     IntoCrateObjectFile,
-    write_relocation,
+    RelocationBatch,
     crate_name_from_path,
     replace_containing_crate_name,
     StrongSectionRef,
     WeakDependent, StrRef,
+    LoadError,
 };
 use path::{Path, PathBuf, Component};
 use by_address::ByAddress;
 
+mod generations;
+pub use generations::{load_new_generation, current_generation, retired_generations, Generation};
+
 
 lazy_static! {
     /// The set of crates that have been previously unloaded (e.g., swapped out) from a `CrateNamespace`.
@@ -67,6 +71,55 @@ pub fn clear_unloaded_crate_cache() {
 }
 
 
+/// A [`RelocationBatch`] that is applied when it is dropped, unless it was already applied via [`ApplyOnDrop::apply()`].
+///
+/// This ensures that queued relocation writes are never lost on an early error return,
+/// which would leave sections whose dependency metadata refers to a new crate
+/// still pointing to the old crate.
+struct ApplyOnDrop<'m> {
+    batch: Option<RelocationBatch>,
+    kernel_mmi_ref: &'m MmiRef,
+    verbose_log: bool,
+}
+
+impl<'m> ApplyOnDrop<'m> {
+    fn new(kernel_mmi_ref: &'m MmiRef, verbose_log: bool) -> ApplyOnDrop<'m> {
+        ApplyOnDrop { batch: Some(RelocationBatch::new()), kernel_mmi_ref, verbose_log }
+    }
+
+    /// Applies the batch now, returning any error that occurred.
+    fn apply(mut self) -> Result<(), &'static str> {
+        match self.batch.take() {
+            Some(batch) => batch.apply(self.kernel_mmi_ref, self.verbose_log),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Deref for ApplyOnDrop<'_> {
+    type Target = RelocationBatch;
+    fn deref(&self) -> &RelocationBatch {
+        self.batch.as_ref().expect("BUG: ApplyOnDrop: batch was already applied")
+    }
+}
+
+impl DerefMut for ApplyOnDrop<'_> {
+    fn deref_mut(&mut self) -> &mut RelocationBatch {
+        self.batch.as_mut().expect("BUG: ApplyOnDrop: batch was already applied")
+    }
+}
+
+impl Drop for ApplyOnDrop<'_> {
+    fn drop(&mut self) {
+        if let Some(batch) = self.batch.take() {
+            if let Err(e) = batch.apply(self.kernel_mmi_ref, self.verbose_log) {
+                error!("swap_crates(): failed to apply relocations after an error: {}", e);
+            }
+        }
+    }
+}
+
+
 /// A state transfer function is an arbitrary function called when swapping crates. 
 /// 
 /// See the `swap_crates()` function for more details. 
@@ -118,6 +171,11 @@ pub type StateTransferFunction = fn(&Arc<CrateNamespace>, &CrateNamespace) -> Re
 /// other crates will be swapped alongside that one. 
 /// It will most likely error out, but this responsibility is currently left to the caller.
 /// 
+/// # Errors
+/// Returns a [`LoadError`] describing which crate, section, or symbol caused the failure,
+/// e.g., [`LoadError::MissingSymbol`] if the new crates don't provide a symbol
+/// that other crates depended on in the old crates.
+/// 
 /// # Crate swapping optimizations
 /// When one or more crates is swapped out, they are not fully unloaded, but rather saved in a cache
 /// in order to accelerate future swapping commands. 
@@ -130,7 +188,7 @@ pub fn swap_crates(
     kernel_mmi_ref: &MmiRef,
     verbose_log: bool,
     cache_old_crates: bool
-) -> Result<(), &'static str> {
+) -> Result<(), LoadError> {
 
     #[cfg(not(loscd_eval))]
     debug!("swap_crates()[0]: \n\t-->override dir: {:?}, \n\t-->cache_old_crates: {:?}, \n\t-->state transfer: {:?},\n\t-->swap_requests: {:?}", 
@@ -188,6 +246,9 @@ pub fn swap_crates(
             (nn, false)
         }
     };
+
+    // A swap can't be undone partway through, so check for symbol conflicts before modifying anything.
+    check_symbol_conflicts(&swap_requests, &namespace_of_new_crates, this_namespace)?;
         
     #[cfg(loscd_eval)]
     let hpet_after_load_crates = hpet.get_counter();
@@ -226,6 +287,10 @@ pub fn swap_crates(
     // we simply need to fix up all of the relocations `WeakDependents` for each of the existing sections
     // that depend on the old crate that we're replacing here,
     // such that they refer to the new_module instead of the old_crate.
+    // The relocation writes into all sections that depend on the old crates, which are applied together after the loop below.
+    // The dependency metadata of those sections is updated as their writes are queued,
+    // so the writes must be applied even if an error occurs before then.
+    let mut relocation_batch = ApplyOnDrop::new(kernel_mmi_ref, verbose_log);
     for req in &swap_requests {
         let SwapRequest { old_crate_name, old_namespace, new_crate_object_file, new_namespace: _new_ns, reexport_new_symbols_as_old } = req; 
         let reexport_new_symbols_as_old = *reexport_new_symbols_as_old;
@@ -338,7 +403,7 @@ pub fn swap_crates(
                 // This closure finds the section in the `new_crate` that corresponds to the given `old_sec` from the `old_crate`.
                 // And, if enabled, it will reexport that new section under the same name as the `old_sec`.
                 // We put this procedure in a closure because it's relatively expensive, allowing us to run it only when necessary.
-                let find_corresponding_new_section = |new_crate_reexported_symbols: &mut BTreeSet<StrRef>| -> Result<StrongSectionRef, &'static str> {
+                let find_corresponding_new_section = |new_crate_reexported_symbols: &mut BTreeSet<StrRef>| -> Result<StrongSectionRef, LoadError> {
                     // Use the new namespace to find the new source_sec that old target_sec should point to.
                     // The new source_sec must have the same name as the old one (old_sec here),
                     // otherwise it wouldn't be a valid swap -- the target_sec's parent crate should have also been swapped.
@@ -361,7 +426,10 @@ pub fn swap_crates(
                         }
                     }.ok_or_else(|| {
                         error!("swap_crates(): couldn't find section in the new crate that corresponds to a match of the old section {:?}", old_sec.name);
-                        "couldn't find section in the new crate that corresponds to a match of the old section"
+                        LoadError::MissingSymbol {
+                            crate_name: StrRef::from(new_crate_name.as_str()),
+                            symbol: old_sec.name.to_string(),
+                        }
                     })?;
                     #[cfg(not(loscd_eval))]
                     debug!("swap_crates(): found match for old source_sec {:?}, new source_sec: {:?}", old_sec, &*new_crate_source_sec);
@@ -419,39 +487,9 @@ pub fn swap_crates(
                     #[cfg(not(loscd_eval))]
                     debug!("    swap_crates(): target_sec: {:?}, old source sec: {:?}, new source sec: {:?}", target_sec, old_sec, new_source_sec);
 
-                    // If the target_sec's mapped pages aren't writable (which is common in the case of swapping),
-                    // then we need to temporarily remap them as writable here so we can fix up the target_sec's new relocation entry.
-                    {
-                        #[cfg(loscd_eval)]
-                        let start_rewriting_relocations = hpet.get_counter();
-
-                        let mut target_sec_mapped_pages = target_sec.mapped_pages.lock();
-                        let target_sec_initial_flags = target_sec_mapped_pages.flags();
-                        if !target_sec_initial_flags.is_writable() {
-                            target_sec_mapped_pages.remap(&mut kernel_mmi_ref.lock().page_table, target_sec_initial_flags.writable(true))?;
-                        }
-
-                        write_relocation(
-                            relocation_entry, 
-                            target_sec_mapped_pages.as_slice_mut(0, target_sec.mapped_pages_offset + target_sec.size)?,
-                            target_sec.mapped_pages_offset, 
-                            new_source_sec.virt_addr,
-                            verbose_log
-                        )?;
-
-                        #[cfg(loscd_eval)] {
-                            let end_rewriting_relocations = hpet.get_counter();
-                            hpet_total_rewriting_relocations += end_rewriting_relocations - start_rewriting_relocations;
-                        }
-
-                        #[cfg(not(loscd_eval))] {
-                            // If we temporarily remapped the target_sec's mapped pages as writable, undo that here
-                            if !target_sec_initial_flags.is_writable() {
-                                target_sec_mapped_pages.remap(&mut kernel_mmi_ref.lock().page_table, target_sec_initial_flags)?;
-                            };
-                        }
-                    }
-                
+                    // Queue the rewrite of the target_sec's relocation entry, which is applied below
+                    // along with all other rewrites, such that each target region is remapped as writable only once.
+                    relocation_batch.add(&target_sec, relocation_entry, new_source_sec.virt_addr);
 
                     #[cfg(loscd_eval)]
                     let start_fixing_dependencies = hpet.get_counter();
@@ -479,7 +517,7 @@ pub fn swap_crates(
                     if !found_strong_dependency {
                         error!("Couldn't find/remove the existing StrongDependency from target_sec {:?} to old_sec {:?}",
                             target_sec.name, old_sec.name);
-                        return Err("Couldn't find/remove the target_sec's StrongDependency on the old crate section");
+                        return Err("Couldn't find/remove the target_sec's StrongDependency on the old crate section".into());
                     }
 
                     #[cfg(loscd_eval)] {
@@ -501,6 +539,20 @@ pub fn swap_crates(
         } // end of scope, drops lock on `new_crate_ref`
     } // end of iterating over all swap requests to fix up old crate dependents
 
+    {
+        #[cfg(loscd_eval)]
+        let start_rewriting_relocations = hpet.get_counter();
+
+        #[cfg(not(loscd_eval))]
+        debug!("swap_crates(): rewriting {} relocations in {} regions", relocation_batch.len(), relocation_batch.num_regions());
+        relocation_batch.apply()?;
+
+        #[cfg(loscd_eval)] {
+            let end_rewriting_relocations = hpet.get_counter();
+            hpet_total_rewriting_relocations += end_rewriting_relocations - start_rewriting_relocations;
+        }
+    }
+
 
     // Execute the provided state transfer functions
     for symbol in state_transfer_functions {
@@ -517,7 +569,7 @@ pub fn swap_crates(
 
     // Sanity check that we correctly populated the lists "new_crate_names" and "old_crates_are_loaded". 
     if swap_requests.len() != new_crate_names.len() &&  swap_requests.len() != old_crates_are_loaded.len() {
-        return Err("BUG: swap_crates(): didn't properly populate the list of `new_crate_names` and/or `old_crates_are_loaded`.");
+        return Err("BUG: swap_crates(): didn't properly populate the list of `new_crate_names` and/or `old_crates_are_loaded`.".into());
     }
 
     // Remove all of the old crates now that we're fully done using them.
@@ -568,7 +620,7 @@ pub fn swap_crates(
                     for old_sec in old_crate.global_sections_iter() {
                         if old_ns_symbol_map.remove(&old_sec.name).is_none() {
                             error!("swap_crates(): couldn't find old symbol {:?} in the old crate's namespace: {}.", old_sec.name, old_namespace.name());
-                            return Err("couldn't find old symbol {:?} in the old crate's namespace");
+                            return Err("couldn't find old symbol {:?} in the old crate's namespace".into());
                         }
                     }
                 }
@@ -593,7 +645,9 @@ pub fn swap_crates(
             
             if cache_old_crates {
                 #[cfg(not(loscd_eval))]
-                cached_crates.crate_tree().lock().insert(old_crate_name.as_str().into(), old_crate_ref);
+                {
+                    cached_crates.crate_tree().lock().insert(old_crate_name.as_str().into(), old_crate_ref);
+                }
             }
 
             #[cfg(loscd_eval)]
@@ -822,6 +876,41 @@ fn move_file(file: &FileRef, dest_dir: &DirRef) -> Result<Option<(FileOrDir, Dir
 /// A list of one or more `SwapRequest`s that is used by the `swap_crates` function.
 pub type SwapRequestList = Vec<SwapRequest>;
 
+/// Returns an error if any of the new crates in `namespace_of_new_crates` defines a symbol that
+/// is already defined by a crate other than those being replaced, in the namespace it will be added to
+/// or any of that namespace's recursive namespaces, as long as that namespace is in strict symbol conflict mode.
+fn check_symbol_conflicts(
+    swap_requests: &SwapRequestList,
+    namespace_of_new_crates: &CrateNamespace,
+    this_namespace: &Arc<CrateNamespace>,
+) -> Result<(), LoadError> {
+    let replaced_crates: Vec<StrRef> = swap_requests.iter()
+        .filter_map(|req| req.old_crate_name.as_deref().and_then(|ocn| CrateNamespace::get_crate_and_namespace(&req.old_namespace, ocn)))
+        .map(|(old_crate_ref, _ns)| old_crate_ref.lock_as_ref().crate_name.clone())
+        .collect();
+
+    let mut result = Ok(());
+    namespace_of_new_crates.for_each_crate(false, |new_crate_name, new_crate_ref| {
+        // Shared crates were already loaded in the backup namespace, so they aren't added anywhere.
+        if new_crate_ref.is_shared() {
+            return true;
+        }
+        let swap_request = swap_requests.iter().find(|req|
+            crate_name_from_path(&PathBuf::from(req.new_crate_object_file.lock().get_name())) == Some(new_crate_name)
+        );
+        // Crates loaded as dependencies of the new crates are added to `this_namespace` or its recursive namespace.
+        let target_ns: &CrateNamespace = match swap_request {
+            Some(req) => &req.new_namespace,
+            None => this_namespace,
+        };
+        let new_crate = new_crate_ref.lock_as_ref();
+        result = target_ns.check_symbol_conflicts(&new_crate.crate_name, new_crate.sections.values(), &replaced_crates);
+        result.is_ok()
+    });
+    result
+}
+
+
 /// This struct is used to specify the details of a crate-swapping operation,
 /// in which an "old" crate is replaced with a "new" crate that is then used in place of that old crate. 
 /// The old crate is removed from its old `CrateNamespace` while the new crate 
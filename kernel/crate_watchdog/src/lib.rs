@@ -0,0 +1,194 @@
+//! A crate-granularity watchdog that makes unattended live updates safe.
+//!
+//! [`swap_crate_checked()`] swaps a crate via [`CrateNamespace::swap_crate()`]
+//! and then runs the new crate's health-check function, if it exports one,
+//! in a separate task with a timeout.
+//! If the health check returns an error, panics, or doesn't finish in time,
+//! the swap is rolled back by swapping the old crate's object file back in,
+//! which migrates the crate's state back into the old version.
+//! The rollback is itself an ordinary swap rather than an atomic transaction,
+//! so if it fails, the new crate remains in place and the failure is reported to the caller.
+//!
+//! A health check that times out is killed, which stops it from being scheduled again
+//! but doesn't unwind it, so any locks that it holds are never released.
+//! The rollback only begins once the health-check task has stopped running,
+//! as the rollback unmaps the new crate's code.
+//!
+//! A crate exports a health check by defining a public function named [`HEALTH_CHECK_FUNCTION`]
+//! at its top level with the signature of [`HealthCheckFn`], for example:
+//! ```ignore
+//! pub fn health_check() -> Result<(), &'static str> {
+//!     if DEVICE.lock().is_responsive() { Ok(()) } else { Err("device is unresponsive") }
+//! }
+//! ```
+//! The function is found by its symbol in the namespace's symbol map,
+//! so it must not be inlined or removed by the compiler.
+
+#![no_std]
+
+extern crate alloc;
+#[macro_use] extern crate log;
+
+use alloc::{format, string::String, sync::Arc};
+use core::fmt;
+use fs_node::FileRef;
+use memory::MmiRef;
+use mod_mgmt::{CrateNamespace, LoadError, SectionType, StrongCrateRef, SECTION_HASH_DELIMITER};
+use task::{ExitValue, KillReason};
+use time::{Duration, Instant};
+
+/// The name of the function that a crate exports as its health check.
+pub const HEALTH_CHECK_FUNCTION: &str = "health_check";
+
+/// The signature of a crate's health-check function.
+pub type HealthCheckFn = fn() -> Result<(), &'static str>;
+
+/// The default amount of time that a health check may take before it's considered failed.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often the watchdog checks whether the health-check task has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The reason that a crate's health check failed.
+#[derive(Debug)]
+pub enum HealthCheckFailure {
+    /// The health-check function returned an error.
+    ///
+    /// The reason is copied, as the original string is part of the new crate,
+    /// which is unloaded when the swap is rolled back.
+    Failed(String),
+    /// The health-check task panicked or was otherwise killed.
+    Killed(KillReason),
+    /// The health-check function didn't return within the timeout.
+    TimedOut,
+    /// The health-check task couldn't be spawned or joined.
+    Task(&'static str),
+}
+
+impl fmt::Display for HealthCheckFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed(reason) => write!(f, "health check failed: {reason}"),
+            Self::Killed(reason) => write!(f, "health check was killed: {reason}"),
+            Self::TimedOut       => write!(f, "health check timed out"),
+            Self::Task(reason)   => write!(f, "couldn't run health check: {reason}"),
+        }
+    }
+}
+
+/// An error returned by [`swap_crate_checked()`].
+#[derive(Debug)]
+pub enum WatchdogError {
+    /// The swap itself failed, so nothing was changed.
+    Swap(LoadError),
+    /// The new crate's health check failed, so the swap was rolled back.
+    ///
+    /// `rollback` is the result of swapping the old crate back in;
+    /// if it is an error, the new crate remains in place.
+    Unhealthy {
+        failure: HealthCheckFailure,
+        rollback: Result<StrongCrateRef, LoadError>,
+    },
+}
+
+impl fmt::Display for WatchdogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Swap(e) => write!(f, "swap failed: {e}"),
+            Self::Unhealthy { failure, rollback: Ok(_) } => write!(f, "{failure}; the swap was rolled back"),
+            Self::Unhealthy { failure, rollback: Err(e) } => write!(f, "{failure}; rolling back the swap also failed: {e}"),
+        }
+    }
+}
+
+/// Swaps the crate `old_crate_name` in the given `namespace` for the new crate in `new_crate_object_file`,
+/// like [`CrateNamespace::swap_crate()`], and then runs the new crate's health check.
+///
+/// If the new crate doesn't export a [`HEALTH_CHECK_FUNCTION`], the swap is accepted as is.
+/// Otherwise, the health check runs in a new task; if it doesn't return `Ok` within the given `timeout`,
+/// the old crate's object file is swapped back in and [`WatchdogError::Unhealthy`] is returned.
+///
+/// Returns the new crate if the swap succeeded and the new crate is healthy.
+pub fn swap_crate_checked(
+    namespace: &Arc<CrateNamespace>,
+    old_crate_name: &str,
+    new_crate_object_file: &FileRef,
+    kernel_mmi_ref: &MmiRef,
+    timeout: Duration,
+    verbose_log: bool,
+) -> Result<StrongCrateRef, WatchdogError> {
+    let old_crate_object_file = namespace.get_crate(old_crate_name)
+        .map(|crate_ref| crate_ref.lock_as_ref().object_file.clone())
+        .ok_or_else(|| WatchdogError::Swap(LoadError::CrateNotFound { name: String::from(old_crate_name) }))?;
+
+    let new_crate_ref = namespace.swap_crate(old_crate_name, new_crate_object_file, kernel_mmi_ref, verbose_log)
+        .map_err(WatchdogError::Swap)?;
+
+    let Some(health_check) = find_health_check(namespace, &new_crate_ref) else {
+        debug!("swap_crate_checked(): {:?} has no health check", new_crate_ref);
+        return Ok(new_crate_ref);
+    };
+
+    match run_health_check(health_check, timeout) {
+        Ok(()) => Ok(new_crate_ref),
+        Err(failure) => {
+            let new_crate_name = new_crate_ref.lock_as_ref().crate_name.clone();
+            error!("swap_crate_checked(): {:?} is unhealthy ({}), rolling back to {:?}", new_crate_name, failure, old_crate_name);
+            drop(new_crate_ref);
+            let rollback = namespace.swap_crate(&new_crate_name, &old_crate_object_file, kernel_mmi_ref, verbose_log);
+            Err(WatchdogError::Unhealthy { failure, rollback })
+        }
+    }
+}
+
+/// Returns the health-check function exported by the given crate, if any.
+fn find_health_check(namespace: &CrateNamespace, crate_ref: &StrongCrateRef) -> Option<HealthCheckFn> {
+    let krate = crate_ref.lock_as_ref();
+    let prefix = format!("{}{}{}", krate.crate_name_as_prefix(), HEALTH_CHECK_FUNCTION, SECTION_HASH_DELIMITER);
+    let section = namespace.get_symbol_starting_with(&prefix).upgrade()?;
+    let in_crate = section.parent_crate.upgrade().map_or(false, |parent| crate_ref.inner_ptr_eq(&parent));
+    if section.typ != SectionType::Text || !in_crate {
+        return None;
+    }
+    // SAFETY: a crate's health-check function must have the signature of `HealthCheckFn`.
+    // The function pointer remains valid while the health check runs,
+    // because the crate isn't removed from the namespace until it is rolled back afterwards.
+    unsafe { section.as_func::<HealthCheckFn>() }.ok().copied()
+}
+
+/// Runs the given health check in a new task, waiting up to `timeout` for it to return.
+fn run_health_check(health_check: HealthCheckFn, timeout: Duration) -> Result<(), HealthCheckFailure> {
+    let task = spawn::new_task_builder(|f: HealthCheckFn| f(), health_check)
+        .name(String::from("crate_health_check"))
+        .spawn()
+        .map_err(HealthCheckFailure::Task)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut timed_out = false;
+    while !task.has_exited() {
+        if Instant::now() >= deadline {
+            // The task may have exited in the meantime, in which case its exit value is used below.
+            timed_out = task.kill(KillReason::Requested).is_ok();
+            break;
+        }
+        let _ = sleep::sleep(POLL_INTERVAL);
+    }
+    // A killed task continues running until the end of its current timeslice, possibly on another CPU,
+    // so wait until it's no longer running before the caller can roll back the swap.
+    while task.is_running() {
+        let _ = sleep::sleep(POLL_INTERVAL);
+    }
+
+    let exit_value = task.join().map_err(HealthCheckFailure::Task)?;
+    if timed_out {
+        return Err(HealthCheckFailure::TimedOut);
+    }
+    match exit_value {
+        ExitValue::Completed(value) => match value.downcast_ref::<Result<(), &'static str>>() {
+            Some(Ok(())) => Ok(()),
+            Some(Err(reason)) => Err(HealthCheckFailure::Failed(String::from(*reason))),
+            None => Err(HealthCheckFailure::Task("health check returned an unexpected type")),
+        },
+        ExitValue::Killed(reason) => Err(HealthCheckFailure::Killed(reason)),
+    }
+}
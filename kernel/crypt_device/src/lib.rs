@@ -0,0 +1,325 @@
+//! A transparent encryption layer for storage devices, similar to Linux's dm-crypt.
+//!
+//! A [`CryptDevice`] wraps an underlying [`StorageDevice`] and is itself a `StorageDevice`,
+//! so any filesystem or other consumer of block devices can use it as-is.
+//! Every block written through it is encrypted with AES-256 in XTS mode,
+//! using the block's index as the tweak, and every block read through it is decrypted.
+//! All cryptographic primitives come from the [`crypto`] crate.
+//!
+//! The data is encrypted with a random master key, which is chosen when the device is formatted.
+//! The first block of the underlying device holds a small plaintext header containing
+//! the master key encrypted with a key derived from a passphrase via PBKDF2-HMAC-SHA256,
+//! the salt and iteration count used for that derivation, and a hash of the master key
+//! used to detect an incorrect passphrase.
+//! Thus, the passphrase can be changed via [`CryptDevice::change_passphrase()`]
+//! without re-encrypting the device's contents.
+//! The encrypted contents start at the second block, so a `CryptDevice` is one block
+//! smaller than its underlying device.
+//!
+//! Use [`CryptDevice::format()`] to initialize a device for encryption (destroying its contents)
+//! and [`CryptDevice::open()`] to access a device that was formatted before.
+//! Opened devices can be registered by name via [`add_mapping()`] so that other components can find them.
+//!
+//! All keys are wiped from memory once they are no longer needed,
+//! and the master key of an opened device is wiped once the device is dropped.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    string::String,
+    sync::Arc,
+    vec::Vec,
+    vec,
+};
+use crypto::{Aes256Xts, SHA256_LEN, Zeroizing};
+use io::{BlockIo, BlockReader, BlockWriter, IoError, KnownLength};
+use log::{debug, info};
+use spin::Mutex;
+use storage_device::{StorageDevice, StorageDeviceRef};
+
+/// The magic bytes at the start of the header of a formatted device.
+const MAGIC: &[u8; 8] = b"THSCRYPT";
+/// The version of the on-disk header format.
+const VERSION: u32 = 1;
+/// The number of PBKDF2 iterations used when formatting a new device or changing its passphrase.
+pub const DEFAULT_KDF_ITERATIONS: u32 = 100_000;
+/// The number of blocks at the start of the underlying device reserved for the header.
+const HEADER_BLOCKS: usize = 1;
+
+const SALT_LEN: usize = 32;
+/// The length of the master key and of the key derived from the passphrase.
+const KEY_LEN: usize = Aes256Xts::KEY_LEN;
+
+type Key = Zeroizing<[u8; KEY_LEN]>;
+
+/// The plaintext header at the start of a formatted device.
+struct Header {
+    kdf_iterations: u32,
+    salt: [u8; SALT_LEN],
+    /// The master key, encrypted with the key derived from the passphrase.
+    wrapped_key: [u8; KEY_LEN],
+    /// A hash of the master key, used to check whether a passphrase is correct.
+    key_check: [u8; SHA256_LEN],
+}
+
+impl Header {
+    const SIZE: usize = 8 + 4 + 4 + SALT_LEN + KEY_LEN + SHA256_LEN;
+
+    /// Creates a header that stores the given `master_key`, protected by `passphrase`.
+    fn new(passphrase: &[u8], master_key: &Key) -> Header {
+        let mut salt = [0u8; SALT_LEN];
+        random::fill_bytes(&mut salt);
+        let mut wrapped_key = **master_key;
+        wrapping_cipher(passphrase, &salt, DEFAULT_KDF_ITERATIONS).encrypt_area(&mut wrapped_key, KEY_LEN, 0);
+        Header {
+            kdf_iterations: DEFAULT_KDF_ITERATIONS,
+            salt,
+            wrapped_key,
+            key_check: key_check(master_key),
+        }
+    }
+
+    /// Returns the master key stored in this header, if `passphrase` is correct.
+    fn unwrap_key(&self, passphrase: &[u8]) -> Result<Key, &'static str> {
+        let mut master_key = Zeroizing::new(self.wrapped_key);
+        wrapping_cipher(passphrase, &self.salt, self.kdf_iterations).decrypt_area(&mut *master_key, KEY_LEN, 0);
+        if key_check(&master_key) != self.key_check {
+            return Err("crypt_device: incorrect passphrase");
+        }
+        Ok(master_key)
+    }
+
+    fn to_bytes(&self, block: &mut [u8]) {
+        block.fill(0);
+        block[0..8].copy_from_slice(MAGIC);
+        block[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        block[12..16].copy_from_slice(&self.kdf_iterations.to_le_bytes());
+        block[16..48].copy_from_slice(&self.salt);
+        block[48..112].copy_from_slice(&self.wrapped_key);
+        block[112..144].copy_from_slice(&self.key_check);
+    }
+
+    fn from_bytes(block: &[u8]) -> Result<Header, &'static str> {
+        if block.len() < Self::SIZE || &block[0..8] != MAGIC {
+            return Err("crypt_device: the device isn't formatted for encryption");
+        }
+        let version = u32::from_le_bytes(block[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err("crypt_device: unsupported header version");
+        }
+        Ok(Header {
+            kdf_iterations: u32::from_le_bytes(block[12..16].try_into().unwrap()),
+            salt: block[16..48].try_into().unwrap(),
+            wrapped_key: block[48..112].try_into().unwrap(),
+            key_check: block[112..144].try_into().unwrap(),
+        })
+    }
+
+    /// Reads the header from the given device, returning it along with the device's
+    /// block size and its size in blocks.
+    fn read(device: &StorageDeviceRef) -> Result<(Header, usize, usize), &'static str> {
+        let mut dev = device.lock();
+        let block_size = dev.block_size();
+        let mut header_block = vec![0u8; block_size * HEADER_BLOCKS];
+        dev.read_blocks(&mut header_block, 0)?;
+        Ok((Header::from_bytes(&header_block)?, block_size, dev.size_in_blocks()))
+    }
+
+    /// Writes this header to the given device, returning the device's
+    /// block size and its size in blocks.
+    ///
+    /// Any caches of the device are invalidated, as the header is written to it directly.
+    fn write(&self, device: &StorageDeviceRef) -> Result<(usize, usize), &'static str> {
+        let result = {
+            let mut dev = device.lock();
+            let block_size = dev.block_size();
+            if block_size < Header::SIZE {
+                return Err("crypt_device: the device's block size is too small for the header");
+            }
+            let mut header_block = vec![0u8; block_size * HEADER_BLOCKS];
+            self.to_bytes(&mut header_block);
+            dev.write_blocks(&header_block, 0)?;
+            dev.flush()?;
+            (block_size, dev.size_in_blocks())
+        };
+        block_cache::invalidate_device(device, 0 .. HEADER_BLOCKS);
+        Ok(result)
+    }
+}
+
+/// Returns the cipher that encrypts the master key,
+/// whose key is derived from the given passphrase and salt.
+fn wrapping_cipher(passphrase: &[u8], salt: &[u8], kdf_iterations: u32) -> Aes256Xts {
+    let mut key: Key = Zeroizing::new([0u8; KEY_LEN]);
+    crypto::pbkdf2_hmac_sha256(passphrase, salt, kdf_iterations, &mut *key);
+    Aes256Xts::new(&key)
+}
+
+/// Returns the hash used to check whether the master key was decrypted correctly.
+fn key_check(master_key: &Key) -> [u8; SHA256_LEN] {
+    crypto::sha256(&[b"crypt_device key check", &master_key[..]])
+}
+
+/// A storage device that transparently encrypts all data written to an underlying storage device.
+///
+/// See the [crate-level documentation](crate) for more.
+pub struct CryptDevice {
+    device: StorageDeviceRef,
+    cipher: Aes256Xts,
+    block_size: usize,
+    /// The number of blocks available for encrypted data.
+    size_in_blocks: usize,
+}
+
+impl CryptDevice {
+    /// Formats the given device for encryption with a new random master key protected by `passphrase`,
+    /// and returns the opened encrypted device.
+    ///
+    /// This overwrites the first block of the device with the encryption header,
+    /// and makes all existing data on the device unreadable.
+    ///
+    /// Returns an error without modifying the device if no hardware random number generator
+    /// is available, as the master key would then be predictable.
+    pub fn format(device: StorageDeviceRef, passphrase: &[u8]) -> Result<CryptDevice, &'static str> {
+        if !random::is_hardware_seeded() {
+            return Err("crypt_device: refusing to format without a hardware random number generator (RDSEED or RDRAND)");
+        }
+        let mut master_key: Key = Zeroizing::new([0u8; KEY_LEN]);
+        random::fill_bytes(&mut *master_key);
+        let (block_size, size_in_blocks) = Header::new(passphrase, &master_key).write(&device)?;
+        info!("crypt_device: formatted device with {} blocks for encryption", size_in_blocks);
+        Self::new(device, Aes256Xts::new(&master_key), block_size, size_in_blocks)
+    }
+
+    /// Opens the given device, which must have been formatted via [`CryptDevice::format()`],
+    /// using the given `passphrase`.
+    ///
+    /// Returns an error if the passphrase is incorrect.
+    pub fn open(device: StorageDeviceRef, passphrase: &[u8]) -> Result<CryptDevice, &'static str> {
+        let (header, block_size, size_in_blocks) = Header::read(&device)?;
+        let master_key = header.unwrap_key(passphrase)?;
+        debug!("crypt_device: opened encrypted device with {} blocks", size_in_blocks);
+        Self::new(device, Aes256Xts::new(&master_key), block_size, size_in_blocks)
+    }
+
+    /// Changes the passphrase of the given device, which must have been formatted via [`CryptDevice::format()`],
+    /// from `old_passphrase` to `new_passphrase`.
+    ///
+    /// Only the header is rewritten, as the contents are encrypted with a master key that doesn't change.
+    /// Thus, this can be done while the device is open.
+    ///
+    /// Returns an error if `old_passphrase` is incorrect.
+    pub fn change_passphrase(
+        device: &StorageDeviceRef,
+        old_passphrase: &[u8],
+        new_passphrase: &[u8],
+    ) -> Result<(), &'static str> {
+        let (header, ..) = Header::read(device)?;
+        let master_key = header.unwrap_key(old_passphrase)?;
+        Header::new(new_passphrase, &master_key).write(device)?;
+        info!("crypt_device: changed the passphrase of an encrypted device");
+        Ok(())
+    }
+
+    fn new(
+        device: StorageDeviceRef,
+        cipher: Aes256Xts,
+        block_size: usize,
+        size_in_blocks: usize,
+    ) -> Result<CryptDevice, &'static str> {
+        let size_in_blocks = size_in_blocks.checked_sub(HEADER_BLOCKS)
+            .ok_or("crypt_device: the device is too small")?;
+        Ok(CryptDevice { device, cipher, block_size, size_in_blocks })
+    }
+
+    /// Returns the underlying device that holds the encrypted data.
+    pub fn underlying_device(&self) -> &StorageDeviceRef {
+        &self.device
+    }
+
+    /// Checks that `buffer_len` bytes starting at `block_offset` are within this device,
+    /// and returns the number of blocks they span.
+    fn check_bounds(&self, buffer_len: usize, block_offset: usize) -> Result<usize, IoError> {
+        if buffer_len % self.block_size != 0 {
+            return Err(IoError::InvalidInput);
+        }
+        let num_blocks = buffer_len / self.block_size;
+        if block_offset.checked_add(num_blocks).map_or(true, |end| end > self.size_in_blocks) {
+            return Err(IoError::InvalidInput);
+        }
+        Ok(num_blocks)
+    }
+}
+
+impl StorageDevice for CryptDevice {
+    fn size_in_blocks(&self) -> usize {
+        self.size_in_blocks
+    }
+}
+impl BlockIo for CryptDevice {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+}
+impl KnownLength for CryptDevice {
+    fn len(&self) -> usize {
+        self.block_size * self.size_in_blocks
+    }
+}
+impl BlockReader for CryptDevice {
+    fn read_blocks(&mut self, buffer: &mut [u8], block_offset: usize) -> Result<usize, IoError> {
+        self.check_bounds(buffer.len(), block_offset)?;
+        let blocks_read = self.device.lock().read_blocks(buffer, block_offset + HEADER_BLOCKS)?;
+        let bytes_read = blocks_read * self.block_size;
+        self.cipher.decrypt_area(&mut buffer[..bytes_read], self.block_size, block_offset as u128);
+        Ok(blocks_read)
+    }
+}
+impl BlockWriter for CryptDevice {
+    fn write_blocks(&mut self, buffer: &[u8], block_offset: usize) -> Result<usize, IoError> {
+        self.check_bounds(buffer.len(), block_offset)?;
+        let mut ciphertext = Vec::from(buffer);
+        self.cipher.encrypt_area(&mut ciphertext, self.block_size, block_offset as u128);
+        self.device.lock().write_blocks(&ciphertext, block_offset + HEADER_BLOCKS)
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.device.lock().flush()
+    }
+}
+
+/// The opened encrypted devices, by name.
+static MAPPINGS: Mutex<Vec<(String, StorageDeviceRef)>> = Mutex::new(Vec::new());
+
+/// Registers the given opened encrypted device under the given `name`,
+/// and returns a reference to it that can be used like any other storage device.
+pub fn add_mapping(name: &str, device: CryptDevice) -> Result<StorageDeviceRef, &'static str> {
+    let mut mappings = MAPPINGS.lock();
+    if mappings.iter().any(|(n, _)| n == name) {
+        return Err("crypt_device: an encrypted device with that name is already open");
+    }
+    let device_ref: StorageDeviceRef = Arc::new(Mutex::new(device));
+    mappings.push((String::from(name), device_ref.clone()));
+    Ok(device_ref)
+}
+
+/// Returns the opened encrypted device with the given `name`.
+pub fn mapping(name: &str) -> Option<StorageDeviceRef> {
+    MAPPINGS.lock().iter().find(|(n, _)| n == name).map(|(_, dev)| dev.clone())
+}
+
+/// Unregisters the opened encrypted device with the given `name`.
+///
+/// The device's key is dropped once all other references to the device have been dropped.
+pub fn remove_mapping(name: &str) -> Option<StorageDeviceRef> {
+    let mut mappings = MAPPINGS.lock();
+    let index = mappings.iter().position(|(n, _)| n == name)?;
+    Some(mappings.remove(index).1)
+}
+
+/// Returns the names of all opened encrypted devices.
+pub fn mapping_names() -> Vec<String> {
+    MAPPINGS.lock().iter().map(|(n, _)| n.clone()).collect()
+}
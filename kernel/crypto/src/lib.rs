@@ -0,0 +1,70 @@
+//! The cryptographic primitives used throughout the kernel.
+//!
+//! Other crates should use these rather than depending on cryptography crates directly,
+//! such that the implementations in use are vetted and updated in a single place.
+//!
+//! Key material is never left behind in memory: [`Aes256Xts`] wipes its key schedules when dropped,
+//! and intermediate keys should be held in [`Zeroizing`] buffers.
+
+#![no_std]
+
+use aes::{
+    Aes256,
+    cipher::{KeyInit, generic_array::GenericArray},
+};
+use sha2::{Digest, Sha256};
+use xts_mode::{Xts128, get_tweak_default};
+
+pub use zeroize::{Zeroize, Zeroizing};
+
+/// The length in bytes of a SHA-256 hash.
+pub const SHA256_LEN: usize = 32;
+
+/// Returns the SHA-256 hash of the concatenation of the given `parts`.
+pub fn sha256(parts: &[&[u8]]) -> [u8; SHA256_LEN] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// Derives a key from the given `passphrase` and `salt` via PBKDF2-HMAC-SHA256,
+/// filling all of `key`.
+pub fn pbkdf2_hmac_sha256(passphrase: &[u8], salt: &[u8], iterations: u32, key: &mut [u8]) {
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase, salt, iterations, key);
+}
+
+/// AES-256 in XTS mode, as used for encrypting storage.
+///
+/// Data is processed in units of sectors, each of which is encrypted with its index as the tweak.
+pub struct Aes256Xts {
+    cipher: Xts128<Aes256>,
+}
+
+impl Aes256Xts {
+    /// The length in bytes of the key: one AES-256 key for the data and one for the tweak.
+    pub const KEY_LEN: usize = 64;
+
+    /// Creates a new cipher from the given key.
+    pub fn new(key: &[u8; Self::KEY_LEN]) -> Aes256Xts {
+        Aes256Xts {
+            cipher: Xts128::new(
+                Aes256::new(GenericArray::from_slice(&key[..32])),
+                Aes256::new(GenericArray::from_slice(&key[32..])),
+            ),
+        }
+    }
+
+    /// Encrypts `data` in place, which consists of consecutive sectors of `sector_size` bytes
+    /// starting at the sector with index `first_sector`.
+    pub fn encrypt_area(&self, data: &mut [u8], sector_size: usize, first_sector: u128) {
+        self.cipher.encrypt_area(data, sector_size, first_sector, get_tweak_default);
+    }
+
+    /// Decrypts `data` in place, which consists of consecutive sectors of `sector_size` bytes
+    /// starting at the sector with index `first_sector`.
+    pub fn decrypt_area(&self, data: &mut [u8], sector_size: usize, first_sector: u128) {
+        self.cipher.decrypt_area(data, sector_size, first_sector, get_tweak_default);
+    }
+}
@@ -0,0 +1,196 @@
+//! Resolving virtual addresses to source code locations using a crate's `.debug_line` section,
+//! similar to the `addr2line` tool.
+//!
+//! A crate's debug sections are not loaded along with the crate itself;
+//! instead, they are loaded on demand from the crate's `debug_symbols_file`
+//! the first time an address within that crate is resolved.
+//!
+//! Loaded debug sections hold a reference to their crate, which prevents it from being dropped,
+//! so they are only kept for the lifetime of an [`Addr2Line`] resolver.
+//! This makes it well-suited for resolving all of the addresses in a single backtrace.
+
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+use core::{fmt, ops::Deref};
+use gimli::{EndianSlice, NativeEndian, Reader};
+use memory::VirtualAddress;
+use mod_mgmt::{CrateNamespace, StrRef};
+use crate::{DebugSections, DebugSymbols};
+
+/// A location in a source code file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// The path of the source file, including its directory if known.
+    pub file: String,
+    /// The line number, starting at 1, or `None` if unknown.
+    pub line: Option<u64>,
+    /// The column number, starting at 1, or `None` if unknown.
+    pub column: Option<u64>,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Resolves virtual addresses to source code locations,
+/// loading the debug sections of each crate as needed.
+///
+/// All debug sections loaded by this resolver are unloaded when it is dropped.
+#[derive(Default)]
+pub struct Addr2Line {
+    /// The debug symbols of each crate that an address was resolved in, by crate name.
+    /// The debug symbols are `None` if they couldn't be loaded, so that loading isn't retried.
+    crates: Vec<(StrRef, Option<DebugSymbols>)>,
+}
+
+impl Addr2Line {
+    /// Creates a new resolver with no debug sections loaded.
+    pub fn new() -> Addr2Line {
+        Addr2Line::default()
+    }
+
+    /// Returns the source code location of the instruction at the given `address`,
+    /// which is in a crate that is loaded into the given `namespace` (or its recursive namespaces).
+    ///
+    /// Returns `None` if the address isn't in a loaded crate,
+    /// if that crate has no debug symbols, or if they don't cover the address.
+    pub fn lookup(&mut self, namespace: &CrateNamespace, address: VirtualAddress) -> Option<SourceLocation> {
+        let crate_ref = namespace.get_crate_containing_address(address, false)?;
+        let (crate_name, debug_symbols_file) = {
+            let krate = crate_ref.lock_as_ref();
+            (krate.crate_name.clone(), krate.debug_symbols_file.clone())
+        };
+
+        let index = match self.crates.iter().position(|(name, _)| *name == crate_name) {
+            Some(index) => index,
+            None => {
+                let mut debug_symbols = DebugSymbols::Unloaded(debug_symbols_file);
+                let loaded = match debug_symbols.load(&crate_ref, namespace).map(|_| ()) {
+                    Ok(()) => Some(debug_symbols),
+                    Err(e) => {
+                        debug!("addr2line: couldn't load debug symbols for crate {:?}: {}", crate_name, e);
+                        None
+                    }
+                };
+                self.crates.push((crate_name, loaded));
+                self.crates.len() - 1
+            }
+        };
+
+        let debug_sections = self.crates[index].1.as_ref()?.get_loaded()?;
+        match debug_sections.find_location(address) {
+            Ok(location) => location,
+            Err(e) => {
+                warn!("addr2line: error parsing debug sections for address {:#X}: {:?}", address, e);
+                None
+            }
+        }
+    }
+}
+
+/// Returns the source code location of the instruction at the given `address`,
+/// which is in a crate that is loaded into the given `namespace` (or its recursive namespaces).
+///
+/// This loads the containing crate's debug sections and unloads them afterwards;
+/// use an [`Addr2Line`] resolver to look up multiple addresses.
+pub fn addr2line(namespace: &CrateNamespace, address: VirtualAddress) -> Option<SourceLocation> {
+    Addr2Line::new().lookup(namespace, address)
+}
+
+impl DebugSections {
+    /// Returns the source code location of the instruction at the given `address`,
+    /// according to the line number programs in the `.debug_line` section.
+    ///
+    /// Returns `Ok(None)` if no line number program covers the given `address`.
+    pub fn find_location(&self, address: VirtualAddress) -> gimli::Result<Option<SourceLocation>> {
+        let address = address.value() as u64;
+        let dwarf = self.dwarf()?;
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let Some(program) = unit.line_program.clone() else { continue };
+
+            // Each row describes the instructions from its address up to the next row's address,
+            // unless the previous row was the end of a sequence.
+            let mut rows = program.rows();
+            let mut previous: Option<(u64, Option<u64>, Option<u64>, u64)> = None;
+            while let Some((line_header, row)) = rows.next_row()? {
+                if let Some((prev_address, line, column, file_index)) = previous {
+                    if prev_address <= address && address < row.address() {
+                        let file = line_header.file(file_index)
+                            .map(|file| file_path(&dwarf, &unit, line_header, file))
+                            .transpose()?
+                            .unwrap_or_else(|| String::from("??"));
+                        return Ok(Some(SourceLocation { file, line, column }));
+                    }
+                }
+                previous = (!row.end_sequence()).then(|| {
+                    let column = match row.column() {
+                        gimli::ColumnType::LeftEdge => None,
+                        gimli::ColumnType::Column(c) => Some(u64::from(c)),
+                    };
+                    (row.address(), row.line().map(u64::from), column, row.file_index())
+                });
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns all of these debug sections as a `gimli::Dwarf` instance.
+    pub(crate) fn dwarf(&self) -> gimli::Result<gimli::Dwarf<EndianSlice<NativeEndian>>> {
+        gimli::Dwarf::load(|section_id| -> gimli::Result<_> {
+            let slice: &[u8] = match section_id {
+                gimli::SectionId::DebugInfo =>     self.debug_info.0.deref(),
+                gimli::SectionId::DebugLine =>     self.debug_line.0.deref(),
+                gimli::SectionId::DebugLoc =>      self.debug_loc.as_ref().map_or(&[][..], |loc| loc.0.deref()),
+                gimli::SectionId::DebugPubNames => self.debug_pubnames.0.deref(),
+                gimli::SectionId::DebugPubTypes => self.debug_pubtypes.0.deref(),
+                gimli::SectionId::DebugAbbrev =>   self.debug_abbrev.0.deref(),
+                gimli::SectionId::DebugRanges =>   self.debug_ranges.0.deref(),
+                gimli::SectionId::DebugStr =>      self.debug_str.0.deref(),
+                // Other sections aren't loaded, and aren't needed for resolving line numbers.
+                _ => &[],
+            };
+            Ok(EndianSlice::new(slice, NativeEndian))
+        })
+    }
+}
+
+/// Returns the full path of the given `file` in a line number program,
+/// which is prefixed by its directory if it isn't an absolute path.
+fn file_path<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    header: &gimli::LineProgramHeader<R>,
+    file: &gimli::FileEntry<R>,
+) -> gimli::Result<String> {
+    let name = String::from(dwarf.attr_string(unit, file.path_name())?.to_string()?);
+    if name.starts_with('/') {
+        return Ok(name);
+    }
+    match file.directory(header) {
+        Some(directory) => {
+            let directory = dwarf.attr_string(unit, directory)?;
+            let directory = directory.to_string()?;
+            if directory.is_empty() {
+                return Ok(name);
+            }
+            let mut path = String::from(directory.trim_end_matches('/'));
+            path.push('/');
+            path.push_str(&name);
+            Ok(path)
+        }
+        None => Ok(name),
+    }
+}
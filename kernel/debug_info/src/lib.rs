@@ -2,6 +2,11 @@
 //! 
 //! This is a good intro to the DWARF format:
 //! <http://www.dwarfstd.org/doc/Debugging%20using%20DWARF.pdf>
+//!
+//! Debug sections are loaded lazily from a crate's `debug_symbols_file` via [`DebugSymbols::load()`].
+//! The [`Addr2Line`] resolver uses them to map instruction addresses to source file and line numbers,
+//! e.g., for printing backtraces, and [`DebugSections::find_function_signature()`]
+//! looks up the parameter and return types of a function.
 
 #![no_std]
 #![feature(int_roundings)]
@@ -58,6 +63,11 @@ use by_address::ByAddress;
 use crate_metadata::{StrongCrateRef, StrongSectionRef, RelocationEntry, write_relocation};
 use mod_mgmt::{CrateNamespace, find_symbol_table};
 
+mod addr2line;
+pub use addr2line::{Addr2Line, SourceLocation, addr2line};
+mod signature;
+pub use signature::FunctionSignature;
+
 
 /// The set of debug sections that we need to use from a crate object file.
 /// 
@@ -730,9 +740,11 @@ impl DebugSymbols {
                 
                 let mut source_and_target_in_same_crate = false;
 
-                // We first check if the source section is another debug section, then check if its a local section from the given `loaded_crate`.
+                // We first check if the source section is another debug section, then check if its a local section from the given `loaded_crate`,
+                // which may be a lazy section that has no metadata (and thus can't be recorded as a dependency).
                 let (source_sec_vaddr, source_sec_dep) = match shndx_map.get(&source_sec_shndx).map(|s| (s.virt_addr, None))
                     .or_else(|| loaded_crate.lock_as_ref().sections.get(&source_sec_shndx).map(|sec| (sec.virt_addr, Some(sec.clone()))))
+                    .or_else(|| loaded_crate.lock_as_ref().lazy_sections.get(&source_sec_shndx).map(|lazy| (lazy.virt_addr, None)))
                 {
                     // We found the source section in the local debug sections or the given loaded crate. 
                     Some(found) => {
@@ -0,0 +1,74 @@
+//! Looking up the signature of a function, i.e., the types of its parameters and return value,
+//! from a crate's `.debug_info` section.
+//!
+//! Rust's symbol names don't encode function signatures, so this is the only way
+//! to check the signature of a function that was found by its symbol name.
+
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+use gimli::Reader;
+use memory::VirtualAddress;
+use crate::DebugSections;
+
+/// The signature of a function, as described by its DWARF subprogram entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionSignature {
+    /// The names of the types of each of the function's parameters, in order.
+    pub parameters: Vec<String>,
+    /// The name of the function's return type, or `None` if it returns `()`.
+    pub return_type: Option<String>,
+}
+
+impl DebugSections {
+    /// Returns the signature of the function that starts at the given `address`.
+    ///
+    /// Returns `Ok(None)` if no subprogram entry starts at the given `address`.
+    pub fn find_function_signature(&self, address: VirtualAddress) -> gimli::Result<Option<FunctionSignature>> {
+        let address = address.value() as u64;
+        let dwarf = self.dwarf()?;
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let mut entries = unit.entries();
+            while let Some((_, entry)) = entries.next_dfs()? {
+                if entry.tag() != gimli::DW_TAG_subprogram
+                    || entry.attr_value(gimli::DW_AT_low_pc)? != Some(gimli::AttributeValue::Addr(address))
+                {
+                    continue;
+                }
+
+                let mut tree = unit.entries_tree(Some(entry.offset()))?;
+                let root = tree.root()?;
+                let return_type = type_name(&dwarf, &unit, root.entry())?;
+                let mut parameters = Vec::new();
+                let mut children = root.children();
+                while let Some(child) = children.next()? {
+                    if child.entry().tag() == gimli::DW_TAG_formal_parameter {
+                        parameters.push(type_name(&dwarf, &unit, child.entry())?.unwrap_or_else(|| String::from("??")));
+                    }
+                }
+                return Ok(Some(FunctionSignature { parameters, return_type }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Returns the name of the type referred to by the given `entry`'s `DW_AT_type` attribute,
+/// or `None` if it has no type or the type is unnamed.
+fn type_name<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    entry: &gimli::DebuggingInformationEntry<'_, '_, R>,
+) -> gimli::Result<Option<String>> {
+    let offset = match entry.attr_value(gimli::DW_AT_type)? {
+        Some(gimli::AttributeValue::UnitRef(offset)) => offset,
+        _ => return Ok(None),
+    };
+    match unit.entry(offset)?.attr_value(gimli::DW_AT_name)? {
+        Some(name) => Ok(Some(String::from(dwarf.attr_string(unit, name)?.to_string()?))),
+        None => Ok(None),
+    }
+}
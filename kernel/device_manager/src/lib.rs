@@ -9,6 +9,7 @@ use log::*;
 use {
     mpmc::Queue,
     event_types::Event,
+    input_router::InputDeviceKind,
     memory::MemoryManagementInfo,
     alloc::vec::Vec,
     io::{ByteReaderWriterWrapper, LockableIo, ReaderWriter},
@@ -39,6 +40,8 @@ pub fn early_init(
 /// * At least one [`serial_port`] (e.g., `COM1`) with full interrupt support,
 /// * The fully-featured system [`logger`],
 /// * The legacy PS2 controller and any connected devices: [`keyboard`] and [`mouse`],
+///   which are registered with the [`input_router`],
+/// * [`virtio_input`] pointing devices, which share the mouse's event queue,
 /// * All other devices discovered on the [`pci`] bus.
 pub fn init(
     #[cfg(target_arch = "x86_64")]
@@ -77,10 +80,12 @@ pub fn init(
     #[cfg(target_arch = "x86_64")] {
         let ps2_controller = ps2::init()?;
         if let Some(kb) = ps2_controller.keyboard_ref() {
-            keyboard::init(kb, key_producer)?;
+            let input = input_router::register_device("ps2_keyboard", InputDeviceKind::Keyboard, key_producer);
+            keyboard::init(kb, input)?;
         }
         if let Some(m) = ps2_controller.mouse_ref() {
-            mouse::init(m, mouse_producer)?;
+            let input = input_router::register_device("ps2_mouse", InputDeviceKind::Mouse, mouse_producer.clone());
+            mouse::init(m, input)?;
         }
     }
 
@@ -89,6 +94,17 @@ pub fn init(
         debug!("Found PCI device: {:X?}", dev);
     }
 
+    // Now that all PCI devices are known, enable DMA remapping if there's an IOMMU,
+    // such that drivers can isolate their devices via `PciDevice::isolate_dma()`.
+    #[cfg(target_arch = "x86_64")]
+    if iommu::iommu_present() {
+        let devices = pci::pci_device_iter()?
+            .map(|dev| (dev.location.bus(), dev.location.slot(), dev.location.function()));
+        if let Err(e) = iommu::enable_dma_remapping(devices) {
+            warn!("Couldn't enable IOMMU DMA remapping: {}", e);
+        }
+    }
+
     // store all the initialized ixgbe NICs here to be added to the network interface list
     // No NIC support on aarch64 at the moment
     #[cfg(target_arch = "x86_64")]
@@ -119,6 +135,28 @@ pub fn init(
             }
         }
 
+        // If this is a virtio memory balloon, initialize it such that the host can reclaim guest memory.
+        // No virtio support on aarch64 at the moment
+        #[cfg(target_arch = "x86_64")]
+        if dev.vendor_id == virtio::VIRTIO_VENDOR_ID && dev.device_id == virtio_balloon::BALLOON_DEV {
+            info!("virtio-balloon PCI device found at: {:?}", dev.location);
+            if let Err(e) = virtio_balloon::init(dev) {
+                error!("Failed to initialize virtio-balloon device: {}", e);
+            }
+            continue;
+        }
+
+        // If this is a virtio-input device, e.g., a tablet, deliver its events along with those of the PS/2 mouse.
+        // No virtio support on aarch64 at the moment
+        #[cfg(target_arch = "x86_64")]
+        if dev.vendor_id == virtio::VIRTIO_VENDOR_ID && dev.device_id == virtio_input::INPUT_DEV {
+            info!("virtio-input PCI device found at: {:?}", dev.location);
+            if let Err(e) = virtio_input::init(dev, mouse_producer.clone()) {
+                error!("Failed to initialize virtio-input device: {}", e);
+            }
+            continue;
+        }
+
         // If this is a network device, initialize it as such.
         // Look for networking controllers, specifically ethernet cards
         // No NIC support on aarch64 at the moment
@@ -132,6 +170,19 @@ pub fn init(
 
                 continue;
             }
+            if dev.vendor_id == virtio::VIRTIO_VENDOR_ID && virtio_net::NET_DEVS.contains(&dev.device_id) {
+                info!("virtio-net PCI device found at: {:?}", dev.location);
+                match virtio_net::VirtioNetNic::init(dev) {
+                    Ok(nic) => {
+                        let interface = net::register_device(nic);
+                        if let Err(e) = nic.lock().init_interrupts(interface) {
+                            error!("Failed to initialize virtio-net device interrupts: {}", e);
+                        }
+                    }
+                    Err(e) => error!("Failed to initialize virtio-net device: {}", e),
+                }
+                continue;
+            }
             if dev.vendor_id == ixgbe::INTEL_VEND && dev.device_id == ixgbe::INTEL_82599 {
                 info!("ixgbe PCI device found at: {:?}", dev.location);
                 
@@ -0,0 +1,191 @@
+//! Scaffolding shared by filesystems that are stored on a storage device, such as `fat32` and `ext2`.
+//!
+//! Such a filesystem accesses its volume through a [`Disk`], which caches the device's blocks
+//! in a [`BlockCache`], and is mounted by inserting its root directory into an existing directory
+//! of the virtual filesystem via [`mount()`].
+//!
+//! Its directories implement [`DiskDirectory`], from which the [`Directory`] trait is implemented
+//! via the functions of this crate: each file or directory within a directory is represented by
+//! a single node, which is created the first time it is accessed and then cached for as long
+//! as that directory exists.
+//! Because such a filesystem can only store its own files and directories, [`insert()`]
+//! copies the given node onto the volume rather than storing the node itself,
+//! recursively so in the case of a directory.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use block_cache::BlockCache;
+use core::ptr;
+use fs_node::{DirRef, Directory, FileOrDir, FileRef, FsNode};
+use io::{ByteReader, ByteReaderWriterWrapper, ByteWriter, KnownLength, LockableIo};
+use log::error;
+use spin::Mutex;
+use storage_device::{StorageDevice, StorageDeviceRef};
+
+/// The maximum number of blocks that a [`Disk`] caches, i.e., 4 MiB of 512-byte sectors.
+const CACHE_CAPACITY: usize = 8192;
+
+/// A storage device that is accessed at arbitrary byte offsets through a [`BlockCache`].
+///
+/// Modified blocks are written back to the device periodically by a flush task,
+/// and whenever the disk is flushed.
+pub struct Disk {
+    inner: ByteReaderWriterWrapper<LockableIo<'static, dyn StorageDevice + Send, Mutex<dyn StorageDevice + Send>, StorageDeviceRef>>,
+}
+
+impl Disk {
+    /// Wraps the given storage `device` in a new cache, and spawns the task that writes back its modified blocks.
+    pub fn new(device: StorageDeviceRef) -> Result<Disk, &'static str> {
+        let cache = BlockCache::new_ref(device, CACHE_CAPACITY);
+        block_cache::spawn_flush_task(&cache, block_cache::DEFAULT_FLUSH_INTERVAL)?;
+        let cache: StorageDeviceRef = cache;
+        Ok(Disk {
+            inner: ByteReaderWriterWrapper::from(
+                LockableIo::<dyn StorageDevice + Send, Mutex<_>, _>::from(cache)
+            ),
+        })
+    }
+
+    /// Reads exactly `buffer.len()` bytes from the disk at the given byte `offset`.
+    pub fn read_exact(&mut self, buffer: &mut [u8], offset: usize) -> Result<(), &'static str> {
+        match self.inner.read_at(buffer, offset)? {
+            n if n == buffer.len() => Ok(()),
+            _ => Err("short read from storage device"),
+        }
+    }
+
+    /// Writes all of `buffer` to the disk at the given byte `offset`.
+    pub fn write_all(&mut self, buffer: &[u8], offset: usize) -> Result<(), &'static str> {
+        match self.inner.write_at(buffer, offset)? {
+            n if n == buffer.len() => Ok(()),
+            _ => Err("short write to storage device"),
+        }
+    }
+
+    /// Writes back all modified blocks and flushes the underlying storage device.
+    pub fn flush(&mut self) -> Result<(), &'static str> {
+        ByteWriter::flush(&mut self.inner).map_err(Into::into)
+    }
+}
+
+/// Mounts the given `root` directory of a volume within the given `parent` directory.
+///
+/// Returns the `root` directory.
+pub fn mount<D: Directory + Send + 'static>(root: Arc<Mutex<D>>, parent: &DirRef) -> Result<Arc<Mutex<D>>, &'static str> {
+    root.lock().set_parent_dir(Arc::downgrade(parent));
+    parent.lock().insert(FileOrDir::Dir(root.clone()))?;
+    Ok(root)
+}
+
+/// A directory on a volume, from which the [`Directory`] trait can be implemented
+/// via [`get()`], [`insert()`], [`remove()`], and [`list()`].
+pub trait DiskDirectory {
+    /// Either a file or a directory on the same volume as this directory.
+    type Node: Clone + Into<FileOrDir>;
+
+    /// The name of the filesystem, which prefixes the errors that are logged.
+    const FS_NAME: &'static str;
+
+    /// Returns the file or directory with the given `name` in this directory, if any.
+    fn lookup(&self, name: &str) -> Result<Option<Self::Node>, &'static str>;
+
+    /// Deletes the file or directory called `name` from this directory.
+    fn unlink(&self, name: &str) -> Result<Self::Node, &'static str>;
+
+    /// Creates a new empty file called `name` in this directory.
+    fn new_file(&self, name: &str) -> Result<FileRef, &'static str>;
+
+    /// Creates a new empty directory called `name` in this directory.
+    fn new_dir(&self, name: &str) -> Result<DirRef, &'static str>;
+
+    /// Returns the names of all files and directories in this directory.
+    fn entry_names(&self) -> Result<Vec<String>, &'static str>;
+
+    /// Returns the number of bytes copied at a time when a file is copied onto the volume.
+    fn copy_chunk_size(&self) -> usize;
+}
+
+/// Implements [`Directory::get()`] for the given directory.
+pub fn get<D: DiskDirectory>(dir: &D, name: &str) -> Option<FileOrDir> {
+    dir.lookup(name)
+        .map_err(|e| error!("{}: failed to look up {:?}: {}", D::FS_NAME, name, e))
+        .ok()
+        .flatten()
+        .map(Into::into)
+}
+
+/// Implements [`Directory::insert()`] for the given directory,
+/// which copies the given `node` onto the volume unless it is already there.
+pub fn insert<D: DiskDirectory>(dir: &D, node: FileOrDir) -> Result<Option<FileOrDir>, &'static str> {
+    let name = node.get_name();
+    let old_node: Option<FileOrDir> = dir.lookup(&name)?.map(Into::into);
+    if let Some(old_node) = &old_node {
+        if is_same_node(&node, old_node) {
+            return Ok(None);
+        }
+        dir.unlink(&name)?;
+    }
+    copy_in(dir, &node, &name)?;
+    Ok(old_node)
+}
+
+/// Implements [`Directory::remove()`] for the given directory.
+pub fn remove<D: DiskDirectory>(dir: &D, node: &FileOrDir) -> Option<FileOrDir> {
+    let name = node.get_name();
+    dir.unlink(&name)
+        .map_err(|e| error!("{}: failed to remove {:?}: {}", D::FS_NAME, name, e))
+        .ok()
+        .map(Into::into)
+}
+
+/// Implements [`Directory::list()`] for the given directory.
+pub fn list<D: DiskDirectory>(dir: &D) -> Vec<String> {
+    dir.entry_names().unwrap_or_else(|e| {
+        error!("{}: failed to read directory: {}", D::FS_NAME, e);
+        Vec::new()
+    })
+}
+
+/// Copies the given `node`, which is not on the volume, into the given directory as `name`.
+fn copy_in<D: DiskDirectory>(dir: &D, node: &FileOrDir, name: &str) -> Result<(), &'static str> {
+    match node {
+        FileOrDir::File(source) => {
+            let mut buffer = vec![0u8; dir.copy_chunk_size()];
+            let file = dir.new_file(name)?;
+            let mut source = source.lock();
+            let mut file = file.lock();
+            let mut offset = 0;
+            while offset < source.len() {
+                let len = source.read_at(&mut buffer, offset)?;
+                if len == 0 {
+                    break;
+                }
+                file.write_at(&buffer[..len], offset)?;
+                offset += len;
+            }
+        }
+        FileOrDir::Dir(source) => {
+            let new_dir = dir.new_dir(name)?;
+            let source = source.lock();
+            let mut new_dir = new_dir.lock();
+            for child_name in source.list() {
+                if let Some(child) = source.get(&child_name) {
+                    new_dir.insert(child)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` if `a` and `b` are the same node.
+fn is_same_node(a: &FileOrDir, b: &FileOrDir) -> bool {
+    match (a, b) {
+        (FileOrDir::File(a), FileOrDir::File(b)) => ptr::eq(Arc::as_ptr(a) as *const u8, Arc::as_ptr(b) as *const u8),
+        (FileOrDir::Dir(a), FileOrDir::Dir(b)) => ptr::eq(Arc::as_ptr(a) as *const u8, Arc::as_ptr(b) as *const u8),
+        _ => false,
+    }
+}
@@ -0,0 +1,153 @@
+//! A global display scale factor, used to render window decorations, mouse cursors, and text
+//! at a legible size on high-resolution framebuffers.
+//!
+//! All sizes in the window manager and font subsystem are defined in unscaled pixels,
+//! e.g., a 16-pixel title bar or a 9x16-pixel character.
+//! Components that render those elements multiply their sizes by the current [`ScaleFactor`]
+//! via [`ScaleFactor::scale()`] or [`scaled()`].
+//!
+//! The scale factor is chosen automatically based on the screen resolution
+//! when the window manager is initialized (see [`set_default_for_resolution()`]),
+//! unless it has been set explicitly via [`set_scale_factor()`] beforehand.
+//! From then on, the scale factor is fixed, as existing windows and terminals
+//! can't be re-laid out at a different scale factor.
+
+#![no_std]
+
+use core::{fmt, str::FromStr, sync::atomic::{AtomicBool, AtomicU8, Ordering}};
+use log::info;
+
+/// The supported display scale factors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ScaleFactor {
+    /// No scaling, i.e., one screen pixel per unscaled pixel.
+    One,
+    /// Three screen pixels per two unscaled pixels.
+    OneAndHalf,
+    /// Two screen pixels per unscaled pixel.
+    Two,
+}
+
+impl ScaleFactor {
+    /// Returns this scale factor in units of one half, e.g., `3` for 1.5x.
+    const fn halves(self) -> usize {
+        match self {
+            ScaleFactor::One => 2,
+            ScaleFactor::OneAndHalf => 3,
+            ScaleFactor::Two => 4,
+        }
+    }
+
+    const fn from_halves(halves: u8) -> ScaleFactor {
+        match halves {
+            3 => ScaleFactor::OneAndHalf,
+            4 => ScaleFactor::Two,
+            _ => ScaleFactor::One,
+        }
+    }
+
+    /// Converts the given length in unscaled pixels to screen pixels,
+    /// rounding up such that a nonzero length never scales to zero.
+    pub const fn scale(self, pixels: usize) -> usize {
+        (pixels * self.halves() + 1) / 2
+    }
+
+    /// Returns this scale factor rounded down to a whole number, e.g., `1` for 1.5x,
+    /// for scaling up a whole framebuffer by an integer factor.
+    pub const fn integer_scale(self) -> usize {
+        self.halves() / 2
+    }
+
+    /// Converts the given offset in screen pixels back to an offset in unscaled pixels,
+    /// e.g., to find the unscaled glyph pixel that covers a given screen pixel.
+    pub const fn unscale(self, pixels: usize) -> usize {
+        pixels * 2 / self.halves()
+    }
+
+    /// Returns the scale factor best suited to a screen of the given resolution in pixels.
+    ///
+    /// This is based on the screen height, such that a 16-pixel title bar
+    /// has roughly the same physical size on common 1080p, 1440p, and 4K displays.
+    pub fn for_resolution(_width: usize, height: usize) -> ScaleFactor {
+        if height >= 2000 {
+            ScaleFactor::Two
+        } else if height >= 1400 {
+            ScaleFactor::OneAndHalf
+        } else {
+            ScaleFactor::One
+        }
+    }
+}
+
+impl fmt::Display for ScaleFactor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScaleFactor::One => write!(f, "1x"),
+            ScaleFactor::OneAndHalf => write!(f, "1.5x"),
+            ScaleFactor::Two => write!(f, "2x"),
+        }
+    }
+}
+
+impl FromStr for ScaleFactor {
+    type Err = &'static str;
+
+    /// Parses a scale factor like `"1.5"` or `"1.5x"`.
+    fn from_str(s: &str) -> Result<ScaleFactor, &'static str> {
+        match s.trim().trim_end_matches(['x', 'X']) {
+            "1" | "1.0" => Ok(ScaleFactor::One),
+            "1.5" => Ok(ScaleFactor::OneAndHalf),
+            "2" | "2.0" => Ok(ScaleFactor::Two),
+            _ => Err("invalid display scale factor; the supported factors are 1x, 1.5x, and 2x"),
+        }
+    }
+}
+
+/// The current scale factor, in units of one half.
+static SCALE_FACTOR_HALVES: AtomicU8 = AtomicU8::new(2);
+/// Whether the scale factor was set explicitly, in which case it isn't chosen based on the screen resolution.
+static EXPLICITLY_SET: AtomicBool = AtomicBool::new(false);
+/// Whether the scale factor has been fixed by the initialization of the window manager.
+static FIXED: AtomicBool = AtomicBool::new(false);
+
+/// Returns the current display scale factor.
+pub fn scale_factor() -> ScaleFactor {
+    ScaleFactor::from_halves(SCALE_FACTOR_HALVES.load(Ordering::Relaxed))
+}
+
+/// Sets the display scale factor, overriding the one chosen based on the screen resolution.
+///
+/// This must be called during boot, before the window manager is initialized;
+/// afterwards, the scale factor is fixed and an error is returned.
+pub fn set_scale_factor(scale_factor: ScaleFactor) -> Result<(), &'static str> {
+    if FIXED.load(Ordering::Acquire) {
+        return Err("the display scale factor can only be set before the window manager is initialized");
+    }
+    EXPLICITLY_SET.store(true, Ordering::Relaxed);
+    SCALE_FACTOR_HALVES.store(scale_factor.halves() as u8, Ordering::Relaxed);
+    info!("display scale factor set to {}", scale_factor);
+    Ok(())
+}
+
+/// Sets the display scale factor based on the given screen resolution,
+/// unless it has already been set explicitly via [`set_scale_factor()`],
+/// and then fixes it such that it can no longer be changed.
+///
+/// This is called once by the window manager upon initialization.
+/// Returns the resulting scale factor.
+pub fn set_default_for_resolution(width: usize, height: usize) -> ScaleFactor {
+    if FIXED.swap(true, Ordering::AcqRel) {
+        return self::scale_factor();
+    }
+    if !EXPLICITLY_SET.load(Ordering::Relaxed) {
+        let scale_factor = ScaleFactor::for_resolution(width, height);
+        SCALE_FACTOR_HALVES.store(scale_factor.halves() as u8, Ordering::Relaxed);
+        info!("display scale factor for {}x{} screen: {}", width, height, scale_factor);
+    }
+    self::scale_factor()
+}
+
+/// Converts the given length in unscaled pixels to screen pixels using the current scale factor.
+pub fn scaled(pixels: usize) -> usize {
+    scale_factor().scale(pixels)
+}
@@ -13,7 +13,7 @@ extern crate color;
 
 use alloc::string::String;
 use displayable::{Displayable};
-use font::{CHARACTER_HEIGHT, CHARACTER_WIDTH};
+use font::{character_height, character_width};
 use framebuffer::{Pixel, Framebuffer};
 use color::Color;
 use shapes::{Coord, Rectangle};
@@ -64,7 +64,7 @@ impl Displayable for TextDisplay {
         );
 
         if next_line < self.next_line {
-            bounding_box.bottom_right.y = ((self.next_line + 1 ) * CHARACTER_HEIGHT) as isize
+            bounding_box.bottom_right.y = ((self.next_line + 1 ) * character_height()) as isize
         }
 
         self.next_col = next_col;
@@ -112,6 +112,14 @@ impl TextDisplay {
         self.bg_color
     }
     
+    /// Sets the colors of the text and the background behind the text,
+    /// such that all of the text is redrawn upon the next display.
+    pub fn set_colors(&mut self, fg_color: Color, bg_color: Color) {
+        self.fg_color = fg_color;
+        self.bg_color = bg_color;
+        self.reset_cache();
+    }
+
     /// Clear the cache of the text displayable.
     pub fn reset_cache(&mut self) {
         self.cache = String::new();
@@ -119,24 +127,24 @@ impl TextDisplay {
 
     /// Translate the index of a character in the text to the location of the text displayable. Return (column, line).
     pub fn get_location(&self, index: usize) -> (usize, usize) {
-        let text_width = self.width / CHARACTER_WIDTH;
+        let text_width = self.width / character_width();
         (index % text_width, index / text_width)
     }
 
     /// Translate the location of a character to its index in the text.
     pub fn get_index(&self, column: usize, line: usize) -> usize {
-        let text_width = self.width / CHARACTER_WIDTH;
+        let text_width = self.width / character_width();
         line * text_width + column
     }
 
     /// Gets the size of a text displayable in number of characters.
     pub fn get_dimensions(&self) -> (usize, usize) {
-        (self.width / CHARACTER_WIDTH, self.height / CHARACTER_HEIGHT)
+        (self.width / character_width(), self.height / character_height())
     }
 
     /// Gets the index of next character to be displayabled. It is the position next to existing printed characters in the text displayable.
     pub fn get_next_index(&self) -> usize {
-        let col_num = self.width / CHARACTER_WIDTH;
+        let col_num = self.width / character_width();
         self.next_line * col_num + self.next_col
     }
 
@@ -3,15 +3,16 @@
 extern crate alloc;
 
 use alloc::string::String;
+use clipboard::ClipboardCapability;
 use keycodes_ascii::KeyEvent;
-use mouse_data::MouseEvent;
+use mouse_data::{MouseAbsoluteEvent, MouseEvent};
 use shapes::{Coord, Rectangle};
 
 /// An event describing mouse position rather than movement differential from last event.
 /// It contains two position, `coodinate` for the relative position in each window, and `gcoordinate` for global absolute position of the screen.
 #[derive(Debug, Clone)]
 pub struct MousePositionEvent {
-    /// the relative position in window
+    /// the relative position in window, in pixels of the window's framebuffer
     pub coordinate: Coord,
     /// the global position in window
     pub gcoordinate: Coord,
@@ -50,6 +51,9 @@ pub enum Event {
     KeyboardEvent(KeyboardInputEvent),
     /// An input event from a mouse
     MouseMovementEvent(MouseEvent),
+    /// An input event from an absolute pointing device, e.g., a tablet,
+    /// which gives the pointer's position on the screen rather than its movement
+    MouseAbsoluteEvent(MouseAbsoluteEvent),
     /// An event indicating that another entity wants to print the given `String`.
     OutputEvent(String),
     /// Tells an application that the window manager has resized or moved its window
@@ -61,9 +65,31 @@ pub enum Event {
     WindowResizeEvent(Rectangle),
     /// The event tells application about mouse's position currently (including relative to a window and relative to a screen)
     MousePositionEvent(MousePositionEvent),
+    /// Tells an application that its window has been minimized, maximized, or restored.
+    ///
+    /// A window that is maximized or restored also receives a `WindowResizeEvent` for its new size.
+    WindowStateChangeEvent(WindowState),
+    /// Tells an application that the user asked it to copy its current selection to the clipboard,
+    /// granting it a capability to write the clipboard.
+    CopyEvent(ClipboardCapability),
+    /// Tells an application that the user asked it to paste the clipboard's contents,
+    /// granting it a capability to read the clipboard.
+    PasteEvent(ClipboardCapability),
     ExitEvent,
 }
 
+/// Whether a window is shown at its normal size, hidden, or filling the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum WindowState {
+    /// The window is shown at the position and size it was given.
+    #[default]
+    Normal,
+    /// The window is hidden and is not drawn to the screen until it is restored.
+    Minimized,
+    /// The window is resized to fill the whole screen.
+    Maximized,
+}
+
 impl Event {
     /// Create a new keyboard event
     pub fn new_keyboard_event(kev: KeyEvent) -> Event {
@@ -82,6 +108,11 @@ impl Event {
     pub fn new_window_resize_event(new_position: Rectangle) -> Event {
         Event::WindowResizeEvent(new_position)
     }
+
+    /// Create a new window state change event
+    pub fn new_window_state_change_event(state: WindowState) -> Event {
+        Event::WindowStateChangeEvent(state)
+    }
 }
 
 /// A keyboard event, indicating that one or more keys were pressed or released.
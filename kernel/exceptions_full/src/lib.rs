@@ -15,6 +15,7 @@ use x86_64::{
 };
 use locked_idt::LockedIdt;
 use fault_log::log_exception;
+use machine_check::Recovery;
 
 
 /// Initialize the given `idt` with fully-featured exception handlers.
@@ -45,11 +46,20 @@ pub fn init(idt_ref: &'static LockedIdt) {
         idt.segment_not_present.set_handler_fn(segment_not_present_handler);
         idt.stack_segment_fault.set_handler_fn(stack_segment_fault_handler);
         idt.general_protection_fault.set_handler_fn(general_protection_fault_handler);
-        idt.page_fault.set_handler_fn(page_fault_handler);
+        // The page fault handler runs on its own stack, such that a fault in a task's stack guard page
+        // can still be handled (by killing that task) rather than escalating into a double fault.
+        let options = idt.page_fault.set_handler_fn(page_fault_handler);
+        unsafe {
+            options.set_stack_index(tss::PAGE_FAULT_IST_INDEX as u16);
+        }
         // reserved: 0x0F
         idt.x87_floating_point.set_handler_fn(x87_floating_point_handler);
         idt.alignment_check.set_handler_fn(alignment_check_handler);
-        idt.machine_check.set_handler_fn(machine_check_handler);
+        // The machine check entry expects a diverging handler, but our handler returns
+        // in order to resume execution after a recoverable machine check.
+        unsafe {
+            idt.machine_check.set_handler_addr(x86_64::VirtAddr::new(machine_check_handler as usize as u64));
+        }
         idt.simd_floating_point.set_handler_fn(simd_floating_point_handler);
         idt.virtualization.set_handler_fn(virtualization_handler);
         // reserved: 0x15 - 0x1C
@@ -59,6 +69,10 @@ pub fn init(idt_ref: &'static LockedIdt) {
     }
 
     idt_ref.load();
+
+    if let Err(e) = machine_check::init() {
+        warn!("Machine-check exceptions are disabled: {}", e);
+    }
 }
 
 
@@ -162,6 +176,23 @@ fn kill_and_halt(
         println_both!("---------------------- End of Stack Trace ------------------------");
     }
 
+    // Record a core file for the crashed task, if enabled, which is written into a file later.
+    if core_dump::is_enabled() {
+        if let Some(curr_task) = task::get_my_current_task().filter(|t| t.is_application()) {
+            let registers = core_dump::ExceptionRegisters {
+                instruction_pointer: stack_frame.instruction_pointer.as_u64(),
+                stack_pointer: stack_frame.stack_pointer.as_u64(),
+                flags: stack_frame.cpu_flags,
+                code_segment: stack_frame.code_segment,
+                stack_segment: stack_frame.stack_segment,
+            };
+            match core_dump::record_core_file(&curr_task, exception_number, &registers) {
+                Ok(()) => { println_both!("Recorded core file, which will be written into {}", core_dump::CORE_DIRECTORY); }
+                Err(e) => { println_both!("Failed to record core file: {}", e); }
+            }
+        }
+    }
+
     let cause = task::KillReason::Exception(exception_number);
 
     // Call this task's kill handler, if it has one.
@@ -217,8 +248,23 @@ fn kill_and_halt(
             }
             kill_result
         });
-        if res.is_err() {
-            println_both!("BUG: kill_and_halt(): Couldn't get current task in order to kill it.");
+        match res {
+            // The killed task must never run again, so switch to another task.
+            // Interrupts were disabled upon entering the exception handler, so they must be re-enabled
+            // for this CPU to be able to preempt or schedule anything else.
+            //
+            // This is only possible for ordinary faults. NMIs remain blocked on this CPU until
+            // the NMI handler returns, and a double fault is unrecoverable, so after those, this CPU must halt instead.
+            // A page fault runs on this CPU's page fault IST stack, which the next page fault reuses from its top;
+            // that's fine because the killed task never resumes on it.
+            Ok(Ok(())) if !matches!(exception_number, 0x2 | 0x8) => {
+                x86_64::instructions::interrupts::enable();
+                task::scheduler::schedule();
+            }
+            Ok(_) => { }
+            Err(_) => {
+                println_both!("BUG: kill_and_halt(): Couldn't get current task in order to kill it.");
+            }
         }
     }
 
@@ -239,6 +285,25 @@ fn is_stack_overflow(vaddr: VirtualAddress) -> bool {
     ).unwrap_or(false)
 }
 
+/// Checks whether the current task overflowed its stack, i.e., whether either the
+/// `accessed_vaddr` or the interrupted stack pointer falls within its stack's guard page.
+///
+/// If so, this prints a "stack overflow in task X" diagnostic and returns `true`.
+fn check_stack_overflow(accessed_vaddr: usize, stack_frame: &InterruptStackFrame) -> bool {
+    let stack_pointer = stack_frame.stack_pointer.as_u64() as usize;
+    let is_overflow = is_stack_overflow(VirtualAddress::new_canonical(accessed_vaddr))
+        || is_stack_overflow(VirtualAddress::new_canonical(stack_pointer));
+    if is_overflow {
+        let _ = task::with_current_task(|t| {
+            println_both!("--> STACK OVERFLOW in task {:?} (id {}): tried to access {:#X} with stack pointer {:#X}, \
+                guard page is {:X?}. Consider increasing the task's stack size.",
+                t.name, t.id, accessed_vaddr, stack_pointer, t.with_kstack(|kstack| kstack.guard_page().clone()),
+            );
+        });
+    }
+    is_overflow
+}
+
 /// Converts the given `exception_number` into a [`Signal`] category, if relevant.
 fn exception_to_signal(exception_number: u8) -> Option<Signal> {
     match exception_number {
@@ -347,10 +412,11 @@ extern "x86-interrupt" fn double_fault_handler(stack_frame: InterruptStackFrame,
         Note: double faults in Theseus are typically caused by stack overflow, is the stack large enough?",
         stack_frame, accessed_vaddr,
     );
-    if is_stack_overflow(VirtualAddress::new_canonical(accessed_vaddr as usize)) {
-        println_both!("--> This double fault was definitely caused by stack overflow, tried to access {:#X}.\n", accessed_vaddr);
-    }
-    
+    // A stack overflow typically causes a double fault rather than a page fault,
+    // because the CPU cannot push the page fault's exception frame onto the overflowed stack.
+    // This handler runs on a separate stack, so it can still report the overflow and kill the task.
+    check_stack_overflow(accessed_vaddr as usize, &stack_frame);
+
     kill_and_halt(0x8, &stack_frame, Some(error_code.into()), false);
     loop { core::hint::spin_loop() }
 }
@@ -383,17 +449,23 @@ extern "x86-interrupt" fn general_protection_fault_handler(stack_frame: Interrup
 extern "x86-interrupt" fn page_fault_handler(stack_frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
     let accessed_vaddr = Cr2::read_raw() as usize;
 
+    // A write to a present copy-on-write page isn't an error; the page just needs its own copy of its frame.
+    if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE)
+        && memory::handle_copy_on_write_fault(VirtualAddress::new_canonical(accessed_vaddr))
+    {
+        return;
+    }
+
     println_both!("\nEXCEPTION: PAGE FAULT while accessing {:#x}\n\
         error code: {:?}\n{:#X?}",
         accessed_vaddr,
         error_code,
         stack_frame
     );
-    if is_stack_overflow(VirtualAddress::new_canonical(accessed_vaddr)) {
-        println_both!("--> Page fault was caused by stack overflow, tried to access {:#X}\n.", accessed_vaddr);
-    }
-    
-    kill_and_halt(0xE, &stack_frame, Some(ErrorCode::PageFaultError { accessed_address: accessed_vaddr, pf_error: error_code }), true)
+    // Don't print a stack trace for a stack overflow, as the overflowed stack can't be unwound.
+    let is_overflow = check_stack_overflow(accessed_vaddr, &stack_frame);
+
+    kill_and_halt(0xE, &stack_frame, Some(ErrorCode::PageFaultError { accessed_address: accessed_vaddr, pf_error: error_code }), !is_overflow)
 }
 
 
@@ -410,10 +482,20 @@ extern "x86-interrupt" fn alignment_check_handler(stack_frame: InterruptStackFra
 }
 
 /// exception 0x12
-extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
-    println_both!("\nEXCEPTION: MACHINE CHECK\n{:#X?}", stack_frame);
-    kill_and_halt(0x12, &stack_frame, None, true);
-    loop { core::hint::spin_loop() }
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) {
+    let instruction_pointer = VirtualAddress::new_canonical(stack_frame.instruction_pointer.as_u64() as usize);
+    match machine_check::handle_machine_check(instruction_pointer) {
+        // The recovery task logs the errors, as logging here could deadlock.
+        Recovery::Resume => { }
+        Recovery::KillCurrentTask => {
+            println_both!("\nEXCEPTION: MACHINE CHECK\n{:#X?}", stack_frame);
+            kill_and_halt(0x12, &stack_frame, None, true);
+        }
+        Recovery::Halt => {
+            println_both!("\nEXCEPTION: UNRECOVERABLE MACHINE CHECK\n{:#X?}\nHalting this CPU.", stack_frame);
+            loop { core::hint::spin_loop() }
+        }
+    }
 }
 
 /// exception 0x13
@@ -0,0 +1,131 @@
+//! Allocation of blocks and inodes via each block group's bitmaps.
+//!
+//! Each block group has one bitmap for its blocks and one for its inodes, each of which
+//! fits within a single block. The bitmaps are updated on disk immediately, whereas the
+//! free counts in the group descriptors and superblock are written back when the volume is flushed.
+
+use alloc::vec;
+use crate::Ext2Filesystem;
+
+impl Ext2Filesystem {
+    /// Returns the number of blocks in the given block group, as the last group may be smaller.
+    fn blocks_in_group(&self, group: usize) -> u32 {
+        let sb = &self.superblock;
+        let first_block = sb.first_data_block + group as u32 * sb.blocks_per_group;
+        (sb.blocks_count - first_block).min(sb.blocks_per_group)
+    }
+
+    /// Finds a clear bit among the first `count` bits of the given bitmap block, sets it,
+    /// and writes the bitmap back. Returns the index of that bit.
+    fn allocate_bit(&mut self, bitmap_block: u32, count: u32) -> Result<Option<u32>, &'static str> {
+        let mut bitmap = self.read_block(bitmap_block)?;
+        let free_bit = (0 .. count).find(|&bit| bitmap[bit as usize / 8] & (1 << (bit % 8)) == 0);
+        if let Some(bit) = free_bit {
+            bitmap[bit as usize / 8] |= 1 << (bit % 8);
+            self.write_block(bitmap_block, &bitmap)?;
+        }
+        Ok(free_bit)
+    }
+
+    /// Clears the given bit of the given bitmap block and writes the bitmap back.
+    fn free_bit(&mut self, bitmap_block: u32, bit: u32) -> Result<(), &'static str> {
+        let mut bitmap = self.read_block(bitmap_block)?;
+        let mask = 1 << (bit % 8);
+        if bitmap[bit as usize / 8] & mask == 0 {
+            return Err("ext2: freeing a block or inode that is already free");
+        }
+        bitmap[bit as usize / 8] &= !mask;
+        self.write_block(bitmap_block, &bitmap)
+    }
+
+    /// Allocates a free block, preferring one in the given `goal_group`.
+    ///
+    /// The contents of the new block are not modified.
+    pub(crate) fn allocate_block(&mut self, goal_group: usize) -> Result<u32, &'static str> {
+        let group_count = self.groups.len();
+        for i in 0 .. group_count {
+            let group = (goal_group + i) % group_count;
+            if self.groups[group].free_blocks_count == 0 {
+                continue;
+            }
+            let count = self.blocks_in_group(group);
+            if let Some(bit) = self.allocate_bit(self.groups[group].block_bitmap, count)? {
+                self.groups[group].free_blocks_count -= 1;
+                self.superblock.set_free_blocks_count(self.superblock.free_blocks_count().saturating_sub(1));
+                return Ok(self.superblock.first_data_block + group as u32 * self.superblock.blocks_per_group + bit);
+            }
+        }
+        Err("ext2: no free blocks remain on the volume")
+    }
+
+    /// Allocates a free block, preferring one in the given `goal_group`, and fills it with zeros.
+    pub(crate) fn allocate_zeroed_block(&mut self, goal_group: usize) -> Result<u32, &'static str> {
+        let block = self.allocate_block(goal_group)?;
+        let zeros = vec![0u8; self.block_size()];
+        self.write_block(block, &zeros)?;
+        Ok(block)
+    }
+
+    /// Frees the given `block`.
+    pub(crate) fn free_block(&mut self, block: u32) -> Result<(), &'static str> {
+        let sb = &self.superblock;
+        if block < sb.first_data_block || block >= sb.blocks_count {
+            return Err("ext2: freeing an invalid block");
+        }
+        let group = ((block - sb.first_data_block) / sb.blocks_per_group) as usize;
+        let bit = (block - sb.first_data_block) % sb.blocks_per_group;
+        self.free_bit(self.groups[group].block_bitmap, bit)?;
+        self.groups[group].free_blocks_count += 1;
+        self.superblock.set_free_blocks_count(self.superblock.free_blocks_count() + 1);
+        Ok(())
+    }
+
+    /// Returns the block group that holds the given inode.
+    pub(crate) fn inode_group(&self, inode_number: u32) -> usize {
+        ((inode_number - 1) / self.superblock.inodes_per_group) as usize
+    }
+
+    /// Allocates a free inode, preferring one in the given `goal_group`.
+    ///
+    /// The caller is responsible for initializing the new inode and writing it to disk.
+    pub(crate) fn allocate_inode(&mut self, goal_group: usize, is_dir: bool) -> Result<u32, &'static str> {
+        let group_count = self.groups.len();
+        let ipg = self.superblock.inodes_per_group;
+        for i in 0 .. group_count {
+            let group = (goal_group + i) % group_count;
+            if self.groups[group].free_inodes_count == 0 {
+                continue;
+            }
+            let mut bitmap = self.read_block(self.groups[group].inode_bitmap)?;
+            // Reserved inodes are normally marked as used already, but skip them regardless.
+            let first_usable = self.superblock.first_inode;
+            let free_bit = (0 .. ipg).find(|&bit| {
+                group as u32 * ipg + bit + 1 >= first_usable && bitmap[bit as usize / 8] & (1 << (bit % 8)) == 0
+            });
+            let Some(bit) = free_bit else { continue };
+            bitmap[bit as usize / 8] |= 1 << (bit % 8);
+            self.write_block(self.groups[group].inode_bitmap, &bitmap)?;
+
+            self.groups[group].free_inodes_count -= 1;
+            if is_dir {
+                self.groups[group].used_dirs_count += 1;
+            }
+            self.superblock.set_free_inodes_count(self.superblock.free_inodes_count().saturating_sub(1));
+            return Ok(group as u32 * ipg + bit + 1);
+        }
+        Err("ext2: no free inodes remain on the volume")
+    }
+
+    /// Frees the given inode, which must no longer be referenced by any directory entry.
+    pub(crate) fn free_inode(&mut self, inode_number: u32, is_dir: bool) -> Result<(), &'static str> {
+        let group = self.inode_group(inode_number);
+        let bit = (inode_number - 1) % self.superblock.inodes_per_group;
+        self.free_bit(self.groups[group].inode_bitmap, bit)?;
+        self.groups[group].free_inodes_count += 1;
+        if is_dir {
+            self.groups[group].used_dirs_count = self.groups[group].used_dirs_count.saturating_sub(1);
+        }
+        self.superblock.set_free_inodes_count(self.superblock.free_inodes_count() + 1);
+        Ok(())
+    }
+}
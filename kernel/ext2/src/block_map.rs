@@ -0,0 +1,181 @@
+//! Mapping the logical blocks of an inode's contents to blocks on disk,
+//! through its direct block pointers and chains of indirect blocks.
+//!
+//! A block pointer of `0` denotes a hole, i.e., a block that isn't allocated and reads as zeros.
+
+use alloc::vec::Vec;
+use crate::{
+    Ext2Filesystem,
+    inode::{Inode, DIRECT_BLOCKS, SINGLY_INDIRECT, DOUBLY_INDIRECT, TRIPLY_INDIRECT},
+    superblock::{read_u32, write_u32},
+};
+
+impl Ext2Filesystem {
+    /// Returns the number of block pointers that fit in an indirect block.
+    fn pointers_per_block(&self) -> u64 {
+        self.block_size() as u64 / 4
+    }
+
+    /// Returns the largest number of logical blocks that an inode can map.
+    pub(crate) fn max_blocks(&self) -> u64 {
+        let ptrs = self.pointers_per_block();
+        DIRECT_BLOCKS as u64 + ptrs + ptrs * ptrs + ptrs * ptrs * ptrs
+    }
+
+    /// Returns the inode block pointer through which the given logical block is reached,
+    /// followed by the index of the pointer to follow within each level of indirect blocks.
+    fn block_path(&self, logical_block: u64) -> Result<(usize, Vec<usize>), &'static str> {
+        let ptrs = self.pointers_per_block();
+        let mut index = logical_block;
+        if index < DIRECT_BLOCKS as u64 {
+            return Ok((index as usize, Vec::new()));
+        }
+        index -= DIRECT_BLOCKS as u64;
+        if index < ptrs {
+            return Ok((SINGLY_INDIRECT, vec_of(&[index])));
+        }
+        index -= ptrs;
+        if index < ptrs * ptrs {
+            return Ok((DOUBLY_INDIRECT, vec_of(&[index / ptrs, index % ptrs])));
+        }
+        index -= ptrs * ptrs;
+        if index < ptrs * ptrs * ptrs {
+            return Ok((TRIPLY_INDIRECT, vec_of(&[index / (ptrs * ptrs), index / ptrs % ptrs, index % ptrs])));
+        }
+        Err("ext2: file offset is beyond the maximum file size")
+    }
+
+    /// Returns the block on disk that holds the given logical block of `inode`,
+    /// or `None` if that block is a hole.
+    pub(crate) fn lookup_block(&mut self, inode: &Inode, logical_block: u64) -> Result<Option<u32>, &'static str> {
+        let (slot, path) = self.block_path(logical_block)?;
+        let mut block = inode.block(slot);
+        for index in path {
+            if block == 0 {
+                return Ok(None);
+            }
+            let mut pointer = [0u8; 4];
+            self.disk.read_exact(&mut pointer, self.block_offset(block) + index * 4)?;
+            block = u32::from_le_bytes(pointer);
+        }
+        Ok((block != 0).then_some(block))
+    }
+
+    /// Returns the block on disk that holds the given logical block of `inode`,
+    /// allocating it and any indirect blocks needed to reach it if it is a hole.
+    ///
+    /// Also returns whether the block was newly allocated, in which case its contents are undefined.
+    /// The caller must write back the `inode`, whose block pointers and sector count may have changed.
+    pub(crate) fn map_block(&mut self, inode_number: u32, inode: &mut Inode, logical_block: u64) -> Result<(u32, bool), &'static str> {
+        let goal_group = self.inode_group(inode_number);
+        let sectors_per_block = (self.block_size() / 512) as u32;
+        let (slot, path) = self.block_path(logical_block)?;
+
+        let mut newly_allocated = false;
+        let mut block = inode.block(slot);
+        if block == 0 {
+            // Indirect blocks must start out zeroed so that they don't contain bogus pointers.
+            block = if path.is_empty() { self.allocate_block(goal_group)? } else { self.allocate_zeroed_block(goal_group)? };
+            inode.set_block(slot, block);
+            inode.set_sectors(inode.sectors() + sectors_per_block);
+            newly_allocated = true;
+        }
+        for (level, &index) in path.iter().enumerate() {
+            let pointer_offset = self.block_offset(block) + index * 4;
+            let mut pointer = [0u8; 4];
+            self.disk.read_exact(&mut pointer, pointer_offset)?;
+            let mut next = u32::from_le_bytes(pointer);
+            newly_allocated = false;
+            if next == 0 {
+                let is_data_block = level == path.len() - 1;
+                next = if is_data_block { self.allocate_block(goal_group)? } else { self.allocate_zeroed_block(goal_group)? };
+                self.write_all(&next.to_le_bytes(), pointer_offset)?;
+                inode.set_sectors(inode.sectors() + sectors_per_block);
+                newly_allocated = true;
+            }
+            block = next;
+        }
+        Ok((block, newly_allocated))
+    }
+
+    /// Frees every block of `inode` that holds a logical block at or beyond `keep`,
+    /// as well as any indirect blocks that no longer point to any blocks.
+    ///
+    /// The caller must write back the `inode`, whose block pointers and sector count may have changed.
+    pub(crate) fn truncate_blocks(&mut self, inode: &mut Inode, keep: u64) -> Result<(), &'static str> {
+        let ptrs = self.pointers_per_block();
+        for slot in 0 .. DIRECT_BLOCKS {
+            let block = inode.block(slot);
+            if block != 0 && slot as u64 >= keep {
+                self.release_block(inode, block)?;
+                inode.set_block(slot, 0);
+            }
+        }
+        let mut first_logical_block = DIRECT_BLOCKS as u64;
+        for (slot, depth) in [(SINGLY_INDIRECT, 1), (DOUBLY_INDIRECT, 2), (TRIPLY_INDIRECT, 3)] {
+            let block = inode.block(slot);
+            if block != 0 && self.truncate_indirect(inode, block, depth, first_logical_block, keep)? {
+                inode.set_block(slot, 0);
+            }
+            first_logical_block += ptrs.pow(depth);
+        }
+        Ok(())
+    }
+
+    /// Frees the blocks reachable from the given indirect `block` at and beyond logical block `keep`,
+    /// where `depth` is the number of levels of indirect blocks including this one,
+    /// and `first_logical_block` is the first logical block reachable from it.
+    ///
+    /// Returns `true` if the indirect block itself was freed, because it no longer points to any blocks.
+    fn truncate_indirect(&mut self, inode: &mut Inode, block: u32, depth: u32, first_logical_block: u64, keep: u64) -> Result<bool, &'static str> {
+        let ptrs = self.pointers_per_block();
+        let blocks_per_pointer = ptrs.pow(depth - 1);
+        let mut pointers = self.read_block(block)?;
+        let mut changed = false;
+        let mut empty = true;
+
+        for index in 0 .. ptrs as usize {
+            let child = read_u32(&pointers, index * 4);
+            if child == 0 {
+                continue;
+            }
+            let child_first = first_logical_block + index as u64 * blocks_per_pointer;
+            let freed = if child_first + blocks_per_pointer <= keep {
+                // Everything reachable from this pointer is kept.
+                false
+            } else if depth == 1 {
+                self.release_block(inode, child)?;
+                true
+            } else {
+                self.truncate_indirect(inode, child, depth - 1, child_first, keep)?
+            };
+            if freed {
+                write_u32(&mut pointers, index * 4, 0);
+                changed = true;
+            } else {
+                empty = false;
+            }
+        }
+
+        if empty {
+            self.release_block(inode, block)?;
+            return Ok(true);
+        }
+        if changed {
+            self.write_block(block, &pointers)?;
+        }
+        Ok(false)
+    }
+
+    /// Frees the given `block`, which belongs to `inode`.
+    fn release_block(&mut self, inode: &mut Inode, block: u32) -> Result<(), &'static str> {
+        self.free_block(block)?;
+        let sectors_per_block = (self.block_size() / 512) as u32;
+        inode.set_sectors(inode.sectors().saturating_sub(sectors_per_block));
+        Ok(())
+    }
+}
+
+fn vec_of(indices: &[u64]) -> Vec<usize> {
+    indices.iter().map(|&index| index as usize).collect()
+}
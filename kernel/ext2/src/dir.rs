@@ -0,0 +1,225 @@
+//! Reading and modifying the entries of directories.
+//!
+//! A directory's contents are a sequence of variable-length entries, each of which holds
+//! an inode number, the entry's total length, and its name. Entries never span blocks;
+//! the last entry in each block is extended to fill the rest of that block.
+//! An entry with an inode number of `0` is unused.
+
+use alloc::{string::String, vec, vec::Vec};
+use crate::{
+    Ext2Filesystem,
+    inode::{self, Inode},
+    superblock::{read_u16, read_u32, write_u16, write_u32, INCOMPAT_FILETYPE},
+};
+
+/// The size of a directory entry, excluding its name.
+const ENTRY_HEADER_SIZE: usize = 8;
+/// The maximum length of a name, in bytes.
+const MAX_NAME_LEN: usize = 255;
+
+/// The file type recorded in a directory entry for a regular file.
+pub const FILE_TYPE_REGULAR: u8 = 1;
+/// The file type recorded in a directory entry for a directory.
+pub const FILE_TYPE_DIRECTORY: u8 = 2;
+
+/// An entry of a directory, other than `.` and `..`.
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub inode: u32,
+    pub name: String,
+    /// The logical block of the directory that holds this entry.
+    pub block: u64,
+    /// The byte offset of this entry within its block.
+    pub offset: usize,
+}
+
+/// Returns the space taken up by an entry with a name of `name_len` bytes,
+/// which is padded to a multiple of four bytes.
+fn entry_size(name_len: usize) -> usize {
+    (ENTRY_HEADER_SIZE + name_len).next_multiple_of(4)
+}
+
+/// The header of a single raw directory entry.
+struct RawEntry {
+    inode: u32,
+    rec_len: usize,
+    name_len: usize,
+}
+
+impl RawEntry {
+    /// Parses the entry at the given `offset` of a directory block, validating its length.
+    fn parse(block: &[u8], offset: usize) -> Result<RawEntry, &'static str> {
+        if offset + ENTRY_HEADER_SIZE > block.len() {
+            return Err("ext2: corrupted directory entry");
+        }
+        let entry = RawEntry {
+            inode: read_u32(block, offset),
+            rec_len: read_u16(block, offset + 4) as usize,
+            name_len: block[offset + 6] as usize,
+        };
+        if entry.rec_len < ENTRY_HEADER_SIZE
+            || entry.rec_len % 4 != 0
+            || offset + entry.rec_len > block.len()
+            || ENTRY_HEADER_SIZE + entry.name_len > entry.rec_len
+        {
+            return Err("ext2: corrupted directory entry");
+        }
+        Ok(entry)
+    }
+
+    /// Returns the space actually used by this entry, i.e., none if it is unused.
+    fn used_size(&self) -> usize {
+        if self.inode == 0 { 0 } else { entry_size(self.name_len) }
+    }
+}
+
+/// Writes a directory entry at the given `offset` of a directory block.
+fn write_entry(block: &mut [u8], offset: usize, inode: u32, rec_len: usize, name: &[u8], file_type: u8) {
+    write_u32(block, offset, inode);
+    write_u16(block, offset + 4, rec_len as u16);
+    block[offset + 6] = name.len() as u8;
+    block[offset + 7] = file_type;
+    block[offset + ENTRY_HEADER_SIZE .. offset + ENTRY_HEADER_SIZE + name.len()].copy_from_slice(name);
+}
+
+/// Returns an error if `name` can't be used as the name of a file or directory.
+pub fn validate_name(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\0']) {
+        return Err("ext2: invalid file name");
+    }
+    if name.len() > MAX_NAME_LEN {
+        return Err("ext2: file name is longer than 255 bytes");
+    }
+    Ok(())
+}
+
+/// Builds the first block of a new directory, which holds its `.` and `..` entries.
+pub fn new_dir_block(block_size: usize, inode: u32, parent_inode: u32, file_type: u8) -> Vec<u8> {
+    let mut block = vec![0u8; block_size];
+    let dot_len = entry_size(1);
+    write_entry(&mut block, 0, inode, dot_len, b".", file_type);
+    write_entry(&mut block, dot_len, parent_inode, block_size - dot_len, b"..", file_type);
+    block
+}
+
+impl Ext2Filesystem {
+    /// Returns the file type to record in a directory entry, or `0` if the volume doesn't record them.
+    pub(crate) fn dir_entry_file_type(&self, file_type: u8) -> u8 {
+        if self.superblock.feature_incompat & INCOMPAT_FILETYPE != 0 { file_type } else { 0 }
+    }
+
+    /// Reads all of the entries in the given directory, except for `.` and `..`.
+    pub(crate) fn read_dir(&mut self, dir: &Inode) -> Result<Vec<DirEntry>, &'static str> {
+        let mut entries = Vec::new();
+        let block_count = dir.size().div_ceil(self.block_size() as u64);
+        for logical_block in 0 .. block_count {
+            let Some(block) = self.lookup_block(dir, logical_block)? else { continue };
+            let contents = self.read_block(block)?;
+            let mut offset = 0;
+            while offset < contents.len() {
+                let entry = RawEntry::parse(&contents, offset)?;
+                if entry.inode != 0 {
+                    let name = &contents[offset + ENTRY_HEADER_SIZE .. offset + ENTRY_HEADER_SIZE + entry.name_len];
+                    if name != b"." && name != b".." {
+                        entries.push(DirEntry {
+                            inode: entry.inode,
+                            name: String::from_utf8_lossy(name).into_owned(),
+                            block: logical_block,
+                            offset,
+                        });
+                    }
+                }
+                offset += entry.rec_len;
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Finds the entry with the given `name` in the given directory.
+    pub(crate) fn find_entry(&mut self, dir: &Inode, name: &str) -> Result<Option<DirEntry>, &'static str> {
+        Ok(self.read_dir(dir)?.into_iter().find(|entry| entry.name == name))
+    }
+
+    /// Adds an entry with the given `name` that refers to `inode_number` to the given directory,
+    /// whose inode is written back.
+    ///
+    /// The entry is placed in the first unused space large enough to hold it,
+    /// or in a new block appended to the directory if there is none.
+    /// Returns an error if the directory already has an entry with the same name.
+    pub(crate) fn add_dir_entry(
+        &mut self,
+        dir_number: u32,
+        dir: &mut Inode,
+        name: &str,
+        inode_number: u32,
+        file_type: u8,
+    ) -> Result<DirEntry, &'static str> {
+        validate_name(name)?;
+        if self.find_entry(dir, name)?.is_some() {
+            return Err("ext2: an entry with that name already exists");
+        }
+        let file_type = self.dir_entry_file_type(file_type);
+        let needed = entry_size(name.len());
+        let block_size = self.block_size();
+        let block_count = dir.size().div_ceil(block_size as u64);
+
+        for logical_block in 0 .. block_count {
+            let Some(block) = self.lookup_block(dir, logical_block)? else { continue };
+            let mut contents = self.read_block(block)?;
+            let mut offset = 0;
+            while offset < block_size {
+                let entry = RawEntry::parse(&contents, offset)?;
+                let used = entry.used_size();
+                if entry.rec_len - used >= needed {
+                    // Split the unused tail off of this entry, or reuse this entry if it is unused.
+                    let new_offset = offset + used;
+                    if used != 0 {
+                        write_u16(&mut contents, offset + 4, used as u16);
+                    }
+                    write_entry(&mut contents, new_offset, inode_number, entry.rec_len - used, name.as_bytes(), file_type);
+                    self.write_block(block, &contents)?;
+                    dir.touch(inode::now());
+                    self.write_inode(dir_number, dir)?;
+                    return Ok(DirEntry { inode: inode_number, name: name.into(), block: logical_block, offset: new_offset });
+                }
+                offset += entry.rec_len;
+            }
+        }
+
+        // No existing block has enough room, so append a new one.
+        let logical_block = block_count;
+        let (block, _) = self.map_block(dir_number, dir, logical_block)?;
+        let mut contents = vec![0u8; block_size];
+        write_entry(&mut contents, 0, inode_number, block_size, name.as_bytes(), file_type);
+        self.write_block(block, &contents)?;
+        dir.set_size((logical_block + 1) * block_size as u64);
+        dir.touch(inode::now());
+        self.write_inode(dir_number, dir)?;
+        Ok(DirEntry { inode: inode_number, name: name.into(), block: logical_block, offset: 0 })
+    }
+
+    /// Removes the given `entry` from the given directory, by merging it into the previous entry
+    /// in its block, or by marking it as unused if it is the first entry in its block.
+    pub(crate) fn remove_dir_entry(&mut self, dir: &Inode, entry: &DirEntry) -> Result<(), &'static str> {
+        let block = self.lookup_block(dir, entry.block)?.ok_or("ext2: directory entry is in a hole")?;
+        let mut contents = self.read_block(block)?;
+        let removed = RawEntry::parse(&contents, entry.offset)?;
+        if entry.offset == 0 {
+            write_u32(&mut contents, 0, 0);
+        } else {
+            let mut offset = 0;
+            loop {
+                let previous = RawEntry::parse(&contents, offset)?;
+                if offset + previous.rec_len == entry.offset {
+                    write_u16(&mut contents, offset + 4, (previous.rec_len + removed.rec_len) as u16);
+                    break;
+                }
+                offset += previous.rec_len;
+                if offset >= entry.offset {
+                    return Err("ext2: directory entry to remove was not found");
+                }
+            }
+        }
+        self.write_block(block, &contents)
+    }
+}
@@ -0,0 +1,150 @@
+//! The on-disk format of ext2 inodes.
+//!
+//! An inode holds a file's type, size, link count, timestamps, and the block pointers that
+//! locate its contents. The first 12 block pointers refer directly to data blocks,
+//! while the last three refer to singly-, doubly-, and triply-indirect blocks.
+
+use alloc::{vec, vec::Vec};
+use crate::superblock::{read_u16, read_u32, write_u16, write_u32};
+
+/// The inode of the root directory.
+pub const ROOT_INODE: u32 = 2;
+
+/// The number of block pointers that refer directly to data blocks.
+pub const DIRECT_BLOCKS: usize = 12;
+pub const SINGLY_INDIRECT: usize = 12;
+pub const DOUBLY_INDIRECT: usize = 13;
+pub const TRIPLY_INDIRECT: usize = 14;
+
+/// The bits of an inode's mode that hold its file type.
+const MODE_TYPE_MASK: u16 = 0xF000;
+pub const MODE_REGULAR: u16 = 0x8000;
+pub const MODE_DIRECTORY: u16 = 0x4000;
+pub const MODE_SYMLINK: u16 = 0xA000;
+
+/// The size of an inode's block pointers, in which a symbolic link with a shorter target
+/// (a "fast" symbolic link) stores that target instead of in a data block.
+const FAST_SYMLINK_MAX_SIZE: u64 = 60;
+
+const MODE_OFFSET: usize = 0;
+const SIZE_OFFSET: usize = 4;
+const ACCESS_TIME_OFFSET: usize = 8;
+const CHANGE_TIME_OFFSET: usize = 12;
+const MODIFY_TIME_OFFSET: usize = 16;
+const DELETE_TIME_OFFSET: usize = 20;
+const LINKS_COUNT_OFFSET: usize = 26;
+const SECTORS_OFFSET: usize = 28;
+const BLOCK_OFFSET: usize = 40;
+const SIZE_HIGH_OFFSET: usize = 108;
+
+/// An inode, held in its raw on-disk form so that it can be written back
+/// without losing any fields not exposed here.
+#[derive(Debug, Clone)]
+pub struct Inode {
+    raw: Vec<u8>,
+}
+
+impl Inode {
+    /// Wraps the given raw inode, which must be at least 128 bytes long.
+    pub fn from_bytes(raw: Vec<u8>) -> Inode {
+        Inode { raw }
+    }
+
+    /// Creates a new inode of `inode_size` bytes with the given `mode` and one link,
+    /// whose timestamps are set to the given `time`.
+    pub fn new(inode_size: usize, mode: u16, time: u32) -> Inode {
+        let mut inode = Inode { raw: vec![0u8; inode_size] };
+        write_u16(&mut inode.raw, MODE_OFFSET, mode);
+        inode.set_links_count(1);
+        write_u32(&mut inode.raw, ACCESS_TIME_OFFSET, time);
+        inode.touch(time);
+        inode
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn mode(&self) -> u16 {
+        read_u16(&self.raw, MODE_OFFSET)
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode() & MODE_TYPE_MASK == MODE_DIRECTORY
+    }
+
+    pub fn is_regular_file(&self) -> bool {
+        self.mode() & MODE_TYPE_MASK == MODE_REGULAR
+    }
+
+    /// Returns `true` if this inode's block pointers locate its contents.
+    ///
+    /// This isn't the case for fast symbolic links, device nodes, FIFOs, and sockets,
+    /// whose block pointers hold other data or nothing at all.
+    pub fn has_block_map(&self) -> bool {
+        match self.mode() & MODE_TYPE_MASK {
+            MODE_REGULAR | MODE_DIRECTORY => true,
+            MODE_SYMLINK => self.size() >= FAST_SYMLINK_MAX_SIZE,
+            _ => false,
+        }
+    }
+
+    /// Returns the size in bytes of this inode's contents.
+    ///
+    /// For regular files, this includes the upper 32 bits of the size,
+    /// which hold the directory ACL for other file types.
+    pub fn size(&self) -> u64 {
+        let high = if self.is_regular_file() { read_u32(&self.raw, SIZE_HIGH_OFFSET) } else { 0 };
+        ((high as u64) << 32) | read_u32(&self.raw, SIZE_OFFSET) as u64
+    }
+
+    pub fn set_size(&mut self, size: u64) {
+        write_u32(&mut self.raw, SIZE_OFFSET, size as u32);
+        if self.is_regular_file() {
+            write_u32(&mut self.raw, SIZE_HIGH_OFFSET, (size >> 32) as u32);
+        }
+    }
+
+    pub fn links_count(&self) -> u16 {
+        read_u16(&self.raw, LINKS_COUNT_OFFSET)
+    }
+
+    pub fn set_links_count(&mut self, count: u16) {
+        write_u16(&mut self.raw, LINKS_COUNT_OFFSET, count);
+    }
+
+    /// Returns the number of 512-byte sectors allocated to this inode,
+    /// including its indirect blocks.
+    pub fn sectors(&self) -> u32 {
+        read_u32(&self.raw, SECTORS_OFFSET)
+    }
+
+    pub fn set_sectors(&mut self, sectors: u32) {
+        write_u32(&mut self.raw, SECTORS_OFFSET, sectors);
+    }
+
+    /// Returns the block pointer at the given index, where `0` means that no block is allocated.
+    pub fn block(&self, index: usize) -> u32 {
+        read_u32(&self.raw, BLOCK_OFFSET + index * 4)
+    }
+
+    pub fn set_block(&mut self, index: usize, block: u32) {
+        write_u32(&mut self.raw, BLOCK_OFFSET + index * 4, block);
+    }
+
+    /// Sets this inode's modification and change times to the given `time`.
+    pub fn touch(&mut self, time: u32) {
+        write_u32(&mut self.raw, MODIFY_TIME_OFFSET, time);
+        write_u32(&mut self.raw, CHANGE_TIME_OFFSET, time);
+    }
+
+    /// Sets this inode's deletion time, which marks it as no longer in use.
+    pub fn set_delete_time(&mut self, time: u32) {
+        write_u32(&mut self.raw, DELETE_TIME_OFFSET, time);
+    }
+}
+
+/// Returns the current time as seconds since the Unix epoch, as stored in inode timestamps.
+pub fn now() -> u32 {
+    rtc::now_utc().to_unix_timestamp().clamp(0, u32::MAX as i64) as u32
+}
@@ -0,0 +1,219 @@
+//! An ext2 filesystem with support for both reading and writing.
+//!
+//! An ext2 volume on a [`StorageDevice`] is mounted via [`mount()`],
+//! or opened via [`open()`] such that its root directory can be mounted elsewhere.
+//! Its regular files and directories implement the [`File`] and [`Directory`] traits,
+//! as described in the [`disk_fs`] crate; other types of inodes, such as symbolic links
+//! and device nodes, are not exposed.
+//!
+//! This crate supports the original ext2 format, plus the `filetype`, `flex_bg`, `sparse_super`,
+//! and `large_file` features. Volumes with other incompatible features, such as the extents
+//! used by ext4, can't be mounted, while volumes with other read-only compatible features
+//! are mounted read-only.
+//!
+//! # Persistence
+//! File contents, inodes, directory entries, and allocation bitmaps are written directly to the
+//! underlying storage device, but the free block and inode counts in the superblock and
+//! block group descriptors are only written back upon [`Ext2Filesystem::flush()`],
+//! which also flushes the storage device itself and marks the volume as cleanly unmounted.
+//! Until then, the volume's state in its superblock marks it as not cleanly unmounted,
+//! which tells `e2fsck` that it should be checked for consistency.
+//!
+//! [`File`]: fs_node::File
+//! [`Directory`]: fs_node::Directory
+//! [`StorageDevice`]: storage_device::StorageDevice
+
+#![no_std]
+
+extern crate alloc;
+
+mod allocator;
+mod block_map;
+mod dir;
+mod inode;
+mod node;
+mod superblock;
+
+pub use inode::{Inode, ROOT_INODE};
+pub use node::{Ext2DirRef, Ext2Directory, Ext2File, Ext2FileRef, Ext2Node};
+pub use superblock::{GroupDescriptor, Superblock};
+
+use alloc::{sync::{Arc, Weak}, vec, vec::Vec};
+use disk_fs::Disk;
+use fs_node::DirRef;
+use log::warn;
+use spin::Mutex;
+use storage_device::StorageDeviceRef;
+use superblock::{GROUP_DESCRIPTOR_SIZE, STATE_VALID, SUPERBLOCK_OFFSET, SUPERBLOCK_SIZE};
+
+/// A reference to a mounted ext2 filesystem, which is shared by all of its files and directories.
+pub type Ext2FsRef = Arc<Mutex<Ext2Filesystem>>;
+
+/// A mounted ext2 volume.
+pub struct Ext2Filesystem {
+    disk: Disk,
+    superblock: Superblock,
+    groups: Vec<GroupDescriptor>,
+    /// Whether the volume must not be modified, because it uses unsupported features.
+    read_only: bool,
+    /// Whether the volume has been modified since it was mounted or last flushed.
+    dirty: bool,
+}
+
+impl Ext2Filesystem {
+    /// Reads the superblock and block group descriptors of the ext2 volume on the given `device`.
+    pub fn new(device: StorageDeviceRef) -> Result<Ext2Filesystem, &'static str> {
+        let mut disk = Disk::new(device)?;
+        let mut raw = vec![0u8; SUPERBLOCK_SIZE];
+        disk.read_exact(&mut raw, SUPERBLOCK_OFFSET)?;
+        let superblock = Superblock::parse(raw)?;
+
+        let group_count = superblock.group_count();
+        if group_count != superblock.inodes_count.div_ceil(superblock.inodes_per_group) as usize {
+            return Err("ext2: number of block groups is inconsistent");
+        }
+        let mut table = vec![0u8; group_count * GROUP_DESCRIPTOR_SIZE];
+        disk.read_exact(&mut table, superblock.group_table_block() as usize * superblock.block_size)?;
+        let groups = table.chunks_exact(GROUP_DESCRIPTOR_SIZE).map(GroupDescriptor::parse).collect();
+
+        let read_only = superblock.requires_read_only();
+        if read_only {
+            warn!("ext2: volume uses unsupported read-only compatible features, mounting it read-only");
+        }
+        if superblock.state() & STATE_VALID == 0 {
+            warn!("ext2: volume was not cleanly unmounted and may be inconsistent");
+        }
+        Ok(Ext2Filesystem { disk, superblock, groups, read_only, dirty: false })
+    }
+
+    /// Returns the superblock of this volume.
+    pub fn superblock(&self) -> &Superblock {
+        &self.superblock
+    }
+
+    /// Returns `true` if this volume can't be modified.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Returns `true` if this volume has been modified since it was mounted or last flushed.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Writes back the free block and inode counts, flushes the underlying storage device,
+    /// and marks the volume as cleanly unmounted.
+    ///
+    /// This does nothing if the volume isn't dirty.
+    pub fn flush(&mut self) -> Result<(), &'static str> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let table_offset = self.superblock.group_table_block() as usize * self.superblock.block_size;
+        let mut table = vec![0u8; self.groups.len() * GROUP_DESCRIPTOR_SIZE];
+        self.disk.read_exact(&mut table, table_offset)?;
+        for (group, raw) in self.groups.iter().zip(table.chunks_exact_mut(GROUP_DESCRIPTOR_SIZE)) {
+            group.write_to(raw);
+        }
+        self.disk.write_all(&table, table_offset)?;
+
+        self.superblock.set_state(self.superblock.state() | STATE_VALID);
+        self.superblock.set_write_time(inode::now());
+        self.write_superblock()?;
+        self.disk.flush()?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Marks the volume as dirty, clearing the valid bit of its state on disk
+    /// if this is its first modification since it was mounted or last flushed.
+    fn mark_dirty(&mut self) -> Result<(), &'static str> {
+        if self.read_only {
+            return Err("ext2: volume is mounted read-only");
+        }
+        if !self.dirty {
+            self.superblock.set_state(self.superblock.state() & !STATE_VALID);
+            self.write_superblock()?;
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    fn write_superblock(&mut self) -> Result<(), &'static str> {
+        let raw = self.superblock.as_bytes().to_vec();
+        self.disk.write_all(&raw, SUPERBLOCK_OFFSET)
+    }
+
+    fn block_size(&self) -> usize {
+        self.superblock.block_size
+    }
+
+    /// Returns the byte offset on disk of the given `block`.
+    fn block_offset(&self, block: u32) -> usize {
+        block as usize * self.superblock.block_size
+    }
+
+    /// Reads the entire given `block`.
+    fn read_block(&mut self, block: u32) -> Result<Vec<u8>, &'static str> {
+        let mut buffer = vec![0u8; self.block_size()];
+        self.disk.read_exact(&mut buffer, self.block_offset(block))?;
+        Ok(buffer)
+    }
+
+    /// Writes `buffer` at the start of the given `block`, marking the volume as dirty.
+    fn write_block(&mut self, block: u32, buffer: &[u8]) -> Result<(), &'static str> {
+        self.write_all(buffer, self.block_offset(block))
+    }
+
+    /// Returns the byte offset on disk of the given inode.
+    fn inode_offset(&self, inode_number: u32) -> Result<usize, &'static str> {
+        if inode_number == 0 || inode_number > self.superblock.inodes_count {
+            return Err("ext2: invalid inode number");
+        }
+        let index = (inode_number - 1) as usize;
+        let ipg = self.superblock.inodes_per_group as usize;
+        let group = &self.groups[index / ipg];
+        Ok(self.block_offset(group.inode_table) + (index % ipg) * self.superblock.inode_size)
+    }
+
+    /// Reads the given inode.
+    pub fn read_inode(&mut self, inode_number: u32) -> Result<Inode, &'static str> {
+        let offset = self.inode_offset(inode_number)?;
+        let mut raw = vec![0u8; self.superblock.inode_size];
+        self.disk.read_exact(&mut raw, offset)?;
+        Ok(Inode::from_bytes(raw))
+    }
+
+    /// Writes the given inode back to disk.
+    fn write_inode(&mut self, inode_number: u32, inode: &Inode) -> Result<(), &'static str> {
+        let offset = self.inode_offset(inode_number)?;
+        self.write_all(inode.as_bytes(), offset)
+    }
+
+    /// Writes all of `buffer` to the disk at the given byte `offset`, marking the volume as dirty.
+    fn write_all(&mut self, buffer: &[u8], offset: usize) -> Result<(), &'static str> {
+        self.mark_dirty()?;
+        self.disk.write_all(buffer, offset)
+    }
+}
+
+/// Opens the ext2 volume on the given storage `device` and returns its root directory,
+/// which is called `name` and isn't part of the virtual filesystem.
+///
+/// The root directory can then be mounted anywhere in the virtual filesystem via `vfs::mount()`,
+/// in which case `name` should match the final component of the mount path.
+pub fn open(device: StorageDeviceRef, name: &str) -> Result<Ext2DirRef, &'static str> {
+    let mut fs = Ext2Filesystem::new(device)?;
+    if !fs.read_inode(ROOT_INODE)?.is_dir() {
+        return Err("ext2: root inode is not a directory");
+    }
+    Ok(Ext2Directory::new_root(Arc::new(Mutex::new(fs)), name.into(), Weak::<Mutex<Ext2Directory>>::new()))
+}
+
+/// Mounts the ext2 volume on the given storage `device` as a directory called `name`
+/// within the given `parent` directory.
+///
+/// Returns the root directory of the mounted volume.
+pub fn mount(device: StorageDeviceRef, name: &str, parent: &DirRef) -> Result<Ext2DirRef, &'static str> {
+    disk_fs::mount(open(device, name)?, parent)
+}
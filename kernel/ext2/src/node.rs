@@ -0,0 +1,585 @@
+//! The files and directories of a mounted ext2 volume, which implement the `fs_node` traits.
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use disk_fs::DiskDirectory;
+use fs_node::{DirRef, Directory, File, FileOrDir, FileRef, FsNode, WeakDirRef};
+use io::{ByteReader, ByteWriter, IoError, KnownLength};
+use memory::MappedPages;
+use spin::Mutex;
+use crate::{
+    Ext2Filesystem, Ext2FsRef,
+    dir::{self, DirEntry, FILE_TYPE_DIRECTORY, FILE_TYPE_REGULAR},
+    inode::{self, Inode, MODE_DIRECTORY, MODE_REGULAR, ROOT_INODE},
+    superblock::RO_COMPAT_LARGE_FILE,
+};
+
+/// Files on volumes without the `large_file` feature are limited to 2 GiB.
+const MAX_SMALL_FILE_SIZE: u64 = i32::MAX as u64;
+
+/// The permissions given to newly-created files, `rw-r--r--`.
+const NEW_FILE_PERMISSIONS: u16 = 0o644;
+/// The permissions given to newly-created directories, `rwxr-xr-x`.
+const NEW_DIR_PERMISSIONS: u16 = 0o755;
+
+const DELETED_ERROR: &str = "ext2: file or directory was deleted";
+
+/// A reference to a file on an ext2 volume.
+pub type Ext2FileRef = Arc<Mutex<Ext2File>>;
+/// A reference to a directory on an ext2 volume.
+pub type Ext2DirRef = Arc<Mutex<Ext2Directory>>;
+
+/// Either a file or a directory on an ext2 volume.
+///
+/// Unlike a [`FileOrDir`], this gives access to the ext2-specific methods of each node,
+/// such as [`Ext2Directory::create_file()`] and [`Ext2File::set_len()`].
+#[derive(Clone)]
+pub enum Ext2Node {
+    File(Ext2FileRef),
+    Dir(Ext2DirRef),
+}
+
+impl From<Ext2Node> for FileOrDir {
+    fn from(node: Ext2Node) -> FileOrDir {
+        match node {
+            Ext2Node::File(file) => FileOrDir::File(file),
+            Ext2Node::Dir(dir) => FileOrDir::Dir(dir),
+        }
+    }
+}
+
+impl Ext2Filesystem {
+    /// Returns the largest size of a regular file on this volume.
+    fn max_file_size(&self) -> u64 {
+        let mapped = self.max_blocks().saturating_mul(self.block_size() as u64);
+        if self.superblock.feature_ro_compat & RO_COMPAT_LARGE_FILE != 0 {
+            mapped
+        } else {
+            mapped.min(MAX_SMALL_FILE_SIZE)
+        }
+    }
+
+    /// Reads `buffer.len()` bytes at the given `offset` into the contents of `inode`,
+    /// where holes read as zeros.
+    fn read_inode_at(&mut self, inode: &Inode, buffer: &mut [u8], offset: u64) -> Result<(), &'static str> {
+        let block_size = self.block_size() as u64;
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done as u64;
+            let in_block = (position % block_size) as usize;
+            let len = (block_size as usize - in_block).min(buffer.len() - done);
+            let chunk = &mut buffer[done .. done + len];
+            match self.lookup_block(inode, position / block_size)? {
+                Some(block) => self.disk.read_exact(chunk, self.block_offset(block) + in_block)?,
+                None => chunk.fill(0),
+            }
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Writes all of `buffer` at the given `offset` into the contents of `inode`,
+    /// allocating blocks as needed.
+    ///
+    /// The caller must write back the `inode`, whose block pointers and sector count may have changed.
+    fn write_inode_at(&mut self, inode_number: u32, inode: &mut Inode, buffer: &[u8], offset: u64) -> Result<(), &'static str> {
+        let block_size = self.block_size();
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done as u64;
+            let in_block = (position % block_size as u64) as usize;
+            let len = (block_size - in_block).min(buffer.len() - done);
+            let (block, newly_allocated) = self.map_block(inode_number, inode, position / block_size as u64)?;
+            if newly_allocated && len < block_size {
+                // The rest of a newly-allocated block must read back as zeros.
+                let mut contents = vec![0u8; block_size];
+                contents[in_block .. in_block + len].copy_from_slice(&buffer[done .. done + len]);
+                self.write_block(block, &contents)?;
+            } else {
+                self.write_all(&buffer[done .. done + len], self.block_offset(block) + in_block)?;
+            }
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Zeros the part of the block that holds byte `from` of `inode`'s contents from that byte onwards,
+    /// such that it reads back as zeros if the file is later extended.
+    fn zero_block_tail(&mut self, inode: &Inode, from: u64) -> Result<(), &'static str> {
+        let block_size = self.block_size() as u64;
+        let in_block = (from % block_size) as usize;
+        if in_block == 0 {
+            return Ok(());
+        }
+        if let Some(block) = self.lookup_block(inode, from / block_size)? {
+            let zeros = vec![0u8; block_size as usize - in_block];
+            self.write_all(&zeros, self.block_offset(block) + in_block)?;
+        }
+        Ok(())
+    }
+
+    /// Removes one link to the given inode, freeing it and all of its blocks if it has no more links.
+    fn unlink_inode(&mut self, inode_number: u32, inode: &mut Inode) -> Result<(), &'static str> {
+        let is_dir = inode.is_dir();
+        // A directory is also linked to by its own `.` entry, which goes away along with it.
+        let links = if is_dir { 0 } else { inode.links_count().saturating_sub(1) };
+        inode.set_links_count(links);
+        if links == 0 {
+            if inode.has_block_map() {
+                self.truncate_blocks(inode, 0)?;
+            }
+            inode.set_size(0);
+            inode.set_delete_time(inode::now());
+            self.write_inode(inode_number, inode)?;
+            self.free_inode(inode_number, is_dir)
+        } else {
+            inode.touch(inode::now());
+            self.write_inode(inode_number, inode)
+        }
+    }
+}
+
+/// A file on an ext2 volume.
+///
+/// All reads and writes go directly to the underlying storage device;
+/// see [`Ext2Filesystem::flush()`] for how to ensure they are persisted.
+pub struct Ext2File {
+    fs: Ext2FsRef,
+    name: String,
+    parent: WeakDirRef,
+    inode_number: u32,
+    /// The size of this file, which is cached so that [`KnownLength::len()`] doesn't need to access the disk.
+    size: u64,
+    /// Whether this file has been deleted from its directory, after which it can no longer be used.
+    deleted: bool,
+}
+
+impl Ext2File {
+    /// Returns the inode number of this file.
+    pub fn inode_number(&self) -> u32 {
+        self.inode_number
+    }
+}
+
+impl ByteReader for Ext2File {
+    fn read_at(&mut self, buffer: &mut [u8], offset: usize) -> Result<usize, IoError> {
+        if self.deleted {
+            return Err(IoError::Other(DELETED_ERROR));
+        }
+        if offset as u64 >= self.size {
+            return Err(IoError::InvalidInput);
+        }
+        let len = (buffer.len() as u64).min(self.size - offset as u64) as usize;
+        let mut fs = self.fs.lock();
+        let inode = fs.read_inode(self.inode_number)?;
+        fs.read_inode_at(&inode, &mut buffer[..len], offset as u64)?;
+        Ok(len)
+    }
+}
+
+impl ByteWriter for Ext2File {
+    fn write_at(&mut self, buffer: &[u8], offset: usize) -> Result<usize, IoError> {
+        if self.deleted {
+            return Err(IoError::Other(DELETED_ERROR));
+        }
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let mut fs = self.fs.lock();
+        let end = offset as u64 + buffer.len() as u64;
+        if end > fs.max_file_size() {
+            return Err(IoError::Other("ext2: file size exceeds the maximum supported by the volume"));
+        }
+        let mut inode = fs.read_inode(self.inode_number)?;
+        let old_size = inode.size();
+        // Any gap between the old end of the file and the written range must read back as zeros.
+        if offset as u64 > old_size {
+            fs.zero_block_tail(&inode, old_size)?;
+        }
+        let written = fs.write_inode_at(self.inode_number, &mut inode, buffer, offset as u64);
+        // Write back the inode even if writing failed partway, so that no allocated blocks are leaked.
+        if written.is_ok() {
+            inode.set_size(old_size.max(end));
+        }
+        inode.touch(inode::now());
+        fs.write_inode(self.inode_number, &inode)?;
+        written?;
+        self.size = inode.size();
+        Ok(buffer.len())
+    }
+
+    /// Flushes the entire filesystem that this file belongs to.
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.fs.lock().flush().map_err(IoError::from)
+    }
+}
+
+impl KnownLength for Ext2File {
+    fn len(&self) -> usize {
+        self.size as usize
+    }
+}
+
+impl File for Ext2File {
+    fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
+        Err("ext2: files cannot be memory-mapped")
+    }
+
+    /// When a file is extended, its new contents read as zeros but no blocks are allocated for them.
+    /// When a file is truncated, the blocks that no longer hold any of its contents are freed.
+    fn set_len(&mut self, len: usize) -> Result<(), &'static str> {
+        if self.deleted {
+            return Err(DELETED_ERROR);
+        }
+        let len = len as u64;
+        let mut fs = self.fs.lock();
+        if len > fs.max_file_size() {
+            return Err("ext2: file size exceeds the maximum supported by the volume");
+        }
+        let mut inode = fs.read_inode(self.inode_number)?;
+        let old_len = inode.size();
+        if len < old_len {
+            // Update the size first so that the inode never refers to freed blocks beyond its size.
+            inode.set_size(len);
+            fs.write_inode(self.inode_number, &inode)?;
+            fs.truncate_blocks(&mut inode, len.div_ceil(fs.block_size() as u64))?;
+            fs.zero_block_tail(&inode, len)?;
+        } else if len > old_len {
+            fs.zero_block_tail(&inode, old_len)?;
+            inode.set_size(len);
+        }
+        inode.touch(inode::now());
+        fs.write_inode(self.inode_number, &inode)?;
+        self.size = len;
+        Ok(())
+    }
+}
+
+impl FsNode for Ext2File {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        self.parent.upgrade()
+    }
+
+    fn set_parent_dir(&mut self, new_parent: WeakDirRef) {
+        self.parent = new_parent;
+    }
+}
+
+/// A directory on an ext2 volume, whose nodes are cached and inserted as described in [`DiskDirectory`].
+///
+/// Only its regular files and subdirectories are exposed; its other entries,
+/// such as symbolic links and device nodes, are skipped.
+/// Use [`Ext2Directory::create_file()`] or [`Ext2Directory::create_dir()`] to create new nodes directly.
+pub struct Ext2Directory {
+    fs: Ext2FsRef,
+    name: String,
+    parent: WeakDirRef,
+    inode_number: u32,
+    /// The nodes of this directory that have been accessed, keyed by their names.
+    nodes: Mutex<BTreeMap<String, Ext2Node>>,
+    /// A reference to this directory, which is used as the parent of its nodes.
+    self_ref: WeakDirRef,
+    /// Whether this directory has been deleted from its parent, after which it can no longer be used.
+    deleted: bool,
+}
+
+impl Ext2Directory {
+    /// Creates the root directory of the given volume.
+    pub(crate) fn new_root(fs: Ext2FsRef, name: String, parent: WeakDirRef) -> Ext2DirRef {
+        Self::new(fs, name, parent, ROOT_INODE)
+    }
+
+    fn new(fs: Ext2FsRef, name: String, parent: WeakDirRef, inode_number: u32) -> Ext2DirRef {
+        Arc::new_cyclic(|self_ref: &Weak<Mutex<Ext2Directory>>| Mutex::new(Ext2Directory {
+            fs,
+            name,
+            parent,
+            inode_number,
+            nodes: Mutex::new(BTreeMap::new()),
+            self_ref: self_ref.clone(),
+            deleted: false,
+        }))
+    }
+
+    /// Returns the filesystem that this directory belongs to, e.g., in order to flush it.
+    pub fn filesystem(&self) -> &Ext2FsRef {
+        &self.fs
+    }
+
+    /// Returns the inode number of this directory.
+    pub fn inode_number(&self) -> u32 {
+        self.inode_number
+    }
+
+    /// Returns the node for the given entry of this directory, creating and caching it if needed.
+    ///
+    /// Returns `None` if the entry's inode is neither a regular file nor a directory.
+    fn node_for(&self, entry: &DirEntry, inode: &Inode) -> Option<Ext2Node> {
+        let mut nodes = self.nodes.lock();
+        if let Some(node) = nodes.get(&entry.name) {
+            return Some(node.clone());
+        }
+        let node = if inode.is_dir() {
+            Ext2Node::Dir(Ext2Directory::new(
+                Arc::clone(&self.fs),
+                entry.name.clone(),
+                self.self_ref.clone(),
+                entry.inode,
+            ))
+        } else if inode.is_regular_file() {
+            Ext2Node::File(Arc::new(Mutex::new(Ext2File {
+                fs: Arc::clone(&self.fs),
+                name: entry.name.clone(),
+                parent: self.self_ref.clone(),
+                inode_number: entry.inode,
+                size: inode.size(),
+                deleted: false,
+            })))
+        } else {
+            return None;
+        };
+        nodes.insert(entry.name.clone(), node.clone());
+        Some(node)
+    }
+
+    /// Finds the entry with the given `name` in this directory, along with the inode it refers to.
+    fn find(&self, fs: &mut Ext2Filesystem, name: &str) -> Result<Option<(DirEntry, Inode)>, &'static str> {
+        let dir = fs.read_inode(self.inode_number)?;
+        match fs.find_entry(&dir, name)? {
+            Some(entry) => {
+                let inode = fs.read_inode(entry.inode)?;
+                Ok(Some((entry, inode)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the file or directory with the given `name` in this directory, if any.
+    pub fn get_node(&self, name: &str) -> Result<Option<Ext2Node>, &'static str> {
+        if self.deleted {
+            return Err(DELETED_ERROR);
+        }
+        if let Some(node) = self.nodes.lock().get(name) {
+            return Ok(Some(node.clone()));
+        }
+        let found = self.find(&mut self.fs.lock(), name)?;
+        Ok(found.and_then(|(entry, inode)| self.node_for(&entry, &inode)))
+    }
+
+    /// Allocates and initializes a new inode with the given `mode`, then links it into this directory.
+    /// If `is_dir`, the new inode is given a first block that holds its `.` and `..` entries.
+    fn create_node(&self, name: &str, mode: u16, is_dir: bool) -> Result<Ext2Node, &'static str> {
+        if self.deleted {
+            return Err(DELETED_ERROR);
+        }
+        dir::validate_name(name)?;
+        let (entry, inode) = {
+            let mut fs = self.fs.lock();
+            let mut dir = fs.read_inode(self.inode_number)?;
+            if fs.find_entry(&dir, name)?.is_some() {
+                return Err("ext2: an entry with that name already exists");
+            }
+            let goal_group = fs.inode_group(self.inode_number);
+            let inode_number = fs.allocate_inode(goal_group, is_dir)?;
+            let mut inode = Inode::new(fs.superblock.inode_size, mode, inode::now());
+            let linked = self.link_new_inode(&mut fs, &mut dir, name, inode_number, &mut inode, is_dir);
+            let entry = match linked {
+                Ok(entry) => entry,
+                Err(e) => {
+                    fs.truncate_blocks(&mut inode, 0)?;
+                    inode.set_links_count(0);
+                    inode.set_delete_time(inode::now());
+                    fs.write_inode(inode_number, &inode)?;
+                    fs.free_inode(inode_number, is_dir)?;
+                    return Err(e);
+                }
+            };
+            if is_dir {
+                // The new directory's `..` entry links to this directory.
+                dir.set_links_count(dir.links_count() + 1);
+                fs.write_inode(self.inode_number, &dir)?;
+            }
+            (entry, inode)
+        };
+        self.node_for(&entry, &inode).ok_or("BUG: ext2: newly-created node was neither a file nor a directory")
+    }
+
+    /// Initializes the given newly-allocated inode and adds an entry for it to this directory,
+    /// whose inode is `dir`.
+    fn link_new_inode(
+        &self,
+        fs: &mut Ext2Filesystem,
+        dir: &mut Inode,
+        name: &str,
+        inode_number: u32,
+        inode: &mut Inode,
+        is_dir: bool,
+    ) -> Result<DirEntry, &'static str> {
+        let file_type = if is_dir {
+            let block_size = fs.block_size();
+            let (block, _) = fs.map_block(inode_number, inode, 0)?;
+            let dir_file_type = fs.dir_entry_file_type(FILE_TYPE_DIRECTORY);
+            fs.write_block(block, &dir::new_dir_block(block_size, inode_number, self.inode_number, dir_file_type))?;
+            inode.set_size(block_size as u64);
+            // A new directory is linked to by its entry in this directory and by its own `.` entry.
+            inode.set_links_count(2);
+            FILE_TYPE_DIRECTORY
+        } else {
+            FILE_TYPE_REGULAR
+        };
+        fs.write_inode(inode_number, inode)?;
+        fs.add_dir_entry(self.inode_number, dir, name, inode_number, file_type)
+    }
+
+    /// Creates a new empty file called `name` in this directory.
+    pub fn create_file(&self, name: &str) -> Result<Ext2FileRef, &'static str> {
+        match self.create_node(name, MODE_REGULAR | NEW_FILE_PERMISSIONS, false)? {
+            Ext2Node::File(file) => Ok(file),
+            Ext2Node::Dir(_) => Err("BUG: ext2: newly-created file was a directory"),
+        }
+    }
+
+    /// Creates a new empty directory called `name` in this directory.
+    pub fn create_dir(&self, name: &str) -> Result<Ext2DirRef, &'static str> {
+        match self.create_node(name, MODE_DIRECTORY | NEW_DIR_PERMISSIONS, true)? {
+            Ext2Node::Dir(dir) => Ok(dir),
+            Ext2Node::File(_) => Err("BUG: ext2: newly-created directory was a file"),
+        }
+    }
+
+    /// Deletes the entry called `name` from this directory, and frees its inode and blocks
+    /// if that was the inode's last link.
+    ///
+    /// Directories must be empty in order to be deleted.
+    /// Any existing references to the deleted node can no longer be used.
+    pub fn delete(&self, name: &str) -> Result<Ext2Node, &'static str> {
+        if self.deleted {
+            return Err(DELETED_ERROR);
+        }
+        let (entry, inode) = self.find(&mut self.fs.lock(), name)?
+            .ok_or("ext2: no such file or directory")?;
+        let node = self.node_for(&entry, &inode)
+            .ok_or("ext2: only regular files and directories can be deleted")?;
+        // Hold the lock on the node while deleting it, such that it can't be modified
+        // after its blocks have been freed.
+        let no_parent = Weak::<Mutex<Ext2Directory>>::new();
+        match &node {
+            Ext2Node::File(file) => {
+                let mut file = file.lock();
+                self.unlink_entry(&entry)?;
+                file.deleted = true;
+                file.parent = no_parent;
+            }
+            Ext2Node::Dir(dir) => {
+                let mut dir = dir.lock();
+                self.unlink_entry(&entry)?;
+                dir.deleted = true;
+                dir.parent = no_parent;
+            }
+        }
+        self.nodes.lock().remove(&entry.name);
+        Ok(node)
+    }
+
+    /// Removes the given `entry` from this directory and unlinks the inode it refers to.
+    fn unlink_entry(&self, entry: &DirEntry) -> Result<(), &'static str> {
+        let mut fs = self.fs.lock();
+        let mut inode = fs.read_inode(entry.inode)?;
+        let is_dir = inode.is_dir();
+        if is_dir && !fs.read_dir(&inode)?.is_empty() {
+            return Err("ext2: directory is not empty");
+        }
+        let mut dir = fs.read_inode(self.inode_number)?;
+        fs.remove_dir_entry(&dir, entry)?;
+        if is_dir {
+            // The deleted directory's `..` entry no longer links to this directory.
+            dir.set_links_count(dir.links_count().saturating_sub(1));
+        }
+        dir.touch(inode::now());
+        fs.write_inode(self.inode_number, &dir)?;
+        fs.unlink_inode(entry.inode, &mut inode)
+    }
+}
+
+impl DiskDirectory for Ext2Directory {
+    type Node = Ext2Node;
+    const FS_NAME: &'static str = "ext2";
+
+    fn lookup(&self, name: &str) -> Result<Option<Ext2Node>, &'static str> {
+        self.get_node(name)
+    }
+
+    fn unlink(&self, name: &str) -> Result<Ext2Node, &'static str> {
+        self.delete(name)
+    }
+
+    fn new_file(&self, name: &str) -> Result<FileRef, &'static str> {
+        Ok(self.create_file(name)?)
+    }
+
+    fn new_dir(&self, name: &str) -> Result<DirRef, &'static str> {
+        Ok(self.create_dir(name)?)
+    }
+
+    /// Returns the names of the regular files and directories in this directory.
+    fn entry_names(&self) -> Result<Vec<String>, &'static str> {
+        if self.deleted {
+            return Ok(Vec::new());
+        }
+        let mut fs = self.fs.lock();
+        let dir = fs.read_inode(self.inode_number)?;
+        let mut names = Vec::new();
+        for entry in fs.read_dir(&dir)? {
+            let inode = fs.read_inode(entry.inode)?;
+            if inode.is_dir() || inode.is_regular_file() {
+                names.push(entry.name);
+            }
+        }
+        Ok(names)
+    }
+
+    fn copy_chunk_size(&self) -> usize {
+        self.fs.lock().block_size()
+    }
+}
+
+impl Directory for Ext2Directory {
+    fn get(&self, name: &str) -> Option<FileOrDir> {
+        disk_fs::get(self, name)
+    }
+
+    fn insert(&mut self, node: FileOrDir) -> Result<Option<FileOrDir>, &'static str> {
+        disk_fs::insert(self, node)
+    }
+
+    fn remove(&mut self, node: &FileOrDir) -> Option<FileOrDir> {
+        disk_fs::remove(self, node)
+    }
+
+    fn list(&self) -> Vec<String> {
+        disk_fs::list(self)
+    }
+}
+
+impl FsNode for Ext2Directory {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        self.parent.upgrade()
+    }
+
+    fn set_parent_dir(&mut self, new_parent: WeakDirRef) {
+        self.parent = new_parent;
+    }
+}
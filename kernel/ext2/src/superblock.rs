@@ -0,0 +1,210 @@
+//! Parsing of an ext2 volume's superblock and block group descriptors.
+//!
+//! The superblock always starts 1024 bytes into the volume, regardless of the block size,
+//! and is followed in the next block by the table of block group descriptors.
+//! Only the primary copies of these are updated; the backup copies kept in other block groups
+//! are left as they were when the volume was created, as they are only used for recovery.
+
+use alloc::vec::Vec;
+
+/// The byte offset of the superblock on the volume.
+pub const SUPERBLOCK_OFFSET: usize = 1024;
+/// The size in bytes of the superblock.
+pub const SUPERBLOCK_SIZE: usize = 1024;
+/// The size in bytes of a block group descriptor.
+pub const GROUP_DESCRIPTOR_SIZE: usize = 32;
+
+const EXT2_MAGIC: u16 = 0xEF53;
+
+/// Set in the superblock's state if the volume was cleanly unmounted.
+pub const STATE_VALID: u16 = 0x0001;
+
+/// Directory entries record the type of the file they refer to.
+pub const INCOMPAT_FILETYPE: u32 = 0x0002;
+/// Block groups may be packed together, which only affects where their metadata is placed.
+pub const INCOMPAT_FLEX_BG: u32 = 0x0200;
+const SUPPORTED_INCOMPAT: u32 = INCOMPAT_FILETYPE | INCOMPAT_FLEX_BG;
+
+/// Only some block groups hold backup copies of the superblock.
+const RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+/// Regular files may be larger than 2 GiB, using the upper 32 bits of their size.
+pub const RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+const SUPPORTED_RO_COMPAT: u32 = RO_COMPAT_SPARSE_SUPER | RO_COMPAT_LARGE_FILE;
+
+const FREE_BLOCKS_COUNT_OFFSET: usize = 12;
+const FREE_INODES_COUNT_OFFSET: usize = 16;
+const WRITE_TIME_OFFSET: usize = 48;
+const STATE_OFFSET: usize = 58;
+
+/// The superblock of an ext2 volume, which describes its layout and supported features.
+///
+/// The fields that change as the volume is modified are only held in its raw bytes,
+/// such that the superblock can be written back without losing any fields not parsed here.
+#[derive(Debug, Clone)]
+pub struct Superblock {
+    raw: Vec<u8>,
+    pub inodes_count: u32,
+    pub blocks_count: u32,
+    /// The block that holds the superblock, which is `1` for 1 KiB blocks and `0` otherwise.
+    pub first_data_block: u32,
+    pub block_size: usize,
+    pub blocks_per_group: u32,
+    pub inodes_per_group: u32,
+    pub inode_size: usize,
+    /// The first inode that isn't reserved for use by the filesystem itself.
+    pub first_inode: u32,
+    pub feature_incompat: u32,
+    pub feature_ro_compat: u32,
+}
+
+impl Superblock {
+    /// Parses the given raw superblock, which must be [`SUPERBLOCK_SIZE`] bytes long.
+    ///
+    /// Returns an error if the volume uses incompatible features that aren't supported.
+    pub fn parse(raw: Vec<u8>) -> Result<Superblock, &'static str> {
+        if raw.len() < SUPERBLOCK_SIZE || read_u16(&raw, 56) != EXT2_MAGIC {
+            return Err("ext2: superblock magic number is missing");
+        }
+        let log_block_size = read_u32(&raw, 24);
+        // Entry lengths in directories are 16 bits wide, so blocks larger than 32 KiB aren't supported.
+        if log_block_size > 5 {
+            return Err("ext2: unsupported block size");
+        }
+        let rev_level = read_u32(&raw, 76);
+        let (inode_size, first_inode, feature_incompat, feature_ro_compat) = if rev_level == 0 {
+            (128, 11, 0, 0)
+        } else {
+            (read_u16(&raw, 88) as usize, read_u32(&raw, 84), read_u32(&raw, 96), read_u32(&raw, 100))
+        };
+        if feature_incompat & !SUPPORTED_INCOMPAT != 0 {
+            return Err("ext2: volume uses unsupported incompatible features");
+        }
+
+        let superblock = Superblock {
+            inodes_count: read_u32(&raw, 0),
+            blocks_count: read_u32(&raw, 4),
+            first_data_block: read_u32(&raw, 20),
+            block_size: 1024 << log_block_size,
+            blocks_per_group: read_u32(&raw, 32),
+            inodes_per_group: read_u32(&raw, 40),
+            inode_size,
+            first_inode,
+            feature_incompat,
+            feature_ro_compat,
+            raw,
+        };
+        if superblock.blocks_per_group == 0
+            || superblock.inodes_per_group == 0
+            || superblock.blocks_per_group as usize > superblock.block_size * 8
+            || superblock.inodes_per_group as usize > superblock.block_size * 8
+        {
+            return Err("ext2: invalid number of blocks or inodes per group");
+        }
+        if inode_size < 128 || !inode_size.is_power_of_two() || inode_size > superblock.block_size {
+            return Err("ext2: invalid inode size");
+        }
+        if superblock.first_data_block >= superblock.blocks_count {
+            return Err("ext2: volume has no data blocks");
+        }
+        Ok(superblock)
+    }
+
+    /// Returns `true` if the volume uses read-only compatible features that aren't supported,
+    /// in which case it can be read but must not be modified.
+    pub fn requires_read_only(&self) -> bool {
+        self.feature_ro_compat & !SUPPORTED_RO_COMPAT != 0
+    }
+
+    /// Returns the number of block groups on the volume.
+    pub fn group_count(&self) -> usize {
+        (self.blocks_count - self.first_data_block).div_ceil(self.blocks_per_group) as usize
+    }
+
+    /// Returns the block that holds the first block group descriptor.
+    pub fn group_table_block(&self) -> u32 {
+        self.first_data_block + 1
+    }
+
+    pub fn free_blocks_count(&self) -> u32 {
+        read_u32(&self.raw, FREE_BLOCKS_COUNT_OFFSET)
+    }
+
+    pub fn set_free_blocks_count(&mut self, count: u32) {
+        write_u32(&mut self.raw, FREE_BLOCKS_COUNT_OFFSET, count);
+    }
+
+    pub fn free_inodes_count(&self) -> u32 {
+        read_u32(&self.raw, FREE_INODES_COUNT_OFFSET)
+    }
+
+    pub fn set_free_inodes_count(&mut self, count: u32) {
+        write_u32(&mut self.raw, FREE_INODES_COUNT_OFFSET, count);
+    }
+
+    pub fn state(&self) -> u16 {
+        read_u16(&self.raw, STATE_OFFSET)
+    }
+
+    pub fn set_state(&mut self, state: u16) {
+        write_u16(&mut self.raw, STATE_OFFSET, state);
+    }
+
+    pub fn set_write_time(&mut self, time: u32) {
+        write_u32(&mut self.raw, WRITE_TIME_OFFSET, time);
+    }
+
+    /// Returns the raw bytes of this superblock, including any changes made to it.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+}
+
+/// The descriptor of a block group, which locates its bitmaps and inode table
+/// and counts its free blocks and inodes.
+#[derive(Debug, Clone, Copy)]
+pub struct GroupDescriptor {
+    pub block_bitmap: u32,
+    pub inode_bitmap: u32,
+    pub inode_table: u32,
+    pub free_blocks_count: u16,
+    pub free_inodes_count: u16,
+    pub used_dirs_count: u16,
+}
+
+impl GroupDescriptor {
+    /// Parses the given raw group descriptor, which must be [`GROUP_DESCRIPTOR_SIZE`] bytes long.
+    pub fn parse(raw: &[u8]) -> GroupDescriptor {
+        GroupDescriptor {
+            block_bitmap: read_u32(raw, 0),
+            inode_bitmap: read_u32(raw, 4),
+            inode_table: read_u32(raw, 8),
+            free_blocks_count: read_u16(raw, 12),
+            free_inodes_count: read_u16(raw, 14),
+            used_dirs_count: read_u16(raw, 16),
+        }
+    }
+
+    /// Writes the counts of this descriptor into the given raw group descriptor,
+    /// leaving its other contents unchanged.
+    pub fn write_to(&self, raw: &mut [u8]) {
+        write_u16(raw, 12, self.free_blocks_count);
+        write_u16(raw, 14, self.free_inodes_count);
+        write_u16(raw, 16, self.used_dirs_count);
+    }
+}
+
+pub(crate) fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+pub(crate) fn write_u16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset .. offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset .. offset + 4].copy_from_slice(&value.to_le_bytes());
+}
@@ -0,0 +1,140 @@
+//! Parsing of a FAT32 volume's boot sector, which holds its BIOS parameter block (BPB),
+//! and of its FSInfo sector, which caches the volume's free cluster count.
+
+/// The offset of the signature at the end of the boot sector and FSInfo sector.
+const BOOT_SIGNATURE_OFFSET: usize = 510;
+
+const FS_INFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FS_INFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FS_INFO_TRAIL_SIGNATURE: u32 = 0xAA55_0000;
+const FS_INFO_FREE_COUNT_OFFSET: usize = 488;
+const FS_INFO_NEXT_FREE_OFFSET: usize = 492;
+/// The value of an FSInfo field that isn't known.
+pub const FS_INFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+/// The layout of a FAT32 volume, as described by its BIOS parameter block.
+#[derive(Debug, Clone)]
+pub struct BootSector {
+    pub bytes_per_sector: u32,
+    pub sectors_per_cluster: u32,
+    /// The number of sectors before the first FAT, including the boot sector.
+    pub reserved_sectors: u32,
+    pub num_fats: u32,
+    /// The size of each FAT in sectors.
+    pub fat_size: u32,
+    pub total_sectors: u32,
+    /// The first cluster of the root directory.
+    pub root_cluster: u32,
+    /// The sector that holds the FSInfo structure, if any.
+    pub fs_info_sector: Option<u32>,
+}
+
+impl BootSector {
+    /// Parses the given boot sector, which must be at least 512 bytes long.
+    pub fn parse(sector: &[u8]) -> Result<BootSector, &'static str> {
+        if sector.len() < 512 || sector[BOOT_SIGNATURE_OFFSET .. BOOT_SIGNATURE_OFFSET + 2] != [0x55, 0xAA] {
+            return Err("fat32: boot sector signature is missing");
+        }
+        let bytes_per_sector = read_u16(sector, 11) as u32;
+        let sectors_per_cluster = sector[13] as u32;
+        let reserved_sectors = read_u16(sector, 14) as u32;
+        let num_fats = sector[16] as u32;
+        let root_entry_count = read_u16(sector, 17);
+        let total_sectors_16 = read_u16(sector, 19) as u32;
+        let fat_size_16 = read_u16(sector, 22);
+        let total_sectors_32 = read_u32(sector, 32);
+        let fat_size = read_u32(sector, 36);
+        let root_cluster = read_u32(sector, 44);
+        let fs_info_sector = read_u16(sector, 48) as u32;
+
+        if !matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096) {
+            return Err("fat32: invalid number of bytes per sector");
+        }
+        if !sectors_per_cluster.is_power_of_two() || reserved_sectors == 0 || num_fats == 0 {
+            return Err("fat32: invalid BIOS parameter block");
+        }
+        // FAT12 and FAT16 volumes have a fixed-size root directory and a 16-bit FAT size.
+        if root_entry_count != 0 || fat_size_16 != 0 || fat_size == 0 {
+            return Err("fat32: volume is not formatted as FAT32");
+        }
+        let total_sectors = if total_sectors_16 != 0 { total_sectors_16 } else { total_sectors_32 };
+
+        let boot_sector = BootSector {
+            bytes_per_sector,
+            sectors_per_cluster,
+            reserved_sectors,
+            num_fats,
+            fat_size,
+            total_sectors,
+            root_cluster,
+            fs_info_sector: (fs_info_sector != 0 && fs_info_sector < reserved_sectors).then_some(fs_info_sector),
+        };
+        if boot_sector.first_data_sector() >= total_sectors {
+            return Err("fat32: volume has no data region");
+        }
+        if root_cluster < 2 || root_cluster >= boot_sector.cluster_count() + 2 {
+            return Err("fat32: invalid root directory cluster");
+        }
+        Ok(boot_sector)
+    }
+
+    /// Returns the first sector of the data region, which starts with cluster 2.
+    pub fn first_data_sector(&self) -> u32 {
+        self.reserved_sectors + self.num_fats * self.fat_size
+    }
+
+    /// Returns the number of clusters in the data region.
+    pub fn cluster_count(&self) -> u32 {
+        let data_clusters = (self.total_sectors - self.first_data_sector()) / self.sectors_per_cluster;
+        // The FAT can't describe more clusters than it has entries for.
+        let fat_entries = self.fat_size * (self.bytes_per_sector / 4);
+        data_clusters.min(fat_entries.saturating_sub(2))
+    }
+
+    /// Returns the size of a cluster in bytes.
+    pub fn cluster_size(&self) -> u32 {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+}
+
+/// The free cluster information cached in a FAT32 volume's FSInfo sector.
+///
+/// Both values are only hints, and are [`FS_INFO_UNKNOWN`] if not known.
+#[derive(Debug, Clone, Copy)]
+pub struct FsInfo {
+    /// The number of free clusters on the volume.
+    pub free_count: u32,
+    /// The cluster from which to start searching for a free cluster.
+    pub next_free: u32,
+}
+
+impl FsInfo {
+    /// Parses the given FSInfo sector, returning `None` if its signatures are invalid.
+    pub fn parse(sector: &[u8]) -> Option<FsInfo> {
+        if sector.len() < 512
+            || read_u32(sector, 0) != FS_INFO_LEAD_SIGNATURE
+            || read_u32(sector, 484) != FS_INFO_STRUCT_SIGNATURE
+            || read_u32(sector, 508) != FS_INFO_TRAIL_SIGNATURE
+        {
+            return None;
+        }
+        Some(FsInfo {
+            free_count: read_u32(sector, FS_INFO_FREE_COUNT_OFFSET),
+            next_free: read_u32(sector, FS_INFO_NEXT_FREE_OFFSET),
+        })
+    }
+
+    /// Writes this info into the given FSInfo sector, leaving its other contents unchanged.
+    pub fn write_to(&self, sector: &mut [u8]) {
+        sector[FS_INFO_FREE_COUNT_OFFSET .. FS_INFO_FREE_COUNT_OFFSET + 4].copy_from_slice(&self.free_count.to_le_bytes());
+        sector[FS_INFO_NEXT_FREE_OFFSET .. FS_INFO_NEXT_FREE_OFFSET + 4].copy_from_slice(&self.next_free.to_le_bytes());
+    }
+}
+
+pub(crate) fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
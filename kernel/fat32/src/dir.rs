@@ -0,0 +1,181 @@
+//! Reading and modifying the contents of directories, which are stored in cluster chains
+//! just like the contents of files.
+
+use alloc::{vec, vec::Vec};
+use crate::{
+    Fat32Filesystem,
+    dir_entry::{self, DirEntry, DIR_ENTRY_SIZE, DELETED_ENTRY, END_OF_ENTRIES},
+};
+
+/// A directory can hold at most this many entries, including LFN entries.
+const MAX_DIR_ENTRIES: usize = 65536;
+
+/// The location of a file or directory's short entry within its parent directory.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct EntryLocation {
+    /// The first cluster of the parent directory.
+    pub dir_cluster: u32,
+    /// The index of the short entry within the parent directory.
+    pub index: usize,
+}
+
+impl Fat32Filesystem {
+    /// Reads the entire contents of the given cluster `chain`.
+    pub(crate) fn read_chain(&mut self, chain: &[u32]) -> Result<Vec<u8>, &'static str> {
+        let cluster_size = self.boot_sector.cluster_size() as usize;
+        let mut contents = vec![0u8; chain.len() * cluster_size];
+        for (&cluster, buf) in chain.iter().zip(contents.chunks_exact_mut(cluster_size)) {
+            self.disk.read_exact(buf, self.cluster_offset(cluster))?;
+        }
+        Ok(contents)
+    }
+
+    /// Returns the byte offset on disk of `offset` bytes into the contents held by `chain`.
+    pub(crate) fn chain_offset(&self, chain: &[u32], offset: usize) -> Result<usize, &'static str> {
+        let cluster_size = self.boot_sector.cluster_size() as usize;
+        let cluster = chain.get(offset / cluster_size).ok_or("fat32: offset is beyond the end of the cluster chain")?;
+        Ok(self.cluster_offset(*cluster) + offset % cluster_size)
+    }
+
+    /// Reads all of the entries in the directory that starts at `dir_cluster`.
+    pub(crate) fn read_dir(&mut self, dir_cluster: u32) -> Result<Vec<DirEntry>, &'static str> {
+        let chain = self.cluster_chain(dir_cluster)?;
+        let raw = self.read_chain(&chain)?;
+        Ok(dir_entry::parse_entries(&raw))
+    }
+
+    /// Finds the entry with the given `name` in the directory that starts at `dir_cluster`.
+    pub(crate) fn find_entry(&mut self, dir_cluster: u32, name: &str) -> Result<Option<DirEntry>, &'static str> {
+        Ok(self.read_dir(dir_cluster)?
+            .into_iter()
+            .find(|entry| dir_entry::names_equal(&entry.name, name)))
+    }
+
+    /// Sets the first cluster and size recorded in the short entry at the given `location`,
+    /// which also updates its modification time.
+    pub(crate) fn update_dir_entry(&mut self, location: EntryLocation, first_cluster: u32, size: u32) -> Result<(), &'static str> {
+        let offset = self.entry_offset(location)?;
+        let mut entry = [0u8; DIR_ENTRY_SIZE];
+        self.disk.read_exact(&mut entry, offset)?;
+        dir_entry::set_contents(&mut entry, first_cluster, size);
+        self.write_all(&entry, offset)
+    }
+
+    /// Returns the byte offset on disk of the short entry at the given `location`,
+    /// following its directory's cluster chain only as far as the cluster that holds the entry.
+    fn entry_offset(&mut self, location: EntryLocation) -> Result<usize, &'static str> {
+        let cluster_size = self.boot_sector.cluster_size() as usize;
+        let position = location.index * DIR_ENTRY_SIZE;
+        let mut cluster = location.dir_cluster;
+        for _ in 0 .. position / cluster_size {
+            cluster = self.next_cluster(cluster)?.ok_or("fat32: entry is beyond the end of its directory")?;
+        }
+        Ok(self.cluster_offset(cluster) + position % cluster_size)
+    }
+
+    /// Marks the given `entry` and its LFN entries as deleted.
+    ///
+    /// This does not free the clusters that hold the entry's contents.
+    pub(crate) fn remove_dir_entry(&mut self, dir_cluster: u32, entry: &DirEntry) -> Result<(), &'static str> {
+        let chain = self.cluster_chain(dir_cluster)?;
+        for index in entry.first_index ..= entry.index {
+            let offset = self.chain_offset(&chain, index * DIR_ENTRY_SIZE)?;
+            self.write_all(&[DELETED_ENTRY], offset)?;
+        }
+        Ok(())
+    }
+
+    /// Adds a new entry with the given `name` and fields to the directory that starts at `dir_cluster`,
+    /// along with as many LFN entries as are needed to hold its name.
+    ///
+    /// The directory is extended by another cluster if it has no run of free slots large enough.
+    /// Returns an error if the directory already has an entry with the same name.
+    pub(crate) fn add_dir_entry(
+        &mut self,
+        dir_cluster: u32,
+        name: &str,
+        attributes: u8,
+        first_cluster: u32,
+        size: u32,
+    ) -> Result<DirEntry, &'static str> {
+        dir_entry::validate_name(name)?;
+        let mut chain = self.cluster_chain(dir_cluster)?;
+        let mut raw = self.read_chain(&chain)?;
+        let existing = dir_entry::parse_entries(&raw);
+        if existing.iter().any(|entry| dir_entry::names_equal(&entry.name, name)) {
+            return Err("fat32: an entry with that name already exists");
+        }
+
+        let existing_short_names: Vec<[u8; 11]> = existing.iter().map(|entry| entry.short_name).collect();
+        let short_name = dir_entry::generate_short_name(name, &existing_short_names)?;
+        let mut new_entries = if short_name.needs_lfn {
+            dir_entry::long_name_entries(name, dir_entry::checksum(&short_name.raw))
+        } else {
+            Vec::new()
+        };
+        new_entries.push(dir_entry::short_entry(&short_name, attributes, first_cluster, size));
+
+        let first_index = loop {
+            if let Some(index) = find_free_run(&raw, new_entries.len()) {
+                break index;
+            }
+            if raw.len() / DIR_ENTRY_SIZE >= MAX_DIR_ENTRIES {
+                return Err("fat32: directory is full");
+            }
+            let cluster = self.allocate_cluster(chain.last().copied())?;
+            self.zero_cluster(cluster)?;
+            chain.push(cluster);
+            raw.resize(raw.len() + self.boot_sector.cluster_size() as usize, 0);
+        };
+
+        for (i, entry) in new_entries.iter().enumerate() {
+            let offset = self.chain_offset(&chain, (first_index + i) * DIR_ENTRY_SIZE)?;
+            self.write_all(entry, offset)?;
+        }
+        // Slots after the end-of-entries marker are free but not necessarily zeroed,
+        // so make sure the slot after the new entries still ends the directory if it was past the marker.
+        let next_index = first_index + new_entries.len();
+        let end_index = raw.chunks_exact(DIR_ENTRY_SIZE).position(|e| e[0] == END_OF_ENTRIES);
+        if let Some(end_index) = end_index {
+            let next_offset = next_index * DIR_ENTRY_SIZE;
+            if next_index > end_index && next_offset < raw.len() && raw[next_offset] != END_OF_ENTRIES {
+                let offset = self.chain_offset(&chain, next_offset)?;
+                self.write_all(&[END_OF_ENTRIES], offset)?;
+            }
+        }
+
+        Ok(DirEntry {
+            name: name.into(),
+            short_name: short_name.raw,
+            attributes,
+            first_cluster,
+            size,
+            first_index,
+            index: next_index - 1,
+        })
+    }
+}
+
+/// Returns the index of the first run of `count` free slots in the given raw directory contents.
+///
+/// Every slot after the first end-of-entries marker is free, regardless of its contents.
+fn find_free_run(raw: &[u8], count: usize) -> Option<usize> {
+    let mut run_start = 0;
+    let mut run_len = 0;
+    let mut past_end = false;
+    for (index, entry) in raw.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+        past_end |= entry[0] == END_OF_ENTRIES;
+        if past_end || entry[0] == DELETED_ENTRY {
+            if run_len == 0 {
+                run_start = index;
+            }
+            run_len += 1;
+            if run_len == count {
+                return Some(run_start);
+            }
+        } else {
+            run_len = 0;
+        }
+    }
+    None
+}
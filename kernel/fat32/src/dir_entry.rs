@@ -0,0 +1,360 @@
+//! The on-disk format of FAT directory entries, including long file name (LFN) entries.
+//!
+//! Each directory entry is 32 bytes long. A file's short (8.3) entry holds its attributes,
+//! first cluster, and size, and may be preceded by a sequence of LFN entries that together hold
+//! its full name in UCS-2. The LFN entries are stored in reverse order, i.e., the entry
+//! with the last part of the name comes first, and each holds a checksum of the short name.
+
+use alloc::{format, string::String, vec::Vec};
+use crate::boot_sector::{read_u16, read_u32};
+
+/// The size in bytes of a single directory entry.
+pub const DIR_ENTRY_SIZE: usize = 32;
+
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_HIDDEN:    u8 = 0x02;
+pub const ATTR_SYSTEM:    u8 = 0x04;
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE:   u8 = 0x20;
+/// The attributes of an LFN entry.
+pub const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+/// The first byte of a deleted (free) entry.
+pub const DELETED_ENTRY: u8 = 0xE5;
+/// The first byte of a free entry that is followed only by other free entries.
+pub const END_OF_ENTRIES: u8 = 0x00;
+/// Stands in for a first name byte of `0xE5`, which would otherwise mark the entry as deleted.
+const ESCAPED_DELETED: u8 = 0x05;
+
+/// Set in the order byte of the LFN entry that holds the last part of the name.
+const LFN_LAST_ENTRY: u8 = 0x40;
+const LFN_ORDER_MASK: u8 = 0x1F;
+/// The byte offsets of the 13 UCS-2 characters held by each LFN entry.
+const LFN_CHAR_OFFSETS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const LFN_CHECKSUM_OFFSET: usize = 13;
+/// The maximum length of a long file name, in UCS-2 characters.
+const MAX_NAME_LEN: usize = 255;
+
+/// Set in the NTRes byte of a short entry if its base name should be shown in lowercase.
+const NT_LOWERCASE_BASE: u8 = 0x08;
+/// Set in the NTRes byte of a short entry if its extension should be shown in lowercase.
+const NT_LOWERCASE_EXT: u8 = 0x10;
+
+const ATTR_OFFSET: usize = 11;
+const NT_RES_OFFSET: usize = 12;
+const CREATION_TIME_OFFSET: usize = 14;
+const CREATION_DATE_OFFSET: usize = 16;
+const ACCESS_DATE_OFFSET: usize = 18;
+const CLUSTER_HIGH_OFFSET: usize = 20;
+const WRITE_TIME_OFFSET: usize = 22;
+const WRITE_DATE_OFFSET: usize = 24;
+const CLUSTER_LOW_OFFSET: usize = 26;
+const SIZE_OFFSET: usize = 28;
+
+/// A file or directory found in a directory, i.e., a short entry and its long name, if any.
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    /// The long name of this entry, or its short name if it has no valid long name.
+    pub name: String,
+    /// The raw 8.3 short name of this entry, padded with spaces.
+    pub short_name: [u8; 11],
+    pub attributes: u8,
+    /// The first cluster of this entry's contents, or `0` if it has none.
+    pub first_cluster: u32,
+    /// The size in bytes of this entry's contents; always `0` for directories.
+    pub size: u32,
+    /// The index of the first slot used by this entry, i.e., its first LFN entry if it has any.
+    pub first_index: usize,
+    /// The index of the slot that holds this entry's short entry.
+    pub index: usize,
+}
+
+impl DirEntry {
+    pub fn is_dir(&self) -> bool {
+        self.attributes & ATTR_DIRECTORY != 0
+    }
+}
+
+/// Parses every file and directory entry in the given raw directory contents.
+///
+/// The `.` and `..` entries, the volume label, and deleted entries are skipped.
+pub fn parse_entries(raw: &[u8]) -> Vec<DirEntry> {
+    let mut entries = Vec::new();
+    // The LFN parts collected so far, in the order they appear on disk.
+    let mut lfn_parts: Vec<&[u8]> = Vec::new();
+    let mut lfn_checksum = 0;
+    let mut lfn_first_index = 0;
+    // The order number expected of the next LFN entry, which is `0` once the sequence is complete.
+    let mut lfn_next_order = 0;
+
+    for (index, entry) in raw.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+        match entry[0] {
+            END_OF_ENTRIES => break,
+            DELETED_ENTRY => {
+                lfn_parts.clear();
+                continue;
+            }
+            _ => {}
+        }
+
+        if entry[ATTR_OFFSET] & 0x3F == ATTR_LONG_NAME {
+            let order = entry[0] & LFN_ORDER_MASK;
+            if entry[0] & LFN_LAST_ENTRY != 0 {
+                lfn_parts.clear();
+                lfn_checksum = entry[LFN_CHECKSUM_OFFSET];
+                lfn_first_index = index;
+                lfn_next_order = order;
+            } else if lfn_parts.is_empty() || order != lfn_next_order || entry[LFN_CHECKSUM_OFFSET] != lfn_checksum {
+                // An orphaned or out-of-order LFN entry, which is ignored.
+                lfn_parts.clear();
+                continue;
+            }
+            if order == 0 {
+                lfn_parts.clear();
+                continue;
+            }
+            lfn_parts.push(entry);
+            lfn_next_order = order - 1;
+            continue;
+        }
+
+        let attributes = entry[ATTR_OFFSET];
+        let mut short_name = [0u8; 11];
+        short_name.copy_from_slice(&entry[..11]);
+        let lfn_valid = !lfn_parts.is_empty() && lfn_next_order == 0 && lfn_checksum == checksum(&short_name);
+        let long_name = if lfn_valid { decode_long_name(&lfn_parts) } else { None };
+        lfn_parts.clear();
+
+        if attributes & ATTR_VOLUME_ID != 0 || short_name[0] == b'.' {
+            continue;
+        }
+        let (name, first_index) = match long_name {
+            Some(name) => (name, lfn_first_index),
+            None => (display_short_name(&short_name, entry[NT_RES_OFFSET]), index),
+        };
+        entries.push(DirEntry {
+            name,
+            short_name,
+            attributes,
+            first_cluster: ((read_u16(entry, CLUSTER_HIGH_OFFSET) as u32) << 16) | read_u16(entry, CLUSTER_LOW_OFFSET) as u32,
+            size: read_u32(entry, SIZE_OFFSET),
+            first_index,
+            index,
+        });
+    }
+    entries
+}
+
+/// Decodes the name held by the given LFN entries, which are in on-disk order.
+fn decode_long_name(parts: &[&[u8]]) -> Option<String> {
+    let mut units = Vec::with_capacity(parts.len() * LFN_CHAR_OFFSETS.len());
+    for part in parts.iter().rev() {
+        for &offset in LFN_CHAR_OFFSETS.iter() {
+            match read_u16(part, offset) {
+                0x0000 => break,
+                unit => units.push(unit),
+            }
+        }
+    }
+    let name: String = char::decode_utf16(units.iter().copied()).collect::<Result<_, _>>().ok()?;
+    (!name.is_empty()).then_some(name)
+}
+
+/// Returns the displayable form of the given raw short name, e.g., `README.TXT`.
+fn display_short_name(short_name: &[u8; 11], nt_res: u8) -> String {
+    let mut short_name = *short_name;
+    if short_name[0] == ESCAPED_DELETED {
+        short_name[0] = DELETED_ENTRY;
+    }
+    let convert = |bytes: &[u8], lowercase: bool| -> String {
+        bytes.iter()
+            .map(|&b| if lowercase { b.to_ascii_lowercase() } else { b })
+            .map(char::from)
+            .collect::<String>()
+            .trim_end_matches(' ')
+            .into()
+    };
+    let base = convert(&short_name[..8], nt_res & NT_LOWERCASE_BASE != 0);
+    let ext = convert(&short_name[8..], nt_res & NT_LOWERCASE_EXT != 0);
+    if ext.is_empty() { base } else { format!("{base}.{ext}") }
+}
+
+/// Computes the checksum of a short name that is stored in each of its LFN entries.
+pub fn checksum(short_name: &[u8; 11]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &b| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b)
+    })
+}
+
+/// Returns `true` if the two given names refer to the same entry.
+/// As on other FAT implementations, names are compared case-insensitively.
+pub fn names_equal(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+/// Returns an error if `name` can't be used as the name of a file or directory.
+pub fn validate_name(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() || name == "." || name == ".." {
+        return Err("fat32: invalid file name");
+    }
+    if name.encode_utf16().count() > MAX_NAME_LEN {
+        return Err("fat32: file name is longer than 255 characters");
+    }
+    if name.chars().any(|c| c < ' ' || "\"*/:<>?\\|".contains(c)) {
+        return Err("fat32: file name contains a character that FAT does not allow");
+    }
+    // Other implementations silently strip trailing dots and spaces, so reject them to avoid ambiguity.
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Err("fat32: file name cannot end with a dot or space");
+    }
+    Ok(())
+}
+
+/// Returns `true` if `c` is allowed in a short name, apart from lowercase letters.
+fn is_short_name_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || "$%'-_@~`!(){}^#&".contains(c)
+}
+
+/// A short name generated for a long name.
+pub struct ShortName {
+    pub raw: [u8; 11],
+    /// The NTRes flags that make the short name display in the same case as the long name.
+    pub nt_res: u8,
+    /// Whether LFN entries are needed to store the long name,
+    /// i.e., if the short name does not exactly represent it.
+    pub needs_lfn: bool,
+}
+
+/// Generates a short name for `name` that does not conflict with any of the `existing` short names.
+///
+/// If `name` is already a valid 8.3 name, it is used as-is, with NTRes flags preserving its case
+/// if either of its parts is entirely lowercase.
+/// Otherwise, a lossy version of it is used with a numeric tail, e.g., `LONGFI~1.TXT`.
+pub fn generate_short_name(name: &str, existing: &[[u8; 11]]) -> Result<ShortName, &'static str> {
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+        _ => (name, ""),
+    };
+
+    if let Some(short_name) = exact_short_name(base, ext) {
+        if !existing.contains(&short_name.raw) {
+            return Ok(short_name);
+        }
+    }
+
+    let lossy = |part: &str, max: usize| -> Vec<u8> {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| c.to_ascii_uppercase())
+            .map(|c| if is_short_name_char(c) { c as u8 } else { b'_' })
+            .take(max)
+            .collect()
+    };
+    let base = lossy(base, 8);
+    let ext = lossy(ext, 3);
+
+    let mut raw = [b' '; 11];
+    raw[8 .. 8 + ext.len()].copy_from_slice(&ext);
+    for n in 1 ..= 999_999u32 {
+        let tail = format!("~{n}");
+        let base_len = base.len().min(8 - tail.len());
+        raw[..8].fill(b' ');
+        raw[..base_len].copy_from_slice(&base[..base_len]);
+        raw[base_len .. base_len + tail.len()].copy_from_slice(tail.as_bytes());
+        if !existing.contains(&raw) {
+            return Ok(ShortName { raw, nt_res: 0, needs_lfn: true });
+        }
+    }
+    Err("fat32: could not generate a unique short name")
+}
+
+/// Returns the short name that exactly represents the given base name and extension, if any.
+fn exact_short_name(base: &str, ext: &str) -> Option<ShortName> {
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+    // Each part must be entirely uppercase or entirely lowercase to be representable.
+    let case_flag = |part: &str, flag: u8| -> Option<u8> {
+        if part.chars().all(is_short_name_char) {
+            Some(0)
+        } else if part.chars().all(|c| is_short_name_char(c.to_ascii_uppercase()) && !c.is_ascii_uppercase()) {
+            Some(flag)
+        } else {
+            None
+        }
+    };
+    let nt_res = case_flag(base, NT_LOWERCASE_BASE)? | case_flag(ext, NT_LOWERCASE_EXT)?;
+    let mut raw = [b' '; 11];
+    raw[..base.len()].copy_from_slice(base.to_ascii_uppercase().as_bytes());
+    raw[8 .. 8 + ext.len()].copy_from_slice(ext.to_ascii_uppercase().as_bytes());
+    Some(ShortName { raw, nt_res, needs_lfn: false })
+}
+
+/// Builds the LFN entries that hold `name` for the short name with the given `checksum`,
+/// in the order they are stored on disk.
+pub fn long_name_entries(name: &str, checksum: u8) -> Vec<[u8; DIR_ENTRY_SIZE]> {
+    let units: Vec<u16> = name.encode_utf16().collect();
+    let count = units.len().div_ceil(LFN_CHAR_OFFSETS.len());
+    let mut entries = Vec::with_capacity(count);
+    for order in (1 ..= count).rev() {
+        let mut entry = [0u8; DIR_ENTRY_SIZE];
+        entry[0] = order as u8 | if order == count { LFN_LAST_ENTRY } else { 0 };
+        entry[ATTR_OFFSET] = ATTR_LONG_NAME;
+        entry[LFN_CHECKSUM_OFFSET] = checksum;
+        let start = (order - 1) * LFN_CHAR_OFFSETS.len();
+        for (i, &offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            // The name is terminated by a single null character, then padded with 0xFFFF.
+            let unit = match (start + i).cmp(&units.len()) {
+                core::cmp::Ordering::Less => units[start + i],
+                core::cmp::Ordering::Equal => 0x0000,
+                core::cmp::Ordering::Greater => 0xFFFF,
+            };
+            entry[offset .. offset + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        entries.push(entry);
+    }
+    entries
+}
+
+/// Builds a short entry with the given fields, with its creation and modification times set to now.
+pub fn short_entry(short_name: &ShortName, attributes: u8, first_cluster: u32, size: u32) -> [u8; DIR_ENTRY_SIZE] {
+    let mut entry = [0u8; DIR_ENTRY_SIZE];
+    entry[..11].copy_from_slice(&short_name.raw);
+    entry[ATTR_OFFSET] = attributes;
+    entry[NT_RES_OFFSET] = short_name.nt_res;
+    let (date, time) = fat_timestamp();
+    entry[CREATION_TIME_OFFSET .. CREATION_TIME_OFFSET + 2].copy_from_slice(&time.to_le_bytes());
+    entry[CREATION_DATE_OFFSET .. CREATION_DATE_OFFSET + 2].copy_from_slice(&date.to_le_bytes());
+    set_contents(&mut entry, first_cluster, size);
+    entry
+}
+
+/// Builds the `.` or `..` entry of a new directory, which points to the given cluster.
+pub fn dot_entry(dots: &[u8], cluster: u32) -> [u8; DIR_ENTRY_SIZE] {
+    let mut raw = [b' '; 11];
+    raw[..dots.len()].copy_from_slice(dots);
+    short_entry(&ShortName { raw, nt_res: 0, needs_lfn: false }, ATTR_DIRECTORY, cluster, 0)
+}
+
+/// Sets the first cluster and size of the given short entry, and updates its modification time.
+pub fn set_contents(entry: &mut [u8], first_cluster: u32, size: u32) {
+    let (date, time) = fat_timestamp();
+    entry[CLUSTER_HIGH_OFFSET .. CLUSTER_HIGH_OFFSET + 2].copy_from_slice(&((first_cluster >> 16) as u16).to_le_bytes());
+    entry[CLUSTER_LOW_OFFSET .. CLUSTER_LOW_OFFSET + 2].copy_from_slice(&(first_cluster as u16).to_le_bytes());
+    entry[SIZE_OFFSET .. SIZE_OFFSET + 4].copy_from_slice(&size.to_le_bytes());
+    entry[WRITE_TIME_OFFSET .. WRITE_TIME_OFFSET + 2].copy_from_slice(&time.to_le_bytes());
+    entry[WRITE_DATE_OFFSET .. WRITE_DATE_OFFSET + 2].copy_from_slice(&date.to_le_bytes());
+    entry[ACCESS_DATE_OFFSET .. ACCESS_DATE_OFFSET + 2].copy_from_slice(&date.to_le_bytes());
+}
+
+/// Returns the current local date and time in FAT's format, which can't represent years before 1980.
+fn fat_timestamp() -> (u16, u16) {
+    let now = rtc::now_local();
+    if now.year < 1980 {
+        return ((1 << 5) | 1, 0);
+    }
+    let date = ((now.year - 1980).min(127) << 9) | ((now.month as u16) << 5) | now.day as u16;
+    let time = ((now.hour as u16) << 11) | ((now.minute as u16) << 5) | (now.second as u16 / 2);
+    (date, time)
+}
@@ -0,0 +1,190 @@
+//! Reading and modifying cluster chains in the file allocation table (FAT).
+//!
+//! Each FAT32 entry is 32 bits wide, but only its lower 28 bits are meaningful;
+//! the upper 4 bits are reserved and must be preserved when an entry is modified.
+//! Every change is written to all copies of the FAT on the volume.
+
+use alloc::{vec, vec::Vec};
+use crate::{Fat32Filesystem, boot_sector::{read_u32, FS_INFO_UNKNOWN}};
+
+/// The bits of a FAT entry that hold its value.
+const FAT_ENTRY_MASK: u32 = 0x0FFF_FFFF;
+/// The value of a free cluster's FAT entry.
+const FREE_CLUSTER: u32 = 0;
+/// The value of a bad cluster's FAT entry.
+const BAD_CLUSTER: u32 = 0x0FFF_FFF7;
+/// Any FAT entry at or above this value marks the last cluster of a chain.
+const END_OF_CHAIN_MIN: u32 = 0x0FFF_FFF8;
+/// The value written into the FAT entry of the last cluster of a chain.
+const END_OF_CHAIN: u32 = 0x0FFF_FFFF;
+/// The bit of FAT entry 1 that is set while the volume is cleanly unmounted.
+pub(crate) const CLEAN_SHUTDOWN_BIT: u32 = 0x0800_0000;
+
+impl Fat32Filesystem {
+    /// Returns the byte offset on disk of the given `cluster`'s entry in the first FAT.
+    fn fat_entry_offset(&self, cluster: u32) -> usize {
+        let bps = self.boot_sector.bytes_per_sector as usize;
+        self.boot_sector.reserved_sectors as usize * bps + cluster as usize * 4
+    }
+
+    /// Reads the full 32-bit FAT entry of the given `cluster`, including its reserved bits.
+    pub(crate) fn read_fat_entry_raw(&mut self, cluster: u32) -> Result<u32, &'static str> {
+        let mut bytes = [0u8; 4];
+        self.disk.read_exact(&mut bytes, self.fat_entry_offset(cluster))?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// Writes the full 32-bit FAT entry of the given `cluster` into every copy of the FAT.
+    ///
+    /// This bypasses the dirty flag, as it is also used to set and clear the clean shutdown bit.
+    pub(crate) fn write_fat_entry_raw(&mut self, cluster: u32, value: u32) -> Result<(), &'static str> {
+        let fat_bytes = self.boot_sector.fat_size as usize * self.boot_sector.bytes_per_sector as usize;
+        let offset = self.fat_entry_offset(cluster);
+        for fat in 0 .. self.boot_sector.num_fats as usize {
+            self.disk.write_all(&value.to_le_bytes(), offset + fat * fat_bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the value of the given `cluster`'s FAT entry, preserving its reserved bits.
+    fn write_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), &'static str> {
+        self.mark_dirty()?;
+        let old = self.read_fat_entry_raw(cluster)?;
+        self.write_fat_entry_raw(cluster, (old & !FAT_ENTRY_MASK) | (value & FAT_ENTRY_MASK))
+    }
+
+    /// Returns `true` if `cluster` is a valid data cluster on this volume.
+    pub(crate) fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.boot_sector.cluster_count() + 2
+    }
+
+    /// Returns the cluster that follows `cluster` in its chain,
+    /// or `None` if `cluster` is the last one.
+    pub(crate) fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>, &'static str> {
+        match self.read_fat_entry_raw(cluster)? & FAT_ENTRY_MASK {
+            FREE_CLUSTER => Err("fat32: cluster chain contains a free cluster"),
+            BAD_CLUSTER => Err("fat32: cluster chain contains a bad cluster"),
+            next if next >= END_OF_CHAIN_MIN => Ok(None),
+            next if self.is_valid_cluster(next) => Ok(Some(next)),
+            _ => Err("fat32: cluster chain contains an invalid cluster"),
+        }
+    }
+
+    /// Returns all clusters in the chain that starts at `first_cluster`, in order.
+    ///
+    /// A `first_cluster` of `0` denotes an empty chain, e.g., that of an empty file.
+    pub(crate) fn cluster_chain(&mut self, first_cluster: u32) -> Result<Vec<u32>, &'static str> {
+        let mut chain = Vec::new();
+        if first_cluster == 0 {
+            return Ok(chain);
+        }
+        if !self.is_valid_cluster(first_cluster) {
+            return Err("fat32: cluster chain starts at an invalid cluster");
+        }
+        let mut cluster = Some(first_cluster);
+        while let Some(current) = cluster {
+            // A chain can't be longer than the number of clusters, so a longer one must be a cycle.
+            if chain.len() >= self.boot_sector.cluster_count() as usize {
+                return Err("fat32: cluster chain contains a cycle");
+            }
+            chain.push(current);
+            cluster = self.next_cluster(current)?;
+        }
+        Ok(chain)
+    }
+
+    /// Allocates a free cluster and marks it as the end of a chain.
+    ///
+    /// If `last` is given, the new cluster is appended to the chain that `last` currently ends.
+    /// The contents of the new cluster are not modified.
+    pub(crate) fn allocate_cluster(&mut self, last: Option<u32>) -> Result<u32, &'static str> {
+        let cluster_count = self.boot_sector.cluster_count();
+        let hint = self.fs_info
+            .map(|info| info.next_free)
+            .filter(|&next_free| self.is_valid_cluster(next_free))
+            .unwrap_or(2);
+
+        let bps = self.boot_sector.bytes_per_sector as usize;
+        let entries_per_sector = bps as u32 / 4;
+        let mut sector = vec![0u8; bps];
+        let mut loaded_sector = None;
+        let mut free = None;
+        for i in 0 .. cluster_count {
+            let cluster = 2 + (hint - 2 + i) % cluster_count;
+            // Read the FAT one sector at a time rather than one entry at a time.
+            let fat_sector = cluster / entries_per_sector;
+            if loaded_sector != Some(fat_sector) {
+                let offset = self.fat_entry_offset(fat_sector * entries_per_sector);
+                self.disk.read_exact(&mut sector, offset)?;
+                loaded_sector = Some(fat_sector);
+            }
+            let entry = read_u32(&sector, (cluster % entries_per_sector) as usize * 4);
+            if entry & FAT_ENTRY_MASK == FREE_CLUSTER {
+                free = Some(cluster);
+                break;
+            }
+        }
+        let cluster = free.ok_or("fat32: no free clusters remain on the volume")?;
+
+        self.write_fat_entry(cluster, END_OF_CHAIN)?;
+        if let Some(last) = last {
+            self.write_fat_entry(last, cluster)?;
+        }
+        if let Some(info) = self.fs_info.as_mut() {
+            if info.free_count != FS_INFO_UNKNOWN {
+                info.free_count = info.free_count.saturating_sub(1);
+            }
+            info.next_free = cluster + 1;
+        }
+        Ok(cluster)
+    }
+
+    /// Extends the given `chain` with newly-allocated clusters until it holds `count` clusters.
+    pub(crate) fn extend_chain(&mut self, chain: &mut Vec<u32>, count: usize) -> Result<(), &'static str> {
+        while chain.len() < count {
+            let cluster = self.allocate_cluster(chain.last().copied())?;
+            chain.push(cluster);
+        }
+        Ok(())
+    }
+
+    /// Frees every cluster in the chain that starts at `first_cluster`.
+    pub(crate) fn free_chain(&mut self, first_cluster: u32) -> Result<(), &'static str> {
+        let chain = self.cluster_chain(first_cluster)?;
+        self.free_clusters(&chain)
+    }
+
+    /// Shortens the given `chain` to its first `keep` clusters, freeing the rest.
+    ///
+    /// If `keep` is zero, the entire chain is freed.
+    pub(crate) fn truncate_chain(&mut self, chain: &[u32], keep: usize) -> Result<(), &'static str> {
+        if keep >= chain.len() {
+            return Ok(());
+        }
+        if keep > 0 {
+            self.write_fat_entry(chain[keep - 1], END_OF_CHAIN)?;
+        }
+        self.free_clusters(&chain[keep ..])
+    }
+
+    fn free_clusters(&mut self, clusters: &[u32]) -> Result<(), &'static str> {
+        for &cluster in clusters {
+            self.write_fat_entry(cluster, FREE_CLUSTER)?;
+        }
+        if let Some(info) = self.fs_info.as_mut() {
+            if info.free_count != FS_INFO_UNKNOWN {
+                info.free_count = info.free_count.saturating_add(clusters.len() as u32);
+            }
+            if let Some(&lowest) = clusters.iter().min() {
+                info.next_free = info.next_free.min(lowest);
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills the given `cluster` with zeros.
+    pub(crate) fn zero_cluster(&mut self, cluster: u32) -> Result<(), &'static str> {
+        let zeros = vec![0u8; self.boot_sector.cluster_size() as usize];
+        self.write_all(&zeros, self.cluster_offset(cluster))
+    }
+}
@@ -0,0 +1,157 @@
+//! A FAT32 filesystem with support for both reading and writing.
+//!
+//! A FAT32 volume on a [`StorageDevice`] is mounted via [`mount()`],
+//! or opened via [`open()`] such that its root directory can be mounted elsewhere.
+//! Its files and directories implement the [`File`] and [`Directory`] traits,
+//! as described in the [`disk_fs`] crate.
+//!
+//! In addition to reading, this crate supports creating and deleting files and directories,
+//! including allocating their directory entries and long file name (LFN) entries,
+//! as well as writing, extending, and truncating files.
+//!
+//! # Persistence
+//! Writes go directly to the underlying storage device, but the FSInfo sector's free cluster
+//! hints are only written back upon [`Fat32Filesystem::flush()`], which also flushes the
+//! storage device itself and marks the volume as cleanly unmounted.
+//! Until then, the volume is marked as dirty via the clean shutdown bit in its FAT,
+//! which tells other FAT implementations that it may need to be checked for consistency.
+//!
+//! [`File`]: fs_node::File
+//! [`Directory`]: fs_node::Directory
+//! [`StorageDevice`]: storage_device::StorageDevice
+
+#![no_std]
+
+extern crate alloc;
+
+mod boot_sector;
+mod dir;
+mod dir_entry;
+mod fat;
+mod node;
+
+pub use boot_sector::{BootSector, FsInfo};
+pub use node::{FatDirRef, FatDirectory, FatFile, FatFileRef, FatNode};
+
+use alloc::{sync::{Arc, Weak}, vec};
+use disk_fs::Disk;
+use fs_node::DirRef;
+use log::warn;
+use spin::Mutex;
+use storage_device::StorageDeviceRef;
+
+/// A reference to a mounted FAT32 filesystem, which is shared by all of its files and directories.
+pub type Fat32FsRef = Arc<Mutex<Fat32Filesystem>>;
+
+/// A mounted FAT32 volume.
+pub struct Fat32Filesystem {
+    disk: Disk,
+    boot_sector: BootSector,
+    /// The volume's FSInfo, if it has a valid one.
+    fs_info: Option<FsInfo>,
+    /// Whether the volume has been modified since it was mounted or last flushed.
+    dirty: bool,
+}
+
+impl Fat32Filesystem {
+    /// Reads the boot sector and FSInfo sector of the FAT32 volume on the given `device`.
+    pub fn new(device: StorageDeviceRef) -> Result<Fat32Filesystem, &'static str> {
+        let mut disk = Disk::new(device)?;
+        let mut sector = vec![0u8; 512];
+        disk.read_exact(&mut sector, 0)?;
+        let boot_sector = BootSector::parse(&sector)?;
+
+        let fs_info = match boot_sector.fs_info_sector {
+            Some(fs_info_sector) => {
+                let mut sector = vec![0u8; boot_sector.bytes_per_sector as usize];
+                disk.read_exact(&mut sector, fs_info_sector as usize * boot_sector.bytes_per_sector as usize)?;
+                FsInfo::parse(&sector)
+            }
+            None => None,
+        };
+        if fs_info.is_none() {
+            warn!("fat32: volume has no valid FSInfo sector, free cluster hints are unavailable");
+        }
+
+        let mut fs = Fat32Filesystem { disk, boot_sector, fs_info, dirty: false };
+        if fs.read_fat_entry_raw(1)? & fat::CLEAN_SHUTDOWN_BIT == 0 {
+            warn!("fat32: volume was not cleanly unmounted and may be inconsistent");
+        }
+        Ok(fs)
+    }
+
+    /// Returns the layout of this volume.
+    pub fn boot_sector(&self) -> &BootSector {
+        &self.boot_sector
+    }
+
+    /// Returns `true` if this volume has been modified since it was mounted or last flushed.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Writes back all cached metadata, flushes the underlying storage device,
+    /// and marks the volume as cleanly unmounted.
+    ///
+    /// This does nothing if the volume isn't dirty.
+    pub fn flush(&mut self) -> Result<(), &'static str> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let (Some(fs_info), Some(fs_info_sector)) = (self.fs_info, self.boot_sector.fs_info_sector) {
+            let bps = self.boot_sector.bytes_per_sector as usize;
+            let mut sector = vec![0u8; bps];
+            self.disk.read_exact(&mut sector, fs_info_sector as usize * bps)?;
+            fs_info.write_to(&mut sector);
+            self.disk.write_all(&sector, fs_info_sector as usize * bps)?;
+        }
+        let entry = self.read_fat_entry_raw(1)?;
+        self.write_fat_entry_raw(1, entry | fat::CLEAN_SHUTDOWN_BIT)?;
+        self.disk.flush()?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Marks the volume as dirty, clearing its clean shutdown bit if this is its first modification
+    /// since it was mounted or last flushed.
+    fn mark_dirty(&mut self) -> Result<(), &'static str> {
+        if !self.dirty {
+            let entry = self.read_fat_entry_raw(1)?;
+            self.write_fat_entry_raw(1, entry & !fat::CLEAN_SHUTDOWN_BIT)?;
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    /// Returns the byte offset on disk of the given data `cluster`.
+    fn cluster_offset(&self, cluster: u32) -> usize {
+        let sector = self.boot_sector.first_data_sector() as usize
+            + (cluster as usize - 2) * self.boot_sector.sectors_per_cluster as usize;
+        sector * self.boot_sector.bytes_per_sector as usize
+    }
+
+    /// Writes all of `buffer` to the disk at the given byte `offset`, marking the volume as dirty.
+    fn write_all(&mut self, buffer: &[u8], offset: usize) -> Result<(), &'static str> {
+        self.mark_dirty()?;
+        self.disk.write_all(buffer, offset)
+    }
+}
+
+/// Opens the FAT32 volume on the given storage `device` and returns its root directory,
+/// which is called `name` and isn't part of the virtual filesystem.
+///
+/// The root directory can then be mounted anywhere in the virtual filesystem via `vfs::mount()`,
+/// in which case `name` should match the final component of the mount path.
+pub fn open(device: StorageDeviceRef, name: &str) -> Result<FatDirRef, &'static str> {
+    let fs = Fat32Filesystem::new(device)?;
+    let root_cluster = fs.boot_sector.root_cluster;
+    Ok(FatDirectory::new_root(Arc::new(Mutex::new(fs)), name.into(), Weak::<Mutex<FatDirectory>>::new(), root_cluster))
+}
+
+/// Mounts the FAT32 volume on the given storage `device` as a directory called `name`
+/// within the given `parent` directory.
+///
+/// Returns the root directory of the mounted volume.
+pub fn mount(device: StorageDeviceRef, name: &str, parent: &DirRef) -> Result<FatDirRef, &'static str> {
+    disk_fs::mount(open(device, name)?, parent)
+}
@@ -0,0 +1,501 @@
+//! The files and directories of a mounted FAT32 volume, which implement the `fs_node` traits.
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use disk_fs::DiskDirectory;
+use fs_node::{DirRef, Directory, File, FileOrDir, FileRef, FsNode, WeakDirRef};
+use io::{ByteReader, ByteWriter, IoError, KnownLength};
+use memory::MappedPages;
+use spin::Mutex;
+use crate::{
+    Fat32Filesystem, Fat32FsRef,
+    dir::EntryLocation,
+    dir_entry::{self, DirEntry, ATTR_ARCHIVE, ATTR_DIRECTORY, DIR_ENTRY_SIZE},
+};
+
+/// The size of a FAT32 file is stored in 32 bits, so it can be at most 4 GiB minus one byte.
+const MAX_FILE_SIZE: usize = u32::MAX as usize;
+
+const DELETED_ERROR: &str = "fat32: file or directory was deleted";
+
+/// A reference to a file on a FAT32 volume.
+pub type FatFileRef = Arc<Mutex<FatFile>>;
+/// A reference to a directory on a FAT32 volume.
+pub type FatDirRef = Arc<Mutex<FatDirectory>>;
+
+/// Either a file or a directory on a FAT32 volume.
+///
+/// Unlike a [`FileOrDir`], this gives access to the FAT-specific methods of each node,
+/// such as [`FatDirectory::create_file()`] and [`FatFile::set_len()`].
+#[derive(Clone)]
+pub enum FatNode {
+    File(FatFileRef),
+    Dir(FatDirRef),
+}
+
+impl From<FatNode> for FileOrDir {
+    fn from(node: FatNode) -> FileOrDir {
+        match node {
+            FatNode::File(file) => FileOrDir::File(file),
+            FatNode::Dir(dir) => FileOrDir::Dir(dir),
+        }
+    }
+}
+
+impl Fat32Filesystem {
+    /// Reads `buffer.len()` bytes at the given `offset` into the contents held by `chain`.
+    fn read_chain_at(&mut self, chain: &[u32], buffer: &mut [u8], offset: usize) -> Result<(), &'static str> {
+        let cluster_size = self.boot_sector.cluster_size() as usize;
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done;
+            let len = (cluster_size - position % cluster_size).min(buffer.len() - done);
+            let disk_offset = self.chain_offset(chain, position)?;
+            self.disk.read_exact(&mut buffer[done .. done + len], disk_offset)?;
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Writes all of `buffer` at the given `offset` into the contents held by `chain`.
+    fn write_chain_at(&mut self, chain: &[u32], buffer: &[u8], offset: usize) -> Result<(), &'static str> {
+        let cluster_size = self.boot_sector.cluster_size() as usize;
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done;
+            let len = (cluster_size - position % cluster_size).min(buffer.len() - done);
+            let disk_offset = self.chain_offset(chain, position)?;
+            self.write_all(&buffer[done .. done + len], disk_offset)?;
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Fills the given byte range of the contents held by `chain` with zeros.
+    fn zero_chain_range(&mut self, chain: &[u32], start: usize, end: usize) -> Result<(), &'static str> {
+        let zeros = vec![0u8; self.boot_sector.cluster_size() as usize];
+        let mut position = start;
+        while position < end {
+            let len = (zeros.len() - position % zeros.len()).min(end - position);
+            self.write_chain_at(chain, &zeros[..len], position)?;
+            position += len;
+        }
+        Ok(())
+    }
+}
+
+/// A file on a FAT32 volume.
+///
+/// All reads and writes go directly to the underlying storage device;
+/// see [`Fat32Filesystem::flush()`] for how to ensure they are persisted.
+pub struct FatFile {
+    fs: Fat32FsRef,
+    name: String,
+    parent: WeakDirRef,
+    /// The location of this file's short entry within its parent directory.
+    location: EntryLocation,
+    /// The first cluster of this file's contents, or `0` if it is empty and has none.
+    first_cluster: u32,
+    /// All clusters of this file's contents, in order, which are read from the FAT upon first use
+    /// and then kept up to date as the file grows and shrinks.
+    chain: Option<Vec<u32>>,
+    size: u32,
+    /// Whether this file has been deleted from its directory, after which it can no longer be used.
+    deleted: bool,
+}
+
+impl FatFile {
+    /// Returns the cluster chain of this file, reading it from the FAT if it isn't yet cached.
+    fn chain(&mut self, fs: &mut Fat32Filesystem) -> Result<&mut Vec<u32>, &'static str> {
+        let chain = match self.chain.take() {
+            Some(chain) => chain,
+            None => fs.cluster_chain(self.first_cluster)?,
+        };
+        Ok(self.chain.insert(chain))
+    }
+
+    /// Extends the cluster chain of this file to hold at least `len` bytes,
+    /// allocating only the clusters that it lacks.
+    fn grow(&mut self, fs: &mut Fat32Filesystem, len: usize) -> Result<(), &'static str> {
+        let cluster_size = fs.boot_sector.cluster_size() as usize;
+        let chain = self.chain(fs)?;
+        let result = fs.extend_chain(chain, len.div_ceil(cluster_size));
+        let first_cluster = chain.first().copied();
+        // Record a newly-allocated first cluster even if allocating later ones failed,
+        // so that the clusters already added to the chain aren't leaked.
+        if let (0, Some(first_cluster)) = (self.first_cluster, first_cluster) {
+            self.first_cluster = first_cluster;
+            fs.update_dir_entry(self.location, self.first_cluster, self.size)?;
+        }
+        result
+    }
+}
+
+impl ByteReader for FatFile {
+    fn read_at(&mut self, buffer: &mut [u8], offset: usize) -> Result<usize, IoError> {
+        if self.deleted {
+            return Err(IoError::Other(DELETED_ERROR));
+        }
+        let size = self.size as usize;
+        if offset >= size {
+            return Err(IoError::InvalidInput);
+        }
+        let len = buffer.len().min(size - offset);
+        let fs_ref = Arc::clone(&self.fs);
+        let mut fs = fs_ref.lock();
+        let chain = self.chain(&mut fs)?;
+        fs.read_chain_at(chain, &mut buffer[..len], offset)?;
+        Ok(len)
+    }
+}
+
+impl ByteWriter for FatFile {
+    fn write_at(&mut self, buffer: &[u8], offset: usize) -> Result<usize, IoError> {
+        if self.deleted {
+            return Err(IoError::Other(DELETED_ERROR));
+        }
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let end = offset.checked_add(buffer.len())
+            .filter(|&end| end <= MAX_FILE_SIZE)
+            .ok_or(IoError::Other("fat32: files cannot be larger than 4 GiB"))?;
+
+        let fs_ref = Arc::clone(&self.fs);
+        let mut fs = fs_ref.lock();
+        self.grow(&mut fs, end)?;
+        let old_size = self.size as usize;
+        let chain = self.chain(&mut fs)?;
+        // Any gap between the old end of the file and the written range must read back as zeros.
+        if offset > old_size {
+            fs.zero_chain_range(chain, old_size, offset)?;
+        }
+        fs.write_chain_at(chain, buffer, offset)?;
+        self.size = self.size.max(end as u32);
+        fs.update_dir_entry(self.location, self.first_cluster, self.size)?;
+        Ok(buffer.len())
+    }
+
+    /// Flushes the entire filesystem that this file belongs to.
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.fs.lock().flush().map_err(IoError::from)
+    }
+}
+
+impl KnownLength for FatFile {
+    fn len(&self) -> usize {
+        self.size as usize
+    }
+}
+
+impl File for FatFile {
+    fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
+        Err("fat32: files cannot be memory-mapped")
+    }
+
+    /// When a file is extended, its new contents are filled with zeros.
+    /// When a file is truncated, the clusters that no longer hold any of its contents are freed.
+    fn set_len(&mut self, len: usize) -> Result<(), &'static str> {
+        if self.deleted {
+            return Err(DELETED_ERROR);
+        }
+        if len > MAX_FILE_SIZE {
+            return Err("fat32: files cannot be larger than 4 GiB");
+        }
+        let fs_ref = Arc::clone(&self.fs);
+        let mut fs = fs_ref.lock();
+        let cluster_size = fs.boot_sector.cluster_size() as usize;
+        let old_len = self.size as usize;
+
+        if len < old_len {
+            let keep = len.div_ceil(cluster_size);
+            // Read the chain before the first cluster may be cleared below.
+            self.chain(&mut fs)?;
+            if keep == 0 {
+                self.first_cluster = 0;
+            }
+            self.size = len as u32;
+            // Update the directory entry first so that it never refers to freed clusters.
+            fs.update_dir_entry(self.location, self.first_cluster, self.size)?;
+            let chain = self.chain(&mut fs)?;
+            match fs.truncate_chain(chain, keep) {
+                Ok(()) => chain.truncate(keep),
+                Err(e) => {
+                    // The chain may have been partially truncated, so it must be read again.
+                    self.chain = None;
+                    return Err(e);
+                }
+            }
+        } else if len > old_len {
+            self.grow(&mut fs, len)?;
+            let chain = self.chain(&mut fs)?;
+            fs.zero_chain_range(chain, old_len, len)?;
+            self.size = len as u32;
+            fs.update_dir_entry(self.location, self.first_cluster, self.size)?;
+        }
+        Ok(())
+    }
+}
+
+impl FsNode for FatFile {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        self.parent.upgrade()
+    }
+
+    fn set_parent_dir(&mut self, new_parent: WeakDirRef) {
+        self.parent = new_parent;
+    }
+}
+
+/// A directory on a FAT32 volume, whose nodes are cached and inserted as described in [`DiskDirectory`].
+///
+/// Use [`FatDirectory::create_file()`] or [`FatDirectory::create_dir()`] to create new nodes directly.
+pub struct FatDirectory {
+    fs: Fat32FsRef,
+    name: String,
+    parent: WeakDirRef,
+    /// The location of this directory's short entry within its parent directory,
+    /// or `None` if this is the root directory of the volume, which has no entry.
+    location: Option<EntryLocation>,
+    first_cluster: u32,
+    /// The nodes of this directory that have been accessed, keyed by their lowercase names.
+    nodes: Mutex<BTreeMap<String, FatNode>>,
+    /// A reference to this directory, which is used as the parent of its nodes.
+    self_ref: WeakDirRef,
+    /// Whether this directory has been deleted from its parent, after which it can no longer be used.
+    deleted: bool,
+}
+
+impl FatDirectory {
+    /// Creates the root directory of the given volume.
+    pub(crate) fn new_root(fs: Fat32FsRef, name: String, parent: WeakDirRef, root_cluster: u32) -> FatDirRef {
+        Self::new(fs, name, parent, None, root_cluster)
+    }
+
+    fn new(fs: Fat32FsRef, name: String, parent: WeakDirRef, location: Option<EntryLocation>, first_cluster: u32) -> FatDirRef {
+        Arc::new_cyclic(|self_ref: &Weak<Mutex<FatDirectory>>| Mutex::new(FatDirectory {
+            fs,
+            name,
+            parent,
+            location,
+            first_cluster,
+            nodes: Mutex::new(BTreeMap::new()),
+            self_ref: self_ref.clone(),
+            deleted: false,
+        }))
+    }
+
+    /// Returns the filesystem that this directory belongs to, e.g., in order to flush it.
+    pub fn filesystem(&self) -> &Fat32FsRef {
+        &self.fs
+    }
+
+    /// Returns the node for the given entry of this directory, creating and caching it if needed.
+    fn node_for(&self, entry: &DirEntry) -> FatNode {
+        let key = entry.name.to_lowercase();
+        let mut nodes = self.nodes.lock();
+        if let Some(node) = nodes.get(&key) {
+            return node.clone();
+        }
+        let location = EntryLocation { dir_cluster: self.first_cluster, index: entry.index };
+        let node = if entry.is_dir() {
+            FatNode::Dir(FatDirectory::new(
+                Arc::clone(&self.fs),
+                entry.name.clone(),
+                self.self_ref.clone(),
+                Some(location),
+                entry.first_cluster,
+            ))
+        } else {
+            FatNode::File(Arc::new(Mutex::new(FatFile {
+                fs: Arc::clone(&self.fs),
+                name: entry.name.clone(),
+                parent: self.self_ref.clone(),
+                location,
+                first_cluster: entry.first_cluster,
+                chain: None,
+                size: entry.size,
+                deleted: false,
+            })))
+        };
+        nodes.insert(key, node.clone());
+        node
+    }
+
+    /// Returns the file or directory with the given `name` in this directory, if any.
+    ///
+    /// As with other FAT implementations, names are matched case-insensitively.
+    pub fn get_node(&self, name: &str) -> Result<Option<FatNode>, &'static str> {
+        if self.deleted {
+            return Err(DELETED_ERROR);
+        }
+        if let Some(node) = self.nodes.lock().get(&name.to_lowercase()) {
+            return Ok(Some(node.clone()));
+        }
+        let entry = self.fs.lock().find_entry(self.first_cluster, name)?;
+        Ok(entry.map(|entry| self.node_for(&entry)))
+    }
+
+    /// Creates a new empty file called `name` in this directory.
+    pub fn create_file(&self, name: &str) -> Result<FatFileRef, &'static str> {
+        if self.deleted {
+            return Err(DELETED_ERROR);
+        }
+        let entry = self.fs.lock().add_dir_entry(self.first_cluster, name, ATTR_ARCHIVE, 0, 0)?;
+        match self.node_for(&entry) {
+            FatNode::File(file) => Ok(file),
+            FatNode::Dir(_) => Err("BUG: fat32: newly-created file was a directory"),
+        }
+    }
+
+    /// Creates a new empty directory called `name` in this directory.
+    pub fn create_dir(&self, name: &str) -> Result<FatDirRef, &'static str> {
+        if self.deleted {
+            return Err(DELETED_ERROR);
+        }
+        let entry = {
+            let mut fs = self.fs.lock();
+            let cluster = fs.allocate_cluster(None)?;
+            // The `..` entry of a directory in the root directory points to cluster 0.
+            let parent_cluster = if self.location.is_some() { self.first_cluster } else { 0 };
+            let mut contents = vec![0u8; fs.boot_sector.cluster_size() as usize];
+            contents[.. DIR_ENTRY_SIZE].copy_from_slice(&dir_entry::dot_entry(b".", cluster));
+            contents[DIR_ENTRY_SIZE .. 2 * DIR_ENTRY_SIZE].copy_from_slice(&dir_entry::dot_entry(b"..", parent_cluster));
+            let offset = fs.cluster_offset(cluster);
+            let added = fs.write_all(&contents, offset)
+                .and_then(|_| fs.add_dir_entry(self.first_cluster, name, ATTR_DIRECTORY, cluster, 0));
+            match added {
+                Ok(entry) => entry,
+                Err(e) => {
+                    fs.free_chain(cluster)?;
+                    return Err(e);
+                }
+            }
+        };
+        match self.node_for(&entry) {
+            FatNode::Dir(dir) => Ok(dir),
+            FatNode::File(_) => Err("BUG: fat32: newly-created directory was a file"),
+        }
+    }
+
+    /// Deletes the file or directory called `name` from this directory and frees its clusters.
+    ///
+    /// Directories must be empty in order to be deleted.
+    /// Any existing references to the deleted node can no longer be used.
+    pub fn delete(&self, name: &str) -> Result<FatNode, &'static str> {
+        if self.deleted {
+            return Err(DELETED_ERROR);
+        }
+        let entry = self.fs.lock().find_entry(self.first_cluster, name)?
+            .ok_or("fat32: no such file or directory")?;
+        let node = self.node_for(&entry);
+        // Hold the lock on the node while deleting it, such that it can't be modified
+        // after its clusters have been freed.
+        let no_parent = Weak::<Mutex<FatDirectory>>::new();
+        match &node {
+            FatNode::File(file) => {
+                let mut file = file.lock();
+                self.unlink_entry(&entry, file.first_cluster)?;
+                file.deleted = true;
+                file.parent = no_parent;
+                file.chain = None;
+            }
+            FatNode::Dir(dir) => {
+                let mut dir = dir.lock();
+                if !self.fs.lock().read_dir(dir.first_cluster)?.is_empty() {
+                    return Err("fat32: directory is not empty");
+                }
+                self.unlink_entry(&entry, dir.first_cluster)?;
+                dir.deleted = true;
+                dir.parent = no_parent;
+            }
+        }
+        self.nodes.lock().remove(&entry.name.to_lowercase());
+        Ok(node)
+    }
+
+    /// Removes the given `entry` from this directory and frees the chain that starts at `first_cluster`,
+    /// which holds the contents of the entry's node.
+    fn unlink_entry(&self, entry: &DirEntry, first_cluster: u32) -> Result<(), &'static str> {
+        let mut fs = self.fs.lock();
+        fs.remove_dir_entry(self.first_cluster, entry)?;
+        if first_cluster != 0 {
+            fs.free_chain(first_cluster)?;
+        }
+        Ok(())
+    }
+}
+
+impl DiskDirectory for FatDirectory {
+    type Node = FatNode;
+    const FS_NAME: &'static str = "fat32";
+
+    fn lookup(&self, name: &str) -> Result<Option<FatNode>, &'static str> {
+        self.get_node(name)
+    }
+
+    fn unlink(&self, name: &str) -> Result<FatNode, &'static str> {
+        self.delete(name)
+    }
+
+    fn new_file(&self, name: &str) -> Result<FileRef, &'static str> {
+        Ok(self.create_file(name)?)
+    }
+
+    fn new_dir(&self, name: &str) -> Result<DirRef, &'static str> {
+        Ok(self.create_dir(name)?)
+    }
+
+    fn entry_names(&self) -> Result<Vec<String>, &'static str> {
+        if self.deleted {
+            return Ok(Vec::new());
+        }
+        let entries = self.fs.lock().read_dir(self.first_cluster)?;
+        Ok(entries.into_iter().map(|entry| entry.name).collect())
+    }
+
+    fn copy_chunk_size(&self) -> usize {
+        self.fs.lock().boot_sector.cluster_size() as usize
+    }
+}
+
+impl Directory for FatDirectory {
+    fn get(&self, name: &str) -> Option<FileOrDir> {
+        disk_fs::get(self, name)
+    }
+
+    fn insert(&mut self, node: FileOrDir) -> Result<Option<FileOrDir>, &'static str> {
+        disk_fs::insert(self, node)
+    }
+
+    fn remove(&mut self, node: &FileOrDir) -> Option<FileOrDir> {
+        disk_fs::remove(self, node)
+    }
+
+    fn list(&self) -> Vec<String> {
+        disk_fs::list(self)
+    }
+}
+
+impl FsNode for FatDirectory {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        self.parent.upgrade()
+    }
+
+    fn set_parent_dir(&mut self, new_parent: WeakDirRef) {
+        self.parent = new_parent;
+    }
+}
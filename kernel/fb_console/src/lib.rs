@@ -0,0 +1,423 @@
+//! A text console that renders directly to the physical framebuffer,
+//! independently of the window manager.
+//!
+//! This console is used when the window manager is unavailable,
+//! e.g., if it failed to initialize, or as a fallback if it crashes later on
+//! (see [`take_over()`]), such that the machine isn't left with only serial output.
+//!
+//! Like the serial port consoles in the `console` crate, it runs the `hull` shell atop a [`tty`],
+//! with input from all keyboards. It also displays all log messages via [`logger::add_writer()`].
+//! Log messages are only enqueued by the logger and are rendered later by a separate task,
+//! such that logging doesn't draw to the framebuffer with interrupts disabled.
+//!
+//! Only basic terminal features are supported: printable ASCII characters,
+//! newlines, carriage returns, tabs, backspaces, and ANSI foreground color escape sequences.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::fmt;
+use color::Color;
+use event_types::Event;
+use font::{character_height, character_width};
+use framebuffer::{AlphaPixel, Framebuffer};
+use input_router::{InputDeviceKind, Route};
+use log::{error, info, warn};
+use mpmc::Queue;
+use shapes::Coord;
+use spin::{Mutex, Once};
+use sync_irq::{DisableIrq, IrqSafeMutex};
+use keycodes_ascii::KeyAction;
+use wait_queue::WaitQueue;
+
+/// The capacity of the queues that receive keyboard and mouse events.
+const INPUT_QUEUE_CAPACITY: usize = 100;
+
+/// The capacity of the queue of log output waiting to be rendered, in bytes.
+/// Log output that doesn't fit is discarded.
+const LOG_QUEUE_CAPACITY: usize = 16384;
+
+const DEFAULT_FOREGROUND: Color = color::LIGHT_GRAY;
+const BACKGROUND: Color = color::BLACK;
+
+/// The framebuffer console, once it has been started.
+///
+/// This is only ever locked by tasks, never in interrupt context.
+static CONSOLE: Once<Mutex<FramebufferConsole>> = Once::new();
+
+/// Holds the task waiting for input events, which is woken by the input router upon every event.
+/// This must disable interrupts because events are delivered from interrupt handlers.
+static INPUT_WAITERS: WaitQueue<DisableIrq> = WaitQueue::with_name("fb_console input");
+
+/// Holds the task waiting for log output to render, which is woken by [`LogQueue`].
+/// This must disable interrupts because messages may be logged from interrupt handlers.
+static LOG_WAITERS: WaitQueue<DisableIrq> = WaitQueue::with_name("fb_console log");
+
+/// Starts the framebuffer console on the physical framebuffer,
+/// for use when the window manager isn't running.
+///
+/// Returns the producers of keyboard and mouse events, in the same manner as `window_manager::init()`,
+/// such that input devices can be initialized with them.
+pub fn init() -> Result<(Queue<Event>, Queue<Event>), &'static str> {
+    let framebuffer = framebuffer::init::<AlphaPixel>()?;
+    let key_events = Queue::with_capacity(INPUT_QUEUE_CAPACITY);
+    let mouse_events = Queue::with_capacity(INPUT_QUEUE_CAPACITY);
+    start(framebuffer, key_events.clone(), mouse_events.clone())?;
+    Ok((key_events, mouse_events))
+}
+
+/// Starts the framebuffer console on the given `framebuffer`, which should be the physical one
+/// previously used by another display server, e.g., the window manager after it crashed.
+///
+/// The events of all currently-registered input devices are re-routed to the console.
+pub fn take_over(framebuffer: Framebuffer<AlphaPixel>) -> Result<(), &'static str> {
+    let key_events = Queue::with_capacity(INPUT_QUEUE_CAPACITY);
+    let mouse_events = Queue::with_capacity(INPUT_QUEUE_CAPACITY);
+    for device in input_router::devices() {
+        let queue = match device.kind {
+            InputDeviceKind::Keyboard => key_events.clone(),
+            InputDeviceKind::Mouse => mouse_events.clone(),
+        };
+        input_router::set_route(device.id, Route::Queue(queue))?;
+    }
+    start(framebuffer, key_events, mouse_events)
+}
+
+/// Returns `true` if the framebuffer console has been started.
+pub fn is_active() -> bool {
+    CONSOLE.is_completed()
+}
+
+fn start(
+    framebuffer: Framebuffer<AlphaPixel>,
+    key_events: Queue<Event>,
+    mouse_events: Queue<Event>,
+) -> Result<(), &'static str> {
+    if CONSOLE.is_completed() {
+        return Err("the framebuffer console was already started");
+    }
+    CONSOLE.call_once(|| Mutex::new(FramebufferConsole::new(framebuffer)));
+    input_router::add_delivery_hook(|| { INPUT_WAITERS.notify_one(); });
+
+    let log_bytes = Queue::with_capacity(LOG_QUEUE_CAPACITY);
+    spawn::new_task_builder(log_to_console_loop, log_bytes.clone())
+        .name("log_to_fb_console".into())
+        .spawn()?;
+    logger::add_writer(Arc::new(IrqSafeMutex::new(LogQueue { bytes: log_bytes })));
+
+    spawn::new_task_builder(shell_loop, (key_events, mouse_events))
+        .name("fb_console_manager".into())
+        .spawn()?;
+    info!("Started the framebuffer console");
+    Ok(())
+}
+
+/// Repeatedly runs the `hull` shell atop a tty connected to the console,
+/// restarting it whenever it exits.
+fn shell_loop((key_events, mouse_events): (Queue<Event>, Queue<Event>)) -> Result<(), &'static str> {
+    let tty = tty::Tty::new();
+    spawn::new_task_builder(tty_to_console_loop, tty.master())
+        .name("tty_to_fb_console".into())
+        .spawn()?;
+    spawn::new_task_builder(input_to_tty_loop, (key_events, mouse_events, tty.master()))
+        .name("fb_console_input_to_tty".into())
+        .spawn()?;
+
+    loop {
+        let new_app_ns = mod_mgmt::create_application_namespace(None)?;
+        let (app_file, _ns) = mod_mgmt::CrateNamespace::get_crate_object_file_starting_with(&new_app_ns, "hull-")
+            .ok_or("couldn't find hull in default app namespace")?;
+        let path = app_file.lock().get_absolute_path();
+        let task = spawn::new_application_task_builder(path.as_ref(), Some(new_app_ns))?
+            .name("fb_console_hull".into())
+            .block()
+            .spawn()?;
+
+        let stream = Arc::new(tty.slave());
+        app_io::insert_child_streams(
+            task.id,
+            app_io::IoStreams {
+                discipline: Some(stream.discipline()),
+                stdin: stream.clone(),
+                stdout: stream.clone(),
+                stderr: stream,
+            },
+        );
+        task.unblock().map_err(|_| "couldn't unblock hull task")?;
+        if let Err(e) = task.join() {
+            error!("couldn't join the framebuffer console's shell: {e}");
+        }
+        warn!("the framebuffer console's shell exited, restarting it");
+    }
+}
+
+fn tty_to_console_loop(master: tty::Master) {
+    let console = CONSOLE.get().expect("BUG: the framebuffer console was not initialized");
+    let mut data = [0; 256];
+    loop {
+        match master.read(&mut data) {
+            Ok(len) => console.lock().write_bytes(&data[..len]),
+            Err(e) => error!("couldn't read from master: {e}"),
+        }
+    }
+}
+
+/// Renders the log output enqueued by [`LogQueue`].
+fn log_to_console_loop(log_bytes: Queue<u8>) {
+    let console = CONSOLE.get().expect("BUG: the framebuffer console was not initialized");
+    loop {
+        let byte = LOG_WAITERS.wait_until(|| log_bytes.pop());
+        let mut console = console.lock();
+        console.write_byte(byte);
+        while let Some(byte) = log_bytes.pop() {
+            console.write_byte(byte);
+        }
+    }
+}
+
+/// Converts key presses into bytes written to the tty, and discards mouse events.
+fn input_to_tty_loop((key_events, mouse_events, master): (Queue<Event>, Queue<Event>, tty::Master)) {
+    loop {
+        let event = INPUT_WAITERS.wait_until(|| {
+            while mouse_events.pop().is_some() { }
+            key_events.pop()
+        });
+        let Event::KeyboardEvent(input) = event else { continue };
+        let key_event = input.key_event;
+        if key_event.action != KeyAction::Pressed {
+            continue;
+        }
+        let Some(c) = key_event.keycode.to_ascii(key_event.modifiers) else { continue };
+        let mut byte = c as u8;
+        if key_event.modifiers.is_control() && byte.is_ascii_alphabetic() {
+            // Ctrl+A through Ctrl+Z produce the control characters 0x01 through 0x1A.
+            byte = byte.to_ascii_uppercase() - b'A' + 1;
+        }
+        if let Err(e) = master.write_byte(byte) {
+            error!("couldn't write to master: {e}");
+        }
+    }
+}
+
+/// A log writer that enqueues log output for [`log_to_console_loop()`] to render.
+struct LogQueue {
+    bytes: Queue<u8>,
+}
+
+impl fmt::Write for LogQueue {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.bytes.push(byte).is_err() {
+                break;
+            }
+        }
+        LOG_WAITERS.notify_one();
+        Ok(())
+    }
+}
+
+/// The state of parsing an ANSI escape sequence.
+enum EscapeState {
+    None,
+    /// An escape character was received.
+    Escape,
+    /// A control sequence introducer (`ESC [`) was received, followed by the given parameters.
+    Csi { params: [u16; 4], count: usize },
+}
+
+/// A single character on the screen.
+#[derive(Clone, Copy)]
+struct Cell {
+    byte: u8,
+    foreground: Color,
+}
+
+const BLANK: Cell = Cell { byte: b' ', foreground: DEFAULT_FOREGROUND };
+
+/// A grid of text characters rendered onto a framebuffer.
+pub struct FramebufferConsole {
+    framebuffer: Framebuffer<AlphaPixel>,
+    columns: usize,
+    rows: usize,
+    /// The characters on the screen, row by row.
+    /// These are kept such that the screen can be redrawn when scrolling
+    /// without reading from the framebuffer, which is slow.
+    cells: Vec<Cell>,
+    column: usize,
+    row: usize,
+    foreground: Color,
+    escape: EscapeState,
+}
+
+impl FramebufferConsole {
+    /// Creates a new console that renders to the given framebuffer, clearing it.
+    pub fn new(mut framebuffer: Framebuffer<AlphaPixel>) -> Self {
+        let (width, height) = framebuffer.get_size();
+        let columns = (width / character_width()).max(1);
+        let rows = (height / character_height()).max(1);
+        framebuffer.fill(BACKGROUND.into());
+        FramebufferConsole {
+            framebuffer,
+            columns,
+            rows,
+            cells: vec![BLANK; columns * rows],
+            column: 0,
+            row: 0,
+            foreground: DEFAULT_FOREGROUND,
+            escape: EscapeState::None,
+        }
+    }
+
+    /// Returns the `(columns, rows)` of text that fit on the screen.
+    pub fn size(&self) -> (usize, usize) {
+        (self.columns, self.rows)
+    }
+
+    /// Clears the screen and moves the cursor to the top-left corner.
+    pub fn clear(&mut self) {
+        self.cells.fill(BLANK);
+        self.column = 0;
+        self.row = 0;
+        self.framebuffer.fill(BACKGROUND.into());
+    }
+
+    /// Writes the given bytes to the console.
+    ///
+    /// Multi-byte UTF-8 characters are displayed as `?`.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        match self.escape {
+            EscapeState::None => { }
+            EscapeState::Escape => {
+                self.escape = if byte == b'[' {
+                    EscapeState::Csi { params: [0; 4], count: 0 }
+                } else {
+                    EscapeState::None
+                };
+                return;
+            }
+            EscapeState::Csi { ref mut params, ref mut count } => {
+                match byte {
+                    b'0' ..= b'9' => {
+                        if let Some(param) = params.get_mut(*count) {
+                            *param = param.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                        }
+                    }
+                    b';' => *count += 1,
+                    _ => {
+                        let (params, count) = (*params, (*count + 1).min(params.len()));
+                        self.escape = EscapeState::None;
+                        if byte == b'm' {
+                            self.select_graphic_rendition(&params[..count]);
+                        }
+                    }
+                }
+                return;
+            }
+        }
+
+        match byte {
+            0x1B => self.escape = EscapeState::Escape,
+            b'\n' => self.newline(),
+            b'\r' => self.column = 0,
+            b'\t' => {
+                let next_tab_stop = (self.column / 8 + 1) * 8;
+                while self.column < next_tab_stop.min(self.columns) {
+                    self.put(b' ');
+                }
+            }
+            0x08 | 0x7F => {
+                if self.column > 0 {
+                    self.column -= 1;
+                    self.set_cell(self.column, self.row, BLANK);
+                }
+            }
+            // UTF-8 continuation bytes
+            0x80 ..= 0xBF => { }
+            // UTF-8 leading bytes
+            0xC0 ..= 0xFF => self.put(b'?'),
+            b if b.is_ascii_graphic() || b == b' ' => self.put(b),
+            _ => { }
+        }
+    }
+
+    /// Handles the `ESC [ ... m` sequence, of which only foreground colors are supported.
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        for &param in params {
+            self.foreground = match param {
+                0 | 39 => DEFAULT_FOREGROUND,
+                30 => color::DARK_GRAY,
+                31 => color::RED,
+                32 => color::GREEN,
+                33 => color::YELLOW,
+                34 => color::BLUE,
+                35 => color::MAGENTA,
+                36 => color::CYAN,
+                37 => color::WHITE,
+                _ => continue,
+            };
+        }
+    }
+
+    /// Writes a printable character at the cursor and advances it.
+    fn put(&mut self, byte: u8) {
+        if self.column >= self.columns {
+            self.newline();
+        }
+        self.set_cell(self.column, self.row, Cell { byte, foreground: self.foreground });
+        self.column += 1;
+    }
+
+    fn newline(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    /// Moves all lines up by one and redraws the whole screen.
+    fn scroll(&mut self) {
+        self.cells.copy_within(self.columns .., 0);
+        let last_row_start = (self.rows - 1) * self.columns;
+        self.cells[last_row_start ..].fill(BLANK);
+        for row in 0 .. self.rows {
+            for column in 0 .. self.columns {
+                self.draw_cell(column, row);
+            }
+        }
+    }
+
+    fn set_cell(&mut self, column: usize, row: usize, cell: Cell) {
+        self.cells[row * self.columns + column] = cell;
+        self.draw_cell(column, row);
+    }
+
+    fn draw_cell(&mut self, column: usize, row: usize) {
+        let cell = self.cells[row * self.columns + column];
+        framebuffer_printer::print_ascii_character(
+            &mut self.framebuffer,
+            cell.byte,
+            cell.foreground.into(),
+            BACKGROUND.into(),
+            Coord::new(0, 0),
+            column,
+            row,
+        );
+    }
+}
+
+impl fmt::Write for FramebufferConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+//! A per-task table of open files, which provides POSIX-style stateful file access
+//! on top of the stateless `fs_node` traits.
+//!
+//! The [`File`] trait only offers access at explicit offsets via [`ByteReader::read_at()`]
+//! and [`ByteWriter::write_at()`], whereas applications ported from POSIX environments expect
+//! file descriptors that track their own offsets. [`open()`] adds a file to the current task's
+//! table under the lowest free file descriptor, starting at [`FIRST_FD`].
+//! [`read()`], [`write()`], and [`seek()`] then operate on and advance that file descriptor's offset
+//! until it is released via [`close()`].
+//!
+//! The table is stored in task-local storage, so it is neither shared with nor inherited by other tasks,
+//! and any files that a task leaves open are closed when it exits.
+//!
+//! [`File`]: fs_node::File
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use bitflags::bitflags;
+use core::{cell::RefCell, fmt};
+use fs_node::{DirRef, FileOrDir, FileRef};
+use io::{ByteReader, ByteWriter, IoError, KnownLength};
+use memfs::MemFile;
+use path::Path;
+use thread_local_macro::thread_local;
+
+pub use core2::io::SeekFrom;
+
+/// A file descriptor, which identifies an open file of the current task.
+pub type Fd = usize;
+
+/// The lowest file descriptor given to an open file.
+///
+/// File descriptors `0`, `1`, and `2` are reserved for standard input, output, and error,
+/// which are provided by the `app_io` crate rather than by this table.
+pub const FIRST_FD: Fd = 3;
+
+/// The maximum number of files that a task can have open at once.
+pub const MAX_OPEN_FILES: usize = 1024;
+
+bitflags! {
+    /// Options for how a file is opened, like the `O_*` flags of POSIX `open()`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OpenFlags: u32 {
+        /// The file can be read from.
+        const READ      = 1 << 0;
+        /// The file can be written to.
+        const WRITE     = 1 << 1;
+        /// The file can be written to, but only at its end, regardless of the current offset.
+        const APPEND    = 1 << 2;
+        /// The file is created if it doesn't exist.
+        /// Requires `WRITE` or `APPEND`.
+        const CREATE    = 1 << 3;
+        /// Along with `CREATE`, the file must not already exist.
+        const EXCLUSIVE = 1 << 4;
+        /// The file's existing contents are discarded by truncating it in place.
+        /// Requires `WRITE` or `APPEND`.
+        const TRUNCATE  = 1 << 5;
+    }
+}
+
+thread_local! {
+    /// The open files of the current task.
+    static OPEN_FILES: RefCell<OpenFileTable> = RefCell::new(OpenFileTable { files: Vec::new() });
+}
+
+/// An open file, along with the state of the file descriptor that refers to it.
+struct OpenFile {
+    file: FileRef,
+    flags: OpenFlags,
+    /// The offset at which the next read or write occurs.
+    offset: usize,
+}
+
+/// A table of open files, in which each file descriptor minus [`FIRST_FD`] is an index.
+struct OpenFileTable {
+    files: Vec<Option<OpenFile>>,
+}
+
+impl OpenFileTable {
+    /// Adds the given file under the lowest free file descriptor, which is returned.
+    fn insert(&mut self, open_file: OpenFile) -> Result<Fd> {
+        let index = match self.files.iter().position(Option::is_none) {
+            Some(index) => {
+                self.files[index] = Some(open_file);
+                index
+            }
+            None if self.files.len() < MAX_OPEN_FILES => {
+                self.files.push(Some(open_file));
+                self.files.len() - 1
+            }
+            None => return Err(Error::TooManyOpenFiles),
+        };
+        Ok(index + FIRST_FD)
+    }
+
+    fn get_mut(&mut self, fd: Fd) -> Option<&mut OpenFile> {
+        self.files.get_mut(fd.checked_sub(FIRST_FD)?)?.as_mut()
+    }
+
+    fn remove(&mut self, fd: Fd) -> Option<OpenFile> {
+        let open_file = self.files.get_mut(fd.checked_sub(FIRST_FD)?)?.take();
+        while matches!(self.files.last(), Some(None)) {
+            self.files.pop();
+        }
+        open_file
+    }
+}
+
+/// Opens the file at the given `path`, relative to the current task's working directory,
+/// and returns a new file descriptor that refers to it.
+pub fn open(path: &Path, flags: OpenFlags) -> Result<Fd> {
+    let writable = flags.intersects(OpenFlags::WRITE | OpenFlags::APPEND);
+    if !(writable || flags.contains(OpenFlags::READ))
+        || (flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNCATE) && !writable)
+    {
+        return Err(Error::InvalidInput);
+    }
+
+    let working_dir = task::with_current_task(|t| t.get_env().lock().working_dir.clone())
+        .map_err(|_| Error::Other("fd_table: couldn't get the current task"))?;
+    let file = match path.get(&working_dir) {
+        Some(FileOrDir::File(file)) => {
+            if flags.contains(OpenFlags::CREATE | OpenFlags::EXCLUSIVE) {
+                return Err(Error::AlreadyExists);
+            }
+            if flags.contains(OpenFlags::TRUNCATE) {
+                file.lock().set_len(0).map_err(Error::Other)?;
+            }
+            file
+        }
+        Some(FileOrDir::Dir(_)) => return Err(Error::IsADirectory),
+        None if flags.contains(OpenFlags::CREATE) => create(path, &working_dir)?,
+        None => return Err(Error::NotFound),
+    };
+
+    OPEN_FILES.with(|table| table.borrow_mut().insert(OpenFile { file, flags, offset: 0 }))
+}
+
+/// Reads from the given open file into `buffer`, starting at its current offset,
+/// which is then advanced by the number of bytes read.
+///
+/// Returns the number of bytes read, which is `0` at the end of the file.
+pub fn read(fd: Fd, buffer: &mut [u8]) -> Result<usize> {
+    with_open_file(fd, |open_file| {
+        if !open_file.flags.contains(OpenFlags::READ) {
+            return Err(Error::PermissionDenied);
+        }
+        let mut file = open_file.file.lock();
+        // `read_at()` fails at the end of a file, whereas POSIX `read()` reads nothing.
+        if buffer.is_empty() || open_file.offset >= file.len() {
+            return Ok(0);
+        }
+        let bytes_read = file.read_at(buffer, open_file.offset)?;
+        open_file.offset += bytes_read;
+        Ok(bytes_read)
+    })
+}
+
+/// Writes `buffer` to the given open file, starting at its current offset,
+/// which is then advanced by the number of bytes written.
+///
+/// If the file was opened with [`OpenFlags::APPEND`], the offset is first moved to the end of the file.
+pub fn write(fd: Fd, buffer: &[u8]) -> Result<usize> {
+    with_open_file(fd, |open_file| {
+        if !open_file.flags.intersects(OpenFlags::WRITE | OpenFlags::APPEND) {
+            return Err(Error::PermissionDenied);
+        }
+        let mut file = open_file.file.lock();
+        if open_file.flags.contains(OpenFlags::APPEND) {
+            open_file.offset = file.len();
+        }
+        let bytes_written = file.write_at(buffer, open_file.offset)?;
+        open_file.offset += bytes_written;
+        Ok(bytes_written)
+    })
+}
+
+/// Moves the offset of the given open file to the given `position`, and returns the new offset.
+///
+/// The offset may be moved beyond the end of the file, in which case a later write
+/// extends the file.
+pub fn seek(fd: Fd, position: SeekFrom) -> Result<u64> {
+    with_open_file(fd, |open_file| {
+        let new_offset = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => (open_file.file.lock().len() as u64).checked_add_signed(delta),
+            SeekFrom::Current(delta) => (open_file.offset as u64).checked_add_signed(delta),
+        }.ok_or(Error::InvalidInput)?;
+        open_file.offset = usize::try_from(new_offset).map_err(|_| Error::InvalidInput)?;
+        Ok(new_offset)
+    })
+}
+
+/// Flushes the given open file, ensuring that all of its contents are written out.
+pub fn flush(fd: Fd) -> Result<()> {
+    with_open_file(fd, |open_file| open_file.file.lock().flush().map_err(Error::from))
+}
+
+/// Returns the file that the given file descriptor refers to.
+pub fn file(fd: Fd) -> Result<FileRef> {
+    with_open_file(fd, |open_file| Ok(open_file.file.clone()))
+}
+
+/// Closes the given file descriptor, which can then be reused by a later [`open()`].
+pub fn close(fd: Fd) -> Result<()> {
+    OPEN_FILES.with(|table| table.borrow_mut().remove(fd))
+        .map(drop)
+        .ok_or(Error::BadFileDescriptor)
+}
+
+/// Invokes the given function on the current task's open file with the given file descriptor.
+fn with_open_file<F, R>(fd: Fd, function: F) -> Result<R>
+    where F: FnOnce(&mut OpenFile) -> Result<R>
+{
+    OPEN_FILES.with(|table| {
+        let mut table = table.borrow_mut();
+        let open_file = table.get_mut(fd).ok_or(Error::BadFileDescriptor)?;
+        function(open_file)
+    })
+}
+
+/// Creates a new empty file at the given `path`, whose parent directory must already exist.
+fn create(path: &Path, working_dir: &DirRef) -> Result<FileRef> {
+    let name = path.file_name().ok_or(Error::InvalidInput)?;
+    let parent = path.parent()
+        .and_then(|parent| parent.get_dir(working_dir))
+        .ok_or(Error::NotFound)?;
+    create_in(name.into(), &parent)
+}
+
+/// Creates a new empty file called `name` in the given `parent` directory.
+fn create_in(name: String, parent: &DirRef) -> Result<FileRef> {
+    MemFile::create(name.clone(), parent).map_err(Error::Other)?;
+    // Directories that can only store their own kind of file, such as those of a FAT32 or ext2 volume,
+    // copy the new file rather than storing it, so the file must be looked up again.
+    parent.lock().get_file(&name).ok_or(Error::Other("fd_table: newly-created file was not found"))
+}
+
+/// A specialized [`Result`] type for open file operations.
+///
+/// [`Result`]: core::result::Result
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// The error type for open file operations.
+#[derive(Debug)]
+pub enum Error {
+    /// The file descriptor doesn't refer to an open file.
+    BadFileDescriptor,
+    /// No file exists at the given path.
+    NotFound,
+    /// A file already exists at the given path, but exclusive creation was requested.
+    AlreadyExists,
+    /// The path refers to a directory, which can't be opened.
+    IsADirectory,
+    /// The file wasn't opened for the requested kind of access.
+    PermissionDenied,
+    /// The given flags or offset were invalid.
+    InvalidInput,
+    /// The current task has [`MAX_OPEN_FILES`] files open already.
+    TooManyOpenFiles,
+    /// Reading from or writing to the file failed.
+    Io(IoError),
+    /// Another error occurred.
+    Other(&'static str),
+}
+
+impl From<IoError> for Error {
+    fn from(e: IoError) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadFileDescriptor => f.write_str("bad file descriptor"),
+            Error::NotFound => f.write_str("no such file"),
+            Error::AlreadyExists => f.write_str("file already exists"),
+            Error::IsADirectory => f.write_str("is a directory"),
+            Error::PermissionDenied => f.write_str("file was not opened for this kind of access"),
+            Error::InvalidInput => f.write_str("invalid input"),
+            Error::TooManyOpenFiles => f.write_str("too many open files"),
+            Error::Io(e) => write!(f, "I/O error: {e:?}"),
+            Error::Other(s) => f.write_str(s),
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Maps the contents of a file into the current task's address space,
+//! similar to `mmap()` on POSIX systems.
+//!
+//! A [`FileMapping`] is backed by its own `MappedPages`, which are populated with
+//! the file's contents when the mapping is created, so reads go directly through memory.
+//! Because Theseus does not allow multiple mappings to alias the same frames,
+//! the file itself is not modified when a mapping is written to.
+//! Instead, a [`MappingKind::Shared`] mapping writes its modified pages back to the file
+//! upon [`FileMapping::sync()`] (like `msync()`) and when it is dropped (like `munmap()`).
+//! Only pages written to since the previous sync are written back, as determined by their `DIRTY` bits;
+//! see [`MappedPages::dirty_pages()`]. Each page's `DIRTY` bit is only cleared once it has been written back,
+//! so a page that couldn't be written back is retried by the next sync.
+//!
+//! This works for any [`File`], e.g., a `MemFile` or a file on a block device.
+//! Files that can be viewed as a memory mapping via [`File::as_mapping()`]
+//! are populated with a single copy rather than via repeated reads.
+//!
+//! [`File`]: fs_node::File
+//! [`File::as_mapping()`]: fs_node::File::as_mapping
+
+#![no_std]
+
+use core::cmp::min;
+use fs_node::{FileRef, FsNode};
+use io::{ByteReader, ByteWriter, KnownLength};
+use kernel_config::memory::PAGE_SIZE;
+use log::error;
+use memory::{MappedPages, MmiRef, PteFlags};
+
+/// Whether modifications to a [`FileMapping`] are written back to the underlying file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MappingKind {
+    /// Modifications are private to the mapping and never written back, like `MAP_PRIVATE`.
+    Private,
+    /// Modifications are written back to the file upon [`FileMapping::sync()`]
+    /// and when the mapping is dropped, like `MAP_SHARED`.
+    Shared,
+}
+
+/// A region of a file that is mapped into the current task's address space.
+///
+/// The mapped region can be accessed via [`FileMapping::as_slice()`] and [`FileMapping::as_slice_mut()`],
+/// or directly via the underlying `MappedPages` from [`FileMapping::mapped_pages()`].
+pub struct FileMapping {
+    /// The file that this mapping was created from.
+    file: FileRef,
+    /// The byte offset into the file at which this mapping begins.
+    offset: usize,
+    /// The number of bytes of the file that are mapped.
+    len: usize,
+    /// The memory that holds the mapped contents of the file.
+    pages: MappedPages,
+    /// The address space that `pages` are mapped into.
+    mmi: MmiRef,
+    kind: MappingKind,
+}
+
+impl FileMapping {
+    /// Maps `len` bytes of the given `file`, starting at byte `offset`,
+    /// into the current task's address space.
+    ///
+    /// If `len` is `None`, the rest of the file from `offset` onwards is mapped.
+    /// If `writable` is `false`, the mapping is read-only.
+    ///
+    /// Returns an error if the requested region is empty or extends past the end of the file.
+    pub fn new(
+        file: &FileRef,
+        offset: usize,
+        len: Option<usize>,
+        writable: bool,
+        kind: MappingKind,
+    ) -> Result<FileMapping, &'static str> {
+        let mmi = task::with_current_task(|t| t.mmi.clone())
+            .map_err(|_| "FileMapping::new(): couldn't get current task")?;
+
+        let mut locked_file = file.lock();
+        let file_len = locked_file.len();
+        let len = match len {
+            Some(len) => len,
+            None => file_len.checked_sub(offset).ok_or("FileMapping::new(): offset was past the end of the file")?,
+        };
+        if len == 0 {
+            return Err("FileMapping::new(): cannot map zero bytes");
+        }
+        if offset.checked_add(len).map_or(true, |end| end > file_len) {
+            return Err("FileMapping::new(): region extends past the end of the file");
+        }
+
+        // The pages must be writable in order to populate them with the file's contents.
+        let allocated_pages = memory::allocate_pages_by_bytes(len)
+            .ok_or("FileMapping::new(): couldn't allocate pages")?;
+        let mut pages = mmi.lock().page_table.map_allocated_pages(
+            allocated_pages,
+            PteFlags::new().valid(true).writable(true),
+        )?;
+
+        let dest = pages.as_slice_mut::<u8>(0, len)?;
+        if let Ok(mp) = locked_file.as_mapping() {
+            dest.copy_from_slice(mp.as_slice(offset, len)?);
+        } else {
+            let mut bytes_read = 0;
+            while bytes_read < len {
+                match locked_file.read_at(&mut dest[bytes_read..], offset + bytes_read)? {
+                    0 => return Err("FileMapping::new(): file ended before the mapped region was populated"),
+                    n => bytes_read += n,
+                }
+            }
+        }
+        drop(locked_file);
+
+        {
+            let mut mmi_locked = mmi.lock();
+            if !writable {
+                pages.remap(&mut mmi_locked.page_table, PteFlags::new().valid(true))?;
+            }
+            // Clear the DIRTY bits set while populating the mapping,
+            // such that only later modifications are written back.
+            pages.take_dirty_pages(&mut mmi_locked.page_table)?;
+        }
+
+        Ok(FileMapping { file: file.clone(), offset, len, pages, mmi, kind })
+    }
+
+    /// Returns the mapped contents of the file.
+    pub fn as_slice(&self) -> Result<&[u8], &'static str> {
+        self.pages.as_slice(0, self.len)
+    }
+
+    /// Returns the mapped contents of the file as a mutable slice.
+    ///
+    /// Returns an error if this mapping is read-only.
+    pub fn as_slice_mut(&mut self) -> Result<&mut [u8], &'static str> {
+        self.pages.as_slice_mut(0, self.len)
+    }
+
+    /// Returns the underlying `MappedPages` that hold the mapped contents of the file.
+    ///
+    /// Note that the mapped pages may extend beyond the mapped region of the file,
+    /// up to the next page boundary.
+    pub fn mapped_pages(&self) -> &MappedPages {
+        &self.pages
+    }
+
+    /// Returns the file that this mapping was created from.
+    pub fn file(&self) -> &FileRef {
+        &self.file
+    }
+
+    /// Returns the byte offset into the file at which this mapping begins.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the number of bytes of the file that are mapped.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no bytes of the file are mapped, which is never the case.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns whether modifications to this mapping are written back to the file.
+    pub fn kind(&self) -> MappingKind {
+        self.kind
+    }
+
+    /// Writes the pages of this mapping that were modified since it was created
+    /// or since the previous call to this function back to the file, like `msync()`.
+    ///
+    /// Returns the number of bytes written to the file,
+    /// which is always `0` for a [`MappingKind::Private`] mapping.
+    pub fn sync(&mut self) -> Result<usize, &'static str> {
+        if self.kind == MappingKind::Private {
+            return Ok(0);
+        }
+        let dirty_pages = self.pages.dirty_pages(&mut self.mmi.lock().page_table)?;
+        if dirty_pages.is_empty() {
+            return Ok(0);
+        }
+
+        let mut file = self.file.lock();
+        let mut bytes_written = 0;
+        for page_index in dirty_pages {
+            let start = page_index * PAGE_SIZE;
+            if start < self.len {
+                let end = min(start + PAGE_SIZE, self.len);
+                let src = self.pages.as_slice::<u8>(start, end - start)?;
+                bytes_written += file.write_at(src, self.offset + start)?;
+            }
+            // Only `&mut self` can modify the mapping, so the page can't have been written to since it was written back.
+            self.pages.clear_dirty_page(page_index, &mut self.mmi.lock().page_table)?;
+        }
+        file.flush()?;
+        Ok(bytes_written)
+    }
+}
+
+impl Drop for FileMapping {
+    fn drop(&mut self) {
+        if let Err(e) = self.sync() {
+            error!("FileMapping: failed to write back modified pages of {:?} upon drop: {}",
+                self.file.lock().get_absolute_path(), e,
+            );
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Notifications of changes to files and directories in the VFS, similar to `inotify`.
+//!
+//! A [`Watcher`] watches a set of absolute paths, each of which is either a file
+//! or a directory, in which case everything beneath that directory is watched.
+//! Filesystem implementations report changes via [`notify()`],
+//! and each watcher that watches the changed path queues a [`FileEvent`]
+//! and wakes its [`Waker`], after which its owner can [take](Watcher::take_events) the events.
+//!
+//! Events are delivered asynchronously because they are reported while the changed
+//! filesystem node is locked, so the owner of a watcher cannot access that node
+//! until after the change has completed.
+//!
+//! This crate is deliberately independent of the tasking subsystem such that
+//! filesystem crates can depend on it; a watcher's owner typically obtains a `Waker`
+//! that unblocks it from the `waker` crate.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Waker,
+};
+use spin::Mutex;
+
+/// The kinds of changes that can be reported for a filesystem node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileEventKind {
+    /// A node was inserted into a directory.
+    Created,
+    /// A file's contents were written.
+    Modified,
+    /// A node was removed from a directory.
+    Removed,
+}
+
+/// A change to the filesystem node at `path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEvent {
+    /// The absolute path of the node that changed.
+    pub path: String,
+    pub kind: FileEventKind,
+}
+
+struct WatcherInner {
+    paths: Mutex<Vec<String>>,
+    events: Mutex<Vec<FileEvent>>,
+    waker: Waker,
+}
+
+impl WatcherInner {
+    fn watches(&self, path: &str) -> bool {
+        self.paths.lock().iter().any(|watched| is_within(path, watched))
+    }
+}
+
+/// All existing watchers.
+static WATCHERS: Mutex<Vec<Weak<WatcherInner>>> = Mutex::new(Vec::new());
+/// The number of existing watchers, which allows filesystems to skip
+/// determining the path of a changed node when nobody is watching.
+static NUM_WATCHERS: AtomicUsize = AtomicUsize::new(0);
+
+/// A set of watched paths along with the events that occurred within them.
+///
+/// The watcher stops receiving events once it is dropped.
+pub struct Watcher {
+    inner: Arc<WatcherInner>,
+}
+
+impl Watcher {
+    /// Creates a new watcher that doesn't watch any paths yet.
+    ///
+    /// The given `waker` is woken whenever a new event is queued for this watcher.
+    pub fn new(waker: Waker) -> Watcher {
+        let inner = Arc::new(WatcherInner {
+            paths: Mutex::new(Vec::new()),
+            events: Mutex::new(Vec::new()),
+            waker,
+        });
+        let mut watchers = WATCHERS.lock();
+        watchers.retain(|w| w.strong_count() > 0);
+        watchers.push(Arc::downgrade(&inner));
+        NUM_WATCHERS.fetch_add(1, Ordering::Release);
+        Watcher { inner }
+    }
+
+    /// Starts watching the given absolute `path`, which need not exist yet.
+    ///
+    /// If `path` is a directory, all nodes beneath it are watched too.
+    pub fn watch(&self, path: &str) {
+        let path = normalize(path);
+        let mut paths = self.inner.paths.lock();
+        if !paths.iter().any(|p| *p == path) {
+            paths.push(path.to_string());
+        }
+    }
+
+    /// Stops watching the given absolute `path`.
+    pub fn unwatch(&self, path: &str) {
+        let path = normalize(path);
+        self.inner.paths.lock().retain(|p| p != path);
+    }
+
+    /// Removes and returns all events that have been queued for this watcher, oldest first.
+    pub fn take_events(&self) -> Vec<FileEvent> {
+        core::mem::take(&mut *self.inner.events.lock())
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        NUM_WATCHERS.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// Returns whether any watchers exist.
+///
+/// Filesystems can use this to avoid the cost of determining a changed node's path
+/// when nobody is watching.
+pub fn is_active() -> bool {
+    NUM_WATCHERS.load(Ordering::Acquire) > 0
+}
+
+/// Reports that the filesystem node at the given absolute `path` has changed.
+///
+/// This queues an event on and wakes every watcher that watches `path`.
+pub fn notify(path: &str, kind: FileEventKind) {
+    if !is_active() {
+        return;
+    }
+    let path = normalize(path);
+    let watchers: Vec<Arc<WatcherInner>> = WATCHERS.lock().iter().filter_map(Weak::upgrade).collect();
+    for watcher in watchers.iter().filter(|w| w.watches(path)) {
+        watcher.events.lock().push(FileEvent { path: path.to_string(), kind });
+        watcher.waker.wake_by_ref();
+    }
+}
+
+/// Removes any trailing slashes from the given path, except for the root directory.
+fn normalize(path: &str) -> &str {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() { "/" } else { trimmed }
+}
+
+/// Returns whether `path` is `watched` or is beneath it.
+fn is_within(path: &str, watched: &str) -> bool {
+    watched == "/" || path.strip_prefix(watched).map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+}
@@ -1,11 +1,34 @@
 #![no_std]
 extern crate spin;
+extern crate display_scale;
 
-/// The width of a character.
+use display_scale::ScaleFactor;
+
+/// The width of a character, in unscaled pixels.
 pub const CHARACTER_WIDTH: usize = 9;
-/// The height of a character.
+/// The height of a character, in unscaled pixels.
 pub const CHARACTER_HEIGHT: usize = 16;
 
+/// Returns the width of a character in screen pixels at the current display scale factor.
+pub fn character_width() -> usize {
+    display_scale::scaled(CHARACTER_WIDTH)
+}
+
+/// Returns the height of a character in screen pixels at the current display scale factor.
+pub fn character_height() -> usize {
+    display_scale::scaled(CHARACTER_HEIGHT)
+}
+
+/// Returns whether the pixel at (`x`, `y`) within a character cell is part of the given `character`'s glyph,
+/// where `x` and `y` are in screen pixels at the given `scale` factor.
+///
+/// The leftmost column of each character cell is left blank as a gap between characters.
+pub fn is_glyph_pixel(character: u8, scale: ScaleFactor, x: usize, y: usize) -> bool {
+    let x = scale.unscale(x).min(CHARACTER_WIDTH - 1);
+    let y = scale.unscale(y).min(CHARACTER_HEIGHT - 1);
+    x >= 1 && FONT_BASIC[character as usize][y] & (0x80 >> (x - 1)) != 0
+}
+
 // Copied from: https://github.com/goto456/linux-2.6.26/blob/6def53aec8e32bb58a3b50c52a8fb7c48ebef012/arch/ppc/boot/include/iso_font.h
 /// The bitmap array of characters.
 pub static FONT_BASIC: [[u8; CHARACTER_HEIGHT]; 256] = [
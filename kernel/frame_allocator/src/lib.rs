@@ -18,6 +18,20 @@
 //! free chunks for de-fragmentation. It does not iteratively merge adjacent chunks in order to
 //! maximally combine separate chunks into the biggest single chunk.
 //! Instead, free chunks are merged only when they are dropped or when needed to fulfill a specific request.
+//!
+//! # NUMA
+//! On machines with multiple NUMA nodes, the physical memory ranges of each node
+//! can be registered via [`add_node_memory_range()`], e.g., from the ACPI SRAT.
+//! There is no separate free list for each node: allocating from a specific node
+//! via [`allocate_frames_on_node()`] searches the single free general-purpose list
+//! for free frames within that node's ranges, visiting only the chunks that lie within them.
+//! Regular allocations still choose frames from any node.
+//!
+//! # Faulty Memory
+//! Frames that are known to be faulty, e.g., those reported as defective by the firmware's memory map
+//! or poisoned by an uncorrectable machine-check error, can be marked as bad via [`mark_frames_as_bad()`].
+//! Bad frames are permanently withheld from allocation: free bad frames are removed from the free lists,
+//! and bad frames that are currently in use are quarantined when they are deallocated.
 
 #![no_std]
 #![allow(clippy::blocks_in_if_conditions)]
@@ -31,12 +45,13 @@ mod test;
 mod static_array_rb_tree;
 // mod static_array_linked_list;
 
+use alloc::vec::Vec;
 use core::{borrow::Borrow, cmp::{Ordering, min, max}, fmt, mem, ops::{Deref, DerefMut}};
 use intrusive_collections::Bound;
 use kernel_config::memory::*;
 use log::{error, warn, debug, trace};
 use memory_structs::{PhysicalAddress, Frame, FrameRange, MemoryState, PageSize, Page4K, Page2M, Page1G};
-use spin::Mutex;
+use spin::{Mutex, Once};
 use static_array_rb_tree::*;
 use static_assertions::assert_not_impl_any;
 
@@ -57,6 +72,12 @@ static GENERAL_REGIONS: Mutex<StaticArrayRBTree<PhysicalMemoryRegion>> = Mutex::
 /// This does not indicate whether these regions are currently allocated, 
 /// rather just where they exist and which regions are known to this allocator.
 static RESERVED_REGIONS: Mutex<StaticArrayRBTree<PhysicalMemoryRegion>> = Mutex::new(StaticArrayRBTree::empty());
+/// The list of all regions that are known to contain faulty memory.
+/// Frames in these regions are never allocated, even if specifically requested.
+static BAD_REGIONS: Mutex<StaticArrayRBTree<PhysicalMemoryRegion>> = Mutex::new(StaticArrayRBTree::empty());
+/// The physical memory ranges that belong to each NUMA node, as `(node, frames)` pairs.
+/// This is empty if the firmware didn't describe the machine's NUMA topology.
+static NODE_MEMORY_RANGES: Mutex<Vec<(u32, FrameRange<Page4K>)>> = Mutex::new(Vec::new());
 
 
 /// Initialize the frame allocator with the given list of available and reserved physical memory regions.
@@ -313,6 +334,9 @@ pub enum MemoryRegionType {
     /// This includes custom memory regions added by third parties, e.g., 
     /// device memory discovered and added by device drivers later during runtime.
     Reserved,
+    /// Memory that is known to be faulty and must never be allocated or accessed.
+    /// See [`mark_frames_as_bad()`].
+    Bad,
     /// Memory of an unknown type.
     /// This is a default value that acts as a sanity check, because it is invalid
     /// to do any real work (e.g., allocation, access) with an unknown memory region.
@@ -483,6 +507,8 @@ impl<const S: MemoryState, P: PageSize> Drop for Frames<S, P> {
                     typ: self.typ,
                     frame_range: frame_range.into_4k_frames(),
                 };
+                // Bad frames must never be returned to a free list.
+                let Some(free_frames) = discard_bad_frames(free_frames) else { return };
         
                 let mut list = if free_frames.typ == MemoryRegionType::Reserved {
                     FREE_RESERVED_FRAMES_LIST.lock()
@@ -1023,6 +1049,54 @@ fn find_any_chunk(
 }
 
 
+/// Searches the given `list` for a chunk with at least `num_frames` contiguous frames
+/// within one of the given `ranges`, and allocates frames from the start of that overlap.
+fn find_chunk_in_ranges(
+    list: &mut StaticArrayRBTree<FreeFrames>,
+    num_frames: usize,
+    ranges: &[FrameRange<Page4K>],
+) -> Result<(AllocatedFrames<Page4K>, DeferredAllocAction<'static>), AllocationError> {
+    // Returns the first frame of a large-enough overlap between the given chunk and `ranges`.
+    let usable_start = |chunk: &FreeFrames| -> Option<Frame<Page4K>> {
+        if chunk.typ() != MemoryRegionType::Free {
+            return None;
+        }
+        ranges.iter()
+            .filter_map(|range| chunk.overlap(range))
+            .find(|overlap| overlap.size_in_frames() >= num_frames)
+            .map(|overlap| *overlap.start())
+    };
+
+    match list.0 {
+        Inner::Array(ref mut arr) => {
+            for elem in arr.iter_mut() {
+                if let Some(start) = elem.as_ref().and_then(&usable_start) {
+                    return allocate_from_chosen_chunk(FrameRange::new(start, start + (num_frames - 1)), ValueRefMut::Array(elem), None);
+                }
+            }
+        }
+        Inner::RBTree(ref mut tree) => {
+            // The chunks are sorted by address, so only visit those that overlap each range,
+            // moving down from the highest one that starts within it, as in `find_any_chunk()`.
+            for range in ranges {
+                let mut cursor = tree.upper_bound_mut(Bound::Included(range.end()));
+                while let Some(chunk) = cursor.get().map(|w| w.deref()) {
+                    if chunk.end() < range.start() {
+                        break;
+                    }
+                    if let Some(start) = usable_start(chunk) {
+                        return allocate_from_chosen_chunk(FrameRange::new(start, start + (num_frames - 1)), ValueRefMut::RBTree(cursor), None);
+                    }
+                    cursor.move_prev();
+                }
+            }
+        }
+    }
+
+    Err(AllocationError::OutOfAddressSpace(num_frames))
+}
+
+
 /// Removes a `Frames` object from the RBTree. 
 /// `frames_ref` is basically a wrapper over the cursor which stores the position of the frames.
 fn retrieve_frames_from_ref(mut frames_ref: ValueRefMut<FreeFrames>) -> Option<FreeFrames> {
@@ -1078,16 +1152,27 @@ fn contains_any(
     list: &StaticArrayRBTree<PhysicalMemoryRegion>,
     frames: &FrameRange<Page4K>,
 ) -> bool {
+    first_overlap(list, frames).is_some()
+}
+
+/// Returns the lowest range of frames in which the given list overlaps the given `frames`, if any.
+fn first_overlap(
+    list: &StaticArrayRBTree<PhysicalMemoryRegion>,
+    frames: &FrameRange<Page4K>,
+) -> Option<FrameRange<Page4K>> {
     match &list.0 {
         Inner::Array(ref arr) => {
-            for chunk in arr.iter().flatten() {
-                if chunk.overlap(frames).is_some() {
-                    return true;
-                }
-            }
+            arr.iter()
+                .flatten()
+                .filter_map(|chunk| chunk.overlap(frames))
+                .min_by_key(|overlap| *overlap.start())
         }
         Inner::RBTree(ref tree) => {
             let mut cursor = tree.upper_bound(Bound::Included(frames.start()));
+            if cursor.is_null() {
+                // No region starts at or before `frames`, so start from the lowest region.
+                cursor.move_next();
+            }
             while let Some(chunk) = cursor.get() {
                 if chunk.start() > frames.end() {
                     // We're iterating in ascending order over a sorted tree, so we can stop
@@ -1095,14 +1180,14 @@ fn contains_any(
                     break;
                 }
 
-                if chunk.overlap(frames).is_some() {
-                    return true;
+                if let Some(overlap) = chunk.overlap(frames) {
+                    return Some(overlap);
                 }
                 cursor.move_next();
             }
+            None
         }
     }
-    false
 }
 
 /// Adds the given `frames` to the given `regions_list` and `frames_list` as a chunk of reserved frames. 
@@ -1203,6 +1288,11 @@ pub fn allocate_frames_deferred(
     
     if let Some(paddr) = requested_paddr {
         let start_frame = Frame::containing_address(paddr);
+        let requested_frames = FrameRange::new(start_frame, start_frame + (num_frames - 1));
+        if contains_any(&BAD_REGIONS.lock(), &requested_frames) {
+            error!("frame_allocator: requested frames {:X?} include bad frames", requested_frames);
+            return Err("requested frames include frames that are known to be faulty");
+        }
         let mut free_reserved_frames_list = FREE_RESERVED_FRAMES_LIST.lock();
         // First, attempt to allocate the requested frames from the free reserved list.
         let first_allocation_attempt = find_specific_chunk(&mut free_reserved_frames_list, start_frame, num_frames);
@@ -1270,6 +1360,10 @@ pub fn allocate_frames(num_frames: usize) -> Option<AllocatedFrames<Page4K>> {
     allocate_frames_deferred(None, num_frames)
         .map(|(af, _action)| af)
         .ok()
+        .or_else(|| {
+            reclaim_frames(num_frames)?;
+            allocate_frames_deferred(None, num_frames).map(|(af, _action)| af).ok()
+        })
 }
 
 
@@ -1282,6 +1376,109 @@ pub fn allocate_frames_by_bytes(num_bytes: usize) -> Option<AllocatedFrames<Page
     allocate_frames_by_bytes_deferred(None, num_bytes)
         .map(|(af, _action)| af)
         .ok()
+        .or_else(|| {
+            reclaim_frames((num_bytes + FRAME_4K_SIZE_IN_BYTES - 1) / FRAME_4K_SIZE_IN_BYTES)?;
+            allocate_frames_by_bytes_deferred(None, num_bytes).map(|(af, _action)| af).ok()
+        })
+}
+
+
+/// Registers the given range of physical `frames` as belonging to the given NUMA `node`.
+///
+/// Returns an error if the given `frames` overlap a range that belongs to a different node.
+pub fn add_node_memory_range(node: u32, frames: FrameRange<Page4K>) -> Result<(), &'static str> {
+    if frames.is_empty() {
+        return Err("cannot add an empty range of frames to a NUMA node");
+    }
+    let mut node_ranges = NODE_MEMORY_RANGES.lock();
+    if node_ranges.iter().any(|(n, range)| *n != node && range.overlap(&frames).is_some()) {
+        return Err("the given frames overlap the memory of a different NUMA node");
+    }
+    node_ranges.push((node, frames));
+    Ok(())
+}
+
+/// Returns the NUMA node that the given `frame` belongs to,
+/// or `None` if the machine's NUMA topology is unknown.
+pub fn node_of_frame(frame: Frame<Page4K>) -> Option<u32> {
+    NODE_MEMORY_RANGES.lock().iter()
+        .find(|(_, range)| range.contains(&frame))
+        .map(|(node, _)| *node)
+}
+
+/// Allocates the given number of contiguous frames from the memory of the given NUMA `node`.
+///
+/// See [`allocate_frames_deferred()`](fn.allocate_frames_deferred.html) for more details.
+pub fn allocate_frames_on_node_deferred(
+    node: u32,
+    num_frames: usize,
+) -> Result<(AllocatedFrames<Page4K>, DeferredAllocAction<'static>), &'static str> {
+    if num_frames == 0 {
+        return Err("cannot allocate zero frames");
+    }
+    let ranges: Vec<FrameRange<Page4K>> = NODE_MEMORY_RANGES.lock().iter()
+        .filter(|(n, _)| *n == node)
+        .map(|(_, range)| range.clone())
+        .collect();
+    if ranges.is_empty() {
+        return Err("no memory is known to belong to the given NUMA node");
+    }
+    find_chunk_in_ranges(&mut FREE_GENERAL_FRAMES_LIST.lock(), num_frames, &ranges)
+        .map_err(From::from)
+}
+
+/// Allocates the given number of contiguous frames from the memory of the given NUMA `node`.
+///
+/// Returns `None` if the node has no memory or not enough free frames;
+/// callers that merely prefer local memory should then fall back to [`allocate_frames()`].
+pub fn allocate_frames_on_node(node: u32, num_frames: usize) -> Option<AllocatedFrames<Page4K>> {
+    allocate_frames_on_node_deferred(node, num_frames)
+        .map(|(af, _action)| af)
+        .ok()
+}
+
+/// Returns the number of free general-purpose frames within the memory of the given NUMA `node`.
+pub fn num_free_frames_on_node(node: u32) -> usize {
+    let node_ranges = NODE_MEMORY_RANGES.lock();
+    let ranges: Vec<&FrameRange<Page4K>> = node_ranges.iter()
+        .filter(|(n, _)| *n == node)
+        .map(|(_, range)| range)
+        .collect();
+    FREE_GENERAL_FRAMES_LIST.lock().iter()
+        .flat_map(|chunk| ranges.iter().filter_map(move |range| chunk.overlap(range)))
+        .map(|overlap| overlap.size_in_frames())
+        .sum()
+}
+
+
+/// A function that attempts to free up at least the given number of frames
+/// and returns the number of frames that it actually freed.
+pub type ReclaimFunction = fn(usize) -> usize;
+
+/// The function invoked when an allocation fails due to a lack of free frames.
+static RECLAIM_FUNCTION: Once<ReclaimFunction> = Once::new();
+
+/// Sets the function that [`allocate_frames()`] and [`allocate_frames_by_bytes()`] invoke
+/// to free up memory, e.g., by shrinking caches, before giving up on an allocation.
+///
+/// The reclaim function is not invoked by the deferred allocation functions,
+/// as they may be invoked by the heap while it holds locks that the reclaim function needs.
+///
+/// Returns an error if a reclaim function has already been set.
+pub fn set_reclaim_function(func: ReclaimFunction) -> Result<(), &'static str> {
+    let mut was_set = false;
+    RECLAIM_FUNCTION.call_once(|| { was_set = true; func });
+    if was_set { Ok(()) } else { Err("frame_allocator: a reclaim function was already set") }
+}
+
+/// Invokes the reclaim function, if any, to free up `num_frames` frames.
+///
+/// Returns `None` if nothing was reclaimed, meaning that an allocation need not be retried.
+fn reclaim_frames(num_frames: usize) -> Option<()> {
+    let reclaim = RECLAIM_FUNCTION.get()?;
+    let reclaimed = reclaim(num_frames);
+    debug!("frame_allocator: reclaimed {} of {} requested frames", reclaimed, num_frames);
+    (reclaimed > 0).then_some(())
 }
 
 
@@ -1358,6 +1555,155 @@ where
 }
 
 
+/// Returns the total number of free frames in general-purpose memory,
+/// i.e., the number of frames that could currently be allocated without a specific address.
+pub fn num_free_general_frames() -> usize {
+    FREE_GENERAL_FRAMES_LIST.lock().iter().map(|frames| frames.size_in_frames()).sum()
+}
+
+
+/// Marks the given `frames` as bad, i.e., known to contain faulty memory,
+/// such that this allocator will never allocate them again.
+///
+/// Any of the given `frames` that are currently free are immediately removed from the free lists.
+/// Any that are currently allocated remain usable by their owner, e.g., such that it can
+/// recover or be killed gracefully, but they will be quarantined instead of freed once deallocated.
+///
+/// Marking frames that are already marked as bad is harmless.
+///
+/// Returns the number of free frames that were removed from the free lists.
+pub fn mark_frames_as_bad(frames: FrameRange<Page4K>) -> Result<usize, &'static str> {
+    if frames.is_empty() {
+        return Err("cannot mark an empty range of frames as bad");
+    }
+    add_bad_region(&mut BAD_REGIONS.lock(), frames.clone())?;
+
+    let mut num_removed = 0;
+    for list in [&FREE_GENERAL_FRAMES_LIST, &FREE_RESERVED_FRAMES_LIST] {
+        // The lock on the free list must be released before dropping any frames,
+        // as doing so returns them to that free list.
+        loop {
+            let chunk = take_overlapping_chunk(&mut list.lock(), &frames);
+            let Some(chunk) = chunk else { break };
+            num_removed += discard_bad_frames_in(chunk, &frames);
+        }
+    }
+    warn!("frame_allocator: marked frames {:X?} as bad, removed {} free frames", frames, num_removed);
+    Ok(num_removed)
+}
+
+/// Returns `true` if the given `frame` has been marked as bad.
+///
+/// See [`mark_frames_as_bad()`].
+pub fn is_frame_bad(frame: Frame<Page4K>) -> bool {
+    contains_any(&BAD_REGIONS.lock(), &FrameRange::new(frame, frame))
+}
+
+/// Returns the total number of frames that have been marked as bad.
+pub fn num_bad_frames() -> usize {
+    BAD_REGIONS.lock().iter().map(|region| region.size_in_frames()).sum()
+}
+
+/// Adds the given `frames` to the given list of bad regions,
+/// merging them with any existing regions that they overlap.
+fn add_bad_region(
+    regions_list: &mut StaticArrayRBTree<PhysicalMemoryRegion>,
+    mut frames: FrameRange<Page4K>,
+) -> Result<(), &'static str> {
+    while let Some(existing) = take_overlapping_region(regions_list, &frames) {
+        frames = FrameRange::new(
+            min(*frames.start(), *existing.start()),
+            max(*frames.end(), *existing.end()),
+        );
+    }
+    regions_list.insert(PhysicalMemoryRegion::new(frames, MemoryRegionType::Bad))
+        .map(|_| ())
+        .map_err(|_| "Failed to add bad region: out of space in the bad regions list (array)")
+}
+
+/// Removes and returns the first region in the given `list` that overlaps the given `frames`.
+fn take_overlapping_region(
+    list: &mut StaticArrayRBTree<PhysicalMemoryRegion>,
+    frames: &FrameRange<Page4K>,
+) -> Option<PhysicalMemoryRegion> {
+    let start = *first_overlap(list, frames)?.start();
+    match &mut list.0 {
+        Inner::Array(ref mut arr) => arr.iter_mut()
+            .find(|elem| elem.as_ref().is_some_and(|region| region.contains(&start)))
+            .and_then(Option::take),
+        Inner::RBTree(ref mut tree) => {
+            let mut cursor_mut = tree.upper_bound_mut(Bound::Included(&start));
+            cursor_mut.remove().map(|w| w.into_inner())
+        }
+    }
+}
+
+/// Removes and returns the first chunk in the given free `list` that overlaps the given `frames`.
+fn take_overlapping_chunk(
+    list: &mut StaticArrayRBTree<FreeFrames>,
+    frames: &FrameRange<Page4K>,
+) -> Option<FreeFrames> {
+    match &mut list.0 {
+        Inner::Array(ref mut arr) => arr.iter_mut()
+            .find(|elem| elem.as_ref().is_some_and(|chunk| chunk.overlap(frames).is_some()))
+            .and_then(Option::take),
+        Inner::RBTree(ref mut tree) => {
+            let mut cursor_mut = tree.upper_bound_mut(Bound::Included(frames.start()));
+            if cursor_mut.is_null() {
+                cursor_mut.move_next();
+            }
+            while let Some(chunk) = cursor_mut.get() {
+                if chunk.start() > frames.end() {
+                    break;
+                }
+                if chunk.overlap(frames).is_some() {
+                    return cursor_mut.remove().map(|w| w.into_inner());
+                }
+                cursor_mut.move_next();
+            }
+            None
+        }
+    }
+}
+
+/// Splits the given `chunk` of free frames around its overlap with the given `bad_frames`,
+/// dropping the good frames (which returns them to the free list) and quarantining the bad ones.
+///
+/// Returns the number of frames that were quarantined.
+fn discard_bad_frames_in(chunk: FreeFrames, bad_frames: &FrameRange<Page4K>) -> usize {
+    let Some(overlap) = chunk.overlap(bad_frames) else { return 0 };
+    match chunk.split_range(overlap) {
+        Ok(SplitFrames { before_start, start_to_end, after_end }) => {
+            let num_bad = start_to_end.size_in_frames();
+            // Forgetting the bad frames ensures they are never returned to a free list.
+            mem::forget(start_to_end);
+            drop(before_start);
+            drop(after_end);
+            num_bad
+        }
+        Err(chunk) => {
+            error!("BUG: couldn't split {:?} around its overlap with bad frames {:X?}", chunk, bad_frames);
+            mem::forget(chunk);
+            0
+        }
+    }
+}
+
+/// Quarantines any bad frames within the given `free_frames` that are being deallocated.
+///
+/// Returns the given `free_frames` if none of them are bad; otherwise, this drops
+/// the good frames within `free_frames` separately and returns `None`.
+fn discard_bad_frames(free_frames: FreeFrames) -> Option<FreeFrames> {
+    let bad_frames = first_overlap(&BAD_REGIONS.lock(), &free_frames.frame_range);
+    let Some(bad_frames) = bad_frames else { return Some(free_frames) };
+    // Handle the lowest overlapping bad region here; any others are handled
+    // when the remaining good frames after it are dropped.
+    warn!("frame_allocator: quarantining deallocated bad frames {:X?}", bad_frames);
+    discard_bad_frames_in(free_frames, &bad_frames);
+    None
+}
+
+
 /// Converts the frame allocator from using static memory (a primitive array) to dynamically-allocated memory.
 /// 
 /// Call this function once heap allocation is available. 
@@ -1368,6 +1714,7 @@ pub fn convert_frame_allocator_to_heap_based() {
     FREE_RESERVED_FRAMES_LIST.lock().convert_to_heap_allocated();
     GENERAL_REGIONS.lock().convert_to_heap_allocated();
     RESERVED_REGIONS.lock().convert_to_heap_allocated();
+    BAD_REGIONS.lock().convert_to_heap_allocated();
 }
 
 /// A debugging function used to dump the full internal state of the frame allocator. 
@@ -1385,4 +1732,7 @@ pub fn dump_frame_allocator_state() {
     debug!("------------------ RESERVED REGIONS -----------------");
     RESERVED_REGIONS.lock().iter().for_each(|e| debug!("\t {:?}", e) );
     debug!("-----------------------------------------------------");
+    debug!("-------------------- BAD REGIONS --------------------");
+    BAD_REGIONS.lock().iter().for_each(|e| debug!("\t {:?}", e) );
+    debug!("-----------------------------------------------------");
 }
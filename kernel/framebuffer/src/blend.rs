@@ -0,0 +1,218 @@
+//! Accelerated routines for blending rows of [`AlphaPixel`]s.
+//!
+//! The blending routine used is selected at runtime from the [`BlendBackend`]s
+//! supported by both the current CPU and the kernel's build target.
+//! Because regular Theseus tasks don't save SIMD registers upon a context switch,
+//! a SIMD backend is only available if the kernel's target enables it,
+//! e.g., `x86_64-unknown-theseus-sse` for SSE2 or `x86_64-unknown-theseus-avx` for AVX2;
+//! otherwise, the scalar backend is used.
+//!
+//! All backends produce identical results to [`AlphaPixel::blend()`].
+//!
+//! Plain copies (e.g., of [`RGBPixel`](crate::RGBPixel) rows) aren't handled here,
+//! because `copy_from_slice` already compiles to an efficient `memcpy`.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+use crate::AlphaPixel;
+
+/// An implementation of alpha blending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BlendBackend {
+    /// One pixel at a time, without SIMD instructions.
+    Scalar = 0,
+    /// Four pixels at a time, using x86_64 SSE2 instructions.
+    Sse2 = 1,
+    /// Eight pixels at a time, using x86_64 AVX2 instructions.
+    Avx2 = 2,
+    /// Eight pixels at a time, using aarch64 NEON instructions.
+    Neon = 3,
+}
+
+impl BlendBackend {
+    const ALL: [BlendBackend; 4] = [BlendBackend::Scalar, BlendBackend::Sse2, BlendBackend::Avx2, BlendBackend::Neon];
+
+    /// Returns whether this backend can be used on the current CPU with the kernel's build target.
+    pub fn is_supported(self) -> bool {
+        match self {
+            BlendBackend::Scalar => true,
+            BlendBackend::Sse2 => cfg!(all(target_arch = "x86_64", target_feature = "sse2")),
+            BlendBackend::Avx2 => avx2_supported(),
+            BlendBackend::Neon => cfg!(all(target_arch = "aarch64", target_feature = "neon")),
+        }
+    }
+}
+
+/// The value of [`BACKEND`] before the best backend has been selected.
+const UNSELECTED: u8 = u8::MAX;
+
+/// The currently-selected backend.
+static BACKEND: AtomicU8 = AtomicU8::new(UNSELECTED);
+
+/// Returns the backend currently used for blending, selecting the fastest supported one
+/// if none has been selected yet.
+pub fn backend() -> BlendBackend {
+    let backend = BACKEND.load(Ordering::Relaxed);
+    if backend != UNSELECTED {
+        return BlendBackend::ALL[backend as usize];
+    }
+    let best = BlendBackend::ALL.into_iter()
+        .rev()
+        .find(|b| b.is_supported())
+        .unwrap_or(BlendBackend::Scalar);
+    BACKEND.store(best as u8, Ordering::Relaxed);
+    best
+}
+
+/// Sets the backend used for blending, e.g., to compare the performance of different backends.
+///
+/// Returns an error if the given backend isn't supported.
+pub fn set_backend(backend: BlendBackend) -> Result<(), &'static str> {
+    if !backend.is_supported() {
+        return Err("the given blend backend isn't supported by this CPU or kernel build");
+    }
+    BACKEND.store(backend as u8, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Blends each pixel in `src` onto the corresponding pixel in `dest`,
+/// using the currently-selected backend.
+///
+/// `src` and `dest` must have the same length.
+pub fn blend_row(src: &[AlphaPixel], dest: &mut [AlphaPixel]) {
+    assert_eq!(src.len(), dest.len(), "blend_row: source and destination lengths differ");
+    // Each SIMD routine handles a multiple of its batch size, returning how many pixels it blended.
+    let done = match backend() {
+        BlendBackend::Scalar => 0,
+        #[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+        BlendBackend::Sse2 => unsafe { x86::blend_sse2(src, dest) },
+        #[cfg(all(target_arch = "x86_64", target_feature = "avx"))]
+        BlendBackend::Avx2 => unsafe { x86::blend_avx2(src, dest) },
+        #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+        BlendBackend::Neon => unsafe { aarch64::blend_neon(src, dest) },
+        #[allow(unreachable_patterns)]
+        _ => 0,
+    };
+    for (s, d) in src[done..].iter().zip(&mut dest[done..]) {
+        *d = s.blend(*d);
+    }
+}
+
+/// AVX2 instructions use the 256-bit YMM registers, which are only saved upon a context switch
+/// if the kernel's target enables AVX.
+#[cfg(all(target_arch = "x86_64", target_feature = "avx"))]
+fn avx2_supported() -> bool {
+    raw_cpuid::CpuId::new()
+        .get_extended_feature_info()
+        .map_or(false, |info| info.has_avx2())
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "avx")))]
+fn avx2_supported() -> bool {
+    false
+}
+
+#[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+mod x86 {
+    use core::arch::x86_64::*;
+    use crate::AlphaPixel;
+
+    /// The alpha channel of each pixel, which is taken from the source pixel unchanged.
+    const ALPHA_MASK: i32 = 0xFF00_0000_u32 as i32;
+
+    /// Blends 2 pixels that have been widened to 16 bits per channel.
+    #[inline(always)]
+    unsafe fn blend_wide_sse2(s: __m128i, d: __m128i) -> __m128i {
+        // Broadcast each pixel's alpha (its 4th channel) to all of its channels.
+        let alpha = _mm_shufflehi_epi16::<0xFF>(_mm_shufflelo_epi16::<0xFF>(s));
+        let inverse = _mm_sub_epi16(_mm_set1_epi16(255), alpha);
+        let x = _mm_add_epi16(_mm_mullo_epi16(s, inverse), _mm_mullo_epi16(d, alpha));
+        // Divide by 255, which is exact for values up to `255 * 255`: `x / 255 == (x + 1 + (x >> 8)) >> 8`.
+        let x = _mm_add_epi16(_mm_add_epi16(x, _mm_set1_epi16(1)), _mm_srli_epi16::<8>(x));
+        _mm_srli_epi16::<8>(x)
+    }
+
+    /// Blends as many groups of 4 pixels as possible, returning the number of pixels blended.
+    pub(super) unsafe fn blend_sse2(src: &[AlphaPixel], dest: &mut [AlphaPixel]) -> usize {
+        let count = src.len() / 4 * 4;
+        let zero = _mm_setzero_si128();
+        let mask = _mm_set1_epi32(ALPHA_MASK);
+        for i in (0..count).step_by(4) {
+            let s = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+            let d_ptr = dest.as_mut_ptr().add(i) as *mut __m128i;
+            let d = _mm_loadu_si128(d_ptr);
+            let lo = blend_wide_sse2(_mm_unpacklo_epi8(s, zero), _mm_unpacklo_epi8(d, zero));
+            let hi = blend_wide_sse2(_mm_unpackhi_epi8(s, zero), _mm_unpackhi_epi8(d, zero));
+            let blended = _mm_packus_epi16(lo, hi);
+            _mm_storeu_si128(d_ptr, _mm_or_si128(_mm_andnot_si128(mask, blended), _mm_and_si128(mask, s)));
+        }
+        count
+    }
+
+    /// Blends 4 pixels that have been widened to 16 bits per channel.
+    #[cfg(target_feature = "avx")]
+    #[target_feature(enable = "avx2")]
+    unsafe fn blend_wide_avx2(s: __m256i, d: __m256i) -> __m256i {
+        let alpha = _mm256_shufflehi_epi16::<0xFF>(_mm256_shufflelo_epi16::<0xFF>(s));
+        let inverse = _mm256_sub_epi16(_mm256_set1_epi16(255), alpha);
+        let x = _mm256_add_epi16(_mm256_mullo_epi16(s, inverse), _mm256_mullo_epi16(d, alpha));
+        let x = _mm256_add_epi16(_mm256_add_epi16(x, _mm256_set1_epi16(1)), _mm256_srli_epi16::<8>(x));
+        _mm256_srli_epi16::<8>(x)
+    }
+
+    /// Blends as many groups of 8 pixels as possible, returning the number of pixels blended.
+    ///
+    /// The caller must ensure that the CPU supports AVX2.
+    #[cfg(target_feature = "avx")]
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn blend_avx2(src: &[AlphaPixel], dest: &mut [AlphaPixel]) -> usize {
+        let count = src.len() / 8 * 8;
+        let zero = _mm256_setzero_si256();
+        let mask = _mm256_set1_epi32(ALPHA_MASK);
+        for i in (0..count).step_by(8) {
+            let s = _mm256_loadu_si256(src.as_ptr().add(i) as *const __m256i);
+            let d_ptr = dest.as_mut_ptr().add(i) as *mut __m256i;
+            let d = _mm256_loadu_si256(d_ptr);
+            // Unpacking and packing operate within each 128-bit lane, so the pixel order is preserved.
+            let lo = blend_wide_avx2(_mm256_unpacklo_epi8(s, zero), _mm256_unpacklo_epi8(d, zero));
+            let hi = blend_wide_avx2(_mm256_unpackhi_epi8(s, zero), _mm256_unpackhi_epi8(d, zero));
+            let blended = _mm256_packus_epi16(lo, hi);
+            _mm256_storeu_si256(d_ptr, _mm256_or_si256(_mm256_andnot_si256(mask, blended), _mm256_and_si256(mask, s)));
+        }
+        count
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+mod aarch64 {
+    use core::arch::aarch64::*;
+    use crate::AlphaPixel;
+
+    /// Blends one channel of 8 pixels, dividing by 255 as in the x86 routines.
+    #[inline(always)]
+    unsafe fn blend_channel(s: uint8x8_t, d: uint8x8_t, alpha: uint8x8_t, inverse: uint8x8_t) -> uint8x8_t {
+        let x = vmlal_u8(vmull_u8(s, inverse), d, alpha);
+        let x = vaddq_u16(vaddq_u16(x, vdupq_n_u16(1)), vshrq_n_u16::<8>(x));
+        vshrn_n_u16::<8>(x)
+    }
+
+    /// Blends as many groups of 8 pixels as possible, returning the number of pixels blended.
+    pub(super) unsafe fn blend_neon(src: &[AlphaPixel], dest: &mut [AlphaPixel]) -> usize {
+        let count = src.len() / 8 * 8;
+        for i in (0..count).step_by(8) {
+            // De-interleave the blue, green, red, and alpha channels of 8 pixels.
+            let s = vld4_u8(src.as_ptr().add(i) as *const u8);
+            let d_ptr = dest.as_mut_ptr().add(i) as *mut u8;
+            let d = vld4_u8(d_ptr);
+            let inverse = vsub_u8(vdup_n_u8(255), s.3);
+            let blended = uint8x8x4_t(
+                blend_channel(s.0, d.0, s.3, inverse),
+                blend_channel(s.1, d.1, s.3, inverse),
+                blend_channel(s.2, d.2, s.3, inverse),
+                s.3,
+            );
+            vst4_u8(d_ptr, blended);
+        }
+        count
+    }
+}
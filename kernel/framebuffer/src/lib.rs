@@ -3,11 +3,12 @@
 
 #![no_std]
 
+pub mod blend;
 pub mod pixel;
 use core::{ops::{DerefMut, Deref}, hash::{Hash, Hasher}};
 use log::{info, debug};
 use memory::{PteFlags, PteFlagsArch, PhysicalAddress, Mutable, BorrowedSliceMappedPages};
-use shapes::Coord;
+use shapes::{Coord, Rectangle};
 pub use pixel::*;
 
 /// Initializes the final framebuffer based on graphics mode info obtained during boot.
@@ -44,6 +45,9 @@ pub fn init<P: Pixel>() -> Result<Framebuffer<P>, &'static str> {
     Framebuffer::new(width, height, Some(paddr))
 }
 
+/// The maximum factor by which [`Framebuffer::composite_scaled()`] can scale up a framebuffer.
+pub const MAX_SCALE: usize = 4;
+
 /// A framebuffer is a region of memory interpreted as a 2-D array of pixels.
 /// The memory buffer is a rectangular region with a width and height.
 pub struct Framebuffer<P: Pixel> {
@@ -144,6 +148,151 @@ impl<P: Pixel> Framebuffer<P> {
         (self.width, self.height)
     }
 
+    /// Returns a reference to the pixels in the given row of this framebuffer, if it exists.
+    pub fn row(&self, y: usize) -> Option<&[P]> {
+        if y < self.height {
+            Some(&self.buffer[y * self.width .. (y + 1) * self.width])
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the pixels in the given row of this framebuffer, if it exists.
+    pub fn row_mut(&mut self, y: usize) -> Option<&mut [P]> {
+        if y < self.height {
+            let start = y * self.width;
+            let end = start + self.width;
+            Some(&mut self.buffer[start..end])
+        } else {
+            None
+        }
+    }
+
+    /// Composites a rectangular region of `src`, `width * height` pixels in size
+    /// with its top-left corner at `src_coord`, into this framebuffer at `dest_coord`.
+    ///
+    /// Each row is composited at once, which allows [`Pixel::composite_buffer()`]
+    /// to use SIMD instructions (see the [`blend`] module).
+    /// The parts of the region that lie outside of either framebuffer are skipped.
+    pub fn composite_rect(
+        &mut self,
+        src: &Framebuffer<P>,
+        src_coord: Coord,
+        dest_coord: Coord,
+        width: usize,
+        height: usize,
+    ) {
+        self.composite_rect_with_opacity(src, src_coord, dest_coord, width, height, u8::MAX)
+    }
+
+    /// Like [`Framebuffer::composite_rect()`], but fades the composited region of `src`
+    /// by the given `opacity`, where `255` is fully opaque and `0` is invisible.
+    ///
+    /// Only fully-opaque regions are composited using SIMD instructions.
+    pub fn composite_rect_with_opacity(
+        &mut self,
+        src: &Framebuffer<P>,
+        src_coord: Coord,
+        dest_coord: Coord,
+        width: usize,
+        height: usize,
+        opacity: u8,
+    ) {
+        // Clip the region's left and top edges to both framebuffers.
+        let skip_x = 0.max(-src_coord.x).max(-dest_coord.x);
+        let skip_y = 0.max(-src_coord.y).max(-dest_coord.y);
+        let (src_x, src_y) = (src_coord.x + skip_x, src_coord.y + skip_y);
+        let (dest_x, dest_y) = (dest_coord.x + skip_x, dest_coord.y + skip_y);
+        // Clip the region's right and bottom edges to both framebuffers.
+        let width = (width as isize - skip_x)
+            .min(src.width as isize - src_x)
+            .min(self.width as isize - dest_x);
+        let height = (height as isize - skip_y)
+            .min(src.height as isize - src_y)
+            .min(self.height as isize - dest_y);
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        let (src_x, dest_x, width) = (src_x as usize, dest_x as usize, width as usize);
+
+        let dest_width = self.width;
+        for i in 0..height {
+            let src_start = src.width * (src_y + i) as usize + src_x;
+            let dest_start = dest_width * (dest_y + i) as usize + dest_x;
+            P::composite_buffer_with_opacity(
+                &src.buffer[src_start .. src_start + width],
+                &mut self.buffer[dest_start .. dest_start + width],
+                opacity,
+            );
+        }
+    }
+
+    /// Composites `src` scaled up by the integer factor `scale` into this framebuffer,
+    /// such that each pixel of `src` covers `scale * scale` pixels of this framebuffer
+    /// and the top-left corner of `src` is at `dest_coord`.
+    ///
+    /// Only the part of the scaled `src` that lies within `dest_area` is composited,
+    /// and it is faded by the given `opacity` like in [`Framebuffer::composite_rect_with_opacity()`].
+    /// The `scale` is clamped to between `1` and [`MAX_SCALE`].
+    pub fn composite_scaled(
+        &mut self,
+        src: &Framebuffer<P>,
+        dest_coord: Coord,
+        scale: usize,
+        dest_area: Rectangle,
+        opacity: u8,
+    ) {
+        let scale = scale.clamp(1, MAX_SCALE);
+        // Clip the area to the scaled `src` and to this framebuffer.
+        let x_start = dest_area.top_left.x.max(dest_coord.x).max(0);
+        let y_start = dest_area.top_left.y.max(dest_coord.y).max(0);
+        let x_end = dest_area.bottom_right.x
+            .min(dest_coord.x + (src.width * scale) as isize)
+            .min(self.width as isize);
+        let y_end = dest_area.bottom_right.y
+            .min(dest_coord.y + (src.height * scale) as isize)
+            .min(self.height as isize);
+        if x_start >= x_end || y_start >= y_end {
+            return;
+        }
+
+        let dest_width = self.width;
+        for y in y_start..y_end {
+            let src_row_start = src.width * ((y - dest_coord.y) as usize / scale);
+            let dest_row_start = dest_width * y as usize;
+            let mut x = x_start;
+            while x < x_end {
+                // Composite the run of destination pixels covered by a single source pixel at once.
+                let src_x = (x - dest_coord.x) as usize / scale;
+                let run_end = (dest_coord.x + ((src_x + 1) * scale) as isize).min(x_end);
+                let run_len = (run_end - x) as usize;
+                let run = [src.buffer[src_row_start + src_x]; MAX_SCALE];
+                let dest_start = dest_row_start + x as usize;
+                P::composite_buffer_with_opacity(
+                    &run[..run_len],
+                    &mut self.buffer[dest_start .. dest_start + run_len],
+                    opacity,
+                );
+                x = run_end;
+            }
+        }
+    }
+
+    /// Fills (overwrites) a rectangular region of this framebuffer, `width * height` pixels in size
+    /// with its top-left corner at `coordinate`, with the given `pixel` value.
+    ///
+    /// The parts of the region that lie outside of this framebuffer are skipped.
+    pub fn fill_rect(&mut self, coordinate: Coord, width: usize, height: usize, pixel: P) {
+        let x_start = coordinate.x.clamp(0, self.width as isize) as usize;
+        let x_end = (coordinate.x + width as isize).clamp(0, self.width as isize) as usize;
+        let y_start = coordinate.y.clamp(0, self.height as isize) as usize;
+        let y_end = (coordinate.y + height as isize).clamp(0, self.height as isize) as usize;
+        let width = self.width;
+        for y in y_start..y_end {
+            self.buffer[y * width + x_start .. y * width + x_end].fill(pixel);
+        }
+    }
+
     /// Composites `src` to the buffer starting from `index`.
     pub fn composite_buffer(&mut self, src: &[P], index: usize) {
         let len = src.len();
@@ -173,9 +322,7 @@ impl<P: Pixel> Framebuffer<P> {
 
     /// Fills (overwrites) the entire framebuffer with the given `pixel` value.
     pub fn fill(&mut self, pixel: P) {
-        for p in self.buffer.deref_mut() {
-            *p = pixel;
-        }
+        self.buffer.deref_mut().fill(pixel);
     }
 
     /// Returns the index of the given `coordinate` in this framebuffer,
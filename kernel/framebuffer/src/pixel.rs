@@ -14,10 +14,17 @@ pub trait Pixel: Copy + Hash + FromBytes {
 
     /// Blend two pixels linearly with weights, as `blend` for `origin` and (1-`blend`) for `other`.
     fn weight_blend(origin: Self, other: Self, blend: f32) -> Self;
+
+    /// Composites the `src` pixel slice to the `dest` pixel slice as if every `src` pixel
+    /// were additionally faded by the given `opacity`, where `255` is fully opaque and `0` is invisible.
+    ///
+    /// An `opacity` of `255` is equivalent to [`Pixel::composite_buffer()`].
+    fn composite_buffer_with_opacity(src: &[Self], dest: &mut [Self], opacity: u8);
 }
 
 
 #[derive(Hash, Debug, Clone, Copy, FromBytes)]
+#[repr(C)]
 /// An RGB Pixel is a pixel with no extra channel.
 pub struct RGBPixel {
     pub blue: u8,
@@ -27,6 +34,7 @@ pub struct RGBPixel {
 }
 
 #[derive(Hash, Debug, Clone, Copy, FromBytes)]
+#[repr(C)]
 /// An Alpha Pixel is a pixel with an alpha channel
 pub struct AlphaPixel {
     pub blue: u8,
@@ -69,6 +77,19 @@ impl Pixel for RGBPixel {
             blue: new_blue
         }
     }
+
+    fn composite_buffer_with_opacity(src: &[Self], dest: &mut [Self], opacity: u8) {
+        match opacity {
+            u8::MAX => Self::composite_buffer(src, dest),
+            0 => {}
+            _ => {
+                let weight = opacity as f32 / 255f32;
+                for (s, d) in src.iter().zip(dest.iter_mut()) {
+                    *d = Self::weight_blend(*s, *d, weight);
+                }
+            }
+        }
+    }
 }
 
 impl From<Color> for RGBPixel {
@@ -84,9 +105,7 @@ impl From<Color> for RGBPixel {
 
 impl Pixel for AlphaPixel {   
     fn composite_buffer(src: &[Self], dest: &mut[Self]) {
-        for i in 0..src.len() {
-            dest[i] = src[i].blend(dest[i]);
-        }
+        crate::blend::blend_row(src, dest)
     }
 
     fn blend(self, other: Self) -> Self {
@@ -134,6 +153,31 @@ impl Pixel for AlphaPixel {
             blue: new_blue
         }
     }
+
+    fn composite_buffer_with_opacity(src: &[Self], dest: &mut [Self], opacity: u8) {
+        match opacity {
+            u8::MAX => Self::composite_buffer(src, dest),
+            0 => {}
+            _ => {
+                for (s, d) in src.iter().zip(dest.iter_mut()) {
+                    *d = s.with_opacity(opacity).blend(*d);
+                }
+            }
+        }
+    }
+}
+
+impl AlphaPixel {
+    /// Returns this pixel faded by the given `opacity`, where `255` is fully opaque and `0` is invisible.
+    ///
+    /// Note that a pixel's `alpha` channel is its transparency, so fading a pixel raises its `alpha`.
+    pub fn with_opacity(self, opacity: u8) -> Self {
+        let visibility = (255 - self.alpha as u16) * opacity as u16 / 255;
+        AlphaPixel {
+            alpha: (255 - visibility) as u8,
+            ..self
+        }
+    }
 }
 
 impl From<Color> for AlphaPixel {
@@ -16,7 +16,18 @@
 //! If a pixel array is not cached, the compositor will refresh the pixels within the bounding box and cache those `CACHE_BLOCK_HEIGHT` rows.
 //!
 //! In order to cache a range of rows from the source framebuffer, the compositor needs to cache its contents, its location in the destination framebuffer, and its size.
+//! The opacity of the source framebuffer is hashed along with its contents, since the same rows composited with a different opacity look different.
 //! The cache is basically a rectangular region in the destination framebuffer, and we define the structure `CacheBlock` to represent that cached region.
+//!
+//! # Scaling
+//! Source framebuffers with a [`FramebufferUpdates::scale`] greater than `1` are scaled up while they're composited.
+//! Their rows are not cached; instead, any cache blocks they cover are discarded, since the screen content beneath them changed.
+//!
+//! # Performance mode
+//! Checking the cache requires hashing every block of rows to be composited,
+//! which costs about as much as blending those rows when blending uses SIMD instructions
+//! (see `framebuffer::blend`), and is wasted work when most of the screen changes every frame, e.g., for animations.
+//! In performance mode, the compositor skips the cache and always composites the given regions directly.
 
 #![no_std]
 
@@ -43,7 +54,8 @@ pub const CACHE_BLOCK_HEIGHT: usize = 16;
 /// The instance of the framebuffer compositor.
 pub static FRAME_COMPOSITOR: Mutex<FrameCompositor> = Mutex::new(
     FrameCompositor{
-        caches: BTreeMap::new()
+        caches: BTreeMap::new(),
+        performance_mode: false,
     }
 );
 
@@ -88,21 +100,39 @@ impl CacheBlock {
 pub struct FrameCompositor {
     // Cache of updated framebuffers before
     caches: BTreeMap<Coord, CacheBlock>,
+    // Whether to skip the cache and always composite
+    performance_mode: bool,
 }
 
 impl FrameCompositor {
+    /// Enables or disables performance mode, in which the compositor doesn't cache framebuffer rows.
+    ///
+    /// Enabling performance mode discards all cached rows.
+    pub fn set_performance_mode(&mut self, enabled: bool) {
+        self.performance_mode = enabled;
+        if enabled {
+            self.caches.clear();
+        }
+    }
+
+    /// Returns whether performance mode is enabled.
+    pub fn performance_mode(&self) -> bool {
+        self.performance_mode
+    }
+
     /// Checks if some rows of a framebuffer are cached.
     /// # Arguments
     /// * `row_pixels`: the continuous pixels in the rows.
     /// * `dest_coord`: the location of the first pixel in the destination framebuffer.
     /// * `width`: the width of the rows
+    /// * `opacity`: the opacity with which the rows are composited.
     ///
-    fn is_cached<P: Pixel>(&self, row_pixels: &[P], dest_coord: &Coord, width: usize) -> bool {
+    fn is_cached<P: Pixel>(&self, row_pixels: &[P], dest_coord: &Coord, width: usize, opacity: u8) -> bool {
         match self.caches.get(dest_coord) {
             Some(cache) => {
                 // The same hash and width means the cache block is identical to the row pixels.
                 // We do not check the height because if the hashes are the same, the number of pixels, namely `width * height` must be the same.
-                cache.content_hash == hash(row_pixels) && (cache.block.bottom_right.x - cache.block.top_left.x) as usize == width
+                cache.content_hash == hash((row_pixels, opacity)) && (cache.block.bottom_right.x - cache.block.top_left.x) as usize == width
             }
             None => false
         }
@@ -115,11 +145,13 @@ impl FrameCompositor {
     /// * `src_fb`: the updated source framebuffer.
     /// * `dest_coord`: the position of the source framebuffer (its top-left corner) relative to the destination framebuffer's top-left corner.
     /// * `src_fb_row_range`: the range of rows in the source framebuffer to check and cache.
+    /// * `opacity`: the opacity with which the source framebuffer is composited.
     fn check_and_cache<P: Pixel>(
         &mut self, 
         src_fb: &Framebuffer<P>, 
         dest_coord: Coord, 
         src_fb_row_range: &Range<usize>,
+        opacity: u8,
     ) -> Result<bool, &'static str> {
         let (src_width, src_height) = src_fb.get_size();
         let src_buffer_len = src_width * src_height;
@@ -134,7 +166,7 @@ impl FrameCompositor {
         let pixel_slice = &src_fb.buffer()[start_index..core::cmp::min(end_index, src_buffer_len)];
         
         // Skip if the rows are already cached
-        if self.is_cached(pixel_slice, &coordinate_start, src_width) {
+        if self.is_cached(pixel_slice, &coordinate_start, src_width, opacity) {
             return Ok(true);
         }
 
@@ -144,7 +176,7 @@ impl FrameCompositor {
                 top_left: coordinate_start,
                 bottom_right: coordinate_start + (src_width as isize, (pixel_slice.len() / src_width) as isize)
             },
-            content_hash: hash(pixel_slice),
+            content_hash: hash((pixel_slice, opacity)),
         };
         let keys: Vec<_> = self.caches.keys().cloned().collect();
         for key in keys {
@@ -159,6 +191,38 @@ impl FrameCompositor {
         Ok(false)
     }
 
+    /// Removes all cache blocks that overlap with the given `area` of the destination framebuffer.
+    fn invalidate(&mut self, area: &Rectangle) {
+        self.caches.retain(|_, cache| cache.block.intersection(area).is_none());
+    }
+
+    /// Composites the given scaled-up source framebuffer within the given bounding boxes, or entirely if there are none.
+    fn composite_scaled<'a, B: CompositableRegion, P: 'a + Pixel>(
+        &mut self,
+        framebuffer_updates: &FramebufferUpdates<'a, P>,
+        dest_fb: &mut Framebuffer<P>,
+        dest_bounding_boxes: impl IntoIterator<Item = B>,
+    ) -> Result<(), &'static str> {
+        let src_fb = framebuffer_updates.src_framebuffer;
+        let coordinate = framebuffer_updates.coordinate_in_dest_framebuffer;
+        let scale = framebuffer_updates.scale;
+        let (src_width, src_height) = src_fb.get_size();
+        let area = Rectangle {
+            top_left: coordinate,
+            bottom_right: coordinate + ((src_width * scale) as isize, (src_height * scale) as isize),
+        };
+        self.invalidate(&area);
+
+        let mut box_iter = dest_bounding_boxes.into_iter().peekable();
+        if box_iter.peek().is_none() {
+            return area.blend_buffers_scaled(src_fb, dest_fb, coordinate, scale, framebuffer_updates.opacity);
+        }
+        for bounding_box in box_iter {
+            bounding_box.blend_buffers_scaled(src_fb, dest_fb, coordinate, scale, framebuffer_updates.opacity)?;
+        }
+        Ok(())
+    }
+
     /// Returns the range of rows in the source framebuffer that were (1) previously cached as cache blocks
     /// and (2) overlap with the given `dest_bounding_box`. 
     /// This methods extends the row range of the given bounding box because the compositor deals with chunks of `CACHE_BLOCK_HEIGHT` rows.
@@ -204,8 +268,13 @@ impl Compositor for FrameCompositor {
         let mut box_iter = dest_bounding_boxes.clone().into_iter();
         if box_iter.next().is_none() {
             for framebuffer_updates in src_fbs.into_iter() {
+                if framebuffer_updates.scale > 1 {
+                    self.composite_scaled(&framebuffer_updates, dest_fb, dest_bounding_boxes.clone())?;
+                    continue;
+                }
                 let src_fb = framebuffer_updates.src_framebuffer;
                 let coordinate = framebuffer_updates.coordinate_in_dest_framebuffer;
+                let opacity = framebuffer_updates.opacity;
                 // Update the whole screen if the caller does not specify the blocks
                 let (src_width, src_height) = framebuffer_updates.src_framebuffer.get_size();
                 // let block_number = (src_height - 1) / CACHE_BLOCK_HEIGHT + 1;
@@ -219,12 +288,13 @@ impl Compositor for FrameCompositor {
                         break;
                     }
                     let cache_range = row_start..(row_start + CACHE_BLOCK_HEIGHT);
-                    if !self.check_and_cache(src_fb, coordinate, &cache_range)? {
+                    if self.performance_mode || !self.check_and_cache(src_fb, coordinate, &cache_range, opacity)? {
                         area.blend_buffers(
                             src_fb,
                             dest_fb,
                             coordinate,
                             cache_range,
+                            opacity,
                         )?;
                     }
                     row_start += CACHE_BLOCK_HEIGHT;
@@ -232,10 +302,15 @@ impl Compositor for FrameCompositor {
             }
         } else {
             for framebuffer_updates in src_fbs.into_iter() {
+                if framebuffer_updates.scale > 1 {
+                    self.composite_scaled(&framebuffer_updates, dest_fb, dest_bounding_boxes.clone())?;
+                    continue;
+                }
                 //let mut updated_blocks = Vec::new();
                 for bounding_box in dest_bounding_boxes.clone() {
                     let src_fb = framebuffer_updates.src_framebuffer;
                     let coordinate = framebuffer_updates.coordinate_in_dest_framebuffer;
+                    let opacity = framebuffer_updates.opacity;
                     let (_, height) = src_fb.get_size();
                     let mut row_range = self.get_cache_row_range(coordinate, &bounding_box, height);
                     // let cache_block_size = CACHE_BLOCK_HEIGHT * width;
@@ -246,7 +321,7 @@ impl Compositor for FrameCompositor {
                         }
                         let cache_range = row_range.start..(row_range.start + CACHE_BLOCK_HEIGHT);
                         // check cache if the bounding box is not a single pixel
-                        if bounding_box.size() > 1 && self.check_and_cache(src_fb, coordinate, &cache_range)? {
+                        if !self.performance_mode && bounding_box.size() > 1 && self.check_and_cache(src_fb, coordinate, &cache_range, opacity)? {
                             row_range.start += CACHE_BLOCK_HEIGHT;
                             continue;
                         };
@@ -255,6 +330,7 @@ impl Compositor for FrameCompositor {
                             dest_fb,
                             coordinate,
                             cache_range,
+                            opacity,
                         )?;
                         row_range.start += CACHE_BLOCK_HEIGHT;
                     } 
@@ -4,6 +4,7 @@
 #![no_std]
 
 extern crate alloc;
+extern crate display_scale;
 extern crate font;
 extern crate framebuffer;
 extern crate shapes;
@@ -39,25 +40,26 @@ pub fn print_string<P: Pixel>(
     column: usize,
     line: usize,
 ) -> (usize, usize, Rectangle) {
-    let buffer_width = width / CHARACTER_WIDTH;
-    let buffer_height = height / CHARACTER_HEIGHT;
+    let (char_width, char_height) = (font::character_width(), font::character_height());
+    let buffer_width = width / char_width;
+    let buffer_height = height / char_height;
     let (x, y) = (coordinate.x, coordinate.y);
 
     let mut curr_line = line;
     let mut curr_column = column;
 
-    let top_left = Coord::new(0, (curr_line * CHARACTER_HEIGHT) as isize);
+    let top_left = Coord::new(0, (curr_line * char_height) as isize);
 
     for byte in slice.bytes() {
         if byte == b'\n' {
             let mut blank = Rectangle {
                 top_left: Coord::new(
-                    coordinate.x + (curr_column * CHARACTER_WIDTH) as isize,
-                    coordinate.y + (curr_line * CHARACTER_HEIGHT) as isize,
+                    coordinate.x + (curr_column * char_width) as isize,
+                    coordinate.y + (curr_line * char_height) as isize,
                 ),
                 bottom_right: Coord::new(
                     coordinate.x + width as isize,
-                    coordinate.y + ((curr_line + 1) * CHARACTER_HEIGHT) as isize,
+                    coordinate.y + ((curr_line + 1) * char_height) as isize,
                 )
             };
             // fill the remaining blank of current line and go to the next line
@@ -95,12 +97,12 @@ pub fn print_string<P: Pixel>(
 
     let mut blank = Rectangle {
         top_left: Coord::new(
-            x + (curr_column * CHARACTER_WIDTH) as isize,
-            y + (curr_line * CHARACTER_HEIGHT) as isize,
+            x + (curr_column * char_width) as isize,
+            y + (curr_line * char_height) as isize,
         ),
         bottom_right: Coord::new(
             x + width as isize,
-            y + ((curr_line + 1) * CHARACTER_HEIGHT) as isize,
+            y + ((curr_line + 1) * char_height) as isize,
         )
     };
     // fill the blank of the last line
@@ -111,8 +113,8 @@ pub fn print_string<P: Pixel>(
     );
 
     let bottom_right = Coord::new(
-        (buffer_width * CHARACTER_WIDTH) as isize, 
-        ((curr_line + 1) * CHARACTER_HEIGHT) as isize
+        (buffer_width * char_width) as isize, 
+        ((curr_line + 1) * char_height) as isize
     );
 
     let update_area = Rectangle {
@@ -124,7 +126,7 @@ pub fn print_string<P: Pixel>(
     blank = Rectangle {
         top_left: Coord::new(
             x,
-            y + ((curr_line + 1) * CHARACTER_HEIGHT) as isize,
+            y + ((curr_line + 1) * char_height) as isize,
         ),
         bottom_right: Coord::new(
             x + width as isize,
@@ -158,8 +160,10 @@ pub fn print_ascii_character<P: Pixel>(
     column: usize,
     line: usize,
 ) {
-    let start = coordinate + ((column * CHARACTER_WIDTH) as isize, (line * CHARACTER_HEIGHT) as isize);
-    if !framebuffer.overlaps_with(start, CHARACTER_WIDTH, CHARACTER_HEIGHT) {
+    let scale = display_scale::scale_factor();
+    let (char_width, char_height) = (scale.scale(CHARACTER_WIDTH), scale.scale(CHARACTER_HEIGHT));
+    let start = coordinate + ((column * char_width) as isize, (line * char_height) as isize);
+    if !framebuffer.overlaps_with(start, char_width, char_height) {
         return
     }
     // print from the offset within the framebuffer
@@ -171,24 +175,18 @@ pub fn print_ascii_character<P: Pixel>(
     loop {
         let coordinate = start + (j as isize, i as isize);
         if framebuffer.contains(coordinate) {
-            let pixel = if j >= 1 {
-                // leave 1 pixel gap between two characters
-                let index = j - 1;
-                let char_font = font::FONT_BASIC[character as usize][i];
-                if get_bit(char_font, index) != 0 {
-                    fg_pixel
-                } else {
-                    bg_pixel
-                }
+            // the glyph leaves a gap of 1 unscaled pixel between two characters
+            let pixel = if font::is_glyph_pixel(character, scale, j, i) {
+                fg_pixel
             } else {
                 bg_pixel
             };
             framebuffer.draw_pixel(coordinate, pixel);
         }
         j += 1;
-        if j == CHARACTER_WIDTH || start.x + j as isize == buffer_width as isize {
+        if j == char_width || start.x + j as isize == buffer_width as isize {
             i += 1;
-            if i == CHARACTER_HEIGHT || start.y + i as isize == buffer_height as isize {
+            if i == char_height || start.y + i as isize == buffer_height as isize {
                 return
             }
             j = off_set_x;
@@ -227,9 +225,3 @@ pub fn fill_blank<P: Pixel>(
         coordinate.y += 1;
     }
 }
-
-/// Gets the i_th most significant bit of `char_font`. The returned value is `1` or `0`.
-fn get_bit(char_font: u8, i: usize) -> u8 {
-    char_font & (0x80 >> i)
-}
-
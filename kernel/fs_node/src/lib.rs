@@ -73,6 +73,11 @@ pub trait FsNode {
 pub trait File : FsNode + ByteReader + ByteWriter + KnownLength {
     /// Returns a view of this file as an immutable memory-mapped region.
     fn as_mapping(&self) -> Result<&MappedPages, &'static str>;
+
+    /// Truncates or extends this file in place to `len` bytes.
+    ///
+    /// When a file is extended, its new contents read as zeros.
+    fn set_len(&mut self, len: usize) -> Result<(), &'static str>;
 }
 
 /// Trait for directories, implementors of Directory must also implement FsNode
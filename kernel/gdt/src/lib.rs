@@ -59,7 +59,8 @@ impl AvailableSegmentSelector {
 }
 
 
-/// This function first creates and sets up a new TSS with the given double fault stack and privilege stack.
+/// This function first creates and sets up a new TSS with the given double fault stack,
+/// page fault stack, and privilege stack.
 ///
 /// It then creates a new GDT with an entry that references that TSS and loads that new GDT into memory. 
 ///
@@ -72,9 +73,10 @@ impl AvailableSegmentSelector {
 pub fn create_and_load_tss_gdt(
     cpu_id: CpuId,
     double_fault_stack_top_unusable: VirtualAddress,
+    page_fault_stack_top_unusable: VirtualAddress,
     privilege_stack_top_unusable: VirtualAddress
 ) { 
-    let tss_ref = tss::create_tss(cpu_id, double_fault_stack_top_unusable, privilege_stack_top_unusable);
+    let tss_ref = tss::create_tss(cpu_id, double_fault_stack_top_unusable, page_fault_stack_top_unusable, privilege_stack_top_unusable);
     let (gdt, kernel_cs, kernel_ds, user_cs_32, user_ds_32, user_cs_64, user_ds_64, tss_segment) 
         = create_gdt(tss_ref.lock().deref());
 
@@ -0,0 +1,266 @@
+//! A lightweight kernel address sanitizer (KASAN-lite) for the heap,
+//! which is only included when building with the `kasan` cfg option, e.g., `THESEUS_CONFIG="kasan"`.
+//!
+//! Every allocation made through the global [`Heap`](crate::Heap) is surrounded by redzones
+//! filled with a known pattern, and its header records the allocation's size and a backtrace of where it was allocated.
+//! Upon deallocation (including when an allocation is moved by `realloc()`),
+//! the redzones are checked for out-of-bounds writes and the header is checked for double and invalid frees.
+//!
+//! Freed allocations are poisoned and held in a fixed-size quarantine rather than being freed immediately,
+//! such that their memory isn't reused right away.
+//! When an allocation is evicted from the quarantine and actually freed, its poison is checked;
+//! any modification reveals a write to it after it was freed (a use-after-free bug).
+//!
+//! Each detected error is logged along with the backtraces of where the offending allocation
+//! was allocated and freed, which are symbolized by the function registered via [`set_symbolizer()`].
+//! Backtraces are only recorded when building with frame pointers enabled.
+
+use core::alloc::Layout;
+use core::mem::size_of;
+use core::slice;
+use log::error;
+use memory::VirtualAddress;
+use spin::Once;
+use sync_irq::IrqSafeMutex;
+
+/// The number of return addresses recorded in each backtrace.
+const BACKTRACE_DEPTH: usize = 8;
+/// The minimum size of the redzone that precedes each allocation.
+const LEFT_REDZONE_SIZE: usize = 16;
+/// The size of the redzone that follows each allocation.
+pub(crate) const RIGHT_REDZONE_SIZE: usize = 32;
+/// The number of freed allocations held in quarantine before they are actually freed.
+const QUARANTINE_LEN: usize = 512;
+
+/// The byte pattern that fills the redzones around an allocation.
+const REDZONE_POISON: u8 = 0xFB;
+/// The byte pattern that fills an allocation once it has been freed.
+const FREED_POISON: u8 = 0x6B;
+
+/// The value of [`AllocationInfo::magic`] for a live allocation.
+const LIVE_MAGIC: usize = 0x4B41_5341_4E4C_4956;
+/// The value of [`AllocationInfo::magic`] for a freed allocation in quarantine.
+const FREED_MAGIC: usize = 0x4B41_5341_4E46_5245;
+
+/// The metadata stored at the very beginning of each allocation's header.
+#[repr(C)]
+struct AllocationInfo {
+    magic: usize,
+    /// The size of the allocation requested by its caller.
+    size: usize,
+    alloc_backtrace: [usize; BACKTRACE_DEPTH],
+    free_backtrace: [usize; BACKTRACE_DEPTH],
+}
+
+/// The number of bytes that the sanitizer adds to the header of each allocation,
+/// not including the word that holds the allocation's task tag.
+pub(crate) const HEADER_SIZE: usize = size_of::<AllocationInfo>() + LEFT_REDZONE_SIZE;
+
+/// The function used to print a description of a return address in a backtrace.
+static SYMBOLIZER: Once<fn(VirtualAddress)> = Once::new();
+
+/// Sets the function used to print a description of each return address in a backtrace,
+/// e.g., the name of the function that contains it.
+///
+/// Until this is invoked, only the raw addresses are printed.
+/// Only the first invocation of this function has any effect.
+pub fn set_symbolizer(func: fn(VirtualAddress)) {
+    SYMBOLIZER.call_once(|| func);
+}
+
+/// Freed allocations that have not yet been returned to the underlying allocator.
+struct Quarantine {
+    entries: [Option<(usize, Layout)>; QUARANTINE_LEN],
+    /// The index of the next entry to be replaced.
+    next: usize,
+}
+
+static QUARANTINE: IrqSafeMutex<Quarantine> = IrqSafeMutex::new(Quarantine {
+    entries: [None; QUARANTINE_LEN],
+    next: 0,
+});
+
+/// Returns the header of the allocation at `ptr` that was allocated with the given `layout`.
+///
+/// # Safety
+/// `ptr` must have been returned by the global [`Heap`](crate::Heap) for the given `layout`.
+unsafe fn info<'a>(ptr: *mut u8, layout: Layout) -> &'a mut AllocationInfo {
+    // The layout was valid when this block was allocated, so it must still be valid.
+    let (_, header_size) = crate::tagged_layout(layout).unwrap();
+    &mut *(ptr.sub(header_size) as *mut AllocationInfo)
+}
+
+/// Returns the left and right redzones of the allocation at `ptr`.
+///
+/// # Safety
+/// `ptr` must have been returned by the global [`Heap`](crate::Heap) for the given `layout`.
+unsafe fn redzones<'a>(ptr: *mut u8, layout: Layout) -> (&'a mut [u8], &'a mut [u8]) {
+    let (_, header_size) = crate::tagged_layout(layout).unwrap();
+    let left_start = ptr.sub(header_size).add(size_of::<AllocationInfo>());
+    // The last word before `ptr` holds the allocation's task tag.
+    let left_len = header_size - size_of::<AllocationInfo>() - size_of::<usize>();
+    (
+        slice::from_raw_parts_mut(left_start, left_len),
+        slice::from_raw_parts_mut(ptr.add(layout.size()), RIGHT_REDZONE_SIZE),
+    )
+}
+
+/// Sets up the header and redzones of a new allocation at `ptr`.
+///
+/// # Safety
+/// `ptr` must have just been returned by the global [`Heap`](crate::Heap) for the given `layout`.
+pub(crate) unsafe fn on_alloc(ptr: *mut u8, layout: Layout) {
+    let info = info(ptr, layout);
+    info.magic = LIVE_MAGIC;
+    info.size = layout.size();
+    capture_backtrace(&mut info.alloc_backtrace);
+    info.free_backtrace = [0; BACKTRACE_DEPTH];
+    let (left, right) = redzones(ptr, layout);
+    left.fill(REDZONE_POISON);
+    right.fill(REDZONE_POISON);
+}
+
+/// Checks the allocation at `ptr` that is about to be freed, reporting any detected errors,
+/// and then poisons its contents.
+///
+/// Returns `false` if this is a double or invalid free, in which case the allocation must not be freed.
+///
+/// # Safety
+/// `ptr` must have been returned by the global [`Heap`](crate::Heap).
+pub(crate) unsafe fn check_dealloc(ptr: *mut u8, layout: Layout) -> bool {
+    let info = info(ptr, layout);
+    match info.magic {
+        LIVE_MAGIC => { }
+        FREED_MAGIC => {
+            report("double free", ptr, info);
+            return false;
+        }
+        _ => {
+            error!("KASAN: invalid free of {:p} ({:?}): it was not allocated by the heap or its header was overwritten",
+                ptr, layout,
+            );
+            print_current_backtrace();
+            return false;
+        }
+    }
+    if info.size != layout.size() {
+        error!("KASAN: allocation at {:p} was freed with size {}, but was allocated with size {}",
+            ptr, layout.size(), info.size,
+        );
+        print_current_backtrace();
+        return false;
+    }
+    let (left, right) = redzones(ptr, layout);
+    if !is_filled_with(left, REDZONE_POISON) {
+        report("out-of-bounds write before the start of an allocation", ptr, info);
+    }
+    if !is_filled_with(right, REDZONE_POISON) {
+        report("out-of-bounds write past the end of an allocation", ptr, info);
+    }
+
+    info.magic = FREED_MAGIC;
+    capture_backtrace(&mut info.free_backtrace);
+    slice::from_raw_parts_mut(ptr, layout.size()).fill(FREED_POISON);
+    true
+}
+
+/// Places the freed allocation at `ptr` into quarantine.
+///
+/// Returns the allocation that was evicted from the quarantine in order to make room for it, if any,
+/// which must then be freed. The evicted allocation's poison is checked before it is returned.
+///
+/// # Safety
+/// `ptr` must have been returned by the global [`Heap`](crate::Heap) and checked by [`check_dealloc()`].
+pub(crate) unsafe fn quarantine(ptr: *mut u8, layout: Layout) -> Option<(*mut u8, Layout)> {
+    let evicted = {
+        let mut quarantine = QUARANTINE.lock();
+        let next = quarantine.next;
+        quarantine.next = (next + 1) % QUARANTINE_LEN;
+        quarantine.entries[next].replace((ptr as usize, layout))
+    };
+    // Check the evicted allocation only after releasing the lock,
+    // because reporting an error may use the heap, which could free another allocation.
+    let (evicted_ptr, evicted_layout) = evicted?;
+    let evicted_ptr = evicted_ptr as *mut u8;
+    let (left, right) = redzones(evicted_ptr, evicted_layout);
+    if !is_filled_with(slice::from_raw_parts(evicted_ptr, evicted_layout.size()), FREED_POISON)
+        || !is_filled_with(left, REDZONE_POISON)
+        || !is_filled_with(right, REDZONE_POISON)
+    {
+        report("use-after-free write", evicted_ptr, info(evicted_ptr, evicted_layout));
+    }
+    Some((evicted_ptr, evicted_layout))
+}
+
+fn is_filled_with(bytes: &[u8], value: u8) -> bool {
+    bytes.iter().all(|&b| b == value)
+}
+
+/// Logs the given kind of error for the allocation at `ptr`,
+/// along with where it was allocated, freed, and where the error was detected.
+fn report(kind: &str, ptr: *mut u8, info: &AllocationInfo) {
+    error!("KASAN: {} detected for the allocation at {:p} of {} bytes", kind, ptr, info.size);
+    error!("  allocated at:");
+    print_backtrace(&info.alloc_backtrace);
+    if info.magic == FREED_MAGIC {
+        error!("  freed at:");
+        print_backtrace(&info.free_backtrace);
+    }
+    print_current_backtrace();
+}
+
+fn print_current_backtrace() {
+    let mut backtrace = [0; BACKTRACE_DEPTH];
+    capture_backtrace(&mut backtrace);
+    error!("  detected at:");
+    print_backtrace(&backtrace);
+}
+
+fn print_backtrace(backtrace: &[usize]) {
+    if backtrace[0] == 0 {
+        error!("    <no backtrace available>");
+    }
+    for &return_address in backtrace.iter().take_while(|&&addr| addr != 0) {
+        match (SYMBOLIZER.get(), VirtualAddress::new(return_address)) {
+            (Some(symbolizer), Some(vaddr)) => symbolizer(vaddr),
+            _ => error!("    {:>#018X}", return_address),
+        }
+    }
+}
+
+/// Records the return addresses of the current call stack into `backtrace`
+/// by walking the chain of frame pointers, checking that each frame is mapped.
+#[cfg(all(frame_pointers, target_arch = "x86_64"))]
+fn capture_backtrace(backtrace: &mut [usize; BACKTRACE_DEPTH]) {
+    let mut frame_pointer: usize;
+    // SAFE: just reading the current value of the frame pointer register
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) frame_pointer); }
+
+    for slot in backtrace.iter_mut() {
+        // The caller's return address is right after the saved frame pointer.
+        let is_mapped = |addr: usize| VirtualAddress::new(addr).and_then(memory::translate).is_some();
+        if frame_pointer % size_of::<usize>() != 0
+            || !is_mapped(frame_pointer)
+            || !is_mapped(frame_pointer + size_of::<usize>())
+        {
+            break;
+        }
+        // SAFE: both addresses were checked above using page table walks.
+        let (next_frame_pointer, return_address) = unsafe {
+            (*(frame_pointer as *const usize), *((frame_pointer + size_of::<usize>()) as *const usize))
+        };
+        if return_address == 0 {
+            break;
+        }
+        *slot = return_address;
+        // The stack grows downwards, so the frame pointers of callers must be at higher addresses.
+        if next_frame_pointer <= frame_pointer {
+            break;
+        }
+        frame_pointer = next_frame_pointer;
+    }
+}
+
+/// Backtraces require frame pointers, so none are recorded.
+#[cfg(not(all(frame_pointers, target_arch = "x86_64")))]
+fn capture_backtrace(_backtrace: &mut [usize; BACKTRACE_DEPTH]) { }
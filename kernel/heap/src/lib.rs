@@ -11,6 +11,7 @@ extern crate spin;
 extern crate memory;
 extern crate kernel_config;
 extern crate block_allocator;
+extern crate log;
 
 use alloc::alloc::{GlobalAlloc, Layout};
 use memory::PteFlags;
@@ -20,6 +21,14 @@ use spin::Once;
 use alloc::boxed::Box;
 use block_allocator::FixedSizeBlockAllocator;
 
+mod stats;
+pub use stats::*;
+#[cfg_attr(not(task_heap_usage), allow(dead_code))]
+mod task_usage;
+pub use task_usage::{TaskHeapUsage, set_task_id_source, task_heap_usage, all_task_heap_usage, untracked_heap_usage, task_exited};
+#[cfg(kasan)]
+pub mod kasan;
+
 
 #[global_allocator]
 pub static GLOBAL_ALLOCATOR: Heap = Heap::empty();
@@ -49,6 +58,7 @@ const INITIAL_HEAP_END_ADDR: usize = KERNEL_HEAP_START + KERNEL_HEAP_INITIAL_SIZ
 /// Initializes the single heap, which is the first heap used by the system.
 pub fn init_single_heap(start_virt_addr: usize, size_in_bytes: usize) {
     unsafe { GLOBAL_ALLOCATOR.initial_allocator.lock().init(start_virt_addr, size_in_bytes); }
+    record_heap_mapping(size_in_bytes);
 }
 
 
@@ -75,27 +85,94 @@ impl Heap {
     }
 }
 
+/// The number of bytes added to the header of each allocation by the address sanitizer.
+#[cfg(kasan)]
+const SANITIZER_HEADER_SIZE: usize = kasan::HEADER_SIZE;
+#[cfg(not(kasan))]
+const SANITIZER_HEADER_SIZE: usize = 0;
+/// The number of bytes added after each allocation by the address sanitizer.
+#[cfg(kasan)]
+const SANITIZER_TRAILER_SIZE: usize = kasan::RIGHT_REDZONE_SIZE;
+#[cfg(not(kasan))]
+const SANITIZER_TRAILER_SIZE: usize = 0;
+/// The number of bytes added to the header of each allocation to attribute it to a task.
+#[cfg(task_heap_usage)]
+const TAG_SIZE: usize = core::mem::size_of::<usize>();
+#[cfg(not(task_heap_usage))]
+const TAG_SIZE: usize = 0;
+
+/// Returns the layout of an allocation that includes a header before the requested `layout`,
+/// along with the size of that header.
+///
+/// The header preserves the alignment of the requested layout.
+/// When the `task_heap_usage` cfg option is enabled, its last word holds the tag
+/// used to attribute the allocation to a task.
+/// When the address sanitizer is enabled, the header also holds its metadata and redzone,
+/// and another redzone follows the requested layout.
+/// Otherwise, there is no header and the requested layout is returned as-is.
+fn tagged_layout(layout: Layout) -> Option<(Layout, usize)> {
+    if SANITIZER_HEADER_SIZE + TAG_SIZE + SANITIZER_TRAILER_SIZE == 0 {
+        return Some((layout, 0));
+    }
+    let align = layout.align().max(core::mem::size_of::<usize>());
+    let header_size = (SANITIZER_HEADER_SIZE + TAG_SIZE).checked_next_multiple_of(align)?;
+    let size = layout.size().checked_add(header_size)?.checked_add(SANITIZER_TRAILER_SIZE)?;
+    Layout::from_size_align(size, align).ok().map(|l| (l, header_size))
+}
+
 unsafe impl GlobalAlloc for Heap {
 
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        match DEFAULT_ALLOCATOR.get() {
+        let Some((tagged_layout, header_size)) = tagged_layout(layout) else {
+            stats::record_failed_alloc();
+            return core::ptr::null_mut();
+        };
+        let raw = match DEFAULT_ALLOCATOR.get() {
             Some(allocator) => {
-                allocator.alloc(layout)
+                allocator.alloc(tagged_layout)
             }
             None => {       
-                self.initial_allocator.lock().allocate(layout)
+                self.initial_allocator.lock().allocate(tagged_layout)
             }
+        };
+        if raw.is_null() {
+            stats::record_failed_alloc();
+            return raw;
         }
+        stats::record_alloc(layout.size());
+        let ptr = raw.add(header_size);
+        #[cfg(task_heap_usage)]
+        (ptr as *mut usize).sub(1).write(task_usage::record_alloc(layout.size()));
+        #[cfg(kasan)]
+        kasan::on_alloc(ptr, layout);
+        ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        if KERNEL_HEAP_START <= (ptr as usize) && (ptr as usize) < INITIAL_HEAP_END_ADDR {
-            self.initial_allocator.lock().deallocate(ptr, layout);
+        #[cfg(kasan)]
+        if !kasan::check_dealloc(ptr, layout) {
+            // Freeing this allocation (again) could corrupt the heap, so it is leaked instead.
+            return;
+        }
+        stats::record_dealloc(layout.size());
+        #[cfg(task_heap_usage)]
+        task_usage::record_dealloc((ptr as *mut usize).sub(1).read(), layout.size());
+
+        // With the address sanitizer, the freed allocation is quarantined,
+        // and a previously-quarantined allocation is freed in its place.
+        #[cfg(kasan)]
+        let Some((ptr, layout)) = kasan::quarantine(ptr, layout) else { return };
+
+        // The layout was valid when this block was allocated, so it must still be valid.
+        let (tagged_layout, header_size) = tagged_layout(layout).unwrap();
+        let raw = ptr.sub(header_size);
+        if KERNEL_HEAP_START <= (raw as usize) && (raw as usize) < INITIAL_HEAP_END_ADDR {
+            self.initial_allocator.lock().deallocate(raw, tagged_layout);
         }
         else {
             DEFAULT_ALLOCATOR.get()
                 .expect("Ptr passed to dealloc is not within the initial allocator's range, and another allocator has not been set up")
-                .dealloc(ptr, layout);
+                .dealloc(raw, tagged_layout);
         }
     }
 
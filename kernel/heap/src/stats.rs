@@ -0,0 +1,145 @@
+//! Statistics about the usage of the kernel heap.
+//!
+//! These are recorded by the global [`Heap`](crate::Heap) on every allocation and deallocation,
+//! regardless of which underlying allocator is currently in use.
+//! All counters are updated with relaxed atomics, so a [`HeapStats`] snapshot
+//! may be slightly inconsistent if taken while other CPUs are allocating.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of size classes tracked by the heap statistics.
+pub const NUM_SIZE_CLASSES: usize = 12;
+
+/// The maximum size in bytes (inclusive) of an allocation in each size class.
+///
+/// These mirror the slab sizes of the per-core heaps,
+/// with the final size class covering all large allocations.
+pub const SIZE_CLASSES: [usize; NUM_SIZE_CLASSES] = [
+    8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096, 8192, usize::MAX,
+];
+
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of bytes of virtual memory that have been mapped for the heap,
+/// excluding large allocations that are mapped individually.
+static MAPPED_BYTES: AtomicUsize = ZERO;
+static USED_BYTES: AtomicUsize = ZERO;
+static PEAK_USED_BYTES: AtomicUsize = ZERO;
+static TOTAL_ALLOCATIONS: AtomicUsize = ZERO;
+static TOTAL_DEALLOCATIONS: AtomicUsize = ZERO;
+static FAILED_ALLOCATIONS: AtomicUsize = ZERO;
+static CLASS_LIVE_OBJECTS: [AtomicUsize; NUM_SIZE_CLASSES] = [ZERO; NUM_SIZE_CLASSES];
+static CLASS_LIVE_BYTES: [AtomicUsize; NUM_SIZE_CLASSES] = [ZERO; NUM_SIZE_CLASSES];
+static CLASS_TOTAL_ALLOCATIONS: [AtomicUsize; NUM_SIZE_CLASSES] = [ZERO; NUM_SIZE_CLASSES];
+
+
+/// A snapshot of the kernel heap's usage statistics.
+#[derive(Debug, Clone)]
+pub struct HeapStats {
+    /// The total size of the heap in bytes, including both the mapped heap regions
+    /// and all live large allocations.
+    pub total_bytes: usize,
+    /// The number of bytes currently allocated, based on the requested allocation sizes.
+    pub used_bytes: usize,
+    /// The highest value of `used_bytes` observed since boot.
+    pub peak_used_bytes: usize,
+    /// The number of successful allocations since boot.
+    pub total_allocations: usize,
+    /// The number of deallocations since boot.
+    pub total_deallocations: usize,
+    /// The number of allocation requests that could not be satisfied.
+    pub failed_allocations: usize,
+    /// Occupancy of each size class, in the same order as [`SIZE_CLASSES`].
+    pub size_classes: [SizeClassStats; NUM_SIZE_CLASSES],
+}
+
+impl HeapStats {
+    /// Returns the number of heap bytes that are not currently allocated.
+    pub fn free_bytes(&self) -> usize {
+        self.total_bytes.saturating_sub(self.used_bytes)
+    }
+
+    /// Returns the number of allocations that have not yet been freed.
+    pub fn live_allocations(&self) -> usize {
+        self.total_allocations.saturating_sub(self.total_deallocations)
+    }
+}
+
+/// Usage statistics for a single size class.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeClassStats {
+    /// The maximum size in bytes of an allocation in this size class.
+    pub max_size: usize,
+    /// The number of allocations in this size class that have not yet been freed.
+    pub live_objects: usize,
+    /// The number of bytes currently allocated in this size class.
+    pub live_bytes: usize,
+    /// The number of allocations in this size class since boot.
+    pub total_allocations: usize,
+}
+
+/// Returns a snapshot of the current heap statistics.
+pub fn heap_stats() -> HeapStats {
+    let mut size_classes = [SizeClassStats::default(); NUM_SIZE_CLASSES];
+    for (i, class) in size_classes.iter_mut().enumerate() {
+        *class = SizeClassStats {
+            max_size: SIZE_CLASSES[i],
+            live_objects: CLASS_LIVE_OBJECTS[i].load(Ordering::Relaxed),
+            live_bytes: CLASS_LIVE_BYTES[i].load(Ordering::Relaxed),
+            total_allocations: CLASS_TOTAL_ALLOCATIONS[i].load(Ordering::Relaxed),
+        };
+    }
+    let large_bytes = size_classes[NUM_SIZE_CLASSES - 1].live_bytes;
+
+    HeapStats {
+        total_bytes: MAPPED_BYTES.load(Ordering::Relaxed) + large_bytes,
+        used_bytes: USED_BYTES.load(Ordering::Relaxed),
+        peak_used_bytes: PEAK_USED_BYTES.load(Ordering::Relaxed),
+        total_allocations: TOTAL_ALLOCATIONS.load(Ordering::Relaxed),
+        total_deallocations: TOTAL_DEALLOCATIONS.load(Ordering::Relaxed),
+        failed_allocations: FAILED_ALLOCATIONS.load(Ordering::Relaxed),
+        size_classes,
+    }
+}
+
+/// Records that `size_in_bytes` of memory has been mapped for use by the heap.
+///
+/// This should be invoked by allocators whenever they grow the heap,
+/// but not for large allocations, which are accounted for separately.
+pub fn record_heap_mapping(size_in_bytes: usize) {
+    MAPPED_BYTES.fetch_add(size_in_bytes, Ordering::Relaxed);
+}
+
+/// Records that `size_in_bytes` of memory has been unmapped from the heap.
+pub fn record_heap_unmapping(size_in_bytes: usize) {
+    MAPPED_BYTES.fetch_sub(size_in_bytes, Ordering::Relaxed);
+}
+
+/// Returns the index into [`SIZE_CLASSES`] of the size class that fits `size`.
+fn size_class_index(size: usize) -> usize {
+    SIZE_CLASSES.iter()
+        .position(|&max| size <= max)
+        .unwrap_or(NUM_SIZE_CLASSES - 1)
+}
+
+pub(crate) fn record_alloc(size: usize) {
+    let class = size_class_index(size);
+    CLASS_LIVE_OBJECTS[class].fetch_add(1, Ordering::Relaxed);
+    CLASS_LIVE_BYTES[class].fetch_add(size, Ordering::Relaxed);
+    CLASS_TOTAL_ALLOCATIONS[class].fetch_add(1, Ordering::Relaxed);
+    TOTAL_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    let used = USED_BYTES.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK_USED_BYTES.fetch_max(used, Ordering::Relaxed);
+}
+
+pub(crate) fn record_failed_alloc() {
+    FAILED_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn record_dealloc(size: usize) {
+    let class = size_class_index(size);
+    CLASS_LIVE_OBJECTS[class].fetch_sub(1, Ordering::Relaxed);
+    CLASS_LIVE_BYTES[class].fetch_sub(size, Ordering::Relaxed);
+    TOTAL_DEALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    USED_BYTES.fetch_sub(size, Ordering::Relaxed);
+}
@@ -0,0 +1,200 @@
+//! Attribution of heap usage to the tasks that allocated it.
+//!
+//! Every heap allocation made through the global [`Heap`](crate::Heap) is tagged
+//! with a small header that records which task allocated it,
+//! such that it is attributed to that task until it is freed, even if another task frees it.
+//!
+//! The heap cannot depend on the tasking subsystem, so the ID of the current task
+//! is obtained from a function registered via [`set_task_id_source()`].
+//! Until then, all allocations are attributed to no task.
+//!
+//! Per-task usage is tracked in a fixed-size table of atomic counters,
+//! as allocating within the allocator is impossible.
+//! A task's entry is released once it has exited and all of its allocations have been freed;
+//! allocations by tasks that don't fit into the table are counted as untracked.
+//!
+//! Tagging allocations requires a header before each one, so this is only done
+//! if Theseus is built with `THESEUS_CONFIG="task_heap_usage"`;
+//! otherwise, no usage is attributed to any task.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Once;
+
+/// The maximum number of tasks whose heap usage can be tracked at once.
+const NUM_TASK_SLOTS: usize = 1024;
+/// The tag of an allocation that isn't attributed to any task.
+pub(crate) const UNTRACKED: usize = usize::MAX;
+/// The bit of [`TaskSlot::state`] that indicates that the owning task has exited.
+const EXITED: usize = 1 << (usize::BITS - 1);
+
+struct TaskSlot {
+    /// The ID of the task that owns this slot, or 0 if the slot is free.
+    task_id: AtomicUsize,
+    live_bytes: AtomicUsize,
+    /// The number of live allocations, along with the [`EXITED`] bit.
+    ///
+    /// These are combined such that exactly one thread observes the transition to
+    /// "exited with no live allocations", and that thread alone releases the slot.
+    state: AtomicUsize,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SLOT: TaskSlot = TaskSlot {
+    task_id: AtomicUsize::new(0),
+    live_bytes: AtomicUsize::new(0),
+    state: AtomicUsize::new(0),
+};
+
+static TASK_SLOTS: [TaskSlot; NUM_TASK_SLOTS] = [EMPTY_SLOT; NUM_TASK_SLOTS];
+static UNTRACKED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static UNTRACKED_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// The function that returns the ID of the current task, or 0 if there is none.
+static TASK_ID_SOURCE: Once<fn() -> usize> = Once::new();
+
+/// The heap usage of a single task.
+#[derive(Debug, Clone, Copy)]
+pub struct TaskHeapUsage {
+    /// The ID of the task.
+    pub task_id: usize,
+    /// The number of bytes allocated by this task that have not yet been freed.
+    pub live_bytes: usize,
+    /// The number of allocations by this task that have not yet been freed.
+    pub live_allocations: usize,
+    /// Whether this task has exited, in which case its remaining allocations
+    /// are owned by other tasks or have been leaked.
+    pub exited: bool,
+}
+
+/// Sets the function used to obtain the ID of the current task,
+/// which enables the attribution of heap allocations to tasks.
+///
+/// The given function must not allocate, and must be usable in every execution context
+/// from which the heap may be used, which is why this should only be invoked
+/// once all CPUs have been initialized.
+/// Only the first invocation of this function has any effect.
+pub fn set_task_id_source(func: fn() -> usize) {
+    TASK_ID_SOURCE.call_once(|| func);
+}
+
+/// Returns the heap usage of the task with the given ID, if any of its allocations are live.
+pub fn task_heap_usage(task_id: usize) -> Option<TaskHeapUsage> {
+    find_slot(task_id).map(|index| slot_usage(&TASK_SLOTS[index]))
+}
+
+/// Returns the heap usage of every task that has live allocations,
+/// including tasks that have exited.
+pub fn all_task_heap_usage() -> Vec<TaskHeapUsage> {
+    TASK_SLOTS.iter()
+        .filter(|slot| slot.task_id.load(Ordering::Acquire) != 0)
+        .map(slot_usage)
+        .filter(|usage| usage.task_id != 0 && (usage.live_allocations > 0 || !usage.exited))
+        .collect()
+}
+
+/// Returns the number of live bytes and allocations that aren't attributed to any task,
+/// e.g., those made before tasking was initialized.
+pub fn untracked_heap_usage() -> (usize, usize) {
+    (UNTRACKED_BYTES.load(Ordering::Relaxed), UNTRACKED_ALLOCATIONS.load(Ordering::Relaxed))
+}
+
+/// Notifies the heap that the task with the given ID has exited,
+/// allowing its entry to be released once all of its allocations are freed.
+pub fn task_exited(task_id: usize) {
+    if let Some(index) = find_slot(task_id) {
+        let slot = &TASK_SLOTS[index];
+        let previous = slot.state.fetch_or(EXITED, Ordering::AcqRel);
+        if previous == 0 {
+            release_slot(slot);
+        }
+    }
+}
+
+fn slot_usage(slot: &TaskSlot) -> TaskHeapUsage {
+    let state = slot.state.load(Ordering::Relaxed);
+    TaskHeapUsage {
+        task_id: slot.task_id.load(Ordering::Acquire),
+        live_bytes: slot.live_bytes.load(Ordering::Relaxed),
+        live_allocations: state & !EXITED,
+        exited: state & EXITED != 0,
+    }
+}
+
+fn start_index(task_id: usize) -> usize {
+    task_id.wrapping_mul(0x9E37_79B9) % NUM_TASK_SLOTS
+}
+
+fn find_slot(task_id: usize) -> Option<usize> {
+    if task_id == 0 {
+        return None;
+    }
+    let start = start_index(task_id);
+    (0..NUM_TASK_SLOTS)
+        .map(|i| (start + i) % NUM_TASK_SLOTS)
+        .find(|&index| TASK_SLOTS[index].task_id.load(Ordering::Acquire) == task_id)
+}
+
+/// Returns the index of the current task's slot, claiming a free slot if it doesn't have one yet.
+fn current_slot() -> Option<usize> {
+    let task_id = TASK_ID_SOURCE.get().map(|f| f())?;
+    if task_id == 0 {
+        return None;
+    }
+    if let Some(index) = find_slot(task_id) {
+        return Some(index);
+    }
+    let start = start_index(task_id);
+    (0..NUM_TASK_SLOTS)
+        .map(|i| (start + i) % NUM_TASK_SLOTS)
+        .find(|&index| TASK_SLOTS[index].task_id.compare_exchange(0, task_id, Ordering::AcqRel, Ordering::Acquire).is_ok())
+}
+
+/// Frees the given slot, which must have just transitioned to having exited with no live allocations.
+///
+/// Only one thread can observe that transition, so no other thread modifies the slot until
+/// it's been freed here. The task ID is cleared last, such that the slot can't be claimed
+/// by another task until it has been reset.
+fn release_slot(slot: &TaskSlot) {
+    slot.live_bytes.store(0, Ordering::Relaxed);
+    slot.state.store(0, Ordering::Relaxed);
+    slot.task_id.store(0, Ordering::Release);
+}
+
+/// Attributes a new allocation of `size` bytes to the current task and returns its tag,
+/// which is the index of that task's slot.
+pub(crate) fn record_alloc(size: usize) -> usize {
+    let tag = current_slot().filter(|&index| {
+        // A slot whose task has exited with no live allocations is being released,
+        // so it must not gain a new allocation.
+        TASK_SLOTS[index].state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| (state != EXITED).then_some(state + 1))
+            .is_ok()
+    });
+    match tag {
+        Some(index) => {
+            TASK_SLOTS[index].live_bytes.fetch_add(size, Ordering::Relaxed);
+            index
+        }
+        None => {
+            UNTRACKED_BYTES.fetch_add(size, Ordering::Relaxed);
+            UNTRACKED_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            UNTRACKED
+        }
+    }
+}
+
+/// Removes an allocation of `size` bytes with the given `tag` from its task's usage.
+pub(crate) fn record_dealloc(tag: usize, size: usize) {
+    if tag >= NUM_TASK_SLOTS {
+        UNTRACKED_BYTES.fetch_sub(size, Ordering::Relaxed);
+        UNTRACKED_ALLOCATIONS.fetch_sub(1, Ordering::Relaxed);
+        return;
+    }
+    let slot = &TASK_SLOTS[tag];
+    slot.live_bytes.fetch_sub(size, Ordering::Relaxed);
+    let previous = slot.state.fetch_sub(1, Ordering::AcqRel);
+    if previous == EXITED | 1 {
+        release_slot(slot);
+    }
+}
@@ -0,0 +1,57 @@
+//! A background task that periodically returns empty heap pages to the frame allocator.
+//!
+//! The per-core heaps grow in large chunks whenever they run out of memory, but never shrink on their own.
+//! The shrinker task spawned by [`start()`] periodically invokes [`multiple_heaps::shrink_heaps()`],
+//! which unmaps the empty pages beyond those that each heap keeps in reserve.
+//!
+//! When physical memory runs out, empty heap pages are also reclaimed immediately
+//! via the `memory_pressure` shrinker that the `multiple_heaps` crate registers.
+
+#![no_std]
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use log::{debug, info};
+use task::JoinableTaskRef;
+use time::Duration;
+
+/// How often the shrinker task returns empty heap pages.
+pub const SHRINK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Whether the shrinker task returns empty heap pages when it wakes up.
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Enables or disables the periodic shrinking of the heap.
+///
+/// This doesn't affect reclaiming heap pages under memory pressure.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether the periodic shrinking of the heap is enabled.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Spawns the heap shrinker task, which runs forever.
+///
+/// This should only be called once, after the per-core heaps have been set up.
+pub fn start() -> Result<JoinableTaskRef, &'static str> {
+    spawn::new_task_builder(heap_shrinker, ())
+        .name("heap_shrinker".into())
+        .spawn()
+}
+
+/// The entry point for the heap shrinker task.
+fn heap_shrinker(_: ()) -> Result<(), &'static str> {
+    info!("heap_shrinker task started");
+    loop {
+        sleep::sleep(SHRINK_INTERVAL).map_err(|_| "heap_shrinker: failed to sleep")?;
+        if !is_enabled() {
+            continue;
+        }
+        let unmapped = multiple_heaps::shrink_heaps(usize::MAX);
+        if unmapped > 0 {
+            debug!("heap_shrinker: heap is now {} KiB", heap::heap_stats().total_bytes / 1024);
+        }
+    }
+}
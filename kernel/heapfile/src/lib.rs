@@ -90,6 +90,11 @@ impl File for HeapFile {
     fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
         Err("Mapping a HeapFile as a MappedPages object is unimplemented")
     }
+
+    fn set_len(&mut self, len: usize) -> Result<(), &'static str> {
+        self.vec.resize(len, 0u8);
+        Ok(())
+    }
 }
 
 impl FsNode for HeapFile {
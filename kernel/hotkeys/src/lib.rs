@@ -0,0 +1,214 @@
+//! A global registry of hotkeys, i.e., keyboard shortcuts that work regardless of which window is active.
+//!
+//! The window manager and applications [`register()`] key chords like `Alt+Tab` or `Super+Left`
+//! along with a handler that is invoked whenever that chord is pressed.
+//! Only one handler may be registered for a given chord;
+//! registering a chord that is already taken fails, and [`lookup()`] reveals its current owner.
+//! All registered hotkeys can be listed via [`registered()`].
+//!
+//! Whoever receives keyboard input first, typically the window manager,
+//! must pass each key event to [`dispatch()`] before delivering it elsewhere,
+//! and should drop events that were consumed by a hotkey.
+//!
+//! Left and right variants of modifier keys are treated the same,
+//! and lock keys like `CapsLock` are ignored when matching chords.
+//! Only keys that have a [`Keycode`] can be used in hotkeys;
+//! multimedia keys like volume controls are not yet decoded by the keyboard driver.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::fmt;
+use keycodes_ascii::{KeyAction, KeyEvent, Keycode};
+use log::{error, warn};
+use spin::Mutex;
+
+/// A key chord: a non-modifier key pressed while holding a set of modifier keys.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hotkey {
+    pub keycode: Keycode,
+    pub control: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub super_key: bool,
+}
+
+impl Hotkey {
+    /// Returns a hotkey for the given key without any modifiers.
+    ///
+    /// Use the builder-style methods like [`Hotkey::control()`] to add modifiers.
+    pub const fn new(keycode: Keycode) -> Hotkey {
+        Hotkey { keycode, control: false, shift: false, alt: false, super_key: false }
+    }
+
+    /// Returns a copy of this hotkey that also requires holding `Ctrl`.
+    pub const fn control(mut self) -> Hotkey {
+        self.control = true;
+        self
+    }
+
+    /// Returns a copy of this hotkey that also requires holding `Shift`.
+    pub const fn shift(mut self) -> Hotkey {
+        self.shift = true;
+        self
+    }
+
+    /// Returns a copy of this hotkey that also requires holding `Alt`.
+    pub const fn alt(mut self) -> Hotkey {
+        self.alt = true;
+        self
+    }
+
+    /// Returns a copy of this hotkey that also requires holding the `Super` key.
+    pub const fn super_key(mut self) -> Hotkey {
+        self.super_key = true;
+        self
+    }
+
+    /// Returns the hotkey corresponding to the given key event,
+    /// or `None` if the event isn't a key press or is for a modifier key itself.
+    pub fn from_event(event: &KeyEvent) -> Option<Hotkey> {
+        if event.action != KeyAction::Pressed || is_modifier(event.keycode) {
+            return None;
+        }
+        Some(Hotkey {
+            keycode: event.keycode,
+            control: event.modifiers.is_control(),
+            shift: event.modifiers.is_shift(),
+            alt: event.modifiers.is_alt(),
+            super_key: event.modifiers.is_super_key(),
+        })
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.control   { write!(f, "Ctrl+")?; }
+        if self.shift     { write!(f, "Shift+")?; }
+        if self.alt       { write!(f, "Alt+")?; }
+        if self.super_key { write!(f, "Super+")?; }
+        write!(f, "{:?}", self.keycode)
+    }
+}
+
+/// Returns whether the given key is a modifier key, which can't be the main key of a hotkey.
+fn is_modifier(keycode: Keycode) -> bool {
+    matches!(keycode,
+        Keycode::Control | Keycode::Alt | Keycode::LeftShift | Keycode::RightShift
+        | Keycode::SuperKeyLeft | Keycode::SuperKeyRight
+        | Keycode::CapsLock | Keycode::NumLock | Keycode::ScrollLock
+    )
+}
+
+/// A function invoked when its hotkey is pressed.
+///
+/// Handlers are invoked in the context of the task that called [`dispatch()`],
+/// e.g., the window manager's event loop, so they should return quickly.
+pub type HotkeyHandler = Arc<dyn Fn(Hotkey) -> Result<(), &'static str> + Send + Sync>;
+
+/// A unique identifier of a hotkey registration, used to [`unregister()`] it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HotkeyId(u64);
+
+/// Information about a registered hotkey.
+#[derive(Clone, Debug)]
+pub struct HotkeyInfo {
+    pub id: HotkeyId,
+    pub hotkey: Hotkey,
+    /// The name of the component that registered the hotkey, e.g., `"window_manager"`.
+    pub owner: String,
+    /// A human-readable description of what the hotkey does.
+    pub description: String,
+}
+
+struct Registration {
+    info: HotkeyInfo,
+    handler: HotkeyHandler,
+}
+
+struct Registry {
+    registrations: Vec<Registration>,
+    next_id: u64,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry { registrations: Vec::new(), next_id: 0 });
+
+/// Registers the given `handler` to be invoked whenever `hotkey` is pressed.
+///
+/// * `owner`: the name of the component registering the hotkey, shown when querying hotkeys.
+/// * `description`: what the hotkey does, shown when querying hotkeys.
+///
+/// Returns an error if `hotkey` is already registered (see [`lookup()`])
+/// or if its key is a modifier key.
+pub fn register<F>(
+    hotkey: Hotkey,
+    owner: &str,
+    description: &str,
+    handler: F,
+) -> Result<HotkeyId, &'static str>
+    where F: Fn(Hotkey) -> Result<(), &'static str> + Send + Sync + 'static
+{
+    if is_modifier(hotkey.keycode) {
+        return Err("hotkeys: a modifier key can't be the main key of a hotkey");
+    }
+    let mut registry = REGISTRY.lock();
+    if let Some(existing) = registry.registrations.iter().find(|r| r.info.hotkey == hotkey) {
+        warn!("hotkeys: {} can't register {}, which is already registered by {}",
+            owner, hotkey, existing.info.owner,
+        );
+        return Err("hotkeys: the hotkey is already registered");
+    }
+    let id = HotkeyId(registry.next_id);
+    registry.next_id += 1;
+    registry.registrations.push(Registration {
+        info: HotkeyInfo {
+            id,
+            hotkey,
+            owner: String::from(owner),
+            description: String::from(description),
+        },
+        handler: Arc::new(handler),
+    });
+    Ok(id)
+}
+
+/// Unregisters the hotkey registration with the given ID.
+///
+/// Returns the information about the removed registration, if it existed.
+pub fn unregister(id: HotkeyId) -> Option<HotkeyInfo> {
+    let mut registry = REGISTRY.lock();
+    let index = registry.registrations.iter().position(|r| r.info.id == id)?;
+    Some(registry.registrations.remove(index).info)
+}
+
+/// Returns information about the registration of the given `hotkey`, if it is registered.
+pub fn lookup(hotkey: Hotkey) -> Option<HotkeyInfo> {
+    REGISTRY.lock().registrations.iter()
+        .find(|r| r.info.hotkey == hotkey)
+        .map(|r| r.info.clone())
+}
+
+/// Returns information about all registered hotkeys, in the order they were registered.
+pub fn registered() -> Vec<HotkeyInfo> {
+    REGISTRY.lock().registrations.iter().map(|r| r.info.clone()).collect()
+}
+
+/// Invokes the handler of the hotkey corresponding to the given key event, if one is registered.
+///
+/// Returns `true` if the event was consumed by a hotkey handler,
+/// in which case it should not be delivered elsewhere.
+/// An error returned by the handler is logged, but the event is still considered consumed.
+pub fn dispatch(event: &KeyEvent) -> bool {
+    let Some(hotkey) = Hotkey::from_event(event) else { return false };
+    // Release the lock before invoking the handler, which may register or unregister hotkeys.
+    let handler = REGISTRY.lock().registrations.iter()
+        .find(|r| r.info.hotkey == hotkey)
+        .map(|r| r.handler.clone());
+    let Some(handler) = handler else { return false };
+    if let Err(e) = handler(hotkey) {
+        error!("hotkeys: handler for {} failed: {}", hotkey, e);
+    }
+    true
+}
@@ -0,0 +1,318 @@
+//! A background task that detects hung tasks and reports actionable information about them.
+//!
+//! The detector task spawned by [`start()`] periodically scans all tasks for two conditions:
+//! 1. **Long-blocked tasks**: tasks that have been blocked for longer than
+//!    [`HungTaskConfig::blocked_threshold`] while waiting on a named wait queue or lock,
+//!    e.g., a [`WaitQueue`] created with [`WaitQueue::with_name()`].
+//!    Tasks that block without a wait reason (e.g., sleeping tasks or idle services)
+//!    are not reported unless [`HungTaskConfig::report_unnamed_waits`] is enabled.
+//! 2. **Starved tasks**: tasks that have been runnable but have not been scheduled in
+//!    for longer than [`HungTaskConfig::starvation_threshold`].
+//!
+//!
+//! It also acts as a watchdog for CPU cores, using the per-CPU heartbeats from the [`watchdog`] crate
+//! to detect **stuck CPUs**: cores that have had interrupts or preemption disabled for longer than
+//! [`HungTaskConfig::stuck_cpu_threshold`], e.g., because they are spinning on a lock.
+//! Since a stuck CPU's task is still running, its stack can't be walked;
+//! instead, the report includes the function that the CPU was executing at its last timer tick.
+//!
+//! Tasks are only timed from when the detector is started, so a task that was already blocked
+//! before then is measured from its next runstate change.
+//!
+//! Each hung task or stuck CPU is reported once per episode, i.e., a task that stays hung is not
+//! reported again on every scan, but will be reported again if it hangs again later.
+//! Reports include the task's wait reason and, if possible, a backtrace of its call stack.
+//!
+//! Backtraces of other (non-running) tasks are obtained by walking frame pointers
+//! starting from the frame pointer saved in the task's context,
+//! so they are only available when Theseus is built with frame pointers enabled.
+//!
+//! [`WaitQueue`]: ../wait_queue/struct.WaitQueue.html
+//! [`WaitQueue::with_name()`]: ../wait_queue/struct.WaitQueue.html#method.with_name
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::fmt;
+use log::{info, warn};
+use spin::Mutex;
+use task::{JoinableTaskRef, RunState, TaskRef};
+use time::{Duration, Instant};
+use cpu::CpuId;
+use watchdog::{CpuWatchdog, StuckCpu, StuckReason};
+
+/// The configuration of the hung task detector.
+#[derive(Clone, Copy, Debug)]
+pub struct HungTaskConfig {
+    /// How often the detector scans all tasks.
+    pub scan_interval: Duration,
+    /// The minimum duration that a task must be blocked for in order to be reported.
+    pub blocked_threshold: Duration,
+    /// The minimum duration that a runnable task must go without being scheduled in
+    /// in order to be reported.
+    pub starvation_threshold: Duration,
+    /// The minimum duration that a CPU must go without a timer tick, or without being able
+    /// to schedule, in order to be reported as stuck.
+    pub stuck_cpu_threshold: Duration,
+    /// Whether to also report tasks that are blocked without a known wait reason.
+    pub report_unnamed_waits: bool,
+    /// Whether to print a backtrace for each reported task.
+    pub print_backtraces: bool,
+}
+
+impl HungTaskConfig {
+    /// The default configuration of the hung task detector.
+    pub const DEFAULT: HungTaskConfig = HungTaskConfig {
+        scan_interval: Duration::from_secs(5),
+        blocked_threshold: Duration::from_secs(30),
+        starvation_threshold: Duration::from_secs(10),
+        stuck_cpu_threshold: Duration::from_secs(10),
+        report_unnamed_waits: false,
+        print_backtraces: true,
+    };
+}
+
+impl Default for HungTaskConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The current configuration of the hung task detector.
+static CONFIG: Mutex<HungTaskConfig> = Mutex::new(HungTaskConfig::DEFAULT);
+
+/// Returns the current configuration of the hung task detector.
+pub fn config() -> HungTaskConfig {
+    *CONFIG.lock()
+}
+
+/// Sets the configuration of the hung task detector,
+/// which takes effect starting with its next scan.
+pub fn set_config(config: HungTaskConfig) {
+    *CONFIG.lock() = config;
+}
+
+/// Why a task was considered to be hung.
+#[derive(Clone, Copy, Debug)]
+pub enum HungReason {
+    /// The task has been blocked for `duration`,
+    /// waiting on the given wait queue or lock if known.
+    Blocked {
+        duration: Duration,
+        wait_reason: Option<&'static str>,
+    },
+    /// The task has been runnable but not scheduled in for `duration`.
+    Starved {
+        duration: Duration,
+    },
+}
+
+impl fmt::Display for HungReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HungReason::Blocked { duration, wait_reason: Some(reason) } =>
+                write!(f, "blocked for {} ms on {}", duration.as_millis(), reason),
+            HungReason::Blocked { duration, wait_reason: None } =>
+                write!(f, "blocked for {} ms on an unknown wait reason", duration.as_millis()),
+            HungReason::Starved { duration } =>
+                write!(f, "runnable but not scheduled for {} ms", duration.as_millis()),
+        }
+    }
+}
+
+/// A task that was found to be hung by [`scan()`].
+#[derive(Clone)]
+pub struct HungTask {
+    /// The hung task.
+    pub task: TaskRef,
+    /// Why the task was considered to be hung.
+    pub reason: HungReason,
+    /// The time at which the current hang episode started,
+    /// used to avoid reporting the same episode more than once.
+    episode_start: Instant,
+}
+
+impl fmt::Display for HungTask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", &*self.task, self.reason)
+    }
+}
+
+/// Scans all tasks once and returns those that are hung according to the given `config`.
+///
+/// This does not print anything; see [`start()`] for a task that periodically
+/// scans and reports hung tasks.
+pub fn scan(config: &HungTaskConfig) -> Vec<HungTask> {
+    let now = Instant::now();
+    let mut hung_tasks = Vec::new();
+    for (_id, weak_task) in task::all_tasks() {
+        let Some(task) = weak_task.upgrade() else { continue };
+        if task.is_an_idle_task || task.is_suspended() {
+            continue;
+        }
+        // A task whose runstate last changed before the detector was started has no timestamp.
+        let changed_at = task.runstate_changed_at();
+        match task.runstate() {
+            RunState::Blocked if changed_at != Instant::ZERO => {
+                let duration = now.duration_since(changed_at);
+                if duration < config.blocked_threshold {
+                    continue;
+                }
+                let wait_reason = task.wait_reason();
+                if wait_reason.is_none() && !config.report_unnamed_waits {
+                    continue;
+                }
+                hung_tasks.push(HungTask {
+                    task,
+                    reason: HungReason::Blocked { duration, wait_reason },
+                    episode_start: changed_at,
+                });
+            }
+            RunState::Runnable if !task.is_running() => {
+                // A task that was never scheduled in is measured from when it became runnable.
+                let waiting_since = core::cmp::max(changed_at, task.last_scheduled_at());
+                let duration = now.duration_since(waiting_since);
+                if waiting_since == Instant::ZERO || duration < config.starvation_threshold {
+                    continue;
+                }
+                hung_tasks.push(HungTask {
+                    task,
+                    reason: HungReason::Starved { duration },
+                    episode_start: waiting_since,
+                });
+            }
+            _ => { }
+        }
+    }
+    hung_tasks
+}
+
+/// Spawns the hung task detector task, which runs forever.
+///
+/// This also enables timestamping of all tasks' runstate changes.
+/// This should only be called once.
+pub fn start() -> Result<JoinableTaskRef, &'static str> {
+    task::enable_runstate_timestamps();
+    spawn::new_task_builder(hung_task_detector, ())
+        .name("hung_task_detector".into())
+        .spawn()
+}
+
+/// The entry point for the hung task detector task.
+fn hung_task_detector(_: ()) -> Result<(), &'static str> {
+    info!("hung_task_detector task started");
+    // The start of the most recently reported hang episode of each hung task, keyed by task ID.
+    let mut reported: BTreeMap<usize, Instant> = BTreeMap::new();
+    let mut cpu_watchdog = CpuWatchdog::new();
+    // Why each currently-stuck CPU was reported as stuck.
+    let mut reported_cpus: BTreeMap<CpuId, StuckReason> = BTreeMap::new();
+    loop {
+        let config = config();
+
+        let stuck_cpus = cpu_watchdog.check(config.stuck_cpu_threshold);
+        reported_cpus.retain(|cpu, _| stuck_cpus.iter().any(|s| s.cpu == *cpu));
+        for stuck in &stuck_cpus {
+            if reported_cpus.insert(stuck.cpu, stuck.reason) != Some(stuck.reason) {
+                report_stuck_cpu(stuck);
+            }
+        }
+
+        let hung_tasks = scan(&config);
+
+        // Forget about tasks that are no longer hung, such that they can be reported again.
+        reported.retain(|id, _| hung_tasks.iter().any(|h| h.task.id == *id));
+
+        for hung in &hung_tasks {
+            if reported.get(&hung.task.id) == Some(&hung.episode_start) {
+                continue;
+            }
+            reported.insert(hung.task.id, hung.episode_start);
+            report(hung, config.print_backtraces);
+        }
+
+        sleep::sleep(config.scan_interval).map_err(|_| "hung_task_detector: failed to sleep")?;
+    }
+}
+
+/// Prints a report about the given hung task, optionally including its backtrace.
+fn report(hung: &HungTask, print_backtrace: bool) {
+    warn!("Hung task detected: {}", hung);
+    if print_backtrace {
+        match backtrace(&hung.task) {
+            Ok(()) => warn!("  Beginning of stack"),
+            Err(e) => warn!("  Backtrace unavailable: {}", e),
+        }
+    }
+}
+
+/// Prints a report about the given stuck CPU, including where it was executing.
+fn report_stuck_cpu(stuck: &StuckCpu) {
+    let task = task::get_task(stuck.task_id).and_then(|t| t.upgrade());
+    let what = match stuck.reason {
+        StuckReason::NoTicks => "has had no timer ticks",
+        StuckReason::NoScheduling => "has been unable to schedule",
+    };
+    match task.as_ref() {
+        Some(task) => warn!("Stuck CPU detected: CPU {} {} for {} ms, running {}",
+            stuck.cpu, what, stuck.duration.as_millis(), &**task),
+        None => warn!("Stuck CPU detected: CPU {} {} for {} ms, running task {}",
+            stuck.cpu, what, stuck.duration.as_millis(), stuck.task_id),
+    }
+
+    let Some(ip) = stuck.interrupted_ip.and_then(memory::VirtualAddress::new) else {
+        warn!("  Last executed address unknown");
+        return;
+    };
+    let namespace = task.as_ref()
+        .map(|t| t.get_namespace().clone())
+        .or_else(|| mod_mgmt::get_initial_kernel_namespace().cloned());
+    match namespace.and_then(|ns| ns.get_section_containing_address(ip, false)) {
+        Some((sec, offset)) => warn!("  Last executing {:>#018X} in {} + {:#X}", ip, sec.name, offset),
+        None => warn!("  Last executing {:>#018X} in ??", ip),
+    }
+}
+
+/// Prints a backtrace of the given non-running task by walking the frame pointers
+/// starting from the frame pointer that was saved in its context.
+///
+/// This is best-effort: the task may be scheduled in while its stack is being walked,
+/// in which case the backtrace may be inaccurate.
+/// All stack accesses are checked against the task's page table.
+#[cfg(all(frame_pointers, target_arch = "x86_64", not(simd_personality)))]
+fn backtrace(task: &TaskRef) -> Result<(), &'static str> {
+    if task.is_running() {
+        return Err("task is currently running");
+    }
+    let saved_sp = task.saved_stack_pointer();
+    let saved_sp_vaddr = memory::VirtualAddress::new(saved_sp)
+        .ok_or("saved stack pointer was an invalid virtual address")?;
+    let namespace = task.get_namespace().view();
+    let mmi = task.mmi.lock();
+    if mmi.page_table.translate(saved_sp_vaddr).is_none() {
+        return Err("saved stack pointer was not mapped");
+    }
+    // SAFE: the saved stack pointer of a non-running task points to its saved context,
+    //       and we checked above that it is mapped.
+    let frame_pointer = unsafe { &*(saved_sp as *const context_switch::Context) }.frame_pointer();
+    stack_trace_frame_pointers::stack_trace_from_frame_pointer(
+        &mmi.page_table,
+        frame_pointer,
+        &mut |_frame_pointer, instruction_pointer: memory::VirtualAddress| {
+            if let Some((sec, offset)) = namespace.get_section_containing_address(instruction_pointer) {
+                warn!("  {:>#018X} in {} + {:#X}", instruction_pointer, sec.name, offset);
+            } else {
+                warn!("  {:>#018X} in ??", instruction_pointer);
+            }
+            true
+        },
+        None,
+    )
+}
+
+/// Backtraces of other tasks require frame pointers and a single, known context type.
+#[cfg(not(all(frame_pointers, target_arch = "x86_64", not(simd_personality))))]
+fn backtrace(_task: &TaskRef) -> Result<(), &'static str> {
+    Err("backtraces of other tasks require building with frame pointers enabled")
+}
@@ -0,0 +1,316 @@
+//! Routes events from input device drivers to their consumers.
+//!
+//! Rather than pushing events directly onto the window manager's queue,
+//! each input device driver registers its device via [`register_device()`]
+//! and delivers every event via the returned [`InputSource`].
+//! Devices can be registered and removed at any time, e.g., when they are hot-plugged;
+//! a device is removed when its `InputSource` is dropped.
+//!
+//! Each event from a device passes through the following stages:
+//! 1. The [`InputFilter`]s added for that kind of device, in the order they were added,
+//!    each of which can transform or discard the event, e.g., [`MouseAcceleration`] or [`KeyRemap`].
+//! 2. If the device is [grabbed](grab()), e.g., by a game or a virtual machine,
+//!    the event is delivered only to the grab's queue.
+//! 3. Otherwise, the event is delivered according to the device's [`Route`]:
+//!    by default, to the window manager, which passes it to the focused window.
+//!
+//! Events are routed in the context of the driver's interrupt handler,
+//! so filters must be quick and must not block.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use event_types::Event;
+use keycodes_ascii::Keycode;
+use mpmc::Queue;
+use sync_irq::IrqSafeMutex;
+
+/// The capacity of the queue that receives the events of a grabbed device.
+const GRAB_QUEUE_CAPACITY: usize = 100;
+
+static ROUTER: IrqSafeMutex<Router> = IrqSafeMutex::new(Router {
+    devices: BTreeMap::new(),
+    filters: Vec::new(),
+    delivery_hooks: Vec::new(),
+});
+
+/// The source of unique IDs for devices, filters, and grabs.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+fn next_id() -> usize {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A unique identifier of a registered input device.
+pub type InputDeviceId = usize;
+
+/// A unique identifier of an added [`InputFilter`].
+pub type FilterId = usize;
+
+/// The kinds of input devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputDeviceKind {
+    Keyboard,
+    Mouse,
+}
+
+/// Where the events of an input device that isn't grabbed are delivered.
+#[derive(Clone)]
+pub enum Route {
+    /// The device's default consumer given upon registration, typically the window manager.
+    Default,
+    /// The given queue, e.g., the event queue of a specific terminal's window,
+    /// regardless of which window is focused.
+    Queue(Queue<Event>),
+}
+
+/// Information about a registered input device.
+#[derive(Debug, Clone)]
+pub struct InputDeviceInfo {
+    pub id: InputDeviceId,
+    pub name: String,
+    pub kind: InputDeviceKind,
+    /// Whether the device's events are delivered to its default consumer.
+    pub routed_to_default: bool,
+    /// Whether the device is currently grabbed.
+    pub grabbed: bool,
+}
+
+/// A transformation applied to the events of a kind of input device.
+pub trait InputFilter: Send + Sync {
+    /// Returns the transformed `event`, or `None` to discard it.
+    ///
+    /// This is invoked in interrupt context, so it must be quick and must not block.
+    fn filter(&self, event: Event) -> Option<Event>;
+}
+
+struct Device {
+    name: String,
+    kind: InputDeviceKind,
+    default_consumer: Queue<Event>,
+    route: Route,
+    /// The ID of the current grab and the queue that receives this device's events.
+    grab: Option<(usize, Queue<Event>)>,
+}
+
+struct Router {
+    devices: BTreeMap<InputDeviceId, Device>,
+    filters: Vec<(FilterId, InputDeviceKind, Arc<dyn InputFilter>)>,
+    delivery_hooks: Vec<fn()>,
+}
+
+/// Registers a new input device, whose events are delivered to `default_consumer` by default.
+///
+/// The returned `InputSource` should be used by the device's driver to deliver its events.
+/// The device is removed once the `InputSource` is dropped.
+pub fn register_device(name: &str, kind: InputDeviceKind, default_consumer: Queue<Event>) -> InputSource {
+    let id = next_id();
+    ROUTER.lock().devices.insert(id, Device {
+        name: name.to_string(),
+        kind,
+        default_consumer,
+        route: Route::Default,
+        grab: None,
+    });
+    InputSource { id }
+}
+
+/// Returns information about all registered input devices.
+pub fn devices() -> Vec<InputDeviceInfo> {
+    ROUTER.lock().devices.iter()
+        .map(|(id, device)| InputDeviceInfo {
+            id: *id,
+            name: device.name.clone(),
+            kind: device.kind,
+            routed_to_default: matches!(device.route, Route::Default),
+            grabbed: device.grab.is_some(),
+        })
+        .collect()
+}
+
+/// Sets where the events of the given device are delivered while it isn't grabbed.
+pub fn set_route(device: InputDeviceId, route: Route) -> Result<(), &'static str> {
+    ROUTER.lock().devices.get_mut(&device)
+        .ok_or("no such input device")?
+        .route = route;
+    Ok(())
+}
+
+/// Exclusively grabs the given device, such that all of its events are delivered
+/// only to the returned `InputGrab` until it is dropped.
+///
+/// Returns an error if the device is already grabbed.
+pub fn grab(device: InputDeviceId) -> Result<InputGrab, &'static str> {
+    let mut router = ROUTER.lock();
+    let dev = router.devices.get_mut(&device).ok_or("no such input device")?;
+    if dev.grab.is_some() {
+        return Err("input device is already grabbed");
+    }
+    let grab_id = next_id();
+    let queue = Queue::with_capacity(GRAB_QUEUE_CAPACITY);
+    dev.grab = Some((grab_id, queue.clone()));
+    Ok(InputGrab { device, grab_id, queue })
+}
+
+/// Adds a filter that is applied to the events of all devices of the given `kind`,
+/// after all previously-added filters.
+pub fn add_filter(kind: InputDeviceKind, filter: Arc<dyn InputFilter>) -> FilterId {
+    let id = next_id();
+    ROUTER.lock().filters.push((id, kind, filter));
+    id
+}
+
+/// Removes the filter with the given ID.
+///
+/// Returns `false` if no such filter exists.
+pub fn remove_filter(id: FilterId) -> bool {
+    let mut router = ROUTER.lock();
+    let len_before = router.filters.len();
+    router.filters.retain(|(filter_id, ..)| *filter_id != id);
+    router.filters.len() != len_before
+}
+
+/// Adds a function that is invoked after each event is delivered to any queue,
+/// e.g., to wake up a task that waits for events on that queue.
+///
+/// Like filters, this is invoked in interrupt context, so it must be quick and must not block.
+pub fn add_delivery_hook(hook: fn()) {
+    ROUTER.lock().delivery_hooks.push(hook);
+}
+
+/// The means by which an input device driver delivers the events of its device.
+///
+/// Dropping this removes the device.
+pub struct InputSource {
+    id: InputDeviceId,
+}
+
+impl InputSource {
+    /// Returns the ID of this source's device.
+    pub fn id(&self) -> InputDeviceId {
+        self.id
+    }
+
+    /// Filters the given event and delivers it to its destination.
+    ///
+    /// This is safe to invoke from an interrupt handler.
+    pub fn push(&self, mut event: Event) -> Result<(), &'static str> {
+        let router = ROUTER.lock();
+        let device = router.devices.get(&self.id).ok_or("BUG: input device was not registered")?;
+        for (_, kind, filter) in &router.filters {
+            if *kind == device.kind {
+                match filter.filter(event) {
+                    Some(filtered) => event = filtered,
+                    None => return Ok(()),
+                }
+            }
+        }
+        let queue = match (&device.grab, &device.route) {
+            (Some((_, grab_queue)), _) => grab_queue,
+            (None, Route::Queue(queue)) => queue,
+            (None, Route::Default) => &device.default_consumer,
+        };
+        queue.push(event).map_err(|_| "input event queue is full")?;
+        for hook in &router.delivery_hooks {
+            hook();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for InputSource {
+    fn drop(&mut self) {
+        ROUTER.lock().devices.remove(&self.id);
+    }
+}
+
+/// An exclusive grab of an input device, which receives all of that device's events.
+///
+/// The device is released once this is dropped.
+pub struct InputGrab {
+    device: InputDeviceId,
+    grab_id: usize,
+    queue: Queue<Event>,
+}
+
+impl InputGrab {
+    /// Returns the ID of the grabbed device.
+    pub fn device(&self) -> InputDeviceId {
+        self.device
+    }
+
+    /// Returns the next event from the grabbed device, if any.
+    pub fn pop(&self) -> Option<Event> {
+        self.queue.pop()
+    }
+}
+
+impl Drop for InputGrab {
+    fn drop(&mut self) {
+        if let Some(device) = ROUTER.lock().devices.get_mut(&self.device) {
+            if matches!(device.grab, Some((id, _)) if id == self.grab_id) {
+                device.grab = None;
+            }
+        }
+    }
+}
+
+/// An [`InputFilter`] that accelerates mouse movement.
+///
+/// Movement along each axis beyond the `threshold` is multiplied by the `factor`,
+/// such that slow movements remain precise while fast movements cover more distance.
+#[derive(Debug, Clone, Copy)]
+pub struct MouseAcceleration {
+    pub threshold: i16,
+    pub factor: i16,
+}
+
+impl MouseAcceleration {
+    fn accelerate(&self, movement: i16) -> i16 {
+        let magnitude = movement.saturating_abs();
+        if magnitude <= self.threshold {
+            return movement;
+        }
+        let accelerated = self.threshold.saturating_add((magnitude - self.threshold).saturating_mul(self.factor));
+        if movement < 0 { -accelerated } else { accelerated }
+    }
+}
+
+impl InputFilter for MouseAcceleration {
+    fn filter(&self, mut event: Event) -> Option<Event> {
+        if let Event::MouseMovementEvent(ref mut mouse_event) = event {
+            mouse_event.movement.x_movement = self.accelerate(mouse_event.movement.x_movement);
+            mouse_event.movement.y_movement = self.accelerate(mouse_event.movement.y_movement);
+        }
+        Some(event)
+    }
+}
+
+/// An [`InputFilter`] that remaps keys, e.g., to swap Caps Lock and Control.
+///
+/// Each pressed or released key that appears as the first keycode of a pair
+/// is replaced by the second keycode of that pair.
+#[derive(Debug, Clone)]
+pub struct KeyRemap {
+    pub mappings: Vec<(Keycode, Keycode)>,
+}
+
+impl InputFilter for KeyRemap {
+    fn filter(&self, mut event: Event) -> Option<Event> {
+        if let Event::KeyboardEvent(ref mut keyboard_event) = event {
+            let keycode = &mut keyboard_event.key_event.keycode;
+            if let Some((_, to)) = self.mappings.iter().find(|(from, _)| from == keycode) {
+                *keycode = *to;
+            }
+        }
+        Some(event)
+    }
+}
@@ -84,15 +84,18 @@ pub fn is_exception_handler_with_error_code(address: u64) -> bool {
 /// # Arguments: 
 /// * `double_fault_stack_top_unusable`: the address of the top of a newly allocated stack,
 ///    to be used as the double fault exception handler stack.
+/// * `page_fault_stack_top_unusable`: the address of the top of a newly allocated stack,
+///    to be used as the page fault exception handler stack.
 /// * `privilege_stack_top_unusable`: the address of the top of a newly allocated stack,
 ///    to be used as the privilege stack (Ring 3 -> Ring 0 stack).
 pub fn init(
     double_fault_stack_top_unusable: VirtualAddress,
+    page_fault_stack_top_unusable: VirtualAddress,
     privilege_stack_top_unusable: VirtualAddress
 ) -> Result<&'static LockedIdt, &'static str> {
     let bsp_id = cpu::bootstrap_cpu().ok_or("couldn't get BSP's id")?;
     info!("Setting up TSS & GDT for BSP (id {})", bsp_id);
-    gdt::create_and_load_tss_gdt(bsp_id, double_fault_stack_top_unusable, privilege_stack_top_unusable);
+    gdt::create_and_load_tss_gdt(bsp_id, double_fault_stack_top_unusable, page_fault_stack_top_unusable, privilege_stack_top_unusable);
 
     // Before loading this new IDT, we must copy over all exception handlers from the early IDT.
     // However, we can't just clone `EARLY_IDT` into `IDT`, because we must 
@@ -166,10 +169,11 @@ pub fn init(
 pub fn init_ap(
     cpu_id: CpuId, 
     double_fault_stack_top_unusable: VirtualAddress, 
+    page_fault_stack_top_unusable: VirtualAddress, 
     privilege_stack_top_unusable: VirtualAddress,
 ) -> Result<&'static LockedIdt, &'static str> {
     info!("Setting up TSS & GDT for CPU {}", cpu_id);
-    gdt::create_and_load_tss_gdt(cpu_id, double_fault_stack_top_unusable, privilege_stack_top_unusable);
+    gdt::create_and_load_tss_gdt(cpu_id, double_fault_stack_top_unusable, page_fault_stack_top_unusable, privilege_stack_top_unusable);
 
     // We've already created the IDT initially (currently all CPUs share the initial IDT),
     // so we only need to re-load it here for each AP (each secondary CPU).
@@ -247,6 +251,37 @@ pub fn register_msi_interrupt(func: InterruptHandler) -> Result<u8, &'static str
     Ok(interrupt_num as u8)
 } 
 
+/// Allocates a block of contiguous unused interrupt numbers, one for each of the given `handlers`,
+/// and sets their handler functions.
+///
+/// This is needed for multiple-message MSI, in which a device selects one of its interrupt numbers
+/// by modifying the low bits of a single base interrupt number.
+/// Thus, the number of `handlers` must be a power of two,
+/// and the returned base interrupt number is aligned to that number.
+///
+/// Returns the first interrupt number of the block, which is assigned to `handlers[0]`;
+/// the interrupt number of `handlers[i]` is that number plus `i`.
+pub fn register_msi_interrupts(handlers: &[InterruptHandler]) -> Result<u8, &'static str> {
+    let count = handlers.len();
+    if count == 0 || !count.is_power_of_two() || count > 32 {
+        return Err("register_msi_interrupts: the number of handlers must be a power of two from 1 to 32");
+    }
+    let mut idt = IDT.lock();
+
+    // try to find an aligned block of unused interrupt numbers in the IDT, starting from the top
+    let is_unused = |num: usize| idt[num].handler_addr().as_u64() as usize == unimplemented_interrupt_handler as usize;
+    let base = (32..=(256 - count))
+        .rev()
+        .filter(|base| base % count == 0)
+        .find(|&base| (base .. base + count).all(is_unused))
+        .ok_or("register_msi_interrupts: no available block of interrupt numbers")?;
+
+    for (i, &func) in handlers.iter().enumerate() {
+        idt[base + i].set_handler_fn(func);
+    }
+    Ok(base as u8)
+}
+
 /// Deregisters an interrupt handler, making it available to the rest of the system again.
 ///
 /// As a sanity/safety check, the caller must provide the `interrupt_handler`
@@ -276,6 +311,20 @@ pub fn deregister_interrupt(interrupt_num: u8, func: InterruptHandler) -> Result
     }
 }
 
+/// Invokes `f` with the interrupt number and handler address of every IRQ
+/// (interrupt numbers 32 and above) that currently has a handler registered.
+///
+/// The lock on the `IDT` is held while `f` is invoked.
+pub fn for_each_registered_interrupt<F: FnMut(InterruptNumber, VirtualAddress)>(mut f: F) {
+    let idt = IDT.lock();
+    for (i, entry) in idt.slice(32..=255).iter().enumerate() {
+        let handler_addr = entry.handler_addr().as_u64() as usize;
+        if handler_addr != 0 && handler_addr != unimplemented_interrupt_handler as usize {
+            f((i + 32) as InterruptNumber, VirtualAddress::new_canonical(handler_addr));
+        }
+    }
+}
+
 /// Send an end of interrupt signal, notifying the interrupt chip that
 /// the given interrupt request `irq` has been serviced. 
 ///
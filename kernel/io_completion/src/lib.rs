@@ -0,0 +1,127 @@
+//! Completion handles for asynchronous I/O requests.
+//!
+//! A driver that supports multiple outstanding requests, e.g., an AHCI controller with
+//! native command queueing or an NVMe controller, creates a pair of handles via [`new()`]
+//! for each request it accepts.
+//! The [`Completion`] is returned to the submitter, while the driver holds onto the [`Completer`]
+//! until the device finishes the request, typically within its interrupt handler.
+//!
+//! The submitter can then either block on the request via [`Completion::wait()`],
+//! check on it via [`Completion::try_take()`], or `.await` it, as [`Completion`] is a [`Future`].
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+use io::IoError;
+use sync_irq::{DisableIrq, IrqSafeMutex};
+use wait_queue::WaitQueue;
+
+/// Creates a new pair of handles for a single I/O request
+/// whose successful result is a value of type `T`.
+pub fn new<T>() -> (Completer<T>, Completion<T>) {
+    let shared = Arc::new(Shared {
+        state: IrqSafeMutex::new(State { result: None, waker: None }),
+        waiters: WaitQueue::with_name("I/O completion"),
+    });
+    (Completer { shared: Some(Arc::clone(&shared)) }, Completion { shared })
+}
+
+/// The state shared by both handles of a single I/O request.
+struct Shared<T> {
+    state: IrqSafeMutex<State<T>>,
+    /// Holds the task blocked in [`Completion::wait()`], if any.
+    /// This must disable interrupts because requests are usually completed from an interrupt handler.
+    waiters: WaitQueue<DisableIrq>,
+}
+
+struct State<T> {
+    /// The result of the request, which is `None` until the request has completed.
+    result: Option<Result<T, IoError>>,
+    /// The waker of the task that last polled the [`Completion`] as a future.
+    waker: Option<Waker>,
+}
+
+/// The submitter's handle to an I/O request, which yields the request's result once it completes.
+pub struct Completion<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Completion<T> {
+    /// Returns `true` if the request has completed and its result hasn't yet been taken.
+    pub fn is_complete(&self) -> bool {
+        self.shared.state.lock().result.is_some()
+    }
+
+    /// Takes the result of the request if it has completed, without blocking.
+    ///
+    /// Returns `None` if the request is still in flight or its result was already taken.
+    pub fn try_take(&mut self) -> Option<Result<T, IoError>> {
+        self.shared.state.lock().result.take()
+    }
+
+    /// Blocks the current task until the request completes, and returns its result.
+    pub fn wait(mut self) -> Result<T, IoError> {
+        let shared = Arc::clone(&self.shared);
+        shared.waiters.wait_until(|| self.try_take())
+    }
+}
+
+impl<T> Future for Completion<T> {
+    type Output = Result<T, IoError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.shared.state.lock();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// The driver's handle to an I/O request, which is used to report the request's result.
+///
+/// If a `Completer` is dropped without calling [`Completer::complete()`],
+/// e.g., because the device was reset, the request fails with an error.
+pub struct Completer<T> {
+    /// This is only `None` once the request has been completed.
+    shared: Option<Arc<Shared<T>>>,
+}
+
+impl<T> Completer<T> {
+    /// Completes the request with the given `result`,
+    /// waking up the task that is waiting for it, if any.
+    ///
+    /// This can be called from an interrupt handler.
+    pub fn complete(mut self, result: Result<T, IoError>) {
+        self.finish(result);
+    }
+
+    fn finish(&mut self, result: Result<T, IoError>) {
+        let Some(shared) = self.shared.take() else { return };
+        let waker = {
+            let mut state = shared.state.lock();
+            state.result = Some(result);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        shared.waiters.notify_one();
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        self.finish(Err(IoError::Other("I/O request was abandoned before it completed")));
+    }
+}
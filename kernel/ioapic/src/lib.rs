@@ -124,6 +124,33 @@ impl IoApic {
         self.write_reg(irq_reg, direction | (1 << 16));
     }
 
+    /// Returns the number of IRQ lines, i.e., redirection table entries, of this IoApic.
+    pub fn num_irqs(&self) -> u8 {
+        INTERRUPT_ENTRIES_PER_IOAPIC as u8
+    }
+
+    /// Returns the raw ID of the Local APIC that the given IRQ line is redirected to.
+    pub fn irq_destination(&mut self, ioapic_irq: u8) -> u32 {
+        let high_index: u32 = 0x10 + ((ioapic_irq as u32) * 2) + 1;
+        self.read_reg(high_index) >> 24
+    }
+
+    /// Redirects the given IRQ line to the Local APIC (CPU) with the given ID,
+    /// without changing its interrupt vector or whether it is masked.
+    ///
+    /// Returns an error if the given `ApicId` value exceeds the bounds of `u8`; see [`IoApic::set_irq()`].
+    pub fn set_irq_destination(&mut self, ioapic_irq: u8, apic_id: ApicId) -> Result<(), &'static str> {
+        if apic_id.value() > u8::MAX as u32 {
+            return Err("Cannot set IOAPIC redirection table entry for APIC ID larger than 255");
+        }
+        let high_index: u32 = 0x10 + ((ioapic_irq as u32) * 2) + 1;
+        let mut high = self.read_reg(high_index);
+        high &= !0xff000000;
+        high |= apic_id.value() << 24;
+        self.write_reg(high_index, high);
+        Ok(())
+    }
+
     /// Set IRQ to an interrupt vector.
     ///
     /// # Arguments
@@ -0,0 +1,265 @@
+//! DMA remapping domains, each of which is an isolated I/O virtual address space
+//! described by its own set of second-level page tables.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{mem::ManuallyDrop, ops::{Deref, DerefMut}};
+use memory::{MappedPages, PhysicalAddress, PteFlags, DMA_FLAGS, create_contiguous_mapping};
+use kernel_config::memory::PAGE_SIZE;
+use crate::DmaDomainRef;
+
+/// The number of entries in each second-level page table.
+const ENTRIES_PER_TABLE: usize = 512;
+/// The `Read` bit of a second-level paging entry.
+const ENTRY_READ: u64 = 1 << 0;
+/// The `Write` bit of a second-level paging entry.
+const ENTRY_WRITE: u64 = 1 << 1;
+/// The bits of a second-level paging entry that hold the address of a table or page frame.
+const ENTRY_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// The lowest I/O virtual address handed out by a domain,
+/// such that a device that uses a null DMA address causes a fault.
+const FIRST_IOVA: usize = PAGE_SIZE;
+
+/// An address in the I/O virtual address space of a [`DmaDomain`],
+/// which is what a device must use to access memory via DMA.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IoVirtualAddress(usize);
+
+impl IoVirtualAddress {
+    /// Returns the raw value of this address.
+    pub fn value(&self) -> usize {
+        self.0
+    }
+}
+
+/// An I/O virtual address space in which the devices attached to it can only access memory
+/// that was explicitly mapped via [`DmaDomain::map()`].
+pub struct DmaDomain {
+    /// The domain ID used to tag this domain's entries in the IOMMU's caches.
+    id: u16,
+    /// The number of levels of page tables, which is either 3 (39-bit) or 4 (48-bit).
+    levels: usize,
+    /// Whether the IOMMU snoops CPU caches when walking page tables.
+    /// If not, each modified entry must be flushed from the CPU caches.
+    coherent: bool,
+    /// The physical address of the top-level page table.
+    root: PhysicalAddress,
+    /// All page tables of this domain, keyed by their physical address.
+    tables: BTreeMap<PhysicalAddress, MappedPages>,
+    /// The ranges of I/O virtual addresses that are currently mapped,
+    /// as a map from their start address to their size in pages.
+    mappings: BTreeMap<usize, usize>,
+    /// The start of the range of I/O virtual addresses that has never been allocated.
+    next_iova: usize,
+    /// Ranges of I/O virtual addresses that were unmapped and can be reused,
+    /// as tuples of their start address and size in pages.
+    free_ranges: Vec<(usize, usize)>,
+}
+
+impl DmaDomain {
+    pub(crate) fn new(id: u16, levels: usize, coherent: bool) -> Result<DmaDomain, &'static str> {
+        let mut domain = DmaDomain {
+            id,
+            levels,
+            coherent,
+            root: PhysicalAddress::zero(),
+            tables: BTreeMap::new(),
+            mappings: BTreeMap::new(),
+            next_iova: FIRST_IOVA,
+            free_ranges: Vec::new(),
+        };
+        domain.root = domain.new_table()?;
+        Ok(domain)
+    }
+
+    /// Returns the ID of this domain.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Returns the physical address of this domain's top-level page table.
+    pub(crate) fn root(&self) -> PhysicalAddress {
+        self.root
+    }
+
+    /// Maps `size_in_bytes` bytes of physical memory starting at `paddr`
+    /// into this domain, such that the devices attached to it can access that memory.
+    ///
+    /// If `writable` is `false`, devices can only read from that memory.
+    ///
+    /// Returns the I/O virtual address that the devices must use to access `paddr`.
+    pub fn map(
+        &mut self,
+        paddr: PhysicalAddress,
+        size_in_bytes: usize,
+        writable: bool,
+    ) -> Result<IoVirtualAddress, &'static str> {
+        if size_in_bytes == 0 {
+            return Err("DmaDomain::map(): cannot map zero bytes");
+        }
+        let offset = paddr.value() % PAGE_SIZE;
+        let num_pages = (offset + size_in_bytes).div_ceil(PAGE_SIZE);
+        let iova = self.allocate_iova(num_pages)?;
+        let flags = if writable { ENTRY_READ | ENTRY_WRITE } else { ENTRY_READ };
+
+        let first_frame = paddr.value() - offset;
+        for i in 0 .. num_pages {
+            let entry = self.leaf_entry(iova + i * PAGE_SIZE, true)?
+                .ok_or("BUG: DmaDomain::map(): page table wasn't created")?;
+            self.write_entry(entry, (first_frame + i * PAGE_SIZE) as u64 | flags)?;
+        }
+        self.mappings.insert(iova, num_pages);
+        crate::after_map(self.id);
+        Ok(IoVirtualAddress(iova + offset))
+    }
+
+    /// Unmaps the memory that was mapped at the given `iova` via [`DmaDomain::map()`].
+    ///
+    /// `size_in_bytes` must be the same size that was passed into `map()`.
+    /// Once this returns, the devices attached to this domain can no longer access that memory.
+    ///
+    /// Returns an error if no memory of that size is mapped at `iova`, e.g., if it was already unmapped.
+    pub fn unmap(&mut self, iova: IoVirtualAddress, size_in_bytes: usize) -> Result<(), &'static str> {
+        let offset = iova.value() % PAGE_SIZE;
+        let start = iova.value() - offset;
+        let num_pages = (offset + size_in_bytes).div_ceil(PAGE_SIZE);
+        if self.mappings.get(&start) != Some(&num_pages) {
+            return Err("DmaDomain::unmap(): no memory of that size is mapped at the given address");
+        }
+        self.mappings.remove(&start);
+        for i in 0 .. num_pages {
+            if let Some(entry) = self.leaf_entry(start + i * PAGE_SIZE, false)? {
+                self.write_entry(entry, 0)?;
+            }
+        }
+        crate::after_unmap(self.id)?;
+        self.free_ranges.push((start, num_pages));
+        Ok(())
+    }
+
+    /// Allocates a range of `num_pages` pages of I/O virtual addresses.
+    fn allocate_iova(&mut self, num_pages: usize) -> Result<usize, &'static str> {
+        if let Some(i) = self.free_ranges.iter().position(|&(_, pages)| pages >= num_pages) {
+            let (start, pages) = self.free_ranges[i];
+            if pages == num_pages {
+                self.free_ranges.swap_remove(i);
+            } else {
+                self.free_ranges[i] = (start + num_pages * PAGE_SIZE, pages - num_pages);
+            }
+            return Ok(start);
+        }
+        let start = self.next_iova;
+        let end = start + num_pages * PAGE_SIZE;
+        if end > 1 << (12 + 9 * self.levels) {
+            return Err("DmaDomain: out of I/O virtual addresses");
+        }
+        self.next_iova = end;
+        Ok(start)
+    }
+
+    /// Allocates a new, empty page table for this domain and returns its physical address.
+    fn new_table(&mut self) -> Result<PhysicalAddress, &'static str> {
+        let (mut mp, paddr) = create_contiguous_mapping(PAGE_SIZE, PteFlags::new().valid(true).writable(true))?;
+        let entries = mp.as_slice_mut::<u64>(0, ENTRIES_PER_TABLE)?;
+        entries.fill(0);
+        if !self.coherent {
+            for entry in entries.iter() {
+                crate::flush_cache_line(entry);
+            }
+        }
+        self.tables.insert(paddr, mp);
+        Ok(paddr)
+    }
+
+    /// Returns the location of the lowest-level page table entry for the given `iova`,
+    /// as a tuple of the physical address of its page table and its index within that table.
+    ///
+    /// If `create` is `true`, any missing intermediate page tables are created;
+    /// otherwise, `None` is returned if the entry doesn't exist.
+    fn leaf_entry(&mut self, iova: usize, create: bool) -> Result<Option<(PhysicalAddress, usize)>, &'static str> {
+        let mut table = self.root;
+        for level in (1 .. self.levels).rev() {
+            let index = (iova >> (12 + 9 * level)) % ENTRIES_PER_TABLE;
+            let entry = self.table(table)?[index];
+            table = if entry & (ENTRY_READ | ENTRY_WRITE) != 0 {
+                PhysicalAddress::new((entry & ENTRY_ADDRESS_MASK) as usize)
+                    .ok_or("BUG: DmaDomain: invalid page table address")?
+            } else if create {
+                let next_table = self.new_table()?;
+                self.write_entry((table, index), next_table.value() as u64 | ENTRY_READ | ENTRY_WRITE)?;
+                next_table
+            } else {
+                return Ok(None);
+            };
+        }
+        Ok(Some((table, (iova >> 12) % ENTRIES_PER_TABLE)))
+    }
+
+    fn table(&self, paddr: PhysicalAddress) -> Result<&[u64], &'static str> {
+        self.tables.get(&paddr)
+            .ok_or("BUG: DmaDomain: page table not found")?
+            .as_slice(0, ENTRIES_PER_TABLE)
+    }
+
+    /// Writes the given `value` into the page table entry at the given location.
+    fn write_entry(&mut self, (table, index): (PhysicalAddress, usize), value: u64) -> Result<(), &'static str> {
+        let entries = self.tables.get_mut(&table)
+            .ok_or("BUG: DmaDomain: page table not found")?
+            .as_slice_mut::<u64>(0, ENTRIES_PER_TABLE)?;
+        entries[index] = value;
+        if !self.coherent {
+            crate::flush_cache_line(&entries[index]);
+        }
+        Ok(())
+    }
+}
+
+/// A physically-contiguous, writable buffer for DMA that is mapped into a [`DmaDomain`].
+///
+/// The buffer is unmapped from its domain when this is dropped, before its memory is freed,
+/// such that the domain's devices can never access that memory once it's reused.
+/// This dereferences to the buffer's mapping in the kernel's address space.
+pub struct DmaMapping {
+    pages: ManuallyDrop<MappedPages>,
+    iova: IoVirtualAddress,
+    size_in_bytes: usize,
+    domain: DmaDomainRef,
+}
+
+impl DmaMapping {
+    /// Allocates a buffer of `size_in_bytes` bytes and maps it into the given `domain`.
+    pub fn new(domain: &DmaDomainRef, size_in_bytes: usize) -> Result<DmaMapping, &'static str> {
+        let (pages, paddr) = create_contiguous_mapping(size_in_bytes, DMA_FLAGS)?;
+        let iova = domain.lock().map(paddr, size_in_bytes, true)?;
+        Ok(DmaMapping { pages: ManuallyDrop::new(pages), iova, size_in_bytes, domain: domain.clone() })
+    }
+
+    /// Returns the I/O virtual address that the domain's devices must use to access this buffer.
+    pub fn iova(&self) -> IoVirtualAddress {
+        self.iova
+    }
+}
+
+impl Deref for DmaMapping {
+    type Target = MappedPages;
+    fn deref(&self) -> &MappedPages {
+        &self.pages
+    }
+}
+
+impl DerefMut for DmaMapping {
+    fn deref_mut(&mut self) -> &mut MappedPages {
+        &mut self.pages
+    }
+}
+
+impl Drop for DmaMapping {
+    fn drop(&mut self) {
+        match self.domain.lock().unmap(self.iova, self.size_in_bytes) {
+            // SAFETY: the pages are never accessed again.
+            Ok(()) => unsafe { ManuallyDrop::drop(&mut self.pages) },
+            // The devices may still be able to access the buffer, so it must never be reused.
+            Err(e) => error!("DmaMapping: failed to unmap {:?}, leaking its buffer: {}", self.iova, e),
+        }
+    }
+}
@@ -1,24 +1,51 @@
 //! Intel VT-d (IOMMU) implementation.
 //!
+//! The IOMMU is discovered via the ACPI DMAR table and initialized by [`init()`],
+//! after which DMA remapping is enabled by [`enable_dma_remapping()`] once the PCI bus has been scanned.
+//! Initially, every PCI device is placed in pass-through mode, in which it can access all physical memory
+//! as before; a driver can then isolate its device via [`attach_device()`],
+//! which gives the device its own [`DmaDomain`] that only contains the memory explicitly mapped into it.
+//!
 //! [Specification](https://software.intel.com/content/dam/develop/external/us/en/documents-tps/vt-directed-io-spec.pdf)
 
 #![allow(dead_code)]
 #![no_std]
 
+extern crate alloc;
 extern crate sync_irq;
 #[macro_use] extern crate log;
 extern crate memory;
+extern crate kernel_config;
 extern crate spin;
 extern crate volatile;
 extern crate zerocopy;
 extern crate bitflags;
 
+use alloc::{collections::BTreeMap, sync::Arc};
 use spin::Once;
 use sync_irq::IrqSafeMutex;
-use memory::{PageTable, PteFlags, PhysicalAddress, allocate_frames_at, allocate_pages, BorrowedMappedPages, Mutable};
+use memory::{PageTable, PteFlags, PhysicalAddress, MappedPages, allocate_frames_at, allocate_pages, create_contiguous_mapping, BorrowedMappedPages, Mutable};
+use kernel_config::memory::PAGE_SIZE;
 
 mod regs;
 use regs::*;
+mod domain;
+pub use domain::{DmaDomain, DmaMapping, IoVirtualAddress};
+
+/// A shareable reference to a [`DmaDomain`].
+pub type DmaDomainRef = Arc<IrqSafeMutex<DmaDomain>>;
+
+/// The number of 128-bit entries in the root table (one per bus) and in each context table (one per device function).
+const ENTRIES_PER_TABLE: usize = 256;
+/// The `Present` bit of root and context entries.
+const ENTRY_PRESENT: u64 = 1 << 0;
+/// The Translation Type of a context entry for a device whose DMA is translated by its domain's page tables.
+const CONTEXT_TT_TRANSLATED: u64 = 0b00 << 2;
+/// The Translation Type of a context entry for a device whose DMA is passed through untranslated.
+const CONTEXT_TT_PASS_THROUGH: u64 = 0b10 << 2;
+/// The domain ID used for all pass-through devices.
+/// Domain ID 0 is avoided because it's reserved when the IOMMU uses caching mode.
+const PASS_THROUGH_DOMAIN_ID: u16 = 1;
 
 /// Struct representing IOMMU (TODO: rename since this is specific to Intel VT-d)
 pub struct IntelIommu {
@@ -30,6 +57,18 @@ pub struct IntelIommu {
     register_base_address: PhysicalAddress,
     /// Memory mapped control registers
     regs: BorrowedMappedPages<IntelIommuRegisters, Mutable>,
+    cap: Capability,
+    ecap: ExtendedCapability,
+    /// The number of levels of second-level page tables used by every domain.
+    levels: usize,
+    /// The root table, which points to the context table of each bus.
+    /// This is `None` until DMA remapping is enabled.
+    root_table: Option<(MappedPages, PhysicalAddress)>,
+    /// The context table of each bus, which describes the domain of each device function on that bus.
+    context_tables: BTreeMap<u8, MappedPages>,
+    /// The domain of each device that was isolated via [`attach_device()`], keyed by its bus, slot, and function.
+    domains: BTreeMap<(u8, u8, u8), DmaDomainRef>,
+    next_domain_id: u16,
 }
 
 /// Singleton representing IOMMU (TODO: could there be more than one IOMMU?)
@@ -73,14 +112,20 @@ pub fn init(host_address_width: u8,
     }
 
     // check IOMMU capabilities/extended capabilities
-    {
-        let c = Capability(regs.cap.read());
-        let ec = ExtendedCapability(regs.ecap.read());
+    let cap = Capability(regs.cap.read());
+    let ecap = ExtendedCapability(regs.ecap.read());
+    // List capabilities and extended capabilities
+    info!("IOMMU Capabilities: {:?}", cap);
+    info!("IOMMU Extended Capabilities: {:?}", ecap);
 
-        // List capabilities and extended capabilities
-        info!("IOMMU Capabilities: {:?}", c);
-        info!("IOMMU Extended Capabilities: {:?}", ec);
-    }
+    // Prefer 4-level page tables, which can map any I/O virtual address that fits in a 48-bit address.
+    let levels = if cap.supports_48_bit_agaw() {
+        4
+    } else if cap.supports_39_bit_agaw() {
+        3
+    } else {
+        return Err("IOMMU supports neither 39-bit nor 48-bit guest address widths");
+    };
 
     // try reading the status register
     {
@@ -89,21 +134,26 @@ pub fn init(host_address_width: u8,
     }
 
     // create the "iommu" object
-    let iommu = IntelIommu {
+    let mut iommu = IntelIommu {
         host_address_width,
         pci_segment_number,
         register_base_address,
         regs,
+        cap,
+        ecap,
+        levels,
+        root_table: None,
+        context_tables: BTreeMap::new(),
+        domains: BTreeMap::new(),
+        next_domain_id: PASS_THROUGH_DOMAIN_ID + 1,
     };
 
+    // Ensure translation is disabled until `enable_dma_remapping()` is invoked.
+    iommu.set_command_bit(GlobalCommand::TE, false, |x: GlobalStatus| { ! x.intersects(GlobalStatus::TES) });
+
     // initialize the iommu singleton with this object
     IOMMU.call_once(|| {IrqSafeMutex::new(iommu)});
 
-    // Ensure translation is disabled.
-    //
-    // TODO: This can be removed, its only purpose is to test that set_command_bit works
-    set_command_bit(GlobalCommand::TE, false, |x: GlobalStatus| { ! x.intersects(GlobalStatus::TES) })?;
-
     info!("IOMMU Init stage 1 complete.");
 
     Ok(())
@@ -114,31 +164,216 @@ pub fn iommu_present() -> bool {
     IOMMU.is_completed()
 }
 
-/// This function writes a command to the IOMMU Global Command register using
-/// the algorithm described in the Intel documentation:
-/// 1. Read global status register into temporary variable.
-/// 2. Clear all bits in temporary variable that have no effect on command register.
-/// 3. Set or clear the corresponding command bit depending on `x`.
-/// 4. Write the variable to the command register.
-/// 5. Wait until `condition` is met, where `condition` is a function that 
-///    can test the value of the status register.
+/// Enables DMA remapping, placing each of the given PCI devices in pass-through mode.
 ///
-/// # Arguments:
-/// * `command`: command bit to set/clear
-/// * `bit_value`: value to set command bit to
-/// * `condition`: function which interprets status register and returns true when
-///    command has completed.
-fn set_command_bit(
-    command: GlobalCommand, 
-    bit_value: bool,
-    condition: impl Fn(GlobalStatus) -> bool
-) -> Result<(), &'static str> {
-    let iommu = &mut IOMMU.get().ok_or("IOMMU not initialized!")?.lock();
-    let tmp = iommu.regs.gstatus.read();
-    let tmp = tmp & 0x96ffffff;
-    let bits = command as u32;
-    let cmd = if bit_value { tmp | bits } else { tmp & (!bits) };
-    iommu.regs.gcommand.write(cmd);
-    while !condition(GlobalStatus::from_bits_truncate(iommu.regs.gstatus.read())) {}
+/// Each device is given as a tuple of its bus, slot, and function numbers.
+/// A pass-through device can still access all physical memory, as if there were no IOMMU,
+/// until it is isolated in its own domain via [`attach_device()`].
+/// Devices that aren't given here are blocked from performing any DMA.
+///
+/// Returns an error if no IOMMU exists or if it doesn't support pass-through mode,
+/// in which case DMA remapping remains disabled.
+pub fn enable_dma_remapping(devices: impl IntoIterator<Item = (u8, u8, u8)>) -> Result<(), &'static str> {
+    let mut iommu = IOMMU.get().ok_or("IOMMU not initialized!")?.lock();
+    if iommu.root_table.is_some() {
+        return Err("IOMMU DMA remapping was already enabled");
+    }
+    if !iommu.ecap.supports_pass_through() {
+        return Err("IOMMU doesn't support pass-through mode, so DMA remapping would break existing drivers");
+    }
+    if iommu.ecap.iotlb_register_offset() + 16 > core::mem::size_of::<IntelIommuRegisters>() {
+        return Err("IOMMU IOTLB registers are beyond the mapped register page");
+    }
+
+    iommu.root_table = Some(iommu.new_table()?);
+    for (bus, slot, func) in devices {
+        let upper = iommu.address_width() | ((PASS_THROUGH_DOMAIN_ID as u64) << 8);
+        iommu.set_context_entry(bus, slot, func, upper, CONTEXT_TT_PASS_THROUGH | ENTRY_PRESENT)?;
+    }
+
+    // Point the IOMMU to the root table, invalidate any stale cached entries, and then enable translation.
+    let root_paddr = iommu.root_table.as_ref().map(|(_, paddr)| *paddr).ok_or("BUG: no IOMMU root table")?;
+    iommu.regs.rtaddr.write(root_paddr.value() as u64);
+    iommu.set_command_bit(GlobalCommand::Srtp, true, |x: GlobalStatus| { x.intersects(GlobalStatus::RTPS) });
+    iommu.invalidate_context_cache();
+    iommu.invalidate_iotlb(None);
+    iommu.set_command_bit(GlobalCommand::TE, true, |x: GlobalStatus| { x.intersects(GlobalStatus::TES) });
+
+    info!("IOMMU DMA remapping enabled with {}-level page tables", iommu.levels);
     Ok(())
 }
+
+/// Returns `true` if DMA remapping has been enabled via [`enable_dma_remapping()`].
+pub fn dma_remapping_enabled() -> bool {
+    IOMMU.get().map_or(false, |iommu| iommu.lock().root_table.is_some())
+}
+
+/// Isolates the DMA of the PCI device at the given bus, slot, and function in its own [`DmaDomain`].
+///
+/// Afterwards, the device can only access memory that was mapped into that domain,
+/// using the I/O virtual addresses returned by [`DmaDomain::map()`] rather than physical addresses.
+/// If the device was already isolated, its existing domain is returned.
+///
+/// Returns an error if DMA remapping hasn't been enabled.
+pub fn attach_device(bus: u8, slot: u8, func: u8) -> Result<DmaDomainRef, &'static str> {
+    let mut iommu = IOMMU.get().ok_or("IOMMU not initialized!")?.lock();
+    if iommu.root_table.is_none() {
+        return Err("IOMMU DMA remapping is not enabled");
+    }
+    if let Some(domain) = iommu.domains.get(&(bus, slot, func)) {
+        return Ok(domain.clone());
+    }
+    if iommu.next_domain_id as usize >= iommu.cap.num_domains() {
+        return Err("IOMMU is out of domain IDs");
+    }
+    let id = iommu.next_domain_id;
+    let domain = DmaDomain::new(id, iommu.levels, iommu.ecap.is_coherent())?;
+    iommu.next_domain_id += 1;
+
+    // The device's existing context entry must be made not-present and invalidated before it's replaced.
+    iommu.set_context_entry(bus, slot, func, 0, 0)?;
+    iommu.invalidate_context_cache();
+    iommu.invalidate_iotlb(None);
+    let upper = iommu.address_width() | ((id as u64) << 8);
+    let lower = domain.root().value() as u64 | CONTEXT_TT_TRANSLATED | ENTRY_PRESENT;
+    iommu.set_context_entry(bus, slot, func, upper, lower)?;
+    iommu.invalidate_context_cache();
+
+    let domain = Arc::new(IrqSafeMutex::new(domain));
+    iommu.domains.insert((bus, slot, func), domain.clone());
+    info!("IOMMU: isolated PCI device {:02X}:{:02X}.{} in DMA domain {}", bus, slot, func, id);
+    Ok(domain)
+}
+
+/// Returns the domain of the PCI device at the given bus, slot, and function,
+/// if it was isolated via [`attach_device()`].
+pub fn device_domain(bus: u8, slot: u8, func: u8) -> Option<DmaDomainRef> {
+    IOMMU.get()?.lock().domains.get(&(bus, slot, func)).cloned()
+}
+
+/// Invoked after new mappings were added to the domain with the given ID.
+pub(crate) fn after_map(domain_id: u16) {
+    if let Some(iommu) = IOMMU.get() {
+        let mut iommu = iommu.lock();
+        // Hardware in caching mode may have cached the previously not-present entries.
+        if iommu.cap.caching_mode() {
+            iommu.invalidate_iotlb(Some(domain_id));
+        } else if iommu.cap.requires_write_buffer_flush() {
+            iommu.set_command_bit(GlobalCommand::Wbf, true, |x: GlobalStatus| { !x.intersects(GlobalStatus::WBFS) });
+        }
+    }
+}
+
+/// Invoked after mappings were removed from the domain with the given ID,
+/// which ensures that its devices can no longer access the unmapped memory.
+pub(crate) fn after_unmap(domain_id: u16) -> Result<(), &'static str> {
+    let mut iommu = IOMMU.get().ok_or("IOMMU not initialized!")?.lock();
+    iommu.invalidate_iotlb(Some(domain_id));
+    Ok(())
+}
+
+/// Flushes the cache line that contains the given value to memory,
+/// which is required for the IOMMU to observe changes to its translation structures
+/// if it doesn't snoop CPU caches.
+pub(crate) fn flush_cache_line<T>(value: &T) {
+    #[cfg(target_arch = "x86_64")]
+    // SAFE: the address of a valid reference can always be flushed.
+    unsafe { core::arch::x86_64::_mm_clflush(value as *const T as *const u8); }
+}
+
+impl IntelIommu {
+    /// This function writes a command to the IOMMU Global Command register using
+    /// the algorithm described in the Intel documentation:
+    /// 1. Read global status register into temporary variable.
+    /// 2. Clear all bits in temporary variable that have no effect on command register.
+    /// 3. Set or clear the corresponding command bit depending on `x`.
+    /// 4. Write the variable to the command register.
+    /// 5. Wait until `condition` is met, where `condition` is a function that 
+    ///    can test the value of the status register.
+    ///
+    /// # Arguments:
+    /// * `command`: command bit to set/clear
+    /// * `bit_value`: value to set command bit to
+    /// * `condition`: function which interprets status register and returns true when
+    ///    command has completed.
+    fn set_command_bit(
+        &mut self,
+        command: GlobalCommand, 
+        bit_value: bool,
+        condition: impl Fn(GlobalStatus) -> bool
+    ) {
+        let tmp = self.regs.gstatus.read();
+        let tmp = tmp & 0x96ffffff;
+        let bits = command as u32;
+        let cmd = if bit_value { tmp | bits } else { tmp & (!bits) };
+        self.regs.gcommand.write(cmd);
+        while !condition(GlobalStatus::from_bits_truncate(self.regs.gstatus.read())) {}
+    }
+
+    /// Returns the Address Width field of a context entry for this IOMMU's page table levels.
+    fn address_width(&self) -> u64 {
+        (self.levels - 2) as u64
+    }
+
+    /// Allocates a new, empty root or context table.
+    fn new_table(&self) -> Result<(MappedPages, PhysicalAddress), &'static str> {
+        let (mut mp, paddr) = create_contiguous_mapping(PAGE_SIZE, PteFlags::new().valid(true).writable(true))?;
+        let entries = mp.as_slice_mut::<u64>(0, 2 * ENTRIES_PER_TABLE)?;
+        entries.fill(0);
+        if !self.ecap.is_coherent() {
+            for entry in entries.iter() {
+                flush_cache_line(entry);
+            }
+        }
+        Ok((mp, paddr))
+    }
+
+    /// Sets the context entry of the given device function, creating its bus's context table if necessary.
+    ///
+    /// The `upper` half is written before the `lower` half, which holds the `Present` bit.
+    fn set_context_entry(&mut self, bus: u8, slot: u8, func: u8, upper: u64, lower: u64) -> Result<(), &'static str> {
+        if !self.context_tables.contains_key(&bus) {
+            let (context_table, context_paddr) = self.new_table()?;
+            let coherent = self.ecap.is_coherent();
+            let (root_table, _) = self.root_table.as_mut().ok_or("BUG: no IOMMU root table")?;
+            let root_entries = root_table.as_slice_mut::<u64>(0, 2 * ENTRIES_PER_TABLE)?;
+            root_entries[2 * bus as usize] = context_paddr.value() as u64 | ENTRY_PRESENT;
+            if !coherent {
+                flush_cache_line(&root_entries[2 * bus as usize]);
+            }
+            self.context_tables.insert(bus, context_table);
+        }
+        let coherent = self.ecap.is_coherent();
+        let context_table = self.context_tables.get_mut(&bus).ok_or("BUG: no IOMMU context table")?;
+        let entries = context_table.as_slice_mut::<u64>(0, 2 * ENTRIES_PER_TABLE)?;
+        let index = 2 * (((slot as usize) << 3) | (func as usize & 0x7));
+        entries[index + 1] = upper;
+        entries[index] = lower;
+        if !coherent {
+            flush_cache_line(&entries[index]);
+        }
+        Ok(())
+    }
+
+    /// Invalidates all entries in the context-cache.
+    fn invalidate_context_cache(&mut self) {
+        self.regs.ccmd.write(context_command::ICC | context_command::CIRG_GLOBAL);
+        while self.regs.ccmd.read() & context_command::ICC != 0 {}
+    }
+
+    /// Invalidates the IOTLB entries of the domain with the given ID, or of all domains if `None`.
+    fn invalidate_iotlb(&mut self, domain_id: Option<u16>) {
+        let granularity = match domain_id {
+            Some(id) => iotlb_command::IIRG_DOMAIN | ((id as u64) << iotlb_command::DID_SHIFT),
+            None => iotlb_command::IIRG_GLOBAL,
+        };
+        // The IOTLB Invalidate register is the second register at the IOTLB register offset.
+        let offset = self.ecap.iotlb_register_offset() + 8;
+        let iotlb_reg = (&*self.regs as *const IntelIommuRegisters as usize + offset) as *mut u64;
+        // SAFE: the IOTLB registers are within the mapped register page, as given by the extended capabilities.
+        unsafe {
+            iotlb_reg.write_volatile(iotlb_command::IVT | iotlb_command::DR | iotlb_command::DW | granularity);
+            while iotlb_reg.read_volatile() & iotlb_command::IVT != 0 {}
+        }
+    }
+}
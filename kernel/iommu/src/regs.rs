@@ -1,7 +1,7 @@
 //! Structures needed for interacting with the IOMMU.
 
 use zerocopy::FromBytes;
-use volatile::{ReadOnly, WriteOnly};
+use volatile::{Volatile, ReadOnly, WriteOnly};
 use bitflags::bitflags;
 use core::fmt;
 
@@ -21,8 +21,16 @@ pub struct IntelIommuRegisters {
     pub gcommand:           WriteOnly<u32>,    // 0x18
     /// Global status register
     pub gstatus:            ReadOnly<u32>,     // 0x1c
+    /// Root table address register
+    pub rtaddr:             Volatile<u64>,     // 0x20
+    /// Context command register
+    pub ccmd:               Volatile<u64>,     // 0x28
+    /// Reserved
+    _reserved1:             [u8; 4],           // 0x30 - 0x33
+    /// Fault status register
+    pub fsts:               Volatile<u32>,     // 0x34
     /// Unimplemented (may be architecturally defined)
-    _unimplemented:         [u8; 4096-0x20],   // 0x20-0xFFF
+    _unimplemented:         [u8; 4096-0x38],   // 0x38-0xFFF
 }
 // TODO: Hardware may use more than 4kB, which means the registers may occupy
 //       more than one contiguous page.
@@ -33,6 +41,22 @@ const _: () = assert!(core::mem::size_of::<IntelIommuRegisters>() == 4096);
 pub struct Capability(pub u64);
 
 impl Capability {
+    /// Returns the offset of the fault recording registers from the register base address.
+    pub fn fault_recording_offset(&self) -> usize { self.fro() as usize * 16 }
+    /// Returns the number of fault recording registers.
+    pub fn num_fault_recording_registers(&self) -> usize { self.nfr() as usize }
+    /// Returns the number of domain IDs supported by the hardware.
+    pub fn num_domains(&self) -> usize { 1 << (4 + 2 * self.nd()) }
+    /// Returns `true` if the hardware supports 3-level (39-bit) second-level page tables.
+    pub fn supports_39_bit_agaw(&self) -> bool { self.sagaw() & (1 << 1) != 0 }
+    /// Returns `true` if the hardware supports 4-level (48-bit) second-level page tables.
+    pub fn supports_48_bit_agaw(&self) -> bool { self.sagaw() & (1 << 2) != 0 }
+    /// Returns `true` if the hardware may cache not-present and erroneous entries,
+    /// which requires invalidating the IOTLB even when mapping previously unmapped addresses.
+    pub fn caching_mode(&self) -> bool { self.cm() }
+    /// Returns `true` if the write buffer must be flushed after modifying translation structures.
+    pub fn requires_write_buffer_flush(&self) -> bool { self.rwbf() }
+
     fn esrtps(&self)  -> bool { (self.0) & (1 << 63) != 0 }
     fn esirtps(&self) -> bool { (self.0) & (1 << 62) != 0 }
     fn fl5lp(&self)   -> bool { (self.0) & (1 << 60) != 0 }
@@ -88,6 +112,13 @@ impl fmt::Debug for Capability {
 pub struct ExtendedCapability(pub u64);
 
 impl ExtendedCapability {
+    /// Returns the offset of the IOTLB registers from the register base address.
+    pub fn iotlb_register_offset(&self) -> usize { self.iro() as usize * 16 }
+    /// Returns `true` if the hardware supports pass-through translation of DMA requests.
+    pub fn supports_pass_through(&self) -> bool { self.pt() }
+    /// Returns `true` if hardware accesses to translation structures snoop the CPU caches.
+    pub fn is_coherent(&self) -> bool { self.c() }
+
     fn rprivs(&self)  -> bool { (self.0) & (1 << 53) != 0 }
     fn adms(&self)    -> bool { (self.0) & (1 << 52) != 0 }
     fn rps(&self)     -> bool { (self.0) & (1 << 49) != 0 }
@@ -205,3 +236,27 @@ bitflags! {
         const TES   = 1 << 31;
     }
 }
+
+/// Bits of the Context Command register.
+pub mod context_command {
+    /// Invalidate Context-Cache: set to request an invalidation, cleared by hardware once it completes.
+    pub const ICC: u64 = 1 << 63;
+    /// Context Invalidation Request Granularity: global invalidation.
+    pub const CIRG_GLOBAL: u64 = 0b01 << 61;
+}
+
+/// Bits of the IOTLB Invalidate register, which is located at an offset given by [`ExtendedCapability`].
+pub mod iotlb_command {
+    /// Invalidate IOTLB: set to request an invalidation, cleared by hardware once it completes.
+    pub const IVT: u64 = 1 << 63;
+    /// IOTLB Invalidation Request Granularity: global invalidation.
+    pub const IIRG_GLOBAL: u64 = 0b01 << 60;
+    /// IOTLB Invalidation Request Granularity: domain-selective invalidation.
+    pub const IIRG_DOMAIN: u64 = 0b10 << 60;
+    /// Drain reads before completing the invalidation.
+    pub const DR: u64 = 1 << 49;
+    /// Drain writes before completing the invalidation.
+    pub const DW: u64 = 1 << 48;
+    /// The shift of the Domain ID field used for domain-selective invalidation.
+    pub const DID_SHIFT: u64 = 32;
+}
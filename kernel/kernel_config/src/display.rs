@@ -6,3 +6,9 @@
 /// `kernel/nano_core/src/asm/bios/multiboot_header.asm`,
 /// but it's not strictly necessary to do so.
 pub const FRAMEBUFFER_MAX_RESOLUTION: (u16, u16) = (1280, 1024);
+
+/// The initial maximum total size in bytes of the framebuffers of all windows,
+/// or `None` if it is unlimited.
+///
+/// The limit can be changed at runtime via `window_manager::set_window_buffer_limit()`.
+pub const WINDOW_BUFFER_LIMIT: Option<usize> = None;
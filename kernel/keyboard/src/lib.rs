@@ -3,37 +3,86 @@
 #![no_std]
 #![feature(abi_x86_interrupt)]
 
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
 use core::sync::atomic::{AtomicBool, Ordering};
 use keycodes_ascii::{Keycode, KeyboardModifiers, KEY_RELEASED_OFFSET, KeyAction, KeyEvent};
 use log::{error, warn, debug};
-use once_cell::unsync::Lazy;
 use spin::Once;
-use mpmc::Queue;
+use sync_irq::IrqSafeMutex;
 use event_types::Event;
-use ps2::{PS2Keyboard, KeyboardType, LEDState, ScancodeSet};
+use input_router::InputSource;
+use ps2::{PS2Keyboard, KeyboardType, ScancodeSet, TypematicByte};
 use x86_64::structures::idt::InterruptStackFrame;
 
+pub use ps2::{LEDState, TypematicDelay};
+
 /// The first PS/2 port for the keyboard is connected directly to IRQ 1.
 /// Because we perform the typical PIC remapping, the remapped IRQ vector number is 0x21.
 const PS2_KEYBOARD_IRQ: u8 = interrupts::IRQ_BASE_OFFSET + 0x1;
 
-// TODO: avoid unsafe static mut
-static mut KBD_MODIFIERS: Lazy<KeyboardModifiers> = Lazy::new(KeyboardModifiers::new);
+/// The current state of the modifier keys, including the Caps/Num/Scroll lock keys
+/// whose state is mirrored onto the keyboard's LEDs.
+///
+/// This is accessed from both the interrupt handler and regular tasks,
+/// so it must be protected by an IRQ-safe lock.
+static KBD_MODIFIERS: IrqSafeMutex<KeyboardModifiers> = IrqSafeMutex::new(KeyboardModifiers::new());
+
+/// The system-wide default typematic configuration,
+/// used for tasks that have not specified their own configuration.
+static DEFAULT_TYPEMATIC: IrqSafeMutex<TypematicConfig> = IrqSafeMutex::new(TypematicConfig::DEFAULT);
+
+/// Per-task typematic configurations, keyed by task ID.
+///
+/// Entries for tasks that no longer exist are pruned whenever a task's configuration is set.
+static TASK_TYPEMATIC: IrqSafeMutex<BTreeMap<usize, TypematicConfig>> = IrqSafeMutex::new(BTreeMap::new());
+
+/// The typematic configuration most recently written to the keyboard,
+/// used to avoid needlessly re-sending the same configuration whenever the keyboard focus changes.
+static APPLIED_TYPEMATIC: IrqSafeMutex<Option<TypematicConfig>> = IrqSafeMutex::new(None);
 
 static KEYBOARD: Once<KeyboardInterruptParams> = Once::new();
 
+/// The auto-repeat (typematic) settings of the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypematicConfig {
+    /// The repeat rate, from `0` (fastest, 30 Hz) to `31` (slowest, 2 Hz).
+    pub repeat_rate: u8,
+    /// The delay before a held key begins to auto-repeat.
+    pub delay: TypematicDelay,
+}
+
+impl TypematicConfig {
+    /// The slowest possible repeat rate value.
+    pub const MAX_REPEAT_RATE: u8 = 0b1_1111;
+
+    /// The default typematic configuration of a PS/2 keyboard after reset:
+    /// 10.9 characters per second after a 500ms delay.
+    pub const DEFAULT: TypematicConfig = TypematicConfig {
+        repeat_rate: 0b0_1011,
+        delay: TypematicDelay::_500ms,
+    };
+}
+
+impl Default for TypematicConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
 struct KeyboardInterruptParams {
     keyboard: PS2Keyboard<'static>,
-    queue: Queue<Event>,
+    input: InputSource,
 }
 
 /// Initialize the PS/2 keyboard driver and register its interrupt handler.
 /// 
 /// ## Arguments
 /// * `keyboard`: a wrapper around keyboard functionality, used by the keyboard interrupt handler.
-/// * `keyboard_queue_producer`: the queue onto which the keyboard interrupt handler
-///    will push new keyboard events when a key action occurs.
-pub fn init(keyboard: PS2Keyboard<'static>, keyboard_queue_producer: Queue<Event>) -> Result<(), &'static str> {
+/// * `input`: the input source via which the keyboard interrupt handler
+///    will deliver new keyboard events when a key action occurs.
+pub fn init(keyboard: PS2Keyboard<'static>, input: InputSource) -> Result<(), &'static str> {
     // Detect which kind of keyboard is connected.
     // TODO: actually do something with the keyboard type.
     match keyboard.keyboard_detect() {
@@ -61,19 +110,131 @@ pub fn init(keyboard: PS2Keyboard<'static>, keyboard_queue_producer: Queue<Event
         "PS/2 keyboard IRQ was already in use! Sharing IRQs is currently unsupported."
     })?;
 
-    // Final step: set the producer end of the keyboard event queue.
+    // Start from a known state: default typematic settings and LEDs matching the modifiers.
+    let default_typematic = *DEFAULT_TYPEMATIC.lock();
+    if let Err(e) = write_typematic(&keyboard, default_typematic) {
+        warn!("{e}");
+    }
+    set_keyboard_led(&keyboard, &KBD_MODIFIERS.lock());
+
+    // Final step: set the input source for keyboard events.
     // Also add the keyboard struct for access during interrupts.
-    KEYBOARD.call_once(|| KeyboardInterruptParams { keyboard, queue: keyboard_queue_producer });
+    KEYBOARD.call_once(|| KeyboardInterruptParams { keyboard, input });
+    Ok(())
+}
+
+/// Returns the current state of the keyboard modifiers, including the lock keys.
+pub fn modifiers() -> KeyboardModifiers {
+    *KBD_MODIFIERS.lock()
+}
+
+/// Returns the current state of the keyboard LEDs,
+/// which always reflects the Caps/Num/Scroll lock modifier state.
+pub fn led_state() -> LEDState {
+    led_state_from(&KBD_MODIFIERS.lock())
+}
+
+/// Sets the state of the Caps/Num/Scroll lock keys.
+///
+/// Both the modifier state used to translate subsequent key events
+/// and the LEDs on the physical keyboard are updated.
+pub fn set_lock_state(caps_lock: bool, num_lock: bool, scroll_lock: bool) -> Result<(), &'static str> {
+    let mut modifiers = KBD_MODIFIERS.lock();
+    modifiers.set(KeyboardModifiers::CAPS_LOCK, caps_lock);
+    modifiers.set(KeyboardModifiers::NUM_LOCK, num_lock);
+    modifiers.set(KeyboardModifiers::SCROLL_LOCK, scroll_lock);
+    let keyboard = &KEYBOARD.get().ok_or("keyboard not initialized")?.keyboard;
+    keyboard.set_keyboard_led(led_state_from(&modifiers))
+}
+
+/// Returns the system-wide default typematic configuration.
+pub fn default_typematic() -> TypematicConfig {
+    *DEFAULT_TYPEMATIC.lock()
+}
+
+/// Sets the system-wide default typematic configuration and applies it to the keyboard.
+///
+/// Tasks with their own configuration (see [`set_task_typematic()`]) are unaffected.
+pub fn set_default_typematic(config: TypematicConfig) -> Result<(), &'static str> {
+    check_typematic(&config)?;
+    *DEFAULT_TYPEMATIC.lock() = config;
+    let keyboard = &KEYBOARD.get().ok_or("keyboard not initialized")?.keyboard;
+    write_typematic(keyboard, config)
+}
+
+/// Sets (or clears, if `None`) the typematic configuration for the task with the given ID.
+///
+/// The configuration takes effect when [`apply_task_typematic()`] is next invoked
+/// for that task, e.g., when it receives keyboard focus.
+/// It is discarded once that task has exited and been reaped.
+pub fn set_task_typematic(task_id: usize, config: Option<TypematicConfig>) -> Result<(), &'static str> {
+    let mut per_task = TASK_TYPEMATIC.lock();
+    // Task IDs are never reused, so an entry for a task that no longer exists can never be used again.
+    per_task.retain(|id, _| task::get_task(*id).is_some());
+    match config {
+        Some(c) => {
+            check_typematic(&c)?;
+            if task::get_task(task_id).is_none() {
+                return Err("no task with the given ID exists");
+            }
+            per_task.insert(task_id, c);
+        }
+        None => {
+            per_task.remove(&task_id);
+        }
+    }
     Ok(())
 }
 
+/// Returns the typematic configuration that applies to the task with the given ID,
+/// falling back to the system-wide default if that task has not set its own.
+pub fn task_typematic(task_id: usize) -> TypematicConfig {
+    TASK_TYPEMATIC.lock()
+        .get(&task_id)
+        .copied()
+        .unwrap_or_else(default_typematic)
+}
+
+/// Applies the typematic configuration of the task with the given ID to the keyboard.
+///
+/// This is invoked by the window manager whenever a window owned by a different task
+/// receives keyboard focus. The keyboard is only reconfigured if the configuration differs
+/// from the one currently in effect.
+pub fn apply_task_typematic(task_id: usize) -> Result<(), &'static str> {
+    let keyboard = &KEYBOARD.get().ok_or("keyboard not initialized")?.keyboard;
+    let config = task_typematic(task_id);
+    if *APPLIED_TYPEMATIC.lock() == Some(config) {
+        return Ok(());
+    }
+    write_typematic(keyboard, config)
+}
+
+fn check_typematic(config: &TypematicConfig) -> Result<(), &'static str> {
+    if config.repeat_rate > TypematicConfig::MAX_REPEAT_RATE {
+        Err("typematic repeat rate must be in the range 0..=31")
+    } else {
+        Ok(())
+    }
+}
+
+fn write_typematic(keyboard: &PS2Keyboard, config: TypematicConfig) -> Result<(), &'static str> {
+    let result = keyboard.set_keyboard_typematic(
+        TypematicByte::new()
+            .with_repeat_rate(config.repeat_rate)
+            .with_delay(config.delay),
+    );
+    // If writing failed, the keyboard's configuration is unknown, so it must be written again next time.
+    *APPLIED_TYPEMATIC.lock() = result.is_ok().then_some(config);
+    result
+}
+
 /// The interrupt handler for a PS/2-connected keyboard, registered at IRQ 0x21.
 extern "x86-interrupt" fn ps2_keyboard_handler(_stack_frame: InterruptStackFrame) {
     // Some of the scancodes are "extended", which means they generate two different interrupts,
     // the first handling the E0 byte, the second handling their second byte.
     static EXTENDED_SCANCODE: AtomicBool = AtomicBool::new(false);
 
-    if let Some(KeyboardInterruptParams { keyboard, queue }) = KEYBOARD.get() {
+    if let Some(KeyboardInterruptParams { keyboard, input }) = KEYBOARD.get() {
         let scan_code = keyboard.read_scancode();
         let extended = EXTENDED_SCANCODE.load(Ordering::SeqCst);
 
@@ -96,7 +257,7 @@ extern "x86-interrupt" fn ps2_keyboard_handler(_stack_frame: InterruptStackFrame
             // a scan code of zero is a PS2_PORT error that we can ignore,
             // a scan code of 0xFA is a command ACK response, already handled in polling (when sending a command, see ps2 crate)
             if scan_code != 0 && scan_code != 0xFA {
-                if let Err(e) = handle_keyboard_input(keyboard, queue, scan_code, extended) {
+                if let Err(e) = handle_keyboard_input(keyboard, input, scan_code, extended) {
                     error!("ps2_keyboard_handler: error handling PS2_PORT input: {e:?}");
                 }
             }
@@ -114,9 +275,8 @@ extern "x86-interrupt" fn ps2_keyboard_handler(_stack_frame: InterruptStackFrame
 /// 
 /// Returns Ok(()) if everything was handled properly.
 /// Otherwise, returns an error string.
-fn handle_keyboard_input(keyboard: &PS2Keyboard, queue: &Queue<Event>, scan_code: u8, extended: bool) -> Result<(), &'static str> {
-    // SAFE: no real race conditions with keyboard presses
-    let modifiers = unsafe { &mut KBD_MODIFIERS };
+fn handle_keyboard_input(keyboard: &PS2Keyboard, input: &InputSource, scan_code: u8, extended: bool) -> Result<(), &'static str> {
+    let mut modifiers = KBD_MODIFIERS.lock();
     // debug!("KBD_MODIFIERS before {}: {:?}", scan_code, modifiers);
 
     // first, update the modifier keys
@@ -170,15 +330,15 @@ fn handle_keyboard_input(keyboard: &PS2Keyboard, queue: &Queue<Event>, scan_code
         // The "*Lock" keys are toggled only upon being pressed, not when released.
         Ok(Keycode::CapsLock) => {
             modifiers.toggle(KeyboardModifiers::CAPS_LOCK);
-            set_keyboard_led(keyboard, modifiers);
+            set_keyboard_led(keyboard, &modifiers);
         }
         Ok(Keycode::ScrollLock) => {
             modifiers.toggle(KeyboardModifiers::SCROLL_LOCK);
-            set_keyboard_led(keyboard, modifiers);
+            set_keyboard_led(keyboard, &modifiers);
         }
         Ok(Keycode::NumLock) => {
             modifiers.toggle(KeyboardModifiers::NUM_LOCK);
-            set_keyboard_led(keyboard, modifiers);
+            set_keyboard_led(keyboard, &modifiers);
         }
 
         _ => {} // do nothing
@@ -194,8 +354,8 @@ fn handle_keyboard_input(keyboard: &PS2Keyboard, queue: &Queue<Event>, scan_code
     };
 
     if let Ok(keycode) = Keycode::try_from(adjusted_scan_code) {
-        let event = Event::new_keyboard_event(KeyEvent::new(keycode, action, **modifiers));
-        queue.push(event).map_err(|_| "keyboard input queue is full")
+        let event = Event::new_keyboard_event(KeyEvent::new(keycode, action, *modifiers));
+        input.push(event)
     } else {
         error!("handle_keyboard_input(): Unknown scancode: {scan_code:?}, adjusted scancode: {adjusted_scan_code:?}");
         Err("unknown keyboard scancode")
@@ -203,13 +363,15 @@ fn handle_keyboard_input(keyboard: &PS2Keyboard, queue: &Queue<Event>, scan_code
 }
 
 
+fn led_state_from(modifiers: &KeyboardModifiers) -> LEDState {
+    LEDState::new()
+        .with_scroll_lock(modifiers.is_scroll_lock())
+        .with_number_lock(modifiers.is_num_lock())
+        .with_caps_lock(modifiers.is_caps_lock())
+}
+
 fn set_keyboard_led(keyboard: &PS2Keyboard, modifiers: &KeyboardModifiers) {
-    if let Err(e) = keyboard.set_keyboard_led(
-        LEDState::new()
-            .with_scroll_lock(modifiers.is_scroll_lock())
-            .with_number_lock(modifiers.is_num_lock())
-            .with_caps_lock(modifiers.is_caps_lock()),
-    ) {
+    if let Err(e) = keyboard.set_keyboard_led(led_state_from(modifiers)) {
         error!("{e}");
     }
 }
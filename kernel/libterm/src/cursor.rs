@@ -15,6 +15,9 @@ pub struct Cursor {
     show: bool,
     /// The color of the cursor
     color: Color,
+    /// The color of the character under the cursor and the background behind it, when the cursor is hidden.
+    text_color: Color,
+    text_background_color: Color,
     /// The position of the cursor relative to the end of terminal text in number of characters.
     pub offset_from_end: usize,
     /// The underlying character at the position of the cursor.
@@ -74,26 +77,27 @@ impl Cursor {
         line: usize,
         framebuffer: &mut Framebuffer<P>,
     ) -> Result<Rectangle, &'static str> where Color: Into<P> {
+        let (char_width, char_height) = (character_width(), character_height());
         if self.blink() {
             if self.show() {
                 framebuffer_drawer::fill_rectangle(
                     framebuffer,
                     coordinate
                         + (
-                            (column * CHARACTER_WIDTH) as isize,
-                            (line * CHARACTER_HEIGHT) as isize,
+                            (column * char_width) as isize,
+                            (line * char_height) as isize,
                         )
                         + (0, 1),
-                    CHARACTER_WIDTH,
-                    CHARACTER_HEIGHT - 2,
+                    char_width,
+                    char_height - 2,
                     self.color.into(),
                 );
             } else {
                 framebuffer_printer::print_ascii_character(
                     framebuffer,
                     self.underlying_char,
-                    FONT_FOREGROUND_COLOR.into(),
-                    FONT_BACKGROUND_COLOR.into(),
+                    self.text_color.into(),
+                    self.text_background_color.into(),
                     coordinate,
                     column,
                     line,
@@ -103,17 +107,25 @@ impl Cursor {
 
         let top_left = coordinate
             + (
-                (column * CHARACTER_WIDTH) as isize,
-                (line * CHARACTER_HEIGHT) as isize,
+                (column * char_width) as isize,
+                (line * char_height) as isize,
             );
         let bounding_box = Rectangle {
             top_left,
-            bottom_right: top_left + (CHARACTER_WIDTH as isize, CHARACTER_HEIGHT as isize),
+            bottom_right: top_left + (char_width as isize, char_height as isize),
         };
 
         Ok(bounding_box)
     }
 
+    /// Sets the color of the cursor, which is also the color of the text beneath it,
+    /// and the color of the background behind that text.
+    pub fn set_colors(&mut self, foreground: Color, background: Color) {
+        self.color = foreground;
+        self.text_color = foreground;
+        self.text_background_color = background;
+    }
+
     /// Sets the position of the cursor relative to the end of the command
     pub fn set_offset_from_end(&mut self, offset: usize) {
         self.offset_from_end = offset;
@@ -143,6 +155,8 @@ impl Default for Cursor  {
             time: Instant::now(),
             show: true,
             color: FONT_FOREGROUND_COLOR,
+            text_color: FONT_FOREGROUND_COLOR,
+            text_background_color: FONT_BACKGROUND_COLOR,
             offset_from_end: 0,
             underlying_char: 0,
         }
@@ -25,6 +25,7 @@ extern crate window;
 extern crate text_display;
 extern crate shapes;
 extern crate color;
+extern crate spin;
 
 use core::ops::DerefMut;
 use alloc::string::{String, ToString};
@@ -33,12 +34,13 @@ use cursor::*;
 use text_display::TextDisplay;
 use displayable::Displayable;
 use event_types::Event;
-use font::{CHARACTER_HEIGHT, CHARACTER_WIDTH};
+use font::{character_height, character_width};
 use framebuffer::{Framebuffer, Pixel};
 use color::Color;
 use shapes::{Coord, Rectangle};
 use window::Window;
 use time::Duration;
+use spin::Mutex;
 
 pub mod cursor;
 
@@ -46,6 +48,21 @@ pub const FONT_FOREGROUND_COLOR: Color = color::LIGHT_GREEN;
 pub const FONT_BACKGROUND_COLOR: Color = color::BLACK;
 const DEFAULT_CURSOR_FREQ: Duration = Duration::from_millis(530);
 
+/// The colors of the text and the background behind it in all terminals.
+static COLORS: Mutex<(Color, Color)> = Mutex::new((FONT_FOREGROUND_COLOR, FONT_BACKGROUND_COLOR));
+
+/// Sets the colors of the text and the background behind it in all terminals,
+/// which are applied to each terminal the next time it refreshes its display.
+pub fn set_colors(foreground: Color, background: Color) {
+    *COLORS.lock() = (foreground, background);
+}
+
+/// Returns the colors of the text and the background behind it in all terminals,
+/// which default to [`FONT_FOREGROUND_COLOR`] and [`FONT_BACKGROUND_COLOR`].
+pub fn colors() -> (Color, Color) {
+    *COLORS.lock()
+}
+
 /// Error type for tracking different scroll errors that a terminal
 /// application could encounter.
 pub enum ScrollError {
@@ -68,6 +85,8 @@ pub struct Terminal {
     text_display: TextDisplay,
     /// The cursor of the terminal.
     pub cursor: Cursor,
+    /// The foreground and background colors with which the terminal was last displayed.
+    colors: (Color, Color),
 }
 
 /// Private methods of `Terminal`.
@@ -392,6 +411,28 @@ impl Terminal {
     }
 
     /// Display the text displayable in the window and render it to the screen
+    /// Clears the whole terminal with the new background color if the terminal colors
+    /// have changed since it was last displayed, such that all text is redrawn in the new colors.
+    fn apply_colors(&mut self) -> Result<(), &'static str> {
+        let colors = colors();
+        if colors == self.colors {
+            return Ok(());
+        }
+        let (foreground, background) = colors;
+        self.colors = colors;
+        self.text_display.set_colors(foreground, background);
+        self.cursor.set_colors(foreground, background);
+        let area = self.window.area();
+        framebuffer_drawer::fill_rectangle(
+            self.window.framebuffer_mut().deref_mut(),
+            area.top_left,
+            area.width(),
+            area.height(),
+            background.into(),
+        );
+        self.window.render(None)
+    }
+
     fn display_text(&mut self) -> Result<(), &'static str>{
         let coord = self.window.area().top_left;
         let area_to_render = self.text_display.display(coord, self.window.framebuffer_mut().deref_mut())?;
@@ -425,15 +466,18 @@ impl Terminal {
             wm.get_screen_size()
         };
 
+        let (foreground, background) = colors();
         let window = window::Window::new(
             Coord::new(0, 0), 
             window_width, 
             window_height,
-            FONT_BACKGROUND_COLOR,
+            background,
         )?;
         
         let area = window.area();
-        let text_display = TextDisplay::new(area.width(), area.height(), FONT_FOREGROUND_COLOR, FONT_BACKGROUND_COLOR)?;
+        let text_display = TextDisplay::new(area.width(), area.height(), foreground, background)?;
+        let mut cursor = Cursor::default();
+        cursor.set_colors(foreground, background);
 
         let mut terminal = Terminal {
             window,
@@ -441,7 +485,8 @@ impl Terminal {
             scroll_start_idx: 0,
             is_scroll_end: true,
             text_display,
-            cursor: Cursor::default(),
+            cursor,
+            colors: (foreground, background),
         };
         terminal.display_text()?;
 
@@ -457,6 +502,7 @@ impl Terminal {
 
     /// Actually refresh the screen. Currently it's expensive.
     pub fn refresh_display(&mut self) -> Result<(), &'static str> {
+        self.apply_colors()?;
         let start_idx = self.scroll_start_idx;
         // handling display refreshing errors here so that we don't clog the main loop of the terminal
         if self.is_scroll_end {
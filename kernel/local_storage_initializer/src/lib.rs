@@ -25,7 +25,8 @@ use rangemap::RangeMap;
 #[cfg(target_arch = "x86_64")]
 use {
     core::mem::size_of,
-    x86_64::{registers::model_specific::FsBase, VirtAddr},
+    msr::registers::FsBase,
+    x86_64::VirtAddr,
 };
 
 #[cfg(target_arch = "aarch64")]
@@ -116,7 +117,7 @@ pub struct Tls {}
 impl Sealed for Tls {
     unsafe fn set_as_current_base(ptr: u64) {
         #[cfg(target_arch = "x86_64")]
-        FsBase::write(VirtAddr::new(ptr));
+        unsafe { FsBase(ptr).write() };
 
         #[cfg(target_arch = "aarch64")]
         TPIDR_EL0.set(ptr);
@@ -236,7 +237,7 @@ where
         section.virt_addr = VirtualAddress::new(virt_addr_value)
             .ok_or(LocalStorageInitializerError::InvalidVirtualAddress(virt_addr_value))?;
         self.end_of_static_sections = max(self.end_of_static_sections, range.end);
-        let section_ref = Arc::new(section);
+        let section_ref = section.into_ref();
         self.static_section_offsets.insert(range, StrongSectionRefWrapper(section_ref.clone()));
         self.cache_status = CacheStatus::Invalidated;
         Ok(section_ref)
@@ -290,7 +291,7 @@ where
         section.virt_addr = VirtualAddress::new(virt_addr_value)
             .ok_or(LocalStorageInitializerError::InvalidVirtualAddress(virt_addr_value))?;
         let range = start .. (start + section.size);
-        let section_ref = Arc::new(section);
+        let section_ref = section.into_ref();
         self.end_of_dynamic_sections = max(self.end_of_dynamic_sections, range.end);
         self.dynamic_section_offsets.insert(range, StrongSectionRefWrapper(section_ref.clone()));
         // Now that we've added a new section, the cached data is invalid.
@@ -298,6 +299,29 @@ where
         Ok((start, section_ref))
     }
 
+    /// Removes the given section, previously added via [`Self::add_new_dynamic_section()`],
+    /// from this CLS/TLS area, such that its space can be reused by future sections.
+    ///
+    /// Data images that were already generated are unaffected;
+    /// only data images generated after this call will lack the removed section.
+    ///
+    /// Returns `true` if the section was found and removed.
+    /// Static sections from the base kernel image cannot be removed.
+    pub fn remove_dynamic_section(&mut self, section: &StrongSectionRef) -> bool {
+        let range = self.dynamic_section_offsets.iter()
+            .find(|(_range, sec)| Arc::ptr_eq(&sec.0, section))
+            .map(|(range, _sec)| range.clone());
+        let Some(range) = range else { return false };
+
+        self.dynamic_section_offsets.remove(range);
+        self.end_of_dynamic_sections = self.dynamic_section_offsets.iter()
+            .last()
+            .map_or(0, |(range, _sec)| range.end);
+        // Now that we've removed a section, the cached data is invalid.
+        self.cache_status = CacheStatus::Invalidated;
+        true
+    }
+
     /// Invalidates the cached data image in this `LocalStorageInitializer` area.
     /// 
     /// This is useful for when a CLS/TLS section's data has been modified,
@@ -442,6 +466,17 @@ where
         }
     }
 
+    /// Returns the raw bytes of this data image, which reside at the address of the returned slice.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the pointer to this data image that is used as the CLS/TLS base address,
+    /// e.g., the value of the `FS` base register on x86_64.
+    pub fn base_pointer(&self) -> u64 {
+        self.ptr
+    }
+
     /// Inherits the data from another data image.
     ///
     /// # Panics
@@ -0,0 +1,214 @@
+//! A service that streams the kernel log to connected TCP clients.
+//!
+//! This allows headless machines and CI runs to capture the log without serial hardware,
+//! e.g., with `nc <address> 5514`.
+//! Unlike writing to a serial port, streaming the log doesn't slow down the code that logs:
+//! log messages are retained in the logger's ring buffer (see [`logger::enable_log_buffer()`]),
+//! from which the service task sends them to clients asynchronously.
+//!
+//! Upon connecting, a client first receives all retained messages, followed by new ones.
+//! A client can filter the messages it receives by sending a line of the form
+//! `<level> [<target>...]`, e.g., `debug net mod_mgmt`,
+//! after which it only receives messages at or above that level from those targets
+//! (crates or module paths) and their submodules.
+//! Sending an empty line removes the filter.
+//! If a client reads too slowly, messages that are overwritten in the ring buffer
+//! before being sent are skipped, and the client is told how many were skipped.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use log::{info, Level, LevelFilter};
+use logger::LogEntry;
+use net::{tcp, NetworkInterface, Socket};
+use sleep::Duration;
+use task::JoinableTaskRef;
+
+/// The default TCP port on which the service listens.
+pub const DEFAULT_PORT: u16 = 5514;
+/// The recommended size of the logger's ring buffer when using this service.
+pub const LOG_BUFFER_SIZE: usize = 256 * 1024;
+/// The maximum number of clients that can be connected at once.
+const MAX_CLIENTS: usize = 4;
+/// How often the service checks for new connections, input, and log messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// The size of the buffer into which log entries are copied from the ring buffer for a client at once.
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+/// The maximum length of a filter line sent by a client.
+const MAX_INPUT_LINE: usize = 256;
+
+/// Spawns the log streaming service, which listens on the given TCP `port`
+/// of the default network interface.
+///
+/// This also enables the logger's ring buffer if it isn't already enabled.
+pub fn start(port: u16) -> Result<JoinableTaskRef, &'static str> {
+    let interface = net::get_default_interface().ok_or("log_stream: no network interface exists")?;
+    logger::enable_log_buffer(LOG_BUFFER_SIZE);
+    spawn::new_task_builder(log_stream, (interface, port))
+        .name("log_stream".into())
+        .spawn()
+}
+
+/// The messages a client wants to receive.
+struct Filter {
+    level: LevelFilter,
+    /// If empty, messages from all targets are sent.
+    targets: Vec<String>,
+}
+
+impl Filter {
+    const ALL: Filter = Filter { level: LevelFilter::Trace, targets: Vec::new() };
+
+    /// Parses a filter line of the form `<level> [<target>...]`.
+    fn parse(line: &str) -> Result<Filter, &'static str> {
+        let mut words = line.split_whitespace();
+        let Some(level) = words.next() else { return Ok(Filter::ALL) };
+        Ok(Filter {
+            level: level.parse().map_err(|_| "invalid log level, expected one of off, error, warn, info, debug, trace")?,
+            targets: words.map(ToString::to_string).collect(),
+        })
+    }
+
+    fn matches(&self, entry: &LogEntry) -> bool {
+        entry.level <= self.level
+            && (self.targets.is_empty() || self.targets.iter().any(|t| target_matches(&entry.target, t)))
+    }
+}
+
+/// Returns true if `target` is equal to `prefix` or is a submodule of `prefix`.
+fn target_matches(target: &str, prefix: &str) -> bool {
+    target.strip_prefix(prefix)
+        .map_or(false, |rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+struct Client {
+    socket: Socket<tcp::Socket<'static>>,
+    /// The sequence number of the next log entry to send to this client.
+    next_sequence: u64,
+    filter: Filter,
+    /// Formatted output that hasn't yet fit into the socket's transmit buffer.
+    output: Vec<u8>,
+    /// Input received from the client that doesn't yet form a complete line.
+    input: Vec<u8>,
+}
+
+impl Client {
+    /// Handles input from and sends output to this client.
+    ///
+    /// Returns `false` if the client has disconnected.
+    fn service(&mut self, read_buffer: &mut [u8]) -> bool {
+        {
+            let mut socket = self.socket.lock();
+            if !socket.is_active() || !socket.may_send() {
+                return false;
+            }
+            if socket.can_recv() {
+                let input = &mut self.input;
+                let _ = socket.recv(|data| {
+                    input.extend_from_slice(data);
+                    (data.len(), ())
+                });
+            }
+        }
+
+        while let Some(newline) = self.input.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.input.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            match Filter::parse(line.trim()) {
+                Ok(filter) => self.filter = filter,
+                Err(e) => self.output.extend_from_slice(format!("log_stream: {e}\n").as_bytes()),
+            }
+        }
+        if self.input.len() > MAX_INPUT_LINE {
+            self.input.clear();
+        }
+
+        if self.output.is_empty() {
+            self.format_new_entries(read_buffer);
+        }
+        if !self.output.is_empty() {
+            let mut socket = self.socket.lock();
+            if socket.can_send() {
+                if let Ok(sent) = socket.send_slice(&self.output) {
+                    self.output.drain(..sent);
+                }
+            }
+        }
+        true
+    }
+
+    /// Formats the log entries that this client hasn't received yet into its output.
+    fn format_new_entries(&mut self, read_buffer: &mut [u8]) {
+        let Some(entries) = logger::read_log_entries(self.next_sequence, read_buffer) else { return };
+        for entry in entries {
+            if entry.sequence > self.next_sequence {
+                self.output.extend_from_slice(
+                    format!("log_stream: skipped {} messages\n", entry.sequence - self.next_sequence).as_bytes()
+                );
+            }
+            self.next_sequence = entry.sequence + 1;
+            if self.filter.matches(&entry) {
+                let level = match entry.level {
+                    Level::Error => "[E] ",
+                    Level::Warn =>  "[W] ",
+                    Level::Info =>  "[I] ",
+                    Level::Debug => "[D] ",
+                    Level::Trace => "[T] ",
+                };
+                self.output.extend_from_slice(level.as_bytes());
+                self.output.extend_from_slice(entry.message.as_bytes());
+                self.output.push(b'\n');
+            }
+        }
+    }
+}
+
+/// Creates a new socket that listens for a client on the given port.
+fn listen(interface: &alloc::sync::Arc<NetworkInterface>, port: u16) -> Result<Socket<tcp::Socket<'static>>, &'static str> {
+    let rx_buffer = tcp::SocketBuffer::new(vec![0; 256]);
+    let tx_buffer = tcp::SocketBuffer::new(vec![0; 8192]);
+    let socket = interface.clone().add_socket(tcp::Socket::new(rx_buffer, tx_buffer));
+    socket.lock().listen(port).map_err(|_| "log_stream: failed to listen on socket")?;
+    Ok(socket)
+}
+
+/// The entry point for the log streaming task.
+fn log_stream((interface, port): (alloc::sync::Arc<NetworkInterface>, u16)) -> Result<(), &'static str> {
+    // Once the maximum number of clients are connected, no socket listens for new clients,
+    // so further connection attempts are refused.
+    let mut listener = Some(listen(&interface, port)?);
+    let mut clients: Vec<Client> = Vec::new();
+    let mut read_buffer = vec![0u8; READ_BUFFER_SIZE];
+    info!("log_stream: listening on TCP port {}", port);
+
+    loop {
+        interface.poll();
+
+        if listener.as_ref().map_or(false, |l| l.lock().is_active()) {
+            clients.push(Client {
+                socket: listener.take().unwrap(),
+                // Start with the oldest retained entry.
+                next_sequence: 0,
+                filter: Filter::ALL,
+                output: Vec::new(),
+                input: Vec::new(),
+            });
+        }
+
+        // Dropping a disconnected client's socket removes it from the interface, freeing its buffers.
+        clients.retain_mut(|client| client.service(&mut read_buffer));
+        if listener.is_none() && clients.len() < MAX_CLIENTS {
+            listener = Some(listen(&interface, port)?);
+        }
+
+        interface.poll();
+        let _ = sleep::sleep(POLL_INTERVAL);
+    }
+}
@@ -9,6 +9,12 @@
 //! Early log messages (before memory management is initialized) are saved
 //! to a static fixed-sized buffer such that they are not lost and
 //! can be retrieved once logging sinks are ready to be used.
+//!
+//! In addition to the system-wide log level, the log level can be overridden
+//! at runtime for individual targets (crates or modules), e.g., `mod_mgmt` or `net::tcp`.
+//! See [`set_target_log_level()`].
+//!
+//! Recent log messages can also be retained in a ring buffer, see [`enable_log_buffer()`].
 
 #![no_std]
 #![feature(trait_alias)]
@@ -19,15 +25,18 @@ extern crate log;
 extern crate sync_irq;
 extern crate serial_port_basic;
 
-use log::{Record, Level, Metadata, Log};
+use log::{Record, Level, LevelFilter, Metadata, Log};
 use core::{fmt::{self, Write}, ops::Deref};
-use sync_irq::IrqSafeMutex;
+use sync_irq::{IrqSafeMutex, IrqSafeRwLock};
 use serial_port_basic::SerialPort;
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{string::{String, ToString}, sync::Arc, vec::Vec};
 
 #[cfg(mirror_log_to_vga)]
 pub use mirror_log::set_log_mirror_function;
 
+mod log_buffer;
+pub use log_buffer::{LogEntries, LogEntry, MAX_LOG_ENTRY_SIZE, enable_log_buffer, next_log_sequence, read_log_entries};
+
 /// By default, Theseus will print all log levels, including `Trace` and above.
 pub const DEFAULT_LOG_LEVEL: Level = Level::Trace;
 
@@ -57,6 +66,63 @@ static EARLY_LOG_BUFFER: IrqSafeMutex<EarlyLogBuffer<EARLY_LOG_BUFFER_SIZE>> =
 /// If `None`, it is uninitialized, and the [`EARLY_LOGGER`] will be used as a fallback.
 static LOGGER: IrqSafeMutex<Option<Logger>> = IrqSafeMutex::new(None);
 
+/// Writers added via [`add_writer()`], which receive log messages in addition to
+/// the writers of the early logger or the real logger, whichever is in use.
+static ADDITIONAL_WRITERS: IrqSafeMutex<Vec<Arc<IrqSafeMutex<dyn Write + Send>>>> = IrqSafeMutex::new(Vec::new());
+
+/// The log levels currently in effect: the default level and any per-target overrides.
+static LEVELS: IrqSafeRwLock<LevelFilters> = IrqSafeRwLock::new(LevelFilters::new());
+
+/// The set of log levels used to determine whether a log message should be emitted.
+struct LevelFilters {
+    /// The level used for targets that don't match any entry in `targets`.
+    default: LevelFilter,
+    /// Per-target overrides, in which each target is a crate or module path prefix.
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl LevelFilters {
+    const fn new() -> Self {
+        Self {
+            // `Level::to_level_filter()` is not a `const fn`.
+            default: match DEFAULT_LOG_LEVEL {
+                Level::Error => LevelFilter::Error,
+                Level::Warn  => LevelFilter::Warn,
+                Level::Info  => LevelFilter::Info,
+                Level::Debug => LevelFilter::Debug,
+                Level::Trace => LevelFilter::Trace,
+            },
+            targets: Vec::new(),
+        }
+    }
+
+    /// Returns the level that applies to the given `target`,
+    /// which is the level of the longest (most specific) matching target prefix.
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets.iter()
+            .filter(|(prefix, _)| target_matches(target, prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    /// Returns the most verbose level among the default and all overrides,
+    /// which is used as the `log` crate's global maximum level.
+    fn max_level(&self) -> LevelFilter {
+        self.targets.iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, core::cmp::max)
+    }
+}
+
+/// Returns true if `target` is equal to `prefix` or is a submodule of `prefix`.
+///
+/// For example, the prefix `net` matches the targets `net` and `net::tcp`, but not `network`.
+fn target_matches(target: &str, prefix: &str) -> bool {
+    target.strip_prefix(prefix)
+        .map_or(false, |rest| rest.is_empty() || rest.starts_with("::"))
+}
+
 /// An early logger that can only write to a fixed number of [`SerialPort`]s,
 /// intended for basic use before dynamic heap allocation is available.
 struct EarlyLogger([Option<SerialPort>; LOG_MAX_WRITERS]);
@@ -215,6 +281,9 @@ impl DummyLogger {
         } else {
             let _ = EARLY_LOGGER.lock().write_fmt(arguments);
         }
+        for writer in ADDITIONAL_WRITERS.lock().iter() {
+            let _ = writer.lock().write_fmt(arguments);
+        }
         // If there was an error above, there's literally nothing we can do but ignore it,
         // because there is no other lower-level way to log errors than this logger.
         Ok(())
@@ -224,7 +293,15 @@ impl DummyLogger {
 impl Log for DummyLogger {
     #[inline(always)]
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= log::max_level()
+        if metadata.level() > log::max_level() {
+            return false;
+        }
+        let levels = LEVELS.read();
+        if levels.targets.is_empty() {
+            metadata.level() <= levels.default
+        } else {
+            metadata.level() <= levels.level_for(metadata.target())
+        }
     }
 
     fn log(&self, record: &Record) {
@@ -232,6 +309,8 @@ impl Log for DummyLogger {
             return;
         }
 
+        log_buffer::record(record);
+
         let (level_str, color) = match record.level() {
             Level::Error => ("[E] ", LogColor::Red),
             Level::Warn =>  ("[W] ", LogColor::Yellow),
@@ -329,14 +408,74 @@ where
     set_log_level(log_level.unwrap_or(DEFAULT_LOG_LEVEL));
 }
 
+/// Adds another writer that all subsequent log messages will be written to,
+/// e.g., a text console on the screen.
+///
+/// Unlike the writers given to [`init()`], this can be used at any time,
+/// even before the full logger has been initialized.
+/// The writer must not emit log messages itself, as that would deadlock.
+pub fn add_writer<W: Write + Send + 'static>(writer: Arc<IrqSafeMutex<W>>) {
+    ADDITIONAL_WRITERS.lock().push(writer);
+}
+
 /// Set the log level, which determines whether a given log message is actually logged. 
 /// 
 /// For example, if `Level::Trace` is set, all log levels will be logged.
 /// 
 /// If `Level::Info` is set, `debug!()` and `trace!()` will not be logged, 
 /// but `info!()`, `warn!()`, and `error!()` will be. 
+///
+/// This is the default level used for all targets that don't have
+/// a more specific level set via [`set_target_log_level()`].
 pub fn set_log_level(level: Level) {
-    log::set_max_level(level.to_level_filter())
+    set_default_log_level(level.to_level_filter())
+}
+
+/// Same as [`set_log_level()`], but also accepts [`LevelFilter::Off`].
+pub fn set_default_log_level(level: LevelFilter) {
+    let mut levels = LEVELS.write();
+    levels.default = level;
+    log::set_max_level(levels.max_level());
+}
+
+/// Returns the default log level, which applies to all targets without an override.
+pub fn default_log_level() -> LevelFilter {
+    LEVELS.read().default
+}
+
+/// Sets the log level for the given `target`, overriding the default log level.
+///
+/// A `target` is a crate name or module path, e.g., `mod_mgmt` or `net::tcp`.
+/// It applies to that crate/module and all of its submodules.
+/// If multiple overrides match a log message's target, the longest (most specific) one is used.
+///
+/// If `level` is `None`, any existing override for `target` is removed.
+pub fn set_target_log_level(target: &str, level: Option<LevelFilter>) {
+    // Allocate the new target's name before taking the lock, which every log call must also acquire.
+    // Locals are dropped in reverse order, so the lock is released before any name is deallocated.
+    let mut new_target = level.map(|_| target.to_string());
+    let mut _removed = None;
+    let mut levels = LEVELS.write();
+    let existing = levels.targets.iter().position(|(t, _)| t == target);
+    match (existing, level) {
+        (Some(idx), Some(level)) => levels.targets[idx].1 = level,
+        (Some(idx), None) => _removed = Some(levels.targets.remove(idx)),
+        (None, Some(level)) => levels.targets.extend(new_target.take().map(|t| (t, level))),
+        (None, None) => { }
+    }
+    log::set_max_level(levels.max_level());
+}
+
+/// Removes all per-target log level overrides, reverting to only the default log level.
+pub fn clear_target_log_levels() {
+    let mut levels = LEVELS.write();
+    levels.targets.clear();
+    log::set_max_level(levels.max_level());
+}
+
+/// Returns a list of all per-target log level overrides.
+pub fn target_log_levels() -> Vec<(String, LevelFilter)> {
+    LEVELS.read().targets.clone()
 }
 
 /// Convenience function for writing formatted arguments to the logger.
@@ -0,0 +1,221 @@
+//! A ring buffer that retains recent log messages, such that they can be read later,
+//! e.g., by a service that streams them to remote clients.
+//!
+//! Recording a message only formats it into a fixed-size stack buffer and copies it
+//! into the ring buffer, so enabling it doesn't noticeably slow down logging.
+//! Once the ring buffer is full, the oldest messages are overwritten.
+
+use alloc::{boxed::Box, string::String};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use log::{Level, Record};
+use sync_irq::IrqSafeMutex;
+
+/// The maximum size in bytes of a single entry in the log buffer, including its header.
+/// Longer messages are truncated.
+pub const MAX_LOG_ENTRY_SIZE: usize = 1024;
+/// The size of an entry's header: its total length (2 bytes), level, and target length.
+const HEADER_SIZE: usize = 4;
+
+static LOG_BUFFER: IrqSafeMutex<Option<LogRing>> = IrqSafeMutex::new(None);
+/// Whether the log buffer is enabled, which allows logging to skip it without locking.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// A log message retained in the log buffer.
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    /// The sequence number of this entry, which increases by one for each logged message.
+    pub sequence: u64,
+    pub level: Level,
+    /// The target of the log message, typically the module path that logged it.
+    pub target: String,
+    /// The message, prefixed by the source file and line that logged it.
+    pub message: String,
+}
+
+/// Enables retaining log messages in a ring buffer of the given size in bytes.
+///
+/// If the log buffer is already enabled, this does nothing.
+pub fn enable_log_buffer(capacity_in_bytes: usize) {
+    let mut buffer = LOG_BUFFER.lock();
+    if buffer.is_none() {
+        *buffer = Some(LogRing::new(capacity_in_bytes.max(MAX_LOG_ENTRY_SIZE)));
+        ENABLED.store(true, Ordering::Release);
+    }
+}
+
+/// Returns the sequence number that will be assigned to the next logged message.
+///
+/// Returns `None` if the log buffer isn't enabled.
+pub fn next_log_sequence() -> Option<u64> {
+    LOG_BUFFER.lock().as_ref().map(|ring| ring.next_seq)
+}
+
+/// Copies retained log entries, starting with the entry with the given `sequence` number,
+/// into the given `buffer` until the next entry doesn't fit, and returns an iterator over them.
+///
+/// Nothing is allocated while the log buffer is locked, as a heap allocation may itself log a message;
+/// the copied entries are only parsed by the returned iterator.
+/// The `buffer` must be at least [`MAX_LOG_ENTRY_SIZE`] bytes long to ensure that any entry fits.
+///
+/// If the requested entry has already been overwritten, the returned entries start with the oldest retained entry,
+/// which callers can detect by comparing its sequence number to the requested one.
+/// Returns `None` if the log buffer isn't enabled.
+pub fn read_log_entries(sequence: u64, buffer: &mut [u8]) -> Option<LogEntries<'_>> {
+    let (first_sequence, used) = {
+        let ring_buffer = LOG_BUFFER.lock();
+        let ring = ring_buffer.as_ref()?;
+        let mut first_sequence = None;
+        let mut used = 0;
+        let mut seq = ring.oldest_seq;
+        let mut offset = ring.start;
+        while seq < ring.next_seq {
+            let mut len = [0u8; 2];
+            ring.read_at(offset, &mut len);
+            let len = u16::from_le_bytes(len) as usize;
+            if seq >= sequence {
+                if used + len > buffer.len() {
+                    break;
+                }
+                ring.read_at(offset, &mut buffer[used .. used + len]);
+                first_sequence.get_or_insert(seq);
+                used += len;
+            }
+            offset = (offset + len) % ring.buf.len();
+            seq += 1;
+        }
+        (first_sequence.unwrap_or(seq), used)
+    };
+    Some(LogEntries { bytes: &buffer[..used], sequence: first_sequence })
+}
+
+/// An iterator over the log entries copied into a buffer by [`read_log_entries()`].
+pub struct LogEntries<'b> {
+    bytes: &'b [u8],
+    /// The sequence number of the next entry.
+    sequence: u64,
+}
+
+impl Iterator for LogEntries<'_> {
+    type Item = LogEntry;
+
+    fn next(&mut self) -> Option<LogEntry> {
+        if self.bytes.len() < HEADER_SIZE {
+            return None;
+        }
+        let len = u16::from_le_bytes([self.bytes[0], self.bytes[1]]) as usize;
+        let (entry, rest) = self.bytes.split_at(len);
+        let (target, message) = entry[HEADER_SIZE..].split_at(entry[3] as usize);
+        let log_entry = LogEntry {
+            sequence: self.sequence,
+            level: level_from_u8(entry[2]),
+            target: String::from_utf8_lossy(target).into_owned(),
+            message: String::from_utf8_lossy(message).into_owned(),
+        };
+        self.bytes = rest;
+        self.sequence += 1;
+        Some(log_entry)
+    }
+}
+
+/// Records the given log message in the log buffer, if it is enabled.
+pub(crate) fn record(record: &Record) {
+    if !ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+    let target = record.target().as_bytes();
+    let target = &target[..target.len().min(u8::MAX as usize)];
+
+    let mut entry = EntryWriter { buf: [0; MAX_LOG_ENTRY_SIZE], len: HEADER_SIZE + target.len() };
+    entry.buf[2] = record.level() as u8;
+    entry.buf[3] = target.len() as u8;
+    entry.buf[HEADER_SIZE .. HEADER_SIZE + target.len()].copy_from_slice(target);
+    let _ = write!(entry, "{}:{}: {}",
+        record.file().unwrap_or("??"),
+        record.line().unwrap_or(0),
+        record.args(),
+    );
+    let len = entry.len;
+    entry.buf[..2].copy_from_slice(&(len as u16).to_le_bytes());
+
+    if let Some(ring) = LOG_BUFFER.lock().as_mut() {
+        ring.push(&entry.buf[..len]);
+    }
+}
+
+fn level_from_u8(level: u8) -> Level {
+    match level {
+        1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
+/// Formats a log entry into a fixed-size buffer, silently truncating it if it doesn't fit.
+struct EntryWriter {
+    buf: [u8; MAX_LOG_ENTRY_SIZE],
+    len: usize,
+}
+
+impl Write for EntryWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = s.len().min(MAX_LOG_ENTRY_SIZE - self.len);
+        self.buf[self.len .. self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+/// A circular byte buffer of variable-sized log entries.
+struct LogRing {
+    buf: Box<[u8]>,
+    /// The offset of the oldest entry.
+    start: usize,
+    /// The number of bytes occupied by all entries.
+    used: usize,
+    /// The sequence number of the oldest entry.
+    oldest_seq: u64,
+    /// The sequence number of the next entry to be pushed.
+    next_seq: u64,
+}
+
+impl LogRing {
+    fn new(capacity: usize) -> LogRing {
+        LogRing {
+            buf: alloc::vec![0u8; capacity].into_boxed_slice(),
+            start: 0,
+            used: 0,
+            oldest_seq: 0,
+            next_seq: 0,
+        }
+    }
+
+    /// Appends the given encoded entry, overwriting the oldest entries to make room for it.
+    fn push(&mut self, entry: &[u8]) {
+        while self.used + entry.len() > self.buf.len() {
+            let mut len = [0u8; 2];
+            self.read_at(self.start, &mut len);
+            let len = u16::from_le_bytes(len) as usize;
+            self.start = (self.start + len) % self.buf.len();
+            self.used -= len;
+            self.oldest_seq += 1;
+        }
+        let end = (self.start + self.used) % self.buf.len();
+        let first = entry.len().min(self.buf.len() - end);
+        self.buf[end .. end + first].copy_from_slice(&entry[..first]);
+        self.buf[.. entry.len() - first].copy_from_slice(&entry[first..]);
+        self.used += entry.len();
+        self.next_seq += 1;
+    }
+
+    /// Copies bytes starting at the given offset into `out`, wrapping around the end of the buffer.
+    fn read_at(&self, offset: usize, out: &mut [u8]) {
+        let offset = offset % self.buf.len();
+        let first = out.len().min(self.buf.len() - offset);
+        out[..first].copy_from_slice(&self.buf[offset .. offset + first]);
+        let rest = out.len() - first;
+        out[first..].copy_from_slice(&self.buf[..rest]);
+    }
+}
@@ -0,0 +1,596 @@
+//! Support for the x86 machine-check architecture (MCA), which reports hardware errors
+//! such as memory ECC errors, cache and TLB parity errors, and bus errors.
+//!
+//! Each CPU has a set of error-reporting banks, each of which is a group of MSRs
+//! (`IA32_MCi_CTL`, `IA32_MCi_STATUS`, `IA32_MCi_ADDR`, and `IA32_MCi_MISC`)
+//! that describe the most recent error detected by one hardware unit.
+//!
+//! * Errors that the hardware corrected are only recorded in the banks;
+//!   they can be collected and logged via [`poll_corrected_errors()`].
+//! * Uncorrected errors raise a machine-check exception (`#MC`),
+//!   which should invoke [`handle_machine_check()`] in order to decide how to recover.
+//!
+//! When possible, an uncorrected error is contained by killing only the task that consumed it.
+//!
+//! The exception handler can interrupt code that holds any lock, so it must not allocate, lock, or log.
+//! Instead, it stores the errors it reads into fixed slots for the current CPU and wakes the recovery task
+//! started by [`start_recovery_task()`], which logs them and marks any faulty physical memory they reported
+//! as bad such that it will never be reused.
+//!
+//! The most recent errors of all severities are kept in a log, available via [`recorded_errors()`],
+//! and corrected errors can be polled periodically on every CPU via [`start_polling()`].
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{format, string::String, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+use cpu::CpuId;
+use log::{error, info, warn};
+use memory::{Frame, FrameRange, PhysicalAddress, VirtualAddress};
+use msr::{IA32_MC0_ADDR, IA32_MC0_CTL, IA32_MC0_MISC, IA32_MC0_STATUS, IA32_MCG_CAP, IA32_MCG_CTL, IA32_MCG_STATUS};
+use spin::{Mutex, Once};
+use task::TaskRef;
+use x86_64::registers::{
+    control::{Cr4, Cr4Flags},
+    model_specific::Msr,
+};
+
+/// The number of MSRs in each error-reporting bank; bank `i`'s MSRs start at `IA32_MC0_CTL + 4 * i`.
+const MSRS_PER_BANK: u32 = 4;
+
+/// `IA32_MCG_CAP` bit 8: the `IA32_MCG_CTL` MSR is present.
+const MCG_CTL_P: u64 = 1 << 8;
+/// `IA32_MCG_CAP` bit 24: the CPU supports software error recovery,
+/// i.e., the `S` and `AR` bits of `IA32_MCi_STATUS` are valid.
+const MCG_SER_P: u64 = 1 << 24;
+
+/// The address mode in `IA32_MCi_MISC` indicating that `IA32_MCi_ADDR` holds a physical address.
+const ADDRESS_MODE_PHYSICAL: u64 = 2;
+
+/// The maximum number of errors kept in [`RECORDED_ERRORS`]; older errors are discarded.
+const MAX_RECORDED_ERRORS: usize = 64;
+
+/// The number of CPUs that have slots in [`PENDING_ERRORS`], as CPU IDs must fit in a `u8`.
+const MAX_CPUS: usize = u8::MAX as usize + 1;
+
+/// The maximum number of errors on each CPU that can await the recovery task;
+/// further errors are discarded.
+const PENDING_SLOTS_PER_CPU: usize = 8;
+
+/// The default interval at which [`start_polling()`] checks for corrected errors.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The most recent machine-check errors reported on any CPU.
+static RECORDED_ERRORS: Mutex<RecordedErrors> = Mutex::new(RecordedErrors::EMPTY);
+
+/// The errors read by machine-check exceptions that await the recovery task, indexed by CPU ID.
+static PENDING_ERRORS: [PendingErrors; MAX_CPUS] = [PendingErrors::EMPTY; MAX_CPUS];
+
+/// The task that handles the errors in [`PENDING_ERRORS`], which sets this itself once it's running.
+static RECOVERY_TASK: Once<TaskRef> = Once::new();
+
+/// Returns `true` if the current CPU supports the machine-check architecture.
+pub fn is_supported() -> bool {
+    raw_cpuid::CpuId::new()
+        .get_feature_info()
+        .map_or(false, |info| info.has_mce() && info.has_mca())
+}
+
+/// Enables machine-check exceptions on the current CPU and enables error reporting in all of its banks.
+///
+/// This must be invoked on every CPU, after its IDT contains a machine-check handler.
+/// Any errors that were recorded before this was invoked, e.g., during a previous boot, are logged and cleared.
+pub fn init() -> Result<(), &'static str> {
+    if !is_supported() {
+        return Err("this CPU doesn't support the machine-check architecture");
+    }
+
+    for err in collect_errors() {
+        warn!("Machine-check error recorded before boot: {}", err);
+        record(err);
+    }
+
+    // SAFETY: these MSRs exist on all CPUs that support the machine-check architecture,
+    // and we checked for `IA32_MCG_CTL`'s presence.
+    unsafe {
+        if Msr::new(IA32_MCG_CAP).read() & MCG_CTL_P != 0 {
+            Msr::new(IA32_MCG_CTL).write(u64::MAX);
+        }
+        for bank in 0..num_banks() {
+            bank_msr(IA32_MC0_CTL, bank).write(u64::MAX);
+        }
+        Cr4::update(|flags| flags.insert(Cr4Flags::MACHINE_CHECK_EXCEPTION));
+    }
+    info!("Enabled machine-check exceptions on CPU {} with {} banks", cpu::current_cpu(), num_banks());
+    Ok(())
+}
+
+/// Returns the number of error-reporting banks on the current CPU.
+pub fn num_banks() -> usize {
+    // SAFETY: `IA32_MCG_CAP` exists on all CPUs that support the machine-check architecture.
+    (unsafe { Msr::new(IA32_MCG_CAP).read() } & 0xFF) as usize
+}
+
+fn bank_msr(bank0_msr: u32, bank: usize) -> Msr {
+    Msr::new(bank0_msr + MSRS_PER_BANK * bank as u32)
+}
+
+/// The contents of the `IA32_MCG_STATUS` MSR, which describes the state of the processor
+/// when a machine-check exception occurred.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct GlobalStatus(pub u64);
+
+impl GlobalStatus {
+    /// Reads the current CPU's `IA32_MCG_STATUS` MSR.
+    pub fn read() -> GlobalStatus {
+        // SAFETY: `IA32_MCG_STATUS` exists on all CPUs that support the machine-check architecture.
+        GlobalStatus(unsafe { Msr::new(IA32_MCG_STATUS).read() })
+    }
+
+    /// Whether execution can be restarted reliably at the interrupted instruction pointer.
+    pub fn restart_ip_valid(&self) -> bool { self.0 & (1 << 0) != 0 }
+    /// Whether the interrupted instruction pointer is directly associated with the error.
+    pub fn error_ip_valid(&self) -> bool { self.0 & (1 << 1) != 0 }
+    /// Whether a machine-check exception is in progress.
+    pub fn in_progress(&self) -> bool { self.0 & (1 << 2) != 0 }
+}
+
+impl fmt::Debug for GlobalStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GlobalStatus")
+            .field("RIPV", &self.restart_ip_valid())
+            .field("EIPV", &self.error_ip_valid())
+            .field("MCIP", &self.in_progress())
+            .finish()
+    }
+}
+
+/// The contents of an `IA32_MCi_STATUS` MSR, which describes the error recorded in one bank.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct BankStatus(pub u64);
+
+impl BankStatus {
+    /// Whether this bank holds a valid error.
+    pub fn is_valid(&self) -> bool { self.0 & (1 << 63) != 0 }
+    /// Whether another error occurred while this bank already held a valid error.
+    pub fn overflowed(&self) -> bool { self.0 & (1 << 62) != 0 }
+    /// Whether the hardware failed to correct the error.
+    pub fn is_uncorrected(&self) -> bool { self.0 & (1 << 61) != 0 }
+    /// Whether reporting of this error was enabled, i.e., whether it could raise an exception.
+    pub fn is_enabled(&self) -> bool { self.0 & (1 << 60) != 0 }
+    /// Whether the bank's `IA32_MCi_MISC` MSR holds more information about the error.
+    pub fn misc_valid(&self) -> bool { self.0 & (1 << 59) != 0 }
+    /// Whether the bank's `IA32_MCi_ADDR` MSR holds the address involved in the error.
+    pub fn addr_valid(&self) -> bool { self.0 & (1 << 58) != 0 }
+    /// Whether the processor's state may have been corrupted by the error,
+    /// such that execution cannot reliably continue.
+    pub fn processor_context_corrupt(&self) -> bool { self.0 & (1 << 57) != 0 }
+    /// Whether the error was signaled via a machine-check exception.
+    pub fn signaled(&self) -> bool { self.0 & (1 << 56) != 0 }
+    /// Whether software must take action to recover before resuming the interrupted code,
+    /// e.g., because that code consumed poisoned data.
+    pub fn action_required(&self) -> bool { self.0 & (1 << 55) != 0 }
+    /// The number of corrected errors, if the bank keeps such a count.
+    pub fn corrected_error_count(&self) -> u16 { ((self.0 >> 38) & 0x7FFF) as u16 }
+    /// The model-specific error code.
+    pub fn model_specific_code(&self) -> u16 { (self.0 >> 16) as u16 }
+    /// The architecturally-defined MCA error code.
+    pub fn mca_error_code(&self) -> u16 { self.0 as u16 }
+
+    /// Returns the severity of the error in this bank.
+    ///
+    /// This assumes that the CPU supports software error recovery;
+    /// see [`MachineCheckError::severity()`].
+    pub fn severity(&self) -> Severity {
+        if !self.is_uncorrected() {
+            Severity::Corrected
+        } else if self.processor_context_corrupt() {
+            Severity::Fatal
+        } else if self.action_required() {
+            Severity::ActionRequired
+        } else {
+            Severity::ActionOptional
+        }
+    }
+
+    /// Returns a description of the kind of hardware unit that reported the error,
+    /// based on its MCA error code.
+    pub fn error_class(&self) -> &'static str {
+        // Bit 12 only indicates whether corrected errors are filtered, so it is ignored.
+        let code = self.mca_error_code() & !(1 << 12);
+        match code {
+            0x0000 => "no error",
+            0x0001 => "unclassified",
+            0x0002 => "microcode ROM parity error",
+            0x0003 => "external error",
+            0x0004 => "FRC error",
+            0x0005 => "internal parity error",
+            0x0006 => "SMM handler code access violation",
+            0x0400 => "internal timer error",
+            0x0E0B => "I/O error",
+            c if c & 0xFC00 == 0x0400 => "internal unclassified error",
+            c if c & 0xFF80 == 0x0080 => "memory controller error",
+            c if c & 0xFFF0 == 0x0010 => "TLB error",
+            c if c & 0xFF00 == 0x0100 => "cache hierarchy error",
+            c if c & 0xF800 == 0x0800 => "bus or interconnect error",
+            _ => "unknown error",
+        }
+    }
+}
+
+impl fmt::Debug for BankStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BankStatus")
+            .field("VAL", &self.is_valid())
+            .field("OVER", &self.overflowed())
+            .field("UC", &self.is_uncorrected())
+            .field("EN", &self.is_enabled())
+            .field("MISCV", &self.misc_valid())
+            .field("ADDRV", &self.addr_valid())
+            .field("PCC", &self.processor_context_corrupt())
+            .field("S", &self.signaled())
+            .field("AR", &self.action_required())
+            .field("mca_error_code", &format_args!("{:#06X}", self.mca_error_code()))
+            .field("model_specific_code", &format_args!("{:#06X}", self.model_specific_code()))
+            .finish()
+    }
+}
+
+/// How severe a machine-check error is, from least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The hardware corrected the error; no action is needed.
+    Corrected,
+    /// The error was not corrected, but it has not been consumed by the interrupted code,
+    /// e.g., poisoned memory found by a patrol scrub. Execution can continue.
+    ActionOptional,
+    /// The interrupted code consumed the uncorrected error, so it cannot continue,
+    /// but the rest of the system can.
+    ActionRequired,
+    /// The error corrupted the processor's state, so the system cannot continue.
+    Fatal,
+}
+
+/// An error recorded in one of a CPU's machine-check banks.
+#[derive(Clone, Debug)]
+pub struct MachineCheckError {
+    /// The CPU that recorded the error.
+    pub cpu: CpuId,
+    /// The index of the bank that recorded the error.
+    pub bank: usize,
+    pub status: BankStatus,
+    /// The physical address involved in the error, if known.
+    pub address: Option<PhysicalAddress>,
+    /// The contents of the bank's `IA32_MCi_MISC` MSR, if valid.
+    pub misc: Option<u64>,
+    /// Whether the CPU supports software error recovery.
+    pub software_recovery: bool,
+}
+
+impl MachineCheckError {
+    /// Reads the error recorded in the given `bank` of the current CPU, if any.
+    pub fn read(bank: usize) -> Option<MachineCheckError> {
+        // SAFETY: the bank's `IA32_MCi_ADDR` and `IA32_MCi_MISC` MSRs are only read if the status indicates they are valid.
+        unsafe {
+            let status = BankStatus(bank_msr(IA32_MC0_STATUS, bank).read());
+            if !status.is_valid() {
+                return None;
+            }
+            let misc = status.misc_valid().then(|| bank_msr(IA32_MC0_MISC, bank).read());
+            // If present, the MISC register says which kind of address the ADDR register holds;
+            // we're only interested in physical addresses.
+            let addr_is_physical = misc.map_or(true, |misc| (misc >> 6) & 0b111 == ADDRESS_MODE_PHYSICAL);
+            let address = (status.addr_valid() && addr_is_physical)
+                .then(|| bank_msr(IA32_MC0_ADDR, bank).read())
+                .and_then(|addr| PhysicalAddress::new(addr as usize));
+            let software_recovery = Msr::new(IA32_MCG_CAP).read() & MCG_SER_P != 0;
+            Some(MachineCheckError { cpu: cpu::current_cpu(), bank, status, address, misc, software_recovery })
+        }
+    }
+
+    /// Returns the severity of this error.
+    ///
+    /// On CPUs that don't support software error recovery, all uncorrected errors are fatal.
+    pub fn severity(&self) -> Severity {
+        match self.status.severity() {
+            Severity::Corrected => Severity::Corrected,
+            _ if !self.software_recovery => Severity::Fatal,
+            severity => severity,
+        }
+    }
+}
+
+impl fmt::Display for MachineCheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {} on CPU {} bank {}", self.severity(), self.status.error_class(), self.cpu, self.bank)?;
+        if let Some(address) = self.address {
+            write!(f, " at {}", address)?;
+        }
+        if self.status.overflowed() {
+            write!(f, " (overflowed)")?;
+        }
+        write!(f, ", status {:#018X}", self.status.0)
+    }
+}
+
+/// The errors read by machine-check exceptions on one CPU that await the recovery task.
+///
+/// A slot is only filled by the exception handler on its CPU, which can't be interrupted by another one,
+/// and only emptied by the recovery task; its `full` flag determines which of them may access its error.
+struct PendingErrors {
+    slots: [PendingSlot; PENDING_SLOTS_PER_CPU],
+    /// The number of errors that were discarded because all slots were full.
+    discarded: AtomicUsize,
+}
+
+struct PendingSlot {
+    full: AtomicBool,
+    error: UnsafeCell<MaybeUninit<MachineCheckError>>,
+}
+
+// SAFETY: a slot's error is only accessed by the exception handler while the slot is empty,
+// and only by the recovery task while it's full.
+unsafe impl Sync for PendingSlot { }
+
+impl PendingSlot {
+    const EMPTY: PendingSlot = PendingSlot { full: AtomicBool::new(false), error: UnsafeCell::new(MaybeUninit::uninit()) };
+}
+
+impl PendingErrors {
+    const EMPTY: PendingErrors = PendingErrors {
+        slots: [PendingSlot::EMPTY; PENDING_SLOTS_PER_CPU],
+        discarded: AtomicUsize::new(0),
+    };
+
+    /// Stores the given error in an empty slot, or discards it if there is none.
+    ///
+    /// This must only be invoked by the exception handler on this CPU.
+    fn push(&self, err: MachineCheckError) {
+        match self.slots.iter().find(|slot| !slot.full.load(Ordering::Acquire)) {
+            Some(slot) => {
+                // SAFETY: the slot is empty, so the recovery task won't access it until it's marked as full.
+                unsafe { (*slot.error.get()).write(err) };
+                slot.full.store(true, Ordering::Release);
+            }
+            None => {
+                self.discarded.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Removes the errors from all full slots and passes each of them to the given function.
+    ///
+    /// This must only be invoked by the recovery task.
+    fn drain(&self, mut f: impl FnMut(MachineCheckError)) {
+        for slot in &self.slots {
+            if slot.full.load(Ordering::Acquire) {
+                // SAFETY: the slot is full, so the exception handler won't access it until it's marked as empty.
+                let err = unsafe { (*slot.error.get()).assume_init_read() };
+                slot.full.store(false, Ordering::Release);
+                f(err);
+            }
+        }
+    }
+}
+
+/// A fixed-size ring of the most recent errors, which never allocates.
+struct RecordedErrors {
+    errors: [Option<MachineCheckError>; MAX_RECORDED_ERRORS],
+    /// The index in `errors` at which the next error is stored, overwriting the oldest one.
+    next: usize,
+}
+
+impl RecordedErrors {
+    const NONE: Option<MachineCheckError> = None;
+    const EMPTY: RecordedErrors = RecordedErrors { errors: [Self::NONE; MAX_RECORDED_ERRORS], next: 0 };
+
+    /// Stores the given error, replacing the oldest one if the ring is full.
+    fn push(&mut self, err: MachineCheckError) {
+        self.errors[self.next] = Some(err);
+        self.next = (self.next + 1) % MAX_RECORDED_ERRORS;
+    }
+
+    /// Returns an iterator over the stored errors, oldest first.
+    fn iter(&self) -> impl Iterator<Item = &MachineCheckError> {
+        let (newer, older) = self.errors.split_at(self.next);
+        older.iter().chain(newer).flatten()
+    }
+}
+
+/// Adds the given error to the log of recent errors.
+fn record(err: MachineCheckError) {
+    RECORDED_ERRORS.lock().push(err);
+}
+
+/// Returns the most recent machine-check errors reported on any CPU, oldest first.
+///
+/// At most 64 errors are kept; older errors are only available in the system log.
+pub fn recorded_errors() -> Vec<MachineCheckError> {
+    RECORDED_ERRORS.lock().iter().cloned().collect()
+}
+
+/// Reads and clears all errors recorded in the current CPU's banks.
+fn collect_errors() -> Vec<MachineCheckError> {
+    (0..num_banks())
+        .filter_map(|bank| {
+            let err = MachineCheckError::read(bank)?;
+            clear_bank(bank);
+            Some(err)
+        })
+        .collect()
+}
+
+fn clear_bank(bank: usize) {
+    // SAFETY: writing zero to a bank's `IA32_MCi_STATUS` MSR is always permitted.
+    unsafe { bank_msr(IA32_MC0_STATUS, bank).write(0) };
+}
+
+/// Logs and clears the corrected errors recorded in the current CPU's banks, and returns them.
+///
+/// Uncorrected errors are left in place for the machine-check exception handler.
+pub fn poll_corrected_errors() -> Vec<MachineCheckError> {
+    (0..num_banks())
+        .filter_map(|bank| {
+            let err = MachineCheckError::read(bank).filter(|err| err.severity() == Severity::Corrected)?;
+            clear_bank(bank);
+            warn!("Corrected machine-check error: {}", err);
+            record(err.clone());
+            Some(err)
+        })
+        .collect()
+}
+
+/// Spawns a task on every CPU that polls its banks for corrected errors every `interval`.
+///
+/// Corrected errors don't raise a machine-check exception, so they are only noticed by polling.
+/// Returns an error if the machine-check architecture isn't supported.
+pub fn start_polling(interval: Duration) -> Result<(), &'static str> {
+    if !is_supported() {
+        return Err("this CPU doesn't support the machine-check architecture");
+    }
+    for cpu in cpu::cpus() {
+        spawn::new_task_builder(poll_task, interval)
+            .name(format!("machine_check_poll_{cpu}"))
+            .pin_on_cpu(cpu)
+            .spawn()?;
+    }
+    Ok(())
+}
+
+/// The entry point of each task spawned by [`start_polling()`].
+fn poll_task(interval: Duration) -> Result<(), &'static str> {
+    loop {
+        poll_corrected_errors();
+        sleep::sleep(interval).map_err(|_| "machine_check: failed to sleep")?;
+    }
+}
+
+/// What the machine-check exception handler should do after [`handle_machine_check()`] returns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recovery {
+    /// All errors were corrected or did not affect the interrupted code, so it can be resumed.
+    Resume,
+    /// The interrupted code consumed an uncorrected error, so the current task must be killed.
+    KillCurrentTask,
+    /// The error cannot be contained, so the system must be halted.
+    Halt,
+}
+
+/// Spawns the task that logs and records the errors read by machine-check exceptions,
+/// and marks any faulty physical memory that they reported as bad.
+///
+/// Until this is invoked, such errors are kept in a fixed number of slots for each CPU.
+/// Returns an error if the machine-check architecture isn't supported.
+pub fn start_recovery_task() -> Result<(), &'static str> {
+    if !is_supported() {
+        return Err("this CPU doesn't support the machine-check architecture");
+    }
+    spawn::new_task_builder(recovery_task, ())
+        .name(String::from("machine_check_recovery"))
+        .spawn()?;
+    Ok(())
+}
+
+/// The entry point of the task spawned by [`start_recovery_task()`].
+fn recovery_task(_: ()) -> Result<(), &'static str> {
+    let curr_task = task::get_my_current_task().ok_or("machine_check: couldn't get current task")?;
+    RECOVERY_TASK.call_once(|| curr_task.clone());
+    loop {
+        // Blocking before handling the pending errors ensures that an exception that occurs
+        // while they're being handled unblocks this task again, so its errors aren't missed.
+        let _ = curr_task.block();
+        handle_pending_errors();
+        task::schedule();
+    }
+}
+
+/// Logs and records the errors read by machine-check exceptions on all CPUs,
+/// and marks any faulty physical memory that they reported as bad.
+fn handle_pending_errors() {
+    for (cpu, pending) in PENDING_ERRORS.iter().enumerate() {
+        let discarded = pending.discarded.swap(0, Ordering::Relaxed);
+        if discarded > 0 {
+            error!("Discarded {} machine-check errors on CPU {}, as too many were pending", discarded, cpu);
+        }
+        pending.drain(|err| {
+            if err.severity() == Severity::Corrected {
+                warn!("Machine-check error: {}", err);
+            } else {
+                error!("Machine-check error: {}", err);
+            }
+            if let Some(address) = err.address.filter(|_| err.severity() >= Severity::ActionOptional) {
+                let frame = Frame::containing_address(address);
+                if let Err(e) = memory::mark_frames_as_bad(FrameRange::new(frame, frame)) {
+                    error!("Failed to mark poisoned frame {:?} as bad: {}", frame, e);
+                }
+            }
+            record(err);
+        });
+    }
+}
+
+/// Handles a machine-check exception on the current CPU that interrupted the given `instruction_pointer`.
+///
+/// This clears all errors in the current CPU's banks, defers them to the recovery task,
+/// and decides how to recover.
+/// If the interrupted code consumed an uncorrected error, the crate containing `instruction_pointer`
+/// is used to determine whether the damage can be contained to the current task.
+///
+/// This doesn't allocate, log, or take any locks other than those needed to find that crate.
+pub fn handle_machine_check(instruction_pointer: VirtualAddress) -> Recovery {
+    let global = GlobalStatus::read();
+    let pending = PENDING_ERRORS.get(cpu::current_cpu().value() as usize);
+    let mut severity = None;
+    for bank in 0..num_banks() {
+        let Some(err) = MachineCheckError::read(bank) else { continue };
+        clear_bank(bank);
+        severity = severity.max(Some(err.severity()));
+        if let Some(pending) = pending {
+            pending.push(err);
+        }
+    }
+    if let Some(recovery_task) = RECOVERY_TASK.get() {
+        let _ = recovery_task.unblock();
+    }
+
+    let recovery = match severity {
+        None | Some(Severity::Corrected) | Some(Severity::ActionOptional) if global.restart_ip_valid() => Recovery::Resume,
+        Some(Severity::Fatal) => Recovery::Halt,
+        _ => recovery_for_current_task(global, instruction_pointer),
+    };
+
+    // Clearing `MCIP` permits another machine-check exception to be raised;
+    // while it's set, a second one would shut down the processor.
+    // SAFETY: writing zero to `IA32_MCG_STATUS` is always permitted.
+    unsafe { Msr::new(IA32_MCG_STATUS).write(0) };
+    recovery
+}
+
+/// Determines whether an uncorrected error that the interrupted code consumed
+/// can be contained by killing the current task.
+fn recovery_for_current_task(global: GlobalStatus, instruction_pointer: VirtualAddress) -> Recovery {
+    // Without a valid instruction pointer, the code that consumed the error can't be identified.
+    if !global.error_ip_valid() {
+        return Recovery::Halt;
+    }
+    let Some(curr_task) = task::get_my_current_task() else {
+        return Recovery::Halt;
+    };
+    if curr_task.is_an_idle_task {
+        return Recovery::Halt;
+    }
+    // Killing the task only contains the error if it was consumed by the task's own code,
+    // rather than by code outside of any crate, e.g., an early boot stub.
+    match curr_task.get_namespace().get_crate_containing_address(instruction_pointer, false) {
+        Some(_) => Recovery::KillCurrentTask,
+        None => Recovery::Halt,
+    }
+}
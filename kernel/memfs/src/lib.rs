@@ -11,9 +11,11 @@ extern crate fs_node;
 extern crate memory;
 extern crate irq_safety;
 extern crate io;
+extern crate file_watch;
 
 
 use alloc::string::String;
+use alloc::vec;
 use fs_node::{DirRef, WeakDirRef, File, FsNode};
 use memory::{MappedPages, get_kernel_mmi_ref, allocate_pages_by_bytes, PteFlags};
 use alloc::sync::Arc;
@@ -53,6 +55,13 @@ impl MemFile {
         parent.lock().insert(FileOrDir::File(file_ref.clone()))?; // adds the newly created file to the tree
         Ok(file_ref)
     }
+
+    /// Notifies any watchers of this file that its contents have been modified.
+    fn notify_modified(&self) {
+        if file_watch::is_active() {
+            file_watch::notify(&self.get_absolute_path(), file_watch::FileEventKind::Modified);
+        }
+    }
 }
 
 impl ByteReader for MemFile {
@@ -88,6 +97,7 @@ impl ByteWriter for MemFile {
             if end > self.len { 
                 self.len = end; 
             }
+            self.notify_modified();
             Ok(buffer.len()) // we wrote all of the requested bytes successfully
         } 
         // if not, we need to reallocate a new mapped pages 
@@ -126,6 +136,7 @@ impl ByteWriter for MemFile {
             }
             self.mp = new_mapped_pages;
             self.len = end;
+            self.notify_modified();
             Ok(buffer.len())
         }
     }
@@ -144,6 +155,18 @@ impl File for MemFile {
     fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
         Ok(&self.mp)
     }
+
+    fn set_len(&mut self, len: usize) -> Result<(), &'static str> {
+        if len > self.len {
+            // extending the file is the same as writing zeros past its end
+            self.write_at(&vec![0u8; len - self.len], self.len)?;
+        } else if len < self.len {
+            // the underlying mapped pages are kept, as the file may grow again
+            self.len = len;
+            self.notify_modified();
+        }
+        Ok(())
+    }
 }
 
 impl FsNode for MemFile {
@@ -20,7 +20,9 @@ mod paging;
 pub use self::paging::{
     PageTable, Mapper, Mutability, Mutable, Immutable,
     MappedPages, BorrowedMappedPages, BorrowedSliceMappedPages,
-    translate,
+    translate, handle_copy_on_write_fault, mapped_bytes_by_crate, unattributed_mapped_bytes,
+    MmioMapping, MmioMappings, MAX_MMIO_MAPPINGS, mmio_mappings, set_mmio_logging, mmio_logging, dropped_mmio_mappings,
+    merged_page_stats, MergedPageStats,
 };
 
 pub use memory_structs::*;
@@ -46,6 +48,15 @@ pub use frame_allocator::{
     allocate_frames_at,
     allocate_frames_by_bytes,
     allocate_frames_by_bytes_at,
+    allocate_frames_on_node,
+    allocate_frames_on_node_deferred,
+    add_node_memory_range,
+    node_of_frame,
+    num_free_frames_on_node,
+    num_free_general_frames,
+    mark_frames_as_bad,
+    is_frame_bad,
+    num_bad_frames,
     dump_frame_allocator_state,
 };
 
@@ -117,6 +128,7 @@ pub const DMA_FLAGS: PteFlags = PteFlags::from_bits_truncate(
 /// # Locking / Deadlock
 /// Currently, this function acquires the lock on the frame allocator and the kernel's `MemoryManagementInfo` instance.
 /// Thus, the caller should ensure that the locks on those two variables are not held when invoking this function.
+#[track_caller]
 pub fn create_contiguous_mapping<F: Into<PteFlagsArch>>(
     size_in_bytes: usize,
     flags: F,
@@ -135,6 +147,7 @@ pub fn create_contiguous_mapping<F: Into<PteFlagsArch>>(
 /// # Locking / Deadlock
 /// Currently, this function acquires the lock on the frame allocator and the kernel's `MemoryManagementInfo` instance.
 /// Thus, the caller should ensure that the locks on those two variables are not held when invoking this function.
+#[track_caller]
 pub fn map_frame_range<F: Into<PteFlagsArch>>(
     start_address: PhysicalAddress,
     size_in_bytes: usize,
@@ -156,6 +169,7 @@ pub fn map_frame_range<F: Into<PteFlagsArch>>(
 /// # Locking / Deadlock
 /// Currently, this function acquires the lock on the kernel's `MemoryManagementInfo` instance.
 /// Thus, the caller should ensure that lock is not held when invoking this function.
+#[track_caller]
 pub fn create_mapping<F: Into<PteFlagsArch>>(
     size_in_bytes: usize,
     flags: F,
@@ -170,6 +184,7 @@ pub fn create_mapping<F: Into<PteFlagsArch>>(
 ///
 /// The returned `MappedPages` is guaranteed to have virtual pages mapped to physical frames
 /// with the same virtual addresses as physical addresses.
+#[track_caller]
 pub fn create_identity_mapping<F: Into<PteFlagsArch>>(
     num_pages: usize,
     flags: F,
@@ -297,6 +312,8 @@ pub fn init(
     let mut free_index = 0;
     let mut reserved_regions: [Option<PhysicalMemoryRegion>; 32] = Default::default();
     let mut reserved_index = 0;
+    let mut defective_regions: [Option<FrameRange>; 32] = Default::default();
+    let mut defective_index = 0;
 
     reserved_regions[reserved_index] = Some(PhysicalMemoryRegion::new(low_memory_frames, MemoryRegionType::Reserved));
     reserved_index += 1;
@@ -312,11 +329,27 @@ pub fn init(
     }
 
     for region in boot_info.memory_regions()? {
-        let frames = FrameRange::from_phys_addr(region.start(), region.len());
+        if region.is_empty() {
+            continue;
+        }
         if region.is_usable() {
+            // A usable region may not be frame-aligned, in which case only the whole frames within it are usable,
+            // because a partial frame at either end of it also covers part of an adjacent region.
+            let start = region.start().value().next_multiple_of(PAGE_SIZE);
+            let end = (region.start().value() + region.len()) / PAGE_SIZE * PAGE_SIZE;
+            if end <= start {
+                continue;
+            }
+            let frames = FrameRange::from_phys_addr(PhysicalAddress::new_canonical(start), end - start);
             free_regions[free_index] = Some(PhysicalMemoryRegion::new(frames, MemoryRegionType::Free));
             free_index += 1;
         } else {
+            // Unusable regions are rounded out to cover every frame that they overlap.
+            let frames = FrameRange::from_phys_addr(region.start(), region.len());
+            if region.is_defective() {
+                defective_regions[defective_index] = Some(frames.clone());
+                defective_index += 1;
+            }
             reserved_regions[reserved_index] = Some(PhysicalMemoryRegion::new(frames, MemoryRegionType::Reserved));
             reserved_index += 1;
         }
@@ -331,6 +364,11 @@ pub fn init(
     }
 
     let into_alloc_frames_fn = frame_allocator::init(free_regions.iter().flatten(), reserved_regions.iter().flatten())?;
+    // Defective regions were added as reserved regions above, so they can't overlap any free regions,
+    // but they must also be marked as bad such that they can never be allocated, even at a specific address.
+    for frames in defective_regions.into_iter().flatten() {
+        frame_allocator::mark_frames_as_bad(frames)?;
+    }
     debug!("Initialized new frame allocator!");
     frame_allocator::dump_frame_allocator_state();
 
@@ -14,23 +14,29 @@ use core::{
     marker::PhantomData,
     mem,
     ops::{Deref, DerefMut},
+    panic::Location,
     ptr::{NonNull, Unique},
     slice,
 };
 use log::{error, warn, debug, trace};
 use memory_structs::{PageSize, Page4K};
-use crate::{BROADCAST_TLB_SHOOTDOWN_FUNC, VirtualAddress, PhysicalAddress, Page, Frame, FrameRange, AllocatedPages, AllocatedFrames, UnmappedFrames}; 
+use crate::{BROADCAST_TLB_SHOOTDOWN_FUNC, VirtualAddress, PhysicalAddress, Page, PageRange, Frame, FrameRange, AllocatedPages, AllocatedFrames, UnmappedFrames}; 
 use crate::paging::{
     get_current_p4,
+    mapping_owners,
+    mmio_log,
+    page_merging,
     table::{P4, UPCOMING_P4, Table, Level4},
 };
-use pte_flags::PteFlagsArch;
+use pte_flags::{PteFlags, PteFlagsArch};
+use sync_irq::IrqSafeMutex;
 use spin::Once;
 use kernel_config::memory::PAGE_SIZE;
 use super::tlb_flush_virt_addr;
 use zerocopy::FromBytes;
-use page_table_entry::UnmapResult;
+use page_table_entry::{PageTableEntry, UnmapResult};
 use owned_borrowed_trait::{OwnedOrBorrowed, Owned, Borrowed};
+use alloc::vec::Vec;
 
 #[cfg(target_arch = "x86_64")]
 use kernel_config::memory::ENTRIES_PER_PAGE_TABLE;
@@ -106,6 +112,16 @@ impl Mapper {
         unsafe { self.p4.as_mut() }
     }
 
+    /// Returns a mutable reference to the P1-level page table entry for the given `page`.
+    fn p1_entry_mut(&mut self, page: Page) -> Result<&mut PageTableEntry, &'static str> {
+        self.p4_mut()
+            .next_table_mut(page.p4_index())
+            .and_then(|p3| p3.next_table_mut(page.p3_index()))
+            .and_then(|p2| p2.next_table_mut(page.p2_index()))
+            .map(|p1| &mut p1[page.p1_index()])
+            .ok_or("mapping code does not support huge pages")
+    }
+
     /// Dumps all page table entries at all four page table levels for the given `VirtualAddress`, 
     /// and also shows their `PteFlags`.
     /// 
@@ -248,6 +264,7 @@ impl Mapper {
     /// 
     /// Returns a tuple of the new `MappedPages` object containing the allocated `pages`
     /// and the allocated `frames` object.
+    #[track_caller]
     pub(super) fn internal_map_to<P, BF, FL>(
         &mut self,
         pages: AllocatedPages/* <P> */,
@@ -267,7 +284,8 @@ impl Mapper {
         // we are mapping it exclusively (i.e., owned `AllocatedFrames` are passed in).
         let actual_flags = flags
             .valid(true)
-            .exclusive(BF::OWNED);
+            .exclusive(BF::OWNED)
+            .copy_on_write(false);
 
         let pages_count = pages.size_in_pages();
         let frames_count = frames.borrow().size_in_frames();
@@ -294,11 +312,21 @@ impl Mapper {
             p1[page.p1_index()].set_entry(frame, actual_flags);
         }
 
+        let owner = mapping_owners::record_mapping(Location::caller(), pages.size_in_bytes());
+        if actual_flags.is_device_memory() {
+            mmio_log::record_mapping(
+                Location::caller(),
+                frames.borrow().start_address(),
+                pages.start_address(),
+                pages.size_in_bytes(),
+            );
+        }
         Ok((
             MappedPages {
                 page_table_p4: self.target_p4,
                 pages,
                 flags: actual_flags,
+                owner,
             },
             frames,
         ))
@@ -308,6 +336,7 @@ impl Mapper {
     /// Maps the given virtual `AllocatedPages` to the given physical `AllocatedFrames`.
     /// 
     /// Consumes the given `AllocatedPages` and returns a `MappedPages` object which contains those `AllocatedPages`.
+    #[track_caller]
     pub fn map_allocated_pages_to<P, FL>(
         &mut self,
         pages: AllocatedPages /* <P> */,
@@ -337,6 +366,7 @@ impl Mapper {
     /// ## Note on huge pages
     /// This function only supports 4K-sized pages, not huge pages.
     /// To use huge pages, you must provide the huge frames and call [`Self::map_allocated_pages_to()`].
+    #[track_caller]
     pub fn map_allocated_pages<FL: Into<PteFlagsArch>>(
         &mut self,
         pages: AllocatedPages,
@@ -349,7 +379,8 @@ impl Mapper {
         // we are mapping it exclusively (to owned `AllocatedFrames`).
         let actual_flags = flags
             .valid(true)
-            .exclusive(true);
+            .exclusive(true)
+            .copy_on_write(false);
 
         for page in pages.range().clone() {
             let af = frame_allocator::allocate_frames(1).ok_or("map_allocated_pages(): couldn't allocate new frame, out of memory")?;
@@ -369,10 +400,12 @@ impl Mapper {
             core::mem::forget(af); // we currently forget frames allocated here since we don't yet have a way to track them.
         }
 
+        let owner = mapping_owners::record_mapping(Location::caller(), pages.size_in_bytes());
         Ok(MappedPages {
             page_table_p4: self.target_p4,
             pages,
             flags: actual_flags,
+            owner,
         })
     }
 }
@@ -395,6 +428,7 @@ impl Mapper {
     /// Consumes the given `AllocatedPages` and returns a `MappedPages` object
     /// which contains those `AllocatedPages`.
     #[doc(hidden)]
+    #[track_caller]
     pub unsafe fn map_to_non_exclusive<FL: Into<PteFlagsArch>>(
         mapper: &mut Self,
         pages: AllocatedPages,
@@ -503,6 +537,8 @@ pub struct MappedPages {
     pages: AllocatedPages,
     // The PTE flags that define the page permissions of this mapping.
     flags: PteFlagsArch,
+    /// The ID of the source location that created this mapping; see the `mapping_owners` module.
+    owner: u16,
 }
 static_assertions::assert_not_impl_any!(MappedPages: DerefMut, Clone);
 impl Deref for MappedPages {
@@ -520,6 +556,7 @@ impl MappedPages {
             page_table_p4: Frame::containing_address(PhysicalAddress::zero()),
             pages: AllocatedPages::empty(),
             flags: PteFlagsArch::new(),
+            owner: mapping_owners::DISOWNED,
         }
     }
 
@@ -528,6 +565,17 @@ impl MappedPages {
         self.flags
     }
 
+    /// Excludes this `MappedPages` from the per-crate mapping statistics
+    /// reported by [`mapped_bytes_by_crate()`](crate::mapped_bytes_by_crate).
+    ///
+    /// This is useful for mappings whose memory is already accounted for elsewhere,
+    /// e.g., the section pages of loaded crates, which are attributed to those crates
+    /// rather than to the crate loader that mapped them.
+    pub fn disown(&mut self) {
+        mapping_owners::record_unmapping(self.owner, self.size_in_bytes());
+        self.owner = mapping_owners::DISOWNED;
+    }
+
     /// Merges the given `MappedPages` object `mp` into this `MappedPages` object (`self`).
     ///
     /// For example, if you have the following `MappedPages` objects:    
@@ -558,6 +606,7 @@ impl MappedPages {
 
         // Attempt to merge the page ranges together, which will fail if they're not contiguous.
         // First, take ownership of the AllocatedPages inside of the `mp` argument.
+        let size_before_merge = self.pages.size_in_bytes();
         let second_alloc_pages_owned = core::mem::replace(&mut mp.pages, AllocatedPages::empty());
         if let Err(orig) = self.pages.merge(second_alloc_pages_owned) {
             // Upon error, restore the `mp.pages` AllocatedPages that we took ownership of.
@@ -568,6 +617,8 @@ impl MappedPages {
             return Err(("failed to merge MappedPages that weren't virtually contiguous", mp));
         }
 
+        // The merged pages are now attributed to the owner of this mapping.
+        mapping_owners::transfer(mp.owner, self.owner, self.pages.size_in_bytes() - size_before_merge);
         // Ensure the existing mapping doesn't run its drop handler and unmap its pages.
         mem::forget(mp); 
         Ok(())
@@ -598,11 +649,13 @@ impl MappedPages {
                     page_table_p4: self.page_table_p4,
                     pages: first_ap,
                     flags: self.flags,
+                    owner: self.owner,
                 },
                 MappedPages {
                     page_table_p4: self.page_table_p4,
                     pages: second_ap,
                     flags: self.flags,
+                    owner: self.owner,
                 }
                 // When returning here, `self` will be dropped, but it's empty so it has no effect.
             )),
@@ -626,6 +679,7 @@ impl MappedPages {
     /// 
     /// Returns a new `MappedPages` object with the same in-memory contents
     /// as this object, but at a completely new memory region.
+    #[track_caller]
     pub fn deep_copy<F: Into<PteFlagsArch>>(
         &self,
         active_table_mapper: &mut Mapper,
@@ -678,6 +732,7 @@ impl MappedPages {
         // Also ensure these flags are PRESENT (valid), since they are currently being mapped.
         let new_flags = new_flags.into()
             .exclusive(self.flags.is_exclusive())
+            .copy_on_write(false)
             .valid(true);
 
         if new_flags == self.flags {
@@ -692,7 +747,23 @@ impl MappedPages {
                 .and_then(|p2| p2.next_table_mut(page.p2_index()))
                 .ok_or("mapping code does not support huge pages")?;
             
-            p1[page.p1_index()].set_flags(new_flags);
+            let pte = &mut p1[page.p1_index()];
+
+            // A page of an exclusive mapping that isn't mapped exclusively shares a merged frame
+            // (see `page_merging`), e.g., because it was merged or duplicated copy-on-write,
+            // which must never become writable.
+            if self.flags.is_exclusive() && !pte.flags().is_exclusive() {
+                if new_flags.is_writable() {
+                    // If a copy-on-write fault already gave this page its own frame, only its flags must change.
+                    if !unshare_page(active_table_mapper, page, new_flags)? {
+                        active_table_mapper.p1_entry_mut(page)?.set_flags(new_flags);
+                    }
+                } else {
+                    pte.set_flags(new_flags.exclusive(false));
+                }
+            } else {
+                pte.set_flags(new_flags);
+            }
 
             tlb_flush_virt_addr(page.start_address());
         }
@@ -704,7 +775,267 @@ impl MappedPages {
         self.flags = new_flags;
         Ok(())
     }   
+
+    /// Returns the indices of the pages in this `MappedPages` that have been written to
+    /// since they were mapped or since the last call to this function, whichever is more recent,
+    /// and clears the `DIRTY` bit of those pages.
+    ///
+    /// This allows incrementally tracking which pages of a mapping have changed,
+    /// e.g., to only copy modified pages when checkpointing or migrating a task.
+    ///
+    /// This `MappedPages` must be mapped in the page table of the given `active_table_mapper`.
+    ///
+    /// Note: on x86_64, the `DIRTY` bit is set by hardware upon the first write to a page.
+    /// On aarch64, it is only set by hardware if hardware dirty-bit management is enabled.
+    pub fn take_dirty_pages(&mut self, active_table_mapper: &mut Mapper) -> Result<Vec<usize>, &'static str> {
+        self.take_pte_flag(
+            active_table_mapper,
+            |flags| flags.is_dirty(),
+            |flags| flags.dirty(false),
+        )
+    }
+
+    /// Returns the indices of the pages in this `MappedPages` whose `DIRTY` bit is set,
+    /// like [`MappedPages::take_dirty_pages()`], but without clearing it.
+    ///
+    /// This allows the `DIRTY` bit of each page to be cleared via [`MappedPages::clear_dirty_page()`]
+    /// only once that page's contents have been saved, e.g., written back to a file.
+    ///
+    /// This `MappedPages` must be mapped in the page table of the given `active_table_mapper`.
+    pub fn dirty_pages(&self, active_table_mapper: &mut Mapper) -> Result<Vec<usize>, &'static str> {
+        if active_table_mapper.target_p4 != self.page_table_p4 {
+            return Err("MappedPages must be mapped in the current page table to read its dirty or accessed bits");
+        }
+        let mut dirty = Vec::new();
+        for (page_index, page) in self.pages.range().clone().into_iter().enumerate() {
+            if active_table_mapper.p1_entry_mut(page)?.flags().is_dirty() {
+                dirty.push(page_index);
+            }
+        }
+        Ok(dirty)
+    }
+
+    /// Clears the `DIRTY` bit of the page at `page_index` in this `MappedPages`
+    /// and flushes its TLB entry on all CPUs.
+    ///
+    /// This `MappedPages` must be mapped in the page table of the given `active_table_mapper`.
+    pub fn clear_dirty_page(&mut self, page_index: usize, active_table_mapper: &mut Mapper) -> Result<(), &'static str> {
+        if page_index >= self.size_in_pages() {
+            return Err("clear_dirty_page(): page index was out of bounds");
+        }
+        if active_table_mapper.target_p4 != self.page_table_p4 {
+            return Err("MappedPages must be mapped in the current page table to clear its dirty bits");
+        }
+        let page = *self.pages.start() + page_index;
+        let pte = active_table_mapper.p1_entry_mut(page)?;
+        let flags = pte.flags();
+        pte.set_flags(flags.dirty(false));
+        tlb_flush_virt_addr(page.start_address());
+        if let Some(func) = BROADCAST_TLB_SHOOTDOWN_FUNC.get() {
+            func(PageRange::new(page, page));
+        }
+        Ok(())
+    }
+
+    /// Returns the indices of the pages in this `MappedPages` that have been read from or written to
+    /// since the last call to this function, and clears the `ACCESSED` bit of those pages.
+    ///
+    /// Because Theseus sets the `ACCESSED` bit when mapping a page, the first call to this
+    /// will return every page in this `MappedPages`.
+    ///
+    /// This `MappedPages` must be mapped in the page table of the given `active_table_mapper`.
+    pub fn take_accessed_pages(&mut self, active_table_mapper: &mut Mapper) -> Result<Vec<usize>, &'static str> {
+        self.take_pte_flag(
+            active_table_mapper,
+            |flags| flags.is_accessed(),
+            |flags| flags.accessed(false),
+        )
+    }
+
+    /// Returns the indices of the pages whose PTE flags satisfy `is_set`,
+    /// replacing their flags with the result of `clear`.
+    ///
+    /// The TLB entries of changed pages are flushed on all CPUs,
+    /// such that the hardware will set the given flag again upon the next access.
+    fn take_pte_flag(
+        &mut self,
+        active_table_mapper: &mut Mapper,
+        is_set: impl Fn(PteFlagsArch) -> bool,
+        clear: impl Fn(PteFlagsArch) -> PteFlagsArch,
+    ) -> Result<Vec<usize>, &'static str> {
+        if active_table_mapper.target_p4 != self.page_table_p4 {
+            return Err("MappedPages must be mapped in the current page table to read its dirty or accessed bits");
+        }
+        let mut changed = Vec::new();
+        for (page_index, page) in self.pages.range().clone().into_iter().enumerate() {
+            let pte = active_table_mapper.p1_entry_mut(page)?;
+            let flags = pte.flags();
+            if is_set(flags) {
+                pte.set_flags(clear(flags));
+                tlb_flush_virt_addr(page.start_address());
+                changed.push(page_index);
+            }
+        }
+
+        if !changed.is_empty() {
+            if let Some(func) = BROADCAST_TLB_SHOOTDOWN_FUNC.get() {
+                func(self.pages.range().clone());
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Merges the page at `page_index` in this `MappedPages` with the page at `other_page_index` in `other`,
+    /// if both pages are read-only and have identical contents.
+    ///
+    /// Afterwards, both pages map the same frame, and the frame previously mapped by this page is freed.
+    /// The merged frame is copy-on-write: remapping either `MappedPages` as writable
+    /// gives each of its merged pages a private copy of that frame.
+    ///
+    /// Both `MappedPages` must be mapped in the page table of the given `active_table_mapper`.
+    ///
+    /// Returns `Ok(true)` if the pages were merged, or `Ok(false)` if they cannot be merged,
+    /// e.g., because their contents differ, either one is writable, or they already map the same frame.
+    pub fn merge_page(
+        &mut self,
+        page_index: usize,
+        other: &mut MappedPages,
+        other_page_index: usize,
+        active_table_mapper: &mut Mapper,
+    ) -> Result<bool, &'static str> {
+        if page_index >= self.size_in_pages() || other_page_index >= other.size_in_pages() {
+            return Err("merge_page(): page index was out of bounds");
+        }
+        if active_table_mapper.target_p4 != self.page_table_p4 || active_table_mapper.target_p4 != other.page_table_p4 {
+            return Err("merge_page(): both MappedPages must be mapped in the current page table");
+        }
+        // Only pages that are owned by an exclusive mapping and can never be written are merged.
+        if self.flags.is_writable() || other.flags.is_writable()
+            || !self.flags.is_exclusive() || !other.flags.is_exclusive()
+        {
+            return Ok(false);
+        }
+        {
+            let contents: &[u8] = self.as_slice(page_index * PAGE_SIZE, PAGE_SIZE)?;
+            let other_contents: &[u8] = other.as_slice(other_page_index * PAGE_SIZE, PAGE_SIZE)?;
+            if contents != other_contents {
+                return Ok(false);
+            }
+        }
+
+        let page = *self.pages.start() + page_index;
+        let other_page = *other.pages.start() + other_page_index;
+        let into_func = INTO_UNMAPPED_FRAMES_FUNC.get()
+            .ok_or("BUG: merge_page(): the `INTO_UNMAPPED_FRAMES_FUNC` callback was not initialized")?;
+        reserve_merge_scratch_page(active_table_mapper)?;
+
+        let frame = active_table_mapper.p1_entry_mut(page)?
+            .pointed_frame()
+            .ok_or("merge_page(): page not mapped")?;
+        let other_pte = active_table_mapper.p1_entry_mut(other_page)?;
+        let shared_frame = other_pte.pointed_frame().ok_or("merge_page(): page not mapped")?;
+        if frame == shared_frame {
+            return Ok(false);
+        }
+
+        // The other page's frame becomes the merged frame. If it's not merged already,
+        // the other page gives up its exclusive ownership of it to the registry of merged frames.
+        let new_merged_frames = other_pte.set_non_exclusive()
+            .map(|frames| into_func(frames.deref().clone()).into_allocated_frames());
+
+        let old_frames = page_merging::add_mapping(shared_frame, new_merged_frames, |merged_frames| {
+            let pte = active_table_mapper.p1_entry_mut(page)?;
+            let flags = pte.flags();
+            let old_frames = pte.set_non_exclusive();
+            pte.set_entry(merged_frames.as_allocated_frame(), flags.exclusive(false));
+            Ok(old_frames)
+        })?;
+
+        tlb_flush_virt_addr(page.start_address());
+        if let Some(func) = BROADCAST_TLB_SHOOTDOWN_FUNC.get() {
+            func(PageRange::new(page, page));
+        }
+
+        match old_frames {
+            // This page's own frame is dropped here, which frees it.
+            Some(old_frames) => drop(into_func(old_frames.deref().clone())),
+            // This page was already merged with a different frame.
+            None => page_merging::release(frame),
+        }
+        Ok(true)
+    }
     
+    /// Creates a copy-on-write duplicate of this `MappedPages` memory region,
+    /// which maps the same frames as this `MappedPages` at a new range of pages.
+    ///
+    /// Unlike [`MappedPages::deep_copy()`], no memory contents are copied up front.
+    /// Instead, the frames of this `MappedPages` are shared with the new mapping (see `page_merging`),
+    /// and all pages of both mappings are made read-only.
+    /// If this `MappedPages` is writable, both mappings remain logically writable
+    /// and their pages are marked copy-on-write: the first write to a page of either mapping
+    /// causes a page fault that gives that page a private copy of its frame,
+    /// see [`handle_copy_on_write_fault()`].
+    ///
+    /// This `MappedPages` must own its frames and be mapped in the page table of the given `active_table_mapper`.
+    #[track_caller]
+    pub fn duplicate_cow(&mut self, active_table_mapper: &mut Mapper) -> Result<MappedPages, &'static str> {
+        if active_table_mapper.target_p4 != self.page_table_p4 {
+            return Err("duplicate_cow(): MappedPages must be mapped in the current page table");
+        }
+        if !self.flags.is_exclusive() {
+            return Err("duplicate_cow(): MappedPages must own the frames it maps");
+        }
+        let into_func = INTO_UNMAPPED_FRAMES_FUNC.get()
+            .ok_or("BUG: duplicate_cow(): the `INTO_UNMAPPED_FRAMES_FUNC` callback was not initialized")?;
+        reserve_merge_scratch_page(active_table_mapper)?;
+
+        // Check every page up front, such that neither mapping is left partially modified upon error.
+        for page in self.pages.range().clone() {
+            if active_table_mapper.p1_entry_mut(page)?.pointed_frame().is_none() {
+                return Err("duplicate_cow(): page not mapped");
+            }
+        }
+
+        use crate::paging::allocate_pages;
+        let new_pages = allocate_pages(self.size_in_pages()).ok_or("duplicate_cow(): couldn't allocate pages")?;
+        let shared_flags = self.flags
+            .writable(false)
+            .copy_on_write(self.flags.is_writable())
+            .exclusive(false);
+        let higher_level_flags = self.flags.adjust_for_higher_level_pte();
+
+        for (page, new_page) in self.pages.range().clone().into_iter().zip(new_pages.range().clone()) {
+            let pte = active_table_mapper.p1_entry_mut(page)?;
+            let frame = pte.pointed_frame().ok_or("duplicate_cow(): page not mapped")?;
+            // If this page's frame isn't merged already, this page gives up its exclusive ownership of it
+            // to the registry of merged frames.
+            let new_merged_frames = pte.set_non_exclusive()
+                .map(|frames| into_func(frames.deref().clone()).into_allocated_frames());
+            pte.set_flags(shared_flags);
+            tlb_flush_virt_addr(page.start_address());
+
+            page_merging::add_mapping(frame, new_merged_frames, |merged_frames| {
+                let p3 = active_table_mapper.p4_mut().next_table_create(new_page.p4_index(), higher_level_flags);
+                let p2 = p3.next_table_create(new_page.p3_index(), higher_level_flags);
+                let p1 = p2.next_table_create(new_page.p2_index(), higher_level_flags);
+                p1[new_page.p1_index()].set_entry(merged_frames.as_allocated_frame(), shared_flags);
+                Ok(())
+            })?;
+        }
+
+        if let Some(func) = BROADCAST_TLB_SHOOTDOWN_FUNC.get() {
+            func(self.pages.range().clone());
+        }
+
+        let owner = mapping_owners::record_mapping(Location::caller(), new_pages.size_in_bytes());
+        Ok(MappedPages {
+            page_table_p4: self.page_table_p4,
+            pages: new_pages,
+            flags: self.flags,
+            owner,
+        })
+    }
+
     /// Consumes and unmaps this `MappedPages` object without auto-deallocating its `AllocatedPages` and `AllocatedFrames`,
     /// allowing the caller to continue using them directly, e.g., reusing them for a future mapping. 
     /// This removes the need to attempt to to reallocate those same pages or frames on a separate code path.
@@ -815,8 +1146,12 @@ impl MappedPages {
                         current_frame_range = Some(newly_unmapped_frames);
                     }
                 }
-                UnmapResult::NonExclusive(_frames) => {
-                    // trace!("Note: FYI: page {:X?} -> frames {:X?} was just unmapped but not mapped as EXCLUSIVE.", page, _frames);
+                UnmapResult::NonExclusive(frames) => {
+                    // trace!("Note: FYI: page {:X?} -> frames {:X?} was just unmapped but not mapped as EXCLUSIVE.", page, frames);
+                    // A page of a mapping that owns its frames only maps a frame non-exclusively if it's merged.
+                    if self.flags.is_exclusive() {
+                        page_merging::release(*frames.start());
+                    }
                 }
             }
         }
@@ -828,6 +1163,11 @@ impl MappedPages {
             }
         }
 
+        mapping_owners::record_unmapping(self.owner, self.pages.size_in_bytes());
+        if self.flags.is_device_memory() {
+            mmio_log::record_unmapping(self.pages.start_address(), self.pages.size_in_bytes());
+        }
+
         // Ensure that we return at least some frame range, even if we broke out of the above loop early.
         Ok(first_frame_range.map(|f| f.into_allocated_frames())
             .or(current_frame_range.map(|f| f.into_allocated_frames())))
@@ -1107,6 +1447,142 @@ impl MappedPages {
     }
 }
 
+/// Handles a page fault caused by writing to the given `vaddr`
+/// if it's within a copy-on-write page of the currently-active page table,
+/// see [`MappedPages::duplicate_cow()`].
+///
+/// If so, the page is given a private, writable copy of the frame it shared,
+/// such that the faulting write can be retried, and this returns `true`.
+/// If this page was the last one sharing that frame, it takes ownership of the frame instead of copying it.
+///
+/// Returns `false` if the page isn't copy-on-write, meaning that the fault must be handled otherwise.
+pub fn handle_copy_on_write_fault(vaddr: VirtualAddress) -> bool {
+    let page = Page::containing_address(vaddr);
+    let mut mapper = Mapper::from_current();
+    let flags = match mapper.p1_entry_mut(page) {
+        Ok(pte) => pte.flags(),
+        Err(_) => return false,
+    };
+    if !flags.is_valid() || !flags.is_copy_on_write() || flags.is_exclusive() {
+        return false;
+    }
+    let new_flags = flags
+        .writable(true)
+        .copy_on_write(false)
+        .exclusive(true);
+
+    match unshare_page(&mut mapper, page, new_flags) {
+        Ok(true) => { }
+        // Another CPU already gave this page its own frame, in which case the faulting write can be retried.
+        Ok(false) => return mapper.p1_entry_mut(page).map_or(false, |pte| pte.flags().is_writable()),
+        Err(e) => {
+            error!("handle_copy_on_write_fault(): couldn't copy page {:?}: {}", page, e);
+            return false;
+        }
+    }
+
+    if let Some(func) = BROADCAST_TLB_SHOOTDOWN_FUNC.get() {
+        func(PageRange::new(page, page));
+    }
+    true
+}
+
+/// Gives the given `page`, which maps a merged frame (see `page_merging`), its own private frame
+/// mapped with the given `new_flags`: that frame itself if no other page maps it anymore, or else a copy of it.
+///
+/// This is serialized with all other changes to the merged frame's mappings,
+/// such as a copy-on-write fault on another page that shares it.
+/// Returns `Ok(false)` if the page doesn't map a merged frame (anymore),
+/// e.g., because a fault on another CPU already gave it its own frame.
+fn unshare_page(mapper: &mut Mapper, page: Page, new_flags: PteFlagsArch) -> Result<bool, &'static str> {
+    let frame = mapper.p1_entry_mut(page)?.pointed_frame().ok_or("BUG: unshare_page(): merged page was not mapped")?;
+    let unshared = page_merging::unshare(frame, |last_frames| {
+        // Another CPU may have changed this page's mapping while we waited for the lock.
+        let pte = mapper.p1_entry_mut(page)?;
+        if pte.pointed_frame() != Some(frame) || pte.flags().is_exclusive() {
+            return Ok(false);
+        }
+        match last_frames {
+            Some(frames) => pte.set_entry(frames.as_allocated_frame(), new_flags),
+            None => copy_merged_page(mapper, page, new_flags)?,
+        }
+        tlb_flush_virt_addr(page.start_address());
+        Ok(true)
+    })?;
+    Ok(unshared == Some(true))
+}
+
+/// Maps the given `page`, which maps a merged frame (see `page_merging`),
+/// to its own private copy of that frame with the given `new_flags`.
+///
+/// This must only be invoked by [`unshare_page()`], which releases this page's reference to the merged frame.
+fn copy_merged_page(mapper: &mut Mapper, page: Page, new_flags: PteFlagsArch) -> Result<(), &'static str> {
+    let new_frames = crate::allocate_frames(1).ok_or("remap(): couldn't allocate a frame to copy a merged page")?;
+
+    // Copy the contents into the new frame via the scratch page, while this page still maps the merged frame,
+    // such that the new frame is only mapped by this page once it holds the same contents.
+    // We can't use the heap, because growing it may require the page table lock held by our caller,
+    // nor a page-sized stack buffer, because this may run on a small exception stack.
+    with_merge_scratch_page(mapper, &new_frames, |scratch_page| {
+        // SAFETY: both pages are mapped, and the scratch page is mapped writable to the new frame exclusively.
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                page.start_address().value() as *const u8,
+                scratch_page.start_address().value() as *mut u8,
+                PAGE_SIZE,
+            );
+        }
+    })?;
+
+    mapper.p1_entry_mut(page)?.set_entry(new_frames.as_allocated_frame(), new_flags);
+    // The page table entry now owns the new frame exclusively, so it will be freed when this page is unmapped.
+    mem::forget(new_frames);
+    Ok(())
+}
+
+/// A page reserved for temporarily mapping a new frame in order to copy a merged page into it.
+///
+/// Its page tables are created when it's reserved, such that mapping it later doesn't allocate anything.
+static MERGE_SCRATCH_PAGE: IrqSafeMutex<Option<AllocatedPages>> = IrqSafeMutex::new(None);
+
+/// Reserves the scratch page used to copy merged pages, if it isn't reserved already.
+///
+/// This must be invoked before any frame is shared by multiple pages.
+fn reserve_merge_scratch_page(mapper: &mut Mapper) -> Result<(), &'static str> {
+    if MERGE_SCRATCH_PAGE.lock().is_some() {
+        return Ok(());
+    }
+    let pages = crate::allocate_pages(1).ok_or("couldn't allocate a scratch page for copying merged pages")?;
+    // Mapping and then unmapping the page creates the page tables needed to map it again.
+    let mapped = mapper.map_allocated_pages(pages, PteFlags::new().valid(true).writable(true))?;
+    let (pages, _frames) = mapped.unmap_into_parts(mapper).map_err(|_| "couldn't unmap the scratch page for copying merged pages")?;
+    MERGE_SCRATCH_PAGE.lock().get_or_insert(pages);
+    Ok(())
+}
+
+/// Maps the given `frames` to the reserved scratch page, invokes `func` with that page, and unmaps it again.
+///
+/// The scratch page's lock ensures that only one copy uses it at a time.
+fn with_merge_scratch_page<R>(
+    mapper: &mut Mapper,
+    frames: &AllocatedFrames,
+    func: impl FnOnce(Page) -> R,
+) -> Result<R, &'static str> {
+    let scratch = MERGE_SCRATCH_PAGE.lock();
+    let page = *scratch.as_ref().ok_or("BUG: the scratch page for copying merged pages wasn't reserved")?.start();
+    mapper.p1_entry_mut(page)?.set_entry(
+        frames.as_allocated_frame(),
+        PteFlagsArch::from(PteFlags::new().valid(true).writable(true)),
+    );
+    tlb_flush_virt_addr(page.start_address());
+    let result = func(page);
+    // Only this CPU accessed the scratch page, so no other CPU's TLB needs to be flushed.
+    mapper.p1_entry_mut(page)?.zero();
+    tlb_flush_virt_addr(page.start_address());
+    Ok(result)
+}
+
+
 impl Drop for MappedPages {
     fn drop(&mut self) {
         // if self.size_in_pages() > 0 {
@@ -0,0 +1,133 @@
+//! Attribution of [`MappedPages`] to the crates that created them.
+//!
+//! Every `MappedPages` records the source location of the code that mapped it,
+//! obtained via `#[track_caller]` on the mapping functions.
+//! The number of bytes currently mapped from each such location is tracked
+//! in a fixed-size table of atomic counters, which avoids any heap allocation
+//! because the heap itself creates mappings when it grows.
+//! Locations are only aggregated into crates when [`mapped_bytes_by_crate()`] is invoked.
+//!
+//! [`MappedPages`]: crate::MappedPages
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{
+    panic::Location,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+/// The number of distinct mapping locations that can be tracked.
+const NUM_OWNER_SLOTS: usize = 512;
+
+/// The owner of a mapping that couldn't be attributed because the owner table was full.
+pub(crate) const UNATTRIBUTED: u16 = u16::MAX;
+/// The owner of a mapping that is excluded from the owner statistics,
+/// e.g., an empty mapping or one that has been [disowned](crate::MappedPages::disown).
+pub(crate) const DISOWNED: u16 = u16::MAX - 1;
+
+struct OwnerSlot {
+    location: AtomicPtr<Location<'static>>,
+    bytes: AtomicUsize,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SLOT: OwnerSlot = OwnerSlot {
+    location: AtomicPtr::new(ptr::null_mut()),
+    bytes: AtomicUsize::new(0),
+};
+
+static OWNER_SLOTS: [OwnerSlot; NUM_OWNER_SLOTS] = [EMPTY_SLOT; NUM_OWNER_SLOTS];
+static UNATTRIBUTED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Records that `bytes` were mapped by the code at the given `location`,
+/// and returns the owner ID that should be stored in the new mapping.
+pub(crate) fn record_mapping(location: &'static Location<'static>, bytes: usize) -> u16 {
+    let owner = find_or_claim_slot(location);
+    add_bytes(owner, bytes);
+    owner
+}
+
+/// Records that `bytes` of a mapping with the given `owner` were unmapped.
+pub(crate) fn record_unmapping(owner: u16, bytes: usize) {
+    match owner {
+        DISOWNED => { }
+        UNATTRIBUTED => { UNATTRIBUTED_BYTES.fetch_sub(bytes, Ordering::Relaxed); }
+        slot => { OWNER_SLOTS[slot as usize].bytes.fetch_sub(bytes, Ordering::Relaxed); }
+    }
+}
+
+/// Moves `bytes` from the mapping owner `from` to the mapping owner `to`.
+pub(crate) fn transfer(from: u16, to: u16, bytes: usize) {
+    if from != to {
+        record_unmapping(from, bytes);
+        add_bytes(to, bytes);
+    }
+}
+
+fn add_bytes(owner: u16, bytes: usize) {
+    match owner {
+        DISOWNED => { }
+        UNATTRIBUTED => { UNATTRIBUTED_BYTES.fetch_add(bytes, Ordering::Relaxed); }
+        slot => { OWNER_SLOTS[slot as usize].bytes.fetch_add(bytes, Ordering::Relaxed); }
+    }
+}
+
+/// Returns the index of the slot for the given `location`, claiming an empty slot if needed.
+fn find_or_claim_slot(location: &'static Location<'static>) -> u16 {
+    let location_ptr = location as *const Location<'static> as *mut Location<'static>;
+    let start = (location_ptr as usize >> 3) % NUM_OWNER_SLOTS;
+    for i in 0..NUM_OWNER_SLOTS {
+        let index = (start + i) % NUM_OWNER_SLOTS;
+        let slot = &OWNER_SLOTS[index];
+        let existing = slot.location.load(Ordering::Acquire);
+        if existing == location_ptr {
+            return index as u16;
+        }
+        if existing.is_null() {
+            match slot.location.compare_exchange(ptr::null_mut(), location_ptr, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return index as u16,
+                // Another CPU claimed this slot for the same location concurrently.
+                Err(other) if other == location_ptr => return index as u16,
+                Err(_) => continue,
+            }
+        }
+    }
+    UNATTRIBUTED
+}
+
+/// Returns the name of the crate containing the given source file,
+/// i.e., the directory that contains its `src` directory.
+pub(crate) fn crate_of_file(file: &'static str) -> &'static str {
+    match file.rfind("/src/") {
+        Some(src_index) => {
+            let crate_dir = &file[..src_index];
+            crate_dir.rsplit('/').next().unwrap_or(crate_dir)
+        }
+        None => file,
+    }
+}
+
+/// Returns the number of bytes currently mapped by each crate, sorted by crate name.
+///
+/// A crate's mappings are those created by code in that crate,
+/// not including mappings created on its behalf by helper functions in another crate
+/// that aren't annotated with `#[track_caller]`.
+pub fn mapped_bytes_by_crate() -> Vec<(&'static str, usize)> {
+    let mut by_crate: BTreeMap<&'static str, usize> = BTreeMap::new();
+    for slot in OWNER_SLOTS.iter() {
+        let location = slot.location.load(Ordering::Acquire);
+        if location.is_null() {
+            continue;
+        }
+        // SAFE: only `&'static Location` pointers are ever stored in a slot.
+        let file = unsafe { &*location }.file();
+        *by_crate.entry(crate_of_file(file)).or_insert(0) += slot.bytes.load(Ordering::Relaxed);
+    }
+    by_crate.into_iter().filter(|(_, bytes)| *bytes > 0).collect()
+}
+
+/// Returns the number of bytes currently mapped that couldn't be attributed to any crate
+/// because too many distinct locations had created mappings.
+pub fn unattributed_mapped_bytes() -> usize {
+    UNATTRIBUTED_BYTES.load(Ordering::Relaxed)
+}
@@ -0,0 +1,136 @@
+//! A log of every mapping of device memory, i.e., memory-mapped I/O (MMIO) regions.
+//!
+//! Each mapping whose flags include [`PteFlags::DEVICE_MEMORY`](crate::PteFlags::DEVICE_MEMORY)
+//! is recorded along with the source location of the code that mapped it,
+//! obtained via `#[track_caller]` just like the [mapping owners](super::mapping_owners).
+//! This makes it possible to enumerate where hardware-touching code lives at runtime,
+//! e.g., for verification research.
+//!
+//! The log is a fixed-size table, such that recording a mapping never allocates memory
+//! while the page table is locked. Once the table is full, the records of mappings that
+//! have since been unmapped are overwritten, oldest first; if all records are still mapped,
+//! new mappings are only counted in [`dropped_mmio_mappings()`].
+
+use core::{
+    panic::Location,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use spin::{Mutex, MutexGuard};
+use crate::{PhysicalAddress, VirtualAddress};
+
+/// The maximum number of MMIO mappings that can be recorded at once.
+pub const MAX_MMIO_MAPPINGS: usize = 256;
+
+/// A recorded mapping of an MMIO region.
+#[derive(Clone, Copy, Debug)]
+pub struct MmioMapping {
+    /// The source location of the code that created this mapping.
+    pub location: &'static Location<'static>,
+    /// The physical address of the start of the MMIO region.
+    pub phys_start: PhysicalAddress,
+    /// The virtual address at which the MMIO region was mapped.
+    pub virt_start: VirtualAddress,
+    pub size_in_bytes: usize,
+    /// Whether the region is still mapped, i.e., it hasn't been unmapped since it was recorded.
+    pub mapped: bool,
+    /// The order in which this mapping was recorded, starting from zero.
+    pub sequence: usize,
+}
+
+impl MmioMapping {
+    /// Returns the name of the crate whose code created this mapping.
+    pub fn crate_name(&self) -> &'static str {
+        super::mapping_owners::crate_of_file(self.location.file())
+    }
+}
+
+struct MmioLog {
+    records: [Option<MmioMapping>; MAX_MMIO_MAPPINGS],
+    next_sequence: usize,
+}
+
+static MMIO_LOG: Mutex<MmioLog> = Mutex::new(MmioLog {
+    records: [None; MAX_MMIO_MAPPINGS],
+    next_sequence: 0,
+});
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Records that the code at the given `location` mapped the given MMIO region.
+pub(crate) fn record_mapping(
+    location: &'static Location<'static>,
+    phys_start: PhysicalAddress,
+    virt_start: VirtualAddress,
+    size_in_bytes: usize,
+) {
+    if !ENABLED.load(Ordering::Relaxed) || size_in_bytes == 0 {
+        return;
+    }
+    let mut log = MMIO_LOG.lock();
+    let sequence = log.next_sequence;
+    // Use an empty slot, or else overwrite the oldest record of an unmapped region.
+    let slot = log.records.iter().position(Option::is_none).or_else(|| {
+        log.records.iter().enumerate()
+            .filter_map(|(i, r)| r.filter(|r| !r.mapped).map(|r| (i, r.sequence)))
+            .min_by_key(|(_, sequence)| *sequence)
+            .map(|(i, _)| i)
+    });
+    match slot {
+        Some(slot) => {
+            log.records[slot] = Some(MmioMapping { location, phys_start, virt_start, size_in_bytes, mapped: true, sequence });
+            log.next_sequence += 1;
+        }
+        None => { DROPPED.fetch_add(1, Ordering::Relaxed); }
+    }
+}
+
+/// Records that the MMIO mappings starting within the given virtual address range were unmapped.
+pub(crate) fn record_unmapping(start: VirtualAddress, size_in_bytes: usize) {
+    let mut log = MMIO_LOG.lock();
+    for record in log.records.iter_mut().flatten() {
+        if record.mapped && record.virt_start >= start && record.virt_start.value() < start.value() + size_in_bytes {
+            record.mapped = false;
+        }
+    }
+}
+
+/// Returns all recorded MMIO mappings, which are locked until the returned guard is dropped.
+///
+/// This includes mappings that have since been unmapped, unless their records were overwritten.
+///
+/// # Locking
+/// While the guard exists, any mapping or unmapping of MMIO regions blocks,
+/// so callers should copy out the records they need and drop the guard promptly.
+/// To avoid allocating memory while holding the guard, reserve space for
+/// [`MAX_MMIO_MAPPINGS`] records beforehand.
+pub fn mmio_mappings() -> MmioMappings {
+    MmioMappings(MMIO_LOG.lock())
+}
+
+/// A locked view of the recorded MMIO mappings, returned by [`mmio_mappings()`].
+pub struct MmioMappings(MutexGuard<'static, MmioLog>);
+
+impl MmioMappings {
+    /// Returns an iterator over the recorded MMIO mappings, in no particular order.
+    ///
+    /// Sort them by [`MmioMapping::sequence`] to obtain the order in which they were created.
+    pub fn iter(&self) -> impl Iterator<Item = &MmioMapping> + '_ {
+        self.0.records.iter().flatten()
+    }
+}
+
+/// Sets whether new MMIO mappings are recorded. Recording is enabled by default.
+pub fn set_mmio_logging(enable: bool) {
+    ENABLED.store(enable, Ordering::Relaxed);
+}
+
+/// Returns whether new MMIO mappings are recorded. See [`set_mmio_logging()`].
+pub fn mmio_logging() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Returns the number of MMIO mappings that couldn't be recorded because the log was full
+/// of regions that were still mapped.
+pub fn dropped_mmio_mappings() -> usize {
+    DROPPED.load(Ordering::Relaxed)
+}
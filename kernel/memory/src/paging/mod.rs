@@ -9,6 +9,9 @@
 
 mod temporary_page;
 mod mapper;
+mod mapping_owners;
+mod mmio_log;
+mod page_merging;
 mod table;
 
 pub use page_table_entry::PageTableEntry;
@@ -19,8 +22,11 @@ pub use self::{
     temporary_page::TemporaryPage,
     mapper::{
         Mapper, MappedPages, BorrowedMappedPages, BorrowedSliceMappedPages,
-        Mutability, Mutable, Immutable, translate,
+        Mutability, Mutable, Immutable, translate, handle_copy_on_write_fault,
     },
+    mapping_owners::{mapped_bytes_by_crate, unattributed_mapped_bytes},
+    mmio_log::{MmioMapping, MmioMappings, MAX_MMIO_MAPPINGS, mmio_mappings, set_mmio_logging, mmio_logging, dropped_mmio_mappings},
+    page_merging::{merged_page_stats, MergedPageStats},
 };
 
 use core::{
@@ -0,0 +1,127 @@
+//! Same-page merging: sharing one frame among multiple read-only pages with identical contents.
+//!
+//! Normally, each page of a [`MappedPages`] maps a frame that it owns exclusively.
+//! When a page is [merged](crate::MappedPages::merge_page) into another page with identical contents,
+//! the frame of the other page is moved into this module's registry of merged frames,
+//! both pages are mapped to that frame non-exclusively, and the merged page's own frame is freed.
+//! Each page that maps a merged frame holds a reference to it, which is released when that page is unmapped;
+//! the merged frame is freed once its last reference is released.
+//!
+//! Merged frames are copy-on-write: when a `MappedPages` is [remapped](crate::MappedPages::remap)
+//! as writable, each of its pages that maps a merged frame first receives its own private copy of that frame,
+//! or takes back ownership of that frame if no other pages map it anymore.
+//!
+//! The same registry backs [copy-on-write duplicates](crate::MappedPages::duplicate_cow) of a `MappedPages`,
+//! in which every page shares its frame with the corresponding page of the duplicate.
+//! Writing to such a page causes a page fault, upon which that page receives its own private copy of the frame,
+//! or takes back ownership of the frame if no other pages map it anymore;
+//! see [`handle_copy_on_write_fault()`](crate::handle_copy_on_write_fault).
+//!
+//! [`MappedPages`]: crate::MappedPages
+
+use alloc::collections::BTreeMap;
+use frame_allocator::AllocatedFrames;
+use sync_irq::IrqSafeMutex;
+use crate::Frame;
+
+/// A frame shared by multiple pages, along with the number of pages that currently map it.
+struct MergedFrame {
+    frames: AllocatedFrames,
+    mappings: usize,
+}
+
+static MERGED_FRAMES: IrqSafeMutex<BTreeMap<Frame, MergedFrame>> = IrqSafeMutex::new(BTreeMap::new());
+
+/// Statistics about merged frames.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MergedPageStats {
+    /// The number of frames that are shared by multiple pages.
+    pub merged_frames: usize,
+    /// The total number of pages that map those merged frames.
+    pub merged_pages: usize,
+}
+
+impl MergedPageStats {
+    /// Returns the number of bytes of physical memory saved by same-page merging.
+    pub fn bytes_saved(&self) -> usize {
+        (self.merged_pages - self.merged_frames) * crate::PAGE_SIZE
+    }
+}
+
+/// Returns statistics about the frames currently shared by merged pages.
+pub fn merged_page_stats() -> MergedPageStats {
+    let merged = MERGED_FRAMES.lock();
+    MergedPageStats {
+        merged_frames: merged.len(),
+        merged_pages: merged.values().map(|m| m.mappings).sum(),
+    }
+}
+
+/// Invokes `func` with the `AllocatedFrames` of the given merged frame,
+/// and adds one reference to it if `func` returns `Ok`.
+///
+/// If `frames` is given, it is first registered as a new merged frame with one reference,
+/// which belongs to the page that previously mapped those frames exclusively.
+/// Returns an error if the frame isn't a merged frame and no `frames` were given.
+pub(crate) fn add_mapping<R>(
+    frame: Frame,
+    frames: Option<AllocatedFrames>,
+    func: impl FnOnce(&AllocatedFrames) -> Result<R, &'static str>,
+) -> Result<R, &'static str> {
+    let mut merged = MERGED_FRAMES.lock();
+    if let Some(frames) = frames {
+        merged.insert(frame, MergedFrame { frames, mappings: 1 });
+    }
+    let merged_frame = merged.get_mut(&frame).ok_or("BUG: frame is not a merged frame")?;
+    let result = func(&merged_frame.frames)?;
+    merged_frame.mappings += 1;
+    Ok(result)
+}
+
+/// Gives a page that maps the given merged frame its own private frame,
+/// while holding the lock on the registry of merged frames.
+///
+/// This serializes copy-on-write faults on pages that share the same frame, e.g., on multiple CPUs,
+/// with each other and with all other changes to that frame's references.
+///
+/// If only one page maps the merged frame, `func` is given that frame, such that the page can own it exclusively.
+/// Otherwise, `func` is given `None` and must map the page to a private copy of the frame.
+/// `func` must return `true` once the page no longer maps the merged frame, upon which the page's reference
+/// to it is removed, or `false` if the page doesn't map it anymore already.
+///
+/// Returns `Ok(None)` without invoking `func` if the frame isn't a merged frame.
+pub(crate) fn unshare(
+    frame: Frame,
+    func: impl FnOnce(Option<&AllocatedFrames>) -> Result<bool, &'static str>,
+) -> Result<Option<bool>, &'static str> {
+    let mut merged = MERGED_FRAMES.lock();
+    let Some(merged_frame) = merged.get_mut(&frame) else {
+        return Ok(None);
+    };
+    let is_last = merged_frame.mappings == 1;
+    let unshared = func(is_last.then_some(&merged_frame.frames))?;
+    if unshared {
+        if is_last {
+            let merged_frame = merged.remove(&frame).expect("BUG: unshare(): merged frame was removed");
+            // The page table entry now owns the frame exclusively again, so it will be freed when the page is unmapped.
+            core::mem::forget(merged_frame.frames);
+        } else {
+            merged_frame.mappings -= 1;
+        }
+    }
+    Ok(Some(unshared))
+}
+
+/// Releases one reference to the given merged frame, freeing it if no pages map it anymore.
+///
+/// Does nothing if the frame isn't a merged frame.
+pub(crate) fn release(frame: Frame) {
+    let mut merged = MERGED_FRAMES.lock();
+    if let Some(merged_frame) = merged.get_mut(&frame) {
+        merged_frame.mappings = merged_frame.mappings.saturating_sub(1);
+        if merged_frame.mappings == 0 {
+            // The `AllocatedFrames` are dropped here, which frees the frame.
+            merged.remove(&frame);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Reclaims memory from caches and drivers when physical memory runs out.
+//!
+//! Subsystems that hold onto memory they can give back, such as caches or the virtio balloon,
+//! register a *shrinker* via [`register_shrinker()`].
+//! Once [`init()`] has been invoked, an allocation via `frame_allocator::allocate_frames()`
+//! that fails due to a lack of free frames invokes the shrinkers in the order they were registered,
+//! until enough frames have been freed, and then retries the allocation once.
+//!
+//! Shrinkers may be invoked from any task that allocates frames, so they must not block
+//! on locks that may be held while allocating; they should use `try_lock()` and give up instead.
+//!
+//! Subsystems can also report how much memory they currently hold via [`register_usage_reporter()`],
+//! which memory statistics tools can query through [`memory_usage()`].
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use log::{debug, warn};
+use spin::Mutex;
+
+/// A function that attempts to free up at least the given number of frames
+/// and returns the number of frames that it actually freed.
+pub type Shrinker = Box<dyn Fn(usize) -> usize + Send + Sync>;
+
+/// A function that returns the number of bytes of memory currently held by a subsystem.
+pub type UsageReporter = fn() -> usize;
+
+/// The registered shrinkers, in the order they are invoked.
+static SHRINKERS: Mutex<Vec<(&'static str, Arc<Shrinker>)>> = Mutex::new(Vec::new());
+
+/// The registered usage reporters, in the order they were registered.
+static USAGE_REPORTERS: Mutex<Vec<(&'static str, UsageReporter)>> = Mutex::new(Vec::new());
+
+/// Whether a reclaim operation is in progress, which prevents shrinkers that allocate frames
+/// from recursively triggering another reclaim operation.
+static RECLAIMING: AtomicBool = AtomicBool::new(false);
+
+/// The total number of frames freed by all reclaim operations.
+static TOTAL_RECLAIMED: AtomicUsize = AtomicUsize::new(0);
+
+/// Makes the frame allocator reclaim memory via the registered shrinkers
+/// before failing an allocation.
+pub fn init() -> Result<(), &'static str> {
+    frame_allocator::set_reclaim_function(reclaim)
+}
+
+/// Registers a shrinker with the given `name`, which is invoked when memory runs out.
+///
+/// Returns an error if a shrinker with that name is already registered.
+pub fn register_shrinker(name: &'static str, shrinker: Shrinker) -> Result<(), &'static str> {
+    let mut shrinkers = SHRINKERS.lock();
+    if shrinkers.iter().any(|(n, _)| *n == name) {
+        return Err("memory_pressure: a shrinker with that name is already registered");
+    }
+    shrinkers.push((name, Arc::new(shrinker)));
+    debug!("memory_pressure: registered shrinker {:?}", name);
+    Ok(())
+}
+
+/// Unregisters the shrinker with the given `name`.
+///
+/// Returns `true` if it was registered.
+pub fn unregister_shrinker(name: &str) -> bool {
+    let mut shrinkers = SHRINKERS.lock();
+    let len_before = shrinkers.len();
+    shrinkers.retain(|(n, _)| *n != name);
+    shrinkers.len() != len_before
+}
+
+/// Returns the names of the registered shrinkers, in the order they are invoked.
+pub fn shrinker_names() -> Vec<&'static str> {
+    SHRINKERS.lock().iter().map(|(name, _)| *name).collect()
+}
+
+/// Registers a function that reports the memory held by the subsystem with the given `name`.
+///
+/// Returns an error if a usage reporter with that name is already registered.
+pub fn register_usage_reporter(name: &'static str, reporter: UsageReporter) -> Result<(), &'static str> {
+    let mut reporters = USAGE_REPORTERS.lock();
+    if reporters.iter().any(|(n, _)| *n == name) {
+        return Err("memory_pressure: a usage reporter with that name is already registered");
+    }
+    reporters.push((name, reporter));
+    Ok(())
+}
+
+/// Returns the number of bytes of memory currently held by each subsystem
+/// that registered a usage reporter, in the order they were registered.
+pub fn memory_usage() -> Vec<(&'static str, usize)> {
+    let reporters = USAGE_REPORTERS.lock().clone();
+    reporters.into_iter().map(|(name, reporter)| (name, reporter())).collect()
+}
+
+/// Returns the total number of frames freed by shrinkers since boot.
+pub fn total_reclaimed() -> usize {
+    TOTAL_RECLAIMED.load(Ordering::Relaxed)
+}
+
+/// Invokes the registered shrinkers until at least `num_frames` frames have been freed
+/// or every shrinker has been invoked, and returns the number of frames that were freed.
+///
+/// If a reclaim operation is already in progress, e.g., because a shrinker allocated frames,
+/// this returns 0 immediately.
+pub fn reclaim(num_frames: usize) -> usize {
+    if RECLAIMING.swap(true, Ordering::Acquire) {
+        return 0;
+    }
+
+    // Invoke the shrinkers without holding the lock, as they may allocate or free memory.
+    let shrinkers: Vec<(&'static str, Arc<Shrinker>)> = match SHRINKERS.try_lock() {
+        Some(shrinkers) => shrinkers.clone(),
+        None => {
+            RECLAIMING.store(false, Ordering::Release);
+            return 0;
+        }
+    };
+
+    let mut reclaimed = 0;
+    for (name, shrinker) in shrinkers {
+        if reclaimed >= num_frames {
+            break;
+        }
+        let freed = shrinker(num_frames - reclaimed);
+        if freed > 0 {
+            debug!("memory_pressure: shrinker {:?} freed {} frames", name, freed);
+        }
+        reclaimed += freed;
+    }
+    if reclaimed < num_frames {
+        warn!("memory_pressure: only reclaimed {} of {} requested frames", reclaimed, num_frames);
+    }
+
+    TOTAL_RECLAIMED.fetch_add(reclaimed, Ordering::Relaxed);
+    RECLAIMING.store(false, Ordering::Release);
+    reclaimed
+}
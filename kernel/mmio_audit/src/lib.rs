@@ -0,0 +1,255 @@
+//! A queryable database of where hardware-touching code lives at runtime,
+//! supporting verification research on Theseus's unsafe code.
+//!
+//! The database combines two sources of information:
+//! * Every mapping of an MMIO region, as recorded by the [`memory`] crate's MMIO log,
+//!   which identifies the crate and source location that created each mapping.
+//! * Every *volatile access site*: a location (crate, section, and offset within that section)
+//!   of a call to or reference to a volatile accessor, e.g., `core::ptr::read_volatile()`,
+//!   the `volatile` crate's wrapper types, or the `port_io` crate.
+//!   These are found by scanning the dependencies of every loaded section.
+//!
+//! Volatile accesses that were fully inlined by the compiler leave no dependency behind,
+//! so they are only visible as the accessor sections that a crate contains, if any.
+//! Calls to accessors within the same crate are only visible if Theseus was built with
+//! the `internal_deps` cfg option, which records dependencies between sections of the same crate.
+//!
+//! The database can be restricted to a set of *zones*, i.e., crate name prefixes,
+//! via [`set_zones()`], in order to focus on a particular subsystem, e.g., a set of drivers.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::fmt;
+use memory::MmioMapping;
+use mod_mgmt::{CrateNamespace, StrRef};
+use spin::Mutex;
+
+/// The crate name prefixes that the audit is restricted to, or `None` for all crates.
+static ZONES: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+/// Restricts subsequently-collected databases to crates whose names start with one of the given prefixes,
+/// or removes that restriction if `zones` is `None`.
+pub fn set_zones(zones: Option<Vec<String>>) {
+    *ZONES.lock() = zones;
+}
+
+/// Returns the crate name prefixes that the audit is restricted to, if any. See [`set_zones()`].
+pub fn zones() -> Option<Vec<String>> {
+    ZONES.lock().clone()
+}
+
+/// Returns whether the crate with the given name is included in the given `zones`.
+fn in_zones(zones: &Option<Vec<String>>, crate_name: &str) -> bool {
+    zones.as_ref().map_or(true, |zones| zones.iter().any(|zone| crate_name.starts_with(zone.as_str())))
+}
+
+/// The kind of hardware access performed by a volatile accessor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AccessKind {
+    /// A volatile read, e.g., of an MMIO register.
+    VolatileRead,
+    /// A volatile write, e.g., of an MMIO register.
+    VolatileWrite,
+    /// Another volatile operation whose direction is unknown.
+    Volatile,
+    /// An x86 I/O port access.
+    PortIo,
+}
+
+impl AccessKind {
+    /// Returns the kind of access performed by the section with the given demangled name,
+    /// or `None` if it isn't a known volatile accessor.
+    pub fn of_accessor(section_name: &str) -> Option<AccessKind> {
+        let name = section_name.trim_start_matches('<');
+        if name.contains("read_volatile") || name.contains("volatile_load") {
+            Some(AccessKind::VolatileRead)
+        } else if name.contains("write_volatile") || name.contains("volatile_store") || name.contains("volatile_set_memory") {
+            Some(AccessKind::VolatileWrite)
+        } else if name.starts_with("volatile::") {
+            let mut segments = name.split("::");
+            if segments.clone().any(|s| s == "read") {
+                Some(AccessKind::VolatileRead)
+            } else if segments.any(|s| s == "write" || s == "update") {
+                Some(AccessKind::VolatileWrite)
+            } else {
+                Some(AccessKind::Volatile)
+            }
+        } else if name.starts_with("port_io::") {
+            Some(AccessKind::PortIo)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for AccessKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            AccessKind::VolatileRead  => "volatile read",
+            AccessKind::VolatileWrite => "volatile write",
+            AccessKind::Volatile      => "volatile",
+            AccessKind::PortIo        => "port I/O",
+        })
+    }
+}
+
+/// A location in a loaded section that refers to a volatile accessor.
+#[derive(Clone, Debug)]
+pub struct AccessSite {
+    /// The crate containing the accessing section.
+    pub crate_name: StrRef,
+    /// The section that performs the access.
+    pub section: StrRef,
+    /// The offset into `section` of the reference to the accessor, e.g., a call instruction's operand.
+    pub offset: usize,
+    /// The accessor section that is referred to.
+    pub accessor: StrRef,
+    pub kind: AccessKind,
+}
+
+impl fmt::Display for AccessSite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}+{:#X} -> {} ({})", self.crate_name, self.section, self.offset, self.accessor, self.kind)
+    }
+}
+
+/// A section that is itself a volatile accessor, e.g., a copy of `core::ptr::read_volatile::<u32>`
+/// that was instantiated in the crate that uses it.
+#[derive(Clone, Debug)]
+pub struct AccessorSection {
+    pub crate_name: StrRef,
+    pub section: StrRef,
+    pub kind: AccessKind,
+}
+
+/// A summary of the hardware-touching code in one crate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CrateSummary {
+    /// The number of recorded MMIO mappings created by this crate that are still mapped.
+    pub mmio_mappings: usize,
+    /// The total size of those MMIO mappings.
+    pub mmio_bytes: usize,
+    pub access_sites: usize,
+    pub accessor_sections: usize,
+}
+
+/// A snapshot of the MMIO mappings and volatile access sites in a namespace, within the current zones.
+#[derive(Clone, Debug, Default)]
+pub struct AuditDatabase {
+    /// All recorded MMIO mappings, in the order they were created, including ones that were unmapped.
+    pub mmio_mappings: Vec<MmioMapping>,
+    pub access_sites: Vec<AccessSite>,
+    pub accessor_sections: Vec<AccessorSection>,
+}
+
+impl AuditDatabase {
+    /// Collects the MMIO mappings and the volatile access sites of all crates
+    /// in the given `namespace` and its recursive namespaces, within the current zones.
+    ///
+    /// # Locking
+    /// This obtains the lock on every crate, so the caller must not hold any such locks.
+    pub fn collect(namespace: &CrateNamespace) -> AuditDatabase {
+        let zones = zones();
+        // Reserve space up front, such that nothing is allocated while the MMIO log is locked.
+        let mut mmio_mappings = Vec::with_capacity(memory::MAX_MMIO_MAPPINGS);
+        mmio_mappings.extend(memory::mmio_mappings().iter()
+            .filter(|m| in_zones(&zones, m.crate_name()))
+            .copied());
+        mmio_mappings.sort_unstable_by_key(|m| m.sequence);
+
+        let mut access_sites = Vec::new();
+        let mut accessor_sections = Vec::new();
+        // A crate shared by multiple namespaces is only scanned once.
+        let mut scanned: Vec<StrRef> = Vec::new();
+        namespace.for_each_crate(true, |crate_name, crate_ref| {
+            if !in_zones(&zones, crate_name) || scanned.iter().any(|c| c.as_str() == crate_name) {
+                return true;
+            }
+            let krate = crate_ref.lock_as_ref();
+            scanned.push(krate.crate_name.clone());
+            for sec in krate.sections.values() {
+                if let Some(kind) = AccessKind::of_accessor(&sec.name) {
+                    accessor_sections.push(AccessorSection {
+                        crate_name: krate.crate_name.clone(),
+                        section: sec.name.clone(),
+                        kind,
+                    });
+                }
+                let inner = sec.inner.read();
+                for dependency in &inner.sections_i_depend_on {
+                    if let Some(kind) = AccessKind::of_accessor(&dependency.section.name) {
+                        access_sites.push(AccessSite {
+                            crate_name: krate.crate_name.clone(),
+                            section: sec.name.clone(),
+                            offset: dependency.relocation.offset,
+                            accessor: dependency.section.name.clone(),
+                            kind,
+                        });
+                    }
+                }
+                #[cfg(internal_deps)]
+                for dependency in &inner.internal_dependencies {
+                    let Some(source_sec) = krate.sections.get(&dependency.source_sec_shndx) else { continue };
+                    if let Some(kind) = AccessKind::of_accessor(&source_sec.name) {
+                        access_sites.push(AccessSite {
+                            crate_name: krate.crate_name.clone(),
+                            section: sec.name.clone(),
+                            offset: dependency.relocation.offset,
+                            accessor: source_sec.name.clone(),
+                            kind,
+                        });
+                    }
+                }
+            }
+            true
+        });
+        access_sites.sort_by(|a, b| (&a.crate_name, &a.section, a.offset).cmp(&(&b.crate_name, &b.section, b.offset)));
+        accessor_sections.sort_by(|a, b| (&a.crate_name, &a.section).cmp(&(&b.crate_name, &b.section)));
+
+        AuditDatabase { mmio_mappings, access_sites, accessor_sections }
+    }
+
+    /// Returns the volatile access sites in the given crate.
+    pub fn access_sites_in_crate<'a>(&'a self, crate_name: &'a str) -> impl Iterator<Item = &'a AccessSite> + 'a {
+        self.access_sites.iter().filter(move |site| site.crate_name.as_str() == crate_name)
+    }
+
+    /// Returns the volatile access sites of the given kind.
+    pub fn access_sites_of_kind(&self, kind: AccessKind) -> impl Iterator<Item = &AccessSite> {
+        self.access_sites.iter().filter(move |site| site.kind == kind)
+    }
+
+    /// Returns the MMIO mappings created by the given crate.
+    pub fn mmio_mappings_by_crate<'a>(&'a self, crate_name: &'a str) -> impl Iterator<Item = &'a MmioMapping> + 'a {
+        self.mmio_mappings.iter().filter(move |m| m.crate_name() == crate_name)
+    }
+
+    /// Returns the MMIO mapping that contains the given physical address, if it is still mapped.
+    pub fn mmio_mapping_containing(&self, phys_addr: memory::PhysicalAddress) -> Option<&MmioMapping> {
+        self.mmio_mappings.iter().find(|m| m.mapped
+            && m.phys_start <= phys_addr
+            && phys_addr.value() < m.phys_start.value() + m.size_in_bytes
+        )
+    }
+
+    /// Returns a summary of each crate that has any MMIO mappings, access sites, or accessor sections,
+    /// sorted by crate name.
+    pub fn summary(&self) -> BTreeMap<String, CrateSummary> {
+        let mut summary: BTreeMap<String, CrateSummary> = BTreeMap::new();
+        for mapping in self.mmio_mappings.iter().filter(|m| m.mapped) {
+            let entry = summary.entry(String::from(mapping.crate_name())).or_default();
+            entry.mmio_mappings += 1;
+            entry.mmio_bytes += mapping.size_in_bytes;
+        }
+        for site in &self.access_sites {
+            summary.entry(String::from(site.crate_name.as_str())).or_default().access_sites += 1;
+        }
+        for accessor in &self.accessor_sections {
+            summary.entry(String::from(accessor.crate_name.as_str())).or_default().accessor_sections += 1;
+        }
+        summary
+    }
+}
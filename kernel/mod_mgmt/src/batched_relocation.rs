@@ -0,0 +1,127 @@
+//! Batching of relocation writes into already-loaded sections.
+//!
+//! Re-linking the dependents of a section, e.g., when swapping crates, requires writing
+//! relocations into sections whose `MappedPages` are usually not writable.
+//! Remapping those pages as writable and back again for every single relocation
+//! results in two TLB shootdowns per relocation, which adds up to thousands of them
+//! when swapping many crates.
+//!
+//! A [`RelocationBatch`] instead collects relocation writes, grouped by the `MappedPages` region
+//! that they target, and then applies them all at once: each region is remapped as writable
+//! at most once, all of its relocations are written, and then its original flags are restored.
+
+use alloc::{sync::Arc, vec::Vec};
+use spin::Mutex;
+use memory::{MappedPages, MmiRef, VirtualAddress};
+use crate::{RelocationEntry, StrongSectionRef, write_relocation};
+
+/// A set of pending relocation writes, grouped by the `MappedPages` region they target.
+///
+/// Writes are queued with [`add()`](Self::add) and performed by [`apply()`](Self::apply).
+/// Nothing is written into any section until the batch is applied.
+#[derive(Default)]
+pub struct RelocationBatch {
+    regions: Vec<PendingRegion>,
+    num_writes: usize,
+}
+
+/// The pending writes into a single `MappedPages` region.
+struct PendingRegion {
+    mapped_pages: Arc<Mutex<MappedPages>>,
+    writes: Vec<PendingWrite>,
+}
+
+/// A single pending relocation write, equivalent to the arguments of [`write_relocation()`].
+struct PendingWrite {
+    relocation_entry: RelocationEntry,
+    /// The offset of the target section within its `MappedPages`.
+    target_sec_offset: usize,
+    /// The size of the target section.
+    target_sec_size: usize,
+    source_sec_vaddr: VirtualAddress,
+}
+
+impl RelocationBatch {
+    /// Creates a new, empty batch.
+    pub fn new() -> RelocationBatch {
+        RelocationBatch::default()
+    }
+
+    /// Queues a write of the given `relocation_entry` into the `target_sec`,
+    /// such that it refers to the given source section address.
+    pub fn add(
+        &mut self,
+        target_sec: &StrongSectionRef,
+        relocation_entry: RelocationEntry,
+        source_sec_vaddr: VirtualAddress,
+    ) {
+        let write = PendingWrite {
+            relocation_entry,
+            target_sec_offset: target_sec.mapped_pages_offset,
+            target_sec_size: target_sec.size,
+            source_sec_vaddr,
+        };
+        match self.regions.iter_mut().find(|r| Arc::ptr_eq(&r.mapped_pages, &target_sec.mapped_pages)) {
+            Some(region) => region.writes.push(write),
+            None => self.regions.push(PendingRegion {
+                mapped_pages: Arc::clone(&target_sec.mapped_pages),
+                writes: vec![write],
+            }),
+        }
+        self.num_writes += 1;
+    }
+
+    /// Returns the number of queued relocation writes.
+    pub fn len(&self) -> usize {
+        self.num_writes
+    }
+
+    /// Returns `true` if no relocation writes are queued.
+    pub fn is_empty(&self) -> bool {
+        self.num_writes == 0
+    }
+
+    /// Returns the number of distinct `MappedPages` regions targeted by the queued writes,
+    /// which is the maximum number of times that [`apply()`](Self::apply) remaps a region as writable.
+    pub fn num_regions(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Performs all of the queued relocation writes.
+    ///
+    /// Each targeted region that isn't writable is temporarily remapped as writable once,
+    /// and its original flags are restored after all of its writes have been performed,
+    /// even if one of them failed.
+    ///
+    /// If a write fails, the remaining regions are not written to and an error is returned.
+    ///
+    /// # Locking
+    /// This obtains the lock on each targeted `MappedPages` region in turn,
+    /// so the caller must not hold any of those locks.
+    pub fn apply(self, kernel_mmi_ref: &MmiRef, verbose_log: bool) -> Result<(), &'static str> {
+        if verbose_log {
+            debug!("RelocationBatch::apply(): writing {} relocations into {} regions", self.num_writes, self.regions.len());
+        }
+        for region in self.regions {
+            let mut mapped_pages = region.mapped_pages.lock();
+            let initial_flags = mapped_pages.flags();
+            if !initial_flags.is_writable() {
+                mapped_pages.remap(&mut kernel_mmi_ref.lock().page_table, initial_flags.writable(true))?;
+            }
+
+            let result = region.writes.iter().try_for_each(|write| write_relocation(
+                write.relocation_entry,
+                mapped_pages.as_slice_mut(0, write.target_sec_offset + write.target_sec_size)?,
+                write.target_sec_offset,
+                write.source_sec_vaddr,
+                verbose_log,
+            ));
+
+            if !initial_flags.is_writable() {
+                mapped_pages.remap(&mut kernel_mmi_ref.lock().page_table, initial_flags)?;
+            }
+            result?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,313 @@
+//! A two-phase, non-blocking variant of [`CrateNamespace::get_symbol_or_load()`].
+//!
+//! Resolving a symbol that isn't yet loaded may require finding, loading, and relocating
+//! one or more crates, which can block for a long time on file I/O and relocation.
+//! Latency-sensitive callers can instead use [`CrateNamespace::get_symbol_or_enqueue_load()`],
+//! which returns immediately: either with the already-loaded symbol,
+//! or with a [`PendingSymbolLoad`] that completes once a loader task has loaded it.
+//!
+//! The loader side of this is a single task (see the `symbol_loader` crate)
+//! that awaits [`next_symbol_load_request()`] and then calls [`SymbolLoadRequest::process()`].
+//! If no such loader task exists, pending requests can be handled synchronously
+//! by calling [`process_pending_symbol_loads()`].
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::{future::Future, pin::Pin, sync::atomic::{AtomicU64, Ordering}, task::{Context, Poll, Waker}};
+use spin::Mutex;
+use memory::MmiRef;
+use crate::{CrateNamespace, WeakSectionRef, empty_weak_section_ref, get_containing_crate_name};
+
+/// All crate loads that have been requested but not yet finished, in the order they were requested.
+static CRATE_LOADS: Mutex<Vec<CrateLoad>> = Mutex::new(Vec::new());
+/// The waker for the loader task that is waiting for a new request, if any.
+static LOADER_WAKER: Mutex<Option<Waker>> = Mutex::new(None);
+/// The ID given to the next [`PendingSymbolLoad`], used to track each one's waker.
+static NEXT_PENDING_LOAD_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The result of [`CrateNamespace::get_symbol_or_enqueue_load()`].
+pub enum SymbolResolution {
+    /// The symbol was already loaded.
+    Resolved(WeakSectionRef),
+    /// The symbol was not yet loaded, so a request to load it has been enqueued.
+    Pending(PendingSymbolLoad),
+}
+
+/// A requested load of the crate that contains one or more symbols,
+/// along with the symbols that are waiting for it.
+///
+/// All symbols from the same crate share a single load, even if they are requested
+/// while that crate is already being loaded.
+struct CrateLoad {
+    namespace: Arc<CrateNamespace>,
+    /// The name of the crate that most likely contains the waiting symbols,
+    /// or the requested symbol itself if its crate can't be determined (e.g., a `no_mangle` symbol).
+    crate_name: String,
+    temp_backup_namespace: Option<Arc<CrateNamespace>>,
+    /// Whether a loader has already picked up this load.
+    in_flight: bool,
+    /// The symbols that have yet to be resolved, and the completion shared by everyone waiting on each.
+    waiting: Vec<(String, Arc<Completion>)>,
+}
+
+/// The shared completion state between a [`SymbolLoadRequest`] and its [`PendingSymbolLoad`]s.
+struct Completion {
+    result: Mutex<Option<WeakSectionRef>>,
+    /// The waker of each pending load that has been polled, keyed by the ID of that pending load.
+    wakers: Mutex<Vec<(u64, Waker)>>,
+}
+
+impl Completion {
+    fn new() -> Completion {
+        Completion { result: Mutex::new(None), wakers: Mutex::new(Vec::new()) }
+    }
+
+    fn complete(&self, result: WeakSectionRef) {
+        *self.result.lock() = Some(result);
+        let wakers = core::mem::take(&mut *self.wakers.lock());
+        for (_id, waker) in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// A handle to a symbol that is being loaded in the background.
+///
+/// This can be `await`ed, or polled without blocking via [`PendingSymbolLoad::try_take()`].
+/// The output is the same as that of [`CrateNamespace::get_symbol_or_load()`]:
+/// an empty `WeakSectionRef` if the symbol could not be found or loaded.
+pub struct PendingSymbolLoad {
+    /// A unique ID for this handle, such that it only ever has one waker registered at a time.
+    id: u64,
+    symbol: String,
+    completion: Arc<Completion>,
+}
+
+impl PendingSymbolLoad {
+    fn new(symbol: String, completion: Arc<Completion>) -> PendingSymbolLoad {
+        PendingSymbolLoad {
+            id: NEXT_PENDING_LOAD_ID.fetch_add(1, Ordering::Relaxed),
+            symbol,
+            completion,
+        }
+    }
+
+    /// Returns the fully-qualified demangled symbol that is being loaded.
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// Returns `true` if loading has finished, regardless of whether it succeeded.
+    pub fn is_complete(&self) -> bool {
+        self.completion.result.lock().is_some()
+    }
+
+    /// Returns the loaded symbol's section if loading has finished, otherwise `None`.
+    pub fn try_take(&self) -> Option<WeakSectionRef> {
+        self.completion.result.lock().clone()
+    }
+}
+
+impl Clone for PendingSymbolLoad {
+    fn clone(&self) -> Self {
+        PendingSymbolLoad::new(self.symbol.clone(), Arc::clone(&self.completion))
+    }
+}
+
+impl Drop for PendingSymbolLoad {
+    fn drop(&mut self) {
+        self.completion.wakers.lock().retain(|(id, _)| *id != self.id);
+    }
+}
+
+impl Future for PendingSymbolLoad {
+    type Output = WeakSectionRef;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        // Register the waker before checking the result to avoid missing a concurrent completion.
+        // Each handle only keeps its most recent waker, so repeated polls don't accumulate wakers.
+        {
+            let mut wakers = self.completion.wakers.lock();
+            match wakers.iter_mut().find(|(id, _)| *id == self.id) {
+                Some((_, waker)) => {
+                    if !waker.will_wake(context.waker()) {
+                        *waker = context.waker().clone();
+                    }
+                }
+                None => wakers.push((self.id, context.waker().clone())),
+            }
+        }
+        match self.try_take() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// A request to load a crate into a given namespace
+/// in order to resolve the symbols that are waiting for it.
+pub struct SymbolLoadRequest {
+    namespace: Arc<CrateNamespace>,
+    crate_name: String,
+    temp_backup_namespace: Option<Arc<CrateNamespace>>,
+}
+
+impl SymbolLoadRequest {
+    /// Returns the name of the crate that this request will load,
+    /// or the requested symbol itself if the crate containing it can't be determined.
+    pub fn crate_name(&self) -> &str {
+        &self.crate_name
+    }
+
+    /// Returns the namespace that the crate should be loaded into.
+    pub fn namespace(&self) -> &Arc<CrateNamespace> {
+        &self.namespace
+    }
+
+    /// Performs this request by invoking [`CrateNamespace::get_symbol_or_load()`] for each waiting symbol,
+    /// and then notifies everyone waiting on the corresponding [`PendingSymbolLoad`]s.
+    ///
+    /// Symbols from the same crate that are requested while this is in progress are resolved as well.
+    pub fn process(self, kernel_mmi_ref: &MmiRef, verbose_log: bool) {
+        loop {
+            let waiting = {
+                let mut crate_loads = CRATE_LOADS.lock();
+                let Some(index) = crate_loads.iter().position(|load| load.is(&self.namespace, &self.crate_name)) else {
+                    return;
+                };
+                if crate_loads[index].waiting.is_empty() {
+                    crate_loads.remove(index);
+                    return;
+                }
+                core::mem::take(&mut crate_loads[index].waiting)
+            };
+            // Don't hold the lock while loading, since loading can take a long time.
+            for (symbol, completion) in waiting {
+                let result = self.namespace.get_symbol_or_load(
+                    &symbol,
+                    self.temp_backup_namespace.as_deref(),
+                    kernel_mmi_ref,
+                    verbose_log,
+                );
+                if result.upgrade().is_none() {
+                    warn!("Deferred load of symbol {:?} into namespace {:?} failed", symbol, self.namespace.name());
+                }
+                completion.complete(result);
+            }
+        }
+    }
+}
+
+impl CrateLoad {
+    fn is(&self, namespace: &Arc<CrateNamespace>, crate_name: &str) -> bool {
+        Arc::ptr_eq(&self.namespace, namespace) && self.crate_name == crate_name
+    }
+}
+
+impl CrateNamespace {
+    /// A non-blocking version of [`get_symbol_or_load()`](#method.get_symbol_or_load).
+    ///
+    /// If the given symbol is already loaded in this namespace or its recursive namespaces,
+    /// this returns [`SymbolResolution::Resolved`] right away.
+    /// Otherwise, a request to load that symbol is enqueued for the loader task
+    /// and a [`SymbolResolution::Pending`] handle is returned,
+    /// which completes once the loader task has finished loading the symbol.
+    ///
+    /// Multiple requests for symbols from the same crate in the same namespace share a single load,
+    /// including requests made while that crate is already being loaded.
+    pub fn get_symbol_or_enqueue_load(
+        namespace: &Arc<CrateNamespace>,
+        demangled_full_symbol: &str,
+        temp_backup_namespace: Option<&Arc<CrateNamespace>>,
+    ) -> SymbolResolution {
+        // A forbidden symbol is never resolved, just like by `get_symbol_or_load()`,
+        // which the loader task uses for symbols that aren't loaded yet.
+        if let Some(weak_sec) = namespace.get_symbol_internal(demangled_full_symbol) {
+            let permitted = namespace.is_symbol_permitted(demangled_full_symbol);
+            return SymbolResolution::Resolved(if permitted { weak_sec } else { empty_weak_section_ref() });
+        }
+
+        let crate_name = get_containing_crate_name(demangled_full_symbol)
+            .first()
+            .copied()
+            .unwrap_or(demangled_full_symbol);
+        let completion = {
+            let mut crate_loads = CRATE_LOADS.lock();
+            let index = match crate_loads.iter().position(|load| load.is(namespace, crate_name)) {
+                Some(index) => index,
+                None => {
+                    crate_loads.push(CrateLoad {
+                        namespace: Arc::clone(namespace),
+                        crate_name: String::from(crate_name),
+                        temp_backup_namespace: temp_backup_namespace.cloned(),
+                        in_flight: false,
+                        waiting: Vec::new(),
+                    });
+                    crate_loads.len() - 1
+                }
+            };
+            let waiting = &mut crate_loads[index].waiting;
+            match waiting.iter().find(|(symbol, _)| symbol == demangled_full_symbol) {
+                Some((_, completion)) => Arc::clone(completion),
+                None => {
+                    let completion = Arc::new(Completion::new());
+                    waiting.push((String::from(demangled_full_symbol), Arc::clone(&completion)));
+                    completion
+                }
+            }
+        };
+
+        if let Some(loader_waker) = LOADER_WAKER.lock().take() {
+            loader_waker.wake();
+        }
+        SymbolResolution::Pending(PendingSymbolLoad::new(String::from(demangled_full_symbol), completion))
+    }
+}
+
+/// Returns a future that completes with the next enqueued [`SymbolLoadRequest`].
+///
+/// This is intended to be awaited by a single loader task.
+pub fn next_symbol_load_request() -> NextSymbolLoadRequest {
+    NextSymbolLoadRequest { _private: () }
+}
+
+/// The future returned by [`next_symbol_load_request()`].
+pub struct NextSymbolLoadRequest {
+    _private: (),
+}
+
+impl Future for NextSymbolLoadRequest {
+    type Output = SymbolLoadRequest;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        // Register the waker before checking the queue to avoid missing a concurrent request.
+        *LOADER_WAKER.lock() = Some(context.waker().clone());
+        match take_next_request() {
+            Some(request) => Poll::Ready(request),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Marks the oldest crate load that no loader has picked up yet as in flight,
+/// and returns a request to perform it.
+fn take_next_request() -> Option<SymbolLoadRequest> {
+    let mut crate_loads = CRATE_LOADS.lock();
+    let load = crate_loads.iter_mut().find(|load| !load.in_flight)?;
+    load.in_flight = true;
+    Some(SymbolLoadRequest {
+        namespace: Arc::clone(&load.namespace),
+        crate_name: load.crate_name.clone(),
+        temp_backup_namespace: load.temp_backup_namespace.clone(),
+    })
+}
+
+/// Synchronously processes all currently-enqueued symbol load requests
+/// on the current task, and returns the number of requests processed.
+pub fn process_pending_symbol_loads(kernel_mmi_ref: &MmiRef, verbose_log: bool) -> usize {
+    let mut count = 0;
+    while let Some(request) = take_next_request() {
+        request.process(kernel_mmi_ref, verbose_log);
+        count += 1;
+    }
+    count
+}
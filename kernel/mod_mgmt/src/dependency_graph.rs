@@ -0,0 +1,182 @@
+//! Queries over the dependency graph of loaded crates.
+//!
+//! Dependencies are tracked per section: if section `A` depends on section `B`,
+//! then `A`'s [`sections_i_depend_on`] holds a strong reference to `B`,
+//! and `B`'s [`sections_dependent_on_me`] holds a weak reference to `A`.
+//! The functions in this module aggregate those section-level dependencies
+//! into dependencies between crates, e.g., so that a shell tool can show
+//! which crates (and which of their sections) prevent a crate from being unloaded.
+//!
+//! Dependencies between sections in the same crate are not included.
+//!
+//! # Locking
+//! These functions obtain the lock on the given crate and on the crates it is connected to,
+//! one at a time, so the caller must not hold the lock on any crate.
+//!
+//! [`sections_i_depend_on`]: crate::LoadedSectionInner::sections_i_depend_on
+//! [`sections_dependent_on_me`]: crate::LoadedSectionInner::sections_dependent_on_me
+
+use alloc::{collections::VecDeque, vec::Vec};
+use cow_arc::CowArc;
+use crate::{StrRef, StrongCrateRef, WeakCrateRef};
+
+/// A dependency of one section on a section in another crate.
+#[derive(Clone, Debug)]
+pub struct SectionDependency {
+    /// The name of the section that depends on `dependency`.
+    pub dependent: StrRef,
+    /// The name of the section that `dependent` depends on.
+    pub dependency: StrRef,
+}
+
+/// A direct dependency between the queried crate and another crate,
+/// along with the section dependencies that it consists of.
+#[derive(Clone, Debug)]
+pub struct CrateDependency {
+    /// The name of the other crate.
+    pub crate_name: StrRef,
+    pub crate_ref: WeakCrateRef,
+    pub sections: Vec<SectionDependency>,
+}
+
+/// A crate that is reachable from the queried crate by following dependencies in one direction.
+#[derive(Clone, Debug)]
+pub struct TransitiveDependency {
+    pub crate_name: StrRef,
+    pub crate_ref: WeakCrateRef,
+    /// The number of dependency hops from the queried crate; direct dependencies have a depth of 1.
+    pub depth: usize,
+}
+
+/// What would break if a crate were unloaded; see [`would_unload_break()`].
+#[derive(Clone, Debug)]
+pub struct UnloadImpact {
+    pub crate_name: StrRef,
+    /// Whether the crate is shared with another namespace, in which case it can't be unloaded.
+    pub shared: bool,
+    /// The crates that directly depend on the crate, which prevent it from being unloaded.
+    pub dependents: Vec<CrateDependency>,
+    /// All crates that directly or indirectly depend on the crate.
+    pub transitive_dependents: Vec<TransitiveDependency>,
+}
+
+impl UnloadImpact {
+    /// Returns `true` if unloading the crate would break other crates or namespaces,
+    /// which means that [`CrateNamespace::unload_crate()`](crate::CrateNamespace::unload_crate) would refuse to unload it.
+    pub fn would_break(&self) -> bool {
+        self.shared || !self.dependents.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    /// Follow `sections_dependent_on_me`.
+    Dependents,
+    /// Follow `sections_i_depend_on`.
+    Dependencies,
+}
+
+/// Returns the crates that directly depend on the given crate, in order of crate name.
+pub fn crates_dependent_on(krate: &StrongCrateRef) -> Vec<CrateDependency> {
+    direct_dependencies(krate, Direction::Dependents)
+}
+
+/// Returns the crates that the given crate directly depends on, in order of crate name.
+pub fn crates_depended_on_by(krate: &StrongCrateRef) -> Vec<CrateDependency> {
+    direct_dependencies(krate, Direction::Dependencies)
+}
+
+/// Returns all crates that the given crate directly or indirectly depends on,
+/// in breadth-first order, i.e., in order of increasing depth.
+pub fn transitive_dependencies(krate: &StrongCrateRef) -> Vec<TransitiveDependency> {
+    transitive(krate, Direction::Dependencies)
+}
+
+/// Returns all crates that directly or indirectly depend on the given crate,
+/// in breadth-first order, i.e., in order of increasing depth.
+pub fn transitive_dependents(krate: &StrongCrateRef) -> Vec<TransitiveDependency> {
+    transitive(krate, Direction::Dependents)
+}
+
+/// Determines whether unloading the given crate would break other crates,
+/// and if so, which crates and sections are responsible.
+///
+/// The given crate reference must be a shallow one, e.g., as returned by
+/// [`CrateNamespace::get_crate()`](crate::CrateNamespace::get_crate); otherwise the crate always appears to be shared.
+///
+/// This performs the same checks as [`CrateNamespace::unload_crate()`](crate::CrateNamespace::unload_crate),
+/// but doesn't modify anything.
+pub fn would_unload_break(krate: &StrongCrateRef) -> UnloadImpact {
+    let crate_name = krate.lock_as_ref().crate_name.clone();
+    UnloadImpact {
+        crate_name,
+        shared: krate.is_shared(),
+        dependents: crates_dependent_on(krate),
+        transitive_dependents: transitive_dependents(krate),
+    }
+}
+
+fn direct_dependencies(krate: &StrongCrateRef, direction: Direction) -> Vec<CrateDependency> {
+    // Collect the other crates while holding only this crate's lock, then lock each other crate afterwards.
+    let mut edges: Vec<(StrongCrateRef, Vec<SectionDependency>)> = Vec::new();
+    let mut add_edge = |other: StrongCrateRef, dependency: SectionDependency| {
+        if CowArc::inner_ptr_eq(&other, krate) {
+            return;
+        }
+        match edges.iter_mut().find(|(c, _)| CowArc::inner_ptr_eq(c, &other)) {
+            Some((_, sections)) => sections.push(dependency),
+            None => edges.push((other, vec![dependency])),
+        }
+    };
+    {
+        let locked = krate.lock_as_ref();
+        for sec in locked.sections.values() {
+            let inner = sec.inner.read();
+            match direction {
+                Direction::Dependents => for weak_dep in &inner.sections_dependent_on_me {
+                    let Some(dependent) = weak_dep.section.upgrade() else { continue };
+                    let Some(other) = dependent.parent_crate.upgrade() else { continue };
+                    add_edge(other, SectionDependency { dependent: dependent.name.clone(), dependency: sec.name.clone() });
+                },
+                Direction::Dependencies => for strong_dep in &inner.sections_i_depend_on {
+                    let Some(other) = strong_dep.section.parent_crate.upgrade() else { continue };
+                    add_edge(other, SectionDependency { dependent: sec.name.clone(), dependency: strong_dep.section.name.clone() });
+                },
+            }
+        }
+    }
+
+    let mut result: Vec<CrateDependency> = edges.into_iter()
+        .map(|(other, sections)| CrateDependency {
+            crate_name: other.lock_as_ref().crate_name.clone(),
+            crate_ref: CowArc::downgrade(&other),
+            sections,
+        })
+        .collect();
+    result.sort_by(|a, b| a.crate_name.cmp(&b.crate_name));
+    result
+}
+
+fn transitive(krate: &StrongCrateRef, direction: Direction) -> Vec<TransitiveDependency> {
+    let mut visited: Vec<StrongCrateRef> = vec![krate.clone_shallow()];
+    let mut result: Vec<TransitiveDependency> = Vec::new();
+    let mut queue: VecDeque<(StrongCrateRef, usize)> = VecDeque::new();
+    queue.push_back((krate.clone_shallow(), 0));
+
+    while let Some((current, depth)) = queue.pop_front() {
+        for edge in direct_dependencies(&current, direction) {
+            let Some(other) = edge.crate_ref.upgrade() else { continue };
+            if visited.iter().any(|v| CowArc::inner_ptr_eq(v, &other)) {
+                continue;
+            }
+            result.push(TransitiveDependency {
+                crate_name: edge.crate_name,
+                crate_ref: edge.crate_ref,
+                depth: depth + 1,
+            });
+            visited.push(other.clone_shallow());
+            queue.push_back((other, depth + 1));
+        }
+    }
+    result
+}
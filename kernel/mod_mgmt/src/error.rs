@@ -0,0 +1,117 @@
+//! The error type returned when loading, linking, or swapping crates fails.
+
+use core::fmt;
+use alloc::string::String;
+use crate::StrRef;
+
+/// An error that occurred while loading, relocating, or swapping a crate.
+///
+/// Unlike a plain `&'static str`, this preserves details about the failure,
+/// such as which crate, section, or symbol caused it,
+/// which allows callers (e.g., shells or fault recovery supervisors)
+/// to programmatically determine how to handle or report the error.
+///
+/// For compatibility with the rest of the kernel, a `LoadError` can be converted
+/// into a `&'static str` (which loses those details), and vice versa.
+#[derive(Debug, Clone)]
+pub enum LoadError {
+    /// The crate object file could not be parsed as a valid relocatable ELF file.
+    ElfParse {
+        crate_name: StrRef,
+        reason: &'static str,
+    },
+    /// A crate with the same name has already been loaded into the namespace.
+    AlreadyLoaded {
+        crate_name: StrRef,
+    },
+    /// A crate object file could not be found.
+    CrateNotFound {
+        name: String,
+    },
+    /// A symbol needed to relocate a crate could not be found,
+    /// nor could a crate containing that symbol be loaded.
+    MissingSymbol {
+        crate_name: StrRef,
+        symbol: String,
+    },
+    /// Allocating, mapping, or remapping the memory for a crate's sections failed.
+    Mapping {
+        crate_name: StrRef,
+        reason: &'static str,
+    },
+    /// A relocation entry could not be applied to its target section.
+    Relocation {
+        section: StrRef,
+        reason: &'static str,
+    },
+    /// A global symbol of the crate being loaded is already defined by another crate in the namespace,
+    /// which is only an error if the namespace is in strict symbol conflict mode.
+    SymbolConflict {
+        symbol: StrRef,
+        existing_crate: StrRef,
+        new_crate: StrRef,
+    },
+    /// A crate depends on a symbol that its namespace's [`SymbolPolicy`](crate::SymbolPolicy) forbids it from linking against.
+    ForbiddenSymbol {
+        crate_name: StrRef,
+        symbol: String,
+        namespace: String,
+    },
+    /// Any other error.
+    Other(&'static str),
+}
+
+impl LoadError {
+    /// Returns a static description of this error, without any details
+    /// about the specific crate, section, or symbol involved.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ElfParse { reason, .. }   => reason,
+            Self::AlreadyLoaded { .. }      => "the crate has already been loaded, cannot load it again in the same namespace",
+            Self::CrateNotFound { .. }      => "couldn't find crate object file",
+            Self::MissingSymbol { .. }      => "Couldn't get symbol for foreign relocation entry, nor load its containing crate",
+            Self::Mapping { reason, .. }    => reason,
+            Self::Relocation { reason, .. } => reason,
+            Self::SymbolConflict { .. }     => "a global symbol is already defined by another crate in the namespace",
+            Self::ForbiddenSymbol { .. }    => "the crate depends on a symbol that its namespace's symbol policy forbids",
+            Self::Other(reason)             => reason,
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ElfParse { crate_name, reason } =>
+                write!(f, "failed to parse ELF file for crate {crate_name:?}: {reason}"),
+            Self::AlreadyLoaded { crate_name } =>
+                write!(f, "crate {crate_name:?} has already been loaded into this namespace"),
+            Self::CrateNotFound { name } =>
+                write!(f, "couldn't find crate object file {name:?}"),
+            Self::MissingSymbol { crate_name, symbol } =>
+                write!(f, "crate {crate_name:?} depends on missing symbol {symbol:?}"),
+            Self::Mapping { crate_name, reason } =>
+                write!(f, "failed to map memory for crate {crate_name:?}: {reason}"),
+            Self::Relocation { section, reason } =>
+                write!(f, "failed to relocate section {section:?}: {reason}"),
+            Self::SymbolConflict { symbol, existing_crate, new_crate } =>
+                write!(f, "symbol {symbol:?} in crate {new_crate:?} is already defined by crate {existing_crate:?}"),
+            Self::ForbiddenSymbol { crate_name, symbol, namespace } =>
+                write!(f, "crate {crate_name:?} depends on symbol {symbol:?}, which namespace {namespace:?} forbids linking against"),
+            Self::Other(reason) =>
+                write!(f, "{reason}"),
+        }
+    }
+}
+
+impl From<&'static str> for LoadError {
+    fn from(reason: &'static str) -> Self {
+        Self::Other(reason)
+    }
+}
+
+impl From<LoadError> for &'static str {
+    fn from(err: LoadError) -> Self {
+        err.as_str()
+    }
+}
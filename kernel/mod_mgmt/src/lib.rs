@@ -6,13 +6,13 @@
 #[macro_use] extern crate alloc;
 #[macro_use] extern crate log;
 
-use core::{fmt, ops::{Deref, Range}};
+use core::{fmt, ops::{Deref, Range}, sync::atomic::{AtomicBool, Ordering}};
 use alloc::{
     collections::{BTreeMap, btree_map, BTreeSet},
     string::{String, ToString},
-    sync::{Arc, Weak}, vec::Vec
+    sync::Arc, vec::Vec
 };
-use spin::{Mutex, Once};
+use spin::{Mutex, Once, RwLock};
 use xmas_elf::{ElfFile, sections::{SHF_ALLOC, SHF_EXECINSTR, SHF_TLS, SHF_WRITE, SectionData, ShType}, symbol_table::{Binding, Type}};
 use memory::{MmiRef, MemoryManagementInfo, VirtualAddress, MappedPages, PteFlags, allocate_pages_by_bytes, allocate_frames_by_bytes_at, PageRange, allocate_pages_by_bytes_in_range};
 use bootloader_modules::BootloaderModule;
@@ -23,16 +23,48 @@ use fs_node::{FileOrDir, File, FileRef, DirRef};
 use vfs_node::VFSDirectory;
 use path::{Path, PathBuf};
 use memfs::MemFile;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use crate_metadata_serde::{CLS_SECTION_FLAG, CLS_SYMBOL_TYPE};
 
 pub use local_storage_initializer::{TlsInitializer, TlsDataImage};
 pub use crate_name_utils::*;
 pub use crate_metadata::*;
 
+pub mod dependency_graph;
 pub mod parse_nano_core;
 pub mod replace_nano_core_crates;
 mod serde;
+mod error;
+mod batched_relocation;
+mod deferred_load;
+mod load_report;
+mod memory_usage;
+mod namespace_image;
+mod parallel_load;
+mod patch;
+mod prelink;
+mod readahead;
+mod view;
+mod swap;
+mod symbol_conflicts;
+mod symbol_policy;
+mod symbol_removal;
+
+pub use error::LoadError;
+pub use batched_relocation::RelocationBatch;
+pub use prelink::{clear_prelink_cache, prelink_cache_len, MAX_PRELINK_CACHE_ENTRIES};
+pub use readahead::{ReadaheadHooks, ReadaheadRequest, set_readahead_hooks, clear_prefetched_crates, predicted_dependencies};
+pub use parallel_load::{PartiallyLoadedCrate, ParallelLoadHooks, set_parallel_load_hooks};
+pub use view::{NamespaceView, TrackedMutex, TrackedMutexGuard};
+pub use symbol_conflicts::{SymbolConflict, SymbolDefinition};
+pub use symbol_policy::SymbolPolicy;
+pub use symbol_removal::{SymbolInvalidationHook, add_symbol_invalidation_hook, remove_symbol_invalidation_hook};
+pub use load_report::CrateLoadReport;
+pub use memory_usage::{NamespaceMemoryUsage, SectionBytes};
+pub use deferred_load::{
+    SymbolResolution, PendingSymbolLoad, SymbolLoadRequest, NextSymbolLoadRequest,
+    next_symbol_load_request, process_pending_symbol_loads,
+};
 
 
 /// The name of the directory that contains all of the CrateNamespace files.
@@ -52,6 +84,56 @@ pub fn get_initial_kernel_namespace() -> Option<&'static Arc<CrateNamespace>> {
     INITIAL_KERNEL_NAMESPACE.get()
 }
 
+/// Namespaces that have been registered under their name so they can be found at runtime,
+/// e.g., by a shell command. See [`register_namespace()`].
+static REGISTERED_NAMESPACES: Mutex<BTreeMap<String, Arc<CrateNamespace>>> = Mutex::new(BTreeMap::new());
+
+/// Registers the given `namespace` under its name, such that it can be found by
+/// [`get_registered_namespace()`] and listed by [`registered_namespaces()`].
+///
+/// The registry holds a strong reference to the namespace, which keeps it (and its crates) alive
+/// until it is removed via [`unregister_namespace()`].
+///
+/// Returns an error if a namespace with the same name is already registered,
+/// or if the name is that of the initial kernel namespace, which is always registered.
+pub fn register_namespace(namespace: Arc<CrateNamespace>) -> Result<(), &'static str> {
+    if get_initial_kernel_namespace().is_some_and(|kernel_ns| kernel_ns.name() == namespace.name()) {
+        return Err("a namespace cannot be registered under the name of the initial kernel namespace");
+    }
+    match REGISTERED_NAMESPACES.lock().entry(namespace.name().to_string()) {
+        btree_map::Entry::Occupied(_) => Err("a namespace with that name is already registered"),
+        btree_map::Entry::Vacant(entry) => {
+            entry.insert(namespace);
+            Ok(())
+        }
+    }
+}
+
+/// Removes the namespace with the given `name` from the registry and returns it.
+///
+/// The namespace is dropped once all other references to it (e.g., from tasks running in it) are gone.
+pub fn unregister_namespace(name: &str) -> Option<Arc<CrateNamespace>> {
+    REGISTERED_NAMESPACES.lock().remove(name)
+}
+
+/// Returns the registered namespace with the given `name`, including the initial kernel namespace.
+pub fn get_registered_namespace(name: &str) -> Option<Arc<CrateNamespace>> {
+    get_initial_kernel_namespace()
+        .filter(|kernel_ns| kernel_ns.name() == name)
+        .cloned()
+        .or_else(|| REGISTERED_NAMESPACES.lock().get(name).cloned())
+}
+
+/// Returns all registered namespaces, starting with the initial kernel namespace
+/// followed by the others in order of name.
+pub fn registered_namespaces() -> Vec<Arc<CrateNamespace>> {
+    let registered = REGISTERED_NAMESPACES.lock();
+    let mut namespaces = Vec::with_capacity(registered.len() + 1);
+    namespaces.extend(get_initial_kernel_namespace().cloned());
+    namespaces.extend(registered.values().cloned());
+    namespaces
+}
+
 /// Returns the top-level directory that contains all of the namespaces. 
 pub fn get_namespaces_directory() -> Option<DirRef> {
     root::get_root().lock().get_dir(NAMESPACES_DIRECTORY_NAME)
@@ -431,17 +513,7 @@ impl Drop for AppCrateRef {
         // First, remove the actual crate from the namespace.
         if let Some(_removed_app_crate) = self.namespace.crate_tree().lock().remove(&crate_locked.crate_name) {
             // Second, remove all of the crate's global symbols from the namespace's symbol map.
-            let mut symbol_map = self.namespace.symbol_map().lock();
-            for sec_to_remove in crate_locked.global_sections_iter() {
-                match symbol_map.remove(&sec_to_remove.name) {
-                    Some(_removed) => {
-                        // trace!("Removed symbol {}: {:?}", sec_to_remove.name, _removed.upgrade());
-                    }
-                    None => {
-                        error!("NOTE: couldn't find old symbol {:?} in the old crate {:?} to remove from namespace {:?}.", sec_to_remove.name, crate_locked.crate_name, self.namespace.name());
-                    }
-                }
-            }
+            self.namespace.remove_symbols_of_crate(&crate_locked);
         } else {
             error!("BUG: the dropped AppCrateRef {:?} could not be removed from namespace {:?}", self.crate_ref, self.namespace.name());
         }
@@ -457,7 +529,8 @@ impl Drop for AppCrateRef {
 ///
 /// Each `CrateNamespace` can be treated as a separate OS personality, 
 /// but are significantly more efficient than library OS-style personalities. 
-/// A `CrateNamespace` is also useful to create a process (task group) abstraction.
+/// A `CrateNamespace` is also useful to create a process (task group) abstraction;
+/// see the `task_group` crate.
 ///
 /// `CrateNamespace`s can also optionally be recursive. 
 /// For example, a namespace that holds just application crates and symbols 
@@ -472,6 +545,11 @@ pub struct CrateNamespace {
     /// it searches in this directory first.
     dir: NamespaceDir,
 
+    /// Additional directories of crate object files that are searched *before* `dir`,
+    /// in order, e.g., a writable overlay of locally-fetched crates.
+    /// A crate object file in an earlier directory shadows any file of the same name in later directories.
+    overlay_dirs: RwLock<Vec<NamespaceDir>>,
+
     /// The list of all the crates loaded into this namespace,
     /// stored as a map in which the crate's string name
     /// is the key that maps to the value, a strong reference to a crate.
@@ -480,13 +558,13 @@ pub struct CrateNamespace {
     /// and a single crate can be part of multiple namespaces at once.
     /// For example, the "core" (Rust core library) crate is essentially
     /// part of every single namespace, simply because most other crates rely upon it. 
-    crate_tree: Mutex<Trie<StrRef, StrongCrateRef>>,
+    crate_tree: TrackedMutex<Trie<StrRef, StrongCrateRef>>,
 
     /// The "system map" of all symbols that are present in all of the crates in this `CrateNamespace`.
     /// Maps a fully-qualified symbol name string to a corresponding `LoadedSection`,
     /// which is guaranteed to be part of one of the crates in this `CrateNamespace`.  
     /// Symbols declared as "no_mangle" will appear in the map with no crate prefix, as expected.
-    symbol_map: Mutex<SymbolMap>,
+    symbol_map: TrackedMutex<SymbolMap>,
 
     /// The `CrateNamespace` that lies below this namespace, and can also be used by this namespace
     /// to resolve symbols and load crates that are relied on by other crates in this namespace.
@@ -512,7 +590,35 @@ pub struct CrateNamespace {
     /// This is a potentially dangerous setting because it overrides the compiler-chosen dependency links.
     /// Thus, it is false by default, and should only be enabled with expert knowledge, 
     /// ideally only temporarily in order to manually load a given crate.
-    fuzzy_symbol_matching: bool,
+    fuzzy_symbol_matching: AtomicBool,
+
+    /// A setting that toggles whether crates loaded into this namespace by the legacy
+    /// (separate sections) loader create full `LoadedSection` metadata for their local
+    /// `.text` and `.rodata` sections. If `true`, such sections are only recorded as
+    /// a [`LazySection`] in their crate's `lazy_sections`, unless they depend on a section
+    /// in another crate; their full metadata is then created on demand,
+    /// e.g., by [`CrateNamespace::get_section_containing_address()`].
+    ///
+    /// This reduces the memory used by namespaces with many crates, at the cost of
+    /// slower lookups of local sections. It is false by default.
+    /// It has no effect on crates whose sections were merged, as they have few local sections,
+    /// nor when the `internal_deps` cfg option is enabled, as that tracks dependencies between local sections.
+    lazy_section_metadata: AtomicBool,
+
+    /// A setting that toggles whether loading or swapping in a crate fails if one of its global symbols
+    /// is already defined by a different crate in this namespace or in one of its recursive namespaces.
+    /// If `false` (the default), the existing symbol is replaced by the new one with just a warning.
+    ///
+    /// When swapping crates, the symbols of the old crates being replaced don't count as conflicts.
+    /// See [`CrateNamespace::symbol_conflicts()`] to find conflicts among already-loaded crates.
+    strict_symbol_conflicts: AtomicBool,
+
+    /// The policy that restricts which symbols from other namespaces the crates in this namespace may link against.
+    /// If `None` (the default), all symbols are permitted. See [`CrateNamespace::set_symbol_policy()`].
+    symbol_policy: RwLock<Option<SymbolPolicy>>,
+
+    /// The most recent read-only view of this namespace. See [`CrateNamespace::view()`].
+    latest_view: RwLock<Arc<NamespaceView>>,
 }
 
 impl CrateNamespace {
@@ -523,14 +629,20 @@ impl CrateNamespace {
     /// * `recursive_namespace`: another `CrateNamespace` that can optionally be used 
     ///    to recursively resolve missing crates/symbols. 
     pub fn new(name: String, dir: NamespaceDir, recursive_namespace: Option<Arc<CrateNamespace>>) -> CrateNamespace {
+        let name_for_view = name.clone();
         CrateNamespace {
             name,
             dir,
+            overlay_dirs: RwLock::new(Vec::new()),
             recursive_namespace,
             tls_initializer: &TLS_INITIALIZER,
-            crate_tree: Mutex::new(Trie::new()),
-            symbol_map: Mutex::new(SymbolMap::new()),
-            fuzzy_symbol_matching: false,
+            crate_tree: TrackedMutex::new(Trie::new()),
+            symbol_map: TrackedMutex::new(SymbolMap::new()),
+            fuzzy_symbol_matching: AtomicBool::new(false),
+            lazy_section_metadata: AtomicBool::new(false),
+            strict_symbol_conflicts: AtomicBool::new(false),
+            symbol_policy: RwLock::new(None),
+            latest_view: RwLock::new(Arc::new(NamespaceView::empty(name_for_view))),
         }
     }
 
@@ -540,10 +652,61 @@ impl CrateNamespace {
     }
 
     /// Returns the directory that this `CrateNamespace` is based on.
+    ///
+    /// This does not include any overlay directories; see [`search_dirs()`](#method.search_dirs).
     pub fn dir(&self) -> &NamespaceDir {
         &self.dir
     }
 
+    /// Returns all directories that are searched for crate object files in this namespace
+    /// (not including its recursive namespaces), in order of decreasing priority:
+    /// first the overlay directories, then the namespace's base [`dir()`](#method.dir).
+    pub fn search_dirs(&self) -> Vec<NamespaceDir> {
+        let mut dirs = self.overlay_dirs.read().clone();
+        dirs.push(self.dir.clone());
+        dirs
+    }
+
+    /// Adds the given directory as an overlay that is searched for crate object files
+    /// before all existing overlay directories and the base directory of this namespace.
+    ///
+    /// Returns an error if that directory is already one of this namespace's search directories.
+    pub fn add_overlay_dir(&self, dir: NamespaceDir) -> Result<(), &'static str> {
+        let mut overlay_dirs = self.overlay_dirs.write();
+        if Arc::ptr_eq(&*dir, &*self.dir) || overlay_dirs.iter().any(|d| Arc::ptr_eq(&**d, &*dir)) {
+            return Err("directory is already a search directory of this namespace");
+        }
+        overlay_dirs.insert(0, dir);
+        Ok(())
+    }
+
+    /// Removes the given directory from this namespace's overlay directories.
+    ///
+    /// Returns the removed directory, or `None` if it was not an overlay directory of this namespace.
+    pub fn remove_overlay_dir(&self, dir: &DirRef) -> Option<NamespaceDir> {
+        let mut overlay_dirs = self.overlay_dirs.write();
+        let index = overlay_dirs.iter().position(|d| Arc::ptr_eq(&**d, dir))?;
+        Some(overlay_dirs.remove(index))
+    }
+
+    /// Returns the crate object files in this namespace's search directories
+    /// (not including its recursive namespaces) whose names start with the given `prefix`.
+    ///
+    /// Files in earlier search directories shadow same-named files in later ones.
+    fn files_in_search_dirs_starting_with(&self, prefix: &str) -> Vec<FileRef> {
+        let mut files: Vec<FileRef> = Vec::new();
+        let mut file_names: BTreeSet<String> = BTreeSet::new();
+        for dir in self.search_dirs() {
+            for file in dir.get_files_starting_with(prefix) {
+                let file_name = file.lock().get_name();
+                if file_names.insert(file_name) {
+                    files.push(file);
+                }
+            }
+        }
+        files
+    }
+
     /// Returns the recursive namespace that this `CrateNamespace` is built atop,
     /// if one exists.
     pub fn recursive_namespace(&self) -> Option<&Arc<CrateNamespace>> {
@@ -557,23 +720,112 @@ impl CrateNamespace {
     }
 
     #[doc(hidden)]
-    pub fn crate_tree(&self) -> &Mutex<Trie<StrRef, StrongCrateRef>> {
+    pub fn crate_tree(&self) -> &TrackedMutex<Trie<StrRef, StrongCrateRef>> {
         &self.crate_tree
     }
 
     #[doc(hidden)]
-    pub fn symbol_map(&self) -> &Mutex<SymbolMap> {
+    pub fn symbol_map(&self) -> &TrackedMutex<SymbolMap> {
         &self.symbol_map
     }
 
+    /// Returns the current epoch of this namespace, which advances whenever
+    /// its crate tree or symbol map is modified.
+    fn epoch(&self) -> u64 {
+        // An empty view has an epoch of 0, so even an unmodified namespace must start at 1.
+        1 + self.crate_tree.modifications() + self.symbol_map.modifications()
+    }
+
+    /// Returns a read-only view of the crates and symbols in this namespace
+    /// and its recursive namespaces.
+    ///
+    /// The returned view can be inspected without acquiring any locks on this namespace,
+    /// which makes it suitable for backtraces, profilers, and debuggers
+    /// that may run concurrently with (or in the middle of) crate loading or swapping.
+    ///
+    /// A new view is only taken if this namespace has changed since the latest view.
+    /// This function never waits for the namespace's crate tree or symbol map to be unlocked:
+    /// if another task is currently modifying this namespace, the previous view is returned,
+    /// which may be outdated. It does briefly lock the latest view in order to read or replace it.
+    /// Use [`NamespaceView::epoch()`] to determine how recent a view is.
+    pub fn view(&self) -> Arc<NamespaceView> {
+        let recursive = self.recursive_namespace.as_ref().map(|r_ns| r_ns.view());
+        let latest = self.latest_view.read().clone();
+        let epoch = self.epoch();
+
+        let recursive_unchanged = match (&recursive, latest.recursive_namespace()) {
+            (Some(new), Some(old)) => Arc::ptr_eq(new, old),
+            (None, None) => true,
+            _ => false,
+        };
+        if latest.epoch() == epoch && recursive_unchanged {
+            return latest;
+        }
+
+        match NamespaceView::try_build(self, epoch, recursive) {
+            Some(new_view) => {
+                let new_view = Arc::new(new_view);
+                let mut latest_view = self.latest_view.write();
+                // Don't replace a view that was concurrently taken at a more recent epoch.
+                if latest_view.epoch() <= epoch {
+                    *latest_view = Arc::clone(&new_view);
+                }
+                new_view
+            }
+            None => latest,
+        }
+    }
+
     #[doc(hidden)]
     pub fn enable_fuzzy_symbol_matching(&mut self) {
-        self.fuzzy_symbol_matching = true;
+        self.set_fuzzy_symbol_matching(true);
     }
 
     #[doc(hidden)]
     pub fn disable_fuzzy_symbol_matching(&mut self) {
-        self.fuzzy_symbol_matching = false;
+        self.set_fuzzy_symbol_matching(false);
+    }
+
+    /// Sets whether this namespace ignores hash differences in symbols when resolving a dependency.
+    ///
+    /// See the `fuzzy_symbol_matching` field for more details, including why this is dangerous.
+    pub fn set_fuzzy_symbol_matching(&self, enable: bool) {
+        self.fuzzy_symbol_matching.store(enable, Ordering::Relaxed);
+    }
+
+    /// Returns whether this namespace is in fuzzy symbol matching mode.
+    /// See [`CrateNamespace::set_fuzzy_symbol_matching()`].
+    pub fn fuzzy_symbol_matching(&self) -> bool {
+        self.fuzzy_symbol_matching.load(Ordering::Relaxed)
+    }
+
+    /// Sets whether crates subsequently loaded into this namespace defer creating
+    /// the metadata of their local sections until it's needed.
+    ///
+    /// Crates that were already loaded are unaffected.
+    /// See the `lazy_section_metadata` field for more details.
+    pub fn set_lazy_section_metadata(&self, enable: bool) {
+        self.lazy_section_metadata.store(enable, Ordering::Relaxed);
+    }
+
+    /// Returns whether this namespace is in lazy section metadata mode.
+    /// See [`CrateNamespace::set_lazy_section_metadata()`].
+    pub fn lazy_section_metadata(&self) -> bool {
+        self.lazy_section_metadata.load(Ordering::Relaxed)
+    }
+
+    /// Sets whether loading or swapping a crate into this namespace fails with [`LoadError::SymbolConflict`]
+    /// if one of its global symbols is already defined by a different crate.
+    ///
+    /// See the `strict_symbol_conflicts` field for more details.
+    pub fn set_strict_symbol_conflicts(&self, enable: bool) {
+        self.strict_symbol_conflicts.store(enable, Ordering::Relaxed);
+    }
+
+    /// Returns whether this namespace is in strict symbol conflict mode.
+    /// See [`CrateNamespace::set_strict_symbol_conflicts()`].
+    pub fn strict_symbol_conflicts(&self) -> bool {
+        self.strict_symbol_conflicts.load(Ordering::Relaxed)
     }
 
     /// Returns a list of all of the crate names currently loaded into this `CrateNamespace`,
@@ -728,8 +980,7 @@ impl CrateNamespace {
         file_name_prefix: &str
     ) -> Vec<(FileRef, &'n Arc<CrateNamespace>)> {
         // First, we make a list of matching files in this namespace. 
-        let mut files = namespace.dir
-            .get_files_starting_with(file_name_prefix)
+        let mut files = namespace.files_in_search_dirs_starting_with(file_name_prefix)
             .into_iter()
             .map(|f| (f, namespace))
             .collect::<Vec<_>>();
@@ -769,8 +1020,7 @@ impl CrateNamespace {
         file_name_prefix: &str
     ) -> Vec<(FileRef, &CrateNamespace)> {
         // First, we make a list of matching files in this namespace. 
-        let mut files = self.dir
-            .get_files_starting_with(file_name_prefix)
+        let mut files = self.files_in_search_dirs_starting_with(file_name_prefix)
             .into_iter()
             .map(|f| (f, self))
             .collect::<Vec<_>>();
@@ -808,22 +1058,30 @@ impl CrateNamespace {
     /// so to load an application crate multiple times to spawn multiple instances of it,
     /// you can create a new top-level namespace to hold that application crate.
     ///
+    /// The relocations applied to an application crate are cached (see [`clear_prelink_cache()`]),
+    /// such that loading the same application object file again against the same dependencies
+    /// skips the costly symbol resolution and relocation stage.
+    ///
     /// Returns a Result containing the newly-loaded application crate itself.
     pub fn load_crate_as_application(
         namespace: &Arc<CrateNamespace>,
         crate_object_file: &FileRef,
         kernel_mmi_ref: &MmiRef,
         verbose_log: bool
-    ) -> Result<AppCrateRef, &'static str> {
+    ) -> Result<AppCrateRef, LoadError> {
         debug!("load_crate_as_application(): trying to load application crate at {:?}", crate_object_file.lock().get_absolute_path());
         // Don't use a backup namespace when loading applications;
         // we must be able to find all symbols in only this namespace and its backing recursive namespaces.
-        let new_crate_ref = namespace.load_crate_internal(crate_object_file, None, kernel_mmi_ref, verbose_log)?;
+        let new_crate_ref = namespace.load_crate_internal(crate_object_file, None, true, kernel_mmi_ref, verbose_log)?;
+        let new_syms = {
+            let new_crate = new_crate_ref.lock_as_ref();
+            namespace.add_symbols_checked(&new_crate.crate_name, new_crate.sections.values(), verbose_log)?
+        };
+        record_symbols_added(&new_crate_ref, new_syms);
         {
             let new_crate = new_crate_ref.lock_as_ref();
-            let _new_syms = namespace.add_symbols(new_crate.sections.values(), verbose_log);
             namespace.crate_tree.lock().insert(new_crate.crate_name.clone(), CowArc::clone_shallow(&new_crate_ref));
-            info!("loaded new application crate: {:?}, num sections: {}, added {} new symbols", new_crate.crate_name, new_crate.sections.len(), _new_syms);
+            info!("loaded new application crate: {:?}, num sections: {}, added {} new symbols", new_crate.crate_name, new_crate.sections.len(), new_syms);
         }
         Ok(AppCrateRef {
             crate_ref: new_crate_ref,
@@ -850,36 +1108,125 @@ impl CrateNamespace {
         temp_backup_namespace: Option<&CrateNamespace>,
         kernel_mmi_ref: &MmiRef,
         verbose_log: bool
-    ) -> Result<(StrongCrateRef, usize), &'static str> {
+    ) -> Result<(StrongCrateRef, usize), LoadError> {
         #[cfg(not(loscd_eval))]
         debug!("load_crate: trying to load crate at {:?}", crate_object_file.lock().get_absolute_path());
-        let new_crate_ref = self.load_crate_internal(crate_object_file, temp_backup_namespace, kernel_mmi_ref, verbose_log)?;
+        let readahead = self.begin_readahead(crate_object_file);
+        let new_crate_ref = self.load_crate_internal_with_readahead(crate_object_file, temp_backup_namespace, kernel_mmi_ref, verbose_log)?;
 
         let (new_crate_name, _num_sections, new_syms) = {
             let new_crate = new_crate_ref.lock_as_ref();
-            let new_syms = self.add_symbols(new_crate.sections.values(), verbose_log);
+            let new_syms = self.add_symbols_checked(&new_crate.crate_name, new_crate.sections.values(), verbose_log)?;
             (new_crate.crate_name.clone(), new_crate.sections.len(), new_syms)
         };
+        record_symbols_added(&new_crate_ref, new_syms);
 
         #[cfg(not(loscd_eval))]
         info!("loaded new crate {:?}, num sections: {}, added {} new symbols.", new_crate_name, _num_sections, new_syms);
         self.crate_tree.lock().insert(new_crate_name, new_crate_ref.clone_shallow());
+        self.finish_readahead(readahead);
         Ok((new_crate_ref, new_syms))
     }
 
 
+    /// Unloads the crate with the given `crate_name` from this `CrateNamespace`.
+    ///
+    /// This is intended for crates that are only needed temporarily, e.g., diagnostic crates,
+    /// such that the memory they occupy can be reclaimed.
+    /// Unlike [`get_crate()`](#method.get_crate), this does not search the recursive namespace;
+    /// the crate must have been loaded into this namespace itself.
+    ///
+    /// The crate is only unloaded if no sections in other crates depend on any of its sections.
+    /// If so, this function removes the crate from this namespace, removes its global and reexported symbols from the symbol map,
+    /// removes its TLS sections from this namespace's TLS area,
+    /// and removes its sections from the dependents lists of the sections they depend on.
+    ///
+    /// The crate's `MappedPages` are unmapped once the crate is dropped.
+    /// This happens right away unless another reference to the crate still exists,
+    /// e.g., one held by a task currently running it, in which case a warning is logged.
+    ///
+    /// # Errors
+    /// Returns an error if the crate isn't loaded in this namespace, if it is shared with another namespace,
+    /// or if any other crate still depends on it.
+    pub fn unload_crate(&self, crate_name: &str) -> Result<(), &'static str> {
+        let crate_ref = {
+            // Both locks are held from the check for dependents until the crate and its symbols are removed,
+            // such that no other crate can find and link against this crate's symbols in between.
+            let mut crate_tree = self.crate_tree.lock();
+            let mut symbol_map = self.symbol_map.lock();
+            let crate_ref = crate_tree.get(crate_name.as_bytes())
+                .map(CowArc::clone_shallow)
+                .ok_or("unload_crate(): crate is not loaded in this namespace")?;
+            if crate_ref.is_shared() {
+                return Err("unload_crate(): crate is shared with another namespace");
+            }
+
+            let krate = crate_ref.lock_as_ref();
+            for sec in krate.sections.values() {
+                let has_dependents = sec.inner.read().sections_dependent_on_me.iter()
+                    .any(|weak_dep| weak_dep.section.strong_count() > 0);
+                if has_dependents {
+                    error!("unload_crate(): section {:?} in crate {:?} still has dependents", sec.name, krate.crate_name);
+                    return Err("unload_crate(): another crate still depends on the crate being unloaded");
+                }
+            }
+            crate_tree.remove(crate_name.as_bytes());
+            // No other namespace imported this crate's symbols, as none of its sections have dependents.
+            self.remove_symbols_of_crate_locked(&mut symbol_map, &krate);
+            drop(krate);
+            crate_ref
+        };
+
+        self.detach_crate_sections(&crate_ref.lock_as_ref());
+
+        let weak_crate_ref = CowArc::downgrade(&crate_ref);
+        drop(crate_ref);
+        if weak_crate_ref.upgrade().is_some() {
+            warn!("unload_crate(): crate {:?} was unloaded from namespace {:?}, but is still in use and won't be freed until its last reference is dropped",
+                crate_name, self.name,
+            );
+        } else {
+            info!("unloaded crate {:?} from namespace {:?}", crate_name, self.name);
+        }
+        Ok(())
+    }
+
+
+    /// Removes the TLS sections of the given crate, which is no longer part of this namespace,
+    /// and removes its sections from the dependents of the sections that they depend on.
+    fn detach_crate_sections(&self, krate: &LoadedCrate) {
+        for sec in krate.sections.values() {
+            if matches!(sec.typ, SectionType::TlsData | SectionType::TlsBss) {
+                self.tls_initializer.lock().remove_dynamic_section(sec);
+            }
+            // The sections this one depends on no longer have it as a dependent.
+            let dependencies = core::mem::take(&mut sec.inner.write().sections_i_depend_on);
+            for strong_dep in dependencies {
+                strong_dep.section.inner.write().sections_dependent_on_me.retain(|weak_dep|
+                    !core::ptr::eq(weak_dep.section.as_ptr(), Arc::as_ptr(sec))
+                );
+            }
+        }
+    }
+
+
     /// The internal function that does the work for loading crates,
     /// but does not add the crate nor its symbols to this namespace. 
     /// See [`load_crate`](#method.load_crate) and [`load_crate_as_application`](#fn.load_crate_as_application).
+    ///
+    /// If `use_prelink_cache` is true, the relocated contents of this crate may be copied from
+    /// (and will be recorded into) the prelinking cache.
     fn load_crate_internal(&self,
         crate_object_file: &FileRef,
         temp_backup_namespace: Option<&CrateNamespace>,
+        use_prelink_cache: bool,
         kernel_mmi_ref: &MmiRef,
         verbose_log: bool
-    ) -> Result<StrongCrateRef, &'static str> {
+    ) -> Result<StrongCrateRef, LoadError> {
         let cf = crate_object_file.lock();
+        let prelink_path = use_prelink_cache.then(|| cf.get_absolute_path());
         let (new_crate_ref, elf_file) = self.load_crate_sections(cf.deref(), kernel_mmi_ref, verbose_log)?;
-        self.perform_relocations(&elf_file, &new_crate_ref, temp_backup_namespace, kernel_mmi_ref, verbose_log)?;
+        self.perform_relocations(&elf_file, &new_crate_ref, temp_backup_namespace, prelink_path, kernel_mmi_ref, verbose_log)?;
         Ok(new_crate_ref)
     }
 
@@ -890,6 +1237,11 @@ impl CrateNamespace {
     /// This allows multiple object files with circular dependencies on one another
     /// to be loaded all at once, as if they were a single entity.
     ///
+    /// If this namespace is registered and a [`ParallelLoadHooks`] has been registered via
+    /// [`set_parallel_load_hooks()`], the crates' sections are loaded concurrently on multiple CPUs.
+    /// Either all of the crates are loaded, or none of them are;
+    /// see [`link_partially_loaded_crates()`](Self::link_partially_loaded_crates).
+    ///
     /// # Example
     /// If crate `A` depends on crate `B`, and crate `B` depends on crate `A`,
     /// this function will load both crate `A` and `B` before trying to resolve their dependencies individually. 
@@ -899,31 +1251,12 @@ impl CrateNamespace {
         temp_backup_namespace: Option<&CrateNamespace>,
         kernel_mmi_ref: &MmiRef,
         verbose_log: bool,
-    ) -> Result<(), &'static str>
+    ) -> Result<(), LoadError>
         where I: Iterator<Item = &'f FileRef>
     {
-        // First, lock all of the crate object files.
-        let mut locked_crate_files = Vec::new();
-        for crate_file_ref in crate_files {
-            locked_crate_files.push(crate_file_ref.lock());
-        }
-
-        // Second, do all of the section parsing and loading, and add all public symbols to the symbol map.
-        let mut partially_loaded_crates: Vec<(StrongCrateRef, ElfFile)> = Vec::with_capacity(locked_crate_files.len());
-        for locked_crate_file in &locked_crate_files {
-            let (new_crate_ref, elf_file) = self.load_crate_sections(locked_crate_file.deref(), kernel_mmi_ref, verbose_log)?;
-            let _new_syms = self.add_symbols(new_crate_ref.lock_as_ref().sections.values(), verbose_log);
-            partially_loaded_crates.push((new_crate_ref, elf_file));
-        }
-
-        // Finally, we do all of the relocations.
-        for (new_crate_ref, elf_file) in partially_loaded_crates {
-            self.perform_relocations(&elf_file, &new_crate_ref, temp_backup_namespace, kernel_mmi_ref, verbose_log)?;
-            let name = new_crate_ref.lock_as_ref().crate_name.clone();
-            self.crate_tree.lock().insert(name, new_crate_ref);
-        }
-
-        Ok(())
+        let crate_files: Vec<FileRef> = crate_files.cloned().collect();
+        let crates = self.load_crate_sections_with_hooks(&crate_files, kernel_mmi_ref, verbose_log)?;
+        self.link_partially_loaded_crates(crates, temp_backup_namespace, kernel_mmi_ref, verbose_log)
     }
 
 
@@ -952,11 +1285,16 @@ impl CrateNamespace {
         CrateNamespace {
             name: self.name.clone(),
             dir: self.dir.clone(),
+            overlay_dirs: RwLock::new(self.overlay_dirs.read().clone()),
             tls_initializer: &TLS_INITIALIZER,
             recursive_namespace: self.recursive_namespace.clone(),
-            crate_tree: Mutex::new(self.crate_tree.lock().clone()),
-            symbol_map: Mutex::new(self.symbol_map.lock().clone()),
-            fuzzy_symbol_matching: self.fuzzy_symbol_matching,
+            crate_tree: TrackedMutex::new(self.crate_tree.lock().clone()),
+            symbol_map: TrackedMutex::new(self.symbol_map.lock().clone()),
+            fuzzy_symbol_matching: AtomicBool::new(self.fuzzy_symbol_matching()),
+            lazy_section_metadata: AtomicBool::new(self.lazy_section_metadata()),
+            strict_symbol_conflicts: AtomicBool::new(self.strict_symbol_conflicts()),
+            symbol_policy: RwLock::new(self.symbol_policy()),
+            latest_view: RwLock::new(Arc::new(NamespaceView::empty(self.name.clone()))),
         }
     }
 
@@ -965,10 +1303,32 @@ impl CrateNamespace {
     /// and rewrites their relocation entries to point to the given `new_section`.
     /// This effectively replaces the usage of the `old_section` with the `new_section`,
     /// but does not make any modifications to symbol maps.
+    ///
+    /// When re-linking many sections at once, use
+    /// [`rewrite_section_dependents_batched()`](#method.rewrite_section_dependents_batched) instead,
+    /// which remaps each target region only once.
     pub fn rewrite_section_dependents(
         old_section: &StrongSectionRef,
         new_section: &StrongSectionRef,
         kernel_mmi_ref: &MmiRef
+    ) -> Result<(), &'static str> {
+        let mut batch = RelocationBatch::new();
+        // Apply the writes queued before a failure, too, such that they match the already-updated metadata.
+        let queued = Self::rewrite_section_dependents_batched(old_section, new_section, &mut batch);
+        let applied = batch.apply(kernel_mmi_ref, false);
+        queued.and(applied)
+    }
+
+    /// Like [`rewrite_section_dependents()`](#method.rewrite_section_dependents),
+    /// but queues the relocation writes into the given `batch` instead of performing them.
+    ///
+    /// The dependency metadata of the dependents is updated immediately,
+    /// so the caller must [`apply()`](RelocationBatch::apply) the batch
+    /// before any of the dependents are used again.
+    pub fn rewrite_section_dependents_batched(
+        old_section: &StrongSectionRef,
+        new_section: &StrongSectionRef,
+        batch: &mut RelocationBatch,
     ) -> Result<(), &'static str> {
         for weak_dep in &old_section.inner.read().sections_dependent_on_me {
             let target_sec = weak_dep.section.upgrade().ok_or("couldn't upgrade WeakDependent.section")?;
@@ -976,28 +1336,7 @@ impl CrateNamespace {
 
             debug!("rewrite_section_dependents(): target_sec: {:?}, old_sec: {:?}, new_sec: {:?}", target_sec, old_section, new_section);
 
-            // If the target_sec's mapped pages aren't writable (which is common in the case of swapping),
-            // then we need to temporarily remap them as writable here so we can fix up the target_sec's new relocation entry.
-            {
-                let mut target_sec_mapped_pages = target_sec.mapped_pages.lock();
-                let target_sec_initial_flags = target_sec_mapped_pages.flags();
-                if !target_sec_initial_flags.is_writable() {
-                    target_sec_mapped_pages.remap(&mut kernel_mmi_ref.lock().page_table, target_sec_initial_flags.writable(true))?;
-                }
-
-                write_relocation(
-                    relocation_entry,
-                    target_sec_mapped_pages.as_slice_mut(0, target_sec.mapped_pages_offset + target_sec.size)?,
-                    target_sec.mapped_pages_offset,
-                    new_section.virt_addr,
-                    false
-                )?;
-
-                // If we temporarily remapped the target_sec's mapped pages as writable, undo that here
-                if !target_sec_initial_flags.is_writable() {
-                    target_sec_mapped_pages.remap(&mut kernel_mmi_ref.lock().page_table, target_sec_initial_flags)?;
-                };
-            }
+            batch.add(&target_sec, relocation_entry, new_section.virt_addr);
 
             // Tell the new source_sec that the existing target_sec depends on it.
             // Note that we don't need to do this if we're re-swapping in a cached crate,
@@ -1049,7 +1388,9 @@ impl CrateNamespace {
         crate_file: &'f dyn File,
         kernel_mmi_ref: &MmiRef,
         _verbose_log: bool
-    ) -> Result<(StrongCrateRef, ElfFile<'f>), &'static str> {
+    ) -> Result<(StrongCrateRef, ElfFile<'f>), LoadError> {
+        // No clock source exists while the first crates are loaded during boot.
+        let parse_start   = time::Instant::try_now();
         let mapped_pages  = crate_file.as_mapping()?;
         let size_in_bytes = crate_file.len();
         let abs_path      = PathBuf::from(crate_file.get_absolute_path());
@@ -1063,26 +1404,27 @@ impl CrateNamespace {
         // so to load an application crate multiple times and run multiple instances of it,
         // you can create a top-level new namespace to hold that application crate.
         if self.get_crate(&crate_name).is_some() {
-            return Err("the crate has already been loaded, cannot load it again in the same namespace");
+            return Err(LoadError::AlreadyLoaded { crate_name });
         }
 
         // It's probably better to pass in the actual crate file reference so we can use it here,
         // but since we don't currently do that, we just get another reference to the crate object file via its Path.
         let crate_object_file = match Path::get_absolute(&abs_path) {
             Some(FileOrDir::File(f)) => f,
-            _ => return Err("BUG: load_crate_sections(): couldn't get crate object file path"),
+            _ => return Err(LoadError::CrateNotFound { name: abs_path.to_string() }),
         };
 
         // Parse the crate file as an ELF file
         let byte_slice: &[u8] = mapped_pages.as_slice(0, size_in_bytes)?;
-        let elf_file = ElfFile::new(byte_slice)?; // returns Err(&str) if ELF parse fails
+        let elf_file = ElfFile::new(byte_slice)
+            .map_err(|reason| LoadError::ElfParse { crate_name: crate_name.clone(), reason })?;
 
         // Check that elf_file is a relocatable type 
         use xmas_elf::header::Type;
         let typ = elf_file.header.pt2.type_().as_type();
         if typ != Type::Relocatable {
             error!("load_crate_sections(): crate \"{}\" was a {:?} Elf File, must be Relocatable!", &crate_name, typ);
-            return Err("not a relocatable elf file");
+            return Err(LoadError::ElfParse { crate_name, reason: "not a relocatable elf file" });
         }
 
         // If a `.theseus_merged` section exists (it should come before any .text section),
@@ -1108,17 +1450,29 @@ impl CrateNamespace {
         };
 
         // Allocate enough space to load the sections
-        let section_pages = allocate_section_pages(&elf_file, kernel_mmi_ref)?;
+        let section_pages = allocate_section_pages(&elf_file, kernel_mmi_ref)
+            .map_err(|reason| LoadError::Mapping { crate_name: crate_name.clone(), reason })?;
         let text_pages   = section_pages.executable_pages.map(|(tp, range)| (Arc::new(Mutex::new(tp)), range));
         let rodata_pages = section_pages.read_only_pages.map( |(rp, range)| (Arc::new(Mutex::new(rp)), range));
         let data_pages   = section_pages.read_write_pages.map(|(dp, range)| (Arc::new(Mutex::new(dp)), range));
 
+        let mapped_bytes = |pages: &Option<(Arc<Mutex<MappedPages>>, Range<VirtualAddress>)>| {
+            pages.as_ref().map_or(0, |(_, range)| range.end.value() - range.start.value())
+        };
+        let load_stats = CrateLoadStats {
+            text_bytes:   mapped_bytes(&text_pages),
+            rodata_bytes: mapped_bytes(&rodata_pages),
+            data_bytes:   mapped_bytes(&data_pages),
+            ..Default::default()
+        };
+
         // Create the new `LoadedCrate` now such that its sections can refer back to it.
         let new_crate = CowArc::new(LoadedCrate {
-            crate_name,
+            crate_name:              crate_name.clone(),
             debug_symbols_file:      Arc::downgrade(&crate_object_file),
             object_file:             crate_object_file,
             sections:                HashMap::new(),
+            lazy_sections:           BTreeMap::new(),
             text_pages:              text_pages.clone(),
             rodata_pages:            rodata_pages.clone(),
             data_pages:              data_pages.clone(),
@@ -1127,6 +1481,7 @@ impl CrateNamespace {
             cls_sections:            BTreeSet::new(),
             data_sections:           BTreeSet::new(),
             reexported_symbols:      BTreeSet::new(),
+            load_stats,
         });
         let new_crate_weak_ref = CowArc::downgrade(&new_crate);
 
@@ -1138,6 +1493,7 @@ impl CrateNamespace {
 
         let SectionMetadata {
             loaded_sections,
+            lazy_sections,
             global_sections,
             tls_sections,
             cls_sections,
@@ -1149,17 +1505,19 @@ impl CrateNamespace {
             text_pages,
             rodata_pages,
             data_pages,
-        )?;
+        ).map_err(|reason| LoadError::ElfParse { crate_name, reason })?;
 
         // Set up the new_crate's sections, since we couldn't do it when `new_crate` was created.
         {
             let mut new_crate_mut = new_crate.lock_as_mut()
                 .ok_or("BUG: load_crate_sections(): couldn't get exclusive mutable access to new_crate")?;
             new_crate_mut.sections        = loaded_sections;
+            new_crate_mut.lazy_sections   = lazy_sections;
             new_crate_mut.global_sections = global_sections;
             new_crate_mut.tls_sections    = tls_sections;
             new_crate_mut.cls_sections    = cls_sections;
             new_crate_mut.data_sections   = data_sections;
+            new_crate_mut.load_stats.parse_time = parse_start.map(|start| start.elapsed()).unwrap_or_default();
         }
 
         // TODO: Should be reload().
@@ -1426,7 +1784,7 @@ impl CrateNamespace {
                 cls_shndx_and_section = Some((shndx, Arc::clone(&new_cls_section)));
                 new_cls_section
             } else {
-                Arc::new(new_section)
+                new_section.into_ref()
             };
 
             loaded_sections.insert(shndx, new_section_ref);
@@ -1587,7 +1945,7 @@ impl CrateNamespace {
             // Create the new `LoadedSection`
             loaded_sections.insert(
                 last_shndx,
-                Arc::new(LoadedSection::new(
+                LoadedSection::new(
                     typ,
                     demangled,
                     Arc::clone(mapped_pages),
@@ -1596,7 +1954,7 @@ impl CrateNamespace {
                     sec_size,
                     is_global,
                     new_crate.clone(),
-                ))
+                ).into_ref()
             );
 
             if is_global {
@@ -1646,6 +2004,7 @@ impl CrateNamespace {
 
         Ok(SectionMetadata {
             loaded_sections,
+            lazy_sections:   BTreeMap::new(),
             global_sections,
             tls_sections,
             cls_sections,
@@ -1768,6 +2127,10 @@ impl CrateNamespace {
 
         // this maps section header index (shndx) to LoadedSection
         let mut loaded_sections: HashMap<Shndx, StrongSectionRef> = HashMap::new();
+        // In lazy section metadata mode, local .text and .rodata sections are recorded here instead.
+        // Lazy sections can't be used with `internal_deps`, which needs a `LoadedSection` to track each local dependency.
+        let lazy_metadata = !cfg!(internal_deps) && self.lazy_section_metadata();
+        let mut lazy_sections: BTreeMap<Shndx, LazySection> = BTreeMap::new();
         // the set of Shndxes for .data and .bss sections
         let mut data_sections: BTreeSet<Shndx> = BTreeSet::new();
         // the set of Shndxes for TLS sections (.tdata, .tbss)
@@ -1848,7 +2211,6 @@ impl CrateNamespace {
                 } else {
                     name
                 };
-                let demangled = demangle(name).to_string().as_str().into();
 
                 // We already copied the content of all .text sections above, 
                 // so here we just record the metadata into a new `LoadedSection` object.
@@ -1856,9 +2218,20 @@ impl CrateNamespace {
                     let text_offset = sec.offset() as usize;
                     let dest_vaddr = tp_range.start + text_offset;
 
+                    if lazy_metadata && !is_global {
+                        lazy_sections.insert(shndx, LazySection {
+                            typ: SectionType::Text,
+                            mapped_pages_offset: text_offset,
+                            virt_addr: dest_vaddr,
+                            size: sec_size,
+                        });
+                        continue;
+                    }
+
+                    let demangled = demangle(name).to_string().as_str().into();
                     loaded_sections.insert(
                         shndx,
-                        Arc::new(LoadedSection::new(
+                        LoadedSection::new(
                             SectionType::Text,
                             demangled,
                             Arc::clone(tp_ref),
@@ -1867,7 +2240,7 @@ impl CrateNamespace {
                             sec_size,
                             is_global,
                             new_crate.clone(),
-                        ))
+                        ).into_ref()
                     );
                 }
                 else {
@@ -2020,7 +2393,7 @@ impl CrateNamespace {
 
                     loaded_sections.insert(
                         shndx,
-                        Arc::new(LoadedSection::new(
+                        LoadedSection::new(
                             if is_bss { SectionType::Bss } else { SectionType::Data },
                             demangled,
                             Arc::clone(dp_ref),
@@ -2029,7 +2402,7 @@ impl CrateNamespace {
                             sec_size,
                             global_sections.contains(&shndx),
                             new_crate.clone(),
-                        ))
+                        ).into_ref()
                     );
                     data_sections.insert(shndx);
 
@@ -2043,7 +2416,7 @@ impl CrateNamespace {
             // Fourth, if neither executable nor TLS nor writable, handle .rodata sections.
             else if sec_name.starts_with(RODATA_PREFIX) {
                 let name = try_get_symbol_name_after_prefix!(sec_name, RODATA_PREFIX);
-                let demangled = demangle(name).to_string().as_str().into();
+                let is_global = global_sections.contains(&shndx);
 
                 if let Some((ref rp_ref, ref mut rp)) = read_only_pages_locked {
                     // here: we're ready to copy the rodata section to the proper address
@@ -2059,19 +2432,29 @@ impl CrateNamespace {
                         }
                     }
 
-                    loaded_sections.insert(
-                        shndx,
-                        Arc::new(LoadedSection::new(
-                            SectionType::Rodata,
-                            demangled,
-                            Arc::clone(rp_ref),
-                            rodata_offset,
-                            dest_vaddr,
-                            sec_size,
-                            global_sections.contains(&shndx),
-                            new_crate.clone(),
-                        ))
-                    );
+                    if lazy_metadata && !is_global {
+                        lazy_sections.insert(shndx, LazySection {
+                            typ: SectionType::Rodata,
+                            mapped_pages_offset: rodata_offset,
+                            virt_addr: dest_vaddr,
+                            size: sec_size,
+                        });
+                    } else {
+                        let demangled = demangle(name).to_string().as_str().into();
+                        loaded_sections.insert(
+                            shndx,
+                            LoadedSection::new(
+                                SectionType::Rodata,
+                                demangled,
+                                Arc::clone(rp_ref),
+                                rodata_offset,
+                                dest_vaddr,
+                                sec_size,
+                                is_global,
+                                new_crate.clone(),
+                            ).into_ref()
+                        );
+                    }
 
                     rodata_offset += sec_size.next_multiple_of(sec_align);
                 }
@@ -2106,7 +2489,7 @@ impl CrateNamespace {
                     let typ = SectionType::GccExceptTable;
                     loaded_sections.insert(
                         shndx,
-                        Arc::new(LoadedSection::new(
+                        LoadedSection::new(
                             typ,
                             section_name_str_ref(&typ),
                             Arc::clone(rp_ref),
@@ -2115,7 +2498,7 @@ impl CrateNamespace {
                             sec_size,
                             false, // .gcc_except_table sections are never globally visible,
                             new_crate.clone(),
-                        ))
+                        ).into_ref()
                     );
 
                     rodata_offset += sec_size.next_multiple_of(sec_align);
@@ -2145,7 +2528,7 @@ impl CrateNamespace {
                     let typ = SectionType::EhFrame;
                     loaded_sections.insert(
                         shndx,
-                        Arc::new(LoadedSection::new(
+                        LoadedSection::new(
                             typ,
                             section_name_str_ref(&typ),
                             Arc::clone(rp_ref),
@@ -2154,7 +2537,7 @@ impl CrateNamespace {
                             sec_size,
                             false, // .eh_frame section is not globally visible,
                             new_crate.clone(),
-                        ))
+                        ).into_ref()
                     );
 
                     rodata_offset += sec_size.next_multiple_of(sec_align);
@@ -2177,6 +2560,7 @@ impl CrateNamespace {
 
         Ok(SectionMetadata {
             loaded_sections,
+            lazy_sections,
             global_sections,
             tls_sections,
             cls_sections,
@@ -2188,19 +2572,98 @@ impl CrateNamespace {
     /// The second stage of parsing and loading a new kernel crate, 
     /// filling in the missing relocation information in the already-loaded sections. 
     /// It also remaps the `new_crate`'s MappedPages according to each of their section permissions.
+    ///
+    /// If `prelink_path` is the absolute path of this crate's object file, and that file was previously
+    /// relocated against the same dependencies, the cached relocated contents are copied instead;
+    /// otherwise, the relocations performed here are recorded into the prelinking cache.
     fn perform_relocations(
         &self,
         elf_file: &ElfFile,
         new_crate_ref: &StrongCrateRef,
         temp_backup_namespace: Option<&CrateNamespace>,
+        prelink_path: Option<String>,
         kernel_mmi_ref: &MmiRef,
         verbose_log: bool
-    ) -> Result<(), &'static str> {
+    ) -> Result<(), LoadError> {
+        let relocation_start = time::Instant::try_now();
         let mut new_crate = new_crate_ref.lock_as_mut()
             .ok_or("BUG: perform_relocations(): couldn't get exclusive mutable access to new_crate")?;
+        let crate_name = new_crate.crate_name.clone();
         if verbose_log { debug!("=========== moving on to the relocations for crate {} =========", new_crate.crate_name); }
+
+        // If this exact object file was already relocated against the same dependencies,
+        // we can copy its relocated contents instead of resolving every symbol again.
+        let file_hash = prelink_path.is_some().then(|| fnv1a::hash(elf_file.input));
+        if let (Some(path), Some(hash)) = (prelink_path.as_deref(), file_hash) {
+            if let Some(prelinked) = prelink::get(path, elf_file.input.len(), hash) {
+                // A cached dependency may have been resolved in a namespace with a different symbol policy.
+                match prelinked.upgrade_dependencies(|symbol| self.is_symbol_permitted(symbol).then(|| self.get_symbol_internal(symbol)).flatten()) {
+                    Some(dependencies) => {
+                        if verbose_log { debug!("Using cached relocations for crate {}", crate_name); }
+                        self.apply_prelinked_relocations(elf_file, new_crate_ref, &mut new_crate, &prelinked, &dependencies, verbose_log)?;
+                        new_crate.load_stats.num_relocations = prelinked.relocations.iter().map(|(_, r)| r.len()).sum();
+                        new_crate.load_stats.relocation_time = relocation_start.map(|start| start.elapsed()).unwrap_or_default();
+                        return finalize_relocated_crate(&mut new_crate, kernel_mmi_ref);
+                    }
+                    // One or more dependencies have changed (e.g., due to a crate swap), so the cache entry is stale.
+                    None => prelink::remove(path),
+                }
+            }
+        }
+        let prelink_key = prelink_path.zip(file_hash);
+
         let symtab = find_symbol_table(elf_file)?;
 
+        // Before applying any relocations, collect all of the unique foreign symbols that this crate depends on
+        // and resolve them all at once, which avoids repeatedly locking and searching the symbol maps
+        // (and the backup namespace) for every individual relocation entry.
+        let foreign_symbols = {
+            let mut unique_symbols: HashSet<String> = HashSet::new();
+            for sec in elf_file.section_iter().filter(|sec| sec.get_type() == Ok(ShType::Rela) && sec.size() != 0) {
+                if sec.get_name(elf_file).map_or(false, |name| name.starts_with(".rela.debug")) {
+                    continue;
+                }
+                let Ok(SectionData::Rela64(rela_array)) = sec.get_data(elf_file) else { continue };
+                for rela_entry in rela_array {
+                    use xmas_elf::symbol_table::Entry;
+                    let source_sec_entry = &symtab[rela_entry.get_symbol_table_index() as usize];
+                    let source_sec_shndx = source_sec_entry.shndx() as usize;
+                    if new_crate.sections.contains_key(&source_sec_shndx) || new_crate.lazy_sections.contains_key(&source_sec_shndx) {
+                        continue;
+                    }
+                    if let Ok(source_sec_name) = source_sec_entry.get_name(elf_file) {
+                        let source_sec_name = strip_data_rel_ro_prefix(source_sec_name);
+                        if source_sec_name != "__THESEUS_CLS_SIZE" && source_sec_name != "__THESEUS_TLS_SIZE" {
+                            unique_symbols.insert(demangle(source_sec_name).to_string());
+                        }
+                    }
+                }
+            }
+            // Crates that are loaded on demand into this namespace are permitted by its symbol policy,
+            // so the policy can only be checked once the symbols have been resolved.
+            let resolved = self.get_symbols_or_load_unchecked(
+                unique_symbols.iter().map(String::as_str),
+                temp_backup_namespace,
+                kernel_mmi_ref,
+                verbose_log,
+            );
+            self.check_symbol_policy(&crate_name, resolved.keys().map(String::as_str))?;
+            resolved
+        };
+
+        // If using the prelinking cache, record every relocation so that it can be replayed by later loads.
+        let mut prelinked_dependencies: Vec<(String, WeakSectionRef)> = Vec::new();
+        let mut dependency_indices: HashMap<*const LoadedSection, usize> = HashMap::new();
+        let mut prelinked_relocations: Vec<(Shndx, Vec<prelink::CachedRelocation>)> = Vec::new();
+        if prelink_key.is_some() {
+            for (symbol, sec) in &foreign_symbols {
+                dependency_indices.insert(Arc::as_ptr(sec), prelinked_dependencies.len());
+                prelinked_dependencies.push((symbol.clone(), Arc::downgrade(sec)));
+            }
+        }
+
+        let mut num_relocations = 0;
+
         // Fix up the sections that were just loaded, using proper relocation info.
         // Iterate over every non-zero relocation section in the file
         for sec in elf_file.section_iter().filter(|sec| sec.get_type() == Ok(ShType::Rela) && sec.size() != 0) {
@@ -2221,10 +2684,12 @@ impl CrateNamespace {
                 Ok(Rela64(rela_arr)) => rela_arr,
                 _ => {
                     error!("Found Rela section that wasn't able to be parsed as Rela64: {:?}", sec);
-                    return Err("Found Rela section that wasn't able to be parsed as Rela64");
+                    return Err(LoadError::ElfParse { crate_name: crate_name.clone(), reason: "Found Rela section that wasn't able to be parsed as Rela64" });
                 }
             };
 
+            num_relocations += rela_array.len();
+
             // The target section is where we write the relocation data to.
             // The source section is where we get the data from. 
             // There is one target section per rela section (`rela_array`), and one source section per rela_entry in this rela section.
@@ -2232,22 +2697,50 @@ impl CrateNamespace {
 
             // Get the target section (that we already loaded) for this rela_array Rela section.
             let target_sec_shndx = sec.info() as usize;
-            let target_sec = new_crate.sections.get(&target_sec_shndx).ok_or_else(|| {
-                error!("ELF file error: target section was not loaded for Rela section {:?}!", sec.get_name(elf_file));
-                "target section was not loaded for Rela section"
-            })?;
+            let target_sec = match new_crate.sections.get(&target_sec_shndx) {
+                Some(ts) => Arc::clone(ts),
+                None => {
+                    let lazy = new_crate.lazy_sections.get(&target_sec_shndx).copied().ok_or_else(|| {
+                        error!("ELF file error: target section was not loaded for Rela section {:?}!", sec.get_name(elf_file));
+                        LoadError::ElfParse { crate_name: crate_name.clone(), reason: "target section was not loaded for Rela section" }
+                    })?;
+                    // A lazy section needs full metadata in order to be a dependent of a section in another crate.
+                    // Otherwise, we only need its metadata temporarily, in order to write its relocations.
+                    let has_foreign_source = rela_array.iter().any(|rela_entry| {
+                        use xmas_elf::symbol_table::Entry;
+                        let source_sec_shndx = symtab[rela_entry.get_symbol_table_index() as usize].shndx() as usize;
+                        !new_crate.sections.contains_key(&source_sec_shndx) && !new_crate.lazy_sections.contains_key(&source_sec_shndx)
+                    });
+                    let materialized = materialize_lazy_section(
+                        elf_file,
+                        &new_crate,
+                        CowArc::downgrade(new_crate_ref),
+                        target_sec_shndx,
+                        &lazy,
+                    )?.into_ref();
+                    if has_foreign_source {
+                        new_crate.lazy_sections.remove(&target_sec_shndx);
+                        new_crate.sections.insert(target_sec_shndx, Arc::clone(&materialized));
+                    }
+                    materialized
+                }
+            };
+            let relocation_err = |reason| LoadError::Relocation { section: target_sec.name.clone(), reason };
 
             let mut target_sec_data_was_modified = false;
 
             let mut target_sec_dependencies: Vec<StrongDependency> = Vec::new();
             #[cfg(internal_deps)]
             let mut target_sec_internal_dependencies: Vec<InternalDependency> = Vec::new();
+            let mut cached_relocations: Vec<prelink::CachedRelocation> = Vec::new();
             {
                 let mut target_sec_mapped_pages = target_sec.mapped_pages.lock();
                 let target_sec_slice: &mut [u8] = target_sec_mapped_pages.as_slice_mut(
                     0,
                     target_sec.mapped_pages_offset + target_sec.size,
                 )?;
+                // The address that relocations are actually written to, which is what PC-relative relocations depend on.
+                let target_sec_start = VirtualAddress::new_canonical(target_sec_slice.as_ptr() as usize + target_sec.mapped_pages_offset);
 
                 // iterate through each relocation entry in the relocation array for the target_sec
                 for rela_entry in rela_array {
@@ -2270,32 +2763,25 @@ impl CrateNamespace {
                         //     source_sec_entry.shndx(), source_sec_entry.value(), source_sec_entry.size());
                     }
 
-                    let mut source_and_target_in_same_crate = false;
-
                     // We first try to get the source section from loaded_sections, which works if the section is in the crate currently being loaded.
-                    let source_sec = match new_crate.sections.get(&source_sec_shndx) {
-                        Some(ss) => {
-                            source_and_target_in_same_crate = true;
-                            Ok(ss.clone())
-                        }
+                    // Only a source section in another crate (a foreign section) is needed beyond its address.
+                    let local_source_sec_vaddr = new_crate.sections.get(&source_sec_shndx).map(|ss| ss.virt_addr)
+                        .or_else(|| new_crate.lazy_sections.get(&source_sec_shndx).map(|lazy| lazy.virt_addr));
+                    let (source_sec_vaddr, foreign_source_sec) = match local_source_sec_vaddr {
+                        Some(vaddr) => Ok((vaddr, None)),
 
                         // If we couldn't get the section based on its shndx, it means that the source section wasn't in the crate currently being loaded.
                         // Thus, we must get the source section's name and check our list of foreign crates to see if it's there.
                         // At this point, there's no other way to search for the source section besides its name.
                         None => {
                             if let Ok(source_sec_name) = source_sec_entry.get_name(elf_file) {
-                                const DATARELRO: &str = ".data.rel.ro.";
-                                let source_sec_name = if source_sec_name.starts_with(DATARELRO) {
-                                    source_sec_name.get(DATARELRO.len() ..).ok_or("Couldn't get name of .data.rel.ro. section")?
-                                } else {
-                                    source_sec_name
-                                };
+                                let source_sec_name = strip_data_rel_ro_prefix(source_sec_name);
 
                                 // See `cls_macros` for more details.
                                 if source_sec_name == "__THESEUS_CLS_SIZE" {
                                     #[cfg(target_arch = "aarch64")]
                                     {
-                                        return Err("encountered `__THESEUS_CLS_SIZE` relocation on AArch64");
+                                        return Err(relocation_err("encountered `__THESEUS_CLS_SIZE` relocation on AArch64"));
                                     }
                                     #[cfg(target_arch = "x86_64")]
                                     {
@@ -2307,7 +2793,15 @@ impl CrateNamespace {
                                             target_sec.mapped_pages_offset,
                                             cls_size,
                                             verbose_log,
-                                        )?;
+                                        ).map_err(relocation_err)?;
+                                        if prelink_key.is_some() {
+                                            cached_relocations.push(prelink::CachedRelocation {
+                                                entry: relocation_entry,
+                                                source: prelink::RelocationSource::Absolute(cls_size),
+                                                source_vaddr: cls_size,
+                                                target_vaddr: target_sec_start + relocation_entry.offset,
+                                            });
+                                        }
                                         continue;
                                     }
                                 } else if source_sec_name == "__THESEUS_TLS_SIZE" {
@@ -2323,23 +2817,31 @@ impl CrateNamespace {
                                         target_sec.mapped_pages_offset,
                                         tls_size,
                                         verbose_log,
-                                    )?;
+                                    ).map_err(relocation_err)?;
+                                    if prelink_key.is_some() {
+                                        cached_relocations.push(prelink::CachedRelocation {
+                                            entry: relocation_entry,
+                                            source: prelink::RelocationSource::Absolute(tls_size),
+                                            source_vaddr: tls_size,
+                                            target_vaddr: target_sec_start + relocation_entry.offset,
+                                        });
+                                    }
                                     continue;
                                 }
                                 
                                 let demangled = demangle(source_sec_name).to_string();
 
-                                // search for the symbol's demangled name in the kernel's symbol map
-                                self.get_symbol_or_load(&demangled, temp_backup_namespace, kernel_mmi_ref, verbose_log)
-                                    .upgrade()
-                                    .ok_or("Couldn't get symbol for foreign relocation entry, nor load its containing crate")
+                                // the symbol was already resolved (or loaded) above, along with all other foreign symbols.
+                                foreign_symbols.get(demangled.as_str())
+                                    .map(|ss| (ss.virt_addr, Some(Arc::clone(ss))))
+                                    .ok_or_else(|| LoadError::MissingSymbol { crate_name: crate_name.clone(), symbol: demangled })
                             }
                             else {
                                 let _source_sec_header = source_sec_entry
                                     .get_section_header(elf_file, rela_entry.get_symbol_table_index() as usize)
                                     .and_then(|s| s.get_name(elf_file));
                                 error!("Couldn't get name of source section [{}] {:?}, needed for non-local relocation entry", source_sec_shndx, _source_sec_header);
-                                Err("Couldn't get source section's name, needed for non-local relocation entry")
+                                Err(LoadError::ElfParse { crate_name: crate_name.clone(), reason: "Couldn't get source section's name, needed for non-local relocation entry" })
                             }
                         }
                     }?;
@@ -2349,34 +2851,50 @@ impl CrateNamespace {
                         relocation_entry,
                         target_sec_slice,
                         target_sec.mapped_pages_offset,
-                        source_sec.virt_addr + source_sec_value,
+                        source_sec_vaddr + source_sec_value,
                         verbose_log
-                    )?;
+                    ).map_err(relocation_err)?;
                     target_sec_data_was_modified = true;
 
-                    if source_and_target_in_same_crate {
-                        // We keep track of relocation information so that we can be aware of and faithfully reconstruct 
-                        // inter-section dependencies even within the same crate.
-                        // This is necessary for doing a deep copy of the crate in memory, 
-                        // without having to re-parse that crate's ELF file (and requiring the ELF file to still exist)
-                        #[cfg(internal_deps)]
-                        target_sec_internal_dependencies.push(InternalDependency::new(relocation_entry, source_sec_shndx))
+                    if prelink_key.is_some() {
+                        let source = if let Some(ref source_sec) = foreign_source_sec {
+                            let dependency = *dependency_indices.get(&Arc::as_ptr(source_sec))
+                                .ok_or_else(|| relocation_err("BUG: foreign source section was not among the resolved foreign symbols"))?;
+                            prelink::RelocationSource::Foreign { dependency, value: source_sec_value }
+                        } else {
+                            prelink::RelocationSource::Internal { shndx: source_sec_shndx, value: source_sec_value }
+                        };
+                        cached_relocations.push(prelink::CachedRelocation {
+                            entry: relocation_entry,
+                            source,
+                            source_vaddr: source_sec_vaddr + source_sec_value,
+                            target_vaddr: target_sec_start + relocation_entry.offset,
+                        });
                     }
-                    else {
+
+                    if let Some(source_sec) = foreign_source_sec {
                         // tell the source_sec that the target_sec is dependent upon it
                         let weak_dep = WeakDependent {
-                            section: Arc::downgrade(target_sec),
+                            section: Arc::downgrade(&target_sec),
                             relocation: relocation_entry,
                         };
                         source_sec.inner.write().sections_dependent_on_me.push(weak_dep);
 
                         // tell the target_sec that it has a strong dependency on the source_sec
                         let strong_dep = StrongDependency {
-                            section: Arc::clone(&source_sec),
+                            section: source_sec,
                             relocation: relocation_entry,
                         };
                         target_sec_dependencies.push(strong_dep);
                     }
+                    else {
+                        // We keep track of relocation information so that we can be aware of and faithfully reconstruct 
+                        // inter-section dependencies even within the same crate.
+                        // This is necessary for doing a deep copy of the crate in memory, 
+                        // without having to re-parse that crate's ELF file (and requiring the ELF file to still exist)
+                        #[cfg(internal_deps)]
+                        target_sec_internal_dependencies.push(InternalDependency::new(relocation_entry, source_sec_shndx))
+                    }
                 }
             }
 
@@ -2397,40 +2915,169 @@ impl CrateNamespace {
                 #[cfg(internal_deps)]
                 target_sec_inner.internal_dependencies.append(&mut target_sec_internal_dependencies);
             }
+
+            if prelink_key.is_some() {
+                prelinked_relocations.push((target_sec_shndx, cached_relocations));
+            }
         }
         // here, we're done with handling all the relocations in this entire crate
+        new_crate.load_stats.num_relocations = num_relocations;
+        new_crate.load_stats.relocation_time = relocation_start.map(|start| start.elapsed()).unwrap_or_default();
+
+        // Record the relocated contents before the crate's regions are remapped as read-only.
+        let prelinked_images = prelink_key.as_ref().and_then(|_| Some([
+            region_image(&new_crate.text_pages).ok()?,
+            region_image(&new_crate.rodata_pages).ok()?,
+            region_image(&new_crate.data_pages).ok()?,
+        ]));
+
+        finalize_relocated_crate(&mut new_crate, kernel_mmi_ref)?;
+
+        if let (Some((path, file_hash)), Some(images)) = (prelink_key, prelinked_images) {
+            prelink::insert(path, prelink::PrelinkedCrate {
+                file_len: elf_file.input.len(),
+                file_hash,
+                dependencies: prelinked_dependencies,
+                relocations: prelinked_relocations,
+                images,
+            });
+        }
+        Ok(())
+    }
 
 
-        // We need to remap each section's mapped pages with the proper permission bits, 
-        // since we initially mapped them all as writable.
-        if let Some(ref tp) = new_crate.text_pages {
-            tp.0.lock().remap(&mut kernel_mmi_ref.lock().page_table, TEXT_SECTION_FLAGS)?;
-        }
-        if let Some(ref rp) = new_crate.rodata_pages {
-            rp.0.lock().remap(&mut kernel_mmi_ref.lock().page_table, RODATA_SECTION_FLAGS)?;
+    /// Copies the relocated contents previously recorded in the prelinking cache into the given `new_crate`,
+    /// which must have been loaded from the same object file that those contents were recorded for.
+    ///
+    /// Only the relocations whose values depend on where `new_crate` was loaded are rewritten,
+    /// but every relocation's dependency metadata is set up as usual.
+    ///
+    /// The given `dependencies` are the foreign sections that `new_crate` depends on,
+    /// in the same order as `prelinked.dependencies`.
+    fn apply_prelinked_relocations(
+        &self,
+        elf_file: &ElfFile,
+        new_crate_ref: &StrongCrateRef,
+        new_crate: &mut LoadedCrate,
+        prelinked: &prelink::PrelinkedCrate,
+        dependencies: &[StrongSectionRef],
+        verbose_log: bool,
+    ) -> Result<(), LoadError> {
+        let [text_image, rodata_image, data_image] = &prelinked.images;
+        for (pages, image) in [
+            (&new_crate.text_pages,   text_image),
+            (&new_crate.rodata_pages, rodata_image),
+            (&new_crate.data_pages,   data_image),
+        ] {
+            copy_region_image(pages, image.as_ref()).map_err(|reason| LoadError::Mapping {
+                crate_name: new_crate.crate_name.clone(),
+                reason,
+            })?;
         }
-        // data/bss sections are already mapped properly, since they're supposed to be writable
 
+        for (target_sec_shndx, cached_relocations) in &prelinked.relocations {
+            let target_sec = match new_crate.sections.get(target_sec_shndx) {
+                Some(ts) => Arc::clone(ts),
+                // See the corresponding comment in `perform_relocations()`.
+                None => {
+                    let lazy = new_crate.lazy_sections.get(target_sec_shndx).copied().ok_or_else(|| LoadError::ElfParse {
+                        crate_name: new_crate.crate_name.clone(),
+                        reason: "target section of a cached relocation was not loaded",
+                    })?;
+                    let has_foreign_source = cached_relocations.iter()
+                        .any(|cached| matches!(cached.source, prelink::RelocationSource::Foreign { .. }));
+                    let materialized = materialize_lazy_section(
+                        elf_file,
+                        new_crate,
+                        CowArc::downgrade(new_crate_ref),
+                        *target_sec_shndx,
+                        &lazy,
+                    )?.into_ref();
+                    if has_foreign_source {
+                        new_crate.lazy_sections.remove(target_sec_shndx);
+                        new_crate.sections.insert(*target_sec_shndx, Arc::clone(&materialized));
+                    }
+                    materialized
+                }
+            };
+            let relocation_err = |reason| LoadError::Relocation { section: target_sec.name.clone(), reason };
 
-        // By default, we can safely remove the metadata for all private (non-global) .rodata sections
-        // that do not have any strong dependencies (its `sections_i_depend_on` list is empty).
-        // If you want all sections to be kept, e.g., for debugging, you can set the below cfg option.
-        #[cfg(not(keep_private_rodata))]
-        {
-            new_crate.sections.retain(|_shndx, sec| {
-                let should_remove = !sec.global
-                    && sec.typ == SectionType::Rodata
-                    && sec.inner.read().sections_i_depend_on.is_empty();
+            let mut target_sec_dependencies: Vec<StrongDependency> = Vec::new();
+            #[cfg(internal_deps)]
+            let mut target_sec_internal_dependencies: Vec<InternalDependency> = Vec::new();
+            {
+                let mut target_sec_mapped_pages = target_sec.mapped_pages.lock();
+                let target_sec_slice: &mut [u8] = target_sec_mapped_pages.as_slice_mut(
+                    0,
+                    target_sec.mapped_pages_offset + target_sec.size,
+                )?;
+                // See the corresponding comment in `perform_relocations()`.
+                let target_sec_start = VirtualAddress::new_canonical(target_sec_slice.as_ptr() as usize + target_sec.mapped_pages_offset);
+
+                for cached in cached_relocations {
+                    let (source_vaddr, foreign_source_sec) = match cached.source {
+                        prelink::RelocationSource::Absolute(vaddr) => (vaddr, None),
+                        prelink::RelocationSource::Internal { shndx, value } => {
+                            let source_sec_vaddr = new_crate.sections.get(&shndx).map(|ss| ss.virt_addr)
+                                .or_else(|| new_crate.lazy_sections.get(&shndx).map(|lazy| lazy.virt_addr))
+                                .ok_or_else(|| relocation_err("source section of a cached relocation was not loaded"))?;
+                            #[cfg(internal_deps)]
+                            target_sec_internal_dependencies.push(InternalDependency::new(cached.entry, shndx));
+                            (source_sec_vaddr + value, None)
+                        }
+                        prelink::RelocationSource::Foreign { dependency, value } => {
+                            let source_sec = dependencies.get(dependency)
+                                .ok_or_else(|| relocation_err("BUG: cached relocation referred to a nonexistent dependency"))?;
+                            (source_sec.virt_addr + value, Some(source_sec))
+                        }
+                    };
+                    // The copied contents already hold the correct value for most relocations,
+                    // e.g., PC-relative ones within the same region, or absolute ones to a foreign section.
+                    if cached.needs_rewrite(source_vaddr, target_sec_start + cached.entry.offset) {
+                        write_relocation(
+                            cached.entry,
+                            target_sec_slice,
+                            target_sec.mapped_pages_offset,
+                            source_vaddr,
+                            verbose_log
+                        ).map_err(relocation_err)?;
+                    }
 
-                // For an element to be removed, this closure should return `false`.
-                !should_remove
-            });
-        }
+                    if let Some(source_sec) = foreign_source_sec {
+                        // tell the source_sec that the target_sec is dependent upon it
+                        source_sec.inner.write().sections_dependent_on_me.push(WeakDependent {
+                            section: Arc::downgrade(&target_sec),
+                            relocation: cached.entry,
+                        });
+                        // tell the target_sec that it has a strong dependency on the source_sec
+                        target_sec_dependencies.push(StrongDependency {
+                            section: Arc::clone(source_sec),
+                            relocation: cached.entry,
+                        });
+                    }
+                }
+            }
+
+            // The copied contents of this section include its relocations, so they have changed.
+            // See the corresponding comment in `perform_relocations()`.
+            if !cached_relocations.is_empty() &&
+                (target_sec.typ == SectionType::TlsData || target_sec.typ == SectionType::TlsBss)
+            {
+                self.tls_initializer.lock().invalidate();
+            }
 
+            {
+                let mut target_sec_inner = target_sec.inner.write();
+                target_sec_inner.sections_i_depend_on.append(&mut target_sec_dependencies);
+                #[cfg(internal_deps)]
+                target_sec_inner.internal_dependencies.append(&mut target_sec_internal_dependencies);
+            }
+        }
         Ok(())
     }
 
 
+
     /// Adds the given symbol to this namespace's symbol map.
     /// If the symbol already exists in the symbol map, this replaces the existing symbol with the new one, warning if they differ in size.
     /// Returns true if the symbol was added, and false if it already existed and thus was merely replaced.
@@ -2511,6 +3158,7 @@ impl CrateNamespace {
                 }
             }
         }
+        drop(existing_map);
 
         count
     }
@@ -2590,6 +3238,9 @@ impl CrateNamespace {
     /// However, if `search_all_section_types` is `true`, both the read-only and read-write sections
     /// will be included in the search, e.g., `.rodata`, `.data`, `.bss`. 
     ///
+    /// If the address is within one of a crate's `lazy_sections`, that section's metadata is
+    /// created from the crate's object file, and the returned section isn't part of the crate's `sections`.
+    ///
     /// # Usage
     /// This is mostly useful for printing symbol names for a stack trace (backtrace).
     /// It is also similar in functionality to the tool `addr2line`, 
@@ -2647,6 +3298,19 @@ impl CrateNamespace {
                 }
             }
         }
+
+        // Third, if no section was found, the address may be in a section whose metadata wasn't created
+        // because the crate was loaded in lazy section metadata mode.
+        if merged_section_and_offset.is_none() {
+            if let Some((shndx, lazy)) = crate_locked.lazy_section_containing(virt_addr) {
+                if lazy.typ == SectionType::Text || search_all_section_types {
+                    match materialize_lazy_section_from_object_file(&containing_crate, &crate_locked, shndx, lazy) {
+                        Ok(sec) => merged_section_and_offset = Some((sec, virt_addr.value() - lazy.virt_addr.value())),
+                        Err(_e) => warn!("Couldn't create metadata for lazy section [{}] in crate {:?}: {}", shndx, crate_locked.crate_name, _e),
+                    }
+                }
+            }
+        }
         merged_section_and_offset
     }
 
@@ -2669,7 +3333,7 @@ impl CrateNamespace {
     /// Finds the corresponding `LoadedSection` reference for the given fully-qualified symbol string.
     /// Searches this namespace first, and then its recursive namespace as well.
     pub fn get_symbol(&self, demangled_full_symbol: &str) -> WeakSectionRef {
-        self.get_symbol_internal(demangled_full_symbol).unwrap_or_default()
+        self.get_symbol_internal(demangled_full_symbol).unwrap_or_else(empty_weak_section_ref)
     }
 
 
@@ -2700,12 +3364,31 @@ impl CrateNamespace {
     ///   for the missing symbol.
     ///   If `temp_backup_namespace` is `None`, then only this namespace (and its recursive namespaces) will be searched.
     /// * `kernel_mmi_ref`: a reference to the kernel's `MemoryManagementInfo`, which must not be locked.
+    ///
+    /// If this namespace has a [`SymbolPolicy`], the symbol is checked against it after being resolved,
+    /// such that a symbol whose crate was loaded into this namespace on demand is permitted.
     pub fn get_symbol_or_load(
         &self,
         demangled_full_symbol: &str,
         temp_backup_namespace: Option<&CrateNamespace>,
         kernel_mmi_ref: &MmiRef,
         verbose_log: bool
+    ) -> WeakSectionRef {
+        let weak_sec = self.get_symbol_or_load_unchecked(demangled_full_symbol, temp_backup_namespace, kernel_mmi_ref, verbose_log);
+        if weak_sec.strong_count() > 0 && !self.is_symbol_permitted(demangled_full_symbol) {
+            warn!("Symbol \"{}\" is forbidden by the symbol policy of namespace {:?}.", demangled_full_symbol, self.name);
+            return empty_weak_section_ref();
+        }
+        weak_sec
+    }
+
+    /// The same as [`get_symbol_or_load()`](Self::get_symbol_or_load), but without checking this namespace's symbol policy.
+    fn get_symbol_or_load_unchecked(
+        &self,
+        demangled_full_symbol: &str,
+        temp_backup_namespace: Option<&CrateNamespace>,
+        kernel_mmi_ref: &MmiRef,
+        verbose_log: bool
     ) -> WeakSectionRef {
         // First, see if the section for the given symbol is already available and loaded
         // in either this namespace or its recursive namespace
@@ -2725,7 +3408,7 @@ impl CrateNamespace {
 
         // Try to fuzzy match the symbol to see if a single match for it has already been loaded into the backup namespace.
         // This is basically the same code as the above temp_backup_namespace conditional, but checks to ensure there aren't multiple fuzzy matches.
-        if self.fuzzy_symbol_matching {
+        if self.fuzzy_symbol_matching() {
             if let Some(backup) = temp_backup_namespace {
                 // info!("Symbol \"{}\" not initially found, attempting to load it from backup namespace {:?}", 
                 //     demangled_full_symbol, backup.name);
@@ -2741,7 +3424,117 @@ impl CrateNamespace {
         } else {
             #[cfg(not(loscd_eval))]
             warn!("Symbol \"{}\" not found. Try loading the specific crate manually first.", demangled_full_symbol);
-            Weak::default() // same as returning None, since it must be upgraded to an Arc before being used
+            empty_weak_section_ref() // same as returning None, since it must be upgraded to an Arc before being used
+        }
+    }
+
+
+    /// A batched version of [`get_symbol_or_load()`](#method.get_symbol_or_load) that resolves
+    /// many symbols at once, returning a map from each symbol that was found to its section.
+    /// Symbols that could not be found or loaded are absent from the returned map.
+    ///
+    /// This is much faster than invoking `get_symbol_or_load()` for each symbol individually,
+    /// because each symbol map (of this namespace, its recursive namespaces,
+    /// and the `temp_backup_namespace`) is locked and searched only once for all symbols.
+    /// Only the symbols that remain missing after that are resolved one-by-one, 
+    /// i.e., by fuzzy matching or by loading their containing crates.
+    ///
+    /// The arguments are the same as those of `get_symbol_or_load()`.
+    /// Symbols that are forbidden by this namespace's symbol policy are also absent from the returned map.
+    pub fn get_symbols_or_load<'s, I>(
+        &self,
+        demangled_full_symbols: I,
+        temp_backup_namespace: Option<&CrateNamespace>,
+        kernel_mmi_ref: &MmiRef,
+        verbose_log: bool,
+    ) -> HashMap<String, StrongSectionRef>
+        where I: IntoIterator<Item = &'s str>
+    {
+        let mut resolved = self.get_symbols_or_load_unchecked(demangled_full_symbols, temp_backup_namespace, kernel_mmi_ref, verbose_log);
+        resolved.retain(|sym, _| self.is_symbol_permitted(sym));
+        resolved
+    }
+
+    /// The same as [`get_symbols_or_load()`](Self::get_symbols_or_load), but without checking this namespace's symbol policy.
+    fn get_symbols_or_load_unchecked<'s, I>(
+        &self,
+        demangled_full_symbols: I,
+        temp_backup_namespace: Option<&CrateNamespace>,
+        kernel_mmi_ref: &MmiRef,
+        verbose_log: bool,
+    ) -> HashMap<String, StrongSectionRef>
+        where I: IntoIterator<Item = &'s str>
+    {
+        let mut resolved: HashMap<String, StrongSectionRef> = HashMap::new();
+        let mut unresolved: Vec<&'s str> = demangled_full_symbols.into_iter().collect();
+
+        // First, search this namespace and its recursive namespaces.
+        self.resolve_symbols_from_maps(&mut unresolved, |sym, sec| {
+            resolved.insert(String::from(sym), sec);
+        });
+
+        // Second, search the backup namespace using exact matching,
+        // and add each matching section and its parent crate into this namespace all at once.
+        if let Some(backup) = temp_backup_namespace && !unresolved.is_empty() {
+            let mut found_in_backup: Vec<StrongSectionRef> = Vec::new();
+            backup.resolve_symbols_from_maps(&mut unresolved, |sym, sec| {
+                found_in_backup.push(sec.clone());
+                resolved.insert(String::from(sym), sec);
+            });
+            if !found_in_backup.is_empty() {
+                self.add_symbols(found_in_backup.iter(), verbose_log);
+                let mut crate_tree = self.crate_tree.lock();
+                for sec in &found_in_backup {
+                    if let Some(parent_crate_ref) = sec.parent_crate.upgrade() {
+                        let parent_crate_name = parent_crate_ref.lock_as_ref().crate_name.clone();
+                        crate_tree.insert(parent_crate_name, parent_crate_ref);
+                    } else {
+                        error!("BUG: Found symbol {:?} in backup namespace, but unexpectedly couldn't get its parent crate!", sec.name);
+                    }
+                }
+                drop(crate_tree);
+                #[cfg(not(loscd_eval))]
+                info!("Resolved {} symbols from backup namespace {:?} for namespace {:?}", found_in_backup.len(), backup.name, self.name);
+            }
+        }
+
+        // Finally, fall back to resolving each remaining symbol individually,
+        // which handles fuzzy matching and loading the crates that contain missing symbols.
+        // Loading one crate may also provide other missing symbols, which `get_symbol_or_load()` checks first.
+        for sym in unresolved {
+            if let Some(sec) = self.get_symbol_or_load_unchecked(sym, temp_backup_namespace, kernel_mmi_ref, verbose_log).upgrade() {
+                resolved.insert(String::from(sym), sec);
+            }
+        }
+
+        resolved
+    }
+
+
+    /// Searches the symbol maps of this namespace and its recursive namespaces for each symbol in `symbols`,
+    /// locking each symbol map only once.
+    ///
+    /// For each symbol that is found, `on_found` is invoked with that symbol and its section,
+    /// after which it is removed from `symbols`.
+    fn resolve_symbols_from_maps<'s, F>(&self, symbols: &mut Vec<&'s str>, mut on_found: F)
+        where F: FnMut(&'s str, StrongSectionRef)
+    {
+        let mut namespace = Some(self);
+        while let Some(ns) = namespace {
+            if symbols.is_empty() {
+                break;
+            }
+            let symbol_map = ns.symbol_map.lock();
+            symbols.retain(|sym| {
+                match symbol_map.get(sym.as_bytes()).and_then(|weak_sec| weak_sec.upgrade()) {
+                    Some(sec) => {
+                        on_found(*sym, sec);
+                        false
+                    }
+                    None => true,
+                }
+            });
+            namespace = ns.recursive_namespace.as_deref();
         }
     }
 
@@ -2868,6 +3661,7 @@ impl CrateNamespace {
 
                 match ns_of_crate_file.load_crate(&potential_crate_file, temp_backup_namespace, kernel_mmi_ref, verbose_log) {
                     Ok((_new_crate_ref, _num_new_syms)) => {
+                        self.record_missing_symbol_load(&potential_crate_file);
                         // try again to find the missing symbol, now that we've loaded the missing crate
                         if let Some(sec) = ns_of_crate_file.get_symbol_internal(demangled_full_symbol) {
                             return Some(sec);
@@ -2960,7 +3754,7 @@ impl CrateNamespace {
     ///   (note the trailing "`::`").
     pub fn get_symbol_starting_with(&self, symbol_prefix: &str) -> WeakSectionRef {
         self.get_symbol_starting_with_internal(symbol_prefix)
-            .unwrap_or_default()
+            .unwrap_or_else(empty_weak_section_ref)
     }
 
     /// This is an internal version of method: [`get_symbol_starting_with()`](#method.get_symbol_starting_with) 
@@ -3011,7 +3805,8 @@ impl CrateNamespace {
 /// A convenience wrapper around a new crate's data items that are generated
 /// when iterating over and loading its sections.
 struct SectionMetadata {
-    loaded_sections: HashMap<usize, Arc<LoadedSection>>,
+    loaded_sections: HashMap<usize, StrongSectionRef>,
+    lazy_sections:   BTreeMap<usize, LazySection>,
     global_sections: BTreeSet<usize>,
     tls_sections:    BTreeSet<usize>,
     cls_sections:    BTreeSet<usize>,
@@ -3139,7 +3934,7 @@ fn allocate_section_pages(elf_file: &ElfFile, kernel_mmi_ref: &MmiRef) -> Result
 
     // Allocate contiguous virtual memory pages for each section and map them to random frames as writable.
     // We must allocate these pages separately because they use different flags.
-    let alloc_sec = |size_in_bytes: usize, within_range: Option<&PageRange>, flags: PteFlags| {
+    let alloc_sec = |size_in_bytes: usize, within_range: Option<&PageRange>, flags: PteFlags| -> Result<MappedPages, &'static str> {
         let allocated_pages = if let Some(range) = within_range {
             allocate_pages_by_bytes_in_range(size_in_bytes, range)
                 .map_err(|_| "Couldn't allocate pages in text section address range")?
@@ -3148,10 +3943,13 @@ fn allocate_section_pages(elf_file: &ElfFile, kernel_mmi_ref: &MmiRef) -> Result
                 .ok_or("Couldn't allocate pages for new section")?
         };
 
-        kernel_mmi_ref.lock().page_table.map_allocated_pages(
+        let mut mp = kernel_mmi_ref.lock().page_table.map_allocated_pages(
             allocated_pages,
             flags.valid(true).writable(true)
-        )
+        )?;
+        // Section pages are attributed to the crate being loaded, not to `mod_mgmt`.
+        mp.disown();
+        Ok(mp)
     };
 
     let executable_pages = if exec_bytes > 0 {
@@ -3234,6 +4032,138 @@ pub fn find_symbol_table<'e>(elf_file: &'e ElfFile)
 }
 
 
+/// Returns the given relocation source section name without its `.data.rel.ro.` prefix, if any.
+fn strip_data_rel_ro_prefix(source_sec_name: &str) -> &str {
+    const DATARELRO: &str = ".data.rel.ro.";
+    source_sec_name.strip_prefix(DATARELRO).unwrap_or(source_sec_name)
+}
+
+
+/// Creates the full `LoadedSection` metadata for the given lazy section of `krate`,
+/// which was loaded from the given `elf_file`.
+///
+/// See [`CrateNamespace::set_lazy_section_metadata()`].
+fn materialize_lazy_section(
+    elf_file: &ElfFile,
+    krate: &LoadedCrate,
+    parent_crate: WeakCrateRef,
+    shndx: Shndx,
+    lazy: &LazySection,
+) -> Result<LoadedSection, &'static str> {
+    let (pages, prefix) = match lazy.typ {
+        SectionType::Text => (krate.text_pages.as_ref(), ".text."),
+        _                 => (krate.rodata_pages.as_ref(), ".rodata."),
+    };
+    let (mapped_pages, _) = pages.ok_or("BUG: crate has no pages to hold its lazy section")?;
+    let sec_name = elf_file.section_header(shndx as u16)
+        .and_then(|sec| sec.get_name(elf_file))
+        .map_err(|_| "couldn't get the name of a lazy section")?;
+    let name = sec_name.strip_prefix(prefix).ok_or("lazy section's name didn't have the expected prefix")?;
+    Ok(LoadedSection::new(
+        lazy.typ,
+        demangle(name).to_string().as_str().into(),
+        Arc::clone(mapped_pages),
+        lazy.mapped_pages_offset,
+        lazy.virt_addr,
+        lazy.size,
+        false, // lazy sections are never global
+        parent_crate,
+    ))
+}
+
+
+/// Like [`materialize_lazy_section()`], but obtains the ELF file from `krate`'s object file.
+///
+/// The returned section isn't added to `krate`, as it was only needed temporarily.
+fn materialize_lazy_section_from_object_file(
+    crate_ref: &StrongCrateRef,
+    krate: &LoadedCrate,
+    shndx: Shndx,
+    lazy: &LazySection,
+) -> Result<StrongSectionRef, &'static str> {
+    let file = krate.object_file.lock();
+    let bytes: &[u8] = file.as_mapping()?.as_slice(0, file.len())?;
+    let elf_file = ElfFile::new(bytes)?;
+    materialize_lazy_section(&elf_file, krate, CowArc::downgrade(crate_ref), shndx, lazy).map(LoadedSection::into_ref)
+}
+
+
+/// Records the number of symbols that the given newly-loaded crate added to its namespace.
+fn record_symbols_added(new_crate_ref: &StrongCrateRef, symbols_added: usize) {
+    if let Some(mut new_crate) = new_crate_ref.lock_as_mut() {
+        new_crate.load_stats.symbols_added = symbols_added;
+    }
+}
+
+
+/// Returns a copy of the contents of the given crate memory region, e.g., its `text_pages`,
+/// for recording into the prelinking cache, or `None` if the crate has no such region.
+fn region_image(
+    pages: &Option<(Arc<Mutex<MappedPages>>, Range<VirtualAddress>)>,
+) -> Result<Option<prelink::RegionImage>, &'static str> {
+    let Some((mp, range)) = pages.as_ref() else { return Ok(None) };
+    let mp = mp.lock();
+    let offset = range.start.value() - mp.start_address().value();
+    let bytes = mp.as_slice::<u8>(offset, range.end.value() - range.start.value())?;
+    Ok(Some(prelink::RegionImage { bytes: bytes.to_vec() }))
+}
+
+/// Copies the given `image` recorded by [`region_image()`] into the given crate memory region,
+/// which must have the same size as the region that the image was recorded from.
+fn copy_region_image(
+    pages: &Option<(Arc<Mutex<MappedPages>>, Range<VirtualAddress>)>,
+    image: Option<&prelink::RegionImage>,
+) -> Result<(), &'static str> {
+    match (pages, image) {
+        (None, None) => Ok(()),
+        (Some((mp, range)), Some(image)) if range.end.value() - range.start.value() == image.bytes.len() => {
+            let mut mp = mp.lock();
+            let offset = range.start.value() - mp.start_address().value();
+            mp.as_slice_mut::<u8>(offset, image.bytes.len())?.copy_from_slice(&image.bytes);
+            Ok(())
+        }
+        _ => Err("BUG: a crate's memory regions differ from those recorded in the prelinking cache"),
+    }
+}
+
+
+/// Finishes relocating the given `new_crate` by remapping its sections with their proper permissions
+/// and removing the metadata of private sections that are no longer needed.
+fn finalize_relocated_crate(new_crate: &mut LoadedCrate, kernel_mmi_ref: &MmiRef) -> Result<(), LoadError> {
+    // We need to remap each section's mapped pages with the proper permission bits, 
+    // since we initially mapped them all as writable.
+    if let Some(ref tp) = new_crate.text_pages {
+        tp.0.lock().remap(&mut kernel_mmi_ref.lock().page_table, TEXT_SECTION_FLAGS)
+            .map_err(|reason| LoadError::Mapping { crate_name: new_crate.crate_name.clone(), reason })?;
+    }
+    if let Some(ref rp) = new_crate.rodata_pages {
+        rp.0.lock().remap(&mut kernel_mmi_ref.lock().page_table, RODATA_SECTION_FLAGS)
+            .map_err(|reason| LoadError::Mapping { crate_name: new_crate.crate_name.clone(), reason })?;
+    }
+    // data/bss sections are already mapped properly, since they're supposed to be writable
+
+
+    // By default, we can safely remove the metadata for all private (non-global) .rodata sections
+    // that do not have any strong dependencies (its `sections_i_depend_on` list is empty).
+    // If you want all sections to be kept, e.g., for debugging, you can set the below cfg option.
+    #[cfg(not(keep_private_rodata))]
+    {
+        new_crate.sections.retain(|_shndx, sec| {
+            let should_remove = !sec.global
+                && sec.typ == SectionType::Rodata
+                && sec.inner.read().sections_i_depend_on.is_empty();
+
+            // For an element to be removed, this closure should return `false`.
+            !should_remove
+        });
+        // Lazy .rodata sections are always private and never have strong dependencies.
+        new_crate.lazy_sections.retain(|_shndx, lazy| lazy.typ != SectionType::Rodata);
+    }
+
+    Ok(())
+}
+
+
 /// Convenience function for calculating the address range of a MappedPages object.
 fn mp_range(mp_ref: &Arc<Mutex<MappedPages>>) -> Range<VirtualAddress> {
     let mp = mp_ref.lock();
@@ -0,0 +1,77 @@
+//! Aggregate reports of the per-crate [`CrateLoadStats`] in a [`CrateNamespace`].
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::fmt;
+use crate::{CrateLoadStats, CrateNamespace, StrRef};
+
+/// A summary of the load statistics of all crates in a namespace.
+///
+/// Obtain one with [`CrateNamespace::crate_load_report()`].
+#[derive(Clone, Debug)]
+pub struct CrateLoadReport {
+    /// The load statistics of each crate, in order of crate name.
+    pub crates: Vec<(StrRef, CrateLoadStats)>,
+    /// The sum of the load statistics of all crates.
+    pub totals: CrateLoadStats,
+}
+
+impl CrateLoadReport {
+    /// Returns the number of crates included in this report.
+    pub fn num_crates(&self) -> usize {
+        self.crates.len()
+    }
+
+    /// Returns up to `count` crates that took the longest time to load, slowest first.
+    pub fn slowest(&self, count: usize) -> Vec<&(StrRef, CrateLoadStats)> {
+        let mut by_time: Vec<&(StrRef, CrateLoadStats)> = self.crates.iter().collect();
+        by_time.sort_unstable_by(|(_, a), (_, b)| b.total_time().cmp(&a.total_time()));
+        by_time.truncate(count);
+        by_time
+    }
+}
+
+impl fmt::Display for CrateLoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<40} {:>10} {:>10} {:>10} {:>10} {:>8} {:>8}",
+            "CRATE", "PARSE(us)", "RELOC(us)", "TEXT", "RODATA+DATA", "RELOCS", "SYMBOLS"
+        )?;
+        let mut write_row = |name: &str, stats: &CrateLoadStats| writeln!(f, "{:<40} {:>10} {:>10} {:>10} {:>10} {:>8} {:>8}",
+            name,
+            stats.parse_time.as_micros(),
+            stats.relocation_time.as_micros(),
+            stats.text_bytes,
+            stats.rodata_bytes + stats.data_bytes,
+            stats.num_relocations,
+            stats.symbols_added,
+        );
+        for (crate_name, stats) in &self.crates {
+            write_row(crate_name.as_str(), stats)?;
+        }
+        write_row("TOTAL", &self.totals)
+    }
+}
+
+impl CrateNamespace {
+    /// Returns a report of the load statistics of every crate in this namespace,
+    /// including crates in its recursive namespaces if `recursive` is `true`.
+    ///
+    /// Crates that are shared across multiple namespaces are only counted once.
+    pub fn crate_load_report(&self, recursive: bool) -> CrateLoadReport {
+        let mut crates: BTreeMap<StrRef, CrateLoadStats> = BTreeMap::new();
+        self.for_each_crate(recursive, |crate_name, crate_ref| {
+            if !crates.contains_key(crate_name) {
+                crates.insert(StrRef::from(crate_name), crate_ref.lock_as_ref().load_stats);
+            }
+            true
+        });
+
+        let mut totals = CrateLoadStats::default();
+        for stats in crates.values() {
+            totals.accumulate(stats);
+        }
+        CrateLoadReport {
+            crates: crates.into_iter().collect(),
+            totals,
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Attributing the memory occupied by loaded crates to the namespaces that contain them.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::ops::{Add, AddAssign};
+use spin::Mutex;
+use memory::MappedPages;
+use crate::{CrateNamespace, LoadedCrate, SectionType};
+
+/// The number of bytes of memory mapped for crates, broken down by the kind of section they hold.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SectionBytes {
+    /// The bytes of executable pages, which hold `.text` sections.
+    pub text: usize,
+    /// The bytes of read-only pages, which hold `.rodata`, `.eh_frame`, `.gcc_except_table`,
+    /// and `.cls` sections, excluding the TLS data images counted in `tls`.
+    pub rodata: usize,
+    /// The bytes of writable pages, which hold `.data` and `.bss` sections.
+    pub data: usize,
+    /// The bytes of `.tdata` sections, i.e., the initial images of TLS areas.
+    ///
+    /// This doesn't include each task's own TLS area, which is allocated from the heap.
+    pub tls: usize,
+}
+
+impl SectionBytes {
+    /// Returns the total number of bytes across all kinds of sections.
+    pub fn total(&self) -> usize {
+        self.text + self.rodata + self.data + self.tls
+    }
+}
+
+impl Add for SectionBytes {
+    type Output = SectionBytes;
+    fn add(self, other: SectionBytes) -> SectionBytes {
+        SectionBytes {
+            text: self.text + other.text,
+            rodata: self.rodata + other.rodata,
+            data: self.data + other.data,
+            tls: self.tls + other.tls,
+        }
+    }
+}
+
+impl AddAssign for SectionBytes {
+    fn add_assign(&mut self, other: SectionBytes) {
+        *self = *self + other;
+    }
+}
+
+/// The memory mapped for the crates in a namespace, as returned by [`CrateNamespace::memory_usage()`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NamespaceMemoryUsage {
+    /// The memory of crates owned exclusively by the namespace,
+    /// which is freed once the namespace is dropped.
+    pub exclusive: SectionBytes,
+    /// The memory of crates that the namespace shares with other namespaces.
+    pub shared: SectionBytes,
+    pub exclusive_crates: usize,
+    pub shared_crates: usize,
+}
+
+impl NamespaceMemoryUsage {
+    /// Returns the total memory of all crates in the namespace, whether exclusive or shared.
+    pub fn total(&self) -> SectionBytes {
+        self.exclusive + self.shared
+    }
+}
+
+impl CrateNamespace {
+    /// Returns the memory mapped for the crates in this namespace (not its recursive namespace),
+    /// separated into crates owned exclusively by this namespace and crates shared with other namespaces.
+    ///
+    /// Memory is counted at page granularity, i.e., as the size of the `MappedPages` that hold each crate's sections.
+    /// Crates that share the same `MappedPages`, e.g., the crates of the `nano_core`, are only counted once.
+    ///
+    /// Whether a crate is shared is determined by its [`CowArc`](cow_arc::CowArc) shared flag,
+    /// which is set once it has been cloned into another namespace.
+    ///
+    /// # Locking
+    /// This obtains the lock on every crate in this namespace, so the caller must not hold any such locks.
+    pub fn memory_usage(&self) -> NamespaceMemoryUsage {
+        let mut usage = NamespaceMemoryUsage::default();
+        let mut counted_pages: Vec<*const Mutex<MappedPages>> = Vec::new();
+        self.for_each_crate(false, |_crate_name, crate_ref| {
+            let bytes = crate_memory_usage(&crate_ref.lock_as_ref(), &mut counted_pages);
+            if crate_ref.is_shared() {
+                usage.shared += bytes;
+                usage.shared_crates += 1;
+            } else {
+                usage.exclusive += bytes;
+                usage.exclusive_crates += 1;
+            }
+            true
+        });
+        usage
+    }
+}
+
+/// Returns the memory mapped for the given crate, excluding any `MappedPages` in `counted_pages`,
+/// to which this crate's `MappedPages` are added.
+fn crate_memory_usage(krate: &LoadedCrate, counted_pages: &mut Vec<*const Mutex<MappedPages>>) -> SectionBytes {
+    let mut size_of_new_pages = |pages: &Option<(Arc<Mutex<MappedPages>>, _)>| -> usize {
+        let Some((mp, _range)) = pages else { return 0 };
+        let ptr = Arc::as_ptr(mp);
+        if counted_pages.contains(&ptr) {
+            return 0;
+        }
+        counted_pages.push(ptr);
+        mp.lock().size_in_bytes()
+    };
+    let text = size_of_new_pages(&krate.text_pages);
+    let rodata = size_of_new_pages(&krate.rodata_pages);
+    let data = size_of_new_pages(&krate.data_pages);
+
+    // TLS data images are held in the read-only pages, so they are only counted if those pages are.
+    let tls = if rodata == 0 {
+        0
+    } else {
+        krate.sections.values()
+            .filter(|sec| sec.typ == SectionType::TlsData)
+            .map(|sec| sec.size)
+            .sum()
+    };
+    SectionBytes { text, rodata: rodata.saturating_sub(tls), data, tls }
+}
@@ -0,0 +1,441 @@
+//! Saving and restoring images of the already-linked crates in a [`CrateNamespace`].
+//!
+//! Loading a crate requires parsing its object file and then relocating (linking) it
+//! against every crate it depends on, which dominates the time needed to bring up
+//! a large set of dynamically-loaded crates.
+//! A namespace image instead records the fully-relocated memory contents of each crate,
+//! along with its sections and their dependencies, such that those crates can later be
+//! restored at the exact same virtual addresses without parsing or relocating anything.
+//!
+//! An image can only be restored if everything it was linked against is unchanged:
+//! * the same object files must exist in the namespace's directories,
+//! * every foreign section that its crates depend on must exist at the same virtual address,
+//!   which is the case if the kernel and the recursive namespace's crates are unchanged, and
+//! * the virtual addresses that its crates occupied must still be free.
+//!
+//! Because each crate's memory is saved as-is, images should only be taken of namespaces
+//! whose crates are not running, e.g., right after loading them;
+//! otherwise, the current values of their `.data` and `.bss` sections are saved too.
+//! Crates with TLS or CLS sections cannot be saved, as their offsets into the
+//! TLS and CLS areas cannot yet be reserved upon restore.
+
+use alloc::{collections::{BTreeMap, BTreeSet}, string::ToString, sync::Arc, vec::Vec};
+use spin::Mutex;
+use cow_arc::CowArc;
+use crate_metadata_serde::{
+    SerializedCrate, SerializedDependency, SerializedLazySection, SerializedLoadedCrate,
+    SerializedNamespace, SerializedPages, SerializedRelocation, SerializedSection,
+};
+use fs_node::FileRef;
+use hashbrown::HashMap;
+use memory::{MappedPages, MmiRef, VirtualAddress, allocate_pages_by_bytes_at};
+use crate::{
+    CrateLoadStats, CrateNamespace, LazySection, LoadedCrate, LoadedSection, RelocationEntry, Shndx, StrRef,
+    StrongCrateRef, StrongDependency, StrongSectionRef, WeakDependent,
+    DATA_BSS_SECTION_FLAGS, RODATA_SECTION_FLAGS, TEXT_SECTION_FLAGS,
+};
+
+impl CrateNamespace {
+    /// Saves an image of all crates in this namespace (not including its recursive namespace)
+    /// into a byte buffer that can later be given to [`CrateNamespace::restore()`].
+    ///
+    /// Because each crate's memory is saved as-is, this should only be used on namespaces
+    /// whose crates are not running. Crates with TLS or CLS sections cannot be saved.
+    pub fn snapshot(&self) -> Result<Vec<u8>, &'static str> {
+        let mut crates = Vec::new();
+        let mut error = None;
+        self.for_each_crate(false, |_crate_name, crate_ref| {
+            match save_crate(self, crate_ref) {
+                Ok(serialized) => {
+                    crates.push(serialized);
+                    true
+                }
+                Err(e) => {
+                    error = Some(e);
+                    false
+                }
+            }
+        });
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        let image = SerializedNamespace {
+            namespace_name: self.name().to_string(),
+            crates,
+        };
+        bincode::serde::encode_to_vec(&image, bincode::config::standard())
+            .map_err(|_| "failed to serialize namespace image")
+    }
+
+    /// Restores the crates in the given namespace `image` (created by [`CrateNamespace::snapshot()`])
+    /// into the given `namespace`, without parsing or relocating any of them.
+    ///
+    /// This is all-or-nothing: if any crate cannot be restored, no crates are added to `namespace`.
+    ///
+    /// Returns the number of crates that were restored.
+    pub fn restore(
+        namespace: &Arc<CrateNamespace>,
+        image: &[u8],
+        kernel_mmi_ref: &MmiRef,
+        verbose_log: bool,
+    ) -> Result<usize, &'static str> {
+        let (image, _): (SerializedNamespace, _) = bincode::serde::decode_from_slice(image, bincode::config::standard())
+            .map_err(|_| "failed to deserialize namespace image")?;
+
+        // First, recreate every crate and its sections in memory.
+        // Nothing is added to `namespace` or to the crates already loaded into it until every step
+        // that can fail has succeeded, so upon failure, dropping the restored crates unmaps them
+        // and leaves everything else untouched.
+        let mut restored: Vec<(StrongCrateRef, SerializedLoadedCrate)> = Vec::with_capacity(image.crates.len());
+        for serialized_crate in image.crates {
+            if namespace.get_crate(&serialized_crate.crate_info.crate_name).is_some() {
+                error!("Cannot restore crate {:?} from namespace image, it is already loaded", serialized_crate.crate_info.crate_name);
+                return Err("namespace image contains a crate that is already loaded");
+            }
+            let crate_ref = restore_crate(namespace, &serialized_crate, kernel_mmi_ref)?;
+            restored.push((crate_ref, serialized_crate));
+        }
+
+        // Second, find the sections that the restored sections depend on and those of the reexported symbols,
+        // now that all restored crates exist.
+        let mut dependencies = Vec::new();
+        let mut reexported_symbols = Vec::new();
+        for (crate_ref, serialized_crate) in &restored {
+            find_dependencies(namespace, crate_ref, serialized_crate, &restored, &mut dependencies)?;
+            let krate = crate_ref.lock_as_ref();
+            for (symbol, address) in &serialized_crate.reexported_symbols {
+                let sec = find_section_at(&krate, None, *address)
+                    .ok_or("namespace image contains a reexported symbol for a nonexistent section")?;
+                reexported_symbols.push((StrRef::from(symbol.as_str()), Arc::downgrade(sec)));
+            }
+        }
+
+        // Third, make the restored crates' memory read-only or executable as appropriate.
+        for (crate_ref, _) in &restored {
+            let krate = crate_ref.lock_as_ref();
+            let mut kernel_mmi = kernel_mmi_ref.lock();
+            if let Some((ref tp, _)) = krate.text_pages {
+                tp.lock().remap(&mut kernel_mmi.page_table, TEXT_SECTION_FLAGS)?;
+            }
+            if let Some((ref rp, _)) = krate.rodata_pages {
+                rp.lock().remap(&mut kernel_mmi.page_table, RODATA_SECTION_FLAGS)?;
+            }
+        }
+
+        // Finally, nothing else can fail, so connect the restored crates to their dependencies
+        // and add them and their symbols to the namespace.
+        for dependency in dependencies {
+            dependency.connect();
+        }
+        for (crate_ref, _) in &restored {
+            let krate = crate_ref.lock_as_ref();
+            let new_symbols = namespace.add_symbols(krate.sections.values(), verbose_log);
+            if verbose_log {
+                debug!("Restored crate {:?} from namespace image, added {} new symbols", krate.crate_name, new_symbols);
+            }
+        }
+        {
+            let mut symbol_map = namespace.symbol_map.lock();
+            for (symbol, sec) in reexported_symbols {
+                symbol_map.insert(symbol, sec);
+            }
+        }
+        {
+            let mut crate_tree = namespace.crate_tree.lock();
+            for (crate_ref, _) in &restored {
+                let crate_name = crate_ref.lock_as_ref().crate_name.clone();
+                crate_tree.insert(crate_name, crate_ref.clone_shallow());
+            }
+        }
+
+        info!("Restored {} crates into namespace {:?} from an image of namespace {:?}",
+            restored.len(), namespace.name(), image.namespace_name
+        );
+        Ok(restored.len())
+    }
+}
+
+/// Converts the given loaded crate into its serializable form.
+fn save_crate(namespace: &CrateNamespace, crate_ref: &StrongCrateRef) -> Result<SerializedLoadedCrate, &'static str> {
+    let krate = crate_ref.lock_as_ref();
+    if !krate.tls_sections.is_empty() || !krate.cls_sections.is_empty() {
+        error!("Cannot save crate {:?} into a namespace image, it has TLS or CLS sections", krate.crate_name);
+        return Err("cannot save crates with TLS or CLS sections into a namespace image");
+    }
+
+    let (object_file_name, object_file_hash) = {
+        let file = krate.object_file.lock();
+        let bytes = file.as_mapping()?.as_slice::<u8>(0, file.len())?;
+        (file.get_name(), fnv1a::hash(bytes))
+    };
+
+    let mut sections = HashMap::with_capacity(krate.sections.len());
+    let mut dependencies = Vec::new();
+    #[allow(unused_mut)]
+    let mut internal_dependencies = Vec::new();
+    for (shndx, sec) in &krate.sections {
+        sections.insert(*shndx, SerializedSection {
+            name: sec.name.to_string(),
+            ty: sec.typ,
+            global: sec.global,
+            virtual_address: sec.virt_addr.value(),
+            offset: sec.mapped_pages_offset,
+            size: sec.size,
+        });
+
+        let sec_inner = sec.inner.read();
+        for strong_dep in &sec_inner.sections_i_depend_on {
+            let source_crate = strong_dep.section.parent_crate.upgrade()
+                .ok_or("a section's dependency belongs to a crate that was dropped")?;
+            dependencies.push(SerializedDependency {
+                target_shndx: *shndx,
+                source_crate: source_crate.lock_as_ref().crate_name.to_string(),
+                source_section: strong_dep.section.name.to_string(),
+                source_address: strong_dep.section.virt_addr.value(),
+                relocation: save_relocation(strong_dep.relocation),
+            });
+        }
+        #[cfg(internal_deps)]
+        for internal_dep in &sec_inner.internal_dependencies {
+            internal_dependencies.push((*shndx, internal_dep.source_sec_shndx, save_relocation(internal_dep.relocation)));
+        }
+    }
+
+    // Re-exported symbols refer to sections in this crate under another name.
+    let mut reexported_symbols = Vec::with_capacity(krate.reexported_symbols.len());
+    for symbol in &krate.reexported_symbols {
+        let sec = namespace.symbol_map.lock().get(symbol.as_bytes()).and_then(|weak_sec| weak_sec.upgrade())
+            .ok_or("a crate's reexported symbol is missing from its namespace")?;
+        reexported_symbols.push((symbol.to_string(), sec.virt_addr.value()));
+    }
+
+    Ok(SerializedLoadedCrate {
+        crate_info: SerializedCrate {
+            crate_name: krate.crate_name.to_string(),
+            sections,
+            global_sections: krate.global_sections.clone(),
+            tls_sections: krate.tls_sections.clone(),
+            cls_sections: krate.cls_sections.clone(),
+            data_sections: krate.data_sections.clone(),
+            init_symbols: Default::default(),
+        },
+        object_file_name,
+        object_file_hash,
+        text_pages: save_pages(krate.text_pages.as_ref().map(|(mp, _)| mp))?,
+        rodata_pages: save_pages(krate.rodata_pages.as_ref().map(|(mp, _)| mp))?,
+        data_pages: save_pages(krate.data_pages.as_ref().map(|(mp, _)| mp))?,
+        reexported_symbols,
+        dependencies,
+        internal_dependencies,
+        lazy_sections: krate.lazy_sections.iter()
+            .map(|(shndx, lazy)| (*shndx, SerializedLazySection {
+                ty: lazy.typ,
+                virtual_address: lazy.virt_addr.value(),
+                offset: lazy.mapped_pages_offset,
+                size: lazy.size,
+            }))
+            .collect(),
+    })
+}
+
+fn save_pages(pages: Option<&Arc<Mutex<MappedPages>>>) -> Result<Option<SerializedPages>, &'static str> {
+    let Some(pages) = pages else { return Ok(None) };
+    let mp = pages.lock();
+    Ok(Some(SerializedPages {
+        start_address: mp.start_address().value(),
+        contents: mp.as_slice::<u8>(0, mp.size_in_bytes())?.to_vec(),
+    }))
+}
+
+fn save_relocation(relocation: RelocationEntry) -> SerializedRelocation {
+    SerializedRelocation { typ: relocation.typ, addend: relocation.addend, offset: relocation.offset }
+}
+
+fn restore_relocation(relocation: SerializedRelocation) -> RelocationEntry {
+    RelocationEntry { typ: relocation.typ, addend: relocation.addend, offset: relocation.offset }
+}
+
+/// Recreates the given crate and its sections at their original virtual addresses,
+/// without connecting them to any other crates.
+fn restore_crate(
+    namespace: &Arc<CrateNamespace>,
+    serialized_crate: &SerializedLoadedCrate,
+    kernel_mmi_ref: &MmiRef,
+) -> Result<StrongCrateRef, &'static str> {
+    let object_file = find_object_file(namespace, serialized_crate)?;
+    let text_pages   = restore_pages(serialized_crate.text_pages.as_ref(), kernel_mmi_ref)?;
+    let rodata_pages = restore_pages(serialized_crate.rodata_pages.as_ref(), kernel_mmi_ref)?;
+    let data_pages   = restore_pages(serialized_crate.data_pages.as_ref(), kernel_mmi_ref)?;
+
+    let crate_info = &serialized_crate.crate_info;
+    let loaded_crate = CowArc::new(LoadedCrate {
+        crate_name:          StrRef::from(crate_info.crate_name.as_str()),
+        debug_symbols_file:  Arc::downgrade(&object_file),
+        object_file,
+        sections:            HashMap::new(), // placeholder
+        lazy_sections:       restore_lazy_sections(serialized_crate)?,
+        text_pages:          text_pages.clone().map(|mp| { let range = crate::mp_range(&mp); (mp, range) }),
+        rodata_pages:        rodata_pages.clone().map(|mp| { let range = crate::mp_range(&mp); (mp, range) }),
+        data_pages:          data_pages.clone().map(|mp| { let range = crate::mp_range(&mp); (mp, range) }),
+        global_sections:     crate_info.global_sections.clone(),
+        tls_sections:        BTreeSet::new(),
+        cls_sections:        BTreeSet::new(),
+        data_sections:       crate_info.data_sections.clone(),
+        reexported_symbols:  serialized_crate.reexported_symbols.iter().map(|(s, _)| StrRef::from(s.as_str())).collect(),
+        load_stats:          CrateLoadStats::default(),
+    });
+    let parent_crate = CowArc::downgrade(&loaded_crate);
+
+    let mut sections = HashMap::with_capacity(crate_info.sections.len());
+    for (shndx, sec) in &crate_info.sections {
+        let mapped_pages = match sec.ty {
+            crate::SectionType::Text => text_pages.as_ref(),
+            crate::SectionType::Data | crate::SectionType::Bss => data_pages.as_ref(),
+            _ => rodata_pages.as_ref(),
+        }.ok_or("namespace image contains a section without any pages to hold it")?;
+        let virt_addr = VirtualAddress::new(sec.virtual_address)
+            .ok_or("namespace image contains a section with an invalid virtual address")?;
+        sections.insert(*shndx, LoadedSection::new(
+            sec.ty,
+            sec.name.as_str().into(),
+            Arc::clone(mapped_pages),
+            sec.offset,
+            virt_addr,
+            sec.size,
+            sec.global,
+            parent_crate.clone(),
+        ).into_ref());
+    }
+
+    loaded_crate.lock_as_mut()
+        .ok_or("BUG: restore_crate(): couldn't get exclusive mutable access to restored crate")?
+        .sections = sections;
+    Ok(loaded_crate)
+}
+
+/// Recreates the lazy sections (sections without full metadata) of the given crate.
+fn restore_lazy_sections(serialized_crate: &SerializedLoadedCrate) -> Result<BTreeMap<Shndx, LazySection>, &'static str> {
+    serialized_crate.lazy_sections.iter()
+        .map(|(shndx, lazy)| Ok((*shndx, LazySection {
+            typ: lazy.ty,
+            mapped_pages_offset: lazy.offset,
+            virt_addr: VirtualAddress::new(lazy.virtual_address)
+                .ok_or("namespace image contains a lazy section with an invalid virtual address")?,
+            size: lazy.size,
+        })))
+        .collect()
+}
+
+/// Finds the object file that the given crate was loaded from,
+/// and ensures that it's identical to the object file that the crate was originally loaded from.
+fn find_object_file(namespace: &Arc<CrateNamespace>, serialized_crate: &SerializedLoadedCrate) -> Result<FileRef, &'static str> {
+    let (object_file, _ns) = CrateNamespace::get_crate_object_files_starting_with(namespace, &serialized_crate.object_file_name)
+        .into_iter()
+        .find(|(file, _ns)| file.lock().get_name() == serialized_crate.object_file_name)
+        .ok_or("couldn't find the object file of a crate in the namespace image")?;
+    let hash = {
+        let file = object_file.lock();
+        let bytes = file.as_mapping()?.as_slice::<u8>(0, file.len())?;
+        fnv1a::hash(bytes)
+    };
+    if hash != serialized_crate.object_file_hash {
+        error!("Object file {:?} has changed since the namespace image was saved", serialized_crate.object_file_name);
+        return Err("a crate's object file has changed since the namespace image was saved");
+    }
+    Ok(object_file)
+}
+
+/// Allocates and maps the given pages at their original virtual address (as writable),
+/// and copies their saved contents into them.
+fn restore_pages(pages: Option<&SerializedPages>, kernel_mmi_ref: &MmiRef) -> Result<Option<Arc<Mutex<MappedPages>>>, &'static str> {
+    let Some(pages) = pages else { return Ok(None) };
+    let start = VirtualAddress::new(pages.start_address)
+        .ok_or("namespace image contains pages with an invalid virtual address")?;
+    let allocated_pages = allocate_pages_by_bytes_at(start, pages.contents.len())
+        .map_err(|_| "the virtual addresses of a crate in the namespace image are already in use")?;
+    let mut mp = kernel_mmi_ref.lock().page_table.map_allocated_pages(allocated_pages, DATA_BSS_SECTION_FLAGS)?;
+    mp.disown();
+    mp.as_slice_mut::<u8>(0, pages.contents.len())?.copy_from_slice(&pages.contents);
+    Ok(Some(Arc::new(Mutex::new(mp))))
+}
+
+/// A dependency of a restored section on another section, which is only connected
+/// once the whole namespace image is known to be restorable.
+struct RestoredDependency {
+    target_sec: StrongSectionRef,
+    source_sec: StrongSectionRef,
+    relocation: RelocationEntry,
+}
+
+impl RestoredDependency {
+    fn connect(self) {
+        self.source_sec.inner.write().sections_dependent_on_me.push(WeakDependent {
+            section: Arc::downgrade(&self.target_sec),
+            relocation: self.relocation,
+        });
+        self.target_sec.inner.write().sections_i_depend_on.push(StrongDependency {
+            section: self.source_sec,
+            relocation: self.relocation,
+        });
+    }
+}
+
+/// Finds the sections, either in other restored crates or in crates already in the namespace,
+/// that the given restored crate's sections depend on, and adds those dependencies to `dependencies`.
+fn find_dependencies(
+    namespace: &Arc<CrateNamespace>,
+    crate_ref: &StrongCrateRef,
+    serialized_crate: &SerializedLoadedCrate,
+    restored: &[(StrongCrateRef, SerializedLoadedCrate)],
+    dependencies: &mut Vec<RestoredDependency>,
+) -> Result<(), &'static str> {
+    let krate = crate_ref.lock_as_ref();
+    for dep in &serialized_crate.dependencies {
+        let target_sec = krate.sections.get(&dep.target_shndx)
+            .ok_or("namespace image contains a dependency of a nonexistent section")?;
+
+        // The source crate is either one of the other restored crates or one that was already loaded.
+        let source_crate = restored.iter()
+            .find(|(_, sc)| sc.crate_info.crate_name == dep.source_crate)
+            .map(|(cr, _)| cr.clone_shallow())
+            .or_else(|| namespace.get_crate(&dep.source_crate))
+            .ok_or("a crate in the namespace image depends on a crate that isn't loaded")?;
+        let source_sec = {
+            // The source crate may be the same as the target crate, which is already locked.
+            let same_crate = CowArc::ptr_eq(&source_crate, crate_ref);
+            let source_krate;
+            let source = if same_crate { &*krate } else { source_krate = source_crate.lock_as_ref(); &*source_krate };
+            find_section_at(source, Some(&dep.source_section), dep.source_address).cloned()
+        };
+        let source_sec = source_sec.ok_or_else(|| {
+            error!("Section {:?} in crate {:?} is no longer at address {:#X}; the crates that the namespace image was linked against have changed",
+                dep.source_section, dep.source_crate, dep.source_address
+            );
+            "a section that the namespace image depends on has changed"
+        })?;
+
+        dependencies.push(RestoredDependency {
+            target_sec: Arc::clone(target_sec),
+            source_sec,
+            relocation: restore_relocation(dep.relocation),
+        });
+    }
+
+    // Internal dependencies are entirely within the restored crate, so they can be connected right away.
+    #[cfg(internal_deps)]
+    for (target_shndx, source_shndx, relocation) in &serialized_crate.internal_dependencies {
+        let target_sec = krate.sections.get(target_shndx)
+            .ok_or("namespace image contains an internal dependency of a nonexistent section")?;
+        target_sec.inner.write().internal_dependencies.push(
+            crate::InternalDependency::new(restore_relocation(*relocation), *source_shndx)
+        );
+    }
+    Ok(())
+}
+
+/// Returns the section in the given crate that starts at the given `address`
+/// and, if provided, has the given `name`.
+fn find_section_at<'c>(krate: &'c LoadedCrate, name: Option<&str>, address: usize) -> Option<&'c StrongSectionRef> {
+    krate.find_section(|sec| sec.virt_addr.value() == address && name.map_or(true, |n| sec.name.as_str() == n))
+}
@@ -0,0 +1,230 @@
+//! Splitting the loading of multiple crates into separate phases such that
+//! the first phase can run concurrently on multiple CPUs.
+//!
+//! [`CrateNamespace::load_crates()`] loads crates in two phases:
+//! first, it parses each crate's object file and copies its sections into memory,
+//! and then, once all crates' sections have been loaded, it performs their relocations.
+//! Only the first phase is independent for each crate, so it is exposed here as
+//! [`CrateNamespace::load_crate_sections_only()`], which can be invoked from any task.
+//! Its result, a [`PartiallyLoadedCrate`], is `Send`, so it can be handed back
+//! to the task that invokes the second phase, [`CrateNamespace::link_partially_loaded_crates()`].
+//!
+//! The `mod_mgmt` crate can't spawn tasks itself, so distributing the first phase across CPUs
+//! is done elsewhere, once it has registered its [`ParallelLoadHooks`] via [`set_parallel_load_hooks()`];
+//! see the `parallel_crate_loader` crate.
+//! Until then, `load_crates()` loads each crate's sections one after the other on the current CPU.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::ops::Deref;
+use memory::MmiRef;
+use fs_node::FileRef;
+use spin::Mutex;
+use xmas_elf::ElfFile;
+use crate::{CrateNamespace, LoadError, StrRef, StrongCrateRef, WeakSectionRef, record_symbols_added};
+
+/// The functions that [`CrateNamespace::load_crates()`] uses to load crates on multiple CPUs.
+#[derive(Clone, Copy)]
+pub struct ParallelLoadHooks {
+    /// Performs the first phase of loading the crates in the given object files,
+    /// via [`CrateNamespace::load_crate_sections_only()`] on multiple CPUs,
+    /// and returns the partially-loaded crates in the same order as their object files.
+    pub load_sections: fn(&Arc<CrateNamespace>, &[FileRef], &MmiRef, bool) -> Result<Vec<PartiallyLoadedCrate>, LoadError>,
+    /// Regenerates the CLS data image of every CPU, such that newly-loaded CLS sections are included.
+    pub reload_cls: fn(),
+}
+
+static PARALLEL_LOAD_HOOKS: Mutex<Option<ParallelLoadHooks>> = Mutex::new(None);
+
+/// Registers the hooks used to load crates on multiple CPUs,
+/// or reverts to loading crates on the current CPU if `hooks` is `None`.
+pub fn set_parallel_load_hooks(hooks: Option<ParallelLoadHooks>) {
+    *PARALLEL_LOAD_HOOKS.lock() = hooks;
+}
+
+/// A crate whose sections have been loaded into memory, but not yet relocated.
+///
+/// The crate is not usable until it has been passed to
+/// [`CrateNamespace::link_partially_loaded_crates()`].
+pub struct PartiallyLoadedCrate {
+    crate_ref: StrongCrateRef,
+    object_file: FileRef,
+}
+
+impl PartiallyLoadedCrate {
+    /// Returns a reference to the crate, which has not yet been relocated.
+    pub fn crate_ref(&self) -> &StrongCrateRef {
+        &self.crate_ref
+    }
+
+    /// Consumes this partially-loaded crate, returning a reference to the crate.
+    pub(crate) fn into_crate_ref(self) -> StrongCrateRef {
+        self.crate_ref
+    }
+}
+
+/// Partially-loaded crates are moved from the loader tasks to the linking task.
+const _: () = {
+    const fn assert_send<T: Send>() { }
+    assert_send::<PartiallyLoadedCrate>();
+    assert_send::<LoadError>();
+};
+
+impl CrateNamespace {
+    /// Performs the first phase of loading the crate in the given object file:
+    /// parsing the object file and copying its sections into newly-mapped memory.
+    ///
+    /// This does not add the crate or its symbols to this namespace, nor does it relocate the crate.
+    /// Thus, this may be invoked for multiple crates concurrently from different tasks;
+    /// afterwards, all of them must be passed to [`link_partially_loaded_crates()`].
+    ///
+    /// [`link_partially_loaded_crates()`]: CrateNamespace::link_partially_loaded_crates
+    pub fn load_crate_sections_only(
+        &self,
+        crate_object_file: &FileRef,
+        kernel_mmi_ref: &MmiRef,
+        verbose_log: bool,
+    ) -> Result<PartiallyLoadedCrate, LoadError> {
+        let crate_ref = {
+            let locked_file = crate_object_file.lock();
+            self.load_crate_sections(locked_file.deref(), kernel_mmi_ref, verbose_log)?.0
+        };
+        Ok(PartiallyLoadedCrate { crate_ref, object_file: crate_object_file.clone() })
+    }
+
+    /// Performs the first phase of loading the crates in the given object files,
+    /// via the registered [`ParallelLoadHooks`] if this namespace is registered,
+    /// otherwise one crate after the other on the current CPU.
+    pub(crate) fn load_crate_sections_with_hooks(
+        &self,
+        crate_files: &[FileRef],
+        kernel_mmi_ref: &MmiRef,
+        verbose_log: bool,
+    ) -> Result<Vec<PartiallyLoadedCrate>, LoadError> {
+        let hooks = *PARALLEL_LOAD_HOOKS.lock();
+        if let Some(hooks) = hooks.filter(|_| crate_files.len() > 1) {
+            // The loader tasks need their own reference to this namespace,
+            // which can only be obtained for a registered namespace.
+            let this = crate::registered_namespaces().into_iter().find(|ns| core::ptr::eq(Arc::as_ptr(ns), self));
+            if let Some(this) = this {
+                return (hooks.load_sections)(&this, crate_files, kernel_mmi_ref, verbose_log);
+            }
+        }
+        crate_files.iter()
+            .map(|file| self.load_crate_sections_only(file, kernel_mmi_ref, verbose_log))
+            .collect()
+    }
+
+    /// Performs the second phase of loading the given crates:
+    /// adding their symbols to this namespace and then relocating them.
+    ///
+    /// The symbols of all crates are added before any crate is relocated,
+    /// so crates may depend on one another, as with [`load_crates()`].
+    /// Symbols are added in the order that the crates are given,
+    /// which determines which crate's symbol is used if multiple crates provide the same symbol.
+    ///
+    /// Either all of the crates are linked and added to this namespace, or none of them are:
+    /// if adding any crate's symbols or relocating any crate fails, the symbols of all the crates
+    /// are removed again, any symbols they replaced are restored,
+    /// and the crates are detached from the sections they depended on.
+    /// Crates are only added to this namespace's crate tree once all of them have been relocated.
+    ///
+    /// Afterwards, the CLS data image is regenerated on every CPU if a [`ParallelLoadHooks`] is registered,
+    /// or otherwise only on the current CPU.
+    ///
+    /// * `temp_backup_namespace`: the namespace to search for symbols that can't be found in this namespace,
+    ///   as in [`load_crate()`].
+    ///
+    /// [`load_crates()`]: CrateNamespace::load_crates
+    /// [`load_crate()`]: CrateNamespace::load_crate
+    pub fn link_partially_loaded_crates(
+        &self,
+        crates: Vec<PartiallyLoadedCrate>,
+        temp_backup_namespace: Option<&CrateNamespace>,
+        kernel_mmi_ref: &MmiRef,
+        verbose_log: bool,
+    ) -> Result<(), LoadError> {
+        let mut replaced_symbols = Vec::new();
+        if let Err(e) = self.link_crates(&crates, &mut replaced_symbols, temp_backup_namespace, kernel_mmi_ref, verbose_log) {
+            self.unlink_crates(&crates, replaced_symbols);
+            return Err(e);
+        }
+
+        {
+            let mut crate_tree = self.crate_tree.lock();
+            for partial in crates {
+                let name = partial.crate_ref.lock_as_ref().crate_name.clone();
+                crate_tree.insert(name, partial.crate_ref);
+            }
+        }
+
+        // The crates' CLS sections may have been loaded on other CPUs, and may be accessed from any CPU.
+        let hooks = *PARALLEL_LOAD_HOOKS.lock();
+        match hooks {
+            Some(hooks) => (hooks.reload_cls)(),
+            None => cls_allocator::reload_current_cpu(),
+        }
+        Ok(())
+    }
+
+    /// Adds the symbols of the given crates and relocates them, without adding them to the crate tree.
+    ///
+    /// The existing symbols that are replaced by the crates' symbols are appended to `replaced_symbols`.
+    fn link_crates(
+        &self,
+        crates: &[PartiallyLoadedCrate],
+        replaced_symbols: &mut Vec<(StrRef, WeakSectionRef)>,
+        temp_backup_namespace: Option<&CrateNamespace>,
+        kernel_mmi_ref: &MmiRef,
+        verbose_log: bool,
+    ) -> Result<(), LoadError> {
+        for partial in crates {
+            let new_syms = {
+                let krate = partial.crate_ref.lock_as_ref();
+                {
+                    let symbol_map = self.symbol_map.lock();
+                    replaced_symbols.extend(krate.global_sections_iter().filter_map(|sec|
+                        symbol_map.get(sec.name.as_bytes()).map(|old_sec| (sec.name.clone(), old_sec.clone()))
+                    ));
+                }
+                self.add_symbols_checked(&krate.crate_name, krate.sections.values(), verbose_log)?
+            };
+            record_symbols_added(&partial.crate_ref, new_syms);
+        }
+
+        for partial in crates {
+            // Re-parse the object file, as the `ElfFile` from the first phase borrowed its lock.
+            // This only parses the ELF headers, which is cheap compared to loading the sections.
+            let locked_file = partial.object_file.lock();
+            let mapped_pages = locked_file.as_mapping()?;
+            let byte_slice: &[u8] = mapped_pages.as_slice(0, locked_file.len())?;
+            let crate_name = partial.crate_ref.lock_as_ref().crate_name.clone();
+            let elf_file = ElfFile::new(byte_slice)
+                .map_err(|reason| LoadError::ElfParse { crate_name, reason })?;
+            self.perform_relocations(&elf_file, &partial.crate_ref, temp_backup_namespace, None, kernel_mmi_ref, verbose_log)?;
+        }
+        Ok(())
+    }
+
+    /// Undoes a failed [`link_crates()`](Self::link_crates): removes the symbols of the given crates,
+    /// restores the `replaced_symbols` that still exist, and detaches the crates' sections.
+    fn unlink_crates(&self, crates: &[PartiallyLoadedCrate], replaced_symbols: Vec<(StrRef, WeakSectionRef)>) {
+        {
+            let mut symbol_map = self.symbol_map.lock();
+            for partial in crates {
+                self.remove_symbols_of_crate_locked(&mut symbol_map, &partial.crate_ref.lock_as_ref());
+            }
+            for (name, old_sec) in replaced_symbols {
+                // A symbol may have been replaced by another one of these crates.
+                let is_outside_batch = old_sec.upgrade()
+                    .and_then(|sec| sec.parent_crate.upgrade())
+                    .is_some_and(|old_crate| !crates.iter().any(|partial| partial.crate_ref.inner_ptr_eq(&old_crate)));
+                if is_outside_batch && symbol_map.get(name.as_bytes()).is_none() {
+                    symbol_map.insert(name, old_sec);
+                }
+            }
+        }
+        for partial in crates {
+            self.detach_crate_sections(&partial.crate_ref.lock_as_ref());
+        }
+    }
+}
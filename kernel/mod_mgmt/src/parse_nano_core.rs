@@ -183,6 +183,7 @@ fn parse_nano_core_symbol_file_or_binary(
         debug_symbols_file:  Arc::downgrade(&nano_core_file),
         object_file:         nano_core_file,
         sections:            HashMap::new(),
+        lazy_sections:       BTreeMap::new(),
         text_pages:          Some((text_pages.clone(),   mp_range(text_pages))),
         rodata_pages:        Some((rodata_pages.clone(), mp_range(rodata_pages))),
         data_pages:          Some((data_pages.clone(),   mp_range(data_pages))),
@@ -191,6 +192,7 @@ fn parse_nano_core_symbol_file_or_binary(
         cls_sections:        BTreeSet::new(),
         data_sections:       BTreeSet::new(),
         reexported_symbols:  BTreeSet::new(),
+        load_stats:          CrateLoadStats::default(),
     });
 
     let parsed_crate_items = f(
@@ -352,7 +354,7 @@ fn parse_nano_core_symbol_file(
             let typ = SectionType::EhFrame;
             crate_items.sections.insert(
                 section_counter,
-                Arc::new(LoadedSection::new(
+                LoadedSection::new(
                     typ,
                     section_name_str_ref(&typ),
                     Arc::clone(rodata_pages),
@@ -361,7 +363,7 @@ fn parse_nano_core_symbol_file(
                     sec_size,
                     false, // .eh_frame is not global
                     new_crate_weak_ref.clone(),
-                ))
+                ).into_ref()
             );
             section_counter += 1;
         }
@@ -373,7 +375,7 @@ fn parse_nano_core_symbol_file(
             let typ = SectionType::GccExceptTable;
             crate_items.sections.insert(
                 section_counter,
-                Arc::new(LoadedSection::new(
+                LoadedSection::new(
                     typ,
                     section_name_str_ref(&typ),
                     Arc::clone(rodata_pages),
@@ -382,7 +384,7 @@ fn parse_nano_core_symbol_file(
                     sec_size,
                     false, // .gcc_except_table is not global
                     new_crate_weak_ref.clone(),
-                ))
+                ).into_ref()
             );
             section_counter += 1;
         }
@@ -619,7 +621,7 @@ fn parse_nano_core_binary(
                 let typ = SectionType::GccExceptTable;
                 crate_items.sections.insert(
                     section_counter,
-                    Arc::new(LoadedSection::new(
+                    LoadedSection::new(
                         typ,
                         section_name_str_ref(&typ),
                         Arc::clone(rodata_pages),
@@ -628,7 +630,7 @@ fn parse_nano_core_binary(
                         sec_size,
                         false, // .gcc_except_table is not global
                         new_crate_weak_ref.clone(),
-                    ))
+                    ).into_ref()
                 );
                 section_counter += 1;
             }
@@ -640,7 +642,7 @@ fn parse_nano_core_binary(
                 let typ = SectionType::EhFrame;
                 crate_items.sections.insert(
                     section_counter,
-                    Arc::new(LoadedSection::new(
+                    LoadedSection::new(
                         typ,
                         section_name_str_ref(&typ),
                         Arc::clone(rodata_pages),
@@ -649,7 +651,7 @@ fn parse_nano_core_binary(
                         sec_size,
                         false, // .eh_frame is not global
                         new_crate_weak_ref.clone(),
-                    ))
+                    ).into_ref()
                 );
                 section_counter += 1;
             }
@@ -783,7 +785,7 @@ fn add_new_section(
     let new_section = if sec_ndx == main_section_info.text_shndx {
         let sec_vaddr = VirtualAddress::new(sec_vaddr)
             .ok_or("new text section had invalid virtual address")?;
-        Some(Arc::new(LoadedSection::new(
+        Some(LoadedSection::new(
             SectionType::Text,
             sec_name,
             Arc::clone(text_pages),
@@ -792,12 +794,12 @@ fn add_new_section(
             sec_size,
             global,
             new_crate_weak_ref.clone(), 
-        )))
+        ).into_ref())
     }
     else if sec_ndx == main_section_info.rodata_shndx {
         let sec_vaddr = VirtualAddress::new(sec_vaddr)
             .ok_or("new rodata section had invalid virtual address")?;
-        Some(Arc::new(LoadedSection::new(
+        Some(LoadedSection::new(
             SectionType::Rodata,
             sec_name,
             Arc::clone(rodata_pages),
@@ -806,12 +808,12 @@ fn add_new_section(
             sec_size,
             global,
             new_crate_weak_ref.clone(),
-        )))
+        ).into_ref())
     }
     else if sec_ndx == main_section_info.data_shndx {
         let sec_vaddr = VirtualAddress::new(sec_vaddr)
             .ok_or("new data section had invalid virtual address")?;
-        Some(Arc::new(LoadedSection::new(
+        Some(LoadedSection::new(
             SectionType::Data,
             sec_name,
             Arc::clone(data_pages),
@@ -820,12 +822,12 @@ fn add_new_section(
             sec_size,
             global,
             new_crate_weak_ref.clone(),
-        )))
+        ).into_ref())
     }
     else if sec_ndx == main_section_info.bss_shndx {
         let sec_vaddr = VirtualAddress::new(sec_vaddr)
             .ok_or("new bss section had invalid virtual address")?;
-        Some(Arc::new(LoadedSection::new(
+        Some(LoadedSection::new(
             SectionType::Bss,
             sec_name,
             Arc::clone(data_pages),
@@ -834,7 +836,7 @@ fn add_new_section(
             sec_size,
             global,
             new_crate_weak_ref.clone(),
-        )))
+        ).into_ref())
     }
     else if main_section_info.tls_data_info.map_or(false, |(shndx, _)| sec_ndx == shndx) {
         // Skip zero-sized TLS sections, which are just markers, not real sections.
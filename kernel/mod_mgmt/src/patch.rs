@@ -0,0 +1,210 @@
+//! Hot-patching individual functions within a loaded crate.
+//!
+//! [`CrateNamespace::patch_function()`] replaces a single `.text` section of a loaded crate
+//! with a new version of that function, without swapping the rest of the crate.
+//! Callers are redirected to the new function in two ways:
+//! * Callers that are tracked as dependents of the old section, i.e., those in other crates,
+//!   have their relocations rewritten to point directly to the new section.
+//! * All other call sites, e.g., calls from within the same crate or through function pointers,
+//!   still reach the old section, so its first instructions are overwritten with a *trampoline*
+//!   that jumps to the new section.
+//!
+//! The trampoline is recorded as a dependency of the old section on the new section,
+//! so the new section stays loaded as long as the old one does, and swapping out the
+//! replacement crate later re-targets the trampoline just like any other relocation.
+
+use alloc::{string::{String, ToString}, sync::Arc, vec::Vec};
+use memory::{MmiRef, VirtualAddress};
+use fs_node::FileRef;
+use crate::{
+    CrateNamespace, LoadError, RelocationBatch, RelocationEntry, SectionType,
+    StrongCrateRef, StrongDependency, StrongSectionRef, WeakDependent,
+};
+
+impl CrateNamespace {
+    /// Replaces the function with the given fully-qualified symbol, which must be a `.text` section
+    /// of a crate loaded into this namespace (not its recursive namespace),
+    /// with the version of that function in the given `replacement_object_file`.
+    ///
+    /// The replacement object file must contain the new function under the same symbol,
+    /// though its hash may differ, and ideally contains nothing else.
+    /// It is loaded as its own crate, so its file name must not match any crate already in this namespace.
+    ///
+    /// The patch proceeds as follows:
+    /// 1. The replacement crate is loaded into a temporary namespace, using this namespace to resolve its dependencies.
+    /// 2. All sections that depend on the old function are re-linked to the new function in one batch,
+    ///    using [`rewrite_section_dependents_batched()`](#method.rewrite_section_dependents_batched).
+    /// 3. A trampoline that jumps to the new function is written over the start of the old function.
+    /// 4. The old function is recorded as depending on the new function, via the trampoline.
+    /// 5. The symbol of the old function is redirected to the new function in this namespace's symbol map,
+    ///    and the replacement crate (plus any crates newly loaded as its dependencies) is added to this namespace.
+    ///
+    /// The rest of the old crate remains loaded and unmodified. Returns the new function's section.
+    ///
+    /// Note that a task that is currently executing the old function continues to do so until it returns,
+    /// and a task that enters the old function while the trampoline is being written may execute a torn instruction.
+    /// Thus, functions should only be patched while they are known not to be running.
+    ///
+    /// # Errors
+    /// If this namespace is in strict symbol conflict mode, the patch fails before modifying anything
+    /// if the replacement crate defines any symbol other than the new function that is already defined
+    /// ([`LoadError::SymbolConflict`]).
+    ///
+    /// If re-linking the dependents or writing the trampoline fails ([`LoadError::Relocation`]),
+    /// the dependents that were already re-linked are restored to point to the old function.
+    pub fn patch_function(
+        &self,
+        demangled_full_symbol: &str,
+        replacement_object_file: &FileRef,
+        kernel_mmi_ref: &MmiRef,
+        verbose_log: bool,
+    ) -> Result<StrongSectionRef, LoadError> {
+        let old_sec = self.symbol_map.lock().get(demangled_full_symbol.as_bytes())
+            .and_then(|weak_sec| weak_sec.upgrade())
+            .ok_or("patch_function(): couldn't find the symbol to be patched in this namespace")?;
+        if old_sec.typ != SectionType::Text {
+            return Err("patch_function(): the symbol to be patched is not a .text section".into());
+        }
+        if old_sec.size < TRAMPOLINE_SIZE {
+            return Err("patch_function(): the function to be patched is too small to hold a trampoline".into());
+        }
+
+        let namespace_of_new_crate = CrateNamespace::new(String::from("temp_patch"), self.dir.clone(), None);
+        let (new_crate_ref, _num_syms) = namespace_of_new_crate.load_crate(replacement_object_file, Some(self), kernel_mmi_ref, verbose_log)?;
+        let new_crate_name = new_crate_ref.lock_as_ref().crate_name.clone();
+        if self.crate_tree.lock().get(new_crate_name.as_bytes()).is_some() {
+            return Err(LoadError::AlreadyLoaded { crate_name: new_crate_name });
+        }
+
+        let new_sec = namespace_of_new_crate.symbol_map.lock().get(demangled_full_symbol.as_bytes())
+            .and_then(|weak_sec| weak_sec.upgrade())
+            .or_else(|| namespace_of_new_crate.get_symbol_starting_with(old_sec.name_without_hash()).upgrade())
+            .filter(|new_sec| new_sec.typ == SectionType::Text && new_sec.name_without_hash() == old_sec.name_without_hash())
+            .ok_or_else(|| LoadError::MissingSymbol {
+                crate_name: new_crate_name.clone(),
+                symbol: old_sec.name.to_string(),
+            })?;
+
+        // Check for symbol conflicts before modifying anything. The new function is exempt,
+        // as it is meant to take over the old function's symbol.
+        let mut new_crates: Vec<StrongCrateRef> = Vec::new();
+        namespace_of_new_crate.for_each_crate(false, |_crate_name, crate_ref| {
+            if !crate_ref.is_shared() {
+                new_crates.push(crate_ref.clone());
+            }
+            true
+        });
+        for crate_ref in &new_crates {
+            let krate = crate_ref.lock_as_ref();
+            let sections = krate.sections.values().filter(|sec| !Arc::ptr_eq(sec, &new_sec));
+            self.check_symbol_conflicts(&krate.crate_name, sections, &[])?;
+        }
+
+        // Re-link all dependents in one batch, such that each of their regions is remapped only once.
+        let mut batch = RelocationBatch::new();
+        let relinked = CrateNamespace::rewrite_section_dependents_batched(&old_sec, &new_sec, &mut batch);
+        // Apply the queued writes even after a failure, such that they match the already-updated metadata.
+        let applied = batch.apply(kernel_mmi_ref, verbose_log);
+        let result = relinked.and(applied)
+            .and_then(|_| write_trampoline(&old_sec, new_sec.virt_addr, kernel_mmi_ref));
+        if let Err(reason) = result {
+            error!("patch_function(): failed to redirect {:?} to its replacement: {}. Rolling back.", old_sec.name, reason);
+            let mut restore_batch = RelocationBatch::new();
+            crate::swap::restore_section_dependents(&old_sec, &new_sec, &mut restore_batch);
+            if let Err(e) = restore_batch.apply(kernel_mmi_ref, verbose_log) {
+                error!("BUG: patch_function(): failed to restore dependents of {:?}: {}", old_sec.name, e);
+            }
+            return Err(LoadError::Relocation { section: old_sec.name.clone(), reason });
+        }
+
+        // The patch can no longer fail, so the old section no longer has any dependents.
+        old_sec.inner.write().sections_dependent_on_me.clear();
+        // The trampoline refers to the new section, just like a relocation in the old section would.
+        let relocation = RelocationEntry { typ: TRAMPOLINE_RELOCATION_TYPE, addend: 0, offset: TRAMPOLINE_TARGET_OFFSET };
+        new_sec.inner.write().sections_dependent_on_me.push(WeakDependent {
+            section: Arc::downgrade(&old_sec),
+            relocation,
+        });
+        old_sec.inner.write().sections_i_depend_on.push(StrongDependency {
+            section: Arc::clone(&new_sec),
+            relocation,
+        });
+        self.symbol_map.lock().insert(old_sec.name.clone(), Arc::downgrade(&new_sec));
+
+        // Move the replacement crate, and any crates newly loaded as its dependencies, into this namespace.
+        // Their symbols were already checked for conflicts above.
+        for crate_ref in new_crates {
+            let crate_name = {
+                let krate = crate_ref.lock_as_ref();
+                self.add_symbols(krate.sections.values(), verbose_log);
+                krate.crate_name.clone()
+            };
+            self.crate_tree.lock().insert(crate_name, crate_ref);
+        }
+
+        info!("patch_function(): patched {:?} with {:?} from crate {:?}", old_sec.name, new_sec.name, new_crate_name);
+        Ok(new_sec)
+    }
+}
+
+/// The size in bytes of the trampoline written by [`write_trampoline()`].
+#[cfg(target_arch = "x86_64")]
+const TRAMPOLINE_SIZE: usize = 14;
+#[cfg(target_arch = "aarch64")]
+const TRAMPOLINE_SIZE: usize = 16;
+
+/// The offset within a trampoline of its 8-byte absolute target address.
+const TRAMPOLINE_TARGET_OFFSET: usize = TRAMPOLINE_SIZE - 8;
+/// The relocation type that writes an 8-byte absolute address, as the trampoline's target is written.
+#[cfg(target_arch = "x86_64")]
+const TRAMPOLINE_RELOCATION_TYPE: u32 = 1; // R_X86_64_64
+#[cfg(target_arch = "aarch64")]
+const TRAMPOLINE_RELOCATION_TYPE: u32 = 257; // R_AARCH64_ABS64
+
+/// Returns the machine code of a trampoline that jumps to the given `target` address.
+///
+/// The trampoline loads its target from the 8 bytes that follow its jump instruction.
+#[cfg(target_arch = "x86_64")]
+fn trampoline(target: VirtualAddress) -> [u8; TRAMPOLINE_SIZE] {
+    let mut code = [0u8; TRAMPOLINE_SIZE];
+    // jmp qword ptr [rip + 0]
+    code[..6].copy_from_slice(&[0xFF, 0x25, 0x00, 0x00, 0x00, 0x00]);
+    code[6..].copy_from_slice(&(target.value() as u64).to_le_bytes());
+    code
+}
+
+/// Returns the machine code of a trampoline that jumps to the given `target` address.
+///
+/// The trampoline loads its target from the 8 bytes that follow its branch instruction
+/// into `x16`, which the procedure call standard reserves as a scratch register for veneers like this one.
+#[cfg(target_arch = "aarch64")]
+fn trampoline(target: VirtualAddress) -> [u8; TRAMPOLINE_SIZE] {
+    let mut code = [0u8; TRAMPOLINE_SIZE];
+    // ldr x16, #8
+    code[0..4].copy_from_slice(&0x5800_0050u32.to_le_bytes());
+    // br x16
+    code[4..8].copy_from_slice(&0xD61F_0200u32.to_le_bytes());
+    code[8..].copy_from_slice(&(target.value() as u64).to_le_bytes());
+    code
+}
+
+/// Overwrites the start of the given `.text` section with a trampoline that jumps to the given `target` address.
+fn write_trampoline(sec: &StrongSectionRef, target: VirtualAddress, kernel_mmi_ref: &MmiRef) -> Result<(), &'static str> {
+    let code = trampoline(target);
+    let mut mapped_pages = sec.mapped_pages.lock();
+    let initial_flags = mapped_pages.flags();
+    if !initial_flags.is_writable() {
+        mapped_pages.remap(&mut kernel_mmi_ref.lock().page_table, initial_flags.writable(true))?;
+    }
+    let result = mapped_pages.as_slice_mut::<u8>(sec.mapped_pages_offset, code.len())
+        .map(|dest| dest.copy_from_slice(&code));
+    if !initial_flags.is_writable() {
+        mapped_pages.remap(&mut kernel_mmi_ref.lock().page_table, initial_flags)?;
+    }
+    #[cfg(target_arch = "aarch64")]
+    if result.is_ok() {
+        // Ensure that the new instructions are visible to instruction fetches.
+        unsafe { core::arch::asm!("dsb ish", "ic iallu", "dsb ish", "isb") };
+    }
+    result
+}
@@ -0,0 +1,167 @@
+//! A cache of crate relocation results, which allows the relocation (linking) stage
+//! to be mostly skipped when the same crate object file is loaded repeatedly
+//! against the same dependencies, e.g., when spawning many instances of an application.
+//!
+//! Each entry is keyed by the absolute path of the crate object file,
+//! and is only used if that file still has the same length and content hash.
+//! It records the relocated contents of each of the crate's memory regions,
+//! every relocation that was applied to that crate,
+//! and the exact foreign sections (dependencies) that it was linked against.
+//! A cached entry is only used if all of those dependencies are still
+//! the ones that the loading namespace resolves those symbols to.
+//!
+//! When an entry is used, its relocated regions are copied into the new crate's regions,
+//! and only the relocations whose values depend on where the new crate was loaded are rewritten.
+//!
+//! The cache holds at most [`MAX_PRELINK_CACHE_ENTRIES`] entries;
+//! inserting another one evicts the least recently used entry.
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use spin::Mutex;
+use memory::VirtualAddress;
+use crate::{RelocationEntry, Shndx, StrongSectionRef, WeakSectionRef};
+
+/// The maximum number of crate object files whose relocation results are cached at once.
+///
+/// Each entry holds a copy of its crate's relocated regions, so the cache must be bounded.
+pub const MAX_PRELINK_CACHE_ENTRIES: usize = 32;
+
+/// The system-wide cache of relocation results, keyed by object file path.
+static PRELINK_CACHE: Mutex<PrelinkCache> = Mutex::new(PrelinkCache {
+    entries: BTreeMap::new(),
+    clock: 0,
+});
+
+struct PrelinkCache {
+    /// Each entry, along with the value of `clock` when it was last used.
+    entries: BTreeMap<String, (u64, Arc<PrelinkedCrate>)>,
+    /// Incremented upon every use of an entry, to find the least recently used one.
+    clock: u64,
+}
+
+/// The recorded result of relocating a single crate.
+pub(crate) struct PrelinkedCrate {
+    /// The length of the crate object file that this crate was loaded from.
+    pub(crate) file_len: usize,
+    /// The hash of the contents of the crate object file that this crate was loaded from.
+    pub(crate) file_hash: u64,
+    /// The foreign symbols that this crate depends on and the sections they resolved to.
+    pub(crate) dependencies: Vec<(String, WeakSectionRef)>,
+    /// All relocations applied to this crate, grouped by the shndx of their target section.
+    pub(crate) relocations: Vec<(Shndx, Vec<CachedRelocation>)>,
+    /// The contents of the crate's text, rodata, and data regions (in that order)
+    /// after all relocations were applied, if the crate has that region.
+    pub(crate) images: [Option<RegionImage>; 3],
+}
+
+/// The contents of one of a crate's memory regions after its relocations were applied.
+pub(crate) struct RegionImage {
+    pub(crate) bytes: Vec<u8>,
+}
+
+/// A single relocation that was previously applied to a crate's target section.
+pub(crate) struct CachedRelocation {
+    pub(crate) entry: RelocationEntry,
+    pub(crate) source: RelocationSource,
+    /// The source value that was written, i.e., the source section's address plus the `value` offset.
+    pub(crate) source_vaddr: VirtualAddress,
+    /// The address that the relocation was written to.
+    pub(crate) target_vaddr: VirtualAddress,
+}
+
+impl CachedRelocation {
+    /// Returns whether this relocation must be rewritten if its source value is now `source_vaddr`
+    /// and it is written to `target_vaddr`, i.e., whether the value written would differ.
+    pub(crate) fn needs_rewrite(&self, source_vaddr: VirtualAddress, target_vaddr: VirtualAddress) -> bool {
+        if self.entry.is_absolute() {
+            source_vaddr != self.source_vaddr
+        } else {
+            // Conservatively, any other relocation is assumed to depend on the target address.
+            source_vaddr.value().wrapping_sub(target_vaddr.value())
+                != self.source_vaddr.value().wrapping_sub(self.target_vaddr.value())
+        }
+    }
+}
+
+/// The source of a cached relocation, i.e., what the relocation entry refers to.
+pub(crate) enum RelocationSource {
+    /// A section within the same crate, plus an offset into that section.
+    Internal { shndx: Shndx, value: usize },
+    /// A section in another crate, plus an offset into that section.
+    /// The `dependency` is an index into [`PrelinkedCrate::dependencies`].
+    Foreign { dependency: usize, value: usize },
+    /// A fixed address that doesn't depend on any section,
+    /// e.g., for the special `__THESEUS_TLS_SIZE` and `__THESEUS_CLS_SIZE` symbols.
+    Absolute(VirtualAddress),
+}
+
+impl PrelinkedCrate {
+    /// Returns strong references to this crate's dependencies, in the same order as `dependencies`.
+    ///
+    /// Returns `None` if any dependency has been dropped
+    /// or if `resolve` now returns a different section for it.
+    pub(crate) fn upgrade_dependencies<F>(&self, mut resolve: F) -> Option<Vec<StrongSectionRef>>
+        where F: FnMut(&str) -> Option<WeakSectionRef>
+    {
+        self.dependencies.iter()
+            .map(|(symbol, weak_sec)| {
+                let current = resolve(symbol)?;
+                if current.ptr_eq(weak_sec) {
+                    weak_sec.upgrade()
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the cached relocation results for the object file at the given `path`,
+/// if any were recorded for a file with the given length and content hash.
+///
+/// A stale entry for a different version of that object file is removed.
+pub(crate) fn get(path: &str, file_len: usize, file_hash: u64) -> Option<Arc<PrelinkedCrate>> {
+    let mut cache = PRELINK_CACHE.lock();
+    cache.clock += 1;
+    let clock = cache.clock;
+    let (last_used, prelinked) = cache.entries.get_mut(path)?;
+    if prelinked.file_len != file_len || prelinked.file_hash != file_hash {
+        cache.entries.remove(path);
+        return None;
+    }
+    *last_used = clock;
+    Some(Arc::clone(prelinked))
+}
+
+/// Caches the relocation results for the object file at the given `path`,
+/// evicting the least recently used entry if the cache is full.
+pub(crate) fn insert(path: String, prelinked: PrelinkedCrate) {
+    let mut cache = PRELINK_CACHE.lock();
+    if !cache.entries.contains_key(&path) && cache.entries.len() >= MAX_PRELINK_CACHE_ENTRIES {
+        let least_recently_used = cache.entries.iter()
+            .min_by_key(|(_, (last_used, _))| *last_used)
+            .map(|(path, _)| path.clone());
+        if let Some(lru_path) = least_recently_used {
+            cache.entries.remove(&lru_path);
+        }
+    }
+    cache.clock += 1;
+    let clock = cache.clock;
+    cache.entries.insert(path, (clock, Arc::new(prelinked)));
+}
+
+/// Removes the cached relocation results for the object file at the given `path`, if any.
+pub(crate) fn remove(path: &str) {
+    PRELINK_CACHE.lock().entries.remove(path);
+}
+
+/// Removes all entries from the prelinking cache, e.g., to reclaim memory
+/// or after swapping crates that many cached applications depend on.
+pub fn clear_prelink_cache() {
+    PRELINK_CACHE.lock().entries.clear();
+}
+
+/// Returns the number of crate object files whose relocation results are currently cached.
+pub fn prelink_cache_len() -> usize {
+    PRELINK_CACHE.lock().entries.len()
+}
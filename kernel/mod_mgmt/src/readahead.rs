@@ -0,0 +1,315 @@
+//! Readahead of the crate object files that a crate is likely to need while it is being loaded.
+//!
+//! Relocating a crate often encounters symbols that aren't loaded yet, at which point
+//! [`CrateNamespace::get_symbol_or_load()`] loads the crates that contain them one at a time,
+//! each time stalling the relocation until the crate's object file is read and parsed.
+//!
+//! To hide that latency, `mod_mgmt` records the history of which crates were loaded
+//! to resolve missing symbols while each crate was being loaded.
+//! Loads are tracked per task, such that concurrent loads by different tasks aren't mixed together.
+//! When a crate is loaded again later, e.g., in another namespace or after being unloaded,
+//! the crates that its last load pulled in are prefetched: their object files are read,
+//! parsed, and their sections are copied into memory ahead of time,
+//! via [`CrateNamespace::load_crate_sections_only()`].
+//! When a relocation then needs one of those crates, only its relocation work remains.
+//!
+//! The `mod_mgmt` crate can't spawn tasks itself, so readahead is disabled until
+//! [`ReadaheadHooks`] that prefetch crates asynchronously have been registered via [`set_readahead_hooks()`];
+//! see the `crate_readahead` crate. While it is disabled, no history is recorded either.
+//!
+//! Prefetching is purely an optimization: if a prefetched crate is never needed,
+//! it is eventually discarded, and if a crate is needed before its prefetch has finished,
+//! it is loaded as usual and the prefetched copy is discarded.
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use memory::MmiRef;
+use fs_node::FileRef;
+use path::Path;
+use spin::Mutex;
+use xmas_elf::ElfFile;
+use crate::{CrateNamespace, LoadError, PartiallyLoadedCrate, StrongCrateRef, CRATE_HASH_DELIMITER, crate_name_from_path};
+
+/// The maximum number of crates remembered as the likely dependencies of a single crate.
+const MAX_PREDICTED_CRATES: usize = 16;
+/// The maximum number of missing-symbol crate loads that are remembered for the loads in progress in one task.
+const MAX_RECENT_LOADS: usize = 256;
+/// The maximum number of prefetched crates kept in memory, waiting to be used.
+const MAX_PREFETCHED_CRATES: usize = 32;
+
+/// The functions that [`CrateNamespace::load_crate()`] uses to prefetch crates.
+#[derive(Clone, Copy)]
+pub struct ReadaheadHooks {
+    /// Prefetches crates asynchronously, which is invoked with a request for the crates
+    /// that a crate currently being loaded is likely to need.
+    ///
+    /// This should prefetch each crate via [`CrateNamespace::prefetch_crate()`]
+    /// in the namespace with the requested name, e.g., as found by [`get_registered_namespace()`](crate::get_registered_namespace),
+    /// and must not block.
+    pub prefetch: fn(ReadaheadRequest),
+    /// Returns the ID of the current task, by which the crate loads in progress are tracked.
+    pub current_task_id: fn() -> usize,
+    /// Regenerates the CLS data image of every CPU, such that CLS sections prefetched on other CPUs are included.
+    pub reload_cls: fn(),
+}
+
+/// A request to prefetch the object files of crates that will likely be loaded soon.
+#[derive(Clone)]
+pub struct ReadaheadRequest {
+    /// The name of the crate whose load prompted this request.
+    pub crate_name: String,
+    /// The object files to prefetch, each along with the name of the namespace that should load it.
+    pub files: Vec<(String, FileRef)>,
+}
+
+static READAHEAD_HOOKS: Mutex<Option<ReadaheadHooks>> = Mutex::new(None);
+
+/// For each crate (without its hash), the crates (without their hashes) that its last load pulled in.
+static LOAD_HISTORY: Mutex<BTreeMap<String, Vec<String>>> = Mutex::new(BTreeMap::new());
+
+/// The crate loads in progress in each task, keyed by task ID.
+static LOADS_IN_PROGRESS: Mutex<BTreeMap<usize, TaskLoads>> = Mutex::new(BTreeMap::new());
+
+/// The crate loads in progress in a single task.
+#[derive(Default)]
+struct TaskLoads {
+    /// The number of loads in progress, as loading a crate can load others to resolve missing symbols.
+    depth: usize,
+    /// The crates (without their hashes) that were loaded to resolve missing symbols
+    /// since the outermost load began, in the order they were loaded.
+    pulled_in: Vec<String>,
+}
+
+/// Crates whose prefetch was requested, keyed by namespace name and object file path.
+static PREFETCHED: Mutex<Vec<PrefetchedCrate>> = Mutex::new(Vec::new());
+
+struct PrefetchedCrate {
+    namespace: String,
+    path: String,
+    /// `None` while the prefetch is still in progress.
+    partial: Option<PartiallyLoadedCrate>,
+}
+
+/// Registers the hooks that perform prefetching, or disables readahead if `hooks` is `None`.
+///
+/// Disabling readahead discards all prefetched crates.
+pub fn set_readahead_hooks(hooks: Option<ReadaheadHooks>) {
+    *READAHEAD_HOOKS.lock() = hooks;
+    if hooks.is_none() {
+        clear_prefetched_crates();
+    }
+}
+
+/// Discards all prefetched crates that haven't been used yet.
+pub fn clear_prefetched_crates() {
+    let discarded = core::mem::take(&mut *PREFETCHED.lock());
+    drop(discarded);
+}
+
+/// Returns the crates (without their hashes) that the last load of the given crate pulled in
+/// to resolve missing symbols, in the order they were loaded.
+pub fn predicted_dependencies(crate_name_without_hash: &str) -> Vec<String> {
+    LOAD_HISTORY.lock().get(crate_name_without_hash).cloned().unwrap_or_default()
+}
+
+/// Returns the name of the crate in the given object file, without its hash.
+fn crate_name_without_hash(object_file: &FileRef) -> Option<String> {
+    let path = object_file.lock().get_absolute_path();
+    let crate_name = crate_name_from_path(Path::new(&path))?;
+    crate_name.split(CRATE_HASH_DELIMITER).next().map(ToString::to_string)
+}
+
+/// The state of a crate load for the purpose of recording its history, returned by [`CrateNamespace::begin_readahead()`].
+///
+/// This is `None` if readahead was disabled when the load began.
+/// Dropping it without passing it to [`CrateNamespace::finish_readahead()`], e.g., because the load failed,
+/// ends the load without recording its history.
+pub(crate) struct ReadaheadLoad(Option<TrackedLoad>);
+
+struct TrackedLoad {
+    crate_name: String,
+    task_id: usize,
+    /// The number of crates that the task's loads in progress had pulled in when this load began.
+    start: usize,
+}
+
+impl Drop for ReadaheadLoad {
+    fn drop(&mut self) {
+        let Some(load) = self.0.take() else { return };
+        let mut loads = LOADS_IN_PROGRESS.lock();
+        if let Some(task_loads) = loads.get_mut(&load.task_id) {
+            task_loads.depth -= 1;
+            if task_loads.depth == 0 {
+                loads.remove(&load.task_id);
+            }
+        }
+    }
+}
+
+impl CrateNamespace {
+    /// Invoked when loading the crate in the given object file begins:
+    /// requests that the crates its previous load pulled in are prefetched.
+    ///
+    /// Does nothing if readahead is disabled.
+    pub(crate) fn begin_readahead(&self, crate_object_file: &FileRef) -> ReadaheadLoad {
+        let hooks = *READAHEAD_HOOKS.lock();
+        let (Some(hooks), Some(crate_name)) = (hooks, crate_name_without_hash(crate_object_file)) else {
+            return ReadaheadLoad(None);
+        };
+        let task_id = (hooks.current_task_id)();
+        let start = {
+            let mut loads = LOADS_IN_PROGRESS.lock();
+            let task_loads = loads.entry(task_id).or_default();
+            task_loads.depth += 1;
+            task_loads.pulled_in.len()
+        };
+
+        let mut files = Vec::new();
+        for predicted in predicted_dependencies(&crate_name) {
+            let prefix = alloc::format!("{predicted}{CRATE_HASH_DELIMITER}");
+            if self.get_crate_starting_with(&prefix).is_some() {
+                continue;
+            }
+            let Some((file, namespace)) = self.method_get_crate_object_file_starting_with(&prefix) else { continue };
+            let path = file.lock().get_absolute_path();
+            let namespace = String::from(namespace.name());
+            let mut prefetched = PREFETCHED.lock();
+            if prefetched.iter().any(|p| p.namespace == namespace && p.path == path) {
+                continue;
+            }
+            prefetched.push(PrefetchedCrate { namespace: namespace.clone(), path, partial: None });
+            files.push((namespace, file));
+        }
+        evict_prefetched_crates();
+        if !files.is_empty() {
+            (hooks.prefetch)(ReadaheadRequest { crate_name: crate_name.clone(), files });
+        }
+        ReadaheadLoad(Some(TrackedLoad { crate_name, task_id, start }))
+    }
+
+    /// Invoked when the given crate load has finished successfully:
+    /// records the crates that it pulled in to resolve missing symbols.
+    pub(crate) fn finish_readahead(&self, load: ReadaheadLoad) {
+        let Some(TrackedLoad { crate_name, task_id, start }) = load.0.as_ref() else { return };
+        let pulled_in: Vec<String> = LOADS_IN_PROGRESS.lock().get(task_id)
+            .map(|task_loads| task_loads.pulled_in.iter()
+                .skip(*start)
+                .filter(|name| *name != crate_name)
+                .take(MAX_PREDICTED_CRATES)
+                .cloned()
+                .collect()
+            )
+            .unwrap_or_default();
+        // A load that pulled in nothing, e.g., because everything was already loaded, isn't informative.
+        if !pulled_in.is_empty() {
+            LOAD_HISTORY.lock().insert(crate_name.clone(), pulled_in);
+        }
+    }
+
+    /// Records that the crate in the given object file was loaded to resolve a missing symbol
+    /// by one of the current task's loads in progress.
+    pub(crate) fn record_missing_symbol_load(&self, crate_object_file: &FileRef) {
+        let hooks = *READAHEAD_HOOKS.lock();
+        let Some(hooks) = hooks else { return };
+        let task_id = (hooks.current_task_id)();
+        let Some(crate_name) = crate_name_without_hash(crate_object_file) else { return };
+        if let Some(task_loads) = LOADS_IN_PROGRESS.lock().get_mut(&task_id) {
+            if task_loads.pulled_in.len() < MAX_RECENT_LOADS {
+                task_loads.pulled_in.push(crate_name);
+            }
+        }
+    }
+
+    /// Prefetches the crate in the given object file, whose prefetch was requested
+    /// by a [`ReadaheadRequest`] for this namespace: loads its sections into memory
+    /// such that a later load of the crate only needs to relocate it.
+    ///
+    /// This may be invoked from any task. It does nothing if the prefetch is no longer wanted,
+    /// e.g., because the crate was loaded in the meantime.
+    pub fn prefetch_crate(
+        &self,
+        crate_object_file: &FileRef,
+        kernel_mmi_ref: &MmiRef,
+        verbose_log: bool,
+    ) -> Result<(), LoadError> {
+        let path = crate_object_file.lock().get_absolute_path();
+        let is_wanted = |p: &PrefetchedCrate| p.namespace == self.name() && p.path == path && p.partial.is_none();
+        if !PREFETCHED.lock().iter().any(is_wanted) {
+            return Ok(());
+        }
+        let result = self.load_crate_sections_only(crate_object_file, kernel_mmi_ref, verbose_log);
+        let mut prefetched = PREFETCHED.lock();
+        let entry = prefetched.iter_mut().find(|p| is_wanted(p));
+        match (result, entry) {
+            (Ok(partial), Some(entry)) => entry.partial = Some(partial),
+            (Ok(_unwanted), None) => { }
+            (Err(e), entry) => {
+                if entry.is_some() {
+                    prefetched.retain(|p| !is_wanted(p));
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes and returns the prefetched copy of the crate in the given object file, if it is ready.
+    ///
+    /// If its prefetch is still in progress, it is abandoned, as the caller will load the crate itself.
+    fn take_prefetched_crate(&self, crate_object_file: &FileRef) -> Option<PartiallyLoadedCrate> {
+        let path = crate_object_file.lock().get_absolute_path();
+        let mut prefetched = PREFETCHED.lock();
+        let index = prefetched.iter().position(|p| p.namespace == self.name() && p.path == path)?;
+        prefetched.remove(index).partial
+    }
+
+    /// Loads the crate in the given object file like [`load_crate_internal()`](#method.load_crate_internal),
+    /// but only relocates its prefetched copy if there is one.
+    pub(crate) fn load_crate_internal_with_readahead(
+        &self,
+        crate_object_file: &FileRef,
+        temp_backup_namespace: Option<&CrateNamespace>,
+        kernel_mmi_ref: &MmiRef,
+        verbose_log: bool,
+    ) -> Result<StrongCrateRef, LoadError> {
+        let Some(partial) = self.take_prefetched_crate(crate_object_file) else {
+            return self.load_crate_internal(crate_object_file, temp_backup_namespace, false, kernel_mmi_ref, verbose_log);
+        };
+        #[cfg(not(loscd_eval))]
+        debug!("load_crate: using prefetched copy of {:?}", partial.crate_ref());
+        {
+            // Re-parse the object file, as the `ElfFile` from the prefetch borrowed its lock.
+            let locked_file = crate_object_file.lock();
+            let mapped_pages = locked_file.as_mapping()?;
+            let byte_slice: &[u8] = mapped_pages.as_slice(0, locked_file.len())?;
+            let crate_name = partial.crate_ref().lock_as_ref().crate_name.clone();
+            let elf_file = ElfFile::new(byte_slice)
+                .map_err(|reason| LoadError::ElfParse { crate_name, reason })?;
+            self.perform_relocations(&elf_file, partial.crate_ref(), temp_backup_namespace, None, kernel_mmi_ref, verbose_log)?;
+        }
+        // The crate's CLS sections may have been loaded on another CPU, and may be accessed from any CPU.
+        let hooks = *READAHEAD_HOOKS.lock();
+        match hooks {
+            Some(hooks) => (hooks.reload_cls)(),
+            None => cls_allocator::reload_current_cpu(),
+        }
+        Ok(partial.into_crate_ref())
+    }
+}
+
+/// Discards the oldest prefetched crates, such that at most `MAX_PREFETCHED_CRATES` are kept.
+///
+/// Discarding a crate whose prefetch is still in progress abandons it,
+/// such that its result is dropped once it finishes.
+fn evict_prefetched_crates() {
+    let discarded: Vec<PrefetchedCrate> = {
+        let mut prefetched = PREFETCHED.lock();
+        let excess = prefetched.len().saturating_sub(MAX_PREFETCHED_CRATES);
+        prefetched.drain(..excess).collect()
+    };
+    // Drop the discarded crates (and unmap their sections) without holding the lock.
+    drop(discarded);
+}
@@ -150,7 +150,7 @@ fn load_crate_using_nano_core_data_sections(
     }
 
     // (5) Perform the actual relocations, using the replaced data sections above.
-    namespace.perform_relocations(&elf_file, &new_crate_ref, None, kernel_mmi_ref, verbose_log)?;
+    namespace.perform_relocations(&elf_file, &new_crate_ref, None, None, kernel_mmi_ref, verbose_log)?;
 
     info!("Replaced nano_core constituent crate {:?}, num sections: {}, added {} new symbols (should be 0).",
         new_crate_name, _num_new_sections, _num_new_syms
@@ -39,6 +39,7 @@ pub(crate) fn into_loaded_crate(
         debug_symbols_file:  Arc::downgrade(&object_file),
         object_file,
         sections:            HashMap::new(), // placeholder
+        lazy_sections:       BTreeMap::new(),
         text_pages:          Some((Arc::clone(text_pages), mp_range(text_pages))),
         rodata_pages:        Some((Arc::clone(rodata_pages), mp_range(rodata_pages))),
         data_pages:          Some((Arc::clone(data_pages), mp_range(data_pages))),
@@ -47,6 +48,7 @@ pub(crate) fn into_loaded_crate(
         cls_sections:        serialized_crate.cls_sections,
         data_sections:       serialized_crate.data_sections,
         reexported_symbols:  BTreeSet::new(),
+        load_stats:          CrateLoadStats::default(),
     });
     let parent_crate_weak_ref = CowArc::downgrade(&loaded_crate);
 
@@ -105,7 +107,7 @@ fn into_loaded_section(
     data_pages:         &Arc<Mutex<MappedPages>>,
     total_tls_size:     usize,
     total_cls_size:     usize,
-) -> Result<Arc<LoadedSection>, &'static str> {
+) -> Result<StrongSectionRef, &'static str> {
     let mapped_pages = match serialized_section.ty {
         SectionType::Text => Arc::clone(text_pages),
         SectionType::Rodata
@@ -147,6 +149,6 @@ fn into_loaded_section(
     } else if serialized_section.ty == SectionType::Cls && serialized_section.size > 0 {
         cls_allocator::add_static_section(loaded_section, serialized_section.virtual_address, total_cls_size).map_err(|e| panic!("{:?}", e))
     } else {
-        Ok(Arc::new(loaded_section))
+        Ok(loaded_section.into_ref())
     }
 }
@@ -0,0 +1,208 @@
+//! Replacing a loaded crate with a newer version of itself while preserving its state.
+//!
+//! [`CrateNamespace::swap_crate()`] is an end-to-end wrapper around the lower-level building blocks
+//! used for crate swapping, i.e., dependency tracking and [`CrateNamespace::rewrite_section_dependents()`].
+//! Unlike the more general `crate_swap` crate, it handles exactly one crate,
+//! and it restores every rewritten dependent if the swap fails partway through.
+
+use alloc::{string::{String, ToString}, sync::Weak, vec::Vec};
+use memory::MmiRef;
+use fs_node::FileRef;
+use cow_arc::CowArc;
+use crate::{CrateNamespace, LoadError, RelocationBatch, SectionType, StrongCrateRef, StrongSectionRef};
+
+impl CrateNamespace {
+    /// Replaces the crate `old_crate_name` in this namespace
+    /// with a newer version of the same crate loaded from `new_crate_object_file`.
+    ///
+    /// This is not atomic: other tasks may observe dependents being re-linked one region at a time,
+    /// and the old crate's state being migrated.
+    ///
+    /// The swap proceeds as follows:
+    /// 1. The new crate is loaded into a temporary namespace, using this namespace to resolve its dependencies.
+    /// 2. All sections in other crates that depend on the old crate are re-linked to the new crate
+    ///    using [`rewrite_section_dependents_batched()`](#method.rewrite_section_dependents_batched).
+    /// 3. The contents of each `.data` and `.bss` section in the old crate are copied into
+    ///    the section with the same name (excluding its hash) in the new crate.
+    ///    Old sections with no counterpart in the new crate are skipped.
+    /// 4. The old crate and its symbols are replaced by the new crate and its symbols in this namespace.
+    ///
+    /// Any crates loaded as new dependencies of the new crate are also added to this namespace.
+    /// The old crate itself is dropped once it is no longer in use, e.g., by a running task.
+    ///
+    /// Note that state is migrated after dependents are re-linked, such that the old crate's state
+    /// no longer changes once it is copied, unless a task is still running code in the old crate.
+    /// However, any modifications that re-linked dependents make to the new crate's state
+    /// before it is migrated are overwritten.
+    ///
+    /// # Errors
+    /// Before modifying anything, this checks that none of the new crates' symbols conflict with
+    /// those of crates other than the old crate, if this namespace is in strict symbol conflict mode
+    /// ([`LoadError::SymbolConflict`]), that the new crate provides a section corresponding to
+    /// every old section that other crates depend on ([`LoadError::MissingSymbol`]),
+    /// and that every `.data`/`.bss` section to be migrated has the same size in both crates ([`LoadError::Other`]).
+    ///
+    /// If re-linking a dependent fails ([`LoadError::Relocation`]) or migrating the state fails,
+    /// all dependents that were already re-linked are restored to point to the old crate,
+    /// which remains loaded as before.
+    pub fn swap_crate(
+        &self,
+        old_crate_name: &str,
+        new_crate_object_file: &FileRef,
+        kernel_mmi_ref: &MmiRef,
+        verbose_log: bool,
+    ) -> Result<StrongCrateRef, LoadError> {
+        let old_crate_ref = self.crate_tree.lock().get(old_crate_name.as_bytes())
+            .map(CowArc::clone_shallow)
+            .ok_or_else(|| LoadError::CrateNotFound { name: String::from(old_crate_name) })?;
+        if old_crate_ref.is_shared() {
+            return Err("swap_crate(): the old crate is shared with another namespace".into());
+        }
+
+        let namespace_of_new_crate = CrateNamespace::new(String::from("temp_swap"), self.dir.clone(), None);
+        let (new_crate_ref, _num_syms) = namespace_of_new_crate.load_crate(new_crate_object_file, Some(self), kernel_mmi_ref, verbose_log)?;
+
+        // The new crate and its newly-loaded dependencies are moved into this namespace below,
+        // so check their symbols against this namespace before modifying anything.
+        let replaced_crates = [old_crate_ref.lock_as_ref().crate_name.clone()];
+        let mut conflict_check = Ok(());
+        namespace_of_new_crate.for_each_crate(false, |_crate_name, crate_ref| {
+            if !crate_ref.is_shared() {
+                let krate = crate_ref.lock_as_ref();
+                conflict_check = self.check_symbol_conflicts(&krate.crate_name, krate.sections.values(), &replaced_crates);
+            }
+            conflict_check.is_ok()
+        });
+        conflict_check?;
+
+        let old_crate = old_crate_ref.lock_as_ref();
+        let new_crate = new_crate_ref.lock_as_ref();
+        if old_crate.crate_name == new_crate.crate_name {
+            return Err(LoadError::AlreadyLoaded { crate_name: new_crate.crate_name.clone() });
+        }
+        if old_crate.crate_name_without_hash() != new_crate.crate_name_without_hash() {
+            return Err("swap_crate(): the new crate is not a version of the old crate".into());
+        }
+
+        // Find the new sections corresponding to the old sections that other crates depend on.
+        let mut dependency_pairs: Vec<(&StrongSectionRef, StrongSectionRef)> = Vec::new();
+        for old_sec in old_crate.global_sections_iter() {
+            if old_sec.inner.read().sections_dependent_on_me.iter().all(|weak_dep| weak_dep.section.strong_count() == 0) {
+                continue;
+            }
+            let new_sec = namespace_of_new_crate.get_symbol(&old_sec.name).upgrade()
+                .or_else(|| new_crate.global_sections_iter()
+                    .find(|sec| sec.name_without_hash() == old_sec.name_without_hash())
+                    .cloned()
+                )
+                .ok_or_else(|| LoadError::MissingSymbol {
+                    crate_name: new_crate.crate_name.clone(),
+                    symbol: old_sec.name.to_string(),
+                })?;
+            dependency_pairs.push((old_sec, new_sec));
+        }
+
+        // Find the new sections that the old crate's state will be migrated into.
+        let mut state_pairs: Vec<(&StrongSectionRef, &StrongSectionRef)> = Vec::new();
+        for old_sec in old_crate.data_sections_iter() {
+            let name = old_sec.name_without_hash();
+            let Some(new_sec) = new_crate.data_sections_iter().find(|sec| sec.name_without_hash() == name) else {
+                debug!("swap_crate(): dropping state of {:?}, which doesn't exist in the new crate", old_sec.name);
+                continue;
+            };
+            if new_sec.size != old_sec.size {
+                error!("swap_crate(): size of {:?} changed from {:#X} to {:#X}", old_sec.name, old_sec.size, new_sec.size);
+                return Err("swap_crate(): the size of a .data or .bss section changed, so its state can't be migrated".into());
+            }
+            state_pairs.push((old_sec, new_sec));
+        }
+
+        // Re-link all dependents in one batch, such that each of their regions is remapped only once.
+        let mut batch = RelocationBatch::new();
+        let mut failure = None;
+        for (i, (old_sec, new_sec)) in dependency_pairs.iter().enumerate() {
+            if let Err(reason) = CrateNamespace::rewrite_section_dependents_batched(old_sec, new_sec, &mut batch) {
+                failure = Some((i, reason));
+                break;
+            }
+        }
+        // Apply the queued writes even after a failure, such that they match the already-updated metadata.
+        if let Err(reason) = batch.apply(kernel_mmi_ref, false) {
+            // The failed write can't be attributed to a single pair, so roll back all of them.
+            failure = failure.or(Some((dependency_pairs.len().saturating_sub(1), reason)));
+        }
+        if let Some((i, reason)) = failure {
+            let old_sec_name = dependency_pairs[i].0.name.clone();
+            error!("swap_crate(): failed to re-link dependents of {:?}: {}. Rolling back.", old_sec_name, reason);
+            restore_all_dependents(&dependency_pairs[..= i], kernel_mmi_ref);
+            return Err(LoadError::Relocation { section: old_sec_name, reason });
+        }
+
+        // Migrate the old crate's state only once its dependents use the new crate.
+        for (old_sec, new_sec) in &state_pairs {
+            if let Err(reason) = old_sec.copy_section_data_to(new_sec) {
+                error!("swap_crate(): failed to migrate the state of {:?}: {}. Rolling back.", old_sec.name, reason);
+                restore_all_dependents(&dependency_pairs, kernel_mmi_ref);
+                return Err(reason.into());
+            }
+        }
+
+        // The swap can no longer fail, so the old sections no longer have any dependents.
+        for (old_sec, _new_sec) in &dependency_pairs {
+            old_sec.inner.write().sections_dependent_on_me.clear();
+        }
+
+        // Every dependent of the old crate was re-linked above, so no other namespace still imports its symbols.
+        self.remove_symbols_of_crate_locked(&mut self.symbol_map.lock(), &old_crate);
+        for old_sec in old_crate.sections.values() {
+            if matches!(old_sec.typ, SectionType::TlsData | SectionType::TlsBss) {
+                self.tls_initializer.lock().remove_dynamic_section(old_sec);
+            }
+        }
+        self.crate_tree.lock().remove(old_crate_name.as_bytes());
+
+        // Move the new crate, and any crates newly loaded as its dependencies, into this namespace.
+        drop(new_crate);
+        namespace_of_new_crate.for_each_crate(false, |crate_name, crate_ref| {
+            if !crate_ref.is_shared() {
+                self.add_symbols(crate_ref.lock_as_ref().sections.values(), verbose_log);
+                self.crate_tree.lock().insert(crate_name.into(), crate_ref.clone());
+            }
+            true
+        });
+
+        info!("swap_crate(): swapped {:?} for {:?} in namespace {:?}", old_crate.crate_name, new_crate_ref, self.name);
+        Ok(new_crate_ref)
+    }
+}
+
+/// Restores the dependents of every old section in the given `(old_sec, new_sec)` pairs,
+/// such that they point to the old crate again.
+fn restore_all_dependents(dependency_pairs: &[(&StrongSectionRef, StrongSectionRef)], kernel_mmi_ref: &MmiRef) {
+    let mut restore_batch = RelocationBatch::new();
+    for (old_sec, new_sec) in dependency_pairs.iter().rev() {
+        restore_section_dependents(old_sec, new_sec, &mut restore_batch);
+    }
+    if let Err(e) = restore_batch.apply(kernel_mmi_ref, false) {
+        error!("BUG: swap_crate(): failed to restore dependents of the old crate: {}", e);
+    }
+}
+
+/// Undoes a (possibly partial) call to `rewrite_section_dependents(old_sec, new_sec)`,
+/// such that the dependents that were re-linked to `new_sec` point to `old_sec` again.
+///
+/// The relocation writes are queued into the given `batch`, which the caller must apply.
+pub(crate) fn restore_section_dependents(old_sec: &StrongSectionRef, new_sec: &StrongSectionRef, batch: &mut RelocationBatch) {
+    // `new_sec` is only depended on by the dependents that were already re-linked to it.
+    // Rewriting them adds them back to `old_sec`'s dependents, so remove them from there first to avoid duplicates.
+    let relinked = core::mem::take(&mut new_sec.inner.write().sections_dependent_on_me);
+    old_sec.inner.write().sections_dependent_on_me.retain(|weak_dep|
+        !relinked.iter().any(|r| Weak::ptr_eq(&r.section, &weak_dep.section) && r.relocation == weak_dep.relocation)
+    );
+    new_sec.inner.write().sections_dependent_on_me = relinked;
+
+    if let Err(e) = CrateNamespace::rewrite_section_dependents_batched(new_sec, old_sec, batch) {
+        error!("BUG: failed to restore dependents of {:?}: {}", old_sec.name, e);
+    }
+    new_sec.inner.write().sections_dependent_on_me.clear();
+}
@@ -0,0 +1,137 @@
+//! Detection of global symbols that are defined by more than one crate.
+//!
+//! By default, adding a crate's symbols to a namespace silently replaces any existing symbol
+//! of the same name, which hides bugs such as two crates exporting the same `no_mangle` symbol.
+//! A namespace in strict symbol conflict mode (see [`CrateNamespace::set_strict_symbol_conflicts()`])
+//! instead refuses to load or swap in such a crate, whether the existing definition is in that namespace
+//! or in one of its recursive namespaces, and [`CrateNamespace::symbol_conflicts()`]
+//! finds all such duplicates among the crates that are already loaded.
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use crate::{CrateNamespace, LoadError, StrRef, StrongSectionRef, WeakSectionRef};
+
+/// A fully-qualified global symbol that is defined by more than one crate.
+#[derive(Clone, Debug)]
+pub struct SymbolConflict {
+    pub symbol: StrRef,
+    /// Every definition of the symbol, in order from the top-level namespace downwards.
+    pub definitions: Vec<SymbolDefinition>,
+}
+
+/// One definition of a [`SymbolConflict`]'s symbol.
+#[derive(Clone, Debug)]
+pub struct SymbolDefinition {
+    /// The name of the namespace containing the defining crate.
+    pub namespace: String,
+    pub crate_name: StrRef,
+    /// The size in bytes of the section that defines the symbol.
+    pub size: usize,
+    /// Whether this definition is the one in its namespace's symbol map,
+    /// i.e., the one used to resolve dependencies on the symbol within that namespace.
+    pub in_symbol_map: bool,
+}
+
+impl CrateNamespace {
+    /// Adds the global symbols in the given `sections` of the crate named `crate_name`
+    /// to this namespace's symbol map, like [`add_symbols()`](#method.add_symbols).
+    ///
+    /// If this namespace is in strict symbol conflict mode and any of those symbols
+    /// is already defined by a different crate, no symbols are added and an error is returned;
+    /// see [`check_symbol_conflicts()`](Self::check_symbol_conflicts).
+    pub(crate) fn add_symbols_checked<'a, I>(
+        &self,
+        crate_name: &StrRef,
+        sections: I,
+        log_replacements: bool,
+    ) -> Result<usize, LoadError>
+        where I: IntoIterator<Item = &'a StrongSectionRef> + Clone,
+    {
+        self.check_symbol_conflicts(crate_name, sections.clone(), &[])?;
+        Ok(self.add_symbols(sections, log_replacements))
+    }
+
+    /// If this namespace is in strict symbol conflict mode, returns an error if any global symbol
+    /// in the given `sections` of the crate named `crate_name` is already defined by a different crate,
+    /// either in this namespace or in any of its recursive namespaces.
+    ///
+    /// Definitions by the crates named in `replaced_crates` are ignored,
+    /// as those crates are about to be replaced, e.g., by a crate swap.
+    ///
+    /// Every path that adds a crate's symbols to a namespace must check them via this function
+    /// before modifying anything, such that a conflicting crate is never partially added.
+    pub fn check_symbol_conflicts<'a, I>(
+        &self,
+        crate_name: &StrRef,
+        sections: I,
+        replaced_crates: &[StrRef],
+    ) -> Result<(), LoadError>
+        where I: IntoIterator<Item = &'a StrongSectionRef> + Clone,
+    {
+        if !self.strict_symbol_conflicts() {
+            return Ok(());
+        }
+        let mut namespace = Some(self);
+        while let Some(ns) = namespace {
+            // Collect the existing sections first, such that no crate is locked while the symbol map is locked.
+            let existing: Vec<(StrRef, WeakSectionRef)> = {
+                let symbol_map = ns.symbol_map.lock();
+                sections.clone().into_iter()
+                    .filter(|sec| sec.global)
+                    .filter_map(|sec| symbol_map.get(sec.name.as_bytes()).map(|old| (sec.name.clone(), old.clone())))
+                    .collect()
+            };
+            for (symbol, old_sec) in existing {
+                let Some(old_sec) = old_sec.upgrade() else { continue };
+                let Some(existing_crate) = old_sec.parent_crate.upgrade() else { continue };
+                let existing_crate = existing_crate.lock_as_ref().crate_name.clone();
+                if existing_crate != *crate_name && !replaced_crates.contains(&existing_crate) {
+                    return Err(LoadError::SymbolConflict { symbol, existing_crate, new_crate: crate_name.clone() });
+                }
+            }
+            namespace = ns.recursive_namespace.as_deref();
+        }
+        Ok(())
+    }
+
+    /// Returns every fully-qualified global symbol that is defined by more than one crate
+    /// in this namespace and its recursive namespaces, in order of symbol name.
+    ///
+    /// This includes symbols that were replaced in the symbol map when a later crate was loaded,
+    /// as well as symbols in this namespace that shadow a symbol in a recursive namespace.
+    /// A crate that is shared by multiple namespaces only counts as a single definition.
+    ///
+    /// # Locking
+    /// This obtains the lock on every crate, so the caller must not hold any such locks.
+    pub fn symbol_conflicts(&self) -> Vec<SymbolConflict> {
+        let mut definitions: BTreeMap<StrRef, Vec<SymbolDefinition>> = BTreeMap::new();
+        let mut namespace = Some(self);
+        while let Some(ns) = namespace {
+            let crates: Vec<_> = ns.crate_tree.lock().iter().map(|(_, crate_ref)| crate_ref.clone_shallow()).collect();
+            for crate_ref in crates {
+                let krate = crate_ref.lock_as_ref();
+                for sec in krate.global_sections.iter().filter_map(|shndx| krate.sections.get(shndx)) {
+                    let defs = definitions.entry(sec.name.clone()).or_default();
+                    if defs.iter().any(|d| d.crate_name == krate.crate_name) {
+                        continue;
+                    }
+                    let in_symbol_map = ns.symbol_map.lock()
+                        .get(sec.name.as_bytes())
+                        .and_then(WeakSectionRef::upgrade)
+                        .map_or(false, |mapped| Arc::ptr_eq(&mapped, sec));
+                    defs.push(SymbolDefinition {
+                        namespace: String::from(ns.name()),
+                        crate_name: krate.crate_name.clone(),
+                        size: sec.size,
+                        in_symbol_map,
+                    });
+                }
+            }
+            namespace = ns.recursive_namespace.as_deref();
+        }
+
+        definitions.into_iter()
+            .filter(|(_, defs)| defs.len() > 1)
+            .map(|(symbol, definitions)| SymbolConflict { symbol, definitions })
+            .collect()
+    }
+}
@@ -0,0 +1,134 @@
+//! Restricting which symbols the crates in a namespace may link against.
+//!
+//! A sandboxed application namespace can be given a [`SymbolPolicy`]
+//! via [`CrateNamespace::set_symbol_policy()`], which limits the symbols that its crates
+//! can depend on from other namespaces, e.g., the kernel crates in its recursive namespace.
+//! Loading a crate that depends on a forbidden symbol fails with [`LoadError::ForbiddenSymbol`],
+//! and [`CrateNamespace::get_symbol_or_load()`] refuses to resolve forbidden symbols.
+//!
+//! Symbols defined by crates in the namespace itself are always permitted,
+//! so an application's crates can always link against each other.
+//! This includes crates that are loaded into the namespace on demand to resolve a missing symbol,
+//! which is why symbols are checked against the policy only after they have been resolved.
+
+use alloc::{string::String, vec::Vec};
+use crate::{CrateNamespace, LoadError, StrRef};
+
+/// A set of rules that determine which symbols the crates in a namespace may link against.
+///
+/// A symbol is permitted if it starts with one of the allowed prefixes (or if there is no allow-list)
+/// and doesn't start with any of the denied prefixes.
+/// Prefixes are matched against the demangled symbol without its leading `<` characters,
+/// such that denying `memory::` also denies trait method implementations
+/// like `<memory::MappedPages as core::ops::Drop>::drop`.
+///
+/// Note that nearly every crate depends on symbols in `core`, `alloc`, and `compiler_builtins`,
+/// which must therefore be included in an allow-list.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolPolicy {
+    /// If `Some`, only symbols with one of these prefixes are permitted.
+    allowed_prefixes: Option<Vec<String>>,
+    /// Symbols with any of these prefixes are forbidden, even if they are allowed by `allowed_prefixes`.
+    denied_prefixes: Vec<String>,
+}
+
+impl SymbolPolicy {
+    /// Returns a policy that permits all symbols, to which denied prefixes can be added.
+    pub fn allow_all() -> SymbolPolicy {
+        SymbolPolicy::default()
+    }
+
+    /// Returns a policy that only permits symbols starting with one of the given `prefixes`.
+    pub fn allow_only<I, S>(prefixes: I) -> SymbolPolicy
+        where I: IntoIterator<Item = S>, S: Into<String>
+    {
+        SymbolPolicy {
+            allowed_prefixes: Some(prefixes.into_iter().map(Into::into).collect()),
+            denied_prefixes: Vec::new(),
+        }
+    }
+
+    /// Adds the given prefix to the allow-list of this policy.
+    ///
+    /// This has no effect on a policy without an allow-list, which already permits all symbols.
+    pub fn allow<S: Into<String>>(mut self, prefix: S) -> SymbolPolicy {
+        if let Some(allowed) = self.allowed_prefixes.as_mut() {
+            allowed.push(prefix.into());
+        }
+        self
+    }
+
+    /// Adds the given prefix to the deny-list of this policy.
+    pub fn deny<S: Into<String>>(mut self, prefix: S) -> SymbolPolicy {
+        self.denied_prefixes.push(prefix.into());
+        self
+    }
+
+    /// Returns the allow-list of this policy, or `None` if it permits all symbols not in the deny-list.
+    pub fn allowed_prefixes(&self) -> Option<&[String]> {
+        self.allowed_prefixes.as_deref()
+    }
+
+    /// Returns the deny-list of this policy.
+    pub fn denied_prefixes(&self) -> &[String] {
+        &self.denied_prefixes
+    }
+
+    /// Returns whether this policy permits linking against the given demangled symbol.
+    pub fn permits(&self, demangled_full_symbol: &str) -> bool {
+        let symbol = demangled_full_symbol.trim_start_matches('<');
+        let allowed = self.allowed_prefixes.as_ref()
+            .map_or(true, |allowed| allowed.iter().any(|prefix| symbol.starts_with(prefix.as_str())));
+        allowed && !self.denied_prefixes.iter().any(|prefix| symbol.starts_with(prefix.as_str()))
+    }
+}
+
+impl CrateNamespace {
+    /// Sets the policy that restricts which symbols from other namespaces
+    /// the crates loaded into this namespace may link against,
+    /// or removes it if `policy` is `None`.
+    ///
+    /// Crates that were already loaded are unaffected.
+    pub fn set_symbol_policy(&self, policy: Option<SymbolPolicy>) {
+        *self.symbol_policy.write() = policy;
+    }
+
+    /// Returns a copy of this namespace's symbol policy, if it has one.
+    /// See [`CrateNamespace::set_symbol_policy()`].
+    pub fn symbol_policy(&self) -> Option<SymbolPolicy> {
+        self.symbol_policy.read().clone()
+    }
+
+    /// Returns whether crates in this namespace may link against the given demangled symbol.
+    ///
+    /// This is always true if this namespace has no symbol policy
+    /// or if the symbol is defined by a crate in this namespace itself.
+    pub fn is_symbol_permitted(&self, demangled_full_symbol: &str) -> bool {
+        let policy = self.symbol_policy.read();
+        match policy.as_ref() {
+            None => true,
+            Some(policy) => policy.permits(demangled_full_symbol)
+                || self.symbol_map.lock().get(demangled_full_symbol.as_bytes()).is_some(),
+        }
+    }
+
+    /// Checks the given symbols that the crate named `crate_name` depends on against this namespace's symbol policy,
+    /// returning an error for the first forbidden symbol.
+    ///
+    /// The symbols must already have been resolved, such that those of crates loaded on demand are permitted.
+    pub(crate) fn check_symbol_policy<'s, I>(&self, crate_name: &StrRef, demangled_full_symbols: I) -> Result<(), LoadError>
+        where I: IntoIterator<Item = &'s str>
+    {
+        if self.symbol_policy.read().is_none() {
+            return Ok(());
+        }
+        match demangled_full_symbols.into_iter().find(|symbol| !self.is_symbol_permitted(symbol)) {
+            Some(symbol) => Err(LoadError::ForbiddenSymbol {
+                crate_name: crate_name.clone(),
+                symbol: String::from(symbol),
+                namespace: String::from(self.name()),
+            }),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Bulk removal of a crate's global and reexported symbols from a namespace's symbol map.
+//!
+//! Removing a crate's symbols one at a time costs one trie traversal per symbol.
+//! [`CrateNamespace::remove_symbols_of_crate()`] instead removes all of them at once,
+//! rebuilding the symbol map in a single pass over the trie when the crate accounts for
+//! a sizable fraction of the map's symbols, e.g., an application crate in its own namespace.
+//!
+//! Crates in other namespaces that link against this namespace, e.g., through their recursive namespace chain
+//! or a backup namespace, may have imported some of the removed symbols. Those importing crates
+//! are found directly via the removed sections' dependents, regardless of which namespace they are in
+//! or whether that namespace is registered, and every [`SymbolInvalidationHook`] is invoked for each of them
+//! so that any state derived from the removed symbols, e.g., a cached symbol lookup, can be discarded.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::ptr;
+use hashbrown::{HashMap, HashSet};
+use spin::Mutex;
+use crate::{CrateNamespace, LoadedCrate, LoadedSection, StrRef, StrongCrateRef, SymbolMap, WeakSectionRef};
+
+/// A function invoked when symbols that the crate `importer` linked against
+/// have been removed from the namespace `removed_from`, which `importer` isn't part of.
+///
+/// `symbols` contains only the removed symbols that `importer` depended on.
+pub type SymbolInvalidationHook = fn(importer: &StrongCrateRef, removed_from: &CrateNamespace, symbols: &[StrRef]);
+
+static SYMBOL_INVALIDATION_HOOKS: Mutex<Vec<SymbolInvalidationHook>> = Mutex::new(Vec::new());
+
+/// Registers a hook that is invoked whenever a crate's symbols are removed
+/// while crates in another namespace still depend on some of them.
+pub fn add_symbol_invalidation_hook(hook: SymbolInvalidationHook) {
+    SYMBOL_INVALIDATION_HOOKS.lock().push(hook);
+}
+
+/// Unregisters a hook previously added via [`add_symbol_invalidation_hook()`].
+///
+/// Returns `true` if the hook was registered.
+pub fn remove_symbol_invalidation_hook(hook: SymbolInvalidationHook) -> bool {
+    let mut hooks = SYMBOL_INVALIDATION_HOOKS.lock();
+    let len_before = hooks.len();
+    hooks.retain(|h| *h as usize != hook as usize);
+    hooks.len() != len_before
+}
+
+/// If a crate's symbols make up at least `1 / REBUILD_THRESHOLD` of a symbol map,
+/// the map is rebuilt in one pass rather than removing each symbol individually.
+const REBUILD_THRESHOLD: usize = 8;
+
+impl CrateNamespace {
+    /// Removes all of the given crate's global symbols from this namespace's symbol map,
+    /// along with any symbols that it reexported, e.g., under the names of a crate it replaced.
+    ///
+    /// Only entries that still refer to a section of `krate` are removed,
+    /// so symbols that have since been replaced by another crate's sections are left intact.
+    /// This also notifies the crates in other namespaces that imported any of the removed symbols;
+    /// see the [module-level docs](self).
+    ///
+    /// Returns the number of symbols removed.
+    pub fn remove_symbols_of_crate(&self, krate: &LoadedCrate) -> usize {
+        let removed = self.remove_symbols_of_crate_locked(&mut self.symbol_map.lock(), krate);
+        if removed != 0 {
+            self.invalidate_importers(krate);
+        }
+        removed
+    }
+
+    /// Like [`remove_symbols_of_crate()`](Self::remove_symbols_of_crate), but operates on
+    /// this namespace's `symbol_map`, which the caller has already locked,
+    /// and doesn't notify the namespaces that imported the removed symbols.
+    pub(crate) fn remove_symbols_of_crate_locked(&self, symbol_map: &mut SymbolMap, krate: &LoadedCrate) -> usize {
+        let sections: Vec<_> = krate.global_sections_iter().collect();
+        if sections.is_empty() && krate.reexported_symbols.is_empty() {
+            return 0;
+        }
+
+        // Index the crate's global sections by name, such that each symbol map entry is checked in constant time.
+        let sections_by_name: HashMap<&str, *const LoadedSection> = sections.iter()
+            .map(|sec| (sec.name.as_str(), Arc::as_ptr(sec)))
+            .collect();
+        let is_from_crate = |name: &StrRef, weak_sec: &WeakSectionRef| {
+            sections_by_name.get(name.as_str()).is_some_and(|sec| ptr::eq(weak_sec.as_ptr(), *sec))
+        };
+        let mut removed = if sections.len() * REBUILD_THRESHOLD >= symbol_map.count() {
+            let mut removed = Vec::with_capacity(sections.len());
+            let mut rebuilt = SymbolMap::new();
+            for (name, weak_sec) in symbol_map.iter() {
+                if is_from_crate(name, weak_sec) {
+                    removed.push(name.clone());
+                } else {
+                    rebuilt.insert(name.clone(), weak_sec.clone());
+                }
+            }
+            *symbol_map = rebuilt;
+            removed
+        } else {
+            let mut removed = Vec::with_capacity(sections.len());
+            for sec in &sections {
+                if symbol_map.get(sec.name.as_bytes()).is_some_and(|weak_sec| is_from_crate(&sec.name, weak_sec)) {
+                    symbol_map.remove(&sec.name);
+                    removed.push(sec.name.clone());
+                }
+            }
+            removed
+        };
+        if removed.len() != sections.len() {
+            warn!("remove_symbols_of_crate(): removed only {} of {} symbols of crate {:?} from namespace {:?}",
+                removed.len(), sections.len(), krate.crate_name, self.name
+            );
+        }
+
+        // A reexported symbol refers to one of this crate's sections under another name.
+        let all_sections: HashSet<*const LoadedSection> = if krate.reexported_symbols.is_empty() {
+            HashSet::new()
+        } else {
+            krate.sections.values().map(Arc::as_ptr).collect()
+        };
+        for name in &krate.reexported_symbols {
+            let is_reexport_of_crate = symbol_map.get(name.as_bytes())
+                .is_some_and(|weak_sec| all_sections.contains(&weak_sec.as_ptr()));
+            if is_reexport_of_crate {
+                symbol_map.remove(name);
+                removed.push(name.clone());
+            }
+        }
+        removed.len()
+    }
+
+    /// Notifies every crate outside of this namespace that depends on
+    /// any global section of `krate` that those sections are gone.
+    fn invalidate_importers(&self, krate: &LoadedCrate) {
+        // Each importing crate, along with the symbols it imported from `krate`.
+        let mut importers: Vec<(StrongCrateRef, Vec<StrRef>)> = Vec::new();
+        for sec in krate.global_sections_iter() {
+            for weak_dep in &sec.inner.read().sections_dependent_on_me {
+                let Some(dep_sec) = weak_dep.section.upgrade() else { continue };
+                let Some(dep_crate) = dep_sec.parent_crate.upgrade() else { continue };
+                match importers.iter_mut().find(|(c, _)| c.inner_ptr_eq(&dep_crate)) {
+                    Some((_, symbols)) => symbols.push(sec.name.clone()),
+                    None => importers.push((dep_crate, alloc::vec![sec.name.clone()])),
+                }
+            }
+        }
+        // Crates in this namespace are expected to handle the removal themselves.
+        importers.retain(|(dep_crate, _)| {
+            let dep_crate_name = dep_crate.lock_as_ref().crate_name.clone();
+            !self.crate_tree.lock().get(dep_crate_name.as_bytes()).is_some_and(|c| c.inner_ptr_eq(dep_crate))
+        });
+        if importers.is_empty() {
+            return;
+        }
+
+        let hooks = SYMBOL_INVALIDATION_HOOKS.lock().clone();
+        for (importer, mut symbols) in importers {
+            symbols.sort_unstable();
+            symbols.dedup();
+            debug!("Crate {:?} imported {} symbols removed from namespace {:?}",
+                importer.lock_as_ref().crate_name, symbols.len(), self.name
+            );
+            for hook in &hooks {
+                hook(&importer, self, &symbols);
+            }
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Immutable, point-in-time views of a [`CrateNamespace`]'s crates and symbols.
+//!
+//! Components like backtraces, profilers, and debugger stubs need to walk
+//! the loaded crates and sections of a namespace, possibly while crates are being
+//! loaded or swapped, or from a context that already holds a namespace's locks.
+//! A [`NamespaceView`] contains its own copy of a namespace's crate list and symbol map,
+//! so readers can inspect it freely without acquiring any of the namespace's locks.
+//!
+//! Each `CrateNamespace` tracks an epoch that is advanced automatically whenever its crates or symbols
+//! are modified, as its crate tree and symbol map are each protected by a [`TrackedMutex`].
+//! A new view is only built when the epoch has moved past that of the latest view,
+//! and building one never waits on a writer: if the namespace's locks are currently held,
+//! the most recent (slightly outdated) view is returned instead.
+//! Taking a view is not lock-free, though, as the latest view itself is briefly locked
+//! in order to read or replace it.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::{ops::{Deref, DerefMut, Range}, sync::atomic::{AtomicU64, Ordering}};
+use memory::VirtualAddress;
+use spin::{Mutex, MutexGuard};
+use crate::{CrateNamespace, SectionType, StrRef, StrongCrateRef, StrongSectionRef, WeakSectionRef};
+
+/// A read-only view of the crates and symbols in a [`CrateNamespace`]
+/// (and its recursive namespaces) at a given epoch.
+///
+/// A view holds strong references to its crates,
+/// so any crate in a view will not be dropped while that view exists,
+/// even if that crate is later removed from the actual namespace.
+pub struct NamespaceView {
+    name: String,
+    epoch: u64,
+    /// The crates in this namespace, sorted by crate name.
+    crates: Vec<(StrRef, StrongCrateRef)>,
+    /// The symbols in this namespace, sorted by symbol name.
+    symbols: Vec<(StrRef, WeakSectionRef)>,
+    /// The address ranges of all non-TLS/CLS symbol sections, sorted by starting address.
+    /// Each entry holds an index into `symbols`.
+    sections_by_address: Vec<(Range<VirtualAddress>, usize)>,
+    recursive: Option<Arc<NamespaceView>>,
+}
+
+impl NamespaceView {
+    /// Returns an empty view, used before the first real view of a namespace has been taken.
+    pub(crate) fn empty(name: String) -> NamespaceView {
+        NamespaceView {
+            name,
+            epoch: 0,
+            crates: Vec::new(),
+            symbols: Vec::new(),
+            sections_by_address: Vec::new(),
+            recursive: None,
+        }
+    }
+
+    /// Builds a new view of the given `namespace` at the given `epoch`.
+    ///
+    /// Returns `None` if the namespace's crate list or symbol map are currently locked.
+    pub(crate) fn try_build(
+        namespace: &CrateNamespace,
+        epoch: u64,
+        recursive: Option<Arc<NamespaceView>>,
+    ) -> Option<NamespaceView> {
+        let mut crates: Vec<(StrRef, StrongCrateRef)> = {
+            let crate_tree = namespace.crate_tree.try_lock()?;
+            crate_tree.iter().map(|(name, crate_ref)| (name.clone(), crate_ref.clone_shallow())).collect()
+        };
+        let mut symbols: Vec<(StrRef, WeakSectionRef)> = {
+            let symbol_map = namespace.symbol_map.try_lock()?;
+            symbol_map.iter().map(|(name, sec)| (name.clone(), sec.clone())).collect()
+        };
+        crates.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        symbols.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut sections_by_address: Vec<(Range<VirtualAddress>, usize)> = symbols.iter()
+            .enumerate()
+            .filter_map(|(i, (_, weak_sec))| {
+                let sec = weak_sec.upgrade()?;
+                if sec.typ.is_tls() || sec.typ == SectionType::Cls {
+                    return None;
+                }
+                Some((sec.virt_addr .. (sec.virt_addr + sec.size), i))
+            })
+            .collect();
+        sections_by_address.sort_unstable_by_key(|(range, _)| range.start);
+
+        Some(NamespaceView {
+            name: namespace.name().into(),
+            epoch,
+            crates,
+            symbols,
+            sections_by_address,
+            recursive,
+        })
+    }
+
+    /// Returns the name of the namespace that this view was taken of.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the epoch of the namespace at the time this view was taken.
+    ///
+    /// A namespace's epoch only increases, so a view with a higher epoch is more recent.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns the view of the recursive namespace beneath this namespace, if one exists.
+    pub fn recursive_namespace(&self) -> Option<&Arc<NamespaceView>> {
+        self.recursive.as_ref()
+    }
+
+    /// Returns an iterator over the names and references of all crates in this view,
+    /// in order of crate name, not including crates in recursive namespaces.
+    pub fn crates(&self) -> impl Iterator<Item = (&str, &StrongCrateRef)> {
+        self.crates.iter().map(|(name, crate_ref)| (name.as_str(), crate_ref))
+    }
+
+    /// Returns an iterator over all global symbols in this view and the sections they refer to,
+    /// in order of symbol name, not including symbols in recursive namespaces.
+    pub fn symbols(&self) -> impl Iterator<Item = (&str, &WeakSectionRef)> {
+        self.symbols.iter().map(|(name, sec)| (name.as_str(), sec))
+    }
+
+    /// Returns the crate with the given `crate_name`,
+    /// searching this view first and then its recursive namespaces' views.
+    pub fn get_crate(&self, crate_name: &str) -> Option<&StrongCrateRef> {
+        match self.crates.binary_search_by(|(name, _)| name.as_str().cmp(crate_name)) {
+            Ok(index) => Some(&self.crates[index].1),
+            Err(_) => self.recursive.as_ref().and_then(|r| r.get_crate(crate_name)),
+        }
+    }
+
+    /// Returns the section for the given fully-qualified demangled symbol,
+    /// searching this view first and then its recursive namespaces' views.
+    ///
+    /// Returns `None` if the symbol doesn't exist or its section has since been dropped.
+    pub fn get_symbol(&self, demangled_full_symbol: &str) -> Option<StrongSectionRef> {
+        match self.symbols.binary_search_by(|(name, _)| name.as_str().cmp(demangled_full_symbol)) {
+            Ok(index) => self.symbols[index].1.upgrade(),
+            Err(_) => self.recursive.as_ref().and_then(|r| r.get_symbol(demangled_full_symbol)),
+        }
+    }
+
+    /// Returns all symbols in this view (not including recursive namespaces)
+    /// that start with the given `symbol_prefix`.
+    pub fn symbols_starting_with<'s>(&'s self, symbol_prefix: &'s str) -> impl Iterator<Item = (&'s str, &'s WeakSectionRef)> {
+        let start = self.symbols.partition_point(|(name, _)| name.as_str() < symbol_prefix);
+        self.symbols[start..].iter()
+            .take_while(move |(name, _)| name.starts_with(symbol_prefix))
+            .map(|(name, sec)| (name.as_str(), sec))
+    }
+
+    /// Returns the global section that contains the given `virt_addr`
+    /// and the offset of `virt_addr` into that section,
+    /// searching this view first and then its recursive namespaces' views.
+    ///
+    /// Unlike [`CrateNamespace::get_section_containing_address()`], this does not acquire
+    /// any locks, so it is safe to use when printing a backtrace.
+    /// However, only global (publicly-visible) sections are searched.
+    pub fn get_section_containing_address(&self, virt_addr: VirtualAddress) -> Option<(StrongSectionRef, usize)> {
+        // Find the last section that starts at or before `virt_addr`, then check its bounds.
+        let end = self.sections_by_address.partition_point(|(range, _)| range.start <= virt_addr);
+        self.sections_by_address[..end].iter()
+            .rev()
+            .find(|(range, _)| range.contains(&virt_addr))
+            .and_then(|(range, index)| {
+                let sec = self.symbols[*index].1.upgrade()?;
+                Some((sec, virt_addr.value() - range.start.value()))
+            })
+            .or_else(|| self.recursive.as_ref().and_then(|r| r.get_section_containing_address(virt_addr)))
+    }
+}
+
+
+/// A mutex that counts how many times its contents have been modified,
+/// which is used to determine when a namespace's view is outdated.
+///
+/// The contents are considered modified when a guard returned by [`TrackedMutex::lock()`]
+/// is mutably dereferenced at least once, in which case the count is incremented
+/// when that guard is dropped, before the lock is released.
+pub struct TrackedMutex<T> {
+    inner: Mutex<T>,
+    modifications: AtomicU64,
+}
+
+impl<T> TrackedMutex<T> {
+    pub(crate) const fn new(value: T) -> TrackedMutex<T> {
+        TrackedMutex { inner: Mutex::new(value), modifications: AtomicU64::new(0) }
+    }
+
+    /// Acquires the lock, spinning until it is available.
+    pub fn lock(&self) -> TrackedMutexGuard<'_, T> {
+        TrackedMutexGuard { guard: self.inner.lock(), modifications: &self.modifications, modified: false }
+    }
+
+    /// Acquires the lock if it is available, without spinning.
+    pub fn try_lock(&self) -> Option<TrackedMutexGuard<'_, T>> {
+        self.inner.try_lock().map(|guard| TrackedMutexGuard { guard, modifications: &self.modifications, modified: false })
+    }
+
+    /// Returns the number of times that the contents of this mutex have been modified.
+    pub fn modifications(&self) -> u64 {
+        self.modifications.load(Ordering::Acquire)
+    }
+}
+
+/// A guard that releases the lock of a [`TrackedMutex`] when dropped.
+pub struct TrackedMutexGuard<'m, T> {
+    guard: MutexGuard<'m, T>,
+    modifications: &'m AtomicU64,
+    modified: bool,
+}
+
+impl<T> Deref for TrackedMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for TrackedMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.modified = true;
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for TrackedMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        if self.modified {
+            self.modifications.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+}
@@ -5,8 +5,8 @@
 
 use log::{error, warn};
 use spin::Once;
-use mpmc::Queue;
 use event_types::Event;
+use input_router::InputSource;
 use x86_64::structures::idt::InterruptStackFrame;
 use mouse_data::{MouseButtons, MouseEvent, MouseMovementRelative};
 use ps2::{PS2Mouse, MousePacket};
@@ -19,16 +19,16 @@ static MOUSE: Once<MouseInterruptParams> = Once::new();
 
 struct MouseInterruptParams {
     mouse: PS2Mouse<'static>,
-    queue: Queue<Event>,
+    input: InputSource,
 }
 
 /// Initialize the PS/2 mouse driver and register its interrupt handler.
 /// 
 /// ## Arguments
 /// * `mouse`: a wrapper around mouse functionality and id, used by the mouse interrupt handler.
-/// * `mouse_queue_producer`: the queue onto which the mouse interrupt handler
-///    will push new mouse events when a mouse action occurs.
-pub fn init(mut mouse: PS2Mouse<'static>, mouse_queue_producer: Queue<Event>) -> Result<(), &'static str> {
+/// * `input`: the input source via which the mouse interrupt handler
+///    will deliver new mouse events when a mouse action occurs.
+pub fn init(mut mouse: PS2Mouse<'static>, input: InputSource) -> Result<(), &'static str> {
     // Set MouseId to the highest possible one
     if let Err(e) = mouse.set_mouse_id() {
         error!("Failed to set the mouse id: {e}");
@@ -41,9 +41,9 @@ pub fn init(mut mouse: PS2Mouse<'static>, mouse_queue_producer: Queue<Event>) ->
         "PS/2 mouse IRQ was already in use! Sharing IRQs is currently unsupported."
     })?;
 
-    // Final step: set the producer end of the mouse event queue.
+    // Final step: set the input source for mouse events.
     // Also add the mouse struct for access during interrupts.
-    MOUSE.call_once(|| MouseInterruptParams { mouse, queue: mouse_queue_producer });
+    MOUSE.call_once(|| MouseInterruptParams { mouse, input });
     Ok(())
 }
 
@@ -56,7 +56,7 @@ pub fn init(mut mouse: PS2Mouse<'static>, mouse_queue_producer: Queue<Event>) ->
 /// 
 /// In some cases (e.g. on device init), [the PS/2 controller can also send an interrupt](https://wiki.osdev.org/%228042%22_PS/2_Controller#Interrupts).
 extern "x86-interrupt" fn ps2_mouse_handler(_stack_frame: InterruptStackFrame) {
-    if let Some(MouseInterruptParams { mouse, queue }) = MOUSE.get() {
+    if let Some(MouseInterruptParams { mouse, input }) = MOUSE.get() {
         if mouse.is_output_buffer_full() {
             // NOTE: having read some more forum comments now, if this ever breaks on real hardware,
             // try to redesign this to only get one byte per interrupt instead of the 3-4 bytes we
@@ -66,7 +66,7 @@ extern "x86-interrupt" fn ps2_mouse_handler(_stack_frame: InterruptStackFrame) {
             if mouse_packet.always_one() != 1 {
                 // this could signal a hardware error or a mouse which doesn't conform to the rule
                 warn!("ps2_mouse_handler(): Discarding mouse data packet since its third bit should always be 1.");
-            } else if let Err(e) = handle_mouse_input(mouse_packet, queue) {
+            } else if let Err(e) = handle_mouse_input(mouse_packet, input) {
                 error!("ps2_mouse_handler(): {e:?}");
             }
         }
@@ -79,14 +79,14 @@ extern "x86-interrupt" fn ps2_mouse_handler(_stack_frame: InterruptStackFrame) {
 
 
 /// enqueue a Mouse Event according to the data
-fn handle_mouse_input(mouse_packet: MousePacket, queue: &Queue<Event>) -> Result<(), &'static str> {
+fn handle_mouse_input(mouse_packet: MousePacket, input: &InputSource) -> Result<(), &'static str> {
     let buttons = Buttons::from(&mouse_packet).0;
     let movement = Movement::from(&mouse_packet).0;
 
     let mouse_event = MouseEvent::new(buttons, movement);
     let event = Event::MouseMovementEvent(mouse_event);
 
-    queue.push(event).map_err(|_| "failed to enqueue the mouse event")
+    input.push(event)
 }
 
 // both MouseMovementRelative and MousePacketBits4 are in different crates, so we need a newtype wrapper:
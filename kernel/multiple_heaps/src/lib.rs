@@ -18,6 +18,14 @@
 //! When a per-core heap runs out of memory, pages are first moved between the slab allocators of the per-core heap, then requested from other per-core heaps.
 //! If no empty pages are available within any of the per-core heaps, then more virtual pages are allocated from the range of virtual addresses dedicated to the heap
 //! [KERNEL_HEAP_START](../kernel_config/memory/constant.KERNEL_HEAP_START.html) and dynamically mapped to physical memory frames.
+//! The heap grows in chunks of `HEAP_GROWTH_AMOUNT` pages sets at a time, which are all given to the heap that ran out of memory.
+//!
+//! Empty pages can be returned to the frame allocator via [`shrink_heaps()`], which unmaps them.
+//! This leaves holes in the heap's range of virtual addresses, which are mapped again before the heap grows any further.
+//! This is done whenever the frame allocator runs out of memory (via a `memory_pressure` shrinker),
+//! and periodically by the `heap_shrinker` task.
+//! Each per-core heap always keeps more than `EMPTY_PAGES_THRESHOLD` empty pages, such that it doesn't need to immediately grow again.
+//! Shrinking is not supported with the `unsafe_heap` configuration, in which the heap is a single contiguous mapping.
 
 #![feature(allocator_api)]
 #![no_std]
@@ -30,6 +38,9 @@ extern crate kernel_config;
 extern crate apic;
 extern crate heap;
 extern crate hashbrown;
+extern crate memory_pressure;
+extern crate numa;
+extern crate spin;
 #[macro_use] extern crate cfg_if;
 
 #[cfg(all(not(unsafe_heap), not(safe_heap)))]
@@ -45,13 +56,14 @@ use core::ptr::NonNull;
 use alloc::alloc::{GlobalAlloc, Layout};
 use alloc::boxed::Box;
 use hashbrown::HashMap;
-use memory::{MappedPages, VirtualAddress, get_kernel_mmi_ref, create_mapping};
+use memory::{MappedPages, Page, PageRange, VirtualAddress, get_kernel_mmi_ref, create_mapping, allocate_frames_on_node_deferred};
 use kernel_config::memory::{PAGE_SIZE, KERNEL_HEAP_START, KERNEL_HEAP_INITIAL_SIZE};
 use core::ops::Deref;
 use core::ptr;
 use heap::HEAP_FLAGS;
 use sync_irq::IrqSafeMutex;
-use page_allocator::{DeferredAllocAction, allocate_pages_by_bytes_deferred};
+use page_allocator::{AllocationRequest, DeferredAllocAction, allocate_pages_by_bytes_deferred};
+use spin::Once;
 
 #[cfg(all(not(unsafe_heap), not(safe_heap)))]
 use slabmalloc::{ZoneAllocator, ObjectPage8k, AllocablePage, MappedPages8k};
@@ -81,16 +93,22 @@ const PAGES_PER_SIZE_CLASS: usize = 128; // was 24
 pub const PER_CORE_HEAP_INITIAL_SIZE_PAGES: usize = ZoneAllocator::MAX_BASE_SIZE_CLASSES *  PAGES_PER_SIZE_CLASS;
 
 /// The number of heap page sets that are requested from the OS whenever the heap is grown.
+/// These are mapped all at once as a single chunk (currently 256 KiB), which is then split into individual page sets.
 /// This should be minimum `2` in order to ensure that there is at least:
 /// * one empty page for the requested allocation, and
 /// * one empty page for the deferred allocations to occur. 
 /// 
 /// # Important Note
-/// The total size of heap objects allocated during/by the deferred alloc actions must be able to fit 
-/// into the additional page(s) here, which is currently just one extra heap page set (currently 8KiB). 
-/// Currently, each `DeferredAllocAction` creates 3 chunks, so that means that the current calculation is:
-/// `(3 * HEAP_GROWTH_AMOUNT * sizeof(Chunk)` bytes must fit within one 8KiB heap page set.
-const HEAP_GROWTH_AMOUNT: usize = 2;
+/// The total size of heap objects allocated during/by the deferred alloc action must be able to fit 
+/// into the additional page(s) here. Currently, the single `DeferredAllocAction` for the chunk creates 3 chunks,
+/// so `3 * sizeof(Chunk)` bytes must fit within one 8KiB heap page set.
+const HEAP_GROWTH_AMOUNT: usize = 32;
+
+/// The size in bytes of the chunk of pages that is mapped whenever the heap is grown.
+const HEAP_GROWTH_CHUNK_SIZE_IN_BYTES: usize = HEAP_GROWTH_AMOUNT * HEAP_MAPPED_PAGES_SIZE_IN_BYTES;
+
+/// The multiple heaps, once they have been set as the default allocator.
+static MULTIPLE_HEAPS: Once<MultipleHeaps> = Once::new();
 
 /// Creates and initializes the multiple heaps using the apic id as the key, which is mapped to a heap.
 /// If we want to change the value the heap id is based on, we would substitute 
@@ -109,31 +127,74 @@ fn initialize_multiple_heaps() -> Result<MultipleHeaps, &'static str> {
 /// The setup routine for multiple heaps. It creates and initializes the multiple heaps,
 /// then sets the multiple heaps as the default allocator.
 /// Only call this function when the multiple heaps are ready to be used.
+/// It also registers a `memory_pressure` shrinker that returns empty heap pages to the frame allocator.
 pub fn switch_to_multiple_heaps() -> Result<(), &'static str> {
-    let multiple_heaps = Box::new(initialize_multiple_heaps()?);
+    let multiple_heaps = initialize_multiple_heaps()?;
+    if MULTIPLE_HEAPS.is_completed() {
+        return Err("multiple_heaps: the multiple heaps were already set up");
+    }
+    let multiple_heaps = MULTIPLE_HEAPS.call_once(|| multiple_heaps);
     //set the multiple heaps as the default allocator
-    heap::set_allocator(multiple_heaps);
+    heap::set_allocator(Box::new(StaticMultipleHeaps(multiple_heaps)));
+
+    memory_pressure::register_shrinker("multiple_heaps", Box::new(|num_frames| {
+        let page_sets = num_frames.div_ceil(HEAP_MAPPED_PAGES_SIZE_IN_PAGES);
+        shrink_heaps(page_sets) * HEAP_MAPPED_PAGES_SIZE_IN_PAGES
+    }))?;
 
     Ok(())
 }
 
+/// Unmaps up to `max_page_sets` empty heap page sets from the per-core heaps,
+/// returning their frames to the frame allocator.
+///
+/// Heaps that are currently locked are skipped, as are heaps that have no more than `EMPTY_PAGES_THRESHOLD` empty pages.
+/// Returns the number of page sets that were unmapped; each contains `HEAP_MAPPED_PAGES_SIZE_IN_PAGES` pages.
+///
+/// This does nothing if the multiple heaps have not yet been set up.
+pub fn shrink_heaps(max_page_sets: usize) -> usize {
+    MULTIPLE_HEAPS.get().map_or(0, |multiple_heaps| multiple_heaps.shrink(max_page_sets))
+}
+
+/// Wraps the static instance of the multiple heaps so that it can be set as the default allocator
+/// while remaining accessible via [`shrink_heaps()`].
+struct StaticMultipleHeaps(&'static MultipleHeaps);
+
+unsafe impl GlobalAlloc for StaticMultipleHeaps {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.dealloc(ptr, layout)
+    }
+}
+
 
 
-/// Allocates pages from the given starting address and maps them to frames.
+/// Allocates pages as described by the given `request` and maps them to frames.
 /// Returns the new mapped pages or an error if the heap memory limit is reached.
+///
+/// If `node` is `Some`, the frames are preferably allocated from that NUMA node's memory.
+/// The deferred action of that frame allocation is completed within this function,
+/// so a `node` must only be given when initializing a heap, never when growing one.
 fn create_heap_mapping(
-    starting_address: VirtualAddress, 
-    size_in_bytes: usize
+    request: AllocationRequest,
+    size_in_bytes: usize,
+    node: Option<u32>,
 ) -> Result<(MappedPages, DeferredAllocAction<'static>), &'static str> {
     let kernel_mmi_ref = get_kernel_mmi_ref().ok_or("create_heap_mapping(): KERNEL_MMI was not yet initialized!")?;
-    let (pages, action) = allocate_pages_by_bytes_deferred(
-        page_allocator::AllocationRequest::AtVirtualAddress(starting_address),
-        size_in_bytes,
-    ).map_err(|_e| "create_heap_mapping(): failed to allocate pages at the starting address")?;
+    let (pages, action) = allocate_pages_by_bytes_deferred(request, size_in_bytes)
+        .map_err(|_e| "create_heap_mapping(): failed to allocate pages for the heap")?;
     if pages.start_address().value() % HEAP_MAPPED_PAGES_SIZE_IN_BYTES != 0 {
         return Err("multiple_heaps: the allocated pages for the heap wasn't properly aligned");
     }
-    let mp = kernel_mmi_ref.lock().page_table.map_allocated_pages(pages, HEAP_FLAGS)?;
+    let node_frames = node.and_then(|node| allocate_frames_on_node_deferred(node, pages.size_in_pages()).ok());
+    let mp = match node_frames {
+        Some((frames, _frames_action)) => kernel_mmi_ref.lock().page_table.map_allocated_pages_to(pages, frames, HEAP_FLAGS)?,
+        None => kernel_mmi_ref.lock().page_table.map_allocated_pages(pages, HEAP_FLAGS)?,
+    };
+    heap::record_heap_mapping(mp.size_in_bytes());
     // trace!("Allocated heap pages at: {:#X}", starting_address);
     Ok((mp, action))
 }
@@ -148,9 +209,6 @@ fn create_heap_mapping(
 cfg_if! {
 if #[cfg(unsafe_heap)] {
     extern crate alloc;
-    extern crate spin;
-
-    use spin::Once;
 
     /// Initializes the heap given by `key`.
     /// There are 11 size classes in each heap ranging from [8,16,32,64 ..`ZoneAllocator::MAX_ALLOC_SIZE`].
@@ -159,6 +217,8 @@ if #[cfg(unsafe_heap)] {
 
         let mut heap_end = multiple_heaps.end.lock();
         let mut heap_end_addr = *heap_end;
+        // back this heap with memory from the NUMA node of its CPU, if known
+        let node = numa::topology().and_then(|t| t.node_of_raw_cpu(key as u32));
 
         let mapped_pages_per_size_class = PER_CORE_HEAP_INITIAL_SIZE_PAGES / (ZoneAllocator::MAX_BASE_SIZE_CLASSES * HEAP_MAPPED_PAGES_SIZE_IN_PAGES);
         let mut zone_allocator = ZoneAllocator::new(key);
@@ -174,7 +234,7 @@ if #[cfg(unsafe_heap)] {
                 let layout = Layout::from_size_align(*size, alignment).map_err(|_e| "Incorrect layout")?;
 
                 // create the mapped pages starting from the previous end of the heap
-                let (mp, _action) = create_heap_mapping(heap_end_addr, HEAP_MAPPED_PAGES_SIZE_IN_BYTES)?;
+                let (mp, _action) = create_heap_mapping(AllocationRequest::AtVirtualAddress(heap_end_addr), HEAP_MAPPED_PAGES_SIZE_IN_BYTES, node)?;
 
                 let start_addr = mp.start_address().value();
                 if start_addr % ObjectPage8k::SIZE != 0 {
@@ -212,6 +272,8 @@ if #[cfg(unsafe_heap)] {
 
         let mut heap_end = multiple_heaps.end.lock();
         let mut heap_end_addr = *heap_end;
+        // back this heap with memory from the NUMA node of its CPU, if known
+        let node = numa::topology().and_then(|t| t.node_of_raw_cpu(key as u32));
 
         let mapped_pages_per_size_class = PER_CORE_HEAP_INITIAL_SIZE_PAGES / (ZoneAllocator::MAX_BASE_SIZE_CLASSES * HEAP_MAPPED_PAGES_SIZE_IN_PAGES);
         let mut zone_allocator = ZoneAllocator::new(key);
@@ -227,7 +289,7 @@ if #[cfg(unsafe_heap)] {
                 let layout = Layout::from_size_align(*size, alignment).map_err(|_e| "Incorrect layout")?;
 
                 // create the mapped pages starting from the previous end of the heap
-                let (mp, _action) = create_heap_mapping(heap_end_addr, HEAP_MAPPED_PAGES_SIZE_IN_BYTES)?;
+                let (mp, _action) = create_heap_mapping(AllocationRequest::AtVirtualAddress(heap_end_addr), HEAP_MAPPED_PAGES_SIZE_IN_BYTES, node)?;
                 let mapping = MappedPages8k::new(mp)?;
                 // add page to the allocator
                 zone_allocator.refill(layout, mapping)?;
@@ -297,8 +359,8 @@ pub struct MultipleHeaps{
     /// Red-black tree to store large allocations
     #[cfg(not(unsafe_large_allocations))]    
     large_allocations: IrqSafeMutex<RBTree<LargeAllocationAdapter>>,
-    /// We currently don't return memory back to the OS. Because of this all memory in the heap is contiguous
-    /// and extra memory for the heap is always allocated from the end.
+    /// Extra memory for the heap is always allocated from the end of the heap's virtual address range.
+    /// Pages that are returned to the OS leave holes in the heap's address range, which are reused before the heap grows further.
     /// The Mutex also serves the purpose of helping to synchronize new allocations.
     end: IrqSafeMutex<VirtualAddress>, 
     /// The mapped pages for the unsafe heap are stored here so that they are not dropped and unmapped.
@@ -340,30 +402,47 @@ if #[cfg(unsafe_heap)] {
         fn grow_heap(&self, layout: Layout, heap_to_grow: &LockedHeap) -> Result<(), &'static str> {
             // (1) Try to retrieve a page from the another heap
             for heap_ref in self.heaps.values() {
-                if let Some((mp, _giving_heap_id)) = heap_ref.try_lock().and_then(|mut giving_heap| 
+                if let Some((mp, giving_heap_id)) = heap_ref.try_lock().and_then(|mut giving_heap| 
                     giving_heap.retrieve_empty_page(EMPTY_PAGES_THRESHOLD).map(|mp| (mp, giving_heap.heap_id))
                 ) {
-                    info!("Added page from another heap {} to heap {}", _giving_heap_id, heap_to_grow.lock().heap_id);
-                    return heap_to_grow.lock().refill(layout, mp);
+                    let heap_id = {
+                        let mut heap = heap_to_grow.lock();
+                        heap.refill(layout, mp)?;
+                        heap.heap_id
+                    };
+                    info!("Added page from another heap {} to heap {}", giving_heap_id, heap_id);
+                    return Ok(());
                 }
             }
 
-            // (2) Allocate page from the OS
+            // (2) Allocate a chunk of pages from the OS
             let mut heap_end = self.end.lock();
-            for _ in 0..HEAP_GROWTH_AMOUNT {
-                let (mp, _action) = create_heap_mapping(*heap_end, HEAP_MAPPED_PAGES_SIZE_IN_BYTES)?;
-                let start_addr = mp.start_address().value();
-                self.extend_heap_mp(mp)?;
-                let page = unsafe { core::mem::transmute(start_addr) };
-                info!("grow_heap:: Allocated page(s) at {:X?} to refill heap {} for layout size: {}, prior heap_end: {:#X}", 
-                    start_addr, heap_to_grow.lock().heap_id, layout.size(), *heap_end
-                );
-                *heap_end += HEAP_MAPPED_PAGES_SIZE_IN_BYTES;
-                heap_to_grow.lock().refill(layout, page)?;
-            }
+            let (chunk, action) = create_heap_mapping(AllocationRequest::AtVirtualAddress(*heap_end), HEAP_GROWTH_CHUNK_SIZE_IN_BYTES, None)?;
+            let chunk_start = chunk.start_address().value();
+            self.extend_heap_mp(chunk)?;
+            let prior_heap_end = *heap_end;
+            *heap_end += HEAP_GROWTH_CHUNK_SIZE_IN_BYTES;
+            let heap_id = {
+                let mut heap = heap_to_grow.lock();
+                for i in 0..HEAP_GROWTH_AMOUNT {
+                    let page = unsafe { core::mem::transmute(chunk_start + i * HEAP_MAPPED_PAGES_SIZE_IN_BYTES) };
+                    heap.refill(layout, page)?;
+                }
+                heap.heap_id
+            };
+            drop(action);
+            drop(heap_end);
+            info!("grow_heap:: Allocated {} bytes at {:#X} to refill heap {} for layout size: {}, prior heap_end: {:#X}", 
+                HEAP_GROWTH_CHUNK_SIZE_IN_BYTES, chunk_start, heap_id, layout.size(), prior_heap_end
+            );
             Ok(())
         } 
 
+        /// Shrinking is not supported for the unsafe heap, as all of its pages are merged into a single mapping.
+        fn shrink(&self, _max_page_sets: usize) -> usize {
+            0
+        }
+
         /// Merge mapped pages `mp` with the heap mapped pages.
         /// 
         /// # Warning
@@ -392,7 +471,8 @@ if #[cfg(unsafe_heap)] {
 
         /// Called when a call to allocate() returns a null pointer. The following steps are used to recover memory:
         /// (1) Pages are first taken from another heap.
-        /// (2) If the above fails, then more pages are allocated from the OS.
+        /// (2) If the above fails, then a page set is mapped into a hole left by shrinking the heap.
+        /// (3) If there are no such holes, then more pages are allocated from the OS.
         /// 
         /// An Err is returned if there is no more memory to be allocated in the heap memory area or if the heap page limit is reached.
         /// 
@@ -402,27 +482,85 @@ if #[cfg(unsafe_heap)] {
         fn grow_heap(&self, layout: Layout, heap_to_grow: &LockedHeap) -> Result<(), &'static str> {
             // (1) Try to retrieve a page from the another heap
             for heap_ref in self.heaps.values() {
-                if let Some((mp, _giving_heap_id)) = heap_ref.try_lock().and_then(|mut giving_heap| 
+                if let Some((mp, giving_heap_id)) = heap_ref.try_lock().and_then(|mut giving_heap| 
                     giving_heap.retrieve_empty_page(EMPTY_PAGES_THRESHOLD).map(|mp| (mp, giving_heap.heap_id))
                 ) {
-                    info!("Added page from another heap {} to heap {}", _giving_heap_id, heap_to_grow.lock().heap_id);
-                    return heap_to_grow.lock().refill(layout, mp);
+                    let heap_id = {
+                        let mut heap = heap_to_grow.lock();
+                        heap.refill(layout, mp)?;
+                        heap.heap_id
+                    };
+                    info!("Added page from another heap {} to heap {}", giving_heap_id, heap_id);
+                    return Ok(());
                 }
             }
 
-            // (2) Allocate page from the OS
             let mut heap_end = self.end.lock();
-            for _ in 0..HEAP_GROWTH_AMOUNT {
-                let (mp, _action) = create_heap_mapping(*heap_end, HEAP_MAPPED_PAGES_SIZE_IN_BYTES)?;
-                let mp = MappedPages8k::new(mp)?;
-                info!("grow_heap:: Allocated page(s) at {:X?} to refill heap {} for layout size: {}, prior heap_end: {:#X}", 
-                    mp.start_address(), heap_to_grow.lock().heap_id, layout.size(), *heap_end
+
+            // (2) Reuse a page set in the holes that `shrink()` left in the heap's address range, if any
+            let holes = PageRange::new(
+                Page::containing_address(VirtualAddress::new_canonical(KERNEL_HEAP_START)),
+                Page::containing_address(*heap_end - 1),
+            );
+            if let Ok((mp, action)) = create_heap_mapping(AllocationRequest::WithinRange(&holes), HEAP_MAPPED_PAGES_SIZE_IN_BYTES, None) {
+                let start_address = mp.start_address();
+                let heap_id = {
+                    let mut heap = heap_to_grow.lock();
+                    heap.refill(layout, MappedPages8k::new(mp)?)?;
+                    heap.heap_id
+                };
+                drop(action);
+                drop(heap_end);
+                info!("grow_heap:: Reused a page set at {:#X} to refill heap {} for layout size: {}", 
+                    start_address, heap_id, layout.size()
                 );
-                *heap_end += HEAP_MAPPED_PAGES_SIZE_IN_BYTES;
-                heap_to_grow.lock().refill(layout, mp)?;
+                return Ok(());
             }
+
+            // (3) Allocate a chunk of pages from the OS and split it into individual page sets
+            let (mut chunk, action) = create_heap_mapping(AllocationRequest::AtVirtualAddress(*heap_end), HEAP_GROWTH_CHUNK_SIZE_IN_BYTES, None)?;
+            let chunk_start = chunk.start_address();
+            let prior_heap_end = *heap_end;
+            *heap_end += HEAP_GROWTH_CHUNK_SIZE_IN_BYTES;
+            let heap_id = {
+                let mut heap = heap_to_grow.lock();
+                for _ in 0..HEAP_GROWTH_AMOUNT {
+                    let split_at = Page::containing_address(chunk.start_address() + HEAP_MAPPED_PAGES_SIZE_IN_BYTES);
+                    let (mp, rest) = chunk.split(split_at)
+                        .map_err(|_| "grow_heap: failed to split the new heap chunk into page sets")?;
+                    heap.refill(layout, MappedPages8k::new(mp)?)?;
+                    chunk = rest;
+                }
+                heap.heap_id
+            };
+            drop(action);
+            drop(heap_end);
+            info!("grow_heap:: Allocated {} bytes at {:#X} to refill heap {} for layout size: {}, prior heap_end: {:#X}", 
+                HEAP_GROWTH_CHUNK_SIZE_IN_BYTES, chunk_start, heap_id, layout.size(), prior_heap_end
+            );
             Ok(())
         }  
+
+        /// Unmaps up to `max_page_sets` empty page sets from the per-core heaps; see [`shrink_heaps()`].
+        fn shrink(&self, max_page_sets: usize) -> usize {
+            let mut freed = 0;
+            for heap_ref in self.heaps.values() {
+                while freed < max_page_sets {
+                    // Don't hold the heap's lock while unmapping the pages,
+                    // as freeing the pages and frames may allocate from this heap.
+                    let mp = heap_ref.try_lock()
+                        .and_then(|mut heap| heap.retrieve_empty_page(EMPTY_PAGES_THRESHOLD));
+                    let Some(mp) = mp else { break };
+                    drop(mp);
+                    heap::record_heap_unmapping(HEAP_MAPPED_PAGES_SIZE_IN_BYTES);
+                    freed += 1;
+                }
+            }
+            if freed > 0 {
+                debug!("multiple_heaps: unmapped {} empty heap page sets ({} KiB)", freed, freed * HEAP_MAPPED_PAGES_SIZE_IN_BYTES / 1024);
+            }
+            freed
+        }
     }
 
 } else {
@@ -440,7 +578,8 @@ if #[cfg(unsafe_heap)] {
 
         /// Called when a call to allocate() returns a null pointer. The following steps are used to recover memory:
         /// (1) Pages are first taken from another heap.
-        /// (2) If the above fails, then more pages are allocated from the OS.
+        /// (2) If the above fails, then a page set is mapped into a hole left by shrinking the heap.
+        /// (3) If there are no such holes, then more pages are allocated from the OS.
         /// 
         /// An Err is returned if there is no more memory to be allocated in the heap memory area.
         /// 
@@ -450,27 +589,85 @@ if #[cfg(unsafe_heap)] {
         fn grow_heap(&self, layout: Layout, heap_to_grow: &LockedHeap) -> Result<(), &'static str> {
             // (1) Try to retrieve a page from the another heap
             for heap_ref in self.heaps.values() {
-                if let Some((mp, _giving_heap_id)) = heap_ref.try_lock().and_then(|mut giving_heap| 
+                if let Some((mp, giving_heap_id)) = heap_ref.try_lock().and_then(|mut giving_heap| 
                     giving_heap.retrieve_empty_page(EMPTY_PAGES_THRESHOLD).map(|mp| (mp, giving_heap.heap_id))
                 ) {
-                    info!("Added page from another heap {} to heap {}", _giving_heap_id, heap_to_grow.lock().heap_id);
-                    return heap_to_grow.lock().refill(layout, mp);
+                    let heap_id = {
+                        let mut heap = heap_to_grow.lock();
+                        heap.refill(layout, mp)?;
+                        heap.heap_id
+                    };
+                    info!("Added page from another heap {} to heap {}", giving_heap_id, heap_id);
+                    return Ok(());
                 }
             }
 
-            // (2) Allocate page from the OS
             let mut heap_end = self.end.lock();
-            for _ in 0..HEAP_GROWTH_AMOUNT {
-                let (mp, _action) = create_heap_mapping(*heap_end, HEAP_MAPPED_PAGES_SIZE_IN_BYTES)?;
-                let mp = MappedPages8k::new(mp)?;
-                info!("grow_heap:: Allocated page(s) at {:X?} to refill heap {} for layout size: {}, prior heap_end: {:#X}", 
-                    mp.start_address(), heap_to_grow.lock().heap_id, layout.size(), *heap_end
+
+            // (2) Reuse a page set in the holes that `shrink()` left in the heap's address range, if any
+            let holes = PageRange::new(
+                Page::containing_address(VirtualAddress::new_canonical(KERNEL_HEAP_START)),
+                Page::containing_address(*heap_end - 1),
+            );
+            if let Ok((mp, action)) = create_heap_mapping(AllocationRequest::WithinRange(&holes), HEAP_MAPPED_PAGES_SIZE_IN_BYTES, None) {
+                let start_address = mp.start_address();
+                let heap_id = {
+                    let mut heap = heap_to_grow.lock();
+                    heap.refill(layout, MappedPages8k::new(mp)?)?;
+                    heap.heap_id
+                };
+                drop(action);
+                drop(heap_end);
+                info!("grow_heap:: Reused a page set at {:#X} to refill heap {} for layout size: {}", 
+                    start_address, heap_id, layout.size()
                 );
-                *heap_end += HEAP_MAPPED_PAGES_SIZE_IN_BYTES;
-                heap_to_grow.lock().refill(layout, mp)?;
+                return Ok(());
             }
+
+            // (3) Allocate a chunk of pages from the OS and split it into individual page sets
+            let (mut chunk, action) = create_heap_mapping(AllocationRequest::AtVirtualAddress(*heap_end), HEAP_GROWTH_CHUNK_SIZE_IN_BYTES, None)?;
+            let chunk_start = chunk.start_address();
+            let prior_heap_end = *heap_end;
+            *heap_end += HEAP_GROWTH_CHUNK_SIZE_IN_BYTES;
+            let heap_id = {
+                let mut heap = heap_to_grow.lock();
+                for _ in 0..HEAP_GROWTH_AMOUNT {
+                    let split_at = Page::containing_address(chunk.start_address() + HEAP_MAPPED_PAGES_SIZE_IN_BYTES);
+                    let (mp, rest) = chunk.split(split_at)
+                        .map_err(|_| "grow_heap: failed to split the new heap chunk into page sets")?;
+                    heap.refill(layout, MappedPages8k::new(mp)?)?;
+                    chunk = rest;
+                }
+                heap.heap_id
+            };
+            drop(action);
+            drop(heap_end);
+            info!("grow_heap:: Allocated {} bytes at {:#X} to refill heap {} for layout size: {}, prior heap_end: {:#X}", 
+                HEAP_GROWTH_CHUNK_SIZE_IN_BYTES, chunk_start, heap_id, layout.size(), prior_heap_end
+            );
             Ok(())
         }  
+
+        /// Unmaps up to `max_page_sets` empty page sets from the per-core heaps; see [`shrink_heaps()`].
+        fn shrink(&self, max_page_sets: usize) -> usize {
+            let mut freed = 0;
+            for heap_ref in self.heaps.values() {
+                while freed < max_page_sets {
+                    // Don't hold the heap's lock while unmapping the pages,
+                    // as freeing the pages and frames may allocate from this heap.
+                    let mp = heap_ref.try_lock()
+                        .and_then(|mut heap| heap.retrieve_empty_page(EMPTY_PAGES_THRESHOLD));
+                    let Some(mp) = mp else { break };
+                    drop(mp);
+                    heap::record_heap_unmapping(HEAP_MAPPED_PAGES_SIZE_IN_BYTES);
+                    freed += 1;
+                }
+            }
+            if freed > 0 {
+                debug!("multiple_heaps: unmapped {} empty heap page sets ({} KiB)", freed, freed * HEAP_MAPPED_PAGES_SIZE_IN_BYTES / 1024);
+            }
+            freed
+        }
     }
 }
 } // end cfg_if for MultipleHeaps impl
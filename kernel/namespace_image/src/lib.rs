@@ -0,0 +1,236 @@
+//! Saves and restores images of already-linked crate namespaces to and from storage devices or files,
+//! which allows the crates in a namespace to be restored at the next boot without re-linking them.
+//!
+//! An image is stored in a contiguous range of blocks on a storage device,
+//! starting with a single header block followed by the image payload
+//! produced by [`CrateNamespace::snapshot()`].
+//! Alternatively, an image can be stored in a file, in which case the payload
+//! immediately follows the header rather than starting at the next block.
+//! The header identifies the image and holds the length and checksum of the payload,
+//! such that a missing, stale, or partially-written image is detected upon restore
+//! rather than restored.
+//!
+//! See [`CrateNamespace::snapshot()`] and [`CrateNamespace::restore()`]
+//! for which namespaces can be saved and when an image can be restored.
+//!
+//! This crate doesn't manage the space on the storage device in any way;
+//! the caller must choose a range of blocks that isn't used by anything else,
+//! e.g., a dedicated partition or a reserved region after a filesystem.
+
+#![no_std]
+
+#[macro_use] extern crate alloc;
+
+use alloc::{sync::Arc, vec::Vec};
+use fs_node::FileRef;
+use io::{BlockIo, BlockReader, BlockWriter, ByteReader, ByteWriter, KnownLength};
+use log::{error, info};
+use memory::MmiRef;
+use mod_mgmt::CrateNamespace;
+use storage_device::StorageDeviceRef;
+
+/// The magic bytes at the start of every image header block.
+const IMAGE_MAGIC: [u8; 8] = *b"THSNSIMG";
+/// The version of the image format, which must be changed whenever
+/// the header or the serialized namespace format changes.
+const IMAGE_VERSION: u32 = 1;
+/// The size in bytes of the header fields at the start of the header block.
+const HEADER_SIZE: usize = 32;
+
+/// The header that occupies the first block of an image.
+struct ImageHeader {
+    version: u32,
+    /// The length in bytes of the payload, which starts at the next block.
+    payload_len: u64,
+    /// The FNV-1a hash of the payload.
+    checksum: u64,
+}
+
+impl ImageHeader {
+    /// Returns the header for the given image payload.
+    fn for_payload(payload: &[u8]) -> ImageHeader {
+        ImageHeader {
+            version: IMAGE_VERSION,
+            payload_len: payload.len() as u64,
+            checksum: fnv1a::hash(payload),
+        }
+    }
+
+    /// Parses the header at the start of the given bytes and checks that its version is supported.
+    fn parse(header_bytes: &[u8]) -> Result<ImageHeader, &'static str> {
+        let header = ImageHeader::read_from(header_bytes).ok_or("no namespace image exists at the given location")?;
+        if header.version != IMAGE_VERSION {
+            error!("Namespace image has version {}, but only version {} is supported", header.version, IMAGE_VERSION);
+            return Err("namespace image has an unsupported version");
+        }
+        Ok(header)
+    }
+
+    /// Returns an error if the given payload doesn't match this header's checksum.
+    fn verify(&self, payload: &[u8]) -> Result<(), &'static str> {
+        if fnv1a::hash(payload) != self.checksum {
+            return Err("namespace image is corrupted: its checksum doesn't match");
+        }
+        Ok(())
+    }
+
+    fn write_to(&self, block: &mut [u8]) {
+        block[0..8].copy_from_slice(&IMAGE_MAGIC);
+        block[8..12].copy_from_slice(&self.version.to_le_bytes());
+        block[12..16].copy_from_slice(&0u32.to_le_bytes());
+        block[16..24].copy_from_slice(&self.payload_len.to_le_bytes());
+        block[24..32].copy_from_slice(&self.checksum.to_le_bytes());
+    }
+
+    fn read_from(block: &[u8]) -> Option<ImageHeader> {
+        if block[0..8] != IMAGE_MAGIC {
+            return None;
+        }
+        Some(ImageHeader {
+            version: u32::from_le_bytes(block[8..12].try_into().ok()?),
+            payload_len: u64::from_le_bytes(block[16..24].try_into().ok()?),
+            checksum: u64::from_le_bytes(block[24..32].try_into().ok()?),
+        })
+    }
+}
+
+/// Saves an image of the given `namespace` onto the given `storage_device`,
+/// starting at block `start_block`.
+///
+/// Returns the number of blocks that the image occupies, including its header block.
+pub fn save_namespace_image(
+    namespace: &CrateNamespace,
+    storage_device: &StorageDeviceRef,
+    start_block: usize,
+) -> Result<usize, &'static str> {
+    let payload = namespace.snapshot()?;
+
+    let mut device = storage_device.lock();
+    let block_size = device.block_size();
+    if block_size < HEADER_SIZE {
+        return Err("storage device's block size is too small to hold a namespace image header");
+    }
+    let num_blocks = 1 + payload.len().div_ceil(block_size);
+    let end_block = start_block.checked_add(num_blocks).ok_or("namespace image is too large")?;
+    if end_block > device.len() / block_size {
+        error!("Namespace image needs {} blocks starting at block {}, but the storage device only has {} blocks",
+            num_blocks, start_block, device.len() / block_size
+        );
+        return Err("namespace image doesn't fit on the storage device");
+    }
+
+    let mut buffer = vec![0u8; num_blocks * block_size];
+    ImageHeader::for_payload(&payload).write_to(&mut buffer[..block_size]);
+    buffer[block_size .. block_size + payload.len()].copy_from_slice(&payload);
+
+    device.write_blocks(&buffer, start_block)?;
+    device.flush()?;
+    info!("Saved image of namespace {:?} ({} bytes) to blocks {}..{}",
+        namespace.name(), payload.len(), start_block, end_block
+    );
+    Ok(num_blocks)
+}
+
+/// Restores the crates in the namespace image stored on the given `storage_device`
+/// (starting at block `start_block`) into the given `namespace`.
+///
+/// Returns the number of crates that were restored.
+pub fn restore_namespace_image(
+    namespace: &Arc<CrateNamespace>,
+    storage_device: &StorageDeviceRef,
+    start_block: usize,
+    kernel_mmi_ref: &MmiRef,
+    verbose_log: bool,
+) -> Result<usize, &'static str> {
+    let payload = {
+        let mut device = storage_device.lock();
+        let block_size = device.block_size();
+        if block_size < HEADER_SIZE {
+            return Err("storage device's block size is too small to hold a namespace image header");
+        }
+
+        let mut header_block = vec![0u8; block_size];
+        device.read_blocks(&mut header_block, start_block)?;
+        let header = ImageHeader::parse(&header_block)?;
+
+        let payload_len = usize::try_from(header.payload_len).map_err(|_| "namespace image is too large")?;
+        let payload_blocks = payload_len.div_ceil(block_size);
+        if start_block + 1 + payload_blocks > device.len() / block_size {
+            return Err("namespace image header is corrupted: its payload extends past the end of the storage device");
+        }
+        let mut payload = vec![0u8; payload_blocks * block_size];
+        device.read_blocks(&mut payload, start_block + 1)?;
+        payload.truncate(payload_len);
+        header.verify(&payload)?;
+        payload
+    };
+
+    CrateNamespace::restore(namespace, &payload, kernel_mmi_ref, verbose_log)
+}
+
+/// Invalidates the namespace image stored on the given `storage_device` at block `start_block`
+/// by overwriting its header block, such that it will no longer be restored.
+pub fn erase_namespace_image(storage_device: &StorageDeviceRef, start_block: usize) -> Result<(), &'static str> {
+    let mut device = storage_device.lock();
+    let block_size = device.block_size();
+    device.write_blocks(&vec![0u8; block_size], start_block)?;
+    device.flush()?;
+    Ok(())
+}
+
+/// Saves an image of the given `namespace` into the given `file`, overwriting it from the start.
+///
+/// The file's existing contents beyond the end of the image are left in place, but are ignored upon restore.
+///
+/// Returns the size in bytes of the image, including its header.
+pub fn save_namespace_image_to_file(namespace: &CrateNamespace, file: &FileRef) -> Result<usize, &'static str> {
+    let payload = namespace.snapshot()?;
+
+    let mut buffer = Vec::with_capacity(HEADER_SIZE + payload.len());
+    buffer.resize(HEADER_SIZE, 0);
+    ImageHeader::for_payload(&payload).write_to(&mut buffer);
+    buffer.extend_from_slice(&payload);
+
+    let mut locked_file = file.lock();
+    let written = locked_file.write_at(&buffer, 0)?;
+    if written != buffer.len() {
+        return Err("failed to write the entire namespace image to the file");
+    }
+    locked_file.flush()?;
+    info!("Saved image of namespace {:?} ({} bytes) to file {}",
+        namespace.name(), payload.len(), locked_file.get_absolute_path()
+    );
+    Ok(buffer.len())
+}
+
+/// Restores the crates in the namespace image stored in the given `file` into the given `namespace`.
+///
+/// Returns the number of crates that were restored.
+pub fn restore_namespace_image_from_file(
+    namespace: &Arc<CrateNamespace>,
+    file: &FileRef,
+    kernel_mmi_ref: &MmiRef,
+    verbose_log: bool,
+) -> Result<usize, &'static str> {
+    let payload = {
+        let mut locked_file = file.lock();
+        let mut header_bytes = [0u8; HEADER_SIZE];
+        if locked_file.read_at(&mut header_bytes, 0)? != HEADER_SIZE {
+            return Err("file is too small to contain a namespace image");
+        }
+        let header = ImageHeader::parse(&header_bytes)?;
+
+        let payload_len = usize::try_from(header.payload_len).map_err(|_| "namespace image is too large")?;
+        if HEADER_SIZE + payload_len > locked_file.len() {
+            return Err("namespace image header is corrupted: its payload extends past the end of the file");
+        }
+        let mut payload = vec![0u8; payload_len];
+        if locked_file.read_at(&mut payload, HEADER_SIZE)? != payload_len {
+            return Err("failed to read the entire namespace image from the file");
+        }
+        header.verify(&payload)?;
+        payload
+    };
+
+    CrateNamespace::restore(namespace, &payload, kernel_mmi_ref, verbose_log)
+}
@@ -0,0 +1,404 @@
+//! Versioned, typed RPC interfaces that allow crates in different namespaces to call each other.
+//!
+//! Crates in different [`CrateNamespace`]s, e.g., two personalities, normally can only interact
+//! by directly linking against each other's symbols, which requires that both were built
+//! against the exact same versions of all crates they share.
+//! Instead, a crate can expose a *service* that implements a named, versioned interface,
+//! which crates in any other namespace can call without linking against the service's crate.
+//!
+//! Each call is sent over a channel to the task serving that interface.
+//! Because two namespaces may each have their own copy of a crate,
+//! a type with the same name in both is not necessarily the same type;
+//! thus, arguments and return values are serialized with `bincode`,
+//! such that both sides need only agree on the interface's encoding, which is captured by its version.
+//! Since all namespaces share the kernel heap, the serialized bytes are passed without further copying.
+//!
+//! ## Describing an interface
+//! An interface is described by the [`rpc_interface!`] macro, typically in a small stub crate
+//! that both the service and its clients depend on. For example:
+//! ```ignore
+//! namespace_rpc::rpc_interface! {
+//!     /// A simple calculator service.
+//!     pub interface Calculator("calculator", 1, 0) {
+//!         client: CalculatorClient,
+//!         server: CalculatorServer,
+//!         fn add(a: u64, b: u64) -> u64;
+//!         fn name() -> String;
+//!     }
+//! }
+//! ```
+//! This generates:
+//! * the `Calculator` trait, which the service implements,
+//! * the `CalculatorServer` wrapper, which dispatches incoming calls to a `Calculator` implementation, and
+//! * the `CalculatorClient` stub, whose methods call the service, e.g., `client.add(1, 2)`.
+//!
+//! The service registers itself and serves calls, typically in its own task:
+//! ```ignore
+//! let endpoint = namespace_rpc::register_service(CalculatorClient::INTERFACE, 16)?;
+//! endpoint.serve(&mut CalculatorServer(MyCalculator))?;
+//! ```
+//!
+//! ## Versioning
+//! An interface's [`InterfaceVersion`] has a major and a minor component.
+//! A client can connect to a service if the service has the same major version
+//! and at least the minor version that the client requires.
+//! Thus, adding methods to an interface only requires increasing its minor version,
+//! whereas changing or removing existing methods requires increasing its major version.
+//!
+//! [`CrateNamespace`]: ../mod_mgmt/struct.CrateNamespace.html
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use log::debug;
+use serde::{de::DeserializeOwned, Serialize};
+use spin::Mutex;
+use sync_channel::{Receiver, Sender};
+
+/// The version of an RPC interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct InterfaceVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl InterfaceVersion {
+    pub const fn new(major: u16, minor: u16) -> Self {
+        InterfaceVersion { major, minor }
+    }
+
+    /// Returns whether a service that implements this version can be used by a client
+    /// that requires the `required` version.
+    pub fn satisfies(&self, required: InterfaceVersion) -> bool {
+        self.major == required.major && self.minor >= required.minor
+    }
+}
+
+impl fmt::Display for InterfaceVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// The name and version of an RPC interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterfaceId {
+    pub name: &'static str,
+    pub version: InterfaceVersion,
+}
+
+/// The errors that can occur when registering, connecting to, or calling a service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcError {
+    /// No service with the requested interface name is registered.
+    NoSuchService,
+    /// A service with the requested interface name is registered,
+    /// but its version doesn't satisfy the required version.
+    IncompatibleVersion {
+        required: InterfaceVersion,
+        available: InterfaceVersion,
+    },
+    /// A service with the given interface name is already registered.
+    AlreadyRegistered,
+    /// The service doesn't implement the called method.
+    UnknownMethod,
+    /// The arguments or return value could not be serialized.
+    Encode,
+    /// The arguments or return value could not be deserialized,
+    /// e.g., because the client and service disagree about the method's signature.
+    Decode,
+    /// The service was unregistered, or it dropped the call without replying.
+    Disconnected,
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RpcError::NoSuchService => write!(f, "no such service"),
+            RpcError::IncompatibleVersion { required, available } =>
+                write!(f, "service version {} is incompatible with required version {}", available, required),
+            RpcError::AlreadyRegistered => write!(f, "service is already registered"),
+            RpcError::UnknownMethod => write!(f, "unknown method"),
+            RpcError::Encode => write!(f, "failed to serialize RPC message"),
+            RpcError::Decode => write!(f, "failed to deserialize RPC message"),
+            RpcError::Disconnected => write!(f, "service disconnected"),
+        }
+    }
+}
+
+/// Serializes the given value into an RPC message.
+pub fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, RpcError> {
+    bincode::serde::encode_to_vec(value, bincode::config::standard())
+        .map_err(|_| RpcError::Encode)
+}
+
+/// Deserializes a value from the given RPC message.
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, RpcError> {
+    bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+        .map(|(value, _len)| value)
+        .map_err(|_| RpcError::Decode)
+}
+
+/// The reply to a call: the serialized return value, or an error.
+type Reply = Result<Vec<u8>, RpcError>;
+
+/// A call to a service that has been received but not yet replied to.
+pub struct IncomingCall {
+    method: String,
+    args: Vec<u8>,
+    reply: Sender<Reply>,
+}
+
+impl IncomingCall {
+    /// Returns the name of the called method.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Returns the serialized arguments of the call.
+    pub fn args(&self) -> &[u8] {
+        &self.args
+    }
+
+    /// Sends the given result back to the caller.
+    ///
+    /// If the caller is no longer waiting for the reply, the result is discarded.
+    pub fn reply(self, result: Result<Vec<u8>, RpcError>) {
+        let _ = self.reply.try_send(result);
+    }
+}
+
+/// A type that handles the calls to a service, typically generated by [`rpc_interface!`].
+pub trait Dispatch {
+    /// Invokes the given `method` with the given serialized `args`,
+    /// returning its serialized return value.
+    fn dispatch(&mut self, method: &str, args: &[u8]) -> Result<Vec<u8>, RpcError>;
+}
+
+/// Information about a registered service.
+#[derive(Debug, Clone)]
+pub struct ServiceInfo {
+    pub name: String,
+    pub version: InterfaceVersion,
+    /// The name of the namespace of the task that registered the service.
+    pub namespace: String,
+}
+
+struct Service {
+    /// The unique ID of the `ServiceEndpoint` that registered this service.
+    endpoint_id: usize,
+    version: InterfaceVersion,
+    namespace: String,
+    requests: Sender<IncomingCall>,
+}
+
+static SERVICES: Mutex<BTreeMap<String, Service>> = Mutex::new(BTreeMap::new());
+
+static NEXT_ENDPOINT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Registers a service that implements the given interface,
+/// which can receive up to `capacity` calls before callers must wait.
+///
+/// The service remains registered until the returned `ServiceEndpoint` is dropped.
+/// Returns an error if a service with the same interface name is already registered.
+pub fn register_service(interface: InterfaceId, capacity: usize) -> Result<ServiceEndpoint, RpcError> {
+    let mut services = SERVICES.lock();
+    if services.contains_key(interface.name) {
+        return Err(RpcError::AlreadyRegistered);
+    }
+    let namespace = task::with_current_task(|t| t.get_namespace().name().to_string())
+        .unwrap_or_default();
+    let endpoint_id = NEXT_ENDPOINT_ID.fetch_add(1, Ordering::Relaxed);
+    let (requests, receiver) = sync_channel::new_channel(capacity);
+    debug!("Registered RPC service {:?} v{} from namespace {:?}", interface.name, interface.version, namespace);
+    services.insert(interface.name.to_string(), Service {
+        endpoint_id,
+        version: interface.version,
+        namespace,
+        requests,
+    });
+    Ok(ServiceEndpoint { interface, endpoint_id, receiver })
+}
+
+/// Returns information about all registered services.
+pub fn services() -> Vec<ServiceInfo> {
+    SERVICES.lock().iter()
+        .map(|(name, service)| ServiceInfo {
+            name: name.clone(),
+            version: service.version,
+            namespace: service.namespace.clone(),
+        })
+        .collect()
+}
+
+/// Connects to the registered service that implements a version of the given interface
+/// that satisfies `interface.version`.
+pub fn connect(interface: InterfaceId) -> Result<Connection, RpcError> {
+    let services = SERVICES.lock();
+    let service = services.get(interface.name).ok_or(RpcError::NoSuchService)?;
+    if !service.version.satisfies(interface.version) {
+        return Err(RpcError::IncompatibleVersion {
+            required: interface.version,
+            available: service.version,
+        });
+    }
+    Ok(Connection {
+        version: service.version,
+        requests: service.requests.clone(),
+    })
+}
+
+/// The receiving end of a registered service.
+///
+/// Dropping this unregisters the service, after which calls from existing connections
+/// fail with [`RpcError::Disconnected`].
+pub struct ServiceEndpoint {
+    interface: InterfaceId,
+    endpoint_id: usize,
+    receiver: Receiver<IncomingCall>,
+}
+
+impl ServiceEndpoint {
+    /// Returns the interface implemented by this service.
+    pub fn interface(&self) -> InterfaceId {
+        self.interface
+    }
+
+    /// Blocks until the next call to this service is received.
+    pub fn receive(&self) -> Result<IncomingCall, RpcError> {
+        self.receiver.receive().map_err(|_| RpcError::Disconnected)
+    }
+
+    /// Receives calls to this service forever, handling each one with the given `dispatcher`.
+    pub fn serve<D: Dispatch>(&self, dispatcher: &mut D) -> Result<(), RpcError> {
+        loop {
+            let call = self.receive()?;
+            let result = dispatcher.dispatch(call.method(), call.args());
+            call.reply(result);
+        }
+    }
+}
+
+impl Drop for ServiceEndpoint {
+    fn drop(&mut self) {
+        let mut services = SERVICES.lock();
+        if services.get(self.interface.name).map_or(false, |s| s.endpoint_id == self.endpoint_id) {
+            services.remove(self.interface.name);
+        }
+    }
+}
+
+/// A client's connection to a service.
+#[derive(Clone)]
+pub struct Connection {
+    version: InterfaceVersion,
+    requests: Sender<IncomingCall>,
+}
+
+impl Connection {
+    /// Returns the version of the interface implemented by the connected service.
+    pub fn version(&self) -> InterfaceVersion {
+        self.version
+    }
+
+    /// Calls the given method of the connected service with already-serialized arguments,
+    /// blocking until it replies with its serialized return value.
+    pub fn call_raw(&self, method: &str, args: Vec<u8>) -> Result<Vec<u8>, RpcError> {
+        let (reply, reply_receiver) = sync_channel::new_channel(2);
+        self.requests.send(IncomingCall { method: method.to_string(), args, reply })
+            .map_err(|_| RpcError::Disconnected)?;
+        reply_receiver.receive().map_err(|_| RpcError::Disconnected)?
+    }
+
+    /// Calls the given method of the connected service, blocking until it returns.
+    pub fn call<A: Serialize, R: DeserializeOwned>(&self, method: &str, args: &A) -> Result<R, RpcError> {
+        let ret = self.call_raw(method, encode(args)?)?;
+        decode(&ret)
+    }
+}
+
+/// Items used by the code generated by [`rpc_interface!`].
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::vec::Vec;
+}
+
+/// Describes an RPC interface, generating a trait for services that implement it,
+/// a server type that dispatches calls to such a service, and a client stub.
+///
+/// See the [crate-level documentation](crate) for an example.
+#[macro_export]
+macro_rules! rpc_interface {
+    (
+        $(#[$meta:meta])*
+        $vis:vis interface $trait_name:ident ($name:literal, $major:literal, $minor:literal) {
+            client: $client:ident,
+            server: $server:ident,
+            $(
+                $(#[$method_meta:meta])*
+                fn $method:ident ( $($arg:ident : $arg_ty:ty),* $(,)? ) -> $ret:ty;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis trait $trait_name {
+            $(
+                $(#[$method_meta])*
+                fn $method(&mut self, $($arg: $arg_ty),*) -> $ret;
+            )*
+        }
+
+        #[doc = concat!("A client of the `", $name, "` interface.")]
+        #[derive(Clone)]
+        $vis struct $client {
+            connection: $crate::Connection,
+        }
+
+        impl $client {
+            #[doc = concat!("The `", $name, "` interface, version ", $major, ".", $minor, ".")]
+            pub const INTERFACE: $crate::InterfaceId = $crate::InterfaceId {
+                name: $name,
+                version: $crate::InterfaceVersion::new($major, $minor),
+            };
+
+            /// Connects to the registered service that implements this interface.
+            pub fn connect() -> Result<Self, $crate::RpcError> {
+                $crate::connect(Self::INTERFACE).map(|connection| Self { connection })
+            }
+
+            $(
+                $(#[$method_meta])*
+                pub fn $method(&self, $($arg: $arg_ty),*) -> Result<$ret, $crate::RpcError> {
+                    self.connection.call(stringify!($method), &($($arg,)*))
+                }
+            )*
+        }
+
+        #[doc = concat!("Dispatches calls to the `", $name, "` interface to the contained implementation.")]
+        $vis struct $server<T: $trait_name>(pub T);
+
+        impl<T: $trait_name> $crate::Dispatch for $server<T> {
+            fn dispatch(&mut self, method: &str, args: &[u8]) -> Result<$crate::__private::Vec<u8>, $crate::RpcError> {
+                match method {
+                    $(
+                        stringify!($method) => {
+                            let ($($arg,)*): ($($arg_ty,)*) = $crate::decode(args)?;
+                            $crate::encode(&self.0.$method($($arg),*))
+                        }
+                    )*
+                    _ => Err($crate::RpcError::UnknownMethod),
+                }
+            }
+        }
+    };
+}
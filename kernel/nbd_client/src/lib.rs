@@ -0,0 +1,390 @@
+//! A network block device (NBD) client that exposes a remote disk as a local storage device.
+//!
+//! An [`NbdDevice`] connects over TCP to a server speaking the [NBD protocol],
+//! such as `nbd-server` or `qemu-nbd` running on the development host,
+//! negotiates an export using the fixed newstyle handshake,
+//! and then forwards every block read, write, and flush to the server.
+//! Since it is itself a [`StorageDevice`], diskless machines can layer a block cache
+//! and a filesystem atop it just like a local disk.
+//!
+//! Connected devices can be registered by name via [`add_device()`] so that other components can find them.
+//!
+//! Requests are issued synchronously, one at a time;
+//! each operation blocks until the server's reply has been received or the connection times out.
+//!
+//! [NBD protocol]: https://github.com/NetworkBlockDevice/nbd/blob/master/doc/proto.md
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    string::String,
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::time::Duration;
+use io::{BlockIo, BlockReader, BlockWriter, IoError, KnownLength};
+use log::{debug, error, info};
+use net::{tcp, IpEndpoint, NetworkInterface, Socket};
+use spin::Mutex;
+use storage_device::{StorageDevice, StorageDeviceRef};
+use time::Instant;
+
+/// The TCP port that NBD servers listen on by default.
+pub const DEFAULT_PORT: u16 = 10809;
+
+/// The block size exposed by NBD devices.
+///
+/// The NBD protocol addresses the export at byte granularity,
+/// so this is only the granularity at which the rest of Theseus accesses it.
+pub const BLOCK_SIZE: usize = 512;
+
+/// How long to wait for the server before failing a request.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to sleep between polls of the interface while waiting for the server.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The size of each of the socket's receive and transmit buffers.
+const SOCKET_BUFFER_SIZE: usize = 64 * 1024;
+
+const NBDMAGIC: u64 = 0x4e42_444d_4147_4943;
+const IHAVEOPT: u64 = 0x4948_4156_454f_5054;
+const REQUEST_MAGIC: u32 = 0x2560_9513;
+const SIMPLE_REPLY_MAGIC: u32 = 0x6744_6698;
+
+const FLAG_FIXED_NEWSTYLE: u16 = 1 << 0;
+const FLAG_NO_ZEROES: u16 = 1 << 1;
+
+const OPT_EXPORT_NAME: u32 = 1;
+
+const TRANSMISSION_FLAG_READ_ONLY: u16 = 1 << 1;
+const TRANSMISSION_FLAG_SEND_FLUSH: u16 = 1 << 2;
+
+/// The types of commands sent during the transmission phase.
+#[derive(Clone, Copy, Debug)]
+#[repr(u16)]
+enum Command {
+    Read = 0,
+    Write = 1,
+    Disconnect = 2,
+    Flush = 3,
+}
+
+/// A blocking wrapper around a TCP socket connected to an NBD server.
+struct Connection {
+    interface: Arc<NetworkInterface>,
+    socket: Socket<tcp::Socket<'static>>,
+}
+
+impl Connection {
+    fn connect(interface: Arc<NetworkInterface>, remote: IpEndpoint) -> Result<Self, &'static str> {
+        let rx_buffer = tcp::SocketBuffer::new(vec![0; SOCKET_BUFFER_SIZE]);
+        let tx_buffer = tcp::SocketBuffer::new(vec![0; SOCKET_BUFFER_SIZE]);
+        let socket = interface
+            .clone()
+            .add_socket(tcp::Socket::new(rx_buffer, tx_buffer));
+        socket
+            .lock()
+            .connect(remote, net::get_ephemeral_port())
+            .map_err(|_| "nbd_client: failed to connect socket")?;
+
+        let connection = Connection { interface, socket };
+        connection.wait_until(|s| s.may_send(), "nbd_client: timed out connecting to the server")?;
+        Ok(connection)
+    }
+
+    /// Polls the interface until `condition` holds for the socket, sleeping between polls,
+    /// returning an error if the connection is closed or the timeout elapses.
+    fn wait_until<F>(&self, condition: F, timeout_error: &'static str) -> Result<(), &'static str>
+    where
+        F: Fn(&tcp::Socket<'static>) -> bool,
+    {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            self.interface.poll();
+            {
+                let socket = self.socket.lock();
+                if condition(&socket) {
+                    return Ok(());
+                }
+                if socket.state() == tcp::State::Closed {
+                    return Err("nbd_client: connection closed by the server");
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(timeout_error);
+            }
+            let _ = sleep::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn send_all(&self, mut buf: &[u8]) -> Result<(), &'static str> {
+        while !buf.is_empty() {
+            self.wait_until(|s| s.can_send(), "nbd_client: timed out sending to the server")?;
+            let sent = self.socket
+                .lock()
+                .send_slice(buf)
+                .map_err(|_| "nbd_client: failed to send to the server")?;
+            buf = &buf[sent..];
+        }
+        self.interface.poll();
+        Ok(())
+    }
+
+    fn recv_exact(&self, mut buf: &mut [u8]) -> Result<(), &'static str> {
+        while !buf.is_empty() {
+            self.wait_until(|s| s.can_recv(), "nbd_client: timed out receiving from the server")?;
+            let received = self.socket
+                .lock()
+                .recv_slice(buf)
+                .map_err(|_| "nbd_client: failed to receive from the server")?;
+            buf = &mut buf[received..];
+        }
+        Ok(())
+    }
+
+    fn recv_u16(&self) -> Result<u16, &'static str> {
+        let mut bytes = [0; 2];
+        self.recv_exact(&mut bytes)?;
+        Ok(u16::from_be_bytes(bytes))
+    }
+
+    fn recv_u32(&self) -> Result<u32, &'static str> {
+        let mut bytes = [0; 4];
+        self.recv_exact(&mut bytes)?;
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn recv_u64(&self) -> Result<u64, &'static str> {
+        let mut bytes = [0; 8];
+        self.recv_exact(&mut bytes)?;
+        Ok(u64::from_be_bytes(bytes))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.socket.lock().close();
+        self.interface.poll();
+    }
+}
+
+/// A storage device backed by an export on a remote NBD server.
+///
+/// See the [crate-level documentation](crate) for more.
+pub struct NbdDevice {
+    connection: Connection,
+    export_name: String,
+    size_in_blocks: usize,
+    read_only: bool,
+    supports_flush: bool,
+    /// The handle of the next request, used to match replies to requests.
+    next_handle: u64,
+}
+
+impl NbdDevice {
+    /// Connects to the NBD server at `remote` via the given `interface`
+    /// and opens the export with the given name.
+    ///
+    /// An empty `export_name` selects the server's default export.
+    pub fn connect(
+        interface: Arc<NetworkInterface>,
+        remote: IpEndpoint,
+        export_name: &str,
+    ) -> Result<NbdDevice, &'static str> {
+        let connection = Connection::connect(interface, remote)?;
+
+        if connection.recv_u64()? != NBDMAGIC || connection.recv_u64()? != IHAVEOPT {
+            return Err("nbd_client: the server doesn't speak the newstyle NBD protocol");
+        }
+        let handshake_flags = connection.recv_u16()?;
+        if handshake_flags & FLAG_FIXED_NEWSTYLE == 0 {
+            return Err("nbd_client: the server doesn't support the fixed newstyle handshake");
+        }
+        let no_zeroes = handshake_flags & FLAG_NO_ZEROES != 0;
+        let client_flags = FLAG_FIXED_NEWSTYLE as u32 | if no_zeroes { FLAG_NO_ZEROES as u32 } else { 0 };
+        connection.send_all(&client_flags.to_be_bytes())?;
+
+        let mut option = Vec::with_capacity(16 + export_name.len());
+        option.extend_from_slice(&IHAVEOPT.to_be_bytes());
+        option.extend_from_slice(&OPT_EXPORT_NAME.to_be_bytes());
+        option.extend_from_slice(&(export_name.len() as u32).to_be_bytes());
+        option.extend_from_slice(export_name.as_bytes());
+        connection.send_all(&option)?;
+
+        let size_in_bytes = connection.recv_u64()?;
+        let transmission_flags = connection.recv_u16()?;
+        if !no_zeroes {
+            let mut zeroes = [0u8; 124];
+            connection.recv_exact(&mut zeroes)?;
+        }
+
+        let device = NbdDevice {
+            connection,
+            export_name: String::from(export_name),
+            size_in_blocks: (size_in_bytes / BLOCK_SIZE as u64) as usize,
+            read_only: transmission_flags & TRANSMISSION_FLAG_READ_ONLY != 0,
+            supports_flush: transmission_flags & TRANSMISSION_FLAG_SEND_FLUSH != 0,
+            next_handle: 0,
+        };
+        info!(
+            "nbd_client: connected to export {:?} at {}, {} blocks{}",
+            export_name, remote, device.size_in_blocks,
+            if device.read_only { " (read-only)" } else { "" },
+        );
+        Ok(device)
+    }
+
+    /// Returns the name of the export this device is connected to.
+    pub fn export_name(&self) -> &str {
+        &self.export_name
+    }
+
+    /// Returns whether the server only permits reading from the export.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Sends a request and waits for the server's reply,
+    /// receiving the reply's payload into `reply_data`.
+    fn request(
+        &mut self,
+        command: Command,
+        offset: u64,
+        length: u32,
+        write_data: &[u8],
+        reply_data: &mut [u8],
+    ) -> Result<(), IoError> {
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1);
+
+        let mut header = [0u8; 28];
+        header[0..4].copy_from_slice(&REQUEST_MAGIC.to_be_bytes());
+        // header[4..6] holds the command flags, which we don't use.
+        header[6..8].copy_from_slice(&(command as u16).to_be_bytes());
+        header[8..16].copy_from_slice(&handle.to_be_bytes());
+        header[16..24].copy_from_slice(&offset.to_be_bytes());
+        header[24..28].copy_from_slice(&length.to_be_bytes());
+        self.connection.send_all(&header)?;
+        self.connection.send_all(write_data)?;
+
+        if let Command::Disconnect = command {
+            // The server does not reply to a disconnect request.
+            return Ok(());
+        }
+
+        if self.connection.recv_u32()? != SIMPLE_REPLY_MAGIC {
+            return Err(IoError::Other("nbd_client: received a reply with an invalid magic number"));
+        }
+        let error = self.connection.recv_u32()?;
+        if self.connection.recv_u64()? != handle {
+            return Err(IoError::Other("nbd_client: received a reply for an unexpected request"));
+        }
+        if error != 0 {
+            error!("nbd_client: {:?} request at offset {:#X} failed with error {}", command, offset, error);
+            return Err(IoError::Other("nbd_client: the server reported an error"));
+        }
+        self.connection.recv_exact(reply_data)?;
+        Ok(())
+    }
+
+    /// Checks that `buffer_len` bytes starting at `block_offset` are within this device,
+    /// and returns the number of blocks they span.
+    fn check_bounds(&self, buffer_len: usize, block_offset: usize) -> Result<usize, IoError> {
+        if buffer_len % BLOCK_SIZE != 0 || buffer_len > u32::MAX as usize {
+            return Err(IoError::InvalidInput);
+        }
+        let num_blocks = buffer_len / BLOCK_SIZE;
+        if block_offset.checked_add(num_blocks).map_or(true, |end| end > self.size_in_blocks) {
+            return Err(IoError::InvalidInput);
+        }
+        Ok(num_blocks)
+    }
+}
+
+impl Drop for NbdDevice {
+    fn drop(&mut self) {
+        if let Err(e) = self.request(Command::Disconnect, 0, 0, &[], &mut []) {
+            debug!("nbd_client: failed to disconnect cleanly: {:?}", e);
+        }
+    }
+}
+
+impl StorageDevice for NbdDevice {
+    fn size_in_blocks(&self) -> usize {
+        self.size_in_blocks
+    }
+}
+impl BlockIo for NbdDevice {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+}
+impl KnownLength for NbdDevice {
+    fn len(&self) -> usize {
+        BLOCK_SIZE * self.size_in_blocks
+    }
+}
+impl BlockReader for NbdDevice {
+    fn read_blocks(&mut self, buffer: &mut [u8], block_offset: usize) -> Result<usize, IoError> {
+        let num_blocks = self.check_bounds(buffer.len(), block_offset)?;
+        let offset = (block_offset * BLOCK_SIZE) as u64;
+        self.request(Command::Read, offset, buffer.len() as u32, &[], buffer)?;
+        Ok(num_blocks)
+    }
+}
+impl BlockWriter for NbdDevice {
+    fn write_blocks(&mut self, buffer: &[u8], block_offset: usize) -> Result<usize, IoError> {
+        if self.read_only {
+            return Err(IoError::Other("nbd_client: the export is read-only"));
+        }
+        let num_blocks = self.check_bounds(buffer.len(), block_offset)?;
+        let offset = (block_offset * BLOCK_SIZE) as u64;
+        self.request(Command::Write, offset, buffer.len() as u32, buffer, &mut [])?;
+        Ok(num_blocks)
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        if !self.supports_flush {
+            return Ok(());
+        }
+        self.request(Command::Flush, 0, 0, &[], &mut [])
+    }
+}
+
+/// The connected network block devices, by name.
+static DEVICES: Mutex<Vec<(String, StorageDeviceRef)>> = Mutex::new(Vec::new());
+
+/// Registers the given connected device under the given `name`,
+/// and returns a reference to it that can be used like any other storage device.
+pub fn add_device(name: &str, device: NbdDevice) -> Result<StorageDeviceRef, &'static str> {
+    let mut devices = DEVICES.lock();
+    if devices.iter().any(|(n, _)| n == name) {
+        return Err("nbd_client: a network block device with that name is already connected");
+    }
+    let device_ref: StorageDeviceRef = Arc::new(Mutex::new(device));
+    devices.push((String::from(name), device_ref.clone()));
+    Ok(device_ref)
+}
+
+/// Returns the connected network block device with the given `name`.
+pub fn device(name: &str) -> Option<StorageDeviceRef> {
+    DEVICES.lock().iter().find(|(n, _)| n == name).map(|(_, dev)| dev.clone())
+}
+
+/// Unregisters the network block device with the given `name`.
+///
+/// The device disconnects from the server once all other references to it have been dropped.
+pub fn remove_device(name: &str) -> Option<StorageDeviceRef> {
+    let mut devices = DEVICES.lock();
+    let index = devices.iter().position(|(n, _)| n == name)?;
+    Some(devices.remove(index).1)
+}
+
+/// Returns the names of all connected network block devices.
+pub fn device_names() -> Vec<String> {
+    DEVICES.lock().iter().map(|(n, _)| n.clone()).collect()
+}
@@ -1,4 +1,5 @@
 use alloc::vec;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 use log::error;
 use nic_buffers::{ReceivedFrame, TransmitBuffer};
@@ -48,6 +49,48 @@ pub trait NetworkDevice: Send + Sync {
 /// ```
 pub(crate) struct DeviceWrapper<'a> {
     pub(crate) inner: &'a mut dyn NetworkDevice,
+    pub(crate) counters: &'a InterfaceCounters,
+}
+
+/// Statistics about the packets sent and received by a [`NetworkInterface`].
+///
+/// [`NetworkInterface`]: crate::NetworkInterface
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InterfaceStats {
+    pub rx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+}
+
+/// The counters from which [`InterfaceStats`] are read.
+#[derive(Default)]
+pub(crate) struct InterfaceCounters {
+    rx_packets: AtomicU64,
+    rx_bytes: AtomicU64,
+    tx_packets: AtomicU64,
+    tx_bytes: AtomicU64,
+}
+
+impl InterfaceCounters {
+    fn record_rx(&self, len: usize) {
+        self.rx_packets.fetch_add(1, Ordering::Relaxed);
+        self.rx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    fn record_tx(&self, len: usize) {
+        self.tx_packets.fetch_add(1, Ordering::Relaxed);
+        self.tx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> InterfaceStats {
+        InterfaceStats {
+            rx_packets: self.rx_packets.load(Ordering::Relaxed),
+            rx_bytes: self.rx_bytes.load(Ordering::Relaxed),
+            tx_packets: self.tx_packets.load(Ordering::Relaxed),
+            tx_bytes: self.tx_bytes.load(Ordering::Relaxed),
+        }
+    }
 }
 
 impl<'a> phy::Device for DeviceWrapper<'a> {
@@ -60,11 +103,15 @@ impl<'a> phy::Device for DeviceWrapper<'a> {
         _: smoltcp::time::Instant,
     ) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
         let frame = self.inner.receive()?;
-        Some((RxToken { inner: frame }, TxToken { device: self.inner }))
+        self.counters.record_rx(frame.0.iter().map(|buf| buf.len()).sum());
+        Some((
+            RxToken { inner: frame },
+            TxToken { device: self.inner, counters: self.counters },
+        ))
     }
 
     fn transmit(&mut self, _: smoltcp::time::Instant) -> Option<Self::TxToken<'_>> {
-        Some(TxToken { device: self.inner })
+        Some(TxToken { device: self.inner, counters: self.counters })
     }
 
     fn capabilities(&self) -> DeviceCapabilities {
@@ -100,6 +147,7 @@ impl phy::RxToken for RxToken {
 /// The transmit token.
 pub(crate) struct TxToken<'a> {
     device: &'a mut dyn NetworkDevice,
+    counters: &'a InterfaceCounters,
 }
 
 impl<'a> phy::TxToken for TxToken<'a> {
@@ -112,6 +160,7 @@ impl<'a> phy::TxToken for TxToken<'a> {
                 // This will only fail if the underlying memory allocation fails.
                 let mut buf = TransmitBuffer::new(len).expect("failed to allocate transmit buffer");
                 let ret = f(&mut buf);
+                self.counters.record_tx(len as usize);
                 self.device.send(buf);
                 ret
             }
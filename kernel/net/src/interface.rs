@@ -9,7 +9,10 @@ pub use smoltcp::{
 use sync_block::Mutex;
 use sync_irq::IrqSafeMutex;
 
-use crate::{device::DeviceWrapper, NetworkDevice, Socket};
+use crate::{
+    device::{DeviceWrapper, InterfaceCounters, InterfaceStats},
+    NetworkDevice, Socket,
+};
 
 /// A network interface.
 ///
@@ -19,6 +22,7 @@ pub struct NetworkInterface {
     pub(crate) inner: Mutex<iface::Interface>,
     device: &'static IrqSafeMutex<dyn crate::NetworkDevice>,
     pub(crate) sockets: Mutex<SocketSet<'static>>,
+    counters: InterfaceCounters,
 }
 
 impl NetworkInterface {
@@ -28,8 +32,10 @@ impl NetworkInterface {
     {
         let hardware_addr = wire::EthernetAddress(device.lock().mac_address()).into();
 
+        let counters = InterfaceCounters::default();
         let mut wrapper = DeviceWrapper {
             inner: &mut *device.lock(),
+            counters: &counters,
         };
 
         let mut config = iface::Config::new(hardware_addr);
@@ -52,6 +58,7 @@ impl NetworkInterface {
             inner: Mutex::new(interface),
             device,
             sockets: Mutex::new(SocketSet::new(Vec::new())),
+            counters,
         }
     }
 
@@ -76,13 +83,51 @@ impl NetworkInterface {
         let mut inner = self.inner.lock();
         let mut wrapper = DeviceWrapper {
             inner: &mut *self.device.lock(),
+            counters: &self.counters,
         };
         let mut sockets = self.sockets.lock();
 
         inner.poll(smoltcp::time::Instant::ZERO, &mut wrapper, &mut sockets)
     }
 
+    /// Replaces the IP address and default gateway of the interface.
+    ///
+    /// Existing sockets remain open, but connections bound to the previous
+    /// address will no longer receive packets.
+    pub fn set_ip_config(&self, ip: IpCidr, gateway: IpAddress) -> Result<(), &'static str> {
+        let mut inner = self.inner.lock();
+        inner.update_ip_addrs(|ip_addrs| {
+            ip_addrs.clear();
+            // NOTE: This won't fail as the addresses were just cleared.
+            ip_addrs.push(ip).unwrap();
+        });
+        let routes = inner.routes_mut();
+        routes.remove_default_ipv4_route();
+        routes.remove_default_ipv6_route();
+        match gateway {
+            IpAddress::Ipv4(addr) => routes.add_default_ipv4_route(addr),
+            IpAddress::Ipv6(addr) => routes.add_default_ipv6_route(addr),
+        }
+        .map_err(|_| "route storage exhausted")?;
+        Ok(())
+    }
+
+    /// Returns the IP addresses of the interface.
+    pub fn ip_addrs(&self) -> Vec<IpCidr> {
+        self.inner.lock().ip_addrs().to_vec()
+    }
+
     pub fn capabilities(&self) -> DeviceCapabilities {
         self.device.lock().capabilities()
     }
+
+    /// Returns the MAC address of the interface's device.
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.device.lock().mac_address()
+    }
+
+    /// Returns the number of packets and bytes sent and received by the interface.
+    pub fn stats(&self) -> InterfaceStats {
+        self.counters.stats()
+    }
 }
@@ -12,7 +12,7 @@ mod device;
 mod interface;
 mod socket;
 
-pub use device::{DeviceCapabilities, NetworkDevice};
+pub use device::{DeviceCapabilities, InterfaceStats, NetworkDevice};
 pub use interface::{IpAddress, IpCidr, NetworkInterface, SocketSet};
 pub use smoltcp::{
     phy,
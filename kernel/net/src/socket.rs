@@ -87,3 +87,13 @@ where
         }
     }
 }
+
+impl<T> Drop for Socket<T>
+where
+    T: AnySocket<'static> + ?Sized,
+{
+    /// Removes the socket from its interface, such that its buffers are freed.
+    fn drop(&mut self) {
+        self.interface.sockets.lock().remove(self.handle);
+    }
+}
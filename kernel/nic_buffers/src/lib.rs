@@ -126,6 +126,20 @@ impl ReceiveBuffer {
             Ok(())
         }
     }
+
+    /// Sets the length of this buffer to that of a packet that was just received into it.
+    ///
+    /// Unlike [`ReceiveBuffer::set_length()`], this can grow the buffer up to the size of its
+    /// underlying memory, which allows a buffer taken from its pool (with a length of `0`)
+    /// to be handed to the NIC and then reused for the next received packet.
+    pub fn set_received_length(&mut self, length: u16) -> Result<(), &'static str> {
+        if usize::from(length) > self.mp.size_in_bytes() {
+            Err("ReceiveBuffer::set_received_length(): length too long")
+        } else {
+            self.length = length;
+            Ok(())
+        }
+    }
 }
 
 impl Deref for ReceiveBuffer {
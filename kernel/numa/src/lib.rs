@@ -0,0 +1,154 @@
+//! The NUMA (Non-Uniform Memory Access) topology of the system.
+//!
+//! On multi-socket machines, each CPU and each range of physical memory belongs
+//! to a NUMA node, and accessing memory on a remote node is slower than accessing local memory.
+//! The topology is discovered from the ACPI SRAT and SLIT tables by the `acpi` crate,
+//! which registers each node's memory ranges with the frame allocator
+//! and then calls [`init()`] with the CPU-to-node mapping and inter-node distances.
+//!
+//! If the firmware doesn't describe a NUMA topology, [`init()`] is never called,
+//! all queries return `None`, and allocations simply fall back to any available memory.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use cpu::CpuId;
+use log::info;
+use memory::AllocatedFrames;
+use spin::Once;
+
+/// The relative distance from a NUMA node to itself.
+pub const LOCAL_DISTANCE: u8 = 10;
+/// The relative distance between two different NUMA nodes,
+/// used when the firmware doesn't provide an explicit distance matrix.
+pub const REMOTE_DISTANCE: u8 = 20;
+
+/// The singleton NUMA topology of this system, set once during ACPI initialization.
+static TOPOLOGY: Once<NumaTopology> = Once::new();
+
+/// A description of the system's NUMA nodes, built up while parsing the ACPI tables.
+#[derive(Debug, Default)]
+pub struct NumaTopology {
+    /// The node of each CPU, keyed by its raw CPU ID (its APIC ID on x86_64).
+    ///
+    /// Raw IDs are used because the topology is discovered before the secondary CPUs
+    /// have been brought up, at which point they aren't yet valid [`CpuId`]s.
+    cpu_nodes: BTreeMap<u32, u32>,
+    /// All known nodes, sorted and without duplicates.
+    nodes: Vec<u32>,
+    /// The number of localities in the `distances` matrix.
+    num_localities: usize,
+    /// The `num_localities x num_localities` matrix of distances between nodes,
+    /// in row-major order. This is empty if no distances were provided.
+    distances: Vec<u8>,
+}
+
+impl NumaTopology {
+    /// Returns a new empty topology.
+    pub fn new() -> NumaTopology {
+        NumaTopology::default()
+    }
+
+    /// Records that the CPU with the given raw ID belongs to the given `node`.
+    pub fn add_cpu(&mut self, raw_cpu_id: u32, node: u32) {
+        self.cpu_nodes.insert(raw_cpu_id, node);
+        self.add_node(node);
+    }
+
+    /// Records that the given `node` exists, e.g., because it contains memory.
+    pub fn add_node(&mut self, node: u32) {
+        if let Err(i) = self.nodes.binary_search(&node) {
+            self.nodes.insert(i, node);
+        }
+    }
+
+    /// Sets the matrix of distances between nodes,
+    /// which must be `num_localities x num_localities` in row-major order.
+    pub fn set_distances(&mut self, num_localities: usize, distances: Vec<u8>) -> Result<(), &'static str> {
+        if num_localities.checked_mul(num_localities) != Some(distances.len()) {
+            return Err("NUMA distance matrix had the wrong number of entries");
+        }
+        self.num_localities = num_localities;
+        self.distances = distances;
+        Ok(())
+    }
+
+    /// Returns the node of the CPU with the given raw ID, if known.
+    pub fn node_of_raw_cpu(&self, raw_cpu_id: u32) -> Option<u32> {
+        self.cpu_nodes.get(&raw_cpu_id).copied()
+    }
+
+    /// Returns all known nodes, sorted in ascending order.
+    pub fn nodes(&self) -> &[u32] {
+        &self.nodes
+    }
+
+    /// Returns the relative distance between the nodes `from` and `to`,
+    /// where [`LOCAL_DISTANCE`] is the distance from a node to itself.
+    ///
+    /// Returns `None` if either node doesn't exist.
+    pub fn distance(&self, from: u32, to: u32) -> Option<u8> {
+        if self.nodes.binary_search(&from).is_err() || self.nodes.binary_search(&to).is_err() {
+            return None;
+        }
+        if self.distances.is_empty() {
+            return Some(if from == to { LOCAL_DISTANCE } else { REMOTE_DISTANCE });
+        }
+        let (from, to) = (from as usize, to as usize);
+        if from >= self.num_localities || to >= self.num_localities {
+            return None;
+        }
+        self.distances.get(from * self.num_localities + to).copied()
+    }
+}
+
+/// Sets the system-wide NUMA topology.
+///
+/// This can only be invoked once; subsequent calls will return an error.
+pub fn init(topology: NumaTopology) -> Result<(), &'static str> {
+    if TOPOLOGY.is_completed() {
+        return Err("BUG: the NUMA topology was already initialized");
+    }
+    info!("NUMA topology: {} node(s) {:?}, {} CPU(s)",
+        topology.nodes.len(), topology.nodes, topology.cpu_nodes.len(),
+    );
+    TOPOLOGY.call_once(|| topology);
+    Ok(())
+}
+
+/// Returns the system-wide NUMA topology, if it has been discovered.
+pub fn topology() -> Option<&'static NumaTopology> {
+    TOPOLOGY.get()
+}
+
+/// Returns the number of NUMA nodes, which is `0` if the topology is unknown.
+pub fn num_nodes() -> usize {
+    topology().map_or(0, |t| t.nodes.len())
+}
+
+/// Returns the NUMA node that the given CPU belongs to, if known.
+pub fn node_of_cpu(cpu: CpuId) -> Option<u32> {
+    topology()?.node_of_raw_cpu(cpu.value())
+}
+
+/// Returns the NUMA node of the currently executing CPU, if known.
+pub fn current_node() -> Option<u32> {
+    node_of_cpu(cpu::current_cpu())
+}
+
+/// Returns the relative distance between the nodes `from` and `to`, if known.
+///
+/// See [`NumaTopology::distance()`].
+pub fn distance(from: u32, to: u32) -> Option<u8> {
+    topology()?.distance(from, to)
+}
+
+/// Allocates the given number of frames from the memory local to the given CPU,
+/// falling back to frames from any node if that's not possible.
+pub fn allocate_frames_near(cpu: CpuId, num_frames: usize) -> Option<AllocatedFrames> {
+    node_of_cpu(cpu)
+        .and_then(|node| memory::allocate_frames_on_node(node, num_frames))
+        .or_else(|| memory::allocate_frames(num_frames))
+}
@@ -0,0 +1,273 @@
+//! Slab allocators for frequently-allocated kernel objects of a single type,
+//! with per-CPU magazine caches in front of them.
+//!
+//! All allocations in Theseus normally go through the global heap, which must serve every size and alignment.
+//! Subsystems that allocate and free many objects of the same type, e.g., task structs or crate sections,
+//! can instead opt into a dedicated [`ObjectCache`] for that type:
+//! ```ignore
+//! static SECTION_CACHE: ObjectCache = ObjectCache::for_arc::<LoadedSection>("LoadedSection");
+//! let section = Arc::new_in(section, &SECTION_CACHE);
+//! ```
+//!
+//! An `ObjectCache` carves objects out of page-sized slabs that hold only objects of that type,
+//! which prevents these objects from fragmenting the general heap.
+//! Each CPU has a small magazine of free objects that it allocates from and frees into
+//! without contending with other CPUs; only when a magazine runs empty or fills up
+//! does it exchange a batch of objects with the cache's shared depot of free objects.
+//!
+//! Slabs are currently never returned to the system, as objects freed into a cache are kept for reuse.
+
+#![no_std]
+#![feature(allocator_api)]
+
+extern crate alloc;
+
+use alloc::{alloc::Global, boxed::Box, vec::Vec};
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    mem::{align_of, size_of},
+    ptr::NonNull,
+};
+use kernel_config::memory::PAGE_SIZE;
+use memory::{MappedPages, PteFlags};
+use spin::Once;
+use sync_irq::IrqSafeMutex;
+
+/// The maximum number of free objects held in each CPU's magazine.
+const MAGAZINE_SIZE: usize = 32;
+/// The number of CPUs that can have a magazine; CPUs with higher IDs allocate directly from the depot.
+const MAX_MAGAZINES: usize = 256;
+/// The minimum size of each slab in bytes.
+const MIN_SLAB_SIZE: usize = 4 * PAGE_SIZE;
+/// The minimum number of objects in each slab.
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+
+/// A slab allocator for objects of a single size and alignment.
+///
+/// This implements the [`Allocator`] trait, so a `&'static ObjectCache` can be used
+/// to allocate objects with `Box::new_in()`, `Arc::new_in()`, etc.
+/// Allocations with layouts that don't fit within this cache's object layout
+/// are passed through to the global heap.
+pub struct ObjectCache {
+    name: &'static str,
+    /// The layout of each object in this cache's slabs,
+    /// which is large and aligned enough to hold a pointer to the next free object.
+    object_layout: Layout,
+    /// One magazine per CPU, indexed by CPU ID, each of which is created upon its first use.
+    magazines: [Once<Box<IrqSafeMutex<Magazine>>>; MAX_MAGAZINES],
+    depot: IrqSafeMutex<Depot>,
+}
+
+/// A fixed-size stack of free objects that belongs to a single CPU.
+struct Magazine {
+    objects: [usize; MAGAZINE_SIZE],
+    count: usize,
+}
+
+/// The free objects and slabs shared by all CPUs.
+struct Depot {
+    /// The address of the first free object, which holds the address of the next free object, and so on.
+    /// This is `0` if there are no free objects in the depot.
+    free_list: usize,
+    free_count: usize,
+    slabs: Vec<MappedPages>,
+    total_objects: usize,
+}
+
+/// Statistics about the memory usage of an [`ObjectCache`].
+#[derive(Debug, Clone, Copy)]
+pub struct ObjectCacheStats {
+    /// The name of the cache.
+    pub name: &'static str,
+    /// The size in bytes of each object, including padding.
+    pub object_size: usize,
+    /// The number of slabs allocated by the cache.
+    pub slabs: usize,
+    /// The total number of objects in all slabs, whether allocated or free.
+    pub total_objects: usize,
+    /// The number of free objects in the depot, not including those in per-CPU magazines.
+    pub free_objects_in_depot: usize,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_MAGAZINE: Once<Box<IrqSafeMutex<Magazine>>> = Once::new();
+
+impl ObjectCache {
+    /// Creates a new empty cache with the given `name` for objects of the given `layout`.
+    ///
+    /// No memory is allocated until the first object is allocated.
+    pub const fn new(name: &'static str, layout: Layout) -> ObjectCache {
+        let align = if layout.align() > size_of::<usize>() { layout.align() } else { size_of::<usize>() };
+        let size = if layout.size() > size_of::<usize>() { layout.size() } else { size_of::<usize>() };
+        let object_layout = match Layout::from_size_align(size.next_multiple_of(align), align) {
+            Ok(layout) => layout,
+            Err(_) => panic!("ObjectCache::new(): invalid object layout"),
+        };
+        ObjectCache {
+            name,
+            object_layout,
+            magazines: [NO_MAGAZINE; MAX_MAGAZINES],
+            depot: IrqSafeMutex::new(Depot { free_list: 0, free_count: 0, slabs: Vec::new(), total_objects: 0 }),
+        }
+    }
+
+    /// Creates a new empty cache with the given `name` for objects of type `T`,
+    /// e.g., for use with `Box::new_in()`.
+    pub const fn for_type<T>(name: &'static str) -> ObjectCache {
+        ObjectCache::new(name, Layout::new::<T>())
+    }
+
+    /// Creates a new empty cache with the given `name` for objects of type `Arc<T>`,
+    /// which must also hold the `Arc`'s strong and weak reference counts, for use with `Arc::new_in()`.
+    pub const fn for_arc<T>(name: &'static str) -> ObjectCache {
+        let counts_size = 2 * size_of::<usize>();
+        let align = if align_of::<T>() > align_of::<usize>() { align_of::<T>() } else { align_of::<usize>() };
+        match Layout::from_size_align(counts_size.next_multiple_of(align_of::<T>()) + size_of::<T>(), align) {
+            Ok(layout) => ObjectCache::new(name, layout),
+            Err(_) => panic!("ObjectCache::for_arc(): invalid object layout"),
+        }
+    }
+
+    /// Returns the name of this cache.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the layout of each object allocated from this cache.
+    pub fn object_layout(&self) -> Layout {
+        self.object_layout
+    }
+
+    /// Returns statistics about this cache's memory usage.
+    pub fn stats(&self) -> ObjectCacheStats {
+        let depot = self.depot.lock();
+        ObjectCacheStats {
+            name: self.name,
+            object_size: self.object_layout.size(),
+            slabs: depot.slabs.len(),
+            total_objects: depot.total_objects,
+            free_objects_in_depot: depot.free_count,
+        }
+    }
+
+    /// Returns the magazine of the current CPU, creating it if necessary.
+    ///
+    /// Returns `None` if the current CPU's ID is too high to have a magazine,
+    /// in which case it must allocate directly from the depot.
+    fn current_magazine(&self) -> Option<&IrqSafeMutex<Magazine>> {
+        let magazine = self.magazines.get(cpu::current_cpu().value() as usize)?;
+        Some(magazine.call_once(|| Box::new(IrqSafeMutex::new(Magazine { objects: [0; MAGAZINE_SIZE], count: 0 }))))
+    }
+
+    /// Allocates one object, preferably from the current CPU's magazine.
+    fn allocate_object(&self) -> Option<NonNull<u8>> {
+        let object = match self.current_magazine() {
+            Some(magazine) => {
+                let mut magazine = magazine.lock();
+                if magazine.count == 0 {
+                    // Refill half of the magazine, such that the next few frees don't immediately overflow it.
+                    let mut depot = self.depot.lock();
+                    while magazine.count < MAGAZINE_SIZE / 2 {
+                        let Some(object) = depot.pop(self.object_layout) else { break };
+                        let count = magazine.count;
+                        magazine.objects[count] = object;
+                        magazine.count += 1;
+                    }
+                }
+                if magazine.count == 0 {
+                    return None;
+                }
+                magazine.count -= 1;
+                magazine.objects[magazine.count]
+            }
+            None => self.depot.lock().pop(self.object_layout)?,
+        };
+        NonNull::new(object as *mut u8)
+    }
+
+    /// Frees the given object, preferably into the current CPU's magazine.
+    fn free_object(&self, object: NonNull<u8>) {
+        let object = object.as_ptr() as usize;
+        match self.current_magazine() {
+            Some(magazine) => {
+                let mut magazine = magazine.lock();
+                if magazine.count == MAGAZINE_SIZE {
+                    // Return half of the magazine to the depot, such that other CPUs can reuse those objects.
+                    let mut depot = self.depot.lock();
+                    while magazine.count > MAGAZINE_SIZE / 2 {
+                        magazine.count -= 1;
+                        depot.push(magazine.objects[magazine.count]);
+                    }
+                }
+                let count = magazine.count;
+                magazine.objects[count] = object;
+                magazine.count += 1;
+            }
+            None => self.depot.lock().push(object),
+        }
+    }
+
+    /// Returns `true` if an allocation with the given `layout` can be served by this cache.
+    fn fits(&self, layout: Layout) -> bool {
+        layout.size() <= self.object_layout.size() && layout.align() <= self.object_layout.align()
+    }
+}
+
+impl Depot {
+    /// Removes a free object from the depot, allocating a new slab if necessary.
+    fn pop(&mut self, object_layout: Layout) -> Option<usize> {
+        if self.free_list == 0 {
+            self.grow(object_layout).ok()?;
+        }
+        let object = self.free_list;
+        // SAFE: every object in the free list is a free object within one of our slabs,
+        //       the start of which holds the address of the next free object.
+        self.free_list = unsafe { *(object as *const usize) };
+        self.free_count -= 1;
+        Some(object)
+    }
+
+    /// Adds the given free object to the depot.
+    fn push(&mut self, object: usize) {
+        // SAFE: the object is free, so we can store the address of the next free object in it.
+        unsafe { *(object as *mut usize) = self.free_list; }
+        self.free_list = object;
+        self.free_count += 1;
+    }
+
+    /// Allocates a new slab and adds all of its objects to the depot.
+    fn grow(&mut self, object_layout: Layout) -> Result<(), &'static str> {
+        if object_layout.align() > PAGE_SIZE {
+            return Err("ObjectCache: objects cannot be aligned to more than a page");
+        }
+        let slab_size = (object_layout.size() * MIN_OBJECTS_PER_SLAB).max(MIN_SLAB_SIZE);
+        let slab = memory::create_mapping(slab_size, PteFlags::new().valid(true).writable(true))?;
+        let start = slab.start_address().value();
+        let num_objects = slab.size_in_bytes() / object_layout.size();
+        // Push the objects in reverse order, such that they're allocated in increasing address order.
+        for i in (0..num_objects).rev() {
+            self.push(start + i * object_layout.size());
+        }
+        self.total_objects += num_objects;
+        self.slabs.push(slab);
+        Ok(())
+    }
+}
+
+unsafe impl Allocator for ObjectCache {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if !self.fits(layout) {
+            return Global.allocate(layout);
+        }
+        let object = self.allocate_object().ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(object, self.object_layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        // A given layout always either fits or doesn't, so this matches how `ptr` was allocated.
+        if !self.fits(layout) {
+            return Global.deallocate(ptr, layout);
+        }
+        self.free_object(ptr);
+    }
+}
@@ -0,0 +1,269 @@
+//! Incremental checkpointing of task stacks and crate data using page-table dirty bits.
+//!
+//! A [`CheckpointTracker`] tracks a set of memory regions, e.g., a task's kernel stack
+//! or the `.data`/`.bss` pages of a crate.
+//! The first call to [`CheckpointTracker::checkpoint()`] copies every page of every region,
+//! while each later call only copies the pages whose `DIRTY` bit is set,
+//! i.e., the pages that have been written to since the previous checkpoint.
+//! Each checkpoint clears the `DIRTY` bits of the pages it copies,
+//! which is what allows the next checkpoint to be incremental;
+//! see [`MappedPages::take_dirty_pages()`].
+//!
+//! Applying a full checkpoint followed by every later incremental checkpoint, in order,
+//! reconstructs the contents of all tracked regions at the time of the last checkpoint.
+//!
+//! This also supports pre-copy migration via [`CheckpointTracker::precopy()`],
+//! which repeatedly sends checkpoints while the tracked task continues running,
+//! until few enough pages are dirtied between rounds that the task can be stopped
+//! and its remaining dirty pages copied in one final checkpoint.
+//!
+//! In addition, [`CheckpointTracker::sample_working_set()`] uses the `ACCESSED` bits
+//! of the tracked pages to count how many of them were used since the previous sample.
+//!
+//! [`MappedPages::take_dirty_pages()`]: memory::MappedPages::take_dirty_pages
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use crate_metadata::StrongCrateRef;
+use kernel_config::memory::PAGE_SIZE;
+use log::{debug, warn};
+use memory::MappedPages;
+use spin::Mutex;
+use task::TaskRef;
+
+/// A memory region whose pages are tracked by a [`CheckpointTracker`].
+enum Region {
+    /// The kernel stack of a task.
+    TaskStack(TaskRef),
+    /// An arbitrary mapping, e.g., the data pages of a crate.
+    Mapping(Arc<Mutex<MappedPages>>),
+}
+
+impl Region {
+    /// Invokes the given `func` with the `MappedPages` backing this region.
+    fn with_pages<R>(&self, func: impl FnOnce(&mut MappedPages) -> R) -> R {
+        match self {
+            Region::TaskStack(task) => task.with_kstack_mut(|stack| func(stack)),
+            Region::Mapping(mp) => func(&mut mp.lock()),
+        }
+    }
+}
+
+/// Whether a [`Checkpoint`] contains every page of the tracked regions or only the modified ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckpointKind {
+    /// Every page of every tracked region.
+    Full,
+    /// Only the pages written to since the previous checkpoint.
+    Incremental,
+}
+
+/// The contents of one page of a tracked region at the time of a checkpoint.
+pub struct PageCopy {
+    /// The index of the tracked region that this page belongs to,
+    /// in the order in which regions were added to the [`CheckpointTracker`].
+    pub region: usize,
+    /// The index of this page within its region.
+    pub page_index: usize,
+    /// The contents of the page.
+    pub contents: Box<[u8]>,
+}
+
+/// A snapshot of the pages of the regions tracked by a [`CheckpointTracker`].
+pub struct Checkpoint {
+    /// The sequence number of this checkpoint, starting at `0` for the first (full) checkpoint.
+    pub sequence: u64,
+    /// Whether this checkpoint is full or incremental.
+    pub kind: CheckpointKind,
+    /// The copied pages.
+    pub pages: Vec<PageCopy>,
+}
+
+impl Checkpoint {
+    /// Returns the total size in bytes of the pages in this checkpoint.
+    pub fn size_in_bytes(&self) -> usize {
+        self.pages.len() * PAGE_SIZE
+    }
+}
+
+/// Tracks which pages of a set of memory regions have changed in order to take incremental checkpoints.
+///
+/// See the [crate-level documentation](crate) for more.
+pub struct CheckpointTracker {
+    regions: Vec<Region>,
+    /// The sequence number of the next checkpoint.
+    next_sequence: u64,
+}
+
+impl CheckpointTracker {
+    /// Creates a new tracker that doesn't yet track any memory regions.
+    pub fn new() -> CheckpointTracker {
+        CheckpointTracker { regions: Vec::new(), next_sequence: 0 }
+    }
+
+    /// Tracks the kernel stack of the given task.
+    ///
+    /// Returns the index of the new region.
+    pub fn track_task_stack(&mut self, task: TaskRef) -> Result<usize, &'static str> {
+        self.add_region(Region::TaskStack(task))
+    }
+
+    /// Tracks the `.data` and `.bss` pages of the given crate, if it has any.
+    ///
+    /// Returns the index of the new region, or `None` if the crate has no data pages.
+    pub fn track_crate_data(&mut self, crate_ref: &StrongCrateRef) -> Result<Option<usize>, &'static str> {
+        let data_pages = crate_ref.lock_as_ref().data_pages.as_ref().map(|(mp, _)| mp.clone());
+        data_pages.map(|mp| self.add_region(Region::Mapping(mp))).transpose()
+    }
+
+    /// Tracks the given mapping.
+    ///
+    /// Returns the index of the new region.
+    pub fn track_mapping(&mut self, mapped_pages: Arc<Mutex<MappedPages>>) -> Result<usize, &'static str> {
+        self.add_region(Region::Mapping(mapped_pages))
+    }
+
+    fn add_region(&mut self, region: Region) -> Result<usize, &'static str> {
+        if self.next_sequence != 0 {
+            return Err("page_checkpoint: regions must be tracked before the first checkpoint is taken");
+        }
+        self.regions.push(region);
+        Ok(self.regions.len() - 1)
+    }
+
+    /// Returns the number of regions tracked by this tracker.
+    pub fn num_regions(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Takes a checkpoint of all tracked regions.
+    ///
+    /// The first checkpoint is a [`CheckpointKind::Full`] checkpoint;
+    /// every later one is [`CheckpointKind::Incremental`] and contains only the pages
+    /// that have been written to since the previous checkpoint.
+    ///
+    /// A page that is written to while it's being copied may contain a torn value in this checkpoint,
+    /// but will be marked as dirty again and thus included in the next checkpoint.
+    pub fn checkpoint(&mut self) -> Result<Checkpoint, &'static str> {
+        let kernel_mmi_ref = memory::get_kernel_mmi_ref().ok_or("page_checkpoint: kernel MMI was not initialized")?;
+        let kind = if self.next_sequence == 0 { CheckpointKind::Full } else { CheckpointKind::Incremental };
+
+        let mut pages = Vec::new();
+        for (region_index, region) in self.regions.iter().enumerate() {
+            region.with_pages(|mp| -> Result<(), &'static str> {
+                // Always harvest the dirty bits, even for a full checkpoint,
+                // such that the next checkpoint only includes pages modified after this one.
+                let dirty = mp.take_dirty_pages(&mut kernel_mmi_ref.lock().page_table)?;
+                let page_indices: Vec<usize> = match kind {
+                    CheckpointKind::Full => (0 .. mp.size_in_pages()).collect(),
+                    CheckpointKind::Incremental => dirty,
+                };
+                for page_index in page_indices {
+                    let contents: &[u8] = mp.as_slice(page_index * PAGE_SIZE, PAGE_SIZE)?;
+                    pages.push(PageCopy { region: region_index, page_index, contents: contents.into() });
+                }
+                Ok(())
+            })?;
+        }
+
+        let checkpoint = Checkpoint { sequence: self.next_sequence, kind, pages };
+        self.next_sequence += 1;
+        debug!("page_checkpoint: took {:?} checkpoint {} of {} pages",
+            checkpoint.kind, checkpoint.sequence, checkpoint.pages.len(),
+        );
+        Ok(checkpoint)
+    }
+
+    /// Performs the pre-copy phase of migrating the tracked regions.
+    ///
+    /// This repeatedly takes a checkpoint and passes it to `send`, while the tracked tasks continue running.
+    /// It stops once a round's checkpoint contains at most `dirty_page_threshold` pages,
+    /// or after `max_rounds` rounds, whichever comes first.
+    ///
+    /// Afterwards, the caller should stop the tracked tasks and take one final [`checkpoint()`]
+    /// containing the pages dirtied during the last round.
+    ///
+    /// Returns the number of rounds performed.
+    ///
+    /// [`checkpoint()`]: CheckpointTracker::checkpoint
+    pub fn precopy<F>(
+        &mut self,
+        dirty_page_threshold: usize,
+        max_rounds: usize,
+        mut send: F,
+    ) -> Result<usize, &'static str>
+        where F: FnMut(Checkpoint) -> Result<(), &'static str>
+    {
+        for round in 1 ..= max_rounds {
+            let checkpoint = self.checkpoint()?;
+            let num_pages = checkpoint.pages.len();
+            let kind = checkpoint.kind;
+            send(checkpoint)?;
+            if kind == CheckpointKind::Incremental && num_pages <= dirty_page_threshold {
+                return Ok(round);
+            }
+        }
+        warn!("page_checkpoint: pre-copy didn't converge below {} dirty pages within {} rounds",
+            dirty_page_threshold, max_rounds,
+        );
+        Ok(max_rounds)
+    }
+
+    /// Writes the pages of the given checkpoints back into the tracked regions, in order.
+    ///
+    /// To restore the state at the time of a given checkpoint, `checkpoints` must start with
+    /// the full checkpoint and include every later incremental checkpoint up to the given one.
+    ///
+    /// The tasks whose stacks are tracked must not be running.
+    pub fn restore(&mut self, checkpoints: &[Checkpoint]) -> Result<(), &'static str> {
+        if checkpoints.first().map(|c| c.kind) != Some(CheckpointKind::Full) {
+            return Err("page_checkpoint: restoring requires a full checkpoint first");
+        }
+        if checkpoints.windows(2).any(|w| w[1].sequence != w[0].sequence + 1) {
+            return Err("page_checkpoint: checkpoints to restore must be consecutive");
+        }
+        for region in &self.regions {
+            if let Region::TaskStack(task) = region {
+                if task.is_running() {
+                    return Err("page_checkpoint: can't restore the stack of a running task");
+                }
+            }
+        }
+
+        for checkpoint in checkpoints {
+            for page in &checkpoint.pages {
+                let region = self.regions.get(page.region)
+                    .ok_or("page_checkpoint: checkpoint contains a page of an untracked region")?;
+                region.with_pages(|mp| -> Result<(), &'static str> {
+                    mp.as_slice_mut(page.page_index * PAGE_SIZE, PAGE_SIZE)?
+                        .copy_from_slice(&page.contents);
+                    Ok(())
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of tracked pages that have been read from or written to
+    /// since the previous call to this function, and clears their `ACCESSED` bits.
+    ///
+    /// Calling this periodically yields an estimate of the size of the tracked regions' working set.
+    /// The first call returns the total number of tracked pages.
+    pub fn sample_working_set(&mut self) -> Result<usize, &'static str> {
+        let kernel_mmi_ref = memory::get_kernel_mmi_ref().ok_or("page_checkpoint: kernel MMI was not initialized")?;
+        let mut accessed = 0;
+        for region in &self.regions {
+            accessed += region.with_pages(|mp| mp.take_accessed_pages(&mut kernel_mmi_ref.lock().page_table))?.len();
+        }
+        Ok(accessed)
+    }
+}
+
+impl Default for CheckpointTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
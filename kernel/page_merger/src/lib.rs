@@ -0,0 +1,143 @@
+//! A background task that reclaims memory by merging identical read-only pages of loaded crates.
+//!
+//! When the same crate is loaded separately into multiple namespaces, e.g., into the
+//! per-application namespaces of several applications, each copy of that crate has its own
+//! `.text` and `.rodata` pages, many of which have identical contents.
+//! The merger task spawned by [`start()`] periodically [`scan()`]s the crates of all namespaces
+//! in use by any task and merges each read-only page with an identical page from another mapping,
+//! such that both share a single frame copy-on-write; see [`MappedPages::merge_page()`].
+//!
+//! Pages are first grouped by a hash of their contents, and a merge only takes place
+//! if the full contents of both pages are equal.
+//! Mappings that are currently locked by another task (e.g., because their crate is being loaded
+//! or swapped) are skipped and will be considered again during the next scan.
+//!
+//! Merging is opt-in: the captain only starts the merger task if Theseus is built with
+//! the `page_merging` cfg option, e.g., `THESEUS_CONFIG="page_merging"`.
+//!
+//! [`MappedPages::merge_page()`]: memory::MappedPages::merge_page
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+use kernel_config::memory::PAGE_SIZE;
+use log::{info, warn};
+use memory::MappedPages;
+use mod_mgmt::CrateNamespace;
+use spin::Mutex;
+use task::JoinableTaskRef;
+use time::Duration;
+
+/// How often the merger task scans all crates.
+pub const SCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Whether the merger task merges pages when it wakes up.
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Enables or disables same-page merging by the merger task.
+///
+/// Disabling it doesn't unmerge pages that were already merged.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether same-page merging by the merger task is enabled.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Scans the read-only pages of all crates in every namespace used by any task,
+/// and merges identical pages.
+///
+/// Returns the number of pages that were newly merged.
+pub fn scan() -> Result<usize, &'static str> {
+    let kernel_mmi_ref = memory::get_kernel_mmi_ref().ok_or("page_merger: kernel MMI was not initialized")?;
+    let mappings = read_only_mappings();
+
+    // The first page found with a given hash, as an index into `mappings` and a page index within it.
+    let mut first_pages: BTreeMap<u64, (usize, usize)> = BTreeMap::new();
+    let mut merged = 0;
+    for (i, mapping) in mappings.iter().enumerate() {
+        let Some(mut pages) = mapping.try_lock() else { continue };
+        if pages.flags().is_writable() {
+            continue;
+        }
+        for page_index in 0 .. pages.size_in_pages() {
+            let hash = fnv1a::hash(pages.as_slice(page_index * PAGE_SIZE, PAGE_SIZE)?);
+            let (j, other_page_index) = *first_pages.entry(hash).or_insert((i, page_index));
+            if j == i {
+                continue;
+            }
+            // Use `try_lock()` throughout, as other tasks may acquire these locks in a different order.
+            let Some(mut other_pages) = mappings[j].try_lock() else { continue };
+            let Some(mut kernel_mmi) = kernel_mmi_ref.try_lock() else { continue };
+            match pages.merge_page(page_index, &mut other_pages, other_page_index, &mut kernel_mmi.page_table) {
+                Ok(true) => merged += 1,
+                Ok(false) => { }
+                Err(e) => warn!("page_merger: failed to merge page {} of {:?}: {}", page_index, *pages, e),
+            }
+        }
+    }
+    Ok(merged)
+}
+
+/// Returns the `.text` and `.rodata` mappings of all crates in every namespace used by any task,
+/// without duplicates.
+fn read_only_mappings() -> Vec<Arc<Mutex<MappedPages>>> {
+    let mut namespaces: Vec<Arc<CrateNamespace>> = Vec::new();
+    for (_id, weak_task) in task::all_tasks() {
+        let Some(task) = weak_task.upgrade() else { continue };
+        let namespace = task.get_namespace();
+        if !namespaces.iter().any(|n| Arc::ptr_eq(n, namespace)) {
+            namespaces.push(Arc::clone(namespace));
+        }
+    }
+
+    let mut mappings: Vec<Arc<Mutex<MappedPages>>> = Vec::new();
+    for namespace in &namespaces {
+        namespace.for_each_crate(true, |_crate_name, crate_ref| {
+            let krate = crate_ref.lock_as_ref();
+            for (pages, _range) in krate.text_pages.iter().chain(krate.rodata_pages.iter()) {
+                // Many crates, e.g., those in the base kernel image, share the same mapping.
+                if !mappings.iter().any(|m| Arc::ptr_eq(m, pages)) {
+                    mappings.push(Arc::clone(pages));
+                }
+            }
+            true
+        });
+    }
+    mappings
+}
+
+/// Spawns the page merger task, which runs forever.
+///
+/// This should only be called once.
+pub fn start() -> Result<JoinableTaskRef, &'static str> {
+    spawn::new_task_builder(page_merger, ())
+        .name("page_merger".into())
+        .spawn()
+}
+
+/// The entry point for the page merger task.
+fn page_merger(_: ()) -> Result<(), &'static str> {
+    info!("page_merger task started");
+    loop {
+        sleep::sleep(SCAN_INTERVAL).map_err(|_| "page_merger: failed to sleep")?;
+        if !is_enabled() {
+            continue;
+        }
+        match scan() {
+            Ok(0) => { }
+            Ok(newly_merged) => {
+                let stats = memory::merged_page_stats();
+                info!("page_merger: merged {} pages; {} pages now share {} frames, saving {} KiB",
+                    newly_merged, stats.merged_pages, stats.merged_frames, stats.bytes_saved() / 1024,
+                );
+            }
+            Err(e) => warn!("page_merger: scan failed: {}", e),
+        }
+    }
+}
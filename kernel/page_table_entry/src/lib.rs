@@ -63,6 +63,22 @@ impl PageTableEntry {
         }
     }
 
+    /// Clears the `EXCLUSIVE` flag of this page table entry without unmapping it.
+    ///
+    /// If the frame(s) pointed to by this entry were mapped exclusively,
+    /// this returns those frames, such that the caller becomes responsible for them,
+    /// e.g., in order to share them with other page table entries.
+    /// Unlike unmapping and remapping the entry, this never leaves the entry briefly unmapped.
+    pub fn set_non_exclusive(&mut self) -> Option<UnmappedFrameRange> {
+        let flags = self.flags();
+        if !flags.is_exclusive() {
+            return None;
+        }
+        let frame = self.frame_value();
+        self.set_flags(flags.exclusive(false));
+        Some(UnmappedFrameRange(FrameRange::new(frame, frame)))
+    }
+
     /// Returns this `PageTableEntry`'s flags.
     pub fn flags(&self) -> PteFlagsArch {
         PteFlagsArch::from_bits_truncate(self.0 & !PTE_FRAME_MASK)
@@ -92,7 +92,6 @@ extern "C" fn rust_eh_personality() -> ! {
 /// that invokes the real `unwind_resume()` function in the `unwind` crate, 
 /// but does so dynamically in loadable mode.
 #[no_mangle]
-#[cfg(target_arch = "x86_64")]
 extern "C" fn _Unwind_Resume(arg: usize) -> ! {
     #[cfg(not(loadable))] {
         unwind::unwind_resume(arg)
@@ -119,12 +118,6 @@ extern "C" fn _Unwind_Resume(arg: usize) -> ! {
     }
 }
 
-#[no_mangle]
-#[cfg(target_arch = "aarch64")]
-extern "C" fn _Unwind_Resume(_arg: usize) -> ! {
-    todo!("_Unwind_Resume is not yet implemented on aarch64")
-}
-
 /// This is the callback entry point that gets invoked when the heap allocator runs out of memory.
 #[alloc_error_handler]
 #[cfg(not(test))]
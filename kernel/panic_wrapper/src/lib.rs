@@ -7,7 +7,7 @@
 
 extern crate alloc;
 
-use core::panic::PanicInfo;
+use core::{panic::PanicInfo, sync::atomic::{AtomicBool, Ordering}};
 use log::{debug, trace};
 use fault_log::log_panic_entry;
 use task::{KillReason, PanicInfoOwned};
@@ -15,6 +15,25 @@ use task::{KillReason, PanicInfoOwned};
 #[cfg(target_arch = "x86_64")]
 use log::{error, warn};
 
+/// Whether DWARF-based stack traces resolve each call site to its source code location.
+static SOURCE_LOCATIONS: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether DWARF-based stack traces printed upon a panic include the source code location
+/// of each call site, which is disabled by default.
+///
+/// Resolving source code locations loads and relocates the debug sections of each crate in the stack trace
+/// from within the panic handler, which allocates memory, acquires locks, and may fault again.
+/// It should therefore only be enabled while debugging, e.g., via the `backtrace_lines` application.
+pub fn set_source_locations_in_backtraces(enable: bool) {
+    SOURCE_LOCATIONS.store(enable, Ordering::Relaxed);
+}
+
+/// Returns whether DWARF-based stack traces printed upon a panic include the source code location
+/// of each call site.
+pub fn source_locations_in_backtraces() -> bool {
+    SOURCE_LOCATIONS.load(Ordering::Relaxed)
+}
+
 /// Performs the standard panic handling routine, which involves the following:
 /// 
 /// * Invoking the current `Task`'s `kill_handler` routine, if it has registered one.
@@ -33,14 +52,22 @@ pub fn panic_wrapper(panic_info: &PanicInfo) -> Result<(), &'static str> {
         // By default, we use DWARF-based debugging stack traces
         #[cfg(not(frame_pointers))] {
             error!("------------------ Stack Trace (DWARF) ---------------------------");
+            // Resolves call sites to source lines, loading each crate's debug sections at most once.
+            let mut addr2line = SOURCE_LOCATIONS.load(Ordering::Relaxed).then(debug_info::Addr2Line::new);
             stack_trace::stack_trace(
                 &mut |stack_frame, stack_frame_iter| {
+                    let call_site = memory::VirtualAddress::new_canonical(stack_frame.call_site_address() as usize);
                     let symbol_offset = stack_frame_iter.namespace().get_section_containing_address(
-                        memory::VirtualAddress::new_canonical(stack_frame.call_site_address() as usize),
+                        call_site,
                         false
                     ).map(|(sec, offset)| (sec.name.clone(), offset));
                     if let Some((symbol_name, offset)) = symbol_offset {
                         error!("  {:>#018X} in {} + {:#X}", stack_frame.call_site_address(), symbol_name, offset);
+                        let location = addr2line.as_mut()
+                            .and_then(|addr2line| addr2line.lookup(stack_frame_iter.namespace(), call_site));
+                        if let Some(location) = location {
+                            error!("{:>22} at {}", "", location);
+                        }
                     } else {
                         error!("  {:>#018X} in ??", stack_frame.call_site_address());
                     }
@@ -96,11 +123,7 @@ pub fn panic_wrapper(panic_info: &PanicInfo) -> Result<(), &'static str> {
         debug!("No kill handler callback in Task {:?}", task::get_my_current_task());
     }
 
-    // Start the unwinding process. Not yet supported on aarch64
-    #[cfg(not(target_arch = "x86_64"))] {
-        Err("Unwinding is currently only supported on x86_64")
-    }
-    #[cfg(target_arch = "x86_64")]
+    // Start the unwinding process.
     {
         let cause = KillReason::Panic(PanicInfoOwned::from(panic_info));
         match unwind::start_unwinding(cause, 5) {
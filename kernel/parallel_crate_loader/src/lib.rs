@@ -0,0 +1,203 @@
+//! Loads multiple crates concurrently by parsing and copying their sections on multiple CPUs.
+//!
+//! [`load_crates_parallel()`] is a drop-in replacement for [`CrateNamespace::load_crates()`].
+//! It spawns one loader task per CPU (up to the number of crates), each pinned to a different CPU.
+//! The loader tasks take crates from a shared work queue, largest object files first,
+//! and perform the first phase of loading each one via [`CrateNamespace::load_crate_sections_only()`].
+//! Once all loader tasks have finished, the calling task adds all of the crates' symbols
+//! and relocates them, one after the other, via [`CrateNamespace::link_partially_loaded_crates()`].
+//! Finally, the CLS data image of every CPU is regenerated, as the crates' CLS sections
+//! may be accessed from any CPU.
+//!
+//! After [`enable()`], [`CrateNamespace::load_crates()`] itself loads crates this way
+//! for every registered namespace.
+//!
+//! If loading any crate fails, none of the crates are added to the namespace.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    collections::VecDeque,
+    format,
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+use fs_node::FileRef;
+use log::{debug, error};
+use memory::MmiRef;
+use mod_mgmt::{CrateNamespace, LoadError, ParallelLoadHooks, PartiallyLoadedCrate};
+use spin::Mutex;
+use task::{ExitValue, JoinableTaskRef};
+
+/// The crates that have yet to be loaded, along with their index in the original list of crates.
+type WorkQueue = Arc<Mutex<VecDeque<(usize, FileRef)>>>;
+
+/// The result of a single loader task: each crate it loaded, along with its index in the original list of crates.
+type LoaderResults = Vec<(usize, Result<PartiallyLoadedCrate, LoadError>)>;
+
+/// Whether this crate's hooks are registered with `mod_mgmt`.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Makes [`CrateNamespace::load_crates()`] load crates on multiple CPUs
+/// for all registered namespaces, and regenerate the CLS data image of every CPU afterwards.
+pub fn enable() {
+    mod_mgmt::set_parallel_load_hooks(Some(ParallelLoadHooks {
+        load_sections: load_sections_parallel,
+        reload_cls: reload_cls_on_all_cpus,
+    }));
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Reverts [`CrateNamespace::load_crates()`] to loading crates on the current CPU.
+pub fn disable() {
+    ENABLED.store(false, Ordering::Release);
+    mod_mgmt::set_parallel_load_hooks(None);
+}
+
+/// Loads the given crates into the given `namespace`, distributing the parsing
+/// and copying of their sections across multiple CPUs.
+///
+/// The arguments and behavior are the same as [`CrateNamespace::load_crates()`],
+/// except that this also works for namespaces that aren't registered.
+pub fn load_crates_parallel(
+    namespace: &Arc<CrateNamespace>,
+    crate_files: &[FileRef],
+    temp_backup_namespace: Option<&CrateNamespace>,
+    kernel_mmi_ref: &MmiRef,
+    verbose_log: bool,
+) -> Result<(), LoadError> {
+    let crates = load_sections_parallel(namespace, crate_files, kernel_mmi_ref, verbose_log)?;
+    namespace.link_partially_loaded_crates(crates, temp_backup_namespace, kernel_mmi_ref, verbose_log)?;
+    // Without this crate's hooks, linking only regenerated the current CPU's CLS data image.
+    if !ENABLED.load(Ordering::Acquire) {
+        reload_cls_on_all_cpus();
+    }
+    Ok(())
+}
+
+/// Performs the first phase of loading the given crates on multiple CPUs,
+/// and returns the partially-loaded crates in the same order as `crate_files`.
+///
+/// If only one CPU or one crate is available, the crates are loaded on the current CPU.
+fn load_sections_parallel(
+    namespace: &Arc<CrateNamespace>,
+    crate_files: &[FileRef],
+    kernel_mmi_ref: &MmiRef,
+    verbose_log: bool,
+) -> Result<Vec<PartiallyLoadedCrate>, LoadError> {
+    let num_loaders = core::cmp::min(cpu::cpu_count() as usize, crate_files.len());
+    if num_loaders <= 1 {
+        return crate_files.iter()
+            .map(|file| namespace.load_crate_sections_only(file, kernel_mmi_ref, verbose_log))
+            .collect();
+    }
+
+    // Load the largest crates first, such that the loader tasks finish at roughly the same time.
+    let mut work: Vec<(usize, FileRef)> = crate_files.iter().cloned().enumerate().collect();
+    work.sort_by_key(|(_, file)| core::cmp::Reverse(file.lock().len()));
+    let queue: WorkQueue = Arc::new(Mutex::new(work.into()));
+
+    let mut loaders: Vec<JoinableTaskRef> = Vec::with_capacity(num_loaders);
+    for cpu in cpu::cpus().take(num_loaders) {
+        let args = (Arc::clone(namespace), Arc::clone(&queue), Arc::clone(kernel_mmi_ref), verbose_log);
+        let loader = spawn::new_task_builder(loader_task, args)
+            .name(format!("crate_loader_{cpu}"))
+            .pin_on_cpu(cpu)
+            .spawn();
+        match loader {
+            Ok(loader) => loaders.push(loader),
+            Err(e) => {
+                // The remaining loader tasks will load this loader's share of the crates.
+                error!("load_crates_parallel(): failed to spawn loader task on CPU {}: {}", cpu, e);
+            }
+        }
+    }
+
+    let mut loaded: Vec<(usize, PartiallyLoadedCrate)> = Vec::with_capacity(crate_files.len());
+    let mut first_error: Option<(usize, LoadError)> = None;
+    for loader in loaders {
+        let results = match loader.join()? {
+            ExitValue::Completed(value) => match value.downcast::<LoaderResults>() {
+                Ok(results) => *results,
+                Err(_) => return Err("BUG: load_crates_parallel(): loader task returned an unexpected value".into()),
+            },
+            ExitValue::Killed(reason) => {
+                error!("load_crates_parallel(): loader task was killed: {}", reason);
+                return Err("load_crates_parallel(): a loader task was killed".into());
+            }
+        };
+        for (index, result) in results {
+            match result {
+                Ok(partial) => loaded.push((index, partial)),
+                Err(e) if first_error.as_ref().map_or(true, |(i, _)| index < *i) => first_error = Some((index, e)),
+                Err(_) => { }
+            }
+        }
+    }
+
+    if let Some((_index, e)) = first_error {
+        // The other partially-loaded crates are dropped here, which unmaps them.
+        return Err(e);
+    }
+    // Crates that weren't loaded because every loader task failed to spawn.
+    if loaded.len() != crate_files.len() || !queue.lock().is_empty() {
+        return Err("load_crates_parallel(): not all crates were loaded".into());
+    }
+
+    // Return the crates in their original order, such that symbol conflicts are resolved as in `load_crates()`.
+    loaded.sort_by_key(|(index, _)| *index);
+    debug!("load_crates_parallel(): loaded sections of {} crates on {} CPUs", loaded.len(), num_loaders);
+    Ok(loaded.into_iter().map(|(_, partial)| partial).collect())
+}
+
+/// Regenerates the CLS data image of every CPU by running a task pinned to each other CPU.
+///
+/// This must be invoked after loading crates whose CLS sections may have been loaded on other CPUs.
+pub fn reload_cls_on_all_cpus() {
+    let current_cpu = cpu::current_cpu();
+    let mut reloaders = Vec::new();
+    for cpu in cpu::cpus().filter(|cpu| *cpu != current_cpu) {
+        let reloader = spawn::new_task_builder(reload_cls_task, ())
+            .name(format!("cls_reload_{cpu}"))
+            .pin_on_cpu(cpu)
+            .spawn();
+        match reloader {
+            Ok(reloader) => reloaders.push((cpu, reloader)),
+            Err(e) => error!("reload_cls_on_all_cpus(): failed to spawn reload task on CPU {}: {}", cpu, e),
+        }
+    }
+    cls_allocator::reload_current_cpu();
+    for (cpu, reloader) in reloaders {
+        if let Err(e) = reloader.join() {
+            error!("reload_cls_on_all_cpus(): failed to join reload task on CPU {}: {}", cpu, e);
+        }
+    }
+}
+
+/// The entry point of a task spawned by [`reload_cls_on_all_cpus()`].
+fn reload_cls_task(_: ()) {
+    cls_allocator::reload_current_cpu();
+}
+
+/// The entry point of a loader task, which loads the sections of crates from the `queue` until it is empty.
+fn loader_task(
+    (namespace, queue, kernel_mmi_ref, verbose_log): (Arc<CrateNamespace>, WorkQueue, MmiRef, bool),
+) -> LoaderResults {
+    let mut results = Vec::new();
+    loop {
+        let next = queue.lock().pop_front();
+        let Some((index, crate_file)) = next else { break };
+        let result = namespace.load_crate_sections_only(&crate_file, &kernel_mmi_ref, verbose_log);
+        let failed = result.is_err();
+        results.push((index, result));
+        if failed {
+            // Stop early, since the whole batch of crates will fail to load anyway.
+            queue.lock().clear();
+            break;
+        }
+    }
+    results
+}
@@ -287,6 +287,8 @@ impl Path {
     /// Returns the file or directory at the given path.
     ///
     /// The path can be relative or absolute.
+    /// Any directory along the path that has a filesystem mounted over it is
+    /// replaced with the root directory of that filesystem, as per [`vfs::resolve_mount()`].
     ///
     /// If the path does not point to a file system object, `None` is returned.
     #[inline]
@@ -297,7 +299,7 @@ impl Path {
                 iter.next();
                 root::get_root().clone()
             }
-            _ => cwd.clone(),
+            _ => vfs::resolve_mount(cwd.clone()),
         };
 
         while let Some(component) = iter.next() {
@@ -306,18 +308,24 @@ impl Path {
                 Component::CurDir => {}
                 Component::ParentDir => {
                     let temp = current.lock().get_parent_dir()?;
-                    current = temp;
+                    current = vfs::resolve_mount(temp);
                 }
                 Component::Normal(name) => {
                     if iter.peek().is_none() {
-                        return current.lock().get(name);
+                        let node = current.lock().get(name);
+                        return match node {
+                            Some(fs_node::FileOrDir::Dir(directory)) => {
+                                Some(fs_node::FileOrDir::Dir(vfs::resolve_mount(directory)))
+                            }
+                            node => node,
+                        };
                     } else {
                         let temp = match current.lock().get(name) {
                             Some(fs_node::FileOrDir::Dir(directory)) => directory,
                             // Path didn't exist or had a file in the middle e.g. /dir/file/dir
                             _ => return None,
                         };
-                        current = temp;
+                        current = vfs::resolve_mount(temp);
                     }
                 }
             }
@@ -355,7 +363,8 @@ impl Path {
     }
 
     // TODO: Move out of path crate.
-    /// Returns the file or directory at the given absolute path.
+    /// Returns the file or directory at the given absolute path,
+    /// crossing any mount points along the way.
     ///
     /// If the path does not point to a file system object or the path is
     /// relative, `None` is returned.
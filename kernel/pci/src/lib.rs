@@ -152,6 +152,8 @@ const PCI_COMMAND_INT_DISABLED: u16 = 1 << 10;
 #[repr(u8)]
 pub enum PciCapability {
     Msi  = 0x05,
+    /// A capability whose layout is defined by the device's vendor, e.g., virtio's configuration structures.
+    VendorSpecific = 0x09,
     Msix = 0x11,
 }
 
@@ -621,6 +623,43 @@ impl PciLocation {
         self.pci_write_16(PCI_COMMAND, new_value);
     }
 
+    /// Returns the offsets into the PCI config space of all instances of the requested capability,
+    /// in the order they appear in the capability list.
+    ///
+    /// Unlike `find_pci_capability()`, this is useful for capabilities
+    /// that a device may have multiple instances of, such as [`PciCapability::VendorSpecific`].
+    pub fn find_pci_capabilities(&self, pci_capability: PciCapability) -> Vec<u8> {
+        let pci_capability = pci_capability as u8;
+        let mut offsets = Vec::new();
+        const CAPABILITIES_VALID: u16 = 1 << 4;
+        if self.pci_read_16(PCI_STATUS) & CAPABILITIES_VALID == 0 {
+            return offsets;
+        }
+        let mut cap_addr = self.pci_read_8(PCI_CAPABILITIES) & 0xFC;
+        // Bound the number of iterations in case of a malformed (circular) capability list.
+        for _ in 0 .. 48 {
+            if cap_addr == 0 {
+                break;
+            }
+            if self.pci_read_8(PciRegister::from_offset(cap_addr, 1)) == pci_capability {
+                offsets.push(cap_addr);
+            }
+            cap_addr = self.pci_read_8(PciRegister::from_offset(cap_addr + 1, 1)) & 0xFC;
+        }
+        offsets
+    }
+
+    /// Reads the 32-bit value at the given `offset` into this device's PCI config space,
+    /// e.g., a field of a capability found via [`Self::find_pci_capabilities()`].
+    ///
+    /// The `offset` must be 4-byte aligned.
+    pub fn pci_read_config_u32(&self, offset: u8) -> Result<u32, &'static str> {
+        if offset & 0b11 != 0 {
+            return Err("pci_read_config_u32(): offset must be 4-byte aligned");
+        }
+        Ok(self.pci_read_32(PciRegister::from_offset(offset, 4)))
+    }
+
     /// Explores the PCI config space and returns address of requested capability, if present.
     /// PCI capabilities are stored as a linked list in the PCI config space,
     /// with each capability storing the pointer to the next capability right after its ID.
@@ -688,6 +727,36 @@ pub struct ModernInterruptSupport {
     pub msix: bool,
 }
 
+/// The layout of a device's MSI capability, returned by [`PciDevice::msi_capability()`].
+#[derive(Clone, Copy, Debug)]
+pub struct MsiCapability {
+    /// The offset of this capability in the PCI config space.
+    offset: u8,
+    /// The maximum number of vectors that the device can use, which is a power of two from 1 to 32.
+    pub max_vectors: u8,
+    /// `true` if the message address register is 64 bits wide.
+    pub is_64_bit: bool,
+    /// `true` if each vector can be masked individually.
+    pub per_vector_masking: bool,
+}
+
+/// The layout of a device's MSI-X capability, returned by [`PciDevice::msix_capability()`].
+#[derive(Clone, Copy, Debug)]
+pub struct MsixCapability {
+    /// The offset of this capability in the PCI config space.
+    offset: u8,
+    /// The number of entries in the device's vector table, from 1 to 2048.
+    pub table_size: u16,
+    /// The index of the BAR whose memory region contains the vector table.
+    pub table_bar: u8,
+    /// The offset of the vector table from the start of its BAR's memory region.
+    pub table_offset: u32,
+    /// The index of the BAR whose memory region contains the Pending Bit Array.
+    pub pba_bar: u8,
+    /// The offset of the Pending Bit Array from the start of its BAR's memory region.
+    pub pba_offset: u32,
+}
+
 /// Contains information common to every type of PCI Device,
 /// and offers functions for reading/writing to the PCI configuration space.
 ///
@@ -801,6 +870,35 @@ impl PciDevice {
         }
     }
 
+    /// Reads and returns the layout of this device's MSI capability, if it has one.
+    pub fn msi_capability(&self) -> Option<MsiCapability> {
+        let offset = self.find_pci_capability(PciCapability::Msi)?;
+        let ctrl = self.pci_read_16(PciRegister { index: offset >> 2, span: Word1 });
+        Some(MsiCapability {
+            offset,
+            max_vectors: 1 << ctrl.get_bits(1..4).min(5),
+            is_64_bit: ctrl.get_bit(7),
+            per_vector_masking: ctrl.get_bit(8),
+        })
+    }
+
+    /// Reads and returns the layout of this device's MSI-X capability, if it has one.
+    pub fn msix_capability(&self) -> Option<MsixCapability> {
+        let offset = self.find_pci_capability(PciCapability::Msix)?;
+        let msix_reg_index = offset >> 2;
+        let ctrl = self.pci_read_16(PciRegister { index: msix_reg_index, span: Word1 });
+        let table = self.pci_read_32(PciRegister { index: msix_reg_index + 1, span: FullDword });
+        let pba = self.pci_read_32(PciRegister { index: msix_reg_index + 2, span: FullDword });
+        Some(MsixCapability {
+            offset,
+            table_size: ctrl.get_bits(0..11) + 1,
+            table_bar: table.get_bits(0..3) as u8,
+            table_offset: table & !0b111,
+            pba_bar: pba.get_bits(0..3) as u8,
+            pba_offset: pba & !0b111,
+        })
+    }
+
     /// Enable MSI interrupts for a PCI device.
     /// We assume the device only supports one MSI vector 
     /// and set the interrupt number and core id for that vector.
@@ -814,11 +912,45 @@ impl PciDevice {
     ///
     /// This function panics if the MSI capability isn't aligned to 4 bytes
     pub fn pci_enable_msi(&self, core_id: u8, int_num: u8) -> Result<(), &'static str> {
-
         // find out if the device is msi capable
-        let cap_addr = self.find_pci_capability(PciCapability::Msi).ok_or("Device not MSI capable")?;
-        assert_eq!(cap_addr & 0b11, 0, "pci_enable_msi: Invalid MSI capability address alignment");
-        let msi_reg_index = cap_addr >> 2;
+        let msi = self.msi_capability().ok_or("Device not MSI capable")?;
+        assert_eq!(msi.offset & 0b11, 0, "pci_enable_msi: Invalid MSI capability address alignment");
+
+        self.pci_write_msi(&msi, core_id, int_num, 1);
+        Ok(())  
+    }
+
+    /// Allocates one interrupt number for each of the given `handlers`
+    /// and enables multiple-message MSI interrupts for this device,
+    /// all of which are delivered to the given `cpu`.
+    ///
+    /// The number of `handlers` must be a power of two no greater than
+    /// [`MsiCapability::max_vectors`], as MSI requires a contiguous, aligned block of interrupt numbers.
+    /// To route each interrupt to a different CPU, use MSI-X via [`PciDevice::pci_enable_msix_vectors()`].
+    ///
+    /// Returns the interrupt number assigned to `handlers[0]`;
+    /// the interrupt number of `handlers[i]` is that number plus `i`.
+    #[cfg(target_arch = "x86_64")]
+    pub fn pci_enable_msi_vectors(
+        &self,
+        cpu: CpuId,
+        handlers: &[InterruptHandler],
+    ) -> Result<InterruptNumber, &'static str> {
+        let msi = self.msi_capability().ok_or("Device not MSI capable")?;
+        if handlers.len() > msi.max_vectors as usize {
+            return Err("pci_enable_msi_vectors(): device doesn't support that many MSI vectors");
+        }
+        let base = interrupts::register_msi_interrupts(handlers)?;
+        self.pci_write_msi(&msi, cpu.into_u8(), base, handlers.len() as u8);
+        Ok(base)
+    }
+
+    /// Programs this device's MSI capability to send `num_vectors` interrupts to the given core,
+    /// starting at the interrupt number `base`, and then enables MSI.
+    ///
+    /// `num_vectors` must be a power of two, and `base` must be aligned to it.
+    fn pci_write_msi(&self, msi: &MsiCapability, core_id: u8, base: u8, num_vectors: u8) {
+        let msi_reg_index = msi.offset >> 2;
 
         // offset in the capability space where the message address register is located 
         const MESSAGE_ADDRESS_REGISTER_OFFSET: u8 = 1 /* one dword */;
@@ -838,27 +970,35 @@ impl PciDevice {
         };
         self.pci_write_32(msg_addr_reg, MEMORY_REGION | core);
 
-        // offset in the capability space where the message data register is located 
-        const MESSAGE_DATA_REGISTER_OFFSET: u8 = 3 /* dwords */;
-
-        // Set the interrupt number for the MSI in the Message Data Register
-        let msg_data_reg = PciRegister {
-            index: msi_reg_index + MESSAGE_DATA_REGISTER_OFFSET,
-            span: FullDword,
+        // the message data register follows the upper 32 bits of the message address, if present
+        let msg_data_index = if msi.is_64_bit {
+            self.pci_write_32(PciRegister { index: msi_reg_index + 2, span: FullDword }, 0);
+            msi_reg_index + 3
+        } else {
+            msi_reg_index + 2
         };
-        self.pci_write_32(msg_data_reg, int_num as u32);
+
+        // Set the interrupt number for the MSI in the Message Data Register.
+        // With multiple messages enabled, the device modifies the low bits of this number.
+        let msg_data_reg = PciRegister { index: msg_data_index, span: Word0 };
+        self.pci_write_16(msg_data_reg, base as u16);
+
+        // unmask all vectors, if the device supports masking them
+        if msi.per_vector_masking {
+            let mask_bits_reg = PciRegister { index: msg_data_index + 1, span: FullDword };
+            self.pci_write_32(mask_bits_reg, 0);
+        }
 
         // to enable the MSI capability, we need to set bit 0 of the message control register
         const MSI_ENABLE: u16 = 1;
 
-        // enable MSI in the Message Control Register
+        // set the number of enabled vectors (log2) and enable MSI in the Message Control Register
         // the message control register corresponds to bits [16:31] of the first dword
         let msg_ctrl_reg = PciRegister { index: msi_reg_index, span: Word1 };
         let mut ctrl = self.pci_read_16(msg_ctrl_reg);
+        ctrl.set_bits(4..7, num_vectors.trailing_zeros() as u16);
         ctrl |= MSI_ENABLE;
         self.pci_write_16(msg_ctrl_reg, ctrl);
-
-        Ok(())  
     }
 
     /// Enable MSI-X interrupts for a PCI device.
@@ -872,11 +1012,14 @@ impl PciDevice {
 
         // write to bit 15 of Message Control Register to enable MSI-X
         const MSIX_ENABLE: u16 = 1 << 15;
+        // the function mask bit masks all vectors regardless of their own mask bits
+        const MSIX_FUNCTION_MASK: u16 = 1 << 14;
 
         // the message control register corresponds to bits [16:31] of the first dword
         let msg_ctrl_reg = PciRegister { index: msix_reg_index, span: Word1 };
         let mut ctrl = self.pci_read_16(msg_ctrl_reg);
         ctrl |= MSIX_ENABLE;
+        ctrl &= !MSIX_FUNCTION_MASK;
         self.pci_write_16(msg_ctrl_reg, ctrl);
 
         // let ctrl = pci_read_16(msg_ctrl_reg, msix_reg_index);
@@ -887,38 +1030,70 @@ impl PciDevice {
 
     /// Returns the memory mapped msix vector table
     ///
+    /// Only the first `max_vectors` entries are mapped,
+    /// or fewer if the device's vector table is smaller than that.
+    ///
     /// - returns `Err("Device not MSI-X capable")` if the device doesn't have the MSI-X capability
     /// - returns `Err("Invalid BAR content")` if the Base Address Register contains an invalid address
     pub fn pci_mem_map_msix(&self, max_vectors: usize) -> Result<MsixVectorTable, &'static str> {
-        // retreive the address in the pci config space for the msi-x capability
-        let cap_addr = self.find_pci_capability(PciCapability::Msix).ok_or("Device not MSI-X capable")?;
-        assert_eq!(cap_addr & 0b11, 0, "pci_enable_msix: Invalid MSI-X capability address alignment");
-        let msix_reg_index = cap_addr >> 2;
-
-        // get physical address of vector table
-        const VECTOR_TABLE_OFFSET: u8 = 1;
-        let vector_table_reg = PciRegister {
-            index: msix_reg_index + VECTOR_TABLE_OFFSET,
-            span: FullDword,
-        };
-        let table_offset = self.pci_read_32(vector_table_reg);
-        let bar = table_offset & 0x7;
-        let offset = table_offset >> 3;
-        let addr = self.bars[bar as usize] + offset;
+        // retreive the location of the vector table from the msi-x capability
+        let msix = self.msix_capability().ok_or("Device not MSI-X capable")?;
+        let num_vectors = max_vectors.min(msix.table_size as usize);
 
         // find the memory base address and size of the area for the vector table
-        let mem_base = PhysicalAddress::new(addr as usize).ok_or("Invalid BAR content")?;
-        let mem_size_in_bytes = size_of::<MsixVectorEntry>() * max_vectors;
+        let bar_base = self.determine_mem_base(msix.table_bar as usize).map_err(|_| "Invalid BAR content")?;
+        let table_addr = PhysicalAddress::new(bar_base.value() + msix.table_offset as usize).ok_or("Invalid BAR content")?;
+        let offset_in_frame = table_addr.frame_offset();
+        let mem_base = PhysicalAddress::new_canonical(table_addr.value() - offset_in_frame);
+        let mem_size_in_bytes = offset_in_frame + size_of::<MsixVectorEntry>() * num_vectors;
 
-        // debug!("msi-x vector table bar: {}, base_address: {:#X} and size: {} bytes", bar, mem_base, mem_size_in_bytes);
+        // debug!("msi-x vector table bar: {}, base_address: {:#X} and size: {} bytes", msix.table_bar, mem_base, mem_size_in_bytes);
 
         let msix_mapped_pages = map_frame_range(mem_base, mem_size_in_bytes, MMIO_FLAGS)?;
-        let vector_table = BorrowedSliceMappedPages::from_mut(msix_mapped_pages, 0, max_vectors)
+        let vector_table = BorrowedSliceMappedPages::from_mut(msix_mapped_pages, offset_in_frame, num_vectors)
             .map_err(|(_mp, err)| err)?;
 
         Ok(MsixVectorTable::new(vector_table))
     }
 
+    /// Allocates an interrupt number for each of the given `vectors` and enables MSI-X interrupts for this device.
+    ///
+    /// Each element of `vectors` is a handler and the CPU to which its interrupt is delivered,
+    /// e.g., the CPU that processes the corresponding queue; entry `i` of the device's vector table
+    /// is assigned to `vectors[i]`.
+    ///
+    /// Returns the device's vector table and the interrupt numbers assigned to each of the given `vectors`.
+    /// The vector table must be kept alive for as long as its interrupts are in use.
+    #[cfg(target_arch = "x86_64")]
+    pub fn pci_enable_msix_vectors(
+        &self,
+        vectors: &[(InterruptHandler, CpuId)],
+    ) -> Result<(MsixVectorTable, Vec<InterruptNumber>), &'static str> {
+        let msix = self.msix_capability().ok_or("Device not MSI-X capable")?;
+        if vectors.len() > msix.table_size as usize {
+            return Err("pci_enable_msix_vectors(): device doesn't support that many MSI-X vectors");
+        }
+        let mut vector_table = self.pci_mem_map_msix(vectors.len())?;
+
+        let mut int_nums = Vec::with_capacity(vectors.len());
+        for (entry, &(handler, cpu)) in vector_table.iter_mut().zip(vectors) {
+            let int_num = match interrupts::register_msi_interrupt(handler) {
+                Ok(int_num) => int_num,
+                Err(e) => {
+                    for (&int_num, &(handler, _)) in int_nums.iter().zip(vectors) {
+                        let _ = interrupts::deregister_interrupt(int_num, handler);
+                    }
+                    return Err(e);
+                }
+            };
+            entry.init(cpu, int_num);
+            int_nums.push(int_num);
+        }
+
+        self.pci_enable_msix()?;
+        Ok((vector_table, int_nums))
+    }
+
     /// Maps device memory specified by a Base Address Register.
     ///
     /// # Arguments 
@@ -929,6 +1104,31 @@ impl PciDevice {
         map_frame_range(mem_base, mem_size as usize, MMIO_FLAGS)
     }
 
+    /// Isolates this device's DMA in its own IOMMU domain, such that it can only access
+    /// memory that is explicitly mapped into that domain, e.g., via [`PciDevice::create_dma_mapping()`].
+    ///
+    /// Returns an error if there is no IOMMU or DMA remapping isn't enabled,
+    /// in which case this device can still access all physical memory.
+    #[cfg(target_arch = "x86_64")]
+    pub fn isolate_dma(&self) -> Result<iommu::DmaDomainRef, &'static str> {
+        iommu::attach_device(self.location.bus, self.location.slot, self.location.func)
+    }
+
+    /// Allocates a physically-contiguous, writable buffer of `size_in_bytes` bytes
+    /// that this device can access via DMA.
+    ///
+    /// If this device's DMA was isolated via [`PciDevice::isolate_dma()`],
+    /// the buffer is mapped into its IOMMU domain until the buffer is dropped.
+    /// Either way, the device must access the buffer via [`DmaBuffer::device_address()`].
+    pub fn create_dma_mapping(&self, size_in_bytes: usize) -> Result<DmaBuffer, &'static str> {
+        #[cfg(target_arch = "x86_64")]
+        if let Some(domain) = iommu::device_domain(self.location.bus, self.location.slot, self.location.func) {
+            return iommu::DmaMapping::new(&domain, size_in_bytes).map(DmaBuffer::Remapped);
+        }
+        let (mp, paddr) = memory::create_contiguous_mapping(size_in_bytes, memory::DMA_FLAGS)?;
+        Ok(DmaBuffer::Physical(mp, paddr))
+    }
+
     /// Reads and returns this PCI device's INTx line and INTx pin registers.
     ///
     /// Returns an error if this PCI device's INTx pin value is invalid (greater than 4).
@@ -1023,6 +1223,50 @@ impl DerefMut for PciDevice {
 }
 
 
+/// A physically-contiguous, writable buffer that a PCI device can access via DMA,
+/// created by [`PciDevice::create_dma_mapping()`].
+///
+/// This dereferences to the buffer's mapping in the kernel's address space.
+pub enum DmaBuffer {
+    /// A buffer that the device accesses via its physical address.
+    Physical(MappedPages, PhysicalAddress),
+    /// A buffer mapped into the device's IOMMU domain, which is unmapped from that domain when dropped.
+    #[cfg(target_arch = "x86_64")]
+    Remapped(iommu::DmaMapping),
+}
+
+impl DmaBuffer {
+    /// Returns the address that the device must use to access this buffer.
+    pub fn device_address(&self) -> usize {
+        match self {
+            DmaBuffer::Physical(_, paddr) => paddr.value(),
+            #[cfg(target_arch = "x86_64")]
+            DmaBuffer::Remapped(mapping) => mapping.iova().value(),
+        }
+    }
+}
+
+impl Deref for DmaBuffer {
+    type Target = MappedPages;
+    fn deref(&self) -> &MappedPages {
+        match self {
+            DmaBuffer::Physical(mp, _) => mp,
+            #[cfg(target_arch = "x86_64")]
+            DmaBuffer::Remapped(mapping) => mapping,
+        }
+    }
+}
+
+impl DerefMut for DmaBuffer {
+    fn deref_mut(&mut self) -> &mut MappedPages {
+        match self {
+            DmaBuffer::Physical(mp, _) => mp,
+            #[cfg(target_arch = "x86_64")]
+            DmaBuffer::Remapped(mapping) => mapping,
+        }
+    }
+}
+
 
 /// Lists the 2 possible PCI configuration space access mechanisms
 /// that can be found from the LSB of the devices's BAR0
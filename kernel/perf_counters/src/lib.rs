@@ -0,0 +1,219 @@
+//! A safe API for counting and sampling architectural performance events.
+//!
+//! This builds on the [`pmu_x86`] crate, which programs the `IA32_PERFEVTSELx`
+//! and `IA32_PMCx` MSRs, to offer a simpler interface for profiling kernel hot paths:
+//! * [`measure()`] counts events while running a closure on the current CPU,
+//!   e.g., around a crate load or a scheduler invocation.
+//! * [`CounterGroup`] counts a set of events on the current CPU until it is stopped.
+//! * [`count_per_cpu()`] counts events on every CPU over a given interval.
+//! * [`start_sampling()`] and [`stop_sampling()`] record the instruction pointer
+//!   and task ID every time an event counter overflows, via the PMU interrupt.
+//!
+//! The performance counters of each CPU can only be read on that CPU,
+//! so counts are only meaningful if the counting task is not moved to another CPU.
+//!
+//! # Example
+//! ```ignore
+//! use perf_counters::PerfEvent;
+//!
+//! let events = [PerfEvent::InstructionsRetired, PerfEvent::LlcMisses];
+//! let (result, counts) = perf_counters::measure(&events, || load_some_crate())?;
+//! for (event, count) in counts {
+//!     info!("{}: {}", event.name(), count);
+//! }
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::time::Duration;
+use cpu::CpuId;
+use log::error;
+use pmu_x86::{Counter, EventType};
+
+pub use pmu_x86::SampleResults;
+
+/// The architectural performance events that can be counted or sampled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PerfEvent {
+    /// Instructions retired, counted by a fixed-function counter.
+    InstructionsRetired,
+    /// Core clock cycles while the core is not halted, counted by a fixed-function counter.
+    CoreCycles,
+    /// Reference clock cycles while the core is not halted, counted by a fixed-function counter.
+    ReferenceCycles,
+    /// References to the last-level cache.
+    LlcReferences,
+    /// Misses in the last-level cache.
+    LlcMisses,
+    /// Branch instructions retired.
+    BranchesRetired,
+    /// Mispredicted branch instructions retired.
+    BranchMispredicts,
+}
+
+impl PerfEvent {
+    /// All of the events that can be counted.
+    pub const ALL: [PerfEvent; 7] = [
+        PerfEvent::InstructionsRetired,
+        PerfEvent::CoreCycles,
+        PerfEvent::ReferenceCycles,
+        PerfEvent::LlcReferences,
+        PerfEvent::LlcMisses,
+        PerfEvent::BranchesRetired,
+        PerfEvent::BranchMispredicts,
+    ];
+
+    /// Returns a short, human-readable name for this event.
+    pub fn name(self) -> &'static str {
+        match self {
+            PerfEvent::InstructionsRetired => "instructions",
+            PerfEvent::CoreCycles => "cycles",
+            PerfEvent::ReferenceCycles => "ref-cycles",
+            PerfEvent::LlcReferences => "llc-references",
+            PerfEvent::LlcMisses => "llc-misses",
+            PerfEvent::BranchesRetired => "branches",
+            PerfEvent::BranchMispredicts => "branch-misses",
+        }
+    }
+
+    fn event_type(self) -> EventType {
+        match self {
+            PerfEvent::InstructionsRetired => EventType::InstructionsRetired,
+            PerfEvent::CoreCycles => EventType::UnhaltedCoreCycles,
+            PerfEvent::ReferenceCycles => EventType::UnhaltedReferenceCycles,
+            PerfEvent::LlcReferences => EventType::LastLevelCacheReferences,
+            PerfEvent::LlcMisses => EventType::LastLevelCacheMisses,
+            PerfEvent::BranchesRetired => EventType::BranchInstructionsRetired,
+            PerfEvent::BranchMispredicts => EventType::BranchMissesRetired,
+        }
+    }
+}
+
+/// The number of times each event occurred.
+pub type EventCounts = Vec<(PerfEvent, u64)>;
+
+/// Initializes the PMU on the current CPU, if it hasn't been already.
+pub fn init() -> Result<(), &'static str> {
+    if pmu_x86::is_initialized() {
+        Ok(())
+    } else {
+        pmu_x86::init()
+    }
+}
+
+/// A set of performance counters that count events on the CPU that created them.
+///
+/// The general-purpose counters used by a `CounterGroup` are released when it is dropped.
+pub struct CounterGroup {
+    cpu: CpuId,
+    counters: Vec<(PerfEvent, Counter)>,
+}
+
+impl CounterGroup {
+    /// Claims a counter for each of the given `events` on the current CPU and starts them.
+    ///
+    /// Returns an error if the PMU is unavailable or there are not enough free counters.
+    pub fn start(events: &[PerfEvent]) -> Result<CounterGroup, &'static str> {
+        init()?;
+        let mut counters = Vec::with_capacity(events.len());
+        for event in events {
+            counters.push((*event, Counter::new(event.event_type())?));
+        }
+        for (_, counter) in counters.iter_mut() {
+            counter.start()?;
+        }
+        Ok(CounterGroup { cpu: cpu::current_cpu(), counters })
+    }
+
+    /// Returns the CPU whose events are counted by this group.
+    pub fn cpu(&self) -> CpuId {
+        self.cpu
+    }
+
+    /// Returns the number of times each event has occurred since this group was started,
+    /// without stopping it.
+    ///
+    /// This must be called on the same CPU that started this group.
+    pub fn read(&self) -> Result<EventCounts, &'static str> {
+        if cpu::current_cpu() != self.cpu {
+            return Err("CounterGroup::read(): must be called on the CPU that started the counters");
+        }
+        self.counters.iter()
+            .map(|(event, counter)| counter.get_count_since_start().map(|count| (*event, count)))
+            .collect()
+    }
+
+    /// Stops this group's counters and returns the number of times each event occurred.
+    ///
+    /// This must be called on the same CPU that started this group.
+    pub fn stop(self) -> Result<EventCounts, &'static str> {
+        let counts = self.read()?;
+        drop(self);
+        Ok(counts)
+    }
+}
+
+/// Counts the given `events` on the current CPU while invoking `f`.
+///
+/// Returns the value returned by `f` and the number of times each event occurred.
+pub fn measure<F, R>(events: &[PerfEvent], f: F) -> Result<(R, EventCounts), &'static str>
+where
+    F: FnOnce() -> R,
+{
+    let group = CounterGroup::start(events)?;
+    let ret = f();
+    let counts = group.stop()?;
+    Ok((ret, counts))
+}
+
+/// Counts the given `events` on every CPU for the given `duration`.
+///
+/// This spawns a task pinned to each CPU that counts events on that CPU
+/// while it sleeps, so all of the other tasks running on that CPU are counted.
+/// Fixed-function events (instructions and cycles) are counted on every CPU,
+/// but a CPU is omitted from the results if its general-purpose counters are all in use.
+pub fn count_per_cpu(events: &[PerfEvent], duration: Duration) -> Result<BTreeMap<CpuId, EventCounts>, &'static str> {
+    let mut counts_per_cpu = BTreeMap::new();
+    for (cpu, result) in spawn::on_each_cpu("perf_counters", count_on_this_cpu, (events.to_vec(), duration))? {
+        match result {
+            Ok(Ok(counts)) => { counts_per_cpu.insert(cpu, counts); }
+            Ok(Err(e)) => error!("count_per_cpu(): couldn't count events on CPU {}: {}", cpu, e),
+            Err(reason) => {
+                error!("count_per_cpu(): counter task on CPU {} was killed: {}", cpu, reason);
+                return Err("count_per_cpu(): a counter task was killed");
+            }
+        }
+    }
+    Ok(counts_per_cpu)
+}
+
+/// The entry point of each task spawned by [`count_per_cpu()`].
+fn count_on_this_cpu((events, duration): (Vec<PerfEvent>, Duration)) -> Result<EventCounts, &'static str> {
+    let group = CounterGroup::start(&events)?;
+    sleep::sleep(duration).map_err(|_| "count_on_this_cpu(): failed to sleep")?;
+    group.stop()
+}
+
+/// Starts recording a sample every time `event` occurs `events_per_sample` times on the current CPU,
+/// until `sample_count` samples have been recorded or [`stop_sampling()`] is called.
+///
+/// Each sample consists of the instruction pointer and the ID of the task
+/// that was running when the event counter overflowed and raised the PMU interrupt.
+/// If `task_id` is `Some`, only samples taken while that task was running are recorded.
+pub fn start_sampling(
+    event: PerfEvent,
+    events_per_sample: u32,
+    task_id: Option<usize>,
+    sample_count: u32,
+) -> Result<(), &'static str> {
+    init()?;
+    pmu_x86::start_samples(event.event_type(), events_per_sample, task_id, sample_count)
+}
+
+/// Stops sampling on the current CPU and returns the recorded samples.
+pub fn stop_sampling() -> Result<SampleResults, &'static str> {
+    pmu_x86::retrieve_samples()
+}
@@ -21,6 +21,7 @@
 //! We support 2 ways to use the PMU. One is to measure the number of events that take place over a length of code.
 //! The second is Event Based Sampling, where after a specified number of events occur, an interrupt is called and we store the instruction pointer 
 //! and task id running at that point.
+//! Additionally, the [`pebs`] module supports precise sampling of memory loads and their latencies using PEBS.
 //! 
 //! Currently we support a maximum core ID of 255, and up to 8 general purpose counters per core. 
 //! A core ID greater than 255 is not supported in Theseus in general since the ID has to fit within a u8.
@@ -75,7 +76,7 @@ extern crate cpu;
 extern crate mod_mgmt;
 extern crate bit_field;
 
-use msr::*;
+use msr::{*, registers::PerfEvtSel};
 use x86_64::{VirtAddr, registers::model_specific::Msr, structures::idt::InterruptStackFrame};
 use raw_cpuid::CpuId as X86CpuIdInstr;
 use spin::Once;
@@ -97,6 +98,7 @@ use core::{
 
 
 pub mod stat;
+pub mod pebs;
 
 /// The minimum version ID a PMU can have, as retrieved by the `CPUID` instruction.
 /// Anything lower than this means PMU is not supported.
@@ -115,10 +117,6 @@ const FIXED_FUNC_1_RDPMC: u32 = (1 << 30) + 1;
 /// read from the fixed counter 2 to retrieve reference cycles
 const FIXED_FUNC_2_RDPMC: u32 = (1 << 30) + 2;
 
-/// Set bit 22 to enable the general PMC to start counting
-const PMC_ENABLE: u64 = 0x01 << 22;
-/// Set bit 20 in the event select register to enable interrupts on a counter overflow
-const INTERRUPT_ENABLE: u64 = 0x01 << 20;
 /// Value to write to the overflow control MSR to clear it
 const CLEAR_PERF_STATUS_MSR: u64 = 0x0000_0000_0000_000F;
 /// The number of words in the CORES_SAMPLING and RESULTS_READY bitmaps, so that information for 256 cores can be recorded
@@ -343,10 +341,7 @@ impl Counter {
         // for a general PMC, it enables the counter to start counting from 0
         else {
             self.start_count = 0;
-            unsafe { 
-                let umask = Msr::new(IA32_PERFEVTSEL0 + self.pmc as u32).read();
-                Msr::new(IA32_PERFEVTSEL0 + self.pmc as u32).write(umask | PMC_ENABLE);
-            }
+            PerfEvtSel::read(self.pmc as u8)?.with_enabled(true).write()?;
         }
         Ok(())
     }
@@ -386,8 +381,10 @@ impl Drop for Counter {
         // Otherwise the counter is a fixed function counter and nothing needs to be done.
         if self.msr_mask < num_pmc as u32 {
             // clears event counting settings and counter 
+            if let Err(e) = PerfEvtSel::cleared(self.msr_mask as u8).write() {
+                error!("pmu_x86: couldn't clear the event select of PMC {}: {}", self.msr_mask, e);
+            }
             unsafe{
-                Msr::new(IA32_PERFEVTSEL0 + self.msr_mask).write(0);
                 Msr::new(IA32_PMC0 + self.msr_mask).write(0);
             }
             free_counter(self.core, self.msr_mask as u8); 
@@ -567,6 +564,12 @@ fn sampling_results_are_ready(core_id: u8) -> bool {
     RESULTS_READY[word_num].load(Ordering::SeqCst).get_bit(bit_in_word)
 }
 
+/// Returns the value of the `IA32_PERFEVTSEL{pmc}` MSR that counts the event given by `event_mask`,
+/// whose low 16 bits are the event select and unit mask, but doesn't yet enable the counter.
+fn event_select(pmc: u8, event_mask: u64) -> PerfEvtSel {
+    PerfEvtSel::new(pmc, event_mask as u8, (event_mask >> 8) as u8)
+}
+
 /// Creates a counter object for a general purpose PMC given the event type.
 fn create_general_counter(event_mask: u64) -> Result<Counter, &'static str> {
     programmable_start(event_mask)
@@ -587,8 +590,8 @@ fn programmable_start(event_mask: u64) -> Result<Counter, &'static str> {
 
         unsafe{
             Msr::new(IA32_PMC0 + (pmc as u32)).write(0);
-            Msr::new(IA32_PERFEVTSEL0 + (pmc as u32)).write(event_mask);
         }
+        event_select(pmc, event_mask).write()?;
         return Ok(Counter {
             start_count: 0, 
             msr_mask: pmc as u32, 
@@ -612,6 +615,11 @@ fn create_fixed_counter(msr_mask: u32) -> Result<Counter, &'static str> {
     })
 }
 
+/// Returns `true` if the PMU has been initialized on this core.
+pub fn is_initialized() -> bool {
+    CORES_INITIALIZED.lock().contains(&cpu::current_cpu().into_u8())
+}
+
 /// Checks that the PMU has been initialized. If it has been,
 /// the version ID tells whether the system has performance monitoring capabilities. 
 fn check_pmu_availability() -> Result<(), &'static str>  {
@@ -730,8 +738,8 @@ pub fn start_samples(event_type: EventType, event_per_sample: u32, task_id: Opti
 
     unsafe{
         Msr::new(IA32_PMC0).write(start_value as u64);
-        Msr::new(IA32_PERFEVTSEL0).write(event_mask | PMC_ENABLE | INTERRUPT_ENABLE);
     }
+    event_select(0, event_mask).with_interrupt(true).with_enabled(true).write()?;
 
     Ok(())
 
@@ -740,8 +748,8 @@ pub fn start_samples(event_type: EventType, event_per_sample: u32, task_id: Opti
 /// Function to manually stop the sampling interrupts. Marks the stored instruction pointers and task IDs as ready to retrieve. 
 fn stop_samples(core_id: u8, samples: &mut SampledEvents) -> Result<(), &'static str> {
     // immediately stops counting and clears the counter
+    PerfEvtSel::cleared(0).write()?;
     unsafe{
-        Msr::new(IA32_PERFEVTSEL0).write(0);
         Msr::new(IA32_PMC0).write(0);
         Msr::new(IA32_PERF_GLOBAL_OVF_CTRL).write(CLEAR_PERF_STATUS_MSR);
     }
@@ -851,6 +859,13 @@ pub fn handle_sample(stack_frame: &InterruptStackFrame) -> Result<bool, &'static
     if unsafe { Msr::new(IA32_PERF_GLOBAL_STAUS).read() } == 0 {
         return Ok(false);
     }
+    // Check whether the PEBS buffer is full, which is handled separately from regular samples.
+    if pebs::handle_buffer_interrupt() {
+        if let Some(my_apic) = apic::get_my_apic() {
+            my_apic.write().clear_pmi_mask();
+        }
+        return Ok(true);
+    }
 
     unsafe { Msr::new(IA32_PERF_GLOBAL_OVF_CTRL).write(CLEAR_PERF_STATUS_MSR); }
 
@@ -883,12 +898,13 @@ pub fn handle_sample(stack_frame: &InterruptStackFrame) -> Result<bool, &'static
     });
 
     // stops the counter, resets it, and restarts it
+    let event_select = PerfEvtSel::read(0)?;
+    PerfEvtSel::cleared(0).write()?;
     unsafe {
-        Msr::new(IA32_PERFEVTSEL0).write(0);
         Msr::new(IA32_PERF_GLOBAL_OVF_CTRL).write(CLEAR_PERF_STATUS_MSR);
         Msr::new(IA32_PMC0).write(samples.start_value as u64);
-        Msr::new(IA32_PERFEVTSEL0).write(Msr::new(IA32_PERFEVTSEL0).read());
     }
+    event_select.write()?;
 
     if let Some(my_apic) = apic::get_my_apic() {
         my_apic.write().clear_pmi_mask();
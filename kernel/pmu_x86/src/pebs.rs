@@ -0,0 +1,383 @@
+//! This module implements memory-access profiling using Precise Event-Based Sampling (PEBS),
+//! specifically the load latency facility.
+//!
+//! With load latency sampling, the hardware records a PEBS record for (a sampled subset of)
+//! the load instructions that took longer than a given number of cycles to complete.
+//! Each record holds the precise instruction pointer of the load, the data address it accessed,
+//! its latency, and where in the memory hierarchy the data came from.
+//! Unlike regular event-based sampling, records are written directly into a memory buffer
+//! by the hardware, so no interrupt is taken per sample and there is no "skid"
+//! between the load instruction and the sampled instruction pointer.
+//!
+//! Samples can then be attributed to the functions (sections) and crates that performed the loads
+//! using [`hotspots()`], which identifies the code that suffers the most from cache misses.
+//!
+//! Like the regular sampler, PEBS uses general purpose PMC 0 and only samples the core
+//! that it was started on, so the code to be profiled must run on that core.
+//! Load latency sampling is supported on Nehalem and later microarchitectures
+//! whose PEBS record format is between 1 and 4; the adaptive PEBS records
+//! of newer microarchitectures are not yet supported.
+//!
+//! # Example
+//! ```
+//! pmu_x86::init()?;
+//!
+//! pmu_x86::pebs::start_load_latency_sampling(pmu_x86::pebs::LoadLatencyConfig::default())?;
+//! ...
+//! // code to be profiled, on the same core
+//! ...
+//! let samples = pmu_x86::pebs::stop_load_latency_sampling()?;
+//! for hotspot in pmu_x86::pebs::hotspots(&samples)? {
+//!     debug!("{}", hotspot);
+//! }
+//! ```
+
+use core::fmt;
+use memory::{MappedPages, PteFlags, VirtualAddress};
+use crate::*;
+
+/// Bit 62 of the global status MSR indicates that the DS buffer (e.g., the PEBS buffer)
+/// has reached its interrupt threshold.
+const OVF_BUFFER_BIT: usize = 62;
+/// Set bit 0 in the PEBS enable MSR to enable PEBS on PMC 0.
+const PEBS_ENABLE_PMC0: u64 = 1 << 0;
+/// Set bit 32 in the PEBS enable MSR to enable load latency sampling on PMC 0.
+const LOAD_LATENCY_ENABLE_PMC0: u64 = 1 << 32;
+/// Bit 12 of the misc enable MSR indicates that PEBS is unavailable.
+const MISC_ENABLE_PEBS_UNAVAILABLE: usize = 12;
+/// The minimum latency threshold (in core cycles) supported by the hardware.
+const MIN_LATENCY_THRESHOLD: u16 = 3;
+/// The value written to PMC 0 is sign-extended from 32 bits,
+/// so the counter reset value in the DS area must match that in its full 48-bit width.
+const COUNTER_MASK: u64 = (1 << 48) - 1;
+
+/// MEM_INST_RETIRED.LATENCY_ABOVE_THRESHOLD on Nehalem and Westmere (PEBS record format 1).
+const LOAD_LATENCY_EVENT_NEHALEM: u64 = (0x03 << 16) | (0x10 << 8) | 0x0B;
+/// MEM_TRANS_RETIRED.LOAD_LATENCY on Sandy Bridge and later (PEBS record format 2 and above).
+const LOAD_LATENCY_EVENT: u64 = (0x03 << 16) | (0x01 << 8) | 0xCD;
+
+// Offsets of fields in the 64-bit DS save area; see Intel SDM 18.6.3.4.
+const DS_PEBS_BUFFER_BASE: usize = 0x20;
+const DS_PEBS_INDEX: usize = 0x28;
+const DS_PEBS_ABSOLUTE_MAXIMUM: usize = 0x30;
+const DS_PEBS_INTERRUPT_THRESHOLD: usize = 0x38;
+const DS_PEBS_COUNTER0_RESET: usize = 0x40;
+const DS_AREA_SIZE: usize = 0x80;
+
+// Offsets of fields in a 64-bit PEBS record; see Intel SDM 18.8.1.1 and 18.9.2.
+const RECORD_RIP: usize = 0x08;
+const RECORD_DATA_LINEAR_ADDRESS: usize = 0x98;
+const RECORD_DATA_SOURCE: usize = 0xA0;
+const RECORD_LATENCY: usize = 0xA8;
+const RECORD_EVENTING_IP: usize = 0xB0;
+
+/// The configuration of load latency sampling.
+#[derive(Clone, Copy, Debug)]
+pub struct LoadLatencyConfig {
+    /// Only loads that take at least this many core cycles are sampled. Must be at least 3.
+    pub latency_threshold: u16,
+    /// One PEBS record is written for every `sample_period` loads that exceed the latency threshold.
+    pub sample_period: u32,
+    /// The maximum number of samples to record; sampling stops once the buffer is full.
+    pub max_samples: usize,
+}
+
+impl Default for LoadLatencyConfig {
+    fn default() -> Self {
+        LoadLatencyConfig {
+            latency_threshold: 32,
+            sample_period: 1000,
+            max_samples: 4096,
+        }
+    }
+}
+
+/// Where in the memory hierarchy the data for a sampled load came from,
+/// decoded from the data source encoding of a PEBS record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataSource {
+    /// The L1 data cache.
+    L1Hit,
+    /// A pending fill buffer, i.e., an in-flight miss to the same cache line.
+    FillBufferHit,
+    /// The L2 cache.
+    L2Hit,
+    /// The last-level cache, without needing to snoop other cores.
+    L3Hit,
+    /// The last-level cache, after snooping other cores.
+    L3HitSnoop,
+    /// A cache on another socket.
+    RemoteCache,
+    /// DRAM attached to this socket.
+    LocalDram,
+    /// DRAM attached to another socket.
+    RemoteDram,
+    /// Memory-mapped I/O.
+    Io,
+    /// Uncacheable memory.
+    Uncacheable,
+    /// A miss in the last-level cache whose source is unknown, or a reserved encoding.
+    Unknown(u8),
+}
+
+impl DataSource {
+    fn from_encoding(encoding: u64) -> DataSource {
+        match (encoding & 0xF) as u8 {
+            0x1 => DataSource::L1Hit,
+            0x2 => DataSource::FillBufferHit,
+            0x3 => DataSource::L2Hit,
+            0x4 => DataSource::L3Hit,
+            0x5..=0x7 => DataSource::L3HitSnoop,
+            0x8 => DataSource::RemoteCache,
+            0xA | 0xC => DataSource::LocalDram,
+            0xB | 0xD => DataSource::RemoteDram,
+            0xE => DataSource::Io,
+            0xF => DataSource::Uncacheable,
+            other => DataSource::Unknown(other),
+        }
+    }
+
+    /// Returns true if the load missed in all of this core's caches.
+    pub fn is_cache_miss(&self) -> bool {
+        !matches!(self, DataSource::L1Hit | DataSource::FillBufferHit | DataSource::L2Hit)
+    }
+}
+
+/// A single load latency sample.
+#[derive(Clone, Copy, Debug)]
+pub struct LoadLatencySample {
+    /// The address of the load instruction.
+    pub instruction_pointer: VirtualAddress,
+    /// The data address that the load accessed.
+    pub data_address: usize,
+    /// The latency of the load, in core cycles.
+    pub latency: u64,
+    /// Where the loaded data came from.
+    pub data_source: DataSource,
+}
+
+/// The PEBS buffers of a core on which load latency sampling is in progress.
+struct PebsArea {
+    ds_area: MappedPages,
+    buffer: MappedPages,
+    record_format: u8,
+    record_size: usize,
+}
+
+/// The PEBS buffers for each core that is currently sampling, keyed by core ID.
+static PEBS_AREAS: IrqSafeMutex<BTreeMap<u8, PebsArea>> = IrqSafeMutex::new(BTreeMap::new());
+
+/// Returns the PEBS record format supported by this core, or an error if PEBS isn't supported.
+fn pebs_record_format() -> Result<u8, &'static str> {
+    let feature_info = X86CpuIdInstr::new().get_feature_info()
+        .ok_or("pmu_x86: couldn't read CPU feature information")?;
+    if !feature_info.has_ds() || !feature_info.has_pdcm() {
+        return Err("pmu_x86: this CPU doesn't support the debug store or PEBS");
+    }
+    if unsafe { Msr::new(IA32_MISC_ENABLE).read() }.get_bit(MISC_ENABLE_PEBS_UNAVAILABLE) {
+        return Err("pmu_x86: PEBS is unavailable on this CPU");
+    }
+    Ok(unsafe { Msr::new(IA32_PERF_CAPABILITIES).read() }.get_bits(8..12) as u8)
+}
+
+/// Returns the size of a PEBS record in the given record format.
+fn pebs_record_size(record_format: u8) -> Result<usize, &'static str> {
+    match record_format {
+        1 => Ok(0xB0),
+        2 => Ok(0xC0),
+        3 | 4 => Ok(0xC8),
+        0 => Err("pmu_x86: this CPU's PEBS doesn't support load latency sampling"),
+        _ => Err("pmu_x86: this CPU's adaptive PEBS record format is not yet supported"),
+    }
+}
+
+/// Starts sampling loads whose latency exceeds the configured threshold on this core.
+///
+/// PMC 0 must be available, and no other sampling may be in progress on this core.
+/// Call [`stop_load_latency_sampling()`] on the same core to retrieve the samples.
+pub fn start_load_latency_sampling(config: LoadLatencyConfig) -> Result<(), &'static str> {
+    check_pmu_availability()?;
+    if config.latency_threshold < MIN_LATENCY_THRESHOLD {
+        return Err("pmu_x86: the load latency threshold must be at least 3 cycles");
+    }
+    if config.sample_period == 0 || config.max_samples == 0 {
+        return Err("pmu_x86: the sample period and maximum number of samples must be nonzero");
+    }
+    let record_format = pebs_record_format()?;
+    let record_size = pebs_record_size(record_format)?;
+
+    let my_core_id = cpu::current_cpu().into_u8();
+    let mut pebs_areas = PEBS_AREAS.lock();
+    if pebs_areas.contains_key(&my_core_id) || core_is_currently_sampling(my_core_id) {
+        return Err("pmu_x86: sampling is already being performed on this CPU");
+    }
+    if !counter_is_available(my_core_id, 0)? {
+        return Err("PMU counter 0 is currently in use and can't be used for sampling. End all other PMU tasks and try again");
+    }
+
+    let flags = PteFlags::new().valid(true).writable(true);
+    let buffer_size = config.max_samples.checked_mul(record_size)
+        .ok_or("pmu_x86: the maximum number of samples is too large")?;
+    let mut ds_area = memory::create_mapping(DS_AREA_SIZE, flags)?;
+    let buffer = memory::create_mapping(buffer_size, flags)?;
+    claim_counter(my_core_id, 0)?;
+
+    let counter_start = core::u32::MAX - config.sample_period;
+    {
+        let buffer_base = buffer.start_address().value() as u64;
+        let ds: &mut [u64] = ds_area.as_slice_mut(0, DS_AREA_SIZE / 8)?;
+        ds.fill(0);
+        ds[DS_PEBS_BUFFER_BASE / 8] = buffer_base;
+        ds[DS_PEBS_INDEX / 8] = buffer_base;
+        ds[DS_PEBS_ABSOLUTE_MAXIMUM / 8] = buffer_base + buffer_size as u64;
+        // Raise an interrupt once the buffer is full, at which point sampling is stopped.
+        ds[DS_PEBS_INTERRUPT_THRESHOLD / 8] = buffer_base + buffer_size as u64;
+        ds[DS_PEBS_COUNTER0_RESET / 8] = COUNTER_MASK - config.sample_period as u64;
+    }
+    let event_mask = if record_format == 1 { LOAD_LATENCY_EVENT_NEHALEM } else { LOAD_LATENCY_EVENT };
+
+    unsafe {
+        Msr::new(IA32_DS_AREA).write(ds_area.start_address().value() as u64);
+        Msr::new(MSR_PEBS_LD_LAT).write(config.latency_threshold as u64);
+        Msr::new(IA32_PMC0).write(counter_start as u64);
+        Msr::new(IA32_PEBS_ENABLE).write(PEBS_ENABLE_PMC0 | LOAD_LATENCY_ENABLE_PMC0);
+    }
+    // Unlike regular sampling, counter overflows are recorded by PEBS rather than raising an interrupt.
+    event_select(0, event_mask).with_enabled(true).write()?;
+
+    pebs_areas.insert(my_core_id, PebsArea { ds_area, buffer, record_format, record_size });
+    trace!("Started PEBS load latency sampling on core {} with record format {}", my_core_id, record_format);
+    Ok(())
+}
+
+/// Stops load latency sampling on this core and returns the recorded samples.
+pub fn stop_load_latency_sampling() -> Result<Vec<LoadLatencySample>, &'static str> {
+    let my_core_id = cpu::current_cpu().into_u8();
+    let pebs_area = PEBS_AREAS.lock().remove(&my_core_id)
+        .ok_or("pmu_x86: load latency sampling is not in progress on this core")?;
+
+    disable_pebs()?;
+    unsafe { Msr::new(IA32_DS_AREA).write(0); }
+    free_counter(my_core_id, 0);
+
+    let ds: &[u64] = pebs_area.ds_area.as_slice(0, DS_AREA_SIZE / 8)?;
+    let buffer_base = ds[DS_PEBS_BUFFER_BASE / 8] as usize;
+    let bytes_written = (ds[DS_PEBS_INDEX / 8] as usize).saturating_sub(buffer_base);
+    let num_records = core::cmp::min(bytes_written, pebs_area.buffer.size_in_bytes()) / pebs_area.record_size;
+
+    let mut samples = Vec::with_capacity(num_records);
+    for i in 0..num_records {
+        let record: &[u64] = pebs_area.buffer.as_slice(i * pebs_area.record_size, pebs_area.record_size / 8)?;
+        // The eventing IP is the address of the load itself,
+        // whereas the RIP field is the address of the instruction after it.
+        let eventing_ip = if pebs_area.record_format >= 2 { record[RECORD_EVENTING_IP / 8] } else { 0 };
+        let ip = if eventing_ip != 0 { eventing_ip } else { record[RECORD_RIP / 8] };
+        samples.push(LoadLatencySample {
+            instruction_pointer: VirtualAddress::new_canonical(ip as usize),
+            data_address: record[RECORD_DATA_LINEAR_ADDRESS / 8] as usize,
+            latency: record[RECORD_LATENCY / 8],
+            data_source: DataSource::from_encoding(record[RECORD_DATA_SOURCE / 8]),
+        });
+    }
+    trace!("Stopped PEBS load latency sampling on core {}: {} samples", my_core_id, samples.len());
+    Ok(samples)
+}
+
+/// Disables PEBS and PMC 0 on this core and clears their overflow status.
+fn disable_pebs() -> Result<(), &'static str> {
+    PerfEvtSel::cleared(0).write()?;
+    unsafe {
+        Msr::new(IA32_PEBS_ENABLE).write(0);
+        Msr::new(IA32_PMC0).write(0);
+        Msr::new(IA32_PERF_GLOBAL_OVF_CTRL).write(CLEAR_PERF_STATUS_MSR | (1 << OVF_BUFFER_BIT));
+    }
+    Ok(())
+}
+
+/// Handles the interrupt raised once the PEBS buffer is full by disabling sampling on this core.
+///
+/// The recorded samples remain in the buffer until retrieved by [`stop_load_latency_sampling()`].
+/// This is invoked from the NMI handler, so it must not acquire any locks.
+///
+/// Returns true if the PEBS buffer interrupt was pending.
+pub(crate) fn handle_buffer_interrupt() -> bool {
+    if !unsafe { Msr::new(IA32_PERF_GLOBAL_STAUS).read() }.get_bit(OVF_BUFFER_BIT) {
+        return false;
+    }
+    // PEBS is only started on cores that have PMC 0, so disabling it cannot fail.
+    disable_pebs().ok();
+    true
+}
+
+/// The load latency samples attributed to a single function (section).
+#[derive(Clone, Debug)]
+pub struct Hotspot {
+    /// The name of the section containing the sampled loads, without its hash.
+    pub section_name: String,
+    /// The name of the crate containing that section.
+    pub crate_name: String,
+    /// The number of samples in this section.
+    pub samples: usize,
+    /// The number of samples in this section that missed in all of the core's caches.
+    pub cache_misses: usize,
+    /// The sum of the latencies of all samples in this section, in core cycles.
+    pub total_latency: u64,
+    /// The maximum latency of any sample in this section, in core cycles.
+    pub max_latency: u64,
+}
+
+impl Hotspot {
+    /// The average latency of the samples in this section, in core cycles.
+    pub fn average_latency(&self) -> u64 {
+        self.total_latency / self.samples as u64
+    }
+}
+
+impl fmt::Display for Hotspot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>6} samples  {:>6} misses  avg {:>5}  max {:>6} cycles  {} ({})",
+            self.samples, self.cache_misses, self.average_latency(), self.max_latency,
+            self.section_name, self.crate_name,
+        )
+    }
+}
+
+/// Attributes the given samples to the functions (sections) that performed the loads,
+/// using the current task's namespace to symbolize their instruction pointers.
+///
+/// Returns one [`Hotspot`] per section, sorted by total latency in descending order.
+pub fn hotspots(samples: &[LoadLatencySample]) -> Result<Vec<Hotspot>, &'static str> {
+    let namespace = task::with_current_task(|f| f.get_namespace().clone())
+        .map_err(|_| "pmu_x86::pebs::hotspots: couldn't get current task")?;
+
+    let mut hotspots: BTreeMap<(String, String), Hotspot> = BTreeMap::new();
+    for sample in samples {
+        let (section_name, crate_name) = match namespace.get_section_containing_address(sample.instruction_pointer, false) {
+            Some((section, _offset)) => {
+                let crate_name = section.parent_crate.upgrade()
+                    .map(|c| c.lock_as_ref().crate_name.to_string())
+                    .unwrap_or_else(|| String::from("<unknown>"));
+                (section.name_without_hash().to_string(), crate_name)
+            }
+            None => (String::from("<unknown>"), String::from("<unknown>")),
+        };
+        let hotspot = hotspots.entry((section_name.clone(), crate_name.clone())).or_insert_with(|| Hotspot {
+            section_name,
+            crate_name,
+            samples: 0,
+            cache_misses: 0,
+            total_latency: 0,
+            max_latency: 0,
+        });
+        hotspot.samples += 1;
+        if sample.data_source.is_cache_miss() {
+            hotspot.cache_misses += 1;
+        }
+        hotspot.total_latency += sample.latency;
+        hotspot.max_latency = core::cmp::max(hotspot.max_latency, sample.latency);
+    }
+
+    let mut hotspots: Vec<Hotspot> = hotspots.into_values().collect();
+    hotspots.sort_unstable_by(|a, b| b.total_latency.cmp(&a.total_latency));
+    Ok(hotspots)
+}
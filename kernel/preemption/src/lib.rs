@@ -13,6 +13,13 @@ use cpu::CpuId;
 #[cls_macros::cpu_local(cls_dep = false)]
 static PREEMPTION_COUNT: u8 = 0;
 
+/// Whether the local timer interrupt has been stopped on this CPU (nonzero),
+/// in which case re-enabling preemption doesn't re-enable the local timer interrupt.
+///
+/// See [`stop_tick()`].
+#[cls_macros::cpu_local(cls_dep = false)]
+static TICK_STOPPED: u8 = 0;
+
 /// Prevents preemption (preemptive task switching) from occurring
 /// until the returned guard object is dropped.
 ///
@@ -128,7 +135,7 @@ impl Drop for PreemptionGuard {
         // If the previous counter value was 1, that means the current value is 0,
         // which means we are transitioning from preemption being disabled to enabled on this CPU.
         // Thus, we re-enable the local timer interrupt used for preemptive task switching.
-        if prev_val == 1 {
+        if prev_val == 1 && TICK_STOPPED.load() == 0 {
             // log::trace!("CPU {}: re-enabling local timer interrupt", cpu_id);
             #[cfg(target_arch = "x86_64")]
             apic::get_my_apic()
@@ -150,3 +157,44 @@ impl Drop for PreemptionGuard {
 pub fn preemption_enabled() -> bool {
     PREEMPTION_COUNT.load() == 0
 }
+
+/// Stops the local timer interrupt used for preemptive task switches on this CPU,
+/// such that the current task runs without being interrupted by timer ticks.
+///
+/// The timer remains stopped even when preemption is re-enabled, until [`restart_tick()`] is called.
+/// This should only be called with interrupts disabled, e.g., from the timer interrupt handler.
+///
+/// This is only supported on x86_64, as aarch64's one-shot timer is re-armed by
+/// the scheduler's timer interrupt handler rather than enabled by preemption guards.
+#[cfg(target_arch = "x86_64")]
+pub fn stop_tick() {
+    if TICK_STOPPED.load() == 0 {
+        TICK_STOPPED.fetch_add(1);
+        apic::get_my_apic()
+            .expect("BUG: stop_tick() couldn't get local APIC")
+            .write()
+            .enable_lvt_timer(false);
+    }
+}
+
+/// Restarts the local timer interrupt on this CPU after it was stopped by [`stop_tick()`].
+///
+/// If preemption is currently disabled, the timer is restarted once preemption is re-enabled.
+/// This should only be called with interrupts or preemption disabled.
+pub fn restart_tick() {
+    if TICK_STOPPED.load() != 0 {
+        TICK_STOPPED.fetch_sub(1);
+        if preemption_enabled() {
+            #[cfg(target_arch = "x86_64")]
+            apic::get_my_apic()
+                .expect("BUG: restart_tick() couldn't get local APIC")
+                .write()
+                .enable_lvt_timer(true);
+        }
+    }
+}
+
+/// Returns `true` if the local timer interrupt is currently stopped on this CPU by [`stop_tick()`].
+pub fn is_tick_stopped() -> bool {
+    TICK_STOPPED.load() != 0
+}
@@ -0,0 +1,316 @@
+//! Hierarchical timer-based profiling scopes, for coarse profiling on machines
+//! without access to hardware performance counters, e.g., when running under TCG emulation.
+//!
+//! Code is instrumented with the [`profile_scope!()`] macro, which measures the time
+//! from where it is invoked until the end of the enclosing block:
+//! ```ignore
+//! fn handle_request() {
+//!     profile_scope!();              // named after the enclosing function
+//!     parse();
+//!     {
+//!         profile_scope!("respond"); // named explicitly
+//!         respond();
+//!     }
+//! }
+//! ```
+//!
+//! While profiling is active (see [`start()`] and [`stop()`]), each completed scope is recorded
+//! into a fixed-size ring buffer belonging to the current CPU, such that recording never allocates memory.
+//! Once a ring buffer is full, its oldest records are overwritten.
+//! When profiling isn't active, a scope costs only a single atomic load.
+//!
+//! [`call_tree()`] aggregates the recorded scopes into a call tree, in which a scope
+//! is the child of the innermost scope of the same task that encloses it in time.
+//! Because scopes are matched by task rather than by CPU, tasks that are preempted or migrated
+//! between CPUs in the middle of a scope are still aggregated correctly,
+//! but the time during which a task was preempted is attributed to its scopes.
+//!
+//! The call tree can be exported via [`CallTree::folded_stacks()`] in the "folded stacks" format,
+//! which can be rendered on the host by flamegraph tools such as `flamegraph.pl` or `inferno-flamegraph`.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use sync_irq::{IrqSafeMutex, IrqSafeRwLock};
+use time::{Duration, Instant};
+
+/// The default number of scope records in each CPU's ring buffer.
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+/// Whether scopes are currently being recorded.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// The number of records that were overwritten before they could be collected.
+static OVERWRITTEN: AtomicUsize = AtomicUsize::new(0);
+/// One ring buffer per CPU, along with the ID of that CPU.
+static BUFFERS: IrqSafeRwLock<Vec<(u32, IrqSafeMutex<RingBuffer>)>> = IrqSafeRwLock::new(Vec::new());
+
+/// Profiles the remainder of the enclosing block as a named scope.
+///
+/// With no arguments, the scope is named after the enclosing function.
+/// Otherwise, the scope is given the `&'static str` name that is passed in.
+///
+/// See the [crate-level documentation](crate) for more.
+#[macro_export]
+macro_rules! profile_scope {
+    () => {
+        let _profile_scope_guard = $crate::ScopeGuard::enter({
+            fn f() {}
+            let name = $crate::type_name_of(f);
+            name.strip_suffix("::f").unwrap_or(name)
+        });
+    };
+    ($name:expr) => {
+        let _profile_scope_guard = $crate::ScopeGuard::enter($name);
+    };
+}
+
+/// Returns the type name of the given value, which is used to name a scope after its enclosing function.
+#[doc(hidden)]
+pub fn type_name_of<T>(_: T) -> &'static str {
+    core::any::type_name::<T>()
+}
+
+/// A completed profiling scope.
+#[derive(Clone, Copy, Debug)]
+pub struct ScopeRecord {
+    pub name: &'static str,
+    /// The ID of the task that executed the scope.
+    pub task_id: usize,
+    /// The CPU that the scope ended on.
+    pub cpu: u32,
+    pub start: Instant,
+    pub end: Instant,
+}
+
+impl ScopeRecord {
+    /// Returns the amount of time spent in this scope, including its nested scopes.
+    pub fn duration(&self) -> Duration {
+        self.end.duration_since(self.start)
+    }
+}
+
+/// A fixed-size ring buffer of scope records.
+struct RingBuffer {
+    records: Vec<Option<ScopeRecord>>,
+    /// The index at which the next record will be written.
+    next: usize,
+}
+
+impl RingBuffer {
+    fn push(&mut self, record: ScopeRecord) {
+        let slot = &mut self.records[self.next];
+        if slot.is_some() {
+            OVERWRITTEN.fetch_add(1, Ordering::Relaxed);
+        }
+        *slot = Some(record);
+        self.next = (self.next + 1) % self.records.len();
+    }
+}
+
+/// An RAII guard that records a profiling scope when it is dropped.
+///
+/// This is typically created by the [`profile_scope!()`] macro rather than directly.
+pub struct ScopeGuard {
+    name: &'static str,
+    /// The start of the scope, or `None` if profiling wasn't active when it began.
+    start: Option<Instant>,
+}
+
+impl ScopeGuard {
+    /// Begins a profiling scope with the given name, which ends when the returned guard is dropped.
+    #[inline]
+    pub fn enter(name: &'static str) -> ScopeGuard {
+        ScopeGuard {
+            name,
+            start: ENABLED.load(Ordering::Relaxed).then(Instant::now),
+        }
+    }
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let Some(start) = self.start else { return };
+        if !ENABLED.load(Ordering::Relaxed) {
+            return;
+        }
+        let end = Instant::now();
+        let cpu = cpu::current_cpu().value();
+        let record = ScopeRecord {
+            name: self.name,
+            task_id: task::get_my_current_task_id(),
+            cpu,
+            start,
+            end,
+        };
+        if let Some((_, buffer)) = BUFFERS.read().iter().find(|(id, _)| *id == cpu) {
+            buffer.lock().push(record);
+        }
+    }
+}
+
+/// Starts recording profiling scopes on all CPUs, discarding any previously-recorded scopes.
+///
+/// Each CPU records up to `capacity` of its most recent scopes.
+pub fn start(capacity: usize) -> Result<(), &'static str> {
+    if capacity == 0 {
+        return Err("profile_scope: the capacity of a ring buffer must be nonzero");
+    }
+    // Allocate the new buffers before taking the lock, which disables interrupts.
+    let buffers: Vec<_> = cpu::cpus()
+        .map(|cpu| (cpu.value(), IrqSafeMutex::new(RingBuffer { records: alloc::vec![None; capacity], next: 0 })))
+        .collect();
+    ENABLED.store(false, Ordering::Relaxed);
+    let old_buffers = core::mem::replace(&mut *BUFFERS.write(), buffers);
+    OVERWRITTEN.store(0, Ordering::Relaxed);
+    ENABLED.store(true, Ordering::Relaxed);
+    drop(old_buffers);
+    Ok(())
+}
+
+/// Stops recording profiling scopes. The recorded scopes are retained until the next call to [`start()`].
+pub fn stop() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// Returns whether profiling scopes are currently being recorded.
+pub fn is_active() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Returns the number of recorded scopes that were overwritten because a ring buffer was full.
+pub fn overwritten_records() -> usize {
+    OVERWRITTEN.load(Ordering::Relaxed)
+}
+
+/// Returns a copy of the scopes recorded on all CPUs since profiling was last started.
+pub fn records() -> Vec<ScopeRecord> {
+    let buffers = BUFFERS.read();
+    let mut records = Vec::new();
+    for (_, buffer) in buffers.iter() {
+        let buffer = buffer.lock();
+        // Copy out the records in order from oldest to newest.
+        let (newer, older) = buffer.records.split_at(buffer.next);
+        records.extend(older.iter().chain(newer).flatten().copied());
+    }
+    records
+}
+
+/// A node in a [`CallTree`], representing all invocations of a scope at one position in the tree.
+#[derive(Clone, Debug)]
+pub struct CallTreeNode {
+    pub name: &'static str,
+    /// The number of times this scope was recorded at this position.
+    pub count: usize,
+    /// The total time spent in this scope, including its children.
+    pub total_time: Duration,
+    pub children: Vec<CallTreeNode>,
+}
+
+impl CallTreeNode {
+    fn new(name: &'static str) -> CallTreeNode {
+        CallTreeNode { name, count: 0, total_time: Duration::ZERO, children: Vec::new() }
+    }
+
+    /// Returns the time spent in this scope itself, excluding its children.
+    pub fn self_time(&self) -> Duration {
+        let children_time: Duration = self.children.iter().map(|c| c.total_time).sum();
+        self.total_time.saturating_sub(children_time)
+    }
+
+    /// Returns the index of the child with the given name, adding one if it doesn't exist.
+    fn child(&mut self, name: &'static str) -> usize {
+        match self.children.iter().position(|c| c.name == name) {
+            Some(index) => index,
+            None => {
+                self.children.push(CallTreeNode::new(name));
+                self.children.len() - 1
+            }
+        }
+    }
+
+    /// Returns the descendant of this node at the given path of child indices.
+    fn descendant(&mut self, path: &[usize]) -> &mut CallTreeNode {
+        path.iter().fold(self, |node, &index| &mut node.children[index])
+    }
+}
+
+/// A call tree aggregated from nested profiling scopes, as returned by [`call_tree()`].
+#[derive(Clone, Debug)]
+pub struct CallTree {
+    /// The outermost scopes, i.e., those not enclosed by any other recorded scope.
+    pub roots: Vec<CallTreeNode>,
+}
+
+impl CallTree {
+    /// Aggregates the given scope records into a call tree.
+    pub fn from_records(records: &[ScopeRecord]) -> CallTree {
+        let mut by_task: BTreeMap<usize, Vec<&ScopeRecord>> = BTreeMap::new();
+        for record in records {
+            by_task.entry(record.task_id).or_default().push(record);
+        }
+
+        let mut root = CallTreeNode::new("");
+        for (_task_id, mut records) in by_task {
+            // Sorting enclosing scopes before the scopes they enclose ensures that
+            // each scope's parent has already been visited.
+            records.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+            // The currently-open scopes, as the end of each one and the path to its node.
+            let mut open: Vec<(Instant, Vec<usize>)> = Vec::new();
+            for record in records {
+                while open.last().map_or(false, |(end, _)| *end <= record.start || record.end > *end) {
+                    open.pop();
+                }
+                let mut path = open.last().map(|(_, path)| path.clone()).unwrap_or_default();
+                let parent = root.descendant(&path);
+                let index = parent.child(record.name);
+                let node = &mut parent.children[index];
+                node.count += 1;
+                node.total_time += record.duration();
+                path.push(index);
+                open.push((record.end, path));
+            }
+        }
+        CallTree { roots: root.children }
+    }
+
+    /// Writes this call tree in the folded stacks format used by flamegraph tools.
+    ///
+    /// Each line consists of the semicolon-separated names of the scopes from a root to a node,
+    /// followed by the node's self time in nanoseconds, e.g., `handle_request;respond 12000`.
+    /// Semicolons and spaces within scope names are replaced with underscores.
+    pub fn folded_stacks(&self) -> String {
+        fn visit(node: &CallTreeNode, stack: &mut String, out: &mut String) {
+            let stack_len = stack.len();
+            if !stack.is_empty() {
+                stack.push(';');
+            }
+            stack.extend(node.name.chars().map(|c| if c == ';' || c == ' ' { '_' } else { c }));
+            let self_time = node.self_time().as_nanos();
+            if self_time > 0 {
+                let _ = writeln!(out, "{} {}", stack, self_time);
+            }
+            for child in &node.children {
+                visit(child, stack, out);
+            }
+            stack.truncate(stack_len);
+        }
+
+        let mut out = String::new();
+        let mut stack = String::new();
+        for root in &self.roots {
+            visit(root, &mut stack, &mut out);
+        }
+        out
+    }
+}
+
+/// Aggregates the scopes recorded on all CPUs into a call tree.
+pub fn call_tree() -> CallTree {
+    CallTree::from_records(&records())
+}
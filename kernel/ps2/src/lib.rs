@@ -6,7 +6,7 @@
 #![no_std]
 
 use log::{debug, warn};
-use modular_bitfield::{specifiers::{B1, B4, B8}, bitfield, BitfieldSpecifier};
+use modular_bitfield::{specifiers::{B1, B4, B5, B8}, bitfield, BitfieldSpecifier};
 use num_enum::TryFromPrimitive;
 use port_io::Port;
 use spin::{Mutex, Once};
@@ -529,6 +529,13 @@ impl<'c> PS2Keyboard<'c> {
             .map_err(|_| "failed to set the keyboard led")
     }
 
+    /// Set the typematic (auto-repeat) rate and the delay before repeating begins.
+    pub fn set_keyboard_typematic(&self, value: TypematicByte) -> Result<(), &'static str> {
+        self.command_to_keyboard(HostToKeyboardCommandOrData::KeyboardCommand(SetRepeatRateAndDelay))
+            .and_then(|_| self.command_to_keyboard(HostToKeyboardCommandOrData::Typematic(value)))
+            .map_err(|_| "failed to set the keyboard typematic rate and delay")
+    }
+
     /// Set the active scancode set currently used by the keyboard.
     /// 
     /// TODO:      set Set2, if Get == 2, return
@@ -773,6 +780,7 @@ impl From<WritableData> for u8 {
                     KeyboardCommand(c) => c as u8,
                     LEDState(l) => u8::from_ne_bytes(l.into_bytes()),
                     ScancodeSet(s) => s as u8,
+                    Typematic(t) => u8::from_ne_bytes(t.into_bytes()),
                 }
                 HostToDevice::Mouse(value) => match value {
                     MouseCommand(c) => c as u8,
@@ -857,7 +865,8 @@ pub enum HostToKeyboardCommandOrData {
     KeyboardCommand(HostToKeyboardCommand),
     LEDState(LEDState),
     ScancodeSet(ScancodeSet),
-    //TODO: Typematic, Scancode
+    Typematic(TypematicByte),
+    //TODO: Scancode
 }
 
 #[derive(Debug, Clone)]
@@ -897,6 +906,29 @@ pub struct LEDState {
     pub caps_lock: bool,
 }
 
+/// The data byte sent along with [HostToKeyboardCommand::SetRepeatRateAndDelay].
+///
+/// [More info](https://wiki.osdev.org/PS/2_Keyboard#Commands)
+#[bitfield(bits = 8)]
+#[derive(Debug, Clone, Copy)]
+pub struct TypematicByte {
+    /// `0b00000` is the fastest rate (30 Hz), `0b11111` is the slowest (2 Hz)
+    pub repeat_rate: B5,
+    pub delay: TypematicDelay,
+    #[allow(dead_code)]
+    must_be_zero: B1,
+}
+
+/// The delay before a held key begins to auto-repeat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
+#[bits = 2]
+pub enum TypematicDelay {
+    _250ms = 0,
+    _500ms = 1,
+    _750ms = 2,
+    _1000ms = 3,
+}
+
 // Note: with hardware translation on, these would be:
 // Set1 = 0x43, Set2 = 0x41, Set3 = 0x3f
 // but we're not using scancode translation.
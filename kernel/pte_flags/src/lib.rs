@@ -142,6 +142,17 @@ bitflags! {
         //
         // This does not require a conversion between architectures.
         const EXCLUSIVE = PteFlagsArch::EXCLUSIVE.bits();
+
+        /// Note: like `EXCLUSIVE`, this flag is managed by Theseus's memory management functions
+        ///       and is ignored when passed to them.
+        ///
+        /// * If set, this read-only page shares its frame with other pages copy-on-write,
+        ///   i.e., the first write to this page gives it a private copy of that frame
+        ///   and makes it writable, rather than causing a fatal page fault.
+        /// * If not set, writing to this page faults as normal if it is read-only.
+        //
+        // This does not require a conversion between architectures.
+        const COPY_ON_WRITE = PteFlagsArch::COPY_ON_WRITE.bits();
     }
 }
 
@@ -248,6 +259,16 @@ impl PteFlags {
         self
     }
 
+    /// Returns a copy of this `PteFlags` with the `COPY_ON_WRITE` bit set or cleared.
+    ///
+    /// * If `enable` is `true`, writing to this read-only page will give it a private copy of its frame.
+    /// * If `enable` is `false`, writing to this page will fault as normal if it is read-only.
+    #[must_use]
+    pub fn copy_on_write(mut self, enable: bool) -> Self {
+        self.set(Self::COPY_ON_WRITE, enable);
+        self
+    }
+
     /// Returns a copy of this `PteFlags` with the `ACCESSED` bit set or cleared.
     ///
     /// Typically this is used to clear the `ACCESSED` bit, in order to indicate
@@ -308,4 +329,8 @@ impl PteFlags {
     pub const fn is_exclusive(&self) -> bool {
         self.contains(Self::EXCLUSIVE)
     }
+
+    pub const fn is_copy_on_write(&self) -> bool {
+        self.contains(Self::COPY_ON_WRITE)
+    }
 }
@@ -185,6 +185,10 @@ bitflags! {
         /// See [PteFlags::EXCLUSIVE].
         ///  We use bit 55 because it is available for custom OS usage on both x86_64 and aarch64.
         const EXCLUSIVE          = 1 << 55;
+
+        /// See [PteFlags::COPY_ON_WRITE].
+        ///  We use bit 56 because it is available for custom OS usage on both x86_64 and aarch64.
+        const COPY_ON_WRITE      = 1 << 56;
     }
 }
 
@@ -291,6 +295,16 @@ impl PteFlagsAarch64 {
         self
     }
 
+    /// Returns a copy of this `PteFlagsAarch64` with the `COPY_ON_WRITE` bit set or cleared.
+    ///
+    /// * If `enable` is `true`, writing to this read-only page will give it a private copy of its frame.
+    /// * If `enable` is `false`, writing to this page will fault as normal if it is read-only.
+    #[must_use]
+    pub fn copy_on_write(mut self, enable: bool) -> Self {
+        self.set(Self::COPY_ON_WRITE, enable);
+        self
+    }
+
     /// Returns a copy of this `PteFlagsAarch64` with the `ACCESSED` bit set or cleared.
     ///
     /// Typically this is used to clear the `ACCESSED` bit, in order to indicate
@@ -351,6 +365,10 @@ impl PteFlagsAarch64 {
     pub const fn is_exclusive(&self) -> bool {
         self.contains(Self::EXCLUSIVE)
     }
+
+    pub const fn is_copy_on_write(&self) -> bool {
+        self.contains(Self::COPY_ON_WRITE)
+    }
 }
 
 /// Functions specific to aarch64 PTE flags only.
@@ -386,6 +404,7 @@ impl PteFlagsAarch64 {
     pub fn adjust_for_higher_level_pte(self) -> Self {
         self.executable(true)
             .exclusive(false)
+            .copy_on_write(false)
             .accessed(true)
             .page_descriptor(true)
             .valid(true)
@@ -12,7 +12,8 @@
 //! - `TSC`
 //!
 //! An error will be logged if the `TSC` is used as it is not a high quality
-//! source of randomness.
+//! source of randomness. Consumers that generate long-lived secrets should check
+//! [`is_hardware_seeded`] first.
 //!
 //! If a consumer requires one-off randomness, [`next_u32`], [`next_u64`], or
 //! [`fill_bytes`] should be used. Otherwise, [`init_rng`] should be used to
@@ -26,6 +27,7 @@ use rand_chacha::{
     rand_core::{RngCore, SeedableRng},
     ChaCha20Rng,
 };
+use core::sync::atomic::{AtomicBool, Ordering};
 use spin::mutex::Mutex;
 
 pub use rand_chacha::rand_core::Error;
@@ -41,13 +43,30 @@ lazy_static::lazy_static! {
     /// Using a single global CSPRNG allows us to feed it with entropy from
     /// device drivers and such.
     static ref CSPRNG: Mutex<ChaCha20Rng> = {
-        let seed = rdseed_seed()
-            .or_else(rdrand_seed)
-            .unwrap_or_else(tsc_seed);
+        let seed = match rdseed_seed().or_else(rdrand_seed) {
+            Some(seed) => {
+                HARDWARE_SEEDED.store(true, Ordering::Relaxed);
+                seed
+            }
+            None => tsc_seed(),
+        };
         Mutex::new(ChaCha20Rng::from_seed(seed))
     };
 }
 
+/// Whether the global CSPRNG was seeded using `RDSEED` or `RDRAND`.
+static HARDWARE_SEEDED: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether the global CSPRNG was seeded by a hardware random number
+/// generator (`RDSEED` or `RDRAND`) rather than the `TSC`.
+///
+/// Consumers that generate long-lived secrets, e.g., encryption keys, should
+/// refuse to do so if this returns `false`.
+pub fn is_hardware_seeded() -> bool {
+    lazy_static::initialize(&CSPRNG);
+    HARDWARE_SEEDED.load(Ordering::Relaxed)
+}
+
 /// Tries to generate a 32 byte seed using the RDSEED x86 instruction.
 fn rdseed_seed() -> Option<[u8; 32]> {
     match rdrand::RdSeed::new() {
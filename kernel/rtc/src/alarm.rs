@@ -0,0 +1,156 @@
+//! Programmable RTC alarms, delivered as events.
+//!
+//! The RTC has a single hardware alarm that fires once the RTC's time of day matches
+//! the programmed hours, minutes, and seconds.
+//! We multiplex any number of software alarms onto it by always programming
+//! the hardware alarm for the earliest pending software alarm.
+//! When the alarm interrupt occurs, every alarm that is due is delivered
+//! as an [`RtcAlarmEvent`] onto the queue that was given when that alarm was set.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use mpmc::Queue;
+use sync_irq::IrqSafeMutex;
+use x86_64::structures::idt::InterruptStackFrame;
+use super::{acknowledge_interrupt, disable_alarm, program_alarm, DateTime};
+
+/// The RTC is connected to IRQ 8.
+/// Because we perform the typical PIC remapping, the remapped IRQ vector number is 0x28.
+const RTC_IRQ: u8 = interrupts::IRQ_BASE_OFFSET + 0x8;
+
+/// All pending alarms, keyed by their UTC time (as a Unix timestamp) and then by ID.
+///
+/// This is accessed from both the interrupt handler and regular tasks,
+/// so it must be protected by an IRQ-safe lock.
+static ALARMS: IrqSafeMutex<BTreeMap<(i64, AlarmId), Queue<RtcAlarmEvent>>> = IrqSafeMutex::new(BTreeMap::new());
+static NEXT_ALARM_ID: AtomicU64 = AtomicU64::new(1);
+static ALARMS_INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// The unique identifier of an alarm set via [`set_alarm()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AlarmId(u64);
+
+/// The event that is delivered when an alarm fires.
+#[derive(Clone, Copy, Debug)]
+pub struct RtcAlarmEvent {
+    /// The ID of the alarm that fired.
+    pub id: AlarmId,
+    /// The UTC time that the alarm was set for.
+    pub time: DateTime,
+}
+
+/// Registers the RTC alarm interrupt handler, which must be done before any alarms can be set.
+pub fn init_alarms() -> Result<(), &'static str> {
+    interrupts::register_interrupt(RTC_IRQ, rtc_alarm_handler).map_err(|e| {
+        error!("RTC IRQ {:#X} was already in use by handler {:#X}! Sharing IRQs is currently unsupported.", RTC_IRQ, e);
+        "RTC IRQ was already in use! Sharing IRQs is currently unsupported."
+    })?;
+    // Clear any stale interrupt so that the RTC can raise new ones.
+    acknowledge_interrupt();
+    ALARMS_INITIALIZED.store(true, Ordering::Release);
+    reprogram_alarms();
+    Ok(())
+}
+
+/// Sets an alarm that fires at the given UTC `time`,
+/// upon which an [`RtcAlarmEvent`] is pushed onto the given `queue`.
+///
+/// Returns the ID of the new alarm, which can be used to cancel it via [`cancel_alarm()`].
+pub fn set_alarm(time: DateTime, queue: Queue<RtcAlarmEvent>) -> Result<AlarmId, &'static str> {
+    if !ALARMS_INITIALIZED.load(Ordering::Acquire) {
+        return Err("RTC alarms have not been initialized");
+    }
+    if !time.is_valid() {
+        return Err("cannot set an alarm for an invalid date and time");
+    }
+    let timestamp = time.to_unix_timestamp();
+    if timestamp <= rtc_now_utc().to_unix_timestamp() {
+        return Err("cannot set an alarm for a time that has already passed");
+    }
+    let id = AlarmId(NEXT_ALARM_ID.fetch_add(1, Ordering::Relaxed));
+    let mut alarms = ALARMS.lock();
+    alarms.insert((timestamp, id), queue);
+    program_earliest_alarm(&alarms);
+    Ok(id)
+}
+
+/// Cancels the alarm with the given `id`.
+///
+/// Returns `true` if that alarm was pending, or `false` if it had already fired or didn't exist.
+pub fn cancel_alarm(id: AlarmId) -> bool {
+    let mut alarms = ALARMS.lock();
+    let key = alarms.keys().find(|(_, alarm_id)| *alarm_id == id).cloned();
+    let removed = key.and_then(|key| alarms.remove(&key)).is_some();
+    if removed {
+        program_earliest_alarm(&alarms);
+    }
+    removed
+}
+
+/// Returns the IDs and UTC times of all pending alarms, earliest first.
+pub fn pending_alarms() -> Vec<(AlarmId, DateTime)> {
+    ALARMS.lock().keys()
+        .map(|&(timestamp, id)| (id, DateTime::from_unix_timestamp(timestamp)))
+        .collect()
+}
+
+/// Re-programs the hardware alarm for the earliest pending alarm,
+/// e.g., after the RTC's time or the timezone settings have changed.
+pub(crate) fn reprogram_alarms() {
+    if ALARMS_INITIALIZED.load(Ordering::Acquire) {
+        program_earliest_alarm(&ALARMS.lock());
+    }
+}
+
+/// Returns the current time according to the RTC itself, converted to UTC.
+///
+/// Alarms are checked against the RTC rather than the wall clock
+/// because the hardware alarm fires based on the RTC's time.
+fn rtc_now_utc() -> DateTime {
+    let rtc_time = super::read_datetime();
+    if super::rtc_holds_local_time() { rtc_time.local_to_utc() } else { rtc_time }
+}
+
+/// Programs the hardware alarm for the earliest of the given `alarms`,
+/// or disables it if there are no pending alarms.
+///
+/// Because the hardware alarm only matches the time of day, an alarm that is more than
+/// a day away may cause spurious interrupts, upon which we simply re-program it.
+fn program_earliest_alarm(alarms: &BTreeMap<(i64, AlarmId), Queue<RtcAlarmEvent>>) {
+    match alarms.keys().next() {
+        Some(&(timestamp, _)) => {
+            let utc = DateTime::from_unix_timestamp(timestamp);
+            let rtc_time = if super::rtc_holds_local_time() { utc.utc_to_local() } else { utc };
+            program_alarm(rtc_time.hour, rtc_time.minute, rtc_time.second);
+        }
+        None => disable_alarm(),
+    }
+}
+
+/// Delivers every alarm that is due and then re-programs the hardware alarm.
+fn fire_due_alarms() {
+    let now = rtc_now_utc().to_unix_timestamp();
+    let mut alarms = ALARMS.lock();
+    while let Some(&(timestamp, id)) = alarms.keys().next() {
+        if timestamp > now {
+            break;
+        }
+        if let Some(queue) = alarms.remove(&(timestamp, id)) {
+            let event = RtcAlarmEvent { id, time: DateTime::from_unix_timestamp(timestamp) };
+            if queue.push(event).is_err() {
+                warn!("RTC alarm {:?} fired, but its event queue was full", id);
+            }
+        }
+    }
+    program_earliest_alarm(&alarms);
+}
+
+/// The RTC interrupt handler, which is only used for alarms.
+extern "x86-interrupt" fn rtc_alarm_handler(_stack_frame: InterruptStackFrame) {
+    // Reading status register C is required to receive any further RTC interrupts.
+    if acknowledge_interrupt() {
+        fire_due_alarms();
+    }
+    interrupts::eoi(RTC_IRQ);
+}
@@ -0,0 +1,222 @@
+//! Calendar dates and times, conversions to and from Unix time, and timezone settings.
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+/// The offset of local time from UTC, in seconds east of UTC.
+static UTC_OFFSET_SECONDS: AtomicI32 = AtomicI32::new(0);
+/// Whether the RTC hardware holds local time rather than UTC,
+/// which is common on machines that also run Windows.
+static RTC_HOLDS_LOCAL_TIME: AtomicBool = AtomicBool::new(false);
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// A calendar date and time of day in the proleptic Gregorian calendar,
+/// with no associated timezone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    pub year: u16,
+    /// From 1 to 12.
+    pub month: u8,
+    /// From 1 to the number of days in `month`.
+    pub day: u8,
+    /// From 0 to 23.
+    pub hour: u8,
+    /// From 0 to 59.
+    pub minute: u8,
+    /// From 0 to 59.
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Returns `true` if every field of this `DateTime` is within its valid range.
+    pub fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+
+    /// Converts the given number of seconds since the Unix epoch (1970-01-01 00:00:00)
+    /// into a `DateTime`.
+    pub fn from_unix_timestamp(seconds: i64) -> DateTime {
+        let days = seconds.div_euclid(SECONDS_PER_DAY);
+        let seconds_of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year: year as u16,
+            month,
+            day,
+            hour: (seconds_of_day / 3600) as u8,
+            minute: (seconds_of_day / 60 % 60) as u8,
+            second: (seconds_of_day % 60) as u8,
+        }
+    }
+
+    /// Converts this `DateTime` into the number of seconds since the Unix epoch,
+    /// treating it as a UTC time.
+    pub fn to_unix_timestamp(&self) -> i64 {
+        let days = days_from_civil(self.year as i64, self.month, self.day);
+        days * SECONDS_PER_DAY
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64
+    }
+
+    /// Returns this UTC `DateTime` converted to local time, using the current [`utc_offset()`].
+    pub fn utc_to_local(&self) -> DateTime {
+        DateTime::from_unix_timestamp(self.to_unix_timestamp() + utc_offset() as i64)
+    }
+
+    /// Returns this local `DateTime` converted to UTC, using the current [`utc_offset()`].
+    pub fn local_to_utc(&self) -> DateTime {
+        DateTime::from_unix_timestamp(self.to_unix_timestamp() - utc_offset() as i64)
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Returns the offset of local time from UTC, in seconds east of UTC.
+pub fn utc_offset() -> i32 {
+    UTC_OFFSET_SECONDS.load(Ordering::Relaxed)
+}
+
+/// Sets the offset of local time from UTC, in seconds east of UTC,
+/// e.g., `-5 * 3600` for UTC-05:00.
+///
+/// If the RTC holds local time, this also affects how the RTC's time is interpreted,
+/// so [`init_wall_clock()`](crate::init_wall_clock) should be called again afterwards.
+pub fn set_utc_offset(seconds_east: i32) -> Result<(), &'static str> {
+    // Real-world UTC offsets range from UTC-12:00 to UTC+14:00.
+    if !(-12 * 3600 ..= 14 * 3600).contains(&seconds_east) {
+        return Err("UTC offset must be between -12 and +14 hours");
+    }
+    UTC_OFFSET_SECONDS.store(seconds_east, Ordering::Relaxed);
+    super::alarm::reprogram_alarms();
+    Ok(())
+}
+
+/// Returns `true` if the RTC hardware is treated as holding local time rather than UTC.
+pub fn rtc_holds_local_time() -> bool {
+    RTC_HOLDS_LOCAL_TIME.load(Ordering::Relaxed)
+}
+
+/// Sets whether the RTC hardware holds local time (`true`) or UTC (`false`, the default).
+///
+/// Like [`set_utc_offset()`], [`init_wall_clock()`](crate::init_wall_clock) should be called
+/// again afterwards to re-interpret the RTC's time.
+pub fn set_rtc_holds_local_time(local: bool) {
+    RTC_HOLDS_LOCAL_TIME.store(local, Ordering::Relaxed);
+    super::alarm::reprogram_alarms();
+}
+
+fn is_leap_year(year: u16) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// Returns the number of days since the Unix epoch for the given civil date.
+///
+/// This is Howard Hinnant's `days_from_civil` algorithm.
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the civil date (year, month, day) for the given number of days since the Unix epoch.
+///
+/// This is Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u8;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime { year, month, day, hour, minute, second }
+    }
+
+    #[test]
+    fn test_unix_timestamp_round_trip() {
+        let cases = [
+            (0, datetime(1970, 1, 1, 0, 0, 0)),
+            (-1, datetime(1969, 12, 31, 23, 59, 59)),
+            (951_782_400, datetime(2000, 2, 29, 0, 0, 0)),
+            (1_709_210_096, datetime(2024, 2, 29, 12, 34, 56)),
+            (2_147_483_647, datetime(2038, 1, 19, 3, 14, 7)),
+            (4_107_542_399, datetime(2100, 2, 28, 23, 59, 59)),
+            (4_107_542_400, datetime(2100, 3, 1, 0, 0, 0)),
+            (-2_208_988_800, datetime(1900, 1, 1, 0, 0, 0)),
+        ];
+        for (timestamp, expected) in cases {
+            assert_eq!(DateTime::from_unix_timestamp(timestamp), expected);
+            assert_eq!(expected.to_unix_timestamp(), timestamp);
+        }
+    }
+
+    #[test]
+    fn test_days_round_trip() {
+        // Every day from 1900-01-01 to 2199-12-31 maps to the following calendar day.
+        let first = days_from_civil(1900, 1, 1);
+        let last = days_from_civil(2199, 12, 31);
+        let mut previous = civil_from_days(first - 1);
+        for days in first..=last {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+            assert!(datetime(year as u16, month, day, 0, 0, 0).is_valid());
+            if day == 1 {
+                assert_eq!(previous.2, days_in_month(previous.0 as u16, previous.1));
+            } else {
+                assert_eq!((year, month, day - 1), previous);
+            }
+            previous = (year, month, day);
+        }
+    }
+
+    #[test]
+    fn test_leap_years() {
+        assert!(is_leap_year(2000));
+        assert!(is_leap_year(2024));
+        assert!(!is_leap_year(1900));
+        assert!(!is_leap_year(2100));
+        assert!(!is_leap_year(2023));
+        assert!(datetime(2024, 2, 29, 0, 0, 0).is_valid());
+        assert!(!datetime(2100, 2, 29, 0, 0, 0).is_valid());
+        assert!(!datetime(2023, 4, 31, 0, 0, 0).is_valid());
+        assert!(!datetime(2023, 13, 1, 0, 0, 0).is_valid());
+        assert!(!datetime(2023, 1, 1, 24, 0, 0).is_valid());
+    }
+}
@@ -3,20 +3,32 @@
 #![feature(abi_x86_interrupt)]
 #![feature(fn_traits)]
 
-// extern crate alloc;
+extern crate alloc;
 #[macro_use] extern crate lazy_static;
 extern crate port_io;
-extern crate irq_safety;
 extern crate spin;
 extern crate state_store;
 #[macro_use] extern crate log;
 extern crate x86_64;
+extern crate interrupts;
+extern crate mpmc;
+extern crate sync_irq;
+extern crate time;
+extern crate acpi;
+extern crate fadt;
+
+mod alarm;
+mod calendar;
+mod wall_clock;
+
+pub use alarm::{AlarmId, RtcAlarmEvent, init_alarms, set_alarm, cancel_alarm, pending_alarms};
+pub use calendar::{DateTime, utc_offset, set_utc_offset, rtc_holds_local_time, set_rtc_holds_local_time};
+pub use wall_clock::{RtcWallClock, init_wall_clock, now_utc, now_local, set_wall_time};
 
 use port_io::Port;
-use irq_safety::hold_interrupts;
 use core::sync::atomic::{AtomicUsize, Ordering};
-use spin::Mutex;
-// use spin::Once;
+use spin::Once;
+use sync_irq::IrqSafeMutex;
 use state_store::{get_state, insert_state, SSCached};
 
 
@@ -25,12 +37,73 @@ const CMOS_WRITE_PORT: u16 = 0x70;
 //standard port to read register values from on CMOS or write to to change settings
 const CMOS_READ_PORT: u16 = 0x71;
 
-//used to select register
-static CMOS_WRITE: Mutex<Port<u8>> = Mutex::new( Port::new(CMOS_WRITE_PORT));
-//used to change cmos settings
-static CMOS_WRITE_SETTINGS: Mutex<Port<u8>> = Mutex::new(Port::new(CMOS_READ_PORT));
-//used to read from cmos register
-static CMOS_READ: Mutex<Port<u8>> = Mutex::new( Port::new(CMOS_READ_PORT));
+/// The CMOS ports through which all RTC registers are accessed.
+///
+/// Each register access selects a register via the index port and then accesses the data port,
+/// so both ports are protected by a single lock that must be held across each such sequence,
+/// preventing other CPUs and the RTC interrupt handler from selecting a different register in between.
+static CMOS: IrqSafeMutex<Cmos> = IrqSafeMutex::new(Cmos {
+    index: Port::new(CMOS_WRITE_PORT),
+    data: Port::new(CMOS_READ_PORT),
+});
+
+struct Cmos {
+    /// Used to select a register.
+    index: Port<u8>,
+    /// Used to read from or write to the selected register.
+    data: Port<u8>,
+}
+
+impl Cmos {
+    /// Reads the raw value of the given RTC `register`, without any BCD conversion.
+    fn read_register(&self, register: u8) -> u8 {
+        unsafe { self.index.write(NMI_DISABLE | register) };
+        self.data.read()
+    }
+
+    /// Writes the raw `value` to the given RTC `register`, without any BCD conversion.
+    fn write_register(&self, register: u8, value: u8) {
+        unsafe {
+            self.index.write(NMI_DISABLE | register);
+            self.data.write(value);
+        }
+    }
+
+    /// Returns `true` if the RTC is currently updating its time registers.
+    fn is_update_in_progress(&self) -> bool {
+        // bit 7 of status register A is set while the rtc is updating its time registers
+        self.read_register(REG_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0
+    }
+}
+
+// RTC register numbers
+const REG_SECONDS: u8 = 0x00;
+const REG_ALARM_SECONDS: u8 = 0x01;
+const REG_MINUTES: u8 = 0x02;
+const REG_ALARM_MINUTES: u8 = 0x03;
+const REG_HOURS: u8 = 0x04;
+const REG_ALARM_HOURS: u8 = 0x05;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+const REG_STATUS_C: u8 = 0x0C;
+
+/// Selecting a register with this bit set also disables NMIs while we access the RTC.
+const NMI_DISABLE: u8 = 0x80;
+
+// Status register A bits
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 0x80;
+// Status register B bits
+const STATUS_B_SET: u8 = 0x80;
+const STATUS_B_ALARM_INTERRUPT_ENABLE: u8 = 0x20;
+const STATUS_B_BINARY_MODE: u8 = 0x04;
+const STATUS_B_24_HOUR: u8 = 0x02;
+// Status register C bits
+const STATUS_C_ALARM_FLAG: u8 = 0x20;
+/// In 12-hour mode, this bit of the hours register indicates PM.
+const HOURS_PM: u8 = 0x80;
 
 
 type RtcTicks = AtomicUsize;
@@ -57,41 +130,112 @@ pub type RtcInterruptFunction = fn(Option<usize>);
 // }
 
 
-//write a u8 to the CMOS port (0x70)
-fn write_cmos(value: u8) {
-    unsafe{
-        CMOS_WRITE.lock().write(value)
+/// Converts a time register value from the RTC's format into binary,
+/// based on the given value of status register B.
+fn decode_value(value: u8, status_b: u8) -> u8 {
+    if status_b & STATUS_B_BINARY_MODE != 0 {
+        value
+    } else {
+        (value / 16) * 10 + (value & 0xf)
+    }
+}
+
+
+/// Converts a binary time value into the RTC's format,
+/// based on the given value of status register B.
+fn encode_value(value: u8, status_b: u8) -> u8 {
+    if status_b & STATUS_B_BINARY_MODE != 0 {
+        value
+    } else {
+        ((value / 10) << 4) | (value % 10)
+    }
+}
+
+
+/// Converts an hours register value from the RTC's format into a binary 24-hour value.
+fn decode_hours(value: u8, status_b: u8) -> u8 {
+    if status_b & STATUS_B_24_HOUR != 0 {
+        return decode_value(value, status_b);
+    }
+    // In 12-hour mode, 12 AM is midnight and 12 PM is noon.
+    let hours = decode_value(value & !HOURS_PM, status_b) % 12;
+    if value & HOURS_PM != 0 { hours + 12 } else { hours }
+}
+
+
+/// Converts a binary 24-hour value into the RTC's hours register format.
+fn encode_hours(hours: u8, status_b: u8) -> u8 {
+    if status_b & STATUS_B_24_HOUR != 0 {
+        return encode_value(hours, status_b);
     }
+    let twelve_hour = match hours % 12 { 0 => 12, h => h };
+    let pm = if hours >= 12 { HOURS_PM } else { 0 };
+    encode_value(twelve_hour, status_b) | pm
 }
 
 
-//read a u8 from CMOS port 0x71
-fn read_cmos() -> u8{
-    CMOS_READ.lock().read()
+/// The raw (but decoded) contents of the RTC's time registers.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct RawRtcTime {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u8,
+    months: u8,
+    years: u8,
+    /// `None` if the RTC has no century register.
+    century: Option<u8>,
 }
 
 
+/// The index of the RTC's century register, obtained once from the FADT.
+static CENTURY_REGISTER: Once<Option<u8>> = Once::new();
 
-//returns true if update in progress, false otherwise
-fn is_update_in_progress() -> bool{
-    //writing to this register causes cmos to output 1 if rtc update in progress 
-    write_cmos(0x0A);
-    let is_in_progress: bool = read_cmos() == 1;
-    is_in_progress
+/// Returns the index of the RTC's century register, or `None` if the RTC doesn't have one.
+///
+/// The FADT's `century` field specifies this index, in which a value of `0`
+/// (or a missing FADT) means that the century register isn't supported.
+fn century_register() -> Option<u8> {
+    *CENTURY_REGISTER.call_once(|| {
+        let acpi_tables = acpi::get_acpi_tables().lock();
+        fadt::Fadt::get(&acpi_tables)
+            .map(|fadt| fadt.century)
+            .filter(|&index| index != 0)
+    })
 }
 
 
-//register value is entered, rtc's associated value is output, waits for update in progress signal to end
-fn read_register(register: u8) -> u8{
-    
+/// Reads all of the RTC's time registers once an update is not in progress,
+/// including the century register at the given index, if any.
+fn read_registers_once(century_register: Option<u8>) -> RawRtcTime {
+    let cmos = CMOS.lock();
     //waits for "update in progress" signal to finish in order to read correct values
-    while is_update_in_progress() {}
-    write_cmos(register);
+    while cmos.is_update_in_progress() {}
+    let status_b = cmos.read_register(REG_STATUS_B);
+    RawRtcTime {
+        seconds: decode_value(cmos.read_register(REG_SECONDS), status_b),
+        minutes: decode_value(cmos.read_register(REG_MINUTES), status_b),
+        hours:   decode_hours(cmos.read_register(REG_HOURS), status_b),
+        days:    decode_value(cmos.read_register(REG_DAY), status_b),
+        months:  decode_value(cmos.read_register(REG_MONTH), status_b),
+        years:   decode_value(cmos.read_register(REG_YEAR), status_b),
+        century: century_register.map(|reg| decode_value(cmos.read_register(reg), status_b)),
+    }
+}
+
 
-    //converts bcd value to binary value which is what is used for printing 
-    let bcd = read_cmos();
-    
-    (bcd/16)*10 + (bcd & 0xf)
+/// Reads all of the RTC's time registers, repeating the read until two consecutive reads agree
+/// such that we never observe a partially-updated time.
+fn read_registers() -> RawRtcTime {
+    let century_register = century_register();
+    let mut previous = read_registers_once(century_register);
+    loop {
+        let current = read_registers_once(century_register);
+        if current == previous {
+            return current;
+        }
+        previous = current;
+    }
 }
 
 /// A timestamp obtained from the real-time clock.
@@ -114,23 +258,94 @@ impl fmt::Display for RtcTime {
 
 //call this function to print RTC's date and time
 pub fn read_rtc() -> RtcTime {
+    let raw = read_registers();
+    RtcTime {
+        seconds: raw.seconds,
+        minutes: raw.minutes,
+        hours: raw.hours,
+        days: raw.days,
+        months: raw.months,
+        years: raw.years,
+    }
+}
 
-    //calls read register function which writes to port 0x70 to set RTC then reads from 0x71 which outputs correct value
-    let second = read_register(0x00);
-    let minute = read_register(0x02);
-    let hour = read_register(0x04);
-    let day = read_register(0x07);
-    let month = read_register(0x08);
-    let year = read_register(0x09);
+/// Reads the date and time currently held by the RTC, including the full year.
+///
+/// This is exactly what the RTC holds, which may be either UTC or local time;
+/// see [`rtc_holds_local_time()`]. Most callers should use [`now_utc()`] or [`now_local()`] instead.
+///
+/// If the RTC has no valid century register, the 21st century is assumed.
+pub fn read_datetime() -> DateTime {
+    let raw = read_registers();
+    let century = match raw.century {
+        Some(century) if (19..=21).contains(&century) => century as u16,
+        _ => 20,
+    };
+    DateTime {
+        year: century * 100 + raw.years as u16,
+        month: raw.months,
+        day: raw.days,
+        hour: raw.hours,
+        minute: raw.minutes,
+        second: raw.seconds,
+    }
+}
 
-    RtcTime {
-        seconds: second, 
-        minutes: minute, 
-        hours: hour, 
-        days: day, 
-        months: month, 
-        years: year
+/// Writes the given date and time into the RTC.
+///
+/// The given `datetime` is written as-is, so it should be in UTC or local time
+/// depending on [`rtc_holds_local_time()`]. Most callers should use [`set_wall_time()`] instead.
+///
+/// If the RTC has no century register, only years in the 21st century can be written.
+pub fn write_datetime(datetime: &DateTime) -> Result<(), &'static str> {
+    if !datetime.is_valid() || !(1900..2200).contains(&datetime.year) {
+        return Err("cannot write an invalid date and time into the RTC");
+    }
+    let century_register = century_register();
+    if century_register.is_none() && !(2000..2100).contains(&datetime.year) {
+        return Err("the RTC has no century register, so it can only hold years 2000 to 2099");
+    }
+    let cmos = CMOS.lock();
+    let status_b = cmos.read_register(REG_STATUS_B);
+    // Setting the SET bit halts RTC updates while we write the time registers.
+    cmos.write_register(REG_STATUS_B, status_b | STATUS_B_SET);
+    cmos.write_register(REG_SECONDS, encode_value(datetime.second, status_b));
+    cmos.write_register(REG_MINUTES, encode_value(datetime.minute, status_b));
+    cmos.write_register(REG_HOURS,   encode_hours(datetime.hour, status_b));
+    cmos.write_register(REG_DAY,     encode_value(datetime.day, status_b));
+    cmos.write_register(REG_MONTH,   encode_value(datetime.month, status_b));
+    cmos.write_register(REG_YEAR,    encode_value((datetime.year % 100) as u8, status_b));
+    if let Some(reg) = century_register {
+        cmos.write_register(reg, encode_value((datetime.year / 100) as u8, status_b));
     }
+    cmos.write_register(REG_STATUS_B, status_b & !STATUS_B_SET);
+    Ok(())
+}
+
+/// Programs the RTC's hardware alarm to fire when the RTC's time next reaches
+/// the given hours, minutes, and seconds, and enables the alarm interrupt.
+fn program_alarm(hour: u8, minute: u8, second: u8) {
+    let cmos = CMOS.lock();
+    let status_b = cmos.read_register(REG_STATUS_B);
+    cmos.write_register(REG_ALARM_SECONDS, encode_value(second, status_b));
+    cmos.write_register(REG_ALARM_MINUTES, encode_value(minute, status_b));
+    cmos.write_register(REG_ALARM_HOURS,   encode_hours(hour, status_b));
+    cmos.write_register(REG_STATUS_B, status_b | STATUS_B_ALARM_INTERRUPT_ENABLE);
+}
+
+/// Disables the RTC's alarm interrupt.
+fn disable_alarm() {
+    let cmos = CMOS.lock();
+    let status_b = cmos.read_register(REG_STATUS_B);
+    cmos.write_register(REG_STATUS_B, status_b & !STATUS_B_ALARM_INTERRUPT_ENABLE);
+}
+
+/// Reads status register C, which acknowledges the pending RTC interrupt,
+/// and returns whether the alarm flag was set.
+///
+/// The RTC will not raise any further interrupts until register C has been read.
+fn acknowledge_interrupt() -> bool {
+    CMOS.lock().read_register(REG_STATUS_C) & STATUS_C_ALARM_FLAG != 0
 }
 
 /// Returns the current RTC tick count.
@@ -141,27 +356,17 @@ pub fn get_rtc_ticks() -> Option<usize> {
 /// turn on IRQ 8 (mapped to 0x28), rtc begins sending interrupts 
 pub fn enable_rtc_interrupt()
 {
-    let _held_interrupts = hold_interrupts();
+    let cmos = CMOS.lock();
 
-    write_cmos(0x0C);
-    read_cmos();
-    //select cmos register 0x8B
-    write_cmos(0x8B);
+    // reading register C acknowledges any pending interrupt
+    cmos.read_register(REG_STATUS_C);
 
     //value needed to turn on bit 6 of register B
-    let prev = read_cmos();
-
-    //we want it to go back to register 0x8B, it was reset when read
-    write_cmos(0x8B);
-
-    //here we don't use the cmos_write function because that only writes to port 0x70, in this case we need to write to 0x71
-    //writing to 0x71 because not selecting register, setting rtc
-    unsafe{
-        CMOS_WRITE_SETTINGS.lock().write(prev | 0x40); 
-    }
+    let prev = cmos.read_register(REG_STATUS_B);
+    cmos.write_register(REG_STATUS_B, prev | 0x40);
 
     trace!("RTC Enabled!");
-    // here: _held_interrupts falls out of scope, re-enabling interrupts if they were previously enabled.
+    // here: the CMOS lock is released, re-enabling interrupts if they were previously enabled.
 }
 
 
@@ -195,19 +400,14 @@ pub fn set_rtc_frequency(rate: usize) -> Result<(), InvalidRtcRate> {
     // formula is "rate = 32768 Hz >> (dividor - 1)"
     let dividor: u8 = log2(rate) as u8 + 2; 
 
-    let _held_interrupts = hold_interrupts();
+    let cmos = CMOS.lock();
 
     // bottom 4 bits of register A are the "rate dividor", setting them to rate we want without altering top 4 bits
-    write_cmos(0x8A);
-    let prev = read_cmos();
-    write_cmos(0x8A); 
-
-    unsafe{
-        CMOS_WRITE_SETTINGS.lock().write((prev & 0xF0) | dividor);
-    }
+    let prev = cmos.read_register(REG_STATUS_A);
+    cmos.write_register(REG_STATUS_A, (prev & 0xF0) | dividor);
 
     trace!("RTC frequency changed to {} Hz!", rate);
     Ok(())
-    
-    // here: _held_interrupts falls out of scope, re-enabling interrupts if they were previously enabled.
+
+    // here: the CMOS lock is released, re-enabling interrupts if they were previously enabled.
 }
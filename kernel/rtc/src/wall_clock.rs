@@ -0,0 +1,92 @@
+//! An RTC-backed wall clock, registered as the system's [`WallTime`] clock source.
+//!
+//! Reading the RTC hardware is slow and only has a resolution of one second,
+//! so we read it once upon initialization and then advance that base time
+//! using the monotonic clock. This ensures that every caller of
+//! `time::now::<WallTime>()` observes the same, consistent wall-clock time.
+
+use sync_irq::IrqSafeMutex;
+use time::{ClockSource, Duration, Instant, Period, WallTime};
+use super::DateTime;
+
+/// The RTC only counts whole seconds, i.e., 10^15 femtoseconds.
+const RTC_PERIOD_FEMTOS: u64 = 1_000_000_000_000_000;
+
+/// The wall-clock time (as a duration since the Unix epoch)
+/// at a given instant of the monotonic clock.
+struct WallClockBase {
+    unix_time: Duration,
+    at: Instant,
+}
+
+static WALL_CLOCK_BASE: IrqSafeMutex<WallClockBase> = IrqSafeMutex::new(WallClockBase {
+    unix_time: Duration::ZERO,
+    at: Instant::ZERO,
+});
+
+/// The wall clock source backed by the RTC; see [`init_wall_clock()`].
+pub struct RtcWallClock;
+
+impl ClockSource for RtcWallClock {
+    type ClockType = WallTime;
+
+    fn now() -> Duration {
+        let base = WALL_CLOCK_BASE.lock();
+        base.unix_time + Instant::now().duration_since(base.at)
+    }
+}
+
+/// Reads the current time from the RTC and registers the RTC as the system's wall clock source.
+///
+/// This must be called after a monotonic clock source has been registered.
+/// It can be called again to re-synchronize the wall clock with the RTC,
+/// e.g., after changing whether the RTC holds local time.
+pub fn init_wall_clock() -> Result<(), &'static str> {
+    let rtc_time = super::read_datetime();
+    if !rtc_time.is_valid() {
+        error!("RTC holds an invalid date and time: {:?}", rtc_time);
+        return Err("RTC holds an invalid date and time");
+    }
+    let utc = if super::rtc_holds_local_time() { rtc_time.local_to_utc() } else { rtc_time };
+    let unix_seconds = utc.to_unix_timestamp();
+    if unix_seconds < 0 {
+        return Err("RTC time is before the Unix epoch");
+    }
+    set_base(Duration::from_secs(unix_seconds as u64));
+    time::register_clock_source::<RtcWallClock>(Period::new(RTC_PERIOD_FEMTOS));
+    info!("Initialized RTC wall clock: {} UTC", utc);
+    Ok(())
+}
+
+/// Sets the system's wall-clock time to the given duration since the Unix epoch,
+/// e.g., after synchronizing with a network time server.
+///
+/// This also writes the new time into the RTC hardware (to whole-second precision),
+/// so that the RTC, the wall clock, and any pending alarms remain consistent.
+pub fn set_wall_time(unix_time: Duration) -> Result<(), &'static str> {
+    let seconds = i64::try_from(unix_time.as_secs()).map_err(|_| "wall-clock time is too large")?;
+    let utc = DateTime::from_unix_timestamp(seconds);
+    let rtc_time = if super::rtc_holds_local_time() { utc.utc_to_local() } else { utc };
+    super::write_datetime(&rtc_time)?;
+    set_base(unix_time);
+    super::alarm::reprogram_alarms();
+    Ok(())
+}
+
+/// Returns the current wall-clock time in UTC, according to the system's wall clock source.
+pub fn now_utc() -> DateTime {
+    DateTime::from_unix_timestamp(time::now::<WallTime>().as_secs() as i64)
+}
+
+/// Returns the current wall-clock time in local time, according to the system's
+/// wall clock source and the current [`utc_offset()`](super::utc_offset).
+pub fn now_local() -> DateTime {
+    now_utc().utc_to_local()
+}
+
+fn set_base(unix_time: Duration) {
+    *WALL_CLOCK_BASE.lock() = WallClockBase {
+        unix_time,
+        at: Instant::now(),
+    };
+}
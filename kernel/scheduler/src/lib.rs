@@ -10,6 +10,13 @@
 //! legacy compatbility, and to act as an easy landing page for code search.
 //! That means that a caller need only depend on [`task`], not this crate,
 //! to invoke the scheduler (yield the CPU) to switch to another task.
+//!
+//! ## Isolated CPUs
+//! CPUs can be isolated from general-purpose task scheduling via [`set_isolated()`],
+//! such that only tasks pinned to them run there.
+//! On x86_64, an isolated CPU that has only a single task to run stops its timer tick
+//! (i.e., it becomes "tickless"), such that the task runs without being interrupted.
+//! Its timer tick is restarted once that task yields or blocks, or another task is added to that CPU.
 
 #![no_std]
 #![cfg_attr(target_arch = "x86_64", feature(abi_x86_interrupt))]
@@ -17,7 +24,12 @@
 use interrupts::{self, CPU_LOCAL_TIMER_IRQ, interrupt_handler, eoi, EoiBehaviour};
 
 /// Re-exports for convenience and legacy compatibility.
-pub use task::scheduler::{inherit_priority, priority, schedule, set_priority};
+pub use task::scheduler::{
+    inherit_priority, priority, schedule, set_priority,
+    base_priority, boost_priority, PriorityBoost,
+    set_deadline, clear_deadline, deadline, DeadlineParams,
+    set_isolated, is_isolated, isolated_cpus,
+};
 
 
 /// Initializes the scheduler on this system using the policy set at compiler time.
@@ -29,8 +41,10 @@ pub use task::scheduler::{inherit_priority, priority, schedule, set_priority};
 /// - `make`: round-robin scheduler
 /// - `make THESEUS_CONFIG=epoch_scheduler`: epoch scheduler
 /// - `make THESEUS_CONFIG=priority_scheduler`: priority scheduler
+/// - `make THESEUS_CONFIG=edf_scheduler`: earliest-deadline-first scheduler
 pub fn init() -> Result<(), &'static str> {
     #[cfg(target_arch = "x86_64")] {
+        task::scheduler::set_kick_cpu_func(kick_cpu);
         interrupts::register_interrupt(
             CPU_LOCAL_TIMER_IRQ,
             timer_tick_handler,
@@ -60,6 +74,18 @@ interrupt_handler!(timer_tick_handler, _, _stack_frame, {
         log::info!("(CPU {}) CPU-LOCAL TIMER HANDLER! TICKS = {}", cpu::current_cpu(), _ticks);
     }
 
+    // Let the watchdog know that this CPU is still alive, and whether it can still schedule.
+    #[cfg(target_arch = "x86_64")]
+    let interrupted_ip = Some(_stack_frame.instruction_pointer.as_u64() as usize);
+    #[cfg(target_arch = "aarch64")]
+    let interrupted_ip = None;
+    watchdog::pet(
+        cpu::current_cpu(),
+        preemption::preemption_enabled(),
+        interrupted_ip,
+        task::get_my_current_task_id(),
+    );
+
     // Inform the `sleep` crate that it should update its inner tick count
     // in order to unblock any tasks that are done sleeping.
     sleep::unblock_sleeping_tasks();
@@ -68,11 +94,44 @@ interrupt_handler!(timer_tick_handler, _, _stack_frame, {
     // because we switch tasks here, which doesn't return.
     eoi(CPU_LOCAL_TIMER_IRQ);
 
-    schedule();
+    if !stop_tick_if_possible() {
+        schedule();
+    }
 
     EoiBehaviour::HandlerSentEoi
 });
 
+/// Stops the timer tick on this CPU if it's isolated and has only a single task to run,
+/// or restarts it if it was stopped but that's no longer the case.
+///
+/// Returns `true` if the timer tick is (still) stopped, in which case there is no need to schedule.
+#[cfg(target_arch = "x86_64")]
+fn stop_tick_if_possible() -> bool {
+    if task::scheduler::can_stop_tick() {
+        watchdog::tick_stopped(cpu::current_cpu());
+        preemption::stop_tick();
+        true
+    } else {
+        // This CPU may have been kicked by another CPU because another task needs to run here.
+        preemption::restart_tick();
+        false
+    }
+}
+
+/// Stopping the timer tick isn't supported on aarch64; see [`preemption::stop_tick()`].
+#[cfg(target_arch = "aarch64")]
+fn stop_tick_if_possible() -> bool {
+    false
+}
+
+/// Sends a timer interrupt to the given CPU, which restarts its timer tick if it was stopped.
+#[cfg(target_arch = "x86_64")]
+fn kick_cpu(cpu_id: cpu::CpuId) {
+    if let Some(my_apic) = apic::get_my_apic() {
+        my_apic.write().send_ipi(CPU_LOCAL_TIMER_IRQ, apic::LapicIpiDestination::One(cpu_id.into()));
+    }
+}
+
 
 /// Returns the (cached) number of system timer ticks needed for the scheduling timeslice interval.
 ///
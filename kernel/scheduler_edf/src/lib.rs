@@ -0,0 +1,247 @@
+//! This crate implements an earliest-deadline-first (EDF) scheduling policy
+//! for soft real-time tasks, e.g., audio mixing or sampling drivers.
+//!
+//! A task becomes a real-time task by being given a reservation via
+//! [`task::scheduler::set_deadline()`]: it is guaranteed to run for `budget`
+//! in every `period`, the end of which is its current deadline.
+//! All other tasks are best-effort tasks.
+//!
+//! * The runnable real-time task with the earliest deadline that still has budget
+//!   left in its current period always runs first.
+//! * A real-time task that has used up its budget is throttled until its next period
+//!   begins, so a misbehaving task can't starve anyone else.
+//! * Best-effort tasks are scheduled round-robin whenever no real-time task can run.
+//!
+//! # Admission control
+//! A reservation is only admitted if the sum of `budget / period` across all
+//! real-time tasks on the CPU stays at or below [`MAX_UTILIZATION`], under which EDF
+//! meets every deadline and best-effort tasks keep a share of the CPU.
+//!
+//! Budgets are accounted at scheduling points, i.e., at every timer tick or yield,
+//! so a task may overrun its budget by up to one timeslice.
+//! A task's reservation is discarded if it is removed from the run queue,
+//! e.g., when migrating off an isolated CPU.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use core::time::Duration;
+
+use task::{scheduler::DeadlineParams, TaskRef};
+use time::Instant;
+
+/// The maximum total utilization of real-time tasks on a CPU, in parts per million.
+pub const MAX_UTILIZATION: u64 = 900_000;
+
+/// An instance of an EDF scheduler, typically one per CPU.
+pub struct Scheduler {
+    idle_task: TaskRef,
+    realtime: Vec<RealtimeTaskRef>,
+    best_effort: VecDeque<TaskRef>,
+    /// The total utilization of all real-time tasks, in parts per million.
+    utilization: u64,
+    /// The real-time task returned by the last call to `next`, and when.
+    running: Option<(TaskRef, Instant)>,
+}
+
+impl Scheduler {
+    /// Creates a new EDF scheduler instance with the given idle task.
+    pub const fn new(idle_task: TaskRef) -> Self {
+        Self {
+            idle_task,
+            realtime: Vec::new(),
+            best_effort: VecDeque::new(),
+            utilization: 0,
+            running: None,
+        }
+    }
+
+    /// Charges the previously running real-time task for the time it ran,
+    /// and starts a new period for each real-time task whose deadline has passed.
+    fn update_budgets(&mut self, now: Instant) {
+        if let Some((task, since)) = self.running.take() {
+            if let Some(rt) = self.realtime.iter_mut().find(|rt| rt.task == task) {
+                rt.remaining = rt.remaining.saturating_sub(now.duration_since(since));
+            }
+        }
+
+        for rt in self.realtime.iter_mut() {
+            if now >= rt.deadline {
+                // Skip any periods that have passed entirely, e.g., while the task was blocked.
+                let period = rt.params.period.as_nanos().max(1);
+                let missed_periods = now.duration_since(rt.deadline).as_nanos() / period;
+                let skipped = (missed_periods + 1) * period;
+                rt.deadline += Duration::from_nanos(skipped as u64);
+                rt.remaining = rt.params.budget;
+            }
+        }
+    }
+
+    fn next_realtime(&mut self) -> Option<TaskRef> {
+        self.realtime
+            .iter()
+            .filter(|rt| rt.task.is_runnable() && !rt.remaining.is_zero())
+            .min_by_key(|rt| rt.deadline)
+            .map(|rt| rt.task.clone())
+    }
+
+    fn next_best_effort(&mut self) -> Option<TaskRef> {
+        let index = self.best_effort.iter().position(|task| task.is_runnable())?;
+        let task = self.best_effort.remove(index)?;
+        self.best_effort.push_back(task.clone());
+        Some(task)
+    }
+}
+
+impl task::scheduler::Scheduler for Scheduler {
+    fn next(&mut self) -> TaskRef {
+        let now = time::now::<time::Monotonic>();
+        self.update_budgets(now);
+
+        if let Some(task) = self.next_realtime() {
+            self.running = Some((task.clone(), now));
+            return task;
+        }
+        self.next_best_effort()
+            .unwrap_or_else(|| self.idle_task.clone())
+    }
+
+    fn add(&mut self, task: TaskRef) {
+        self.best_effort.push_back(task);
+    }
+
+    fn busyness(&self) -> usize {
+        self.realtime.len() + self.best_effort.len()
+    }
+
+    fn remove(&mut self, task: &TaskRef) -> bool {
+        if let Some(index) = self.realtime.iter().position(|rt| rt.task == *task) {
+            let rt = self.realtime.swap_remove(index);
+            self.utilization -= rt.utilization();
+            return true;
+        }
+        if let Some(index) = self.best_effort.iter().position(|t| t == task) {
+            self.best_effort.remove(index);
+            return true;
+        }
+        false
+    }
+
+    fn as_priority_scheduler(&mut self) -> Option<&mut dyn task::scheduler::PriorityScheduler> {
+        None
+    }
+
+    fn as_deadline_scheduler(&mut self) -> Option<&mut dyn task::scheduler::DeadlineScheduler> {
+        Some(self)
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = TaskRef> + '_> {
+        self.utilization = 0;
+        self.running = None;
+        Box::new(
+            self.realtime
+                .drain(..)
+                .map(|rt| rt.task)
+                .chain(self.best_effort.drain(..)),
+        )
+    }
+
+    fn tasks(&self) -> Vec<TaskRef> {
+        self.realtime
+            .iter()
+            .map(|rt| rt.task.clone())
+            .chain(self.best_effort.iter().cloned())
+            .collect()
+    }
+
+    fn has_no_other_tasks(&self, task: &TaskRef) -> bool {
+        self.realtime.iter().all(|rt| rt.task == *task)
+            && self.best_effort.iter().all(|t| t == task)
+    }
+}
+
+impl task::scheduler::DeadlineScheduler for Scheduler {
+    fn contains(&self, task: &TaskRef) -> bool {
+        self.realtime.iter().any(|rt| rt.task == *task)
+            || self.best_effort.iter().any(|t| t == task)
+    }
+
+    fn set_deadline(&mut self, task: &TaskRef, params: DeadlineParams) -> Result<(), &'static str> {
+        let previous = self.realtime.iter().position(|rt| rt.task == *task);
+        if previous.is_none() && !self.best_effort.iter().any(|t| t == task) {
+            return Err("task is not on this CPU's run queue");
+        }
+
+        let utilization = utilization(params);
+        let other_utilization = self.utilization
+            - previous.map_or(0, |index| self.realtime[index].utilization());
+        if other_utilization + utilization > MAX_UTILIZATION {
+            return Err("deadline reservation would exceed the CPU's real-time capacity");
+        }
+
+        let now = time::now::<time::Monotonic>();
+        let rt = RealtimeTaskRef {
+            task: task.clone(),
+            params,
+            deadline: now + params.period,
+            remaining: params.budget,
+        };
+        match previous {
+            Some(index) => self.realtime[index] = rt,
+            None => {
+                self.best_effort.retain(|t| t != task);
+                self.realtime.push(rt);
+            }
+        }
+        self.utilization = other_utilization + utilization;
+        log::debug!(
+            "EDF: admitted {:?} with {:?}, CPU utilization is now {}ppm",
+            task, params, self.utilization
+        );
+        Ok(())
+    }
+
+    fn clear_deadline(&mut self, task: &TaskRef) -> bool {
+        if let Some(index) = self.realtime.iter().position(|rt| rt.task == *task) {
+            let rt = self.realtime.swap_remove(index);
+            self.utilization -= rt.utilization();
+            self.best_effort.push_back(rt.task);
+            true
+        } else {
+            self.best_effort.iter().any(|t| t == task)
+        }
+    }
+
+    fn deadline(&mut self, task: &TaskRef) -> Option<DeadlineParams> {
+        self.realtime
+            .iter()
+            .find(|rt| rt.task == *task)
+            .map(|rt| rt.params)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RealtimeTaskRef {
+    task: TaskRef,
+    params: DeadlineParams,
+    /// The end of the task's current period.
+    deadline: Instant,
+    /// The budget left in the task's current period.
+    remaining: Duration,
+}
+
+impl RealtimeTaskRef {
+    fn utilization(&self) -> u64 {
+        utilization(self.params)
+    }
+}
+
+/// Returns the fraction of the CPU the given reservation needs, in parts per million,
+/// rounded up.
+fn utilization(params: DeadlineParams) -> u64 {
+    let period = params.period.as_nanos().max(1);
+    let utilization = (params.budget.as_nanos() * 1_000_000).div_ceil(period);
+    utilization.min(u64::MAX as u128) as u64
+}
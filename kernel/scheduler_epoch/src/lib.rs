@@ -167,6 +167,10 @@ impl task::scheduler::Scheduler for Scheduler {
             .map(|epoch_task| epoch_task.task)
             .collect()
     }
+
+    fn has_no_other_tasks(&self, task: &TaskRef) -> bool {
+        self.queue.iter().all(|epoch_task| epoch_task.task == *task)
+    }
 }
 
 impl task::scheduler::PriorityScheduler for Scheduler {
@@ -87,6 +87,10 @@ impl task::scheduler::Scheduler for Scheduler {
             .map(|priority_task| priority_task.task)
             .collect()
     }
+
+    fn has_no_other_tasks(&self, task: &TaskRef) -> bool {
+        self.queue.iter().all(|priority_task| priority_task.task == *task)
+    }
 }
 
 impl task::scheduler::PriorityScheduler for Scheduler {
@@ -76,4 +76,8 @@ impl task::scheduler::Scheduler for Scheduler {
     fn tasks(&self) -> Vec<TaskRef> {
         self.queue.clone().into()
     }
+
+    fn has_no_other_tasks(&self, task: &TaskRef) -> bool {
+        self.queue.iter().all(|t| t == task)
+    }
 }
@@ -97,6 +97,34 @@ impl Rectangle {
     pub fn height(&self) -> usize {
         (self.bottom_right.y - self.top_left.y) as usize
     }
+
+    /// Returns the region covered by both this Rectangle and `other`, or `None` if they don't overlap.
+    pub fn intersection(&self, other: &Rectangle) -> Option<Rectangle> {
+        let top_left = Coord::new(
+            core::cmp::max(self.top_left.x, other.top_left.x),
+            core::cmp::max(self.top_left.y, other.top_left.y),
+        );
+        let bottom_right = Coord::new(
+            core::cmp::min(self.bottom_right.x, other.bottom_right.x),
+            core::cmp::min(self.bottom_right.y, other.bottom_right.y),
+        );
+        (top_left.x < bottom_right.x && top_left.y < bottom_right.y)
+            .then_some(Rectangle { top_left, bottom_right })
+    }
+
+    /// Returns the smallest Rectangle that contains both this Rectangle and `other`.
+    pub fn union(&self, other: &Rectangle) -> Rectangle {
+        Rectangle {
+            top_left: Coord::new(
+                core::cmp::min(self.top_left.x, other.top_left.x),
+                core::cmp::min(self.top_left.y, other.top_left.y),
+            ),
+            bottom_right: Coord::new(
+                core::cmp::max(self.bottom_right.x, other.bottom_right.x),
+                core::cmp::max(self.bottom_right.y, other.bottom_right.y),
+            ),
+        }
+    }
 }
 
 impl Add<Coord> for Rectangle {
@@ -69,6 +69,7 @@ extern crate compiler_builtins as _compiler_builtins;
 #[macro_use] extern crate alloc;
 extern crate memory;
 extern crate mod_mgmt;
+extern crate parallel_crate_loader;
 extern crate task;
 extern crate spawn;
 extern crate cpu;
@@ -142,7 +143,7 @@ fn internal_setup_simd_personality(simd_ext: SimdExt) -> Result<(), &'static str
 	let (core_lib_simd, _ns) = CrateNamespace::get_crate_object_file_starting_with(&simd_kernel_namespace, "core-")
 		.ok_or_else(|| "couldn't find a single 'core' object file in simd_personality")?;
 	let crate_files = [compiler_builtins_simd, core_lib_simd];
-	simd_kernel_namespace.load_crates(crate_files.iter(), Some(backup_namespace), kernel_mmi_ref, false)?;
+	parallel_crate_loader::load_crates_parallel(&simd_kernel_namespace, &crate_files, Some(backup_namespace), kernel_mmi_ref, false)?;
 	
 
 	// load the actual crate that we want to run in the simd namespace, "simd_test"
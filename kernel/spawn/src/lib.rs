@@ -24,17 +24,18 @@ use alloc::{
     sync::Arc,
     vec::Vec,
 };
-use log::{error, info, debug};
-use cpu::CpuId;
+use log::{error, info, debug, warn};
+use cpu::{CpuId, CpuSet};
 use debugit::debugit;
 use spin::Mutex;
+use kernel_config::memory::KERNEL_STACK_SIZE_IN_PAGES;
 use memory::{get_kernel_mmi_ref, MmiRef};
 use stack::Stack;
-use task::{Task, TaskRef, RestartInfo, RunState, JoinableTaskRef, ExitableTaskRef, FailureCleanupFunction};
+use task::{Task, TaskRef, RestartInfo, RestartPolicy, RunState, JoinableTaskRef, ExitableTaskRef, FailureCleanupFunction};
 use task_struct::ExposedTask;
-use mod_mgmt::{CrateNamespace, SectionType, SECTION_HASH_DELIMITER};
+use mod_mgmt::{AppCrateRef, CrateNamespace, SectionType, SECTION_HASH_DELIMITER};
 use path::{Path, PathBuf};
-use fs_node::FileOrDir;
+use fs_node::{FileOrDir, FileRef};
 use preemption::{hold_preemption, PreemptionGuard};
 use no_drop::NoDrop;
 
@@ -64,6 +65,8 @@ pub fn init(
             let scheduler = scheduler_epoch::Scheduler::new(idle_task);
         } else if #[cfg(priority_scheduler)] {
             let scheduler = scheduler_priority::Scheduler::new(idle_task);
+        } else if #[cfg(edf_scheduler)] {
+            let scheduler = scheduler_edf::Scheduler::new(idle_task);
         } else {
             let scheduler = scheduler_round_robin::Scheduler::new(idle_task);
         }
@@ -185,6 +188,41 @@ pub fn new_task_builder<F, A, R>(
     TaskBuilder::new(func, argument)
 }
 
+/// Invokes `func` with a clone of `argument` on every CPU, and waits for all of them to finish.
+///
+/// This spawns a new task named `"{name}_{cpu}"` that is pinned to each CPU,
+/// which is needed to access state that only that CPU itself can access, such as its MSRs.
+///
+/// Returns the value returned by `func` on each CPU, or the reason why that CPU's task was killed.
+pub fn on_each_cpu<A, R>(
+    name: &str,
+    func: fn(A) -> R,
+    argument: A,
+) -> Result<Vec<(CpuId, Result<R, task::KillReason>)>, &'static str>
+    where A: Clone + Send + 'static,
+          R: Send + 'static,
+{
+    let mut tasks = Vec::new();
+    for cpu in cpu::cpus() {
+        let task = new_task_builder(func, argument.clone())
+            .name(format!("{name}_{cpu}"))
+            .pin_on_cpu(cpu)
+            .spawn()?;
+        tasks.push((cpu, task));
+    }
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for (cpu, task) in tasks {
+        let result = match task.join()? {
+            task::ExitValue::Completed(value) => Ok(*value.downcast::<R>()
+                .map_err(|_| "BUG: on_each_cpu(): task returned an unexpected value")?),
+            task::ExitValue::Killed(reason) => Err(reason),
+        };
+        results.push((cpu, result));
+    }
+    Ok(results)
+}
+
 
 /// Every executable application must have an entry function named "main".
 const ENTRY_POINT_SECTION_NAME: &str = "main";
@@ -228,10 +266,36 @@ pub fn new_application_task_builder(
         _ => return Err("Couldn't find specified file path for new application crate"),
     };
     
+    let (app_crate_ref, main_func) = load_application_crate(&namespace, &crate_object_file)?;
+
+    // Create the underlying task builder. 
+    // Give it a default name based on the app crate's name, but that can be changed later. 
+    let mut tb = TaskBuilder::new(main_func, MainFuncArg::default())
+        .name(app_crate_ref.lock_as_ref().crate_name.to_string()); 
+
+    // Once the new application task is created (but before its scheduled in),
+    // ensure it has the relevant app-specific fields set properly.
+    tb.post_build_function = Some(Box::new(
+        move |new_task| {
+            new_task.app_crate = Some(Arc::new(app_crate_ref));
+            new_task.namespace = namespace;
+            Ok(None)
+        }
+    ));
+    
+    Ok(tb)
+}
+
+/// Loads a new instance of the application crate from the given object file into the given `namespace`,
+/// and returns it along with its entry point `main` function.
+fn load_application_crate(
+    namespace: &Arc<CrateNamespace>,
+    crate_object_file: &FileRef,
+) -> Result<(AppCrateRef, MainFunc), &'static str> {
     // Load the new application crate
     let app_crate_ref = {
         let kernel_mmi_ref = get_kernel_mmi_ref().ok_or("couldn't get_kernel_mmi_ref")?;
-        CrateNamespace::load_crate_as_application(&namespace, &crate_object_file, kernel_mmi_ref, false)?
+        CrateNamespace::load_crate_as_application(namespace, crate_object_file, kernel_mmi_ref, false)?
     };
 
     // Find the "main" entry point function in the new app crate
@@ -244,25 +308,102 @@ pub fn new_application_task_builder(
     };
     let main_func_sec = main_func_sec_opt.ok_or("spawn::new_application_task_builder(): couldn't find \"main\" function, expected function name like \"<crate_name>::main::<hash>\"\
         --> Is this an app-level library or kernel crate? (Note: you cannot spawn a library crate with no main function)")?;
-    // SAFETY: None. There is a lint in compiler_plugins/application_main_fn.rs, but it's currently disabled.
-    let main_func = unsafe { main_func_sec.as_func::<MainFunc>() }?;
+    // Rust symbol names don't encode function signatures, so the best we can do is ensure that
+    // the entry point is a non-empty, publicly-visible function that the namespace resolves its symbol to.
+    let main_func_is_exported = main_func_sec.global
+        && main_func_sec.size > 0
+        && namespace.get_symbol(&main_func_sec.name).upgrade()
+            .map_or(false, |sec| Arc::ptr_eq(&sec, &main_func_sec));
+    if !main_func_is_exported {
+        return Err("spawn::new_application_task_builder(): the \"main\" function is not a public function in the namespace's symbol map \
+            --> Ensure it is declared as `pub fn main(args: Vec<String>) -> isize`");
+    }
+    #[cfg(all(target_arch = "x86_64", feature = "check_main_signature"))]
+    check_main_func_signature(namespace, &app_crate_ref, crate_object_file, &main_func_sec)?;
+    // SAFETY: the signature of `main` is checked above if the `check_main_signature` feature is enabled
+    // and the app crate has debug symbols.
+    // There is also a lint in compiler_plugins/application_main_fn.rs, but it's currently disabled.
+    let main_func = *unsafe { main_func_sec.as_func::<MainFunc>() }?;
+    Ok((app_crate_ref, main_func))
+}
 
-    // Create the underlying task builder. 
-    // Give it a default name based on the app crate's name, but that can be changed later. 
-    let mut tb = TaskBuilder::new(*main_func, MainFuncArg::default())
-        .name(app_crate_ref.lock_as_ref().crate_name.to_string()); 
+/// The application crate object files whose `main` function has already passed [`check_main_func_signature()`],
+/// identified by each file's absolute path, its length, and the name of its `main` function.
+#[cfg(all(target_arch = "x86_64", feature = "check_main_signature"))]
+static CHECKED_MAIN_FUNCS: Mutex<alloc::collections::BTreeSet<(String, usize, String)>> =
+    Mutex::new(alloc::collections::BTreeSet::new());
 
-    // Once the new application task is created (but before its scheduled in),
-    // ensure it has the relevant app-specific fields set properly.
-    tb.post_build_function = Some(Box::new(
-        move |new_task| {
-            new_task.app_crate = Some(Arc::new(app_crate_ref));
-            new_task.namespace = namespace;
-            Ok(None)
+/// Checks that the given `main` function of the given application crate has the signature of [`MainFunc`],
+/// according to the types of its parameters and return value in the crate's debug symbols.
+///
+/// If the crate has no debug symbols or they don't describe `main`, a warning is logged
+/// and the signature is assumed to be correct.
+///
+/// Loading and parsing the debug symbols is expensive, so a crate object file is only checked
+/// the first time it is spawned (or after it has changed).
+#[cfg(all(target_arch = "x86_64", feature = "check_main_signature"))]
+fn check_main_func_signature(
+    namespace: &CrateNamespace,
+    app_crate_ref: &AppCrateRef,
+    crate_object_file: &FileRef,
+    main_func_sec: &mod_mgmt::StrongSectionRef,
+) -> Result<(), &'static str> {
+    let cache_key = {
+        let file = crate_object_file.lock();
+        (file.get_absolute_path(), file.len(), main_func_sec.name.to_string())
+    };
+    if CHECKED_MAIN_FUNCS.lock().contains(&cache_key) {
+        return Ok(());
+    }
+
+    let debug_symbols_file = app_crate_ref.lock_as_ref().debug_symbols_file.clone();
+    let mut debug_symbols = debug_info::DebugSymbols::Unloaded(debug_symbols_file);
+    let signature = match debug_symbols.load(app_crate_ref, namespace) {
+        Ok(debug_sections) => debug_sections.find_function_signature(main_func_sec.virt_addr),
+        Err(e) => {
+            warn!("couldn't check the signature of {:?}, failed to load debug symbols: {}", main_func_sec.name, e);
+            return Ok(());
         }
-    ));
-    
-    Ok(tb)
+    };
+    match signature {
+        Ok(Some(signature)) if matches!(
+            (signature.parameters.as_slice(), signature.return_type.as_deref()),
+            ([arg], Some("isize")) if is_vec_of_strings(arg)
+        ) => {
+            CHECKED_MAIN_FUNCS.lock().insert(cache_key);
+            Ok(())
+        }
+        Ok(Some(signature)) => {
+            error!("{:?} has the wrong signature: {:?}", main_func_sec.name, signature);
+            Err("spawn::new_application_task_builder(): the \"main\" function has the wrong signature \
+                --> Ensure it is declared as `pub fn main(args: Vec<String>) -> isize`")
+        }
+        Ok(None) => {
+            warn!("couldn't check the signature of {:?}, it isn't described by the debug symbols", main_func_sec.name);
+            Ok(())
+        }
+        Err(e) => {
+            warn!("couldn't check the signature of {:?}, failed to parse debug symbols: {:?}", main_func_sec.name, e);
+            Ok(())
+        }
+    }
+}
+
+/// Returns whether the given DWARF type name describes a `Vec<String>`.
+///
+/// Depending on the compiler version, the name of a `Vec` may or may not be fully qualified,
+/// and may or may not include its allocator type parameter, e.g.,
+/// `Vec<alloc::string::String>` or `alloc::vec::Vec<alloc::string::String, alloc::alloc::Global>`.
+#[cfg(all(target_arch = "x86_64", feature = "check_main_signature"))]
+fn is_vec_of_strings(type_name: &str) -> bool {
+    let type_name = type_name.strip_prefix("alloc::vec::").unwrap_or(type_name);
+    let Some(params) = type_name.strip_prefix("Vec<").and_then(|t| t.strip_suffix('>')) else {
+        return false;
+    };
+    let mut params = params.split(',').map(str::trim);
+    params.next() == Some("alloc::string::String")
+        && matches!(params.next(), None | Some("alloc::alloc::Global"))
+        && params.next().is_none()
 }
 
 /// A struct that offers a builder pattern to create and customize new `Task`s.
@@ -285,8 +426,12 @@ pub struct TaskBuilder<F, A, R> {
     stack: Option<Stack>,
     parent: Option<TaskRef>,
     pin_on_cpu: Option<CpuId>,
+    affinity: Option<CpuSet>,
     blocked: bool,
     idle: bool,
+    restart_policy: RestartPolicy,
+    restart_count: usize,
+    reload_app_crate: bool,
     post_build_function: Option<Box<
         dyn FnOnce(&mut Task) -> Result<Option<FailureCleanupFunction>, &'static str>
     >>,
@@ -311,8 +456,12 @@ impl<F, A, R> TaskBuilder<F, A, R>
             stack: None,
             parent: None,
             pin_on_cpu: None,
+            affinity: None,
             blocked: false,
             idle: false,
+            restart_policy: RestartPolicy::default(),
+            restart_count: 0,
+            reload_app_crate: false,
             post_build_function: None,
 
             #[cfg(simd_personality)]
@@ -353,6 +502,16 @@ impl<F, A, R> TaskBuilder<F, A, R>
         self
     }
 
+    /// Restrict the new Task to only be scheduled on the given set of CPUs.
+    ///
+    /// The new Task is placed on the least busy of those CPUs, and is never moved to a CPU outside of them.
+    /// This is ignored if the new Task is also [pinned to a CPU](Self::pin_on_cpu).
+    /// Spawning fails if none of the given CPUs exist.
+    pub fn affinity(mut self, cpus: CpuSet) -> TaskBuilder<F, A, R> {
+        self.affinity = Some(cpus);
+        self
+    }
+
     /// Mark this new Task as a SIMD-enabled Task 
     /// that can run SIMD instructions and use SIMD registers.
     #[cfg(simd_personality)]
@@ -379,18 +538,27 @@ impl<F, A, R> TaskBuilder<F, A, R>
     /// It does not switch to it immediately; that will happen on the next scheduler invocation.
     #[inline(never)]
     pub fn spawn(self) -> Result<JoinableTaskRef, &'static str> {
-        let mut new_task = Task::new(
-            self.stack,
-            task::get_my_current_task()
-                .ok_or("spawn: couldn't get current task")?
-                .deref()
-                .into(),
-        )?;
+        if let (None, Some(affinity)) = (self.pin_on_cpu, self.affinity.as_ref()) {
+            if !affinity.iter().any(|cpu| task::scheduler::busyness(cpu).is_some()) {
+                return Err("spawn: none of the CPUs in the new task's affinity exist");
+            }
+        }
+        let current_task = task::get_my_current_task().ok_or("spawn: couldn't get current task")?;
+        // A task pinned to a CPU should have its stack in memory local to that CPU's NUMA node.
+        let stack = self.stack.or_else(|| {
+            let node = numa::node_of_cpu(self.pin_on_cpu?)?;
+            stack::alloc_stack_on_node(KERNEL_STACK_SIZE_IN_PAGES, node, &mut current_task.mmi.lock().page_table)
+        });
+        let mut new_task = Task::new(stack, current_task.deref().into())?;
         // If a Task name wasn't provided, then just use the function's name.
         new_task.name = self.name.unwrap_or_else(|| String::from(core::any::type_name::<F>()));
 
         let exposed = ExposedTask { task: new_task };
-        exposed.inner().lock().pinned_cpu = self.pin_on_cpu;
+        {
+            let mut inner = exposed.inner().lock();
+            inner.pinned_cpu = self.pin_on_cpu;
+            inner.affinity = self.affinity;
+        }
         let ExposedTask { task: mut new_task } = exposed;    
 
         #[cfg(simd_personality)] {  
@@ -483,6 +651,24 @@ impl<F, A, R> TaskBuilder<F, A, R>
         self.pin_on_cpu(cpu_id)
     }
 
+    /// Set the [`RestartPolicy`] that determines whether the new Task is restarted after it exits.
+    ///
+    /// This only takes effect if the new Task is spawned with [`TaskBuilder::spawn_restartable()`];
+    /// by default, restartable tasks use [`RestartPolicy::Always`].
+    pub fn restart_policy(mut self, policy: RestartPolicy) -> TaskBuilder<F, A, R> {
+        self.restart_policy = policy;
+        self
+    }
+
+    /// Reload the new Task's application crate from its object file every time the Task is restarted,
+    /// such that each restarted instance runs within a fresh copy of the application crate.
+    ///
+    /// This only has an effect on application tasks created with [`new_application_task_builder()`].
+    pub fn reload_app_crate_on_restart(mut self) -> TaskBuilder<F, A, R> {
+        self.reload_app_crate = true;
+        self
+    }
+
     /// Like [`TaskBuilder::spawn()`], this finishes this `TaskBuilder` and spawns the new task.
     /// It also stores the new Task's function and argument within the Task,
     /// enabling it to be restarted upon exit.
@@ -514,12 +700,20 @@ impl<F, A, R> TaskBuilder<F, A, R>
         let restart_info = RestartInfo {
             argument: Box::new(restart_with_arg.unwrap_or_else(|| self.argument.clone())),
             func: Box::new(self.func.clone()),
+            policy: self.restart_policy,
+            restarts: self.restart_count,
+            reload_app_crate: self.reload_app_crate,
         };
 
         // Once the new task is created, we set its restart info (func and arg),
         // and tell it to use the restartable version of the task entry and cleanup functions.
+        // Any existing post-build function (e.g., for an application task) must still be invoked first.
+        let prev_post_build_function = self.post_build_function.take();
         self.post_build_function = Some(Box::new(
             move |new_task| {
+                if let Some(pb_func) = prev_post_build_function {
+                    pb_func(new_task)?;
+                }
                 new_task.inner_mut().restart_info = Some(restart_info);
                 setup_context_trampoline(new_task, task_wrapper_restartable::<F, A, R>)?;
                 Ok(Some(task_restartable_cleanup_failure::<F, A, R>))
@@ -725,6 +919,13 @@ where
 
     drop(recovered_preemption_guard);
 
+    // A restarted task must wait out the backoff period of its restart policy before running again.
+    if let Some(backoff) = exitable_taskref.with_restart_info(|ri| ri.and_then(RestartInfo::backoff)) {
+        #[cfg(not(rq_eval))]
+        debug!("task_wrapper: restarted task \"{}\" waiting {:?} before calling its entry func", &**exitable_taskref, backoff);
+        let _ = sleep::sleep(backoff);
+    }
+
     // This synchronizes with the acquire fence in `JoinableTaskRef::join()`.
     fence(Ordering::Release);
 
@@ -830,8 +1031,9 @@ fn task_restartable_cleanup_success<F, A, R>(current_task: ExitableTaskRef, exit
           R: Send + 'static,
           F: FnOnce(A) -> R + Send + Clone +'static,
 {
+    let reloaded_app_crate = reload_app_crate_for_restart::<F>(&current_task, false);
     let (preemption_guard, current_task) = task_cleanup_success_internal(current_task, exit_value);
-    task_restartable_cleanup_final::<F, A, R>(preemption_guard, current_task)
+    task_restartable_cleanup_final::<F, A, R>(preemption_guard, current_task, false, reloaded_app_crate)
 }
 
 
@@ -874,8 +1076,9 @@ fn task_restartable_cleanup_failure<F, A, R>(current_task: ExitableTaskRef, kill
           R: Send + 'static,
           F: FnOnce(A) -> R + Send + Clone + 'static, 
 {
+    let reloaded_app_crate = reload_app_crate_for_restart::<F>(&current_task, true);
     let (preemption_guard, current_task) = task_cleanup_failure_internal(current_task, kill_reason);
-    task_restartable_cleanup_final::<F, A, R>(preemption_guard, current_task)
+    task_restartable_cleanup_final::<F, A, R>(preemption_guard, current_task, true, reloaded_app_crate)
 }
 
 
@@ -896,7 +1099,18 @@ fn task_cleanup_final_internal(current_task: &ExitableTaskRef) {
     // Third, reap the task if it has been orphaned (if it's non-joinable).
     current_task.reap_if_orphaned();
 
-    // Fourth, synchronize memory with the release fence of the "parent" task
+    // Fourth, let the heap release this task's usage entry once its remaining allocations are freed.
+    heap::task_exited(current_task.id);
+
+    // Fifth, flag tasks that came close to overflowing their stack, so that its size can be increased.
+    if let Some(usage) = current_task.stack_usage() {
+        if usage.is_near_overflow() {
+            warn!("task_cleanup: {:?} used {} of its {}-byte stack ({}%), it may overflow its stack in the future",
+                current_task.name, usage.max_used_bytes, usage.size_in_bytes, usage.percent_used());
+        }
+    }
+
+    // Sixth, synchronize memory with the release fence of the "parent" task
     // in `TaskBuilder::spawn()`.
     fence(Ordering::Acquire)
 }
@@ -924,8 +1138,17 @@ fn task_cleanup_final<F, A, R>(preemption_guard: PreemptionGuard, current_task:
 
 /// The final piece of the task cleanup logic for restartable tasks.
 /// which removes the task from its runqueue and spawns it again with 
-/// same entry function (F) and argument (A). 
-fn task_restartable_cleanup_final<F, A, R>(preemption_guard: PreemptionGuard, current_task: ExitableTaskRef) -> !
+/// same entry function (F) and argument (A), if its [`RestartPolicy`] allows it.
+/// 
+/// * `killed`: whether the task was killed (`true`) or exited normally (`false`).
+/// * `reloaded_app_crate`: the fresh instance of the task's app crate and its `main` function,
+///    if it was reloaded by [`reload_app_crate_for_restart()`].
+fn task_restartable_cleanup_final<F, A, R>(
+    preemption_guard: PreemptionGuard,
+    current_task: ExitableTaskRef,
+    killed: bool,
+    reloaded_app_crate: Option<(AppCrateRef, F)>,
+) -> !
 where
     A: Send + Clone + 'static,
     R: Send + 'static,
@@ -955,6 +1178,10 @@ where
         // We must not hold the current task's lock when calling spawn().
         let restartable_info = current_task.with_restart_info(|restart_info_opt| {
             restart_info_opt.map(|restart_info| {
+                if !restart_info.should_restart(killed) {
+                    return None;
+                }
+
                 #[cfg(use_crate_replacement)] {
                     let func_ptr = &restart_info.func as *const _ as usize;
                     let arg_ptr = &restart_info.argument as *const _ as usize;
@@ -969,20 +1196,41 @@ where
 
                 let func: &F = restart_info.func.downcast_ref().expect("BUG: failed to downcast restartable task's function");
                 let arg : &A = restart_info.argument.downcast_ref().expect("BUG: failed to downcast restartable task's argument");
-                (func.clone(), arg.clone())
+                Some((func.clone(), arg.clone(), restart_info.policy, restart_info.restarts, restart_info.reload_app_crate))
             })
         });
 
-        if let Some((func, arg)) = restartable_info {
-            let mut new_task = new_task_builder(func, arg)
-                .name(current_task.name.clone());
-            if let Some(cpu) = current_task.pinned_cpu() {
-                new_task = new_task.pin_on_cpu(cpu);
+        match restartable_info {
+            Some(Some((func, arg, policy, restarts, reload_app_crate))) => {
+                let (func, app_crate) = match reloaded_app_crate {
+                    Some((app_crate_ref, main_func)) => (main_func, Some(Arc::new(app_crate_ref))),
+                    // The old function must keep its app crate loaded for as long as it runs.
+                    None => (func, current_task.app_crate.clone()),
+                };
+
+                let mut new_task = new_task_builder(func, arg)
+                    .name(current_task.name.clone())
+                    .restart_policy(policy);
+                new_task.restart_count = restarts + 1;
+                new_task.reload_app_crate = reload_app_crate;
+                if let Some(cpu) = current_task.pinned_cpu() {
+                    new_task = new_task.pin_on_cpu(cpu);
+                } else if let Some(affinity) = current_task.affinity() {
+                    new_task = new_task.affinity(affinity);
+                }
+                if let Some(app_crate) = app_crate {
+                    new_task.post_build_function = Some(Box::new(
+                        move |new_task| {
+                            new_task.app_crate = Some(app_crate);
+                            Ok(None)
+                        }
+                    ));
+                }
+                new_task.spawn_restartable(None)
+                    .expect("Failed to respawn the restartable task");
             }
-            new_task.spawn_restartable(None)
-                .expect("Failed to respawn the restartable task");
-        } else {
-            error!("BUG: Restartable task has no restart information available");
+            Some(None) => debug!("Not restarting task {:?}, as its restart policy does not allow it", current_task.name),
+            None => error!("BUG: Restartable task has no restart information available"),
         }
     }
 
@@ -998,6 +1246,46 @@ where
     loop { core::hint::spin_loop() }
 }
 
+/// Loads a fresh instance of the given restartable task's app crate, if the task will be restarted
+/// and was spawned with [`TaskBuilder::reload_app_crate_on_restart()`].
+///
+/// This must be invoked before the task is marked as exited, because loading a crate
+/// reads its object file, which must not be done while preemption is disabled.
+///
+/// * `killed`: whether the task was killed (`true`) or exited normally (`false`).
+fn reload_app_crate_for_restart<F: Clone + 'static>(
+    current_task: &ExitableTaskRef,
+    killed: bool,
+) -> Option<(AppCrateRef, F)> {
+    let should_reload = current_task.with_restart_info(|restart_info_opt| restart_info_opt
+        .map_or(false, |restart_info| restart_info.reload_app_crate && restart_info.should_restart(killed))
+    );
+    if !should_reload {
+        return None;
+    }
+    reload_application_crate::<F>(current_task).unwrap_or_else(|e| {
+        error!("Failed to reload the app crate of restartable task {:?}, error: {}", current_task.name, e);
+        None
+    })
+}
+
+/// Loads a fresh instance of the given application task's crate from its object file.
+///
+/// Returns `Ok(None)` if the task is not an application task,
+/// or if its entry function type `F` is not an application's `main` function.
+fn reload_application_crate<F: Clone + 'static>(
+    current_task: &ExitableTaskRef,
+) -> Result<Option<(AppCrateRef, F)>, &'static str> {
+    let Some(app_crate) = current_task.app_crate.as_ref() else {
+        return Ok(None);
+    };
+    let object_file = app_crate.lock_as_ref().object_file.clone();
+    let (app_crate_ref, main_func) = load_application_crate(current_task.get_namespace(), &object_file)?;
+    Ok((&main_func as &dyn core::any::Any).downcast_ref::<F>()
+        .map(|func| (app_crate_ref, func.clone()))
+    )
+}
+
 /// Helper function to remove a task from its runqueue and drop it.
 fn remove_current_task_from_runqueue(current_task: &ExitableTaskRef) {
     task::scheduler::remove_task(current_task);
@@ -1,5 +1,10 @@
 //! Provides the `Stack` type that represents a Task's stack 
 //! and functions for allocating new stacks. 
+//!
+//! Newly-allocated stacks are filled with a poison pattern, 
+//! such that the maximum depth a stack has reached (its high-water mark)
+//! can be determined later by scanning for the lowest overwritten word.
+//! See [`Stack::usage()`].
 
 #![no_std]
 
@@ -10,12 +15,18 @@ extern crate memory_structs;
 extern crate memory;
 extern crate page_allocator;
 
-use core::ops::{Deref, DerefMut};
+use core::{mem::size_of, ops::{Deref, DerefMut}};
 use kernel_config::memory::PAGE_SIZE;
 use memory_structs::VirtualAddress;
-use memory::{PteFlags, MappedPages, Mapper};
+use memory::{AllocatedFrames, PteFlags, MappedPages, Mapper};
 use page_allocator::AllocatedPages;
 
+/// The value that every word of a newly-allocated stack is initialized to.
+const POISON: usize = 0x5AC5_5AC5_5AC5_5AC5_u64 as usize;
+
+/// A stack's usage is considered near overflow once it has reached this percentage of its size.
+const NEAR_OVERFLOW_PERCENT: usize = 75;
+
 
 /// Allocates a new stack and maps it to the active page table. 
 ///
@@ -29,15 +40,33 @@ pub fn alloc_stack(
 ) -> Option<Stack> {
     // Allocate enough pages for an additional guard page. 
     let pages = page_allocator::allocate_pages(size_in_pages + 1)?;
-    inner_alloc_stack(pages, page_table)
+    inner_alloc_stack(pages, None, page_table)
+}
+
+/// Allocates a new stack backed by frames from the memory of the given NUMA `node`
+/// and maps it to the active page table.
+///
+/// If the node doesn't have enough free memory, this falls back to frames from any node,
+/// just like [`alloc_stack()`].
+pub fn alloc_stack_on_node(
+    size_in_pages: usize,
+    node: u32,
+    page_table: &mut Mapper, 
+) -> Option<Stack> {
+    let pages = page_allocator::allocate_pages(size_in_pages + 1)?;
+    let frames = memory::allocate_frames_on_node(node, size_in_pages);
+    inner_alloc_stack(pages, frames, page_table)
 }
 
 /// The inner implementation of stack allocation. 
 /// 
 /// `pages` is the combined `AllocatedPages` object that holds
 /// the guard page followed by the actual stack pages to be mapped.
+/// If `frames` is `Some`, the stack pages are mapped to those frames;
+/// otherwise, they are mapped to newly-allocated frames.
 fn inner_alloc_stack(
     pages: AllocatedPages,
+    frames: Option<AllocatedFrames>,
     page_table: &mut Mapper, 
 ) -> Option<Stack> {
     let start_of_stack_pages = *pages.start() + 1; 
@@ -47,7 +76,11 @@ fn inner_alloc_stack(
     let flags = PteFlags::new().writable(true);
 
     // Map stack pages to physical frames, leave the guard page unmapped.
-    let pages = match page_table.map_allocated_pages(stack_pages, flags) {
+    let mapping = match frames {
+        Some(frames) => page_table.map_allocated_pages_to(stack_pages, frames, flags),
+        None => page_table.map_allocated_pages(stack_pages, flags),
+    };
+    let mut pages = match mapping {
         Ok(pages) => pages,
         Err(e) => {
             error!("alloc_stack(): couldn't map pages for the new Stack, error: {}", e);
@@ -55,7 +88,44 @@ fn inner_alloc_stack(
         }
     };
 
-    Some(Stack { guard_page, pages })  
+    // Poison the entire stack such that its usage can be measured later.
+    let num_words = pages.size_in_bytes() / size_of::<usize>();
+    match pages.as_slice_mut::<usize>(0, num_words) {
+        Ok(words) => words.fill(POISON),
+        Err(e) => {
+            error!("alloc_stack(): couldn't poison the new Stack, error: {}", e);
+            return None;
+        }
+    }
+
+    Some(Stack { guard_page, pages, poisoned: true })  
+}
+
+
+/// The amount of a stack that has been used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackUsage {
+    /// The maximum number of bytes that have ever been used on the stack,
+    /// measured from its top.
+    pub max_used_bytes: usize,
+    /// The total size of the stack in bytes, excluding its guard page.
+    pub size_in_bytes: usize,
+}
+
+impl StackUsage {
+    /// Returns the maximum usage of the stack as a percentage of its size.
+    pub fn percent_used(&self) -> usize {
+        if self.size_in_bytes == 0 {
+            return 0;
+        }
+        self.max_used_bytes * 100 / self.size_in_bytes
+    }
+
+    /// Returns `true` if the stack's maximum usage is close enough to its size
+    /// that it is at risk of overflowing.
+    pub fn is_near_overflow(&self) -> bool {
+        self.percent_used() >= NEAR_OVERFLOW_PERCENT
+    }
 }
 
 
@@ -69,6 +139,9 @@ fn inner_alloc_stack(
 pub struct Stack {
     guard_page: AllocatedPages,
     pages: MappedPages,
+    /// Whether this stack was filled with the poison pattern upon allocation.
+    /// Stacks created via [`Stack::from_pages()`] are not.
+    poisoned: bool,
 }
 impl Deref for Stack {
     type Target = MappedPages;
@@ -122,7 +195,7 @@ impl Stack {
         if (*guard_page.end() + 1) == *stack_pages.start() 
             && stack_pages.flags().is_writable()
         {
-            Ok(Stack { guard_page, pages: stack_pages })
+            Ok(Stack { guard_page, pages: stack_pages, poisoned: false })
         } else {
             Err((guard_page, stack_pages))
         }
@@ -135,4 +208,34 @@ impl Stack {
     pub fn guard_page(&self) -> &memory_structs::PageRange {
         self.guard_page.range()
     }
+
+    /// Returns the maximum amount of this stack that has ever been used, i.e., its high-water mark.
+    ///
+    /// This scans upwards from the bottom of this stack for the first word that
+    /// no longer holds the poison pattern, so it takes time proportional to the unused size of this stack.
+    /// The result can slightly underestimate the true usage if the task wrote 
+    /// a value equal to the poison pattern, or reserved stack space that it never wrote to.
+    ///
+    /// This may be invoked while the task using this stack is running, 
+    /// in which case the result reflects its usage at some point during this call.
+    ///
+    /// Returns `None` if this stack wasn't poisoned upon creation,
+    /// i.e., if it was created via [`Stack::from_pages()`].
+    pub fn usage(&self) -> Option<StackUsage> {
+        if !self.poisoned {
+            return None;
+        }
+        let size_in_bytes = self.pages.size_in_bytes();
+        let bottom = self.bottom().value() as *const usize;
+        let num_words = size_in_bytes / size_of::<usize>();
+        let unused_words = (0 .. num_words)
+            // SAFETY: every word is within this stack's mapped pages, which are readable.
+            // Volatile reads are used because the task using this stack may concurrently modify it.
+            .take_while(|&i| unsafe { core::ptr::read_volatile(bottom.add(i)) } == POISON)
+            .count();
+        Some(StackUsage {
+            max_used_bytes: (num_words - unused_words) * size_of::<usize>(),
+            size_in_bytes,
+        })
+    }
 }
@@ -14,7 +14,7 @@ fn main() {
     if let Ok(rustflags) = std::env::var("CARGO_ENCODED_RUSTFLAGS") {
         if rustflags.contains("force-frame-pointers=yes")
         || rustflags.contains("force-frame-pointers=true") {
-            println!("{CFG_PREFIX}frame pointers");
+            println!("{CFG_PREFIX}frame_pointers");
         }
     } else {
         eprintln!("Note: CARGO_ENCODED_RUSTFLAGS env var did not exist.");
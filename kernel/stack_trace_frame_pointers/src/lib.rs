@@ -52,12 +52,30 @@ pub fn stack_trace_using_frame_pointers(
     max_recursion: Option<usize>,
 ) -> Result<(), &'static str> {
 
-    let mut rbp: usize;
+    let rbp: usize;
     // SAFE: just reading current register value
     unsafe {
         core::arch::asm!("mov {}, rbp", out(reg) rbp);
     }
+    stack_trace_from_frame_pointer(current_page_table, rbp, on_each_stack_frame, max_recursion)
+}
+
+
+/// Get a stack trace using frame pointers, starting from the given frame pointer value
+/// rather than the current value of the frame pointer register.
+///
+/// This is useful for obtaining a stack trace of a task that is not currently running,
+/// e.g., by using the frame pointer value saved in its context upon its last context switch.
+///
+/// See [`stack_trace_using_frame_pointers()`] for a description of the other arguments.
+pub fn stack_trace_from_frame_pointer(
+    current_page_table: &PageTable,
+    frame_pointer: usize,
+    on_each_stack_frame: &mut dyn FnMut(usize, VirtualAddress) -> bool,
+    max_recursion: Option<usize>,
+) -> Result<(), &'static str> {
 
+    let mut rbp = frame_pointer;
     for _i in 0 .. max_recursion.unwrap_or(64) {
         // the stack contains the return address (of the caller) right before the current frame pointer
         if let Some(rip_ptr) = rbp.checked_add(core::mem::size_of::<usize>()) {
@@ -8,6 +8,9 @@ extern crate alloc;
 extern crate spin;
 extern crate pci;
 extern crate ata;
+extern crate ahci;
+extern crate virtio;
+extern crate virtio_blk;
 extern crate storage_device;
 
 use alloc::{
@@ -52,14 +55,29 @@ pub fn storage_devices() -> impl Iterator<Item = StorageDeviceRef> {
 /// * `Ok(None)` if the given `PciDevice` isn't a supported storage device,
 /// * An error if it fails to initialize a supported storage device.
 pub fn init_device(pci_device: &PciDevice) -> Result<Option<StorageControllerRef>, &'static str> {
-    // We currently only support IDE controllers for ATA drives (aka PATA).
+    // We currently support IDE controllers for ATA drives (aka PATA), AHCI controllers for SATA drives,
+    // and virtio block devices.
     let storage_controller = if pci_device.class == 0x01 && pci_device.subclass == 0x01 {
         info!("IDE controller PCI device found at: {:?}", pci_device.location);
         let ide_controller = ata::IdeController::new(pci_device)?;
         let storage_controller_ref: StorageControllerRef = Arc::new(Mutex::new(ide_controller));
         STORAGE_CONTROLLERS.lock().push(Arc::clone(&storage_controller_ref));
         Some(storage_controller_ref)
-    } 
+    }
+    else if (pci_device.class, pci_device.subclass, pci_device.prog_if) == ahci::AHCI_CLASS {
+        info!("AHCI controller PCI device found at: {:?}", pci_device.location);
+        let ahci_controller = ahci::init(pci_device)?;
+        let storage_controller_ref: StorageControllerRef = Arc::new(Mutex::new(ahci_controller));
+        STORAGE_CONTROLLERS.lock().push(Arc::clone(&storage_controller_ref));
+        Some(storage_controller_ref)
+    }
+    else if pci_device.vendor_id == virtio::VIRTIO_VENDOR_ID && virtio_blk::BLOCK_DEVS.contains(&pci_device.device_id) {
+        info!("virtio block PCI device found at: {:?}", pci_device.location);
+        let virtio_blk_controller = virtio_blk::init(pci_device)?;
+        let storage_controller_ref: StorageControllerRef = Arc::new(Mutex::new(virtio_blk_controller));
+        STORAGE_CONTROLLERS.lock().push(Arc::clone(&storage_controller_ref));
+        Some(storage_controller_ref)
+    }
     // Here: in the future, handle other supported storage devices
     else {
         None
@@ -0,0 +1,36 @@
+//! A background task that loads crates on behalf of deferred symbol resolution requests.
+//!
+//! Callers that cannot afford to block on crate loading use
+//! [`CrateNamespace::get_symbol_or_enqueue_load()`], which enqueues a request
+//! that the task spawned by [`start()`] picks up and processes.
+//!
+//! [`CrateNamespace::get_symbol_or_enqueue_load()`]: mod_mgmt::CrateNamespace::get_symbol_or_enqueue_load
+
+#![no_std]
+
+extern crate alloc;
+
+use log::info;
+use memory::MmiRef;
+use task::JoinableTaskRef;
+
+/// Spawns the symbol loader task, which runs forever.
+///
+/// This should only be called once.
+pub fn start() -> Result<JoinableTaskRef, &'static str> {
+    let kernel_mmi_ref = memory::get_kernel_mmi_ref()
+        .ok_or("symbol_loader::start(): kernel MMI was not yet initialized")?;
+
+    spawn::new_task_builder(symbol_loader, kernel_mmi_ref)
+        .name("symbol_loader".into())
+        .spawn()
+}
+
+/// The entry point for the symbol loader task.
+fn symbol_loader(kernel_mmi_ref: &'static MmiRef) -> Result<(), &'static str> {
+    info!("symbol_loader task started");
+    loop {
+        let request = dreadnought::block_on(mod_mgmt::next_symbol_load_request());
+        request.process(kernel_mmi_ref, false);
+    }
+}
@@ -1,9 +1,13 @@
 #![feature(negative_impls, let_chains)]
 #![no_std]
 
+extern crate alloc;
+
 mod condvar;
 
+use alloc::vec::Vec;
 use core::sync::atomic::{AtomicUsize, Ordering};
+use scheduler::PriorityBoost;
 use sync::{spin, MutexFlavor, RwLockFlavor};
 use wait_queue::WaitQueue;
 
@@ -27,8 +31,10 @@ pub struct Block {}
 impl MutexFlavor for Block {
     #[allow(clippy::declare_interior_mutable_const)]
     const INIT: Self::LockData = Self::LockData {
-        queue: WaitQueue::new(),
+        queue: WaitQueue::with_name("sync_block::Mutex"),
         holder: AtomicUsize::new(0),
+        waiter_priorities: spin::Mutex::new(Vec::new()),
+        holder_boost: spin::Mutex::new(None),
     };
 
     type LockData = MutexData;
@@ -98,10 +104,7 @@ impl MutexFlavor for Block {
             }
 
             // Slow path
-            #[cfg(priority_inheritance)]
-            let _priority_guard = scheduler::inherit_priority(&holder_task);
-
-            data.queue.wait_until(|| Self::try_lock(mutex, data))
+            Self::wait(mutex, data)
         } else {
             // Unlikely case that another thread just acquired the lock, but hasn't yet set
             // data.holder.
@@ -112,29 +115,115 @@ impl MutexFlavor for Block {
             }
 
             // Slow path
-            data.queue.wait_until(|| Self::try_lock(mutex, data))
+            Self::wait(mutex, data)
         }
     }
 
     #[inline]
     fn post_unlock(data: &Self::LockData) {
-        // See comments in try_lock and lock on why this is necessary.
-        data.holder.store(0, Ordering::Release);
+        // The holder must be cleared while holding the `holder_boost` lock,
+        // so that a concurrent `boost_holder` can't apply a boost meant for us
+        // after we have released the lock.
+        let boost = {
+            let mut holder_boost = data.holder_boost.lock();
+            // See comments in try_lock and lock on why this is necessary.
+            data.holder.store(0, Ordering::Release);
+            holder_boost.take()
+        };
+        // Dropping the boost restores our priority, which takes scheduler locks.
+        drop(boost);
         data.queue.notify_one();
     }
 }
 
+impl Block {
+    /// Blocks the current task until it acquires the mutex.
+    ///
+    /// While blocked, the current task lends its priority to the mutex holder,
+    /// such that a lower-priority holder can't be starved by medium-priority
+    /// tasks while a higher-priority task waits on it (priority inversion).
+    /// This only has an effect under the priority scheduler.
+    fn wait<'a, T>(
+        mutex: &'a spin::Mutex<T>,
+        data: &'a MutexData,
+    ) -> (spin::MutexGuard<'a, T>, ())
+    where
+        T: ?Sized,
+    {
+        let Some(priority) = task::with_current_task(scheduler::priority).ok().flatten() else {
+            return data.queue.wait_until(|| Self::try_lock(mutex, data));
+        };
+
+        data.waiter_priorities.lock().push(priority);
+        Self::boost_holder(data);
+
+        let guards = data.queue.wait_until(|| Self::try_lock(mutex, data));
+
+        {
+            let mut waiter_priorities = data.waiter_priorities.lock();
+            if let Some(index) = waiter_priorities.iter().position(|p| *p == priority) {
+                waiter_priorities.swap_remove(index);
+            }
+        }
+        // We are now the holder, so inherit the priority of the remaining waiters.
+        Self::boost_holder(data);
+
+        guards
+    }
+
+    /// Boosts the current mutex holder to the highest priority of the tasks
+    /// waiting on the mutex.
+    ///
+    /// The holder's previous boost, if any, is only replaced by a higher one,
+    /// and is removed when the holder unlocks the mutex.
+    fn boost_holder(data: &MutexData) {
+        let Some(priority) = data.waiter_priorities.lock().iter().copied().max() else {
+            return;
+        };
+        let holder_id = data.holder.load(Ordering::Acquire);
+        if holder_id == 0 {
+            return;
+        }
+        let Some(holder_task) = task::get_task(holder_id).and_then(|task| task.upgrade()) else {
+            return;
+        };
+        if data.holder_boost.lock().as_ref().is_some_and(|boost| {
+            boost.task() == &holder_task && boost.priority() >= priority
+        }) {
+            return;
+        }
+
+        let Some(boost) = scheduler::boost_priority(&holder_task, priority) else {
+            return;
+        };
+        let previous = {
+            let mut holder_boost = data.holder_boost.lock();
+            if data.holder.load(Ordering::Acquire) == holder_id {
+                holder_boost.replace(boost)
+            } else {
+                // The holder unlocked the mutex in the meantime.
+                Some(boost)
+            }
+        };
+        drop(previous);
+    }
+}
+
 #[doc(hidden)]
 pub struct MutexData {
     queue: WaitQueue,
     holder: AtomicUsize,
+    /// The priorities of the tasks blocked on the mutex, with duplicates.
+    waiter_priorities: spin::Mutex<Vec<u8>>,
+    /// The priority boost lent to the holder by the highest-priority waiter.
+    holder_boost: spin::Mutex<Option<PriorityBoost>>,
 }
 
 impl RwLockFlavor for Block {
     #[allow(clippy::declare_interior_mutable_const)]
     const INIT: Self::LockData = RwLockData {
-        readers: WaitQueue::new(),
-        writers: WaitQueue::new(),
+        readers: WaitQueue::with_name("sync_block::RwLock (read)"),
+        writers: WaitQueue::with_name("sync_block::RwLock (write)"),
     };
 
     type LockData = RwLockData;
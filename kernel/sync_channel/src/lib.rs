@@ -61,8 +61,8 @@ pub fn new_channel_with<T: Send, P: DeadlockPrevention>(
 ) -> (Sender<T, P>, Receiver<T, P>) {
     let channel = Arc::new(Channel {
         queue: MpmcQueue::with_capacity(minimum_capacity),
-        waiting_senders: WaitQueue::new(),
-        waiting_receivers: WaitQueue::new(),
+        waiting_senders: WaitQueue::with_name("sync_channel::Sender"),
+        waiting_receivers: WaitQueue::with_name("sync_channel::Receiver"),
         channel_status: AtomicCell::new(ChannelStatus::Connected),
         sender_count: AtomicUsize::new(1),
         receiver_count: AtomicUsize::new(1),
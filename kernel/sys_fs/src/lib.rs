@@ -0,0 +1,390 @@
+//! A virtual filesystem at `/sys` whose files are generated by kernel subsystems on demand.
+//!
+//! Subsystems register files via [`register_file()`], giving a function that generates
+//! the file's contents as a string. A file's contents are generated when it is opened
+//! (i.e., when it is obtained from its parent directory), so reading a file multiple times
+//! yields a consistent snapshot. This lets existing file utilities such as `cat`, `grep`, and `less`
+//! inspect the system's state without a dedicated command for every statistic.
+//!
+//! Subsystems whose set of files changes over time, e.g., one file per network interface,
+//! can instead register a directory via [`register_dynamic_dir()`], which is given functions
+//! that list the directory's files and generate the contents of a file by its name.
+//!
+//! # Access control
+//! Every file and dynamic directory has an [`Access`] level.
+//! [`Access::Restricted`] entries, e.g., those that reveal kernel addresses,
+//! can only be read by kernel tasks and by applications that were granted access via [`grant_access()`].
+//! Other applications can still list restricted entries, but reading them fails.
+//!
+//! The built-in entries are registered by [`init()`]:
+//! * `/sys/tasks`: a summary of every task.
+//! * `/sys/namespaces`: every crate namespace, with its number of crates and recursive namespace.
+//! * `/sys/crates/<namespace>`: the crates loaded into each namespace.
+//! * `/sys/net/<interface>`: the addresses and packet statistics of each network interface.
+//! * `/sys/interrupts`: the handler registered for each IRQ (restricted; x86_64 only).
+//! * `/sys/machine_check`: the most recent machine-check errors (restricted; x86_64 only).
+
+#![no_std]
+
+extern crate alloc;
+
+mod providers;
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use fs_node::{DirRef, Directory, File, FileOrDir, FileRef, FsNode, WeakDirRef};
+use io::{ByteReader, ByteWriter, IoError, KnownLength};
+use memory::MappedPages;
+use path::{Path, PathBuf};
+use spin::Mutex;
+
+/// The name of the sys directory in the root directory.
+pub const SYS_DIRECTORY_NAME: &str = "sys";
+/// The absolute path of the sys directory.
+pub const SYS_DIRECTORY_PATH: &str = "/sys";
+
+/// Who may read a file in the sys filesystem.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// Any task may read the file.
+    Public,
+    /// Only kernel tasks and applications granted access via [`grant_access()`] may read the file.
+    Restricted,
+}
+
+/// A function that generates the contents of a file.
+type FileGenerator = Arc<dyn Fn() -> String + Send + Sync>;
+/// A function that lists the names of the files in a dynamic directory.
+type DirLister = Arc<dyn Fn() -> Vec<String> + Send + Sync>;
+/// A function that generates the contents of the file with the given name in a dynamic directory,
+/// or returns `None` if no such file exists.
+type DynamicFileGenerator = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// An entry registered in the sys filesystem.
+enum Entry {
+    File(Access, FileGenerator),
+    Dir(BTreeMap<String, Entry>),
+    DynamicDir(Access, DirLister, DynamicFileGenerator),
+}
+
+/// The entries in the top-level sys directory.
+static ENTRIES: Mutex<BTreeMap<String, Entry>> = Mutex::new(BTreeMap::new());
+
+/// The names of the application crates that may read restricted files.
+static GRANTED_APPS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Creates the sys directory in the root directory and registers the built-in entries.
+pub fn init() -> Result<(), &'static str> {
+    let sys_dir = Arc::new(Mutex::new(SysDir { path: String::new() })) as DirRef;
+    root::get_root().lock().insert(FileOrDir::Dir(sys_dir))?;
+    providers::register_all()
+}
+
+/// Registers a file at the given `path` relative to the sys directory, e.g., `"net/arp_cache"`.
+///
+/// The file's contents are produced by `generator` whenever the file is opened.
+/// Intermediate directories are created as needed.
+///
+/// Returns an error if an entry already exists at `path` or if a parent of `path` isn't a regular directory.
+pub fn register_file<G>(path: &str, access: Access, generator: G) -> Result<(), &'static str>
+where
+    G: Fn() -> String + Send + Sync + 'static,
+{
+    insert_entry(path, Entry::File(access, Arc::new(generator)))
+}
+
+/// Registers a directory at the given `path` relative to the sys directory,
+/// whose files are listed by `list` and generated by `generator`.
+///
+/// The `generator` is given the name of the file being opened,
+/// and returns `None` if no file with that name exists.
+///
+/// Returns an error if an entry already exists at `path` or if a parent of `path` isn't a regular directory.
+pub fn register_dynamic_dir<L, G>(path: &str, access: Access, list: L, generator: G) -> Result<(), &'static str>
+where
+    L: Fn() -> Vec<String> + Send + Sync + 'static,
+    G: Fn(&str) -> Option<String> + Send + Sync + 'static,
+{
+    insert_entry(path, Entry::DynamicDir(access, Arc::new(list), Arc::new(generator)))
+}
+
+/// Removes the file or directory registered at the given `path` relative to the sys directory,
+/// including everything beneath it.
+///
+/// Returns `true` if an entry was removed.
+pub fn unregister(path: &str) -> bool {
+    let components: Vec<&str> = components(path).collect();
+    let Some((last, parents)) = components.split_last() else {
+        return false;
+    };
+    let mut entries = ENTRIES.lock();
+    let mut dir = &mut *entries;
+    for component in parents {
+        match dir.get_mut(*component) {
+            Some(Entry::Dir(children)) => dir = children,
+            _ => return false,
+        }
+    }
+    dir.remove(*last).is_some()
+}
+
+/// Allows the application crate with the given name (without its hash) to read restricted files.
+pub fn grant_access(app_crate_name: &str) {
+    let mut granted = GRANTED_APPS.lock();
+    if !granted.iter().any(|name| name == app_crate_name) {
+        granted.push(app_crate_name.to_string());
+    }
+}
+
+/// Revokes the access to restricted files previously given via [`grant_access()`].
+///
+/// Returns `true` if the application had been granted access.
+pub fn revoke_access(app_crate_name: &str) -> bool {
+    let mut granted = GRANTED_APPS.lock();
+    let len_before = granted.len();
+    granted.retain(|name| name != app_crate_name);
+    granted.len() != len_before
+}
+
+impl Access {
+    /// Returns whether the current task may read entries with this access level.
+    fn permits_current_task(self) -> bool {
+        match self {
+            Access::Public => true,
+            Access::Restricted => task::with_current_task(|task| match &task.app_crate {
+                None => true,
+                Some(app) => {
+                    let app = app.lock_as_ref();
+                    GRANTED_APPS.lock().iter().any(|name| name == app.crate_name_without_hash())
+                }
+            }).unwrap_or(false),
+        }
+    }
+}
+
+/// Returns the non-empty components of the given `path`.
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|c| !c.is_empty())
+}
+
+fn insert_entry(path: &str, entry: Entry) -> Result<(), &'static str> {
+    let components: Vec<&str> = components(path).collect();
+    let (last, parents) = components.split_last().ok_or("sys_fs: an entry's path must not be empty")?;
+    let mut entries = ENTRIES.lock();
+    let mut dir = &mut *entries;
+    for component in parents {
+        let child = dir.entry(component.to_string()).or_insert_with(|| Entry::Dir(BTreeMap::new()));
+        match child {
+            Entry::Dir(children) => dir = children,
+            _ => return Err("sys_fs: a parent of the entry's path is not a directory"),
+        }
+    }
+    if dir.contains_key(*last) {
+        return Err("sys_fs: an entry already exists at that path");
+    }
+    dir.insert(last.to_string(), entry);
+    Ok(())
+}
+
+/// The result of looking up a path in the registered entries.
+enum Lookup {
+    File(Access, FileGenerator),
+    Dir(Vec<String>),
+    DynamicDir(Access, DirLister),
+    DynamicFile(Access, DynamicFileGenerator),
+}
+
+/// Looks up the entry at the given `path` relative to the sys directory.
+fn lookup(path: &str) -> Option<Lookup> {
+    let entries = ENTRIES.lock();
+    let mut dir = &*entries;
+    let mut components = components(path).peekable();
+    while let Some(component) = components.next() {
+        let is_last = components.peek().is_none();
+        match dir.get(component)? {
+            Entry::Dir(children) => dir = children,
+            Entry::File(access, generator) if is_last => return Some(Lookup::File(*access, generator.clone())),
+            Entry::DynamicDir(access, lister, _) if is_last => return Some(Lookup::DynamicDir(*access, lister.clone())),
+            Entry::DynamicDir(access, _, generator) => {
+                // Dynamic directories contain only files.
+                components.next()?;
+                return components.next().is_none().then(|| Lookup::DynamicFile(*access, generator.clone()));
+            }
+            Entry::File(..) => return None,
+        }
+    }
+    Some(Lookup::Dir(dir.keys().cloned().collect()))
+}
+
+/// Returns the absolute path of the given `path` relative to the sys directory.
+fn absolute_path(path: &str) -> String {
+    if path.is_empty() {
+        String::from(SYS_DIRECTORY_PATH)
+    } else {
+        format!("{SYS_DIRECTORY_PATH}/{path}")
+    }
+}
+
+/// Returns the parent directory of the given `path` relative to the sys directory.
+fn parent_dir(path: &str) -> Option<DirRef> {
+    if path.is_empty() {
+        return Some(root::get_root().clone());
+    }
+    let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+    match Path::get_absolute(&PathBuf::from(absolute_path(parent))) {
+        Some(FileOrDir::Dir(d)) => Some(d),
+        _ => None,
+    }
+}
+
+/// Returns the last component of the given `path` relative to the sys directory.
+fn name(path: &str) -> String {
+    match path.rsplit_once('/') {
+        Some((_, name)) => name.to_string(),
+        None if path.is_empty() => String::from(SYS_DIRECTORY_NAME),
+        None => path.to_string(),
+    }
+}
+
+
+/// A directory in the sys filesystem, either the top-level sys directory or one beneath it.
+///
+/// Only the top-level directory is persistent; others are created when they are accessed.
+struct SysDir {
+    /// The path of this directory relative to the sys directory, which is empty for the sys directory itself.
+    path: String,
+}
+
+impl SysDir {
+    fn child_path(&self, name: &str) -> String {
+        if self.path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", self.path, name)
+        }
+    }
+}
+
+impl FsNode for SysDir {
+    fn get_absolute_path(&self) -> String {
+        absolute_path(&self.path)
+    }
+
+    fn get_name(&self) -> String {
+        name(&self.path)
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        parent_dir(&self.path)
+    }
+
+    fn set_parent_dir(&mut self, _new_parent: WeakDirRef) {
+        // do nothing
+    }
+}
+
+impl Directory for SysDir {
+    fn get(&self, name: &str) -> Option<FileOrDir> {
+        let path = self.child_path(name);
+        let contents = match lookup(&path)? {
+            Lookup::Dir(_) | Lookup::DynamicDir(..) => {
+                return Some(FileOrDir::Dir(Arc::new(Mutex::new(SysDir { path })) as DirRef));
+            }
+            Lookup::File(access, generator) => {
+                access.permits_current_task().then(|| generator()).ok_or(PERMISSION_DENIED)
+            }
+            Lookup::DynamicFile(access, generator) => {
+                if access.permits_current_task() {
+                    Ok(generator(name)?)
+                } else {
+                    Err(PERMISSION_DENIED)
+                }
+            }
+        };
+        Some(FileOrDir::File(Arc::new(Mutex::new(SysFile { path, contents })) as FileRef))
+    }
+
+    fn list(&self) -> Vec<String> {
+        match lookup(&self.path) {
+            Some(Lookup::Dir(names)) => names,
+            Some(Lookup::DynamicDir(_access, lister)) => lister(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, _node: FileOrDir) -> Result<Option<FileOrDir>, &'static str> {
+        Err("cannot insert node into read-only sys filesystem")
+    }
+
+    fn remove(&mut self, _node: &FileOrDir) -> Option<FileOrDir> {
+        None
+    }
+}
+
+const PERMISSION_DENIED: &str = "permission denied: this file is restricted to kernel tasks and privileged applications";
+
+/// A file in the sys filesystem, whose contents were generated when it was opened.
+struct SysFile {
+    /// The path of this file relative to the sys directory.
+    path: String,
+    /// The generated contents, or an error if the current task may not read this file.
+    contents: Result<String, &'static str>,
+}
+
+impl FsNode for SysFile {
+    fn get_absolute_path(&self) -> String {
+        absolute_path(&self.path)
+    }
+
+    fn get_name(&self) -> String {
+        name(&self.path)
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        parent_dir(&self.path)
+    }
+
+    fn set_parent_dir(&mut self, _new_parent: WeakDirRef) {
+        // do nothing
+    }
+}
+
+impl ByteReader for SysFile {
+    fn read_at(&mut self, buf: &mut [u8], offset: usize) -> Result<usize, IoError> {
+        let output = self.contents.as_ref().map_err(|e| IoError::from(*e))?;
+        if offset > output.len() {
+            return Err(IoError::InvalidInput);
+        }
+        let count = core::cmp::min(buf.len(), output.len() - offset);
+        buf[..count].copy_from_slice(&output.as_bytes()[offset..(offset + count)]);
+        Ok(count)
+    }
+}
+
+impl ByteWriter for SysFile {
+    fn write_at(&mut self, _buffer: &[u8], _offset: usize) -> Result<usize, IoError> {
+        Err(IoError::from("not permitted to write to files in the sys filesystem"))
+    }
+    fn flush(&mut self) -> Result<(), IoError> { Ok(()) }
+}
+
+impl KnownLength for SysFile {
+    fn len(&self) -> usize {
+        self.contents.as_ref().map_or(0, String::len)
+    }
+}
+
+impl File for SysFile {
+    fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
+        Err("sys files are autogenerated, cannot be memory mapped")
+    }
+
+    fn set_len(&mut self, _len: usize) -> Result<(), &'static str> {
+        Err("sys files are autogenerated, cannot be resized")
+    }
+}
@@ -0,0 +1,119 @@
+//! The built-in entries of the sys filesystem, which describe the kernel's core subsystems.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+use crate::{register_dynamic_dir, register_file, Access};
+
+pub(crate) fn register_all() -> Result<(), &'static str> {
+    register_file("tasks", Access::Public, tasks)?;
+    register_file("namespaces", Access::Public, namespaces)?;
+    register_dynamic_dir("crates", Access::Public, namespace_names, crates_in_namespace)?;
+    register_dynamic_dir("net", Access::Public, interface_names, interface)?;
+    #[cfg(target_arch = "x86_64")]
+    register_file("interrupts", Access::Restricted, interrupts)?;
+    #[cfg(target_arch = "x86_64")]
+    register_file("machine_check", Access::Restricted, machine_check_errors)?;
+    Ok(())
+}
+
+fn tasks() -> String {
+    let mut out = String::from("ID\tRUNSTATE\tCPU\tPIN\tPRIO\tSTACK\tNAMESPACE\tNAME\n");
+    for info in task::all_task_info() {
+        let cpu = info.running_on_cpu.map_or(String::from("-"), |c| c.to_string());
+        // Pinned tasks show their CPU, and tasks restricted to several CPUs show all of them.
+        let pin = info.affinity.map_or(String::from("-"), |cpus| cpus.to_string());
+        let priority = info.priority.map_or(String::from("-"), |p| p.to_string());
+        let stack = info.stack_usage.map_or(String::from("-"), |s| format!("{}/{}", s.max_used_bytes, s.size_in_bytes));
+        let _ = writeln!(out, "{}\t{:?}\t{}\t{}\t{}\t{}\t{}\t{}",
+            info.id, info.runstate, cpu, pin, priority, stack, info.namespace, info.name,
+        );
+    }
+    out
+}
+
+fn namespaces() -> String {
+    let mut out = String::from("NAME\tCRATES\tRECURSIVE\n");
+    for ns in mod_mgmt::registered_namespaces() {
+        let _ = writeln!(out, "{}\t{}\t{}",
+            ns.name(),
+            ns.crate_names(false).len(),
+            ns.recursive_namespace().map_or("-", |r| r.name()),
+        );
+    }
+    out
+}
+
+fn namespace_names() -> Vec<String> {
+    mod_mgmt::registered_namespaces().iter().map(|ns| ns.name().to_string()).collect()
+}
+
+fn crates_in_namespace(namespace: &str) -> Option<String> {
+    let ns = mod_mgmt::registered_namespaces().into_iter().find(|ns| ns.name() == namespace)?;
+    let mut names = ns.crate_names(false);
+    names.sort_unstable();
+    let mut out = String::new();
+    for name in names {
+        let _ = writeln!(out, "{}", name);
+    }
+    Some(out)
+}
+
+/// Network interfaces are named by their index in the list of interfaces, e.g., `eth0`.
+fn interface_names() -> Vec<String> {
+    (0..net::get_interfaces().lock().len()).map(|i| format!("eth{i}")).collect()
+}
+
+fn interface(name: &str) -> Option<String> {
+    let index: usize = name.strip_prefix("eth")?.parse().ok()?;
+    let interface = net::get_interfaces().lock().get(index)?.clone();
+
+    let mac = interface.mac_address();
+    let stats = interface.stats();
+    let mut out = String::new();
+    let _ = writeln!(out, "mac:\t\t{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        mac[0], mac[1], mac[2], mac[3], mac[4], mac[5],
+    );
+    for ip in interface.ip_addrs() {
+        let _ = writeln!(out, "ip:\t\t{}", ip);
+    }
+    let _ = writeln!(out, "mtu:\t\t{}", interface.capabilities().max_transmission_unit);
+    let _ = writeln!(out, "rx_packets:\t{}", stats.rx_packets);
+    let _ = writeln!(out, "rx_bytes:\t{}", stats.rx_bytes);
+    let _ = writeln!(out, "tx_packets:\t{}", stats.tx_packets);
+    let _ = writeln!(out, "tx_bytes:\t{}", stats.tx_bytes);
+    Some(out)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn interrupts() -> String {
+    let mut handlers = Vec::new();
+    interrupts::for_each_registered_interrupt(|num, addr| handlers.push((num, addr)));
+
+    // Look up the crates after releasing the IDT lock.
+    let namespaces = mod_mgmt::registered_namespaces();
+    let mut out = String::from("IRQ\tHANDLER\t\t\tCRATE\n");
+    for (num, addr) in handlers {
+        let crate_name = namespaces.iter()
+            .find_map(|ns| ns.get_crate_containing_address(addr, false))
+            .map_or(String::from("unknown"), |c| c.lock_as_ref().crate_name.to_string());
+        let _ = writeln!(out, "{:#04X}\t{:#018X}\t{}", num, addr.value(), crate_name);
+    }
+    out
+}
+
+#[cfg(target_arch = "x86_64")]
+fn machine_check_errors() -> String {
+    let mut out = String::from("CPU	BANK	SEVERITY	STATUS			ADDRESS			CLASS
+");
+    for err in machine_check::recorded_errors() {
+        let address = err.address.map_or(String::from("-"), |a| format!("{:#018X}", a.value()));
+        let _ = writeln!(out, "{}	{}	{:?}	{:#018X}	{}	{}",
+            err.cpu, err.bank, err.severity(), err.status.0, address, err.status.error_class(),
+        );
+    }
+    out
+}
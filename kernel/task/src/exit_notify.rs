@@ -0,0 +1,185 @@
+//! Waitpid-style notifications of task exits.
+//!
+//! An [`ExitReceiver`] can subscribe to the exits of arbitrary tasks by their IDs,
+//! without needing to hold their [`JoinableTaskRef`](crate::JoinableTaskRef)s.
+//! When a subscribed task exits, its [`ExitValue`] is sent to the receiver
+//! instead of being stored for a later `join()`, and the task is reaped right away.
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+};
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+};
+use spin::Mutex;
+use sync_irq::IrqSafeMutex;
+use task_struct::{ExitValue, RunState};
+use crate::{get_my_current_task, get_task, ScheduleOnDrop};
+
+/// The channels subscribed to the exits of tasks, keyed by the ID of the subscribed task.
+///
+/// Each task can have at most one subscriber, as its exit value can only be taken once.
+static EXIT_SUBSCRIBERS: IrqSafeMutex<BTreeMap<usize, Weak<ExitChannel>>> = IrqSafeMutex::new(BTreeMap::new());
+
+/// The state shared between an [`ExitReceiver`] and the exiting tasks that send to it.
+pub(crate) struct ExitChannel {
+    /// The IDs and exit values of subscribed tasks that have exited but not yet been received.
+    queue: Mutex<VecDeque<(usize, ExitValue)>>,
+    /// The number of subscribed tasks that have not yet exited.
+    pending: AtomicUsize,
+    /// The waker that is awoken when a subscribed task exits.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl ExitChannel {
+    pub(crate) fn send(&self, task_id: usize, exit_value: ExitValue) {
+        self.queue.lock().push_back((task_id, exit_value));
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+        if let Some(waker) = self.waker.lock().take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Removes and returns the live subscriber to the exit of the task with the given ID, if any.
+pub(crate) fn take_subscriber(task_id: usize) -> Option<Arc<ExitChannel>> {
+    EXIT_SUBSCRIBERS.lock().remove(&task_id).and_then(|s| s.upgrade())
+}
+
+/// The receiving end of a channel of task exit notifications.
+///
+/// Dropping an `ExitReceiver` cancels all of its remaining subscriptions;
+/// the exit values of those tasks are then stored for a `join()` as usual.
+pub struct ExitReceiver {
+    channel: Arc<ExitChannel>,
+}
+
+impl Default for ExitReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExitReceiver {
+    /// Creates a new `ExitReceiver` that is not yet subscribed to any tasks.
+    pub fn new() -> ExitReceiver {
+        ExitReceiver {
+            channel: Arc::new(ExitChannel {
+                queue: Mutex::new(VecDeque::new()),
+                pending: AtomicUsize::new(0),
+                waker: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Subscribes this receiver to the exit of the task with the given ID.
+    ///
+    /// The task's [`ExitValue`] will be sent to this receiver once the task exits,
+    /// so a later `join()` on that task will fail to obtain it.
+    /// If the task has already exited but has not yet been reaped,
+    /// its exit value is taken and sent to this receiver immediately.
+    ///
+    /// # Return
+    /// * `Err` if there is no task with the given ID,
+    ///   or if another `ExitReceiver` is already subscribed to that task.
+    ///
+    /// # Locking / Deadlock
+    /// Obtains the lock on the system task list.
+    pub fn subscribe(&self, task_id: usize) -> Result<(), &'static str> {
+        let task = get_task(task_id)
+            .and_then(|t| t.upgrade())
+            .ok_or("ExitReceiver::subscribe(): no task exists with the given ID")?;
+        {
+            let mut subscribers = EXIT_SUBSCRIBERS.lock();
+            if subscribers.get(&task_id).map_or(false, |s| s.strong_count() > 0) {
+                return Err("ExitReceiver::subscribe(): another ExitReceiver is already subscribed to that task");
+            }
+            self.channel.pending.fetch_add(1, Ordering::Relaxed);
+            subscribers.insert(task_id, Arc::downgrade(&self.channel));
+        }
+
+        // If the task exited before we subscribed to it, its exit value is still in its mailbox.
+        if task.runstate() == RunState::Exited {
+            if let Some(exit_value) = task.reap_exit_value() {
+                EXIT_SUBSCRIBERS.lock().remove(&task_id);
+                self.channel.send(task_id, exit_value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the ID and exit value of a subscribed task that has exited, if any,
+    /// without blocking.
+    pub fn try_recv(&self) -> Option<(usize, ExitValue)> {
+        self.channel.queue.lock().pop_front()
+    }
+
+    /// Returns the number of subscribed tasks that have not yet exited.
+    pub fn pending(&self) -> usize {
+        self.channel.pending.load(Ordering::Relaxed)
+    }
+
+    /// Blocks the current task until a subscribed task has exited,
+    /// and then returns that task's ID and exit value.
+    ///
+    /// Exit notifications are received in the order that the subscribed tasks exited.
+    ///
+    /// # Return
+    /// * `Err` if there are no exit notifications to receive
+    ///   and this receiver is not subscribed to any tasks that have yet to exit.
+    pub fn recv(&self) -> Result<(usize, ExitValue), &'static str> {
+        loop {
+            if let Some(notification) = self.try_recv() {
+                return Ok(notification);
+            }
+            if self.pending() == 0 {
+                return Err("ExitReceiver::recv(): not subscribed to any tasks that have yet to exit");
+            }
+
+            // Create a waker+blocker pair that will block the current task
+            // and then wake it once a subscribed task exits.
+            let curr_task = get_my_current_task().ok_or("ExitReceiver::recv(): couldn't get current task")?;
+            let task_to_block = curr_task.clone();
+            let wake_action = move || {
+                let _ = curr_task.unblock();
+            };
+            let (waker, blocker) = waker_generic::new_waker(wake_action);
+            *self.channel.waker.lock() = Some(waker);
+
+            // A subscribed task may have exited before the waker was set.
+            if let Some(notification) = self.try_recv() {
+                self.channel.waker.lock().take();
+                return Ok(notification);
+            }
+            let block_action = || {
+                let _ = task_to_block.block();
+                ScheduleOnDrop { }
+            };
+            blocker.block(block_action);
+        }
+    }
+
+    /// The asynchronous version of [`ExitReceiver::recv()`].
+    ///
+    /// Returns `Poll::Ready(Err(..))` under the same conditions that `recv()` returns an error.
+    pub fn poll_recv(&self, cx: &mut Context) -> Poll<Result<(usize, ExitValue), &'static str>> {
+        if let Some(notification) = self.try_recv() {
+            return Poll::Ready(Ok(notification));
+        }
+        if self.pending() == 0 {
+            return Poll::Ready(Err("ExitReceiver::poll_recv(): not subscribed to any tasks that have yet to exit"));
+        }
+        *self.channel.waker.lock() = Some(cx.waker().clone());
+
+        // A subscribed task may have exited before the waker was set.
+        match self.try_recv() {
+            Some(notification) => {
+                self.channel.waker.lock().take();
+                Poll::Ready(Ok(notification))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Point-in-time summaries of tasks for listing and monitoring tools.
+
+use alloc::{string::String, vec::Vec};
+use core::time::Duration;
+use cpu::{CpuId, CpuSet};
+use task_struct::{RunState, StackUsage};
+use crate::TaskRef;
+
+/// A summary of a task's state at the time [`TaskInfo::new()`] was called.
+///
+/// The summary is not kept up to date as the task continues to run.
+#[derive(Clone, Debug)]
+pub struct TaskInfo {
+    pub id: usize,
+    pub name: String,
+    /// The name of the `CrateNamespace` that the task runs in.
+    pub namespace: String,
+    /// The name (without its hash) of the application crate the task was spawned from, if any.
+    pub app_crate: Option<String>,
+    pub runstate: RunState,
+    /// The CPU that the task is currently running on, if any.
+    pub running_on_cpu: Option<CpuId>,
+    /// The CPU that the task is pinned to, if any.
+    pub pinned_cpu: Option<CpuId>,
+    /// The CPUs that the task may be scheduled on, if it is restricted to a subset of CPUs.
+    pub affinity: Option<CpuSet>,
+    pub is_idle_task: bool,
+    /// The priority of the task, if it is on a priority scheduler's run queue.
+    pub priority: Option<u8>,
+    pub stack_usage: Option<StackUsage>,
+    pub runtime_stats: RuntimeStats,
+}
+
+impl TaskInfo {
+    /// Gathers a summary of the given task's current state.
+    ///
+    /// # Locking / Deadlock
+    /// Obtains the lock on the task's inner state to scan its stack,
+    /// the lock on its application crate, and the locks on the system's run queues.
+    pub fn new(task: &TaskRef) -> TaskInfo {
+        TaskInfo {
+            id: task.id,
+            name: task.name.clone(),
+            namespace: String::from(task.get_namespace().name()),
+            app_crate: task.app_crate.as_ref()
+                .map(|app| String::from(app.lock_as_ref().crate_name_without_hash())),
+            runstate: task.runstate(),
+            running_on_cpu: task.running_on_cpu(),
+            pinned_cpu: task.pinned_cpu(),
+            affinity: task.affinity(),
+            is_idle_task: task.is_an_idle_task,
+            priority: crate::scheduler::priority(task),
+            stack_usage: task.stack_usage(),
+            runtime_stats: RuntimeStats::new(task),
+        }
+    }
+}
+
+/// Returns a summary of every task that currently exists, in order of task ID.
+pub fn all_task_info() -> Vec<TaskInfo> {
+    crate::all_tasks()
+        .into_iter()
+        .filter_map(|(_, weak_task)| weak_task.upgrade())
+        .map(|task| TaskInfo::new(&task))
+        .collect()
+}
+
+/// How much CPU time a task has used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RuntimeStats {
+    /// The total time the task has spent running on any CPU.
+    pub runtime: Duration,
+    /// The number of times the task has been switched to.
+    pub context_switches: u64,
+    /// The time since the task was created.
+    pub lifetime: Duration,
+}
+
+impl RuntimeStats {
+    /// Gathers the given task's CPU time usage so far.
+    pub fn new(task: &TaskRef) -> RuntimeStats {
+        RuntimeStats {
+            runtime: task.runtime(),
+            context_switches: task.context_switches(),
+            lifetime: task.created_at().elapsed(),
+        }
+    }
+
+    /// Returns the share of its lifetime that the task has spent running,
+    /// in tenths of a percent (0 to 1000).
+    pub fn cpu_permille(&self) -> u64 {
+        let lifetime = self.lifetime.as_nanos();
+        if lifetime == 0 {
+            return 0;
+        }
+        (self.runtime.as_nanos() * 1000 / lifetime).min(1000) as u64
+    }
+}
+
+/// Returns the CPU time usage of the task with the given ID,
+/// or `None` if no such task exists.
+pub fn runtime_stats(task_id: usize) -> Option<RuntimeStats> {
+    crate::get_task(task_id)
+        .and_then(|weak_task| weak_task.upgrade())
+        .map(|task| RuntimeStats::new(&task))
+}
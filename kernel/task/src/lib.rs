@@ -19,6 +19,7 @@
 //! 2. Register a kill handler for the current task -- [`set_kill_handler()`].
 //! 3. Yield the current CPU and schedule in another task -- [`schedule()`].
 //! 4. Switch from the current task to another specific "next" task -- [`task_switch()`].
+//! 5. Be notified when arbitrary tasks exit, without joining them -- [`ExitReceiver`].
 //!
 //! To create new task, use the task builder functions in [`spawn`](../spawn/index.html)
 //! rather than attempting to manually instantiate a `TaskRef`.
@@ -27,10 +28,13 @@
 #![feature(negative_impls)]
 #![feature(thread_local)]
 #![feature(let_chains)]
+#![feature(allocator_api)]
 
 extern crate alloc;
 
 pub mod scheduler;
+mod info;
+mod exit_notify;
 
 use alloc::{
     boxed::Box,
@@ -46,6 +50,7 @@ use core::{
     ops::Deref,
     sync::atomic::{AtomicBool, fence, Ordering},
     task::Waker,
+    time::Duration,
 };
 use cpu::CpuId;
 use irq_safety::hold_interrupts;
@@ -53,6 +58,7 @@ use log::error;
 use environment::Environment;
 use memory::MmiRef;
 use no_drop::NoDrop;
+use object_cache::ObjectCache;
 use preemption::PreemptionGuard;
 use spin::Mutex;
 use sync_irq::IrqSafeMutex;
@@ -63,11 +69,14 @@ use task_struct::ExposedTask;
 // Re-export main types from `task_struct`.
 pub use task_struct::{
     ExitValue, InheritedStates, KillHandler, KillReason,
-    PanicInfoOwned, RestartInfo, RunState, Task,
+    PanicInfoOwned, RestartInfo, RestartPolicy, RunState, StackUsage, Task,
+    enable_runstate_timestamps,
 };
 #[cfg(simd_personality)]
 pub use task_struct::SimdExt;
 pub use scheduler::schedule;
+pub use info::{TaskInfo, all_task_info, RuntimeStats, runtime_stats};
+pub use exit_notify::ExitReceiver;
 
 
 /// The list of all Tasks in the system.
@@ -107,7 +116,7 @@ pub type FailureCleanupFunction = fn(ExitableTaskRef, KillReason) -> !;
 /// `TaskRef` implements the [`PartialEq`] and [`Eq`] traits to ensure that
 /// two `TaskRef`s are considered equal if they point to the same underlying `Task`.
 #[derive(Clone)]
-pub struct TaskRef(Arc<TaskRefInner>);
+pub struct TaskRef(Arc<TaskRefInner, &'static ObjectCache>);
 impl fmt::Debug for TaskRef {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("TaskRef")
@@ -115,6 +124,10 @@ impl fmt::Debug for TaskRef {
             .finish_non_exhaustive()
     }
 }
+/// Tasks are created and reaped frequently, so they're allocated from a dedicated object cache
+/// rather than the general heap.
+static TASK_CACHE: ObjectCache = ObjectCache::for_arc::<TaskRefInner>("TaskRef");
+
 struct TaskRefInner {
     /// A wrapper around `Task` that allows us to access all of its internal state.
     /// Note that the deref implementation of `TaskRef` bypasses this as to not expose
@@ -157,13 +170,13 @@ impl TaskRef {
         failure_cleanup_function: FailureCleanupFunction,
     ) -> JoinableTaskRef {
         let exit_value_mailbox = Mutex::new(None);
-        let taskref = TaskRef(Arc::new(TaskRefInner {
+        let taskref = TaskRef(Arc::new_in(TaskRefInner {
             task: task.into(),
             failure_cleanup_function,
             exit_value_mailbox,
             // A new task is joinable until its `JoinableTaskRef` is dropped.
             joinable: AtomicBool::new(true),
-        }));
+        }, &TASK_CACHE));
 
         // Add the new TaskRef to the global task list.
         let _existing_task = TASKLIST.lock().insert(taskref.id, taskref.clone());
@@ -224,13 +237,30 @@ impl TaskRef {
             return Err("BUG: task was already exited! (did not overwrite its existing exit value)");
         }
         {
-            *self.0.exit_value_mailbox.lock() = Some(val);
+            // If an `ExitReceiver` is subscribed to this task's exit, the exit value is sent to it
+            // rather than being stored in the mailbox.
+            let notification = match exit_notify::take_subscriber(self.id) {
+                Some(subscriber) => Some((subscriber, val)),
+                None => {
+                    *self.0.exit_value_mailbox.lock() = Some(val);
+                    None
+                }
+            };
             self.0.task.runstate().store(RunState::Exited);
 
             // Synchronize with the acquire fence in `JoinableTaskRef::join()`,
             // as we have just stored the exit value that `join()` will load.
             fence(Ordering::Release);
 
+            // The subscriber has taken responsibility for this task's exit value,
+            // so this task can be reaped immediately.
+            if let Some((subscriber, val)) = notification {
+                if self.0.task.runstate().compare_exchange(RunState::Exited, RunState::Reaped).is_ok() {
+                    TASKLIST.lock().remove(&self.id);
+                }
+                subscriber.send(self.id, val);
+            }
+
             // Now that we have set the exit value and marked the task as exited,
             // it is safe to wake any other tasks that are waiting for this task to exit.
             if let Some(waker) = self.0.task.inner().lock().waker.take() {
@@ -322,7 +352,7 @@ impl Deref for TaskRef {
 ///
 /// This is created via [`TaskRef::downgrade()`].
 #[derive(Clone)]
-pub struct WeakTaskRef(Weak<TaskRefInner>);
+pub struct WeakTaskRef(Weak<TaskRefInner, &'static ObjectCache>);
 impl WeakTaskRef {
     /// Attempts to upgrade this `WeakTaskRef` to a `TaskRef`; see [`Weak::upgrade()`].
     ///
@@ -424,8 +454,10 @@ impl JoinableTaskRef {
         // when the exit value for this task was stored.
         fence(Ordering::Acquire);
 
-        self.reap_exit_value()
-            .ok_or("BUG: `join()` could not retrieve `ExitValue` after task had exited.")
+        self.reap_exit_value().ok_or(
+            "`join()` could not retrieve `ExitValue` after task had exited; \
+            was it sent to an `ExitReceiver` subscribed to this task?"
+        )
     }
 }
 impl Drop for JoinableTaskRef {
@@ -828,6 +860,21 @@ fn task_switch_inner(
         inner.saved_sp
     };
 
+    // Charge the current task for the time it ran since it was last switched to.
+    // Early task switches occur before a clock source exists, so they aren't timed.
+    let now = time::Instant::try_now().unwrap_or(time::Instant::ZERO);
+    let curr_scheduled_at = curr.0.task.last_scheduled_at().load();
+    let curr_ran_for = if now == time::Instant::ZERO || curr_scheduled_at == time::Instant::ZERO {
+        // The bootstrap task was never switched to, and tasks switched to before a clock source existed
+        // have no timestamp, so we don't know when they started running.
+        Duration::ZERO
+    } else {
+        now.duration_since(curr_scheduled_at)
+    };
+    curr.0.task.runtime_nanos().fetch_add(curr_ran_for.as_nanos() as u64, Ordering::Relaxed);
+    next.0.task.context_switches().fetch_add(1, Ordering::Relaxed);
+    scheduler::run_context_switch_hooks(cpu_id, curr, &next, curr_ran_for);
+
     // Mark the current task as no longer running
     curr.0.task.running_on_cpu().store(None.into());
 
@@ -866,6 +913,7 @@ fn task_switch_inner(
     {
         let _held_interrupts = hold_interrupts();
         next.0.task.running_on_cpu().store(Some(cpu_id).into());
+        next.0.task.last_scheduled_at().store(now);
         next.set_as_current_task();
         drop(_held_interrupts);
     }
@@ -1,8 +1,9 @@
-use alloc::{boxed::Box, sync::Arc, vec::Vec};
-use core::ptr;
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{marker::PhantomData, ptr, time::Duration};
 
 use cpu::CpuId;
-use spin::Mutex;
+use spin::{Mutex, Once};
+use sync_irq::IrqSafeRwLock;
 use sync_preemption::PreemptionSafeMutex;
 
 use crate::TaskRef;
@@ -26,6 +27,27 @@ static SCHEDULER: Option<Arc<ConcurrentScheduler>> = None;
 
 type ConcurrentScheduler = PreemptionSafeMutex<dyn Scheduler>;
 
+/// The CPUs that are isolated from general-purpose task scheduling; see [`set_isolated()`].
+///
+/// This is accessed from the timer interrupt handler, so it must be IRQ-safe.
+static ISOLATED_CPUS: IrqSafeRwLock<Vec<CpuId>> = IrqSafeRwLock::new(Vec::new());
+
+/// The function that interrupts another CPU whose timer tick is stopped,
+/// such that it restarts its timer tick.
+static KICK_CPU_FUNC: Once<fn(CpuId)> = Once::new();
+
+/// The functions invoked upon every context switch; see [`add_context_switch_hook()`].
+///
+/// This is accessed during task switching, so it must be IRQ-safe.
+static CONTEXT_SWITCH_HOOKS: IrqSafeRwLock<Vec<ContextSwitchHook>> = IrqSafeRwLock::new(Vec::new());
+
+/// A function invoked upon every context switch on the given CPU
+/// from the `prev` task, which ran for the given duration, to the `next` task.
+///
+/// Hooks run in the middle of a context switch with preemption disabled,
+/// so they must be short and must not block or switch tasks.
+pub type ContextSwitchHook = fn(cpu: CpuId, prev: &TaskRef, next: &TaskRef, prev_ran_for: Duration);
+
 /// Yields the current CPU by selecting a new `Task` to run next,
 /// and then switches to that new `Task`.
 ///
@@ -51,6 +73,11 @@ pub fn schedule() -> bool {
 
     let cpu_id = preemption_guard.cpu_id();
 
+    // A task that yields or blocks on a tickless CPU may no longer be the only task to run there.
+    if preemption::is_tick_stopped() {
+        preemption::restart_tick();
+    }
+
     let next_task = SCHEDULER.update_guarded(
         |scheduler| scheduler.as_ref().unwrap().lock().next(),
         &preemption_guard,
@@ -102,22 +129,28 @@ where
     });
 }
 
-/// Adds the given task to the least busy run queue.
+/// Adds the given task to the least busy run queue among the CPUs in the task's
+/// [affinity](crate::Task::affinity).
+///
+/// Isolated CPUs are only chosen if the task's affinity contains no other CPUs.
+/// If the task's affinity contains no CPU that has a scheduler, it is ignored.
 pub fn add_task(task: TaskRef) {
     let locked = SCHEDULERS.lock();
-
-    let mut min_busyness = usize::MAX;
-    let mut least_busy_index = None;
-
-    for (i, (_, scheduler)) in locked.iter().enumerate() {
-        let busyness = scheduler.lock().busyness();
-        if busyness < min_busyness {
-            least_busy_index = Some(i);
-            min_busyness = busyness;
-        }
-    }
-
-    locked[least_busy_index.unwrap()].1.lock().add(task);
+    let isolated_cpus = ISOLATED_CPUS.read();
+    let affinity = task.affinity();
+
+    let least_busy = |ignore_affinity: bool| locked.iter()
+        .filter(|(cpu, _)| ignore_affinity || affinity.as_ref().map_or(true, |affinity| affinity.contains(*cpu)))
+        .min_by_key(|(cpu, scheduler)| (isolated_cpus.contains(cpu), scheduler.lock().busyness()))
+        .map(|(_, scheduler)| scheduler);
+
+    let scheduler = least_busy(false)
+        .or_else(|| {
+            log::error!("none of the CPUs in the affinity of task {:?} have a scheduler", task);
+            least_busy(true)
+        })
+        .expect("BUG: add_task(): no schedulers exist");
+    scheduler.lock().add(task);
 }
 
 /// Adds the given task to the specified CPU's run queue.
@@ -125,14 +158,21 @@ pub fn add_task_to(cpu_id: CpuId, task: TaskRef) {
     for (cpu, scheduler) in SCHEDULERS.lock().iter() {
         if *cpu == cpu_id {
             scheduler.lock().add(task);
-            return;
+            break;
         }
     }
+    if is_isolated(cpu_id) {
+        restart_tick_on(cpu_id);
+    }
 }
 
 /// Adds the given task to the current CPU's run queue.
 pub fn add_task_to_current(task: TaskRef) {
-    SCHEDULER.update(|scheduler| scheduler.as_ref().unwrap().lock().add(task))
+    SCHEDULER.update(|scheduler| scheduler.as_ref().unwrap().lock().add(task));
+    if preemption::is_tick_stopped() {
+        let _preemption_guard = preemption::hold_preemption();
+        preemption::restart_tick();
+    }
 }
 
 /// Removes the given task from all run queues.
@@ -161,6 +201,136 @@ pub fn remove_task_from_current(task: &TaskRef) -> bool {
     SCHEDULER.update(|scheduler| scheduler.as_ref().unwrap().lock().remove(task))
 }
 
+/// Isolates the given CPU from general-purpose task scheduling, or ends its isolation.
+///
+/// Tasks are never added to an isolated CPU's run queue unless they are explicitly pinned to it
+/// or their affinity contains only isolated CPUs, so only those tasks will run there,
+/// e.g., latency-critical network polling or realtime tasks.
+/// Upon isolating a CPU, the tasks on its run queue that aren't currently running
+/// are moved to other CPUs, if their affinity permits it.
+///
+/// On x86_64, while an isolated CPU has only one task to run, its timer tick is stopped
+/// such that the task runs without interruption; see [`can_stop_tick()`].
+///
+/// Returns an error if the given CPU has no scheduler,
+/// or if isolating it would leave no CPUs for general-purpose tasks.
+pub fn set_isolated(cpu_id: CpuId, isolated: bool) -> Result<(), &'static str> {
+    let locked = SCHEDULERS.lock();
+    let scheduler = locked.iter()
+        .find(|(cpu, _)| *cpu == cpu_id)
+        .map(|(_, scheduler)| scheduler.clone())
+        .ok_or("no scheduler exists for the given CPU")?;
+
+    let mut isolated_cpus = ISOLATED_CPUS.write();
+    if !isolated {
+        isolated_cpus.retain(|cpu| *cpu != cpu_id);
+        drop(isolated_cpus);
+        drop(locked);
+        restart_tick_on(cpu_id);
+        return Ok(());
+    }
+    if isolated_cpus.contains(&cpu_id) {
+        return Ok(());
+    }
+    if locked.iter().all(|(cpu, _)| *cpu == cpu_id || isolated_cpus.contains(cpu)) {
+        return Err("cannot isolate the last CPU available for general-purpose tasks");
+    }
+    isolated_cpus.push(cpu_id);
+    let general_purpose_cpus: Vec<CpuId> = locked.iter()
+        .map(|(cpu, _)| *cpu)
+        .filter(|cpu| !isolated_cpus.contains(cpu))
+        .collect();
+    drop(isolated_cpus);
+    drop(locked);
+
+    let mut migrated = Vec::new();
+    {
+        let mut scheduler = scheduler.lock();
+        for task in scheduler.tasks() {
+            let can_migrate = general_purpose_cpus.iter().any(|cpu| task.can_run_on(*cpu));
+            if can_migrate && !task.is_running() && scheduler.remove(&task) {
+                migrated.push(task);
+            }
+        }
+    }
+    for task in migrated {
+        add_task(task);
+    }
+    Ok(())
+}
+
+/// Returns whether the given CPU is isolated from general-purpose task scheduling.
+///
+/// See [`set_isolated()`].
+pub fn is_isolated(cpu_id: CpuId) -> bool {
+    ISOLATED_CPUS.read().contains(&cpu_id)
+}
+
+/// Returns the list of CPUs that are isolated from general-purpose task scheduling.
+pub fn isolated_cpus() -> Vec<CpuId> {
+    ISOLATED_CPUS.read().clone()
+}
+
+/// Returns whether the current CPU can stop its timer tick,
+/// i.e., whether it's isolated and its run queue contains no tasks other than the current task.
+///
+/// A blocked task on the run queue also prevents the tick from being stopped,
+/// because it may be unblocked at any time.
+pub fn can_stop_tick() -> bool {
+    if !is_isolated(cpu::current_cpu()) {
+        return false;
+    }
+    super::with_current_task(|current| SCHEDULER.update(|scheduler|
+        scheduler.as_ref().unwrap().lock().has_no_other_tasks(current)
+    )).unwrap_or(false)
+}
+
+/// Sets the function used to interrupt another CPU such that it restarts its stopped timer tick.
+///
+/// This is set by the `scheduler` crate, which handles timer ticks.
+pub fn set_kick_cpu_func(func: fn(CpuId)) {
+    KICK_CPU_FUNC.call_once(|| func);
+}
+
+/// Registers a function to be invoked upon every context switch, e.g., for profiling.
+///
+/// Per-task runtimes are already accounted by the task subsystem itself;
+/// see [`crate::runtime_stats()`].
+pub fn add_context_switch_hook(hook: ContextSwitchHook) {
+    CONTEXT_SWITCH_HOOKS.write().push(hook);
+}
+
+/// Unregisters a function previously registered via [`add_context_switch_hook()`].
+///
+/// Returns `false` if the function wasn't registered.
+pub fn remove_context_switch_hook(hook: ContextSwitchHook) -> bool {
+    let mut hooks = CONTEXT_SWITCH_HOOKS.write();
+    let old_len = hooks.len();
+    hooks.retain(|h| *h as usize != hook as usize);
+    hooks.len() != old_len
+}
+
+/// Invokes all registered context switch hooks.
+pub(crate) fn run_context_switch_hooks(cpu_id: CpuId, prev: &TaskRef, next: &TaskRef, prev_ran_for: Duration) {
+    // Skip the hooks rather than spin in the middle of a context switch
+    // if another CPU is currently (un)registering one.
+    if let Some(hooks) = CONTEXT_SWITCH_HOOKS.try_read() {
+        for hook in hooks.iter() {
+            hook(cpu_id, prev, next, prev_ran_for);
+        }
+    }
+}
+
+/// Restarts the timer tick on the given CPU if it was stopped.
+fn restart_tick_on(cpu_id: CpuId) {
+    let preemption_guard = preemption::hold_preemption();
+    if preemption_guard.cpu_id() == cpu_id {
+        preemption::restart_tick();
+    } else if let Some(kick) = KICK_CPU_FUNC.get() {
+        kick(cpu_id);
+    }
+}
+
 /// A task scheduler.
 pub trait Scheduler: Send + Sync + 'static {
     /// Returns the next task to run.
@@ -179,6 +349,11 @@ pub trait Scheduler: Send + Sync + 'static {
     /// Returns a reference to this scheduler as a priority scheduler, if it is one.
     fn as_priority_scheduler(&mut self) -> Option<&mut dyn PriorityScheduler>;
 
+    /// Returns a reference to this scheduler as a deadline scheduler, if it is one.
+    fn as_deadline_scheduler(&mut self) -> Option<&mut dyn DeadlineScheduler> {
+        None
+    }
+
     /// Clears the scheduler's runqueue, returning an iterator over all contained tasks.
     fn drain(&mut self) -> Box<dyn Iterator<Item = TaskRef> + '_>;
 
@@ -187,6 +362,12 @@ pub trait Scheduler: Send + Sync + 'static {
     /// The list should be considered out-of-date as soon as it is called,
     /// but can be useful as a heuristic or for debugging.
     fn tasks(&self) -> Vec<TaskRef>;
+
+    /// Returns `true` if this scheduler contains no tasks other than the given `task`.
+    ///
+    /// Unlike [`Scheduler::tasks()`], this must not allocate,
+    /// as it is invoked from the timer interrupt handler.
+    fn has_no_other_tasks(&self, task: &TaskRef) -> bool;
 }
 
 /// A task scheduler that supports some notion of priority.
@@ -198,7 +379,91 @@ pub trait PriorityScheduler {
     fn priority(&mut self, task: &TaskRef) -> Option<u8>;
 }
 
-/// Returns the priority of the given task.
+/// The real-time parameters of a task under a deadline scheduler.
+///
+/// The task is guaranteed to run for `budget` within each `period`,
+/// the end of which is the task's deadline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeadlineParams {
+    pub period: Duration,
+    pub budget: Duration,
+}
+
+/// A task scheduler that supports real-time deadlines, e.g., earliest deadline first.
+pub trait DeadlineScheduler {
+    /// Returns `true` if the given task is on this scheduler's run queue.
+    fn contains(&self, task: &TaskRef) -> bool;
+
+    /// Gives the given task a reservation of `params.budget` in every `params.period`,
+    /// replacing its previous reservation, if any.
+    ///
+    /// Returns an error if the task isn't on this scheduler's run queue,
+    /// or if admitting it would overload the CPU such that deadlines could be missed.
+    /// In the latter case, the task's previous reservation is kept.
+    fn set_deadline(&mut self, task: &TaskRef, params: DeadlineParams) -> Result<(), &'static str>;
+
+    /// Removes the given task's reservation, making it a best-effort task again.
+    ///
+    /// Returns `false` if the task isn't on this scheduler's run queue.
+    fn clear_deadline(&mut self, task: &TaskRef) -> bool;
+
+    /// Returns the given task's reservation, if it has one.
+    fn deadline(&mut self, task: &TaskRef) -> Option<DeadlineParams>;
+}
+
+/// Gives the given task a real-time reservation of `budget` in every `period`,
+/// such that it runs ahead of best-effort tasks.
+///
+/// Returns an error if the task is not on a deadline run queue,
+/// or if its CPU can't admit the reservation.
+pub fn set_deadline(task: &TaskRef, period: Duration, budget: Duration) -> Result<(), &'static str> {
+    if budget.is_zero() || budget > period {
+        return Err("deadline budget must be non-zero and no longer than its period");
+    }
+    let params = DeadlineParams { period, budget };
+    for (_, scheduler) in SCHEDULERS.lock().iter() {
+        if let Some(deadline_scheduler) = scheduler.lock().as_deadline_scheduler() {
+            if deadline_scheduler.contains(task) {
+                return deadline_scheduler.set_deadline(task, params);
+            }
+        }
+    }
+    Err("task is not on a deadline run queue")
+}
+
+/// Removes the given task's real-time reservation, if any.
+///
+/// Returns `false` if the task is not on a deadline run queue.
+pub fn clear_deadline(task: &TaskRef) -> bool {
+    for (_, scheduler) in SCHEDULERS.lock().iter() {
+        if let Some(true) = scheduler
+            .lock()
+            .as_deadline_scheduler()
+            .map(|deadline_scheduler| deadline_scheduler.clear_deadline(task))
+        {
+            return true;
+        }
+    }
+    false
+}
+
+/// Returns the given task's real-time reservation.
+///
+/// Returns `None` if the task has no reservation or is not on a deadline run queue.
+pub fn deadline(task: &TaskRef) -> Option<DeadlineParams> {
+    for (_, scheduler) in SCHEDULERS.lock().iter() {
+        if let Some(params) = scheduler
+            .lock()
+            .as_deadline_scheduler()
+            .and_then(|deadline_scheduler| deadline_scheduler.deadline(task))
+        {
+            return Some(params);
+        }
+    }
+    None
+}
+
+/// Returns the priority of the given task, including any boosts.
 ///
 /// Returns `None` if the task is not on a priority run queue.
 pub fn priority(task: &TaskRef) -> Option<u8> {
@@ -216,8 +481,40 @@ pub fn priority(task: &TaskRef) -> Option<u8> {
 
 /// Sets the priority of the given task.
 ///
-/// Returns `None` if the task is not on a priority run queue.
+/// If the task is currently boosted (see [`boost_priority()`]), this sets its
+/// base priority, which takes effect once it is higher than all boosts or
+/// once all boosts have been dropped.
+///
+/// Returns `false` if the task is not on a priority run queue.
 pub fn set_priority(task: &TaskRef, priority: u8) -> bool {
+    let mut boosts = BOOSTS.lock();
+    if let Some(entry) = boosts.get_mut(&task.id) {
+        if self::priority(task).is_none() {
+            return false;
+        }
+        entry.base = priority;
+        return set_effective_priority(task, entry.effective());
+    }
+    drop(boosts);
+    set_effective_priority(task, priority)
+}
+
+/// Returns the priority of the given task, ignoring any boosts.
+///
+/// Returns `None` if the task is not on a priority run queue.
+pub fn base_priority(task: &TaskRef) -> Option<u8> {
+    let boosts = BOOSTS.lock();
+    match boosts.get(&task.id) {
+        Some(entry) => priority(task).map(|_| entry.base),
+        None => {
+            drop(boosts);
+            priority(task)
+        }
+    }
+}
+
+/// Sets the priority of the given task in its run queue, bypassing boosts.
+fn set_effective_priority(task: &TaskRef, priority: u8) -> bool {
     for (_, scheduler) in SCHEDULERS.lock().iter() {
         if let Some(true) = scheduler
             .lock()
@@ -241,44 +538,105 @@ pub fn busyness(cpu_id: CpuId) -> Option<usize> {
     None
 }
 
-/// Modifies the given task's priority to be the maximum of its priority
-/// and the current task's priority.
+/// The priority boosts currently applied to each task, keyed by task ID.
 ///
-/// Returns a guard which reverts the change when dropped.
-pub fn inherit_priority(task: &TaskRef) -> PriorityInheritanceGuard<'_> {
-    let current_priority = super::with_current_task(priority).unwrap();
-    let other_priority = priority(task);
+/// A task only has an entry while it has at least one boost.
+static BOOSTS: Mutex<BTreeMap<usize, PriorityBoosts>> = Mutex::new(BTreeMap::new());
+
+struct PriorityBoosts {
+    /// The priority the task would have without any boosts.
+    base: u8,
+    /// The priorities the task has been boosted to, one per [`PriorityBoost`].
+    boosts: Vec<u8>,
+}
 
-    if let (Some(current_priority), Some(other_priority)) =
-        (current_priority, other_priority) && current_priority > other_priority
-    {
-        set_priority(task, current_priority);
+impl PriorityBoosts {
+    fn effective(&self) -> u8 {
+        self.boosts.iter().copied().fold(self.base, u8::max)
     }
+}
 
-    PriorityInheritanceGuard {
-        inner: if let (Some(current_priority), Some(other_priority)) =
-            (current_priority, other_priority)
-            && current_priority > other_priority
-        {
-            Some((task, other_priority))
-        } else {
-            None
-        },
+/// Temporarily raises the given task's priority to at least `priority`.
+///
+/// A task can hold several boosts at once, e.g., if it holds several locks
+/// that higher-priority tasks are waiting on; its effective priority is the
+/// maximum of its base priority and all of its boosts. The boost is removed
+/// when the returned guard is dropped, which restores the task's base priority
+/// once no other boosts remain.
+///
+/// Returns `None` if the task is not on a priority run queue.
+pub fn boost_priority(task: &TaskRef, priority: u8) -> Option<PriorityBoost> {
+    let mut boosts = BOOSTS.lock();
+    let current = self::priority(task)?;
+    let entry = boosts.entry(task.id).or_insert_with(|| PriorityBoosts {
+        base: current,
+        boosts: Vec::new(),
+    });
+    entry.boosts.push(priority);
+    let effective = entry.effective();
+    if effective != current {
+        set_effective_priority(task, effective);
     }
+    Some(PriorityBoost {
+        task: task.clone(),
+        priority,
+    })
 }
 
-/// A guard that lowers a task's priority back to its previous value when dropped.
-pub struct PriorityInheritanceGuard<'a> {
-    inner: Option<(&'a TaskRef, u8)>,
+/// A boost of a task's priority, which is removed when dropped.
+///
+/// See [`boost_priority()`].
+#[derive(Debug)]
+pub struct PriorityBoost {
+    task: TaskRef,
+    priority: u8,
 }
-impl<'a> Drop for PriorityInheritanceGuard<'a> {
+
+impl PriorityBoost {
+    /// Returns the task whose priority is boosted.
+    pub fn task(&self) -> &TaskRef {
+        &self.task
+    }
+
+    /// Returns the priority the task is boosted to.
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+}
+
+impl Drop for PriorityBoost {
     fn drop(&mut self) {
-        if let Some((task, priority)) = self.inner {
-            set_priority(task, priority);
+        let mut boosts = BOOSTS.lock();
+        let Some(entry) = boosts.get_mut(&self.task.id) else { return };
+        if let Some(index) = entry.boosts.iter().position(|p| *p == self.priority) {
+            entry.boosts.swap_remove(index);
+        }
+        let effective = entry.effective();
+        if entry.boosts.is_empty() {
+            boosts.remove(&self.task.id);
         }
+        set_effective_priority(&self.task, effective);
+    }
+}
+
+/// Modifies the given task's priority to be the maximum of its priority
+/// and the current task's priority.
+///
+/// Returns a guard which reverts the change when dropped.
+pub fn inherit_priority(task: &TaskRef) -> PriorityInheritanceGuard<'_> {
+    let current_priority = super::with_current_task(priority).ok().flatten();
+    PriorityInheritanceGuard {
+        _boost: current_priority.and_then(|p| boost_priority(task, p)),
+        _task: PhantomData,
     }
 }
 
+/// A guard that lowers a task's priority back to its previous value when dropped.
+pub struct PriorityInheritanceGuard<'a> {
+    _boost: Option<PriorityBoost>,
+    _task: PhantomData<&'a TaskRef>,
+}
+
 /// Returns the list of tasks running on each CPU.
 ///
 /// To avoid race conditions with migrating tasks, this function takes a lock
@@ -321,6 +321,10 @@ impl File for TaskFile {
     fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
         Err("task files are autogenerated, cannot be memory mapped")
     }
+
+    fn set_len(&mut self, _len: usize) -> Result<(), &'static str> {
+        Err("task files are autogenerated, cannot be resized")
+    }
 }
 
 
@@ -475,5 +479,9 @@ impl File for MmiFile {
     fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
         Err("task files are autogenerated, cannot be memory mapped")
     }
+
+    fn set_len(&mut self, _len: usize) -> Result<(), &'static str> {
+        Err("task files are autogenerated, cannot be resized")
+    }
 }
 
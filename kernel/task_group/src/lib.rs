@@ -0,0 +1,246 @@
+//! Task groups: a process-like abstraction over the tasks that run within a [`CrateNamespace`].
+//!
+//! Each namespace has at most one [`TaskGroup`], obtained via [`TaskGroup::for_namespace()`].
+//! Tasks become members of a group by being spawned via [`TaskGroup::spawn()`]
+//! or added via [`TaskGroup::add()`], after which the group owns the right to join them.
+//! This lets a group be managed as a unit:
+//! * [`TaskGroup::tasks()`] enumerates its members,
+//! * [`TaskGroup::request_exit()`] asks all members to exit by unwinding,
+//!   which they do upon calling [`unwind_if_exit_requested()`],
+//! * [`TaskGroup::kill_all()`] forcibly kills all members, and
+//! * [`TaskGroup::wait_all()`] waits for all members to exit and aggregates their exit values.
+//!
+//! To enumerate all tasks running in a namespace, including those that aren't group members,
+//! use [`tasks_in_namespace()`].
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+use mod_mgmt::CrateNamespace;
+use spawn::TaskBuilder;
+use spin::Mutex;
+use task::{ExitValue, JoinableTaskRef, KillReason, TaskRef};
+
+/// All existing task groups, at most one per namespace.
+static GROUPS: Mutex<Vec<Weak<TaskGroupInner>>> = Mutex::new(Vec::new());
+
+/// A group of tasks that run within the same [`CrateNamespace`]; see the [crate-level docs](crate).
+///
+/// Cloning a `TaskGroup` returns another reference to the same group.
+/// The group is destroyed once all references to it are dropped,
+/// at which point its members that haven't been waited on become orphans.
+#[derive(Clone)]
+pub struct TaskGroup {
+    inner: Arc<TaskGroupInner>,
+}
+
+struct TaskGroupInner {
+    namespace: Arc<CrateNamespace>,
+    members: Mutex<Vec<JoinableTaskRef>>,
+    exit_requested: AtomicBool,
+}
+
+impl TaskGroup {
+    /// Returns the task group of the given `namespace`, creating it if it doesn't exist.
+    pub fn for_namespace(namespace: &Arc<CrateNamespace>) -> TaskGroup {
+        let mut groups = GROUPS.lock();
+        groups.retain(|group| group.strong_count() > 0);
+        if let Some(inner) = groups.iter()
+            .filter_map(Weak::upgrade)
+            .find(|inner| Arc::ptr_eq(&inner.namespace, namespace))
+        {
+            return TaskGroup { inner };
+        }
+        let inner = Arc::new(TaskGroupInner {
+            namespace: namespace.clone(),
+            members: Mutex::new(Vec::new()),
+            exit_requested: AtomicBool::new(false),
+        });
+        groups.push(Arc::downgrade(&inner));
+        TaskGroup { inner }
+    }
+
+    /// Returns the task group of the current task's namespace, if one exists.
+    pub fn current() -> Option<TaskGroup> {
+        let namespace = task::with_current_task(|t| t.get_namespace().clone()).ok()?;
+        GROUPS.lock().iter()
+            .filter_map(Weak::upgrade)
+            .find(|inner| Arc::ptr_eq(&inner.namespace, &namespace))
+            .map(|inner| TaskGroup { inner })
+    }
+
+    /// Returns the namespace that this group's tasks run within.
+    pub fn namespace(&self) -> &Arc<CrateNamespace> {
+        &self.inner.namespace
+    }
+
+    /// Spawns a new task from the given `builder` as a member of this group.
+    ///
+    /// The task is added to the group before it first runs, so it cannot miss
+    /// an exit request. Returns an error if the task could not be spawned,
+    /// or if it doesn't run within this group's namespace, in which case
+    /// it is still spawned but not added to the group.
+    pub fn spawn<F, A, R>(&self, builder: TaskBuilder<F, A, R>) -> Result<TaskRef, &'static str>
+    where
+        A: Send + 'static,
+        R: Send + 'static,
+        F: FnOnce(A) -> R,
+    {
+        let joinable = builder.block().spawn()?;
+        let task = TaskRef::clone(&joinable);
+        let result = self.add(joinable);
+        task.unblock()
+            .map_err(|_| "task_group: newly-spawned task was not blocked")?;
+        result.map(|_| task)
+    }
+
+    /// Adds the given task to this group, which takes over the right to join it.
+    ///
+    /// Returns an error if the task doesn't run within this group's namespace.
+    pub fn add(&self, task: JoinableTaskRef) -> Result<(), &'static str> {
+        if !Arc::ptr_eq(task.get_namespace(), &self.inner.namespace) {
+            return Err("task_group: task does not run within the group's namespace");
+        }
+        self.inner.members.lock().push(task);
+        Ok(())
+    }
+
+    /// Returns the members of this group that haven't yet been waited on.
+    ///
+    /// The list may be out of date as soon as it is returned.
+    pub fn tasks(&self) -> Vec<TaskRef> {
+        self.inner.members.lock().iter()
+            .map(|joinable| TaskRef::clone(joinable))
+            .collect()
+    }
+
+    /// Returns the number of members of this group that haven't yet been waited on.
+    pub fn len(&self) -> usize {
+        self.inner.members.lock().len()
+    }
+
+    /// Returns `true` if this group has no members that haven't yet been waited on.
+    pub fn is_empty(&self) -> bool {
+        self.inner.members.lock().is_empty()
+    }
+
+    /// Asks all members of this group to exit.
+    ///
+    /// This is cooperative: each member exits by unwinding its stack,
+    /// thereby releasing its resources, the next time it calls [`unwind_if_exit_requested()`].
+    /// Use [`TaskGroup::kill_all()`] to stop members that don't cooperate.
+    pub fn request_exit(&self) {
+        self.inner.exit_requested.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if [`TaskGroup::request_exit()`] has been called on this group.
+    pub fn exit_requested(&self) -> bool {
+        self.inner.exit_requested.load(Ordering::Acquire)
+    }
+
+    /// Kills all members of this group that haven't exited yet, except the current task,
+    /// with [`KillReason::Requested`].
+    ///
+    /// Killing a task doesn't unwind it; see [`TaskRef::kill()`].
+    ///
+    /// Returns the number of tasks that were killed.
+    pub fn kill_all(&self) -> usize {
+        let current_id = task::get_my_current_task_id();
+        self.tasks().into_iter()
+            .filter(|t| t.id != current_id && !t.has_exited())
+            .filter(|t| match t.kill(KillReason::Requested) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::warn!("task_group: failed to kill {:?}: {}", t, e);
+                    false
+                }
+            })
+            .count()
+    }
+
+    /// Blocks until all members of this group have exited, and returns their exit values.
+    ///
+    /// The current task is never waited on, even if it is a member.
+    /// Members added while this is waiting are waited on too.
+    pub fn wait_all(&self) -> Result<GroupExitStatus, &'static str> {
+        let current_id = task::get_my_current_task_id();
+        let mut exits = Vec::new();
+        loop {
+            let joinable = {
+                let mut members = self.inner.members.lock();
+                match members.iter().position(|t| t.id != current_id) {
+                    Some(index) => members.swap_remove(index),
+                    None => break,
+                }
+            };
+            let id = joinable.id;
+            match joinable.join() {
+                Ok(exit_value) => exits.push((id, exit_value)),
+                Err(e) => {
+                    // Put it back so the caller can retry.
+                    self.inner.members.lock().push(joinable);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(GroupExitStatus { exits })
+    }
+}
+
+/// The aggregated exit values of the members of a [`TaskGroup`], from [`TaskGroup::wait_all()`].
+#[derive(Debug)]
+pub struct GroupExitStatus {
+    /// The ID and exit value of each member, in the order they were joined.
+    pub exits: Vec<(usize, ExitValue)>,
+}
+
+impl GroupExitStatus {
+    /// Returns `true` if every member ran to completion, i.e., none were killed or panicked.
+    pub fn all_completed(&self) -> bool {
+        self.exits.iter().all(|(_, exit)| matches!(exit, ExitValue::Completed(_)))
+    }
+
+    /// Returns the ID of each member that didn't run to completion, along with the reason.
+    pub fn killed(&self) -> impl Iterator<Item = (usize, &KillReason)> {
+        self.exits.iter().filter_map(|(id, exit)| match exit {
+            ExitValue::Killed(reason) => Some((*id, reason)),
+            ExitValue::Completed(_) => None,
+        })
+    }
+
+    /// Returns the exit codes of application members that ran to completion,
+    /// i.e., the values returned from their `main` functions.
+    pub fn exit_codes(&self) -> impl Iterator<Item = (usize, isize)> + '_ {
+        self.exits.iter().filter_map(|(id, exit)| match exit {
+            ExitValue::Completed(value) => value.downcast_ref::<isize>().map(|code| (*id, *code)),
+            ExitValue::Killed(_) => None,
+        })
+    }
+}
+
+/// Unwinds the current task if its namespace's task group has been asked to exit
+/// via [`TaskGroup::request_exit()`]; otherwise, does nothing.
+///
+/// Long-running group members should call this periodically, e.g., once per loop iteration.
+pub fn unwind_if_exit_requested() {
+    if TaskGroup::current().is_some_and(|group| group.exit_requested()) {
+        panic!("task group was asked to exit");
+    }
+}
+
+/// Returns all tasks that currently run within the given `namespace`,
+/// whether or not they are members of its task group.
+///
+/// Like [`task::all_tasks()`], this is expensive and should be used rarely.
+pub fn tasks_in_namespace(namespace: &Arc<CrateNamespace>) -> Vec<TaskRef> {
+    task::all_tasks().into_iter()
+        .filter_map(|(_, weak)| weak.upgrade())
+        .filter(|t| Arc::ptr_eq(t.get_namespace(), namespace))
+        .collect()
+}
@@ -17,8 +17,9 @@ use core::{
     hash::{Hash, Hasher},
     ops::Deref,
     panic::PanicInfo,
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
     task::Waker,
+    time::Duration,
 };
 use alloc::{
     boxed::Box,
@@ -26,16 +27,18 @@ use alloc::{
     string::String,
     sync::Arc,
 };
-use cpu::{CpuId, OptionalCpuId};
+use cpu::{CpuId, CpuSet, OptionalCpuId};
 use crossbeam_utils::atomic::AtomicCell;
 use sync_irq::IrqSafeMutex;
 use log::{warn, trace};
 use memory::MmiRef;
 use stack::Stack;
+pub use stack::StackUsage;
 use kernel_config::memory::KERNEL_STACK_SIZE_IN_PAGES;
 use mod_mgmt::{AppCrateRef, CrateNamespace, TlsDataImage};
 use environment::Environment;
 use spin::Mutex;
+use time::Instant;
 
 /// The function signature of the callback that will be invoked when a `Task`
 /// panics or otherwise fails, e.g., a machine exception occurs.
@@ -154,12 +157,61 @@ pub enum SimdExt {
     None,
 }
 
+/// The policy that determines whether a restartable `Task` is restarted after it exits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestartPolicy {
+    /// The task is restarted every time it exits, whether it returned or was killed.
+    /// This is the default policy for restartable tasks, e.g., idle tasks.
+    #[default]
+    Always,
+    /// The task is never restarted.
+    Never,
+    /// The task is restarted only if it was killed, e.g., it panicked or hit an exception.
+    OnPanic {
+        /// The maximum number of times the task will be restarted.
+        max_restarts: usize,
+        /// How long each restarted instance of the task waits before invoking its entry function.
+        backoff: Duration,
+    },
+}
+
 /// A struct holding data items needed to restart a `Task`.
 pub struct RestartInfo {
     /// Stores the argument of the task for restartable tasks
     pub argument: Box<dyn Any + Send>,
     /// Stores the function of the task for restartable tasks
     pub func: Box<dyn Any + Send>,
+    /// The policy that determines whether this task is restarted when it exits.
+    pub policy: RestartPolicy,
+    /// The number of times this task has already been restarted.
+    pub restarts: usize,
+    /// Whether this task's application crate is reloaded from its object file
+    /// when this task is restarted. This has no effect on non-application tasks.
+    pub reload_app_crate: bool,
+}
+
+impl RestartInfo {
+    /// Returns `true` if this task should be restarted according to its [`RestartPolicy`].
+    ///
+    /// * `killed`: whether this task was killed (`true`) or exited normally (`false`).
+    pub fn should_restart(&self, killed: bool) -> bool {
+        match self.policy {
+            RestartPolicy::Always => true,
+            RestartPolicy::Never => false,
+            RestartPolicy::OnPanic { max_restarts, .. } => killed && self.restarts < max_restarts,
+        }
+    }
+
+    /// Returns the duration that this instance of the task must wait
+    /// before invoking its entry function, if any.
+    ///
+    /// Only restarted instances of a task wait, never the initially-spawned instance.
+    pub fn backoff(&self) -> Option<Duration> {
+        match self.policy {
+            RestartPolicy::OnPanic { backoff, .. } if self.restarts > 0 && !backoff.is_zero() => Some(backoff),
+            _ => None,
+        }
+    }
 }
 
 
@@ -184,6 +236,9 @@ pub struct TaskInner {
     /// Whether or not this task is pinned to a certain CPU.
     /// The idle tasks are always pinned to their respective CPU.
     pub pinned_cpu: Option<CpuId>,
+    /// The set of CPUs that this task may be scheduled on, or `None` if it may run on any CPU.
+    /// This is ignored if the task is pinned to a CPU.
+    pub affinity: Option<CpuSet>,
     /// The function that will be called when this `Task` panics or fails due to a machine exception.
     /// It will be invoked before the task is cleaned up via stack unwinding.
     /// This is similar to Rust's built-in panic hook, but is also called upon a machine exception, not just a panic.
@@ -195,6 +250,9 @@ pub struct TaskInner {
     pub restart_info: Option<RestartInfo>,
     /// The waker that is awoken when this task completes.
     pub waker: Option<Waker>,
+    /// A description of what this task is currently blocked on, e.g., the name of a wait queue.
+    /// This is only meaningful while the task is blocked, and is used for debugging.
+    wait_reason: Option<&'static str>,
 }
 
 
@@ -228,6 +286,28 @@ pub struct Task {
     ///
     /// This is not public because it permits interior mutability.
     runstate: AtomicCell<RunState>,
+    /// The time at which this task's runstate last changed,
+    /// e.g., when it was last blocked or unblocked,
+    /// or `Instant::ZERO` if that change wasn't timed; see [`enable_runstate_timestamps()`].
+    ///
+    /// This is not public because it permits interior mutability.
+    runstate_changed_at: AtomicCell<Instant>,
+    /// The time at which this task was last switched to (scheduled in) on any CPU.
+    ///
+    /// This is not public because it permits interior mutability.
+    last_scheduled_at: AtomicCell<Instant>,
+    /// The time at which this task was created,
+    /// or `Instant::ZERO` if it was created before a clock source was registered.
+    created_at: Instant,
+    /// The total time this task has spent running, in nanoseconds,
+    /// excluding the timeslice it's currently running in (if any).
+    ///
+    /// This is not public because it permits interior mutability.
+    runtime_nanos: AtomicU64,
+    /// The number of times this task has been switched to.
+    ///
+    /// This is not public because it permits interior mutability.
+    context_switches: AtomicU64,
     /// Whether the task is suspended.
     ///
     /// This is only triggered by a Ctrl + Z in the terminal.
@@ -259,6 +339,35 @@ pub struct Task {
 // Ensure that atomic fields in the `Tast` struct are actually lock-free atomics.
 const _: () = assert!(AtomicCell::<OptionalCpuId>::is_lock_free());
 const _: () = assert!(AtomicCell::<RunState>::is_lock_free());
+const _: () = assert!(AtomicCell::<Instant>::is_lock_free());
+
+/// Returns the current time, or `Instant::ZERO` if no clock source has been registered yet,
+/// as tasks are created and change runstates long before a clock source exists during boot.
+fn now_or_zero() -> Instant {
+    Instant::try_now().unwrap_or(Instant::ZERO)
+}
+
+/// Whether runstate changes are timestamped; see [`enable_runstate_timestamps()`].
+static RUNSTATE_TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+
+/// Starts recording the time at which each task's runstate changes,
+/// which is needed to know how long a task has been blocked, e.g., to detect hung tasks.
+///
+/// This is disabled by default, such that blocking and unblocking tasks doesn't read the clock.
+/// Runstate changes that weren't timed are reported as `Instant::ZERO`
+/// by [`Task::runstate_changed_at()`].
+pub fn enable_runstate_timestamps() {
+    RUNSTATE_TIMESTAMPS.store(true, Ordering::Relaxed);
+}
+
+/// Returns the time to record for a runstate change that is occurring now.
+fn runstate_timestamp() -> Instant {
+    if RUNSTATE_TIMESTAMPS.load(Ordering::Relaxed) {
+        now_or_zero()
+    } else {
+        Instant::ZERO
+    }
+}
 
 impl fmt::Debug for Task {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -269,6 +378,9 @@ impl fmt::Debug for Task {
             .field("runstate", &self.runstate());
         if let Some(inner) = self.inner.try_lock() {
             ds.field("pinned", &inner.pinned_cpu);
+            if let Some(affinity) = inner.affinity.as_ref() {
+                ds.field("affinity", affinity);
+            }
         } else {
             ds.field("pinned", &"<Locked>");
         }
@@ -327,15 +439,22 @@ impl Task {
                 saved_sp: 0,
                 kstack,
                 pinned_cpu: None,
+                affinity: None,
                 kill_handler: None,
                 env,
                 restart_info: None,
                 waker: None,
+                wait_reason: None,
             }),
             id: task_id,
             name: format!("task_{task_id}"),
             running_on_cpu: AtomicCell::new(None.into()),
             runstate: AtomicCell::new(RunState::Initing),
+            runstate_changed_at: AtomicCell::new(runstate_timestamp()),
+            last_scheduled_at: AtomicCell::new(Instant::ZERO),
+            created_at: now_or_zero(),
+            runtime_nanos: AtomicU64::new(0),
+            context_switches: AtomicU64::new(0),
             suspended: AtomicBool::new(false),
             mmi,
             is_an_idle_task: false,
@@ -380,6 +499,29 @@ impl Task {
         self.inner.lock().pinned_cpu
     }
 
+    /// Returns the set of CPUs this `Task` may be scheduled on,
+    /// or `None` if it may run on any CPU.
+    ///
+    /// A `Task` that is [pinned](Self::pinned_cpu) to a CPU can only run on that CPU regardless of its affinity.
+    pub fn affinity(&self) -> Option<CpuSet> {
+        let inner = self.inner.lock();
+        match inner.pinned_cpu {
+            Some(cpu) => Some(CpuSet::single(cpu)),
+            None => inner.affinity.clone(),
+        }
+    }
+
+    /// Returns `true` if this `Task` may be scheduled on the given CPU,
+    /// according to the CPU it is pinned to or else its [affinity](Self::affinity).
+    pub fn can_run_on(&self, cpu: CpuId) -> bool {
+        let inner = self.inner.lock();
+        match (inner.pinned_cpu, inner.affinity.as_ref()) {
+            (Some(pinned), _) => pinned == cpu,
+            (None, Some(affinity)) => affinity.contains(cpu),
+            (None, None) => true,
+        }
+    }
+
     /// Returns the current [`RunState`] of this `Task`.
     pub fn runstate(&self) -> RunState {
         self.runstate.load()
@@ -401,6 +543,73 @@ impl Task {
         self.runstate() == RunState::Runnable && !self.is_suspended()
     }
 
+    /// Returns the time at which this `Task`'s runstate last changed,
+    /// e.g., when it was last blocked or unblocked.
+    ///
+    /// This is `Instant::ZERO` if that change wasn't timed,
+    /// i.e., if it occurred before [`enable_runstate_timestamps()`] was called.
+    pub fn runstate_changed_at(&self) -> Instant {
+        self.runstate_changed_at.load()
+    }
+
+    /// Returns the time at which this `Task` was last switched to on any CPU.
+    ///
+    /// This is [`Instant::ZERO`] if this `Task` has never been scheduled in.
+    pub fn last_scheduled_at(&self) -> Instant {
+        self.last_scheduled_at.load()
+    }
+
+    /// Returns the time at which this `Task` was created,
+    /// or `Instant::ZERO` if it was created before a clock source was registered.
+    pub fn created_at(&self) -> Instant {
+        self.created_at
+    }
+
+    /// Returns the total time this `Task` has spent running on any CPU,
+    /// including its current timeslice if it is currently running.
+    pub fn runtime(&self) -> Duration {
+        let mut runtime = Duration::from_nanos(self.runtime_nanos.load(Ordering::Relaxed));
+        if self.is_running() {
+            runtime += self.last_scheduled_at().elapsed();
+        }
+        runtime
+    }
+
+    /// Returns the number of times this `Task` has been switched to.
+    pub fn context_switches(&self) -> u64 {
+        self.context_switches.load(Ordering::Relaxed)
+    }
+
+    /// Sets a description of what this `Task` is about to block on,
+    /// e.g., the name of a wait queue or lock, or `None` to clear it.
+    ///
+    /// This is purely informational, e.g., for diagnosing hung tasks.
+    ///
+    /// # Locking / Deadlock
+    /// Obtains the lock on this `Task`'s inner state in order to mutate it.
+    pub fn set_wait_reason(&self, reason: Option<&'static str>) {
+        self.inner.lock().wait_reason = reason;
+    }
+
+    /// Returns the stack pointer value that was saved when this `Task` was last switched out.
+    ///
+    /// This points to the task's saved register context, and is only meaningful
+    /// while this `Task` is not running.
+    ///
+    /// # Locking / Deadlock
+    /// Obtains the lock on this `Task`'s inner state in order to access it.
+    pub fn saved_stack_pointer(&self) -> usize {
+        self.inner.lock().saved_sp
+    }
+
+    /// Returns the description of what this `Task` is currently blocked on, if known.
+    ///
+    /// # Locking / Deadlock
+    /// Obtains the lock on this `Task`'s inner state in order to access it.
+    pub fn wait_reason(&self) -> Option<&'static str> {
+        self.inner.lock().wait_reason
+    }
+
     /// Returns the namespace that this `Task` is loaded/linked into and runs within.
     pub fn get_namespace(&self) -> &Arc<CrateNamespace> {
         &self.namespace
@@ -419,6 +628,32 @@ impl Task {
         func(&self.inner.lock().kstack)
     }
 
+    /// Exposes mutable access to this `Task`'s [`Stack`] by invoking
+    /// the given `func` with a mutable reference to its kernel stack.
+    ///
+    /// This is intended for operations on the stack's `MappedPages` as a whole,
+    /// e.g., tracking which of its pages have been modified.
+    /// The stack must not be replaced or modified while this `Task` may be running.
+    ///
+    /// # Locking / Deadlock
+    /// Same as [`Task::with_kstack()`].
+    pub fn with_kstack_mut<R, F>(&self, func: F) -> R 
+        where F: FnOnce(&mut Stack) -> R
+    {
+        func(&mut self.inner.lock().kstack)
+    }
+
+    /// Returns the maximum amount of this `Task`'s kernel stack that it has used so far.
+    ///
+    /// This scans the stack, see [`Stack::usage()`] for more details.
+    /// Returns `None` if this `Task`'s stack usage cannot be measured.
+    ///
+    /// # Locking / Deadlock
+    /// Obtains the lock on this `Task`'s inner state for the duration of the scan.
+    pub fn stack_usage(&self) -> Option<StackUsage> {
+        self.with_kstack(Stack::usage)
+    }
+
     /// Returns a mutable reference to this `Task`'s inner state. 
     ///
     /// # Note about mutability
@@ -476,6 +711,7 @@ impl Task {
         use RunState::{Blocked, Runnable};
 
         if self.runstate.compare_exchange(Runnable, Blocked).is_ok() {
+            self.runstate_changed_at.store(runstate_timestamp());
             Ok(Runnable)
         } else if self.runstate.compare_exchange(Blocked, Blocked).is_ok() {
             // warn!("Blocked an already blocked task: {:?}", self);
@@ -494,6 +730,7 @@ impl Task {
     /// or the current runstate on error.
     pub fn block_initing_task(&self) -> Result<RunState, RunState> {
         if self.runstate.compare_exchange(RunState::Initing, RunState::Blocked).is_ok() {
+            self.runstate_changed_at.store(runstate_timestamp());
             Ok(RunState::Initing)
         } else {
             Err(self.runstate.load())
@@ -508,6 +745,7 @@ impl Task {
         use RunState::{Blocked, Runnable};
 
         if self.runstate.compare_exchange(Blocked, Runnable).is_ok() {
+            self.runstate_changed_at.store(runstate_timestamp());
             Ok(Blocked)
         } else if self.runstate.compare_exchange(Runnable, Runnable).is_ok() {
             // warn!("Unblocked an already runnable task: {:?}", self);
@@ -526,6 +764,7 @@ impl Task {
     /// the current runstate on error.
     pub fn make_inited_task_runnable(&self) -> Result<RunState, RunState> {
         if self.runstate.compare_exchange(RunState::Initing, RunState::Runnable).is_ok() {
+            self.runstate_changed_at.store(runstate_timestamp());
             Ok(RunState::Initing)
         } else {
             Err(self.runstate.load())
@@ -612,6 +851,22 @@ impl ExposedTask {
     pub fn runstate(&self) -> &AtomicCell<RunState> {
         &self.runstate
     }
+    #[inline(always)]
+    pub fn runstate_changed_at(&self) -> &AtomicCell<Instant> {
+        &self.runstate_changed_at
+    }
+    #[inline(always)]
+    pub fn last_scheduled_at(&self) -> &AtomicCell<Instant> {
+        &self.last_scheduled_at
+    }
+    #[inline(always)]
+    pub fn runtime_nanos(&self) -> &AtomicU64 {
+        &self.runtime_nanos
+    }
+    #[inline(always)]
+    pub fn context_switches(&self) -> &AtomicU64 {
+        &self.context_switches
+    }
 }
 
 
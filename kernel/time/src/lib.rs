@@ -4,8 +4,9 @@
 
 mod dummy;
 
-use core::{fmt, ops};
+use core::{fmt, ops, sync::atomic::{AtomicUsize, Ordering}};
 use crossbeam_utils::atomic::AtomicCell;
+use spin::Once;
 
 pub use core::time::Duration;
 
@@ -14,11 +15,11 @@ const FEMTOS_TO_NANOS: u128 = 1_000_000;
 static EARLY_SLEEP_FUNCTION: AtomicCell<fn(Duration)> = AtomicCell::new(dummy::early_sleep);
 static EARLY_SLEEPER_PERIOD: AtomicCell<Period> = AtomicCell::new(Period::MAX);
 
-static MONOTONIC_NOW_FUNCTION: AtomicCell<fn() -> Instant> = AtomicCell::new(dummy::monotonic_now);
-static MONOTONIC_PERIOD: AtomicCell<Period> = AtomicCell::new(Period::MAX);
+static MONOTONIC_SOURCES: ClockSources<Instant> = ClockSources::new(dummy::monotonic_now);
+static WALL_TIME_SOURCES: ClockSources<Duration> = ClockSources::new(dummy::wall_time_now);
 
-static WALL_TIME_NOW_FUNCTION: AtomicCell<fn() -> Duration> = AtomicCell::new(dummy::wall_time_now);
-static WALL_TIME_PERIOD: AtomicCell<Period> = AtomicCell::new(Period::MAX);
+/// The maximum number of times that the clock source of each clock type can be replaced.
+const MAX_CLOCK_SOURCES: usize = 8;
 
 /// A measurement of a monotonically nondecreasing clock.
 ///
@@ -42,6 +43,12 @@ impl Instant {
         now::<Monotonic>()
     }
 
+    /// Returns the current instant, or `None` if no monotonic clock source
+    /// has been registered yet, e.g., during early boot.
+    pub fn try_now() -> Option<Self> {
+        try_now::<Monotonic>()
+    }
+
     pub fn elapsed(&self) -> Duration {
         Self::now().duration_since(*self)
     }
@@ -56,7 +63,7 @@ impl Instant {
         let instant = Instant {
             counter: self.counter.checked_sub(earlier.counter)?,
         };
-        let femtos = u128::from(instant.counter) * u128::from(MONOTONIC_PERIOD.load());
+        let femtos = u128::from(instant.counter) * u128::from(MONOTONIC_SOURCES.period());
         Some(Duration::from_nanos((femtos / FEMTOS_TO_NANOS) as u64))
     }
 }
@@ -72,7 +79,7 @@ impl ops::Add<Duration> for Instant {
 
     fn add(self, rhs: Duration) -> Self::Output {
         let femtos = rhs.as_nanos() * FEMTOS_TO_NANOS;
-        let ticks = (femtos / u128::from(MONOTONIC_PERIOD.load())) as u64;
+        let ticks = (femtos / u128::from(MONOTONIC_SOURCES.period())) as u64;
         Self {
             counter: self
                 .counter
@@ -93,7 +100,7 @@ impl ops::Sub<Duration> for Instant {
 
     fn sub(self, rhs: Duration) -> Self::Output {
         let femtos = rhs.as_nanos() * FEMTOS_TO_NANOS;
-        let ticks = (femtos / u128::from(MONOTONIC_PERIOD.load())) as u64;
+        let ticks = (femtos / u128::from(MONOTONIC_SOURCES.period())) as u64;
         Self {
             counter: self
                 .counter
@@ -203,29 +210,16 @@ pub fn early_sleep(duration: Duration) {
 /// The provided clock source will overwrite the current clock source only if
 /// `period` is smaller than that of the current clock source.
 ///
+/// The clock source's `now` function and period are published together, so
+/// concurrent callers of [`now`] and [`try_now`] always observe a consistent pair.
+/// The clock source of each clock type can be overwritten at most 8 times.
+///
 /// Returns whether the clock source was overwritten.
 pub fn register_clock_source<T>(period: Period) -> bool
 where
     T: ClockSource,
 {
-    let period_atomic = T::ClockType::period_atomic();
-    if period_atomic
-        .fetch_update(|old_period| {
-            if period < old_period {
-                Some(period)
-            } else {
-                None
-            }
-        })
-        .is_ok()
-    {
-        let now_fn = T::ClockType::now_fn();
-        now_fn.store(T::now);
-
-        true
-    } else {
-        false
-    }
+    T::ClockType::sources().register(T::now, period)
 }
 
 /// Returns the current time.
@@ -240,10 +234,105 @@ pub fn now<T>() -> T::Unit
 where
     T: ClockType,
 {
-    let f = T::now_fn().load();
+    let sources = T::sources();
+    let f = sources.current().map_or(sources.dummy_now, |source| source.now);
     f()
 }
 
+/// Returns the current time, or `None` if no clock source of the specified type
+/// has been registered yet.
+///
+/// Unlike [`now`], this can be called at any time, e.g., by code that only
+/// measures durations opportunistically and also runs before any clock exists.
+pub fn try_now<T>() -> Option<T::Unit>
+where
+    T: ClockType,
+{
+    T::sources().current().map(|source| (source.now)())
+}
+
+/// A registered clock source's `now` function and period.
+struct ClockSourceEntry<U: 'static> {
+    now: fn() -> U,
+    period: Period,
+}
+
+/// The clock sources that have been registered for one clock type.
+///
+/// The current clock source is published by atomically swapping a single index,
+/// such that its `now` function and its period are always observed together.
+#[doc(hidden)]
+pub struct ClockSources<U: 'static> {
+    /// One plus the index of the current clock source in `entries`,
+    /// or zero if no clock source has been registered yet.
+    current: AtomicUsize,
+    /// Each entry is initialized before it becomes the current clock source
+    /// and is never modified afterwards.
+    entries: [Once<ClockSourceEntry<U>>; MAX_CLOCK_SOURCES],
+    /// The number of `entries` that have been claimed by a registration.
+    claimed: AtomicUsize,
+    /// The function used as the `now` function until a clock source has been registered.
+    dummy_now: fn() -> U,
+}
+
+impl<U: 'static> ClockSources<U> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY_ENTRY: Once<ClockSourceEntry<U>> = Once::new();
+
+    const fn new(dummy_now: fn() -> U) -> Self {
+        Self {
+            current: AtomicUsize::new(0),
+            entries: [Self::EMPTY_ENTRY; MAX_CLOCK_SOURCES],
+            claimed: AtomicUsize::new(0),
+            dummy_now,
+        }
+    }
+
+    /// Returns the current clock source, if one has been registered.
+    fn current(&self) -> Option<&ClockSourceEntry<U>> {
+        self.entry(self.current.load(Ordering::Acquire))
+    }
+
+    /// Returns the entry that the given value of `current` refers to, if any.
+    fn entry(&self, current: usize) -> Option<&ClockSourceEntry<U>> {
+        self.entries.get(current.checked_sub(1)?)?.get()
+    }
+
+    /// Returns the period of the current clock source, or `Period::MAX` if there is none.
+    fn period(&self) -> Period {
+        self.current().map_or(Period::MAX, |source| source.period)
+    }
+
+    /// Makes the given clock source the current one if its `period` is smaller
+    /// than that of the current clock source.
+    fn register(&self, now: fn() -> U, period: Period) -> bool {
+        let mut current = self.current.load(Ordering::Acquire);
+        let mut new = None;
+        loop {
+            if period >= self.entry(current).map_or(Period::MAX, |source| source.period) {
+                return false;
+            }
+            // Claim and initialize an entry only once, even if we have to retry.
+            let new = match new {
+                Some(new) => new,
+                None => {
+                    let index = self.claimed.fetch_add(1, Ordering::Relaxed);
+                    let Some(entry) = self.entries.get(index) else {
+                        log::error!("cannot register more than {MAX_CLOCK_SOURCES} clock sources of the same type");
+                        return false;
+                    };
+                    entry.call_once(|| ClockSourceEntry { now, period });
+                    *new.insert(index + 1)
+                }
+            };
+            match self.current.compare_exchange(current, new, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
 /// A clock source.
 pub trait ClockSource {
     /// The type of clock (either [`Monotonic`] or [`WallTime`]).
@@ -286,9 +375,7 @@ pub trait ClockType: private::Sealed {
     type Unit: 'static;
 
     #[doc(hidden)]
-    fn now_fn() -> &'static AtomicCell<fn() -> Self::Unit>;
-    #[doc(hidden)]
-    fn period_atomic() -> &'static AtomicCell<Period>;
+    fn sources() -> &'static ClockSources<Self::Unit>;
 }
 
 pub struct Monotonic;
@@ -298,12 +385,8 @@ impl private::Sealed for Monotonic {}
 impl ClockType for Monotonic {
     type Unit = Instant;
 
-    fn now_fn() -> &'static AtomicCell<fn() -> Self::Unit> {
-        &MONOTONIC_NOW_FUNCTION
-    }
-
-    fn period_atomic() -> &'static AtomicCell<Period> {
-        &MONOTONIC_PERIOD
+    fn sources() -> &'static ClockSources<Self::Unit> {
+        &MONOTONIC_SOURCES
     }
 }
 
@@ -314,12 +397,8 @@ impl private::Sealed for WallTime {}
 impl ClockType for WallTime {
     type Unit = Duration;
 
-    fn now_fn() -> &'static AtomicCell<fn() -> Self::Unit> {
-        &WALL_TIME_NOW_FUNCTION
-    }
-
-    fn period_atomic() -> &'static AtomicCell<Period> {
-        &WALL_TIME_PERIOD
+    fn sources() -> &'static ClockSources<Self::Unit> {
+        &WALL_TIME_SOURCES
     }
 }
 
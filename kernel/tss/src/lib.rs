@@ -8,6 +8,8 @@ use cpu::CpuId;
 
 /// The index of the double fault stack in a TaskStateSegment (TSS)
 pub const DOUBLE_FAULT_IST_INDEX: usize = 0;
+/// The index of the page fault stack in a TaskStateSegment (TSS)
+pub const PAGE_FAULT_IST_INDEX: usize = 1;
 
 /// The TSS list, one per CPU.
 static TSS: AtomicMap<CpuId, Mutex<TaskStateSegment>> = AtomicMap::new();
@@ -35,12 +37,14 @@ pub fn tss_set_rsp0(new_privilege_stack_top: VirtualAddress) -> Result<(), &'sta
 pub fn create_tss(
     cpu_id: CpuId, 
     double_fault_stack_top_unusable: VirtualAddress, 
+    page_fault_stack_top_unusable: VirtualAddress, 
     privilege_stack_top_unusable: VirtualAddress
 ) -> &'static Mutex<TaskStateSegment> {
     let mut tss = TaskStateSegment::new();
     // TSS.RSP0 is used in kernel space after a transition from Ring 3 -> Ring 0
     tss.privilege_stack_table[0] = x86_64::VirtAddr::new(privilege_stack_top_unusable.value() as u64);
     tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX] = x86_64::VirtAddr::new(double_fault_stack_top_unusable.value() as u64);
+    tss.interrupt_stack_table[PAGE_FAULT_IST_INDEX] = x86_64::VirtAddr::new(page_fault_stack_top_unusable.value() as u64);
 
     // insert into TSS list
     TSS.insert(cpu_id, Mutex::new(tss));
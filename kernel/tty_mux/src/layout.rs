@@ -0,0 +1,110 @@
+use alloc::{boxed::Box, vec::Vec};
+
+/// The direction in which a pane is split.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Split {
+    /// The new pane is placed to the right of the existing one.
+    Vertical,
+    /// The new pane is placed below the existing one.
+    Horizontal,
+}
+
+/// A rectangular region of the outer terminal, in character cells.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Rect {
+    pub column: usize,
+    pub row: usize,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+/// A binary tree describing how the panes of a session tile the terminal.
+///
+/// Leaves refer to panes by their ID.
+#[derive(Debug)]
+pub(crate) enum Layout {
+    Pane(usize),
+    Split {
+        split: Split,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    /// Replaces the leaf for `target` with a split of `target` and `new`.
+    ///
+    /// Returns `false` if `target` is not in the layout.
+    pub(crate) fn split(&mut self, target: usize, new: usize, split: Split) -> bool {
+        match self {
+            Layout::Pane(id) if *id == target => {
+                *self = Layout::Split {
+                    split,
+                    first: Box::new(Layout::Pane(target)),
+                    second: Box::new(Layout::Pane(new)),
+                };
+                true
+            }
+            Layout::Pane(_) => false,
+            Layout::Split { first, second, .. } => {
+                first.split(target, new, split) || second.split(target, new, split)
+            }
+        }
+    }
+
+    /// Removes the leaf for `target`, letting its sibling take over the space.
+    ///
+    /// Returns `false` if `target` is not in the layout or is its only pane.
+    pub(crate) fn remove(&mut self, target: usize) -> bool {
+        let Layout::Split { first, second, .. } = self else {
+            return false;
+        };
+        let remaining = if matches!(**first, Layout::Pane(id) if id == target) {
+            second
+        } else if matches!(**second, Layout::Pane(id) if id == target) {
+            first
+        } else {
+            return first.remove(target) || second.remove(target);
+        };
+        *self = core::mem::replace(&mut **remaining, Layout::Pane(target));
+        true
+    }
+
+    /// Computes the region occupied by each pane, in left-to-right,
+    /// top-to-bottom order.
+    ///
+    /// One column or row is left between split panes for a border.
+    pub(crate) fn regions(&self, area: Rect, regions: &mut Vec<(usize, Rect)>) {
+        match self {
+            Layout::Pane(id) => regions.push((*id, area)),
+            Layout::Split { split, first, second } => {
+                let (a, b) = match split {
+                    Split::Vertical => {
+                        let left = area.columns.saturating_sub(1) / 2;
+                        (
+                            Rect { columns: left, ..area },
+                            Rect {
+                                column: area.column + left + 1,
+                                columns: area.columns.saturating_sub(left + 1),
+                                ..area
+                            },
+                        )
+                    }
+                    Split::Horizontal => {
+                        let top = area.rows.saturating_sub(1) / 2;
+                        (
+                            Rect { rows: top, ..area },
+                            Rect {
+                                row: area.row + top + 1,
+                                rows: area.rows.saturating_sub(top + 1),
+                                ..area
+                            },
+                        )
+                    }
+                };
+                first.regions(a, regions);
+                second.regions(b, regions);
+            }
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Pty-backed panes and detachable sessions for terminal multiplexing.
+//!
+//! A [`Session`] tiles one or more [`Pane`]s, each running its own `hull`
+//! shell on its own [`tty::Tty`], within a single terminal. Sessions are kept
+//! in a global registry, so a multiplexer application can detach from a
+//! session, leaving its shells running, and later reattach to it from the same
+//! or another terminal, e.g., a serial console.
+//!
+//! This crate only manages the panes and their layout; rendering the panes and
+//! handling key bindings is left to the `mux` application.
+
+#![no_std]
+
+extern crate alloc;
+
+mod layout;
+mod pane;
+mod screen;
+
+pub use layout::{Rect, Split};
+pub use pane::Pane;
+pub use screen::Screen;
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+use layout::Layout;
+use sync_block::Mutex;
+
+/// All live sessions, keyed by name.
+static SESSIONS: spin::Mutex<BTreeMap<String, Arc<Session>>> = spin::Mutex::new(BTreeMap::new());
+
+/// Creates a new session with a single pane and registers it under `name`.
+///
+/// The session is initially attached.
+pub fn create_session(name: &str, columns: usize, rows: usize) -> Result<Arc<Session>, &'static str> {
+    const NAME_TAKEN: &str = "a mux session with that name already exists";
+    if SESSIONS.lock().contains_key(name) {
+        return Err(NAME_TAKEN);
+    }
+    // Creating a pane loads and spawns a shell, which can block, so the registry isn't locked meanwhile.
+    let pane = Pane::new(0, name, columns, rows)?;
+    let session = Arc::new(Session {
+        name: String::from(name),
+        attached: AtomicBool::new(true),
+        inner: Mutex::new(SessionInner {
+            area: Rect { column: 0, row: 0, columns, rows },
+            panes: alloc::vec![Arc::new(pane)],
+            layout: Layout::Pane(0),
+            focused: 0,
+            next_id: 1,
+        }),
+    });
+    let mut sessions = SESSIONS.lock();
+    // Another session with the same name may have been created in the meantime,
+    // in which case the new session is dropped, killing its shell.
+    if sessions.contains_key(name) {
+        drop(sessions);
+        return Err(NAME_TAKEN);
+    }
+    sessions.insert(String::from(name), session.clone());
+    Ok(session)
+}
+
+/// Attaches to the detached session with the given `name`.
+pub fn attach_session(name: &str) -> Result<Arc<Session>, &'static str> {
+    let session = SESSIONS
+        .lock()
+        .get(name)
+        .cloned()
+        .ok_or("no mux session with that name exists")?;
+    if session.attached.swap(true, Ordering::AcqRel) {
+        return Err("mux session is already attached");
+    }
+    Ok(session)
+}
+
+/// Returns the names of all sessions and whether each is attached.
+pub fn sessions() -> Vec<(String, bool)> {
+    SESSIONS
+        .lock()
+        .values()
+        .map(|s| (s.name.clone(), s.is_attached()))
+        .collect()
+}
+
+/// Removes the session with the given `name`, killing all of its shells.
+pub fn kill_session(name: &str) -> Result<(), &'static str> {
+    SESSIONS
+        .lock()
+        .remove(name)
+        .map(|_| ())
+        .ok_or("no mux session with that name exists")
+}
+
+/// A set of panes tiled within one terminal.
+pub struct Session {
+    name: String,
+    attached: AtomicBool,
+    inner: Mutex<SessionInner>,
+}
+
+struct SessionInner {
+    area: Rect,
+    /// The panes in this session, in the order they were created.
+    panes: Vec<Arc<Pane>>,
+    layout: Layout,
+    /// The ID of the pane receiving input.
+    focused: usize,
+    next_id: usize,
+}
+
+impl SessionInner {
+    fn regions(&self) -> Vec<(usize, Rect)> {
+        let mut regions = Vec::with_capacity(self.panes.len());
+        self.layout.regions(self.area, &mut regions);
+        regions
+    }
+
+    fn pane(&self, id: usize) -> Option<&Arc<Pane>> {
+        self.panes.iter().find(|p| p.id() == id)
+    }
+
+    fn resize_panes(&self) {
+        for (id, rect) in self.regions() {
+            if let Some(pane) = self.pane(id) {
+                pane.resize(rect.columns, rect.rows);
+            }
+        }
+    }
+}
+
+impl Session {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.attached.load(Ordering::Acquire)
+    }
+
+    /// Detaches from this session, leaving its shells running.
+    pub fn detach(&self) {
+        self.attached.store(false, Ordering::Release);
+    }
+
+    /// Returns each pane along with the region of the terminal it occupies.
+    pub fn panes(&self) -> Vec<(Arc<Pane>, Rect)> {
+        let inner = self.inner.lock();
+        inner
+            .regions()
+            .into_iter()
+            .filter_map(|(id, rect)| inner.pane(id).map(|p| (p.clone(), rect)))
+            .collect()
+    }
+
+    /// Returns the pane that currently receives input.
+    pub fn focused_pane(&self) -> Option<Arc<Pane>> {
+        let inner = self.inner.lock();
+        inner.pane(inner.focused).cloned()
+    }
+
+    /// Sets the size of the terminal the session is displayed in.
+    ///
+    /// This is typically called when reattaching from a different terminal.
+    pub fn resize(&self, columns: usize, rows: usize) {
+        let mut inner = self.inner.lock();
+        inner.area = Rect { column: 0, row: 0, columns, rows };
+        inner.resize_panes();
+    }
+
+    /// Splits the focused pane, starting a new shell in the new pane and
+    /// focusing it.
+    pub fn split(&self, split: Split) -> Result<Arc<Pane>, &'static str> {
+        let mut inner = self.inner.lock();
+        let id = inner.next_id;
+        let focused = inner.focused;
+        if !inner.layout.split(focused, id, split) {
+            return Err("BUG: focused mux pane was not in the layout");
+        }
+        let (_, rect) = inner
+            .regions()
+            .into_iter()
+            .find(|(pane_id, _)| *pane_id == id)
+            .ok_or("BUG: new mux pane was not in the layout")?;
+
+        let pane = match Pane::new(id, &self.name, rect.columns, rect.rows) {
+            Ok(pane) => Arc::new(pane),
+            Err(e) => {
+                inner.layout.remove(id);
+                return Err(e);
+            }
+        };
+        inner.next_id += 1;
+        inner.panes.push(pane.clone());
+        inner.focused = id;
+        inner.resize_panes();
+        Ok(pane)
+    }
+
+    /// Moves the focus to the next pane in layout order.
+    pub fn focus_next(&self) {
+        let mut inner = self.inner.lock();
+        let regions = inner.regions();
+        if let Some(index) = regions.iter().position(|(id, _)| *id == inner.focused) {
+            inner.focused = regions[(index + 1) % regions.len()].0;
+        }
+    }
+
+    /// Moves the focus to the previous pane in layout order.
+    pub fn focus_previous(&self) {
+        let mut inner = self.inner.lock();
+        let regions = inner.regions();
+        if let Some(index) = regions.iter().position(|(id, _)| *id == inner.focused) {
+            inner.focused = regions[(index + regions.len() - 1) % regions.len()].0;
+        }
+    }
+
+    /// Closes the pane with the given ID, killing its shell.
+    ///
+    /// Returns `true` if the session has no panes left, in which case it is
+    /// removed from the registry.
+    pub fn close_pane(&self, id: usize) -> bool {
+        let mut inner = self.inner.lock();
+        let Some(index) = inner.panes.iter().position(|p| p.id() == id) else {
+            return inner.panes.is_empty();
+        };
+        if inner.panes.len() == 1 {
+            inner.panes.clear();
+            drop(inner);
+            let _ = kill_session(&self.name);
+            return true;
+        }
+
+        inner.layout.remove(id);
+        inner.panes.remove(index);
+        if inner.focused == id {
+            inner.focused = inner.regions()[0].0;
+        }
+        inner.resize_panes();
+        false
+    }
+
+    /// Closes every pane whose shell has exited.
+    ///
+    /// Returns `true` if the session has no panes left.
+    pub fn reap_exited_panes(&self) -> bool {
+        let exited: Vec<usize> = self
+            .inner
+            .lock()
+            .panes
+            .iter()
+            .filter(|p| p.has_exited())
+            .map(|p| p.id())
+            .collect();
+        let mut empty = false;
+        for id in exited {
+            empty = self.close_pane(id);
+        }
+        empty
+    }
+}
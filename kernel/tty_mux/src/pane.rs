@@ -0,0 +1,125 @@
+use crate::screen::Screen;
+use alloc::{format, string::String, sync::Arc};
+use log::{error, warn};
+use sync_block::{Mutex, MutexGuard};
+use task::{JoinableTaskRef, KillReason};
+
+/// A single pty-backed shell running within a [`Session`](crate::Session).
+///
+/// Each pane owns a [`tty::Tty`]: the shell holds the slave end, while a
+/// reader task drains the master end into the pane's [`Screen`].
+pub struct Pane {
+    id: usize,
+    master: tty::Master,
+    screen: Arc<Mutex<Screen>>,
+    shell: JoinableTaskRef,
+    reader: JoinableTaskRef,
+}
+
+impl Pane {
+    /// Creates a new pane of the given size running a new `hull` shell.
+    pub(crate) fn new(
+        id: usize,
+        session_name: &str,
+        columns: usize,
+        rows: usize,
+    ) -> Result<Self, &'static str> {
+        let tty = tty::Tty::new();
+        let screen = Arc::new(Mutex::new(Screen::new(columns, rows)));
+
+        let new_app_ns = mod_mgmt::create_application_namespace(None)?;
+        let (app_file, _ns) =
+            mod_mgmt::CrateNamespace::get_crate_object_file_starting_with(&new_app_ns, "hull-")
+                .ok_or("couldn't find hull in default app namespace")?;
+        let path = app_file.lock().get_absolute_path();
+
+        let shell = spawn::new_application_task_builder(path.as_ref(), Some(new_app_ns))?
+            .name(format!("mux_{session_name}_{id}_hull"))
+            .block()
+            .spawn()?;
+
+        let stream = Arc::new(tty.slave());
+        app_io::insert_child_streams(
+            shell.id,
+            app_io::IoStreams {
+                discipline: Some(stream.discipline()),
+                stdin: stream.clone(),
+                stdout: stream.clone(),
+                stderr: stream,
+            },
+        );
+
+        let reader = spawn::new_task_builder(master_to_screen_loop, (tty.master(), screen.clone()))
+            .name(format!("mux_{session_name}_{id}_reader"))
+            .spawn()?;
+
+        shell.unblock().map_err(|_| "couldn't unblock hull task")?;
+
+        Ok(Self {
+            id,
+            master: tty.master(),
+            screen,
+            shell,
+            reader,
+        })
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Returns the name of the shell task running in this pane.
+    pub fn task_name(&self) -> String {
+        self.shell.name.clone()
+    }
+
+    /// Returns whether the shell running in this pane has exited.
+    pub fn has_exited(&self) -> bool {
+        self.shell.has_exited()
+    }
+
+    /// Locks and returns the screen contents of this pane.
+    pub fn screen(&self) -> MutexGuard<'_, Screen> {
+        self.screen.lock()
+    }
+
+    /// Sends input bytes to the shell running in this pane.
+    pub fn write(&self, buf: &[u8]) -> Result<(), &'static str> {
+        self.master
+            .write(buf)
+            .map(|_| ())
+            .map_err(|_| "couldn't write to pane's tty")
+    }
+
+    pub(crate) fn resize(&self, columns: usize, rows: usize) {
+        self.screen.lock().resize(columns, rows);
+    }
+}
+
+impl Drop for Pane {
+    fn drop(&mut self) {
+        if !self.shell.has_exited() {
+            if let Err(e) = self.shell.kill(KillReason::Requested) {
+                warn!("couldn't kill shell of mux pane {}: {}", self.id, e);
+            }
+        }
+        let _ = self.reader.kill(KillReason::Requested);
+        app_io::remove_child_streams(self.shell.id);
+    }
+}
+
+/// Copies the shell's output into the pane's screen, blocking until output is available.
+///
+/// Returns once the master can no longer be read, rather than retrying indefinitely.
+fn master_to_screen_loop((master, screen): (tty::Master, Arc<Mutex<Screen>>)) {
+    let mut data = [0; 256];
+    loop {
+        match master.read(&mut data) {
+            Ok(len) => screen.lock().write(&data[..len]),
+            Err(e) => {
+                error!("couldn't read from mux pane's master: {e}");
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use alloc::vec::Vec;
+
+/// The state of the escape sequence parser.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum EscapeState {
+    None,
+    /// An `ESC` byte was received.
+    Escape,
+    /// Inside a control sequence (`ESC [`), waiting for its final byte.
+    Csi,
+}
+
+/// A grid of characters holding the visible contents of a pane.
+///
+/// The screen understands just enough of the byte stream written by an
+/// application to lay out text: printable ASCII, carriage returns, line feeds,
+/// backspaces and tabs. Escape sequences are consumed and discarded, as the
+/// multiplexer owns the outer terminal's cursor and colors.
+pub struct Screen {
+    cells: Vec<u8>,
+    columns: usize,
+    rows: usize,
+    cursor_row: usize,
+    cursor_column: usize,
+    escape: EscapeState,
+    dirty: bool,
+}
+
+impl Screen {
+    pub(crate) fn new(columns: usize, rows: usize) -> Self {
+        let columns = columns.max(1);
+        let rows = rows.max(1);
+        Self {
+            cells: alloc::vec![b' '; columns * rows],
+            columns,
+            rows,
+            cursor_row: 0,
+            cursor_column: 0,
+            escape: EscapeState::None,
+            dirty: true,
+        }
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the cursor position as `(column, row)`.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cursor_column, self.cursor_row)
+    }
+
+    /// Returns the contents of the given row.
+    pub fn row(&self, row: usize) -> &[u8] {
+        let start = row * self.columns;
+        &self.cells[start..start + self.columns]
+    }
+
+    /// Returns whether the screen changed since the last call, clearing the flag.
+    pub fn take_dirty(&mut self) -> bool {
+        core::mem::replace(&mut self.dirty, false)
+    }
+
+    /// Marks the whole screen as needing to be redrawn.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Resizes the screen, keeping the bottom-most rows of existing contents.
+    pub(crate) fn resize(&mut self, columns: usize, rows: usize) {
+        let columns = columns.max(1);
+        let rows = rows.max(1);
+        if columns == self.columns && rows == self.rows {
+            return;
+        }
+
+        let mut cells = alloc::vec![b' '; columns * rows];
+        let kept_rows = rows.min(self.cursor_row + 1);
+        let first_old_row = self.cursor_row + 1 - kept_rows;
+        for row in 0..kept_rows {
+            let old = self.row(first_old_row + row);
+            let len = old.len().min(columns);
+            cells[row * columns..row * columns + len].copy_from_slice(&old[..len]);
+        }
+
+        self.cells = cells;
+        self.columns = columns;
+        self.rows = rows;
+        self.cursor_row = kept_rows - 1;
+        self.cursor_column = self.cursor_column.min(columns - 1);
+        self.dirty = true;
+    }
+
+    /// Processes bytes written by the application running in the pane.
+    pub(crate) fn write(&mut self, buf: &[u8]) {
+        for &byte in buf {
+            self.write_byte(byte);
+        }
+        self.dirty = true;
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        match self.escape {
+            EscapeState::Escape => {
+                self.escape = if byte == b'[' { EscapeState::Csi } else { EscapeState::None };
+                return;
+            }
+            EscapeState::Csi => {
+                if (0x40..=0x7e).contains(&byte) {
+                    self.escape = EscapeState::None;
+                }
+                return;
+            }
+            EscapeState::None => {}
+        }
+
+        match byte {
+            0x1b => self.escape = EscapeState::Escape,
+            b'\r' => self.cursor_column = 0,
+            b'\n' => self.line_feed(),
+            // Backspace and delete both move the cursor left; the line
+            // discipline follows them with a space to erase the character.
+            0x08 | 0x7f => self.cursor_column = self.cursor_column.saturating_sub(1),
+            b'\t' => {
+                let next = (self.cursor_column / 8 + 1) * 8;
+                while self.cursor_column < next.min(self.columns) {
+                    self.put(b' ');
+                }
+            }
+            0x20..=0x7e => self.put(byte),
+            _ => {}
+        }
+    }
+
+    fn put(&mut self, byte: u8) {
+        if self.cursor_column >= self.columns {
+            self.cursor_column = 0;
+            self.line_feed();
+        }
+        self.cells[self.cursor_row * self.columns + self.cursor_column] = byte;
+        self.cursor_column += 1;
+    }
+
+    fn line_feed(&mut self) {
+        if self.cursor_row + 1 < self.rows {
+            self.cursor_row += 1;
+        } else {
+            self.cells.copy_within(self.columns.., 0);
+            let len = self.cells.len();
+            self.cells[len - self.columns..].fill(b' ');
+        }
+    }
+}
@@ -29,6 +29,11 @@
 //!   Finally, the task is marked as killed so it can no longer be scheduled in. 
 //! 
 //! 
+//! Unwinding is supported on both x86_64 and aarch64.
+//! On aarch64, the return address of each frame is taken from the link register (`X30`)
+//! as restored by the DWARF CFI rules, and unwinding through exception handler frames
+//! is not yet supported.
+//! 
 //! The flow of some functions was inspired by gcc's `libunwind`
 //! and from `gimli/unwind-rs/src/glue.rs`.
 
@@ -68,9 +73,12 @@ use gimli::{
     NativeEndian,
     CfaRule,
     RegisterRule,
-    X86_64
 };
-use registers::{Registers, LandingRegisters, SavedRegs};
+#[cfg(target_arch = "x86_64")]
+use gimli::X86_64;
+#[cfg(target_arch = "aarch64")]
+use gimli::AArch64;
+use registers::{Registers, LandingRegisters, SavedRegs, SP, RA};
 use fallible_iterator::FallibleIterator;
 use mod_mgmt::{
     CrateNamespace,
@@ -247,14 +255,18 @@ impl FallibleIterator for StackFrameIter {
 
         if let Some((unwind_row_ref, cfa)) = self.state.take() {
             let mut newregs = registers.clone();
-            newregs[X86_64::RA] = None;
+            // On aarch64, the return address is the link register, which keeps its value
+            // if the frame didn't save it, so we only clear it on x86_64.
+            #[cfg(target_arch = "x86_64")] {
+                newregs[RA] = None;
+            }
 
-            // For x86_64, the stack pointer is defined to be the previously-calculated CFA.
-            newregs[X86_64::RSP] = Some(cfa);
+            // For both x86_64 and aarch64, the stack pointer is defined to be the previously-calculated CFA.
+            newregs[SP] = Some(cfa);
             // If this frame is an exception/interrupt handler, we need to adjust RSP and the return address RA accordingly.
             if let Some(extra_offset) = prev_cfa_adjustment {
-                newregs[X86_64::RSP] = Some(cfa.wrapping_add(extra_offset as u64));
-                trace!("adjusting RSP to {:X?}", newregs[X86_64::RSP]);
+                newregs[SP] = Some(cfa.wrapping_add(extra_offset as u64));
+                trace!("adjusting RSP to {:X?}", newregs[SP]);
             } 
 
             unwind_row_ref.with_unwind_info(|_fde, row| {
@@ -272,8 +284,8 @@ impl FallibleIterator for StackFrameIter {
                     // debug!("Looking at register rule:  {:?} {:?}", reg_num, rule);
                     // The stack pointer (RSP) is given by the CFA calculated during the previous iteration;
                     // there should *not* be a register rule defining the value of the RSP directly.
-                    if reg_num == X86_64::RSP {
-                        warn!("Ignoring unwind row's register rule for RSP {:?}, which is invalid on x86_64 because RSP is always set to the CFA value.", rule);
+                    if reg_num == SP {
+                        warn!("Ignoring unwind row's register rule for the stack pointer {:?}, which is invalid because it is always set to the CFA value.", rule);
                         continue;
                     }
 
@@ -294,12 +306,12 @@ impl FallibleIterator for StackFrameIter {
                     //
                     // Thus, we want to skip the error code so we can get the instruction pointer, 
                     // i.e., the value at CFA + 0x08.
-                    if reg_num == X86_64::RA && prev_cfa_adjustment.is_some() {
+                    if reg_num == RA && prev_cfa_adjustment.is_some() {
                         let size_of_error_code = core::mem::size_of::<usize>();
                         // TODO FIXME: only skip the error code if the prev_cfa_adjustment included it
                         let value = unsafe { *(cfa.wrapping_add(size_of_error_code as u64) as *const u64) };
                         trace!("Using return address from CPU-pushed exception stack frame. Value: {:#X}", value);
-                        newregs[X86_64::RA] = Some(value);
+                        newregs[RA] = Some(value);
                         continue;
                     }
 
@@ -320,6 +332,14 @@ impl FallibleIterator for StackFrameIter {
                         RegisterRule::Architectural => return Err("StackFrameIter: encountered an unsupported RegisterRule::Architectural"),
                     };
                 }
+
+                // On aarch64, a new task starts executing with its link register set to its own entry point,
+                // so a return address equal to the entry point of the frame means we've reached the first frame.
+                #[cfg(target_arch = "aarch64")] {
+                    if newregs[RA] == Some(_fde.initial_address()) {
+                        newregs[RA] = None;
+                    }
+                }
                 Ok(())
             })?;
 
@@ -328,7 +348,7 @@ impl FallibleIterator for StackFrameIter {
 
         // The return address (used to find the caller's stack frame) should be in the newly-calculated register set.
         // If there isn't one, or if it's 0, then we have reached the beginning of the call stack, and are done iterating.
-        let return_address = match registers[X86_64::RA] {
+        let return_address = match registers[RA] {
             Some(0) | None => return Ok(None),
             Some(ra) => ra,
         };
@@ -337,6 +357,7 @@ impl FallibleIterator for StackFrameIter {
         // because the processor has advanced it to continue executing after the function returns.
         // As x86 has variable-length instructions, we don't know exactly where the previous instruction starts,
         // but we know that subtracting `1` will give us an address *within* that previous instruction.
+        // On aarch64, the `bl` instruction is 4 bytes before the return address, so the same holds.
         let caller = return_address - 1;
         // TODO FIXME: only subtract 1 for non-"fault" exceptions, e.g., page faults should NOT subtract 1
         // trace!("call_site_address: {:#X}", caller);
@@ -395,7 +416,12 @@ impl FallibleIterator for StackFrameIter {
             // Thus, we need to adjust this next frame's stack pointer (i.e., `cfa` which becomes the stack pointer)
             // to account for the change in stack contents. 
             // TODO FIXME: check for any type of exception/interrupt handler, and differentiate between error codes
-            cfa_adjustment = if interrupts::is_exception_handler_with_error_code(fde.initial_address()) {
+            // TODO: on aarch64, account for the exception frame saved by the exception vector.
+            #[cfg(target_arch = "x86_64")]
+            let is_exception_handler_with_error_code = interrupts::is_exception_handler_with_error_code(fde.initial_address());
+            #[cfg(target_arch = "aarch64")]
+            let is_exception_handler_with_error_code = false;
+            cfa_adjustment = if is_exception_handler_with_error_code {
                 let size_of_error_code: i64 = core::mem::size_of::<usize>() as i64;
                 trace!("StackFrameIter: next stack frame has a CPU-pushed error code on the stack, adjusting CFA to {:#X}", cfa);
 
@@ -474,6 +500,7 @@ pub fn invoke_with_current_registers<F>(f: &mut F) -> Result<(), &'static str>
     /// that are relative to the current register values, so we must have those current values as a starting point.
     /// 
     /// The argument is a pointer to a function reference, so effectively a pointer to a pointer. 
+    #[cfg(target_arch = "x86_64")]
     #[naked]
     unsafe extern "C" fn unwind_trampoline(_func: *mut FuncWithRegistersRefMut) -> *mut Result<(), &'static str> {
         // This is a naked function, so you CANNOT place anything here before the asm block, not even log statements.
@@ -512,11 +539,52 @@ pub fn invoke_with_current_registers<F>(f: &mut F) -> Result<(), &'static str>
         );
     }
 
+    /// The aarch64 version of the above `unwind_trampoline()`.
+    /// 
+    /// DO NOT touch the X0 register, which has the `_func` function; it needs to be passed into unwind_recorder.
+    #[cfg(target_arch = "aarch64")]
+    #[naked]
+    unsafe extern "C" fn unwind_trampoline(_func: *mut FuncWithRegistersRefMut) -> *mut Result<(), &'static str> {
+        asm!(
+            // copy the stack pointer to X1; unlike x86_64, the return address is not on the stack.
+            "
+            mov x1, sp
+            sub sp, sp, #8 * 12
+            stp x19, x20, [sp, #8 * 0]
+            stp x21, x22, [sp, #8 * 2]
+            stp x23, x24, [sp, #8 * 4]
+            stp x25, x26, [sp, #8 * 6]
+            stp x27, x28, [sp, #8 * 8]
+            stp x29, x30, [sp, #8 * 10]
+            ",
+            // To invoke `unwind_recorder`, we need to put: 
+            // (1) the func in X0 (it's already there, just don't overwrite it),
+            // (2) the stack in X1,
+            // (3) a pointer to the saved registers in X2.
+            "
+            mov x2, sp   // pointer to saved regs (on the stack)
+            bl unwind_recorder
+            ",
+            // Finally, restore saved registers
+            "
+            ldp x19, x20, [sp, #8 * 0]
+            ldp x21, x22, [sp, #8 * 2]
+            ldp x23, x24, [sp, #8 * 4]
+            ldp x25, x26, [sp, #8 * 6]
+            ldp x27, x28, [sp, #8 * 8]
+            ldp x29, x30, [sp, #8 * 10]
+            add sp, sp, #8 * 12
+            ret
+            ",
+            options(noreturn)
+        );
+    }
+
 
     /// The calling convention dictates the following order of arguments: 
-    /// * first arg in `RDI` register, the function (or closure) to invoke with the saved registers arg,
-    /// * second arg in `RSI` register, the stack pointer,
-    /// * third arg in `RDX` register, the saved register values used to recover execution context
+    /// * first arg in `RDI` (`X0` on aarch64) register, the function (or closure) to invoke with the saved registers arg,
+    /// * second arg in `RSI` (`X1`) register, the stack pointer,
+    /// * third arg in `RDX` (`X2`) register, the saved register values used to recover execution context
     ///   after we change the register values during unwinding,
     #[no_mangle]
     unsafe extern "C" fn unwind_recorder(
@@ -528,14 +596,31 @@ pub fn invoke_with_current_registers<F>(f: &mut F) -> Result<(), &'static str>
         let saved_regs = &*saved_regs;
 
         let mut registers = Registers::default();
-        registers[X86_64::RBX] = Some(saved_regs.rbx);
-        registers[X86_64::RBP] = Some(saved_regs.rbp);
-        registers[X86_64::RSP] = Some(stack + 8); // the stack value passed in is one pointer width before the real RSP
-        registers[X86_64::R12] = Some(saved_regs.r12);
-        registers[X86_64::R13] = Some(saved_regs.r13);
-        registers[X86_64::R14] = Some(saved_regs.r14);
-        registers[X86_64::R15] = Some(saved_regs.r15);
-        registers[X86_64::RA]  = Some(*(stack as *const u64));
+        #[cfg(target_arch = "x86_64")] {
+            registers[X86_64::RBX] = Some(saved_regs.rbx);
+            registers[X86_64::RBP] = Some(saved_regs.rbp);
+            registers[X86_64::RSP] = Some(stack + 8); // the stack value passed in is one pointer width before the real RSP
+            registers[X86_64::R12] = Some(saved_regs.r12);
+            registers[X86_64::R13] = Some(saved_regs.r13);
+            registers[X86_64::R14] = Some(saved_regs.r14);
+            registers[X86_64::R15] = Some(saved_regs.r15);
+            registers[X86_64::RA]  = Some(*(stack as *const u64));
+        }
+        #[cfg(target_arch = "aarch64")] {
+            registers[AArch64::X19] = Some(saved_regs.x19);
+            registers[AArch64::X20] = Some(saved_regs.x20);
+            registers[AArch64::X21] = Some(saved_regs.x21);
+            registers[AArch64::X22] = Some(saved_regs.x22);
+            registers[AArch64::X23] = Some(saved_regs.x23);
+            registers[AArch64::X24] = Some(saved_regs.x24);
+            registers[AArch64::X25] = Some(saved_regs.x25);
+            registers[AArch64::X26] = Some(saved_regs.x26);
+            registers[AArch64::X27] = Some(saved_regs.x27);
+            registers[AArch64::X28] = Some(saved_regs.x28);
+            registers[AArch64::X29] = Some(saved_regs.x29);
+            registers[AArch64::X30] = Some(saved_regs.x30); // the return address
+            registers[AArch64::SP]  = Some(stack); // `bl` doesn't push anything onto the stack
+        }
 
         let res = func(registers);
         Box::into_raw(Box::new(res))
@@ -551,6 +636,7 @@ pub fn invoke_with_current_registers<F>(f: &mut F) -> Result<(), &'static str>
 /// 
 /// This is similar in design to how the latter half of a context switch routine
 /// must restore the previously-saved registers for the next task.
+#[cfg(target_arch = "x86_64")]
 unsafe fn land(regs: &Registers, landing_pad_address: u64) -> Result<(), &'static str> {
     let mut landing_regs = LandingRegisters {
         rax: regs[X86_64::RAX].unwrap_or(0),
@@ -615,6 +701,63 @@ unsafe fn land(regs: &Registers, landing_pad_address: u64) -> Result<(), &'stati
     }
 }
 
+/// The aarch64 version of the above `land()` function.
+/// 
+/// As aarch64 returns via the link register rather than the stack,
+/// the landing pad address is passed directly to `unwind_lander()` instead of being placed on the stack.
+#[cfg(target_arch = "aarch64")]
+unsafe fn land(regs: &Registers, landing_pad_address: u64) -> Result<(), &'static str> {
+    let mut landing_regs = LandingRegisters {
+        x: [0; 31],
+        sp: regs[AArch64::SP].ok_or("unwind::land(): SP was None, \
+            it must be set so that the landing pad function can execute properly."
+        )?,
+    };
+    for (i, x) in landing_regs.x.iter_mut().enumerate() {
+        *x = regs[gimli::Register(i as u16)].unwrap_or(0);
+    }
+    // trace!("unwind_lander regs: {:#X?}", landing_regs);
+    unwind_lander(&landing_regs, landing_pad_address);
+    // this is the end of the code in this function, the following is just inner functions.
+
+
+    /// This function places the values of the given landing registers
+    /// into the actual CPU registers, and then jumps to the given landing pad address.
+    /// 
+    /// The landing pad address is kept in X16, an intra-procedure-call scratch register
+    /// that the landing pad does not expect to be preserved, so it is not restored.
+    /// 
+    /// It is marked as divergent (returning `!`) because it doesn't return to the caller,
+    /// instead it jumps to that landing pad address.
+    #[naked]
+    unsafe extern "C" fn unwind_lander(_regs: *const LandingRegisters, _landing_pad_address: u64) -> ! {
+        asm!("
+            mov x16, x1
+            ldr x2, [x0, #8 * 31]
+            mov sp, x2
+            ldp x2,  x3,  [x0, #8 * 2]
+            ldp x4,  x5,  [x0, #8 * 4]
+            ldp x6,  x7,  [x0, #8 * 6]
+            ldp x8,  x9,  [x0, #8 * 8]
+            ldp x10, x11, [x0, #8 * 10]
+            ldp x12, x13, [x0, #8 * 12]
+            ldp x14, x15, [x0, #8 * 14]
+            ldr x17,      [x0, #8 * 17]
+            ldp x18, x19, [x0, #8 * 18]
+            ldp x20, x21, [x0, #8 * 20]
+            ldp x22, x23, [x0, #8 * 22]
+            ldp x24, x25, [x0, #8 * 24]
+            ldp x26, x27, [x0, #8 * 26]
+            ldp x28, x29, [x0, #8 * 28]
+            ldr x30,      [x0, #8 * 30]
+            ldp x0,  x1,  [x0, #8 * 0]
+            br x16  // jump to the actual landing pad function
+            ",
+            options(noreturn)
+        );
+    }
+}
+
 
 type NativeEndianSliceReader<'i> = EndianSlice<'i, NativeEndian>;
 
@@ -877,7 +1020,12 @@ fn continue_unwinding(unwinding_context_ptr: *mut UnwindingContext) -> Result<()
     // So, whatever we put into RAX in the landing regs will be placed into the first arg (RDI) in _Unwind_Resume.
     // This is arch-specific; for x86_64 the transfer is from RAX -> RDI, for ARM/AARCH64, the transfer is from R0 -> R1 or X0 -> X1.
     // See this for more mappings: <https://github.com/rust-lang/rust/blob/master/src/libpanic_unwind/gcc.rs#L102>
-    regs[gimli::X86_64::RAX] = Some(unwinding_context_ptr as u64);
+    #[cfg(target_arch = "x86_64")] {
+        regs[X86_64::RAX] = Some(unwinding_context_ptr as u64);
+    }
+    #[cfg(target_arch = "aarch64")] {
+        regs[AArch64::X0] = Some(unwinding_context_ptr as u64);
+    }
     unsafe {
         land(&regs, landing_pad_address)?;
     }
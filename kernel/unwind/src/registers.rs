@@ -1,6 +1,10 @@
 //! Struct definitions for various sets of register values that are useful in unwinding.
 
-use gimli::{self, X86_64};
+use gimli;
+#[cfg(target_arch = "x86_64")]
+use gimli::X86_64;
+#[cfg(target_arch = "aarch64")]
+use gimli::AArch64;
 use core::fmt::{Debug, Formatter, Result as FmtResult};
 use core::ops::{Index, IndexMut};
 
@@ -11,30 +15,53 @@ use core::ops::{Index, IndexMut};
 /// and calculating the caller frame's register values.
 /// 
 /// The register values herein can be indexed by using DWARF-specific register IDs,
-/// which are constant values that are defined in the ELF x86_86 ABI.
+/// which are constant values that are defined in the ELF ABI of each architecture.
 /// [Here is a brief link](https://docs.rs/gimli/0.19.0/gimli/struct.X86_64.html)
 /// that defines these constants in a practical, useful manner.
 /// 
 /// # Important Note
-/// The number of registers defined here must be one greater than 
+/// On x86_64, the number of registers defined here must be one greater than 
 /// the number of registers defined in the `LandingRegisters` struct,
 /// because this one includes the return address too.
+/// On aarch64, the return address is held in the link register (`X30`),
+/// so the two must have the same number of registers.
 /// 
-/// Currently, this structure has room for `17` optional registers.
+/// Currently, this structure has room for `17` optional registers on x86_64
+/// (`RAX` to `R15` and `RA`), and `32` on aarch64 (`X0` to `X30` and `SP`).
 #[derive(Default, Clone, PartialEq, Eq)]
 pub struct Registers {
-    registers: [Option<u64>; 17],
+    registers: [Option<u64>; NUM_REGISTERS],
 }
 
+#[cfg(target_arch = "x86_64")]
+const NUM_REGISTERS: usize = 17;
+#[cfg(target_arch = "aarch64")]
+const NUM_REGISTERS: usize = 32;
+
+/// The DWARF register number of the stack pointer.
+#[cfg(target_arch = "x86_64")]
+pub const SP: gimli::Register = X86_64::RSP;
+/// The DWARF register number of the stack pointer.
+#[cfg(target_arch = "aarch64")]
+pub const SP: gimli::Register = AArch64::SP;
+
+/// The DWARF register number of the return address.
+#[cfg(target_arch = "x86_64")]
+pub const RA: gimli::Register = X86_64::RA;
+/// The DWARF register number of the return address,
+/// which on aarch64 is the link register (`X30`).
+#[cfg(target_arch = "aarch64")]
+pub const RA: gimli::Register = AArch64::X30;
+
 impl Registers {
     /// Returns the value of the stack pointer register.
     pub fn stack_pointer(&self) -> Option<u64> {
-        self[X86_64::RSP]
+        self[SP]
     }
 
     /// Returns the value of the return address for this register set.
     pub fn return_address(&self) -> Option<u64> {
-        self[X86_64::RA]
+        self[RA]
     }
 }
 
@@ -71,6 +98,7 @@ impl IndexMut<gimli::Register> for Registers {
 /// # Important Note
 /// This should be kept in sync with the number of elements 
 /// in the `Registers` struct; this must have one less element.
+#[cfg(target_arch = "x86_64")]
 #[derive(Debug)]
 #[repr(C)]
 pub struct LandingRegisters {
@@ -94,6 +122,22 @@ pub struct LandingRegisters {
     // We probably do for SIMD at least.
 }
 
+/// Contains the register values that will be restored to the actual CPU registers
+/// right before jumping to a landing pad function.
+/// 
+/// # Important Note
+/// This should be kept in sync with the number of elements 
+/// in the `Registers` struct, and with the offsets used in `unwind_lander()`.
+#[cfg(target_arch = "aarch64")]
+#[derive(Debug)]
+#[repr(C)]
+pub struct LandingRegisters {
+    /// The general-purpose registers `X0` to `X30`,
+    /// in which `X29` is the frame pointer and `X30` is the link register.
+    pub x: [u64; 31],
+    pub sp: u64,
+}
+
 
 /// Contains the registers that are callee-saved.
 /// This is intended to be used at the beginning of stack unwinding for two purposes:
@@ -101,7 +145,8 @@ pub struct LandingRegisters {
 ///    calculate the register values for the previous stack frame based on register transformation rules,
 /// 2. To know which register values to restore after unwinding is complete.
 /// 
-/// This is currently x86_64-specific.
+/// The order of these fields must match the order in which `unwind_trampoline()` saves them.
+#[cfg(target_arch = "x86_64")]
 #[derive(Debug)]
 #[repr(C)]
 pub struct SavedRegs {
@@ -112,3 +157,30 @@ pub struct SavedRegs {
     pub rbx: u64,
     pub rbp: u64,
 }
+
+/// Contains the registers that are callee-saved.
+/// This is intended to be used at the beginning of stack unwinding for two purposes:
+/// 1. The unwinding tables need an initial value for these registers in order to 
+///    calculate the register values for the previous stack frame based on register transformation rules,
+/// 2. To know which register values to restore after unwinding is complete.
+/// 
+/// The order of these fields must match the order in which `unwind_trampoline()` saves them.
+#[cfg(target_arch = "aarch64")]
+#[derive(Debug)]
+#[repr(C)]
+pub struct SavedRegs {
+    pub x19: u64,
+    pub x20: u64,
+    pub x21: u64,
+    pub x22: u64,
+    pub x23: u64,
+    pub x24: u64,
+    pub x25: u64,
+    pub x26: u64,
+    pub x27: u64,
+    pub x28: u64,
+    /// The frame pointer.
+    pub x29: u64,
+    /// The link register, i.e., the return address of `unwind_trampoline()`.
+    pub x30: u64,
+}
@@ -0,0 +1,138 @@
+//! The virtual filesystem's mount table, which allows the root directory of any filesystem
+//! to be grafted into the directory tree at an arbitrary path, e.g., `/mnt/usb`.
+//!
+//! Mounting a filesystem at a path covers the directory at that path, such that path traversal
+//! enters the mounted filesystem's root directory instead, via [`resolve_mount()`].
+//! The covered directory is left untouched and becomes visible again once the filesystem is unmounted.
+//! If multiple filesystems are mounted at the same path, the most recently mounted one is visible.
+//!
+//! Because the directory containing a mount point isn't modified, filesystems can be mounted within
+//! any other filesystem, including ones that can't store foreign nodes, such as FAT32 or ext2 volumes.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{sync::{Arc, Weak}, vec::Vec};
+use fs_node::{DirRef, Directory, FsNode};
+use log::info;
+use root::RootDirectory;
+use spin::Mutex;
+
+/// A filesystem that is mounted in the directory tree.
+struct Mount {
+    /// The directory covered by this mount.
+    mount_point: DirRef,
+    /// The root directory of the mounted filesystem.
+    root: DirRef,
+}
+
+/// All mounted filesystems, in the order in which they were mounted.
+static MOUNTS: Mutex<Vec<Mount>> = Mutex::new(Vec::new());
+
+/// Returns the directory that is visible in place of the given `dir`, i.e.,
+/// the root directory of the filesystem most recently mounted over it,
+/// or `dir` itself if it isn't a mount point.
+///
+/// Path traversal must call this for every directory that it enters.
+/// The lock on `dir` must not be held.
+pub fn resolve_mount(dir: DirRef) -> DirRef {
+    let mounts = MOUNTS.lock();
+    let mut dir = dir;
+    // A filesystem can be mounted over the root directory of another mounted filesystem.
+    while let Some(mount) = mounts.iter().rev().find(|mount| Arc::ptr_eq(&mount.mount_point, &dir)) {
+        dir = mount.root.clone();
+    }
+    dir
+}
+
+/// Mounts the filesystem whose root directory is `fs` at the given absolute `path`,
+/// which must refer to an existing directory other than the root directory.
+///
+/// The `fs` root directory must not already be part of the directory tree, i.e., it must not
+/// have a parent directory, because its parent directory is set to that of the mount point,
+/// such that `..` leads out of the mounted filesystem.
+/// Ideally, its name is also the final component of `path`.
+pub fn mount(path: &str, fs: DirRef) -> Result<(), &'static str> {
+    let mount_point = lookup_dir(path)?;
+    if Arc::ptr_eq(&mount_point, root::get_root()) {
+        return Err("vfs: cannot mount over the root directory");
+    }
+    // Otherwise, resolving the mount point would never terminate, as it would lead back to itself.
+    if is_within(&mount_point, &fs) {
+        return Err("vfs: cannot mount a filesystem over a directory within it");
+    }
+    if fs.lock().get_parent_dir().is_some() {
+        return Err("vfs: filesystem's root directory is already part of the directory tree");
+    }
+    let parent = mount_point.lock().get_parent_dir();
+
+    let mut mounts = MOUNTS.lock();
+    if mounts.iter().any(|mount| Arc::ptr_eq(&mount.root, &fs) || Arc::ptr_eq(&mount.mount_point, &fs)) {
+        return Err("vfs: filesystem is already mounted or covered by another filesystem");
+    }
+    if let Some(parent) = parent {
+        fs.lock().set_parent_dir(Arc::downgrade(&parent));
+    }
+    mounts.push(Mount { mount_point, root: fs });
+    info!("vfs: mounted filesystem at {:?}", path);
+    Ok(())
+}
+
+/// Unmounts the filesystem that is visible at the given absolute `path`,
+/// and returns its root directory.
+///
+/// Returns an error if `path` isn't a mount point, or if another filesystem
+/// is mounted within the filesystem to be unmounted.
+pub fn umount(path: &str) -> Result<DirRef, &'static str> {
+    let fs = lookup_dir(path)?;
+
+    let mut mounts = MOUNTS.lock();
+    let index = mounts.iter()
+        .position(|mount| Arc::ptr_eq(&mount.root, &fs))
+        .ok_or("vfs: path is not a mount point")?;
+    if mounts.iter().any(|mount| is_within(&mount.mount_point, &fs)) {
+        return Err("vfs: another filesystem is mounted within the filesystem to unmount");
+    }
+    mounts.remove(index);
+    drop(mounts);
+
+    fs.lock().set_parent_dir(Weak::<Mutex<RootDirectory>>::new());
+    info!("vfs: unmounted filesystem at {:?}", path);
+    Ok(fs)
+}
+
+/// Returns the directory at the given absolute `path`, crossing mount points along the way.
+///
+/// This can't use the `path` crate, as it depends on this crate in order to cross mount points.
+fn lookup_dir(path: &str) -> Result<DirRef, &'static str> {
+    if !path.starts_with('/') {
+        return Err("vfs: mount path must be absolute");
+    }
+    let mut current = root::get_root().clone();
+    for name in path.split('/').filter(|name| !name.is_empty() && *name != ".") {
+        let next = if name == ".." {
+            current.lock().get_parent_dir()
+        } else {
+            current.lock().get_dir(name)
+        };
+        current = resolve_mount(next.ok_or("vfs: mount path does not refer to a directory")?);
+    }
+    Ok(current)
+}
+
+/// Returns `true` if `dir` is `ancestor` or is contained within it.
+fn is_within(dir: &DirRef, ancestor: &DirRef) -> bool {
+    let mut current = dir.clone();
+    loop {
+        if Arc::ptr_eq(&current, ancestor) {
+            return true;
+        }
+        let parent = current.lock().get_parent_dir();
+        match parent {
+            // The root directory is its own parent.
+            Some(parent) if !Arc::ptr_eq(&parent, &current) => current = parent,
+            _ => return false,
+        }
+    }
+}
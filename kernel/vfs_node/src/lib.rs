@@ -11,6 +11,7 @@ extern crate alloc;
 extern crate spin;
 extern crate fs_node;
 extern crate memory;
+extern crate file_watch;
 
 use alloc::string::String;
 use alloc::vec::Vec;
@@ -18,6 +19,7 @@ use spin::Mutex;
 use alloc::sync::{Arc, Weak};
 use alloc::collections::BTreeMap;
 use fs_node::{DirRef, WeakDirRef, Directory, FileOrDir, FsNode};
+use file_watch::FileEventKind;
 
 
 /// A struct that represents a node in the VFS 
@@ -43,15 +45,29 @@ impl VFSDirectory {
         parent.lock().insert(FileOrDir::Dir(dir_ref.clone()))?;
         Ok(dir_ref)
     }
+
+    /// Notifies any watchers of the child node with the given `name` that it has changed.
+    fn notify_child(&self, name: &str, kind: FileEventKind) {
+        if file_watch::is_active() {
+            let mut path = self.get_absolute_path();
+            if !path.ends_with('/') {
+                path.push('/');
+            }
+            path.push_str(name);
+            file_watch::notify(&path, kind);
+        }
+    }
 }
 
 impl Directory for VFSDirectory {
     fn insert(&mut self, node: FileOrDir) -> Result<Option<FileOrDir>, &'static str> {
         let name = node.get_name();
-        if let Some(mut old_node) = self.children.insert(name, node) {
+        if let Some(mut old_node) = self.children.insert(name.clone(), node) {
             old_node.set_parent_dir(Weak::<Mutex<VFSDirectory>>::new());
+            self.notify_child(&name, FileEventKind::Modified);
             Ok(Some(old_node))
         } else {
+            self.notify_child(&name, FileEventKind::Created);
             Ok(None)
         }
     }
@@ -66,8 +82,10 @@ impl Directory for VFSDirectory {
     }
 
     fn remove(&mut self, node: &FileOrDir) -> Option<FileOrDir> {
-        if let Some(mut old_node) = self.children.remove(&node.get_name()) {
+        let name = node.get_name();
+        if let Some(mut old_node) = self.children.remove(&name) {
             old_node.set_parent_dir(Weak::<Mutex<VFSDirectory>>::new());
+            self.notify_child(&name, FileEventKind::Removed);
             Some(old_node)
         } else {
             None
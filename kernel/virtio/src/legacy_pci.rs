@@ -0,0 +1,155 @@
+//! The legacy virtio PCI transport, in which a device's registers are accessed via the I/O port range in BAR0.
+
+use log::debug;
+use pci::PciDevice;
+use port_io::{Port, PortReadOnly, PortWriteOnly};
+use crate::{status, Virtqueue};
+
+// Offsets of the common registers from the start of the I/O port range.
+const DEVICE_FEATURES: u16 = 0x00;
+const GUEST_FEATURES:  u16 = 0x04;
+const QUEUE_ADDRESS:   u16 = 0x08;
+const QUEUE_SIZE:      u16 = 0x0C;
+const QUEUE_SELECT:    u16 = 0x0E;
+const QUEUE_NOTIFY:    u16 = 0x10;
+const DEVICE_STATUS:   u16 = 0x12;
+const ISR_STATUS:      u16 = 0x13;
+/// The device-specific configuration follows the common registers when MSI-X is disabled.
+const DEVICE_CONFIG:   u16 = 0x14;
+
+/// The legacy interface specifies queue addresses as page frame numbers of this size.
+const QUEUE_ADDRESS_SHIFT: usize = 12;
+
+/// Access to a virtio device via the legacy PCI interface.
+pub struct LegacyPciTransport {
+    io_base: u16,
+    device_features: PortReadOnly<u32>,
+    guest_features: Port<u32>,
+    queue_address: Port<u32>,
+    queue_size: PortReadOnly<u16>,
+    queue_select: Port<u16>,
+    queue_notify: PortWriteOnly<u16>,
+    device_status: Port<u8>,
+    isr_status: PortReadOnly<u8>,
+}
+
+impl LegacyPciTransport {
+    /// Resets the given virtio PCI device and acknowledges it,
+    /// returning a transport through which it can be further initialized.
+    ///
+    /// The device's features should then be negotiated, followed by setting up its queues
+    /// and finally marking the driver as ready via [`LegacyPciTransport::driver_ok()`].
+    pub fn new(pci_device: &PciDevice) -> Result<LegacyPciTransport, &'static str> {
+        if pci_device.vendor_id != crate::VIRTIO_VENDOR_ID {
+            return Err("virtio: PCI device is not a virtio device");
+        }
+        let bar0 = pci_device.bars[0];
+        if bar0 & 0x1 == 0 {
+            return Err("virtio: BAR0 is not an I/O port range, so the device doesn't support the legacy interface");
+        }
+        let io_base = (bar0 & !0x3) as u16;
+        pci_device.pci_set_command_bus_master_bit();
+
+        let transport = LegacyPciTransport {
+            io_base,
+            device_features: PortReadOnly::new(io_base + DEVICE_FEATURES),
+            guest_features:  Port::new(io_base + GUEST_FEATURES),
+            queue_address:   Port::new(io_base + QUEUE_ADDRESS),
+            queue_size:      PortReadOnly::new(io_base + QUEUE_SIZE),
+            queue_select:    Port::new(io_base + QUEUE_SELECT),
+            queue_notify:    PortWriteOnly::new(io_base + QUEUE_NOTIFY),
+            device_status:   Port::new(io_base + DEVICE_STATUS),
+            isr_status:      PortReadOnly::new(io_base + ISR_STATUS),
+        };
+        transport.reset();
+        transport.add_status(status::ACKNOWLEDGE);
+        transport.add_status(status::DRIVER);
+        debug!("virtio: found legacy device {:#06X} at {}, I/O base {:#X}",
+            pci_device.device_id, pci_device.location, io_base
+        );
+        Ok(transport)
+    }
+
+    /// Resets the device, which also disables all of its queues.
+    pub fn reset(&self) {
+        // SAFETY: writing zero to the status register only resets this virtio device.
+        unsafe { self.device_status.write(0) };
+    }
+
+    /// Returns the current value of the device status register.
+    pub fn status(&self) -> u8 {
+        self.device_status.read()
+    }
+
+    /// Sets the given bits of the device status register, in addition to those already set.
+    pub fn add_status(&self, bits: u8) {
+        let current = self.device_status.read();
+        // SAFETY: the status register only affects this virtio device.
+        unsafe { self.device_status.write(current | bits) };
+    }
+
+    /// Offers the subset of the device's features that are also in `supported_features` to the device,
+    /// and returns that subset.
+    ///
+    /// Only the lower 32 feature bits are accessible through the legacy interface.
+    pub fn negotiate_features(&self, supported_features: u32) -> u32 {
+        let features = self.device_features.read() & supported_features;
+        // SAFETY: the guest features register only affects this virtio device.
+        unsafe { self.guest_features.write(features) };
+        features
+    }
+
+    /// Allocates a virtqueue for the device's queue with the given `index` and tells the device its address.
+    pub fn setup_queue(&self, index: u16) -> Result<Virtqueue, &'static str> {
+        // SAFETY: selecting a queue only affects which queue the other queue registers refer to.
+        unsafe { self.queue_select.write(index) };
+        if self.queue_address.read() != 0 {
+            return Err("virtio: queue is already in use");
+        }
+        let size = self.queue_size.read();
+        if size == 0 {
+            return Err("virtio: queue doesn't exist");
+        }
+        let queue = Virtqueue::new(index, size)?;
+        let pfn = queue.physical_address().value() >> QUEUE_ADDRESS_SHIFT;
+        let pfn = u32::try_from(pfn).map_err(|_| "virtio: queue memory is above the legacy interface's 44-bit limit")?;
+        // SAFETY: the queue's memory is owned by the returned `Virtqueue`.
+        //         The caller must reset the device before dropping it, as with any DMA buffer.
+        unsafe { self.queue_address.write(pfn) };
+        Ok(queue)
+    }
+
+    /// Tells the device that new buffers are available in the given queue.
+    pub fn notify(&self, queue: &Virtqueue) {
+        // SAFETY: notifying a queue only causes the device to process buffers already added to that queue.
+        unsafe { self.queue_notify.write(queue.index()) };
+    }
+
+    /// Marks the driver as ready, after which the device may start using its queues.
+    pub fn driver_ok(&self) {
+        self.add_status(status::DRIVER_OK);
+    }
+
+    /// Marks the device as failed, e.g., if it couldn't be initialized.
+    pub fn set_failed(&self) {
+        self.add_status(status::FAILED);
+    }
+
+    /// Reads and thereby clears the ISR status register.
+    ///
+    /// Bit 0 indicates a used buffer notification, and bit 1 indicates a configuration change.
+    pub fn read_isr(&self) -> u8 {
+        self.isr_status.read()
+    }
+
+    /// Reads the 32-bit value at the given byte offset into the device-specific configuration.
+    pub fn read_config_u32(&self, offset: u16) -> u32 {
+        PortReadOnly::<u32>::new(self.io_base + DEVICE_CONFIG + offset).read()
+    }
+
+    /// Writes the 32-bit value at the given byte offset into the device-specific configuration.
+    pub fn write_config_u32(&self, offset: u16, value: u32) {
+        // SAFETY: the device-specific configuration only affects this virtio device.
+        unsafe { PortWriteOnly::<u32>::new(self.io_base + DEVICE_CONFIG + offset).write(value) };
+    }
+}
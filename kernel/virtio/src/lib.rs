@@ -0,0 +1,65 @@
+//! Common support for virtio devices, as specified by the [Virtual I/O Device (VIRTIO) specification].
+//!
+//! This crate provides the building blocks used by individual virtio device drivers:
+//! * [`LegacyPciTransport`]: access to a virtio device's common registers and device-specific
+//!   configuration through the legacy (a.k.a. "transitional") PCI interface,
+//!   which QEMU offers by default for most virtio PCI devices.
+//! * [`ModernPciTransport`]: access to the same through the modern PCI interface,
+//!   which is required by devices that have no legacy interface, such as virtio-input.
+//! * [`Virtqueue`]: a split virtqueue, through which buffers are exchanged with the device.
+//!
+//! Most drivers poll virtqueues for completions rather than using interrupts;
+//! those that do use interrupts must acknowledge each one by reading the ISR status register.
+//!
+//! [Virtual I/O Device (VIRTIO) specification]: https://docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.html
+
+#![no_std]
+
+extern crate alloc;
+
+#[cfg(target_arch = "x86_64")]
+mod legacy_pci;
+mod modern_pci;
+mod virtqueue;
+
+#[cfg(target_arch = "x86_64")]
+pub use legacy_pci::LegacyPciTransport;
+pub use modern_pci::{ModernPciTransport, VIRTIO_F_VERSION_1};
+pub use virtqueue::{Virtqueue, BufferSegment};
+
+/// The PCI vendor ID of all virtio devices.
+pub const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+
+/// PCI device IDs of transitional virtio devices, which support the legacy interface.
+pub mod transitional_device_ids {
+    pub const NETWORK: u16 = 0x1000;
+    pub const BLOCK:   u16 = 0x1001;
+    pub const BALLOON: u16 = 0x1002;
+    pub const CONSOLE: u16 = 0x1003;
+}
+
+/// PCI device IDs of modern virtio devices, which are `0x1040` plus the virtio device type.
+pub mod modern_device_ids {
+    pub const NETWORK: u16 = 0x1041;
+    pub const BLOCK:   u16 = 0x1042;
+    pub const CONSOLE: u16 = 0x1043;
+    pub const BALLOON: u16 = 0x1045;
+    pub const GPU:     u16 = 0x1050;
+    pub const INPUT:   u16 = 0x1052;
+}
+
+/// Bits of the device status register, which the driver sets as it initializes a device.
+pub mod status {
+    /// The guest OS has noticed the device.
+    pub const ACKNOWLEDGE: u8 = 1;
+    /// The guest OS knows how to drive the device.
+    pub const DRIVER: u8 = 2;
+    /// The driver is set up and ready to drive the device.
+    pub const DRIVER_OK: u8 = 4;
+    /// The driver has acknowledged all the features it understands.
+    pub const FEATURES_OK: u8 = 8;
+    /// The device has experienced an error from which it can't recover.
+    pub const DEVICE_NEEDS_RESET: u8 = 64;
+    /// Something went wrong in the guest, and it has given up on the device.
+    pub const FAILED: u8 = 128;
+}
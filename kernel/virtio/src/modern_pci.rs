@@ -0,0 +1,316 @@
+//! The modern virtio PCI transport, in which a device's registers are memory-mapped structures
+//! whose locations are given by vendor-specific PCI capabilities.
+//!
+//! Devices that were introduced after virtio 0.9.5, such as virtio-input,
+//! only support this interface and not the legacy one.
+
+use alloc::vec::Vec;
+use log::debug;
+use memory::MappedPages;
+use pci::{PciCapability, PciDevice};
+use volatile::{ReadOnly, Volatile};
+use zerocopy::FromBytes;
+use crate::{status, Virtqueue};
+
+// Values of the `cfg_type` field of a virtio PCI capability.
+const COMMON_CFG: u8 = 1;
+const NOTIFY_CFG: u8 = 2;
+const ISR_CFG:    u8 = 3;
+const DEVICE_CFG: u8 = 4;
+
+/// The feature bit indicating compliance with version 1.0 (or later) of the virtio specification,
+/// which must be negotiated in order to use the modern interface.
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+/// The common configuration structure, located by the `COMMON_CFG` capability.
+#[derive(FromBytes)]
+#[repr(C)]
+struct CommonConfig {
+    device_feature_select: Volatile<u32>,
+    device_feature:        ReadOnly<u32>,
+    driver_feature_select: Volatile<u32>,
+    driver_feature:        Volatile<u32>,
+    msix_config:           Volatile<u16>,
+    num_queues:            ReadOnly<u16>,
+    device_status:         Volatile<u8>,
+    config_generation:     ReadOnly<u8>,
+    queue_select:          Volatile<u16>,
+    queue_size:            Volatile<u16>,
+    queue_msix_vector:     Volatile<u16>,
+    queue_enable:          Volatile<u16>,
+    queue_notify_off:      ReadOnly<u16>,
+    queue_desc:            Volatile<u64>,
+    queue_driver:          Volatile<u64>,
+    queue_device:          Volatile<u64>,
+}
+
+/// The location of one of a device's configuration structures within its BARs.
+#[derive(Clone, Copy, Debug)]
+struct Region {
+    /// The index into `ModernPciTransport::bars` of the mapped BAR that contains this region.
+    bar: usize,
+    offset: usize,
+    length: usize,
+}
+
+/// Access to a virtio device via the modern PCI interface.
+pub struct ModernPciTransport {
+    /// The mapped BARs that contain configuration structures, along with their BAR index.
+    bars: Vec<(u8, MappedPages)>,
+    common: Region,
+    notify: Region,
+    notify_off_multiplier: u32,
+    isr: Region,
+    device: Option<Region>,
+    /// The offset into the notify region at which each set-up queue is notified, by queue index.
+    queue_notify_offsets: Vec<(u16, usize)>,
+}
+
+impl ModernPciTransport {
+    /// Resets the given virtio PCI device and acknowledges it,
+    /// returning a transport through which it can be further initialized.
+    ///
+    /// The device's features should then be negotiated, followed by setting up its queues
+    /// and finally marking the driver as ready via [`ModernPciTransport::driver_ok()`].
+    pub fn new(pci_device: &PciDevice) -> Result<ModernPciTransport, &'static str> {
+        if pci_device.vendor_id != crate::VIRTIO_VENDOR_ID {
+            return Err("virtio: PCI device is not a virtio device");
+        }
+
+        let mut bars: Vec<(u8, MappedPages)> = Vec::new();
+        let mut common = None;
+        let mut notify = None;
+        let mut isr = None;
+        let mut device = None;
+        for cap_offset in pci_device.find_pci_capabilities(PciCapability::VendorSpecific) {
+            let header = pci_device.pci_read_config_u32(cap_offset)?;
+            let cfg_type = (header >> 24) as u8;
+            let bar = pci_device.pci_read_config_u32(cap_offset + 4)? as u8;
+            if !matches!(cfg_type, COMMON_CFG | NOTIFY_CFG | ISR_CFG | DEVICE_CFG) || bar > 5 {
+                continue;
+            }
+            // Only the first capability of each type is used, as recommended by the specification.
+            let slot = match cfg_type {
+                COMMON_CFG => &mut common,
+                NOTIFY_CFG => &mut notify,
+                ISR_CFG => &mut isr,
+                _ => &mut device,
+            };
+            if slot.is_some() {
+                continue;
+            }
+
+            let bar_slot = match bars.iter().position(|(index, _)| *index == bar) {
+                Some(bar_slot) => bar_slot,
+                None => {
+                    bars.push((bar, pci_device.pci_map_bar_mem(bar as usize)?));
+                    bars.len() - 1
+                }
+            };
+            let region = Region {
+                bar: bar_slot,
+                offset: pci_device.pci_read_config_u32(cap_offset + 8)? as usize,
+                length: pci_device.pci_read_config_u32(cap_offset + 12)? as usize,
+            };
+            let notify_off_multiplier = if cfg_type == NOTIFY_CFG {
+                pci_device.pci_read_config_u32(cap_offset + 16)?
+            } else {
+                0
+            };
+            *slot = Some((region, notify_off_multiplier));
+        }
+
+        let (common, _) = common.ok_or("virtio: device has no common configuration capability")?;
+        let (notify, notify_off_multiplier) = notify.ok_or("virtio: device has no notification capability")?;
+        let (isr, _) = isr.ok_or("virtio: device has no ISR status capability")?;
+        if common.length < core::mem::size_of::<CommonConfig>() {
+            return Err("virtio: device's common configuration structure is too small");
+        }
+        pci_device.pci_set_command_bus_master_bit();
+
+        let mut transport = ModernPciTransport {
+            bars,
+            common,
+            notify,
+            notify_off_multiplier,
+            isr,
+            device: device.map(|(region, _)| region),
+            queue_notify_offsets: Vec::new(),
+        };
+        transport.reset()?;
+        transport.add_status(status::ACKNOWLEDGE)?;
+        transport.add_status(status::DRIVER)?;
+        debug!("virtio: found modern device {:#06X} at {}", pci_device.device_id, pci_device.location);
+        Ok(transport)
+    }
+
+    fn common_config(&mut self) -> Result<&mut CommonConfig, &'static str> {
+        let Region { bar, offset, .. } = self.common;
+        self.bars[bar].1.as_type_mut(offset)
+    }
+
+    /// Returns the mapped pages and the offset into them of the given byte range of the given `region`.
+    fn region_mut(&mut self, region: Region, offset: usize, len: usize) -> Result<(&mut MappedPages, usize), &'static str> {
+        if offset + len > region.length {
+            return Err("virtio: access is beyond the end of the configuration structure");
+        }
+        Ok((&mut self.bars[region.bar].1, region.offset + offset))
+    }
+
+    /// Resets the device, which also disables all of its queues,
+    /// and waits for the device to acknowledge the reset.
+    pub fn reset(&mut self) -> Result<(), &'static str> {
+        self.queue_notify_offsets.clear();
+        let common = self.common_config()?;
+        common.device_status.write(0);
+        // The device indicates that the reset is complete by reading back a status of zero.
+        for _ in 0 .. 1_000_000 {
+            if common.device_status.read() == 0 {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err("virtio: device didn't complete its reset")
+    }
+
+    /// Returns the current value of the device status register.
+    pub fn status(&mut self) -> Result<u8, &'static str> {
+        Ok(self.common_config()?.device_status.read())
+    }
+
+    /// Sets the given bits of the device status register, in addition to those already set.
+    pub fn add_status(&mut self, bits: u8) -> Result<(), &'static str> {
+        let common = self.common_config()?;
+        let current = common.device_status.read();
+        common.device_status.write(current | bits);
+        Ok(())
+    }
+
+    /// Offers the subset of the device's features that are also in `supported_features` to the device,
+    /// and returns that subset once the device has accepted it.
+    ///
+    /// [`VIRTIO_F_VERSION_1`] is always offered, and an error is returned if the device doesn't support it
+    /// or doesn't accept the offered features.
+    pub fn negotiate_features(&mut self, supported_features: u64) -> Result<u64, &'static str> {
+        let common = self.common_config()?;
+        common.device_feature_select.write(0);
+        let low = common.device_feature.read() as u64;
+        common.device_feature_select.write(1);
+        let high = common.device_feature.read() as u64;
+        let features = ((high << 32) | low) & (supported_features | VIRTIO_F_VERSION_1);
+        if features & VIRTIO_F_VERSION_1 == 0 {
+            return Err("virtio: device doesn't support the VIRTIO_F_VERSION_1 feature");
+        }
+
+        common.driver_feature_select.write(0);
+        common.driver_feature.write(features as u32);
+        common.driver_feature_select.write(1);
+        common.driver_feature.write((features >> 32) as u32);
+        self.add_status(status::FEATURES_OK)?;
+        if self.status()? & status::FEATURES_OK == 0 {
+            return Err("virtio: device didn't accept the negotiated features");
+        }
+        Ok(features)
+    }
+
+    /// Returns the number of queues that the device supports.
+    pub fn num_queues(&mut self) -> Result<u16, &'static str> {
+        Ok(self.common_config()?.num_queues.read())
+    }
+
+    /// Allocates a virtqueue for the device's queue with the given `index`, tells the device its address,
+    /// and enables it.
+    ///
+    /// The queue is no larger than `max_size` entries, or the device's maximum size if that's smaller.
+    pub fn setup_queue(&mut self, index: u16, max_size: u16) -> Result<Virtqueue, &'static str> {
+        let notify_off_multiplier = self.notify_off_multiplier as usize;
+        let common = self.common_config()?;
+        common.queue_select.write(index);
+        if common.queue_enable.read() != 0 {
+            return Err("virtio: queue is already in use");
+        }
+        let device_max_size = common.queue_size.read();
+        if device_max_size == 0 {
+            return Err("virtio: queue doesn't exist");
+        }
+        // The queue size must be a power of two for the split virtqueue layout.
+        let size = core::cmp::min(device_max_size, max_size.max(1));
+        let size = 1u16 << (u16::BITS - 1 - size.leading_zeros());
+        let queue = Virtqueue::new(index, size)?;
+
+        common.queue_size.write(size);
+        common.queue_desc.write(queue.physical_address().value() as u64);
+        common.queue_driver.write(queue.avail_physical_address().value() as u64);
+        common.queue_device.write(queue.used_physical_address().value() as u64);
+        let notify_offset = common.queue_notify_off.read() as usize * notify_off_multiplier;
+        // The queue's memory is owned by the returned `Virtqueue`.
+        // The caller must reset the device before dropping it, as with any DMA buffer.
+        common.queue_enable.write(1);
+
+        self.queue_notify_offsets.retain(|(i, _)| *i != index);
+        self.queue_notify_offsets.push((index, notify_offset));
+        Ok(queue)
+    }
+
+    /// Tells the device that new buffers are available in the given queue.
+    pub fn notify(&mut self, queue: &Virtqueue) -> Result<(), &'static str> {
+        let notify_offset = self.queue_notify_offsets.iter()
+            .find(|(index, _)| *index == queue.index())
+            .map(|(_, offset)| *offset)
+            .ok_or("virtio: queue wasn't set up through this transport")?;
+        let (mapped_pages, offset) = self.region_mut(self.notify, notify_offset, 2)?;
+        mapped_pages.as_type_mut::<Volatile<u16>>(offset)?.write(queue.index());
+        Ok(())
+    }
+
+    /// Marks the driver as ready, after which the device may start using its queues.
+    pub fn driver_ok(&mut self) -> Result<(), &'static str> {
+        self.add_status(status::DRIVER_OK)
+    }
+
+    /// Marks the device as failed, e.g., if it couldn't be initialized.
+    pub fn set_failed(&mut self) -> Result<(), &'static str> {
+        self.add_status(status::FAILED)
+    }
+
+    /// Reads and thereby clears the ISR status register.
+    ///
+    /// Bit 0 indicates a used buffer notification, and bit 1 indicates a configuration change.
+    pub fn read_isr(&mut self) -> Result<u8, &'static str> {
+        let (mapped_pages, offset) = self.region_mut(self.isr, 0, 1)?;
+        Ok(mapped_pages.as_type::<ReadOnly<u8>>(offset)?.read())
+    }
+
+    /// Returns the device-specific configuration region, if the device has one.
+    fn device_region(&self) -> Result<Region, &'static str> {
+        self.device.ok_or("virtio: device has no device-specific configuration")
+    }
+
+    /// Reads the byte at the given offset into the device-specific configuration.
+    pub fn read_config_u8(&mut self, offset: usize) -> Result<u8, &'static str> {
+        let (mapped_pages, offset) = self.region_mut(self.device_region()?, offset, 1)?;
+        Ok(mapped_pages.as_type::<ReadOnly<u8>>(offset)?.read())
+    }
+
+    /// Writes the byte at the given offset into the device-specific configuration.
+    pub fn write_config_u8(&mut self, offset: usize, value: u8) -> Result<(), &'static str> {
+        let (mapped_pages, offset) = self.region_mut(self.device_region()?, offset, 1)?;
+        mapped_pages.as_type_mut::<Volatile<u8>>(offset)?.write(value);
+        Ok(())
+    }
+
+    /// Reads the 32-bit value at the given offset into the device-specific configuration,
+    /// which must be 4-byte aligned.
+    pub fn read_config_u32(&mut self, offset: usize) -> Result<u32, &'static str> {
+        let (mapped_pages, offset) = self.region_mut(self.device_region()?, offset, 4)?;
+        Ok(mapped_pages.as_type::<ReadOnly<u32>>(offset)?.read())
+    }
+
+    /// Returns the configuration generation, which the device changes whenever
+    /// the device-specific configuration changes.
+    ///
+    /// Multi-field configuration reads should be repeated if this changes while reading them.
+    pub fn config_generation(&mut self) -> Result<u8, &'static str> {
+        Ok(self.common_config()?.config_generation.read())
+    }
+}
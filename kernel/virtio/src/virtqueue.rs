@@ -0,0 +1,226 @@
+//! Split virtqueues in the memory layout required by the legacy virtio interface.
+//!
+//! The modern interface accepts the same layout, as it allows each part of the queue to be placed separately.
+
+use core::sync::atomic::{fence, Ordering};
+use memory::{MappedPages, PhysicalAddress, DMA_FLAGS, create_contiguous_mapping};
+use volatile::Volatile;
+use zerocopy::FromBytes;
+
+/// The alignment of the used ring required by the legacy interface.
+const LEGACY_QUEUE_ALIGNMENT: usize = 4096;
+
+/// This descriptor continues via the `next` field.
+const DESC_F_NEXT: u16 = 1;
+/// This descriptor's buffer is write-only for the device (otherwise it's read-only).
+const DESC_F_WRITE: u16 = 2;
+
+#[derive(FromBytes)]
+#[repr(C)]
+struct Descriptor {
+    addr:  Volatile<u64>,
+    len:   Volatile<u32>,
+    flags: Volatile<u16>,
+    next:  Volatile<u16>,
+}
+
+#[derive(FromBytes)]
+#[repr(C)]
+struct UsedElement {
+    /// The index of the head of the descriptor chain that was used.
+    id:  Volatile<u32>,
+    /// The number of bytes written into the buffers of the descriptor chain.
+    len: Volatile<u32>,
+}
+
+/// A segment of a buffer passed to the device, which must be in physically-contiguous memory.
+#[derive(Clone, Copy, Debug)]
+pub struct BufferSegment {
+    pub phys_addr: PhysicalAddress,
+    pub len: u32,
+    /// Whether the device writes into (rather than reads from) this segment.
+    pub device_writable: bool,
+}
+
+/// A split virtqueue, consisting of a descriptor table, an available ring, and a used ring.
+///
+/// The driver offers buffers to the device via [`Virtqueue::add()`] and then notifies the device
+/// through its transport; once the device has processed a buffer, it is returned by [`Virtqueue::pop_used()`].
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    memory: MappedPages,
+    phys_addr: PhysicalAddress,
+    /// Byte offset of the available ring within `memory`.
+    avail_offset: usize,
+    /// Byte offset of the used ring within `memory`.
+    used_offset: usize,
+    /// The head of the list of free descriptors, which are linked via their `next` fields.
+    free_head: u16,
+    num_free: u16,
+    /// Our copy of the available ring's index, i.e., the number of buffers ever added.
+    avail_idx: u16,
+    /// The used ring index up to which used buffers have been popped.
+    last_used_idx: u16,
+}
+
+impl Virtqueue {
+    /// Allocates a new virtqueue with `size` entries for the queue with the given `index`.
+    pub(crate) fn new(index: u16, size: u16) -> Result<Virtqueue, &'static str> {
+        if size == 0 || !size.is_power_of_two() {
+            return Err("virtio: virtqueue size must be a nonzero power of two");
+        }
+        let n = size as usize;
+        let avail_offset = core::mem::size_of::<Descriptor>() * n;
+        let used_offset = align_up(avail_offset + 2 * (3 + n), LEGACY_QUEUE_ALIGNMENT);
+        let total_size = used_offset + align_up(2 * 3 + core::mem::size_of::<UsedElement>() * n, LEGACY_QUEUE_ALIGNMENT);
+        let (mut memory, phys_addr) = create_contiguous_mapping(total_size, DMA_FLAGS)?;
+        memory.as_slice_mut::<u8>(0, total_size)?.fill(0);
+
+        let mut queue = Virtqueue {
+            index,
+            size,
+            memory,
+            phys_addr,
+            avail_offset,
+            used_offset,
+            free_head: 0,
+            num_free: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+        };
+        let descriptors = queue.descriptors()?;
+        for (i, desc) in descriptors.iter_mut().enumerate() {
+            desc.next.write((i + 1) as u16);
+        }
+        Ok(queue)
+    }
+
+    /// Returns the index of this queue within its device.
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// Returns the number of entries in this queue.
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Returns the starting physical address of this queue's memory.
+    pub fn physical_address(&self) -> PhysicalAddress {
+        self.phys_addr
+    }
+
+    /// Returns the physical address of this queue's available ring, a.k.a. the driver area.
+    pub(crate) fn avail_physical_address(&self) -> PhysicalAddress {
+        self.phys_addr + self.avail_offset
+    }
+
+    /// Returns the physical address of this queue's used ring, a.k.a. the device area.
+    pub(crate) fn used_physical_address(&self) -> PhysicalAddress {
+        self.phys_addr + self.used_offset
+    }
+
+    /// Returns the number of free descriptors, i.e., the maximum number of segments that can currently be added.
+    pub fn num_free(&self) -> u16 {
+        self.num_free
+    }
+
+    /// Offers a buffer consisting of the given segments to the device.
+    ///
+    /// The device isn't aware of the new buffer until it is notified via the transport.
+    /// Returns the ID of the buffer, which is returned by [`Virtqueue::pop_used()`] once the device has used it.
+    pub fn add(&mut self, segments: &[BufferSegment]) -> Result<u16, &'static str> {
+        if segments.is_empty() {
+            return Err("virtio: can't add a buffer with no segments");
+        }
+        if segments.len() > self.num_free as usize {
+            return Err("virtio: not enough free descriptors in virtqueue");
+        }
+
+        let head = self.free_head;
+        let mut idx = head;
+        {
+            let descriptors = self.descriptors()?;
+            for (i, segment) in segments.iter().enumerate() {
+                let desc = &mut descriptors[idx as usize];
+                let next_free = desc.next.read();
+                let mut flags = 0;
+                if segment.device_writable {
+                    flags |= DESC_F_WRITE;
+                }
+                if i + 1 < segments.len() {
+                    flags |= DESC_F_NEXT;
+                }
+                desc.addr.write(segment.phys_addr.value() as u64);
+                desc.len.write(segment.len);
+                desc.flags.write(flags);
+                // A descriptor's `next` field already links it to the next free descriptor,
+                // so it also links the segments of this buffer together.
+                if i + 1 == segments.len() {
+                    self.free_head = next_free;
+                } else {
+                    idx = next_free;
+                }
+            }
+        }
+        self.num_free -= segments.len() as u16;
+
+        let slot = 2 + (self.avail_idx % self.size) as usize;
+        let avail_idx = self.avail_idx.wrapping_add(1);
+        let avail_ring = self.avail_ring()?;
+        avail_ring[slot].write(head);
+        // The device must see the new ring entry before it sees the updated index.
+        fence(Ordering::SeqCst);
+        avail_ring[1].write(avail_idx);
+        self.avail_idx = avail_idx;
+        Ok(head)
+    }
+
+    /// Returns the ID of the next buffer that the device has finished using,
+    /// along with the number of bytes the device wrote into it, if any buffer has been used.
+    ///
+    /// The buffer's descriptors are freed, such that they can be reused for new buffers.
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let used_offset = self.used_offset;
+        let used_idx = self.memory.as_slice::<Volatile<u16>>(used_offset, 2).ok()?[1].read();
+        if used_idx == self.last_used_idx {
+            return None;
+        }
+        // Read the used element only after seeing the updated index.
+        fence(Ordering::SeqCst);
+        let slot = (self.last_used_idx % self.size) as usize;
+        let (id, len) = {
+            let elements = self.memory.as_slice::<UsedElement>(used_offset + 4, self.size as usize).ok()?;
+            (elements[slot].id.read() as u16, elements[slot].len.read())
+        };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+        // Return the buffer's descriptor chain to the free list.
+        let free_head = self.free_head;
+        let descriptors = self.descriptors().ok()?;
+        let mut idx = id;
+        let mut freed = 1;
+        while descriptors[idx as usize].flags.read() & DESC_F_NEXT != 0 {
+            idx = descriptors[idx as usize].next.read();
+            freed += 1;
+        }
+        descriptors[idx as usize].next.write(free_head);
+        self.free_head = id;
+        self.num_free += freed;
+        Some((id, len))
+    }
+
+    fn descriptors(&mut self) -> Result<&mut [Descriptor], &'static str> {
+        self.memory.as_slice_mut(0, self.size as usize)
+    }
+
+    /// Returns the available ring as a slice of `u16`s: `flags`, `idx`, `ring[size]`, and `used_event`.
+    fn avail_ring(&mut self) -> Result<&mut [Volatile<u16>], &'static str> {
+        self.memory.as_slice_mut(self.avail_offset, 3 + self.size as usize)
+    }
+}
+
+const fn align_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) & !(alignment - 1)
+}
@@ -0,0 +1,300 @@
+//! A driver for the virtio memory balloon device, as offered by QEMU/KVM via `-device virtio-balloon-pci`.
+//!
+//! The host sets a target size for the balloon, in 4KiB pages.
+//! When the target grows, the balloon *inflates*: the driver allocates frames
+//! and hands them to the host, which can then reclaim the guest memory backing them.
+//! When the target shrinks, the balloon *deflates*: the driver tells the host
+//! which frames it is taking back and then frees them.
+//!
+//! The balloon never causes an allocation to fail.
+//! When no free frames are left while inflating, the driver first reclaims memory from caches
+//! via [`memory_pressure::reclaim()`], and inflation only stops once nothing more can be reclaimed.
+//! If the host permits it (the `DEFLATE_ON_OOM` feature), the balloon also registers a [`memory_pressure`] shrinker
+//! that deflates it when the frame allocator runs out of memory.
+//! After deflating due to memory pressure, the driver waits a while before inflating again.
+//!
+//! The driver is a polling task that checks the host's target every [`POLL_INTERVAL`];
+//! it doesn't use configuration change interrupts.
+//! After handing a batch of frames to the device, it blocks until the device's used-buffer interrupt.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use kernel_config::memory::PAGE_SIZE;
+use log::{debug, error, info};
+use memory::{AllocatedFrames, MappedPages, PhysicalAddress, DMA_FLAGS, create_contiguous_mapping};
+use pci::PciDevice;
+use spin::{Mutex, Once};
+use sync_irq::DisableIrq;
+use time::Duration;
+use virtio::{BufferSegment, LegacyPciTransport, Virtqueue};
+use wait_queue::WaitQueue;
+
+/// The PCI device ID of the transitional virtio balloon device.
+pub const BALLOON_DEV: u16 = virtio::transitional_device_ids::BALLOON;
+
+/// How often the driver checks the host's target balloon size.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The number of polls during which the balloon doesn't inflate after deflating due to memory pressure.
+const OOM_BACKOFF_POLLS: usize = 30;
+
+/// The host must be told about deflated pages before the guest uses them.
+const VIRTIO_BALLOON_F_MUST_TELL_HOST: u32 = 1 << 0;
+/// The guest may deflate the balloon when it runs out of memory.
+const VIRTIO_BALLOON_F_DEFLATE_ON_OOM: u32 = 1 << 2;
+
+/// Offset of the host's target number of pages in the device configuration.
+const CONFIG_NUM_PAGES: u16 = 0;
+/// Offset of the guest's actual number of pages in the device configuration.
+const CONFIG_ACTUAL: u16 = 4;
+
+const INFLATE_QUEUE: u16 = 0;
+const DEFLATE_QUEUE: u16 = 1;
+
+/// The balloon always works in terms of 4KiB pages, regardless of the guest's page size.
+const BALLOON_PFN_SHIFT: usize = 12;
+/// The maximum number of page frame numbers sent to the device in one buffer.
+const PFNS_PER_BATCH: usize = 256;
+
+static BALLOON: Once<Mutex<Balloon>> = Once::new();
+
+/// The task waiting for the device to use a buffer, which is woken by the device's interrupt.
+static USED_BUFFER_WAITERS: WaitQueue<DisableIrq> = WaitQueue::with_name("virtio_balloon used buffer");
+
+/// The number of remaining polls during which the balloon won't inflate.
+static OOM_BACKOFF: AtomicUsize = AtomicUsize::new(0);
+
+/// The state of the balloon device.
+struct Balloon {
+    pci_device: &'static PciDevice,
+    transport: LegacyPciTransport,
+    inflate_queue: Virtqueue,
+    deflate_queue: Virtqueue,
+    /// The page frame numbers of a batch of frames are written here for the device to read.
+    pfn_buffer: MappedPages,
+    pfn_buffer_paddr: PhysicalAddress,
+    /// The frames currently in the balloon, i.e., given to the host.
+    frames: Vec<AllocatedFrames>,
+    deflate_on_oom: bool,
+}
+
+/// Initializes the given virtio balloon device and spawns the task that drives it.
+pub fn init(pci_device: &'static PciDevice) -> Result<(), &'static str> {
+    if BALLOON.is_completed() {
+        return Err("virtio_balloon: only one balloon device is supported");
+    }
+    let transport = LegacyPciTransport::new(pci_device)?;
+    let features = transport.negotiate_features(VIRTIO_BALLOON_F_MUST_TELL_HOST | VIRTIO_BALLOON_F_DEFLATE_ON_OOM);
+    let queues = transport.setup_queue(INFLATE_QUEUE).and_then(|inflate| {
+        transport.setup_queue(DEFLATE_QUEUE).map(|deflate| (inflate, deflate))
+    });
+    let (inflate_queue, deflate_queue) = match queues {
+        Ok(queues) => queues,
+        Err(e) => {
+            transport.set_failed();
+            return Err(e);
+        }
+    };
+    let (pfn_buffer, pfn_buffer_paddr) = create_contiguous_mapping(PAGE_SIZE, DMA_FLAGS)?;
+    // The PCI INTx handler masks the device's interrupt after waking us; `send_pfns()` unmasks it again.
+    let (waker, _blocker) = waker_generic::new_waker(|| USED_BUFFER_WAITERS.notify_all());
+    if let Err(e) = pci_device.set_intx_waker(waker) {
+        transport.set_failed();
+        return Err(e);
+    }
+    pci_device.pci_enable_intx(true);
+    transport.driver_ok();
+
+    let deflate_on_oom = features & VIRTIO_BALLOON_F_DEFLATE_ON_OOM != 0;
+    BALLOON.call_once(|| Mutex::new(Balloon {
+        pci_device,
+        transport,
+        inflate_queue,
+        deflate_queue,
+        pfn_buffer,
+        pfn_buffer_paddr,
+        frames: Vec::new(),
+        deflate_on_oom,
+    }));
+    if deflate_on_oom {
+        memory_pressure::register_shrinker("virtio_balloon", alloc::boxed::Box::new(deflate_on_memory_pressure))?;
+    }
+    info!("virtio_balloon: initialized device at {}, features {:#X}", pci_device.location, features);
+
+    spawn::new_task_builder(balloon_task, ())
+        .name("virtio_balloon".into())
+        .spawn()?;
+    Ok(())
+}
+
+/// Returns the number of frames currently in the balloon, or `None` if there is no balloon device.
+pub fn balloon_size_in_frames() -> Option<usize> {
+    BALLOON.get().map(|b| b.lock().frames.len())
+}
+
+/// Returns the host's target balloon size in frames, or `None` if there is no balloon device.
+pub fn target_size_in_frames() -> Option<usize> {
+    BALLOON.get().map(|b| b.lock().target())
+}
+
+/// The entry point for the balloon task, which moves the balloon towards the host's target size.
+fn balloon_task(_: ()) -> Result<(), &'static str> {
+    let balloon = BALLOON.get().ok_or("virtio_balloon: device not initialized")?;
+    loop {
+        let backoff = OOM_BACKOFF.load(Ordering::Relaxed);
+        if backoff > 0 {
+            OOM_BACKOFF.store(backoff - 1, Ordering::Relaxed);
+        }
+        if let Err(e) = balloon.lock().adjust(backoff == 0) {
+            error!("virtio_balloon: failed to adjust balloon size: {}", e);
+        }
+        sleep::sleep(POLL_INTERVAL).map_err(|_| "virtio_balloon: failed to sleep")?;
+    }
+}
+
+/// The [`memory_pressure`] shrinker, which deflates the balloon to free up frames.
+fn deflate_on_memory_pressure(num_frames: usize) -> usize {
+    // Deflating blocks until the device has processed each batch, which isn't possible if preemption is disabled.
+    if !preemption::preemption_enabled() {
+        return 0;
+    }
+    // Don't wait for the balloon task, as it may be the task that is allocating frames.
+    let Some(mut balloon) = BALLOON.get().and_then(Mutex::try_lock) else { return 0 };
+    if !balloon.deflate_on_oom {
+        return 0;
+    }
+    OOM_BACKOFF.store(OOM_BACKOFF_POLLS, Ordering::Relaxed);
+    match balloon.deflate(num_frames) {
+        Ok(freed) => freed,
+        Err(e) => {
+            error!("virtio_balloon: failed to deflate under memory pressure: {}", e);
+            0
+        }
+    }
+}
+
+impl Balloon {
+    fn target(&self) -> usize {
+        self.transport.read_config_u32(CONFIG_NUM_PAGES) as usize
+    }
+
+    /// Inflates or deflates the balloon towards the host's target size.
+    fn adjust(&mut self, allow_inflate: bool) -> Result<(), &'static str> {
+        let target = self.target();
+        let current = self.frames.len();
+        if target > current && allow_inflate {
+            let inflated = self.inflate(target - current)?;
+            if inflated < target - current {
+                debug!("virtio_balloon: only inflated by {} of {} frames due to lack of free memory",
+                    inflated, target - current
+                );
+            }
+        } else if target < current {
+            self.deflate(current - target)?;
+        }
+        Ok(())
+    }
+
+    /// Moves up to `num_frames` free frames into the balloon and returns how many were moved.
+    ///
+    /// This stops early, without returning an error, if no more frames can be allocated.
+    fn inflate(&mut self, num_frames: usize) -> Result<usize, &'static str> {
+        let mut inflated = 0;
+        while inflated < num_frames {
+            let batch_len = core::cmp::min(num_frames - inflated, PFNS_PER_BATCH);
+            let mut batch = Vec::with_capacity(batch_len);
+            while batch.len() < batch_len {
+                // Use the deferred allocation function, which doesn't invoke the shrinkers when there are no free frames,
+                // such that we can reclaim memory explicitly instead of failing the allocation.
+                match memory::allocate_frames_deferred(None, 1) {
+                    Ok((frames, _action)) => batch.push(frames),
+                    // Our own shrinker doesn't deflate the balloon here, as it can't obtain the balloon's lock.
+                    Err(_) if memory_pressure::reclaim(batch_len - batch.len()) > 0 => continue,
+                    Err(_) => break,
+                }
+            }
+            if batch.is_empty() {
+                break;
+            }
+            let ran_out_of_frames = batch.len() < batch_len;
+            let num_pfns = write_pfns(&mut self.pfn_buffer, batch.iter())?;
+            inflated += batch.len();
+            // Even if sending fails, the host may already have reclaimed these frames, so they must not be freed.
+            self.frames.append(&mut batch);
+            self.send_pfns(true, num_pfns)?;
+            self.update_actual();
+            if ran_out_of_frames {
+                break;
+            }
+        }
+        Ok(inflated)
+    }
+
+    /// Moves up to `num_frames` frames out of the balloon, freeing them, and returns how many were moved.
+    fn deflate(&mut self, num_frames: usize) -> Result<usize, &'static str> {
+        let mut deflated = 0;
+        while deflated < num_frames && !self.frames.is_empty() {
+            let batch_len = core::cmp::min(core::cmp::min(num_frames - deflated, PFNS_PER_BATCH), self.frames.len());
+            let batch_start = self.frames.len() - batch_len;
+            let num_pfns = write_pfns(&mut self.pfn_buffer, self.frames[batch_start..].iter())?;
+            // The host must be told before the guest uses the frames again.
+            self.send_pfns(false, num_pfns)?;
+            self.frames.truncate(batch_start);
+            deflated += batch_len;
+            self.update_actual();
+        }
+        if deflated > 0 {
+            debug!("virtio_balloon: deflated by {} frames", deflated);
+        }
+        Ok(deflated)
+    }
+
+    /// Sends the first `num_pfns` page frame numbers in the PFN buffer to the inflate or deflate queue,
+    /// and blocks until the device has processed them.
+    fn send_pfns(&mut self, inflate: bool, num_pfns: usize) -> Result<(), &'static str> {
+        let segment = BufferSegment {
+            phys_addr: self.pfn_buffer_paddr,
+            len: (num_pfns * core::mem::size_of::<u32>()) as u32,
+            device_writable: false,
+        };
+        let queue = if inflate { &mut self.inflate_queue } else { &mut self.deflate_queue };
+        queue.add(&[segment])?;
+        self.transport.notify(queue);
+        let (transport, pci_device) = (&self.transport, self.pci_device);
+        USED_BUFFER_WAITERS.wait_until(|| {
+            queue.pop_used().or_else(|| {
+                // Acknowledge any pending interrupt, which deasserts it, and unmask the device's interrupt.
+                // Check again afterwards, as the device may have used the buffer while its interrupt was masked.
+                transport.read_isr();
+                pci_device.pci_enable_intx(true);
+                queue.pop_used()
+            })
+        });
+        Ok(())
+    }
+
+    /// Tells the host how many frames are currently in the balloon.
+    fn update_actual(&self) {
+        self.transport.write_config_u32(CONFIG_ACTUAL, self.frames.len() as u32);
+    }
+}
+
+/// Writes the page frame numbers of the given frames into the PFN buffer
+/// and returns how many were written.
+fn write_pfns<'f>(
+    pfn_buffer: &mut MappedPages,
+    frames: impl Iterator<Item = &'f AllocatedFrames>,
+) -> Result<usize, &'static str> {
+    let pfns = pfn_buffer.as_slice_mut::<u32>(0, PFNS_PER_BATCH)?;
+    let mut count = 0;
+    for (pfn, frames) in pfns.iter_mut().zip(frames) {
+        *pfn = u32::try_from(frames.start_address().value() >> BALLOON_PFN_SHIFT)
+            .map_err(|_| "virtio_balloon: frame is above the balloon's 44-bit limit")?;
+        count += 1;
+    }
+    Ok(count)
+}
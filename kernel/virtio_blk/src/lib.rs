@@ -0,0 +1,308 @@
+//! A driver for virtio block devices, as offered by QEMU/KVM via `-drive if=virtio`
+//! or `-device virtio-blk-pci`.
+//!
+//! Each request is a chain of three descriptors: a header that the device reads,
+//! the data buffer, and a status byte that the device writes once the request is complete.
+//! Data is transferred through a physically-contiguous bounce buffer of [`MAX_TRANSFER_SIZE`] bytes,
+//! so larger reads and writes are split into multiple requests.
+//!
+//! The driver waits for each request by polling its virtqueue rather than using interrupts,
+//! which matches the synchronous [`BlockReader`] and [`BlockWriter`] interfaces.
+//! If the device doesn't complete a request in time, it is reset and the drive is marked as failed,
+//! because the device may still access that request's buffers at any later point.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, sync::Arc};
+use io::{BlockIo, BlockReader, BlockWriter, IoError, KnownLength};
+use log::{error, info, warn};
+use memory::{MappedPages, PhysicalAddress, DMA_FLAGS, create_contiguous_mapping};
+use pci::PciDevice;
+use spin::Mutex;
+use storage_device::{StorageController, StorageDevice, StorageDeviceRef};
+use virtio::{BufferSegment, ModernPciTransport, Virtqueue};
+
+/// The PCI device IDs of virtio block devices, both transitional and modern.
+pub const BLOCK_DEVS: [u16; 2] = [virtio::transitional_device_ids::BLOCK, virtio::modern_device_ids::BLOCK];
+
+/// The size of a sector, which is the unit of all offsets and sizes in virtio block requests.
+pub const SECTOR_SIZE: usize = 512;
+/// The maximum number of bytes transferred by a single request.
+pub const MAX_TRANSFER_SIZE: usize = 64 * 1024;
+
+/// The device is read-only.
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+/// The device supports the flush command.
+const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+
+/// Offset of the device's capacity in sectors, a `u64`, in the device configuration.
+const CONFIG_CAPACITY: usize = 0;
+
+const REQUEST_QUEUE: u16 = 0;
+/// A request uses at most 3 descriptors, so a small queue suffices for one request at a time.
+const MAX_QUEUE_SIZE: u16 = 16;
+
+// Request types.
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+
+// Values of the status byte written by the device.
+const VIRTIO_BLK_S_OK: u8 = 0;
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+/// The size of a request header: a `u32` type, a reserved `u32`, and a `u64` sector.
+const HEADER_SIZE: usize = 16;
+/// The offset of the status byte within the request memory, right after the header.
+const STATUS_OFFSET: usize = HEADER_SIZE;
+
+/// How many times to poll the virtqueue for a completed request before giving up.
+const MAX_POLLS: usize = 100_000_000;
+
+/// Initializes the given virtio block device and returns a storage controller
+/// through which its single drive can be accessed.
+pub fn init(pci_device: &PciDevice) -> Result<VirtioBlkController, &'static str> {
+    let mut transport = ModernPciTransport::new(pci_device)?;
+    match VirtioBlkDrive::negotiate(&mut transport) {
+        Ok((queue, read_only, flush)) => {
+            let drive = VirtioBlkDrive::start(transport, queue, read_only, flush)?;
+            info!("virtio_blk: initialized {}drive with {} sectors at {}",
+                if read_only { "read-only " } else { "" }, drive.capacity, pci_device.location,
+            );
+            Ok(VirtioBlkController { drive: Arc::new(Mutex::new(drive)) })
+        }
+        Err(e) => {
+            let _ = transport.set_failed();
+            Err(e)
+        }
+    }
+}
+
+/// A storage controller for a virtio block device, which has exactly one drive.
+pub struct VirtioBlkController {
+    drive: Arc<Mutex<VirtioBlkDrive>>,
+}
+
+impl VirtioBlkController {
+    /// Returns the drive of this virtio block device.
+    pub fn drive(&self) -> &Arc<Mutex<VirtioBlkDrive>> {
+        &self.drive
+    }
+}
+
+impl StorageController for VirtioBlkController {
+    fn devices<'c>(&'c self) -> Box<(dyn Iterator<Item = StorageDeviceRef> + 'c)> {
+        Box::new(core::iter::once(Arc::clone(&self.drive) as StorageDeviceRef))
+    }
+}
+
+/// A drive backed by a virtio block device.
+pub struct VirtioBlkDrive {
+    transport: ModernPciTransport,
+    queue: Virtqueue,
+    /// The size of the drive in sectors.
+    capacity: u64,
+    read_only: bool,
+    /// Whether the device supports flushing its write cache.
+    flush: bool,
+    /// Holds the request header followed by the status byte.
+    request: MappedPages,
+    request_paddr: PhysicalAddress,
+    /// The buffer through which all data is transferred to and from the device.
+    bounce_buffer: MappedPages,
+    bounce_buffer_paddr: PhysicalAddress,
+    /// Whether the device timed out on a request, after which no further requests are submitted.
+    failed: bool,
+}
+
+impl VirtioBlkDrive {
+    /// Negotiates the device's features and sets up its request queue.
+    ///
+    /// Returns the queue and whether the device is read-only and supports flushing.
+    fn negotiate(transport: &mut ModernPciTransport) -> Result<(Virtqueue, bool, bool), &'static str> {
+        let features = transport.negotiate_features(VIRTIO_BLK_F_RO | VIRTIO_BLK_F_FLUSH)?;
+        let queue = transport.setup_queue(REQUEST_QUEUE, MAX_QUEUE_SIZE)?;
+        if queue.size() < 3 {
+            return Err("virtio_blk: request queue is too small");
+        }
+        Ok((queue, features & VIRTIO_BLK_F_RO != 0, features & VIRTIO_BLK_F_FLUSH != 0))
+    }
+
+    /// Allocates the request and bounce buffers and marks the driver as ready.
+    fn start(
+        mut transport: ModernPciTransport,
+        queue: Virtqueue,
+        read_only: bool,
+        flush: bool,
+    ) -> Result<VirtioBlkDrive, &'static str> {
+        let buffers = read_capacity(&mut transport).and_then(|capacity| {
+            let (request, request_paddr) = create_contiguous_mapping(HEADER_SIZE + 1, DMA_FLAGS)?;
+            let (bounce_buffer, bounce_buffer_paddr) = create_contiguous_mapping(MAX_TRANSFER_SIZE, DMA_FLAGS)?;
+            Ok((capacity, request, request_paddr, bounce_buffer, bounce_buffer_paddr))
+        });
+        let (capacity, request, request_paddr, bounce_buffer, bounce_buffer_paddr) = match buffers {
+            Ok(buffers) => buffers,
+            Err(e) => {
+                let _ = transport.set_failed();
+                return Err(e);
+            }
+        };
+        transport.driver_ok()?;
+        Ok(VirtioBlkDrive {
+            transport,
+            queue,
+            capacity,
+            read_only,
+            flush,
+            request,
+            request_paddr,
+            bounce_buffer,
+            bounce_buffer_paddr,
+            failed: false,
+        })
+    }
+
+    /// Returns `true` if the device doesn't allow writes.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Submits a request of the given type and waits for the device to complete it.
+    ///
+    /// The first `data_len` bytes of the bounce buffer are transferred,
+    /// which the device writes into if `request_type` is `VIRTIO_BLK_T_IN`.
+    fn do_request(&mut self, request_type: u32, sector: u64, data_len: usize) -> Result<(), IoError> {
+        if self.failed {
+            return Err(IoError::Other("virtio_blk: drive has failed"));
+        }
+        {
+            let header = self.request.as_slice_mut::<u8>(0, HEADER_SIZE + 1).map_err(IoError::Other)?;
+            header[0..4].copy_from_slice(&request_type.to_le_bytes());
+            header[4..8].fill(0);
+            header[8..16].copy_from_slice(&sector.to_le_bytes());
+            header[STATUS_OFFSET] = 0xFF;
+        }
+
+        let header = BufferSegment { phys_addr: self.request_paddr, len: HEADER_SIZE as u32, device_writable: false };
+        let status = BufferSegment { phys_addr: self.request_paddr + STATUS_OFFSET, len: 1, device_writable: true };
+        let added = if data_len == 0 {
+            self.queue.add(&[header, status])
+        } else {
+            let data = BufferSegment {
+                phys_addr: self.bounce_buffer_paddr,
+                len: data_len as u32,
+                device_writable: request_type == VIRTIO_BLK_T_IN,
+            };
+            self.queue.add(&[header, data, status])
+        };
+        added.map_err(IoError::Other)?;
+        self.transport.notify(&self.queue).map_err(IoError::Other)?;
+
+        let mut completed = false;
+        for _ in 0 .. MAX_POLLS {
+            if self.queue.pop_used().is_some() {
+                completed = true;
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        if !completed {
+            warn!("virtio_blk: device didn't complete a request for sector {}, marking the drive as failed", sector);
+            self.fail();
+            return Err(IoError::TimedOut);
+        }
+
+        match self.request.as_slice::<u8>(STATUS_OFFSET, 1).map_err(IoError::Other)?[0] {
+            VIRTIO_BLK_S_OK => Ok(()),
+            VIRTIO_BLK_S_UNSUPP => Err(IoError::Other("virtio_blk: device doesn't support the request")),
+            _ => Err(IoError::Other("virtio_blk: device failed the request")),
+        }
+    }
+
+    /// Stops the device from using the request still in flight and marks the drive as failed.
+    ///
+    /// The request and bounce buffers are never reused afterwards,
+    /// so a late completion can't be credited to a different request.
+    fn fail(&mut self) {
+        self.failed = true;
+        if let Err(e) = self.transport.reset() {
+            error!("virtio_blk: failed to reset the device: {}", e);
+        }
+        let _ = self.transport.set_failed();
+    }
+
+    /// Checks that `buffer_len` bytes starting at `block_offset` are within the drive,
+    /// returning the number of sectors.
+    fn check_bounds(&self, buffer_len: usize, block_offset: usize) -> Result<usize, IoError> {
+        if buffer_len % SECTOR_SIZE != 0 {
+            return Err(IoError::InvalidInput);
+        }
+        let num_sectors = buffer_len / SECTOR_SIZE;
+        match block_offset.checked_add(num_sectors) {
+            Some(end) if end as u64 <= self.capacity => Ok(num_sectors),
+            _ => Err(IoError::InvalidInput),
+        }
+    }
+}
+
+/// Reads the device's capacity in sectors,
+/// retrying if the configuration changes while reading its two halves.
+fn read_capacity(transport: &mut ModernPciTransport) -> Result<u64, &'static str> {
+    loop {
+        let generation = transport.config_generation()?;
+        let low = transport.read_config_u32(CONFIG_CAPACITY)? as u64;
+        let high = transport.read_config_u32(CONFIG_CAPACITY + 4)? as u64;
+        if transport.config_generation()? == generation {
+            return Ok((high << 32) | low);
+        }
+    }
+}
+
+impl StorageDevice for VirtioBlkDrive {
+    fn size_in_blocks(&self) -> usize {
+        self.capacity as usize
+    }
+}
+impl BlockIo for VirtioBlkDrive {
+    fn block_size(&self) -> usize { SECTOR_SIZE }
+}
+impl KnownLength for VirtioBlkDrive {
+    fn len(&self) -> usize { self.block_size() * self.size_in_blocks() }
+}
+impl BlockReader for VirtioBlkDrive {
+    fn read_blocks(&mut self, buffer: &mut [u8], block_offset: usize) -> Result<usize, IoError> {
+        let num_sectors = self.check_bounds(buffer.len(), block_offset)?;
+        let mut sector = block_offset;
+        for chunk in buffer.chunks_mut(MAX_TRANSFER_SIZE) {
+            self.do_request(VIRTIO_BLK_T_IN, sector as u64, chunk.len())?;
+            chunk.copy_from_slice(self.bounce_buffer.as_slice(0, chunk.len()).map_err(IoError::Other)?);
+            sector += chunk.len() / SECTOR_SIZE;
+        }
+        Ok(num_sectors)
+    }
+}
+impl BlockWriter for VirtioBlkDrive {
+    fn write_blocks(&mut self, buffer: &[u8], block_offset: usize) -> Result<usize, IoError> {
+        if self.read_only {
+            return Err(IoError::Other("virtio_blk: drive is read-only"));
+        }
+        let num_sectors = self.check_bounds(buffer.len(), block_offset)?;
+        let mut sector = block_offset;
+        for chunk in buffer.chunks(MAX_TRANSFER_SIZE) {
+            self.bounce_buffer.as_slice_mut(0, chunk.len()).map_err(IoError::Other)?.copy_from_slice(chunk);
+            self.do_request(VIRTIO_BLK_T_OUT, sector as u64, chunk.len())?;
+            sector += chunk.len() / SECTOR_SIZE;
+        }
+        Ok(num_sectors)
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        if self.flush {
+            self.do_request(VIRTIO_BLK_T_FLUSH, 0, 0)
+        } else {
+            Ok(())
+        }
+    }
+}
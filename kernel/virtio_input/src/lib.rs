@@ -0,0 +1,326 @@
+//! A driver for virtio-input pointing devices, as offered by QEMU via `-device virtio-tablet-pci`
+//! or `-device virtio-mouse-pci`.
+//!
+//! A tablet reports the absolute position of the pointer, which QEMU derives from the host's mouse,
+//! such that the guest's cursor tracks the host's cursor exactly.
+//! Its events are delivered as [`Event::MouseAbsoluteEvent`]s, whose positions are normalized
+//! to the device's reported axis ranges.
+//! A virtio mouse reports relative movements, which are delivered as [`Event::MouseMovementEvent`]s,
+//! just like those from a PS/2 mouse.
+//!
+//! virtio-input devices have no legacy interface, so this driver uses the [`ModernPciTransport`].
+//! Other kinds of virtio-input devices, e.g., keyboards, are not yet supported.
+//!
+//! Each device is driven by a task that polls its event queue every [`POLL_INTERVAL`];
+//! the driver doesn't use interrupts.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{format, string::String, vec::Vec};
+use event_types::Event;
+use input_router::{InputDeviceKind, InputSource};
+use log::{error, info, warn};
+use memory::{MappedPages, PhysicalAddress, DMA_FLAGS, create_contiguous_mapping};
+use mouse_data::{MouseAbsoluteEvent, MouseButtons, MouseEvent, MouseMovementRelative, MousePositionAbsolute};
+use mpmc::Queue;
+use pci::PciDevice;
+use time::Duration;
+use virtio::{BufferSegment, ModernPciTransport, Virtqueue};
+
+/// The PCI device ID of virtio-input devices.
+pub const INPUT_DEV: u16 = virtio::modern_device_ids::INPUT;
+
+/// How often the driver checks a device's event queue for new events.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// The queue through which the device sends input events.
+const EVENT_QUEUE: u16 = 0;
+/// The maximum number of event buffers offered to the device at once.
+const MAX_EVENT_BUFFERS: u16 = 64;
+
+// Offsets of the fields of the device configuration.
+const CONFIG_SELECT: usize = 0;
+const CONFIG_SUBSEL: usize = 1;
+const CONFIG_SIZE:   usize = 2;
+/// The offset of the union of all possible configuration values, which is selected via `select` and `subsel`.
+const CONFIG_DATA:   usize = 8;
+
+// Values of `select` in the device configuration.
+const CFG_ID_NAME:  u8 = 0x01;
+const CFG_EV_BITS:  u8 = 0x11;
+const CFG_ABS_INFO: u8 = 0x12;
+
+// Event types and codes, which are the same as those of Linux's evdev interface.
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+const SYN_REPORT: u16 = 0x00;
+const BTN_LEFT:   u16 = 0x110;
+const BTN_RIGHT:  u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+const BTN_SIDE:   u16 = 0x113;
+const BTN_EXTRA:  u16 = 0x114;
+const REL_X:     u16 = 0x00;
+const REL_Y:     u16 = 0x01;
+const REL_WHEEL: u16 = 0x08;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+
+/// The size of a `virtio_input_event`: a `u16` type, a `u16` code, and a `u32` value.
+const EVENT_SIZE: usize = 8;
+
+/// Initializes the given virtio-input device and spawns the task that drives it,
+/// whose events are delivered to `default_consumer` unless routed elsewhere by the [`input_router`].
+///
+/// Devices other than pointing devices are ignored.
+pub fn init(pci_device: &'static PciDevice, default_consumer: Queue<Event>) -> Result<(), &'static str> {
+    let mut transport = ModernPciTransport::new(pci_device)?;
+    match detect_pointer_mode(&mut transport) {
+        Ok(Some(mode)) => {
+            let name = device_name(&mut transport).unwrap_or_else(|_| String::from("unknown"));
+            let device = mode.start(transport, &name, default_consumer)?;
+            info!("virtio_input: initialized {} device {:?} at {}",
+                if device.absolute.is_some() { "absolute pointer" } else { "relative pointer" },
+                name, pci_device.location,
+            );
+            spawn::new_task_builder(input_task, device)
+                .name(format!("virtio_input_{}", pci_device.location))
+                .spawn()?;
+            Ok(())
+        }
+        Ok(None) => {
+            warn!("virtio_input: ignoring device at {}, which is not a pointing device", pci_device.location);
+            transport.reset()
+        }
+        Err(e) => {
+            let _ = transport.set_failed();
+            Err(e)
+        }
+    }
+}
+
+/// The entry point for a device's task, which delivers the device's events as they arrive.
+fn input_task(mut device: VirtioInput) -> Result<(), &'static str> {
+    loop {
+        if let Err(e) = device.poll() {
+            error!("virtio_input: failed to handle events: {}", e);
+        }
+        sleep::sleep(POLL_INTERVAL).map_err(|_| "virtio_input: failed to sleep")?;
+    }
+}
+
+/// Negotiates the device's features and determines what kind of pointing device it is,
+/// returning `None` if it isn't one.
+fn detect_pointer_mode(transport: &mut ModernPciTransport) -> Result<Option<PointerMode>, &'static str> {
+    transport.negotiate_features(0)?;
+    if supports_event(transport, EV_ABS, ABS_X)? && supports_event(transport, EV_ABS, ABS_Y)? {
+        let absolute = AbsoluteAxes {
+            x: abs_range(transport, ABS_X)?,
+            y: abs_range(transport, ABS_Y)?,
+        };
+        return Ok(Some(PointerMode { absolute: Some(absolute) }));
+    }
+    if supports_event(transport, EV_REL, REL_X)? && supports_event(transport, EV_REL, REL_Y)? {
+        return Ok(Some(PointerMode { absolute: None }));
+    }
+    Ok(None)
+}
+
+/// Selects the given device configuration value and returns its size in bytes.
+fn select_config(transport: &mut ModernPciTransport, select: u8, subsel: u8) -> Result<u8, &'static str> {
+    transport.write_config_u8(CONFIG_SELECT, select)?;
+    transport.write_config_u8(CONFIG_SUBSEL, subsel)?;
+    transport.read_config_u8(CONFIG_SIZE)
+}
+
+/// Returns the device's name, as reported by the host.
+fn device_name(transport: &mut ModernPciTransport) -> Result<String, &'static str> {
+    let size = select_config(transport, CFG_ID_NAME, 0)?;
+    let mut name = String::with_capacity(size as usize);
+    for i in 0 .. size as usize {
+        name.push(transport.read_config_u8(CONFIG_DATA + i)? as char);
+    }
+    Ok(name)
+}
+
+/// Returns whether the device supports the event with the given type and code.
+fn supports_event(transport: &mut ModernPciTransport, event_type: u16, code: u16) -> Result<bool, &'static str> {
+    let size = select_config(transport, CFG_EV_BITS, event_type as u8)?;
+    let byte = code as usize / 8;
+    if byte >= size as usize {
+        return Ok(false);
+    }
+    Ok(transport.read_config_u8(CONFIG_DATA + byte)? & (1 << (code % 8)) != 0)
+}
+
+/// Returns the minimum and maximum values of the given absolute axis.
+fn abs_range(transport: &mut ModernPciTransport, axis: u16) -> Result<(i32, i32), &'static str> {
+    if select_config(transport, CFG_ABS_INFO, axis as u8)? == 0 {
+        return Err("virtio_input: device didn't report the range of an absolute axis");
+    }
+    let min = transport.read_config_u32(CONFIG_DATA)? as i32;
+    let max = transport.read_config_u32(CONFIG_DATA + 4)? as i32;
+    Ok((min, max))
+}
+
+/// The ranges of an absolute pointing device's axes.
+#[derive(Clone, Copy, Debug)]
+struct AbsoluteAxes {
+    x: (i32, i32),
+    y: (i32, i32),
+}
+
+/// The kind of pointing device, as determined by [`detect_pointer_mode()`].
+struct PointerMode {
+    absolute: Option<AbsoluteAxes>,
+}
+
+impl PointerMode {
+    /// Sets up the device's event queue and marks the driver as ready.
+    fn start(
+        self,
+        mut transport: ModernPciTransport,
+        name: &str,
+        default_consumer: Queue<Event>,
+    ) -> Result<VirtioInput, &'static str> {
+        let setup = (|| -> Result<_, &'static str> {
+            let mut event_queue = transport.setup_queue(EVENT_QUEUE, MAX_EVENT_BUFFERS)?;
+            let num_buffers = event_queue.size() as usize;
+            let (event_buffers, event_buffers_paddr) = create_contiguous_mapping(num_buffers * EVENT_SIZE, DMA_FLAGS)?;
+            let mut slot_of_buffer_id = Vec::with_capacity(num_buffers);
+            slot_of_buffer_id.resize(num_buffers, 0);
+            for slot in 0 .. num_buffers {
+                let id = event_queue.add(&[event_buffer_segment(event_buffers_paddr, slot)])?;
+                slot_of_buffer_id[id as usize] = slot;
+            }
+            Ok((event_queue, event_buffers, event_buffers_paddr, slot_of_buffer_id))
+        })();
+        let (event_queue, event_buffers, event_buffers_paddr, slot_of_buffer_id) = match setup {
+            Ok(setup) => setup,
+            Err(e) => {
+                let _ = transport.set_failed();
+                return Err(e);
+            }
+        };
+        transport.driver_ok()?;
+        transport.notify(&event_queue)?;
+
+        let input = input_router::register_device(
+            &format!("virtio_input: {}", name),
+            InputDeviceKind::Mouse,
+            default_consumer,
+        );
+        Ok(VirtioInput {
+            transport,
+            event_queue,
+            event_buffers,
+            event_buffers_paddr,
+            slot_of_buffer_id,
+            input,
+            absolute: self.absolute,
+            buttons: MouseButtons::new(),
+            position: MousePositionAbsolute::new(0, 0),
+            relative: (0, 0),
+            scroll: 0,
+        })
+    }
+}
+
+/// The state of a virtio-input pointing device.
+struct VirtioInput {
+    transport: ModernPciTransport,
+    event_queue: Virtqueue,
+    /// The buffers into which the device writes events, one event per buffer.
+    event_buffers: MappedPages,
+    event_buffers_paddr: PhysicalAddress,
+    /// The slot in `event_buffers` of each buffer ID returned by the event queue.
+    slot_of_buffer_id: Vec<usize>,
+    input: InputSource,
+    /// The axis ranges of an absolute device, or `None` for a relative device.
+    absolute: Option<AbsoluteAxes>,
+    // The pointer state accumulated from the events received since the last `SYN_REPORT`.
+    buttons: MouseButtons,
+    position: MousePositionAbsolute,
+    relative: (i32, i32),
+    scroll: i32,
+}
+
+impl VirtioInput {
+    /// Handles all of the events that the device has sent since the last poll,
+    /// and offers their buffers back to the device.
+    fn poll(&mut self) -> Result<(), &'static str> {
+        let mut returned_buffers = false;
+        while let Some((id, len)) = self.event_queue.pop_used() {
+            let slot = *self.slot_of_buffer_id.get(id as usize).ok_or("virtio_input: device returned an invalid buffer ID")?;
+            if len as usize >= EVENT_SIZE {
+                let bytes = self.event_buffers.as_slice::<u8>(slot * EVENT_SIZE, EVENT_SIZE)?;
+                let event_type = u16::from_le_bytes([bytes[0], bytes[1]]);
+                let code = u16::from_le_bytes([bytes[2], bytes[3]]);
+                let value = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as i32;
+                self.handle_event(event_type, code, value)?;
+            }
+            let id = self.event_queue.add(&[event_buffer_segment(self.event_buffers_paddr, slot)])?;
+            self.slot_of_buffer_id[id as usize] = slot;
+            returned_buffers = true;
+        }
+        if returned_buffers {
+            self.transport.notify(&self.event_queue)?;
+        }
+        Ok(())
+    }
+
+    /// Accumulates the given event into the pointer state,
+    /// which is delivered as a single mouse event once the device sends a `SYN_REPORT`.
+    fn handle_event(&mut self, event_type: u16, code: u16, value: i32) -> Result<(), &'static str> {
+        match (event_type, code) {
+            (EV_KEY, BTN_LEFT)   => self.buttons.set_left(value != 0),
+            (EV_KEY, BTN_RIGHT)  => self.buttons.set_right(value != 0),
+            (EV_KEY, BTN_MIDDLE) => self.buttons.set_middle(value != 0),
+            (EV_KEY, BTN_SIDE)   => self.buttons.set_fourth(value != 0),
+            (EV_KEY, BTN_EXTRA)  => self.buttons.set_fifth(value != 0),
+            (EV_REL, REL_X)     => self.relative.0 = self.relative.0.saturating_add(value),
+            (EV_REL, REL_Y)     => self.relative.1 = self.relative.1.saturating_add(value),
+            (EV_REL, REL_WHEEL) => self.scroll = self.scroll.saturating_add(value),
+            (EV_ABS, ABS_X) => if let Some(axes) = self.absolute {
+                self.position.x = MousePositionAbsolute::normalize(value, axes.x.0, axes.x.1);
+            },
+            (EV_ABS, ABS_Y) => if let Some(axes) = self.absolute {
+                self.position.y = MousePositionAbsolute::normalize(value, axes.y.0, axes.y.1);
+            },
+            (EV_SYN, SYN_REPORT) => return self.report(),
+            _ => { }
+        }
+        Ok(())
+    }
+
+    /// Delivers the accumulated pointer state as a mouse event.
+    fn report(&mut self) -> Result<(), &'static str> {
+        let scroll = self.scroll.clamp(i8::MIN as i32, i8::MAX as i32) as i8;
+        let event = if self.absolute.is_some() {
+            Event::MouseAbsoluteEvent(MouseAbsoluteEvent::new(self.buttons.clone(), self.position, scroll))
+        } else {
+            let clamp = |movement: i32| movement.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+            // Like PS/2 mice, relative mouse events treat upwards movement as positive,
+            // whereas evdev treats downwards movement as positive.
+            Event::MouseMovementEvent(MouseEvent::new(
+                self.buttons.clone(),
+                MouseMovementRelative::new(clamp(self.relative.0), clamp(self.relative.1.saturating_neg()), scroll),
+            ))
+        };
+        self.relative = (0, 0);
+        self.scroll = 0;
+        self.input.push(event)
+    }
+}
+
+/// Returns the segment describing the event buffer in the given slot, which the device writes into.
+fn event_buffer_segment(event_buffers_paddr: PhysicalAddress, slot: usize) -> BufferSegment {
+    BufferSegment {
+        phys_addr: event_buffers_paddr + slot * EVENT_SIZE,
+        len: EVENT_SIZE as u32,
+        device_writable: true,
+    }
+}
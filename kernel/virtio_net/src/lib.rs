@@ -0,0 +1,311 @@
+//! A driver for virtio network devices, as offered by QEMU/KVM via `-device virtio-net-pci`.
+//!
+//! The device has one receive queue and one transmit queue.
+//! Every packet in either direction is a chain of two descriptors:
+//! a virtio-net header followed by the Ethernet frame itself,
+//! such that received frames can be handed to the network stack without copying them.
+//!
+//! Like the [`e1000`](../e1000/index.html) driver, this driver uses the device's legacy INTx interrupt,
+//! whose handler wakes a deferred task that polls the device's network interface.
+//! Only one virtio network device is currently supported.
+
+#![no_std]
+#![feature(abi_x86_interrupt)]
+
+extern crate alloc;
+
+use alloc::{format, sync::Arc, vec, vec::Vec};
+use interrupts::{eoi, InterruptNumber, InterruptStackFrame};
+use log::{error, info};
+use memory::{MappedPages, PhysicalAddress, DMA_FLAGS, create_contiguous_mapping};
+use nic_buffers::{ReceiveBuffer, ReceivedFrame, TransmitBuffer};
+use pci::PciDevice;
+use spin::Once;
+use sync_irq::IrqSafeMutex;
+use virtio::{BufferSegment, ModernPciTransport, Virtqueue};
+
+/// The PCI device IDs of virtio network devices, both transitional and modern.
+pub const NET_DEVS: [u16; 2] = [virtio::transitional_device_ids::NETWORK, virtio::modern_device_ids::NETWORK];
+
+/// The device reports its MAC address in its configuration.
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+
+/// Offset of the device's MAC address in the device configuration.
+const CONFIG_MAC: usize = 0;
+
+const RX_QUEUE: u16 = 0;
+const TX_QUEUE: u16 = 1;
+/// The maximum number of descriptors in each queue; each packet uses two of them.
+const MAX_QUEUE_SIZE: u16 = 256;
+
+/// The size of the virtio-net header that precedes every packet, given that `VIRTIO_F_VERSION_1` is negotiated.
+const NET_HEADER_SIZE: usize = 12;
+/// The size of each receive buffer, which holds a full Ethernet frame without its FCS.
+const RX_BUFFER_SIZE: u16 = 1514;
+/// How many receive buffers are preallocated for this driver to use.
+const RX_BUFFER_POOL_SIZE: usize = 256;
+
+/// The pool of receive buffers used by the device and temporarily given to higher layers in the network stack.
+static RX_BUFFER_POOL: Once<mpmc::Queue<ReceiveBuffer>> = Once::new();
+
+/// The single instance of the virtio network device.
+static VIRTIO_NET_NIC: Once<IrqSafeMutex<VirtioNetNic>> = Once::new();
+
+/// Returns a reference to the virtio network device, if it has been initialized.
+pub fn get_virtio_net_nic() -> Option<&'static IrqSafeMutex<VirtioNetNic>> {
+    VIRTIO_NET_NIC.get()
+}
+
+/// A virtio network device.
+pub struct VirtioNetNic {
+    transport: ModernPciTransport,
+    interrupt_num: InterruptNumber,
+    mac_address: [u8; 6],
+    rx_queue: Virtqueue,
+    tx_queue: Virtqueue,
+    /// The headers that the device writes into, one per receive buffer slot,
+    /// followed by the single zeroed header that precedes every transmitted frame.
+    /// These are only accessed by the device, so this is only held to keep them mapped.
+    _headers: MappedPages,
+    headers_paddr: PhysicalAddress,
+    /// The receive buffer slot and buffer currently offered to the device, indexed by buffer ID.
+    rx_buffers: Vec<Option<(usize, ReceiveBuffer)>>,
+    /// The transmit buffers currently being sent by the device, indexed by buffer ID.
+    tx_buffers: Vec<Option<TransmitBuffer>>,
+    rx_buffer_pool: &'static mpmc::Queue<ReceiveBuffer>,
+    deferred_task: Option<task::JoinableTaskRef>,
+}
+
+impl VirtioNetNic {
+    /// Initializes the given virtio network device.
+    ///
+    /// [`VirtioNetNic::init_interrupts()`] must be called after the NIC has been registered with the `net` subsystem.
+    pub fn init(pci_device: &PciDevice) -> Result<&'static IrqSafeMutex<VirtioNetNic>, &'static str> {
+        if VIRTIO_NET_NIC.is_completed() {
+            return Err("virtio_net: only one virtio network device is supported");
+        }
+        let interrupt_num = match pci_device.pci_get_intx_info() {
+            Ok((Some(irq), _pin)) => (irq + interrupts::IRQ_BASE_OFFSET) as InterruptNumber,
+            _ => return Err("virtio_net: PCI device had no interrupt number (IRQ vector)"),
+        };
+
+        let mut transport = ModernPciTransport::new(pci_device)?;
+        let nic = match Self::setup(&mut transport) {
+            Ok((mac_address, rx_queue, tx_queue)) => Self::start(transport, interrupt_num, mac_address, rx_queue, tx_queue)?,
+            Err(e) => {
+                let _ = transport.set_failed();
+                return Err(e);
+            }
+        };
+        info!("virtio_net: initialized device with MAC address {:02X?} at {}", nic.mac_address, pci_device.location);
+        Ok(VIRTIO_NET_NIC.call_once(|| IrqSafeMutex::new(nic)))
+    }
+
+    /// Negotiates the device's features, reads its MAC address, and sets up its queues.
+    fn setup(transport: &mut ModernPciTransport) -> Result<([u8; 6], Virtqueue, Virtqueue), &'static str> {
+        let features = transport.negotiate_features(VIRTIO_NET_F_MAC)?;
+        if features & VIRTIO_NET_F_MAC == 0 {
+            return Err("virtio_net: device doesn't report its MAC address");
+        }
+        let mut mac_address = [0; 6];
+        for (i, byte) in mac_address.iter_mut().enumerate() {
+            *byte = transport.read_config_u8(CONFIG_MAC + i)?;
+        }
+        let rx_queue = transport.setup_queue(RX_QUEUE, MAX_QUEUE_SIZE)?;
+        let tx_queue = transport.setup_queue(TX_QUEUE, MAX_QUEUE_SIZE)?;
+        if rx_queue.size() < 2 || tx_queue.size() < 2 {
+            return Err("virtio_net: queues are too small");
+        }
+        Ok((mac_address, rx_queue, tx_queue))
+    }
+
+    /// Offers receive buffers to the device and marks the driver as ready.
+    fn start(
+        mut transport: ModernPciTransport,
+        interrupt_num: InterruptNumber,
+        mac_address: [u8; 6],
+        mut rx_queue: Virtqueue,
+        tx_queue: Virtqueue,
+    ) -> Result<VirtioNetNic, &'static str> {
+        let num_rx_slots = rx_queue.size() as usize / 2;
+        let setup = (|| -> Result<_, &'static str> {
+            let rx_buffer_pool = RX_BUFFER_POOL.call_once(|| mpmc::Queue::with_capacity(RX_BUFFER_POOL_SIZE));
+            nic_initialization::init_rx_buf_pool(RX_BUFFER_POOL_SIZE, RX_BUFFER_SIZE, rx_buffer_pool)?;
+            let (headers, headers_paddr) = create_contiguous_mapping((num_rx_slots + 1) * NET_HEADER_SIZE, DMA_FLAGS)?;
+            Ok((rx_buffer_pool, headers, headers_paddr))
+        })();
+        let (rx_buffer_pool, mut headers, headers_paddr) = match setup {
+            Ok(setup) => setup,
+            Err(e) => {
+                let _ = transport.set_failed();
+                return Err(e);
+            }
+        };
+        headers.as_slice_mut::<u8>(0, (num_rx_slots + 1) * NET_HEADER_SIZE)?.fill(0);
+
+        let mut rx_buffers = Vec::new();
+        rx_buffers.resize_with(rx_queue.size() as usize, || None);
+        for slot in 0 .. num_rx_slots {
+            let buffer = take_rx_buffer(rx_buffer_pool)?;
+            let id = rx_queue.add(&rx_segments(headers_paddr, slot, &buffer))?;
+            rx_buffers[id as usize] = Some((slot, buffer));
+        }
+        let mut tx_buffers = Vec::new();
+        tx_buffers.resize_with(tx_queue.size() as usize, || None);
+
+        transport.driver_ok()?;
+        transport.notify(&rx_queue)?;
+        Ok(VirtioNetNic {
+            transport,
+            interrupt_num,
+            mac_address,
+            rx_queue,
+            tx_queue,
+            _headers: headers,
+            headers_paddr,
+            rx_buffers,
+            tx_buffers,
+            rx_buffer_pool,
+            deferred_task: None,
+        })
+    }
+
+    /// Registers the interrupt handler for this NIC.
+    ///
+    /// The provided `interface` must be the network interface associated with this NIC.
+    /// It will be polled in a deferred task whenever the device signals an interrupt.
+    pub fn init_interrupts(&mut self, interface: Arc<net::NetworkInterface>) -> Result<(), &'static str> {
+        let deferred_task = deferred_interrupt_tasks::register_interrupt_handler(
+            self.interrupt_num,
+            virtio_net_handler,
+            poll_interface,
+            interface,
+            Some(format!("virtio_net_deferred_task_irq_{:#X}", self.interrupt_num)),
+        )
+        .map_err(|error| {
+            error!("error registering virtio_net handler: {:?}", error);
+            "virtio_net interrupt number was already in use! Sharing IRQs is currently unsupported."
+        })?;
+        self.deferred_task = Some(deferred_task);
+        Ok(())
+    }
+
+    /// Frees the transmit buffers that the device has finished sending.
+    fn reclaim_tx_buffers(&mut self) {
+        while let Some((id, _len)) = self.tx_queue.pop_used() {
+            if let Some(buffer) = self.tx_buffers.get_mut(id as usize) {
+                buffer.take();
+            }
+        }
+    }
+
+    /// Returns the physical address of the header that precedes every transmitted frame.
+    fn tx_header_paddr(&self) -> PhysicalAddress {
+        self.headers_paddr + (self.rx_queue.size() as usize / 2) * NET_HEADER_SIZE
+    }
+
+    /// Takes the next received frame from the device, if any, and offers a new buffer in its place.
+    fn receive_frame(&mut self) -> Result<Option<ReceivedFrame>, &'static str> {
+        let Some((id, len)) = self.rx_queue.pop_used() else { return Ok(None) };
+        let (slot, mut buffer) = self.rx_buffers.get_mut(id as usize)
+            .and_then(Option::take)
+            .ok_or("virtio_net: device returned an invalid buffer ID")?;
+
+        let new_buffer = take_rx_buffer(self.rx_buffer_pool)?;
+        let new_id = self.rx_queue.add(&rx_segments(self.headers_paddr, slot, &new_buffer))?;
+        self.rx_buffers[new_id as usize] = Some((slot, new_buffer));
+        self.transport.notify(&self.rx_queue)?;
+
+        let frame_len = (len as usize).saturating_sub(NET_HEADER_SIZE);
+        buffer.set_received_length(frame_len as u16)?;
+        Ok(Some(ReceivedFrame(vec![buffer])))
+    }
+}
+
+impl net::NetworkDevice for VirtioNetNic {
+    fn send(&mut self, buf: TransmitBuffer) {
+        self.reclaim_tx_buffers();
+        let header = BufferSegment {
+            phys_addr: self.tx_header_paddr(),
+            len: NET_HEADER_SIZE as u32,
+            device_writable: false,
+        };
+        let frame = BufferSegment {
+            phys_addr: buf.phys_addr(),
+            len: buf.length() as u32,
+            device_writable: false,
+        };
+        match self.tx_queue.add(&[header, frame]) {
+            Ok(id) => {
+                self.tx_buffers[id as usize] = Some(buf);
+                if let Err(e) = self.transport.notify(&self.tx_queue) {
+                    error!("virtio_net: failed to notify device of a transmitted frame: {}", e);
+                }
+            }
+            Err(e) => error!("virtio_net: dropping transmitted frame: {}", e),
+        }
+    }
+
+    fn receive(&mut self) -> Option<ReceivedFrame> {
+        self.receive_frame().unwrap_or_else(|e| {
+            error!("virtio_net: failed to receive frame: {}", e);
+            None
+        })
+    }
+
+    fn mac_address(&self) -> [u8; 6] {
+        self.mac_address
+    }
+}
+
+/// Returns a receive buffer from the pool, allocating a new one if the pool is empty.
+fn take_rx_buffer(pool: &'static mpmc::Queue<ReceiveBuffer>) -> Result<ReceiveBuffer, &'static str> {
+    match pool.pop() {
+        Some(buffer) => Ok(buffer),
+        None => {
+            let (mp, phys_addr) = create_contiguous_mapping(RX_BUFFER_SIZE as usize, DMA_FLAGS)?;
+            ReceiveBuffer::new(mp, phys_addr, RX_BUFFER_SIZE, pool)
+        }
+    }
+}
+
+/// Returns the segments that describe the receive buffer in the given slot:
+/// the slot's header, followed by the buffer itself.
+fn rx_segments(headers_paddr: PhysicalAddress, slot: usize, buffer: &ReceiveBuffer) -> [BufferSegment; 2] {
+    [
+        BufferSegment {
+            phys_addr: headers_paddr + slot * NET_HEADER_SIZE,
+            len: NET_HEADER_SIZE as u32,
+            device_writable: true,
+        },
+        BufferSegment {
+            phys_addr: buffer.phys_addr(),
+            len: RX_BUFFER_SIZE as u32,
+            device_writable: true,
+        },
+    ]
+}
+
+extern "x86-interrupt" fn virtio_net_handler(_stack_frame: InterruptStackFrame) {
+    if let Some(nic_ref) = VIRTIO_NET_NIC.get() {
+        let mut nic = nic_ref.lock();
+        // Reading the ISR status acknowledges the interrupt, which deasserts the INTx line.
+        match nic.transport.read_isr() {
+            Ok(0) => { }
+            Ok(_) => if let Some(ref deferred_task) = nic.deferred_task {
+                let _ = deferred_task.unblock();
+            },
+            Err(e) => error!("virtio_net_handler(): couldn't read ISR status: {}", e),
+        }
+        eoi(nic.interrupt_num);
+    } else {
+        error!("BUG: virtio_net_handler(): virtio network device hasn't yet been initialized!");
+    }
+}
+
+/// The deferred interrupt task, which polls the network interface associated with the device
+/// such that it receives the frames that the device has received.
+fn poll_interface(interface: &Arc<net::NetworkInterface>) -> Result<(), ()> {
+    interface.poll();
+    Ok(())
+}
@@ -27,6 +27,9 @@ where
     P: DeadlockPrevention,
 {
     inner: Queue<TaskRef, P>,
+    /// The optional name of this wait queue, which is recorded as the wait reason
+    /// of each task that blocks on it.
+    name: Option<&'static str>,
 }
 
 impl<P> WaitQueue<P>
@@ -37,20 +40,41 @@ where
     pub const fn new() -> Self {
         Self {
             inner: Queue::new(),
+            name: None,
         }
     }
 
+    /// Creates a new empty wait queue with the given `name`.
+    ///
+    /// While a task is blocked on this wait queue, its wait reason is set to `name`,
+    /// which helps identify what a hung or long-blocked task is waiting for.
+    pub const fn with_name(name: &'static str) -> Self {
+        Self {
+            inner: Queue::new(),
+            name: Some(name),
+        }
+    }
+
+    /// Returns the name of this wait queue, if it has one.
+    pub const fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
     /// Blocks the current task until the given condition succeeds.
     pub fn wait_until<F, T>(&self, mut condition: F) -> T
     where
         F: FnMut() -> Option<T>,
     {
         let task = get_my_current_task().unwrap();
+        let mut has_blocked = false;
         loop {
             let wrapped_condition = || {
                 if let Some(value) = condition() {
                     Ok(value)
                 } else {
+                    if self.name.is_some() {
+                        task.set_wait_reason(self.name);
+                    }
                     // Ensure that we don't get preempted after blocking ourselves
                     // before we get a chance to release the internal lock of the queue.
                     let preemption_guard = hold_preemption();
@@ -60,8 +84,14 @@ where
             };
 
             match self.inner.push_if_fail(task.clone(), wrapped_condition) {
-                Ok(value) => return value,
+                Ok(value) => {
+                    if has_blocked && self.name.is_some() {
+                        task.set_wait_reason(None);
+                    }
+                    return value;
+                }
                 Err(preemption_guard) => {
+                    has_blocked = true;
                     drop(preemption_guard);
                     scheduler::schedule();
                 }
@@ -0,0 +1,186 @@
+//! Per-CPU heartbeats that let a monitor detect stuck CPU cores.
+//!
+//! Each CPU's heartbeat is allocated via [`register_cpu()`] when that CPU is brought up,
+//! after which it "pets" its heartbeat via [`pet()`] from its timer interrupt handler,
+//! recording whether it was able to schedule at that tick and which code it interrupted.
+//! A monitor (e.g., the `hung_task_detector`) periodically calls [`CpuWatchdog::check()`]
+//! to find CPUs that have stopped making progress:
+//! * a CPU whose heartbeat stopped entirely has had interrupts disabled for too long, and
+//! * a CPU whose heartbeat continues but that hasn't been able to schedule
+//!   has had preemption disabled for too long, e.g., because it's spinning on a lock.
+//!
+//! CPUs whose timer tick was deliberately stopped (see [`tick_stopped()`]) are not reported.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use cpu::CpuId;
+use sync_irq::IrqSafeRwLock;
+use time::{Duration, Instant};
+
+/// The heartbeat of each CPU that has been registered.
+///
+/// This is accessed from timer interrupt handlers, so it must be IRQ-safe,
+/// and those handlers only read it, such that they never allocate while holding the lock.
+/// Heartbeats are leaked because CPUs are never removed.
+static HEARTBEATS: IrqSafeRwLock<Vec<(CpuId, &'static Heartbeat)>> = IrqSafeRwLock::new(Vec::new());
+
+#[derive(Default)]
+struct Heartbeat {
+    /// The number of timer ticks on this CPU.
+    ticks: AtomicU64,
+    /// The number of timer ticks at which this CPU was able to schedule.
+    scheduling_ticks: AtomicU64,
+    /// The instruction pointer interrupted by the most recent tick, or zero if unknown.
+    interrupted_ip: AtomicUsize,
+    /// The ID of the task that was running at the most recent tick.
+    task_id: AtomicUsize,
+    /// Whether this CPU's timer tick is stopped.
+    tick_stopped: AtomicBool,
+}
+
+/// Allocates the heartbeat of the given CPU, if it doesn't have one yet.
+///
+/// This must be called for each CPU before it enables its timer interrupt,
+/// as [`pet()`] and [`tick_stopped()`] ignore CPUs that aren't registered.
+pub fn register_cpu(cpu: CpuId) {
+    let mut heartbeats = HEARTBEATS.write();
+    if !heartbeats.iter().any(|(c, _)| *c == cpu) {
+        heartbeats.push((cpu, Box::leak(Box::default())));
+    }
+}
+
+fn heartbeat(cpu: CpuId) -> Option<&'static Heartbeat> {
+    HEARTBEATS.read().iter().find(|(c, _)| *c == cpu).map(|(_, hb)| *hb)
+}
+
+/// Records a timer tick on the given CPU, which must have been registered via [`register_cpu()`].
+///
+/// * `can_schedule`: whether preemption was enabled, i.e., whether the CPU can switch tasks.
+/// * `interrupted_ip`: the instruction pointer of the code that the tick interrupted, if known.
+/// * `task_id`: the ID of the task that was running.
+///
+/// This should be called from the timer interrupt handler on every tick.
+pub fn pet(cpu: CpuId, can_schedule: bool, interrupted_ip: Option<usize>, task_id: usize) {
+    let Some(hb) = heartbeat(cpu) else { return };
+    hb.ticks.fetch_add(1, Ordering::Relaxed);
+    if can_schedule {
+        hb.scheduling_ticks.fetch_add(1, Ordering::Relaxed);
+    }
+    hb.interrupted_ip.store(interrupted_ip.unwrap_or(0), Ordering::Relaxed);
+    hb.task_id.store(task_id, Ordering::Relaxed);
+    hb.tick_stopped.store(false, Ordering::Release);
+}
+
+/// Records that the given CPU is about to stop its timer tick,
+/// such that its missing heartbeat is not mistaken for a stuck CPU.
+///
+/// The next call to [`pet()`] on that CPU clears this again.
+pub fn tick_stopped(cpu: CpuId) {
+    if let Some(hb) = heartbeat(cpu) {
+        hb.tick_stopped.store(true, Ordering::Release);
+    }
+}
+
+/// Why a CPU was considered to be stuck.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StuckReason {
+    /// The CPU hasn't had a timer tick, i.e., interrupts have been disabled.
+    NoTicks,
+    /// The CPU has had timer ticks but couldn't schedule at any of them,
+    /// i.e., preemption has been disabled.
+    NoScheduling,
+}
+
+/// A CPU that was found to be stuck by [`CpuWatchdog::check()`].
+#[derive(Clone, Copy, Debug)]
+pub struct StuckCpu {
+    pub cpu: CpuId,
+    pub reason: StuckReason,
+    /// How long the CPU has been stuck, at least.
+    pub duration: Duration,
+    /// The instruction pointer interrupted by the CPU's most recent tick, if known.
+    ///
+    /// For [`StuckReason::NoScheduling`], this is where the CPU was recently executing.
+    /// For [`StuckReason::NoTicks`], this is only where it was before it got stuck.
+    pub interrupted_ip: Option<usize>,
+    /// The ID of the task that was running at the CPU's most recent tick.
+    pub task_id: usize,
+}
+
+/// The progress of a CPU as of the last check.
+struct Progress {
+    ticks: u64,
+    scheduling_ticks: u64,
+    /// When `ticks` last changed.
+    ticked_at: Instant,
+    /// When `scheduling_ticks` last changed.
+    scheduled_at: Instant,
+}
+
+/// Detects stuck CPUs by comparing their heartbeats across periodic checks.
+#[derive(Default)]
+pub struct CpuWatchdog {
+    progress: BTreeMap<CpuId, Progress>,
+}
+
+impl CpuWatchdog {
+    /// Creates a new watchdog that hasn't yet observed any CPU.
+    pub const fn new() -> CpuWatchdog {
+        CpuWatchdog { progress: BTreeMap::new() }
+    }
+
+    /// Returns the CPUs that haven't made progress for at least `threshold`,
+    /// as of the previous calls to this function.
+    ///
+    /// This must be called periodically, more often than `threshold`.
+    pub fn check(&mut self, threshold: Duration) -> Vec<StuckCpu> {
+        let now = Instant::now();
+        let mut stuck = Vec::new();
+        for (cpu, hb) in HEARTBEATS.read().iter() {
+            let ticks = hb.ticks.load(Ordering::Relaxed);
+            let scheduling_ticks = hb.scheduling_ticks.load(Ordering::Relaxed);
+            let progress = self.progress.entry(*cpu).or_insert(Progress {
+                ticks,
+                scheduling_ticks,
+                ticked_at: now,
+                scheduled_at: now,
+            });
+            if ticks != progress.ticks {
+                progress.ticks = ticks;
+                progress.ticked_at = now;
+            }
+            if scheduling_ticks != progress.scheduling_ticks {
+                progress.scheduling_ticks = scheduling_ticks;
+                progress.scheduled_at = now;
+            }
+
+            if hb.tick_stopped.load(Ordering::Acquire) {
+                // A CPU with a stopped tick still schedules when its task blocks or yields.
+                progress.ticked_at = now;
+                progress.scheduled_at = now;
+                continue;
+            }
+
+            let (reason, since) = if now.duration_since(progress.ticked_at) >= threshold {
+                (StuckReason::NoTicks, progress.ticked_at)
+            } else if now.duration_since(progress.scheduled_at) >= threshold {
+                (StuckReason::NoScheduling, progress.scheduled_at)
+            } else {
+                continue;
+            };
+            let interrupted_ip = hb.interrupted_ip.load(Ordering::Relaxed);
+            stuck.push(StuckCpu {
+                cpu: *cpu,
+                reason,
+                duration: now.duration_since(since),
+                interrupted_ip: (interrupted_ip != 0).then_some(interrupted_ip),
+                task_id: hb.task_id.load(Ordering::Relaxed),
+            });
+        }
+        stuck
+    }
+}
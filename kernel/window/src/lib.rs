@@ -5,6 +5,10 @@
 //!
 //! A window can render itself to the screen via a window manager. The window manager will compute the bounding box of the updated part and composites it with other existing windows according to their order.
 //!
+//! Applications that redraw a lot at once can make their window double buffered via
+//! [`Window::enable_double_buffering()`], draw each frame into the back buffer,
+//! and then show it all at once via [`Window::present()`].
+//!
 //! The library
 //! frees applications from handling the complicated interaction with window manager, however, advanced users could learn from
 //! this library about how to use window manager APIs directly.
@@ -14,7 +18,6 @@
 #![feature(type_alias_impl_trait)]
 
 extern crate alloc;
-extern crate mpmc;
 extern crate event_types;
 extern crate spin;
 #[macro_use]
@@ -27,19 +30,24 @@ extern crate window_manager;
 extern crate shapes;
 extern crate color;
 extern crate dereffer;
+extern crate display_scale;
+extern crate task;
 
 use alloc::sync::Arc;
 use dereffer::{DerefsTo, DerefsToMut};
-use mpmc::Queue;
+use display_scale::ScaleFactor;
 use event_types::{Event, MousePositionEvent};
 use framebuffer::{Framebuffer, AlphaPixel};
 use color::Color;
 use shapes::{Coord, Rectangle};
 use spin::{Mutex, MutexGuard};
-use window_inner::{WindowInner, WindowMovingStatus, DEFAULT_BORDER_SIZE, DEFAULT_TITLE_BAR_HEIGHT};
+pub use window_inner::CursorImage;
+use window_inner::{EventQueue, WindowInner, WindowMovingStatus, WindowState, DEFAULT_BORDER_SIZE, DEFAULT_EVENT_QUEUE_CAPACITY, DEFAULT_TITLE_BAR_HEIGHT};
 use window_manager::{WINDOW_MANAGER};
 
 
+// The sizes below are in number of unscaled pixels, see the `display_scale` crate.
+
 // border radius, in number of pixels
 const WINDOW_RADIUS: usize = 5;
 // border and title bar color when window is inactive
@@ -65,9 +73,9 @@ const WINDOW_BUTTON_SIZE: usize = 6;
 enum TopButton {
     // Button to close the window
     Close,
-    // Button to minimize/maximize the window (depends on the current state)
+    // Button to maximize the window, or restore it if it is already maximized
     MinimizeMaximize,
-    // Button to hide the window
+    // Button to hide (minimize) the window
     Hide,
 }
 
@@ -93,12 +101,15 @@ pub struct Window {
     /// This is wrapped in an `Arc` such that the window manager can hold `Weak` references to it.
     inner: Arc<Mutex<WindowInner>>,
     /// The event queue
-    event_consumer: Queue<Event>,
+    event_consumer: EventQueue,
     /// last mouse position event, used to judge click and press-moving event
     /// TODO FIXME (kevinaboos): why is mouse-specific stuff here? 
     last_mouse_position_event: MousePositionEvent,
     /// record last result of whether this window is active, to reduce redraw overhead
     last_is_active: bool,
+    /// The display scale factor at the time this window was created,
+    /// which is used for the title bar buttons and rounded corners for the lifetime of this window.
+    scale_factor: ScaleFactor,
 }
 
 impl Window {
@@ -117,6 +128,38 @@ impl Window {
         width: usize,
         height: usize,
         initial_background: Color,
+    ) -> Result<Window, &'static str> {
+        Self::with_surface_scale(coordinate, width, height, initial_background, 1)
+    }
+
+    /// Creates a new window like [`Window::new()`], but whose framebuffer has the given logical `width` and `height`
+    /// and is scaled up onto the screen by the display scale factor, rounded down to a whole number.
+    ///
+    /// This lets an application render at a fixed resolution that remains legible on a high-resolution screen.
+    /// The scale is reduced as needed for the window to fit on the screen; see [`surface_scale()`](Self::surface_scale).
+    /// The `coordinate` is in screen pixels, whereas everything within the window,
+    /// e.g., its [`area()`](Self::area) and the coordinates of mouse events, is in logical pixels.
+    pub fn new_scaled(
+        coordinate: Coord,
+        width: usize,
+        height: usize,
+        initial_background: Color,
+    ) -> Result<Window, &'static str> {
+        let wm_ref = window_manager::WINDOW_MANAGER.get().ok_or("The window manager is not initialized")?;
+        let (screen_width, screen_height) = wm_ref.lock().get_screen_size();
+        let mut surface_scale = display_scale::scale_factor().integer_scale().min(framebuffer::MAX_SCALE);
+        while surface_scale > 1 && (width * surface_scale > screen_width || height * surface_scale > screen_height) {
+            surface_scale -= 1;
+        }
+        Self::with_surface_scale(coordinate, width, height, initial_background, surface_scale)
+    }
+
+    fn with_surface_scale(
+        coordinate: Coord,
+        width: usize,
+        height: usize,
+        initial_background: Color,
+        surface_scale: usize,
     ) -> Result<Window, &'static str> {
         let wm_ref = window_manager::WINDOW_MANAGER.get().ok_or("The window manager is not initialized")?;
 
@@ -126,22 +169,28 @@ impl Window {
         framebuffer.fill(initial_background.into());
         let (width, height) = framebuffer.get_size();
 
+        // A scaled-up window's decorations are scaled up along with its framebuffer.
+        let scale_factor = if surface_scale > 1 { ScaleFactor::One } else { display_scale::scale_factor() };
+        let title_bar_height = scale_factor.scale(DEFAULT_TITLE_BAR_HEIGHT);
+        let border_size = scale_factor.scale(DEFAULT_BORDER_SIZE);
         // TODO: FIXME: (kevinaboos) this condition seems wrong... at least the first conditional does.
-        if width <= 2 * DEFAULT_TITLE_BAR_HEIGHT || height <= DEFAULT_TITLE_BAR_HEIGHT + DEFAULT_BORDER_SIZE {
+        if width <= 2 * title_bar_height || height <= title_bar_height + border_size {
             return Err("window dimensions must be large enough for the title bar and borders to be drawn");
         }
 
         // Create an event queue to allow the window manager to pass events to this `Window` via its `WindowInner` instance,
         // and to allow applications to receive events from this `Window` object itself.
-        let event_consumer = Queue::with_capacity(100);
+        let event_consumer = EventQueue::with_capacity(DEFAULT_EVENT_QUEUE_CAPACITY);
         let event_producer = event_consumer.clone();
 
-        let window_inner = WindowInner::new(coordinate, framebuffer, event_producer);
+        let mut window_inner = WindowInner::new(coordinate, framebuffer, surface_scale, event_producer)?;
+        window_inner.set_owner_task(task::get_my_current_task_id());
         let mut window = Window {
             inner: Arc::new(Mutex::new(window_inner)),
             event_consumer,
             last_mouse_position_event: MousePositionEvent::default(),
             last_is_active: true, // new window is now set as the active window by default 
+            scale_factor,
         };
 
         // Draw the actual window frame, the title bar and borders.
@@ -176,6 +225,9 @@ impl Window {
         let mut call_later_do_move_active_window = false;
         let mut need_to_set_active = false;
         let mut need_refresh_three_button = false;
+        let mut need_redraw_frame = false;
+        let mut call_later_do_minimize = false;
+        let mut call_later_do_toggle_maximize = false;
 
         let wm_ref = window_manager::WINDOW_MANAGER.get().ok_or("The window manager is not initialized")?;
         
@@ -201,12 +253,12 @@ impl Window {
             // TODO FIXME: for a performant design, the goal is to AVOID holding the lock on `inner` as much as possible. 
             //             That means that most of the drawing logic should be moved into the `window_inner` crate itself.
             let mut inner = self.inner.lock();
-            let (width, height) = inner.get_size();
+            let (width, height) = inner.surface_size();
 
             match event {
                 Event::MousePositionEvent(ref mouse_event) => {
-                    match inner.moving {
-                        WindowMovingStatus::Moving(_) => {
+                    match inner.moving_status() {
+                        WindowMovingStatus::Moving { .. } => {
                             // only wait for left button up to exit this mode
                             if !mouse_event.left_button_hold {
                                 self.last_mouse_position_event = mouse_event.clone();
@@ -219,13 +271,14 @@ impl Window {
                                 && (mouse_event.coordinate.x as usize) < width
                             {
                                 // the region of title bar
-                                let r2 = WINDOW_RADIUS * WINDOW_RADIUS;
+                                let radius = self.scale_factor.scale(WINDOW_RADIUS);
+                                let r2 = radius * radius;
                                 let mut is_three_button = false;
                                 for i in 0..3 {
                                     let dcoordinate = Coord::new(
                                         mouse_event.coordinate.x
-                                            - WINDOW_BUTTON_BIAS_X as isize
-                                            - (i as isize) * WINDOW_BUTTON_BETWEEN as isize,
+                                            - self.scale_factor.scale(WINDOW_BUTTON_BIAS_X) as isize
+                                            - (i as isize) * self.scale_factor.scale(WINDOW_BUTTON_BETWEEN) as isize,
                                         mouse_event.coordinate.y - inner.title_bar_height as isize / 2,
                                     );
                                     if dcoordinate.x * dcoordinate.x + dcoordinate.y * dcoordinate.y
@@ -239,15 +292,19 @@ impl Window {
                                             self.show_button(TopButton::from(i), 0, &mut inner);
                                             need_refresh_three_button = true;
                                             if self.last_mouse_position_event.left_button_hold {
-                                                // Kevin: disabling the close button until it actually works
-                                                /*
                                                 // click event
-                                                if i == 0 {
-                                                    debug!("close window");
-                                                    return Err("user close window");
-                                                    // window will not close until app drop self
+                                                match TopButton::from(i) {
+                                                    TopButton::Close => {
+                                                        // Kevin: disabling the close button until it actually works
+                                                        /*
+                                                        debug!("close window");
+                                                        return Err("user close window");
+                                                        // window will not close until app drop self
+                                                        */
+                                                    }
+                                                    TopButton::MinimizeMaximize => call_later_do_toggle_maximize = true,
+                                                    TopButton::Hide => call_later_do_minimize = true,
                                                 }
-                                                */
                                             }
                                         }
                                     } else {
@@ -260,7 +317,7 @@ impl Window {
                                     && !self.last_mouse_position_event.left_button_hold
                                     && mouse_event.left_button_hold
                                 {
-                                    inner.moving = WindowMovingStatus::Moving(mouse_event.gcoordinate);
+                                    inner.start_move(mouse_event.gcoordinate);
                                     call_later_do_refresh_floating_border = true;
                                 }
                             } else {
@@ -279,6 +336,12 @@ impl Window {
                         }
                     }
                 }
+                Event::WindowResizeEvent(_) => {
+                    // The window's framebuffer was reallocated, so we must redraw its title bar and border.
+                    // The application must also handle this event in order to redraw its content.
+                    need_redraw_frame = true;
+                    unhandled_event = Some(event);
+                }
                 unhandled => {
                     unhandled_event = Some(unhandled);
                 }
@@ -291,11 +354,25 @@ impl Window {
             }
         }
 
+        if need_redraw_frame {
+            self.draw_border(self.last_is_active);
+            let mut inner = self.inner.lock();
+            self.show_button(TopButton::Close, 1, &mut inner);
+            self.show_button(TopButton::MinimizeMaximize, 1, &mut inner);
+            self.show_button(TopButton::Hide, 1, &mut inner);
+        }
+
         let mut wm = wm_ref.lock();
         if need_to_set_active {
             wm.set_active(&self.inner, true)?;
         }
 
+        if need_redraw_frame {
+            let bounding_box = self.inner.lock().bounding_box();
+            wm.refresh_windows(Some(bounding_box))?;
+            wm.refresh_mouse()?;
+        }
+
         if need_refresh_three_button {
             let area = self.get_button_area();
             wm.refresh_active_window(Some(area))?;
@@ -307,8 +384,19 @@ impl Window {
         }
 
         if call_later_do_move_active_window {
+            // This finishes the move, which makes the window stationary again.
             wm.move_active_window()?;
-            self.inner.lock().moving = WindowMovingStatus::Stationary;
+        }
+
+        if call_later_do_minimize {
+            wm.minimize_window(&self.inner)?;
+        } else if call_later_do_toggle_maximize {
+            let state = self.inner.lock().state();
+            if state == WindowState::Maximized {
+                wm.restore_window(&self.inner)?;
+            } else {
+                wm.maximize_window(&self.inner)?;
+            }
         }
 
         Ok(unhandled_event)
@@ -320,19 +408,137 @@ impl Window {
     /// Refreshes the whole window if `bounding_box` is `None`.
     /// 
     /// This method should be invoked after updating the window's contents in order to see its new content.
+    /// If this window is double buffered, use [`present()`](Self::present) instead,
+    /// since this only renders what has already been presented.
     pub fn render(&mut self, bounding_box: Option<Rectangle>) -> Result<(), &'static str> {
         let wm_ref = WINDOW_MANAGER.get().ok_or("The static window manager was not yet initialized")?;
 
         // Convert the given relative `bounding_box` to an absolute one (relative to the screen, not the window).
-        let coordinate = {
-            let window = self.inner.lock();
-            window.get_position()
+        let absolute_bounding_box = {
+            let mut window = self.inner.lock();
+            if bounding_box.is_none() {
+                // The whole window is about to be composited, so nothing remains dirty.
+                window.take_dirty_regions();
+            }
+            bounding_box.map(|bb| window.to_screen_region(bb))
         };
-        let absolute_bounding_box = bounding_box.map(|bb| bb + coordinate);
 
         wm_ref.lock().refresh_windows(absolute_bounding_box)
     }
 
+    /// Marks the given `region` of this `Window` as changed,
+    /// such that it is composited upon the next call to [`render_dirty()`](Self::render_dirty).
+    ///
+    /// The `region` is relative to the top-left coordinate of this `Window`.
+    /// Marking only the areas that changed avoids recompositing the whole window.
+    pub fn mark_dirty(&mut self, region: Rectangle) {
+        self.inner.lock().mark_dirty(region);
+    }
+
+    /// Renders only the regions of this `Window` that were marked via [`mark_dirty()`](Self::mark_dirty)
+    /// since they were last rendered.
+    ///
+    /// Does nothing if no regions are dirty.
+    pub fn render_dirty(&mut self) -> Result<(), &'static str> {
+        let wm_ref = WINDOW_MANAGER.get().ok_or("The static window manager was not yet initialized")?;
+        wm_ref.lock().refresh_dirty_regions(&self.inner)
+    }
+
+    /// Resizes and moves this window to the given `new_position`,
+    /// which is relative to the top-left corner of the screen.
+    ///
+    /// This reallocates the window's framebuffer, discarding its contents.
+    /// The title bar and border are redrawn when [`handle_event()`](Self::handle_event)
+    /// next returns the resulting [`Event::WindowResizeEvent`], upon which the application
+    /// should redraw its content within the new [`area()`](Self::area) and then [`render()`](Self::render) it.
+    pub fn resize(&mut self, new_position: Rectangle) -> Result<(), &'static str> {
+        let wm_ref = WINDOW_MANAGER.get().ok_or("The static window manager was not yet initialized")?;
+        wm_ref.lock().resize_window(&self.inner, new_position)
+    }
+
+    /// Minimizes this window, hiding it until it is restored, e.g., by switching to it.
+    pub fn minimize(&mut self) -> Result<(), &'static str> {
+        let wm_ref = WINDOW_MANAGER.get().ok_or("The static window manager was not yet initialized")?;
+        wm_ref.lock().minimize_window(&self.inner)
+    }
+
+    /// Maximizes this window to fill the whole screen.
+    ///
+    /// Like [`resize()`](Self::resize), this discards the window's contents,
+    /// so the application should redraw them upon the resulting [`Event::WindowResizeEvent`].
+    pub fn maximize(&mut self) -> Result<(), &'static str> {
+        let wm_ref = WINDOW_MANAGER.get().ok_or("The static window manager was not yet initialized")?;
+        wm_ref.lock().maximize_window(&self.inner)
+    }
+
+    /// Restores this window from being minimized or maximized.
+    ///
+    /// Restoring a maximized window resizes it back to its previous position and size.
+    pub fn restore(&mut self) -> Result<(), &'static str> {
+        let wm_ref = WINDOW_MANAGER.get().ok_or("The static window manager was not yet initialized")?;
+        wm_ref.lock().restore_window(&self.inner)
+    }
+
+    /// Raises this window above all others and makes it the active window.
+    pub fn raise(&mut self) -> Result<(), &'static str> {
+        let wm_ref = WINDOW_MANAGER.get().ok_or("The static window manager was not yet initialized")?;
+        wm_ref.lock().raise_window(&self.inner)
+    }
+
+    /// Lowers this window beneath all other shown windows.
+    pub fn lower(&mut self) -> Result<(), &'static str> {
+        let wm_ref = WINDOW_MANAGER.get().ok_or("The static window manager was not yet initialized")?;
+        wm_ref.lock().lower_window(&self.inner)
+    }
+
+    /// Sets how opaque this whole window is, from `0` (invisible) to `255` (fully opaque).
+    ///
+    /// This is applied on top of the transparency of each pixel in the window,
+    /// which allows building overlays and tooltips that fade in and out.
+    pub fn set_opacity(&mut self, opacity: u8) -> Result<(), &'static str> {
+        let wm_ref = WINDOW_MANAGER.get().ok_or("The static window manager was not yet initialized")?;
+        wm_ref.lock().set_window_opacity(&self.inner, opacity)
+    }
+
+    /// Returns how opaque this whole window is, from `0` (invisible) to `255` (fully opaque).
+    pub fn opacity(&self) -> u8 {
+        self.inner.lock().opacity()
+    }
+
+    /// Sets the cursor image shown while the mouse is over this window,
+    /// e.g., [`CursorImage::resize_horizontal()`] while it is over a border that can be dragged,
+    /// or restores the default cursor if `cursor` is `None`.
+    pub fn set_cursor(&mut self, cursor: Option<CursorImage>) -> Result<(), &'static str> {
+        self.inner.lock().set_cursor(cursor);
+        let wm_ref = WINDOW_MANAGER.get().ok_or("The static window manager was not yet initialized")?;
+        wm_ref.lock().refresh_cursor()
+    }
+
+    /// Sets the maximum number of events that can be queued for this window
+    /// before further events are dropped. The default is [`DEFAULT_EVENT_QUEUE_CAPACITY`].
+    pub fn set_event_queue_capacity(&mut self, capacity: usize) {
+        self.event_consumer.set_capacity(capacity);
+    }
+
+    /// Returns the number of events sent to this window that were dropped because its event queue was full.
+    ///
+    /// If this increases, the application didn't handle events quickly enough and may have missed input;
+    /// it can recover by, e.g., redrawing itself or discarding a partially-typed key sequence.
+    pub fn dropped_events(&self) -> usize {
+        self.event_consumer.dropped_events()
+    }
+
+    /// Returns how many screen pixels each pixel of this window's framebuffer covers along each axis;
+    /// see [`Window::new_scaled()`].
+    pub fn surface_scale(&self) -> usize {
+        self.inner.lock().surface_scale()
+    }
+
+    /// Returns whether this window is minimized, maximized, or neither.
+    pub fn state(&self) -> WindowState {
+        self.inner.lock().state()
+    }
+
     /// Returns a `Rectangle` describing the position and dimensions of this Window's content region,
     /// i.e., the area within the window excluding the title bar and border
     /// that is available for rendering application content. 
@@ -343,22 +549,57 @@ impl Window {
     }
 
     /// Returns an immutable reference to this window's virtual `Framebuffer`. 
+    ///
+    /// If this window is double buffered, this is the back buffer.
     pub fn framebuffer(&self) -> FramebufferRef {
         FramebufferRef::new(
             self.inner.lock(),
-            |guard| guard.framebuffer(),
+            |guard| guard.draw_buffer(),
         )
     }
 
     /// Returns a mutable reference to this window's virtual `Framebuffer`. 
+    ///
+    /// If this window is double buffered, this is the back buffer,
+    /// whose contents are only shown once they are [`present()`](Self::present)ed.
     pub fn framebuffer_mut(&mut self) -> FramebufferRefMut {
         FramebufferRefMut::new(
             self.inner.lock(),
-            |guard| guard.framebuffer(),
-            |guard| guard.framebuffer_mut(),
+            |guard| guard.draw_buffer(),
+            |guard| guard.draw_buffer_mut(),
         )
     }
 
+    /// Makes this window double buffered, such that the application draws into a back buffer
+    /// via [`framebuffer_mut()`](Self::framebuffer_mut) that is only shown upon [`present()`](Self::present).
+    ///
+    /// This prevents the screen from showing a partially-drawn frame, at the cost of a second framebuffer.
+    pub fn enable_double_buffering(&mut self) -> Result<(), &'static str> {
+        self.inner.lock().enable_double_buffering()
+    }
+
+    /// Makes this window single buffered again, discarding anything drawn but not yet presented.
+    pub fn disable_double_buffering(&mut self) {
+        self.inner.lock().disable_double_buffering()
+    }
+
+    /// Returns `true` if this window is double buffered.
+    pub fn is_double_buffered(&self) -> bool {
+        self.inner.lock().is_double_buffered()
+    }
+
+    /// Shows the given `region` of the back buffer, relative to the top-left coordinate of this `Window`,
+    /// by copying it to the front buffer and rendering it. If `region` is `None`, the whole [`area()`](Self::area) is presented.
+    ///
+    /// If this window is not double buffered, this just renders the `region`.
+    pub fn present(&mut self, region: Option<Rectangle>) -> Result<(), &'static str> {
+        let presented = self.inner.lock().present(region);
+        match presented {
+            Some(presented) => self.render(Some(presented)),
+            None => Ok(()),
+        }
+    }
+
     /// Returns `true` if this window is the currently active window. 
     /// 
     /// Obtains the lock on the window manager instance. 
@@ -380,7 +621,7 @@ impl Window {
         } else {
             WINDOW_BORDER_COLOR_INACTIVE
         };
-        let (width, height) = inner.get_size();
+        let (width, height) = inner.surface_size();
 
         framebuffer_drawer::draw_rectangle(
             inner.framebuffer_mut(),
@@ -434,13 +675,14 @@ impl Window {
         }
 
         // draw radius finally
-        let r2 = WINDOW_RADIUS * WINDOW_RADIUS;
+        let radius = self.scale_factor.scale(WINDOW_RADIUS);
+        let r2 = radius * radius;
         let trans_pixel = color::TRANSPARENT.into();
   
-        for i in 0..WINDOW_RADIUS {
-            for j in 0..WINDOW_RADIUS {
-                let dx1 = WINDOW_RADIUS - i;
-                let dy1 = WINDOW_RADIUS - j;
+        for i in 0..radius {
+            for j in 0..radius {
+                let dx1 = radius - i;
+                let dy1 = radius - j;
                 if dx1 * dx1 + dy1 * dy1 > r2 {
                     // draw this to transparent
                     inner.framebuffer_mut().overwrite_pixel(Coord::new(i as isize, j as isize), trans_pixel);
@@ -453,8 +695,8 @@ impl Window {
     /// show three button with status. state = 0,1,2 for three different color
     fn show_button(&self, button: TopButton, state: usize, inner: &mut WindowInner) {
         let y = inner.title_bar_height / 2;
-        let x = WINDOW_BUTTON_BIAS_X
-            + WINDOW_BUTTON_BETWEEN
+        let x = self.scale_factor.scale(WINDOW_BUTTON_BIAS_X)
+            + self.scale_factor.scale(WINDOW_BUTTON_BETWEEN)
                 * match button {
                     TopButton::Close => 0,
                     TopButton::MinimizeMaximize => 1,
@@ -468,7 +710,7 @@ impl Window {
         framebuffer_drawer::draw_circle(
             inner.framebuffer_mut(),
             Coord::new(x as isize, y as isize),
-            WINDOW_BUTTON_SIZE,
+            self.scale_factor.scale(WINDOW_BUTTON_SIZE),
             framebuffer::Pixel::weight_blend(
                 color::BLACK.into(), 
                 color.into(),
@@ -477,14 +719,14 @@ impl Window {
         );
     }
 
-    /// Gets the rectangle occupied by the three buttons
+    /// Gets the area of the screen occupied by the three buttons
     fn get_button_area(&self) -> Rectangle {
         let inner = self.inner.lock();
-        let width = inner.get_size().0;
-        Rectangle {
+        let width = inner.surface_size().0;
+        inner.to_screen_region(Rectangle {
             top_left: Coord::new(0, 0),
             bottom_right: Coord::new(width as isize, inner.title_bar_height as isize)
-        }
+        })
     }
 }
 
@@ -0,0 +1,144 @@
+//! Images of the mouse cursor, which the window manager composites atop all windows.
+//!
+//! A window can supply its own [`CursorImage`] that is shown while the mouse is over that window,
+//! e.g., resize arrows while the mouse is over its border.
+
+use alloc::vec::Vec;
+use color::Color;
+use shapes::Coord;
+
+/// The maximum width and height of a cursor image, in number of unscaled pixels.
+pub const MAX_CURSOR_SIZE: usize = 64;
+
+/// A bitmap image of the mouse cursor.
+///
+/// The image is given in unscaled pixels; the window manager scales it by the display scale factor.
+#[derive(Clone, Debug)]
+pub struct CursorImage {
+    width: usize,
+    height: usize,
+    /// The pixels of the image, row by row.
+    pixels: Vec<Color>,
+    /// The pixel in the image that points at the mouse position,
+    /// relative to the top-left corner of the image.
+    hotspot: Coord,
+}
+
+impl CursorImage {
+    /// Creates a new cursor image that is `width` by `height` pixels in size,
+    /// from the given row-major `pixels`.
+    ///
+    /// The `hotspot` is the pixel in the image that points at the mouse position,
+    /// e.g., the tip of an arrow, relative to the image's top-left corner.
+    ///
+    /// Returns an error if the number of `pixels` doesn't match the given size,
+    /// if either dimension is zero or exceeds [`MAX_CURSOR_SIZE`], or if the `hotspot` is outside the image.
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>, hotspot: Coord) -> Result<CursorImage, &'static str> {
+        if width == 0 || height == 0 || width > MAX_CURSOR_SIZE || height > MAX_CURSOR_SIZE {
+            return Err("cursor image dimensions must be between 1 and MAX_CURSOR_SIZE pixels");
+        }
+        if pixels.len() != width * height {
+            return Err("cursor image has the wrong number of pixels for its dimensions");
+        }
+        if hotspot.x < 0 || hotspot.y < 0 || hotspot.x >= width as isize || hotspot.y >= height as isize {
+            return Err("cursor image hotspot is outside the image");
+        }
+        Ok(CursorImage { width, height, pixels, hotspot })
+    }
+
+    /// Returns the width of this image in unscaled pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of this image in unscaled pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the pixel in this image that points at the mouse position.
+    pub fn hotspot(&self) -> Coord {
+        self.hotspot
+    }
+
+    /// Returns the color of the pixel at the given `x` and `y` position within this image,
+    /// or `None` if that position is outside the image.
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<Color> {
+        if x < self.width && y < self.height {
+            Some(self.pixels[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    /// The default cursor: an arrow pointing up and to the left.
+    pub fn arrow() -> CursorImage {
+        Self::from_rows(&[
+            "B..........",
+            "BB.........",
+            "BCB........",
+            "BCCB.......",
+            "BCCCB......",
+            "BCCCCB.....",
+            "BCCCCCB....",
+            "BCCCCCCB...",
+            "BCCCCCCCB..",
+            "BCCCCCCCCB.",
+            "BCCCCCCBBBB",
+            "BCCCCCB....",
+            "BCCBCCB....",
+            "BCB.BCCB...",
+            "BB..BCCB...",
+            "B....BCCB..",
+            ".....BCBB..",
+            "......B....",
+        ], Coord::new(0, 0))
+    }
+
+    /// A double-headed arrow pointing left and right, e.g., for resizing a window horizontally.
+    pub fn resize_horizontal() -> CursorImage {
+        Self::from_rows(&RESIZE_HORIZONTAL, Coord::new(6, 3))
+    }
+
+    /// A double-headed arrow pointing up and down, e.g., for resizing a window vertically.
+    pub fn resize_vertical() -> CursorImage {
+        let horizontal = Self::resize_horizontal();
+        let (width, height) = (horizontal.height, horizontal.width);
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| horizontal.pixels[x * horizontal.width + y])
+            .collect();
+        let hotspot = Coord::new(horizontal.hotspot.y, horizontal.hotspot.x);
+        CursorImage { width, height, pixels, hotspot }
+    }
+
+    /// Creates an image from rows of characters, in which `B` is a white border pixel,
+    /// `C` is a black pixel, and any other character is transparent.
+    fn from_rows(rows: &[&str], hotspot: Coord) -> CursorImage {
+        let pixels = rows.iter()
+            .flat_map(|row| row.bytes())
+            .map(|b| match b {
+                b'B' => color::WHITE,
+                b'C' => color::BLACK,
+                _ => color::TRANSPARENT,
+            })
+            .collect();
+        CursorImage { width: rows[0].len(), height: rows.len(), pixels, hotspot }
+    }
+}
+
+impl Default for CursorImage {
+    fn default() -> Self {
+        Self::arrow()
+    }
+}
+
+const RESIZE_HORIZONTAL: [&str; 7] = [
+    "...B.....B...",
+    "..BCB...BCB..",
+    ".BCCBBBBBCCB.",
+    "BCCCCCCCCCCCB",
+    ".BCCBBBBBCCB.",
+    "..BCB...BCB..",
+    "...B.....B...",
+];
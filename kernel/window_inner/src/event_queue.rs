@@ -0,0 +1,143 @@
+//! A bounded queue of events sent from the window manager to a window,
+//! which coalesces redundant events and counts the events it had to drop.
+//!
+//! If an application stops handling its window's events for a while,
+//! the mouse alone can produce enough `MousePositionEvent`s to fill its queue,
+//! after which keyboard events would be dropped. To prevent this:
+//! * A `MousePositionEvent` replaces the previous event if that is also a `MousePositionEvent`
+//!   with the same button state, because only the latest position matters.
+//!   Events that differ in their button state are kept, so clicks are never lost,
+//!   and scroll events are never replaced, as each one is a separate scroll tick.
+//! * A `WindowResizeEvent` replaces the previous event if that is also a `WindowResizeEvent`.
+//! * When the queue is full, a `MousePositionEvent` is dropped rather than queued,
+//!   while any other event evicts the oldest queued `MousePositionEvent` to make room.
+//!   Only if no `MousePositionEvent` is queued is the new event dropped.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use event_types::{Event, MousePositionEvent};
+use spin::Mutex;
+
+/// The capacity of a window's event queue unless changed via [`EventQueue::set_capacity()`].
+pub const DEFAULT_EVENT_QUEUE_CAPACITY: usize = 100;
+
+/// A bounded queue of events that can be shared between the window manager and a window.
+///
+/// Cloning an `EventQueue` returns another reference to the same queue.
+#[derive(Clone)]
+pub struct EventQueue {
+    inner: Arc<Mutex<EventQueueInner>>,
+}
+
+struct EventQueueInner {
+    events: VecDeque<Event>,
+    capacity: usize,
+    /// The number of events dropped because the queue was full.
+    dropped: usize,
+}
+
+impl EventQueue {
+    /// Creates a new, empty queue that holds at most `capacity` events.
+    pub fn with_capacity(capacity: usize) -> EventQueue {
+        EventQueue {
+            inner: Arc::new(Mutex::new(EventQueueInner {
+                events: VecDeque::new(),
+                capacity: capacity.max(1),
+                dropped: 0,
+            })),
+        }
+    }
+
+    /// Pushes the given `event` onto the end of the queue,
+    /// coalescing it with the previous event if possible.
+    ///
+    /// If the queue is full and no room could be made for the `event`,
+    /// it is counted as dropped and returned as `Err(event)`.
+    pub fn push(&self, event: Event) -> Result<(), Event> {
+        let mut inner = self.inner.lock();
+        if let Some(last) = inner.events.back_mut() {
+            if coalesces_with(last, &event) {
+                *last = event;
+                return Ok(());
+            }
+        }
+
+        if inner.events.len() >= inner.capacity {
+            let evicted = match event {
+                Event::MousePositionEvent(_) => None,
+                _ => inner.events.iter()
+                    .position(|e| matches!(e, Event::MousePositionEvent(_)))
+                    .and_then(|i| inner.events.remove(i)),
+            };
+            inner.dropped += 1;
+            if evicted.is_none() {
+                return Err(event);
+            }
+        }
+        inner.events.push_back(event);
+        Ok(())
+    }
+
+    /// Removes and returns the event at the front of the queue, if any.
+    pub fn pop(&self) -> Option<Event> {
+        self.inner.lock().events.pop_front()
+    }
+
+    /// Returns the number of events currently in the queue.
+    pub fn len(&self) -> usize {
+        self.inner.lock().events.len()
+    }
+
+    /// Returns `true` if there are no events in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().events.is_empty()
+    }
+
+    /// Returns the maximum number of events the queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().capacity
+    }
+
+    /// Sets the maximum number of events the queue can hold, which must be at least one.
+    ///
+    /// If the queue holds more than `capacity` events, the newest ones are dropped.
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock();
+        inner.capacity = capacity.max(1);
+        let excess = inner.events.len().saturating_sub(inner.capacity);
+        if excess > 0 {
+            let new_len = inner.capacity;
+            inner.events.truncate(new_len);
+            inner.dropped += excess;
+        }
+    }
+
+    /// Returns the number of events that have been dropped because the queue was full,
+    /// including queued `MousePositionEvent`s that were evicted to make room for other events.
+    ///
+    /// Coalesced events are not counted as dropped.
+    pub fn dropped_events(&self) -> usize {
+        self.inner.lock().dropped
+    }
+}
+
+/// Returns whether the `next` event makes the queued `prev` event redundant.
+fn coalesces_with(prev: &Event, next: &Event) -> bool {
+    match (prev, next) {
+        (Event::MousePositionEvent(prev), Event::MousePositionEvent(next)) => {
+            !is_scroll(prev) && !is_scroll(next) && same_buttons(prev, next)
+        }
+        (Event::WindowResizeEvent(_), Event::WindowResizeEvent(_)) => true,
+        _ => false,
+    }
+}
+
+fn is_scroll(event: &MousePositionEvent) -> bool {
+    event.scrolling_up || event.scrolling_down
+}
+
+fn same_buttons(a: &MousePositionEvent, b: &MousePositionEvent) -> bool {
+    a.left_button_hold == b.left_button_hold
+        && a.right_button_hold == b.right_button_hold
+        && a.fourth_button_hold == b.fourth_button_hold
+        && a.fifth_button_hold == b.fifth_button_hold
+}
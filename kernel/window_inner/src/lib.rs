@@ -12,33 +12,134 @@
 //! 
 //! It also allows the window manager to control the window, e.g., move, hide, show, or resize it
 //! in a way that applications may not be able to do.
+//!
+//! This crate also accounts for the memory used by the framebuffers of all `WindowInner`s,
+//! which is initially capped at `kernel_config::display::WINDOW_BUFFER_LIMIT`
+//! and can be changed via [`set_window_buffer_limit()`].
+//! See [`window_buffer_stats()`].
+//!
+//! A window's framebuffer can be rendered at a lower logical resolution and scaled up by an integer factor
+//! when it is composited onto the screen, e.g., 2x on a high-resolution screen; see [`WindowInner::surface_scale()`].
+//! Positions and sizes relative to the screen are in screen pixels,
+//! whereas the window's framebuffer, content area, and dirty regions are in the framebuffer's (logical) pixels.
+//!
+//! A window can optionally be double buffered; see [`WindowInner::enable_double_buffering()`].
+//!
+//! Events are sent to a window through an [`EventQueue`], which coalesces redundant mouse and resize events
+//! and counts the events it had to drop because the window's application wasn't keeping up.
 
 #![no_std]
 
-extern crate mpmc;
+extern crate alloc;
+extern crate spin;
+extern crate display_scale;
+extern crate kernel_config;
 extern crate event_types;
 extern crate framebuffer;
 extern crate shapes;
+extern crate color;
 
-use mpmc::Queue;
+mod cursor;
+mod event_queue;
+
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use event_types::{Event};
-use framebuffer::{Framebuffer, AlphaPixel};
+pub use event_types::WindowState;
+pub use cursor::{CursorImage, MAX_CURSOR_SIZE};
+pub use event_queue::{EventQueue, DEFAULT_EVENT_QUEUE_CAPACITY};
+use framebuffer::{Framebuffer, AlphaPixel, MAX_SCALE};
 use shapes::{Coord, Rectangle};
 
 
-// The title bar height, in number of pixels
+// The title bar height, in number of unscaled pixels
 pub const DEFAULT_TITLE_BAR_HEIGHT: usize = 16;
-// left, right, bottom border size, in number of pixels
+// left, right, bottom border size, in number of unscaled pixels
 pub const DEFAULT_BORDER_SIZE: usize = 2;
 
 
+/// The total size in bytes of the framebuffers of all existing `WindowInner`s.
+static WINDOW_BUFFER_BYTES: AtomicUsize = AtomicUsize::new(0);
+/// The number of existing `WindowInner`s.
+static NUM_WINDOWS: AtomicUsize = AtomicUsize::new(0);
+/// The maximum value of `WINDOW_BUFFER_BYTES`, or `usize::MAX` if there is no limit.
+static WINDOW_BUFFER_LIMIT: AtomicUsize = AtomicUsize::new(match kernel_config::display::WINDOW_BUFFER_LIMIT {
+    Some(limit) => limit,
+    None => usize::MAX,
+});
+
+/// Statistics about the memory used by window framebuffers.
+#[derive(Clone, Copy, Debug)]
+pub struct WindowBufferStats {
+    /// The number of existing windows.
+    pub num_windows: usize,
+    /// The total size in bytes of all windows' framebuffers.
+    pub total_bytes: usize,
+    /// The maximum total size in bytes of all windows' framebuffers, if limited.
+    pub limit: Option<usize>,
+}
+
+/// Returns statistics about the memory currently used by window framebuffers.
+pub fn window_buffer_stats() -> WindowBufferStats {
+    let limit = WINDOW_BUFFER_LIMIT.load(Ordering::Relaxed);
+    WindowBufferStats {
+        num_windows: NUM_WINDOWS.load(Ordering::Relaxed),
+        total_bytes: WINDOW_BUFFER_BYTES.load(Ordering::Relaxed),
+        limit: (limit != usize::MAX).then_some(limit),
+    }
+}
+
+/// Sets the maximum total size in bytes of all windows' framebuffers, or removes the limit if `None`.
+///
+/// Once the limit is reached, creating a window or growing an existing one fails.
+/// Lowering the limit below the current usage doesn't affect existing windows.
+pub fn set_window_buffer_limit(limit: Option<usize>) {
+    WINDOW_BUFFER_LIMIT.store(limit.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+/// Returns the size in bytes of a window framebuffer with the given dimensions.
+pub const fn window_buffer_size(width: usize, height: usize) -> usize {
+    width * height * core::mem::size_of::<AlphaPixel>()
+}
+
+const WINDOW_BUFFER_LIMIT_ERROR: &str = "window framebuffer memory limit exceeded; close other windows or raise the limit";
+
+/// Adds the given number of bytes to the window buffer usage, if it doesn't exceed the limit.
+fn reserve_window_buffer_bytes(bytes: usize) -> Result<(), &'static str> {
+    let limit = WINDOW_BUFFER_LIMIT.load(Ordering::Relaxed);
+    WINDOW_BUFFER_BYTES.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total|
+        total.checked_add(bytes).filter(|&new_total| new_total <= limit)
+    )
+    .map(|_| ())
+    .map_err(|_| WINDOW_BUFFER_LIMIT_ERROR)
+}
+
+/// Removes the given number of bytes from the window buffer usage.
+fn release_window_buffer_bytes(bytes: usize) {
+    WINDOW_BUFFER_BYTES.fetch_sub(bytes, Ordering::Relaxed);
+}
+
+
+/// The maximum number of separate dirty regions tracked for a window,
+/// beyond which they are merged into a single region covering all of them.
+const MAX_DIRTY_REGIONS: usize = 16;
+
+/// The number of pixels of a window that must remain on the screen after it is moved,
+/// such that it can always be dragged back.
+const MIN_VISIBLE_PIXELS: isize = 16;
+
 /// Whether a window is moving (being dragged by the mouse).
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum WindowMovingStatus {
     /// The window is not in motion.
     Stationary,
-    /// The window is currently in motion. 
-    /// The enclosed `Coord` represents the initial position of the window before it started moving.
-    Moving(Coord),
+    /// The window is currently in motion.
+    Moving {
+        /// The screen coordinate of the mouse when the window started moving.
+        initial: Coord,
+        /// How far the mouse has moved since the window started moving.
+        delta: Coord,
+    },
 }
 
 /// The `WindowInner` struct is the internal system-facing representation of a window. 
@@ -69,44 +170,134 @@ pub struct WindowInner {
     /// 
     /// The corresponding consumer for this event queue is found in the `Window` struct
     /// that created and owns this `WindowInner` instance.
-    event_producer: Queue<Event>, // event output used by window manager
+    event_producer: EventQueue, // event output used by window manager
     /// The virtual framebuffer that is used exclusively for rendering only this window.
+    /// If this window is double buffered, this is the front buffer that is composited onto the screen.
     framebuffer: Framebuffer<AlphaPixel>,
+    /// The buffer that the application draws this window's content into, if this window is double buffered.
+    back_buffer: Option<Framebuffer<AlphaPixel>>,
+    /// The size of `framebuffer` and `back_buffer` in bytes, as accounted for in the window buffer usage.
+    buffer_bytes: usize,
     /// Whether a window is moving or stationary.
-    /// 
-    /// TODO: FIXME (kevinaboos): this should be private, and window moving logic should be moved into this crate.
-    pub moving: WindowMovingStatus,
+    moving: WindowMovingStatus,
+    /// Whether this window is minimized, maximized, or neither.
+    state: WindowState,
+    /// The state this window was in before it was minimized, which it returns to when restored.
+    state_before_minimize: WindowState,
+    /// The position and size of this window before it was maximized, which it returns to when restored.
+    normal_position: Option<Rectangle>,
+    /// The regions of this window that have changed since they were last composited onto the screen,
+    /// relative to the top-left corner of this window. These regions never overlap each other.
+    dirty_regions: Vec<Rectangle>,
+    /// How opaque this whole window is when composited onto the screen,
+    /// from `0` (invisible) to `255` (fully opaque).
+    opacity: u8,
+    /// The cursor image shown while the mouse is over this window, or `None` for the default cursor.
+    cursor: Option<Arc<CursorImage>>,
+    /// How many screen pixels each pixel of `framebuffer` covers along each axis.
+    surface_scale: usize,
+    /// The ID of the task that created this window, if known.
+    owner_task: Option<usize>,
 }
 
 impl WindowInner {
     /// Creates a new `WindowInner` object backed by the given `framebuffer`
     /// and that will be rendered at the given `coordinate` relative to the screen.
+    ///
+    /// The `framebuffer` is scaled up by `surface_scale` when it is composited onto the screen;
+    /// see [`surface_scale()`](Self::surface_scale).
+    /// The title bar and border sizes are scaled by the current display scale factor,
+    /// unless the `surface_scale` is greater than `1`, in which case they're already scaled up along with the framebuffer.
+    ///
+    /// Returns an error if the `surface_scale` is `0` or greater than [`MAX_SCALE`],
+    /// or if the `framebuffer` would exceed the window buffer limit; see [`set_window_buffer_limit()`].
     pub fn new(
         coordinate: Coord,
         framebuffer: Framebuffer<AlphaPixel>,
-        event_producer: Queue<Event>,
-    ) -> WindowInner {
-        WindowInner {
+        surface_scale: usize,
+        event_producer: EventQueue,
+    ) -> Result<WindowInner, &'static str> {
+        if surface_scale == 0 || surface_scale > MAX_SCALE {
+            return Err("window surface scale must be between 1 and framebuffer::MAX_SCALE");
+        }
+        let decoration_size = |unscaled: usize| if surface_scale > 1 { unscaled } else { display_scale::scaled(unscaled) };
+        let (width, height) = framebuffer.get_size();
+        let buffer_bytes = window_buffer_size(width, height);
+        reserve_window_buffer_bytes(buffer_bytes)?;
+        NUM_WINDOWS.fetch_add(1, Ordering::Relaxed);
+        Ok(WindowInner {
             coordinate,
-            border_size: DEFAULT_BORDER_SIZE,
-            title_bar_height: DEFAULT_TITLE_BAR_HEIGHT,
+            border_size: decoration_size(DEFAULT_BORDER_SIZE),
+            title_bar_height: decoration_size(DEFAULT_TITLE_BAR_HEIGHT),
             event_producer,
             framebuffer,
+            back_buffer: None,
+            buffer_bytes,
             moving: WindowMovingStatus::Stationary,
-        }
+            state: WindowState::Normal,
+            state_before_minimize: WindowState::Normal,
+            normal_position: None,
+            dirty_regions: Vec::new(),
+            opacity: u8::MAX,
+            cursor: None,
+            surface_scale,
+            owner_task: None,
+        })
     }
 
-    /// Returns `true` if the given `coordinate` (relative to the top-left corner of this window)
+    /// Returns `true` if the given `coordinate` (relative to the top-left corner of this window, in screen pixels)
     /// is within the bounds of this window.
     pub fn contains(&self, coordinate: Coord) -> bool {
-        self.framebuffer.contains(coordinate)
+        self.framebuffer.contains(self.to_surface_coordinate(coordinate))
     }
 
-    /// Gets the size of a window in pixels
+    /// Gets the size of a window on the screen, in screen pixels.
     pub fn get_size(&self) -> (usize, usize) {
+        let (width, height) = self.surface_size();
+        (width * self.surface_scale, height * self.surface_scale)
+    }
+
+    /// Gets the size of this window's framebuffer, in its own (logical) pixels.
+    ///
+    /// This equals [`get_size()`](Self::get_size) unless the [`surface_scale()`](Self::surface_scale) is greater than `1`.
+    pub fn surface_size(&self) -> (usize, usize) {
         self.framebuffer.get_size()
     }
 
+    /// Returns how many screen pixels each pixel of this window's framebuffer covers along each axis.
+    ///
+    /// A window with a surface scale of `2` is rendered at half the screen resolution
+    /// and scaled up 2x when it is composited onto the screen.
+    pub fn surface_scale(&self) -> usize {
+        self.surface_scale
+    }
+
+    /// Returns the ID of the task that owns this window, i.e., that receives its keyboard input, if known.
+    pub fn owner_task(&self) -> Option<usize> {
+        self.owner_task
+    }
+
+    /// Sets the ID of the task that owns this window.
+    pub fn set_owner_task(&mut self, task_id: usize) {
+        self.owner_task = Some(task_id);
+    }
+
+    /// Converts the given `coordinate` relative to the top-left corner of this window in screen pixels
+    /// to the coordinate of the framebuffer pixel that covers it.
+    pub fn to_surface_coordinate(&self, coordinate: Coord) -> Coord {
+        let scale = self.surface_scale as isize;
+        Coord::new(coordinate.x.div_euclid(scale), coordinate.y.div_euclid(scale))
+    }
+
+    /// Converts the given `region` of this window's framebuffer to the area of the screen that it covers.
+    pub fn to_screen_region(&self, region: Rectangle) -> Rectangle {
+        let scale = self.surface_scale as isize;
+        Rectangle {
+            top_left: self.coordinate + (region.top_left.x * scale, region.top_left.y * scale),
+            bottom_right: self.coordinate + (region.bottom_right.x * scale, region.bottom_right.y * scale),
+        }
+    }
+
     /// Gets the top-left position of the window relative to the top-left of the screen
     pub fn get_position(&self) -> Coord {
         self.coordinate
@@ -117,6 +308,211 @@ impl WindowInner {
         self.coordinate = coordinate;
     }
 
+    /// Returns whether this window is moving or stationary.
+    pub fn moving_status(&self) -> WindowMovingStatus {
+        self.moving
+    }
+
+    /// Returns `true` if this window is currently being moved.
+    pub fn is_moving(&self) -> bool {
+        matches!(self.moving, WindowMovingStatus::Moving { .. })
+    }
+
+    /// Starts moving this window, e.g., when the user presses the mouse on its title bar.
+    ///
+    /// `initial` is the screen coordinate of the mouse when the move started.
+    /// The window's position does not change until [`finish_move()`](Self::finish_move).
+    pub fn start_move(&mut self, initial: Coord) {
+        self.moving = WindowMovingStatus::Moving { initial, delta: Coord::new(0, 0) };
+    }
+
+    /// Records that the mouse has moved by `delta` since this window started moving.
+    ///
+    /// Does nothing if this window is not moving.
+    pub fn update_move(&mut self, delta: Coord) {
+        if let WindowMovingStatus::Moving { delta: ref mut current_delta, .. } = self.moving {
+            *current_delta = delta;
+        }
+    }
+
+    /// Returns the top-left position that this window would be moved to
+    /// if the current move were finished, or `None` if this window is not moving.
+    ///
+    /// The position is clamped such that the window's title bar stays within the vertical bounds
+    /// of a screen of the given `screen_size`, and at least a few pixels of the window
+    /// remain within its horizontal bounds.
+    pub fn move_target(&self, screen_size: (usize, usize)) -> Option<Coord> {
+        let WindowMovingStatus::Moving { delta, .. } = self.moving else {
+            return None;
+        };
+        let (width, _height) = self.get_size();
+        let (screen_width, screen_height) = (screen_size.0 as isize, screen_size.1 as isize);
+        let target = self.coordinate + (delta.x, delta.y);
+
+        let min_x = MIN_VISIBLE_PIXELS.min(width as isize) - width as isize;
+        let max_x = (screen_width - MIN_VISIBLE_PIXELS).max(min_x);
+        let max_y = (screen_height - (self.title_bar_height * self.surface_scale) as isize).max(0);
+        Some(Coord::new(target.x.clamp(min_x, max_x), target.y.clamp(0, max_y)))
+    }
+
+    /// Finishes moving this window, setting its position to its [`move_target()`](Self::move_target).
+    ///
+    /// Returns the window's previous top-left position, or `None` if this window was not moving.
+    pub fn finish_move(&mut self, screen_size: (usize, usize)) -> Option<Coord> {
+        let target = self.move_target(screen_size)?;
+        let previous = self.coordinate;
+        self.coordinate = target;
+        self.moving = WindowMovingStatus::Stationary;
+        Some(previous)
+    }
+
+    /// Stops moving this window without changing its position.
+    pub fn cancel_move(&mut self) {
+        self.moving = WindowMovingStatus::Stationary;
+    }
+
+    /// Returns whether this window is minimized, maximized, or neither.
+    pub fn state(&self) -> WindowState {
+        self.state
+    }
+
+    /// Returns `true` if this window is minimized, meaning that it should not be drawn to the screen.
+    pub fn is_minimized(&self) -> bool {
+        self.state == WindowState::Minimized
+    }
+
+    /// Minimizes this window, which hides it until it is restored.
+    ///
+    /// This only changes the window's state; the window manager is responsible for
+    /// no longer drawing it and for redrawing the area it used to cover.
+    /// A [`Event::WindowStateChangeEvent`] is sent to this window.
+    pub fn minimize(&mut self) -> Result<(), &'static str> {
+        if self.state == WindowState::Minimized {
+            return Ok(());
+        }
+        self.moving = WindowMovingStatus::Stationary;
+        self.state_before_minimize = self.state;
+        self.set_state(WindowState::Minimized)
+    }
+
+    /// Maximizes this window by resizing it to fill the given `screen_area`.
+    ///
+    /// The window's current position and size are saved such that [`restore()`](Self::restore)
+    /// can return the window to them. Both a [`Event::WindowResizeEvent`]
+    /// and a [`Event::WindowStateChangeEvent`] are sent to this window.
+    pub fn maximize(&mut self, screen_area: Rectangle) -> Result<(), &'static str> {
+        if self.state == WindowState::Maximized {
+            return Ok(());
+        }
+        let normal_position = self.normal_position.unwrap_or_else(|| self.bounding_box());
+        self.resize(screen_area)?;
+        self.normal_position = Some(normal_position);
+        self.set_state(WindowState::Maximized)
+    }
+
+    /// Restores this window from being minimized or maximized.
+    ///
+    /// A minimized window returns to the state it was in before it was minimized,
+    /// i.e., a window that was maximized before being minimized is still maximized.
+    /// A maximized window is resized back to the position and size it had before it was maximized.
+    /// A [`Event::WindowStateChangeEvent`] is sent to this window if its state changed.
+    pub fn restore(&mut self) -> Result<(), &'static str> {
+        match self.state {
+            WindowState::Normal => Ok(()),
+            WindowState::Minimized => self.set_state(self.state_before_minimize),
+            WindowState::Maximized => {
+                if let Some(normal_position) = self.normal_position {
+                    self.resize(normal_position)?;
+                }
+                self.normal_position = None;
+                self.set_state(WindowState::Normal)
+            }
+        }
+    }
+
+    /// Sets this window's state and notifies this window of the change.
+    fn set_state(&mut self, state: WindowState) -> Result<(), &'static str> {
+        self.state = state;
+        self.send_event(Event::new_window_state_change_event(state))
+            .map_err(|_e| "Failed to enqueue the window state change event; window event queue was full.")
+    }
+
+    /// Returns how opaque this whole window is, from `0` (invisible) to `255` (fully opaque).
+    pub fn opacity(&self) -> u8 {
+        self.opacity
+    }
+
+    /// Sets how opaque this whole window is, from `0` (invisible) to `255` (fully opaque).
+    ///
+    /// The compositor multiplies the `opacity` into the transparency of each of this window's pixels.
+    /// The window manager is responsible for redrawing the window afterwards.
+    pub fn set_opacity(&mut self, opacity: u8) {
+        self.opacity = opacity;
+    }
+
+    /// Returns the cursor image shown while the mouse is over this window,
+    /// or `None` if the default cursor is shown.
+    pub fn cursor(&self) -> Option<&Arc<CursorImage>> {
+        self.cursor.as_ref()
+    }
+
+    /// Sets the cursor image shown while the mouse is over this window,
+    /// or restores the default cursor if `cursor` is `None`.
+    ///
+    /// The window manager is responsible for redrawing the cursor afterwards.
+    pub fn set_cursor(&mut self, cursor: Option<CursorImage>) {
+        self.cursor = cursor.map(Arc::new);
+    }
+
+    /// Returns the position and dimensions of this whole window, relative to the top-left of the screen.
+    pub fn bounding_box(&self) -> Rectangle {
+        let (width, height) = self.get_size();
+        Rectangle {
+            top_left: self.coordinate,
+            bottom_right: self.coordinate + (width as isize, height as isize),
+        }
+    }
+
+    /// Records that the given `region` of this window has changed and must be composited onto the screen.
+    ///
+    /// The `region` is relative to the top-left corner of this window's framebuffer and is clipped to its bounds.
+    /// It is merged with any overlapping dirty regions, such that no pixel is blended onto the screen
+    /// more than once when the dirty regions are composited.
+    pub fn mark_dirty(&mut self, region: Rectangle) {
+        let (width, height) = self.surface_size();
+        let window_area = Rectangle {
+            top_left: Coord::new(0, 0),
+            bottom_right: Coord::new(width as isize, height as isize),
+        };
+        let Some(mut region) = region.intersection(&window_area) else {
+            return;
+        };
+        while let Some(i) = self.dirty_regions.iter().position(|r| r.intersection(&region).is_some()) {
+            region = region.union(&self.dirty_regions.swap_remove(i));
+        }
+        self.dirty_regions.push(region);
+
+        if self.dirty_regions.len() > MAX_DIRTY_REGIONS {
+            let merged = self.dirty_regions.iter().fold(region, |merged, r| merged.union(r));
+            self.dirty_regions.clear();
+            self.dirty_regions.push(merged);
+        }
+    }
+
+    /// Returns `true` if any region of this window has been marked as dirty
+    /// since the dirty regions were last taken.
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty_regions.is_empty()
+    }
+
+    /// Removes and returns this window's dirty regions, relative to the top-left corner of this window's framebuffer.
+    /// See [`to_screen_region()`](Self::to_screen_region).
+    ///
+    /// The returned regions do not overlap each other.
+    pub fn take_dirty_regions(&mut self) -> Vec<Rectangle> {
+        core::mem::take(&mut self.dirty_regions)
+    }
+
     /// Returns an immutable reference to this window's virtual Framebuffer. 
     pub fn framebuffer(&self) -> &Framebuffer<AlphaPixel> {
         &self.framebuffer
@@ -127,6 +523,89 @@ impl WindowInner {
         &mut self.framebuffer
     }
 
+    /// Returns `true` if this window has a back buffer; see [`enable_double_buffering()`](Self::enable_double_buffering).
+    pub fn is_double_buffered(&self) -> bool {
+        self.back_buffer.is_some()
+    }
+
+    /// Gives this window a back buffer that the application draws into,
+    /// such that the compositor never reads a partially-drawn frame.
+    ///
+    /// The back buffer starts out as a copy of the current framebuffer.
+    /// Its content area is copied to the framebuffer upon [`present()`](Self::present);
+    /// the title bar and border are still drawn directly into the framebuffer.
+    ///
+    /// Returns an error if the back buffer would exceed the window buffer limit.
+    pub fn enable_double_buffering(&mut self) -> Result<(), &'static str> {
+        if self.back_buffer.is_some() {
+            return Ok(());
+        }
+        let (width, height) = self.surface_size();
+        let back_buffer_bytes = window_buffer_size(width, height);
+        reserve_window_buffer_bytes(back_buffer_bytes)?;
+        let mut back_buffer = match Framebuffer::new(width, height, None) {
+            Ok(back_buffer) => back_buffer,
+            Err(e) => {
+                release_window_buffer_bytes(back_buffer_bytes);
+                return Err(e);
+            }
+        };
+        back_buffer.buffer_mut().copy_from_slice(self.framebuffer.buffer());
+        self.back_buffer = Some(back_buffer);
+        self.buffer_bytes += back_buffer_bytes;
+        Ok(())
+    }
+
+    /// Removes this window's back buffer, after which the application draws directly into its framebuffer again.
+    ///
+    /// Anything drawn into the back buffer that hasn't been presented is discarded.
+    pub fn disable_double_buffering(&mut self) {
+        if self.back_buffer.take().is_some() {
+            let (width, height) = self.surface_size();
+            let back_buffer_bytes = window_buffer_size(width, height);
+            release_window_buffer_bytes(back_buffer_bytes);
+            self.buffer_bytes -= back_buffer_bytes;
+        }
+    }
+
+    /// Returns the framebuffer that the application draws into:
+    /// the back buffer if this window is double buffered, otherwise the framebuffer itself.
+    pub fn draw_buffer(&self) -> &Framebuffer<AlphaPixel> {
+        self.back_buffer.as_ref().unwrap_or(&self.framebuffer)
+    }
+
+    /// Returns a mutable reference to the framebuffer that the application draws into;
+    /// see [`draw_buffer()`](Self::draw_buffer).
+    pub fn draw_buffer_mut(&mut self) -> &mut Framebuffer<AlphaPixel> {
+        self.back_buffer.as_mut().unwrap_or(&mut self.framebuffer)
+    }
+
+    /// Copies the given `region` of the back buffer to the framebuffer,
+    /// such that it is shown when this window is next composited.
+    ///
+    /// The `region` is relative to the top-left corner of this window and is clipped to its content area;
+    /// if `None`, the whole content area is presented.
+    /// Because this window is locked while copying, the compositor sees either the old or the new frame, never a mix.
+    ///
+    /// Returns the region that was presented, or `None` if it was empty.
+    /// If this window is not double buffered, nothing is copied, but the region is still returned.
+    pub fn present(&mut self, region: Option<Rectangle>) -> Option<Rectangle> {
+        let content_area = self.content_area();
+        let region = match region {
+            Some(region) => region.intersection(&content_area)?,
+            None => content_area,
+        };
+        if let Some(back_buffer) = self.back_buffer.as_ref() {
+            let (x_start, x_end) = (region.top_left.x as usize, region.bottom_right.x as usize);
+            for y in region.top_left.y as usize .. region.bottom_right.y as usize {
+                if let (Some(src), Some(dest)) = (back_buffer.row(y), self.framebuffer.row_mut(y)) {
+                    dest[x_start..x_end].copy_from_slice(&src[x_start..x_end]);
+                }
+            }
+        }
+        Some(region)
+    }
+
     /// Returns the pixel value at the given `coordinate`,
     /// if the `coordinate` is within the window's bounds.
     pub fn get_pixel(&self, coordinate: Coord) -> Option<AlphaPixel> {
@@ -148,9 +627,9 @@ impl WindowInner {
     /// Returns the position and dimensions of the Window's content region,
     /// i.e., the area within the window excluding the title bar and border.
     /// 
-    /// The returned `Rectangle` is expressed relative to this Window's position.
+    /// The returned `Rectangle` is expressed relative to the top-left corner of this Window's framebuffer.
     pub fn content_area(&self) -> Rectangle {
-        let (window_width, window_height) = self.get_size();
+        let (window_width, window_height) = self.surface_size();
         // There is one title bar on top, and a border on the left, right, and bottom
         let top_left = Coord::new(self.border_size as isize, self.title_bar_height as isize);
         let bottom_right = Coord::new((window_width - self.border_size) as isize, (window_height - self.border_size) as isize);
@@ -158,10 +637,54 @@ impl WindowInner {
     }
 
     /// Resizes and moves this window to fit the given `Rectangle` that describes its new position. 
+    ///
+    /// The `new_position` is in screen pixels; the new framebuffer is smaller by the [`surface_scale()`](Self::surface_scale),
+    /// rounding down.
+    ///
+    /// This reallocates the window's framebuffer and back buffer (if any),
+    /// so its entire contents (including the title bar and border) must be redrawn. To that end, a [`Event::WindowResizeEvent`] is sent to this window.
+    /// Any move of this window that is in progress is cancelled.
+    ///
+    /// Returns an error and leaves this window unchanged if the new size is too small
+    /// to fit the title bar and border, or if growing it would exceed the window buffer limit.
     pub fn resize(&mut self, new_position: Rectangle) -> Result<(), &'static str> {
-        // First, perform the actual resize of the inner window
+        let new_width = new_position.width() / self.surface_scale;
+        let new_height = new_position.height() / self.surface_scale;
+        if new_width <= 2 * self.title_bar_height
+            || new_height <= self.title_bar_height + self.border_size
+        {
+            return Err("window dimensions must be large enough for the title bar and borders to be drawn");
+        }
+
+        // First, perform the actual resize of the inner window,
+        // accounting for the new framebuffer's size before allocating it.
+        let num_buffers = if self.back_buffer.is_some() { 2 } else { 1 };
+        let new_buffer_bytes = num_buffers * window_buffer_size(new_width, new_height);
+        let growth = new_buffer_bytes.saturating_sub(self.buffer_bytes);
+        reserve_window_buffer_bytes(growth)?;
+        let new_back_buffer = match self.back_buffer {
+            Some(_) => Framebuffer::new(new_width, new_height, None).map(Some),
+            None => Ok(None),
+        };
+        let new_buffers = new_back_buffer.and_then(|back_buffer|
+            Framebuffer::new(new_width, new_height, None)
+                .map(|framebuffer| (framebuffer, back_buffer))
+        );
+        let (new_framebuffer, new_back_buffer) = match new_buffers {
+            Ok(new_buffers) => new_buffers,
+            Err(e) => {
+                release_window_buffer_bytes(growth);
+                return Err(e);
+            }
+        };
+        self.framebuffer = new_framebuffer;
+        self.back_buffer = new_back_buffer;
+        release_window_buffer_bytes(self.buffer_bytes.saturating_sub(new_buffer_bytes));
+        self.buffer_bytes = new_buffer_bytes;
         self.coordinate = new_position.top_left;
-        self.framebuffer = Framebuffer::new(new_position.width(), new_position.height(), None)?;
+        self.moving = WindowMovingStatus::Stationary;
+        // The whole window must be redrawn and composited anyway.
+        self.dirty_regions.clear();
 
         // Second, send a resize event to that application window (the `Window` object) 
         // so it knows to refresh its display.
@@ -177,8 +700,21 @@ impl WindowInner {
 
     /// Sends the given `event` to this window.
     /// 
+    /// The `event` may be coalesced with the previously-sent event; see [`EventQueue::push()`].
     /// If the event queue was full, `Err(event)` is returned.
     pub fn send_event(&self, event: Event) -> Result<(), Event> {
         self.event_producer.push(event)
     }
+
+    /// Returns the number of events that were dropped because this window's event queue was full.
+    pub fn dropped_events(&self) -> usize {
+        self.event_producer.dropped_events()
+    }
+}
+
+impl Drop for WindowInner {
+    fn drop(&mut self) {
+        release_window_buffer_bytes(self.buffer_bytes);
+        NUM_WINDOWS.fetch_sub(1, Ordering::Relaxed);
+    }
 }
@@ -2,10 +2,16 @@
 //!
 //! A window manager holds a set of `WindowInner` objects, including an active window, a list of shown windows and a list of hidden windows. The hidden windows are totally overlapped by others.
 //!
-//! A window manager owns a bottom framebuffer and a top framebuffer. The bottom is the background of the desktop and the top framebuffer contains a floating window border and a mouse arrow. 
-//! A window manager also contains a final framebuffer which is mapped to the screen. In refreshing an area, the manager will render all the framebuffers to the final one in order: bottom -> hide list -> showlist -> active -> top.
+//! A window manager owns a bottom framebuffer and a top framebuffer. The bottom is the background of the desktop and the top framebuffer contains a floating window border. 
+//! The mouse cursor is drawn into its own small framebuffer, which is composited atop everything else at the mouse position,
+//! so it never touches any window's contents. Windows can supply their own cursor image; see [`CursorImage`].
+//! A window manager also contains a final framebuffer which is mapped to the screen. In refreshing an area, the manager will render all the framebuffers to the final one in order: bottom -> hide list -> showlist -> active -> top -> cursor.
 //!
 //! The window manager provides methods to update within some bounding boxes rather than the whole screen for better performance.
+//!
+//! The total memory used by all windows' framebuffers is tracked and can be capped;
+//! see [`window_buffer_stats()`] and [`set_window_buffer_limit()`].
+//! It is also reported as `"window buffers"` via `memory_pressure::memory_usage()`.
 
 #![no_std]
 
@@ -15,6 +21,7 @@ extern crate alloc;
 extern crate mpmc;
 extern crate event_types;
 extern crate compositor;
+extern crate display_scale;
 extern crate framebuffer;
 extern crate framebuffer_compositor;
 extern crate framebuffer_drawer;
@@ -27,12 +34,22 @@ extern crate spawn;
 extern crate window_inner;
 extern crate shapes;
 extern crate color;
+extern crate fb_console;
+extern crate hotkeys;
+extern crate task;
+extern crate clipboard;
+extern crate memory_pressure;
+extern crate profile_scope;
+#[cfg(target_arch = "x86_64")]
+extern crate keyboard;
 
 use alloc::collections::VecDeque;
 use alloc::string::ToString;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
+use profile_scope::profile_scope;
 use compositor::{Compositor, FramebufferUpdates, CompositableRegion};
+use display_scale::ScaleFactor;
 
 use mpmc::Queue;
 use event_types::{Event, MousePositionEvent};
@@ -40,38 +57,19 @@ use framebuffer::{Framebuffer, AlphaPixel};
 use color::Color;
 use shapes::{Coord, Rectangle};
 use framebuffer_compositor::{FRAME_COMPOSITOR};
-use keycodes_ascii::{KeyAction, KeyEvent, Keycode};
-use mouse_data::MouseEvent;
+use hotkeys::Hotkey;
+use keycodes_ascii::{KeyEvent, Keycode};
+use mouse_data::{MouseEvent, MouseMovementRelative, MousePositionAbsolute};
 use spin::{Mutex, Once};
+use task::{ExitValue, JoinableTaskRef};
 use window_inner::{WindowInner, WindowMovingStatus};
+pub use window_inner::CursorImage;
+pub use window_inner::{WindowBufferStats, window_buffer_stats, set_window_buffer_limit};
 
 /// The instance of the default window manager
 pub static WINDOW_MANAGER: Once<Mutex<WindowManager>> = Once::new();
 
-/// The width and height size of mouse in number of pixels.
-const MOUSE_POINTER_SIZE_Y: usize = 18;
-const MOUSE_POINTER_SIZE_X: usize = 11;
-/// The mouse pointer image defined as a 2-D pixel array.
-static MOUSE_POINTER_IMAGE: [[Color; MOUSE_POINTER_SIZE_Y]; MOUSE_POINTER_SIZE_X] = {
-    const T: Color = color::TRANSPARENT;
-    const C: Color = color::BLACK; // Cursor
-    const B: Color = color::WHITE; // Border
-    [
-        [B, B, B, B, B, B, B, B, B, B, B, B, B, B, B, B, T, T],
-        [T, B, C, C, C, C, C, C, C, C, C, C, C, C, B, T, T, T],
-        [T, T, B, C, C, C, C, C, C, C, C, C, C, B, T, T, T, T],
-        [T, T, T, B, C, C, C, C, C, C, C, C, B, T, T, T, T, T],
-        [T, T, T, T, B, C, C, C, C, C, C, C, C, B, B, T, T, T],
-        [T, T, T, T, T, B, C, C, C, C, C, C, C, C, C, B, B, T],
-        [T, T, T, T, T, T, B, C, C, C, C, B, B, C, C, C, C, B],
-        [T, T, T, T, T, T, T, B, C, C, B, T, T, B, B, C, B, T],
-        [T, T, T, T, T, T, T, T, B, C, B, T, T, T, T, B, B, T],
-        [T, T, T, T, T, T, T, T, T, B, B, T, T, T, T, T, T, T],
-        [T, T, T, T, T, T, T, T, T, T, B, T, T, T, T, T, T, T],
-    ]
-};
-
-// the border indicating new window position and size
+// the border indicating new window position and size, in number of unscaled pixels
 const WINDOW_BORDER_SIZE: usize = 3;
 // border's inner color
 const WINDOW_BORDER_COLOR_INNER: Color = Color::new(0x00CA6F1E);
@@ -92,10 +90,20 @@ pub struct WindowManager {
     /// which is displayed by default when no other windows exist on top of it.
     bottom_fb: Framebuffer<AlphaPixel>,
     /// The top framebuffer is used for overlaying visual elements atop the rest of the windows, 
-    /// e.g., the mouse pointer, the border of a window being dragged/moved, etc. 
+    /// e.g., the border of a window being dragged/moved. 
     top_fb: Framebuffer<AlphaPixel>,
+    /// The framebuffer holding the (scaled) image of the mouse cursor,
+    /// which is composited atop the top framebuffer such that its hotspot is at the mouse position.
+    cursor_fb: Framebuffer<AlphaPixel>,
+    /// The cursor image currently drawn in `cursor_fb`.
+    cursor: Arc<CursorImage>,
+    /// The cursor image shown when the mouse isn't over a window that supplies its own cursor.
+    default_cursor: Arc<CursorImage>,
     /// The final framebuffer which is mapped to the screen (the actual display device).
     pub final_fb: Framebuffer<AlphaPixel>,
+    /// The display scale factor used for the mouse cursor and the floating window border,
+    /// chosen when the window manager is initialized.
+    scale_factor: ScaleFactor,
 }
 
 impl WindowManager {
@@ -131,6 +139,13 @@ impl WindowManager {
         self.active = Arc::downgrade(inner_ref);
         let area = {
             let window = inner_ref.lock();
+            // The keyboard auto-repeats keys according to the settings of the task that receives them.
+            #[cfg(target_arch = "x86_64")]
+            if let Some(task_id) = window.owner_task() {
+                if let Err(e) = keyboard::apply_task_typematic(task_id) {
+                    debug!("couldn't apply the typematic settings of task {task_id}: {e}");
+                }
+            }
             let top_left = window.get_position();
             let (width, height) = window.get_size();          
             Rectangle {
@@ -217,10 +232,13 @@ impl WindowManager {
         bounding_box: impl IntoIterator<Item = B> + Clone,
         active: bool,
     ) -> Result<(), &'static str> {
+        profile_scope!();
         // bottom framebuffer
         let bottom_fb_area = FramebufferUpdates {
             src_framebuffer: &self.bottom_fb,
             coordinate_in_dest_framebuffer: Coord::new(0, 0),
+            opacity: u8::MAX,
+            scale: 1,
         };
 
         // list of windows to be updated
@@ -241,14 +259,19 @@ impl WindowManager {
             }
         }
 
-        // lock windows
-        let locked_window_list = &window_ref_list.iter().map(|x| x.lock()).collect::<Vec<_>>();
+        // lock windows, skipping minimized windows since they aren't drawn
+        let locked_window_list = &window_ref_list.iter()
+            .map(|x| x.lock())
+            .filter(|window| !window.is_minimized())
+            .collect::<Vec<_>>();
 
         // create updated framebuffer info objects
         let window_bufferlist = locked_window_list.iter().map(|window| {
             FramebufferUpdates {
                 src_framebuffer: window.framebuffer(),
                 coordinate_in_dest_framebuffer: window.get_position(),
+                opacity: window.opacity(),
+                scale: window.surface_scale(),
             }
         });
         
@@ -258,17 +281,26 @@ impl WindowManager {
         Ok(())
     }
 
-    /// Refresh the region of `bounding_box` in the top framebuffer
+    /// Refresh the region of `bounding_box` in the top framebuffer and the mouse cursor atop it.
     pub fn refresh_top<B: CompositableRegion + Clone>(
         &mut self, 
         bounding_box: impl IntoIterator<Item = B> + Clone
     ) -> Result<(), &'static str> {
+        profile_scope!();
         let top_buffer = FramebufferUpdates {
             src_framebuffer: &self.top_fb,
             coordinate_in_dest_framebuffer: Coord::new(0, 0),
+            opacity: u8::MAX,
+            scale: 1,
         }; 
+        let cursor_buffer = FramebufferUpdates {
+            src_framebuffer: &self.cursor_fb,
+            coordinate_in_dest_framebuffer: self.cursor_area().top_left,
+            opacity: u8::MAX,
+            scale: 1,
+        };
 
-        FRAME_COMPOSITOR.lock().composite(Some(top_buffer), &mut self.final_fb, bounding_box)
+        FRAME_COMPOSITOR.lock().composite([top_buffer, cursor_buffer], &mut self.final_fb, bounding_box)
     }
 
     /// Refresh the part in `bounding_box` of every window. `bounding_box` is a region relative to the top-left of the screen. Refresh the whole screen if the bounding box is None.
@@ -276,6 +308,7 @@ impl WindowManager {
         &mut self, 
         bounding_box: impl IntoIterator<Item = B> + Clone,
     ) -> Result<(), &'static str> {
+        profile_scope!();
         // reference of windows
         let mut window_ref_list = Vec::new();
         for window in &self.hide_list {
@@ -293,13 +326,18 @@ impl WindowManager {
             window_ref_list.push(window_ref)
         }
 
-        // lock windows
-        let locked_window_list = &window_ref_list.iter().map(|x| x.lock()).collect::<Vec<_>>();
+        // lock windows, skipping minimized windows since they aren't drawn
+        let locked_window_list = &window_ref_list.iter()
+            .map(|x| x.lock())
+            .filter(|window| !window.is_minimized())
+            .collect::<Vec<_>>();
         // create updated framebuffer info objects
         let bufferlist = locked_window_list.iter().map(|window| {
             FramebufferUpdates {
                 src_framebuffer: window.framebuffer(),
                 coordinate_in_dest_framebuffer: window.get_position(),
+                opacity: window.opacity(),
+                scale: window.surface_scale(),
             }
         });
 
@@ -307,13 +345,38 @@ impl WindowManager {
     }
 
 
+    /// Composites only the regions of the given `window` that have been marked as dirty
+    /// since the last time they were composited, along with the windows beneath them.
+    ///
+    /// Does nothing if no regions of the `window` are dirty.
+    pub fn refresh_dirty_regions(&mut self, window: &Arc<Mutex<WindowInner>>) -> Result<(), &'static str> {
+        let dirty_regions = {
+            let mut inner = window.lock();
+            let regions = inner.take_dirty_regions();
+            if inner.is_minimized() {
+                return Ok(());
+            }
+            regions.into_iter().map(|r| inner.to_screen_region(r)).collect::<Vec<_>>()
+        };
+        if dirty_regions.is_empty() {
+            return Ok(());
+        }
+        self.refresh_windows(dirty_regions.iter().copied())
+    }
+
     /// Refresh the part in `bounding_box` of the active window. `bounding_box` is a region relative to the top-left of the screen. Refresh the whole screen if the bounding box is None.
     pub fn refresh_active_window(&mut self, bounding_box: Option<Rectangle>) -> Result<(), &'static str> {
+        profile_scope!();
         if let Some(window_ref) = self.active.upgrade() {
             let window = window_ref.lock();
+            if window.is_minimized() {
+                return Ok(());
+            }
             let buffer_update = FramebufferUpdates {
                 src_framebuffer: window.framebuffer(),
                 coordinate_in_dest_framebuffer: window.get_position(),
+                opacity: window.opacity(),
+                scale: window.surface_scale(),
             };
             FRAME_COMPOSITOR.lock().composite(Some(buffer_update), &mut self.final_fb, bounding_box)
         } else {
@@ -321,6 +384,145 @@ impl WindowManager {
         } 
     }
     
+    /// Resizes and moves the given `window` to the given `new_position`,
+    /// which is relative to the top-left of the screen.
+    ///
+    /// The window's new framebuffer is blank until it handles the [`Event::WindowResizeEvent`]
+    /// that this sends to it, at which point it should redraw its title bar, border, and content.
+    pub fn resize_window(
+        &mut self,
+        window: &Arc<Mutex<WindowInner>>,
+        new_position: Rectangle,
+    ) -> Result<(), &'static str> {
+        let old_area = {
+            let mut inner = window.lock();
+            let old_top_left = inner.get_position();
+            let (width, height) = inner.get_size();
+            inner.resize(new_position)?;
+            Rectangle {
+                top_left: old_top_left,
+                bottom_right: old_top_left + (width as isize, height as isize),
+            }
+        };
+        // Redraw whatever was beneath the window's old area, along with the window at its new area.
+        self.refresh_bottom_windows([old_area, new_position], true)?;
+        self.refresh_mouse()
+    }
+
+    /// Minimizes the given `window`, such that it is no longer drawn until it is restored.
+    ///
+    /// If the `window` is active, the most recently active window that isn't minimized becomes active instead.
+    pub fn minimize_window(&mut self, window: &Arc<Mutex<WindowInner>>) -> Result<(), &'static str> {
+        let area = {
+            let mut inner = window.lock();
+            inner.minimize()?;
+            inner.bounding_box()
+        };
+        if self.is_active(window) {
+            let next_active = self.show_list.iter()
+                .filter_map(Weak::upgrade)
+                .find(|w| !w.lock().is_minimized());
+            match next_active {
+                Some(next_active) => {
+                    self.set_active(&next_active, false)?;
+                }
+                None => {
+                    // Keep the minimized window around, but no longer give it keyboard input.
+                    self.show_list.push_front(core::mem::take(&mut self.active));
+                }
+            }
+        }
+        self.refresh_bottom_windows(Some(area), true)?;
+        self.refresh_mouse()
+    }
+
+    /// Maximizes the given `window` to fill the whole screen and makes it the active window.
+    pub fn maximize_window(&mut self, window: &Arc<Mutex<WindowInner>>) -> Result<(), &'static str> {
+        let (width, height) = self.get_screen_size();
+        let screen_area = Rectangle {
+            top_left: Coord::new(0, 0),
+            bottom_right: Coord::new(width as isize, height as isize),
+        };
+        let old_area = {
+            let mut inner = window.lock();
+            let old_area = inner.bounding_box();
+            inner.maximize(screen_area)?;
+            old_area
+        };
+        self.set_active(window, false)?;
+        self.refresh_bottom_windows([old_area, screen_area], true)?;
+        self.refresh_mouse()
+    }
+
+    /// Restores the given `window` from being minimized or maximized and makes it the active window.
+    pub fn restore_window(&mut self, window: &Arc<Mutex<WindowInner>>) -> Result<(), &'static str> {
+        let (old_area, new_area) = {
+            let mut inner = window.lock();
+            let old_area = inner.bounding_box();
+            inner.restore()?;
+            (old_area, inner.bounding_box())
+        };
+        self.set_active(window, false)?;
+        self.refresh_bottom_windows([old_area, new_area], true)?;
+        self.refresh_mouse()
+    }
+
+    /// Raises the given `window` to the top of the z-order and makes it the active window.
+    ///
+    /// A minimized window is restored.
+    pub fn raise_window(&mut self, window: &Arc<Mutex<WindowInner>>) -> Result<(), &'static str> {
+        if window.lock().is_minimized() {
+            return self.restore_window(window);
+        }
+        self.set_active(window, true)?;
+        self.refresh_mouse()
+    }
+
+    /// Lowers the given `window` to the bottom of the z-order of shown windows.
+    ///
+    /// If the `window` is active, the top-most window that isn't minimized becomes active instead.
+    pub fn lower_window(&mut self, window: &Arc<Mutex<WindowInner>>) -> Result<(), &'static str> {
+        if self.is_active(window) {
+            self.active = Weak::new();
+            let next_active = self.show_list.iter()
+                .rev()
+                .filter_map(Weak::upgrade)
+                .find(|w| !w.lock().is_minimized());
+            if let Some(next_active) = next_active {
+                self.set_active(&next_active, false)?;
+            }
+        } else if let Some(i) = self.is_window_in_show_list(window) {
+            self.show_list.remove(i);
+        } else if let Some(i) = self.is_window_in_hide_list(window) {
+            self.hide_list.remove(i);
+        } else {
+            return Err("cannot find this window");
+        }
+        self.show_list.push_front(Arc::downgrade(window));
+
+        let area = window.lock().bounding_box();
+        self.refresh_bottom_windows(Some(area), true)?;
+        self.refresh_mouse()
+    }
+
+    /// Sets the opacity of the given `window`, from `0` (invisible) to `255` (fully opaque),
+    /// and redraws the area it covers.
+    pub fn set_window_opacity(&mut self, window: &Arc<Mutex<WindowInner>>, opacity: u8) -> Result<(), &'static str> {
+        let area = {
+            let mut inner = window.lock();
+            if inner.opacity() == opacity {
+                return Ok(());
+            }
+            inner.set_opacity(opacity);
+            if inner.is_minimized() {
+                return Ok(());
+            }
+            inner.bounding_box()
+        };
+        self.refresh_bottom_windows(Some(area), true)?;
+        self.refresh_mouse()
+    }
+
     /// Passes the given keyboard event to the currently active window.
     fn pass_keyboard_event_to_window(&self, key_event: KeyEvent) -> Result<(), &'static str> {
         let active_window = self.active.upgrade().ok_or("no window was set as active to receive a keyboard event")?;
@@ -355,9 +557,10 @@ impl WindowManager {
         if let Some(current_active) = self.active.upgrade() {
             let current_active_win = current_active.lock();
             let current_coordinate = current_active_win.get_position();
-            if current_active_win.contains(*coordinate - current_coordinate) || matches!(current_active_win.moving, WindowMovingStatus::Moving(_))
+            if !current_active_win.is_minimized()
+                && (current_active_win.contains(*coordinate - current_coordinate) || current_active_win.is_moving())
             {
-                event.coordinate = *coordinate - current_coordinate;
+                event.coordinate = current_active_win.to_surface_coordinate(*coordinate - current_coordinate);
                 // debug!("pass to active: {}, {}", event.x, event.y);
                 current_active_win.send_event(Event::MousePositionEvent(event))
                     .map_err(|_e| "Failed to enqueue the mouse event; window event queue was full.")?;
@@ -372,8 +575,8 @@ impl WindowManager {
             if let Some(now_inner_mutex) = self.show_list[i].upgrade() {
                 let now_inner = now_inner_mutex.lock();
                 let current_coordinate = now_inner.get_position();
-                if now_inner.contains(*coordinate - current_coordinate) {
-                    event.coordinate = *coordinate - current_coordinate;
+                if !now_inner.is_minimized() && now_inner.contains(*coordinate - current_coordinate) {
+                    event.coordinate = now_inner.to_surface_coordinate(*coordinate - current_coordinate);
                     now_inner.send_event(Event::MousePositionEvent(event))
                         .map_err(|_e| "Failed to enqueue the mouse event; window event queue was full.")?;
                     return Ok(());
@@ -416,7 +619,7 @@ impl WindowManager {
     fn draw_floating_border(&mut self, border: &Rectangle, color: Color) -> Vec<Coord> {
         let mut coordinates = Vec::new();
         let pixel = color.into();
-        for i in 0..(WINDOW_BORDER_SIZE) as isize {
+        for i in 0..self.scale_factor.scale(WINDOW_BORDER_SIZE) as isize {
             let width = (border.bottom_right.x - border.top_left.x) - 2 * i;
             let height = (border.bottom_right.y - border.top_left.y) - 2 * i;
             let coordinate = border.top_left + (i, i);
@@ -456,24 +659,18 @@ impl WindowManager {
 
             let (old_top_left, old_bottom_right, new_top_left, new_bottom_right) = {
                 let mut current_active_win = current_active.lock();
-                let (current_x, current_y) = {
-                    let m = &self.mouse;
-                    (m.x, m.y)
+                let screen_size = self.get_screen_size();
+                let WindowMovingStatus::Moving { initial, .. } = current_active_win.moving_status() else {
+                    return Err("The window is not moving");
                 };
-                match current_active_win.moving {
-                    WindowMovingStatus::Moving(base) => {
-                        let old_top_left = current_active_win.get_position();
-                        let new_top_left = old_top_left + ((current_x - base.x), (current_y - base.y));
-                        let (width, height) = current_active_win.get_size();
-                        let old_bottom_right = old_top_left + (width as isize, height as isize);
-                        let new_bottom_right = new_top_left + (width as isize, height as isize);
-                        current_active_win.set_position(new_top_left);
-                        (old_top_left, old_bottom_right, new_top_left, new_bottom_right)        
-                    },
-                    WindowMovingStatus::Stationary => {
-                        return Err("The window is not moving");
-                    }
-                }
+                current_active_win.update_move(Coord::new(self.mouse.x, self.mouse.y) - initial);
+                let old_top_left = current_active_win.finish_move(screen_size)
+                    .ok_or("The window is not moving")?;
+                let new_top_left = current_active_win.get_position();
+                let (width, height) = current_active_win.get_size();
+                let old_bottom_right = old_top_left + (width as isize, height as isize);
+                let new_bottom_right = new_top_left + (width as isize, height as isize);
+                (old_top_left, old_bottom_right, new_top_left, new_bottom_right)
             };
             self.refresh_bottom_windows(Some(Rectangle{top_left: old_top_left, bottom_right: old_bottom_right}), false)?;
 
@@ -485,21 +682,105 @@ impl WindowManager {
         Ok(())
     }
 
+    /// Returns the area of the screen covered by the mouse cursor.
+    fn cursor_area(&self) -> Rectangle {
+        let hotspot = self.cursor.hotspot();
+        let top_left = self.mouse - (
+            self.scale_factor.scale(hotspot.x as usize) as isize,
+            self.scale_factor.scale(hotspot.y as usize) as isize,
+        );
+        let (width, height) = self.cursor_fb.get_size();
+        Rectangle {
+            top_left,
+            bottom_right: top_left + (width as isize, height as isize),
+        }
+    }
+
     /// Refresh the mouse display
     pub fn refresh_mouse(&mut self) -> Result<(), &'static str> {
-        let bounding_box = Some(Rectangle {
-            top_left: self.mouse,
-            bottom_right: self.mouse + (MOUSE_POINTER_SIZE_X as isize, MOUSE_POINTER_SIZE_Y as isize)
-        });
-        
+        let bounding_box = Some(self.cursor_area());
         self.refresh_top(bounding_box)
     }
 
+    /// Sets the cursor image shown when the mouse isn't over a window that supplies its own cursor.
+    pub fn set_default_cursor(&mut self, cursor: CursorImage) -> Result<(), &'static str> {
+        self.default_cursor = Arc::new(cursor);
+        self.refresh_cursor()
+    }
+
+    /// Shows the cursor image supplied by the window under the mouse, or the default cursor,
+    /// redrawing the cursor if its image changed.
+    ///
+    /// This should be invoked after a window's cursor image has been changed.
+    pub fn refresh_cursor(&mut self) -> Result<(), &'static str> {
+        let old_area = self.cursor_area();
+        if self.update_cursor_image()? {
+            self.redraw_cursor(old_area)?;
+        }
+        Ok(())
+    }
+
+    /// Selects the cursor image for the current mouse position and draws it into the cursor framebuffer.
+    ///
+    /// Returns whether the cursor image changed. The screen is not refreshed.
+    fn update_cursor_image(&mut self) -> Result<bool, &'static str> {
+        let cursor = self.window_under_mouse()
+            .and_then(|window| window.lock().cursor().cloned())
+            .unwrap_or_else(|| self.default_cursor.clone());
+        if Arc::ptr_eq(&cursor, &self.cursor) {
+            return Ok(false);
+        }
+        self.cursor_fb = render_cursor(&cursor, self.scale_factor)?;
+        self.cursor = cursor;
+        Ok(true)
+    }
+
+    /// Redraws what was beneath the cursor at its `old_area`, and then draws the cursor at its current area.
+    fn redraw_cursor(&mut self, old_area: Rectangle) -> Result<(), &'static str> {
+        let new_area = self.cursor_area();
+        // Overlapping areas are merged such that no pixel is blended twice.
+        let areas = match old_area.intersection(&new_area) {
+            Some(_) => [Some(old_area.union(&new_area)), None],
+            None => [Some(old_area), Some(new_area)],
+        };
+        self.refresh_bottom_windows(areas.iter().flatten().copied(), true)?;
+        self.refresh_top(areas.iter().flatten().copied())
+    }
+
+    /// Returns the topmost window that the mouse is over, if any.
+    fn window_under_mouse(&self) -> Option<Arc<Mutex<WindowInner>>> {
+        self.active.upgrade()
+            .into_iter()
+            .chain(self.show_list.iter().filter_map(Weak::upgrade))
+            .find(|window| {
+                let window = window.lock();
+                !window.is_minimized() && window.contains(self.mouse - window.get_position())
+            })
+    }
+
     /// Move mouse. `relative` indicates the new position relative to current position.
     fn move_mouse(&mut self, relative: Coord) -> Result<(), &'static str> {
-        let old = self.mouse;
-        let mut new = old + relative;
-        
+        let new = self.clamp_mouse_position(self.mouse + relative);
+        self.move_mouse_to(new)
+    }
+
+    /// Move mouse to the screen position corresponding to the given absolute `position`,
+    /// whose range spans the whole screen.
+    fn move_mouse_absolute(&mut self, position: MousePositionAbsolute) -> Result<(), &'static str> {
+        let (screen_width, screen_height) = self.get_screen_size();
+        const AXIS_RANGE: isize = u16::MAX as isize + 1;
+        let new = self.clamp_mouse_position(Coord::new(
+            position.x as isize * screen_width as isize / AXIS_RANGE,
+            position.y as isize * screen_height as isize / AXIS_RANGE,
+        ));
+        if new == self.mouse {
+            return Ok(());
+        }
+        self.move_mouse_to(new)
+    }
+
+    /// Returns the given mouse position, clamped such that the mouse pointer remains on the screen.
+    fn clamp_mouse_position(&self, mut new: Coord) -> Coord {
         let (screen_width, screen_height) = self.get_screen_size();
         if new.x < 0 {
             new.x = 0;
@@ -512,58 +793,32 @@ impl WindowManager {
         const MOUSE_POINTER_BORDER: isize = 3;
         new.x = core::cmp::min(new.x, screen_width as isize - MOUSE_POINTER_BORDER);
         new.y = core::cmp::min(new.y, screen_height as isize - MOUSE_POINTER_BORDER);
-            
-        self.move_mouse_to(new)
+        new
     }
     
     // Move mouse to absolute position `new`
     fn move_mouse_to(&mut self, new: Coord) -> Result<(), &'static str> {
-        // clear old mouse
-        for y in self.mouse.y..self.mouse.y + MOUSE_POINTER_SIZE_Y as isize {
-            for x in
-                self.mouse.x..self.mouse.x + MOUSE_POINTER_SIZE_X as isize {
-                let coordinate = Coord::new(x, y);
-                self.top_fb.overwrite_pixel(coordinate, color::TRANSPARENT.into());
-            }
-        }
-        let bounding_box = Some(Rectangle {
-            top_left: self.mouse,
-            bottom_right: self.mouse + (MOUSE_POINTER_SIZE_X as isize, MOUSE_POINTER_SIZE_Y as isize)
-        });
-        self.refresh_bottom_windows(bounding_box.into_iter(), true)?;
-
-        // draw new mouse
+        let old_area = self.cursor_area();
         self.mouse = new;
-        for y in new.y..new.y + MOUSE_POINTER_SIZE_Y as isize {
-            for x in new.x..new.x + MOUSE_POINTER_SIZE_X as isize {
-                let coordinate = Coord::new(x, y);
-                let pixel = MOUSE_POINTER_IMAGE[(x - new.x) as usize][(y - new.y) as usize].into();
-                self.top_fb.overwrite_pixel(coordinate, pixel);
-            }
-        }
-        self.refresh_mouse()?;
-
-        Ok(())
+        self.update_cursor_image()?;
+        self.redraw_cursor(old_area)
     }
 
     /// Move the floating border when a window is moving.
     pub fn move_floating_border(&mut self) -> Result<(), &'static str> {
-        let (new_x, new_y) = {
-            let m = &self.mouse;
-            (m.x, m.y)
-        };
-        
+        let mouse = Coord::new(self.mouse.x, self.mouse.y);
+        let screen_size = self.get_screen_size();
+
         if let Some(current_active) = self.active.upgrade() {
             let (is_draw, border_start, border_end) = {
-                let current_active_win = current_active.lock();
-                match current_active_win.moving {
-                    WindowMovingStatus::Moving(base) => {
-                        // move this window
+                let mut current_active_win = current_active.lock();
+                match current_active_win.moving_status() {
+                    WindowMovingStatus::Moving { initial, .. } => {
                         // for better performance, while moving window, only border is shown for indication
-                        let coordinate = current_active_win.get_position();
-                        // let (current_x, current_y) = (coordinate.x, coordinate.y);
+                        current_active_win.update_move(mouse - initial);
+                        let border_start = current_active_win.move_target(screen_size)
+                            .ok_or("The window is not moving")?;
                         let (width, height) = current_active_win.get_size();
-                        let border_start = coordinate + (new_x - base.x, new_y - base.y);
                         let border_end = border_start + (width as isize, height as isize);
                         (true, border_start, border_end)
                     }
@@ -603,6 +858,7 @@ impl WindowManager {
 pub fn init() -> Result<(Queue<Event>, Queue<Event>), &'static str> {
     let final_fb: Framebuffer<AlphaPixel> = framebuffer::init()?;
     let (width, height) = final_fb.get_size();
+    let scale_factor = display_scale::set_default_for_resolution(width, height);
 
     let mut bottom_fb = Framebuffer::new(width, height, None)?;
     let mut top_fb = Framebuffer::new(width, height, None)?;
@@ -616,6 +872,9 @@ pub fn init() -> Result<(Queue<Event>, Queue<Event>), &'static str> {
         y: screen_height as isize / 2,
     }; 
 
+    let default_cursor = Arc::new(CursorImage::default());
+    let cursor_fb = render_cursor(&default_cursor, scale_factor)?;
+
     // Initialize static window manager
     let window_manager = WindowManager {
         hide_list: VecDeque::new(),
@@ -625,9 +884,15 @@ pub fn init() -> Result<(Queue<Event>, Queue<Event>), &'static str> {
         repositioned_border: None,
         bottom_fb,
         top_fb,
+        cursor_fb,
+        cursor: default_cursor.clone(),
+        default_cursor,
         final_fb,
+        scale_factor,
     };
     WINDOW_MANAGER.call_once(|| Mutex::new(window_manager));
+    register_hotkeys()?;
+    memory_pressure::register_usage_reporter("window buffers", || window_buffer_stats().total_bytes)?;
 
     // keyinput queue initialization
     let key_consumer: Queue<Event> = Queue::with_capacity(100);
@@ -637,13 +902,60 @@ pub fn init() -> Result<(Queue<Event>, Queue<Event>), &'static str> {
     let mouse_consumer: Queue<Event> = Queue::with_capacity(100);
     let mouse_producer = mouse_consumer.clone();
 
-    spawn::new_task_builder(window_manager_loop, (key_consumer, mouse_consumer))
+    let wm_loop_task = spawn::new_task_builder(window_manager_loop, (key_consumer, mouse_consumer))
         .name("window_manager_loop".to_string())
         .spawn()?;
+    spawn::new_task_builder(watch_window_manager_loop, wm_loop_task)
+        .name("window_manager_watcher".to_string())
+        .spawn()?;
 
     Ok((key_producer, mouse_producer))
 }
 
+/// Draws the given cursor `image` into a new framebuffer, scaled up by the given `scale_factor`.
+fn render_cursor(image: &CursorImage, scale_factor: ScaleFactor) -> Result<Framebuffer<AlphaPixel>, &'static str> {
+    let width = scale_factor.scale(image.width());
+    let height = scale_factor.scale(image.height());
+    let mut cursor_fb = Framebuffer::new(width, height, None)?;
+    cursor_fb.fill(color::TRANSPARENT.into());
+    for y in 0..height {
+        for x in 0..width {
+            // scale the cursor image up by using the nearest unscaled pixel
+            let image_x = scale_factor.unscale(x).min(image.width() - 1);
+            let image_y = scale_factor.unscale(y).min(image.height() - 1);
+            if let Some(color) = image.get_pixel(image_x, image_y) {
+                cursor_fb.overwrite_pixel(Coord::new(x as isize, y as isize), color.into());
+            }
+        }
+    }
+    Ok(cursor_fb)
+}
+
+/// Waits for the window manager loop task to exit, which only happens if it failed,
+/// and then falls back to a framebuffer console such that the screen remains usable.
+fn watch_window_manager_loop(wm_loop_task: JoinableTaskRef) -> Result<(), &'static str> {
+    match wm_loop_task.join()? {
+        ExitValue::Completed(_) => error!("The window manager loop exited unexpectedly"),
+        ExitValue::Killed(reason) => error!("The window manager loop was killed: {}", reason),
+    }
+    let final_fb = release_final_framebuffer()?;
+    fb_console::take_over(final_fb)
+}
+
+/// Takes the final framebuffer away from the window manager, replacing it with a virtual one,
+/// such that another display server can render to the screen instead.
+///
+/// After this, the window manager no longer displays anything.
+pub fn release_final_framebuffer() -> Result<Framebuffer<AlphaPixel>, &'static str> {
+    let mut wm = WINDOW_MANAGER.get()
+        .ok_or("The static window manager was not yet initialized")?
+        .try_lock()
+        .ok_or("The window manager is locked, e.g., by a task that crashed while using it")?;
+    let (width, height) = wm.final_fb.get_size();
+    let virtual_fb = Framebuffer::new(width, height, None)?;
+    Ok(core::mem::replace(&mut wm.final_fb, virtual_fb))
+}
+
 /// handles all keyboard and mouse movement in this window manager
 fn window_manager_loop(
     (key_consumer, mouse_consumer): (Queue<Event>, Queue<Event>),
@@ -703,6 +1015,21 @@ fn window_manager_loop(
                     }
                     cursor_handle_application(mouse_event.clone())?; // tell the event to application, or moving window
                 }
+                Event::MouseAbsoluteEvent(ref mouse_event) => {
+                    {
+                        let mut wm = WINDOW_MANAGER
+                            .get()
+                            .ok_or("The static window manager was not yet initialized")?
+                            .lock();
+                        wm.move_mouse_absolute(mouse_event.position)?;
+                    }
+                    // Applications and window moving are driven by button state and position,
+                    // so pass the event on as a mouse event without any relative movement.
+                    cursor_handle_application(MouseEvent::new(
+                        mouse_event.buttons.clone(),
+                        MouseMovementRelative::new(0, 0, mouse_event.scroll_movement),
+                    ))?;
+                }
                 _other => {
                     trace!("WINDOW_MANAGER: ignoring unexpected event: {:?}", _other);
                 }
@@ -715,65 +1042,8 @@ fn window_manager_loop(
 fn keyboard_handle_application(key_input: KeyEvent) -> Result<(), &'static str> {
     let win_mgr = WINDOW_MANAGER.get().ok_or("The window manager was not yet initialized")?;
     
-    // First, we handle keyboard shortcuts understood by the window manager.
-    
-    // "Super + Arrow" will resize and move windows to the specified half of the screen (left, right, top, or bottom)
-    if key_input.modifiers.is_super_key() && key_input.action == KeyAction::Pressed {
-        let screen_dimensions = win_mgr.lock().get_screen_size();
-        let (width, height) = (screen_dimensions.0 as isize, screen_dimensions.1 as isize);
-        let new_position: Option<Rectangle> = match key_input.keycode {
-            Keycode::Left => Some(Rectangle {
-                top_left:     Coord { x: 0, y: 0 },
-                bottom_right: Coord { x: width / 2, y: height },
-            }),
-            Keycode::Right => Some(Rectangle {
-                top_left:     Coord { x: width / 2, y: 0 },
-                bottom_right: Coord { x: width, y: height },
-            }),
-            Keycode::Up => Some(Rectangle {
-                top_left:     Coord { x: 0, y: 0 },
-                bottom_right: Coord { x: width, y: height / 2 },
-            }),
-            Keycode::Down => Some(Rectangle {
-                top_left:     Coord { x: 0, y: height / 2 },
-                bottom_right: Coord { x: width, y: height },
-            }),
-            _ => None,
-        };
-        
-        if let Some(position) = new_position {
-            let mut wm = win_mgr.lock();
-            if let Some(active_window) = wm.active.upgrade() {
-                debug!("window_manager: resizing active window to {:?}", new_position);
-                active_window.lock().resize(position)?;
-
-                // force refresh the entire screen for now
-                // TODO: perform a proper screen refresh here: only refresh the area that contained the active_window's old bounds.
-                wm.refresh_bottom_windows(Option::<Rectangle>::None, true)?;
-            }
-        }
-
-        return Ok(());
-    }
-
-    // Spawn a new terminal via Ctrl+Alt+T
-    if key_input.modifiers.is_control()
-        && key_input.modifiers.is_alt()
-        && key_input.keycode == Keycode::T
-        && key_input.action == KeyAction::Pressed
-    {
-        // Because this task (the window manager loop) runs in a kernel-only namespace,
-        // we have to create a new application namespace in order to be able to actually spawn a shell.
-
-        let new_app_namespace = mod_mgmt::create_application_namespace(None)?;
-        let shell_objfile = new_app_namespace.dir().get_file_starting_with("shell-")
-            .ok_or("Couldn't find shell application file to run upon Ctrl+Alt+T")?;
-        let path = shell_objfile.lock().get_absolute_path();
-        spawn::new_application_task_builder(path.as_ref(), Some(new_app_namespace))?
-            .name("shell".to_string())
-            .spawn()?;
-
-        debug!("window_manager: spawned new shell app in new app namespace.");
+    // First, we handle global hotkeys, including the keyboard shortcuts of the window manager itself.
+    if hotkeys::dispatch(&key_input) {
         return Ok(());
     }
 
@@ -787,6 +1057,117 @@ fn keyboard_handle_application(key_input: KeyEvent) -> Result<(), &'static str>
     Ok(())
 }
 
+/// Registers the keyboard shortcuts understood by the window manager as global hotkeys.
+fn register_hotkeys() -> Result<(), &'static str> {
+    // "Super + Arrow" will resize and move windows to the specified half of the screen (left, right, top, or bottom)
+    let snap_directions = [
+        (Keycode::Left,  "Snap the active window to the left half of the screen"),
+        (Keycode::Right, "Snap the active window to the right half of the screen"),
+        (Keycode::Up,    "Snap the active window to the top half of the screen"),
+        (Keycode::Down,  "Snap the active window to the bottom half of the screen"),
+    ];
+    for (keycode, description) in snap_directions {
+        hotkeys::register(Hotkey::new(keycode).super_key(), "window_manager", description, snap_active_window)?;
+    }
+    hotkeys::register(Hotkey::new(Keycode::Tab).alt(), "window_manager", "Switch to the next window", |_| switch_to_next_window())?;
+    hotkeys::register(Hotkey::new(Keycode::T).control().alt(), "window_manager", "Open a new terminal", |_| spawn_terminal())?;
+    // "Ctrl + Shift" is used because many terminal applications already use "Ctrl + C" to interrupt a task.
+    hotkeys::register(Hotkey::new(Keycode::C).control().shift(), "window_manager", "Copy from the active window", |_| {
+        send_clipboard_event(clipboard::Rights::Write)
+    })?;
+    hotkeys::register(Hotkey::new(Keycode::V).control().shift(), "window_manager", "Paste into the active window", |_| {
+        send_clipboard_event(clipboard::Rights::Read)
+    })?;
+    Ok(())
+}
+
+/// Grants the active window a capability to access the clipboard with the given `rights`,
+/// sending it a `CopyEvent` for write rights or a `PasteEvent` for read rights.
+fn send_clipboard_event(rights: clipboard::Rights) -> Result<(), &'static str> {
+    let wm = WINDOW_MANAGER.get().ok_or("The window manager was not yet initialized")?.lock();
+    let Some(active_window) = wm.active.upgrade() else {
+        return Ok(());
+    };
+    let capability = clipboard::grant(rights)?;
+    let event = match rights {
+        clipboard::Rights::Write => Event::CopyEvent(capability),
+        clipboard::Rights::Read => Event::PasteEvent(capability),
+    };
+    active_window.lock().send_event(event)
+        .map_err(|_e| "Failed to enqueue the clipboard event; window event queue was full.")
+}
+
+/// Resizes and moves the active window to the half of the screen given by the arrow key of `hotkey`.
+fn snap_active_window(hotkey: Hotkey) -> Result<(), &'static str> {
+    let mut wm = WINDOW_MANAGER.get().ok_or("The window manager was not yet initialized")?.lock();
+    let screen_dimensions = wm.get_screen_size();
+    let (width, height) = (screen_dimensions.0 as isize, screen_dimensions.1 as isize);
+    let position = match hotkey.keycode {
+        Keycode::Left => Rectangle {
+            top_left:     Coord { x: 0, y: 0 },
+            bottom_right: Coord { x: width / 2, y: height },
+        },
+        Keycode::Right => Rectangle {
+            top_left:     Coord { x: width / 2, y: 0 },
+            bottom_right: Coord { x: width, y: height },
+        },
+        Keycode::Up => Rectangle {
+            top_left:     Coord { x: 0, y: 0 },
+            bottom_right: Coord { x: width, y: height / 2 },
+        },
+        Keycode::Down => Rectangle {
+            top_left:     Coord { x: 0, y: height / 2 },
+            bottom_right: Coord { x: width, y: height },
+        },
+        _ => return Err("window_manager: snapping a window requires an arrow key"),
+    };
+
+    if let Some(active_window) = wm.active.upgrade() {
+        debug!("window_manager: resizing active window to {:?}", position);
+        wm.resize_window(&active_window, position)?;
+    }
+    Ok(())
+}
+
+/// Activates the shown window that has been inactive the longest,
+/// such that repeatedly switching windows cycles through all shown windows.
+///
+/// A minimized window is restored when it is switched to.
+fn switch_to_next_window() -> Result<(), &'static str> {
+    let mut wm = WINDOW_MANAGER.get().ok_or("The window manager was not yet initialized")?.lock();
+    // Windows that have been dropped remain in the show list until they are deleted.
+    while let Some(weak_window) = wm.show_list.back() {
+        if let Some(window) = weak_window.upgrade() {
+            let is_minimized = window.lock().is_minimized();
+            if is_minimized {
+                wm.restore_window(&window)?;
+            } else {
+                wm.set_active(&window, true)?;
+            }
+            return Ok(());
+        }
+        wm.show_list.pop_back();
+    }
+    Ok(())
+}
+
+/// Spawns a new shell application, which opens a new terminal window.
+fn spawn_terminal() -> Result<(), &'static str> {
+    // Because this task (the window manager loop) runs in a kernel-only namespace,
+    // we have to create a new application namespace in order to be able to actually spawn a shell.
+
+    let new_app_namespace = mod_mgmt::create_application_namespace(None)?;
+    let shell_objfile = new_app_namespace.dir().get_file_starting_with("shell-")
+        .ok_or("Couldn't find shell application file to run upon Ctrl+Alt+T")?;
+    let path = shell_objfile.lock().get_absolute_path();
+    spawn::new_application_task_builder(path.as_ref(), Some(new_app_namespace))?
+        .name("shell".to_string())
+        .spawn()?;
+
+    debug!("window_manager: spawned new shell app in new app namespace.");
+    Ok(())
+}
+
 /// handle mouse event, push it to related window or anyone asked for it
 fn cursor_handle_application(mouse_event: MouseEvent) -> Result<(), &'static str> {
     let wm = WINDOW_MANAGER.get().ok_or("The static window manager was not yet initialized")?.lock();
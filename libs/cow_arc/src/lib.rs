@@ -109,6 +109,12 @@ impl<T> CowArc<T> {
         Arc::ptr_eq(&self.arc, &other.arc)
     }
 
+    /// Returns true if the two `CowArc`s refer to the same inner data,
+    /// even if they are separate references to it, e.g., one obtained by upgrading a [`CowWeak`].
+    pub fn inner_ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.arc.inner_arc, &other.arc.inner_arc)
+    }
+
 
     /// Creates a shallow clone of this `CowArc` that **does not** affect its `Shared` state.
     /// This means that it will not change it to `Shared` if it was `Exclusive,
@@ -0,0 +1,17 @@
+//! The 64-bit [FNV-1a] hash function.
+//!
+//! FNV-1a is simple, fast, and doesn't require any random state, which makes it suitable
+//! for checksums and for identifying unchanged data, but not for protecting against
+//! maliciously-crafted inputs.
+//!
+//! [FNV-1a]: https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function
+
+#![no_std]
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Returns the 64-bit FNV-1a hash of the given bytes.
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+}
@@ -44,4 +44,51 @@ impl MouseEvent {
             movement,
         }
     }
-}
\ No newline at end of file
+}
+
+/// An absolute pointer position, as reported by tablets and virtual pointing devices
+/// that track the host's mouse, e.g., under QEMU.
+///
+/// Each axis is normalized to the range `0 ..= u16::MAX`, which spans the whole screen,
+/// such that the position is independent of the device's own resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MousePositionAbsolute {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl MousePositionAbsolute {
+    pub fn new(x: u16, y: u16) -> Self {
+        Self { x, y }
+    }
+
+    /// Normalizes the given device axis `value` within `min ..= max` to the range of one axis of this position.
+    ///
+    /// Values outside of that range are clamped to it.
+    pub fn normalize(value: i32, min: i32, max: i32) -> u16 {
+        if max <= min {
+            return 0;
+        }
+        let value = value.clamp(min, max) as i64 - min as i64;
+        (value * u16::MAX as i64 / (max as i64 - min as i64)) as u16
+    }
+}
+
+/// An event from an absolute pointing device, which reports where the pointer is
+/// rather than how far it has moved.
+#[derive(Debug, Clone)]
+pub struct MouseAbsoluteEvent {
+    pub buttons: MouseButtons,
+    pub position: MousePositionAbsolute,
+    pub scroll_movement: i8,
+}
+
+impl MouseAbsoluteEvent {
+    pub fn new(buttons: MouseButtons, position: MousePositionAbsolute, scroll_movement: i8) -> MouseAbsoluteEvent {
+        MouseAbsoluteEvent {
+            buttons,
+            position,
+            scroll_movement,
+        }
+    }
+}
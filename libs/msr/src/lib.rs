@@ -2,12 +2,18 @@
 //!
 //! Taken from an old version of the [`x86_64`] crate, which no longer includes them.
 //!
+//! The [`registers`] module offers typed wrappers around commonly-used MSRs,
+//! which should be preferred over reading and writing the raw constants below.
+//!
 //! [`x86_64`]: https://crates.io/crates/x86_64
 
 #![no_std]
 
 #![allow(missing_docs)]
 
+#[cfg(target_arch = "x86_64")]
+pub mod registers;
+
 // What follows is a long list of all MSR register taken from Intel's manual.
 // Some of the register values appear duplicated as they may be
 // called differently for different architectures or they just have
@@ -0,0 +1,294 @@
+//! Typed wrappers around commonly-used MSRs.
+//!
+//! Each type wraps the raw value of one MSR and offers accessors for its bitfields.
+//! Reading checks via `CPUID` that the MSR exists on the current CPU,
+//! as accessing a nonexistent MSR causes a General Protection fault.
+//! Writing is `unsafe` for MSRs that control how the CPU executes or accesses memory.
+//!
+//! MSRs are per-CPU, so these functions only access the MSRs of the current CPU.
+
+use core::fmt;
+use raw_cpuid::CpuId;
+use x86_64::registers::model_specific::Msr;
+use crate::{IA32_APIC_BASE, IA32_EFER, IA32_FS_BASE, IA32_GS_BASE, IA32_KERNEL_GS_BASE, IA32_PERFEVTSEL0};
+
+/// Reads the MSR at the given `address`, which the caller must have ensured exists.
+fn rdmsr(address: u32) -> u64 {
+    // SAFETY: every caller checks that the MSR exists, and reading an MSR has no side effects.
+    unsafe { Msr::new(address).read() }
+}
+
+/// Writes `value` to the MSR at the given `address`, which the caller must have ensured exists.
+///
+/// # Safety
+/// The caller must ensure that writing `value` does not violate memory safety.
+unsafe fn wrmsr(address: u32, value: u64) {
+    Msr::new(address).write(value)
+}
+
+/// Returns a copy of `value` with the given `bit` set to `enable`.
+fn with_bit(value: u64, bit: u32, enable: bool) -> u64 {
+    if enable { value | (1 << bit) } else { value & !(1 << bit) }
+}
+
+
+/// The `IA32_APIC_BASE` MSR, which holds the physical address of the Local APIC's registers
+/// and the bits that enable it in xAPIC or x2APIC mode.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ApicBase(pub u64);
+
+impl ApicBase {
+    const BASE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+    /// Returns `true` if this CPU has a Local APIC, and thus an `IA32_APIC_BASE` MSR.
+    pub fn is_supported() -> bool {
+        CpuId::new().get_feature_info().map_or(false, |f| f.has_apic())
+    }
+
+    /// Reads the current CPU's `IA32_APIC_BASE` MSR.
+    pub fn read() -> Result<ApicBase, &'static str> {
+        if !Self::is_supported() {
+            return Err("ApicBase::read(): this CPU does not have a Local APIC");
+        }
+        Ok(ApicBase(rdmsr(IA32_APIC_BASE)))
+    }
+
+    /// Writes this value to the current CPU's `IA32_APIC_BASE` MSR.
+    ///
+    /// # Safety
+    /// Moving or disabling the Local APIC breaks any existing mappings of its registers.
+    pub unsafe fn write(self) -> Result<(), &'static str> {
+        if !Self::is_supported() {
+            return Err("ApicBase::write(): this CPU does not have a Local APIC");
+        }
+        wrmsr(IA32_APIC_BASE, self.0);
+        Ok(())
+    }
+
+    /// Whether this CPU is the bootstrap processor.
+    pub fn is_bsp(&self) -> bool { self.0 & (1 << 8) != 0 }
+    /// Whether the Local APIC is in x2APIC mode, in which its registers are accessed via MSRs.
+    pub fn x2apic_enabled(&self) -> bool { self.0 & (1 << 10) != 0 }
+    /// Whether the Local APIC is globally enabled.
+    pub fn xapic_enabled(&self) -> bool { self.0 & (1 << 11) != 0 }
+    /// The physical address of the Local APIC's memory-mapped registers.
+    pub fn base_address(&self) -> u64 { self.0 & Self::BASE_ADDRESS_MASK }
+
+    /// Returns a copy of this value with x2APIC mode enabled or disabled.
+    pub fn with_x2apic_enabled(self, enable: bool) -> ApicBase { ApicBase(with_bit(self.0, 10, enable)) }
+    /// Returns a copy of this value with the Local APIC globally enabled or disabled.
+    pub fn with_xapic_enabled(self, enable: bool) -> ApicBase { ApicBase(with_bit(self.0, 11, enable)) }
+}
+
+impl fmt::Debug for ApicBase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ApicBase")
+            .field("BSP", &self.is_bsp())
+            .field("EXTD", &self.x2apic_enabled())
+            .field("EN", &self.xapic_enabled())
+            .field("base_address", &format_args!("{:#X}", self.base_address()))
+            .finish()
+    }
+}
+
+
+/// The `IA32_EFER` (Extended Feature Enable Register) MSR.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Efer(pub u64);
+
+impl Efer {
+    /// Returns `true` if this CPU supports extended features, and thus has an `IA32_EFER` MSR.
+    pub fn is_supported() -> bool {
+        CpuId::new().get_extended_processor_and_feature_identifiers().is_some()
+    }
+
+    /// Reads the current CPU's `IA32_EFER` MSR.
+    pub fn read() -> Result<Efer, &'static str> {
+        if !Self::is_supported() {
+            return Err("Efer::read(): this CPU does not support the IA32_EFER MSR");
+        }
+        Ok(Efer(rdmsr(IA32_EFER)))
+    }
+
+    /// Writes this value to the current CPU's `IA32_EFER` MSR.
+    ///
+    /// # Safety
+    /// Changing these features, e.g., disabling no-execute, affects how all memory is accessed.
+    pub unsafe fn write(self) -> Result<(), &'static str> {
+        if !Self::is_supported() {
+            return Err("Efer::write(): this CPU does not support the IA32_EFER MSR");
+        }
+        wrmsr(IA32_EFER, self.0);
+        Ok(())
+    }
+
+    /// Whether the `syscall` and `sysret` instructions are enabled.
+    pub fn syscall_enabled(&self) -> bool { self.0 & (1 << 0) != 0 }
+    /// Whether long mode (IA-32e mode) is enabled.
+    pub fn long_mode_enabled(&self) -> bool { self.0 & (1 << 8) != 0 }
+    /// Whether long mode (IA-32e mode) is active.
+    pub fn long_mode_active(&self) -> bool { self.0 & (1 << 10) != 0 }
+    /// Whether the no-execute page protection bit is enabled.
+    pub fn no_execute_enabled(&self) -> bool { self.0 & (1 << 11) != 0 }
+
+    /// Returns a copy of this value with the `syscall` and `sysret` instructions enabled or disabled.
+    pub fn with_syscall_enabled(self, enable: bool) -> Efer { Efer(with_bit(self.0, 0, enable)) }
+    /// Returns a copy of this value with the no-execute page protection bit enabled or disabled.
+    pub fn with_no_execute_enabled(self, enable: bool) -> Efer { Efer(with_bit(self.0, 11, enable)) }
+}
+
+impl fmt::Debug for Efer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Efer")
+            .field("SCE", &self.syscall_enabled())
+            .field("LME", &self.long_mode_enabled())
+            .field("LMA", &self.long_mode_active())
+            .field("NXE", &self.no_execute_enabled())
+            .finish()
+    }
+}
+
+
+/// Defines a type for an MSR that holds a segment base address,
+/// which exists on all CPUs that support long mode.
+macro_rules! segment_base_msr {
+    ($(#[$attr:meta])* $name:ident, $address:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub struct $name(pub u64);
+
+        impl $name {
+            #[doc = concat!("Reads the current CPU's `", stringify!($address), "` MSR.")]
+            pub fn read() -> $name {
+                $name(rdmsr($address))
+            }
+
+            #[doc = concat!("Writes this value to the current CPU's `", stringify!($address), "` MSR.")]
+            ///
+            /// # Safety
+            /// Thread-local storage and per-CPU data are accessed relative to segment base addresses,
+            /// so the caller must ensure that this base address points to valid data.
+            pub unsafe fn write(self) {
+                wrmsr($address, self.0)
+            }
+        }
+    };
+}
+
+segment_base_msr!(
+    /// The `IA32_FS_BASE` MSR, which holds the base address of the `FS` segment.
+    FsBase, IA32_FS_BASE
+);
+segment_base_msr!(
+    /// The `IA32_GS_BASE` MSR, which holds the base address of the `GS` segment.
+    GsBase, IA32_GS_BASE
+);
+segment_base_msr!(
+    /// The `IA32_KERNEL_GS_BASE` MSR, whose value is swapped with `IA32_GS_BASE` by `swapgs`.
+    KernelGsBase, IA32_KERNEL_GS_BASE
+);
+
+
+/// One of the `IA32_PERFEVTSELx` MSRs, which selects the event counted by
+/// the general-purpose performance counter `IA32_PMCx`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PerfEvtSel {
+    index: u8,
+    value: u64,
+}
+
+impl PerfEvtSel {
+    /// Returns the number of general-purpose performance counters on this CPU,
+    /// each of which has its own `IA32_PERFEVTSELx` MSR.
+    pub fn num_counters() -> u8 {
+        CpuId::new().get_performance_monitoring_info()
+            .filter(|pmi| pmi.version_id() > 0)
+            .map_or(0, |pmi| pmi.number_of_counters())
+    }
+
+    /// Creates a new value for the `IA32_PERFEVTSEL{index}` MSR
+    /// that counts the event with the given event select and unit mask,
+    /// at all privilege levels, but is not yet enabled.
+    pub fn new(index: u8, event_select: u8, unit_mask: u8) -> PerfEvtSel {
+        PerfEvtSel { index, value: 0 }
+            .with_event(event_select, unit_mask)
+            .with_usr(true)
+            .with_os(true)
+    }
+
+    /// Creates a new value for the `IA32_PERFEVTSEL{index}` MSR with all bits cleared,
+    /// which disables the counter and counts no event.
+    pub fn cleared(index: u8) -> PerfEvtSel {
+        PerfEvtSel { index, value: 0 }
+    }
+
+    /// Reads the current CPU's `IA32_PERFEVTSEL{index}` MSR.
+    pub fn read(index: u8) -> Result<PerfEvtSel, &'static str> {
+        if index >= Self::num_counters() {
+            return Err("PerfEvtSel::read(): this CPU does not have a performance counter with that index");
+        }
+        Ok(PerfEvtSel { index, value: rdmsr(IA32_PERFEVTSEL0 + index as u32) })
+    }
+
+    /// Writes this value to the current CPU's `IA32_PERFEVTSEL{index}` MSR.
+    pub fn write(self) -> Result<(), &'static str> {
+        if self.index >= Self::num_counters() {
+            return Err("PerfEvtSel::write(): this CPU does not have a performance counter with that index");
+        }
+        // SAFETY: selecting which event a performance counter counts cannot affect memory safety.
+        unsafe { wrmsr(IA32_PERFEVTSEL0 + self.index as u32, self.value) };
+        Ok(())
+    }
+
+    /// The index `x` of this `IA32_PERFEVTSELx` MSR.
+    pub fn index(&self) -> u8 { self.index }
+    /// The raw value of this MSR.
+    pub fn bits(&self) -> u64 { self.value }
+    /// The event select code of the counted event.
+    pub fn event_select(&self) -> u8 { self.value as u8 }
+    /// The unit mask that selects a condition of the counted event.
+    pub fn unit_mask(&self) -> u8 { (self.value >> 8) as u8 }
+    /// Whether events are counted while the CPU is at privilege levels 1, 2, or 3.
+    pub fn usr(&self) -> bool { self.value & (1 << 16) != 0 }
+    /// Whether events are counted while the CPU is at privilege level 0.
+    pub fn os(&self) -> bool { self.value & (1 << 17) != 0 }
+    /// Whether the counter raises an interrupt via the Local APIC when it overflows.
+    pub fn interrupt(&self) -> bool { self.value & (1 << 20) != 0 }
+    /// Whether the counter is enabled.
+    pub fn enabled(&self) -> bool { self.value & (1 << 22) != 0 }
+    /// The counter mask; if nonzero, only cycles with at least this many events are counted.
+    pub fn counter_mask(&self) -> u8 { (self.value >> 24) as u8 }
+
+    /// Returns a copy of this value that counts the event with the given event select and unit mask.
+    pub fn with_event(self, event_select: u8, unit_mask: u8) -> PerfEvtSel {
+        let value = (self.value & !0xFFFF) | ((unit_mask as u64) << 8) | event_select as u64;
+        PerfEvtSel { value, ..self }
+    }
+    /// Returns a copy of this value that does or doesn't count events at privilege levels 1, 2, and 3.
+    pub fn with_usr(self, enable: bool) -> PerfEvtSel { PerfEvtSel { value: with_bit(self.value, 16, enable), ..self } }
+    /// Returns a copy of this value that does or doesn't count events at privilege level 0.
+    pub fn with_os(self, enable: bool) -> PerfEvtSel { PerfEvtSel { value: with_bit(self.value, 17, enable), ..self } }
+    /// Returns a copy of this value that does or doesn't raise an interrupt when the counter overflows.
+    pub fn with_interrupt(self, enable: bool) -> PerfEvtSel { PerfEvtSel { value: with_bit(self.value, 20, enable), ..self } }
+    /// Returns a copy of this value with the counter enabled or disabled.
+    pub fn with_enabled(self, enable: bool) -> PerfEvtSel { PerfEvtSel { value: with_bit(self.value, 22, enable), ..self } }
+    /// Returns a copy of this value with the given counter mask.
+    pub fn with_counter_mask(self, counter_mask: u8) -> PerfEvtSel {
+        PerfEvtSel { value: (self.value & !(0xFF << 24)) | ((counter_mask as u64) << 24), ..self }
+    }
+}
+
+impl fmt::Debug for PerfEvtSel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PerfEvtSel")
+            .field("index", &self.index)
+            .field("event_select", &format_args!("{:#04X}", self.event_select()))
+            .field("unit_mask", &format_args!("{:#04X}", self.unit_mask()))
+            .field("USR", &self.usr())
+            .field("OS", &self.os())
+            .field("INT", &self.interrupt())
+            .field("EN", &self.enabled())
+            .field("CMASK", &self.counter_mask())
+            .finish()
+    }
+}